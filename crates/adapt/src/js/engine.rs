@@ -1,15 +1,26 @@
 use super::error::JsError;
+use super::fetch::{self, Fetcher};
+use super::host::{self, HostCaller, HostDenial, HostFn, HostState};
+use super::log::{self, LogLevels, LogScope};
+use super::storage::{self, KvStore, MemoryKvStore};
 use super::value::JsValue;
+use boa_engine::builtins::promise::PromiseState;
 use boa_engine::context::Context;
 use boa_engine::property::PropertyKey;
 use boa_engine::JsValue as BoaJsValue;
-use boa_engine::{js_string, Source};
+use boa_engine::{js_string, JsError as BoaJsError, JsNativeError, JsResult, Source};
 use serde_json::Value as Json;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::Arc;
 
 /// Engine abstraction.
 ///
 /// For now, all calls are synchronous and assume the JS function returns
-/// a JSON-like value (no engine-specific objects crossing the boundary).
+/// a JSON-like value (no engine-specific objects crossing the boundary). A
+/// function may also return a promise of one, provided it settles once the
+/// engine's pending jobs and `fetch` requests are done (e.g. an `async`
+/// hook awaiting `fetch`).
 pub trait JsEngine {
     /// Evaluate arbitrary JS code and return a JsValue.
     fn eval(&mut self, code: &str) -> Result<JsValue, JsError>;
@@ -21,7 +32,53 @@ pub trait JsEngine {
     fn load_module(&mut self, name: &str, source: &str) -> Result<(), JsError>;
 
     /// Call a JS function by a dotted path (e.g. "plugin.handle" or "theme.handle").
+    ///
+    /// A returned promise is settled first: its value, or its rejection as
+    /// an error.
     fn call_function(&mut self, func_path: &str, args: &[JsValue]) -> Result<JsValue, JsError>;
+
+    /// Expose a host function to JS as `__host(op, args)`.
+    ///
+    /// When `capability` is set, only callers granted it may invoke `op`.
+    fn register_host_fn(&mut self, op: &str, capability: Option<&str>, func: HostFn);
+
+    /// Set (or clear) who subsequent JS runs on behalf of.
+    fn set_host_caller(&mut self, caller: Option<HostCaller>);
+
+    /// Let JS run a function on behalf of `caller` with
+    /// `__runAs(key, fn, ...args)`.
+    fn register_host_caller(&mut self, key: &str, caller: HostCaller);
+
+    /// Take the most recent capability denial, if any.
+    fn take_host_denial(&mut self) -> Option<HostDenial>;
+
+    /// Set (or clear) the extension and request `log` events are tagged
+    /// with.
+    fn set_log_scope(&mut self, scope: Option<LogScope>);
+
+    /// Take the named globals away from JS. `call_function` still finds
+    /// them (as the first part of a path); code loaded afterwards does not.
+    fn hide_globals(&mut self, names: &[&str]) -> Result<(), JsError>;
+}
+
+/// What the engines of one site share: where plugins keep their `storage`
+/// values and which extension `log` events get through. Every engine a
+/// site builds, rebuilds included, is given the same one, and no two sites
+/// share theirs.
+#[derive(Clone)]
+pub struct EngineEnv {
+    pub storage: Arc<dyn KvStore>,
+    pub log_levels: Arc<LogLevels>,
+}
+
+impl Default for EngineEnv {
+    /// Values in memory, default levels.
+    fn default() -> Self {
+        Self {
+            storage: Arc::new(MemoryKvStore::default()),
+            log_levels: Arc::default(),
+        }
+    }
 }
 
 /// Concrete Boa-backed engine.
@@ -30,12 +87,77 @@ pub trait JsEngine {
 /// reuse. All conversions to/from Rust types go through serde_json::Value.
 pub struct BoaEngine {
     context: Context,
+    host: Rc<HostState>,
+    /// Globals moved out of JS's reach by `hide_globals`.
+    hidden: HashMap<String, BoaJsValue>,
 }
 
 impl BoaEngine {
     pub fn new() -> Self {
+        let mut context = Context::default();
+        // Registering a global on a fresh context cannot collide.
+        host::install(&mut context).expect("install __host binding");
+        fetch::install(&mut context).expect("install fetch binding");
+        storage::install(&mut context).expect("install storage binding");
+        log::install(&mut context).expect("install log binding");
+
         Self {
-            context: Context::default(),
+            context,
+            host: Rc::new(HostState::default()),
+            hidden: HashMap::new(),
+        }
+    }
+
+    /// A fresh engine keeping values and filtering logs as `env` says.
+    pub fn with_env(env: EngineEnv) -> Self {
+        let engine = Self::new();
+        engine.host.set_storage(env.storage);
+        engine.host.set_log_levels(env.log_levels);
+        engine
+    }
+
+    /// The environment this engine was given, for building another like it.
+    pub fn env(&self) -> EngineEnv {
+        EngineEnv {
+            storage: self.host.storage(),
+            log_levels: self.host.log_levels(),
+        }
+    }
+
+    /// Send `fetch` requests through `fetcher` instead of the network.
+    pub fn set_fetcher(&mut self, fetcher: Arc<dyn Fetcher>) {
+        self.host.set_fetcher(fetcher);
+    }
+
+    /// Keep this engine's `storage` values in `store` instead of memory.
+    pub fn set_storage(&mut self, store: Arc<dyn KvStore>) {
+        self.host.set_storage(store);
+    }
+
+    /// Run pending jobs, then unwrap `value` if it is a promise, waiting on
+    /// `fetch` requests while it is pending and some are still out.
+    fn settle(&mut self, value: BoaJsValue) -> JsResult<BoaJsValue> {
+        let mut block = false;
+        loop {
+            self.host.fetches().settle(block, &mut self.context)?;
+            self.context.run_jobs()?;
+            let pending = value
+                .as_promise()
+                .is_some_and(|p| matches!(p.state(), PromiseState::Pending));
+            if !pending || self.host.fetches().is_empty() {
+                break;
+            }
+            block = true;
+        }
+        let Some(promise) = value.as_promise() else {
+            return Ok(value);
+        };
+        match promise.state() {
+            PromiseState::Fulfilled(v) => Ok(v),
+            PromiseState::Rejected(e) => Err(BoaJsError::from_opaque(e)),
+            PromiseState::Pending => Err(JsNativeError::error()
+                .with_message("returned a promise that never settled")
+                .into()),
         }
     }
 
@@ -99,17 +221,26 @@ impl BoaEngine {
     // Function resolution
     // ─────────────────────────────────────────────────────────────────────────
 
-    /// Resolve a dotted path against the global object, e.g. "plugin.handle".
+    /// Resolve a dotted path against the global object, e.g. "plugin.handle",
+    /// or against a hidden global when the first part names one.
     fn resolve_path(&mut self, func_path: &str) -> Result<BoaJsValue, JsError> {
         let trimmed = func_path.trim();
         if trimmed.is_empty() {
             return Err(JsError::Call("empty function path".into()));
         }
 
-        // Start from global object.
-        let mut current = BoaJsValue::new(self.context.global_object().clone());
+        let mut parts = trimmed.split('.').peekable();
+        let mut current = match parts.peek().and_then(|first| self.hidden.get(*first)) {
+            Some(value) => {
+                let value = value.clone();
+                parts.next();
+                value
+            }
+            // Start from global object.
+            None => BoaJsValue::new(self.context.global_object().clone()),
+        };
 
-        for part in trimmed.split('.') {
+        for part in parts {
             let obj = current
                 .as_object()
                 .ok_or_else(|| JsError::Call("intermediate value is not object".into()))?
@@ -127,7 +258,9 @@ impl BoaEngine {
 
 impl JsEngine for BoaEngine {
     fn eval(&mut self, code: &str) -> Result<JsValue, JsError> {
-        match self.context.eval(Source::from_bytes(code)) {
+        let host = self.host.clone();
+        let res = host::with_active(&host, || self.context.eval(Source::from_bytes(code)));
+        match res {
             Ok(v) => self.from_boajs_value(&v),
             Err(e) => Err(JsError::Eval(e.to_string())),
         }
@@ -137,8 +270,8 @@ impl JsEngine for BoaEngine {
         // For Boa, "loading a module" is just evaluating the source in this context.
         // The module itself is expected to attach things to globalThis (e.g.,
         // globalThis.plugin = { init(ctx) { ... }, handle(ctx) { ... } }).
-        self.context
-            .eval(Source::from_bytes(source))
+        let host = self.host.clone();
+        host::with_active(&host, || self.context.eval(Source::from_bytes(source)))
             .map_err(|e| JsError::Eval(e.to_string()))?;
         Ok(())
     }
@@ -159,13 +292,55 @@ impl JsEngine for BoaEngine {
 
         // Use global object as `this`.
         let this = BoaJsValue::new(self.context.global_object().clone());
-        let res = func_obj.call(&this, &js_args, &mut self.context);
+        let host = self.host.clone();
+        let res = host::with_active(&host, || {
+            let value = func_obj.call(&this, &js_args, &mut self.context)?;
+            self.settle(value)
+        });
 
         match res {
             Ok(v) => self.from_boajs_value(&v),
             Err(e) => Err(JsError::Call(e.to_string())),
         }
     }
+
+    fn register_host_fn(&mut self, op: &str, capability: Option<&str>, func: HostFn) {
+        self.host.register(op, capability, func);
+    }
+
+    fn set_host_caller(&mut self, caller: Option<HostCaller>) {
+        self.host.set_caller(caller);
+    }
+
+    fn register_host_caller(&mut self, key: &str, caller: HostCaller) {
+        self.host.register_caller(key, caller);
+    }
+
+    fn take_host_denial(&mut self) -> Option<HostDenial> {
+        self.host.take_denial()
+    }
+
+    fn set_log_scope(&mut self, scope: Option<LogScope>) {
+        self.host.set_log_scope(scope);
+    }
+
+    fn hide_globals(&mut self, names: &[&str]) -> Result<(), JsError> {
+        let global = self.context.global_object().clone();
+        for name in names {
+            let key = PropertyKey::from(js_string!(*name));
+            let value = global
+                .get(key.clone(), &mut self.context)
+                .map_err(|e| JsError::Eval(e.to_string()))?;
+            let deleted = global
+                .delete_property_or_throw(key, &mut self.context)
+                .is_ok();
+            if value.is_undefined() || !deleted {
+                return Err(JsError::Eval(format!("cannot hide global '{name}'")));
+            }
+            self.hidden.insert((*name).to_string(), value);
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -378,6 +553,27 @@ mod tests {
         }
     }
 
+    #[test]
+    fn hidden_globals_are_callable_from_rust_only() {
+        let mut engine = BoaEngine::new();
+        engine
+            .eval("globalThis.secret = { twice: (n) => n * 2 };")
+            .expect("setup should succeed");
+        engine
+            .hide_globals(&["secret"])
+            .expect("hide should succeed");
+
+        assert_eq!(
+            engine.eval("typeof secret").unwrap(),
+            JsValue::string("undefined")
+        );
+        let out = engine
+            .call_function("secret.twice", &[JsValue::number(21.0)])
+            .expect("hidden path should resolve");
+        assert_number(&out, 42.0);
+        assert!(engine.hide_globals(&["missing"]).is_err());
+    }
+
     #[test]
     fn call_function_that_throws_propagates_error() {
         let mut engine = BoaEngine::new();
@@ -0,0 +1,834 @@
+// crates/adapt/src/js/fetch.rs
+
+//! `fetch(url, init)` for plugins.
+//!
+//! A plugin granted the `network` capability may request the hosts its
+//! manifest lists:
+//!
+//! ```toml
+//! permissions = ["network"]
+//!
+//! [fetch]
+//! hosts = ["webmention.io", "*.disqus.com"]
+//! timeout_ms = 5000       # whole request, connect to last byte
+//! max_bytes = 1048576     # response body
+//! ```
+//!
+//! ```js
+//! const res = await fetch("https://webmention.io/api/mentions.jf2?target=" + url);
+//! if (res.ok) {
+//!     const mentions = await res.json();
+//! }
+//! ```
+//!
+//! The promise resolves to a plain object shaped like a web `Response`
+//! (`ok`, `status`, `headers`, `url`, `text()`, `json()`); `init` takes
+//! `method`, `headers` and a string `body`. Requests are HTTP/1.1 over
+//! `http` or `https`, without cookies or compression, and redirects are not
+//! followed. The promise rejects when the host is not listed, the request
+//! fails or runs past the timeout, or the body is over the cap.
+//!
+//! `fetch` hands the request to a thread of its own and returns a pending
+//! promise, so a plugin may have several in flight (up to
+//! [`MAX_IN_FLIGHT`]) and await them together. Hooks may be `async`: the
+//! engine waits for the requests a returned promise depends on, each for
+//! its timeout at most, and settles it before handing the result back.
+//!
+//! Each request runs in a `plugin.fetch` span under the calling hook's, and
+//! carries the headers [`set_trace_headers`] produces (W3C `traceparent`
+//! when traces are exported) unless the plugin set them itself, so the
+//! service it calls can continue the same trace.
+
+use super::host;
+use boa_engine::builtins::promise::ResolvingFunctions;
+use boa_engine::object::builtins::JsPromise;
+use boa_engine::{
+    js_string, Context, JsNativeError, JsResult, JsValue as BoaJsValue, NativeFunction, Source,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::sync::{mpsc, LazyLock, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tracing::{debug, info_span};
+use ureq::tls::{RootCerts, TlsConfig, TlsProvider};
+use ureq::{Agent, AsSendBody};
+use url::Url;
+
+/// Capability tag `fetch` requires (`Capability::Network`).
+pub const NETWORK: &str = "network";
+
+/// Requests one engine may have unsettled at once.
+pub const MAX_IN_FLIGHT: usize = 16;
+
+/// Largest status line and headers a response may have.
+const HEAD_LIMIT: usize = 64 * 1024;
+
+/// How long past its timeout a request is waited for, should a fetcher not
+/// keep to it, before its promise is rejected anyway.
+const STRAGGLER_GRACE: Duration = Duration::from_secs(1);
+
+/// Headers the client sets itself; a plugin's values for them are dropped.
+const MANAGED_HEADERS: [&str; 5] = [
+    "host",
+    "connection",
+    "content-length",
+    "transfer-encoding",
+    "accept-encoding",
+];
+
+/// The `[fetch]` table of a plugin manifest.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FetchPolicy {
+    /// Host names, exact (`"webmention.io"`) or `"*."` for any subdomain
+    /// (`"*.disqus.com"`, which does not match `disqus.com` itself).
+    pub hosts: Vec<String>,
+    pub timeout_ms: u64,
+    pub max_bytes: usize,
+}
+
+impl Default for FetchPolicy {
+    fn default() -> Self {
+        Self {
+            hosts: Vec::new(),
+            timeout_ms: 5_000,
+            max_bytes: 1024 * 1024,
+        }
+    }
+}
+
+impl FetchPolicy {
+    pub fn allows(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        self.hosts.iter().any(|pattern| {
+            let pattern = pattern.to_ascii_lowercase();
+            match pattern.strip_prefix("*.") {
+                Some(domain) => host
+                    .strip_suffix(domain)
+                    .is_some_and(|sub| sub.len() > 1 && sub.ends_with('.')),
+                None => host == pattern,
+            }
+        })
+    }
+
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_ms)
+    }
+}
+
+/// `fetch(url, { method, headers, body })`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct FetchRequest {
+    #[serde(skip)]
+    pub url: String,
+    pub method: String,
+    pub headers: BTreeMap<String, String>,
+    pub body: Option<String>,
+}
+
+impl Default for FetchRequest {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            method: "GET".to_owned(),
+            headers: BTreeMap::new(),
+            body: None,
+        }
+    }
+}
+
+/// What the `fetch` promise resolves to, before the shim adds `ok`,
+/// `text()` and `json()`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FetchResponse {
+    pub url: String,
+    pub status: u16,
+    /// Lowercase names; a repeated header is joined with `", "`.
+    pub headers: BTreeMap<String, String>,
+    pub body: String,
+}
+
+#[derive(Debug, Error)]
+pub enum FetchError {
+    #[error("invalid request: {0}")]
+    Invalid(String),
+
+    #[error("I/O: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("TLS: {0}")]
+    Tls(String),
+
+    #[error("malformed response: {0}")]
+    Protocol(String),
+
+    #[error("response body is over {0} bytes")]
+    TooLarge(usize),
+
+    #[error("timed out")]
+    TimedOut,
+}
+
+/// Carries out a request the binding has already checked against the
+/// caller's policy, on a thread of its own. The engine uses [`HttpFetcher`]
+/// unless told otherwise.
+pub trait Fetcher: Send + Sync {
+    fn fetch(
+        &self,
+        request: &FetchRequest,
+        policy: &FetchPolicy,
+    ) -> Result<FetchResponse, FetchError>;
+}
+
+/// HTTP/1.1 through `ureq`, with the platform's TLS library for `https`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct HttpFetcher;
+
+impl Fetcher for HttpFetcher {
+    fn fetch(
+        &self,
+        request: &FetchRequest,
+        policy: &FetchPolicy,
+    ) -> Result<FetchResponse, FetchError> {
+        let (mut response, body) = self.fetch_bytes(request, policy)?;
+        response.body = String::from_utf8_lossy(&body).into_owned();
+        Ok(response)
+    }
+}
+
+/// Shared by every request, so connections to the same host are reused.
+static AGENT: LazyLock<Agent> = LazyLock::new(|| {
+    let tls = TlsConfig::builder()
+        .provider(TlsProvider::NativeTls)
+        .root_certs(RootCerts::PlatformVerifier)
+        .build();
+    Agent::new_with_config(
+        Agent::config_builder()
+            .http_status_as_error(false)
+            .max_redirects(0)
+            .user_agent("WhisperCMS")
+            .max_response_header_size(HEAD_LIMIT)
+            .tls_config(tls)
+            .build(),
+    )
+});
+
+impl HttpFetcher {
+    /// As [`Fetcher::fetch`], but the body is returned apart, as the bytes
+    /// sent, and `body` is left empty: for downloads that are not text.
+    pub fn fetch_bytes(
+        &self,
+        request: &FetchRequest,
+        policy: &FetchPolicy,
+    ) -> Result<(FetchResponse, Vec<u8>), FetchError> {
+        let url = Url::parse(&request.url)
+            .map_err(|e| FetchError::Invalid(format!("{}: {e}", request.url)))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(FetchError::Invalid(format!(
+                "unsupported scheme '{}'",
+                url.scheme()
+            )));
+        }
+        if url.host_str().is_none() {
+            return Err(FetchError::Invalid(format!("{url} has no host")));
+        }
+
+        let method = request.method.to_ascii_uppercase();
+        if method.is_empty() || !method.bytes().all(|b| b.is_ascii_uppercase()) {
+            return Err(FetchError::Invalid(format!(
+                "bad method '{}'",
+                request.method
+            )));
+        }
+        let mut builder = http::Request::builder()
+            .method(method.as_str())
+            .uri(url.as_str());
+        for (name, value) in &request.headers {
+            if MANAGED_HEADERS.iter().any(|m| name.eq_ignore_ascii_case(m)) {
+                continue;
+            }
+            builder = builder.header(name, value);
+        }
+        let mut response = match &request.body {
+            Some(body) => send(builder.body(body.as_str()), policy)?,
+            None => send(builder.body(()), policy)?,
+        };
+
+        let declared = response
+            .headers()
+            .get(http::header::CONTENT_LENGTH)
+            .and_then(|len| len.to_str().ok()?.parse::<u64>().ok());
+        if declared.is_some_and(|len| len > policy.max_bytes as u64) {
+            return Err(FetchError::TooLarge(policy.max_bytes));
+        }
+
+        let mut headers = BTreeMap::new();
+        for (name, value) in response.headers() {
+            let value = String::from_utf8_lossy(value.as_bytes());
+            headers
+                .entry(name.as_str().to_owned())
+                .and_modify(|v: &mut String| {
+                    v.push_str(", ");
+                    v.push_str(&value);
+                })
+                .or_insert_with(|| value.into_owned());
+        }
+        let body = response
+            .body_mut()
+            .with_config()
+            .limit(policy.max_bytes as u64)
+            .read_to_vec()
+            .map_err(|e| match e {
+                ureq::Error::BodyExceedsLimit(_) => FetchError::TooLarge(policy.max_bytes),
+                e => FetchError::from(e),
+            })?;
+
+        let response = FetchResponse {
+            url: url.to_string(),
+            status: response.status().as_u16(),
+            headers,
+            body: String::new(),
+        };
+        Ok((response, body))
+    }
+}
+
+/// Run `request` on the shared agent, bounded by `policy`'s timeout.
+fn send<S: AsSendBody>(
+    request: Result<http::Request<S>, http::Error>,
+    policy: &FetchPolicy,
+) -> Result<http::Response<ureq::Body>, FetchError> {
+    let request = request.map_err(|e| FetchError::Invalid(e.to_string()))?;
+    let request = AGENT
+        .configure_request(request)
+        .timeout_global(Some(policy.timeout()))
+        .build();
+    Ok(AGENT.run(request)?)
+}
+
+impl From<ureq::Error> for FetchError {
+    fn from(e: ureq::Error) -> Self {
+        match e {
+            ureq::Error::Timeout(_) => FetchError::TimedOut,
+            ureq::Error::Io(e) => match e.kind() {
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => FetchError::TimedOut,
+                _ => FetchError::Io(e),
+            },
+            ureq::Error::BadUri(e) => FetchError::Invalid(e),
+            ureq::Error::Http(e) => FetchError::Invalid(e.to_string()),
+            ureq::Error::Tls(e) => FetchError::Tls(e.to_owned()),
+            e => FetchError::Protocol(e.to_string()),
+        }
+    }
+}
+
+/// Requests an engine has sent and not yet settled.
+pub(crate) struct InFlight {
+    next: Cell<u64>,
+    waiting: RefCell<HashMap<u64, Waiting>>,
+    done_tx: mpsc::Sender<Done>,
+    done_rx: mpsc::Receiver<Done>,
+}
+
+struct Waiting {
+    url: String,
+    resolvers: ResolvingFunctions,
+    /// When the request is given up on, should the fetcher not stop it.
+    deadline: Instant,
+}
+
+type Done = (u64, Result<FetchResponse, FetchError>);
+
+impl Default for InFlight {
+    fn default() -> Self {
+        let (done_tx, done_rx) = mpsc::channel();
+        Self {
+            next: Cell::new(0),
+            waiting: RefCell::new(HashMap::new()),
+            done_tx,
+            done_rx,
+        }
+    }
+}
+
+impl InFlight {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.waiting.borrow().is_empty()
+    }
+
+    fn len(&self) -> usize {
+        self.waiting.borrow().len()
+    }
+
+    fn start(&self, url: &str, resolvers: ResolvingFunctions, timeout: Duration) -> u64 {
+        let id = self.next.get();
+        self.next.set(id + 1);
+        self.waiting.borrow_mut().insert(
+            id,
+            Waiting {
+                url: url.to_owned(),
+                resolvers,
+                deadline: Instant::now() + timeout + STRAGGLER_GRACE,
+            },
+        );
+        id
+    }
+
+    /// Settle the promises of requests that have finished, first waiting
+    /// for one to when `block` is set (or for the earliest to run out of
+    /// time, which rejects it).
+    pub(crate) fn settle(&self, block: bool, context: &mut Context) -> JsResult<()> {
+        let mut done = Vec::new();
+        if block {
+            let deadline = self.waiting.borrow().values().map(|w| w.deadline).min();
+            if let Some(deadline) = deadline {
+                let wait = deadline.saturating_duration_since(Instant::now());
+                match self.done_rx.recv_timeout(wait) {
+                    Ok(finished) => done.push(finished),
+                    Err(_) => {
+                        let now = Instant::now();
+                        let overdue: Vec<u64> = self
+                            .waiting
+                            .borrow()
+                            .iter()
+                            .filter(|(_, w)| w.deadline <= now)
+                            .map(|(id, _)| *id)
+                            .collect();
+                        done.extend(
+                            overdue
+                                .into_iter()
+                                .map(|id| (id, Err(FetchError::TimedOut))),
+                        );
+                    }
+                }
+            }
+        }
+        done.extend(self.done_rx.try_iter());
+
+        for (id, result) in done {
+            // Gone if it ran out of time before the fetcher answered.
+            let Some(waiting) = self.waiting.borrow_mut().remove(&id) else {
+                continue;
+            };
+            let undefined = BoaJsValue::undefined();
+            match result
+                .map_err(|e| e.to_string())
+                .and_then(|response| serde_json::to_value(&response).map_err(|e| e.to_string()))
+            {
+                Ok(json) => {
+                    let value = BoaJsValue::from_json(&json, context)?;
+                    waiting
+                        .resolvers
+                        .resolve
+                        .call(&undefined, &[value], context)?;
+                }
+                Err(e) => {
+                    let error = JsNativeError::error()
+                        .with_message(format!("fetch {}: {e}", waiting.url))
+                        .to_opaque(context);
+                    waiting
+                        .resolvers
+                        .reject
+                        .call(&undefined, &[error.into()], context)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// JS binding
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// `fetch` as plugins see it, over the native `__fetch` (which sends the
+/// request off the JS thread and returns a promise of the raw response).
+const FETCH_SHIM_SRC: &str = r#"
+(function (global) {
+    const native = global.__fetch;
+    // Fixed, like `storage`: no plugin sees another's requests.
+    Object.defineProperty(global, "fetch", { value: function (url, init) {
+        return native(String(url), init).then(function (raw) {
+            return {
+                ok: raw.status >= 200 && raw.status < 300,
+                status: raw.status,
+                url: raw.url,
+                headers: raw.headers,
+                text: function () { return Promise.resolve(raw.body); },
+                json: function () {
+                    return new Promise(function (res) { res(JSON.parse(raw.body)); });
+                },
+            };
+        });
+    } });
+})(globalThis);
+"#;
+
+/// Headers naming the current trace.
+pub type TraceHeaders = fn() -> Vec<(String, String)>;
+
+static TRACE_HEADERS: RwLock<Option<TraceHeaders>> = RwLock::new(None);
+
+/// Add the headers `headers` returns to every plugin request, from now on.
+pub fn set_trace_headers(headers: TraceHeaders) {
+    *TRACE_HEADERS.write().unwrap_or_else(|e| e.into_inner()) = Some(headers);
+}
+
+/// Install `fetch(url, init)` and the `__fetch` it is built on.
+pub(crate) fn install(context: &mut Context) -> JsResult<()> {
+    context.register_global_callable(
+        js_string!("__fetch"),
+        2,
+        NativeFunction::from_fn_ptr(fetch_call),
+    )?;
+    context.eval(Source::from_bytes(FETCH_SHIM_SRC))?;
+    Ok(())
+}
+
+fn fetch_call(_this: &BoaJsValue, args: &[BoaJsValue], ctx: &mut Context) -> JsResult<BoaJsValue> {
+    let url = args
+        .first()
+        .and_then(|v| v.as_string())
+        .map(|s| s.to_std_string_escaped())
+        .ok_or_else(|| JsNativeError::typ().with_message("fetch: url must be a string"))?;
+
+    let mut request: FetchRequest = match args.get(1) {
+        Some(v) if !v.is_undefined() && !v.is_null() => {
+            serde_json::from_value(v.to_json(ctx)?.unwrap_or(Json::Null))
+                .map_err(|e| JsNativeError::typ().with_message(format!("fetch: {e}")))?
+        }
+        _ => FetchRequest::default(),
+    };
+    request.url = url;
+
+    let state = host::active()?;
+    let caller = state.authorize("fetch", NETWORK)?;
+
+    let host = Url::parse(&request.url)
+        .ok()
+        .and_then(|u| u.host_str().map(str::to_owned))
+        .ok_or_else(|| {
+            JsNativeError::typ()
+                .with_message(format!("fetch: '{}' is not an absolute URL", request.url))
+        })?;
+    if !caller.fetch.allows(&host) {
+        return Err(JsNativeError::error()
+            .with_message(format!("fetch: '{}' may not fetch from {host}", caller.id))
+            .into());
+    }
+
+    let span = info_span!(
+        "plugin.fetch",
+        plugin_id = %caller.id,
+        method = %request.method,
+        host = %host
+    );
+    let _entered = span.enter();
+    let trace = *TRACE_HEADERS.read().unwrap_or_else(|e| e.into_inner());
+    for (name, value) in trace.map(|headers| headers()).unwrap_or_default() {
+        if !request
+            .headers
+            .keys()
+            .any(|h| h.eq_ignore_ascii_case(&name))
+        {
+            request.headers.insert(name, value);
+        }
+    }
+
+    if state.fetches().len() >= MAX_IN_FLIGHT {
+        return Err(JsNativeError::error()
+            .with_message(format!(
+                "fetch: '{}' has {MAX_IN_FLIGHT} requests in flight already",
+                caller.id
+            ))
+            .into());
+    }
+
+    let (promise, resolvers) = JsPromise::new_pending(ctx);
+    let id = state
+        .fetches()
+        .start(&request.url, resolvers, caller.fetch.timeout());
+    let done = state.fetches().done_tx.clone();
+    let fetcher = state.fetcher();
+    let policy = caller.fetch;
+    let plugin = caller.id;
+    let thread_span = span.clone();
+    thread::Builder::new()
+        .name("plugin-fetch".into())
+        .spawn(move || {
+            let _entered = thread_span.enter();
+            let started = Instant::now();
+            let result = fetcher.fetch(&request, &policy);
+            debug!(
+                plugin = %plugin,
+                url = %request.url,
+                status = result.as_ref().map(|r| r.status).ok(),
+                elapsed_ms = started.elapsed().as_millis() as u64,
+                "plugin fetch"
+            );
+            let _ = done.send((id, result));
+        })
+        .map_err(|e| JsNativeError::error().with_message(format!("fetch: {e}")))?;
+
+    Ok(promise.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::js::{BoaEngine, HostCaller, JsEngine, JsValue};
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn policies_allow_listed_hosts_and_their_wildcards() {
+        let policy = FetchPolicy {
+            hosts: vec!["webmention.io".into(), "*.disqus.com".into()],
+            ..FetchPolicy::default()
+        };
+        assert!(policy.allows("webmention.io"));
+        assert!(policy.allows("WebMention.io."));
+        assert!(policy.allows("a.disqus.com"));
+        assert!(policy.allows("a.b.disqus.com"));
+        assert!(!policy.allows("disqus.com"));
+        assert!(!policy.allows("evildisqus.com"));
+        assert!(!policy.allows("api.webmention.io"));
+        assert!(!FetchPolicy::default().allows("webmention.io"));
+    }
+
+    /// Answers each connection with the next of `responses`, as raw bytes.
+    fn serve(responses: Vec<&'static str>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for response in responses {
+                let (mut conn, _) = listener.accept().unwrap();
+                let mut buf = [0u8; 4096];
+                let _ = conn.read(&mut buf);
+                conn.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        format!("http://{addr}/")
+    }
+
+    #[test]
+    fn http_fetcher_decodes_bodies_and_enforces_the_cap() {
+        let base = serve(vec![
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 11\r\n\r\n{\"a\":[1,2]}",
+            "HTTP/1.1 201 Created\r\nTransfer-Encoding: chunked\r\nSet-Cookie: a\r\nSet-Cookie: b\r\n\r\n5\r\nhello\r\n6;x=y\r\n world\r\n0\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nContent-Length: 64\r\n\r\n",
+            "HTTP/1.1 200 OK\r\n\r\nthis body runs until the server closes and is too long",
+        ]);
+        let policy = FetchPolicy {
+            max_bytes: 32,
+            ..FetchPolicy::default()
+        };
+        let get = |path: &str| {
+            HttpFetcher.fetch(
+                &FetchRequest {
+                    url: format!("{base}{path}"),
+                    ..FetchRequest::default()
+                },
+                &policy,
+            )
+        };
+
+        let res = get("json").unwrap();
+        assert_eq!(res.status, 200);
+        assert_eq!(res.headers["content-type"], "application/json");
+        assert_eq!(res.body, r#"{"a":[1,2]}"#);
+
+        let res = get("chunked").unwrap();
+        assert_eq!(res.status, 201);
+        assert_eq!(res.body, "hello world");
+        assert_eq!(res.headers["set-cookie"], "a, b");
+
+        assert!(matches!(get("declared"), Err(FetchError::TooLarge(32))));
+        assert!(matches!(get("streamed"), Err(FetchError::TooLarge(32))));
+    }
+
+    struct Canned(Mutex<Vec<FetchRequest>>);
+
+    impl Fetcher for Canned {
+        fn fetch(
+            &self,
+            request: &FetchRequest,
+            _: &FetchPolicy,
+        ) -> Result<FetchResponse, FetchError> {
+            self.0.lock().unwrap().push(request.clone());
+            Ok(FetchResponse {
+                url: request.url.clone(),
+                status: 200,
+                headers: BTreeMap::new(),
+                body: r#"{"count":3}"#.into(),
+            })
+        }
+    }
+
+    #[test]
+    fn plugins_await_fetch_from_allowed_hosts_only() {
+        let mut engine = BoaEngine::new();
+        let canned = Arc::new(Canned(Mutex::new(Vec::new())));
+        engine.set_fetcher(canned.clone());
+        engine
+            .load_module(
+                "p",
+                r#"
+                globalThis.mentions = async (url, init) => {
+                    const res = await fetch(url, init);
+                    return { ok: res.ok, count: (await res.json()).count };
+                };
+                "#,
+            )
+            .unwrap();
+
+        let caller = |capabilities: &[&str]| HostCaller {
+            id: "mentions".into(),
+            capabilities: capabilities.iter().map(|c| c.to_string()).collect(),
+            fetch: FetchPolicy {
+                hosts: vec!["webmention.io".into()],
+                ..FetchPolicy::default()
+            },
+        };
+
+        set_trace_headers(|| vec![("traceparent".into(), "00-4bf9-00f0-01".into())]);
+        engine.set_host_caller(Some(caller(&[NETWORK])));
+        let init = JsValue::object(
+            [("method".to_string(), JsValue::string("POST"))]
+                .into_iter()
+                .collect(),
+        );
+        let out = engine
+            .call_function(
+                "mentions",
+                &[JsValue::string("https://webmention.io/api/count"), init],
+            )
+            .unwrap();
+        assert_eq!(
+            out,
+            JsValue::object(
+                [
+                    ("ok".to_string(), JsValue::bool(true)),
+                    ("count".to_string(), JsValue::number(3.0)),
+                ]
+                .into_iter()
+                .collect()
+            )
+        );
+        assert_eq!(canned.0.lock().unwrap()[0].method, "POST");
+        assert_eq!(
+            canned.0.lock().unwrap()[0].headers["traceparent"],
+            "00-4bf9-00f0-01"
+        );
+
+        let err = engine
+            .call_function("mentions", &[JsValue::string("https://example.com/")])
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("may not fetch from example.com"),
+            "{err}"
+        );
+        assert!(engine.take_host_denial().is_none());
+
+        engine.set_host_caller(Some(caller(&[])));
+        assert!(engine
+            .call_function("mentions", &[JsValue::string("https://webmention.io/")])
+            .is_err());
+        let denial = engine.take_host_denial().unwrap();
+        assert_eq!(
+            (denial.op.as_str(), denial.capability.as_str()),
+            ("fetch", NETWORK)
+        );
+        assert_eq!(canned.0.lock().unwrap().len(), 1);
+    }
+
+    /// Answers after `delay`, or never within a test when it is long.
+    struct Slow(Duration);
+
+    impl Fetcher for Slow {
+        fn fetch(
+            &self,
+            request: &FetchRequest,
+            _: &FetchPolicy,
+        ) -> Result<FetchResponse, FetchError> {
+            thread::sleep(self.0);
+            Ok(FetchResponse {
+                url: request.url.clone(),
+                status: 204,
+                headers: BTreeMap::new(),
+                body: String::new(),
+            })
+        }
+    }
+
+    fn slow_engine(delay: Duration, timeout_ms: u64) -> BoaEngine {
+        let mut engine = BoaEngine::new();
+        engine.set_fetcher(Arc::new(Slow(delay)));
+        engine.set_host_caller(Some(HostCaller {
+            id: "slow".into(),
+            capabilities: [NETWORK.to_string()].into(),
+            fetch: FetchPolicy {
+                hosts: vec!["example.com".into()],
+                timeout_ms,
+                ..FetchPolicy::default()
+            },
+        }));
+        engine
+            .load_module(
+                "p",
+                r#"
+                globalThis.pending = () => {
+                    const p = fetch("https://example.com/");
+                    let settled = false;
+                    p.then(() => { settled = true; });
+                    return settled;
+                };
+                globalThis.both = async () => {
+                    const all = await Promise.all([
+                        fetch("https://example.com/a"),
+                        fetch("https://example.com/b"),
+                    ]);
+                    return all.map((r) => r.status);
+                };
+                "#,
+            )
+            .unwrap();
+        engine
+    }
+
+    #[test]
+    fn fetch_returns_at_once_and_requests_run_side_by_side() {
+        let mut engine = slow_engine(Duration::from_millis(300), 5_000);
+
+        let started = Instant::now();
+        assert_eq!(
+            engine.call_function("pending", &[]).unwrap(),
+            JsValue::bool(false)
+        );
+        assert!(started.elapsed() < Duration::from_millis(300));
+
+        let started = Instant::now();
+        assert_eq!(
+            engine.call_function("both", &[]).unwrap(),
+            JsValue::array(vec![JsValue::number(204.0), JsValue::number(204.0)])
+        );
+        assert!(started.elapsed() < Duration::from_millis(600));
+    }
+
+    #[test]
+    fn a_fetcher_that_overruns_its_timeout_is_given_up_on() {
+        let mut engine = slow_engine(Duration::from_secs(30), 50);
+
+        let started = Instant::now();
+        let err = engine.call_function("both", &[]).unwrap_err();
+        assert!(err.to_string().contains("timed out"), "{err}");
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
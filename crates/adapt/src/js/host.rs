@@ -0,0 +1,260 @@
+// crates/adapt/src/js/host.rs
+
+//! Host API bindings exposed to JS as `__host(op, args)`.
+//!
+//! Each op may name a capability tag (e.g. `"db.write"`). The engine runs JS
+//! on behalf of one caller at a time; an op whose capability the caller was
+//! not granted throws in JS and is recorded as a [`HostDenial`] so the host
+//! can report exactly what was missing.
+//!
+//! The binding is a plain function pointer, so the state it needs (registry,
+//! caller, denial slot) is installed in a thread-local for the duration of a
+//! single engine call. Boa is single-threaded and JS runs synchronously, so
+//! that scope is exactly "the JS currently executing on this engine".
+//! `fetch` and `storage` (see [`super::fetch`], [`super::storage`]) check
+//! their caller through the same state, and `log` ([`super::log`]) reads
+//! its scope and levels from it.
+//!
+//! JS that runs a function written by someone else (a plugin's hook
+//! listener, say) calls it through `__runAs(key, fn, ...args)`: the caller
+//! registered under `key` is in force for that call, then the previous one
+//! again.
+
+use super::fetch::{FetchPolicy, Fetcher, HttpFetcher, InFlight};
+use super::log::{LogLevels, LogScope};
+use super::storage::{KvStore, MemoryKvStore};
+use boa_engine::{js_string, Context, JsNativeError, JsResult, JsValue as BoaJsValue};
+use serde_json::Value as Json;
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap};
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// A host function callable from JS: JSON args in, JSON result out.
+pub type HostFn = Box<dyn Fn(&Json) -> Result<Json, String>>;
+
+struct HostBinding {
+    capability: Option<String>,
+    func: HostFn,
+}
+
+/// Who JS is currently running for, and what it may do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostCaller {
+    pub id: String,
+    pub capabilities: BTreeSet<String>,
+    /// Hosts `fetch` may reach, and its limits.
+    pub fetch: FetchPolicy,
+}
+
+/// A host call refused for lack of a capability.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostDenial {
+    pub caller: String,
+    pub op: String,
+    pub capability: String,
+}
+
+/// Per-engine host state, shared with the thread-local while JS runs.
+#[derive(Default)]
+pub(crate) struct HostState {
+    bindings: RefCell<HashMap<String, HostBinding>>,
+    caller: RefCell<Option<HostCaller>>,
+    denial: RefCell<Option<HostDenial>>,
+    fetcher: RefCell<Option<Arc<dyn Fetcher>>>,
+    fetches: InFlight,
+    storage: RefCell<Option<Arc<dyn KvStore>>>,
+    log_levels: RefCell<Arc<LogLevels>>,
+    log_scope: RefCell<Option<LogScope>>,
+    /// Callers `__runAs` may switch to, by key.
+    callers: RefCell<HashMap<String, HostCaller>>,
+}
+
+impl HostState {
+    pub(crate) fn register(&self, op: &str, capability: Option<&str>, func: HostFn) {
+        self.bindings.borrow_mut().insert(
+            op.to_string(),
+            HostBinding {
+                capability: capability.map(str::to_string),
+                func,
+            },
+        );
+    }
+
+    pub(crate) fn set_caller(&self, caller: Option<HostCaller>) {
+        *self.caller.borrow_mut() = caller;
+    }
+
+    pub(crate) fn caller(&self) -> Option<HostCaller> {
+        self.caller.borrow().clone()
+    }
+
+    pub(crate) fn register_caller(&self, key: &str, caller: HostCaller) {
+        self.callers.borrow_mut().insert(key.to_string(), caller);
+    }
+
+    pub(crate) fn take_denial(&self) -> Option<HostDenial> {
+        self.denial.borrow_mut().take()
+    }
+
+    pub(crate) fn set_fetcher(&self, fetcher: Arc<dyn Fetcher>) {
+        *self.fetcher.borrow_mut() = Some(fetcher);
+    }
+
+    /// What `fetch` requests go through; [`HttpFetcher`] unless replaced.
+    pub(crate) fn fetcher(&self) -> Arc<dyn Fetcher> {
+        self.fetcher
+            .borrow_mut()
+            .get_or_insert_with(|| Arc::new(HttpFetcher))
+            .clone()
+    }
+
+    /// The `fetch` requests this engine is waiting on.
+    pub(crate) fn fetches(&self) -> &InFlight {
+        &self.fetches
+    }
+
+    pub(crate) fn set_storage(&self, store: Arc<dyn KvStore>) {
+        *self.storage.borrow_mut() = Some(store);
+    }
+
+    /// Where `storage` keeps values; memory of this engine's own unless
+    /// replaced.
+    pub(crate) fn storage(&self) -> Arc<dyn KvStore> {
+        self.storage
+            .borrow_mut()
+            .get_or_insert_with(|| Arc::new(MemoryKvStore::default()))
+            .clone()
+    }
+
+    pub(crate) fn set_log_levels(&self, levels: Arc<LogLevels>) {
+        *self.log_levels.borrow_mut() = levels;
+    }
+
+    /// Which `log` events get through.
+    pub(crate) fn log_levels(&self) -> Arc<LogLevels> {
+        self.log_levels.borrow().clone()
+    }
+
+    pub(crate) fn set_log_scope(&self, scope: Option<LogScope>) {
+        *self.log_scope.borrow_mut() = scope;
+    }
+
+    pub(crate) fn log_scope(&self) -> Option<LogScope> {
+        self.log_scope.borrow().clone()
+    }
+
+    /// The current caller, if it holds `capability`; otherwise record the
+    /// denial of `op` and throw.
+    pub(crate) fn authorize(&self, op: &str, capability: &str) -> JsResult<HostCaller> {
+        let caller = self.caller.borrow();
+        match caller.as_ref() {
+            Some(c) if c.capabilities.contains(capability) => Ok(c.clone()),
+            _ => {
+                let who = caller.as_ref().map(|c| c.id.clone()).unwrap_or_default();
+                *self.denial.borrow_mut() = Some(HostDenial {
+                    caller: who.clone(),
+                    op: op.to_string(),
+                    capability: capability.to_string(),
+                });
+                Err(JsNativeError::error()
+                    .with_message(format!(
+                        "'{who}' is missing capability '{capability}' required by '{op}'"
+                    ))
+                    .into())
+            }
+        }
+    }
+}
+
+thread_local! {
+    static ACTIVE: RefCell<Option<Rc<HostState>>> = const { RefCell::new(None) };
+}
+
+/// Run `f` with `state` installed as the active host for `__host` calls.
+pub(crate) fn with_active<R>(state: &Rc<HostState>, f: impl FnOnce() -> R) -> R {
+    let prev = ACTIVE.with(|a| a.borrow_mut().replace(state.clone()));
+    let out = f();
+    ACTIVE.with(|a| *a.borrow_mut() = prev);
+    out
+}
+
+/// The host state of the engine call in progress.
+pub(crate) fn active() -> JsResult<Rc<HostState>> {
+    ACTIVE
+        .with(|a| a.borrow().clone())
+        .ok_or_else(|| JsNativeError::error().with_message("no active host").into())
+}
+
+/// Install the `__host(op, args)` and `__runAs(key, fn, ...args)` globals
+/// on a fresh context.
+pub(crate) fn install(context: &mut Context) -> JsResult<()> {
+    context.register_global_callable(
+        js_string!("__host"),
+        2,
+        boa_engine::NativeFunction::from_fn_ptr(host_call),
+    )?;
+    context.register_global_callable(
+        js_string!("__runAs"),
+        2,
+        boa_engine::NativeFunction::from_fn_ptr(run_as),
+    )
+}
+
+fn run_as(_this: &BoaJsValue, args: &[BoaJsValue], ctx: &mut Context) -> JsResult<BoaJsValue> {
+    let key = args
+        .first()
+        .and_then(|v| v.as_string())
+        .map(|s| s.to_std_string_escaped())
+        .ok_or_else(|| JsNativeError::typ().with_message("__runAs: key must be a string"))?;
+    let func = args
+        .get(1)
+        .and_then(|v| v.as_callable())
+        .ok_or_else(|| JsNativeError::typ().with_message("__runAs: not a function"))?;
+
+    let state = active()?;
+    let caller = state.callers.borrow().get(&key).cloned().ok_or_else(|| {
+        JsNativeError::error().with_message(format!("__runAs: unknown caller '{key}'"))
+    })?;
+    let req_id = state.log_scope().map(|s| s.req_id).unwrap_or_default();
+    let scope = LogScope::plugin(caller.id.as_str(), req_id);
+
+    let prev_caller = state.caller.replace(Some(caller));
+    let prev_scope = state.log_scope.replace(Some(scope));
+    let out = func.call(
+        &BoaJsValue::undefined(),
+        args.get(2..).unwrap_or_default(),
+        ctx,
+    );
+    *state.caller.borrow_mut() = prev_caller;
+    *state.log_scope.borrow_mut() = prev_scope;
+    out
+}
+
+fn host_call(_this: &BoaJsValue, args: &[BoaJsValue], ctx: &mut Context) -> JsResult<BoaJsValue> {
+    let op = args
+        .first()
+        .and_then(|v| v.as_string())
+        .map(|s| s.to_std_string_escaped())
+        .ok_or_else(|| JsNativeError::typ().with_message("__host: op must be a string"))?;
+
+    let payload = match args.get(1) {
+        Some(v) if !v.is_undefined() => v.to_json(ctx)?.unwrap_or(Json::Null),
+        _ => Json::Null,
+    };
+
+    let state = active()?;
+
+    let bindings = state.bindings.borrow();
+    let binding = bindings
+        .get(&op)
+        .ok_or_else(|| JsNativeError::error().with_message(format!("__host: unknown op '{op}'")))?;
+
+    if let Some(cap) = &binding.capability {
+        state.authorize(&op, cap)?;
+    }
+
+    let out = (binding.func)(&payload)
+        .map_err(|e| JsNativeError::error().with_message(format!("{op}: {e}")))?;
+    BoaJsValue::from_json(&out, ctx)
+}
@@ -0,0 +1,257 @@
+// crates/adapt/src/js/log.rs
+
+//! `log` (and `console`) for plugins and themes, as `tracing` events.
+//!
+//! ```js
+//! log.info("mentions fetched", { count: 3, target: ctx.request.path });
+//! log.warn({ msg: "slow upstream", ms: 1800 });
+//! console.error("unexpected", err);   // same as log.error
+//! ```
+//!
+//! Every event has target [`LOG_TARGET`] and is tagged with the extension
+//! it came from (`extension`, `kind`) and the request being handled
+//! (`req_id`); the object, if any, is attached as `fields` JSON. Which
+//! levels get through is decided per extension, not by `RUST_LOG`: the
+//! engine's [`LogLevels`] (from its [`EngineEnv`](super::engine::EngineEnv))
+//! hold a default and overrides by plugin or theme id, and the host lets
+//! everything for `LOG_TARGET` past its own filter.
+
+use super::host;
+use boa_engine::{
+    js_string, Context, JsNativeError, JsResult, JsValue as BoaJsValue, NativeFunction, Source,
+};
+use serde_json::Value as Json;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use tracing::level_filters::LevelFilter;
+use tracing::Level;
+
+/// Target of every extension log event.
+pub const LOG_TARGET: &str = "extension";
+
+/// The JS an engine is running on behalf of, for tagging log events.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogScope {
+    /// Configured plugin or theme id.
+    pub extension: String,
+    /// `"plugin"` or `"theme"`.
+    pub kind: &'static str,
+    pub req_id: String,
+}
+
+impl LogScope {
+    pub fn plugin(id: impl Into<String>, req_id: impl Into<String>) -> Self {
+        Self {
+            extension: id.into(),
+            kind: "plugin",
+            req_id: req_id.into(),
+        }
+    }
+
+    pub fn theme(id: impl Into<String>, req_id: impl Into<String>) -> Self {
+        Self {
+            extension: id.into(),
+            kind: "theme",
+            req_id: req_id.into(),
+        }
+    }
+}
+
+/// The most verbose level logged for each extension.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogLevels {
+    pub default: LevelFilter,
+    pub overrides: BTreeMap<String, LevelFilter>,
+}
+
+impl Default for LogLevels {
+    fn default() -> Self {
+        Self {
+            default: LevelFilter::INFO,
+            overrides: BTreeMap::new(),
+        }
+    }
+}
+
+impl LogLevels {
+    /// Levels by extension id as configured (`"off"`, `"error"`, `"warn"`,
+    /// `"info"`, `"debug"` or `"trace"`); `"*"` sets the default.
+    pub fn parse(config: &BTreeMap<String, String>) -> Result<Self, String> {
+        let mut levels = Self::default();
+        for (id, level) in config {
+            let level = LevelFilter::from_str(level)
+                .map_err(|_| format!("invalid log level {level:?} for {id:?}"))?;
+            if id == "*" {
+                levels.default = level;
+            } else {
+                levels.overrides.insert(id.clone(), level);
+            }
+        }
+        Ok(levels)
+    }
+
+    pub fn level_for(&self, extension: &str) -> LevelFilter {
+        self.overrides
+            .get(extension)
+            .copied()
+            .unwrap_or(self.default)
+    }
+}
+
+const LOG_SHIM_SRC: &str = r#"
+(function (global) {
+    const native = global.__log;
+    const at = function (level) {
+        return function (message, fields) { native(level, message, fields); };
+    };
+    // Fixed, like `storage`: no plugin sees another's log lines.
+    Object.defineProperty(global, "log", { value: Object.freeze({
+        error: at("error"),
+        warn: at("warn"),
+        info: at("info"),
+        debug: at("debug"),
+        trace: at("trace"),
+    }) });
+
+    const text = function (args) {
+        return Array.prototype.map.call(args, function (a) {
+            if (typeof a === "string") return a;
+            if (a instanceof Error) return String(a);
+            try { return JSON.stringify(a); } catch (_) { return String(a); }
+        }).join(" ");
+    };
+    const line = function (level) {
+        return function () { native(level, text(arguments)); };
+    };
+    Object.defineProperty(global, "console", { value: Object.freeze({
+        log: line("info"),
+        info: line("info"),
+        warn: line("warn"),
+        error: line("error"),
+        debug: line("debug"),
+        trace: line("trace"),
+    }) });
+})(globalThis);
+"#;
+
+/// Install `log`, `console` and the `__log(level, message, fields)` they
+/// are built on.
+pub(crate) fn install(context: &mut Context) -> JsResult<()> {
+    context.register_global_callable(
+        js_string!("__log"),
+        3,
+        NativeFunction::from_fn_ptr(log_call),
+    )?;
+    context.eval(Source::from_bytes(LOG_SHIM_SRC))?;
+    Ok(())
+}
+
+fn log_call(_this: &BoaJsValue, args: &[BoaJsValue], ctx: &mut Context) -> JsResult<BoaJsValue> {
+    let level = args
+        .first()
+        .and_then(|v| v.as_string())
+        .map(|s| s.to_std_string_escaped())
+        .and_then(|s| Level::from_str(&s).ok())
+        .ok_or_else(|| JsNativeError::typ().with_message("log: unknown level"))?;
+
+    let state = host::active().ok();
+    let scope = state.as_ref().and_then(|state| state.log_scope());
+    let extension = scope.as_ref().map_or("", |s| s.extension.as_str());
+    let enabled = state
+        .map(|state| state.log_levels().level_for(extension))
+        .unwrap_or(LogLevels::default().default);
+    if level > enabled {
+        return Ok(BoaJsValue::undefined());
+    }
+
+    let json = |v: Option<&BoaJsValue>, ctx: &mut Context| -> JsResult<Option<Json>> {
+        match v {
+            Some(v) if !v.is_undefined() && !v.is_null() => v.to_json(ctx),
+            _ => Ok(None),
+        }
+    };
+    let (message, fields) = match json(args.get(1), ctx)? {
+        Some(Json::String(message)) => (message, json(args.get(2), ctx)?),
+        Some(obj) => {
+            let message = ["msg", "message"]
+                .iter()
+                .find_map(|k| obj.get(k).and_then(Json::as_str))
+                .unwrap_or_default()
+                .to_owned();
+            (message, Some(obj))
+        }
+        None => (String::new(), None),
+    };
+
+    emit(level, scope.as_ref(), &message, fields.as_ref());
+    Ok(BoaJsValue::undefined())
+}
+
+fn emit(level: Level, scope: Option<&LogScope>, message: &str, fields: Option<&Json>) {
+    let extension = scope.map_or("", |s| s.extension.as_str());
+    let kind = scope.map_or("", |s| s.kind);
+    let req_id = scope.map_or("", |s| s.req_id.as_str());
+    let fields = fields.map(Json::to_string).unwrap_or_default();
+    macro_rules! at {
+        ($level:expr) => {
+            tracing::event!(
+                target: LOG_TARGET,
+                $level,
+                extension,
+                kind,
+                req_id,
+                fields = fields.as_str(),
+                "{message}"
+            )
+        };
+    }
+    match level {
+        Level::ERROR => at!(Level::ERROR),
+        Level::WARN => at!(Level::WARN),
+        Level::INFO => at!(Level::INFO),
+        Level::DEBUG => at!(Level::DEBUG),
+        Level::TRACE => at!(Level::TRACE),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_parse_with_a_wildcard_default() {
+        let levels = LogLevels::parse(&BTreeMap::from([
+            ("*".to_owned(), "warn".to_owned()),
+            ("seo".to_owned(), "debug".to_owned()),
+            ("noisy".to_owned(), "off".to_owned()),
+        ]))
+        .unwrap();
+        assert_eq!(levels.level_for("seo"), LevelFilter::DEBUG);
+        assert_eq!(levels.level_for("noisy"), LevelFilter::OFF);
+        assert_eq!(levels.level_for("other"), LevelFilter::WARN);
+        assert!(Level::INFO > levels.level_for("other"));
+        assert!(
+            LogLevels::parse(&BTreeMap::from([("seo".to_owned(), "loud".to_owned())])).is_err()
+        );
+    }
+
+    #[test]
+    fn log_and_console_accept_messages_and_objects() {
+        use crate::js::{BoaEngine, JsEngine};
+
+        let mut engine = BoaEngine::new();
+        engine.set_log_scope(Some(LogScope::plugin("seo", "req-1")));
+        engine
+            .eval(
+                r#"
+                log.info("fetched", { count: 3 });
+                log.warn({ msg: "slow", ms: 1800 });
+                log.error();
+                console.log("a", 1, { b: [2] });
+                console.error(new Error("boom"));
+                "#,
+            )
+            .unwrap();
+        assert!(engine.eval(r#"__log("loud", "x")"#).is_err());
+    }
+}
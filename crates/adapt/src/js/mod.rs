@@ -1,7 +1,15 @@
 pub mod engine;
 pub mod error;
+pub mod fetch;
+pub mod host;
+pub mod log;
+pub mod storage;
 pub mod value;
 
-pub use engine::{BoaEngine, JsEngine};
+pub use engine::{BoaEngine, EngineEnv, JsEngine};
 pub use error::JsError;
+pub use fetch::{FetchPolicy, Fetcher, HttpFetcher};
+pub use host::{HostCaller, HostDenial, HostFn};
+pub use log::{LogLevels, LogScope};
+pub use storage::{KvStore, MemoryKvStore, ScratchKvStore};
 pub use value::JsValue;
@@ -0,0 +1,349 @@
+// crates/adapt/src/js/storage.rs
+
+//! `storage`: a key-value store each plugin has to itself.
+//!
+//! ```js
+//! const hits = (storage.get("hits") ?? 0) + 1;
+//! storage.set("hits", hits);
+//! storage.set("cache:/about", { html, at: Date.now() });
+//! storage.list("cache:");          // ["cache:/about"]
+//! storage.delete("cache:/about");  // true
+//! ```
+//!
+//! Values are anything JSON can hold and persist across requests, reloads
+//! and restarts. Every call is made in the namespace of the plugin JS is
+//! running for (its configured id, from [`HostCaller`](super::HostCaller));
+//! JS only ever passes keys, so one plugin cannot name another's data.
+//! Outside a plugin call (e.g. in a theme) `storage` throws.
+//!
+//! Each engine keeps values where its [`EngineEnv`](super::engine::EngineEnv)
+//! says, so the plugins of two sites in one process never share a store;
+//! an engine given none keeps them in memory of its own.
+
+use super::host;
+use boa_engine::{
+    js_string, Context, JsNativeError, JsResult, JsValue as BoaJsValue, NativeFunction, Source,
+};
+use serde_json::Value as Json;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex};
+
+/// Longest key, in bytes.
+pub const MAX_KEY_BYTES: usize = 256;
+
+/// Largest value, as serialized JSON.
+pub const MAX_VALUE_BYTES: usize = 64 * 1024;
+
+/// Where plugin values are kept. `namespace` is always the calling
+/// plugin's id.
+pub trait KvStore: Send + Sync {
+    fn get(&self, namespace: &str, key: &str) -> Result<Option<Json>, String>;
+
+    fn set(&self, namespace: &str, key: &str, value: Json) -> Result<(), String>;
+
+    /// Whether `key` was set.
+    fn delete(&self, namespace: &str, key: &str) -> Result<bool, String>;
+
+    /// Keys starting with `prefix`, sorted.
+    fn list(&self, namespace: &str, prefix: &str) -> Result<Vec<String>, String>;
+}
+
+/// Values in memory, gone when the process exits.
+#[derive(Debug, Default)]
+pub struct MemoryKvStore {
+    namespaces: Mutex<BTreeMap<String, BTreeMap<String, Json>>>,
+}
+
+impl KvStore for MemoryKvStore {
+    fn get(&self, namespace: &str, key: &str) -> Result<Option<Json>, String> {
+        let namespaces = self.namespaces.lock().map_err(|e| e.to_string())?;
+        Ok(namespaces
+            .get(namespace)
+            .and_then(|ns| ns.get(key))
+            .cloned())
+    }
+
+    fn set(&self, namespace: &str, key: &str, value: Json) -> Result<(), String> {
+        let mut namespaces = self.namespaces.lock().map_err(|e| e.to_string())?;
+        namespaces
+            .entry(namespace.to_owned())
+            .or_default()
+            .insert(key.to_owned(), value);
+        Ok(())
+    }
+
+    fn delete(&self, namespace: &str, key: &str) -> Result<bool, String> {
+        let mut namespaces = self.namespaces.lock().map_err(|e| e.to_string())?;
+        Ok(namespaces
+            .get_mut(namespace)
+            .is_some_and(|ns| ns.remove(key).is_some()))
+    }
+
+    fn list(&self, namespace: &str, prefix: &str) -> Result<Vec<String>, String> {
+        let namespaces = self.namespaces.lock().map_err(|e| e.to_string())?;
+        Ok(namespaces
+            .get(namespace)
+            .map(|ns| {
+                ns.keys()
+                    .filter(|k| k.starts_with(prefix))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+}
+
+/// Reads through to `base`, keeps writes to itself: what plugins see while
+/// they are tried out, so a trial changes nothing they have stored.
+pub struct ScratchKvStore {
+    base: Arc<dyn KvStore>,
+    /// `None` marks a key deleted in the scratch.
+    changes: Mutex<BTreeMap<(String, String), Option<Json>>>,
+}
+
+impl ScratchKvStore {
+    pub fn over(base: Arc<dyn KvStore>) -> Self {
+        Self {
+            base,
+            changes: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    fn changed(&self, namespace: &str, key: &str) -> Result<Option<Option<Json>>, String> {
+        let changes = self.changes.lock().map_err(|e| e.to_string())?;
+        Ok(changes
+            .get(&(namespace.to_owned(), key.to_owned()))
+            .cloned())
+    }
+}
+
+impl KvStore for ScratchKvStore {
+    fn get(&self, namespace: &str, key: &str) -> Result<Option<Json>, String> {
+        match self.changed(namespace, key)? {
+            Some(value) => Ok(value),
+            None => self.base.get(namespace, key),
+        }
+    }
+
+    fn set(&self, namespace: &str, key: &str, value: Json) -> Result<(), String> {
+        let mut changes = self.changes.lock().map_err(|e| e.to_string())?;
+        changes.insert((namespace.to_owned(), key.to_owned()), Some(value));
+        Ok(())
+    }
+
+    fn delete(&self, namespace: &str, key: &str) -> Result<bool, String> {
+        let existed = self.get(namespace, key)?.is_some();
+        let mut changes = self.changes.lock().map_err(|e| e.to_string())?;
+        changes.insert((namespace.to_owned(), key.to_owned()), None);
+        Ok(existed)
+    }
+
+    fn list(&self, namespace: &str, prefix: &str) -> Result<Vec<String>, String> {
+        let mut keys: BTreeSet<String> = self.base.list(namespace, prefix)?.into_iter().collect();
+        let changes = self.changes.lock().map_err(|e| e.to_string())?;
+        for ((ns, key), value) in changes.iter() {
+            if ns != namespace || !key.starts_with(prefix) {
+                continue;
+            }
+            if value.is_some() {
+                keys.insert(key.clone());
+            } else {
+                keys.remove(key);
+            }
+        }
+        Ok(keys.into_iter().collect())
+    }
+}
+
+const STORAGE_SHIM_SRC: &str = r#"
+(function (global) {
+    const native = global.__storage;
+    // Fixed, so no plugin can slip a wrapper in front of another's calls.
+    Object.defineProperty(global, "storage", { value: Object.freeze({
+        get: function (key) { return native("get", key); },
+        set: function (key, value) { native("set", key, value); },
+        delete: function (key) { return native("delete", key); },
+        list: function (prefix) { return native("list", prefix === undefined ? "" : prefix); },
+    }) });
+})(globalThis);
+"#;
+
+/// Install `storage` and the `__storage(op, key, value)` it is built on.
+pub(crate) fn install(context: &mut Context) -> JsResult<()> {
+    context.register_global_callable(
+        js_string!("__storage"),
+        3,
+        NativeFunction::from_fn_ptr(storage_call),
+    )?;
+    context.eval(Source::from_bytes(STORAGE_SHIM_SRC))?;
+    Ok(())
+}
+
+fn storage_call(
+    _this: &BoaJsValue,
+    args: &[BoaJsValue],
+    ctx: &mut Context,
+) -> JsResult<BoaJsValue> {
+    let string_arg = |i: usize, what: &str| {
+        args.get(i)
+            .and_then(|v| v.as_string())
+            .map(|s| s.to_std_string_escaped())
+            .ok_or_else(|| JsNativeError::typ().with_message(format!("storage: {what}")))
+    };
+    let op = string_arg(0, "op must be a string")?;
+    let key = string_arg(1, "key must be a string")?;
+    if op != "list" && (key.is_empty() || key.len() > MAX_KEY_BYTES) {
+        return Err(JsNativeError::range()
+            .with_message(format!("storage: keys are 1 to {MAX_KEY_BYTES} bytes"))
+            .into());
+    }
+
+    let state = host::active()?;
+    let namespace = state
+        .caller()
+        .map(|c| c.id)
+        .ok_or_else(|| JsNativeError::error().with_message("storage: only plugins have storage"))?;
+    let store = state.storage();
+    let failed = |e: String| JsNativeError::error().with_message(format!("storage.{op}: {e}"));
+
+    let out = match op.as_str() {
+        "get" => store
+            .get(&namespace, &key)
+            .map_err(failed)?
+            .unwrap_or(Json::Null),
+        "set" => {
+            let value = match args.get(2) {
+                Some(v) if !v.is_undefined() => v.to_json(ctx)?.unwrap_or(Json::Null),
+                _ => {
+                    return Err(JsNativeError::typ()
+                        .with_message("storage.set: value is undefined")
+                        .into())
+                }
+            };
+            let size = serde_json::to_vec(&value).map_or(0, |b| b.len());
+            if size > MAX_VALUE_BYTES {
+                return Err(JsNativeError::range()
+                    .with_message(format!(
+                        "storage.set: value is {size} bytes, over {MAX_VALUE_BYTES}"
+                    ))
+                    .into());
+            }
+            store.set(&namespace, &key, value).map_err(failed)?;
+            Json::Null
+        }
+        "delete" => Json::Bool(store.delete(&namespace, &key).map_err(failed)?),
+        "list" => Json::from(store.list(&namespace, &key).map_err(failed)?),
+        other => {
+            return Err(JsNativeError::typ()
+                .with_message(format!("storage: unknown op '{other}'"))
+                .into())
+        }
+    };
+    BoaJsValue::from_json(&out, ctx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::js::{BoaEngine, EngineEnv, HostCaller, JsEngine, JsValue};
+
+    fn caller(id: &str) -> Option<HostCaller> {
+        Some(HostCaller {
+            id: id.into(),
+            capabilities: Default::default(),
+            fetch: Default::default(),
+        })
+    }
+
+    #[test]
+    fn each_plugin_sees_only_its_own_namespace() {
+        let mut engine = BoaEngine::new();
+        let store = Arc::new(MemoryKvStore::default());
+        engine.set_storage(store.clone());
+
+        engine.set_host_caller(caller("counter"));
+        engine
+            .eval(
+                r#"
+                storage.set("hits", (storage.get("hits") ?? 0) + 1);
+                storage.set("hits", (storage.get("hits") ?? 0) + 1);
+                storage.set("cache:/a", { html: "<p>a</p>" });
+                storage.set("cache:/b", [1, 2]);
+                "#,
+            )
+            .unwrap();
+        assert_eq!(
+            engine.eval(r#"storage.list("cache:")"#).unwrap(),
+            JsValue::array(vec![
+                JsValue::string("cache:/a"),
+                JsValue::string("cache:/b")
+            ])
+        );
+        assert_eq!(
+            engine.eval(r#"storage.delete("cache:/a")"#).unwrap(),
+            JsValue::bool(true)
+        );
+        assert_eq!(
+            store.get("counter", "hits").unwrap(),
+            Some(serde_json::json!(2))
+        );
+
+        engine.set_host_caller(caller("spy"));
+        assert_eq!(
+            engine.eval(r#"storage.get("hits")"#).unwrap(),
+            JsValue::Null
+        );
+        assert_eq!(
+            engine.eval("storage.list()").unwrap(),
+            JsValue::array(vec![])
+        );
+        engine.eval(r#"storage.set("hits", "mine")"#).unwrap();
+        assert_eq!(
+            store.get("counter", "hits").unwrap(),
+            Some(serde_json::json!(2))
+        );
+
+        assert!(engine.eval(r#"storage.set("", 1)"#).is_err());
+        assert!(engine
+            .eval(r#"storage.set("big", "x".repeat(70000))"#)
+            .is_err());
+        engine.set_host_caller(None);
+        assert!(engine.eval(r#"storage.get("hits")"#).is_err());
+    }
+
+    #[test]
+    fn engines_share_values_only_within_their_env() {
+        let (blog, shop) = (EngineEnv::default(), EngineEnv::default());
+        let mut first = BoaEngine::with_env(blog.clone());
+        first.set_host_caller(caller("counter"));
+        first.eval(r#"storage.set("hits", 1)"#).unwrap();
+
+        let mut rebuilt = BoaEngine::with_env(first.env());
+        rebuilt.set_host_caller(caller("counter"));
+        assert_eq!(
+            rebuilt.eval(r#"storage.get("hits")"#).unwrap(),
+            JsValue::Number(1.0)
+        );
+        let mut other = BoaEngine::with_env(shop.clone());
+        other.set_host_caller(caller("counter"));
+        assert_eq!(other.eval(r#"storage.get("hits")"#).unwrap(), JsValue::Null);
+        assert_eq!(shop.storage.get("counter", "hits").unwrap(), None);
+    }
+
+    #[test]
+    fn a_scratch_store_reads_through_and_keeps_its_writes() {
+        let base = Arc::new(MemoryKvStore::default());
+        base.set("seo", "a", Json::from(1)).unwrap();
+        base.set("seo", "b", Json::from(2)).unwrap();
+        let scratch = ScratchKvStore::over(base.clone());
+
+        scratch.set("seo", "c", Json::from(3)).unwrap();
+        assert!(scratch.delete("seo", "a").unwrap());
+        assert_eq!(scratch.get("seo", "a").unwrap(), None);
+        assert_eq!(scratch.get("seo", "b").unwrap(), Some(Json::from(2)));
+        assert_eq!(scratch.list("seo", "").unwrap(), ["b", "c"]);
+
+        assert_eq!(base.get("seo", "a").unwrap(), Some(Json::from(1)));
+        assert_eq!(base.list("seo", "").unwrap(), ["a", "b"]);
+    }
+}
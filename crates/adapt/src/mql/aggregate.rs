@@ -0,0 +1,406 @@
+// crates/adapt/src/mql/aggregate.rs
+
+// Grouped counts over the documents a filter matches, so a tag cloud, a
+// monthly archive or a category counter does not need every document:
+//
+//   { "by": "tax.tags", "sort": "-count", "limit": 30 }
+//     → [{ "key": "rust", "count": 12 }, { "key": "wasm", "count": 7 }, …]
+//
+//   { "by": "publish.date", "bucket": "month", "sort": "-key",
+//     "max": ["publish.date"] }
+//     → [{ "key": "2026-03", "count": 4,
+//          "max": { "publish.date": "2026-03-28T09:00:00Z" } }, …]
+//
+//   { "sum": ["stats.words"], "min": ["publish.date"] }
+//     → [{ "key": null, "count": 310, "sum": { "stats.words": 412030 },
+//          "min": { "publish.date": "2019-01-04T10:00:00Z" } }]
+//
+// An array field counts a document once under each distinct element, and a
+// document without the field is left out of that grouping. `bucket` (year,
+// month or day) groups RFC 3339 dates by their UTC calendar prefix. Without
+// `by` every document falls into one group whose key is null. The keys of a
+// grouping are the field's distinct values.
+
+use super::error::QueryError;
+use super::eval::get_field_value;
+use super::query::json_cmp;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+use serde_json::{Map, Value as Json};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+
+/// How group keys are derived from a field value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Bucket {
+    /// The value itself.
+    #[default]
+    Value,
+    /// `2026`
+    Year,
+    /// `2026-03`
+    Month,
+    /// `2026-03-28`
+    Day,
+}
+
+/// Order of the rows of a grouping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GroupSort {
+    /// Largest groups first; ties by key.
+    #[default]
+    CountDesc,
+    CountAsc,
+    KeyAsc,
+    KeyDesc,
+}
+
+/// One grouping: which field to group by and what to compute per group.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GroupSpec {
+    /// Field whose values are the group keys; `None` for a single group.
+    pub by: Option<String>,
+    pub bucket: Bucket,
+    /// Fields whose smallest value is reported per group.
+    pub min: Vec<String>,
+    /// Fields whose largest value is reported per group.
+    pub max: Vec<String>,
+    /// Numeric fields totalled per group.
+    pub sum: Vec<String>,
+    pub sort: GroupSort,
+    /// Keep only the first `limit` rows after sorting.
+    pub limit: Option<usize>,
+}
+
+impl GroupSpec {
+    pub fn by(field: impl Into<String>) -> Self {
+        Self {
+            by: Some(field.into()),
+            ..Self::default()
+        }
+    }
+}
+
+/// A group's key and what was computed for it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GroupRow {
+    pub key: Json,
+    pub count: usize,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub min: BTreeMap<String, Json>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub max: BTreeMap<String, Json>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub sum: BTreeMap<String, Json>,
+}
+
+/// Parse a grouping from JSON; a bare string is shorthand for `{ "by": .. }`.
+pub fn parse_group_spec(json: &Json) -> Result<GroupSpec, QueryError> {
+    if let Some(field) = json.as_str() {
+        return Ok(GroupSpec::by(field));
+    }
+    let obj = json
+        .as_object()
+        .ok_or_else(|| QueryError::InvalidFilter("grouping must be an object".into()))?;
+
+    let mut spec = GroupSpec::default();
+    for (key, value) in obj {
+        match key.as_str() {
+            "by" => {
+                spec.by = match value {
+                    Json::Null => None,
+                    Json::String(s) => Some(s.clone()),
+                    _ => return Err(QueryError::InvalidFilter("by expects a field".into())),
+                }
+            }
+            "bucket" => {
+                spec.bucket = match value.as_str() {
+                    Some("value") => Bucket::Value,
+                    Some("year") => Bucket::Year,
+                    Some("month") => Bucket::Month,
+                    Some("day") => Bucket::Day,
+                    _ => {
+                        return Err(QueryError::InvalidFilter(
+                            "bucket expects value, year, month or day".into(),
+                        ))
+                    }
+                }
+            }
+            "min" => spec.min = field_list(key, value)?,
+            "max" => spec.max = field_list(key, value)?,
+            "sum" => spec.sum = field_list(key, value)?,
+            "sort" => {
+                spec.sort = match value.as_str() {
+                    Some("-count") => GroupSort::CountDesc,
+                    Some("count") => GroupSort::CountAsc,
+                    Some("key") => GroupSort::KeyAsc,
+                    Some("-key") => GroupSort::KeyDesc,
+                    _ => {
+                        return Err(QueryError::InvalidSort(
+                            "grouping sort expects count, -count, key or -key".into(),
+                        ))
+                    }
+                }
+            }
+            "limit" => {
+                let n = value.as_u64().ok_or_else(|| {
+                    QueryError::InvalidFilter("limit expects non-negative integer".into())
+                })?;
+                spec.limit = Some(n as usize);
+            }
+            other => {
+                return Err(QueryError::InvalidOperator(format!(
+                    "unsupported grouping option {other}"
+                )))
+            }
+        }
+    }
+    Ok(spec)
+}
+
+/// `["a", "b"]` or a single `"a"`.
+fn field_list(name: &str, value: &Json) -> Result<Vec<String>, QueryError> {
+    match value {
+        Json::String(s) => Ok(vec![s.clone()]),
+        Json::Array(items) => items
+            .iter()
+            .map(|v| {
+                v.as_str()
+                    .map(str::to_owned)
+                    .ok_or_else(|| QueryError::InvalidFilter(format!("{name} expects field names")))
+            })
+            .collect(),
+        _ => Err(QueryError::InvalidFilter(format!(
+            "{name} expects field names"
+        ))),
+    }
+}
+
+/// Accumulates one grouping over documents fed to it one at a time.
+#[derive(Debug)]
+pub struct Aggregator {
+    spec: GroupSpec,
+    /// Keyed by the key's JSON text.
+    groups: HashMap<String, Group>,
+}
+
+#[derive(Debug)]
+struct Group {
+    row: GroupRow,
+    sums: BTreeMap<String, f64>,
+}
+
+impl Aggregator {
+    pub fn new(spec: GroupSpec) -> Self {
+        Self {
+            spec,
+            groups: HashMap::new(),
+        }
+    }
+
+    /// Count `doc` in each of its groups.
+    pub fn add(&mut self, doc: &Json) {
+        for key in self.keys(doc) {
+            let group = self.groups.entry(key.to_string()).or_insert_with(|| Group {
+                row: GroupRow {
+                    key,
+                    count: 0,
+                    min: BTreeMap::new(),
+                    max: BTreeMap::new(),
+                    sum: BTreeMap::new(),
+                },
+                sums: BTreeMap::new(),
+            });
+            group.row.count += 1;
+
+            for field in &self.spec.min {
+                keep(&mut group.row.min, field, doc, Ordering::Less);
+            }
+            for field in &self.spec.max {
+                keep(&mut group.row.max, field, doc, Ordering::Greater);
+            }
+            for field in &self.spec.sum {
+                let n = get_field_value(doc, field)
+                    .and_then(Json::as_f64)
+                    .unwrap_or(0.0);
+                *group.sums.entry(field.clone()).or_default() += n;
+            }
+        }
+    }
+
+    /// The sorted, limited rows.
+    pub fn finish(self) -> Vec<GroupRow> {
+        let mut rows: Vec<GroupRow> = self
+            .groups
+            .into_values()
+            .map(|mut g| {
+                g.row.sum = g.sums.into_iter().map(|(f, n)| (f, number(n))).collect();
+                g.row
+            })
+            .collect();
+
+        let by_key = |a: &GroupRow, b: &GroupRow| json_cmp(Some(&a.key), Some(&b.key));
+        match self.spec.sort {
+            GroupSort::CountDesc => rows.sort_by(|a, b| b.count.cmp(&a.count).then(by_key(a, b))),
+            GroupSort::CountAsc => rows.sort_by(|a, b| a.count.cmp(&b.count).then(by_key(a, b))),
+            GroupSort::KeyAsc => rows.sort_by(by_key),
+            GroupSort::KeyDesc => rows.sort_by(|a, b| by_key(b, a)),
+        }
+        if let Some(limit) = self.spec.limit {
+            rows.truncate(limit);
+        }
+        rows
+    }
+
+    /// Distinct group keys of `doc`.
+    fn keys(&self, doc: &Json) -> Vec<Json> {
+        let Some(field) = &self.spec.by else {
+            return vec![Json::Null];
+        };
+        let values: Vec<&Json> = match get_field_value(doc, field) {
+            None | Some(Json::Null) => return Vec::new(),
+            Some(Json::Array(items)) => items.iter().collect(),
+            Some(v) => vec![v],
+        };
+
+        let mut keys: Vec<Json> = Vec::new();
+        for v in values {
+            if let Some(key) = bucket_key(v, self.spec.bucket) {
+                if !keys.contains(&key) {
+                    keys.push(key);
+                }
+            }
+        }
+        keys
+    }
+}
+
+/// Store `doc`'s `field` under `field` in `seen` if it beats what is there.
+fn keep(seen: &mut BTreeMap<String, Json>, field: &str, doc: &Json, wins: Ordering) {
+    let Some(v) = get_field_value(doc, field).filter(|v| !v.is_null()) else {
+        return;
+    };
+    match seen.get(field) {
+        Some(best) if json_cmp(Some(v), Some(best)) != wins => {}
+        _ => {
+            seen.insert(field.to_owned(), v.clone());
+        }
+    }
+}
+
+fn bucket_key(value: &Json, bucket: Bucket) -> Option<Json> {
+    let format = match bucket {
+        Bucket::Value => return Some(value.clone()),
+        Bucket::Year => "%Y",
+        Bucket::Month => "%Y-%m",
+        Bucket::Day => "%Y-%m-%d",
+    };
+    let s = value.as_str()?;
+    let date = match DateTime::parse_from_rfc3339(s) {
+        Ok(at) => at.with_timezone(&Utc).date_naive(),
+        Err(_) => NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()?,
+    };
+    Some(Json::String(date.format(format).to_string()))
+}
+
+/// Whole totals as integers.
+fn number(n: f64) -> Json {
+    if n.fract() == 0.0 && n.abs() < i64::MAX as f64 {
+        Json::from(n as i64)
+    } else {
+        Json::from(n)
+    }
+}
+
+/// Named groupings from a `{ "name": spec, .. }` object, in name order.
+pub fn parse_facets(json: &Json) -> Result<Vec<(String, GroupSpec)>, QueryError> {
+    let obj: &Map<String, Json> = json
+        .as_object()
+        .ok_or_else(|| QueryError::InvalidFilter("facets must be an object".into()))?;
+    let mut facets: Vec<(String, GroupSpec)> = obj
+        .iter()
+        .map(|(name, spec)| Ok((name.clone(), parse_group_spec(spec)?)))
+        .collect::<Result<_, QueryError>>()?;
+    facets.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(facets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn run(spec: GroupSpec, docs: &[Json]) -> Vec<GroupRow> {
+        let mut agg = Aggregator::new(spec);
+        for doc in docs {
+            agg.add(doc);
+        }
+        agg.finish()
+    }
+
+    fn posts() -> Vec<Json> {
+        vec![
+            json!({ "tax": { "tags": ["rust", "wasm"] }, "publish": { "date": "2026-03-28T09:00:00Z" }, "words": 100 }),
+            json!({ "tax": { "tags": ["rust", "rust"] }, "publish": { "date": "2026-03-02T09:00:00Z" }, "words": 250 }),
+            json!({ "tax": { "tags": ["go"] }, "publish": { "date": "2026-01-15T09:00:00+02:00" }, "words": 40.5 }),
+            json!({ "publish": { "date": "not a date" } }),
+        ]
+    }
+
+    #[test]
+    fn array_fields_count_each_document_once_per_element() {
+        let rows = run(GroupSpec::by("tax.tags"), &posts());
+        let counts: Vec<_> = rows.iter().map(|r| (r.key.clone(), r.count)).collect();
+        assert_eq!(
+            counts,
+            [(json!("rust"), 2), (json!("go"), 1), (json!("wasm"), 1)]
+        );
+    }
+
+    #[test]
+    fn dates_group_by_month_with_min_max_and_sum() {
+        let spec = parse_group_spec(&json!({
+            "by": "publish.date",
+            "bucket": "month",
+            "sort": "-key",
+            "max": "publish.date",
+            "sum": ["words"],
+        }))
+        .unwrap();
+        let rows = run(spec, &posts());
+
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].key, json!("2026-03"));
+        assert_eq!(rows[0].count, 2);
+        assert_eq!(rows[0].max["publish.date"], json!("2026-03-28T09:00:00Z"));
+        assert_eq!(rows[0].sum["words"], json!(350));
+        assert_eq!(rows[1].key, json!("2026-01"));
+        assert_eq!(rows[1].sum["words"], json!(40.5));
+    }
+
+    #[test]
+    fn without_by_everything_is_one_group() {
+        let spec = parse_group_spec(&json!({ "min": ["words"], "limit": 5 })).unwrap();
+        let rows = run(spec, &posts());
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].key, Json::Null);
+        assert_eq!(rows[0].count, 4);
+        assert_eq!(rows[0].min["words"], json!(40.5));
+
+        let row = serde_json::to_value(&rows[0]).unwrap();
+        assert!(row.get("max").is_none());
+    }
+
+    #[test]
+    fn bad_specs_are_rejected() {
+        assert!(parse_group_spec(&json!(1)).is_err());
+        assert!(parse_group_spec(&json!({ "bucket": "week" })).is_err());
+        assert!(parse_group_spec(&json!({ "sort": "size" })).is_err());
+        assert!(parse_group_spec(&json!({ "avg": ["words"] })).is_err());
+        assert!(parse_facets(&json!([])).is_err());
+
+        let facets = parse_facets(&json!({ "tags": "tax.tags", "all": {} })).unwrap();
+        assert_eq!(facets[0].0, "all");
+        assert_eq!(facets[1].1, GroupSpec::by("tax.tags"));
+    }
+}
@@ -1,3 +1,5 @@
+use super::cursor::Cursor;
+use super::pattern::Pattern;
 use serde::{Deserialize, Serialize};
 use serde_json::Value as Json;
 
@@ -19,6 +21,15 @@ pub enum CmpOp {
     Exists(bool),
     Size(i64),
     Not(Box<FieldExpr>),
+    /// Every word of the query appears in the field; see `mql::text`.
+    Text(String),
+    /// The string (or a string element) matches from its start; see
+    /// `mql::pattern`.
+    Regex(Pattern),
+    /// Some element of the array satisfies the filter. Operator-only forms
+    /// such as `{ "$in": [..] }` apply to the element itself and parse to
+    /// field expressions with an empty path.
+    ElemMatch(Box<Filter>),
 }
 
 /// A field-level predicate: path + comparison op.
@@ -50,12 +61,14 @@ pub enum Filter {
 /// - sort: Vec<(field_path, dir: 1|-1)>
 /// - limit: Option<usize>
 /// - skip: Option<usize>
+/// - after: Option<Cursor>, start after this position (see `mql::cursor`)
 ///
 /// The JSON *input* format for plugins is:
 ///   {
 ///     "sort": { "fieldA": 1, "fieldB": -1 },
 ///     "limit": 10,
-///     "skip": 5
+///     "skip": 5,
+///     "after": "7b2273223a…"
 ///   }
 ///
 /// That is parsed into this internal representation by `parse_find_options`.
@@ -64,6 +77,8 @@ pub struct FindOptions {
     pub sort: Vec<(String, i8)>,
     pub limit: Option<usize>,
     pub skip: Option<usize>,
+    #[serde(default)]
+    pub after: Option<Cursor>,
 }
 
 impl Default for FindOptions {
@@ -72,6 +87,7 @@ impl Default for FindOptions {
             sort: Vec::new(),
             limit: None,
             skip: None,
+            after: None,
         }
     }
 }
@@ -98,6 +114,8 @@ mod tests {
             CmpOp::All(vec![json!("tag1"), json!("tag2")]),
             CmpOp::Exists(true),
             CmpOp::Size(3),
+            CmpOp::Text("rust async".into()),
+            CmpOp::Regex(Pattern::new("rust|go").unwrap()),
         ];
 
         for op in ops {
@@ -115,6 +133,8 @@ mod tests {
                 (CmpOp::All(a), CmpOp::All(b)) => assert_eq!(a, b),
                 (CmpOp::Exists(a), CmpOp::Exists(b)) => assert_eq!(a, b),
                 (CmpOp::Size(a), CmpOp::Size(b)) => assert_eq!(a, b),
+                (CmpOp::Text(a), CmpOp::Text(b)) => assert_eq!(a, b),
+                (CmpOp::Regex(a), CmpOp::Regex(b)) => assert_eq!(a, b),
                 _ => panic!("variant mismatch after roundtrip: {:?} vs {:?}", op, back),
             }
         }
@@ -300,6 +320,7 @@ mod tests {
             ],
             limit: Some(10),
             skip: Some(5),
+            after: None,
         };
 
         let v = to_value(&opts).expect("serialize FindOptions");
@@ -371,6 +392,7 @@ mod tests {
             sort: vec![("front_matter.date".to_string(), -1)],
             limit: Some(20),
             skip: Some(0),
+            after: None,
         };
 
         let fv = to_value(&filter).expect("serialize Filter");
@@ -0,0 +1,170 @@
+// crates/adapt/src/mql/collection.rs
+
+// Saved queries ("collections"): a named filter plus find options, defined
+// once and used by name wherever the query is needed:
+//
+//   "recent-posts": { "filter": { "type": "post" },
+//                     "sort": { "publish.date": -1 }, "limit": 5 }
+//   "featured":     { "filter": { "tax.tags": "featured" } }
+//
+// A definition has the same shape as a Content API query body. It is parsed
+// when it is defined, so using it costs no parsing, and a bad definition is
+// refused then rather than when a page first needs it. Cursors (`after`)
+// belong to one page of one caller and are refused in definitions.
+//
+// The registry is a plain map; the host decides where definitions come
+// from and shares one registry between renders, replacing it on change.
+
+use super::ast::{Filter, FindOptions};
+use super::error::QueryError;
+use super::parser::{parse_filter, parse_find_options};
+use serde_json::Value as Json;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// A parsed named query.
+#[derive(Debug, Clone)]
+pub struct SavedQuery {
+    pub name: String,
+    /// The definition as written.
+    pub source: Json,
+    pub filter: Filter,
+    pub options: FindOptions,
+}
+
+impl SavedQuery {
+    /// Parse the definition `source` of the collection `name`.
+    pub fn parse(name: &str, source: &Json) -> Result<Self, QueryError> {
+        let invalid = |reason: &str| QueryError::InvalidCollection(format!("{name}: {reason}"));
+        if !is_valid_name(name) {
+            return Err(invalid("names are lowercase letters, digits, '-' and '_'"));
+        }
+        let Some(obj) = source.as_object() else {
+            return Err(invalid("a definition is an object"));
+        };
+        if obj.get("after").is_some_and(|a| !a.is_null()) {
+            return Err(invalid("a definition cannot start after a cursor"));
+        }
+        let filter = match obj.get("filter") {
+            Some(f) if !f.is_null() => parse_filter(f)?,
+            _ => Filter::And(vec![]),
+        };
+        Ok(Self {
+            name: name.to_owned(),
+            source: source.clone(),
+            filter,
+            options: parse_find_options(source)?,
+        })
+    }
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-' || b == b'_')
+}
+
+/// Saved queries by name.
+#[derive(Debug, Clone, Default)]
+pub struct CollectionRegistry {
+    queries: BTreeMap<String, Arc<SavedQuery>>,
+}
+
+impl CollectionRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse and add `source` as `name`, replacing any earlier definition.
+    pub fn define(&mut self, name: &str, source: &Json) -> Result<Arc<SavedQuery>, QueryError> {
+        let query = Arc::new(SavedQuery::parse(name, source)?);
+        self.queries.insert(name.to_owned(), query.clone());
+        Ok(query)
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Arc<SavedQuery>> {
+        self.queries.remove(name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<SavedQuery>> {
+        self.queries.get(name).cloned()
+    }
+
+    /// Every collection, by name.
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<SavedQuery>> {
+        self.queries.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.queries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mql::eval_filter;
+    use serde_json::json;
+
+    #[test]
+    fn definitions_are_parsed_once_and_replaced_by_name() {
+        let mut registry = CollectionRegistry::new();
+        registry
+            .define(
+                "recent-posts",
+                &json!({ "filter": { "type": "post" }, "sort": { "publish.date": -1 }, "limit": 5 }),
+            )
+            .unwrap();
+        registry.define("everything", &json!({})).unwrap();
+
+        let recent = registry.get("recent-posts").unwrap();
+        assert_eq!(recent.options.sort, vec![("publish.date".to_owned(), -1)]);
+        assert_eq!(recent.options.limit, Some(5));
+        assert!(eval_filter(&recent.filter, &json!({ "type": "post" })));
+        assert!(eval_filter(
+            &registry.get("everything").unwrap().filter,
+            &json!({ "type": "page" })
+        ));
+
+        registry
+            .define("recent-posts", &json!({ "filter": { "type": "note" } }))
+            .unwrap();
+        let recent = registry.get("recent-posts").unwrap();
+        assert!(!eval_filter(&recent.filter, &json!({ "type": "post" })));
+        assert_eq!(recent.options.limit, None);
+        assert_eq!(registry.len(), 2);
+        assert!(registry.remove("everything").is_some());
+        assert!(registry.get("everything").is_none());
+    }
+
+    #[test]
+    fn bad_definitions_are_refused() {
+        let mut registry = CollectionRegistry::new();
+        for (name, source) in [
+            ("Featured", json!({})),
+            ("", json!({})),
+            ("featured", json!([])),
+            ("featured", json!({ "after": "7b7d" })),
+        ] {
+            assert!(
+                matches!(
+                    registry.define(name, &source),
+                    Err(QueryError::InvalidCollection(_))
+                ),
+                "{name} {source}"
+            );
+        }
+        assert!(registry
+            .define("featured", &json!({ "filter": { "a": { "$bogus": 1 } } }))
+            .is_err());
+        assert!(registry
+            .define("featured", &json!({ "sort": { "a": 2 } }))
+            .is_err());
+        assert!(registry.is_empty());
+    }
+}
@@ -0,0 +1,156 @@
+// crates/adapt/src/mql/cursor.rs
+
+// Cursors for keyset pagination:
+//
+//   { "sort": { "publish.date": -1 }, "limit": 20 }
+//     → page 1, plus next = "7b2273223a5b5b…"
+//   { "sort": { "publish.date": -1 }, "limit": 20, "after": "7b2273223a5b5b…" }
+//     → page 2
+//
+// A cursor records the sort it was made for and the sort keys of the last
+// document on its page, followed by that document's `id` (`CURSOR_ID_FIELD`).
+// Paged queries order by the sort keys and then by `id`, so every document
+// has one place in the order, and the next page starts strictly after the
+// cursor. Unlike `skip`, a document added or removed before the cursor does
+// not shift later pages, and a deep page costs no more than the first.
+//
+// The encoding is hex over JSON: opaque to callers, but not signed. A
+// tampered cursor can only pick a different starting point.
+
+use super::error::QueryError;
+use super::eval::get_field_value;
+use domain::hex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value as Json;
+
+/// Field that breaks ties between documents with equal sort keys.
+pub const CURSOR_ID_FIELD: &str = "id";
+
+/// Position just after one document in a sorted result.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cursor {
+    sort: Vec<(String, i8)>,
+    /// Sort keys then the id; `None` where the document had no value.
+    keys: Vec<Option<Json>>,
+}
+
+/// Wire form. A key is `[]` when missing and `[value]` otherwise, so a
+/// missing field and an explicit `null` stay apart.
+#[derive(Serialize, Deserialize)]
+struct Encoded {
+    s: Vec<(String, i8)>,
+    k: Vec<Vec<Json>>,
+}
+
+impl Cursor {
+    /// The cursor just after `doc` in a result sorted by `sort`.
+    pub fn after(doc: &Json, sort: &[(String, i8)]) -> Self {
+        Self {
+            sort: sort.to_vec(),
+            keys: page_keys(doc, sort),
+        }
+    }
+
+    /// The sort this cursor was made for.
+    pub fn sort(&self) -> &[(String, i8)] {
+        &self.sort
+    }
+
+    pub(crate) fn keys(&self) -> &[Option<Json>] {
+        &self.keys
+    }
+
+    pub fn encode(&self) -> String {
+        let encoded = Encoded {
+            s: self.sort.clone(),
+            k: self
+                .keys
+                .iter()
+                .map(|k| k.iter().cloned().collect())
+                .collect(),
+        };
+        let bytes = serde_json::to_vec(&encoded).unwrap_or_default();
+        hex::encode(bytes)
+    }
+
+    pub fn decode(s: &str) -> Result<Self, QueryError> {
+        let invalid = || QueryError::InvalidCursor(s.chars().take(32).collect());
+        let bytes = hex::decode(s).ok_or_else(invalid)?;
+        let encoded: Encoded = serde_json::from_slice(&bytes).map_err(|_| invalid())?;
+        if encoded.k.len() != encoded.s.len() + 1 || encoded.k.iter().any(|k| k.len() > 1) {
+            return Err(invalid());
+        }
+        Ok(Self {
+            sort: encoded.s,
+            keys: encoded
+                .k
+                .into_iter()
+                .map(|k| k.into_iter().next())
+                .collect(),
+        })
+    }
+}
+
+impl Serialize for Cursor {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.encode())
+    }
+}
+
+impl<'de> Deserialize<'de> for Cursor {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        Cursor::decode(&encoded).map_err(serde::de::Error::custom)
+    }
+}
+
+/// `sort` followed by the id, ascending: the order paged queries use.
+pub(crate) fn page_sort(sort: &[(String, i8)]) -> Vec<(String, i8)> {
+    let mut keys = sort.to_vec();
+    keys.push((CURSOR_ID_FIELD.to_owned(), 1));
+    keys
+}
+
+/// The values of `page_sort(sort)` in `doc`.
+pub(crate) fn page_keys(doc: &Json, sort: &[(String, i8)]) -> Vec<Option<Json>> {
+    page_sort(sort)
+        .iter()
+        .map(|(field, _)| get_field_value(doc, field).cloned())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn cursors_roundtrip_and_keep_missing_apart_from_null() {
+        let sort = vec![("publish.date".to_owned(), -1), ("nav.order".to_owned(), 1)];
+        let doc = json!({ "id": "/posts/a", "publish": { "date": null } });
+        let cursor = Cursor::after(&doc, &sort);
+
+        let back = Cursor::decode(&cursor.encode()).unwrap();
+        assert_eq!(back, cursor);
+        assert_eq!(back.sort(), sort.as_slice());
+        assert_eq!(
+            back.keys(),
+            [Some(Json::Null), None, Some(json!("/posts/a"))]
+        );
+    }
+
+    #[test]
+    fn malformed_cursors_are_rejected() {
+        for bad in ["", "zz", "7b", "abc", &hex(r#"{"s":[],"k":[]}"#)] {
+            assert!(
+                matches!(Cursor::decode(bad), Err(QueryError::InvalidCursor(_))),
+                "{bad}"
+            );
+        }
+        assert!(Cursor::decode(&hex(r#"{"s":[],"k":[["/a"]]}"#)).is_ok());
+    }
+
+    fn hex(s: &str) -> String {
+        hex::encode(s)
+    }
+}
@@ -11,6 +11,15 @@ pub enum QueryError {
     #[error("invalid sort spec: {0}")]
     InvalidSort(String),
 
+    #[error("invalid cursor: {0}")]
+    InvalidCursor(String),
+
+    #[error("invalid collection: {0}")]
+    InvalidCollection(String),
+
+    #[error("query budget exceeded: {0}")]
+    BudgetExceeded(String),
+
     #[error("json error: {0}")]
     Json(#[from] serde_json::Error),
 
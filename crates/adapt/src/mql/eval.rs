@@ -3,11 +3,17 @@
 use serde_json::Value as Json;
 
 use crate::mql::ast::{CmpOp, FieldExpr, Filter};
+use crate::mql::text::text_matches;
 
 /// Resolve a dotted field path (e.g. "front_matter.tags") into a nested JSON value.
 ///
 /// Returns `None` if any segment is missing.
+/// The empty path is the document itself (`$elemMatch` operators on the
+/// element).
 fn field_value<'a>(doc: &'a Json, path: &str) -> Option<&'a Json> {
+    if path.is_empty() {
+        return Some(doc);
+    }
     let mut current = doc;
     for part in path.split('.') {
         current = current.get(part)?;
@@ -110,6 +116,25 @@ fn eval_cmp(op: &CmpOp, actual: Option<&Json>) -> bool {
             _ => false,
         },
 
+        // { field: { $text: "words" } }
+        Text(query) => actual.is_some_and(|v| text_matches(v, query)),
+
+        // { field: { $regex: "pattern" } } on strings or string arrays
+        Regex(pattern) => match actual {
+            Some(Json::String(s)) => pattern.is_match(s),
+            Some(Json::Array(arr)) => arr
+                .iter()
+                .filter_map(Json::as_str)
+                .any(|s| pattern.is_match(s)),
+            _ => false,
+        },
+
+        // { field: { $elemMatch: { .. } } } for array fields
+        ElemMatch(inner) => match actual {
+            Some(Json::Array(arr)) => arr.iter().any(|el| eval_filter(inner, el)),
+            _ => false,
+        },
+
         // { field: { $not: { <cmp expr> } } }
         Not(inner) => {
             // `inner` is a FieldExpr over the *same* field; we pass the already
@@ -353,6 +378,61 @@ mod tests {
         assert!(!eval_filter(&f, &doc2));
     }
 
+    // ─────────────────────────────────────────────────────────────
+    // Regex / ElemMatch
+    // ─────────────────────────────────────────────────────────────
+
+    #[test]
+    fn eval_regex_on_strings_and_string_arrays() {
+        use crate::mql::pattern::Pattern;
+
+        let doc = json!({ "slug": "rust-async", "tags": ["web", "rustlang"], "n": 1 });
+        let re = |p: &str| CmpOp::Regex(Pattern::new(p).unwrap());
+
+        assert!(eval_filter(&field_filter("slug", re("rust")), &doc));
+        assert!(!eval_filter(&field_filter("slug", re("async")), &doc));
+        assert!(eval_filter(&field_filter("tags", re("rust")), &doc));
+        assert!(!eval_filter(&field_filter("n", re("1")), &doc));
+        assert!(!eval_filter(&field_filter("missing", re(".*")), &doc));
+    }
+
+    #[test]
+    fn eval_elem_match_on_scalar_and_object_arrays() {
+        let doc = json!({
+            "tags": ["rust", "wasm"],
+            "authors": [
+                { "name": "ana", "role": "writer" },
+                { "name": "bo", "role": "editor", "since": 2019 }
+            ]
+        });
+
+        let in_tags = |v: Json| {
+            field_filter(
+                "tags",
+                CmpOp::ElemMatch(Box::new(field_filter("", CmpOp::In(vec![v])))),
+            )
+        };
+        assert!(eval_filter(&in_tags(json!("wasm")), &doc));
+        assert!(!eval_filter(&in_tags(json!("go")), &doc));
+
+        // Both conditions must hold for the same element.
+        let editor_before = |year: i64| {
+            field_filter(
+                "authors",
+                CmpOp::ElemMatch(Box::new(Filter::And(vec![
+                    field_filter("role", CmpOp::Eq(json!("editor"))),
+                    field_filter("since", CmpOp::Lt(json!(year))),
+                ]))),
+            )
+        };
+        assert!(eval_filter(&editor_before(2020), &doc));
+        assert!(!eval_filter(&editor_before(2019), &doc));
+
+        // Not an array -> false
+        let doc2 = json!({ "tags": "wasm" });
+        assert!(!eval_filter(&in_tags(json!("wasm")), &doc2));
+    }
+
     // ─────────────────────────────────────────────────────────────
     // Exists
     // ─────────────────────────────────────────────────────────────
@@ -1,4 +1,5 @@
 // crates/adapt/src/mql/index.rs
+use super::text::{text_index_key, tokenize};
 use anyhow::Result as AnyResult;
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
@@ -23,6 +24,9 @@ use std::hash::Hash;
 #[derive(Debug, Clone)]
 pub struct IndexConfig {
     fields: HashSet<String>,
+    /// Array fields indexed one entry per element rather than as a whole
+    /// value, so `$elemMatch` equality can use them.
+    element_fields: HashSet<String>,
 }
 
 impl IndexConfig {
@@ -44,9 +48,30 @@ impl IndexConfig {
     {
         Self {
             fields: fields.into_iter().map(Into::into).collect(),
+            element_fields: HashSet::new(),
         }
     }
 
+    /// Mark array fields whose index holds each element, as `IndexRecord`
+    /// does for `tax.*`. They are indexed too.
+    pub fn with_element_fields<I, S>(mut self, fields: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        for field in fields {
+            let field = field.into();
+            self.fields.insert(field.clone());
+            self.element_fields.insert(field);
+        }
+        self
+    }
+
+    /// Returns true if this field's index holds its array elements.
+    pub fn indexes_elements(&self, field: &str) -> bool {
+        self.element_fields.contains(field)
+    }
+
     /// Returns true if this field has an index defined.
     pub fn is_indexed(&self, field: &str) -> bool {
         self.fields.contains(field)
@@ -124,6 +149,15 @@ pub trait IndexBackend {
     ) -> Option<HashSet<Self::Id>> {
         None
     }
+
+    /// Lookup IDs whose `field` contains every one of `words` (already
+    /// tokenized; see `mql::text`).
+    ///
+    /// Returns:
+    /// - `Some(HashSet<Id>)` if the backend keeps an inverted index of
+    ///   this field's words.
+    /// - `None` if not supported / not indexed.
+    async fn lookup_text(&self, field: &str, words: &[String]) -> Option<HashSet<Self::Id>>;
 }
 
 // ─────────────────────────────────────────────────────────────────────────────
@@ -159,15 +193,74 @@ pub struct IndexRecord {
 pub struct ContentFields {
     pub title: Option<String>,
     pub section: Option<String>,
+    /// Short description; only its words are indexed, for `$text`.
+    pub summary: Option<String>,
+}
+
+impl ContentFields {
+    /// The fields searched by `$text`, with their word index keys.
+    fn text_fields(&self) -> impl Iterator<Item = (&'static str, &str)> + '_ {
+        [
+            ("content.title", self.title.as_deref()),
+            ("content.summary", self.summary.as_deref()),
+        ]
+        .into_iter()
+        .filter_map(|(field, text)| Some((text_index_key(field)?, text?)))
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct PublishFields {
     pub status: Option<String>,
-    /// ISO-8601 publish date
+    /// ISO-8601 publish date; while it is in the future the content is
+    /// hidden from queries and lookups, as if not yet published.
     pub date: Option<String>,
     /// ISO-8601 modified date
     pub modified: Option<String>,
+    /// ISO-8601 expiry; once passed, the content drops out of queries and
+    /// lookups. Clearing the field re-publishes it.
+    pub unpublish_at: Option<String>,
+}
+
+/// `publish.status` values the public may see. A record without a status
+/// is public too; drafts, pending, private and scheduled content are not.
+pub const PUBLIC_STATUSES: &[&str] = &["published"];
+
+impl PublishFields {
+    /// True when `status` is unset or one of `PUBLIC_STATUSES`.
+    pub fn is_public(&self) -> bool {
+        self.status
+            .as_deref()
+            .is_none_or(|s| PUBLIC_STATUSES.contains(&s))
+    }
+
+    /// True once `unpublish_at` is set, parseable, and not after `now`.
+    ///
+    /// An unparseable value never expires the content, mirroring how
+    /// `timestamp()` ignores a bad `publish.date`.
+    pub fn is_expired_at(&self, now: DateTime<Utc>) -> bool {
+        self.unpublish_at().is_some_and(|at| at <= now)
+    }
+
+    /// True while `date` is set, parseable, and after `now`.
+    pub fn is_scheduled_at(&self, now: DateTime<Utc>) -> bool {
+        self.publish_at().is_some_and(|at| at > now)
+    }
+
+    /// `date`, if it parses.
+    pub fn publish_at(&self) -> Option<DateTime<Utc>> {
+        parse_instant(self.date.as_deref())
+    }
+
+    /// `unpublish_at`, if it parses.
+    pub fn unpublish_at(&self) -> Option<DateTime<Utc>> {
+        parse_instant(self.unpublish_at.as_deref())
+    }
+}
+
+fn parse_instant(s: Option<&str>) -> Option<DateTime<Utc>> {
+    s.and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+        .map(|at| at.with_timezone(&Utc))
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -200,6 +293,19 @@ pub struct AuthorFields {
 }
 
 impl IndexRecord {
+    /// Whether this record should be visible at `now`: published (its
+    /// `publish.date` has come) and not yet expired.
+    pub fn is_live_at(&self, now: DateTime<Utc>) -> bool {
+        !self.publish.is_scheduled_at(now) && !self.publish.is_expired_at(now)
+    }
+
+    /// `is_live_at(now)` with a public `publish.status`: what anonymous
+    /// readers may see. Drafts stay live for previews, so lookups check only
+    /// `is_live_at` and the public surfaces check this.
+    pub fn is_public_at(&self, now: DateTime<Utc>) -> bool {
+        self.is_live_at(now) && self.publish.is_public()
+    }
+
     /// Build an IndexRecord from a raw document JSON and explicit id.
     ///
     /// Missing fields simply become `None` / `Vec::new()`.
@@ -236,12 +342,17 @@ impl IndexRecord {
         let content = ContentFields {
             title: as_string(get_field_value(doc, "content.title")),
             section: as_string(get_field_value(doc, "content.section")),
+            summary: as_string(get_field_value(doc, "content.summary")),
         };
 
         let publish = PublishFields {
             status: as_string(get_field_value(doc, "publish.status")),
             date: as_string(get_field_value(doc, "publish.date")),
             modified: as_string(get_field_value(doc, "publish.modified")),
+            unpublish_at: as_string(
+                get_field_value(doc, "publish.unpublish_at")
+                    .or_else(|| get_field_value(doc, "unpublish_at")),
+            ),
         };
 
         let nav = NavFields {
@@ -327,6 +438,13 @@ impl IjIndexable for IndexRecord {
             )));
         }
 
+        // text.*: one entry per distinct word of the searchable fields
+        for (key, text) in self.content.text_fields() {
+            for word in tokenize(text) {
+                out.push(Box::new(StringField::new(key, word)));
+            }
+        }
+
         // publish.*
         if let Some(status) = &self.publish.status {
             out.push(Box::new(StringField::new("publish.status", status.clone())));
@@ -340,6 +458,12 @@ impl IjIndexable for IndexRecord {
                 modified.clone(),
             )));
         }
+        if let Some(at) = &self.publish.unpublish_at {
+            out.push(Box::new(StringField::new(
+                "publish.unpublish_at",
+                at.clone(),
+            )));
+        }
 
         // nav.*
         if let Some(order) = self.nav.menu_order {
@@ -419,6 +543,16 @@ impl IjIndexable for IndexRecord {
                 self.content.section.as_ref().map(|v| v.cmp(&f.value))
             }
 
+            // text.*: a record "contains" each word of its text fields
+            "text.content.title" | "text.content.summary" => {
+                let f = i.as_any().downcast_ref::<StringField>()?;
+                let (_, text) = self
+                    .content
+                    .text_fields()
+                    .find(|(key, _)| *key == i.key())?;
+                tokenize(text).contains(&f.value).then_some(Ordering::Equal)
+            }
+
             // publish.*
             "publish.status" => {
                 let f = i.as_any().downcast_ref::<StringField>()?;
@@ -432,6 +566,10 @@ impl IjIndexable for IndexRecord {
                 let f = i.as_any().downcast_ref::<StringField>()?;
                 self.publish.modified.as_ref().map(|v| v.cmp(&f.value))
             }
+            "publish.unpublish_at" => {
+                let f = i.as_any().downcast_ref::<StringField>()?;
+                self.publish.unpublish_at.as_ref().map(|v| v.cmp(&f.value))
+            }
 
             // nav.*
             "nav.menu_order" => {
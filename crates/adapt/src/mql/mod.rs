@@ -1,11 +1,19 @@
+pub mod aggregate;
 pub mod ast;
+pub mod collection;
+pub mod cursor;
 pub mod error;
 pub mod eval;
 pub mod index;
 pub mod parser;
+pub mod pattern;
 pub mod query;
+pub mod text;
 
+pub use aggregate::{parse_facets, parse_group_spec, Bucket, GroupRow, GroupSort, GroupSpec};
 pub use ast::{CmpOp, FieldExpr, Filter, FindOptions};
+pub use collection::{CollectionRegistry, SavedQuery};
+pub use cursor::Cursor;
 pub use error::QueryError;
 pub use eval::eval_filter;
 pub use index::{
@@ -14,4 +22,7 @@ pub use index::{
     // These will exist once you add the skeleton in `index.rs`:
     JsonStore,
 };
-pub use query::{execute_query, QueryPlanner, QueryResult};
+pub use query::{
+    aggregate_query, execute_query, governed, page_query, stream_query, QueryBudget, QueryGovernor,
+    QueryPlan, QueryPlanner, QueryResult, QueryResultPage,
+};
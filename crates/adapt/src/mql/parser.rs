@@ -1,7 +1,10 @@
 // crates/adapt/src/mql/parser.rs
 
 use super::ast::{CmpOp, FieldExpr, Filter, FindOptions};
+use super::cursor::Cursor;
 use super::error::QueryError;
+use super::pattern::Pattern;
+use super::text::tokenize;
 use serde_json::Value as Json;
 
 /// Parse a Mongo-style JSON filter into a Filter AST.
@@ -144,6 +147,24 @@ fn parse_cmp_op(_path: &str, op_name: &str, value: &Json) -> Result<CmpOp, Query
             };
             Ok(Size(n))
         }
+        "$text" => {
+            let query = value
+                .as_str()
+                .ok_or_else(|| QueryError::InvalidFilter("$text expects string".into()))?;
+            if tokenize(query).is_empty() {
+                return Err(QueryError::InvalidFilter(
+                    "$text expects at least one word".into(),
+                ));
+            }
+            Ok(Text(query.to_owned()))
+        }
+        "$regex" => {
+            let source = value
+                .as_str()
+                .ok_or_else(|| QueryError::InvalidFilter("$regex expects string".into()))?;
+            Ok(Regex(Pattern::new(source)?))
+        }
+        "$elemMatch" => Ok(ElemMatch(Box::new(parse_elem_match(value)?))),
         "$not" => {
             // $not value is a single-field expression object
             let inner_obj = value
@@ -169,6 +190,27 @@ fn parse_cmp_op(_path: &str, op_name: &str, value: &Json) -> Result<CmpOp, Query
     }
 }
 
+/// `$elemMatch` takes either operators on the element itself
+/// (`{ "$in": ["rust", "go"] }`) or a filter over object elements
+/// (`{ "role": "editor", "since": { "$lt": 2020 } }`), not both.
+fn parse_elem_match(value: &Json) -> Result<Filter, QueryError> {
+    let obj = value
+        .as_object()
+        .filter(|o| !o.is_empty())
+        .ok_or_else(|| QueryError::InvalidFilter("$elemMatch expects object".into()))?;
+
+    let is_op = |k: &String| k.starts_with('$') && k != "$and" && k != "$or";
+    if obj.keys().all(is_op) {
+        parse_field_expr("", value)
+    } else if obj.keys().any(is_op) {
+        Err(QueryError::InvalidFilter(
+            "$elemMatch cannot mix operators and fields".into(),
+        ))
+    } else {
+        parse_filter(value)
+    }
+}
+
 /// Parse FindOptions from a JSON object (typically coming from JS).
 ///
 /// {
@@ -232,6 +274,17 @@ pub fn parse_find_options(json: &Json) -> Result<FindOptions, QueryError> {
         }
     }
 
+    // after: a cursor from an earlier page
+    match obj.get("after") {
+        None | Some(Json::Null) => {}
+        Some(Json::String(s)) => opts.after = Some(Cursor::decode(s)?),
+        Some(_) => {
+            return Err(QueryError::InvalidCursor(
+                "after must be a cursor string".into(),
+            ))
+        }
+    }
+
     Ok(opts)
 }
 
@@ -453,6 +506,56 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_text_requires_a_string_with_words() {
+        let f = parse_filter(&json!({ "content.title": { "$text": "async rust" } })).unwrap();
+        match &as_field(&f).op {
+            CmpOp::Text(q) => assert_eq!(q, "async rust"),
+            other => panic!("expected Text, got: {:?}", other),
+        }
+
+        assert!(matches!(
+            parse_filter(&json!({ "content.title": { "$text": 1 } })),
+            Err(QueryError::InvalidFilter(_))
+        ));
+        assert!(matches!(
+            parse_filter(&json!({ "content.title": { "$text": " - " } })),
+            Err(QueryError::InvalidFilter(_))
+        ));
+    }
+
+    #[test]
+    fn parse_regex_and_elem_match() {
+        let f = parse_filter(&json!({ "slug": { "$regex": "2026-" } })).unwrap();
+        assert!(matches!(&as_field(&f).op, CmpOp::Regex(p) if p.as_str() == "2026-"));
+        assert!(parse_filter(&json!({ "slug": { "$regex": "(" } })).is_err());
+        assert!(parse_filter(&json!({ "slug": { "$regex": 1 } })).is_err());
+
+        let f =
+            parse_filter(&json!({ "tax.tags": { "$elemMatch": { "$in": ["rust"] } } })).unwrap();
+        match &as_field(&f).op {
+            CmpOp::ElemMatch(inner) => {
+                assert_eq!(as_field(inner).path, "");
+                assert!(matches!(as_field(inner).op, CmpOp::In(_)));
+            }
+            other => panic!("expected ElemMatch, got: {:?}", other),
+        }
+
+        let f =
+            parse_filter(&json!({ "authors": { "$elemMatch": { "role": "editor" } } })).unwrap();
+        match &as_field(&f).op {
+            CmpOp::ElemMatch(inner) => assert_eq!(as_field(inner).path, "role"),
+            other => panic!("expected ElemMatch, got: {:?}", other),
+        }
+
+        for bad in [json!({}), json!("rust"), json!({ "$eq": "a", "role": "b" })] {
+            assert!(matches!(
+                parse_filter(&json!({ "tax.tags": { "$elemMatch": bad } })),
+                Err(QueryError::InvalidFilter(_))
+            ));
+        }
+    }
+
     #[test]
     fn parse_in_nin_all_require_array() {
         let bad_in = json!({ "tags": { "$in": "not-array" } });
@@ -614,6 +717,18 @@ mod tests {
         assert!(opts.skip.is_none());
     }
 
+    #[test]
+    fn parse_find_options_after_takes_a_cursor() {
+        let cursor = Cursor::after(&json!({ "id": "/a", "n": 3 }), &[("n".into(), -1)]);
+        let opts = parse_find_options(&json!({ "after": cursor.encode() })).unwrap();
+        assert_eq!(opts.after, Some(cursor));
+
+        for bad in [json!({ "after": 5 }), json!({ "after": "nope" })] {
+            let err = parse_find_options(&bad).unwrap_err();
+            assert!(matches!(err, QueryError::InvalidCursor(_)));
+        }
+    }
+
     #[test]
     fn parse_find_options_limit_and_skip_ignore_non_numeric() {
         let json = json!({
@@ -0,0 +1,113 @@
+// crates/adapt/src/mql/pattern.rs
+
+// Patterns for `$regex`:
+//
+//   { "slug": { "$regex": "2026-0[1-3]-" } }
+//   { "tax.tags": { "$regex": "(?i)rust" } }
+//
+// A pattern is anchored at the start of the value, as if it began with `^`,
+// so `rust` matches "rust-async" but not "trust". Add `.*` in front to match
+// anywhere. On an array field it matches when any string element does.
+//
+// Filters come from themes, plugins and the public Content API, so patterns
+// are bounded: at most `MAX_PATTERN_LEN` bytes, `NEST_LIMIT` levels of
+// nesting, and a compiled program of at most `SIZE_LIMIT` bytes. The `regex`
+// crate matches in linear time, so no pattern can make a query backtrack.
+
+use super::error::QueryError;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+/// Longest pattern accepted, in bytes.
+pub const MAX_PATTERN_LEN: usize = 256;
+
+/// Deepest nesting of groups and repetitions accepted.
+pub const NEST_LIMIT: u32 = 16;
+
+/// Largest compiled program accepted, in bytes.
+pub const SIZE_LIMIT: usize = 64 * 1024;
+
+/// A compiled, start-anchored `$regex` pattern.
+#[derive(Clone)]
+pub struct Pattern {
+    source: String,
+    re: Regex,
+}
+
+impl Pattern {
+    pub fn new(source: &str) -> Result<Self, QueryError> {
+        if source.len() > MAX_PATTERN_LEN {
+            return Err(QueryError::InvalidFilter(format!(
+                "$regex longer than {MAX_PATTERN_LEN} bytes"
+            )));
+        }
+        let re = RegexBuilder::new(&format!("^(?:{source})"))
+            .nest_limit(NEST_LIMIT)
+            .size_limit(SIZE_LIMIT)
+            .dfa_size_limit(SIZE_LIMIT)
+            .build()
+            .map_err(|e| QueryError::InvalidFilter(format!("$regex: {e}")))?;
+        Ok(Self {
+            source: source.to_owned(),
+            re,
+        })
+    }
+
+    /// The pattern as written, without the implied anchor.
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    pub fn is_match(&self, s: &str) -> bool {
+        self.re.is_match(s)
+    }
+}
+
+impl fmt::Debug for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Pattern").field(&self.source).finish()
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+impl Serialize for Pattern {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let source = String::deserialize(deserializer)?;
+        Pattern::new(&source).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns_are_anchored_at_the_start() {
+        let p = Pattern::new("rust|go").unwrap();
+        assert!(p.is_match("rust-async"));
+        assert!(p.is_match("go-generics"));
+        assert!(!p.is_match("trust"));
+        assert!(Pattern::new(".*rust").unwrap().is_match("trust"));
+        assert!(Pattern::new("(?i)RUST").unwrap().is_match("rust"));
+    }
+
+    #[test]
+    fn oversized_or_invalid_patterns_are_rejected() {
+        assert!(Pattern::new(&"a".repeat(MAX_PATTERN_LEN + 1)).is_err());
+        assert!(Pattern::new(&format!("{}a{}", "(".repeat(20), ")".repeat(20))).is_err());
+        assert!(Pattern::new("a{1000}{1000}").is_err());
+        assert!(Pattern::new("(unclosed").is_err());
+    }
+}
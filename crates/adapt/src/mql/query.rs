@@ -1,13 +1,23 @@
 // crates/adapt/src/mql/query.rs
 
+use super::aggregate::{Aggregator, GroupRow, GroupSpec};
 use super::ast::{CmpOp, FieldExpr, Filter, FindOptions};
+use super::cursor::{page_keys, page_sort, Cursor};
 use super::error::QueryError;
 use super::eval::{eval_filter, get_field_value};
 use super::index::{IndexBackend, IndexConfig, JsonStore};
+use super::text::tokenize;
 
 use serde_json::Value as Json;
 use std::cmp::Ordering;
 use std::collections::HashSet;
+use std::fmt;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tracing::{debug, field::Empty, warn, Span};
 
 /// Result of a query: document ID + owned JSON document.
 ///
@@ -18,14 +28,194 @@ pub struct QueryResult<Id> {
     pub doc: Json,
 }
 
+/// One page of a query, with where the next one starts.
+#[derive(Debug, Clone)]
+pub struct QueryResultPage<Id> {
+    pub results: Vec<QueryResult<Id>>,
+    /// Every match, before `after`, `skip` and `limit` were applied.
+    pub total: usize,
+    /// Pass as `after` for the next page; `None` on the last one.
+    pub next: Option<Cursor>,
+}
+
+/// How the planner found the candidates of a query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryPlan {
+    /// The intersection of `constraints` index lookups.
+    Index { constraints: usize },
+    /// Every document in the store.
+    FullScan,
+}
+
+impl fmt::Display for QueryPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryPlan::Index { constraints } => write!(f, "index({constraints})"),
+            QueryPlan::FullScan => f.write_str("full-scan"),
+        }
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Query budgets
+// ─────────────────────────────────────────────────────────────────────────────
+
+tokio::task_local! {
+    static GOVERNOR: Arc<QueryGovernor>;
+}
+
+/// Limits on the work all the queries of one request may do together.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueryBudget {
+    /// Documents loaded and evaluated, over every query.
+    pub max_scanned: Option<usize>,
+    /// Time since the governor was made.
+    pub max_time: Option<Duration>,
+    /// A query slower than this is logged as a warning.
+    pub slow_query: Option<Duration>,
+}
+
+/// What the queries run under `governed` have spent of a `QueryBudget`.
+/// A query that goes over fails with `QueryError::BudgetExceeded`.
+#[derive(Debug)]
+pub struct QueryGovernor {
+    /// Names the request in logs, e.g. its path.
+    label: String,
+    budget: QueryBudget,
+    started: Instant,
+    scanned: AtomicUsize,
+    queries: AtomicUsize,
+}
+
+impl QueryGovernor {
+    pub fn new(label: impl Into<String>, budget: QueryBudget) -> Self {
+        Self {
+            label: label.into(),
+            budget,
+            started: Instant::now(),
+            scanned: AtomicUsize::new(0),
+            queries: AtomicUsize::new(0),
+        }
+    }
+
+    /// Documents scanned so far.
+    pub fn scanned(&self) -> usize {
+        self.scanned.load(AtomicOrdering::Relaxed)
+    }
+
+    /// Queries started so far.
+    pub fn queries(&self) -> usize {
+        self.queries.load(AtomicOrdering::Relaxed)
+    }
+
+    fn check_time(&self) -> Result<(), QueryError> {
+        match self.budget.max_time {
+            Some(max) if self.started.elapsed() > max => Err(QueryError::BudgetExceeded(format!(
+                "{} ran queries for more than {} ms",
+                self.label,
+                max.as_millis()
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    fn charge(&self) -> Result<(), QueryError> {
+        let scanned = self.scanned.fetch_add(1, AtomicOrdering::Relaxed) + 1;
+        match self.budget.max_scanned {
+            Some(max) if scanned > max => Err(QueryError::BudgetExceeded(format!(
+                "{} scanned more than {max} documents",
+                self.label
+            ))),
+            _ => self.check_time(),
+        }
+    }
+}
+
+/// Run `fut`, charging every query it makes to `governor`. Queries outside
+/// any `governed` future have no budget.
+pub async fn governed<F: Future>(governor: Arc<QueryGovernor>, fut: F) -> F::Output {
+    GOVERNOR.scope(governor, fut).await
+}
+
+/// One query on its way through the planner: charges the governor for each
+/// document it loads and reports to the current `mql.query` span.
+struct QueryRun<'f> {
+    filter: &'f Filter,
+    started: Instant,
+    scanned: usize,
+    plan: QueryPlan,
+}
+
+impl<'f> QueryRun<'f> {
+    fn start(filter: &'f Filter) -> Result<Self, QueryError> {
+        GOVERNOR
+            .try_with(|g| {
+                g.queries.fetch_add(1, AtomicOrdering::Relaxed);
+                g.check_time()
+            })
+            .unwrap_or(Ok(()))?;
+        Ok(Self {
+            filter,
+            started: Instant::now(),
+            scanned: 0,
+            plan: QueryPlan::FullScan,
+        })
+    }
+
+    fn planned(&mut self, plan: QueryPlan) {
+        self.plan = plan;
+        Span::current().record("plan", plan.to_string());
+    }
+
+    /// Count one loaded document against the budget.
+    fn scan(&mut self) -> Result<(), QueryError> {
+        self.scanned += 1;
+        let charged = GOVERNOR.try_with(|g| g.charge()).unwrap_or(Ok(()));
+        if let Err(e) = &charged {
+            warn!(
+                "MQL query stopped after {} documents ({}): {}; filter {:?}",
+                self.scanned, self.plan, e, self.filter
+            );
+        }
+        charged
+    }
+
+    fn finish(self, matched: usize) {
+        let took = self.started.elapsed();
+        let span = Span::current();
+        span.record("scanned", self.scanned);
+        span.record("matched", matched);
+        let slow = GOVERNOR
+            .try_with(|g| match g.budget.slow_query {
+                Some(limit) if took > limit => Some(g.label.clone()),
+                _ => None,
+            })
+            .ok()
+            .flatten();
+        if let Some(label) = slow {
+            warn!(
+                "Slow MQL query for {}: {} ms, {} scanned, {} matched ({}); filter {:?}",
+                label,
+                took.as_millis(),
+                self.scanned,
+                matched,
+                self.plan,
+                self.filter
+            );
+        }
+    }
+}
+
 /// Plans and executes queries over a JsonStore + IndexBackend pair.
 ///
 /// - Uses `IndexConfig` to discover which fields are indexed.
-/// - Extracts simple indexable constraints from the filter (equality / IN).
+/// - Extracts simple indexable constraints from the filter (equality / IN / text).
 /// - Asks the index backend for candidate ID sets.
 /// - Intersects candidate sets when multiple constraints are available.
 /// - Falls back to full scan when no index can be used.
 /// - Always uses `eval_filter` for final correctness.
+/// - Charges each document it loads to the request's `QueryGovernor`, if
+///   any, and records the plan in an `mql.query` span.
 ///
 /// This stays generic over the actual storage engine (in-memory, indexed_json, etc.).
 #[derive(Debug)]
@@ -50,51 +240,248 @@ impl<'a> QueryPlanner<'a> {
         S: JsonStore,
         I: IndexBackend<Id = S::Id>,
     {
-        // 1. Collect indexable constraints (only equality / IN on indexed fields).
-        let constraints = collect_indexable_constraints(filter, self.index_config);
+        Ok(self.page(store, index, filter, opts).await?.results)
+    }
 
-        // 2. Determine candidate IDs using the index, or fall back to all IDs.
-        let candidate_ids: Vec<S::Id> = if constraints.is_empty() {
-            store.all_ids().await
-        } else {
-            let mut sets: Vec<HashSet<S::Id>> = Vec::new();
+    /// Like `execute`, but also count every match and, when `opts.limit`
+    /// cut the result short, return a cursor for the next page.
+    ///
+    /// Matches are ordered by `opts.sort` and then by `id`, so pages are
+    /// stable; `opts.after` starts strictly after a cursor from an earlier
+    /// page, and `skip` / `limit` apply from there.
+    #[tracing::instrument(name = "mql.query", skip_all, fields(op = "page", plan = Empty, scanned = Empty, matched = Empty))]
+    pub async fn page<S, I>(
+        &self,
+        store: &S,
+        index: &I,
+        filter: &Filter,
+        opts: &FindOptions,
+    ) -> Result<QueryResultPage<S::Id>, QueryError>
+    where
+        S: JsonStore,
+        I: IndexBackend<Id = S::Id>,
+    {
+        check_cursor(opts)?;
+        let mut run = QueryRun::start(filter)?;
+        let candidate_ids = self.candidate_ids(store, index, filter, &mut run).await;
+
+        // 3. Load documents, evaluate filter, and collect matches.
+        let mut matches: Vec<QueryResult<S::Id>> = Vec::new();
 
-            for c in &constraints {
-                if let Some(ids) = lookup_ids_for_constraint(index, c).await {
-                    sets.push(ids);
+        for id in candidate_ids {
+            run.scan()?;
+            if let Some(doc) = store.get(id).await {
+                if eval_filter(filter, &doc) {
+                    matches.push(QueryResult { id, doc });
                 }
             }
+        }
+        let total = matches.len();
+        run.finish(total);
 
-            if sets.is_empty() {
-                // No usable index constraints (backend couldn't answer any).
-                store.all_ids().await
-            } else {
-                // Intersect all constraint sets to get final candidate IDs.
-                let mut iter = sets.into_iter();
-                let first = iter.next().unwrap();
-                let acc: HashSet<S::Id> =
-                    iter.fold(first, |acc, set| acc.intersection(&set).copied().collect());
-
-                acc.into_iter().collect()
-            }
+        // 4. Apply sorting, the cursor, skipping, and limiting.
+        apply_sort(&mut matches, opts);
+        if let Some(cursor) = &opts.after {
+            matches.retain(|r| is_past(cursor, &r.doc, &opts.sort));
+        }
+        let remaining = matches.len();
+        let skipped = opts.skip.unwrap_or(0).min(remaining);
+        let results = apply_skip_limit(matches, opts.skip, opts.limit);
+
+        let next = match skipped + results.len() < remaining {
+            true => results.last().map(|r| Cursor::after(&r.doc, &opts.sort)),
+            false => None,
         };
+        Ok(QueryResultPage {
+            results,
+            total,
+            next,
+        })
+    }
 
-        // 3. Load documents, evaluate filter, and collect matches.
-        let mut matches: Vec<QueryResult<S::Id>> = Vec::new();
+    /// Like `execute`, but send each result to `tx` instead of collecting
+    /// them, so bulk readers (sitemaps, feeds, exports) never hold every
+    /// document at once. Returns the number of results sent.
+    ///
+    /// Without a sort, documents are sent as they are found, in no
+    /// particular order. With one, a first pass keeps only IDs and sort keys
+    /// and a second pass loads and sends the documents in order. A document
+    /// that changed in between so it no longer matches is skipped.
+    ///
+    /// `opts.after` is honoured as in `page`, and implies the sorted path.
+    ///
+    /// Stops early, without error, once the receiver is dropped.
+    #[tracing::instrument(name = "mql.query", skip_all, fields(op = "stream", plan = Empty, scanned = Empty, matched = Empty))]
+    pub async fn stream<S, I>(
+        &self,
+        store: &S,
+        index: &I,
+        filter: &Filter,
+        opts: &FindOptions,
+        tx: &mpsc::Sender<QueryResult<S::Id>>,
+    ) -> Result<usize, QueryError>
+    where
+        S: JsonStore,
+        I: IndexBackend<Id = S::Id>,
+    {
+        check_cursor(opts)?;
+        let mut run = QueryRun::start(filter)?;
+        let candidate_ids = self.candidate_ids(store, index, filter, &mut run).await;
+        let skip = opts.skip.unwrap_or(0);
+        let limit = opts.limit.unwrap_or(usize::MAX);
+        let mut sent = 0;
+
+        if opts.sort.is_empty() && opts.after.is_none() {
+            let mut skipped = 0;
+            for id in candidate_ids {
+                if sent >= limit {
+                    break;
+                }
+                run.scan()?;
+                let Some(doc) = store.get(id).await else {
+                    continue;
+                };
+                if !eval_filter(filter, &doc) {
+                    continue;
+                }
+                if skipped < skip {
+                    skipped += 1;
+                    continue;
+                }
+                if tx.send(QueryResult { id, doc }).await.is_err() {
+                    break;
+                }
+                sent += 1;
+            }
+            run.finish(sent);
+            return Ok(sent);
+        }
 
+        let mut keyed: Vec<(S::Id, Vec<Option<Json>>)> = Vec::new();
         for id in candidate_ids {
+            run.scan()?;
             if let Some(doc) = store.get(id).await {
                 if eval_filter(filter, &doc) {
-                    matches.push(QueryResult { id, doc });
+                    keyed.push((id, page_keys(&doc, &opts.sort)));
                 }
             }
         }
+        let order = page_sort(&opts.sort);
+        keyed.sort_by(|(_, a), (_, b)| compare_sort_keys(a, b, &order));
+        if let Some(cursor) = &opts.after {
+            keyed.retain(|(_, keys)| {
+                compare_sort_keys(keys, cursor.keys(), &order) == Ordering::Greater
+            });
+        }
 
-        // 4. Apply sorting, skipping, and limiting.
-        apply_sort(&mut matches, opts);
-        let sliced = apply_skip_limit(matches, opts.skip, opts.limit);
+        for (id, _) in keyed.into_iter().skip(skip).take(limit) {
+            run.scan()?;
+            let Some(doc) = store.get(id).await else {
+                continue;
+            };
+            if !eval_filter(filter, &doc) {
+                continue;
+            }
+            if tx.send(QueryResult { id, doc }).await.is_err() {
+                break;
+            }
+            sent += 1;
+        }
+        run.finish(sent);
+        Ok(sent)
+    }
+
+    /// Group the documents matching `filter` once per spec, in one pass
+    /// over the matches. Rows come back in the order of `specs`.
+    #[tracing::instrument(name = "mql.query", skip_all, fields(op = "aggregate", plan = Empty, scanned = Empty, matched = Empty))]
+    pub async fn aggregate<S, I>(
+        &self,
+        store: &S,
+        index: &I,
+        filter: &Filter,
+        specs: &[GroupSpec],
+    ) -> Result<Vec<Vec<GroupRow>>, QueryError>
+    where
+        S: JsonStore,
+        I: IndexBackend<Id = S::Id>,
+    {
+        let mut run = QueryRun::start(filter)?;
+        let candidate_ids = self.candidate_ids(store, index, filter, &mut run).await;
+        let mut aggregators: Vec<Aggregator> = specs.iter().cloned().map(Aggregator::new).collect();
+
+        let mut matched = 0;
+        for id in candidate_ids {
+            run.scan()?;
+            if let Some(doc) = store.get(id).await {
+                if eval_filter(filter, &doc) {
+                    matched += 1;
+                    for agg in &mut aggregators {
+                        agg.add(&doc);
+                    }
+                }
+            }
+        }
+        run.finish(matched);
+
+        Ok(aggregators.into_iter().map(Aggregator::finish).collect())
+    }
+
+    /// IDs that may match `filter`: from the index when it can answer an
+    /// indexable constraint, else every ID in the store. The plan is
+    /// recorded on `run`.
+    async fn candidate_ids<S, I>(
+        &self,
+        store: &S,
+        index: &I,
+        filter: &Filter,
+        run: &mut QueryRun<'_>,
+    ) -> Vec<S::Id>
+    where
+        S: JsonStore,
+        I: IndexBackend<Id = S::Id>,
+    {
+        // 1. Collect indexable constraints (only equality / IN on indexed fields).
+        let constraints = collect_indexable_constraints(filter, self.index_config);
+
+        // 2. Determine candidate IDs using the index, or fall back to all IDs.
+        if constraints.is_empty() {
+            debug!("MQL full scan: no indexed field in the filter");
+            run.planned(QueryPlan::FullScan);
+            return store.all_ids().await;
+        }
 
-        Ok(sliced)
+        let mut sets: Vec<HashSet<S::Id>> = Vec::new();
+        for c in &constraints {
+            if let Some(ids) = lookup_ids_for_constraint(index, c).await {
+                sets.push(ids);
+            }
+        }
+
+        if sets.is_empty() {
+            // No usable index constraints (backend couldn't answer any).
+            debug!(
+                "MQL full scan: the index answered none of {} constraints",
+                constraints.len()
+            );
+            run.planned(QueryPlan::FullScan);
+            store.all_ids().await
+        } else {
+            // Intersect all constraint sets to get final candidate IDs.
+            debug!(
+                "MQL index hit: {} of {} constraints answered",
+                sets.len(),
+                constraints.len()
+            );
+            run.planned(QueryPlan::Index {
+                constraints: sets.len(),
+            });
+            let mut iter = sets.into_iter();
+            let first = iter.next().unwrap();
+            let acc: HashSet<S::Id> =
+                iter.fold(first, |acc, set| acc.intersection(&set).copied().collect());
+
+            acc.into_iter().collect()
+        }
     }
 }
 
@@ -117,24 +504,78 @@ where
     planner.execute(store, index, filter, opts).await
 }
 
+/// Paging counterpart of `execute_query`; see `QueryPlanner::page`.
+pub async fn page_query<S, I>(
+    index_config: &IndexConfig,
+    store: &S,
+    index: &I,
+    filter: &Filter,
+    opts: &FindOptions,
+) -> Result<QueryResultPage<S::Id>, QueryError>
+where
+    S: JsonStore,
+    I: IndexBackend<Id = S::Id>,
+{
+    let planner = QueryPlanner::new(index_config);
+    planner.page(store, index, filter, opts).await
+}
+
+/// Streaming counterpart of `execute_query`; see `QueryPlanner::stream`.
+pub async fn stream_query<S, I>(
+    index_config: &IndexConfig,
+    store: &S,
+    index: &I,
+    filter: &Filter,
+    opts: &FindOptions,
+    tx: &mpsc::Sender<QueryResult<S::Id>>,
+) -> Result<usize, QueryError>
+where
+    S: JsonStore,
+    I: IndexBackend<Id = S::Id>,
+{
+    let planner = QueryPlanner::new(index_config);
+    planner.stream(store, index, filter, opts, tx).await
+}
+
+/// Aggregation counterpart of `execute_query`; see `QueryPlanner::aggregate`.
+pub async fn aggregate_query<S, I>(
+    index_config: &IndexConfig,
+    store: &S,
+    index: &I,
+    filter: &Filter,
+    specs: &[GroupSpec],
+) -> Result<Vec<Vec<GroupRow>>, QueryError>
+where
+    S: JsonStore,
+    I: IndexBackend<Id = S::Id>,
+{
+    let planner = QueryPlanner::new(index_config);
+    planner.aggregate(store, index, filter, specs).await
+}
+
 /// Constraints that can be answered by the index backend.
 ///
 /// For now we only use:
 /// - field == value
 /// - field IN values
+/// - field contains words (`$text`)
 ///
 /// Range support can be added later if/when backends implement `lookup_range`.
 #[derive(Debug, Clone)]
 enum IndexConstraint {
     Eq { field: String, value: Json },
     In { field: String, values: Vec<Json> },
+    Text { field: String, words: Vec<String> },
 }
 
 /// Walk the filter and extract indexable constraints.
 ///
 /// We are conservative:
 /// - Only take constraints on fields that `IndexConfig::is_indexed`.
-/// - Only equality / IN (`$eq` / `$in`) are considered indexable for now.
+/// - Only equality / IN / text (`$eq` / `$in` / `$text`) are considered
+///   indexable for now, plus `$elemMatch` equality / IN on fields the
+///   config says are indexed per element. `$nin`, `$exists` and `$regex`
+///   are always post-filtered.
 /// - We only harvest constraints in AND contexts; constraints under OR are
 ///   ignored for indexing (correctness still ensured by eval_filter).
 fn collect_indexable_constraints(filter: &Filter, config: &IndexConfig) -> Vec<IndexConstraint> {
@@ -185,6 +626,15 @@ fn collect_indexable_constraints_inner(
                         values: values.clone(),
                     });
                 }
+                CmpOp::Text(query) => {
+                    out.push(IndexConstraint::Text {
+                        field: path.clone(),
+                        words: tokenize(query),
+                    });
+                }
+                CmpOp::ElemMatch(inner) if config.indexes_elements(path) => {
+                    collect_element_constraints(path, inner, out);
+                }
                 // For now we do not try to use range constraints with indexes.
                 _ => {}
             }
@@ -192,6 +642,31 @@ fn collect_indexable_constraints_inner(
     }
 }
 
+/// Equality / IN on the element itself (`{ "$elemMatch": { "$in": [..] } }`)
+/// over a field whose index holds one entry per element. Conditions on the
+/// fields of object elements are left to `eval_filter`.
+fn collect_element_constraints(field: &str, inner: &Filter, out: &mut Vec<IndexConstraint>) {
+    match inner {
+        Filter::And(children) => {
+            for child in children {
+                collect_element_constraints(field, child, out);
+            }
+        }
+        Filter::Field(FieldExpr { path, op }) if path.is_empty() => match op {
+            CmpOp::Eq(v) => out.push(IndexConstraint::Eq {
+                field: field.to_owned(),
+                value: v.clone(),
+            }),
+            CmpOp::In(values) => out.push(IndexConstraint::In {
+                field: field.to_owned(),
+                values: values.clone(),
+            }),
+            _ => {}
+        },
+        _ => {}
+    }
+}
+
 /// Ask the index backend for candidate IDs for a single constraint (async).
 ///
 /// If the backend cannot answer this constraint, returns None and the caller
@@ -203,21 +678,35 @@ where
     match c {
         IndexConstraint::Eq { field, value } => index.lookup_eq(field, value).await,
         IndexConstraint::In { field, values } => index.lookup_in(field, values).await,
+        IndexConstraint::Text { field, words } => index.lookup_text(field, words).await,
     }
 }
 
-/// Apply sorting in-place using `FindOptions.sort`.
+/// Apply sorting in-place using `FindOptions.sort`, then the id, so equal
+/// keys keep one order across calls.
 ///
 /// We rely on `get_field_value` to resolve the sort key path, and a simple
 /// JSON comparison that orders:
 /// - `None` (missing field) after `Some`
 /// - Within `Some`, compares only if types match (string, number, bool).
 fn apply_sort<Id>(results: &mut [QueryResult<Id>], opts: &FindOptions) {
-    if opts.sort.is_empty() {
-        return;
+    let order = page_sort(&opts.sort);
+    results.sort_by(|a, b| compare_docs_for_sort(a, b, &order));
+}
+
+/// A cursor only makes sense for the sort it was made for.
+fn check_cursor(opts: &FindOptions) -> Result<(), QueryError> {
+    match &opts.after {
+        Some(cursor) if cursor.sort() != opts.sort.as_slice() => Err(QueryError::InvalidCursor(
+            "cursor was made for a different sort".into(),
+        )),
+        _ => Ok(()),
     }
+}
 
-    results.sort_by(|a, b| compare_docs_for_sort(a, b, &opts.sort));
+/// Whether `doc` comes after `cursor` in the order of `page_sort(sort)`.
+fn is_past(cursor: &Cursor, doc: &Json, sort: &[(String, i8)]) -> bool {
+    compare_sort_keys(&page_keys(doc, sort), cursor.keys(), &page_sort(sort)) == Ordering::Greater
 }
 
 fn compare_docs_for_sort<Id>(
@@ -237,6 +726,21 @@ fn compare_docs_for_sort<Id>(
     Ordering::Equal
 }
 
+/// `compare_docs_for_sort` over sort keys extracted ahead of time.
+fn compare_sort_keys(
+    a: &[Option<Json>],
+    b: &[Option<Json>],
+    sort_keys: &[(String, i8)],
+) -> Ordering {
+    for ((av, bv), (_, dir)) in a.iter().zip(b).zip(sort_keys) {
+        let ord = json_cmp(av.as_ref(), bv.as_ref());
+        if ord != Ordering::Equal {
+            return if *dir >= 0 { ord } else { ord.reverse() };
+        }
+    }
+    Ordering::Equal
+}
+
 /// Compare two optional JSON values for sorting.
 ///
 /// Rules:
@@ -246,7 +750,7 @@ fn compare_docs_for_sort<Id>(
 ///   - numbers compare by numeric value,
 ///   - bools compare `false < true`,
 ///   - other types compare as `Equal` (stable but arbitrary).
-fn json_cmp(a: Option<&Json>, b: Option<&Json>) -> Ordering {
+pub(crate) fn json_cmp(a: Option<&Json>, b: Option<&Json>) -> Ordering {
     match (a, b) {
         (None, None) => Ordering::Equal,
         (None, Some(_)) => Ordering::Greater,
@@ -287,3 +791,346 @@ fn apply_skip_limit<Id: Clone>(
 
     results[start..end].to_vec()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mql::parser::parse_filter;
+    use async_trait::async_trait;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    /// Documents by position, with `tag` indexed.
+    struct Docs(Vec<Json>);
+
+    #[async_trait]
+    impl JsonStore for Docs {
+        type Id = usize;
+
+        async fn all_ids(&self) -> Vec<usize> {
+            (0..self.0.len()).collect()
+        }
+
+        async fn get(&self, id: usize) -> Option<Json> {
+            self.0.get(id).cloned()
+        }
+    }
+
+    #[async_trait]
+    impl IndexBackend for Docs {
+        type Id = usize;
+
+        async fn lookup_eq(&self, field: &str, value: &Json) -> Option<HashSet<usize>> {
+            (field == "tag").then(|| {
+                self.0
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, d)| d.get("tag") == Some(value))
+                    .map(|(i, _)| i)
+                    .collect()
+            })
+        }
+
+        async fn lookup_in(&self, _field: &str, _values: &[Json]) -> Option<HashSet<usize>> {
+            None
+        }
+
+        /// Only the first document is in the word index.
+        async fn lookup_text(&self, field: &str, words: &[String]) -> Option<HashSet<usize>> {
+            (field == "title" && words == ["rust"]).then(|| HashSet::from([0]))
+        }
+    }
+
+    fn docs(n: usize) -> Docs {
+        Docs(
+            (0..n)
+                .map(|i| json!({ "n": i, "tag": if i % 2 == 0 { "even" } else { "odd" } }))
+                .collect(),
+        )
+    }
+
+    async fn streamed(docs: &Docs, filter: &Filter, opts: &FindOptions) -> Vec<Json> {
+        let config = IndexConfig::new(["tag"]);
+        let (tx, mut rx) = mpsc::channel(2);
+        let (sent, received) = tokio::join!(
+            async move { stream_query(&config, docs, docs, filter, opts, &tx).await },
+            async move {
+                let mut out = Vec::new();
+                while let Some(r) = rx.recv().await {
+                    out.push(r.doc);
+                }
+                out
+            }
+        );
+        assert_eq!(sent.unwrap(), received.len());
+        received
+    }
+
+    fn even() -> Filter {
+        Filter::Field(FieldExpr {
+            path: "tag".into(),
+            op: CmpOp::Eq(json!("even")),
+        })
+    }
+
+    #[tokio::test]
+    async fn sorted_streams_match_execute() {
+        let docs = docs(50);
+        let config = IndexConfig::new(["tag"]);
+        let opts = FindOptions {
+            sort: vec![("n".into(), -1)],
+            skip: Some(3),
+            limit: Some(10),
+            after: None,
+        };
+
+        let expected: Vec<Json> = execute_query(&config, &docs, &docs, &even(), &opts)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|r| r.doc)
+            .collect();
+        let got = streamed(&docs, &even(), &opts).await;
+
+        assert_eq!(got, expected);
+        assert_eq!(got[0]["n"], 42);
+    }
+
+    #[tokio::test]
+    async fn unsorted_streams_apply_skip_and_limit() {
+        let docs = docs(50);
+        let opts = FindOptions {
+            sort: Vec::new(),
+            skip: Some(20),
+            limit: Some(100),
+            after: None,
+        };
+
+        let got = streamed(&docs, &Filter::And(vec![]), &opts).await;
+        assert_eq!(got.len(), 30);
+        let unique: HashMap<String, ()> = got.iter().map(|d| (d["n"].to_string(), ())).collect();
+        assert_eq!(unique.len(), 30);
+    }
+
+    #[tokio::test]
+    async fn cursors_page_through_ties_and_survive_inserts() {
+        // Three posts per day, so the sort key alone does not order them.
+        let mut docs = Docs(
+            (0..9)
+                .map(|i| json!({ "id": format!("/p/{i}"), "day": i / 3 }))
+                .collect(),
+        );
+        let config = IndexConfig::new(["tag"]);
+        let mut opts = FindOptions {
+            sort: vec![("day".into(), -1)],
+            limit: Some(4),
+            ..FindOptions::default()
+        };
+        let ids = |page: &QueryResultPage<usize>| -> Vec<Json> {
+            page.results.iter().map(|r| r.doc["id"].clone()).collect()
+        };
+
+        let first = page_query(&config, &docs, &docs, &Filter::And(vec![]), &opts)
+            .await
+            .unwrap();
+        assert_eq!(first.total, 9);
+        assert_eq!(
+            ids(&first),
+            [json!("/p/6"), json!("/p/7"), json!("/p/8"), json!("/p/3")]
+        );
+
+        // A new post on the newest day lands before the cursor.
+        docs.0.push(json!({ "id": "/p/9", "day": 2 }));
+        opts.after = first.next;
+        let second = page_query(&config, &docs, &docs, &Filter::And(vec![]), &opts)
+            .await
+            .unwrap();
+        assert_eq!(
+            ids(&second),
+            [json!("/p/4"), json!("/p/5"), json!("/p/0"), json!("/p/1")]
+        );
+        assert_eq!(
+            streamed(&docs, &Filter::And(vec![]), &opts).await,
+            second
+                .results
+                .iter()
+                .map(|r| r.doc.clone())
+                .collect::<Vec<_>>()
+        );
+
+        opts.after = second.next;
+        let last = page_query(&config, &docs, &docs, &Filter::And(vec![]), &opts)
+            .await
+            .unwrap();
+        assert_eq!(ids(&last), [json!("/p/2")]);
+        assert!(last.next.is_none());
+
+        opts.sort = vec![("day".into(), 1)];
+        let err = page_query(&config, &docs, &docs, &Filter::And(vec![]), &opts)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, QueryError::InvalidCursor(_)));
+    }
+
+    #[tokio::test]
+    async fn text_constraints_come_from_the_word_index() {
+        let docs = Docs(vec![
+            json!({ "title": "Rust in production" }),
+            json!({ "title": "Go in production" }),
+            json!({ "title": "More Rust" }),
+        ]);
+        let filter = Filter::Field(FieldExpr {
+            path: "title".into(),
+            op: CmpOp::Text("RUST".into()),
+        });
+
+        let unindexed = IndexConfig::new(["tag"]);
+        let scanned = execute_query(&unindexed, &docs, &docs, &filter, &FindOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(scanned.len(), 2);
+
+        let indexed = IndexConfig::new(["title"]);
+        let planned = execute_query(&indexed, &docs, &docs, &filter, &FindOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(planned.len(), 1);
+        assert_eq!(planned[0].id, 0);
+    }
+
+    #[tokio::test]
+    async fn elem_match_uses_element_indexes_only() {
+        let docs = Docs(vec![
+            json!({ "tag": ["rust", "web"] }),
+            json!({ "tag": ["go"] }),
+            json!({ "tag": "rust" }),
+        ]);
+        let filter = parse_filter(&json!({ "tag": { "$elemMatch": { "$eq": "rust" } } })).unwrap();
+
+        // Without an element index the planner scans. Declaring one makes it
+        // trust `lookup_eq`, which for `Docs` compares whole values, so the
+        // empty result shows the index was consulted.
+        let whole = IndexConfig::new(["tag"]);
+        let scanned = execute_query(&whole, &docs, &docs, &filter, &FindOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(scanned.len(), 1);
+        assert_eq!(scanned[0].id, 0);
+
+        let elements = IndexConfig::new(Vec::<String>::new()).with_element_fields(["tag"]);
+        let planned = execute_query(&elements, &docs, &docs, &filter, &FindOptions::default())
+            .await
+            .unwrap();
+        assert!(planned.is_empty());
+    }
+
+    #[tokio::test]
+    async fn aggregates_only_count_matching_documents() {
+        let docs = docs(10);
+        let config = IndexConfig::new(["tag"]);
+        let specs = [
+            GroupSpec::by("tag"),
+            GroupSpec {
+                max: vec!["n".into()],
+                sum: vec!["n".into()],
+                ..GroupSpec::default()
+            },
+        ];
+
+        let all = aggregate_query(&config, &docs, &docs, &Filter::And(vec![]), &specs)
+            .await
+            .unwrap();
+        let tags: Vec<_> = all[0].iter().map(|r| (r.key.clone(), r.count)).collect();
+        assert_eq!(tags, [(json!("even"), 5), (json!("odd"), 5)]);
+
+        let even = aggregate_query(&config, &docs, &docs, &even(), &specs)
+            .await
+            .unwrap();
+        assert_eq!(even[0].len(), 1);
+        assert_eq!(even[1][0].count, 5);
+        assert_eq!(even[1][0].max["n"], json!(8));
+        assert_eq!(even[1][0].sum["n"], json!(20));
+    }
+
+    #[tokio::test]
+    async fn a_dropped_receiver_stops_the_stream() {
+        let docs = docs(50);
+        let config = IndexConfig::new(["tag"]);
+        let (tx, rx) = mpsc::channel(1);
+        drop(rx);
+
+        let sent = stream_query(
+            &config,
+            &docs,
+            &docs,
+            &Filter::And(vec![]),
+            &FindOptions::default(),
+            &tx,
+        )
+        .await
+        .unwrap();
+        assert_eq!(sent, 0);
+    }
+
+    #[tokio::test]
+    async fn governed_queries_share_one_budget() {
+        let docs = docs(10);
+        let config = IndexConfig::new(["tag"]);
+        let opts = FindOptions::default();
+        let governor = Arc::new(QueryGovernor::new(
+            "/archive.html",
+            QueryBudget {
+                max_scanned: Some(12),
+                ..Default::default()
+            },
+        ));
+
+        let (indexed, scanned, too_much) = governed(governor.clone(), async {
+            // The index narrows `tag` to 5 candidates; `n` has no index.
+            let indexed = execute_query(&config, &docs, &docs, &even(), &opts).await;
+            let scanned = governor.scanned();
+            let all = parse_filter(&json!({ "n": { "$gte": 0 } })).unwrap();
+            let too_much = execute_query(&config, &docs, &docs, &all, &opts).await;
+            (indexed, scanned, too_much)
+        })
+        .await;
+
+        assert_eq!(indexed.unwrap().len(), 5);
+        assert_eq!(scanned, 5);
+        assert!(matches!(too_much, Err(QueryError::BudgetExceeded(_))));
+        assert_eq!(governor.scanned(), 13);
+        assert_eq!(governor.queries(), 2);
+
+        // Outside `governed` nothing is charged.
+        let all = parse_filter(&json!({ "n": { "$gte": 0 } })).unwrap();
+        assert_eq!(
+            execute_query(&config, &docs, &docs, &all, &opts)
+                .await
+                .unwrap()
+                .len(),
+            10
+        );
+        assert_eq!(governor.scanned(), 13);
+    }
+
+    #[tokio::test]
+    async fn governors_stop_queries_that_run_too_long() {
+        let docs = docs(10);
+        let config = IndexConfig::new(["tag"]);
+        let governor = Arc::new(QueryGovernor::new(
+            "/slow.html",
+            QueryBudget {
+                max_time: Some(Duration::ZERO),
+                ..Default::default()
+            },
+        ));
+        tokio::time::sleep(Duration::from_millis(2)).await;
+
+        let result = governed(
+            governor,
+            execute_query(&config, &docs, &docs, &even(), &FindOptions::default()),
+        )
+        .await;
+        assert!(matches!(result, Err(QueryError::BudgetExceeded(_))));
+    }
+}
@@ -0,0 +1,90 @@
+// crates/adapt/src/mql/text.rs
+
+// Word matching for `$text`:
+//
+//   { "content.title": { "$text": "rust async" } }
+//
+// matches documents whose title contains both words, in any order and case.
+// Text is split into lowercase runs of letters and digits, so "Async/Await"
+// holds the words `async` and `await`. Backends keep an inverted index of
+// those words under `text_index_key(field)`; see `IndexBackend::lookup_text`.
+
+use serde_json::Value as Json;
+
+/// Fields whose words are indexed for `$text`.
+pub const TEXT_FIELDS: &[&str] = &["content.title", "content.summary"];
+
+/// Distinct lowercase words of `s`, in first-seen order.
+pub fn tokenize(s: &str) -> Vec<String> {
+    let mut words: Vec<String> = Vec::new();
+    for word in s
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+    {
+        let word = word.to_lowercase();
+        if !words.contains(&word) {
+            words.push(word);
+        }
+    }
+    words
+}
+
+/// Words of a string field, or of every string in an array field.
+pub fn tokenize_json(value: &Json) -> Vec<String> {
+    match value {
+        Json::String(s) => tokenize(s),
+        Json::Array(items) => {
+            let joined: Vec<&str> = items.iter().filter_map(Json::as_str).collect();
+            tokenize(&joined.join(" "))
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Whether `value` contains every word of `query`.
+pub fn text_matches(value: &Json, query: &str) -> bool {
+    let words = tokenize_json(value);
+    tokenize(query).iter().all(|w| words.contains(w))
+}
+
+/// Index key holding the words of `field`, if `field` is one of
+/// `TEXT_FIELDS`.
+pub fn text_index_key(field: &str) -> Option<&'static str> {
+    match field {
+        "content.title" => Some("text.content.title"),
+        "content.summary" => Some("text.content.summary"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn words_are_lowercase_and_distinct() {
+        assert_eq!(
+            tokenize("Async/Await in Rust — async Rust, 2024!"),
+            ["async", "await", "in", "rust", "2024"]
+        );
+        assert!(tokenize(" -- ").is_empty());
+    }
+
+    #[test]
+    fn every_query_word_must_appear() {
+        let title = json!("Getting started with async Rust");
+        assert!(text_matches(&title, "rust ASYNC"));
+        assert!(!text_matches(&title, "rust wasm"));
+        assert!(text_matches(&json!(["Rust", "WASM"]), "wasm rust"));
+        assert!(!text_matches(&json!(42), "42"));
+    }
+
+    #[test]
+    fn only_text_fields_have_index_keys() {
+        for field in TEXT_FIELDS {
+            assert!(text_index_key(field).is_some(), "{field}");
+        }
+        assert_eq!(text_index_key("slug"), None);
+    }
+}
@@ -1,13 +1,22 @@
 // crates/adapt/src/runtime/bootstrap.rs
 
-use crate::js::engine::BoaEngine;
-use crate::js::JsEngine;
+use crate::js::engine::{BoaEngine, EngineEnv};
+use crate::js::fetch::{FetchError, FetchRequest, FetchResponse};
+use crate::js::{FetchPolicy, Fetcher, JsEngine, ScratchKvStore};
 use crate::runtime::error::RuntimeError;
+use crate::runtime::permissions::Capability;
 use crate::runtime::plugin::{PluginRuntime, PluginSpec};
 use crate::runtime::plugin_actor::PluginRuntimeClient;
+use crate::runtime::pool::Pooled;
+use crate::runtime::services::ServiceContract;
+use crate::runtime::settings::SettingsSchema;
 use crate::runtime::theme::{ThemeRuntime, ThemeSpec};
 use crate::runtime::theme_actor::ThemeRuntimeClient;
+use serde_json::Value as Json;
 use serve::render::http::{RequestContext, ResponseBodySpec};
+use serve::resolver::PluginRoute;
+use std::collections::BTreeSet;
+use std::sync::Arc;
 
 /// Configuration for plugins.
 ///
@@ -21,6 +30,20 @@ pub struct PluginConfig {
     pub name: String,
     /// JavaScript source of the plugin (already loaded from disk).
     pub source: String,
+    /// Services the plugin provides to other plugins.
+    pub provides: Vec<ServiceContract>,
+    /// Services the plugin consumes from other plugins.
+    pub requires: Vec<ServiceContract>,
+    /// Host capabilities granted to the plugin.
+    pub permissions: BTreeSet<Capability>,
+    /// Hosts the plugin may `fetch` from.
+    pub fetch: FetchPolicy,
+    /// Settings the plugin takes.
+    pub settings: SettingsSchema,
+    /// Stored settings values, checked against `settings` at load.
+    pub config: Json,
+    /// Endpoints the plugin answers itself.
+    pub routes: Vec<PluginRoute>,
 }
 
 impl From<&PluginConfig> for PluginSpec {
@@ -29,6 +52,13 @@ impl From<&PluginConfig> for PluginSpec {
             id: cfg.id.clone(),
             name: cfg.name.clone(),
             source: cfg.source.clone(),
+            provides: cfg.provides.clone(),
+            requires: cfg.requires.clone(),
+            permissions: cfg.permissions.clone(),
+            fetch: cfg.fetch.clone(),
+            settings: cfg.settings.clone(),
+            config: cfg.config.clone(),
+            routes: cfg.routes.clone(),
         }
     }
 }
@@ -39,6 +69,13 @@ impl From<&PluginSpec> for PluginConfig {
             id: spec.id.to_owned(),
             name: spec.name.to_owned(),
             source: spec.source.to_owned(),
+            provides: spec.provides.clone(),
+            requires: spec.requires.clone(),
+            permissions: spec.permissions.clone(),
+            fetch: spec.fetch.clone(),
+            settings: spec.settings.clone(),
+            config: spec.config.clone(),
+            routes: spec.routes.clone(),
         }
     }
 }
@@ -57,11 +94,16 @@ pub struct ThemeConfig {
     pub mount_path: String,
     /// JavaScript source of the theme (already loaded from disk).
     pub source: String,
+    /// Settings the theme takes.
+    pub settings: SettingsSchema,
+    /// Stored settings values, checked against `settings` at load.
+    pub config: Json,
 }
 
 impl From<&ThemeConfig> for ThemeSpec {
     fn from(cfg: &ThemeConfig) -> Self {
         ThemeSpec::new(&cfg.id, &cfg.name, &cfg.mount_path, &cfg.source)
+            .with_settings(cfg.settings.clone(), cfg.config.clone())
     }
 }
 
@@ -72,6 +114,8 @@ impl From<&ThemeSpec> for ThemeConfig {
             name: spec.name.to_owned(),
             mount_path: spec.mount_path.to_owned(),
             source: spec.source.to_owned(),
+            settings: spec.settings.clone(),
+            config: spec.config.clone(),
         }
     }
 }
@@ -119,22 +163,40 @@ impl BoundTheme<BoaEngine> {
     }
 }
 
+impl Pooled for BoundTheme<BoaEngine> {
+    fn reset(&mut self) -> Result<(), RuntimeError> {
+        self.runtime.reset_globals()
+    }
+
+    /// Same theme in a new engine, initialized with the same context.
+    fn rebuild(&self) -> Result<Self, RuntimeError> {
+        let engine = BoaEngine::with_env(self.runtime.engine().env());
+        let runtime = ThemeRuntime::new(engine, self.runtime.spec().clone())?;
+        let mut fresh = BoundTheme {
+            id: self.id.clone(),
+            runtime,
+        };
+        if let Some(ctx) = self.runtime.init_ctx() {
+            fresh.init(ctx)?;
+        }
+        Ok(fresh)
+    }
+}
+
 /// Build all runtimes (plugins + themes) and wrap them in actor clients.
 ///
-/// Call this once at process-start and pass the returned `RuntimeHandles`
-/// down into your Axum / HTTP bootstrap.
+/// Call this once per site at process-start, with the site's `env`, and
+/// pass the returned `RuntimeHandles` down into that site's HTTP bootstrap.
 pub fn bootstrap_all(
     plugin_cfgs: Vec<PluginConfig>,
     theme_cfgs: Vec<ThemeConfig>,
+    env: EngineEnv,
 ) -> Result<RuntimeHandles, RuntimeError> {
     // ─────────────────────────────────────────────────────────────────────
     // 1. Build plugin runtime: one Boa engine shared across all plugins.
     // ─────────────────────────────────────────────────────────────────────
-    let engine = BoaEngine::new();
-    let mut plugin_rt = PluginRuntime::new(engine)?;
-
     let plugin_specs: Vec<PluginSpec> = plugin_cfgs.iter().map(PluginSpec::from).collect();
-    plugin_rt.load_plugins(&plugin_specs)?;
+    let plugin_rt = build_plugin_runtime(&plugin_specs, env.clone())?;
 
     // Wrap the plugin runtime in its single-threaded actor.
     let plugin_client = PluginRuntimeClient::spawn(plugin_rt);
@@ -142,10 +204,10 @@ pub fn bootstrap_all(
     // ─────────────────────────────────────────────────────────────────────
     // 2. Build all theme runtimes: one Boa engine per theme.
     // ─────────────────────────────────────────────────────────────────────
-    let bound_themes = load_themes(&theme_cfgs)?;
+    let bound_themes = load_themes(&theme_cfgs, &env)?;
 
     // Wrap all themes in a single-threaded actor.
-    let theme_client = ThemeRuntimeClient::spawn(bound_themes);
+    let theme_client = ThemeRuntimeClient::spawn(bound_themes, env);
 
     // ─────────────────────────────────────────────────────────────────────
     // 3. Return handles to the HTTP / edge layer.
@@ -163,24 +225,81 @@ pub fn bootstrap_all(
 /// Each theme gets its own `BoaEngine` and `ThemeRuntime`. This keeps the
 /// Boa requirement of "single-threaded" satisfied while still letting the
 /// outer Axum server be multi-threaded (via the actor indirection).
-fn load_themes(theme_cfgs: &[ThemeConfig]) -> Result<Vec<BoundTheme<BoaEngine>>, RuntimeError> {
+fn load_themes(
+    theme_cfgs: &[ThemeConfig],
+    env: &EngineEnv,
+) -> Result<Vec<BoundTheme<BoaEngine>>, RuntimeError> {
     let mut themes = Vec::with_capacity(theme_cfgs.len());
 
     for cfg in theme_cfgs {
-        // Fresh JS engine per theme.
-        let engine = BoaEngine::new();
+        themes.push(build_bound_theme(cfg, env.clone())?);
+    }
+
+    Ok(themes)
+}
 
-        // Convert config → spec for the runtime.
-        let spec: ThemeSpec = ThemeSpec::from(cfg);
+/// Build a plugin runtime: one fresh Boa engine with every plugin loaded.
+///
+/// Used at boot and again by the plugin actor when plugins are hot-reloaded.
+pub(crate) fn build_plugin_runtime(
+    specs: &[PluginSpec],
+    env: EngineEnv,
+) -> Result<PluginRuntime<BoaEngine>, RuntimeError> {
+    let engine = BoaEngine::with_env(env);
+    let mut plugin_rt = PluginRuntime::new(engine)?;
+    plugin_rt.load_plugins(specs)?;
+    Ok(plugin_rt)
+}
 
-        // Create a ThemeRuntime for this theme (loads and evaluates JS).
-        let runtime = ThemeRuntime::new(engine, spec)?;
+/// Build a plugin runtime to try an upgrade out on: `storage` reads the
+/// real values in `env` but writes only to a scratch copy, and `fetch`
+/// answers 503 without sending anything. Whatever `init` or the smoke
+/// request does there leaves no trace.
+pub(crate) fn build_trial_plugin_runtime(
+    specs: &[PluginSpec],
+    env: EngineEnv,
+) -> Result<PluginRuntime<BoaEngine>, RuntimeError> {
+    let mut engine = BoaEngine::with_env(EngineEnv {
+        storage: Arc::new(ScratchKvStore::over(env.storage)),
+        log_levels: env.log_levels,
+    });
+    engine.set_fetcher(Arc::new(UnsentFetcher));
+    let mut plugin_rt = PluginRuntime::new(engine)?;
+    plugin_rt.load_plugins(specs)?;
+    Ok(plugin_rt)
+}
 
-        themes.push(BoundTheme {
-            id: cfg.id.clone(),
-            runtime,
-        });
+/// Answers every request as if the service were down, sending nothing.
+struct UnsentFetcher;
+
+impl Fetcher for UnsentFetcher {
+    fn fetch(
+        &self,
+        request: &FetchRequest,
+        _policy: &FetchPolicy,
+    ) -> Result<FetchResponse, FetchError> {
+        Ok(FetchResponse {
+            url: request.url.clone(),
+            status: 503,
+            headers: Default::default(),
+            body: String::new(),
+        })
     }
+}
 
-    Ok(themes)
+/// Build a single theme with its own fresh JS engine in `env`.
+pub(crate) fn build_bound_theme(
+    cfg: &ThemeConfig,
+    env: EngineEnv,
+) -> Result<BoundTheme<BoaEngine>, RuntimeError> {
+    // Convert config → spec for the runtime.
+    let spec: ThemeSpec = ThemeSpec::from(cfg);
+
+    // Create a ThemeRuntime for this theme (loads and evaluates JS).
+    let runtime = ThemeRuntime::new(BoaEngine::with_env(env), spec)?;
+
+    Ok(BoundTheme {
+        id: cfg.id.clone(),
+        runtime,
+    })
 }
@@ -8,8 +8,10 @@ use crate::runtime::error::RuntimeError;
 use http::{header, HeaderMap, HeaderValue, StatusCode};
 use serde_json::{json, Map as JsonMap, Value as Json};
 use serve::render::http::{RequestContext, ResponseBodySpec, ResponseSpec};
+use serve::render::islands::islands_from_json;
 use serve::render::recommendation::{
     BodyPatch, BodyPatchKind, DomOp, HeaderPatch, HeaderPatchKind, ModelPatch, Recommendations,
+    Uncacheable,
 };
 use tracing::debug;
 
@@ -56,7 +58,37 @@ pub const CTX_SHIM_SRC: &str = r#"
         };
     }
 
+    // `pattern` as `serve::l10n` formats it: `{ $var }`, `{ -term }`,
+    // `{ message }` and `{ "literal" }`.
+    function translate(messages, key, args, depth) {
+        const pattern = messages[key];
+        if (typeof pattern !== 'string' || depth > 8) {
+            return undefined;
+        }
+        return pattern.replace(/\{\s*("(?:[^"\\]|\\.)*"|[^{}"]*?)\s*\}/g, (_, inner) => {
+            if (inner.startsWith('$')) {
+                const value = args[inner.slice(1)];
+                return value === undefined || value === null ? '{' + inner + '}' : String(value);
+            }
+            if (inner.startsWith('"')) {
+                return inner.slice(1, -1).replace(/\\(.)/g, '$1');
+            }
+            const referred = translate(messages, inner, {}, depth + 1);
+            return referred === undefined ? '{' + inner + '}' : referred;
+        });
+    }
+
     function wrapCtx(ctx) {
+        if (ctx && ctx.locale && !Object.prototype.hasOwnProperty.call(ctx, 't')) {
+            const messages = ctx.locale.messages || {};
+            // Not enumerable, so it stays out of what JS hands back.
+            Object.defineProperty(ctx, 't', {
+                value(key, args) {
+                    const out = translate(messages, String(key), args || {}, 0);
+                    return out === undefined ? String(key) : out;
+                },
+            });
+        }
         if (ctx && ctx.request && ctx.request.headers && !ctx.request.headers.__wrapped) {
             const raw = ctx.request.headers;
             ctx.request.headers = wrapHeaders(raw);
@@ -78,7 +110,7 @@ pub const CTX_SHIM_SRC: &str = r#"
 #[tracing::instrument(skip_all)]
 pub fn ctx_to_js_for_plugins(ctx: &RequestContext, plugin_id: &str) -> JsValue {
     let cfg = ctx.plugin_configs.get(plugin_id);
-    ctx_to_js(ctx, cfg)
+    ctx_to_js(ctx, cfg, plugin_id)
 }
 
 /// Build the JS context object for themes.
@@ -90,7 +122,7 @@ pub fn ctx_to_js_for_plugins(ctx: &RequestContext, plugin_id: &str) -> JsValue {
 pub fn ctx_to_js_for_theme(ctx: &RequestContext, theme_id: &str) -> JsValue {
     debug!("RequestContext for theme {}: {:?}", theme_id, ctx.req_id);
     let cfg = Some(&ctx.theme_config);
-    ctx_to_js(ctx, cfg)
+    ctx_to_js(ctx, cfg, theme_id)
 }
 
 /// Merge JS result back into Rust context for plugins.
@@ -115,8 +147,14 @@ pub fn merge_theme_ctx_from_js(
 // Rust -> JS
 // ─────────────────────────────────────────────────────────────────────────────
 
+/// Build the JS context object for the theme or plugin `owner`, with
+/// `config` as `ctx.config`.
+pub(crate) fn ctx_to_js_with_config(ctx: &RequestContext, config: &Json, owner: &str) -> JsValue {
+    ctx_to_js(ctx, Some(config), owner)
+}
+
 #[tracing::instrument(skip_all)]
-fn ctx_to_js(ctx: &RequestContext, config: Option<&serde_json::Value>) -> JsValue {
+fn ctx_to_js(ctx: &RequestContext, config: Option<&serde_json::Value>, owner: &str) -> JsValue {
     debug!("Config: {:?}", config);
     let mut root = JsonMap::new();
 
@@ -133,6 +171,14 @@ fn ctx_to_js(ctx: &RequestContext, config: Option<&serde_json::Value>) -> JsValu
     req_obj.insert("version".to_string(), ctx.req_version.clone());
     req_obj.insert("headers".to_string(), ctx.req_headers.clone());
     req_obj.insert("queryParams".to_string(), ctx.req_params.clone());
+    req_obj.insert("routeParams".to_string(), json!(ctx.route_params));
+    req_obj.insert(
+        "baseUrl".to_string(),
+        ctx.base_url.clone().map_or(Json::Null, Json::String),
+    );
+    let (body, raw_body) = body_to_js(ctx);
+    req_obj.insert("body".to_string(), body);
+    req_obj.insert("rawBody".to_string(), raw_body);
 
     root.insert("request".to_string(), Json::Object(req_obj));
 
@@ -175,6 +221,9 @@ fn ctx_to_js(ctx: &RequestContext, config: Option<&serde_json::Value>) -> JsValu
     let body_vals: Vec<Json> = recs.body_patches.iter().map(body_patch_to_js).collect();
     recs_obj.insert("bodyPatches".to_string(), Json::Array(body_vals));
 
+    let uncacheable_vals: Vec<Json> = recs.uncacheable.iter().map(uncacheable_to_js).collect();
+    recs_obj.insert("uncacheable".to_string(), Json::Array(uncacheable_vals));
+
     content_obj.insert("recommendations".to_string(), Json::Object(recs_obj));
 
     root.insert("content".to_string(), Json::Object(content_obj));
@@ -188,9 +237,92 @@ fn ctx_to_js(ctx: &RequestContext, config: Option<&serde_json::Value>) -> JsValu
         root.insert("config".to_string(), Json::Object(JsonMap::new()));
     }
 
+    // ---------------------------------------------------------------------
+    // feature flags (read-only; decided by the host before any JS runs)
+    // ---------------------------------------------------------------------
+    let flags: JsonMap<String, Json> = ctx
+        .flags
+        .iter()
+        .map(|(name, on)| (name.clone(), Json::Bool(*on)))
+        .collect();
+    root.insert("flags".to_string(), Json::Object(flags));
+
+    // ---------------------------------------------------------------------
+    // saved queries, already run (read-only, like flags)
+    // ---------------------------------------------------------------------
+    let collections: JsonMap<String, Json> = ctx
+        .collections
+        .iter()
+        .map(|(name, docs)| (name.clone(), docs.clone()))
+        .collect();
+    root.insert("collections".to_string(), Json::Object(collections));
+
+    // ---------------------------------------------------------------------
+    // the owner's catalog strings, for `ctx.t` (see CTX_SHIM_SRC)
+    // ---------------------------------------------------------------------
+    if let Some(localized) = ctx.messages.get(owner) {
+        root.insert(
+            "locale".to_string(),
+            serde_json::to_value(localized.as_ref()).unwrap_or(Json::Null),
+        );
+    }
+
     JsValue::from_json(&Json::Object(root))
 }
 
+/// The request body as `(ctx.request.body, ctx.request.rawBody)`.
+///
+/// `body` is parsed by content type: JSON (`application/json`, `*+json`)
+/// as its value, a form (`application/x-www-form-urlencoded`) as an object
+/// of strings, where a field sent more than once is an array, and `text/*`
+/// as a string. Anything else, or a body that does not parse, is null.
+/// `rawBody` is the body as sent, for checking webhook signatures, or null
+/// when it is not UTF-8. The router caps the size before the body gets here.
+fn body_to_js(ctx: &RequestContext) -> (Json, Json) {
+    let Some(bytes) = ctx.req_body.as_ref().filter(|b| !b.is_empty()) else {
+        return (Json::Null, Json::Null);
+    };
+    let text = std::str::from_utf8(bytes).ok();
+    let media_type = ctx
+        .req_headers
+        .as_object()
+        .and_then(|h| {
+            h.iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+        })
+        .and_then(|(_, v)| v.as_str())
+        .and_then(|v| v.split(';').next())
+        .map(|v| v.trim().to_ascii_lowercase())
+        .unwrap_or_default();
+
+    let body = match media_type.as_str() {
+        "application/json" => serde_json::from_slice(bytes).unwrap_or(Json::Null),
+        t if t.ends_with("+json") => serde_json::from_slice(bytes).unwrap_or(Json::Null),
+        "application/x-www-form-urlencoded" => form_to_js(bytes),
+        t if t.starts_with("text/") => text.map_or(Json::Null, |s| Json::String(s.to_owned())),
+        _ => Json::Null,
+    };
+    (
+        body,
+        text.map_or(Json::Null, |s| Json::String(s.to_owned())),
+    )
+}
+
+fn form_to_js(bytes: &[u8]) -> Json {
+    let mut fields = JsonMap::new();
+    for (name, value) in form_urlencoded::parse(bytes) {
+        let value = Json::String(value.into_owned());
+        match fields.get_mut(name.as_ref()) {
+            None => {
+                fields.insert(name.into_owned(), value);
+            }
+            Some(Json::Array(values)) => values.push(value),
+            Some(first) => *first = Json::Array(vec![first.take(), value]),
+        }
+    }
+    Json::Object(fields)
+}
+
 #[tracing::instrument(skip_all)]
 fn response_spec_to_js(spec: &ResponseSpec) -> Json {
     debug!("Response spec headers: {:?}", spec.headers);
@@ -213,10 +345,15 @@ fn response_spec_to_js(spec: &ResponseSpec) -> Json {
 
     // body
     let body_json = match &spec.body {
-        ResponseBodySpec::HtmlTemplate { template, model } => json!({
+        ResponseBodySpec::HtmlTemplate {
+            template,
+            model,
+            islands,
+        } => json!({
             "kind": "htmlTemplate",
             "template": template,
             "model": model,
+            "islands": islands,
         }),
         ResponseBodySpec::HtmlString(html) => json!({
             "kind": "htmlString",
@@ -240,6 +377,7 @@ fn header_patch_to_js(hp: &HeaderPatch) -> Json {
         HeaderPatchKind::Set => ("set", true),
         HeaderPatchKind::Append => ("append", true),
         HeaderPatchKind::Remove => ("remove", false),
+        HeaderPatchKind::AppendSource => ("appendSource", true),
     };
 
     let mut obj = JsonMap::new();
@@ -271,6 +409,14 @@ fn model_patch_to_js(mp: &ModelPatch) -> Json {
     Json::Object(obj)
 }
 
+#[tracing::instrument(skip_all)]
+fn uncacheable_to_js(u: &Uncacheable) -> Json {
+    json!({
+        "reason": u.reason,
+        "sourcePlugin": u.source_plugin,
+    })
+}
+
 #[tracing::instrument(skip_all)]
 fn dom_op_to_js(op: &DomOp) -> Json {
     match op {
@@ -347,6 +493,7 @@ fn parse_header_patch(v: &Json) -> Option<HeaderPatch> {
         "set" => HeaderPatchKind::Set,
         "append" => HeaderPatchKind::Append,
         "remove" => HeaderPatchKind::Remove,
+        "appendSource" => HeaderPatchKind::AppendSource,
         _ => return None,
     };
 
@@ -381,6 +528,21 @@ fn parse_model_patch(v: &Json) -> Option<ModelPatch> {
     }
 }
 
+#[tracing::instrument(skip_all)]
+fn parse_uncacheable(v: &Json) -> Option<Uncacheable> {
+    let obj = v.as_object()?;
+    let source_plugin = obj.get("sourcePlugin")?.as_str()?.to_string();
+    let reason = obj
+        .get("reason")
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string());
+
+    Some(Uncacheable {
+        reason,
+        source_plugin,
+    })
+}
+
 #[tracing::instrument(skip_all)]
 fn parse_dom_op(v: &Json) -> Option<DomOp> {
     let obj = v.as_object()?;
@@ -487,7 +649,12 @@ fn parse_response_spec(v: &Json) -> Option<ResponseSpec> {
                     .unwrap_or_default()
                     .to_string();
                 let model = body_obj.get("model").cloned().unwrap_or(Json::Null);
-                ResponseBodySpec::HtmlTemplate { template, model }
+                let islands = islands_from_json(body_obj.get("islands"));
+                ResponseBodySpec::HtmlTemplate {
+                    template,
+                    model,
+                    islands,
+                }
             }
 
             // 🔧 NEW: handle htmlString from JS
@@ -554,6 +721,14 @@ fn merge_from_js(ret: &JsValue, ctx: &mut RequestContext) -> Result<(), RuntimeE
                 }
             }
 
+            if let Some(u_arr) = recs_obj.get("uncacheable").and_then(|v| v.as_array()) {
+                for u_v in u_arr {
+                    if let Some(u) = parse_uncacheable(u_v) {
+                        new_recs.uncacheable.push(u);
+                    }
+                }
+            }
+
             ctx.recommendations
                 .header_patches
                 .extend(new_recs.header_patches.into_iter());
@@ -563,6 +738,9 @@ fn merge_from_js(ret: &JsValue, ctx: &mut RequestContext) -> Result<(), RuntimeE
             ctx.recommendations
                 .body_patches
                 .extend(new_recs.body_patches.into_iter());
+            ctx.recommendations
+                .uncacheable
+                .extend(new_recs.uncacheable.into_iter());
         }
     }
 
@@ -657,6 +835,78 @@ mod tests {
         assert_eq!(config.get("darkMode").and_then(|v| v.as_bool()), Some(true));
     }
 
+    #[test]
+    fn ctx_to_js_exposes_flags() {
+        let mut ctx = make_base_ctx();
+        ctx.flags.insert("new-nav".to_string(), true);
+        ctx.flags.insert("comments".to_string(), false);
+
+        let json = ctx_to_js_for_theme(&ctx, "any-theme-id").to_json();
+
+        assert_eq!(json["flags"], json!({"new-nav": true, "comments": false}));
+        assert_eq!(
+            ctx_to_js_for_plugins(&make_base_ctx(), "p").to_json()["flags"],
+            json!({})
+        );
+    }
+
+    #[test]
+    fn ctx_to_js_exposes_the_base_url() {
+        let mut ctx = make_base_ctx();
+        assert_eq!(
+            ctx_to_js_for_theme(&ctx, "t").to_json()["request"]["baseUrl"],
+            Json::Null
+        );
+
+        ctx.base_url = Some("https://example.com".to_string());
+        assert_eq!(
+            ctx_to_js_for_theme(&ctx, "t").to_json()["request"]["baseUrl"],
+            json!("https://example.com")
+        );
+    }
+
+    #[test]
+    fn ctx_to_js_parses_the_request_body_by_content_type() {
+        let with_body = |content_type: &str, body: &'static [u8]| {
+            let mut ctx = make_base_ctx();
+            ctx.req_headers = json!({ "Content-Type": content_type });
+            ctx.req_body = Some(bytes::Bytes::from_static(body));
+            ctx_to_js_for_plugins(&ctx, "p").to_json()["request"].clone()
+        };
+
+        let req = with_body("application/json; charset=utf-8", br#"{"event":"ping"}"#);
+        assert_eq!(req["body"], json!({ "event": "ping" }));
+        assert_eq!(req["rawBody"], json!(r#"{"event":"ping"}"#));
+
+        let req = with_body(
+            "application/x-www-form-urlencoded",
+            b"name=Ada+L&topic=a&topic=b&msg=hi%21",
+        );
+        assert_eq!(
+            req["body"],
+            json!({ "name": "Ada L", "topic": ["a", "b"], "msg": "hi!" })
+        );
+
+        assert_eq!(with_body("text/plain", b"hello")["body"], json!("hello"));
+        assert_eq!(with_body("application/json", b"{oops")["body"], Json::Null);
+        let req = with_body("application/octet-stream", b"\xff\x00");
+        assert_eq!((&req["body"], &req["rawBody"]), (&Json::Null, &Json::Null));
+
+        let req = ctx_to_js_for_plugins(&make_base_ctx(), "p").to_json();
+        assert_eq!(req["request"]["body"], Json::Null);
+    }
+
+    #[test]
+    fn ctx_to_js_exposes_collections() {
+        let mut ctx = make_base_ctx();
+        ctx.collections
+            .insert("featured".to_string(), json!([{ "id": "/a" }]));
+
+        let json = ctx_to_js_for_plugins(&ctx, "p").to_json();
+
+        assert_eq!(json["collections"], json!({ "featured": [{ "id": "/a" }] }));
+    }
+
     #[test]
     fn merge_from_js_appends_recommendations_and_overrides_response() {
         let mut ctx = make_base_ctx();
@@ -692,6 +942,10 @@ mod tests {
                         "replacement": "bar",
                         "sourcePlugin": "p1"
                     }
+                ],
+                "uncacheable": [
+                    { "reason": "per-visitor greeting", "sourcePlugin": "p1" },
+                    { "reason": "no source plugin" }
                 ]
             },
             "response": {
@@ -732,6 +986,16 @@ mod tests {
             BodyPatchKind::Regex { .. }
         );
 
+        // Only the attributed uncacheable mark is kept
+        assert_eq!(
+            ctx.recommendations.uncacheable,
+            vec![Uncacheable {
+                reason: Some("per-visitor greeting".into()),
+                source_plugin: "p1".into(),
+            }]
+        );
+        assert!(!ctx.recommendations.is_cacheable());
+
         // Response overridden
         assert_eq!(ctx.response_spec.status, StatusCode::CREATED);
         let ct = ctx
@@ -790,6 +1054,35 @@ mod tests {
         assert_eq!(hv, vec!["a".to_string(), "b".to_string()]);
     }
 
+    #[test]
+    fn parse_response_spec_reads_template_islands() {
+        let js = json!({
+            "body": {
+                "kind": "htmlTemplate",
+                "template": "post.hbs",
+                "model": { "title": "Hello" },
+                "islands": [
+                    { "component": "Comments", "props": { "postId": 42 } },
+                    { "component": "Rating", "id": "rating", "props": { "stars": 4 } }
+                ]
+            }
+        });
+
+        let spec = parse_response_spec(&js).expect("parse_response_spec must return Some");
+        match &spec.body {
+            ResponseBodySpec::HtmlTemplate { islands, .. } => {
+                assert_eq!(islands.len(), 2);
+                assert_eq!(islands[0].component, "Comments");
+                assert_eq!(islands[0].props, json!({ "postId": 42 }));
+                assert_eq!(islands[1].id.as_deref(), Some("rating"));
+            }
+            other => panic!("expected HtmlTemplate body, got {:?}", other),
+        }
+
+        let round_trip = response_spec_to_js(&spec);
+        assert_eq!(round_trip["body"]["islands"][1]["props"]["stars"], 4);
+    }
+
     #[test]
     fn parse_body_patch_rejects_missing_source_plugin() {
         let v = json!({
@@ -847,4 +1140,63 @@ mod tests {
             .expect("headers missing");
         assert_eq!(hdrs.get("x-test").and_then(|v| v.as_str()), Some("ok"));
     }
+
+    #[test]
+    fn plugins_translate_with_their_catalog_when_they_have_one() {
+        use crate::js::engine::BoaEngine;
+        use crate::runtime::plugin::{PluginRuntime, PluginSpec};
+        use serve::l10n::{Localized, Messages};
+        use std::sync::Arc;
+
+        let id = "greeter";
+        let spec = PluginSpec {
+            id: id.into(),
+            name: id.into(),
+            source: format!(
+                r#"function init(ctx) {{
+                    registerPlugin({{ before(ctx) {{
+                        const said = typeof ctx.t === "function"
+                            ? ctx.t("hi", {{ name: "Ada" }}) + "|" + ctx.t("nope")
+                            : "none";
+                        return {{ recommendations: {{ headerPatches: [
+                            {{ kind: "set", name: "x-{id}", value: said, sourcePlugin: "{id}" }},
+                        ] }} }};
+                    }} }});
+                }}"#
+            ),
+            provides: Vec::new(),
+            requires: Vec::new(),
+            permissions: Default::default(),
+            fetch: Default::default(),
+            settings: Default::default(),
+            config: Json::Null,
+            routes: Vec::new(),
+        };
+        let mut rt = PluginRuntime::new(BoaEngine::new()).unwrap();
+        rt.load_plugins(&[spec]).unwrap();
+        rt.init_all(&make_base_ctx()).unwrap();
+
+        let mut ctx = make_base_ctx();
+        ctx.messages.insert(
+            "greeter".into(),
+            Arc::new(Localized {
+                lang: "de".into(),
+                messages: Messages::from([
+                    ("-brand".to_owned(), "Whisper".to_owned()),
+                    (
+                        "hi".to_owned(),
+                        r#"Hallo { $name }, { -brand } { "{" }"#.to_owned(),
+                    ),
+                ]),
+            }),
+        );
+        let mut said = |ctx: &RequestContext| {
+            let mut ctx = ctx.clone();
+            rt.before_plugin(id, &mut ctx).unwrap();
+            ctx.recommendations.header_patches.pop().unwrap().value
+        };
+        assert_eq!(said(&ctx).as_deref(), Some("Hallo Ada, Whisper {|nope"));
+        ctx.messages.clear();
+        assert_eq!(said(&ctx).as_deref(), Some("none"));
+    }
 }
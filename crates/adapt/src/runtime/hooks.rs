@@ -0,0 +1,340 @@
+// crates/adapt/src/runtime/hooks.rs
+
+//! Plugin-to-plugin hooks: named events any plugin can listen for.
+//!
+//! A plugin registers listeners from `init`:
+//!
+//! ```js
+//! function init(ctx) {
+//!     ctx.hooks.register("content.before_render", (req) => ({
+//!         recommendations: {
+//!             headerPatches: [{ kind: "set", name: "x-reading-time", value: minutes(req) }],
+//!         },
+//!     }));
+//!     ctx.hooks.register("seo.title", (title) => title + " | Blog", 20);
+//! }
+//! ```
+//!
+//! and any plugin, or the host, fires them:
+//!
+//! - `ctx.hooks.emit(name, payload)` calls every listener with `payload`
+//!   and returns what they returned (`null`/`undefined` left out). Emitted
+//!   by the host ([`PluginRuntime::emit`]), each result is a
+//!   recommendations object merged into the request context like a `before`
+//!   hook's.
+//! - `ctx.hooks.filter(name, value)` passes `value` through the listeners in
+//!   turn, each returning the next value (`undefined` keeps it), and
+//!   returns the last ([`PluginRuntime::filter`]).
+//!
+//! A listener is called as `fn(payload, { hooks, host })`: its own
+//! plugin's `ctx.hooks` and `ctx.host`, which the `init` ctx it closed over
+//! no longer has.
+//!
+//! Listeners run by priority (lower first, [`DEFAULT_PRIORITY`] if not
+//! given), then in registration order, which follows the deterministic
+//! init order. Each runs with its own plugin's permissions and log tags,
+//! not the emitter's. Listeners are synchronous; registering is only
+//! possible during `init`, so the set is fixed once plugins are up.
+//!
+//! [`PluginRuntime::emit`]: super::PluginRuntime::emit
+//! [`PluginRuntime::filter`]: super::PluginRuntime::filter
+
+/// Fired by the host once every plugin's `before` hook has run, with the
+/// request as the payload.
+pub const CONTENT_BEFORE_RENDER: &str = "content.before_render";
+
+/// Filtered by the host with each form submission (`{ form, fields }`)
+/// before it is stored. A listener returns it changed, or with a
+/// `rejected` reason to turn it away; like any listener it runs with its
+/// own plugin's permissions, so one that forwards submissions elsewhere
+/// needs `network`.
+pub const FORM_SUBMISSION: &str = "form.submission";
+
+/// Priority of a listener registered without one.
+pub const DEFAULT_PRIORITY: i32 = 10;
+
+/// How deeply emits may nest (a listener emitting, and so on) before the
+/// innermost throws.
+pub const MAX_EMIT_DEPTH: u32 = 8;
+
+/// JS side of the hook registry. `__hooksFor(internalId, registering)` is
+/// the per-plugin `ctx.hooks` view the services trampoline attaches;
+/// `__emitHook` and `__filterHook` are the host's way in. All three, and
+/// the `__runAs` they are built on, are among the runtime's
+/// [`HOST_ONLY_GLOBALS`](super::plugin::HOST_ONLY_GLOBALS), which plugin
+/// code never sees.
+pub fn hooks_shim_src() -> String {
+    format!(
+        r#"
+(function (global) {{
+    // Captured now: these globals are hidden before any plugin loads.
+    const runAs = global.__runAs;
+    const host = global.__host;
+    const listeners = Object.create(null);
+    let seq = 0;
+    let depth = 0;
+
+    function checkName(op, name) {{
+        if (typeof name !== "string" || name === "") {{
+            throw new TypeError(`hooks.${{op}}: name must be a non-empty string`);
+        }}
+    }}
+
+    // A listener's second argument: its own plugin's hooks and host API,
+    // since the ctx it registered from no longer carries them.
+    function toolsFor(internalId) {{
+        return {{
+            hooks: hooksFor(internalId, false),
+            host: {{ call: (op, args) => host(op, args) }},
+        }};
+    }}
+
+    function each(op, name, visit) {{
+        checkName(op, name);
+        if (depth >= {max_depth}) {{
+            throw new Error(`hooks.${{op}}: '${{name}}' nested more than {max_depth} deep`);
+        }}
+        depth++;
+        try {{
+            for (const l of (listeners[name] || []).slice()) {{
+                visit(l);
+            }}
+        }} finally {{
+            depth--;
+        }}
+    }}
+
+    function emitHook(name, payload) {{
+        const results = [];
+        each("emit", name, (l) => {{
+            const r = runAs(l.internalId, l.fn, payload, toolsFor(l.internalId));
+            if (r !== undefined && r !== null) {{
+                results.push(r);
+            }}
+        }});
+        return results;
+    }}
+
+    function filterHook(name, value) {{
+        each("filter", name, (l) => {{
+            const r = runAs(l.internalId, l.fn, value, toolsFor(l.internalId));
+            if (r !== undefined) {{
+                value = r;
+            }}
+        }});
+        return value;
+    }}
+
+    function hooksFor(internalId, registering) {{
+        return {{
+            register(name, fn, priority) {{
+                checkName("register", name);
+                if (!registering) {{
+                    throw new Error("hooks.register: listeners are registered in init");
+                }}
+                if (typeof fn !== "function") {{
+                    throw new TypeError("hooks.register: listener must be a function");
+                }}
+                const p = priority === undefined ? {default_priority} : priority;
+                if (!Number.isFinite(p)) {{
+                    throw new TypeError("hooks.register: priority must be a number");
+                }}
+                const list = listeners[name] || (listeners[name] = []);
+                list.push({{ priority: p, seq: seq++, internalId: internalId, fn: fn }});
+                list.sort((a, b) => a.priority - b.priority || a.seq - b.seq);
+            }},
+            emit: (name, payload) => emitHook(name, payload),
+            filter: (name, value) => filterHook(name, value),
+            has: (name) => (listeners[name] || []).length > 0,
+        }};
+    }}
+
+    global.__emitHook = emitHook;
+    global.__filterHook = filterHook;
+    global.__hooksFor = hooksFor;
+}})(typeof globalThis !== "undefined" ? globalThis : this);
+"#,
+        max_depth = MAX_EMIT_DEPTH,
+        default_priority = DEFAULT_PRIORITY,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::js::engine::BoaEngine;
+    use crate::js::{JsValue, KvStore, MemoryKvStore};
+    use crate::runtime::permissions::Capability;
+    use crate::runtime::plugin::{PluginRuntime, PluginSpec};
+    use serve::render::http::RequestContext;
+    use std::sync::Arc;
+
+    fn spec(id: &str, source: &str) -> PluginSpec {
+        PluginSpec {
+            id: id.into(),
+            name: id.into(),
+            source: source.into(),
+            provides: Vec::new(),
+            requires: Vec::new(),
+            permissions: Default::default(),
+            fetch: Default::default(),
+            settings: Default::default(),
+            config: Default::default(),
+            routes: Vec::new(),
+        }
+    }
+
+    fn runtime(specs: &[PluginSpec]) -> PluginRuntime<BoaEngine> {
+        let mut rt = PluginRuntime::new(BoaEngine::new()).unwrap();
+        rt.load_plugins(specs).unwrap();
+        rt.init_all(&RequestContext::builder().build()).unwrap();
+        rt
+    }
+
+    #[test]
+    fn listeners_run_by_priority_then_registration_order() {
+        let rt = &mut runtime(&[
+            spec(
+                "late",
+                r#"function init(ctx) { ctx.hooks.register("title", (t) => t + " late", 20); }"#,
+            ),
+            spec(
+                "early",
+                r#"function init(ctx) {
+                    ctx.hooks.register("title", (t) => t + " first", 5);
+                    ctx.hooks.register("title", (t) => undefined);
+                    ctx.hooks.register("title", (t) => t + " default");
+                }"#,
+            ),
+            spec(
+                "relay",
+                r#"function init(ctx) {
+                    ctx.hooks.register("page", (p, tools) => ({
+                        recommendations: {
+                            headerPatches: [{
+                                kind: "set", name: "x-title", sourcePlugin: "relay",
+                                value: tools.hooks.filter("title", p.title),
+                            }],
+                        },
+                    }));
+                    ctx.hooks.register("page", () => null);
+                }"#,
+            ),
+        ]);
+        let ctx = RequestContext::builder().build();
+
+        let title = rt.filter("title", JsValue::string("Home"), &ctx).unwrap();
+        assert_eq!(title, JsValue::string("Home first default late"));
+        assert_eq!(
+            rt.filter("nobody", JsValue::number(1.0), &ctx).unwrap(),
+            JsValue::number(1.0)
+        );
+
+        let mut ctx = ctx;
+        let payload = JsValue::from_json(&serde_json::json!({ "title": "About" }));
+        rt.emit("page", payload, &mut ctx).unwrap();
+        let patches = &ctx.recommendations.header_patches;
+        assert_eq!(patches.len(), 1);
+        assert_eq!(
+            patches[0].value.as_deref(),
+            Some("About first default late")
+        );
+    }
+
+    #[test]
+    fn listeners_run_with_their_own_permissions_and_only_register_in_init() {
+        let mut trusted = spec(
+            "trusted",
+            r#"function init(ctx) {
+                ctx.hooks.register("ping", (_, { host }) => host.call("secret"));
+            }"#,
+        );
+        trusted.permissions.insert(Capability::DbRead);
+        let untrusted = spec(
+            "untrusted",
+            r#"function init(ctx) {
+                ctx.hooks.register("steal", (_, { host }) => host.call("secret"));
+                registerPlugin({ before: (ctx) => ctx.hooks.register("ping", () => 1) });
+            }"#,
+        );
+
+        let mut rt = PluginRuntime::new(BoaEngine::new()).unwrap();
+        rt.register_host_fn(
+            "secret",
+            Some(Capability::DbRead),
+            Box::new(|_| Ok(serde_json::json!("s3cret"))),
+        );
+        rt.load_plugins(&[trusted, untrusted]).unwrap();
+        rt.init_all(&RequestContext::builder().build()).unwrap();
+        let ctx = RequestContext::builder().build();
+
+        assert_eq!(
+            rt.filter("ping", JsValue::Null, &ctx).unwrap(),
+            JsValue::string("s3cret")
+        );
+        let err = rt
+            .filter("steal", JsValue::Null, &ctx)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("'untrusted' is missing capability"),
+            "got {err}"
+        );
+
+        let err = rt
+            .before_plugin("untrusted", &mut ctx.clone())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("registered in init"), "got {err}");
+    }
+
+    #[test]
+    fn plugins_cannot_act_as_or_reach_another_plugin() {
+        let store = Arc::new(MemoryKvStore::default());
+        let victim = spec(
+            "victim",
+            r#"function init(ctx) {
+                storage.set("secret", "s3cret");
+                ctx.hooks.register("ping", () => storage.get("secret"));
+            }"#,
+        );
+        let spy = spec(
+            "spy",
+            r#"function init(ctx) {
+                const reached = Object.getOwnPropertyNames(globalThis).filter((name) =>
+                    name.startsWith("plugin_") || name === "__pluginInits" ||
+                    name === "__runAs" || name === "__emitHook" ||
+                    name === "__filterHook" || name === "__hooksFor");
+                try { globalThis.storage = { get: () => "mine" }; } catch (_) {}
+                const wrapped = storage.get("secret") === "mine";
+                storage.set("stolen", [ctx.hooks.filter("ping", null), storage.get("secret")]);
+                globalThis.probe = { reached: reached, wrapped: wrapped };
+            }"#,
+        );
+
+        let mut engine = BoaEngine::new();
+        engine.set_storage(store.clone());
+        let mut rt = PluginRuntime::new(engine).unwrap();
+        rt.load_plugins(&[victim, spy]).unwrap();
+        rt.init_all(&RequestContext::builder().build()).unwrap();
+
+        assert_eq!(
+            rt.eval_for_test("probe").unwrap().to_json(),
+            serde_json::json!({ "reached": [], "wrapped": false })
+        );
+        // The listener ran as the victim, so it read the victim's value;
+        // the spy's own namespace has no such key.
+        assert_eq!(
+            store.get("spy", "stolen").unwrap(),
+            Some(serde_json::json!(["s3cret", null]))
+        );
+        assert_eq!(
+            store.get("victim", "secret").unwrap(),
+            Some(serde_json::json!("s3cret"))
+        );
+        let err = rt
+            .eval_for_test(r#"__runAs("plugin_x", () => 1)"#)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("__runAs"), "got {err}");
+    }
+}
@@ -1,14 +1,22 @@
 pub mod bootstrap;
 pub mod bridge;
 pub mod error;
+pub mod hooks;
+pub mod permissions;
 pub mod plugin;
 pub mod plugin_actor;
+pub mod pool;
+pub mod services;
+pub mod settings;
 pub mod theme;
 pub mod theme_actor;
 
 pub use bridge::{ctx_to_js_for_plugins, merge_recommendations_from_js};
 pub use error::RuntimeError;
+pub use permissions::Capability;
 pub use plugin::{PluginRuntime, PluginSpec};
 pub use plugin_actor::PluginRuntimeClient;
+pub use pool::{EnginePool, Pooled};
+pub use settings::{SettingDef, SettingType, SettingsSchema};
 pub use theme::{ThemeRuntime, ThemeSpec};
 pub use theme_actor::ThemeRuntimeClient;
@@ -0,0 +1,138 @@
+// crates/adapt/src/runtime/permissions.rs
+
+//! Plugin capabilities.
+//!
+//! A plugin lists what it may do in its manifest:
+//!
+//! ```toml
+//! permissions = ["db.read", "network"]
+//! ```
+//!
+//! Host APIs exposed to JS are registered against a capability; a plugin
+//! calling one it was not granted fails with
+//! [`RuntimeError::PluginExecution`](super::RuntimeError::PluginExecution)
+//! naming the missing capability. Unknown permission names are rejected when
+//! the manifest is parsed.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// A capability a plugin can be granted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Capability {
+    /// Outbound network access: `fetch`, to the hosts in the manifest's
+    /// `[fetch]` table (see [`crate::js::fetch`]).
+    #[serde(rename = "network")]
+    Network,
+    /// Reading content and indexes.
+    #[serde(rename = "db.read")]
+    DbRead,
+    /// Creating, updating or deleting content.
+    #[serde(rename = "db.write")]
+    DbWrite,
+    /// Reading files from the site root.
+    #[serde(rename = "fs.read")]
+    FsRead,
+}
+
+impl Capability {
+    /// The manifest spelling, e.g. `"db.write"`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Capability::Network => "network",
+            Capability::DbRead => "db.read",
+            Capability::DbWrite => "db.write",
+            Capability::FsRead => "fs.read",
+        }
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::js::engine::BoaEngine;
+    use crate::runtime::error::RuntimeError;
+    use crate::runtime::plugin::{PluginRuntime, PluginSpec};
+    use serde_json::json;
+    use serve::render::http::RequestContext;
+    use std::collections::BTreeSet;
+
+    #[derive(Deserialize)]
+    struct Manifest {
+        permissions: BTreeSet<Capability>,
+    }
+
+    fn writer(id: &str, permissions: &[Capability]) -> PluginSpec {
+        PluginSpec {
+            id: id.into(),
+            name: id.into(),
+            source: r#"
+                function init(ctx) {
+                    globalThis.__putResult = ctx.host.call("content.put", { id: "a" });
+                }
+            "#
+            .into(),
+            provides: Vec::new(),
+            requires: Vec::new(),
+            permissions: permissions.iter().copied().collect(),
+            fetch: Default::default(),
+            settings: Default::default(),
+            config: Default::default(),
+            routes: Vec::new(),
+        }
+    }
+
+    fn runtime_with_put() -> PluginRuntime<BoaEngine> {
+        let mut rt = PluginRuntime::new(BoaEngine::new()).unwrap();
+        rt.register_host_fn(
+            "content.put",
+            Some(Capability::DbWrite),
+            Box::new(|args| Ok(json!({ "stored": args["id"] }))),
+        );
+        rt
+    }
+
+    #[test]
+    fn manifest_permissions_parse_and_unknown_names_fail() {
+        let m: Manifest = toml::from_str(r#"permissions = ["db.read", "network"]"#).unwrap();
+        assert_eq!(
+            m.permissions.into_iter().collect::<Vec<_>>(),
+            vec![Capability::Network, Capability::DbRead]
+        );
+
+        assert!(toml::from_str::<Manifest>(r#"permissions = ["db.delete"]"#).is_err());
+    }
+
+    #[test]
+    fn write_api_without_db_write_names_the_missing_capability() {
+        let mut rt = runtime_with_put();
+        rt.load_plugins(&[writer("reader", &[Capability::DbRead])])
+            .unwrap();
+
+        match rt.init_all(&RequestContext::builder().build()) {
+            Err(RuntimeError::PluginExecution(msg)) => {
+                assert!(msg.contains("'reader'"), "got {msg}");
+                assert!(msg.contains("'db.write'"), "got {msg}");
+                assert!(msg.contains("'content.put'"), "got {msg}");
+            }
+            other => panic!("expected PluginExecution, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn granted_plugin_can_call_write_api() {
+        let mut rt = runtime_with_put();
+        rt.load_plugins(&[writer("editor", &[Capability::DbWrite])])
+            .unwrap();
+
+        rt.init_all(&RequestContext::builder().build()).unwrap();
+        let out = rt.eval_for_test("globalThis.__putResult").unwrap();
+        assert_eq!(out.to_json(), json!({ "stored": "a" }));
+    }
+}
@@ -1,13 +1,20 @@
 // crates/adapt/src/runtime/plugin.rs
 
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 
-use super::bridge::{ctx_to_js_for_plugins, merge_recommendations_from_js, CTX_SHIM_SRC};
+use super::bridge::{ctx_to_js_with_config, merge_recommendations_from_js, CTX_SHIM_SRC};
 use super::error::RuntimeError;
-use crate::js::{JsEngine, JsError, JsValue};
+use super::hooks::hooks_shim_src;
+use super::permissions::Capability;
+use super::pool::{global_shape, reset_globals, GlobalShape, Pooled};
+use super::services::{resolve_init_order, service_grants, ServiceContract, SERVICES_SHIM_SRC};
+use super::settings::SettingsSchema;
+use crate::js::engine::BoaEngine;
+use crate::js::{FetchPolicy, HostCaller, HostFn, JsEngine, JsValue, LogScope};
 use serve::render::http::RequestContext;
+use serve::resolver::PluginRoute;
 
-use serde_json;
+use serde_json::{self, Value as Json};
 use tracing::debug;
 use uuid::Uuid;
 
@@ -17,6 +24,22 @@ pub struct PluginSpec {
     pub id: String,
     pub name: String,
     pub source: String,
+    /// Services this plugin registers during `init`.
+    pub provides: Vec<ServiceContract>,
+    /// Services this plugin may look up via `ctx.services.get`.
+    pub requires: Vec<ServiceContract>,
+    /// Host APIs this plugin may call (see [`Capability`]).
+    pub permissions: BTreeSet<Capability>,
+    /// Hosts `fetch` may reach (needs [`Capability::Network`]).
+    pub fetch: FetchPolicy,
+    /// The settings the plugin takes (`[settings]` in plugin.toml).
+    pub settings: SettingsSchema,
+    /// The site's stored settings values, null for none; checked against
+    /// `settings` on load.
+    pub config: Json,
+    /// Endpoints dispatched straight to one of this plugin's functions
+    /// (`[[routes]]` in plugin.toml).
+    pub routes: Vec<PluginRoute>,
 }
 
 /// Metadata for runtime bookkeeping
@@ -25,6 +48,25 @@ pub struct PluginMeta {
     pub internal_id: String,   // opaque runtime ID, used to call hooks
     pub configured_id: String, // used ONLY for ctx.config lookup
     pub name: String,
+    pub permissions: BTreeSet<Capability>,
+    pub fetch: FetchPolicy,
+    /// Stored settings with defaults filled in: `ctx.config`.
+    pub config: Json,
+}
+
+impl PluginMeta {
+    /// Who JS runs as on this plugin's behalf.
+    fn host_caller(&self) -> HostCaller {
+        HostCaller {
+            id: self.configured_id.clone(),
+            capabilities: self
+                .permissions
+                .iter()
+                .map(|c| c.as_str().to_string())
+                .collect(),
+            fetch: self.fetch.clone(),
+        }
+    }
 }
 
 /// PluginRuntime: manages a single Boa engine and multiple plugins inside it
@@ -33,104 +75,147 @@ pub struct PluginRuntime<E: JsEngine> {
     engine: E,
     /// Keyed by internal (opaque) ID.
     plugins: HashMap<String, PluginMeta>,
+    /// Internal IDs in load order (dependency-resolved; providers first).
+    order: Vec<String>,
+    /// Everything loaded so far, to build an identical runtime from.
+    specs: Vec<PluginSpec>,
+    /// The context `init_all` last ran with.
+    init_ctx: Option<RequestContext>,
+    /// Globals as of the last load or init; `reset` returns to this.
+    baseline: GlobalShape,
 }
 
-#[tracing::instrument(skip_all)]
-fn build_plugin_prelude(internal_id: &str) -> String {
-    let internal_id_json = serde_json::to_string(internal_id).unwrap();
-
-    // No configured ID. No user ID. Only opaque internal ID known by host.
-    format!(
-        r#"(function (global) {{
-    const INTERNAL_ID = {internal_id};
-
-    // Host-provided registration. Plugins call this INSIDE init().
-    global.registerPlugin = function(hooks) {{
-        if (!hooks || typeof hooks !== "object") {{
-            throw new Error("registerPlugin: hooks object is required");
-        }}
-        // We DO NOT register init. Only before/after.
-        global[INTERNAL_ID] = {{
-            before: typeof hooks.before === "function"
-                ? hooks.before
-                : undefined,
-            after: typeof hooks.after === "function"
-                ? hooks.after
-                : undefined
-        }};
-    }};
-}})(typeof globalThis !== "undefined" ? globalThis : this);"#,
-        internal_id = internal_id_json,
-    )
+fn req_id(ctx: &RequestContext) -> &str {
+    ctx.req_id.as_str().unwrap_or_default()
 }
 
+/// Globals the shims leave for the host to call, hidden from JS before
+/// any plugin loads: with them a plugin could run code as another plugin
+/// (`__runAs`), fire or replace its hooks, or change its grants.
+pub const HOST_ONLY_GLOBALS: &[&str] = &[
+    "__runAs",
+    "__hooksFor",
+    "__emitHook",
+    "__filterHook",
+    "__beginPlugin",
+    "__endPlugin",
+    "__callPluginHook",
+    "__callPluginRoute",
+];
+
 impl<E: JsEngine> PluginRuntime<E> {
     #[tracing::instrument(skip_all)]
     pub fn new(mut engine: E) -> Result<Self, RuntimeError> {
         engine.load_module("__ctx_shim__", CTX_SHIM_SRC)?;
+        engine.load_module("__hooks_shim__", &hooks_shim_src())?;
+        engine.load_module("__services_shim__", SERVICES_SHIM_SRC)?;
+        engine.hide_globals(HOST_ONLY_GLOBALS)?;
+        let baseline = global_shape(&mut engine)?;
         Ok(Self {
             engine,
             plugins: HashMap::new(),
+            order: Vec::new(),
+            specs: Vec::new(),
+            init_ctx: None,
+            baseline,
         })
     }
 
+    /// Expose a host API to plugins as `ctx.host.call(op, args)`.
+    ///
+    /// With a `capability`, only plugins granted it in their manifest may
+    /// call `op`; others fail with [`RuntimeError::PluginExecution`].
+    pub fn register_host_fn(&mut self, op: &str, capability: Option<Capability>, func: HostFn) {
+        self.engine
+            .register_host_fn(op, capability.map(Capability::as_str), func);
+    }
+
     #[tracing::instrument(skip_all)]
     pub fn load_plugins(&mut self, specs: &[PluginSpec]) -> Result<(), RuntimeError> {
-        for spec in specs {
+        // Providers load (and later init) before the plugins that use them.
+        let order = resolve_init_order(specs)?;
+        let configs = specs
+            .iter()
+            .map(|spec| {
+                spec.settings.resolve(&spec.config).map_err(|e| {
+                    RuntimeError::plugin_bootstrap(format!("plugin '{}' settings: {e}", spec.id))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for idx in order {
+            let spec = &specs[idx];
             let configured_id = spec.id.clone();
             let internal_id = format!("plugin_{}", Uuid::new_v4().simple());
 
-            // Prelude: defines registerPlugin()
-            let prelude = build_plugin_prelude(&internal_id);
-            let prelude_name = format!("__plugin_prelude_{}", internal_id);
-            self.engine.load_module(&prelude_name, &prelude)?;
-
-            // Load plugin JS → its top-level defines init(ctx)
-            self.engine.load_module(&configured_id, &spec.source)?;
+            // Load plugin JS → its top-level defines init(ctx); the
+            // shim keeps that and the route handlers under the internal ID.
+            let [provides, requires] = service_grants(spec);
+            self.engine.call_function(
+                "__beginPlugin",
+                &[JsValue::string(internal_id.clone()), provides, requires],
+            )?;
+            let loaded = self.engine.load_module(&configured_id, &spec.source);
+            let handlers: BTreeSet<&str> = spec.routes.iter().map(|r| r.handler.as_str()).collect();
+            let handlers = handlers.into_iter().map(JsValue::string).collect();
+            self.engine.call_function(
+                "__endPlugin",
+                &[
+                    JsValue::string(internal_id.clone()),
+                    JsValue::Array(handlers),
+                ],
+            )?;
+            loaded?;
 
             // Record metadata
-            self.plugins.insert(
-                internal_id.clone(),
-                PluginMeta {
-                    internal_id,
-                    configured_id,
-                    name: spec.name.clone(),
-                },
-            );
+            let meta = PluginMeta {
+                internal_id: internal_id.clone(),
+                configured_id,
+                name: spec.name.clone(),
+                permissions: spec.permissions.clone(),
+                fetch: spec.fetch.clone(),
+                config: configs[idx].clone(),
+            };
+            // Hook listeners run as the plugin that registered them.
+            self.engine
+                .register_host_caller(&internal_id, meta.host_caller());
+            self.order.push(internal_id.clone());
+            self.plugins.insert(internal_id, meta);
         }
 
+        self.specs.extend(specs.iter().cloned());
+        self.baseline = global_shape(&mut self.engine)?;
         Ok(())
     }
 
-    /// Call `init(ctx)` on every loaded plugin, in registration order.
+    /// Configured (host-facing) IDs of every loaded plugin, in load order.
+    pub fn configured_ids(&self) -> Vec<String> {
+        self.order
+            .iter()
+            .filter_map(|internal| self.plugins.get(internal))
+            .map(|meta| meta.configured_id.clone())
+            .collect()
+    }
+
+    /// Call `init(ctx)` on every loaded plugin, providers before consumers.
     #[tracing::instrument(skip_all)]
     pub fn init_all(&mut self, ctx: &RequestContext) -> Result<(), RuntimeError> {
-        let metas: Vec<PluginMeta> = self.plugins.values().cloned().collect();
+        let metas = self.ordered_metas();
         for meta in &metas {
             self.call_init(meta, ctx)?;
         }
+
+        // Whatever init put on globalThis is part of the warm state.
+        self.init_ctx = Some(ctx.clone());
+        self.baseline = global_shape(&mut self.engine)?;
         Ok(())
     }
 
     #[tracing::instrument(skip_all)]
     fn call_init(&mut self, meta: &PluginMeta, ctx: &RequestContext) -> Result<(), RuntimeError> {
-        let js_ctx = ctx_to_js_for_plugins(ctx, &meta.configured_id);
-        let js_ctx = self.engine.call_function("__wrapCtx", &[js_ctx])?;
-
-        // Call global init(ctx) defined in plugin module.
-        // Plugin decides whether to call registerPlugin inside.
-        let result = self
-            .engine
-            .call_function("init", &[js_ctx])
-            .or_else(|err| {
-                if let JsError::Call(msg) = &err {
-                    if msg.contains("is not a function") {
-                        // Plugin has no init(ctx); that's fine.
-                        return Ok(JsValue::Null);
-                    }
-                }
-                Err(err)
-            })?;
+        // Call the init(ctx) captured from this plugin's module (a no-op if
+        // it has none). Plugin decides whether to call registerPlugin inside.
+        let result = self.call_hook(meta, "init", ctx)?;
 
         // Init is *not* merged — plugin returns ctx only for convenience.
         let _ = result;
@@ -144,7 +229,7 @@ impl<E: JsEngine> PluginRuntime<E> {
 
     #[tracing::instrument(skip_all)]
     pub fn before_all(&mut self, ctx: &mut RequestContext) -> Result<(), RuntimeError> {
-        let metas: Vec<PluginMeta> = self.plugins.values().cloned().collect();
+        let metas = self.ordered_metas();
         for meta in &metas {
             self.call_before(meta, ctx)?;
         }
@@ -154,7 +239,7 @@ impl<E: JsEngine> PluginRuntime<E> {
     #[tracing::instrument(skip_all)]
     pub fn after_all(&mut self, ctx: &mut RequestContext) -> Result<(), RuntimeError> {
         // Reverse order for after()
-        let mut metas: Vec<_> = self.plugins.values().cloned().collect();
+        let mut metas = self.ordered_metas();
         metas.reverse();
 
         for meta in &metas {
@@ -212,33 +297,182 @@ impl<E: JsEngine> PluginRuntime<E> {
         Ok(())
     }
 
+    /// Emit `event` to the plugins listening for it (see [`super::hooks`])
+    /// and merge each listener's result into `ctx` as recommendations.
+    #[tracing::instrument(skip_all, fields(event))]
+    pub fn emit(
+        &mut self,
+        event: &str,
+        payload: JsValue,
+        ctx: &mut RequestContext,
+    ) -> Result<(), RuntimeError> {
+        let scope = LogScope::plugin("", req_id(ctx));
+        let results = self.call_as(
+            None,
+            scope,
+            "__emitHook",
+            &[JsValue::string(event), payload],
+        )?;
+
+        if let JsValue::Array(results) = results {
+            for result in results.iter().filter(|r| matches!(r, JsValue::Object(_))) {
+                merge_recommendations_from_js(result, ctx)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Pass `value` through the listeners for `event` and return what the
+    /// last one made of it.
+    #[tracing::instrument(skip_all, fields(event))]
+    pub fn filter(
+        &mut self,
+        event: &str,
+        value: JsValue,
+        ctx: &RequestContext,
+    ) -> Result<JsValue, RuntimeError> {
+        let scope = LogScope::plugin("", req_id(ctx));
+        self.call_as(
+            None,
+            scope,
+            "__filterHook",
+            &[JsValue::string(event), value],
+        )
+    }
+
+    /// Answer a request to one of `configured_id`'s routes by calling its
+    /// `handler` function with `ctx`, and merge what it returns into `ctx`
+    /// like a `before` hook's result (`response` included).
+    #[tracing::instrument(skip_all, fields(handler))]
+    pub fn route(
+        &mut self,
+        configured_id: &str,
+        handler: &str,
+        ctx: &mut RequestContext,
+    ) -> Result<(), RuntimeError> {
+        let meta = self
+            .plugins
+            .values()
+            .find(|m| m.configured_id == configured_id)
+            .cloned()
+            .ok_or_else(|| {
+                RuntimeError::plugin_execution(format!("no plugin '{configured_id}' is loaded"))
+            })?;
+
+        let result = self.call_trampoline(&meta, "__callPluginRoute", handler, ctx)?;
+
+        if let JsValue::Object(_) = result {
+            merge_recommendations_from_js(&result, ctx)?;
+        }
+        Ok(())
+    }
+
+    /// Run every plugin's `before` and `after` hooks once against `ctx`, then
+    /// restore the warm globals. An upgraded runtime must pass this before it
+    /// serves real requests.
+    #[tracing::instrument(skip_all)]
+    pub fn smoke_test(&mut self, ctx: &RequestContext) -> Result<(), RuntimeError> {
+        for id in self.configured_ids() {
+            let mut ctx = ctx.clone();
+            self.before_plugin(&id, &mut ctx)
+                .and_then(|()| self.after_plugin(&id, &mut ctx))
+                .map_err(|e| {
+                    RuntimeError::plugin_execution(format!("smoke request to {id} failed: {e}"))
+                })?;
+        }
+        self.reset_globals()
+    }
+
     // ─────────────────────────────────────────────────────────────────────
     // Internal helpers for calling JS hooks
     // ─────────────────────────────────────────────────────────────────────
 
+    fn ordered_metas(&self) -> Vec<PluginMeta> {
+        self.order
+            .iter()
+            .filter_map(|internal| self.plugins.get(internal).cloned())
+            .collect()
+    }
+
+    /// Call one of a plugin's hooks through the services trampoline, which
+    /// wraps the ctx and attaches that plugin's `ctx.services` view.
+    ///
+    /// The plugin's permissions are in force for the duration of the call; a
+    /// host API refused for lack of one surfaces as `PluginExecution`.
+    ///
+    /// A missing hook yields `JsValue::Null`.
+    fn call_hook(
+        &mut self,
+        meta: &PluginMeta,
+        hook: &str,
+        ctx: &RequestContext,
+    ) -> Result<JsValue, RuntimeError> {
+        self.call_trampoline(meta, "__callPluginHook", hook, ctx)
+    }
+
+    /// Call `trampoline(internalId, name, ctx)` as `meta`'s plugin.
+    fn call_trampoline(
+        &mut self,
+        meta: &PluginMeta,
+        trampoline: &str,
+        name: &str,
+        ctx: &RequestContext,
+    ) -> Result<JsValue, RuntimeError> {
+        // A config the host put on the request wins over the stored one.
+        let config = ctx
+            .plugin_configs
+            .get(&meta.configured_id)
+            .unwrap_or(&meta.config);
+        let js_ctx = ctx_to_js_with_config(ctx, config, &meta.configured_id);
+        let args = [
+            JsValue::string(meta.internal_id.clone()),
+            JsValue::string(name),
+            js_ctx,
+        ];
+
+        let scope = LogScope::plugin(meta.configured_id.as_str(), req_id(ctx));
+        self.call_as(Some(meta.host_caller()), scope, trampoline, &args)
+    }
+
+    /// Call `func` with `caller`'s permissions in force, mapping a host API
+    /// refused for lack of one to `PluginExecution`.
+    fn call_as(
+        &mut self,
+        caller: Option<HostCaller>,
+        scope: LogScope,
+        func: &str,
+        args: &[JsValue],
+    ) -> Result<JsValue, RuntimeError> {
+        self.engine.set_host_caller(caller);
+        self.engine.set_log_scope(Some(scope));
+        let _ = self.engine.take_host_denial();
+        let res = self.engine.call_function(func, args);
+        self.engine.set_host_caller(None);
+        self.engine.set_log_scope(None);
+
+        match (res, self.engine.take_host_denial()) {
+            (Ok(v), _) => Ok(v),
+            (Err(_), Some(denied)) => Err(RuntimeError::plugin_execution(format!(
+                "plugin '{}' is missing capability '{}' (required by '{}')",
+                denied.caller, denied.capability, denied.op
+            ))),
+            (Err(e), None) => Err(e.into()),
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn eval_for_test(&mut self, code: &str) -> Result<JsValue, RuntimeError> {
+        Ok(self.engine.eval(code)?)
+    }
+
     #[tracing::instrument(skip_all)]
     fn call_before(
         &mut self,
         meta: &PluginMeta,
         ctx: &mut RequestContext,
     ) -> Result<(), RuntimeError> {
-        let js_ctx = ctx_to_js_for_plugins(ctx, &meta.configured_id);
-        let js_ctx = self.engine.call_function("__wrapCtx", &[js_ctx])?;
-
-        let func_name = format!("{}.before", meta.internal_id);
-
-        let result = self
-            .engine
-            .call_function(&func_name, &[js_ctx])
-            .or_else(|err| {
-                if let JsError::Call(msg) = &err {
-                    if msg.contains("is not a function") {
-                        // Plugin has no before() hook; silently ignore.
-                        return Ok(JsValue::Null);
-                    }
-                }
-                Err(err)
-            })?;
+        // Plugin has no before() hook → Null; silently ignore.
+        let result = self.call_hook(meta, "before", ctx)?;
 
         debug!(
             "Result from executing plugin before lifecycle: {:?}",
@@ -258,23 +492,8 @@ impl<E: JsEngine> PluginRuntime<E> {
         meta: &PluginMeta,
         ctx: &mut RequestContext,
     ) -> Result<(), RuntimeError> {
-        let js_ctx = ctx_to_js_for_plugins(ctx, &meta.configured_id);
-        let js_ctx = self.engine.call_function("__wrapCtx", &[js_ctx])?;
-
-        let func_name = format!("{}.after", meta.internal_id);
-
-        let result = self
-            .engine
-            .call_function(&func_name, &[js_ctx])
-            .or_else(|err| {
-                if let JsError::Call(msg) = &err {
-                    if msg.contains("is not a function") {
-                        // Plugin has no after() hook; silently ignore.
-                        return Ok(JsValue::Null);
-                    }
-                }
-                Err(err)
-            })?;
+        // Plugin has no after() hook → Null; silently ignore.
+        let result = self.call_hook(meta, "after", ctx)?;
 
         if let JsValue::Object(_) = result {
             merge_recommendations_from_js(&result, ctx)?;
@@ -283,3 +502,33 @@ impl<E: JsEngine> PluginRuntime<E> {
         Ok(())
     }
 }
+
+impl<E: JsEngine> PluginRuntime<E> {
+    pub fn engine(&self) -> &E {
+        &self.engine
+    }
+
+    /// Return the engine's globals to their state after the last load or
+    /// `init_all`.
+    pub fn reset_globals(&mut self) -> Result<(), RuntimeError> {
+        reset_globals(&mut self.engine, &self.baseline)
+    }
+}
+
+impl Pooled for PluginRuntime<BoaEngine> {
+    fn reset(&mut self) -> Result<(), RuntimeError> {
+        self.reset_globals()
+    }
+
+    /// Same plugins in a new engine, initialized with the same context.
+    ///
+    /// Host functions registered after construction are not carried over.
+    fn rebuild(&self) -> Result<Self, RuntimeError> {
+        let mut fresh = PluginRuntime::new(BoaEngine::with_env(self.engine.env()))?;
+        fresh.load_plugins(&self.specs)?;
+        if let Some(ctx) = &self.init_ctx {
+            fresh.init_all(ctx)?;
+        }
+        Ok(fresh)
+    }
+}
@@ -1,15 +1,26 @@
 // crates/adapt/src/runtime/plugin_actor.rs
 
-use crate::js::engine::BoaEngine;
+use crate::js::engine::{BoaEngine, EngineEnv};
+use crate::js::JsValue;
+use crate::runtime::bootstrap::{build_plugin_runtime, build_trial_plugin_runtime, PluginConfig};
 use crate::runtime::error::RuntimeError;
-use crate::runtime::plugin::PluginRuntime;
+use crate::runtime::plugin::{PluginRuntime, PluginSpec};
+use crate::runtime::pool::EnginePool;
+use serde_json::json;
 use serve::render::http::RequestContext;
+use std::sync::{Arc, RwLock};
 use tokio::sync::{mpsc, oneshot};
+use tracing::{info, info_span, warn, Span};
+
+/// Path of the synthetic request an upgraded runtime must answer before it
+/// replaces the running one.
+pub const SMOKE_PATH: &str = "/__whisper/plugin-upgrade-check";
 
 /// Commands handled by the plugin actor.
 ///
-/// The actor owns a single `PluginRuntime<BoaEngine>` instance and executes
-/// all JS hooks on a single Tokio `LocalSet` thread.
+/// The actor owns a pool of warm `PluginRuntime<BoaEngine>` instances and
+/// executes all JS hooks on a single Tokio `LocalSet` thread, resetting the
+/// runtime's globals after each one.
 enum PluginCommand {
     /// Call `init_all(&ctx)` on the runtime.
     InitAll {
@@ -18,9 +29,13 @@ enum PluginCommand {
     },
 
     /// Call `before_plugin(configured_id, &mut ctx)` for a single plugin.
+    ///
+    /// `span` is entered around the hook so it joins the caller's trace
+    /// despite running on the actor thread.
     BeforePlugin {
         plugin_id: String,
         ctx: RequestContext,
+        span: Span,
         reply: oneshot::Sender<Result<RequestContext, RuntimeError>>,
     },
 
@@ -28,9 +43,50 @@ enum PluginCommand {
     AfterPlugin {
         plugin_id: String,
         ctx: RequestContext,
+        span: Span,
+        reply: oneshot::Sender<Result<RequestContext, RuntimeError>>,
+    },
+
+    /// Call `emit(event, payload, &mut ctx)`: every plugin listening for
+    /// `event`.
+    Emit {
+        event: String,
+        payload: JsValue,
+        ctx: RequestContext,
+        span: Span,
+        reply: oneshot::Sender<Result<RequestContext, RuntimeError>>,
+    },
+
+    /// Call `filter(event, value, &ctx)`: pass `value` through every
+    /// plugin listening for `event`.
+    Filter {
+        event: String,
+        value: JsValue,
+        ctx: RequestContext,
+        span: Span,
+        reply: oneshot::Sender<Result<JsValue, RuntimeError>>,
+    },
+
+    /// Call `route(configured_id, handler, &mut ctx)`: a request to one of
+    /// the plugin's routes.
+    Route {
+        plugin_id: String,
+        handler: String,
+        ctx: RequestContext,
+        span: Span,
         reply: oneshot::Sender<Result<RequestContext, RuntimeError>>,
     },
 
+    /// Try `specs` out on a sandboxed runtime (bootstrap, `init_all(&ctx)`, a
+    /// smoke request, with `fetch` and `storage` writes going nowhere), then
+    /// build and initialize the real one and swap it in. The current runtime
+    /// keeps serving meanwhile, and is kept on any failure.
+    ReloadAll {
+        specs: Vec<PluginSpec>,
+        ctx: RequestContext,
+        reply: oneshot::Sender<Result<Vec<String>, RuntimeError>>,
+    },
+
     /// Stop the actor loop.
     Shutdown,
 }
@@ -41,6 +97,8 @@ enum PluginCommand {
 #[derive(Clone)]
 pub struct PluginRuntimeClient {
     tx: mpsc::UnboundedSender<PluginCommand>,
+    /// Configured plugin IDs in load order; replaced on a successful reload.
+    ids: Arc<RwLock<Vec<String>>>,
 }
 
 impl PluginRuntimeClient {
@@ -54,13 +112,55 @@ impl PluginRuntimeClient {
     #[tracing::instrument(skip_all)]
     pub fn spawn(runtime: PluginRuntime<BoaEngine>) -> Self {
         let (tx, rx) = mpsc::unbounded_channel::<PluginCommand>();
+        let ids = Arc::new(RwLock::new(runtime.configured_ids()));
 
         // Spawn the actor loop as a !Send task bound to the LocalSet thread.
         tokio::task::spawn_local(async move {
             plugin_actor_loop(runtime, rx).await;
         });
 
-        Self { tx }
+        Self { tx, ids }
+    }
+
+    /// Configured IDs of the currently loaded plugins, in load order.
+    ///
+    /// Read this per request rather than caching it: a hot reload may add,
+    /// remove, or reorder plugins.
+    pub fn plugin_ids(&self) -> Vec<String> {
+        self.ids.read().map(|ids| ids.clone()).unwrap_or_default()
+    }
+
+    /// Replace every loaded plugin with `cfgs`.
+    ///
+    /// The new set is loaded into a fresh engine, initialized with `ctx` and
+    /// sent a synthetic request (see [`SMOKE_PATH`]) while the previous set
+    /// keeps serving; it replaces that set only if every stage succeeded.
+    /// A reload overtaken by a later one fails without swapping.
+    #[tracing::instrument(skip_all)]
+    pub async fn reload(
+        &self,
+        cfgs: &[PluginConfig],
+        ctx: RequestContext,
+    ) -> Result<(), RuntimeError> {
+        let specs = cfgs.iter().map(PluginSpec::from).collect();
+        let (reply_tx, reply_rx) = oneshot::channel();
+
+        self.tx
+            .send(PluginCommand::ReloadAll {
+                specs,
+                ctx,
+                reply: reply_tx,
+            })
+            .map_err(|_| channel_error("plugin actor terminated before reload"))?;
+
+        let ids = reply_rx
+            .await
+            .map_err(|_| channel_error("plugin actor dropped reload reply"))??;
+
+        if let Ok(mut guard) = self.ids.write() {
+            *guard = ids;
+        }
+        Ok(())
     }
 
     /// Call `init_all(ctx)` in the actor.
@@ -89,12 +189,14 @@ impl PluginRuntimeClient {
         ctx: RequestContext,
     ) -> Result<RequestContext, RuntimeError> {
         let plugin_id = plugin_id.into();
+        let span = info_span!("plugin.before", plugin_id = %plugin_id);
         let (reply_tx, reply_rx) = oneshot::channel();
 
         self.tx
             .send(PluginCommand::BeforePlugin {
                 plugin_id,
                 ctx,
+                span,
                 reply: reply_tx,
             })
             .map_err(|_| channel_error("plugin actor terminated before before_plugin"))?;
@@ -113,12 +215,14 @@ impl PluginRuntimeClient {
         ctx: RequestContext,
     ) -> Result<RequestContext, RuntimeError> {
         let plugin_id = plugin_id.into();
+        let span = info_span!("plugin.after", plugin_id = %plugin_id);
         let (reply_tx, reply_rx) = oneshot::channel();
 
         self.tx
             .send(PluginCommand::AfterPlugin {
                 plugin_id,
                 ctx,
+                span,
                 reply: reply_tx,
             })
             .map_err(|_| channel_error("plugin actor terminated before after_plugin"))?;
@@ -128,10 +232,101 @@ impl PluginRuntimeClient {
             .map_err(|_| channel_error("plugin actor dropped after_plugin reply"))?
     }
 
+    /// Emit `event` to the plugins listening for it (see
+    /// [`crate::runtime::hooks`]) and return `ctx` with their
+    /// recommendations merged in.
+    #[tracing::instrument(skip_all)]
+    pub async fn emit(
+        &self,
+        event: impl Into<String>,
+        payload: JsValue,
+        ctx: RequestContext,
+    ) -> Result<RequestContext, RuntimeError> {
+        let event = event.into();
+        let span = info_span!("plugin.emit", event = %event);
+        let (reply_tx, reply_rx) = oneshot::channel();
+
+        self.tx
+            .send(PluginCommand::Emit {
+                event,
+                payload,
+                ctx,
+                span,
+                reply: reply_tx,
+            })
+            .map_err(|_| channel_error("plugin actor terminated before emit"))?;
+
+        reply_rx
+            .await
+            .map_err(|_| channel_error("plugin actor dropped emit reply"))?
+    }
+
+    /// Pass `value` through the plugins listening for `event` (see
+    /// [`crate::runtime::hooks`]) and return what the last one made of it.
+    #[tracing::instrument(skip_all)]
+    pub async fn filter(
+        &self,
+        event: impl Into<String>,
+        value: JsValue,
+        ctx: RequestContext,
+    ) -> Result<JsValue, RuntimeError> {
+        let event = event.into();
+        let span = info_span!("plugin.filter", event = %event);
+        let (reply_tx, reply_rx) = oneshot::channel();
+
+        self.tx
+            .send(PluginCommand::Filter {
+                event,
+                value,
+                ctx,
+                span,
+                reply: reply_tx,
+            })
+            .map_err(|_| channel_error("plugin actor terminated before filter"))?;
+
+        reply_rx
+            .await
+            .map_err(|_| channel_error("plugin actor dropped filter reply"))?
+    }
+
+    /// Answer a request to one of `plugin_id`'s routes with its `handler`
+    /// function and return the `RequestContext` it left, response included.
+    #[tracing::instrument(skip_all)]
+    pub async fn route(
+        &self,
+        plugin_id: impl Into<String>,
+        handler: impl Into<String>,
+        ctx: RequestContext,
+    ) -> Result<RequestContext, RuntimeError> {
+        let plugin_id = plugin_id.into();
+        let handler = handler.into();
+        let span = info_span!("plugin.route", plugin_id = %plugin_id, handler = %handler);
+        let (reply_tx, reply_rx) = oneshot::channel();
+
+        self.tx
+            .send(PluginCommand::Route {
+                plugin_id,
+                handler,
+                ctx,
+                span,
+                reply: reply_tx,
+            })
+            .map_err(|_| channel_error("plugin actor terminated before route"))?;
+
+        reply_rx
+            .await
+            .map_err(|_| channel_error("plugin actor dropped route reply"))?
+    }
+
     /// Fire-and-forget shutdown signal (no guarantee it’s processed).
     pub fn stop(&self) {
         let _ = self.tx.send(PluginCommand::Shutdown);
     }
+
+    /// Whether the actor loop is still running to take commands.
+    pub fn is_alive(&self) -> bool {
+        !self.tx.is_closed()
+    }
 }
 
 /// Internal helper to map channel failures into a `RuntimeError`.
@@ -143,52 +338,377 @@ fn channel_error(msg: &str) -> RuntimeError {
     RuntimeError::ThemeBootstrap(msg.to_string())
 }
 
+/// An upgraded runtime that passed every stage, waiting to be swapped in.
+struct Staged {
+    /// Which `ReloadAll` built it; only the latest may be swapped in.
+    generation: u64,
+    runtime: PluginRuntime<BoaEngine>,
+    reply: oneshot::Sender<Result<Vec<String>, RuntimeError>>,
+}
+
+/// The synthetic request a staged runtime is smoke-tested with.
+fn smoke_ctx() -> RequestContext {
+    RequestContext::builder()
+        .path(SMOKE_PATH)
+        .method("GET")
+        .version("HTTP/1.1")
+        .headers(json!({}))
+        .params(json!({}))
+        .content_meta(json!({}))
+        .build()
+}
+
+/// Initialize and smoke-test `specs` on a trial runtime that can reach
+/// nothing, then build the one to swap in, yielding between stages so the
+/// actor keeps answering hooks with the current runtime. Only that last
+/// runtime runs `init` with the real `fetch` and `storage`, and only once
+/// the trial passed.
+#[tracing::instrument(skip_all)]
+async fn stage_upgrade(
+    specs: &[PluginSpec],
+    ctx: &RequestContext,
+    env: EngineEnv,
+) -> Result<PluginRuntime<BoaEngine>, RuntimeError> {
+    let mut trial = build_trial_plugin_runtime(specs, env.clone())?;
+    tokio::task::yield_now().await;
+    trial.init_all(ctx)?;
+    tokio::task::yield_now().await;
+    trial.smoke_test(&smoke_ctx())?;
+    drop(trial);
+    tokio::task::yield_now().await;
+
+    let mut fresh = build_plugin_runtime(specs, env)?;
+    tokio::task::yield_now().await;
+    fresh.init_all(ctx)?;
+    Ok(fresh)
+}
+
 /// Actor event loop – runs on the Tokio `LocalSet` thread.
 ///
 /// All interaction with `PluginRuntime<BoaEngine>` happens here, on a single
-/// thread, so Boa's single-threaded requirement is upheld.
+/// thread, so Boa's single-threaded requirement is upheld. Upgrades are
+/// staged by separate local tasks on the same thread and handed back over
+/// `staged_rx` for the swap.
 #[tracing::instrument(skip_all)]
 async fn plugin_actor_loop(
-    mut runtime: PluginRuntime<BoaEngine>,
+    runtime: PluginRuntime<BoaEngine>,
     mut rx: mpsc::UnboundedReceiver<PluginCommand>,
 ) {
-    while let Some(cmd) = rx.recv().await {
-        match cmd {
-            PluginCommand::InitAll { ctx, reply } => {
-                let res = runtime.init_all(&ctx);
-                let _ = reply.send(res);
-            }
+    // Upgrades keep the site's storage and log levels.
+    let env = runtime.engine().env();
+    let mut pool = EnginePool::new(runtime);
+    let (staged_tx, mut staged_rx) = mpsc::unbounded_channel::<Staged>();
+    let mut generation = 0u64;
 
-            PluginCommand::BeforePlugin {
-                plugin_id,
-                mut ctx,
-                reply,
-            } => {
-                let res = (|| {
-                    runtime.before_plugin(&plugin_id, &mut ctx)?;
-                    Ok::<_, RuntimeError>(ctx)
-                })();
-
-                let _ = reply.send(res);
+    loop {
+        tokio::select! {
+            Some(staged) = staged_rx.recv() => {
+                let res = if staged.generation == generation {
+                    let ids = staged.runtime.configured_ids();
+                    pool.replace(staged.runtime);
+                    info!("Plugin upgrade {} swapped in: {:?}", staged.generation, ids);
+                    Ok(ids)
+                } else {
+                    Err(RuntimeError::plugin_bootstrap(format!(
+                        "plugin upgrade {} was superseded by {}",
+                        staged.generation, generation
+                    )))
+                };
+                let _ = staged.reply.send(res);
             }
 
-            PluginCommand::AfterPlugin {
-                plugin_id,
-                mut ctx,
-                reply,
-            } => {
-                let res = (|| {
-                    runtime.after_plugin(&plugin_id, &mut ctx)?;
-                    Ok::<_, RuntimeError>(ctx)
-                })();
-
-                let _ = reply.send(res);
-            }
+            cmd = rx.recv() => {
+                let Some(cmd) = cmd else { break };
+                match cmd {
+                    PluginCommand::InitAll { ctx, reply } => {
+                        let res = pool.run(|runtime| runtime.init_all(&ctx));
+                        // Spares were built before init; rebuild them from this one.
+                        pool.clear_spares();
+                        let _ = reply.send(res);
+                    }
+
+                    PluginCommand::BeforePlugin {
+                        plugin_id,
+                        mut ctx,
+                        span,
+                        reply,
+                    } => {
+                        let res = span.in_scope(|| {
+                            pool.run(|runtime| runtime.before_plugin(&plugin_id, &mut ctx))?;
+                            Ok::<_, RuntimeError>(ctx)
+                        });
+
+                        let _ = reply.send(res);
+                    }
+
+                    PluginCommand::AfterPlugin {
+                        plugin_id,
+                        mut ctx,
+                        span,
+                        reply,
+                    } => {
+                        let res = span.in_scope(|| {
+                            pool.run(|runtime| runtime.after_plugin(&plugin_id, &mut ctx))?;
+                            Ok::<_, RuntimeError>(ctx)
+                        });
+
+                        let _ = reply.send(res);
+                    }
+
+                    PluginCommand::Emit {
+                        event,
+                        payload,
+                        mut ctx,
+                        span,
+                        reply,
+                    } => {
+                        let res = span.in_scope(|| {
+                            pool.run(|runtime| runtime.emit(&event, payload, &mut ctx))?;
+                            Ok::<_, RuntimeError>(ctx)
+                        });
+
+                        let _ = reply.send(res);
+                    }
+
+                    PluginCommand::Filter {
+                        event,
+                        value,
+                        ctx,
+                        span,
+                        reply,
+                    } => {
+                        let res =
+                            span.in_scope(|| pool.run(|runtime| runtime.filter(&event, value, &ctx)));
+
+                        let _ = reply.send(res);
+                    }
+
+                    PluginCommand::Route {
+                        plugin_id,
+                        handler,
+                        mut ctx,
+                        span,
+                        reply,
+                    } => {
+                        let res = span.in_scope(|| {
+                            pool.run(|runtime| runtime.route(&plugin_id, &handler, &mut ctx))?;
+                            Ok::<_, RuntimeError>(ctx)
+                        });
 
-            PluginCommand::Shutdown => {
-                // Break the loop; actor task will exit and drop the runtime.
-                break;
+                        let _ = reply.send(res);
+                    }
+
+                    PluginCommand::ReloadAll { specs, ctx, reply } => {
+                        generation += 1;
+                        let this = generation;
+                        let (staged_tx, env) = (staged_tx.clone(), env.clone());
+                        tokio::task::spawn_local(async move {
+                            match stage_upgrade(&specs, &ctx, env).await {
+                                Ok(runtime) => {
+                                    let _ = staged_tx.send(Staged {
+                                        generation: this,
+                                        runtime,
+                                        reply,
+                                    });
+                                }
+                                Err(e) => {
+                                    warn!("Plugin upgrade {} rolled back: {}", this, e);
+                                    let _ = reply.send(Err(e));
+                                }
+                            }
+                        });
+                    }
+
+                    PluginCommand::Shutdown => {
+                        // Break the loop; actor task will exit and drop the runtime.
+                        break;
+                    }
+                }
             }
         }
+
+        // Nothing waiting: a good moment to build a spare.
+        if rx.is_empty() {
+            pool.top_up();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serve::render::http::ResponseBodySpec;
+    use serve::resolver::PluginRoute;
+    use tokio::task::LocalSet;
+
+    fn plugin(id: &str, before: &str) -> PluginConfig {
+        PluginConfig {
+            id: id.into(),
+            name: id.into(),
+            source: format!(
+                "function init(ctx) {{ registerPlugin({{ before(ctx) {{ {before} return ctx; }} }}); }}"
+            ),
+            provides: Vec::new(),
+            requires: Vec::new(),
+            permissions: Default::default(),
+            fetch: Default::default(),
+            settings: Default::default(),
+            config: Default::default(),
+            routes: Vec::new(),
+        }
+    }
+
+    fn runtime(cfgs: &[PluginConfig]) -> PluginRuntime<BoaEngine> {
+        runtime_in(cfgs, EngineEnv::default())
+    }
+
+    fn runtime_in(cfgs: &[PluginConfig], env: EngineEnv) -> PluginRuntime<BoaEngine> {
+        let specs: Vec<PluginSpec> = cfgs.iter().map(PluginSpec::from).collect();
+        let mut rt = build_plugin_runtime(&specs, env).unwrap();
+        rt.init_all(&RequestContext::builder().build()).unwrap();
+        rt
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn a_failing_smoke_request_keeps_the_old_plugins() {
+        LocalSet::new()
+            .run_until(async {
+                let client = PluginRuntimeClient::spawn(runtime(&[plugin("seo", "")]));
+
+                let broken = plugin("seo", "throw new Error('boom');");
+                let err = client
+                    .reload(&[broken], RequestContext::builder().build())
+                    .await
+                    .unwrap_err();
+                assert!(err.to_string().contains("smoke request to seo"), "{err}");
+
+                let ctx = client
+                    .before_plugin("seo", RequestContext::builder().path("/a").build())
+                    .await;
+                assert!(ctx.is_ok(), "old plugin should still serve: {ctx:?}");
+                client.stop();
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn routes_call_the_named_function_of_their_plugin() {
+        LocalSet::new()
+            .run_until(async {
+                let handler = |id: &str| PluginConfig {
+                    source: format!(
+                        "function handle(ctx) {{ \
+                           ctx.response.status = 201; \
+                           ctx.response.body = {{ kind: 'json', value: {{ by: '{id}', \
+                             post: ctx.request.routeParams.post }} }}; \
+                           return ctx; }}"
+                    ),
+                    routes: vec![PluginRoute {
+                        method: "POST".into(),
+                        path: "/api/comments/{post}".into(),
+                        handler: "handle".into(),
+                    }],
+                    ..plugin(id, "")
+                };
+                // Both define a global `handle`; each route keeps its own.
+                let client =
+                    PluginRuntimeClient::spawn(runtime(&[handler("first"), handler("second")]));
+
+                let ctx = RequestContext::builder()
+                    .method("POST")
+                    .route_params([("post".to_string(), "hello".to_string())].into())
+                    .build();
+                let ctx = client.route("first", "handle", ctx).await.unwrap();
+                assert_eq!(ctx.response_spec.status.as_u16(), 201);
+                match &ctx.response_spec.body {
+                    ResponseBodySpec::JsonValue(v) => {
+                        assert_eq!(v, &json!({ "by": "first", "post": "hello" }))
+                    }
+                    other => panic!("unexpected body {other:?}"),
+                }
+
+                let missing = client
+                    .route("first", "nope", RequestContext::builder().build())
+                    .await;
+                assert!(missing.is_err());
+                client.stop();
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn filters_pass_the_value_through_every_listener() {
+        LocalSet::new()
+            .run_until(async {
+                let listener = |id: &str, tag: &str| PluginConfig {
+                    source: format!(
+                        "function init(ctx) {{ \
+                           ctx.hooks.register('form.submission', (s) => \
+                             ({{ ...s, tags: [...(s.tags || []), '{tag}'] }})); \
+                           registerPlugin({{}}); }}"
+                    ),
+                    ..plugin(id, "")
+                };
+                let client = PluginRuntimeClient::spawn(runtime(&[
+                    listener("crm", "crm"),
+                    listener("spam", "checked"),
+                ]));
+
+                let value = JsValue::from_json(&json!({ "form": "contact" }));
+                let out = client
+                    .filter("form.submission", value, RequestContext::builder().build())
+                    .await
+                    .unwrap();
+                assert_eq!(
+                    out.to_json(),
+                    json!({ "form": "contact", "tags": ["crm", "checked"] })
+                );
+                client.stop();
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn a_passing_upgrade_is_swapped_in() {
+        LocalSet::new()
+            .run_until(async {
+                let client = PluginRuntimeClient::spawn(runtime(&[plugin("seo", "")]));
+
+                client
+                    .reload(
+                        &[plugin("seo", ""), plugin("analytics", "")],
+                        RequestContext::builder().build(),
+                    )
+                    .await
+                    .unwrap();
+                assert_eq!(client.plugin_ids(), ["seo", "analytics"]);
+                client.stop();
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn a_rejected_upgrade_stores_nothing() {
+        LocalSet::new()
+            .run_until(async {
+                let env = EngineEnv::default();
+                let client =
+                    PluginRuntimeClient::spawn(runtime_in(&[plugin("seo", "")], env.clone()));
+
+                let broken = PluginConfig {
+                    source: "function init(ctx) { storage.set('ran', true); \
+                               registerPlugin({ before(ctx) { throw new Error('boom'); } }); }"
+                        .into(),
+                    ..plugin("trial-only", "")
+                };
+                let err = client
+                    .reload(&[broken], RequestContext::builder().build())
+                    .await
+                    .unwrap_err();
+                assert!(err.to_string().contains("boom"), "{err}");
+                assert_eq!(env.storage.get("trial-only", "ran"), Ok(None));
+                client.stop();
+            })
+            .await;
     }
 }
@@ -0,0 +1,359 @@
+// crates/adapt/src/runtime/pool.rs
+
+// Warm engine reuse.
+//
+// A plugin or theme runtime is expensive to build: a new Boa context, the
+// ctx/services shims, every plugin's source, and `init`. The actors therefore
+// keep built ("warm") runtimes in an `EnginePool` and reuse them for every
+// hook call. Between calls the runtime is reset: globals created since it was
+// warmed are deleted and the global object's shape (names and `typeof`) is
+// checked against the warm baseline. A runtime that fails the check is
+// dropped and replaced by a spare or a freshly built one, so state one
+// request leaves on `globalThis` never reaches the next.
+//
+// Spares are rebuilt while the actor is idle (`top_up`), which runs the
+// plugin or theme `init` again with the context it was first initialized
+// with.
+
+use super::error::RuntimeError;
+use crate::js::{JsEngine, JsValue};
+use tracing::{debug, warn};
+
+/// Warm runtimes kept per pool (the one in use plus spares).
+pub const DEFAULT_POOL_CAPACITY: usize = 2;
+
+/// A runtime the pool can reset between uses and rebuild when that fails.
+pub trait Pooled: Sized {
+    /// Clear request-scoped state. An error means the instance must not be
+    /// reused.
+    fn reset(&mut self) -> Result<(), RuntimeError>;
+
+    /// A freshly built and warmed equivalent of `self`.
+    fn rebuild(&self) -> Result<Self, RuntimeError>;
+}
+
+/// Counters for logging and tests.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Uses served by a runtime that had already been used and reset.
+    pub reused: u64,
+    /// Resets that failed validation.
+    pub reset_failures: u64,
+    /// Runtimes built by the pool (spares and replacements).
+    pub rebuilt: u64,
+}
+
+/// Warm runtimes for one plugin set or theme; never empty.
+pub struct EnginePool<R: Pooled> {
+    idle: Vec<R>,
+    capacity: usize,
+    /// Whether `idle.last()` has served a call since it was built.
+    used: bool,
+    stats: PoolStats,
+}
+
+impl<R: Pooled> EnginePool<R> {
+    pub fn new(warm: R) -> Self {
+        Self::with_capacity(warm, DEFAULT_POOL_CAPACITY)
+    }
+
+    pub fn with_capacity(warm: R, capacity: usize) -> Self {
+        Self {
+            idle: vec![warm],
+            capacity: capacity.max(1),
+            used: false,
+            stats: PoolStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        self.stats
+    }
+
+    /// Number of warm runtimes held.
+    pub fn warm(&self) -> usize {
+        self.idle.len()
+    }
+
+    /// Run `f` on a warm runtime, then reset it for the next call.
+    pub fn run<T>(&mut self, f: impl FnOnce(&mut R) -> T) -> T {
+        let mut rt = self.idle.pop().expect("engine pool is never empty");
+        if self.used {
+            self.stats.reused += 1;
+        }
+
+        let out = f(&mut rt);
+        self.checkin(rt);
+        out
+    }
+
+    /// Replace every runtime with `warm`, e.g. after (re)initialization
+    /// changed what a warm runtime looks like.
+    pub fn replace(&mut self, warm: R) {
+        self.idle.clear();
+        self.idle.push(warm);
+        self.used = false;
+    }
+
+    /// Drop the spares, keeping the runtime in use; they are rebuilt from
+    /// it by `top_up`.
+    pub fn clear_spares(&mut self) {
+        let keep = self.idle.len().saturating_sub(1);
+        self.idle.drain(..keep);
+    }
+
+    /// Build one spare if below capacity. Meant for idle moments, so a
+    /// failed reset can switch to a spare instead of building on the
+    /// request path.
+    pub fn top_up(&mut self) {
+        if self.idle.len() >= self.capacity {
+            return;
+        }
+        let Some(template) = self.idle.first() else {
+            return;
+        };
+
+        match template.rebuild() {
+            Ok(spare) => {
+                self.stats.rebuilt += 1;
+                // Spares go to the bottom; the runtime in use stays on top.
+                self.idle.insert(0, spare);
+            }
+            Err(e) => warn!("Building a spare JS runtime failed: {}", e),
+        }
+    }
+
+    fn checkin(&mut self, mut rt: R) {
+        match rt.reset() {
+            Ok(()) => {
+                self.idle.push(rt);
+                self.used = true;
+            }
+            Err(e) => {
+                self.stats.reset_failures += 1;
+                debug!("JS runtime reset failed, discarding it: {}", e);
+
+                if !self.idle.is_empty() {
+                    // A spare takes over; `top_up` replaces it later.
+                    self.used = false;
+                    return;
+                }
+
+                match rt.rebuild() {
+                    Ok(fresh) => {
+                        self.stats.rebuilt += 1;
+                        self.idle.push(fresh);
+                        self.used = false;
+                    }
+                    Err(e) => {
+                        // Serving from a dirty runtime beats serving nothing.
+                        warn!("Rebuilding JS runtime failed, keeping the old one: {}", e);
+                        self.idle.push(rt);
+                        self.used = true;
+                    }
+                }
+            }
+        }
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Global-object snapshots
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Own properties of `globalThis` as `(name, typeof value)`, sorted by name.
+pub(crate) type GlobalShape = Vec<(String, String)>;
+
+const SHAPE_SRC: &str = r#"Object.getOwnPropertyNames(globalThis)
+    .sort()
+    .map((n) => [n, typeof globalThis[n]])"#;
+
+/// Snapshot the global object's shape.
+pub(crate) fn global_shape<E: JsEngine>(engine: &mut E) -> Result<GlobalShape, RuntimeError> {
+    let JsValue::Array(items) = engine.eval(SHAPE_SRC)? else {
+        return Err(RuntimeError::other("global shape is not an array"));
+    };
+
+    items
+        .into_iter()
+        .map(|item| match item {
+            JsValue::Array(pair) => match pair.as_slice() {
+                [JsValue::String(name), JsValue::String(kind)] => Ok((name.clone(), kind.clone())),
+                _ => Err(RuntimeError::other("malformed global shape entry")),
+            },
+            _ => Err(RuntimeError::other("malformed global shape entry")),
+        })
+        .collect()
+}
+
+/// Delete every global not in `baseline`, then check the shape matches it.
+pub(crate) fn reset_globals<E: JsEngine>(
+    engine: &mut E,
+    baseline: &GlobalShape,
+) -> Result<(), RuntimeError> {
+    let keep: Vec<&str> = baseline.iter().map(|(name, _)| name.as_str()).collect();
+    let keep = serde_json::to_string(&keep)
+        .map_err(|e| RuntimeError::other(format!("encoding global names: {e}")))?;
+
+    engine.eval(&format!(
+        r#"(function (keep) {{
+    const k = new Set(keep);
+    for (const n of Object.getOwnPropertyNames(globalThis)) {{
+        if (!k.has(n)) {{
+            delete globalThis[n];
+        }}
+    }}
+}})({keep});"#
+    ))?;
+
+    let now = global_shape(engine)?;
+    if &now != baseline {
+        let changed: Vec<&str> = now
+            .iter()
+            .filter(|entry| !baseline.contains(entry))
+            .chain(baseline.iter().filter(|entry| !now.contains(entry)))
+            .map(|(name, _)| name.as_str())
+            .collect();
+        return Err(RuntimeError::other(format!(
+            "globals differ from the warm baseline: {}",
+            changed.join(", ")
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::js::engine::BoaEngine;
+
+    /// An engine with one warm global, reset against its baseline.
+    struct Warm {
+        engine: BoaEngine,
+        baseline: GlobalShape,
+        generation: u32,
+    }
+
+    impl Warm {
+        fn new(generation: u32) -> Self {
+            let mut engine = BoaEngine::new();
+            engine.eval("globalThis.config = { n: 1 };").unwrap();
+            let baseline = global_shape(&mut engine).unwrap();
+            Self {
+                engine,
+                baseline,
+                generation,
+            }
+        }
+    }
+
+    impl Pooled for Warm {
+        fn reset(&mut self) -> Result<(), RuntimeError> {
+            reset_globals(&mut self.engine, &self.baseline)
+        }
+
+        fn rebuild(&self) -> Result<Self, RuntimeError> {
+            Ok(Warm::new(self.generation + 1))
+        }
+    }
+
+    #[test]
+    fn request_globals_are_cleared_between_runs() {
+        let mut pool = EnginePool::with_capacity(Warm::new(0), 1);
+
+        pool.run(|w| w.engine.eval("globalThis.leak = 'secret';").unwrap());
+        let leaked = pool.run(|w| w.engine.eval("typeof globalThis.leak").unwrap());
+
+        assert_eq!(leaked, JsValue::String("undefined".into()));
+        assert_eq!(pool.run(|w| w.generation), 0);
+        assert_eq!(
+            pool.stats(),
+            PoolStats {
+                reused: 2,
+                reset_failures: 0,
+                rebuilt: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn failed_validation_falls_back_to_a_fresh_runtime() {
+        let mut pool = EnginePool::with_capacity(Warm::new(0), 1);
+
+        // Replacing a warm global with another type cannot be undone by
+        // deleting new names.
+        pool.run(|w| w.engine.eval("globalThis.config = 42;").unwrap());
+
+        assert_eq!(pool.run(|w| w.generation), 1);
+        let config = pool.run(|w| w.engine.eval("typeof config").unwrap());
+        assert_eq!(config, JsValue::String("object".into()));
+        assert_eq!(pool.stats().reset_failures, 1);
+        assert_eq!(pool.stats().rebuilt, 1);
+    }
+
+    #[test]
+    fn a_spare_takes_over_and_is_replaced_when_idle() {
+        let mut pool = EnginePool::new(Warm::new(0));
+        pool.top_up();
+        assert_eq!(pool.warm(), 2);
+
+        pool.run(|w| w.engine.eval("globalThis.config = 'gone';").unwrap());
+        assert_eq!(pool.warm(), 1);
+        assert_eq!(pool.run(|w| w.generation), 1);
+
+        pool.top_up();
+        assert_eq!(pool.warm(), 2);
+    }
+
+    #[test]
+    fn plugin_runtimes_keep_init_state_across_resets() {
+        use crate::runtime::plugin::{PluginRuntime, PluginSpec};
+        use serve::render::http::RequestContext;
+
+        let spec = PluginSpec {
+            id: "counter".into(),
+            name: "counter".into(),
+            source: r#"
+                function init(ctx) {
+                    globalThis.hits = 0;
+                    registerPlugin({
+                        before(ctx) {
+                            globalThis.hits += 1;
+                            globalThis.lastPath = "/seen";
+                            return ctx;
+                        }
+                    });
+                }
+            "#
+            .into(),
+            provides: Vec::new(),
+            requires: Vec::new(),
+            permissions: Default::default(),
+            fetch: Default::default(),
+            settings: Default::default(),
+            config: Default::default(),
+            routes: Vec::new(),
+        };
+
+        let mut rt = PluginRuntime::new(BoaEngine::new()).unwrap();
+        rt.load_plugins(&[spec]).unwrap();
+        rt.init_all(&RequestContext::builder().build()).unwrap();
+        let mut pool = EnginePool::new(rt);
+
+        for _ in 0..2 {
+            let mut ctx = RequestContext::builder().build();
+            pool.run(|rt| rt.before_plugin("counter", &mut ctx))
+                .unwrap();
+        }
+
+        let hits = pool.run(|rt| rt.eval_for_test("hits").unwrap());
+        assert_eq!(hits, JsValue::Number(2.0));
+        let last = pool.run(|rt| rt.eval_for_test("typeof lastPath").unwrap());
+        assert_eq!(last, JsValue::String("undefined".into()));
+        assert_eq!(pool.stats().reset_failures, 0);
+
+        // A spare is built and initialized from the same specs and context.
+        pool.top_up();
+        assert_eq!(pool.warm(), 2);
+    }
+}
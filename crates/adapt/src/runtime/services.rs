@@ -0,0 +1,439 @@
+// crates/adapt/src/runtime/services.rs
+
+//! Cross-plugin shared services.
+//!
+//! A plugin declares the services it provides and requires in its manifest:
+//!
+//! ```toml
+//! [[provides]]
+//! name = "image-optimizer"
+//! version = "1.2.0"
+//!
+//! [[requires]]
+//! name = "image-optimizer"
+//! version = "1.1"        # same major, at least 1.1.0
+//! ```
+//!
+//! At load time the host resolves those declarations into a deterministic
+//! init order (providers before consumers, ties in load order) and rejects
+//! missing providers, incompatible versions, duplicates, and cycles.
+//!
+//! At run time every plugin sees `ctx.services`:
+//! - `register(name, impl)` — only for names it declared in `provides`;
+//! - `get(name)` / `has(name)` — only for names it declared in `requires`.
+//!
+//! All plugins share one JS engine, so a service is just a JS object handed
+//! from one plugin to another; nothing crosses the Rust boundary.
+
+use super::error::RuntimeError;
+use super::plugin::PluginSpec;
+use crate::js::JsValue;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+
+/// A named, versioned service contract.
+///
+/// In `provides`, `version` is the exact version implemented. In `requires`,
+/// it is the minimum compatible version within the same major.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServiceContract {
+    pub name: String,
+    pub version: String,
+}
+
+impl ServiceContract {
+    pub fn new(name: impl Into<String>, version: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            version: version.into(),
+        }
+    }
+}
+
+/// Parse `MAJOR[.MINOR[.PATCH]]`; missing parts are zero.
+fn parse_version(v: &str) -> Option<(u64, u64, u64)> {
+    let mut parts = v.trim().split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next().map_or(Some(0), |p| p.parse().ok())?;
+    let patch = parts.next().map_or(Some(0), |p| p.parse().ok())?;
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((major, minor, patch))
+}
+
+/// Does a provided `have` version satisfy a required `want` version?
+///
+/// Same major, and `have >= want`. Unparseable versions never match.
+pub fn version_satisfies(want: &str, have: &str) -> bool {
+    match (parse_version(want), parse_version(have)) {
+        (Some(w), Some(h)) => w.0 == h.0 && h >= w,
+        _ => false,
+    }
+}
+
+/// Resolve plugin service dependencies into an init order.
+///
+/// Returns indices into `specs`, providers first. Among plugins whose
+/// dependencies are satisfied, the one loaded earliest goes first, so the
+/// result is stable for a given plugin list.
+pub fn resolve_init_order(specs: &[PluginSpec]) -> Result<Vec<usize>, RuntimeError> {
+    // service name → providing plugin index
+    let mut providers: HashMap<&str, usize> = HashMap::new();
+    for (idx, spec) in specs.iter().enumerate() {
+        for svc in &spec.provides {
+            if parse_version(&svc.version).is_none() {
+                return Err(RuntimeError::plugin_bootstrap(format!(
+                    "plugin '{}' provides '{}' with invalid version '{}'",
+                    spec.id, svc.name, svc.version
+                )));
+            }
+            if let Some(&other) = providers.get(svc.name.as_str()) {
+                return Err(RuntimeError::plugin_bootstrap(format!(
+                    "service '{}' is provided by both '{}' and '{}'",
+                    svc.name, specs[other].id, spec.id
+                )));
+            }
+            providers.insert(svc.name.as_str(), idx);
+        }
+    }
+
+    // deps[i] = plugins that must init before i
+    let mut deps: Vec<BTreeSet<usize>> = vec![BTreeSet::new(); specs.len()];
+    for (idx, spec) in specs.iter().enumerate() {
+        for req in &spec.requires {
+            let &provider = providers.get(req.name.as_str()).ok_or_else(|| {
+                RuntimeError::plugin_bootstrap(format!(
+                    "plugin '{}' requires service '{}', which no plugin provides",
+                    spec.id, req.name
+                ))
+            })?;
+
+            let provided = specs[provider]
+                .provides
+                .iter()
+                .find(|p| p.name == req.name)
+                .map(|p| p.version.as_str())
+                .unwrap_or_default();
+
+            if !version_satisfies(&req.version, provided) {
+                return Err(RuntimeError::plugin_bootstrap(format!(
+                    "plugin '{}' requires '{}' {} but '{}' provides {}",
+                    spec.id, req.name, req.version, specs[provider].id, provided
+                )));
+            }
+
+            if provider != idx {
+                deps[idx].insert(provider);
+            }
+        }
+    }
+
+    // Kahn's algorithm, always picking the lowest ready index.
+    let mut order = Vec::with_capacity(specs.len());
+    let mut done = vec![false; specs.len()];
+    while order.len() < specs.len() {
+        let next = (0..specs.len())
+            .find(|&i| !done[i] && deps[i].iter().all(|&d| done[d]))
+            .ok_or_else(|| {
+                let stuck: Vec<&str> = (0..specs.len())
+                    .filter(|&i| !done[i])
+                    .map(|i| specs[i].id.as_str())
+                    .collect();
+                RuntimeError::plugin_bootstrap(format!(
+                    "service dependency cycle between plugins: {}",
+                    stuck.join(", ")
+                ))
+            })?;
+        done[next] = true;
+        order.push(next);
+    }
+
+    Ok(order)
+}
+
+/// The arguments `__beginPlugin` takes besides the internal ID: the
+/// services `spec` may register and the ones it may look up, as
+/// `{ name: version }` objects.
+pub(crate) fn service_grants(spec: &PluginSpec) -> [JsValue; 2] {
+    let to_map = |list: &[ServiceContract]| {
+        JsValue::from_json(&serde_json::Value::Object(
+            list.iter()
+                .map(|c| (c.name.clone(), serde_json::Value::String(c.version.clone())))
+                .collect(),
+        ))
+    };
+    [to_map(&spec.provides), to_map(&spec.requires)]
+}
+
+/// JS side of the registry, plus the hook trampoline that attaches the
+/// per-plugin `ctx.services` and `ctx.hooks` (see [`super::hooks`]) views
+/// before calling `init`, `before`, `after`, or a route handler.
+///
+/// Each plugin's source loads between `__beginPlugin(internalId, provides,
+/// requires)` and `__endPlugin(internalId, handlers)`, which keep its
+/// grants, `init` and route handlers here, out of other plugins' reach;
+/// `registerPlugin` files `before`/`after` under whichever plugin is loading
+/// or in `init`.
+pub const SERVICES_SHIM_SRC: &str = r#"
+(function (global) {
+    // Captured now: these globals are hidden before any plugin loads.
+    const hooksFor = global.__hooksFor;
+    const host = global.__host;
+    const wrapCtx = global.__wrapCtx;
+
+    // No prototypes, so nothing a plugin adds to Object.prototype turns up.
+    const registry = Object.create(null);
+    const grants = Object.create(null);
+    const inits = Object.create(null);
+    const lifecycles = Object.create(null);
+    const routes = Object.create(null);
+    let current = null;
+    const hasOwn = Function.prototype.call.bind(Object.prototype.hasOwnProperty);
+
+    // Host-provided registration. Plugins call this inside init().
+    Object.defineProperty(global, "registerPlugin", {
+        value: function registerPlugin(hooks) {
+            if (current === null) {
+                throw new Error("registerPlugin: only callable while a plugin loads or inits");
+            }
+            if (!hooks || typeof hooks !== "object") {
+                throw new Error("registerPlugin: hooks object is required");
+            }
+            // We DO NOT register init. Only before/after.
+            lifecycles[current] = {
+                __proto__: null,
+                before: typeof hooks.before === "function" ? hooks.before : undefined,
+                after: typeof hooks.after === "function" ? hooks.after : undefined,
+            };
+        },
+        writable: false,
+        configurable: false,
+    });
+
+    global.__beginPlugin = function (internalId, provides, requires) {
+        grants[internalId] = { provides: provides || {}, requires: requires || {} };
+        current = internalId;
+    };
+
+    // Every plugin declares a global `init`, so take this one before the
+    // next plugin overwrites it, along with the functions its routes name.
+    global.__endPlugin = function (internalId, handlers) {
+        current = null;
+        inits[internalId] = typeof global.init === "function" ? global.init : undefined;
+        global.init = undefined;
+
+        const own = Object.create(null);
+        for (const name of handlers) {
+            if (typeof global[name] === "function") {
+                own[name] = global[name];
+            }
+        }
+        routes[internalId] = own;
+    };
+
+    function servicesFor(internalId) {
+        const g = grants[internalId] || { provides: {}, requires: {} };
+        return {
+            register(name, impl) {
+                if (!hasOwn(g.provides, name)) {
+                    throw new Error(`services.register: '${name}' is not declared in provides`);
+                }
+                if (hasOwn(registry, name)) {
+                    throw new Error(`services.register: '${name}' is already registered`);
+                }
+                registry[name] = { version: g.provides[name], impl: impl };
+            },
+            get(name) {
+                if (!hasOwn(g.requires, name)) {
+                    throw new Error(`services.get: '${name}' is not declared in requires`);
+                }
+                return hasOwn(registry, name) ? registry[name].impl : undefined;
+            },
+            has(name) {
+                return hasOwn(g.requires, name) && hasOwn(registry, name);
+            },
+            version(name) {
+                return hasOwn(g.requires, name) && hasOwn(registry, name)
+                    ? registry[name].version
+                    : undefined;
+            },
+        };
+    }
+
+    function callWithViews(internalId, fn, ctx, initPhase) {
+        const wrapped = wrapCtx(ctx);
+        wrapped.services = servicesFor(internalId);
+        wrapped.hooks = hooksFor(internalId, initPhase);
+        // Capability checks happen host-side against the calling plugin.
+        wrapped.host = { call: (op, args) => host(op, args) };
+        const outer = current;
+        current = initPhase ? internalId : null;
+        try {
+            return fn(wrapped);
+        } finally {
+            current = outer;
+            delete wrapped.services;
+            delete wrapped.hooks;
+            delete wrapped.host;
+        }
+    }
+
+    global.__callPluginHook = function (internalId, hook, ctx) {
+        const fn = hook === "init"
+            ? inits[internalId]
+            : lifecycles[internalId] && lifecycles[internalId][hook];
+        if (typeof fn !== "function") {
+            return null;
+        }
+        return callWithViews(internalId, fn, ctx, hook === "init");
+    };
+
+    global.__callPluginRoute = function (internalId, handler, ctx) {
+        const fn = routes[internalId] && routes[internalId][handler];
+        if (typeof fn !== "function") {
+            throw new Error(`route handler '${handler}' is not a function`);
+        }
+        return callWithViews(internalId, fn, ctx, false);
+    };
+})(typeof globalThis !== "undefined" ? globalThis : this);
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::js::engine::BoaEngine;
+    use crate::runtime::plugin::PluginRuntime;
+    use serde_json::json;
+    use serve::render::http::RequestContext;
+
+    fn spec(id: &str, provides: &[(&str, &str)], requires: &[(&str, &str)]) -> PluginSpec {
+        PluginSpec {
+            id: id.into(),
+            name: id.into(),
+            source: String::new(),
+            provides: provides
+                .iter()
+                .map(|(n, v)| ServiceContract::new(*n, *v))
+                .collect(),
+            requires: requires
+                .iter()
+                .map(|(n, v)| ServiceContract::new(*n, *v))
+                .collect(),
+            permissions: Default::default(),
+            fetch: Default::default(),
+            settings: Default::default(),
+            config: Default::default(),
+            routes: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn version_rules_are_same_major_and_at_least() {
+        assert!(version_satisfies("1", "1.4.2"));
+        assert!(version_satisfies("1.4", "1.4.0"));
+        assert!(!version_satisfies("1.5", "1.4.9"));
+        assert!(!version_satisfies("1", "2.0.0"));
+        assert!(!version_satisfies("1", "one"));
+    }
+
+    #[test]
+    fn providers_init_before_consumers_and_ties_keep_load_order() {
+        let specs = vec![
+            spec("gallery", &[], &[("image-optimizer", "1")]),
+            spec("seo", &[], &[]),
+            spec("images", &[("image-optimizer", "1.2.0")], &[]),
+        ];
+
+        let order = resolve_init_order(&specs).unwrap();
+        let ids: Vec<&str> = order.iter().map(|&i| specs[i].id.as_str()).collect();
+        assert_eq!(ids, vec!["seo", "images", "gallery"]);
+    }
+
+    #[test]
+    fn resolver_rejects_missing_incompatible_duplicate_and_cyclic() {
+        let missing = vec![spec("a", &[], &[("cache", "1")])];
+        assert!(resolve_init_order(&missing).is_err());
+
+        let too_old = vec![
+            spec("a", &[("cache", "1.0.0")], &[]),
+            spec("b", &[], &[("cache", "1.3")]),
+        ];
+        assert!(resolve_init_order(&too_old).is_err());
+
+        let duplicate = vec![
+            spec("a", &[("cache", "1.0.0")], &[]),
+            spec("b", &[("cache", "1.0.0")], &[]),
+        ];
+        assert!(resolve_init_order(&duplicate).is_err());
+
+        let cycle = vec![
+            spec("a", &[("x", "1.0.0")], &[("y", "1")]),
+            spec("b", &[("y", "1.0.0")], &[("x", "1")]),
+        ];
+        let err = resolve_init_order(&cycle).unwrap_err().to_string();
+        assert!(err.contains("cycle"), "got {err}");
+    }
+
+    #[test]
+    fn consumer_calls_service_registered_by_provider() {
+        let mut consumer = spec("gallery", &[], &[("upper", "1")]);
+        consumer.source = r#"
+            function init(ctx) {
+                globalThis.__galleryResult = ctx.services.get("upper").shout("hi");
+            }
+        "#
+        .into();
+
+        let mut provider = spec("shouter", &[("upper", "1.0.0")], &[]);
+        provider.source = r#"
+            function init(ctx) {
+                ctx.services.register("upper", { shout: (s) => s.toUpperCase() + "!" });
+            }
+        "#
+        .into();
+
+        // Consumer listed first: the resolver must still init the provider first.
+        let mut rt = PluginRuntime::new(BoaEngine::new()).unwrap();
+        rt.load_plugins(&[consumer, provider]).unwrap();
+        assert_eq!(rt.configured_ids(), vec!["shouter", "gallery"]);
+
+        rt.init_all(&RequestContext::builder().build()).unwrap();
+        let out = rt.eval_for_test("globalThis.__galleryResult").unwrap();
+        assert_eq!(out.to_json(), json!("HI!"));
+    }
+
+    #[test]
+    fn undeclared_service_access_is_denied() {
+        let mut sneaky = spec("sneaky", &[], &[]);
+        sneaky.source = r#"
+            function init(ctx) { ctx.services.register("upper", {}); }
+        "#
+        .into();
+
+        let mut rt = PluginRuntime::new(BoaEngine::new()).unwrap();
+        rt.load_plugins(&[sneaky]).unwrap();
+
+        let err = rt
+            .init_all(&RequestContext::builder().build())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("not declared in provides"), "got {err}");
+    }
+
+    #[test]
+    fn grants_are_out_of_plugin_reach() {
+        let mut thief = spec("thief", &[], &[]);
+        thief.source = r#"
+            globalThis.seen = [typeof __grantServices, typeof __beginPlugin];
+        "#
+        .into();
+
+        let mut rt = PluginRuntime::new(BoaEngine::new()).unwrap();
+        rt.load_plugins(&[thief]).unwrap();
+        assert_eq!(
+            rt.eval_for_test("seen").unwrap().to_json(),
+            json!(["undefined", "undefined"])
+        );
+    }
+}
@@ -0,0 +1,281 @@
+// crates/adapt/src/runtime/settings.rs
+
+//! Typed settings for plugins and themes.
+//!
+//! A manifest describes the settings it takes under `[settings.<key>]`:
+//!
+//! ```toml
+//! [settings.posts_per_page]
+//! type = "integer"
+//! default = 10
+//! min = 1
+//! max = 100
+//!
+//! [settings.layout]
+//! type = "string"
+//! enum = ["grid", "list"]
+//! required = true
+//! ```
+//!
+//! The site's stored values are checked against it when the runtime loads
+//! the extension, and what the extension sees as `ctx.config` is the stored
+//! values with defaults filled in. Every problem is reported at once, each
+//! naming the key, so a bad value stops the load instead of surfacing as a
+//! surprise in the middle of a request.
+//!
+//! An extension without `[settings]` gets its stored values as they are.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map as JsonMap, Value as Json};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// The JSON type a setting holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SettingType {
+    String,
+    Integer,
+    Number,
+    Boolean,
+    Array,
+    Object,
+}
+
+impl SettingType {
+    fn admits(self, value: &Json) -> bool {
+        match self {
+            SettingType::String => value.is_string(),
+            SettingType::Integer => value.is_i64() || value.is_u64(),
+            SettingType::Number => value.is_number(),
+            SettingType::Boolean => value.is_boolean(),
+            SettingType::Array => value.is_array(),
+            SettingType::Object => value.is_object(),
+        }
+    }
+}
+
+impl fmt::Display for SettingType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SettingType::String => "string",
+            SettingType::Integer => "integer",
+            SettingType::Number => "number",
+            SettingType::Boolean => "boolean",
+            SettingType::Array => "array",
+            SettingType::Object => "object",
+        })
+    }
+}
+
+/// One `[settings.<key>]` table.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SettingDef {
+    #[serde(rename = "type")]
+    pub kind: SettingType,
+    /// Used when the site stores no value.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<Json>,
+    /// Whether the site must store a value (only useful without a default).
+    #[serde(default)]
+    pub required: bool,
+    /// The only values allowed.
+    #[serde(default, rename = "enum", skip_serializing_if = "Option::is_none")]
+    pub choices: Option<Vec<Json>>,
+    /// Bounds of a number, or of a string's or array's length.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+    /// For admin screens.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+impl SettingDef {
+    /// Why `value` is not allowed, if it is not.
+    fn check(&self, value: &Json) -> Option<String> {
+        if !self.kind.admits(value) {
+            return Some(format!("expected {}, got {value}", self.kind));
+        }
+        if let Some(choices) = &self.choices {
+            if !choices.contains(value) {
+                let allowed: Vec<String> = choices.iter().map(Json::to_string).collect();
+                return Some(format!("{value} is not one of {}", allowed.join(", ")));
+            }
+        }
+        let (measure, what) = match value {
+            Json::Number(n) => (n.as_f64(), "value"),
+            Json::String(s) => (Some(s.chars().count() as f64), "length"),
+            Json::Array(a) => (Some(a.len() as f64), "length"),
+            _ => (None, ""),
+        };
+        let measure = measure?;
+        if let Some(min) = self.min.filter(|min| measure < *min) {
+            return Some(format!("{what} {measure} is below the minimum {min}"));
+        }
+        if let Some(max) = self.max.filter(|max| measure > *max) {
+            return Some(format!("{what} {measure} is above the maximum {max}"));
+        }
+        None
+    }
+}
+
+/// The `[settings]` of a manifest, by key.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct SettingsSchema(pub BTreeMap<String, SettingDef>);
+
+impl SettingsSchema {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// `stored` (an object, or null for nothing stored) checked against the
+    /// schema, with defaults filled in. The error lists every problem.
+    pub fn resolve(&self, stored: &Json) -> Result<Json, String> {
+        let stored = match stored {
+            Json::Null => JsonMap::new(),
+            Json::Object(map) => map.clone(),
+            other => return Err(format!("stored settings must be an object, got {other}")),
+        };
+        if self.is_empty() {
+            return Ok(Json::Object(stored));
+        }
+
+        let mut problems = Vec::new();
+        for key in stored.keys().filter(|k| !self.0.contains_key(*k)) {
+            problems.push(format!("`{key}`: not a setting this extension takes"));
+        }
+
+        let mut resolved = JsonMap::new();
+        for (key, def) in &self.0 {
+            if let Some(default) = &def.default {
+                if let Some(why) = def.check(default) {
+                    problems.push(format!("`{key}`: default {why}"));
+                }
+            }
+            match stored.get(key).or(def.default.as_ref()) {
+                Some(value) => {
+                    if let Some(why) = stored.get(key).and_then(|v| def.check(v)) {
+                        problems.push(format!("`{key}`: {why}"));
+                    }
+                    resolved.insert(key.clone(), value.clone());
+                }
+                None if def.required => {
+                    problems.push(format!("`{key}`: required, and no value is stored"))
+                }
+                None => {}
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(Json::Object(resolved))
+        } else {
+            Err(problems.join("; "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn schema() -> SettingsSchema {
+        toml::from_str(
+            r#"
+            [posts_per_page]
+            type = "integer"
+            default = 10
+            min = 1
+            max = 100
+
+            [layout]
+            type = "string"
+            enum = ["grid", "list"]
+            required = true
+
+            [tagline]
+            type = "string"
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn stored_values_are_checked_and_defaults_filled_in() {
+        let schema = schema();
+        assert_eq!(
+            schema.resolve(&json!({ "layout": "grid" })).unwrap(),
+            json!({ "layout": "grid", "posts_per_page": 10 })
+        );
+        assert_eq!(
+            schema
+                .resolve(&json!({ "layout": "list", "posts_per_page": 5, "tagline": "hi" }))
+                .unwrap(),
+            json!({ "layout": "list", "posts_per_page": 5, "tagline": "hi" })
+        );
+
+        let err = schema
+            .resolve(&json!({ "posts_per_page": 0, "colour": "red" }))
+            .unwrap_err();
+        assert_eq!(
+            err,
+            "`colour`: not a setting this extension takes; \
+             `layout`: required, and no value is stored; \
+             `posts_per_page`: value 0 is below the minimum 1"
+        );
+        let err = schema
+            .resolve(&json!({ "layout": "masonry", "posts_per_page": "ten" }))
+            .unwrap_err();
+        assert!(err.contains(r#"`layout`: "masonry" is not one of "grid", "list""#));
+        assert!(err.contains(r#"`posts_per_page`: expected integer, got "ten""#));
+
+        // Without a schema the stored values pass through.
+        assert_eq!(
+            SettingsSchema::default().resolve(&Json::Null).unwrap(),
+            json!({})
+        );
+        assert!(schema.resolve(&json!([1])).is_err());
+    }
+
+    #[test]
+    fn plugins_see_resolved_settings_and_bad_ones_stop_the_load() {
+        use crate::js::engine::BoaEngine;
+        use crate::runtime::plugin::{PluginRuntime, PluginSpec};
+        use crate::runtime::RuntimeError;
+        use serve::render::http::RequestContext;
+
+        let spec = |config: Json| PluginSpec {
+            id: "lister".into(),
+            name: "lister".into(),
+            source: r#"function init(ctx) { globalThis.seen = ctx.config; }"#.into(),
+            provides: Vec::new(),
+            requires: Vec::new(),
+            permissions: Default::default(),
+            fetch: Default::default(),
+            settings: schema(),
+            config,
+            routes: Vec::new(),
+        };
+
+        let mut rt = PluginRuntime::new(BoaEngine::new()).unwrap();
+        rt.load_plugins(&[spec(json!({ "layout": "list" }))])
+            .unwrap();
+        rt.init_all(&RequestContext::builder().build()).unwrap();
+        assert_eq!(
+            rt.eval_for_test("globalThis.seen").unwrap().to_json(),
+            json!({ "layout": "list", "posts_per_page": 10 })
+        );
+
+        let mut rt = PluginRuntime::new(BoaEngine::new()).unwrap();
+        let err = rt.load_plugins(&[spec(json!({}))]).unwrap_err();
+        assert!(matches!(err, RuntimeError::PluginBootstrap(_)));
+        assert!(
+            err.to_string()
+                .contains("plugin 'lister' settings: `layout`: required"),
+            "got {err}"
+        );
+    }
+}
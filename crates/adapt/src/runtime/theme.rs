@@ -1,9 +1,11 @@
 // crates/adapt/src/runtime/theme.rs
 
-use super::bridge::{ctx_to_js_for_theme, merge_theme_ctx_from_js, CTX_SHIM_SRC};
+use super::bridge::{ctx_to_js_with_config, merge_theme_ctx_from_js, CTX_SHIM_SRC};
 use super::error::RuntimeError;
-use crate::js::{JsEngine, JsValue};
-use serde_json;
+use super::pool::{global_shape, reset_globals, GlobalShape};
+use super::settings::SettingsSchema;
+use crate::js::{JsEngine, JsValue, LogScope};
+use serde_json::{self, Value as Json};
 use serve::render::http::RequestContext;
 use tracing::debug;
 use uuid::Uuid;
@@ -39,6 +41,11 @@ pub struct ThemeSpec {
     pub name: String,
     pub mount_path: String,
     pub source: String,
+    /// The settings the theme takes (`[settings]` in theme.toml).
+    pub settings: SettingsSchema,
+    /// The site's stored settings values, null for none; checked against
+    /// `settings` on load.
+    pub config: Json,
 }
 
 impl ThemeSpec {
@@ -53,8 +60,16 @@ impl ThemeSpec {
             name: name.into(),
             mount_path: mount_path.into(),
             source: source.into(),
+            settings: SettingsSchema::default(),
+            config: Json::Null,
         }
     }
+
+    pub fn with_settings(mut self, settings: SettingsSchema, config: Json) -> Self {
+        self.settings = settings;
+        self.config = config;
+        self
+    }
 }
 
 /// ThemeRuntime manages a single theme.
@@ -67,15 +82,27 @@ pub struct ThemeRuntime<E: JsEngine> {
     /// Configured theme id (from TOML / discovery).
     configured_id: String,
 
-    /// Display name (not used internally).
-    _name: String,
+    /// The spec it was built from (display name, source).
+    spec: ThemeSpec,
+
+    /// Stored settings with defaults filled in: `ctx.config`.
+    config: Json,
+
+    /// The context `init` last ran with.
+    init_ctx: Option<RequestContext>,
+
+    /// Globals as of load or the last `init`; `reset_globals` returns to this.
+    baseline: GlobalShape,
 }
 
 impl<E: JsEngine> ThemeRuntime<E> {
     #[tracing::instrument(skip_all)]
     pub fn new(mut engine: E, spec: ThemeSpec) -> Result<Self, RuntimeError> {
-        let configured_id = spec.id;
+        let configured_id = spec.id.clone();
         let internal_id = format!("theme_{}", Uuid::new_v4().simple());
+        let config = spec.settings.resolve(&spec.config).map_err(|e| {
+            RuntimeError::theme_bootstrap(format!("theme '{configured_id}' settings: {e}"))
+        })?;
 
         // 1) host prelude: defines registerTheme(...)
         let prelude = build_theme_prelude(&internal_id, &configured_id);
@@ -86,19 +113,67 @@ impl<E: JsEngine> ThemeRuntime<E> {
 
         // 3) ctx shim
         engine.load_module("__ctx_shim__", CTX_SHIM_SRC)?;
+        let baseline = global_shape(&mut engine)?;
 
         Ok(Self {
             engine,
             internal_id,
             configured_id,
-            _name: spec.name,
+            spec,
+            config,
+            init_ctx: None,
+            baseline,
         })
     }
 
+    pub fn spec(&self) -> &ThemeSpec {
+        &self.spec
+    }
+
+    pub fn engine(&self) -> &E {
+        &self.engine
+    }
+
+    /// The context `init` last ran with, if it has run.
+    pub fn init_ctx(&self) -> Option<&RequestContext> {
+        self.init_ctx.as_ref()
+    }
+
+    /// Return the engine's globals to their state after load or the last
+    /// `init`.
+    pub fn reset_globals(&mut self) -> Result<(), RuntimeError> {
+        reset_globals(&mut self.engine, &self.baseline)
+    }
+
+    /// `ctx` as JS sees it. A config the host put on the request wins over
+    /// the stored one.
+    fn js_ctx(&self, ctx: &RequestContext) -> JsValue {
+        let host_config = match &ctx.theme_config {
+            Json::Null => false,
+            Json::Object(map) => !map.is_empty(),
+            _ => true,
+        };
+        let config = if host_config {
+            &ctx.theme_config
+        } else {
+            &self.config
+        };
+        ctx_to_js_with_config(ctx, config, &self.configured_id)
+    }
+
+    /// Tag the theme's `log` events with it and `ctx`'s request until the
+    /// next call.
+    fn set_log_scope(&mut self, ctx: &RequestContext) {
+        let req_id = ctx.req_id.as_str().unwrap_or_default();
+        self.engine
+            .set_log_scope(Some(LogScope::theme(self.configured_id.as_str(), req_id)));
+    }
+
     /// Optionally call `init(ctx)` once.
     #[tracing::instrument(skip_all)]
     pub fn init(&mut self, ctx: &RequestContext) -> Result<(), RuntimeError> {
-        let js_ctx = ctx_to_js_for_theme(ctx, &self.configured_id);
+        self.set_log_scope(ctx);
+        let js_ctx = self.js_ctx(ctx);
 
         let js_ctx = self.engine.call_function("__wrapCtx", &[js_ctx])?;
 
@@ -115,6 +190,9 @@ impl<E: JsEngine> ThemeRuntime<E> {
                 Err(err)
             })?;
 
+        // Whatever init put on globalThis is part of the warm state.
+        self.init_ctx = Some(ctx.clone());
+        self.baseline = global_shape(&mut self.engine)?;
         Ok(())
     }
 
@@ -125,7 +203,8 @@ impl<E: JsEngine> ThemeRuntime<E> {
             "Before Handling theme {} with context {}",
             self.internal_id, ctx.req_id
         );
-        let js_ctx = ctx_to_js_for_theme(ctx, &self.configured_id);
+        self.set_log_scope(ctx);
+        let js_ctx = self.js_ctx(ctx);
 
         let js_ctx = self.engine.call_function("__wrapCtx", &[js_ctx])?;
 
@@ -1,11 +1,13 @@
 // crates/adapt/src/runtime/theme_actor.rs
 
-use crate::js::engine::BoaEngine;
-use crate::runtime::bootstrap::BoundTheme;
+use crate::js::engine::{BoaEngine, EngineEnv};
+use crate::runtime::bootstrap::{build_bound_theme, BoundTheme, ThemeConfig};
 use crate::runtime::error::RuntimeError;
+use crate::runtime::pool::EnginePool;
 use serve::render::http::{RequestContext, ResponseBodySpec};
 use std::collections::HashMap;
 use tokio::sync::{mpsc, oneshot};
+use tracing::{info_span, Span};
 
 /// Commands handled by the theme actor.
 enum ThemeCommand {
@@ -15,13 +17,22 @@ enum ThemeCommand {
         reply: oneshot::Sender<Result<(), RuntimeError>>,
     },
 
-    /// Render using a specific theme id.
+    /// Render using a specific theme id, inside `span` (the caller's trace).
     Render {
         theme_id: String,
         ctx: RequestContext,
+        span: Span,
         reply: oneshot::Sender<Result<ResponseBodySpec, RuntimeError>>,
     },
 
+    /// Build and init a theme from `cfg`, then add it or replace the theme
+    /// with the same id. On failure the current theme is kept.
+    ReloadTheme {
+        cfg: ThemeConfig,
+        ctx: RequestContext,
+        reply: oneshot::Sender<Result<(), RuntimeError>>,
+    },
+
     /// Stop the actor loop.
     Shutdown,
 }
@@ -38,12 +49,12 @@ impl ThemeRuntimeClient {
     /// Spawn the theme actor on its own dedicated thread.
     ///
     /// The `BoundTheme<BoaEngine>` values (and all Boa contexts) live only
-    /// on that thread.
-    pub fn spawn(themes: Vec<BoundTheme<BoaEngine>>) -> Self {
+    /// on that thread. Themes reloaded later get engines in `env`.
+    pub fn spawn(themes: Vec<BoundTheme<BoaEngine>>, env: EngineEnv) -> Self {
         let (tx, rx) = mpsc::unbounded_channel::<ThemeCommand>();
 
         tokio::task::spawn_local(async move {
-            theme_actor_loop(themes, rx).await;
+            theme_actor_loop(themes, env, rx).await;
         });
 
         Self { tx }
@@ -77,6 +88,7 @@ impl ThemeRuntimeClient {
             .send(ThemeCommand::Render {
                 theme_id: theme_id.to_string(),
                 ctx,
+                span: info_span!("theme.render", theme_id),
                 reply: reply_tx,
             })
             .map_err(|_| channel_error("theme actor terminated before render"))?;
@@ -86,10 +98,36 @@ impl ThemeRuntimeClient {
             .map_err(|_| channel_error("theme actor dropped render reply"))?
     }
 
+    /// Load (or reload) a single theme without touching the others.
+    pub async fn reload_theme(
+        &self,
+        cfg: ThemeConfig,
+        ctx: RequestContext,
+    ) -> Result<(), RuntimeError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+
+        self.tx
+            .send(ThemeCommand::ReloadTheme {
+                cfg,
+                ctx,
+                reply: reply_tx,
+            })
+            .map_err(|_| channel_error("theme actor terminated before reload_theme"))?;
+
+        reply_rx
+            .await
+            .map_err(|_| channel_error("theme actor dropped reload_theme reply"))?
+    }
+
     /// Fire-and-forget shutdown signal.
     pub fn stop(&self) {
         let _ = self.tx.send(ThemeCommand::Shutdown);
     }
+
+    /// Whether the actor loop is still running to take commands.
+    pub fn is_alive(&self) -> bool {
+        !self.tx.is_closed()
+    }
 }
 
 fn channel_error(msg: &str) -> RuntimeError {
@@ -98,17 +136,18 @@ fn channel_error(msg: &str) -> RuntimeError {
 
 async fn theme_actor_loop(
     themes: Vec<BoundTheme<BoaEngine>>,
+    env: EngineEnv,
     mut rx: mpsc::UnboundedReceiver<ThemeCommand>,
 ) {
-    // Map: theme_id → BoundTheme
+    // Map: theme_id → pool of warm BoundThemes
     //
-    // We keep themes mutable because `BoundTheme::render` takes `&mut self`
-    // (engine is mutated while executing JS).
-    let mut themes_by_id: HashMap<String, BoundTheme<BoaEngine>> = themes
+    // Each render borrows a theme from its pool, which resets the engine's
+    // globals afterwards (see runtime::pool).
+    let mut themes_by_id: HashMap<String, EnginePool<BoundTheme<BoaEngine>>> = themes
         .into_iter()
         .map(|t| {
             let id = t.id().to_string();
-            (id, t)
+            (id, EnginePool::new(t))
         })
         .collect();
 
@@ -116,8 +155,9 @@ async fn theme_actor_loop(
         match cmd {
             ThemeCommand::InitAll { ctx, reply } => {
                 let res = (|| {
-                    for theme in themes_by_id.values_mut() {
-                        theme.init(&ctx)?;
+                    for pool in themes_by_id.values_mut() {
+                        pool.run(|theme| theme.init(&ctx))?;
+                        pool.clear_spares();
                     }
                     Ok::<_, RuntimeError>(())
                 })();
@@ -128,15 +168,26 @@ async fn theme_actor_loop(
             ThemeCommand::Render {
                 theme_id,
                 ctx,
+                span,
                 reply,
             } => {
-                let res = (|| {
-                    let theme = themes_by_id.get_mut(&theme_id).ok_or_else(|| {
+                let res = span.in_scope(|| {
+                    let pool = themes_by_id.get_mut(&theme_id).ok_or_else(|| {
                         RuntimeError::ThemeBootstrap(format!("unknown theme id: {theme_id}"))
                     })?;
 
-                    theme.render(ctx)
-                })();
+                    pool.run(|theme| theme.render(ctx))
+                });
+
+                let _ = reply.send(res);
+            }
+
+            ThemeCommand::ReloadTheme { cfg, ctx, reply } => {
+                let res = build_bound_theme(&cfg, env.clone()).and_then(|mut theme| {
+                    theme.init(&ctx)?;
+                    themes_by_id.insert(cfg.id.clone(), EnginePool::new(theme));
+                    Ok(())
+                });
 
                 let _ = reply.send(res);
             }
@@ -145,6 +196,12 @@ async fn theme_actor_loop(
                 break;
             }
         }
+
+        if rx.is_empty() {
+            for pool in themes_by_id.values_mut() {
+                pool.top_up();
+            }
+        }
     }
 }
 
@@ -227,7 +284,7 @@ mod tests {
                 let client = ThemeRuntimeClient { tx: tx.clone() };
 
                 // Spawn the actor loop with an empty Vec<BoundTheme<BoaEngine>>
-                tokio::task::spawn_local(theme_actor_loop(Vec::new(), rx));
+                tokio::task::spawn_local(theme_actor_loop(Vec::new(), EngineEnv::default(), rx));
 
                 let res = client.init_all(dummy_ctx()).await;
                 assert!(res.is_ok(), "init_all should succeed with no themes");
@@ -274,7 +331,7 @@ mod tests {
                 let client = ThemeRuntimeClient { tx: tx.clone() };
 
                 // Actor with no themes available
-                tokio::task::spawn_local(theme_actor_loop(Vec::new(), rx));
+                tokio::task::spawn_local(theme_actor_loop(Vec::new(), EngineEnv::default(), rx));
 
                 let res = client.render("missing-theme", dummy_ctx()).await;
                 match res {
@@ -293,6 +350,44 @@ mod tests {
             .await;
     }
 
+    // -------------------------------------------------------------------------
+    // ThemeRuntimeClient::reload_theme tests
+    // -------------------------------------------------------------------------
+
+    // A theme whose JS fails to evaluate is rejected and never registered.
+    #[tokio::test(flavor = "current_thread")]
+    async fn reload_theme_with_broken_source_is_not_registered() {
+        let local = LocalSet::new();
+
+        local
+            .run_until(async {
+                let client = ThemeRuntimeClient::spawn(Vec::new(), EngineEnv::default());
+
+                let cfg = ThemeConfig {
+                    id: "broken".into(),
+                    name: "Broken".into(),
+                    mount_path: "/".into(),
+                    source: "this is not ( valid js".into(),
+                    settings: Default::default(),
+                    config: Default::default(),
+                };
+
+                let res = client.reload_theme(cfg, dummy_ctx()).await;
+                assert!(res.is_err(), "broken theme source should fail to load");
+
+                match client.render("broken", dummy_ctx()).await {
+                    Err(RuntimeError::ThemeBootstrap(msg)) => {
+                        assert!(msg.contains("unknown theme id: broken"), "got {msg}");
+                    }
+                    Ok(_) => panic!("broken theme must not be registered"),
+                    Err(other) => panic!("unexpected error variant: {other:?}"),
+                }
+
+                client.stop();
+            })
+            .await;
+    }
+
     // -------------------------------------------------------------------------
     // ThemeRuntimeClient::spawn / stop tests
     // -------------------------------------------------------------------------
@@ -306,7 +401,7 @@ mod tests {
         local
             .run_until(async {
                 // This uses the real spawn, which internally calls spawn_local.
-                let client = ThemeRuntimeClient::spawn(Vec::new());
+                let client = ThemeRuntimeClient::spawn(Vec::new(), EngineEnv::default());
 
                 // init_all should behave as a no-op on empty theme set.
                 let init_res = client.init_all(dummy_ctx()).await;
@@ -343,7 +438,11 @@ mod tests {
                 let (tx, rx) = mpsc::unbounded_channel::<ThemeCommand>();
                 let client = ThemeRuntimeClient { tx: tx.clone() };
 
-                let handle = tokio::task::spawn_local(theme_actor_loop(Vec::new(), rx));
+                let handle = tokio::task::spawn_local(theme_actor_loop(
+                    Vec::new(),
+                    EngineEnv::default(),
+                    rx,
+                ));
 
                 // Send a shutdown signal and then drop the client/tx.
                 client.stop();
@@ -0,0 +1,22 @@
+// crates/domain/src/author.rs
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A person documents can be attributed to via `author.author` and
+/// `author.co_authors`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Author {
+    /// URL-safe identifier, e.g. `ada` for `/author/ada`.
+    pub slug: String,
+    /// Name shown on pages and in feeds.
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bio: Option<String>,
+    /// Avatar image URL or site path.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avatar: Option<String>,
+    /// Profile links by network, e.g. `github = "https://github.com/ada"`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub links: BTreeMap<String, String>,
+}
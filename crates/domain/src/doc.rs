@@ -22,6 +22,8 @@ pub enum BodyKind {
     Plain,
     ReStructuredText,
     OrgMode,
+    /// A JSON array of typed blocks (`.blocks` files).
+    Blocks,
 }
 
 // ─────────────────────────────────────────────────────────────────────────────
@@ -1,3 +1,5 @@
+pub mod author;
 pub mod content;
 pub mod doc;
+pub mod hex;
 pub mod setting;
@@ -1,9 +1,55 @@
-use serde::Deserialize;
-use std::{net::IpAddr, path::PathBuf};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, net::IpAddr, path::PathBuf};
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct CertSettings {
     pub dir: PathBuf,
+
+    /// Where the public HTTPS listener's certificate comes from
+    #[serde(default)]
+    pub mode: TlsMode,
+
+    /// Required when `mode = "acme"`
+    pub acme: Option<AcmeSettings>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TlsMode {
+    /// No public listeners; a reverse proxy in front talks to the loopback
+    /// web server
+    Off,
+    /// A pem/crt and key pair placed in `dir`
+    #[default]
+    Manual,
+    /// Issued and renewed automatically over ACME (HTTP-01), kept in `dir`
+    Acme,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AcmeSettings {
+    /// Names on the certificate; each must resolve to this server
+    pub domains: Vec<String>,
+
+    /// Contact for expiry and policy notices from the CA
+    pub email: Option<String>,
+
+    /// ACME directory URL; defaults to Let's Encrypt production
+    #[serde(default = "default_acme_directory")]
+    pub directory: String,
+
+    /// Renew once the certificate expires within this many days
+    #[serde(default = "default_acme_renew_days")]
+    pub renew_days: u32,
+}
+
+fn default_acme_directory() -> String {
+    "https://acme-v02.api.letsencrypt.org/directory".to_owned()
+}
+
+fn default_acme_renew_days() -> u32 {
+    30
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -16,6 +62,68 @@ pub struct EdgeSettings {
 
     /// Public HTTPS port (proxy + TLS)
     pub https_port: u16,
+
+    /// Offer HTTP/2 (ALPN `h2`) on the HTTPS port
+    #[serde(default = "default_http2")]
+    pub http2: bool,
+
+    /// How long an idle connection is kept open for the next request
+    #[serde(default = "default_keep_alive_secs")]
+    pub keep_alive_secs: u64,
+
+    /// How long a client has to send a request's headers
+    #[serde(default = "default_request_timeout_secs")]
+    pub request_timeout_secs: u64,
+
+    /// On shutdown, how long in-flight requests get to finish once new
+    /// connections are refused
+    #[serde(default = "default_drain_secs")]
+    pub drain_secs: u64,
+
+    /// Largest request body a theme or plugin route accepts and hands to
+    /// plugins (`ctx.request.body`); larger requests get 413
+    #[serde(default = "default_max_body_bytes")]
+    pub max_body_bytes: usize,
+
+    /// Keep the `X-Request-Id` the proxy in front sets instead of assigning
+    /// a new one
+    #[serde(default)]
+    pub trust_request_id: bool,
+
+    /// Take the client's address from the `Forwarded`/`X-Forwarded-For` the
+    /// proxy in front sets instead of from the connection
+    #[serde(default)]
+    pub trust_forwarded: bool,
+
+    /// Show what went wrong on error pages; for development only
+    #[serde(default)]
+    pub dev: bool,
+
+    /// Host names this site answers when one process serves several
+    /// (`whispercms start --site`); requests for any other host go to the
+    /// first site
+    #[serde(default)]
+    pub hosts: Vec<String>,
+}
+
+fn default_http2() -> bool {
+    true
+}
+
+fn default_keep_alive_secs() -> u64 {
+    5
+}
+
+fn default_request_timeout_secs() -> u64 {
+    5
+}
+
+fn default_drain_secs() -> u64 {
+    30
+}
+
+fn default_max_body_bytes() -> usize {
+    1024 * 1024
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -31,6 +139,29 @@ pub struct LoopbackSettings {
 #[derive(Debug, Clone, Deserialize)]
 pub struct ExtensionSettings {
     pub dir: PathBuf,
+
+    /// What startup does when plugin or theme files differ from
+    /// `extensions.lock`
+    #[serde(default)]
+    pub integrity: IntegrityMode,
+
+    /// Most verbose `log` level by plugin or theme id, `"*"` for the rest
+    /// (off, error, warn, info, debug or trace; info if unset)
+    #[serde(default)]
+    pub log: BTreeMap<String, String>,
+}
+
+/// Response to extension files changed since they were locked.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IntegrityMode {
+    /// Do not check
+    Off,
+    /// Log each changed file and start anyway
+    #[default]
+    Warn,
+    /// Refuse to start
+    Refuse,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -40,6 +171,990 @@ pub struct ContentSettings {
     pub index_dir: Option<PathBuf>,
 }
 
+/// OTLP trace export; absent means spans are only logged.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TelemetrySettings {
+    /// OTLP/gRPC collector endpoint, e.g. `http://localhost:4317`
+    pub endpoint: String,
+
+    /// Fraction of new traces to keep, 0.0–1.0
+    #[serde(default = "default_sample_rate")]
+    pub sample_rate: f64,
+
+    /// `service.name` reported with every span
+    #[serde(default = "default_service_name")]
+    pub service_name: String,
+}
+
+fn default_sample_rate() -> f64 {
+    1.0
+}
+
+fn default_service_name() -> String {
+    "whispercms".to_owned()
+}
+
+/// Rendered-response cache; absent means every request is rendered.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CacheSettings {
+    /// Most responses kept in memory
+    #[serde(default = "default_cache_entries")]
+    pub max_entries: usize,
+
+    /// Most body bytes kept in memory
+    #[serde(default = "default_cache_bytes")]
+    pub max_bytes: usize,
+
+    /// Optional second tier on disk, relative to the site directory
+    pub disk_dir: Option<PathBuf>,
+
+    /// Most bytes kept in the disk tier; the least recently used files go
+    /// first
+    #[serde(default = "default_cache_disk_bytes")]
+    pub disk_max_bytes: u64,
+
+    /// Query parameters that may select a cached page; a request with any
+    /// other is rendered but not stored
+    #[serde(default = "default_cache_query_params")]
+    pub query_params: Vec<String>,
+
+    /// Request headers that select between variants of a response
+    #[serde(default = "default_cache_vary")]
+    pub vary: Vec<String>,
+
+    /// Milliseconds a miss waits for an identical render already in
+    /// progress before rendering itself; 0 renders every miss
+    #[serde(default = "default_cache_coalesce_wait_ms")]
+    pub coalesce_wait_ms: u64,
+
+    /// Bearer token that may purge without a login, for deploy scripts
+    pub purge_token: Option<String>,
+}
+
+fn default_cache_entries() -> usize {
+    1024
+}
+
+fn default_cache_bytes() -> usize {
+    64 * 1024 * 1024
+}
+
+fn default_cache_disk_bytes() -> u64 {
+    512 * 1024 * 1024
+}
+
+fn default_cache_query_params() -> Vec<String> {
+    vec!["page".to_owned()]
+}
+
+fn default_cache_vary() -> Vec<String> {
+    vec!["accept".to_owned(), "accept-language".to_owned()]
+}
+
+fn default_cache_coalesce_wait_ms() -> u64 {
+    5_000
+}
+
+/// `/sitemap.xml` and `/robots.txt`; both are served with defaults when the
+/// table is absent.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SitemapSettings {
+    /// Absolute site URL used in `<loc>`, e.g. `https://example.com`;
+    /// defaults to the scheme and host of the request. When set, requests
+    /// for another host, or over http when this is https, are redirected
+    /// to it (the `canonical` middleware layer)
+    pub base_url: Option<String>,
+
+    #[serde(default)]
+    pub robots: RobotsSettings,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RobotsSettings {
+    #[serde(default = "default_robots_user_agent")]
+    pub user_agent: String,
+
+    #[serde(default)]
+    pub allow: Vec<String>,
+
+    #[serde(default)]
+    pub disallow: Vec<String>,
+
+    /// Lines appended verbatim, for directives not covered above
+    #[serde(default)]
+    pub extra: Vec<String>,
+}
+
+impl Default for RobotsSettings {
+    fn default() -> Self {
+        Self {
+            user_agent: default_robots_user_agent(),
+            allow: Vec::new(),
+            disallow: Vec::new(),
+            extra: Vec::new(),
+        }
+    }
+}
+
+fn default_robots_user_agent() -> String {
+    "*".to_owned()
+}
+
+/// RSS/Atom/JSON feeds; served with defaults when the table is absent. Item
+/// URLs use `[sitemap] base_url`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct FeedSettings {
+    /// Feed title; defaults to the site URL
+    pub title: Option<String>,
+
+    /// Most items per feed
+    #[serde(default = "default_feed_items")]
+    pub items: usize,
+
+    /// Include each document's full HTML instead of a text summary
+    #[serde(default)]
+    pub full_content: bool,
+
+    /// Longest summary, in characters
+    #[serde(default = "default_feed_summary_chars")]
+    pub summary_chars: usize,
+
+    /// Only list documents in this `i18n.lang` unless `?lang=` asks for
+    /// another
+    pub lang: Option<String>,
+}
+
+impl Default for FeedSettings {
+    fn default() -> Self {
+        Self {
+            title: None,
+            items: default_feed_items(),
+            full_content: false,
+            summary_chars: default_feed_summary_chars(),
+            lang: None,
+        }
+    }
+}
+
+fn default_feed_items() -> usize {
+    20
+}
+
+fn default_feed_summary_chars() -> usize {
+    280
+}
+
+/// Author entities and their `/author/{slug}` pages; served with defaults
+/// when the table is absent.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AuthorSettings {
+    /// Author data file, relative to the site directory
+    #[serde(default = "default_authors_file")]
+    pub file: PathBuf,
+
+    /// Theme template for author pages; a built-in page is used when the
+    /// theme has none
+    #[serde(default = "default_author_template")]
+    pub template: String,
+}
+
+impl Default for AuthorSettings {
+    fn default() -> Self {
+        Self {
+            file: default_authors_file(),
+            template: default_author_template(),
+        }
+    }
+}
+
+fn default_authors_file() -> PathBuf {
+    PathBuf::from("authors.toml")
+}
+
+fn default_author_template() -> String {
+    "author.hbs".to_owned()
+}
+
+/// `/tags/{tag}`, `/categories/{category}` and `/series/{series}` archive
+/// pages; served with defaults when the table is absent.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TaxonomySettings {
+    /// Documents on one archive page
+    #[serde(default = "default_taxonomy_per_page")]
+    pub per_page: usize,
+
+    /// Theme template for archive pages; a built-in page is used when the
+    /// theme has none
+    #[serde(default = "default_taxonomy_template")]
+    pub template: String,
+}
+
+impl Default for TaxonomySettings {
+    fn default() -> Self {
+        Self {
+            per_page: default_taxonomy_per_page(),
+            template: default_taxonomy_template(),
+        }
+    }
+}
+
+fn default_taxonomy_per_page() -> usize {
+    10
+}
+
+fn default_taxonomy_template() -> String {
+    "taxonomy.hbs".to_owned()
+}
+
+/// Related posts offered to templates as `related`; computed with defaults
+/// when the table is absent.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RelatedSettings {
+    /// Most related posts per document
+    #[serde(default = "default_related_limit")]
+    pub limit: usize,
+
+    /// Score of each shared tag
+    #[serde(default = "default_tag_weight")]
+    pub tag_weight: f64,
+
+    /// Score of each shared category
+    #[serde(default = "default_category_weight")]
+    pub category_weight: f64,
+
+    /// Days after which a post's score counts half; 0 turns decay off
+    #[serde(default = "default_half_life_days")]
+    pub half_life_days: f64,
+}
+
+impl Default for RelatedSettings {
+    fn default() -> Self {
+        Self {
+            limit: default_related_limit(),
+            tag_weight: default_tag_weight(),
+            category_weight: default_category_weight(),
+            half_life_days: default_half_life_days(),
+        }
+    }
+}
+
+fn default_related_limit() -> usize {
+    5
+}
+
+fn default_tag_weight() -> f64 {
+    1.0
+}
+
+fn default_category_weight() -> f64 {
+    2.0
+}
+
+fn default_half_life_days() -> f64 {
+    365.0
+}
+
+/// The `/search` page; served with defaults when the table is absent.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SearchSettings {
+    /// Results on one page
+    #[serde(default = "default_search_per_page")]
+    pub per_page: usize,
+
+    /// Characters of body text shown around the first match
+    #[serde(default = "default_snippet_chars")]
+    pub snippet_chars: usize,
+
+    /// Theme template for results; a built-in page is used when the theme
+    /// has none
+    #[serde(default = "default_search_template")]
+    pub template: String,
+}
+
+impl Default for SearchSettings {
+    fn default() -> Self {
+        Self {
+            per_page: default_search_per_page(),
+            snippet_chars: default_snippet_chars(),
+            template: default_search_template(),
+        }
+    }
+}
+
+fn default_search_per_page() -> usize {
+    10
+}
+
+fn default_snippet_chars() -> usize {
+    160
+}
+
+fn default_search_template() -> String {
+    "search.hbs".to_owned()
+}
+
+/// Scheduled maintenance; every request inside a window is answered with a
+/// 503 page.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct MaintenanceSettings {
+    #[serde(default)]
+    pub windows: Vec<MaintenanceWindow>,
+
+    /// In maintenance until this is turned off again, whatever the windows
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Shown on the 503 page while `enabled`
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct MaintenanceWindow {
+    /// Quoted RFC 3339, e.g. `"2026-11-01T02:00:00Z"`; bare TOML datetimes
+    /// are not accepted
+    pub start: DateTime<Utc>,
+
+    /// Quoted RFC 3339; the window is over at this instant
+    pub end: DateTime<Utc>,
+
+    /// Shown on the 503 page
+    pub message: Option<String>,
+}
+
+/// Resized and transcoded images under `/media`; served with defaults when
+/// the table is absent.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ImageSettings {
+    /// Originals, relative to the site directory
+    #[serde(default = "default_media_dir")]
+    pub media_dir: PathBuf,
+
+    /// Generated derivatives, relative to the site directory
+    #[serde(default = "default_image_cache_dir")]
+    pub cache_dir: PathBuf,
+
+    /// Sizes `?w=` and `?h=` may ask for; an empty list turns resizing off
+    #[serde(default = "default_image_widths")]
+    pub widths: Vec<u32>,
+
+    /// Largest width or height of a derivative, in pixels
+    #[serde(default = "default_image_max_dimension")]
+    pub max_dimension: u32,
+
+    /// JPEG and AVIF quality, 1–100
+    #[serde(default = "default_image_quality")]
+    pub quality: u8,
+
+    /// Add `loading="lazy"` and `decoding="async"` to rendered `<img>` tags
+    #[serde(default = "default_lazy_loading")]
+    pub lazy_loading: bool,
+
+    /// How many images at the top of a page stay eager, so the likely
+    /// largest contentful paint is not delayed
+    #[serde(default = "default_eager_images")]
+    pub eager_images: usize,
+
+    /// Add a `srcset` of `widths` derivatives to local images
+    #[serde(default)]
+    pub srcset: bool,
+}
+
+impl Default for ImageSettings {
+    fn default() -> Self {
+        Self {
+            media_dir: default_media_dir(),
+            cache_dir: default_image_cache_dir(),
+            widths: default_image_widths(),
+            max_dimension: default_image_max_dimension(),
+            quality: default_image_quality(),
+            lazy_loading: default_lazy_loading(),
+            eager_images: default_eager_images(),
+            srcset: false,
+        }
+    }
+}
+
+fn default_media_dir() -> PathBuf {
+    PathBuf::from("media")
+}
+
+fn default_image_cache_dir() -> PathBuf {
+    PathBuf::from("cache/images")
+}
+
+fn default_image_widths() -> Vec<u32> {
+    vec![320, 640, 1280]
+}
+
+fn default_image_max_dimension() -> u32 {
+    4096
+}
+
+fn default_image_quality() -> u8 {
+    82
+}
+
+fn default_lazy_loading() -> bool {
+    true
+}
+
+fn default_eager_images() -> usize {
+    1
+}
+
+/// The media library's upload endpoint, `POST /admin/media`. Uploads need
+/// this token or a login with at least the author role.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MediaSettings {
+    /// Bearer token that may upload without a login
+    pub upload_token: Option<String>,
+
+    /// Largest accepted file, in bytes
+    #[serde(default = "default_max_upload_bytes")]
+    pub max_upload_bytes: usize,
+}
+
+impl Default for MediaSettings {
+    fn default() -> Self {
+        Self {
+            upload_token: None,
+            max_upload_bytes: default_max_upload_bytes(),
+        }
+    }
+}
+
+fn default_max_upload_bytes() -> usize {
+    20 * 1024 * 1024
+}
+
+/// Login sessions of user accounts (`whispercms user`).
+#[derive(Debug, Clone, Deserialize)]
+pub struct AuthSettings {
+    /// How long a login lasts, in hours
+    #[serde(default = "default_session_hours")]
+    pub session_hours: u32,
+
+    /// Send the session cookie over HTTPS only; turn off for plain-HTTP
+    /// development
+    #[serde(default = "default_secure_cookie")]
+    pub secure_cookie: bool,
+
+    /// How long an emailed password reset code lasts, in minutes
+    #[serde(default = "default_reset_minutes")]
+    pub reset_minutes: u32,
+
+    /// Refuse admins the admin routes until they turn on two-factor login
+    #[serde(default)]
+    pub require_two_factor: bool,
+}
+
+impl Default for AuthSettings {
+    fn default() -> Self {
+        Self {
+            session_hours: default_session_hours(),
+            secure_cookie: default_secure_cookie(),
+            reset_minutes: default_reset_minutes(),
+            require_two_factor: false,
+        }
+    }
+}
+
+fn default_session_hours() -> u32 {
+    14 * 24
+}
+
+fn default_secure_cookie() -> bool {
+    true
+}
+
+fn default_reset_minutes() -> u32 {
+    60
+}
+
+/// The installed-components registry (`/admin/components`,
+/// `whispercms components list`).
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ComponentSettings {
+    /// TOML file naming the latest release of each component, kept in sync
+    /// with wherever the site gets its plugins and themes
+    pub updates_file: Option<PathBuf>,
+
+    /// SPDX expression the site is distributed under; copyleft components
+    /// are flagged when it is permissive
+    pub license: Option<String>,
+
+    /// SPDX identifiers components may use; empty accepts any
+    #[serde(default)]
+    pub allowed_licenses: Vec<String>,
+}
+
+/// Per-tenant request limits. A tenant is the request's host name; the
+/// top-level values apply to every tenant without its own
+/// `[quotas.tenants."<host>"]` table.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct QuotaSettings {
+    #[serde(flatten)]
+    pub default: TenantQuota,
+
+    #[serde(default)]
+    pub tenants: BTreeMap<String, TenantQuota>,
+}
+
+impl QuotaSettings {
+    /// The limits for `host`: its own table's values, falling back to the
+    /// defaults field by field.
+    pub fn for_tenant(&self, host: &str) -> TenantQuota {
+        match self.tenants.get(host) {
+            Some(own) => TenantQuota {
+                requests_per_sec: own.requests_per_sec.or(self.default.requests_per_sec),
+                burst: own.burst.or(self.default.burst),
+                concurrent_renders: own.concurrent_renders.or(self.default.concurrent_renders),
+                cache_bytes: own.cache_bytes.or(self.default.cache_bytes),
+                media_bytes_per_sec: own.media_bytes_per_sec.or(self.default.media_bytes_per_sec),
+            },
+            None => self.default.clone(),
+        }
+    }
+}
+
+/// Limits for one tenant; an absent value is unlimited.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct TenantQuota {
+    /// Sustained request rate
+    pub requests_per_sec: Option<u32>,
+
+    /// Requests allowed at once above the sustained rate; defaults to
+    /// `requests_per_sec`
+    pub burst: Option<u32>,
+
+    /// Requests rendered at the same time; later ones wait their turn
+    pub concurrent_renders: Option<usize>,
+
+    /// Body bytes of rendered pages the response cache holds for the tenant
+    pub cache_bytes: Option<usize>,
+
+    /// Sustained rate of `/media` bytes sent; a second's worth may go at once
+    pub media_bytes_per_sec: Option<u64>,
+}
+
+/// One feature flag, from `[flags.<name>]` in `settings.toml` or a plugin's
+/// `plugin.toml`. It is off everywhere when `enabled` is false; otherwise
+/// on for every request when no targeting is given, else for requests that
+/// match any of `percentage`, `cookies` or `roles`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct FlagSettings {
+    #[serde(default = "default_flag_enabled")]
+    pub enabled: bool,
+
+    /// Share of visitors (0-100) who get the flag; the same visitor keeps
+    /// the same answer
+    pub percentage: Option<u8>,
+
+    /// Cookies that turn the flag on: `name` when present, `name=value`
+    /// when it has that value
+    #[serde(default)]
+    pub cookies: Vec<String>,
+
+    /// Roles of signed-in users who get the flag
+    #[serde(default)]
+    pub roles: Vec<String>,
+
+    pub description: Option<String>,
+}
+
+fn default_flag_enabled() -> bool {
+    true
+}
+
+/// Block documents (`.blocks` files); served with the built-in block types
+/// when the table is absent.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BlockSettings {
+    /// Folder of `<type>.hbs` block templates, relative to the site
+    /// directory; a template replaces the built-in rendering of its type
+    #[serde(default = "default_block_templates")]
+    pub templates: PathBuf,
+
+    /// Site block types by name, on top of the built-in `paragraph`,
+    /// `image`, `embed` and `code`; each needs a template
+    #[serde(default)]
+    pub types: BTreeMap<String, BlockTypeSettings>,
+}
+
+impl Default for BlockSettings {
+    fn default() -> Self {
+        Self {
+            templates: default_block_templates(),
+            types: BTreeMap::new(),
+        }
+    }
+}
+
+fn default_block_templates() -> PathBuf {
+    PathBuf::from("blocks")
+}
+
+/// Fields of one block type, by name, as `"string"`, `"number"`,
+/// `"boolean"`, `"array"`, `"object"` or `"any"`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct BlockTypeSettings {
+    #[serde(default)]
+    pub required: BTreeMap<String, String>,
+
+    #[serde(default)]
+    pub optional: BTreeMap<String, String>,
+}
+
+/// First-party comments; the public submit endpoint exists only when the
+/// table is present.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct CommentSettings {
+    /// Submissions each client address may make per minute
+    #[serde(default = "default_comments_per_minute")]
+    pub per_minute: u32,
+
+    /// Longest comment body, in characters
+    #[serde(default = "default_comment_chars")]
+    pub max_chars: usize,
+
+    /// Addresses told about each comment awaiting moderation
+    #[serde(default)]
+    pub notify: Vec<String>,
+}
+
+impl Default for CommentSettings {
+    fn default() -> Self {
+        Self {
+            per_minute: default_comments_per_minute(),
+            max_chars: default_comment_chars(),
+            notify: Vec::new(),
+        }
+    }
+}
+
+fn default_comments_per_minute() -> u32 {
+    3
+}
+
+fn default_comment_chars() -> usize {
+    5000
+}
+
+/// Outbound email. Mail is queued in the outbox either way; without this
+/// table it waits there undelivered.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct MailSettings {
+    /// Sender address, e.g. `"Blog <blog@example.com>"`
+    pub from: String,
+
+    #[serde(default)]
+    pub transport: MailTransport,
+
+    /// The relay, for the `smtp` transport
+    pub smtp: Option<SmtpSettings>,
+
+    /// Folder the `file` transport writes `.eml` files to, relative to the
+    /// site directory
+    #[serde(default = "default_mail_dir")]
+    pub dir: PathBuf,
+}
+
+fn default_mail_dir() -> PathBuf {
+    PathBuf::from("mail_out")
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MailTransport {
+    /// Send through `[mail.smtp]`
+    #[default]
+    Smtp,
+    /// Write each message to `dir` instead of sending it; for development
+    File,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct SmtpSettings {
+    pub host: String,
+
+    /// 465 for `tls`, 587 for `starttls`, 25 for `none` when absent
+    pub port: Option<u16>,
+
+    #[serde(default)]
+    pub security: SmtpSecurity,
+
+    pub username: Option<String>,
+
+    /// Environment variable holding the password, which so stays out of
+    /// settings.toml
+    pub password_env: Option<String>,
+
+    /// Seconds to wait on the relay before giving up
+    #[serde(default = "default_smtp_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_smtp_timeout_secs() -> u64 {
+    30
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+    /// TLS from the first byte
+    Tls,
+    /// Plain connection upgraded with STARTTLS
+    #[default]
+    Starttls,
+    /// No encryption; only for a relay on the same host
+    None,
+}
+
+/// An endpoint told about content changes, one `[[webhooks]]` entry each.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct WebhookSettings {
+    /// Where the change notices are POSTed
+    pub url: String,
+
+    /// Environment variable holding the HMAC-SHA256 key the payload is
+    /// signed with; unsigned without one
+    pub secret_env: Option<String>,
+
+    /// Extra request headers, e.g. an API token the endpoint expects
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+/// The languages a site is published in. Without this table there is one
+/// language and no language routing.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct I18nSettings {
+    /// Language of documents without `i18n.lang`, e.g. `"en"`
+    pub default: String,
+
+    /// Every other language, e.g. `["de", "fr"]`
+    #[serde(default)]
+    pub languages: Vec<String>,
+
+    #[serde(default)]
+    pub routing: LanguageRouting,
+
+    /// Host per language, for `routing = "domain"`, e.g.
+    /// `{ de = "example.de" }`
+    #[serde(default)]
+    pub domains: BTreeMap<String, String>,
+
+    /// Send readers who have not picked a language to the translation
+    /// their `Accept-Language` prefers
+    #[serde(default = "default_i18n_negotiate")]
+    pub negotiate: bool,
+}
+
+fn default_i18n_negotiate() -> bool {
+    true
+}
+
+/// How a URL names its language.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LanguageRouting {
+    /// The first path segment, `/de/…`; the default language has none
+    #[default]
+    Prefix,
+    /// The host, from `domains`
+    Domain,
+}
+
+/// A site form, posted to `/forms/<name>`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct FormDefinition {
+    pub fields: Vec<FormField>,
+
+    /// Addresses every submission is emailed to, through the outbox
+    #[serde(default)]
+    pub email_to: Vec<String>,
+
+    /// Subject of those emails; the form name when absent
+    pub subject: Option<String>,
+
+    /// Where a browser goes after posting the form; back to the page it
+    /// came from when absent
+    pub redirect: Option<String>,
+
+    /// Submissions each client address may make per minute
+    #[serde(default = "default_form_per_minute")]
+    pub per_minute: u32,
+}
+
+fn default_form_per_minute() -> u32 {
+    5
+}
+
+/// One input of a form.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct FormField {
+    pub name: String,
+
+    pub label: Option<String>,
+
+    #[serde(default)]
+    pub kind: FormFieldKind,
+
+    #[serde(default)]
+    pub required: bool,
+
+    /// Longest value, in characters
+    pub max_chars: Option<usize>,
+
+    /// The values a `select` allows
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub options: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FormFieldKind {
+    #[default]
+    Text,
+    Textarea,
+    Email,
+    Url,
+    Number,
+    Checkbox,
+    Select,
+}
+
+/// The MQL query budget of one theme render or Content API request; an
+/// absent value is unlimited.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct QueryBudgetSettings {
+    /// Documents all the request's queries may load together
+    pub max_scanned: Option<usize>,
+
+    /// Milliseconds the request may spend on queries
+    pub max_time_ms: Option<u64>,
+
+    /// Queries slower than this many milliseconds are logged as warnings
+    pub slow_query_ms: Option<u64>,
+
+    /// Milliseconds a render waits for its template model's queries, run
+    /// together, before going on without the unfinished ones (5000 if unset)
+    pub model_timeout_ms: Option<u64>,
+}
+
+/// What the `security_headers` middleware layer sends.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct SecurityHeaderSettings {
+    /// Sent as `Referrer-Policy`
+    #[serde(default = "default_referrer_policy")]
+    pub referrer_policy: String,
+
+    /// Who may frame the site's pages, as CSP `frame-ancestors` sources;
+    /// `X-Frame-Options` follows when that is just `'self'` or `'none'`
+    #[serde(default = "default_frame_ancestors")]
+    pub frame_ancestors: Vec<String>,
+
+    /// The base `Content-Security-Policy`, sources by directive, e.g.
+    /// `script-src = ["'self'"]`; plugins may add sources, never remove them
+    #[serde(default)]
+    pub csp: BTreeMap<String, Vec<String>>,
+}
+
+impl Default for SecurityHeaderSettings {
+    fn default() -> Self {
+        Self {
+            referrer_policy: default_referrer_policy(),
+            frame_ancestors: default_frame_ancestors(),
+            csp: BTreeMap::new(),
+        }
+    }
+}
+
+fn default_referrer_policy() -> String {
+    "strict-origin-when-cross-origin".to_owned()
+}
+
+fn default_frame_ancestors() -> Vec<String> {
+    vec!["'self'".to_owned()]
+}
+
+/// The request middleware, outermost first.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct MiddlewareSettings {
+    pub stack: Vec<MiddlewareLayer>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MiddlewareLayer {
+    /// Canonical paths and queries; always first
+    Normalize,
+    /// Redirects other hosts, and plain http, to `[sitemap] base_url`
+    Canonical,
+    /// Scheduled and manual maintenance windows
+    Maintenance,
+    /// Per-tenant request rates and render slots
+    Quota,
+    /// The admin audit log
+    Audit,
+    /// gzip, brotli or zstd responses, as the client accepts
+    Compress,
+    /// `X-Content-Type-Options`, `Referrer-Policy`, `X-Frame-Options` and
+    /// the `Content-Security-Policy` of `[security_headers]`
+    SecurityHeaders,
+    /// CSRF tokens on state-changing requests that carry a session
+    Csrf,
+    /// One line per request, as `[access_log]` says
+    AccessLog,
+}
+
+/// What the `access_log` middleware layer writes, and where.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct AccessLogSettings {
+    #[serde(default)]
+    pub format: AccessLogFormat,
+
+    /// File to append to, relative to the site directory; stdout when absent
+    pub path: Option<PathBuf>,
+
+    /// Size at which the file is rotated to `<path>.1`
+    #[serde(default = "default_access_log_max_bytes")]
+    pub max_bytes: u64,
+
+    /// Rotated files kept
+    #[serde(default = "default_access_log_keep")]
+    pub keep: usize,
+}
+
+impl Default for AccessLogSettings {
+    fn default() -> Self {
+        Self {
+            format: AccessLogFormat::default(),
+            path: None,
+            max_bytes: default_access_log_max_bytes(),
+            keep: default_access_log_keep(),
+        }
+    }
+}
+
+fn default_access_log_max_bytes() -> u64 {
+    10 * 1024 * 1024
+}
+
+fn default_access_log_keep() -> usize {
+    5
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessLogFormat {
+    /// Combined log format, then latency and request id
+    #[default]
+    Common,
+    /// One JSON object per line
+    Json,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct Settings {
     pub cert: CertSettings,
@@ -47,4 +1162,34 @@ pub struct Settings {
     pub loopback: LoopbackSettings,
     pub ext: Option<ExtensionSettings>,
     pub content: Option<ContentSettings>,
+    pub telemetry: Option<TelemetrySettings>,
+    pub cache: Option<CacheSettings>,
+    pub sitemap: Option<SitemapSettings>,
+    pub feeds: Option<FeedSettings>,
+    pub authors: Option<AuthorSettings>,
+    pub taxonomies: Option<TaxonomySettings>,
+    pub related: Option<RelatedSettings>,
+    pub search: Option<SearchSettings>,
+    pub maintenance: Option<MaintenanceSettings>,
+    pub quotas: Option<QuotaSettings>,
+    pub images: Option<ImageSettings>,
+    pub media: Option<MediaSettings>,
+    pub auth: Option<AuthSettings>,
+    pub components: Option<ComponentSettings>,
+    /// Site flags by name; they win over plugin flags of the same name
+    pub flags: Option<BTreeMap<String, FlagSettings>>,
+    pub blocks: Option<BlockSettings>,
+    pub query_budget: Option<QueryBudgetSettings>,
+    pub middleware: Option<MiddlewareSettings>,
+    pub security_headers: Option<SecurityHeaderSettings>,
+    pub access_log: Option<AccessLogSettings>,
+    pub comments: Option<CommentSettings>,
+    /// Site forms by name
+    pub forms: Option<BTreeMap<String, FormDefinition>>,
+    pub mail: Option<MailSettings>,
+    /// Endpoints told about content changes
+    pub webhooks: Option<Vec<WebhookSettings>>,
+    /// URL patterns by content type, e.g. `post = "/:year/:month/:slug/"`
+    pub permalinks: Option<BTreeMap<String, String>>,
+    pub i18n: Option<I18nSettings>,
 }
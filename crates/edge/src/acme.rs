@@ -0,0 +1,637 @@
+// crates/edge/src/acme.rs
+
+// Certificates from an ACME CA (Let's Encrypt unless told otherwise), for
+// sites that face the internet without a reverse proxy:
+//
+//   [cert]
+//   dir = "./certs/"
+//   mode = "acme"
+//
+//   [cert.acme]
+//   domains = ["example.com", "www.example.com"]
+//   email = "ops@example.com"
+//
+// At start the certificate kept in `dir` (acme.crt + acme.key) is loaded if
+// there is one, and a background thread issues a new one when there is
+// none, when it does not name every domain, or when it expires within
+// `renew_days`; it then checks again twice a day, and retries hourly after
+// a failure. Issuance uses the HTTP-01 challenge: the CA fetches
+// `/.well-known/acme-challenge/<token>` over port 80, which the edge's HTTP
+// listener answers from `challenge` before redirecting everything else to
+// https. The HTTPS listener asks `CertStore` for the certificate on every
+// handshake, so a renewal applies without a restart.
+//
+// The account key is kept next to the certificate so renewals reuse the
+// account. Requests to the CA go through the same small HTTP client
+// plugins' `fetch` uses.
+
+use crate::db::history::write_atomic;
+use adapt::js::fetch::{FetchError, FetchPolicy, FetchRequest, FetchResponse, Fetcher};
+use adapt::js::HttpFetcher;
+use async_trait::async_trait;
+use domain::setting::AcmeSettings;
+use http::Uri;
+use openssl::base64::encode_block;
+use openssl::bn::BigNumContext;
+use openssl::ec::{EcGroup, EcKey};
+use openssl::ecdsa::EcdsaSig;
+use openssl::error::ErrorStack;
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{PKey, Private};
+use openssl::sha::sha256;
+use openssl::stack::Stack;
+use openssl::x509::extension::SubjectAlternativeName;
+use openssl::x509::{X509NameBuilder, X509ReqBuilder, X509};
+use parking_lot::RwLock;
+use pingora::listeners::TlsAccept;
+use pingora::protocols::tls::TlsRef;
+use pingora::tls::ext::{ssl_add_chain_cert, ssl_use_certificate, ssl_use_private_key};
+use serde::Deserialize;
+use serde_json::{json, Value as Json};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, LazyLock};
+use std::thread;
+use std::time::Duration;
+use thiserror::Error;
+use tracing::{debug, error, info};
+
+/// The issued certificate chain, PEM, inside the cert directory.
+pub const CERT_FILE: &str = "acme.crt";
+
+/// Its private key, PEM.
+pub const KEY_FILE: &str = "acme.key";
+
+/// The ACME account key, PEM.
+pub const ACCOUNT_KEY_FILE: &str = "acme-account.key";
+
+/// Where the CA looks for HTTP-01 answers.
+pub const CHALLENGE_PREFIX: &str = "/.well-known/acme-challenge/";
+
+const CHECK_EVERY: Duration = Duration::from_secs(12 * 60 * 60);
+const RETRY_AFTER: Duration = Duration::from_secs(60 * 60);
+const POLL_EVERY: Duration = Duration::from_secs(2);
+const POLL_ATTEMPTS: u32 = 60;
+
+#[derive(Debug, Error)]
+pub enum AcmeError {
+    #[error("I/O: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("OpenSSL: {0}")]
+    Ssl(#[from] ErrorStack),
+
+    #[error("request to {url} failed: {source}")]
+    Fetch {
+        url: String,
+        #[source]
+        source: FetchError,
+    },
+
+    #[error("{0}")]
+    Protocol(String),
+}
+
+fn protocol(msg: impl Into<String>) -> AcmeError {
+    AcmeError::Protocol(msg.into())
+}
+
+/// Key authorizations for challenges in progress, by token.
+static CHALLENGES: LazyLock<RwLock<HashMap<String, String>>> = LazyLock::new(Default::default);
+
+/// The answer to the HTTP-01 challenge `token`, while one is in progress.
+pub fn challenge(token: &str) -> Option<String> {
+    CHALLENGES.read().get(token).cloned()
+}
+
+/// Removes a published challenge answer when dropped.
+struct Published(String);
+
+impl Published {
+    fn new(token: &str, key_authorization: String) -> Self {
+        CHALLENGES
+            .write()
+            .insert(token.to_owned(), key_authorization);
+        Self(token.to_owned())
+    }
+}
+
+impl Drop for Published {
+    fn drop(&mut self) {
+        CHALLENGES.write().remove(&self.0);
+    }
+}
+
+/// A certificate chain (leaf first) and its key.
+pub struct Certified {
+    pub chain: Vec<X509>,
+    pub key: PKey<Private>,
+}
+
+impl Certified {
+    fn from_pem(cert: &[u8], key: &[u8]) -> Result<Self, AcmeError> {
+        let chain = X509::stack_from_pem(cert)?;
+        if chain.is_empty() {
+            return Err(protocol("the certificate file holds no certificate"));
+        }
+        Ok(Self {
+            chain,
+            key: PKey::private_key_from_pem(key)?,
+        })
+    }
+
+    /// Whether a new certificate is needed: one of `domains` is missing
+    /// from this one, or it expires within `renew_days`.
+    pub fn needs_renewal(&self, domains: &[String], renew_days: u32) -> bool {
+        let leaf = &self.chain[0];
+        let names: Vec<String> = leaf
+            .subject_alt_names()
+            .map(|sans| {
+                sans.iter()
+                    .filter_map(|n| n.dnsname().map(str::to_ascii_lowercase))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let missing = domains
+            .iter()
+            .any(|d| !names.contains(&d.to_ascii_lowercase()));
+        let expiring = openssl::asn1::Asn1Time::days_from_now(renew_days)
+            .map(|threshold| leaf.not_after() <= threshold)
+            .unwrap_or(true);
+        missing || expiring
+    }
+}
+
+/// The certificate the HTTPS listener presents, swapped in place on renewal.
+#[derive(Clone, Default)]
+pub struct CertStore(Arc<RwLock<Option<Arc<Certified>>>>);
+
+impl CertStore {
+    /// A store holding the certificate kept in `dir`, if there is one.
+    pub fn load(dir: &Path) -> Result<Self, AcmeError> {
+        let store = Self::default();
+        match (fs::read(dir.join(CERT_FILE)), fs::read(dir.join(KEY_FILE))) {
+            (Ok(cert), Ok(key)) => store.set(Certified::from_pem(&cert, &key)?),
+            (Err(e), _) | (_, Err(e)) if e.kind() == io::ErrorKind::NotFound => {}
+            (Err(e), _) | (_, Err(e)) => return Err(e.into()),
+        }
+        Ok(store)
+    }
+
+    pub fn current(&self) -> Option<Arc<Certified>> {
+        self.0.read().clone()
+    }
+
+    fn set(&self, certified: Certified) {
+        *self.0.write() = Some(Arc::new(certified));
+    }
+}
+
+#[async_trait]
+impl TlsAccept for CertStore {
+    async fn certificate_callback(&self, ssl: &mut TlsRef) {
+        // Without a certificate yet the handshake fails, which is all a
+        // client can be told before the first issuance completes.
+        let Some(current) = self.current() else {
+            debug!("TLS handshake before any ACME certificate was issued");
+            return;
+        };
+        let used = ssl_use_certificate(ssl, &current.chain[0])
+            .and_then(|()| {
+                current
+                    .chain
+                    .iter()
+                    .skip(1)
+                    .try_for_each(|c| ssl_add_chain_cert(ssl, c))
+            })
+            .and_then(|()| ssl_use_private_key(ssl, &current.key));
+        if let Err(e) = used {
+            error!("Presenting the ACME certificate failed: {}", e);
+        }
+    }
+}
+
+/// Keep the certificate in `dir` current for `settings`, on a thread of its
+/// own, putting each new one in `store`.
+pub fn spawn_renewal(settings: AcmeSettings, dir: std::path::PathBuf, store: CertStore) {
+    thread::spawn(move || loop {
+        let wait = match renew_if_due(&settings, &dir, &store) {
+            Ok(true) => {
+                info!(
+                    "ACME certificate issued for {}",
+                    settings.domains.join(", ")
+                );
+                CHECK_EVERY
+            }
+            Ok(false) => CHECK_EVERY,
+            Err(e) => {
+                error!(
+                    "ACME certificate for {} failed: {}",
+                    settings.domains.join(", "),
+                    e
+                );
+                RETRY_AFTER
+            }
+        };
+        thread::sleep(wait);
+    });
+}
+
+/// Issue a certificate if the stored one needs renewing. `Ok(true)` when
+/// one was issued.
+fn renew_if_due(settings: &AcmeSettings, dir: &Path, store: &CertStore) -> Result<bool, AcmeError> {
+    if store
+        .current()
+        .is_some_and(|c| !c.needs_renewal(&settings.domains, settings.renew_days))
+    {
+        return Ok(false);
+    }
+
+    fs::create_dir_all(dir)?;
+    let account_key = account_key(&dir.join(ACCOUNT_KEY_FILE))?;
+    let mut client = Client::connect(&settings.directory, account_key)?;
+    client.register(settings.email.as_deref())?;
+    let (cert, key) = client.issue(&settings.domains)?;
+    let certified = Certified::from_pem(cert.as_bytes(), &key)?;
+
+    write_atomic(&dir.join(KEY_FILE), &key)?;
+    restrict(&dir.join(KEY_FILE))?;
+    write_atomic(&dir.join(CERT_FILE), cert.as_bytes())?;
+    store.set(certified);
+    Ok(true)
+}
+
+/// The account key at `path`, created on first use.
+fn account_key(path: &Path) -> Result<EcKey<Private>, AcmeError> {
+    match fs::read(path) {
+        Ok(pem) => Ok(EcKey::private_key_from_pem(&pem)?),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let key = new_key()?;
+            write_atomic(path, &key.private_key_to_pem()?)?;
+            restrict(path)?;
+            Ok(key)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn new_key() -> Result<EcKey<Private>, ErrorStack> {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+    EcKey::generate(&group)
+}
+
+/// Private keys are readable by their owner only.
+fn restrict(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+/// Unpadded base64url (RFC 8555 §6.1).
+fn b64u(bytes: &[u8]) -> String {
+    encode_block(bytes)
+        .trim_end_matches('=')
+        .replace('+', "-")
+        .replace('/', "_")
+}
+
+/// The account key as a JWK, members in the order RFC 7638 hashes them.
+fn jwk(key: &EcKey<Private>) -> Result<Json, ErrorStack> {
+    let mut ctx = BigNumContext::new()?;
+    let mut x = openssl::bn::BigNum::new()?;
+    let mut y = openssl::bn::BigNum::new()?;
+    key.public_key()
+        .affine_coordinates(key.group(), &mut x, &mut y, &mut ctx)?;
+    Ok(json!({
+        "crv": "P-256",
+        "kty": "EC",
+        "x": b64u(&x.to_vec_padded(32)?),
+        "y": b64u(&y.to_vec_padded(32)?),
+    }))
+}
+
+/// The RFC 7638 thumbprint of `jwk`, which key authorizations end with.
+fn thumbprint(jwk: &Json) -> String {
+    // serde_json keeps object members sorted, which is the order required.
+    b64u(&sha256(jwk.to_string().as_bytes()))
+}
+
+/// A flattened JWS, ES256-signed with `key` (RFC 8555 §6.2).
+fn sign(key: &EcKey<Private>, protected: &Json, payload: &str) -> Result<Json, ErrorStack> {
+    let protected = b64u(protected.to_string().as_bytes());
+    let digest = sha256(format!("{protected}.{payload}").as_bytes());
+    let sig = EcdsaSig::sign(&digest, key)?;
+    let mut raw = sig.r().to_vec_padded(32)?;
+    raw.extend(sig.s().to_vec_padded(32)?);
+    Ok(json!({
+        "protected": protected,
+        "payload": payload,
+        "signature": b64u(&raw),
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Directory {
+    new_nonce: String,
+    new_account: String,
+    new_order: String,
+}
+
+/// One ACME account talking to one CA.
+struct Client {
+    directory: Directory,
+    key: EcKey<Private>,
+    jwk: Json,
+    /// The account URL, once registered.
+    kid: Option<String>,
+    nonce: Option<String>,
+}
+
+impl Client {
+    fn connect(directory_url: &str, key: EcKey<Private>) -> Result<Self, AcmeError> {
+        let res = send(directory_url, "GET", None)?;
+        let directory = serde_json::from_str(&res.body)
+            .map_err(|e| protocol(format!("{directory_url} is not an ACME directory: {e}")))?;
+        Ok(Self {
+            directory,
+            jwk: jwk(&key)?,
+            key,
+            kid: None,
+            nonce: None,
+        })
+    }
+
+    /// Find or create the account for the key.
+    fn register(&mut self, email: Option<&str>) -> Result<(), AcmeError> {
+        let mut payload = json!({ "termsOfServiceAgreed": true });
+        if let Some(email) = email {
+            payload["contact"] = json!([format!("mailto:{email}")]);
+        }
+        let url = self.directory.new_account.clone();
+        let res = self.post(&url, Some(&payload))?;
+        self.kid = Some(location(&res, "account")?);
+        Ok(())
+    }
+
+    /// Order, prove and download a certificate for `domains`. Returns the
+    /// chain and a new private key, both PEM.
+    fn issue(&mut self, domains: &[String]) -> Result<(String, Vec<u8>), AcmeError> {
+        let identifiers: Vec<Json> = domains
+            .iter()
+            .map(|d| json!({ "type": "dns", "value": d }))
+            .collect();
+        let url = self.directory.new_order.clone();
+        let res = self.post(&url, Some(&json!({ "identifiers": identifiers })))?;
+        let order_url = location(&res, "order")?;
+        let order = body(&res)?;
+
+        for authz in strings(&order["authorizations"]) {
+            self.authorize(&authz)?;
+        }
+
+        let key = PKey::from_ec_key(new_key()?)?;
+        let csr = csr(domains, &key)?;
+        let finalize = str_field(&order, "finalize")?;
+        self.post(&finalize, Some(&json!({ "csr": b64u(&csr) })))?;
+        let order = self.poll(&order_url, "the order")?;
+
+        let cert_url = str_field(&order, "certificate")?;
+        let cert = self.post(&cert_url, None)?.body;
+        Ok((cert, key.private_key_to_pem_pkcs8()?))
+    }
+
+    /// Answer the HTTP-01 challenge of one authorization.
+    fn authorize(&mut self, url: &str) -> Result<(), AcmeError> {
+        let authz = body(&self.post(url, None)?)?;
+        if authz["status"] == "valid" {
+            return Ok(());
+        }
+        let domain = authz["identifier"]["value"].as_str().unwrap_or(url);
+        let challenge = authz["challenges"]
+            .as_array()
+            .and_then(|cs| cs.iter().find(|c| c["type"] == "http-01"))
+            .ok_or_else(|| protocol(format!("no http-01 challenge offered for {domain}")))?;
+        let token = str_field(challenge, "token")?;
+        let challenge_url = str_field(challenge, "url")?;
+
+        let _published = Published::new(&token, format!("{token}.{}", thumbprint(&self.jwk)));
+        self.post(&challenge_url, Some(&json!({})))?;
+        self.poll(url, &format!("the challenge for {domain}"))?;
+        Ok(())
+    }
+
+    /// POST-as-GET `url` until its status is `valid`.
+    fn poll(&mut self, url: &str, what: &str) -> Result<Json, AcmeError> {
+        for _ in 0..POLL_ATTEMPTS {
+            let obj = body(&self.post(url, None)?)?;
+            match obj["status"].as_str() {
+                Some("valid") => return Ok(obj),
+                Some("invalid") => {
+                    let why = obj["challenges"]
+                        .as_array()
+                        .and_then(|cs| cs.iter().find_map(|c| c["error"]["detail"].as_str()))
+                        .or(obj["error"]["detail"].as_str())
+                        .unwrap_or("no reason given");
+                    return Err(protocol(format!("{what} failed: {why}")));
+                }
+                _ => thread::sleep(POLL_EVERY),
+            }
+        }
+        Err(protocol(format!(
+            "{what} was still pending after {POLL_ATTEMPTS} checks"
+        )))
+    }
+
+    /// A signed POST; `None` is POST-as-GET. A stale nonce is retried once.
+    fn post(&mut self, url: &str, payload: Option<&Json>) -> Result<FetchResponse, AcmeError> {
+        let payload = payload.map_or_else(String::new, |p| b64u(p.to_string().as_bytes()));
+        for attempt in 0..2 {
+            let nonce = match self.nonce.take() {
+                Some(nonce) => nonce,
+                None => {
+                    let res = send(&self.directory.new_nonce, "HEAD", None)?;
+                    nonce_of(&res).ok_or_else(|| protocol("the CA returned no nonce"))?
+                }
+            };
+            let mut protected = json!({ "alg": "ES256", "nonce": nonce, "url": url });
+            match &self.kid {
+                Some(kid) => protected["kid"] = json!(kid),
+                None => protected["jwk"] = self.jwk.clone(),
+            }
+            let jws = sign(&self.key, &protected, &payload)?;
+
+            let res = send(url, "POST", Some(jws.to_string()))?;
+            self.nonce = nonce_of(&res);
+            if res.status < 400 {
+                return Ok(res);
+            }
+            let problem: Json = serde_json::from_str(&res.body).unwrap_or_default();
+            let kind = problem["type"].as_str().unwrap_or_default();
+            if kind.ends_with(":badNonce") && attempt == 0 {
+                continue;
+            }
+            let detail = problem["detail"].as_str().unwrap_or(&res.body);
+            return Err(protocol(format!("{url} answered {}: {detail}", res.status)));
+        }
+        unreachable!("the second attempt always returns")
+    }
+}
+
+fn send(url: &str, method: &str, body: Option<String>) -> Result<FetchResponse, AcmeError> {
+    let fail = |source| AcmeError::Fetch {
+        url: url.to_owned(),
+        source,
+    };
+    let host = url
+        .parse::<Uri>()
+        .ok()
+        .and_then(|u| u.host().map(str::to_owned))
+        .ok_or_else(|| fail(FetchError::Invalid(format!("{url} has no host"))))?;
+    let mut headers = BTreeMap::new();
+    if body.is_some() {
+        headers.insert(
+            "content-type".to_owned(),
+            "application/jose+json".to_owned(),
+        );
+    }
+    let request = FetchRequest {
+        url: url.to_owned(),
+        method: method.to_owned(),
+        headers,
+        body,
+    };
+    let policy = FetchPolicy {
+        hosts: vec![host],
+        timeout_ms: 30_000,
+        max_bytes: 1024 * 1024,
+    };
+    HttpFetcher.fetch(&request, &policy).map_err(fail)
+}
+
+fn nonce_of(res: &FetchResponse) -> Option<String> {
+    res.headers.get("replay-nonce").cloned()
+}
+
+fn location(res: &FetchResponse, what: &str) -> Result<String, AcmeError> {
+    res.headers
+        .get("location")
+        .cloned()
+        .ok_or_else(|| protocol(format!("the CA returned no {what} URL")))
+}
+
+fn body(res: &FetchResponse) -> Result<Json, AcmeError> {
+    serde_json::from_str(&res.body)
+        .map_err(|e| protocol(format!("the CA answered with invalid JSON: {e}")))
+}
+
+fn str_field(obj: &Json, name: &str) -> Result<String, AcmeError> {
+    obj[name]
+        .as_str()
+        .map(str::to_owned)
+        .ok_or_else(|| protocol(format!("the CA's answer has no `{name}`")))
+}
+
+fn strings(v: &Json) -> Vec<String> {
+    v.as_array()
+        .map(|a| {
+            a.iter()
+                .filter_map(|s| s.as_str().map(str::to_owned))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// A DER certificate request for `domains`, the first as common name.
+fn csr(domains: &[String], key: &PKey<Private>) -> Result<Vec<u8>, ErrorStack> {
+    let mut name = X509NameBuilder::new()?;
+    if let Some(first) = domains.first() {
+        name.append_entry_by_nid(Nid::COMMONNAME, first)?;
+    }
+    let mut req = X509ReqBuilder::new()?;
+    req.set_subject_name(&name.build())?;
+    req.set_pubkey(key)?;
+    let mut san = SubjectAlternativeName::new();
+    for domain in domains {
+        san.dns(domain);
+    }
+    let mut extensions = Stack::new()?;
+    extensions.push(san.build(&req.x509v3_context(None))?)?;
+    req.add_extensions(&extensions)?;
+    req.sign(key, MessageDigest::sha256())?;
+    req.build().to_der()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::asn1::Asn1Time;
+    use openssl::x509::X509Builder;
+
+    fn unb64u(s: &str) -> Vec<u8> {
+        let padded = format!("{s}{}", "=".repeat((4 - s.len() % 4) % 4));
+        openssl::base64::decode_block(&padded.replace('-', "+").replace('_', "/")).unwrap()
+    }
+
+    #[test]
+    fn requests_are_signed_with_the_account_key() {
+        let key = new_key().unwrap();
+        let jwk = jwk(&key).unwrap();
+        assert_eq!(thumbprint(&jwk), thumbprint(&jwk.clone()));
+        assert!(!thumbprint(&jwk).contains(['=', '+', '/']));
+
+        let jws = sign(&key, &json!({ "alg": "ES256", "url": "u" }), "e30").unwrap();
+        let input = format!("{}.e30", jws["protected"].as_str().unwrap());
+        let raw = unb64u(jws["signature"].as_str().unwrap());
+        assert_eq!(raw.len(), 64);
+        let sig = EcdsaSig::from_private_components(
+            openssl::bn::BigNum::from_slice(&raw[..32]).unwrap(),
+            openssl::bn::BigNum::from_slice(&raw[32..]).unwrap(),
+        )
+        .unwrap();
+        assert!(sig.verify(&sha256(input.as_bytes()), &key).unwrap());
+
+        assert!(csr(&["example.com".into()], &PKey::from_ec_key(key).unwrap()).is_ok());
+    }
+
+    #[test]
+    fn certificates_are_renewed_for_new_domains_or_near_expiry() {
+        let key = PKey::from_ec_key(new_key().unwrap()).unwrap();
+        let cert = |days: u32| {
+            let mut b = X509Builder::new().unwrap();
+            b.set_pubkey(&key).unwrap();
+            b.set_not_before(&Asn1Time::days_from_now(0).unwrap())
+                .unwrap();
+            b.set_not_after(&Asn1Time::days_from_now(days).unwrap())
+                .unwrap();
+            let san = SubjectAlternativeName::new()
+                .dns("example.com")
+                .build(&b.x509v3_context(None, None))
+                .unwrap();
+            b.append_extension(san).unwrap();
+            b.sign(&key, MessageDigest::sha256()).unwrap();
+            Certified {
+                chain: vec![b.build()],
+                key: key.clone(),
+            }
+        };
+        let domains = ["example.com".to_owned()];
+
+        assert!(!cert(90).needs_renewal(&domains, 30));
+        assert!(cert(10).needs_renewal(&domains, 30));
+        assert!(cert(90).needs_renewal(&["example.com".into(), "www.example.com".into()], 30));
+
+        let published = Published::new("tok", "tok.thumb".into());
+        assert_eq!(challenge("tok").as_deref(), Some("tok.thumb"));
+        drop(published);
+        assert_eq!(challenge("tok"), None);
+    }
+}
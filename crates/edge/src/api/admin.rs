@@ -0,0 +1,875 @@
+// crates/edge/src/api/admin.rs
+
+// Content editing for logged-in users (see auth):
+//
+//   GET    /admin/api/content            same query and response as /api/content
+//   GET    /admin/api/content/{path}     { path, front_matter, body[, blocks] }
+//   POST   /admin/api/content            { path, front_matter, body | blocks } → 201
+//   PUT    /admin/api/content/{path}     { front_matter, body | blocks }
+//   DELETE /admin/api/content/{path}     → 204
+//
+//   GET    /admin/api/content/{path}/revisions            [{ revision, who, at, action, … }]
+//   GET    /admin/api/content/{path}/revisions/{n}        { …, diff, document }
+//   POST   /admin/api/content/{path}/revisions/{n}/restore  → the restored document
+//
+// `path` is relative to the content directory and must have one of its
+// extensions. Files are written with TOML front matter and re-indexed
+// straight away, without waiting for the watcher. Viewers may read; authors
+// may write only documents whose `author.author` is their username; editors
+// and admins may write anything. Every write leaves a before/after diff
+// in the audit trail and a revision (db::revisions) with the whole file, so
+// a bad save or a deletion can be undone by restoring an earlier revision;
+// restoring needs the same rights as writing both the current document and
+// the revision.
+//
+// Block documents (`.blocks`) are also read as a parsed `blocks` array and
+// may be written as one; either way the blocks must pass their schemas
+// (crate::blocks) before anything is saved.
+
+use crate::api::content::{run_query, ContentQuery};
+use crate::audit::{self, AuditNote};
+use crate::auth::{Author, RequireRole, Viewer};
+use crate::db::history::write_atomic;
+use crate::db::revisions::{Revision, RevisionAction, RevisionError, RevisionStore, REVISIONS_DIR};
+use crate::db::users::{Role, User};
+use crate::fs::index::{ContentMgr, SiteContent};
+use actix_web::{
+    dev::HttpServiceFactory, http::StatusCode, web, HttpRequest, HttpResponse, ResponseError,
+};
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::{json, Map, Value as Json};
+use serve::blocks::{to_source, Block, BlockRegistry};
+use serve::front_matter;
+use serve::indexer::process_changed_docs;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use thiserror::Error;
+use tracing::{error, info};
+
+/// Where documents live, which files count as documents, and the site
+/// content they are indexed into.
+#[derive(Clone)]
+struct AdminContent {
+    root: PathBuf,
+    file_re: Option<Regex>,
+    site: Arc<SiteContent>,
+}
+
+/// Build the `/admin/api/content` scope for the site in `root_dir`, editing
+/// the documents under `content_root` whose file names match `file_re` and
+/// re-indexing them into `site`.
+///
+/// Mount this ahead of any theme scopes, like the public Content API.
+pub fn scope(
+    root_dir: &Path,
+    content_root: PathBuf,
+    file_re: Option<Regex>,
+    site: Arc<SiteContent>,
+) -> impl HttpServiceFactory {
+    let content = AdminContent {
+        root: content_root,
+        file_re,
+        site,
+    };
+    scope_with(content, RevisionStore::new(root_dir.join(REVISIONS_DIR)))
+}
+
+fn scope_with(content: AdminContent, revisions: RevisionStore) -> impl HttpServiceFactory {
+    web::scope("/admin/api/content")
+        .app_data(web::Data::new(content))
+        .app_data(web::Data::new(revisions))
+        .route("/{path:.*}/revisions", web::get().to(revisions_handler))
+        .route(
+            "/{path:.*}/revisions/{revision:\\d+}",
+            web::get().to(revision_handler),
+        )
+        .route(
+            "/{path:.*}/revisions/{revision:\\d+}/restore",
+            web::post().to(restore_handler),
+        )
+        .route("", web::get().to(list_handler))
+        .route("", web::post().to(create_handler))
+        .route("/{path:.*}", web::get().to(read_handler))
+        .route("/{path:.*}", web::put().to(update_handler))
+        .route("/{path:.*}", web::delete().to(delete_handler))
+}
+
+#[derive(Debug, Error)]
+pub enum AdminError {
+    #[error("invalid document path {0:?}")]
+    InvalidPath(String),
+
+    #[error("invalid front matter: {0}")]
+    FrontMatter(String),
+
+    #[error("invalid blocks: {0}")]
+    Blocks(String),
+
+    #[error("no document at {0}")]
+    NotFound(String),
+
+    #[error("a document already exists at {0}")]
+    Exists(String),
+
+    #[error("{0} belongs to another author")]
+    NotOwner(String),
+
+    #[error("I/O: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("writing front matter: {0}")]
+    Toml(#[from] toml::ser::Error),
+
+    #[error("revisions: {0}")]
+    Revision(#[from] RevisionError),
+}
+
+impl ResponseError for AdminError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AdminError::InvalidPath(_) | AdminError::FrontMatter(_) | AdminError::Blocks(_) => {
+                StatusCode::BAD_REQUEST
+            }
+            AdminError::NotFound(_) => StatusCode::NOT_FOUND,
+            AdminError::Exists(_) => StatusCode::CONFLICT,
+            AdminError::NotOwner(_) => StatusCode::FORBIDDEN,
+            AdminError::Revision(RevisionError::InvalidPath(_)) => StatusCode::BAD_REQUEST,
+            AdminError::Revision(RevisionError::NotFound { .. }) => StatusCode::NOT_FOUND,
+            AdminError::Io(_) | AdminError::Toml(_) | AdminError::Revision(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        if self.status_code().is_server_error() {
+            error!("Admin content request failed: {}", self);
+        }
+        HttpResponse::build(self.status_code()).json(json!({ "error": self.to_string() }))
+    }
+}
+
+impl From<actix_web::error::BlockingError> for AdminError {
+    fn from(e: actix_web::error::BlockingError) -> Self {
+        AdminError::Io(io::Error::other(e))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct DocBody {
+    #[serde(default)]
+    path: Option<String>,
+    #[serde(default)]
+    front_matter: Option<Json>,
+    #[serde(default)]
+    body: String,
+    /// Replaces `body` for block documents.
+    #[serde(default)]
+    blocks: Option<Json>,
+}
+
+impl AdminContent {
+    /// `raw` under the content root, if it names a document there.
+    fn resolve(&self, raw: &str) -> Result<(String, PathBuf), AdminError> {
+        let rel = Path::new(raw.trim_start_matches('/'));
+        let plain = rel.components().all(|c| matches!(c, Component::Normal(_)));
+        let name = rel.file_name().map(|n| n.to_string_lossy());
+        let matches = name
+            .as_ref()
+            .is_some_and(|n| self.file_re.as_ref().is_none_or(|re| re.is_match(n)));
+        if !plain || !matches {
+            return Err(AdminError::InvalidPath(raw.to_owned()));
+        }
+        Ok((rel.to_string_lossy().into_owned(), self.root.join(rel)))
+    }
+
+    /// Bring the live index in line with the file at `rel`. The file is
+    /// already saved, so a failure here is logged rather than returned.
+    async fn reindex(&self, rel: &str) {
+        // The startup scan indexed canonical paths.
+        let root = fs::canonicalize(&self.root).unwrap_or_else(|_| self.root.clone());
+        let outcome = process_changed_docs(
+            [root.join(rel)],
+            self.file_re.as_ref(),
+            &ContentMgr::new(self.root.clone(), self.site.clone()),
+        )
+        .await;
+        for (path, e) in outcome.errors {
+            error!("Re-indexing {} failed: {}", path.display(), e);
+        }
+    }
+}
+
+/// The file of the document at `path`.
+fn read_text(rel: &str, path: &Path) -> Result<String, AdminError> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(text),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Err(AdminError::NotFound(rel.to_owned())),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// The front matter and body of a document's file.
+fn parse_doc(path: &Path, text: &str) -> Result<(Json, String), AdminError> {
+    match front_matter::parse(path, text) {
+        Ok(Some(fm)) => Ok((fm.data, fm.body)),
+        Ok(None) => Ok((Json::Object(Map::new()), text.to_owned())),
+        Err(e) => Err(AdminError::FrontMatter(e.to_string())),
+    }
+}
+
+/// The front matter and body of the document at `path`.
+fn read_doc(rel: &str, path: &Path) -> Result<(Json, String), AdminError> {
+    parse_doc(path, &read_text(rel, path)?)
+}
+
+fn author_of(front_matter: &Json) -> Option<&str> {
+    front_matter
+        .pointer("/author/author")
+        .and_then(Json::as_str)
+}
+
+/// Whether `user` may change a document whose front matter is `front_matter`.
+fn may_write(user: &User, front_matter: &Json) -> bool {
+    user.role.allows(Role::Editor) || author_of(front_matter) == Some(user.username.as_str())
+}
+
+/// The front matter to save for `user`: an object, credited to them when
+/// it names no author.
+fn front_matter_for(user: &User, given: Option<Json>) -> Result<Json, AdminError> {
+    let mut fm = match given {
+        None | Some(Json::Null) => Json::Object(Map::new()),
+        Some(fm @ Json::Object(_)) => fm,
+        Some(_) => return Err(AdminError::FrontMatter("must be an object".into())),
+    };
+    if author_of(&fm).is_none() {
+        match fm.get_mut("author") {
+            Some(Json::Object(author)) => {
+                author.insert("author".into(), Json::from(user.username.clone()));
+            }
+            _ => fm["author"] = json!({ "author": user.username }),
+        }
+    }
+    Ok(fm)
+}
+
+fn is_block_doc(rel: &str) -> bool {
+    Path::new(rel).extension().and_then(|e| e.to_str()) == Some("blocks")
+}
+
+/// The body to save at `rel`: `blocks` as block source, or `body` as given.
+/// Block documents are validated against `registry` either way.
+fn body_for(
+    registry: &BlockRegistry,
+    rel: &str,
+    body: String,
+    blocks: Option<Json>,
+) -> Result<String, AdminError> {
+    if !is_block_doc(rel) {
+        return match blocks {
+            None => Ok(body),
+            Some(_) => Err(AdminError::Blocks(format!("{rel} is not a block document"))),
+        };
+    }
+    let invalid = |e: serde_json::Error| AdminError::Blocks(e.to_string());
+    match blocks {
+        Some(blocks) => {
+            let blocks: Vec<Block> = serde_json::from_value(blocks).map_err(invalid)?;
+            registry
+                .validate(&blocks)
+                .map_err(|e| AdminError::Blocks(e.to_string()))?;
+            Ok(to_source(&blocks))
+        }
+        None => {
+            registry
+                .parse(&body)
+                .map_err(|e| AdminError::Blocks(e.to_string()))?;
+            Ok(body)
+        }
+    }
+}
+
+/// A document as the API returns it.
+fn doc_json(rel: &str, front_matter: &Json, body: &str) -> Json {
+    let mut doc = json!({
+        "path": rel,
+        "front_matter": front_matter,
+        "body": body,
+    });
+    if is_block_doc(rel) {
+        doc["blocks"] = serde_json::from_str(body).unwrap_or(Json::Null);
+    }
+    doc
+}
+
+/// Save a document; the file as written.
+fn write_doc(path: &Path, front_matter: &Json, body: &str) -> Result<String, AdminError> {
+    write_text(path, &front_matter::to_toml_file(front_matter, body)?)
+}
+
+fn write_text(path: &Path, text: &str) -> Result<String, AdminError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    write_atomic(path, text.as_bytes())?;
+    Ok(text.to_owned())
+}
+
+/// Record a saved change as a revision. The file is already saved, so a
+/// failure here is logged rather than returned.
+fn keep_revision(
+    revisions: &RevisionStore,
+    rel: &str,
+    action: RevisionAction,
+    before: Option<&str>,
+    after: Option<&str>,
+    who: &str,
+) {
+    if let Err(e) = revisions.record(rel, action, before, after, who) {
+        error!("Recording a revision of {} failed: {}", rel, e);
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn list_handler(_auth: RequireRole<Viewer>, req: HttpRequest) -> HttpResponse {
+    match ContentQuery::from_query_string(req.query_string()) {
+        Ok(q) => run_query(&SiteContent::of(&req), q).await,
+        Err(e) => e.to_response(),
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn read_handler(
+    _auth: RequireRole<Viewer>,
+    content: web::Data<AdminContent>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AdminError> {
+    let (rel, file) = content.resolve(&path)?;
+    let (front_matter, body) = {
+        let rel = rel.clone();
+        web::block(move || read_doc(&rel, &file)).await??
+    };
+    Ok(HttpResponse::Ok().json(doc_json(&rel, &front_matter, &body)))
+}
+
+/// A document as the audit trail compares it.
+fn snapshot(front_matter: &Json, body: &str) -> Json {
+    json!({ "front_matter": front_matter, "body": body })
+}
+
+#[tracing::instrument(skip_all)]
+async fn create_handler(
+    auth: RequireRole<Author>,
+    content: web::Data<AdminContent>,
+    revisions: web::Data<RevisionStore>,
+    req: HttpRequest,
+    doc: web::Json<DocBody>,
+) -> Result<HttpResponse, AdminError> {
+    let DocBody {
+        path,
+        front_matter,
+        body,
+        blocks,
+    } = doc.into_inner();
+    let (rel, file) = content.resolve(path.as_deref().unwrap_or_default())?;
+    let fm = front_matter_for(&auth.user, front_matter)?;
+    let body = body_for(&content.site.blocks(), &rel, body, blocks)?;
+    if !may_write(&auth.user, &fm) {
+        return Err(AdminError::NotOwner(rel));
+    }
+
+    let saved = {
+        let (rel, fm) = (rel.clone(), fm.clone());
+        let who = auth.user.username.clone();
+        let revisions = revisions.get_ref().clone();
+        web::block(move || {
+            if file.exists() {
+                return Err(AdminError::Exists(rel));
+            }
+            let text = write_doc(&file, &fm, &body)?;
+            keep_revision(
+                &revisions,
+                &rel,
+                RevisionAction::Create,
+                None,
+                Some(&text),
+                &who,
+            );
+            Ok(body)
+        })
+        .await??
+    };
+    audit::annotate(
+        &req,
+        AuditNote::new("content.create", format!("content:{rel}"))
+            .with_change(&Json::Null, &snapshot(&fm, &saved)),
+    );
+    content.reindex(&rel).await;
+    info!("{} created {}", auth.user.username, rel);
+
+    Ok(HttpResponse::Created().json(doc_json(&rel, &fm, &saved)))
+}
+
+#[tracing::instrument(skip_all)]
+async fn update_handler(
+    auth: RequireRole<Author>,
+    content: web::Data<AdminContent>,
+    revisions: web::Data<RevisionStore>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    doc: web::Json<DocBody>,
+) -> Result<HttpResponse, AdminError> {
+    let (rel, file) = content.resolve(&path)?;
+    let DocBody {
+        front_matter,
+        body,
+        blocks,
+        ..
+    } = doc.into_inner();
+    let fm = front_matter_for(&auth.user, front_matter)?;
+    let body = body_for(&content.site.blocks(), &rel, body, blocks)?;
+
+    let (before, saved) = {
+        let (rel, fm) = (rel.clone(), fm.clone());
+        let user = auth.user.clone();
+        let revisions = revisions.get_ref().clone();
+        web::block(move || {
+            let current_text = read_text(&rel, &file)?;
+            let (current, current_body) = parse_doc(&file, &current_text)?;
+            if !may_write(&user, &current) || !may_write(&user, &fm) {
+                return Err(AdminError::NotOwner(rel));
+            }
+            let text = write_doc(&file, &fm, &body)?;
+            keep_revision(
+                &revisions,
+                &rel,
+                RevisionAction::Update,
+                Some(&current_text),
+                Some(&text),
+                &user.username,
+            );
+            Ok((snapshot(&current, &current_body), body))
+        })
+        .await??
+    };
+    audit::annotate(
+        &req,
+        AuditNote::new("content.update", format!("content:{rel}"))
+            .with_change(&before, &snapshot(&fm, &saved)),
+    );
+    content.reindex(&rel).await;
+    info!("{} updated {}", auth.user.username, rel);
+
+    Ok(HttpResponse::Ok().json(doc_json(&rel, &fm, &saved)))
+}
+
+#[tracing::instrument(skip_all)]
+async fn delete_handler(
+    auth: RequireRole<Author>,
+    content: web::Data<AdminContent>,
+    revisions: web::Data<RevisionStore>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AdminError> {
+    let (rel, file) = content.resolve(&path)?;
+    let before = {
+        let rel = rel.clone();
+        let user = auth.user.clone();
+        let revisions = revisions.get_ref().clone();
+        web::block(move || {
+            let current_text = read_text(&rel, &file)?;
+            let (current, current_body) = parse_doc(&file, &current_text)?;
+            if !may_write(&user, &current) {
+                return Err(AdminError::NotOwner(rel));
+            }
+            fs::remove_file(&file)?;
+            keep_revision(
+                &revisions,
+                &rel,
+                RevisionAction::Delete,
+                Some(&current_text),
+                None,
+                &user.username,
+            );
+            Ok::<_, AdminError>(snapshot(&current, &current_body))
+        })
+        .await??
+    };
+    audit::annotate(
+        &req,
+        AuditNote::new("content.delete", format!("content:{rel}"))
+            .with_change(&before, &Json::Null),
+    );
+    content.reindex(&rel).await;
+    info!("{} deleted {}", auth.user.username, rel);
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// A revision without its file and diff, for lists.
+fn revision_json(r: &Revision) -> Json {
+    json!({
+        "revision": r.revision,
+        "who": r.who,
+        "at": r.at,
+        "action": r.action,
+        "restored_from": r.restored_from,
+    })
+}
+
+#[tracing::instrument(skip_all)]
+async fn revisions_handler(
+    _auth: RequireRole<Viewer>,
+    content: web::Data<AdminContent>,
+    revisions: web::Data<RevisionStore>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AdminError> {
+    let (rel, _) = content.resolve(&path)?;
+    let list = {
+        let (rel, revisions) = (rel.clone(), revisions.get_ref().clone());
+        web::block(move || revisions.revisions(&rel)).await??
+    };
+    Ok(HttpResponse::Ok().json(json!({
+        "path": rel,
+        "revisions": list.iter().map(revision_json).collect::<Vec<_>>(),
+    })))
+}
+
+#[tracing::instrument(skip_all)]
+async fn revision_handler(
+    _auth: RequireRole<Viewer>,
+    content: web::Data<AdminContent>,
+    revisions: web::Data<RevisionStore>,
+    path: web::Path<(String, u32)>,
+) -> Result<HttpResponse, AdminError> {
+    let (raw, number) = path.into_inner();
+    let (rel, file) = content.resolve(&raw)?;
+    let r = {
+        let (rel, revisions) = (rel.clone(), revisions.get_ref().clone());
+        web::block(move || revisions.get(&rel, number)).await??
+    };
+    let document = match &r.text {
+        Some(text) => {
+            let (fm, body) = parse_doc(&file, text)?;
+            doc_json(&rel, &fm, &body)
+        }
+        None => Json::Null,
+    };
+    let mut out = revision_json(&r);
+    out["path"] = json!(rel);
+    out["diff"] = json!(r.diff);
+    out["document"] = document;
+    Ok(HttpResponse::Ok().json(out))
+}
+
+#[tracing::instrument(skip_all)]
+async fn restore_handler(
+    auth: RequireRole<Author>,
+    content: web::Data<AdminContent>,
+    revisions: web::Data<RevisionStore>,
+    req: HttpRequest,
+    path: web::Path<(String, u32)>,
+) -> Result<HttpResponse, AdminError> {
+    let (raw, number) = path.into_inner();
+    let (rel, file) = content.resolve(&raw)?;
+
+    let (before, fm, saved, restored) = {
+        let rel = rel.clone();
+        let user = auth.user.clone();
+        let revisions = revisions.get_ref().clone();
+        let registry = content.site.blocks();
+        web::block(move || {
+            let target = revisions.get(&rel, number)?;
+            let Some(text) = target.text.as_deref() else {
+                return Err(AdminError::NotFound(format!("{rel} in revision {number}")));
+            };
+            let (fm, body) = parse_doc(&file, text)?;
+            let body = body_for(&registry, &rel, body, None)?;
+            let current_text = match read_text(&rel, &file) {
+                Ok(text) => Some(text),
+                Err(AdminError::NotFound(_)) => None,
+                Err(e) => return Err(e),
+            };
+            let before = match &current_text {
+                Some(current_text) => {
+                    let (current, current_body) = parse_doc(&file, current_text)?;
+                    if !may_write(&user, &current) {
+                        return Err(AdminError::NotOwner(rel));
+                    }
+                    snapshot(&current, &current_body)
+                }
+                None => Json::Null,
+            };
+            if !may_write(&user, &fm) {
+                return Err(AdminError::NotOwner(rel));
+            }
+
+            write_text(&file, text)?;
+            let restored =
+                match revisions.restored(&rel, &target, current_text.as_deref(), &user.username) {
+                    Ok(r) => Some(r.revision),
+                    Err(e) => {
+                        error!("Recording a revision of {} failed: {}", rel, e);
+                        None
+                    }
+                };
+            Ok((before, fm, body, restored))
+        })
+        .await??
+    };
+    audit::annotate(
+        &req,
+        AuditNote::new("content.restore", format!("content:{rel}"))
+            .with_change(&before, &snapshot(&fm, &saved)),
+    );
+    content.reindex(&rel).await;
+    info!(
+        "{} restored {} to revision {}",
+        auth.user.username, rel, number
+    );
+
+    let mut doc = doc_json(&rel, &fm, &saved);
+    doc["revision"] = json!(restored);
+    Ok(HttpResponse::Ok().json(doc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{self, SESSION_COOKIE};
+    use crate::db::audit::{AuditLog, AUDIT_LOG_FILE};
+    use crate::db::users::ValidatedPassword;
+    use actix_web::cookie::Cookie;
+    use actix_web::{middleware::from_fn, test as actix_test, App};
+
+    fn content(root: &Path) -> AdminContent {
+        AdminContent {
+            root: root.join("content"),
+            file_re: Some(Regex::new(r"\.md$").unwrap()),
+            site: Arc::default(),
+        }
+    }
+
+    #[test]
+    fn paths_must_stay_inside_the_content_root() {
+        let content = content(Path::new("/site"));
+        let (rel, file) = content.resolve("/blog/a.md").unwrap();
+        assert_eq!(rel, "blog/a.md");
+        assert_eq!(file, Path::new("/site/content/blog/a.md"));
+
+        for bad in ["../a.md", "blog/../../a.md", "blog/a.txt", ""] {
+            assert!(
+                matches!(content.resolve(bad), Err(AdminError::InvalidPath(_))),
+                "{bad}"
+            );
+        }
+    }
+
+    #[test]
+    fn block_documents_round_trip_as_validated_blocks() {
+        let blocks = json!([
+            { "type": "paragraph", "text": "Hello" },
+            { "type": "image", "src": "/media/a.png", "alt": "A" },
+        ]);
+        let registry = BlockRegistry::new();
+        let body = body_for(
+            &registry,
+            "blog/a.blocks",
+            String::new(),
+            Some(blocks.clone()),
+        )
+        .unwrap();
+        assert_eq!(
+            body_for(&registry, "blog/a.blocks", body.clone(), None).unwrap(),
+            body
+        );
+
+        let doc = doc_json("blog/a.blocks", &json!({}), &body);
+        assert_eq!(doc["blocks"], blocks);
+        assert!(doc_json("blog/a.md", &json!({}), "Hello")
+            .get("blocks")
+            .is_none());
+
+        for (rel, body, blocks) in [
+            ("blog/a.blocks", "", Some(json!([{ "type": "image" }]))),
+            ("blog/a.blocks", "not json", None),
+            ("blog/a.md", "", Some(json!([]))),
+        ] {
+            assert!(
+                matches!(
+                    body_for(&registry, rel, body.into(), blocks),
+                    Err(AdminError::Blocks(_))
+                ),
+                "{rel} {body}"
+            );
+        }
+    }
+
+    #[actix_web::test]
+    async fn authors_edit_their_own_documents_and_every_write_is_audited() {
+        let site = tempfile::tempdir().unwrap();
+        let store = auth::store(site.path());
+        let password = ValidatedPassword::new("a long enough secret").unwrap();
+        let mut cookies = Vec::new();
+        for (name, role) in [("amy", Role::Author), ("bob", Role::Author)] {
+            let user = store.create(name, role, &password).unwrap();
+            let token = store
+                .issue_session(&user, chrono::Duration::hours(1))
+                .unwrap();
+            cookies.push(Cookie::new(SESSION_COOKIE, token));
+        }
+        let (amy, bob) = (cookies[0].clone(), cookies[1].clone());
+
+        let app = actix_test::init_service(
+            App::new().service(
+                web::scope("")
+                    .app_data(store)
+                    .app_data(audit::log(site.path()))
+                    .service(scope_with(
+                        content(site.path()),
+                        RevisionStore::new(site.path().join(REVISIONS_DIR)),
+                    ))
+                    .wrap(from_fn(audit::record)),
+            ),
+        )
+        .await;
+
+        let doc = json!({
+            "path": "blog/a.md",
+            "front_matter": { "content": { "title": "A" } },
+            "body": "Hello\n",
+        });
+        let req = actix_test::TestRequest::post()
+            .uri("/admin/api/content")
+            .set_json(&doc)
+            .to_request();
+        let res = actix_test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let req = actix_test::TestRequest::post()
+            .uri("/admin/api/content")
+            .cookie(amy.clone())
+            .set_json(&doc)
+            .to_request();
+        let res = actix_test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let req = actix_test::TestRequest::post()
+            .uri("/admin/api/content")
+            .cookie(amy.clone())
+            .set_json(&doc)
+            .to_request();
+        let res = actix_test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+
+        let req = actix_test::TestRequest::get()
+            .uri("/admin/api/content/blog/a.md")
+            .cookie(bob.clone())
+            .to_request();
+        let read: Json = actix_test::call_and_read_body_json(&app, req).await;
+        assert_eq!(read["front_matter"]["content"]["title"], "A");
+        assert_eq!(read["front_matter"]["author"]["author"], "amy");
+        assert_eq!(read["body"], "Hello\n");
+
+        let edit = json!({ "front_matter": read["front_matter"], "body": "Edited\n" });
+        let req = actix_test::TestRequest::put()
+            .uri("/admin/api/content/blog/a.md")
+            .cookie(bob.clone())
+            .set_json(&edit)
+            .to_request();
+        let res = actix_test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        let req = actix_test::TestRequest::put()
+            .uri("/admin/api/content/blog/a.md")
+            .cookie(amy.clone())
+            .set_json(&edit)
+            .to_request();
+        let res = actix_test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // The bad save is undone from the revisions, by its author only.
+        let req = actix_test::TestRequest::get()
+            .uri("/admin/api/content/blog/a.md/revisions")
+            .cookie(bob.clone())
+            .to_request();
+        let list: Json = actix_test::call_and_read_body_json(&app, req).await;
+        let actions: Vec<_> = list["revisions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| (r["revision"].clone(), r["who"].clone(), r["action"].clone()))
+            .collect();
+        assert_eq!(
+            actions,
+            vec![
+                (json!(1), json!("amy"), json!("create")),
+                (json!(2), json!("amy"), json!("update")),
+            ]
+        );
+        let req = actix_test::TestRequest::get()
+            .uri("/admin/api/content/blog/a.md/revisions/2")
+            .cookie(bob.clone())
+            .to_request();
+        let r2: Json = actix_test::call_and_read_body_json(&app, req).await;
+        assert!(r2["diff"].as_str().unwrap().contains("-Hello\n+Edited\n"));
+        assert_eq!(r2["document"]["body"], "Edited\n");
+
+        let req = actix_test::TestRequest::post()
+            .uri("/admin/api/content/blog/a.md/revisions/1/restore")
+            .cookie(bob.clone())
+            .to_request();
+        let res = actix_test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        let req = actix_test::TestRequest::post()
+            .uri("/admin/api/content/blog/a.md/revisions/1/restore")
+            .cookie(amy.clone())
+            .to_request();
+        let restored: Json = actix_test::call_and_read_body_json(&app, req).await;
+        assert_eq!(
+            (&restored["body"], &restored["revision"]),
+            (&json!("Hello\n"), &json!(3))
+        );
+
+        let req = actix_test::TestRequest::delete()
+            .uri("/admin/api/content/blog/a.md")
+            .cookie(amy)
+            .to_request();
+        let res = actix_test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        assert!(!site.path().join("content/blog/a.md").exists());
+        let req = actix_test::TestRequest::get()
+            .uri("/admin/api/content/blog/a.md/revisions/4")
+            .cookie(bob)
+            .to_request();
+        let deleted: Json = actix_test::call_and_read_body_json(&app, req).await;
+        assert_eq!(deleted["action"], "delete");
+        assert_eq!(deleted["document"], Json::Null);
+
+        let entries = AuditLog::new(site.path().join(AUDIT_LOG_FILE))
+            .entries()
+            .unwrap();
+        let trail: Vec<_> = entries
+            .iter()
+            .map(|e| (e.actor.as_str(), e.action.as_str(), e.status.unwrap()))
+            .collect();
+        assert_eq!(
+            trail,
+            vec![
+                ("anonymous", "POST", 401),
+                ("amy", "content.create", 201),
+                ("amy", "POST", 409),
+                ("bob", "PUT", 403),
+                ("amy", "content.update", 200),
+                ("bob", "POST", 403),
+                ("amy", "content.restore", 200),
+                ("amy", "content.delete", 204),
+            ]
+        );
+        assert_eq!(
+            entries[4].diff,
+            Some(json!({ "body": { "before": "Hello\n", "after": "Edited\n" } }))
+        );
+    }
+}
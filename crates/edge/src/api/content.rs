@@ -0,0 +1,489 @@
+// crates/edge/src/api/content.rs
+
+// Headless Content API: read-only MQL queries over the front-matter archive.
+//
+//   GET  /api/content?filter={..}&sort=-publish.date,slug&limit=10&after=7b22..
+//   POST /api/content/query   { "filter": {..}, "sort": {..}, "limit": 10, "skip": 20 }
+//   GET  /api/content/slug/{slug}
+//   POST /api/content/aggregate
+//        { "filter": { "type": "post" },
+//          "facets": { "tags": { "by": "tax.tags", "limit": 30 },
+//                      "months": { "by": "publish.date", "bucket": "month", "sort": "-key" } } }
+//
+// Results are paginated JSON: { items, total, limit, skip, next }. `next` is
+// an opaque cursor for the following page (null on the last one); pass it
+// back as `after` with the same filter and sort. Cursor pages stay put while
+// content is published, unlike `skip`. Aggregates come
+// back as { facets: { tags: [{ key, count }, ..], months: [..] } }; see
+// adapt::mql::aggregate for what a facet can compute. Only public content
+// is served (IndexRecord::is_public_at): drafts, pending, private and
+// scheduled entries are left out, and the archive already drops anything
+// outside its publish window. The admin API and preview links (see preview)
+// show the rest.
+//
+// Each request's queries share the `[query_budget]` from settings.toml; one
+// that goes over it gets a 503.
+
+use crate::fs::index::{query_governor, FrontMatterIndexError, SiteContent};
+
+use actix_web::{
+    body::MessageBody,
+    dev::{HttpServiceFactory, ServiceRequest, ServiceResponse},
+    middleware::{from_fn, Next},
+    web, Error, HttpRequest, HttpResponse,
+};
+use adapt::mql::index::{IndexRecord, PUBLIC_STATUSES};
+use adapt::mql::parser::{parse_filter, parse_find_options};
+use adapt::mql::{
+    governed, parse_facets, CmpOp, Cursor, FieldExpr, Filter, FindOptions, GroupSpec, QueryError,
+};
+use chrono::Utc;
+use domain::setting::QueryBudgetSettings;
+use serde_json::{json, Value as Json};
+use thiserror::Error;
+use tracing::error;
+
+/// Page size used when the caller does not ask for one.
+pub const DEFAULT_LIMIT: usize = 20;
+
+/// Upper bound on the page size a caller may request.
+pub const MAX_LIMIT: usize = 100;
+
+#[derive(Debug, Error)]
+pub enum ContentApiError {
+    #[error("Query: {0}")]
+    Query(#[from] QueryError),
+
+    #[error("Invalid parameter {0}: {1}")]
+    InvalidParam(String, String),
+
+    #[error("Index: {0}")]
+    Index(#[from] FrontMatterIndexError),
+}
+
+impl ContentApiError {
+    pub(crate) fn to_response(&self) -> HttpResponse {
+        let body = json!({ "error": self.to_string() });
+        match self {
+            ContentApiError::Query(_) | ContentApiError::InvalidParam(..) => {
+                HttpResponse::BadRequest().json(body)
+            }
+            ContentApiError::Index(FrontMatterIndexError::NoIndex(_))
+            | ContentApiError::Index(FrontMatterIndexError::Query(QueryError::BudgetExceeded(_))) => {
+                HttpResponse::ServiceUnavailable().json(body)
+            }
+            ContentApiError::Index(_) => HttpResponse::InternalServerError().json(body),
+        }
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Request parsing
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// A parsed Content API query: MQL filter plus sort / pagination options.
+#[derive(Debug, Clone)]
+pub struct ContentQuery {
+    pub filter: Filter,
+    pub opts: FindOptions,
+}
+
+impl ContentQuery {
+    /// Parse from URL query parameters.
+    ///
+    /// - `filter`: JSON-encoded MQL filter object.
+    /// - `sort`: JSON object (`{"field":-1}`) or a comma list where a leading
+    ///   `-` means descending (`-publish.date,slug`).
+    /// - `limit` / `skip`: non-negative integers.
+    /// - `after`: the `next` cursor of an earlier page.
+    ///
+    /// Unknown parameters are ignored.
+    pub fn from_query_string(raw_query: &str) -> Result<Self, ContentApiError> {
+        let mut filter = Filter::And(vec![]);
+        let mut opts = FindOptions::default();
+
+        for (key, value) in form_urlencoded::parse(raw_query.as_bytes()) {
+            match key.as_ref() {
+                "filter" => {
+                    let json: Json = serde_json::from_str(&value).map_err(|e| {
+                        ContentApiError::InvalidParam("filter".into(), e.to_string())
+                    })?;
+                    filter = parse_filter(&json)?;
+                }
+                "sort" => opts.sort = parse_sort_param(&value)?,
+                "limit" => opts.limit = Some(parse_usize_param("limit", &value)?),
+                "skip" => opts.skip = Some(parse_usize_param("skip", &value)?),
+                "after" => opts.after = Some(Cursor::decode(&value)?),
+                _ => {}
+            }
+        }
+
+        Ok(Self::clamped(filter, opts))
+    }
+
+    /// Parse from a JSON request body shaped like the plugin-facing
+    /// `find(filter, options)` arguments, flattened into one object.
+    pub fn from_json_body(body: &Json) -> Result<Self, ContentApiError> {
+        let filter = match body.get("filter") {
+            Some(f) if !f.is_null() => parse_filter(f)?,
+            _ => Filter::And(vec![]),
+        };
+        let opts = parse_find_options(body)?;
+
+        Ok(Self::clamped(filter, opts))
+    }
+
+    /// The same query, limited to public content. The filter matches the
+    /// status half of `IndexRecord::is_public_at`; the archive applies the
+    /// `is_live_at` half to every record a query reads.
+    pub fn published_only(self) -> Self {
+        let status = |op| {
+            Filter::Field(FieldExpr {
+                path: "publish.status".into(),
+                op,
+            })
+        };
+        let public = std::iter::once(Json::Null).chain(PUBLIC_STATUSES.iter().map(|s| json!(s)));
+        let public = Filter::Or(vec![
+            status(CmpOp::Exists(false)),
+            status(CmpOp::In(public.collect())),
+        ]);
+        Self {
+            filter: Filter::And(vec![self.filter, public]),
+            ..self
+        }
+    }
+
+    pub(crate) fn clamped(filter: Filter, mut opts: FindOptions) -> Self {
+        opts.limit = Some(opts.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT));
+        Self { filter, opts }
+    }
+}
+
+/// A parsed aggregation: MQL filter plus named groupings.
+#[derive(Debug, Clone)]
+pub struct ContentAggregate {
+    pub filter: Filter,
+    pub facets: Vec<(String, GroupSpec)>,
+}
+
+impl ContentAggregate {
+    /// Parse from `{ "filter": {..}, "facets": { name: spec, .. } }`.
+    pub fn from_json_body(body: &Json) -> Result<Self, ContentApiError> {
+        let filter = ContentQuery::from_json_body(&json!({ "filter": body.get("filter") }))?.filter;
+        let facets = match body.get("facets") {
+            Some(f) => parse_facets(f)?,
+            None => {
+                return Err(ContentApiError::InvalidParam(
+                    "facets".into(),
+                    "missing".into(),
+                ))
+            }
+        };
+        Ok(Self { filter, facets })
+    }
+
+    /// The same aggregation, limited to public content.
+    pub fn published_only(self) -> Self {
+        let filter = ContentQuery {
+            filter: self.filter,
+            opts: FindOptions::default(),
+        }
+        .published_only()
+        .filter;
+        Self { filter, ..self }
+    }
+}
+
+fn parse_usize_param(name: &str, value: &str) -> Result<usize, ContentApiError> {
+    value
+        .parse::<usize>()
+        .map_err(|e| ContentApiError::InvalidParam(name.into(), e.to_string()))
+}
+
+fn parse_sort_param(value: &str) -> Result<Vec<(String, i8)>, ContentApiError> {
+    let trimmed = value.trim();
+
+    if trimmed.starts_with('{') {
+        let json: Json = serde_json::from_str(trimmed)
+            .map_err(|e| ContentApiError::InvalidParam("sort".into(), e.to_string()))?;
+        return Ok(parse_find_options(&json!({ "sort": json }))?.sort);
+    }
+
+    Ok(trimmed
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| match s.strip_prefix('-') {
+            Some(field) => (field.to_string(), -1),
+            None => (s.trim_start_matches('+').to_string(), 1),
+        })
+        .collect())
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Routes
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Build the `/api/content` scope.
+///
+/// Mount this ahead of any theme scopes so a theme bound at `/` does not
+/// swallow the API paths.
+pub fn scope() -> impl HttpServiceFactory {
+    web::scope("/api/content")
+        .route("", web::get().to(query_get_handler))
+        .route("/query", web::post().to(query_post_handler))
+        .route("/slug/{slug}", web::get().to(slug_handler))
+        .route("/aggregate", web::post().to(aggregate_handler))
+        .wrap(from_fn(govern_queries))
+}
+
+/// Run the request under its own query budget.
+async fn govern_queries(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let budget = req.app_data::<web::Data<QueryBudgetSettings>>().cloned();
+    let governor = query_governor(req.path(), budget.as_ref().map(|b| b.get_ref()));
+    governed(governor, next.call(req)).await
+}
+
+#[tracing::instrument(skip_all)]
+async fn query_get_handler(req: HttpRequest) -> HttpResponse {
+    match ContentQuery::from_query_string(req.query_string()) {
+        Ok(q) => run_query(&SiteContent::of(&req), q.published_only()).await,
+        Err(e) => e.to_response(),
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn query_post_handler(req: HttpRequest, body: web::Json<Json>) -> HttpResponse {
+    match ContentQuery::from_json_body(&body) {
+        Ok(q) => run_query(&SiteContent::of(&req), q.published_only()).await,
+        Err(e) => e.to_response(),
+    }
+}
+
+/// Whether a looked-up record is one the public may see right now.
+fn is_public(doc: &Json) -> bool {
+    serde_json::from_value::<IndexRecord>(doc.clone()).is_ok_and(|rec| rec.is_public_at(Utc::now()))
+}
+
+#[tracing::instrument(skip_all)]
+async fn slug_handler(req: HttpRequest, slug: web::Path<String>) -> HttpResponse {
+    match SiteContent::of(&req)
+        .lookup_front_matter_by_slug(&slug)
+        .await
+    {
+        Ok(Some(doc)) if is_public(&doc) => HttpResponse::Ok().json(doc),
+        Ok(_) => HttpResponse::NotFound().json(json!({ "error": "not found" })),
+        Err(e) => ContentApiError::from(e).to_response(),
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn aggregate_handler(req: HttpRequest, body: web::Json<Json>) -> HttpResponse {
+    match ContentAggregate::from_json_body(&body) {
+        Ok(a) => run_aggregate(&SiteContent::of(&req), a.published_only()).await,
+        Err(e) => e.to_response(),
+    }
+}
+
+pub(crate) async fn run_aggregate(content: &SiteContent, a: ContentAggregate) -> HttpResponse {
+    let specs: Vec<GroupSpec> = a.facets.iter().map(|(_, spec)| spec.clone()).collect();
+    match content.aggregate_front_matter(&a.filter, &specs).await {
+        Ok(rows) => {
+            let facets: serde_json::Map<String, Json> = a
+                .facets
+                .into_iter()
+                .zip(rows)
+                .map(|((name, _), rows)| (name, json!(rows)))
+                .collect();
+            HttpResponse::Ok().json(json!({ "facets": facets }))
+        }
+        Err(e) => {
+            error!("content api aggregate failed: {}", e);
+            ContentApiError::from(e).to_response()
+        }
+    }
+}
+
+pub(crate) async fn run_query(content: &SiteContent, q: ContentQuery) -> HttpResponse {
+    match content.query_front_matter(&q.filter, &q.opts).await {
+        Ok((total, items, next)) => HttpResponse::Ok().json(json!({
+            "items": items,
+            "total": total,
+            "limit": q.opts.limit,
+            "skip": q.opts.skip.unwrap_or(0),
+            "next": next.map(|c| c.encode()),
+        })),
+        Err(e) => {
+            error!("content api query failed: {}", e);
+            ContentApiError::from(e).to_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_string_defaults_to_match_all_with_default_limit() {
+        let q = ContentQuery::from_query_string("").unwrap();
+        assert!(matches!(q.filter, Filter::And(ref v) if v.is_empty()));
+        assert_eq!(q.opts.limit, Some(DEFAULT_LIMIT));
+        assert_eq!(q.opts.skip, None);
+    }
+
+    #[test]
+    fn query_string_parses_filter_sort_and_paging() {
+        let raw = "filter=%7B%22type%22%3A%22post%22%7D&sort=-publish.date,slug&limit=5&skip=10";
+        let q = ContentQuery::from_query_string(raw).unwrap();
+
+        match q.filter {
+            Filter::Field(fe) => assert_eq!(fe.path, "type"),
+            other => panic!("expected field filter, got {:?}", other),
+        }
+        assert_eq!(
+            q.opts.sort,
+            vec![("publish.date".to_string(), -1), ("slug".to_string(), 1)]
+        );
+        assert_eq!(q.opts.limit, Some(5));
+        assert_eq!(q.opts.skip, Some(10));
+    }
+
+    #[test]
+    fn query_string_takes_an_after_cursor() {
+        let sort = vec![("publish.date".to_string(), -1)];
+        let cursor = Cursor::after(
+            &json!({ "id": "/a", "publish": { "date": "2026-01-02" } }),
+            &sort,
+        );
+        let raw = format!("sort=-publish.date&after={}", cursor.encode());
+
+        let q = ContentQuery::from_query_string(&raw).unwrap();
+        assert_eq!(q.opts.after, Some(cursor));
+        assert!(matches!(
+            ContentQuery::from_query_string("after=zz"),
+            Err(ContentApiError::Query(QueryError::InvalidCursor(_)))
+        ));
+    }
+
+    #[test]
+    fn query_string_limit_is_clamped() {
+        let q = ContentQuery::from_query_string("limit=100000").unwrap();
+        assert_eq!(q.opts.limit, Some(MAX_LIMIT));
+    }
+
+    #[test]
+    fn query_string_rejects_bad_input() {
+        assert!(matches!(
+            ContentQuery::from_query_string("filter=not-json"),
+            Err(ContentApiError::InvalidParam(..))
+        ));
+        assert!(matches!(
+            ContentQuery::from_query_string("limit=-1"),
+            Err(ContentApiError::InvalidParam(..))
+        ));
+        assert!(matches!(
+            ContentQuery::from_query_string("filter=%7B%22a%22%3A%7B%22%24bogus%22%3A1%7D%7D"),
+            Err(ContentApiError::Query(_))
+        ));
+    }
+
+    #[test]
+    fn json_body_parses_like_find_options() {
+        let body = json!({
+            "filter": { "publish.status": "published" },
+            "sort": { "nav.menu_order": 1 },
+            "skip": 2
+        });
+        let q = ContentQuery::from_json_body(&body).unwrap();
+
+        assert!(matches!(q.filter, Filter::Field(_)));
+        assert_eq!(q.opts.sort, vec![("nav.menu_order".to_string(), 1)]);
+        assert_eq!(q.opts.limit, Some(DEFAULT_LIMIT));
+        assert_eq!(q.opts.skip, Some(2));
+    }
+
+    #[test]
+    fn public_queries_leave_out_drafts() {
+        use adapt::mql::eval_filter;
+
+        let q = ContentQuery::from_json_body(&json!({ "filter": { "type": "post" } }))
+            .unwrap()
+            .published_only();
+        let post = |status: &str| json!({ "type": "post", "publish": { "status": status } });
+
+        assert!(eval_filter(&q.filter, &post("published")));
+        assert!(eval_filter(&q.filter, &json!({ "type": "post" })));
+        assert!(eval_filter(
+            &q.filter,
+            &json!({ "type": "post", "publish": { "status": null } })
+        ));
+        for hidden in ["draft", "pending", "private", "scheduled"] {
+            assert!(!eval_filter(&q.filter, &post(hidden)), "{hidden}");
+        }
+    }
+
+    #[test]
+    fn slug_lookups_agree_with_public_queries() {
+        use adapt::mql::eval_filter;
+
+        let q = ContentQuery::from_json_body(&json!({}))
+            .unwrap()
+            .published_only();
+        let record = |publish: Json| {
+            serde_json::to_value(IndexRecord::from_json_with_id(
+                "/a.md".into(),
+                &json!({ "publish": publish }),
+            ))
+            .unwrap()
+        };
+
+        for status in ["published", "draft", "pending", "private"] {
+            let doc = record(json!({ "status": status }));
+            assert_eq!(is_public(&doc), eval_filter(&q.filter, &doc), "{status}");
+        }
+        assert!(is_public(&record(json!({}))));
+        // Dates are the archive's half: a future one hides a published record.
+        assert!(!is_public(&record(
+            json!({ "status": "published", "date": "2999-01-01T00:00:00Z" })
+        )));
+    }
+
+    #[test]
+    fn aggregates_need_facets_and_leave_out_drafts() {
+        use adapt::mql::eval_filter;
+
+        assert!(ContentAggregate::from_json_body(&json!({ "filter": {} })).is_err());
+        assert!(
+            ContentAggregate::from_json_body(&json!({ "facets": { "tags": { "by": 1 } } }))
+                .is_err()
+        );
+
+        let a = ContentAggregate::from_json_body(&json!({
+            "filter": { "type": "post" },
+            "facets": { "tags": "tax.tags", "months": { "by": "publish.date", "bucket": "month" } },
+        }))
+        .unwrap()
+        .published_only();
+        let names: Vec<_> = a.facets.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(names, ["months", "tags"]);
+        assert!(!eval_filter(
+            &a.filter,
+            &json!({ "type": "post", "publish": { "status": "draft" } })
+        ));
+    }
+
+    #[test]
+    fn queries_over_budget_are_unavailable_not_bad() {
+        let over = ContentApiError::Index(FrontMatterIndexError::Query(
+            QueryError::BudgetExceeded("/api/content scanned more than 10 documents".into()),
+        ));
+        assert_eq!(over.to_response().status(), 503);
+        let bad = ContentApiError::Query(QueryError::InvalidFilter("x".into()));
+        assert_eq!(bad.to_response().status(), 400);
+    }
+}
@@ -0,0 +1,1029 @@
+// crates/edge/src/api/extensions.rs
+
+// Plugin and theme management for admins (see auth):
+//
+//   GET /admin/api/extensions
+//       { plugins: [{ id, name, dir, enabled, settings, config }],
+//         themes:  [{ id, name, dir, mount, serving, settings, config }] }
+//   PUT /admin/api/extensions/plugins/{id}/enabled    { enabled }
+//   PUT /admin/api/extensions/plugins/{id}/settings   { <key>: <value>, .. }
+//   PUT /admin/api/extensions/themes/{id}/settings    { <key>: <value>, .. }
+//   PUT /admin/api/extensions/themes/{id}/active      { mount }
+//
+// `settings` is the schema from the manifest's `[settings]`
+// (adapt::runtime::settings), which is what an admin screen builds its form
+// from; `config` is what is stored for the site. New settings are checked
+// against the schema before anything is saved, and a rejection lists every
+// problem. `serving` is the mounts a theme answers on, which may differ from
+// the `mount` in its theme.toml once another theme is picked for that mount.
+//
+// The same, server-rendered for a browser (crate::form_pages), each form
+// posting back to its page and returning to the list when it is saved:
+//
+//   GET  /admin/extensions                          plugins and themes
+//   POST /admin/extensions/plugins/{id}/enabled     enabled=true|false
+//   POST /admin/extensions/themes/{id}/active       mount=/..
+//   GET  /admin/extensions/{kind}/{id}/settings     form built from `settings`
+//   POST /admin/extensions/{kind}/{id}/settings
+//
+// Settings are typed from the schema: numbers and strings as inputs, `enum`
+// as a select, booleans as a checkbox, arrays and objects as JSON. A field
+// left empty falls back to the default. Someone not logged in is sent to
+// /admin/login first.
+//
+// Every change is a file in the site directory: the plugin `disabled` marker
+// (as in the TUI) and db::ext_settings. The extension watcher reloads the
+// affected runtimes and, for a new pick, the router; each change is audited
+// with a before/after diff.
+
+use crate::audit::{self, AuditNote};
+use crate::auth::{Admin, AuthError, RequireRole};
+use crate::db::ext_settings::{self, EXT_SETTINGS_DIR};
+use crate::db::history::ExtKind;
+use crate::form_pages;
+use crate::fs::ext::{self, DiscoveredPlugin, DiscoveredTheme};
+use crate::fs::reload;
+use actix_web::{
+    dev::HttpServiceFactory, http::StatusCode, web, HttpRequest, HttpResponse, ResponseError,
+};
+use adapt::runtime::error::RuntimeError;
+use adapt::runtime::settings::{SettingDef, SettingType, SettingsSchema};
+use html_escape::encode_text as text;
+use serde::Deserialize;
+use serde_json::{json, Map as JsonMap, Value as Json};
+use serve::render::form::{FieldKind, FieldSpec, FormErrors, FormSpec, FormValues};
+use std::fmt::Write as _;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use tracing::{error, info};
+
+/// Where the extensions and the site's choices about them live.
+#[derive(Debug, Clone)]
+struct Extensions {
+    ext_dir: PathBuf,
+    /// `<site>/ext_settings`
+    stored: PathBuf,
+}
+
+/// Build the `/admin/api/extensions` scope for the site in `root_dir`,
+/// managing the extensions under `ext_dir`.
+pub fn scope(root_dir: &Path, ext_dir: PathBuf) -> impl HttpServiceFactory {
+    scope_with(Extensions::of_site(root_dir, ext_dir))
+}
+
+/// Build the `/admin/extensions` pages for the site in `root_dir`,
+/// managing the extensions under `ext_dir`.
+pub fn pages(root_dir: &Path, ext_dir: PathBuf) -> impl HttpServiceFactory {
+    pages_with(Extensions::of_site(root_dir, ext_dir))
+}
+
+fn scope_with(extensions: Extensions) -> impl HttpServiceFactory {
+    web::scope("/admin/api/extensions")
+        .app_data(web::Data::new(extensions))
+        .route("", web::get().to(list_handler))
+        .route("/plugins/{id}/enabled", web::put().to(enable_handler))
+        .route("/{kind}/{id}/settings", web::put().to(settings_handler))
+        .route("/themes/{id}/active", web::put().to(activate_handler))
+}
+
+fn pages_with(extensions: Extensions) -> impl HttpServiceFactory {
+    web::scope("/admin/extensions")
+        .app_data(web::Data::new(extensions))
+        .route("", web::get().to(list_page_handler))
+        .route("/plugins/{id}/enabled", web::post().to(enable_form_handler))
+        .route("/themes/{id}/active", web::post().to(activate_form_handler))
+        .route(
+            "/{kind}/{id}/settings",
+            web::get().to(settings_page_handler),
+        )
+        .route(
+            "/{kind}/{id}/settings",
+            web::post().to(settings_form_handler),
+        )
+}
+
+/// The kind a `plugins` or `themes` path segment names.
+fn ext_kind(segment: &str) -> Option<ExtKind> {
+    [ExtKind::Plugin, ExtKind::Theme]
+        .into_iter()
+        .find(|kind| kind.dir_name() == segment)
+}
+
+#[derive(Debug, Error)]
+pub enum ExtensionsError {
+    #[error("no {0} {1:?}")]
+    NotFound(ExtKind, String),
+
+    #[error("invalid settings: {0}")]
+    Settings(String),
+
+    #[error("invalid mount {0:?}: mounts start with '/'")]
+    Mount(String),
+
+    #[error("discovery: {0}")]
+    Discovery(#[from] RuntimeError),
+
+    #[error("I/O: {0}")]
+    Io(#[from] io::Error),
+}
+
+impl ResponseError for ExtensionsError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ExtensionsError::NotFound(..) => StatusCode::NOT_FOUND,
+            ExtensionsError::Settings(_) | ExtensionsError::Mount(_) => StatusCode::BAD_REQUEST,
+            ExtensionsError::Discovery(_) | ExtensionsError::Io(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        if self.status_code().is_server_error() {
+            error!("Admin extensions request failed: {}", self);
+        }
+        HttpResponse::build(self.status_code()).json(json!({ "error": self.to_string() }))
+    }
+}
+
+impl From<actix_web::error::BlockingError> for ExtensionsError {
+    fn from(e: actix_web::error::BlockingError) -> Self {
+        ExtensionsError::Io(io::Error::other(e))
+    }
+}
+
+impl Extensions {
+    fn of_site(root_dir: &Path, ext_dir: PathBuf) -> Self {
+        Extensions {
+            ext_dir,
+            stored: root_dir.join(EXT_SETTINGS_DIR),
+        }
+    }
+
+    fn plugins(&self) -> Result<Vec<DiscoveredPlugin>, ExtensionsError> {
+        Ok(ext::discover_all_plugins(self.ext_dir.join("plugins/"))?)
+    }
+
+    fn themes(&self) -> Result<Vec<DiscoveredTheme>, ExtensionsError> {
+        Ok(ext::discover_themes(self.ext_dir.join("themes/"))?)
+    }
+
+    fn plugin(&self, id: &str) -> Result<DiscoveredPlugin, ExtensionsError> {
+        self.plugins()?
+            .into_iter()
+            .find(|p| p.spec.id == id)
+            .ok_or_else(|| ExtensionsError::NotFound(ExtKind::Plugin, id.to_owned()))
+    }
+
+    fn theme(&self, id: &str) -> Result<DiscoveredTheme, ExtensionsError> {
+        self.themes()?
+            .into_iter()
+            .find(|t| t.spec.id == id)
+            .ok_or_else(|| ExtensionsError::NotFound(ExtKind::Theme, id.to_owned()))
+    }
+
+    fn list(&self) -> Result<Json, ExtensionsError> {
+        let plugins = self
+            .plugins()?
+            .iter()
+            .map(|p| {
+                Ok(json!({
+                    "id": p.spec.id,
+                    "name": p.spec.name,
+                    "dir": p.dir,
+                    "enabled": ext::plugin_enabled(&p.dir),
+                    "settings": p.spec.settings,
+                    "config": ext_settings::stored(&self.stored, ExtKind::Plugin, &p.spec.id)?,
+                }))
+            })
+            .collect::<Result<Vec<_>, ExtensionsError>>()?;
+
+        let themes = self.themes()?;
+        let bindings = ext_settings::theme_bindings(&self.stored, &themes);
+        let themes = themes
+            .iter()
+            .map(|t| {
+                let serving: Vec<&str> = bindings
+                    .iter()
+                    .filter(|b| b.theme_id == t.spec.id)
+                    .map(|b| b.mount_path.as_str())
+                    .collect();
+                Ok(json!({
+                    "id": t.spec.id,
+                    "name": t.spec.name,
+                    "dir": t.dir,
+                    "mount": t.mount_path,
+                    "serving": serving,
+                    "settings": t.spec.settings,
+                    "config": ext_settings::stored(&self.stored, ExtKind::Theme, &t.spec.id)?,
+                }))
+            })
+            .collect::<Result<Vec<_>, ExtensionsError>>()?;
+
+        Ok(json!({ "plugins": plugins, "themes": themes }))
+    }
+
+    /// `id`'s folder, display name and settings schema.
+    fn describe(
+        &self,
+        kind: ExtKind,
+        id: &str,
+    ) -> Result<(PathBuf, String, SettingsSchema), ExtensionsError> {
+        Ok(match kind {
+            ExtKind::Plugin => {
+                let p = self.plugin(id)?;
+                (p.dir, p.spec.name, p.spec.settings)
+            }
+            ExtKind::Theme => {
+                let t = self.theme(id)?;
+                (t.dir, t.spec.name, t.spec.settings)
+            }
+        })
+    }
+
+    /// Check `values` against `id`'s schema and store them; returns what
+    /// was stored before, the manifest whose reload applies them, and the
+    /// values as the extension will see them.
+    fn save_settings(
+        &self,
+        kind: ExtKind,
+        id: &str,
+        values: Json,
+    ) -> Result<(Json, PathBuf, Json), ExtensionsError> {
+        let (dir, _, schema) = self.describe(kind, id)?;
+        let resolved = schema.resolve(&values).map_err(ExtensionsError::Settings)?;
+        let before = ext_settings::stored(&self.stored, kind, id)?;
+        ext_settings::save(&self.stored, kind, id, &values)?;
+        Ok((before, dir.join(kind.manifest_name()), resolved))
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn list_handler(
+    _auth: RequireRole<Admin>,
+    extensions: web::Data<Extensions>,
+) -> Result<HttpResponse, ExtensionsError> {
+    let listed = web::block(move || extensions.list()).await??;
+    Ok(HttpResponse::Ok().json(listed))
+}
+
+#[derive(Debug, Deserialize)]
+struct Enabled {
+    enabled: bool,
+}
+
+#[tracing::instrument(skip_all)]
+async fn enable_handler(
+    auth: RequireRole<Admin>,
+    extensions: web::Data<Extensions>,
+    req: HttpRequest,
+    id: web::Path<String>,
+    body: web::Json<Enabled>,
+) -> Result<HttpResponse, ExtensionsError> {
+    let id = id.into_inner();
+    let enabled = body.enabled;
+    set_enabled(&auth, extensions, &req, &id, enabled).await?;
+    Ok(HttpResponse::Ok().json(json!({ "id": id, "enabled": enabled })))
+}
+
+async fn set_enabled(
+    auth: &RequireRole<Admin>,
+    extensions: web::Data<Extensions>,
+    req: &HttpRequest,
+    id: &str,
+    enabled: bool,
+) -> Result<(), ExtensionsError> {
+    let was = {
+        let id = id.to_owned();
+        web::block(move || {
+            let plugin = extensions.plugin(&id)?;
+            let was = ext::plugin_enabled(&plugin.dir);
+            // The watcher sees the marker itself.
+            ext::set_plugin_enabled(&plugin.dir, enabled)?;
+            Ok::<_, ExtensionsError>(was)
+        })
+        .await??
+    };
+    audit::annotate(
+        req,
+        AuditNote::new("plugin.enabled", format!("plugin:{id}"))
+            .with_change(&json!({ "enabled": was }), &json!({ "enabled": enabled })),
+    );
+    info!(
+        "{} {} plugin {}",
+        auth.user.username,
+        if enabled { "enabled" } else { "disabled" },
+        id
+    );
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn settings_handler(
+    auth: RequireRole<Admin>,
+    extensions: web::Data<Extensions>,
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    values: web::Json<Json>,
+) -> Result<HttpResponse, ExtensionsError> {
+    let (kind, id) = path.into_inner();
+    let Some(kind) = ext_kind(&kind) else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let resolved = save_settings(&auth, extensions, &req, kind, &id, values.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(json!({ "id": id, "config": resolved })))
+}
+
+async fn save_settings(
+    auth: &RequireRole<Admin>,
+    extensions: web::Data<Extensions>,
+    req: &HttpRequest,
+    kind: ExtKind,
+    id: &str,
+    values: Json,
+) -> Result<Json, ExtensionsError> {
+    let (before, manifest, resolved) = {
+        let (id, values) = (id.to_owned(), values.clone());
+        web::block(move || extensions.save_settings(kind, &id, values)).await??
+    };
+    reload::notify(req, &manifest);
+    audit::annotate(
+        req,
+        AuditNote::new(format!("{kind}.settings"), format!("{kind}:{id}"))
+            .with_change(&before, &values),
+    );
+    info!("{} changed {} {} settings", auth.user.username, kind, id);
+    Ok(resolved)
+}
+
+#[derive(Debug, Deserialize)]
+struct Activate {
+    mount: String,
+}
+
+#[tracing::instrument(skip_all)]
+async fn activate_handler(
+    auth: RequireRole<Admin>,
+    extensions: web::Data<Extensions>,
+    req: HttpRequest,
+    id: web::Path<String>,
+    body: web::Json<Activate>,
+) -> Result<HttpResponse, ExtensionsError> {
+    let id = id.into_inner();
+    let mount = body.into_inner().mount;
+    activate(&auth, extensions, &req, &id, &mount).await?;
+    Ok(HttpResponse::Ok().json(json!({ "id": id, "mount": mount })))
+}
+
+async fn activate(
+    auth: &RequireRole<Admin>,
+    extensions: web::Data<Extensions>,
+    req: &HttpRequest,
+    id: &str,
+    mount: &str,
+) -> Result<(), ExtensionsError> {
+    if !mount.starts_with('/') {
+        return Err(ExtensionsError::Mount(mount.to_owned()));
+    }
+
+    let (before, manifest) = {
+        let (id, mount) = (id.to_owned(), mount.to_owned());
+        web::block(move || {
+            let theme = extensions.theme(&id)?;
+            let before = ext_settings::active_themes(&extensions.stored).remove(&mount);
+            ext_settings::set_active_theme(&extensions.stored, &mount, &id)?;
+            Ok::<_, ExtensionsError>((before, theme.dir.join(ExtKind::Theme.manifest_name())))
+        })
+        .await??
+    };
+    reload::notify(req, &manifest);
+    audit::annotate(
+        req,
+        AuditNote::new("theme.activate", format!("mount:{mount}"))
+            .with_change(&json!({ "theme": before }), &json!({ "theme": id })),
+    );
+    info!("{} made theme {} serve {}", auth.user.username, id, mount);
+    Ok(())
+}
+
+/// Where the pages return to once a change is saved.
+const LIST_PAGE: &str = "/admin/extensions";
+
+#[tracing::instrument(skip_all)]
+async fn list_page_handler(
+    req: HttpRequest,
+    auth: Result<RequireRole<Admin>, AuthError>,
+    extensions: web::Data<Extensions>,
+) -> Result<HttpResponse, ExtensionsError> {
+    if let Err(res) = form_pages::signed_in(&req, auth) {
+        return Ok(res);
+    }
+    let (plugins, themes) = web::block(move || {
+        let plugins: Vec<_> = extensions
+            .plugins()?
+            .into_iter()
+            .map(|p| {
+                let enabled = ext::plugin_enabled(&p.dir);
+                (p, enabled)
+            })
+            .collect();
+        let themes = extensions.themes()?;
+        let bindings = ext_settings::theme_bindings(&extensions.stored, &themes);
+        let themes: Vec<_> = themes
+            .into_iter()
+            .map(|t| {
+                let serving: Vec<String> = bindings
+                    .iter()
+                    .filter(|b| b.theme_id == t.spec.id)
+                    .map(|b| b.mount_path.clone())
+                    .collect();
+                (t, serving)
+            })
+            .collect();
+        Ok::<_, ExtensionsError>((plugins, themes))
+    })
+    .await??;
+
+    let mut out = String::from(concat!(
+        "<!DOCTYPE html><html lang=\"en\"><head><meta charset=\"utf-8\">",
+        "<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">",
+        "<title>Extensions</title></head><body><main id=\"main\"><h1>Extensions</h1>",
+        "<h2>Plugins</h2><ul class=\"extensions\">"
+    ));
+    for (plugin, enabled) in &plugins {
+        let spec = &plugin.spec;
+        let toggle = FormSpec::new(
+            format!("plugin-{}-enabled", spec.id),
+            &spec.name,
+            format!("{LIST_PAGE}/plugins/{}/enabled", spec.id),
+        )
+        .with_field(FieldSpec::new("enabled", "", FieldKind::Hidden))
+        .with_submit_label(if *enabled { "Disable" } else { "Enable" });
+        let values = FormValues::from([("enabled".to_string(), (!enabled).to_string())]);
+        let state = if *enabled { "enabled" } else { "disabled" };
+        list_item(
+            &mut out,
+            ExtKind::Plugin,
+            &spec.id,
+            &spec.name,
+            state,
+            &spec.settings,
+        );
+        out.push_str(&form_pages::fragment(&req, &toggle, &values));
+        out.push_str("</li>");
+    }
+    out.push_str("</ul><h2>Themes</h2><ul class=\"extensions\">");
+    for (theme, serving) in &themes {
+        let spec = &theme.spec;
+        let state = if serving.is_empty() {
+            "not serving".to_string()
+        } else {
+            format!("serving {}", serving.join(", "))
+        };
+        list_item(
+            &mut out,
+            ExtKind::Theme,
+            &spec.id,
+            &spec.name,
+            &state,
+            &spec.settings,
+        );
+        if !serving.contains(&theme.mount_path) {
+            let pick = FormSpec::new(
+                format!("theme-{}-active", spec.id),
+                &spec.name,
+                format!("{LIST_PAGE}/themes/{}/active", spec.id),
+            )
+            .with_field(FieldSpec::new("mount", "", FieldKind::Hidden))
+            .with_submit_label(format!("Serve {}", theme.mount_path));
+            let values = FormValues::from([("mount".to_string(), theme.mount_path.clone())]);
+            out.push_str(&form_pages::fragment(&req, &pick, &values));
+        }
+        out.push_str("</li>");
+    }
+    out.push_str("</ul></main></body></html>");
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(out))
+}
+
+/// Open an extension's `<li>`: its name, state and a link to its settings.
+fn list_item(
+    out: &mut String,
+    kind: ExtKind,
+    id: &str,
+    name: &str,
+    state: &str,
+    settings: &SettingsSchema,
+) {
+    let _ = write!(
+        out,
+        "<li><strong>{}</strong> <span class=\"state\">{}</span>",
+        text(name),
+        text(state)
+    );
+    if !settings.is_empty() {
+        let _ = write!(
+            out,
+            " <a href=\"{LIST_PAGE}/{}/{}/settings\">Settings</a>",
+            kind.dir_name(),
+            text(id)
+        );
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn enable_form_handler(
+    req: HttpRequest,
+    auth: Result<RequireRole<Admin>, AuthError>,
+    extensions: web::Data<Extensions>,
+    id: web::Path<String>,
+    body: web::Bytes,
+) -> Result<HttpResponse, ExtensionsError> {
+    let auth = match form_pages::signed_in(&req, auth) {
+        Ok(auth) => auth,
+        Err(res) => return Ok(res),
+    };
+    let enabled = form_pages::values(&body).get("enabled").map(String::as_str) == Some("true");
+    set_enabled(&auth, extensions, &req, &id, enabled).await?;
+    Ok(form_pages::redirect(&req, LIST_PAGE))
+}
+
+#[tracing::instrument(skip_all)]
+async fn activate_form_handler(
+    req: HttpRequest,
+    auth: Result<RequireRole<Admin>, AuthError>,
+    extensions: web::Data<Extensions>,
+    id: web::Path<String>,
+    body: web::Bytes,
+) -> Result<HttpResponse, ExtensionsError> {
+    let auth = match form_pages::signed_in(&req, auth) {
+        Ok(auth) => auth,
+        Err(res) => return Ok(res),
+    };
+    let mount = form_pages::values(&body)
+        .remove("mount")
+        .unwrap_or_default();
+    activate(&auth, extensions, &req, &id, &mount).await?;
+    Ok(form_pages::redirect(&req, LIST_PAGE))
+}
+
+#[tracing::instrument(skip_all)]
+async fn settings_page_handler(
+    req: HttpRequest,
+    auth: Result<RequireRole<Admin>, AuthError>,
+    extensions: web::Data<Extensions>,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, ExtensionsError> {
+    if let Err(res) = form_pages::signed_in(&req, auth) {
+        return Ok(res);
+    }
+    let (kind, id) = path.into_inner();
+    let Some(kind) = ext_kind(&kind) else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let (name, schema, stored) = web::block(move || {
+        let (_, name, schema) = extensions.describe(kind, &id)?;
+        let stored = ext_settings::stored(&extensions.stored, kind, &id)?;
+        Ok::<_, ExtensionsError>((name, schema, stored))
+    })
+    .await??;
+
+    let spec = settings_form(kind, &name, &schema, &req);
+    let values = settings_values(&schema, &stored);
+    Ok(form_pages::respond(
+        &req,
+        StatusCode::OK,
+        &spec,
+        &values,
+        &FormErrors::default(),
+    ))
+}
+
+#[tracing::instrument(skip_all)]
+async fn settings_form_handler(
+    req: HttpRequest,
+    auth: Result<RequireRole<Admin>, AuthError>,
+    extensions: web::Data<Extensions>,
+    path: web::Path<(String, String)>,
+    body: web::Bytes,
+) -> Result<HttpResponse, ExtensionsError> {
+    let auth = match form_pages::signed_in(&req, auth) {
+        Ok(auth) => auth,
+        Err(res) => return Ok(res),
+    };
+    let (kind, id) = path.into_inner();
+    let Some(kind) = ext_kind(&kind) else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let (name, schema) = {
+        let (extensions, id) = (extensions.clone(), id.clone());
+        web::block(move || {
+            let (_, name, schema) = extensions.describe(kind, &id)?;
+            Ok::<_, ExtensionsError>((name, schema))
+        })
+        .await??
+    };
+
+    let spec = settings_form(kind, &name, &schema, &req);
+    let values = form_pages::values(&body);
+    let mut errors = spec.validate_required(&values);
+    let parsed = parse_settings(&schema, &values, &mut errors);
+    if errors.is_empty() {
+        match save_settings(&auth, extensions, &req, kind, &id, parsed).await {
+            Ok(_) => return Ok(form_pages::redirect(&req, LIST_PAGE)),
+            Err(ExtensionsError::Settings(problems)) => errors.add_form(problems),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(form_pages::respond(
+        &req,
+        StatusCode::UNPROCESSABLE_ENTITY,
+        &spec,
+        &values,
+        &errors,
+    ))
+}
+
+/// The form for an extension's settings, one field per key of `schema`.
+fn settings_form(
+    kind: ExtKind,
+    name: &str,
+    schema: &SettingsSchema,
+    req: &HttpRequest,
+) -> FormSpec {
+    let mut spec = FormSpec::new(
+        format!("{kind}-settings"),
+        format!("{name} settings"),
+        req.path(),
+    )
+    .with_submit_label("Save");
+    for (key, def) in &schema.0 {
+        let kind = match (&def.kind, &def.choices) {
+            (SettingType::Boolean, _) => FieldKind::Checkbox,
+            (_, Some(choices)) => {
+                let mut options: Vec<(String, String)> = choices
+                    .iter()
+                    .map(|c| (form_text(c), form_text(c)))
+                    .collect();
+                if !def.required {
+                    options.insert(0, (String::new(), String::new()));
+                }
+                FieldKind::Select { options }
+            }
+            (SettingType::Integer | SettingType::Number, None) => FieldKind::Number,
+            (SettingType::String, None) => FieldKind::Text,
+            (SettingType::Array | SettingType::Object, None) => FieldKind::TextArea { rows: 4 },
+        };
+        let mut field = FieldSpec::new(key, key, kind);
+        if def.required && def.kind != SettingType::Boolean {
+            field = field.required();
+        }
+        if let Some(help) = setting_help(def) {
+            field = field.with_help(help);
+        }
+        spec = spec.with_field(field);
+    }
+    spec
+}
+
+/// A setting's description, and what an empty field falls back to.
+fn setting_help(def: &SettingDef) -> Option<String> {
+    let default = def
+        .default
+        .as_ref()
+        .filter(|_| def.kind != SettingType::Boolean)
+        .map(|d| format!("Empty for the default, {}.", form_text(d)));
+    match (&def.description, default) {
+        (Some(description), Some(default)) => Some(format!("{description} {default}")),
+        (description, default) => description.clone().or(default),
+    }
+}
+
+/// A stored setting as a form value: strings bare, the rest as JSON.
+fn form_text(value: &Json) -> String {
+    match value {
+        Json::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// The stored settings as form values; a checkbox shows its default when
+/// nothing is stored.
+fn settings_values(schema: &SettingsSchema, stored: &Json) -> FormValues {
+    schema
+        .0
+        .iter()
+        .filter_map(|(key, def)| {
+            let value = stored.get(key).or(match def.kind {
+                SettingType::Boolean => def.default.as_ref(),
+                _ => None,
+            })?;
+            Some((key.clone(), form_text(value)))
+        })
+        .collect()
+}
+
+/// The submitted `values` typed by `schema`, leaving out empty fields so
+/// their defaults apply; a value that is not its type is added to `errors`.
+fn parse_settings(schema: &SettingsSchema, values: &FormValues, errors: &mut FormErrors) -> Json {
+    let mut parsed = JsonMap::new();
+    for (key, def) in &schema.0 {
+        let raw = values.get(key).map(String::as_str).unwrap_or("");
+        if def.kind == SettingType::Boolean {
+            parsed.insert(key.clone(), Json::Bool(matches!(raw, "on" | "true" | "1")));
+            continue;
+        }
+        if raw.trim().is_empty() {
+            continue;
+        }
+        let value = match def.kind {
+            SettingType::String => Some(Json::String(raw.to_owned())),
+            SettingType::Integer => raw.trim().parse::<i64>().ok().map(Json::from),
+            SettingType::Number => raw.trim().parse::<f64>().ok().map(Json::from),
+            SettingType::Array | SettingType::Object | SettingType::Boolean => {
+                serde_json::from_str(raw).ok()
+            }
+        };
+        match value {
+            Some(value) => {
+                parsed.insert(key.clone(), value);
+            }
+            None => errors.add(key, format!("{key} must be {}.", type_phrase(def.kind))),
+        }
+    }
+    Json::Object(parsed)
+}
+
+fn type_phrase(kind: SettingType) -> &'static str {
+    match kind {
+        SettingType::String => "text",
+        SettingType::Integer => "a whole number",
+        SettingType::Number => "a number",
+        SettingType::Boolean => "true or false",
+        SettingType::Array => "a JSON array",
+        SettingType::Object => "a JSON object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{self, SESSION_COOKIE};
+    use crate::csrf::CsrfKey;
+    use crate::db::users::{Role, ValidatedPassword};
+    use actix_web::cookie::Cookie;
+    use actix_web::{middleware::from_fn, test as actix_test, App};
+    use std::fs;
+
+    #[actix_web::test]
+    async fn admins_list_configure_and_activate_extensions() {
+        let site = tempfile::tempdir().unwrap();
+        let ext_dir = site.path().join("extensions");
+        let seo = ext_dir.join("plugins/seo");
+        fs::create_dir_all(&seo).unwrap();
+        fs::write(
+            seo.join("plugin.toml"),
+            "id = \"seo\"\n[settings.suffix]\ntype = \"string\"\ndefault = \" | Blog\"\nmax = 20\n",
+        )
+        .unwrap();
+        fs::write(seo.join("plugin.js"), "").unwrap();
+        for (id, mount) in [("plain", "/"), ("bold", "/")] {
+            let dir = ext_dir.join("themes").join(id);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("theme.toml"), format!("mount = \"{mount}\"\n")).unwrap();
+            fs::write(dir.join("theme.js"), "").unwrap();
+        }
+
+        let store = auth::store(site.path());
+        let password = ValidatedPassword::new("a long enough secret").unwrap();
+        let mut cookies = Vec::new();
+        for (name, role) in [("ada", Role::Admin), ("ed", Role::Editor)] {
+            let user = store.create(name, role, &password).unwrap();
+            let token = store
+                .issue_session(&user, chrono::Duration::hours(1))
+                .unwrap();
+            cookies.push(Cookie::new(SESSION_COOKIE, token));
+        }
+        let (ada, ed) = (cookies[0].clone(), cookies[1].clone());
+
+        let app = actix_test::init_service(
+            App::new().service(
+                web::scope("")
+                    .app_data(store)
+                    .app_data(audit::log(site.path()))
+                    .service(scope_with(Extensions {
+                        ext_dir: ext_dir.clone(),
+                        stored: site.path().join(EXT_SETTINGS_DIR),
+                    }))
+                    .wrap(from_fn(audit::record)),
+            ),
+        )
+        .await;
+        let put = |uri: &str, body: Json, cookie: &Cookie<'static>| {
+            actix_test::TestRequest::put()
+                .uri(uri)
+                .cookie(cookie.clone())
+                .set_json(body)
+                .to_request()
+        };
+
+        let req = put(
+            "/admin/api/extensions/plugins/seo/enabled",
+            json!({ "enabled": false }),
+            &ed,
+        );
+        let res = actix_test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let req = put(
+            "/admin/api/extensions/plugins/seo/enabled",
+            json!({ "enabled": false }),
+            &ada,
+        );
+        assert!(actix_test::call_service(&app, req)
+            .await
+            .status()
+            .is_success());
+        assert!(!ext::plugin_enabled(&seo));
+
+        let req = put(
+            "/admin/api/extensions/plugins/seo/settings",
+            json!({ "suffix": "a suffix far too long to fit", "colour": "red" }),
+            &ada,
+        );
+        let res = actix_test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let body: Json = actix_test::read_body_json(res).await;
+        let err = body["error"].as_str().unwrap();
+        assert!(
+            err.contains("`colour`") && err.contains("`suffix`"),
+            "{err}"
+        );
+
+        let req = put(
+            "/admin/api/extensions/plugins/seo/settings",
+            json!({}),
+            &ada,
+        );
+        let body: Json = actix_test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["config"], json!({ "suffix": " | Blog" }));
+
+        let req = put(
+            "/admin/api/extensions/themes/bold/active",
+            json!({ "mount": "/" }),
+            &ada,
+        );
+        assert!(actix_test::call_service(&app, req)
+            .await
+            .status()
+            .is_success());
+        let req = put(
+            "/admin/api/extensions/themes/gone/active",
+            json!({ "mount": "/" }),
+            &ada,
+        );
+        let res = actix_test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        let req = actix_test::TestRequest::get()
+            .uri("/admin/api/extensions")
+            .cookie(ada.clone())
+            .to_request();
+        let listed: Json = actix_test::call_and_read_body_json(&app, req).await;
+        assert_eq!(listed["plugins"][0]["enabled"], json!(false));
+        assert_eq!(listed["plugins"][0]["config"], json!({}));
+        assert_eq!(listed["plugins"][0]["settings"]["suffix"]["type"], "string");
+        let serving = |id: &str| {
+            listed["themes"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|t| t["id"] == id)
+                .unwrap()["serving"]
+                .clone()
+        };
+        assert_eq!(serving("bold"), json!(["/"]));
+        assert_eq!(serving("plain"), json!([]));
+    }
+
+    #[actix_web::test]
+    async fn admins_manage_extensions_from_the_pages() {
+        let site = tempfile::tempdir().unwrap();
+        let ext_dir = site.path().join("extensions");
+        let seo = ext_dir.join("plugins/seo");
+        fs::create_dir_all(&seo).unwrap();
+        fs::write(
+            seo.join("plugin.toml"),
+            concat!(
+                "id = \"seo\"\nname = \"SEO\"\n",
+                "[settings.suffix]\ntype = \"string\"\nmax = 20\n",
+                "[settings.depth]\ntype = \"integer\"\ndefault = 2\n",
+            ),
+        )
+        .unwrap();
+        fs::write(seo.join("plugin.js"), "").unwrap();
+
+        let store = auth::store(site.path());
+        let password = ValidatedPassword::new("a long enough secret").unwrap();
+        let user = store.create("ada", Role::Admin, &password).unwrap();
+        let token = store
+            .issue_session(&user, chrono::Duration::hours(1))
+            .unwrap();
+        let ada = Cookie::new(SESSION_COOKIE, token);
+        let app = actix_test::init_service(
+            App::new().service(
+                web::scope("")
+                    .app_data(store)
+                    .app_data(web::Data::new(CsrfKey::random()))
+                    .app_data(audit::log(site.path()))
+                    .service(pages_with(Extensions {
+                        ext_dir: ext_dir.clone(),
+                        stored: site.path().join(EXT_SETTINGS_DIR),
+                    }))
+                    .wrap(from_fn(audit::record)),
+            ),
+        )
+        .await;
+        let post = |uri: &str, body: &'static str| {
+            actix_test::TestRequest::post()
+                .uri(uri)
+                .cookie(ada.clone())
+                .insert_header(("content-type", "application/x-www-form-urlencoded"))
+                .set_payload(body)
+                .to_request()
+        };
+        let page = |res: actix_web::dev::ServiceResponse| async {
+            String::from_utf8(actix_test::read_body(res).await.to_vec()).unwrap()
+        };
+
+        let req = actix_test::TestRequest::get()
+            .uri("/admin/extensions")
+            .to_request();
+        let res = actix_test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::SEE_OTHER);
+        assert_eq!(
+            res.headers().get("location").unwrap(),
+            "/admin/login?next=%2Fadmin%2Fextensions"
+        );
+
+        let req = actix_test::TestRequest::get()
+            .uri("/admin/extensions")
+            .cookie(ada.clone())
+            .to_request();
+        let list = page(actix_test::call_service(&app, req).await).await;
+        assert!(list.contains("SEO") && list.contains("Disable"), "{list}");
+        assert!(list.contains(r#"name="_csrf""#) && list.contains("/plugins/seo/settings"));
+
+        let res = actix_test::call_service(
+            &app,
+            post("/admin/extensions/plugins/seo/enabled", "enabled=false"),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::SEE_OTHER);
+        assert!(!ext::plugin_enabled(&seo));
+
+        let req = actix_test::TestRequest::get()
+            .uri("/admin/extensions/plugins/seo/settings")
+            .cookie(ada.clone())
+            .to_request();
+        let form = page(actix_test::call_service(&app, req).await).await;
+        assert!(form.contains(r#"name="suffix""#) && form.contains(r#"type="number""#));
+        assert!(form.contains("Empty for the default, 2."), "{form}");
+
+        let res = actix_test::call_service(
+            &app,
+            post("/admin/extensions/plugins/seo/settings", "depth=deep"),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(page(res).await.contains("depth must be a whole number."));
+
+        let res = actix_test::call_service(
+            &app,
+            post(
+                "/admin/extensions/plugins/seo/settings",
+                "suffix=a+suffix+far+too+long+to+fit",
+            ),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(page(res).await.contains("`suffix`"));
+
+        let res = actix_test::call_service(
+            &app,
+            post(
+                "/admin/extensions/plugins/seo/settings",
+                "suffix=+%7C+Blog&depth=",
+            ),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::SEE_OTHER);
+        let stored =
+            ext_settings::stored(&site.path().join(EXT_SETTINGS_DIR), ExtKind::Plugin, "seo")
+                .unwrap();
+        assert_eq!(stored, json!({ "suffix": " | Blog" }));
+    }
+}
@@ -0,0 +1,4 @@
+pub mod admin;
+pub mod content;
+pub mod extensions;
+pub mod users;
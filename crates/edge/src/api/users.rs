@@ -0,0 +1,357 @@
+// crates/edge/src/api/users.rs
+
+// Accounts for admins, server-rendered (crate::form_pages); the same as
+// `whispercms user add`, `role` and `remove`:
+//
+//   GET  /admin/users                     every account, with its forms
+//   POST /admin/users                     username, role, password → new account
+//   POST /admin/users/{username}/role     role=viewer|author|editor|admin
+//   POST /admin/users/{username}/remove
+//
+// Each form returns to the list once it is saved; the new-account form
+// comes back with its errors instead. Admins cannot change their own role
+// or remove themselves here, so the site keeps at least the admin who is
+// making changes. Every change is audited.
+
+use crate::audit::{self, AuditNote};
+use crate::auth::{Admin, AuthError, RequireRole};
+use crate::db::store::blocking;
+use crate::db::users::{Role, UserError, UserStore, ValidatedPassword};
+use crate::form_pages;
+use actix_web::{
+    dev::HttpServiceFactory, http::StatusCode, web, HttpRequest, HttpResponse, ResponseError,
+};
+use html_escape::encode_text as text;
+use serde_json::json;
+use serve::render::form::{FieldKind, FieldSpec, FormSpec, FormValues};
+use std::fmt::Write as _;
+use thiserror::Error;
+use tracing::{error, info};
+
+/// Where the forms return to once a change is saved.
+const LIST_PAGE: &str = "/admin/users";
+
+const ROLES: [Role; 4] = [Role::Viewer, Role::Author, Role::Editor, Role::Admin];
+
+/// Build the `/admin/users` pages; the store comes from the root scope.
+pub fn pages() -> impl HttpServiceFactory {
+    web::scope(LIST_PAGE)
+        .route("", web::get().to(list_page_handler))
+        .route("", web::post().to(create_form_handler))
+        .route("/{username}/role", web::post().to(role_form_handler))
+        .route("/{username}/remove", web::post().to(remove_form_handler))
+}
+
+#[derive(Debug, Error)]
+pub enum UsersError {
+    #[error("admins cannot change their own role or remove themselves")]
+    OwnAccount,
+
+    #[error(transparent)]
+    Store(#[from] UserError),
+}
+
+impl ResponseError for UsersError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            UsersError::OwnAccount => StatusCode::CONFLICT,
+            UsersError::Store(UserError::NotFound(_)) => StatusCode::NOT_FOUND,
+            UsersError::Store(
+                UserError::InvalidUsername(_)
+                | UserError::UnknownRole(_)
+                | UserError::WeakPassword
+                | UserError::Exists(_),
+            ) => StatusCode::BAD_REQUEST,
+            UsersError::Store(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        if self.status_code().is_server_error() {
+            error!("Admin users request failed: {}", self);
+        }
+        HttpResponse::build(self.status_code()).json(json!({ "error": self.to_string() }))
+    }
+}
+
+fn role_field() -> FieldSpec {
+    let options = ROLES
+        .iter()
+        .map(|r| (r.as_str().to_owned(), r.as_str().to_owned()))
+        .collect();
+    FieldSpec::new("role", "Role", FieldKind::Select { options }).required()
+}
+
+fn new_user_form() -> FormSpec {
+    FormSpec::new("user-new", "Add a user", LIST_PAGE)
+        .with_field(
+            FieldSpec::new("username", "Username", FieldKind::Text)
+                .required()
+                .with_help("1-32 of a-z, 0-9, '-' and '_'."),
+        )
+        .with_field(role_field())
+        .with_field(
+            FieldSpec::new("password", "Password", FieldKind::Password)
+                .required()
+                .with_autocomplete("new-password"),
+        )
+        .with_submit_label("Add user")
+}
+
+#[tracing::instrument(skip_all)]
+async fn list_page_handler(
+    req: HttpRequest,
+    auth: Result<RequireRole<Admin>, AuthError>,
+    store: web::Data<UserStore>,
+) -> Result<HttpResponse, UsersError> {
+    let auth = match form_pages::signed_in(&req, auth) {
+        Ok(auth) => auth,
+        Err(res) => return Ok(res),
+    };
+    let users = blocking(move || store.list()).await?;
+
+    let mut out = String::from(concat!(
+        "<!DOCTYPE html><html lang=\"en\"><head><meta charset=\"utf-8\">",
+        "<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">",
+        "<title>Users</title></head><body><main id=\"main\"><h1>Users</h1>",
+        "<ul class=\"users\">"
+    ));
+    for user in &users {
+        let state = if user.has_two_factor() {
+            format!("{}, two-factor", user.role)
+        } else {
+            user.role.to_string()
+        };
+        let _ = write!(
+            out,
+            "<li><strong>{}</strong> <span class=\"state\">{}</span>",
+            text(&user.username),
+            text(&state)
+        );
+        if user.username != auth.user.username {
+            let role = FormSpec::new(
+                format!("user-{}-role", user.username),
+                &user.username,
+                format!("{LIST_PAGE}/{}/role", user.username),
+            )
+            .with_field(role_field())
+            .with_submit_label("Change role");
+            let values = FormValues::from([("role".to_string(), user.role.to_string())]);
+            out.push_str(&form_pages::fragment(&req, &role, &values));
+
+            let remove = FormSpec::new(
+                format!("user-{}-remove", user.username),
+                &user.username,
+                format!("{LIST_PAGE}/{}/remove", user.username),
+            )
+            .with_submit_label("Remove");
+            out.push_str(&form_pages::fragment(&req, &remove, &FormValues::new()));
+        }
+        out.push_str("</li>");
+    }
+    out.push_str("</ul><h2>Add a user</h2>");
+    out.push_str(&form_pages::fragment(
+        &req,
+        &new_user_form(),
+        &FormValues::new(),
+    ));
+    out.push_str("</main></body></html>");
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(out))
+}
+
+#[tracing::instrument(skip_all)]
+async fn create_form_handler(
+    req: HttpRequest,
+    auth: Result<RequireRole<Admin>, AuthError>,
+    store: web::Data<UserStore>,
+    body: web::Bytes,
+) -> Result<HttpResponse, UsersError> {
+    let auth = match form_pages::signed_in(&req, auth) {
+        Ok(auth) => auth,
+        Err(res) => return Ok(res),
+    };
+    let spec = new_user_form();
+    let mut values = form_pages::values(&body);
+    let mut errors = spec.validate_required(&values);
+    let password = values.remove("password").unwrap_or_default();
+    if errors.is_empty() {
+        let created = match (
+            values["role"].parse::<Role>(),
+            ValidatedPassword::new(password),
+        ) {
+            (Ok(role), Ok(password)) => {
+                let username = values["username"].clone();
+                blocking(move || store.create(&username, role, &password)).await
+            }
+            (Err(e), _) | (_, Err(e)) => Err(e),
+        };
+        match created {
+            Ok(user) => {
+                audit::annotate(
+                    &req,
+                    AuditNote::new("user.create", format!("user:{}", user.username)),
+                );
+                info!(
+                    "{} added {} ({})",
+                    auth.user.username, user.username, user.role
+                );
+                return Ok(form_pages::redirect(&req, LIST_PAGE));
+            }
+            Err(e @ (UserError::InvalidUsername(_) | UserError::Exists(_))) => {
+                errors.add("username", e.to_string())
+            }
+            Err(e @ UserError::UnknownRole(_)) => errors.add("role", e.to_string()),
+            Err(e @ UserError::WeakPassword) => errors.add("password", e.to_string()),
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(form_pages::respond(
+        &req,
+        StatusCode::UNPROCESSABLE_ENTITY,
+        &spec,
+        &values,
+        &errors,
+    ))
+}
+
+#[tracing::instrument(skip_all)]
+async fn role_form_handler(
+    req: HttpRequest,
+    auth: Result<RequireRole<Admin>, AuthError>,
+    store: web::Data<UserStore>,
+    username: web::Path<String>,
+    body: web::Bytes,
+) -> Result<HttpResponse, UsersError> {
+    let auth = match form_pages::signed_in(&req, auth) {
+        Ok(auth) => auth,
+        Err(res) => return Ok(res),
+    };
+    let username = username.into_inner();
+    if username == auth.user.username {
+        return Err(UsersError::OwnAccount);
+    }
+    let role: Role = form_pages::values(&body)
+        .remove("role")
+        .unwrap_or_default()
+        .parse()?;
+    let user = blocking(move || store.set_role(&username, role)).await?;
+    audit::annotate(
+        &req,
+        AuditNote::new("user.role", format!("user:{}", user.username)),
+    );
+    info!(
+        "{} made {} {}",
+        auth.user.username, user.username, user.role
+    );
+    Ok(form_pages::redirect(&req, LIST_PAGE))
+}
+
+#[tracing::instrument(skip_all)]
+async fn remove_form_handler(
+    req: HttpRequest,
+    auth: Result<RequireRole<Admin>, AuthError>,
+    store: web::Data<UserStore>,
+    username: web::Path<String>,
+) -> Result<HttpResponse, UsersError> {
+    let auth = match form_pages::signed_in(&req, auth) {
+        Ok(auth) => auth,
+        Err(res) => return Ok(res),
+    };
+    let username = username.into_inner();
+    if username == auth.user.username {
+        return Err(UsersError::OwnAccount);
+    }
+    {
+        let username = username.clone();
+        blocking(move || store.remove(&username)).await?;
+    }
+    audit::annotate(
+        &req,
+        AuditNote::new("user.remove", format!("user:{username}")),
+    );
+    info!("{} removed {}", auth.user.username, username);
+    Ok(form_pages::redirect(&req, LIST_PAGE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{self, SESSION_COOKIE};
+    use actix_web::{cookie::Cookie, test as actix_test, App};
+
+    #[actix_web::test]
+    async fn admins_add_change_and_remove_users_from_the_page() {
+        let site = tempfile::tempdir().unwrap();
+        let store = auth::store(site.path());
+        let password = ValidatedPassword::new("a long enough secret").unwrap();
+        let ada = store.create("ada", Role::Admin, &password).unwrap();
+        let token = store
+            .issue_session(&ada, chrono::Duration::hours(1))
+            .unwrap();
+        let ada = Cookie::new(SESSION_COOKIE, token);
+        let app = actix_test::init_service(
+            App::new().service(web::scope("").app_data(store.clone()).service(pages())),
+        )
+        .await;
+        let post = |uri: &str, body: &'static str| {
+            actix_test::TestRequest::post()
+                .uri(uri)
+                .cookie(ada.clone())
+                .insert_header(("content-type", "application/x-www-form-urlencoded"))
+                .set_payload(body)
+                .to_request()
+        };
+
+        let req = actix_test::TestRequest::get()
+            .uri("/admin/users")
+            .to_request();
+        let res = actix_test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::SEE_OTHER);
+
+        let res = actix_test::call_service(
+            &app,
+            post(
+                "/admin/users",
+                "username=Bad+Name&role=editor&password=short",
+            ),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let form = String::from_utf8(actix_test::read_body(res).await.to_vec()).unwrap();
+        assert!(form.contains("passwords must be"), "{form}");
+        assert!(!form.contains("short"), "the password is not echoed back");
+
+        let res = actix_test::call_service(
+            &app,
+            post(
+                "/admin/users",
+                "username=ed&role=author&password=another+long+secret",
+            ),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::SEE_OTHER);
+        assert_eq!(store.get("ed").unwrap().unwrap().role, Role::Author);
+
+        let req = actix_test::TestRequest::get()
+            .uri("/admin/users")
+            .cookie(ada.clone())
+            .to_request();
+        let res = actix_test::call_service(&app, req).await;
+        let list = String::from_utf8(actix_test::read_body(res).await.to_vec()).unwrap();
+        assert!(list.contains("/admin/users/ed/role"), "{list}");
+        assert!(!list.contains("/admin/users/ada/role"), "{list}");
+
+        let res = actix_test::call_service(&app, post("/admin/users/ed/role", "role=editor")).await;
+        assert_eq!(res.status(), StatusCode::SEE_OTHER);
+        assert_eq!(store.get("ed").unwrap().unwrap().role, Role::Editor);
+
+        let res = actix_test::call_service(&app, post("/admin/users/ada/remove", "")).await;
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+        let res = actix_test::call_service(&app, post("/admin/users/ed/remove", "")).await;
+        assert_eq!(res.status(), StatusCode::SEE_OTHER);
+        assert!(store.get("ed").unwrap().is_none());
+    }
+}
@@ -0,0 +1,285 @@
+// crates/edge/src/archetypes.rs
+
+// New documents from archetypes, for `whispercms new post`:
+//
+//   whispercms new post ./site "Hello, World"            posts/hello-world.md
+//   whispercms new post ./site "About" --type page --section .
+//
+// An archetype is a front-matter preset for one content type, kept as
+// `<site>/archetypes/<type>.md` in any front-matter format, e.g. for posts
+//
+//   +++
+//   [publish]
+//   status = "draft"
+//   [tax]
+//   categories = ["notes"]
+//   +++
+//
+//   ## {{title}}
+//
+// Types without one use `archetypes/default.md`, else a bare draft.
+// `{{title}}`, `{{slug}}`, `{{date}}` and `{{type}}` are replaced in the
+// body and in string values. The new document keeps the archetype's fields
+// and gets `type`, `slug`, `content.title` and `publish.date`, plus a draft
+// status and empty `tax.categories`/`tax.tags`/`tax.series` where the
+// archetype sets none, and must pass the front-matter checks before it is
+// written. It goes to `<section>/<slug>.md` in the content directory, the
+// section defaulting to the type's plural. A running site indexes it as soon
+// as the content watcher sees it; `--index` also appends its record to a
+// stored front-matter index, as imports do.
+
+use crate::import::wordpress::ImportedDoc;
+use adapt::mql::index::IndexRecord;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::{json, Map, Value as Json};
+use serve::front_matter::{self, FrontMatterError};
+use serve::render::helpers::slugify;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use thiserror::Error;
+
+/// Where a site keeps its archetypes.
+pub const ARCHETYPES_DIR: &str = "./archetypes/";
+
+/// Used for types without their own archetype.
+const DEFAULT_ARCHETYPE: &str = "default";
+
+/// Used when the site has no default archetype either.
+const BUILT_IN: &str = r#"+++
+[publish]
+status = "draft"
++++
+"#;
+
+#[derive(Debug, Error)]
+pub enum ArchetypeError {
+    #[error("I/O: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("{0}")]
+    FrontMatter(#[from] FrontMatterError),
+
+    #[error("Front matter: {0}")]
+    Toml(#[from] toml::ser::Error),
+
+    #[error("no slug can be made from {0:?}; pass one with --slug")]
+    Slug(String),
+
+    #[error("invalid {0}: {1:?}")]
+    Invalid(&'static str, String),
+}
+
+/// What to create; see the module comment for the defaults.
+#[derive(Debug, Clone, Copy)]
+pub struct NewDoc<'a> {
+    pub title: &'a str,
+    /// Content type, naming the archetype.
+    pub kind: &'a str,
+    /// Directory below the content directory.
+    pub section: Option<&'a str>,
+    pub slug: Option<&'a str>,
+}
+
+impl NewDoc<'_> {
+    /// The document, from the archetypes in `archetypes_dir`, dated `now`.
+    pub fn build(
+        &self,
+        archetypes_dir: &Path,
+        now: DateTime<Utc>,
+    ) -> Result<ImportedDoc, ArchetypeError> {
+        if !is_name(self.kind) {
+            return Err(ArchetypeError::Invalid("type", self.kind.to_owned()));
+        }
+        let slug = match self.slug {
+            Some(s) if is_name(s) => s.to_owned(),
+            Some(s) => return Err(ArchetypeError::Invalid("slug", s.to_owned())),
+            None => Some(slugify(self.title))
+                .filter(|s| !s.is_empty())
+                .ok_or_else(|| ArchetypeError::Slug(self.title.to_owned()))?,
+        };
+        let section = match self.section {
+            Some(s) => relative(s).ok_or_else(|| ArchetypeError::Invalid("section", s.into()))?,
+            None => PathBuf::from(format!("{}s", self.kind)),
+        };
+        let date = now.to_rfc3339_opts(SecondsFormat::Secs, true);
+        let vars = [
+            ("{{title}}", self.title),
+            ("{{slug}}", &slug),
+            ("{{date}}", &date),
+            ("{{type}}", self.kind),
+        ];
+
+        let (file, text) = archetype(archetypes_dir, self.kind)?;
+        let (mut data, body) = match front_matter::parse(&file, &text)? {
+            Some(fm) => (fm.data, fm.body),
+            None => (json!({}), text),
+        };
+        fill(&mut data, &vars);
+        let body = vars
+            .iter()
+            .fold(body, |body, (var, value)| body.replace(var, value));
+
+        let fm = data
+            .as_object_mut()
+            .ok_or_else(|| ArchetypeError::Invalid("archetype", file.display().to_string()))?;
+        fm.insert("type".into(), json!(self.kind));
+        fm.insert("slug".into(), json!(slug));
+        table(fm, "content").insert("title".into(), json!(self.title));
+        let publish = table(fm, "publish");
+        publish.insert("date".into(), json!(date));
+        publish.entry("status").or_insert_with(|| json!("draft"));
+        let tax = table(fm, "tax");
+        for name in ["categories", "tags", "series"] {
+            tax.entry(name).or_insert_with(|| json!([]));
+        }
+
+        let rel_stem = section.join(&slug);
+        let rel_path = rel_stem.with_extension("md");
+        let text = front_matter::to_toml_file(&data, &body)?;
+        if let Some(e) = front_matter::lint(&rel_path, &text).into_iter().next() {
+            return Err(e.into());
+        }
+        let served_id = format!("/{}", rel_stem.with_extension("html").to_string_lossy());
+        Ok(ImportedDoc {
+            record: IndexRecord::from_json_with_id(served_id, &data),
+            rel_path,
+            front_matter: data,
+            body,
+        })
+    }
+}
+
+/// The archetype file for `kind` and its text.
+fn archetype(dir: &Path, kind: &str) -> Result<(PathBuf, String), ArchetypeError> {
+    for name in [kind, DEFAULT_ARCHETYPE] {
+        let path = dir.join(name).with_extension("md");
+        match fs::read_to_string(&path) {
+            Ok(text) => return Ok((path, text)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok((PathBuf::from("(built-in archetype)"), BUILT_IN.to_owned()))
+}
+
+/// The object at `fm[key]`, made one if it is missing or not an object.
+fn table<'a>(fm: &'a mut Map<String, Json>, key: &str) -> &'a mut Map<String, Json> {
+    let value = fm.entry(key).or_insert_with(|| json!({}));
+    if !value.is_object() {
+        *value = json!({});
+    }
+    value.as_object_mut().expect("made an object above")
+}
+
+/// Replace `vars` in every string below `value`.
+fn fill(value: &mut Json, vars: &[(&str, &str)]) {
+    match value {
+        Json::String(s) => {
+            for (var, with) in vars {
+                if s.contains(var) {
+                    *s = s.replace(var, with);
+                }
+            }
+        }
+        Json::Array(items) => items.iter_mut().for_each(|v| fill(v, vars)),
+        Json::Object(map) => map.values_mut().for_each(|v| fill(v, vars)),
+        _ => {}
+    }
+}
+
+fn is_name(s: &str) -> bool {
+    !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+/// `s` as a path that stays below the directory it is joined to.
+fn relative(s: &str) -> Option<PathBuf> {
+    let path = Path::new(s);
+    path.components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+        .then(|| {
+            path.components()
+                .filter(|c| matches!(c, Component::Normal(_)))
+                .collect()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn documents_follow_their_archetype_and_pass_the_checks() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = Utc.with_ymd_and_hms(2026, 3, 4, 5, 6, 7).unwrap();
+        let post = NewDoc {
+            title: "Hello, World",
+            kind: "post",
+            section: None,
+            slug: None,
+        };
+
+        // Without archetypes: a dated draft with empty taxonomies.
+        let doc = post.build(dir.path(), now).unwrap();
+        assert_eq!(doc.rel_path, Path::new("posts/hello-world.md"));
+        assert_eq!(doc.record.id, "/posts/hello-world.html");
+        assert_eq!(
+            doc.front_matter,
+            json!({
+                "type": "post",
+                "slug": "hello-world",
+                "content": { "title": "Hello, World" },
+                "publish": { "status": "draft", "date": "2026-03-04T05:06:07Z" },
+                "tax": { "categories": [], "tags": [], "series": [] },
+            })
+        );
+
+        fs::write(
+            dir.path().join("post.md"),
+            "---\npublish:\n  status: published\ntax:\n  categories: [notes]\n\
+             content:\n  summary: About {{title}}\n---\n## {{title}}\n",
+        )
+        .unwrap();
+        let doc = post.build(dir.path(), now).unwrap();
+        assert_eq!(doc.front_matter["publish"]["status"], "published");
+        assert_eq!(doc.front_matter["tax"]["categories"], json!(["notes"]));
+        assert_eq!(doc.front_matter["content"]["summary"], "About Hello, World");
+        assert_eq!(doc.body, "## Hello, World\n");
+
+        // Other types use the default archetype, which must pass the checks.
+        fs::write(
+            dir.path().join("default.md"),
+            "+++\n[nav]\nmenu_order = \"1\"\n+++\n",
+        )
+        .unwrap();
+        let page = NewDoc {
+            kind: "page",
+            section: Some("."),
+            slug: Some("about"),
+            ..post
+        };
+        assert!(matches!(
+            page.build(dir.path(), now),
+            Err(ArchetypeError::FrontMatter(e)) if e.field.as_deref() == Some("nav.menu_order")
+        ));
+        assert!(matches!(
+            NewDoc {
+                section: Some("../out"),
+                ..page
+            }
+            .build(dir.path(), now),
+            Err(ArchetypeError::Invalid("section", _))
+        ));
+        assert!(matches!(
+            NewDoc {
+                title: "!!",
+                ..post
+            }
+            .build(dir.path(), now),
+            Err(ArchetypeError::Slug(_))
+        ));
+    }
+}
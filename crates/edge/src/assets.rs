@@ -0,0 +1,219 @@
+// crates/edge/src/assets.rs
+
+// Theme assets (serve::assets), served under each theme's mount:
+//
+//   GET <mount>/assets/css/app.3f2a9c1b7e.css   immutable, cached for a year
+//   GET <mount>/assets/css/app.css              revalidated through its ETag
+//
+// A theme's manifest is hashed on the first request that needs it and kept in
+// its site's `AssetManifests` until the extension watcher reports a change
+// below the site's themes directory.
+// Templates link assets with `{{asset_url "css/app.css"}}`, so an edited
+// file gets a new URL as soon as the manifest is rebuilt.
+//
+// The island loader (serve::render::islands) is served by the host itself:
+//
+//   GET /_whisper/islands.js                    revalidated per core version
+
+use actix_web::{
+    dev::HttpServiceFactory,
+    http::header::{CACHE_CONTROL, CONTENT_TYPE, ETAG, IF_NONE_MATCH},
+    web, HttpRequest, HttpResponse,
+};
+use serve::assets::{
+    content_type, AssetManifest, AssetMatch, AssetUrl, IMMUTABLE_CACHE_CONTROL,
+    REVALIDATE_CACHE_CONTROL,
+};
+use serve::render::islands::{LOADER_JS, LOADER_PATH};
+use serve::render::HelperSet;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use tracing::{debug, error, warn};
+
+/// The fingerprinted manifests of one site's themes, by assets directory.
+#[derive(Default)]
+pub struct AssetManifests(RwLock<HashMap<PathBuf, Arc<AssetManifest>>>);
+
+impl AssetManifests {
+    /// The manifests of the site serving `req`; empty outside one.
+    pub fn of(req: &HttpRequest) -> Arc<Self> {
+        req.app_data::<web::Data<Self>>()
+            .map(|manifests| manifests.clone().into_inner())
+            .unwrap_or_default()
+    }
+
+    /// The manifest of `dir`, hashing it on first use. A directory that
+    /// cannot be read has no assets until the next `invalidate`.
+    pub fn manifest(&self, dir: &Path) -> Arc<AssetManifest> {
+        if let Some(m) = self.0.read().ok().and_then(|m| m.get(dir).cloned()) {
+            return m;
+        }
+
+        let manifest = Arc::new(AssetManifest::build(dir).unwrap_or_else(|e| {
+            warn!("Reading theme assets in {:?} failed: {}", dir, e);
+            AssetManifest::default()
+        }));
+        debug!("Fingerprinted {} assets in {:?}", manifest.len(), dir);
+        if let Ok(mut all) = self.0.write() {
+            all.insert(dir.to_path_buf(), manifest.clone());
+        }
+        manifest
+    }
+
+    /// Forget every manifest; called when files below the themes change.
+    pub fn invalidate(&self) {
+        if let Ok(mut all) = self.0.write() {
+            all.clear();
+        }
+    }
+
+    /// `asset_url` for templates of the theme mounted at `mount_path`.
+    pub fn helpers(&self, assets_dir: &Path, mount_path: &str) -> HelperSet {
+        HelperSet::new().with_helper(
+            "asset_url",
+            AssetUrl::new(self.manifest(assets_dir), url_prefix(mount_path)),
+        )
+    }
+}
+
+/// URL prefix of the assets of a theme mounted at `mount_path`.
+pub fn url_prefix(mount_path: &str) -> String {
+    format!("{}/assets", mount_path.trim_end_matches('/'))
+}
+
+#[derive(Clone)]
+struct AssetState {
+    dir: PathBuf,
+}
+
+/// The asset resource, for the scope of the theme whose assets are in `dir`.
+pub fn service(dir: PathBuf) -> impl HttpServiceFactory {
+    web::resource("/assets/{path:.*}")
+        .app_data(web::Data::new(AssetState { dir }))
+        .route(web::get().to(asset_handler))
+        .route(web::head().to(asset_handler))
+}
+
+/// The island loader, for the root of the router.
+pub fn islands_loader() -> impl HttpServiceFactory {
+    web::resource(LOADER_PATH)
+        .route(web::get().to(islands_loader_handler))
+        .route(web::head().to(islands_loader_handler))
+}
+
+async fn islands_loader_handler(req: HttpRequest) -> HttpResponse {
+    let etag = concat!("\"islands-", env!("CARGO_PKG_VERSION"), "\"");
+    let not_modified = req
+        .headers()
+        .get(IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.split(',').any(|t| t.trim() == etag || t.trim() == "*"));
+    let mut res = if not_modified {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    res.insert_header((CACHE_CONTROL, REVALIDATE_CACHE_CONTROL))
+        .insert_header((ETAG, etag));
+    if not_modified {
+        return res.finish();
+    }
+    res.insert_header((CONTENT_TYPE, "text/javascript; charset=utf-8"))
+        .body(LOADER_JS)
+}
+
+#[tracing::instrument(skip_all, fields(path = %req.path()))]
+async fn asset_handler(state: web::Data<AssetState>, req: HttpRequest) -> HttpResponse {
+    let manifest = AssetManifests::of(&req).manifest(&state.dir);
+    let requested = req.match_info().get("path").unwrap_or_default();
+    let (entry, cache_control) = match manifest.lookup(requested) {
+        Some(AssetMatch::Fingerprinted(entry)) => (entry, IMMUTABLE_CACHE_CONTROL),
+        Some(AssetMatch::Logical(entry)) => (entry, REVALIDATE_CACHE_CONTROL),
+        None => return HttpResponse::NotFound().finish(),
+    };
+
+    let etag = format!("\"{}\"", entry.digest);
+    let not_modified = req
+        .headers()
+        .get(IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.split(',').any(|t| t.trim() == etag || t.trim() == "*"));
+    if not_modified {
+        return HttpResponse::NotModified()
+            .insert_header((ETAG, etag))
+            .insert_header((CACHE_CONTROL, cache_control))
+            .finish();
+    }
+
+    match tokio::fs::read(&entry.path).await {
+        Ok(body) => HttpResponse::Ok()
+            .insert_header((CONTENT_TYPE, content_type(&entry.path)))
+            .insert_header((CACHE_CONTROL, cache_control))
+            .insert_header((ETAG, etag))
+            .body(body),
+        Err(e) => {
+            // Removed since the manifest was built; the watcher will drop it.
+            error!("Reading asset {:?} failed: {}", entry.path, e);
+            HttpResponse::NotFound().finish()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, test as actix_test, App};
+
+    #[test]
+    fn prefixes_follow_the_mount() {
+        assert_eq!(url_prefix("/"), "/assets");
+        assert_eq!(url_prefix("/docs"), "/docs/assets");
+        assert_eq!(url_prefix("/docs/"), "/docs/assets");
+    }
+
+    #[actix_web::test]
+    async fn fingerprinted_assets_are_immutable_and_logical_ones_revalidate() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("app.css"), "body {}").unwrap();
+        let hashed = AssetManifests::default()
+            .manifest(dir.path())
+            .url_for("/assets", "app.css");
+        assert_ne!(hashed, "/assets/app.css");
+
+        let app = actix_test::init_service(
+            App::new().service(web::scope("").service(service(dir.path().to_path_buf()))),
+        )
+        .await;
+
+        let req = actix_test::TestRequest::get().uri(&hashed).to_request();
+        let res = actix_test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            *res.headers().get(CACHE_CONTROL).unwrap(),
+            IMMUTABLE_CACHE_CONTROL
+        );
+        assert_eq!(
+            *res.headers().get(CONTENT_TYPE).unwrap(),
+            "text/css; charset=utf-8"
+        );
+        let etag = res.headers().get(ETAG).unwrap().clone();
+
+        let req = actix_test::TestRequest::get()
+            .uri("/assets/app.css")
+            .insert_header((IF_NONE_MATCH, etag))
+            .to_request();
+        let res = actix_test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(
+            *res.headers().get(CACHE_CONTROL).unwrap(),
+            REVALIDATE_CACHE_CONTROL
+        );
+
+        let req = actix_test::TestRequest::get()
+            .uri("/assets/app.0000000000.css")
+            .to_request();
+        let res = actix_test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,221 @@
+// crates/edge/src/audit.rs
+
+// Audit trail for every mutating admin request (db::audit). The `record`
+// middleware wraps the root scope and, after each POST, PUT, PATCH or
+// DELETE under `/admin`, appends who made it, what it changed and the
+// response status. Handlers describe the change with `annotate`:
+//
+//   audit::annotate(&req, AuditNote::new("content.update", "content:blog/a.md")
+//       .with_change(&before, &after));
+//
+// Requests without a note are still recorded, by method and path. Bodies
+// are never logged. Admins read the trail at:
+//
+//   GET /admin/audit?filter={"actor":"ed"}&limit=50   same shape as /api/content
+
+use crate::api::content::ContentQuery;
+use crate::auth::{Admin, RequireRole};
+use crate::db::audit::{diff, AuditEntry, AuditError, AuditLog, AUDIT_LOG_FILE};
+use crate::db::users::User;
+use actix_web::{
+    body::{BoxBody, MessageBody},
+    dev::{HttpServiceFactory, ServiceRequest, ServiceResponse},
+    http::{header::AUTHORIZATION, Method},
+    middleware::Next,
+    web, Error, HttpMessage, HttpRequest, HttpResponse,
+};
+use serde_json::{json, Value as Json};
+use std::path::Path;
+use tracing::error;
+
+/// The site's audit log, for `app_data` on the root scope.
+pub fn log(root_dir: &Path) -> web::Data<AuditLog> {
+    web::Data::new(AuditLog::new(root_dir.join(AUDIT_LOG_FILE)))
+}
+
+/// The `/admin/audit` resource, for the root of the router.
+pub fn services() -> impl HttpServiceFactory {
+    web::resource("/admin/audit").route(web::get().to(query_handler))
+}
+
+/// What a handler changed, for the entry `record` writes.
+#[derive(Debug, Clone)]
+pub struct AuditNote {
+    action: String,
+    entity: String,
+    actor: Option<String>,
+    diff: Option<Json>,
+}
+
+impl AuditNote {
+    pub fn new(action: impl Into<String>, entity: impl Into<String>) -> Self {
+        Self {
+            action: action.into(),
+            entity: entity.into(),
+            actor: None,
+            diff: None,
+        }
+    }
+
+    /// Record the fields that differ between the two states.
+    pub fn with_change(mut self, before: &Json, after: &Json) -> Self {
+        self.diff = diff(before, after);
+        self
+    }
+
+    /// Name the actor when no session identifies one, e.g. a login attempt.
+    pub fn with_actor(mut self, actor: impl Into<String>) -> Self {
+        self.actor = Some(actor.into());
+        self
+    }
+}
+
+/// Describe the change `req` is making. A later note replaces an earlier.
+pub fn annotate(req: &HttpRequest, note: AuditNote) {
+    req.extensions_mut().insert(note);
+}
+
+fn is_audited(req: &ServiceRequest) -> bool {
+    matches!(
+        *req.method(),
+        Method::POST | Method::PUT | Method::PATCH | Method::DELETE
+    ) && (req.path() == "/admin" || req.path().starts_with("/admin/"))
+}
+
+/// `actix_web::middleware::from_fn` middleware.
+pub async fn record(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    if !is_audited(&req) {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    }
+    let method = req.method().to_string();
+    let path = req.path().to_owned();
+    let by_token = req.headers().contains_key(AUTHORIZATION);
+
+    let res = next.call(req).await?.map_into_boxed_body();
+
+    let request = res.request();
+    let Some(log) = request.app_data::<web::Data<AuditLog>>().cloned() else {
+        return Ok(res);
+    };
+    let note = request.extensions().get::<AuditNote>().cloned();
+    let user = request
+        .extensions()
+        .get::<User>()
+        .map(|u| u.username.clone());
+    let actor = user
+        .or_else(|| note.as_ref().and_then(|n| n.actor.clone()))
+        .unwrap_or_else(|| if by_token { "token" } else { "anonymous" }.to_owned());
+
+    let entry = match note {
+        Some(note) => AuditEntry::new(actor, note.action, note.entity).with_diff(note.diff),
+        None => AuditEntry::new(actor, method, path),
+    }
+    .with_status(res.status().as_u16());
+    let written = web::block(move || log.record(&entry)).await;
+    match written {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => error!("Writing the audit log failed: {}", e),
+        Err(e) => error!("Writing the audit log was cancelled: {}", e),
+    }
+    Ok(res)
+}
+
+#[tracing::instrument(skip_all)]
+async fn query_handler(
+    _auth: RequireRole<Admin>,
+    log: Option<web::Data<AuditLog>>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let Some(log) = log else {
+        return HttpResponse::NotFound().finish();
+    };
+    let q = match ContentQuery::from_query_string(req.query_string()) {
+        Ok(q) => q,
+        Err(e) => return HttpResponse::BadRequest().json(json!({ "error": e.to_string() })),
+    };
+    match log.query(&q.filter, &q.opts).await {
+        Ok((total, items)) => HttpResponse::Ok().json(json!({
+            "items": items,
+            "total": total,
+            "limit": q.opts.limit,
+            "skip": q.opts.skip.unwrap_or(0),
+        })),
+        Err(AuditError::Query(e)) => {
+            HttpResponse::BadRequest().json(json!({ "error": e.to_string() }))
+        }
+        Err(e) => {
+            error!("Audit query failed: {}", e);
+            HttpResponse::InternalServerError().json(json!({ "error": "audit query failed" }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, middleware::from_fn, test as actix_test, App};
+
+    async fn rename(req: HttpRequest) -> HttpResponse {
+        annotate(
+            &req,
+            AuditNote::new("page.rename", "page:a")
+                .with_change(&json!({ "title": "A" }), &json!({ "title": "B" })),
+        );
+        HttpResponse::Ok().finish()
+    }
+
+    #[actix_web::test]
+    async fn mutating_admin_requests_are_recorded() {
+        let site = tempfile::tempdir().unwrap();
+        let app = actix_test::init_service(
+            App::new().service(
+                web::scope("")
+                    .app_data(log(site.path()))
+                    .route("/admin/pages", web::put().to(rename))
+                    .route("/admin/pages", web::get().to(HttpResponse::Ok))
+                    .route("/admin/other", web::delete().to(HttpResponse::NoContent))
+                    .wrap(from_fn(record)),
+            ),
+        )
+        .await;
+
+        for req in [
+            actix_test::TestRequest::put().uri("/admin/pages"),
+            actix_test::TestRequest::get().uri("/admin/pages"),
+            actix_test::TestRequest::delete()
+                .uri("/admin/other")
+                .insert_header((AUTHORIZATION, "Bearer x")),
+        ] {
+            let res = actix_test::call_service(&app, req.to_request()).await;
+            assert!(res.status().is_success());
+        }
+
+        let entries = AuditLog::new(site.path().join(AUDIT_LOG_FILE))
+            .entries()
+            .unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(
+            (entries[0].actor.as_str(), entries[0].action.as_str()),
+            ("anonymous", "page.rename")
+        );
+        assert_eq!(
+            entries[0].diff,
+            Some(json!({ "title": { "before": "A", "after": "B" } }))
+        );
+        assert_eq!(
+            (
+                entries[1].actor.as_str(),
+                entries[1].entity.as_str(),
+                entries[1].status
+            ),
+            (
+                "token",
+                "/admin/other",
+                Some(StatusCode::NO_CONTENT.as_u16())
+            )
+        );
+    }
+}
@@ -0,0 +1,1664 @@
+// crates/edge/src/auth.rs
+
+// Logins for the accounts in db::users, and the `RequireRole` extractor
+// admin routes use to check them:
+//
+//   POST /admin/login    {"username": "...", "password": "..."} → session cookie
+//   POST /admin/logout   ends the session
+//   GET  /admin/me       who the cookie belongs to
+//
+// `GET /admin/login` is the same login as an HTML form (crate::form_pages).
+// Posted back urlencoded, it answers with the form and its errors, or sets
+// the cookie and redirects to `next` when that is a path on this site. Any
+// other body is refused with 415: the route takes no CSRF token, and a page
+// on another site can post `text/plain` to it.
+//
+//   GET    /admin/sessions        { items: [{ id, created_at, expires_at, current }] }
+//   DELETE /admin/sessions        log out everywhere, this browser included
+//   DELETE /admin/sessions/{id}   end one of them → 204
+//
+// With `[mail]` set, users who have an email address can reset a forgotten
+// password. The code is mailed through the outbox, works once and expires
+// after `reset_minutes`; using it ends every session of the account:
+//
+//   POST /admin/password-reset           {"username": "..."} → 202 whoever asks
+//   POST /admin/password-reset/confirm   {"code": "...", "password": "..."} → 204
+//
+// GET either for its form; posted urlencoded, the request form goes on to
+// the confirm form, and that one to the login page.
+//
+//   async fn handler(auth: RequireRole<Editor>) -> HttpResponse {
+//       // auth.user.role is Editor or Admin here
+//   }
+//
+// A user may turn on two-factor login with an authenticator app (see
+// crate::totp). Login then also takes `"code"`, from the app or one of the
+// recovery codes, each good once:
+//
+//   GET    /admin/two-factor           { enabled, pending, recovery_codes_left }
+//   POST   /admin/two-factor           → { secret, uri } for the app, as a QR code
+//   POST   /admin/two-factor/confirm   { code } → { recovery_codes }, shown once
+//   DELETE /admin/two-factor           { code } → 204
+//
+// A browser turns it on at /admin/two-factor/setup: a button to start, a
+// form for the first code from the app, then the recovery codes.
+//
+// Failed logins are limited per client address, and wrong second-factor
+// codes per account, so neither passwords nor six-digit codes can be
+// guessed at speed; past the limit login answers 429 with `Retry-After`.
+//
+// With `require_two_factor`, admins without it are refused everywhere but
+// those routes (and logout) until they turn it on.
+//
+// Accounts are managed with `whispercms user`. Sessions are configured by
+// an optional `[auth]` table:
+//
+//   [auth]
+//   session_hours = 336
+//   secure_cookie = true
+//   reset_minutes = 60
+//   require_two_factor = false
+
+use crate::audit::{self, AuditNote};
+use crate::cli::OUTBOX_DIR;
+use crate::db::outbox::{Delivery, Outbox};
+use crate::db::store::blocking;
+use crate::db::users::{Role, User, UserError, UserStore, ValidatedPassword, USERS_DB_DIR};
+use crate::form_pages;
+use crate::proxy::EdgeError;
+use crate::quota::{self, ClientLimits};
+use crate::totp;
+use actix_web::{
+    cookie::{time, Cookie, SameSite},
+    dev::{HttpServiceFactory, Payload},
+    http::{header::RETRY_AFTER, StatusCode},
+    web, FromRequest, HttpMessage, HttpRequest, HttpResponse, ResponseError,
+};
+use chrono::{Duration, Utc};
+use domain::setting::AuthSettings;
+use futures::future::LocalBoxFuture;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use serve::render::form::{FieldKind, FieldSpec, FormErrors, FormSpec, FormValues};
+use std::marker::PhantomData;
+use std::path::Path;
+use thiserror::Error;
+use tracing::{debug, error, info};
+
+/// Cookie carrying the session token.
+pub const SESSION_COOKIE: &str = "whisper_session";
+
+/// Who authenticator apps list the account under.
+const TOTP_ISSUER: &str = "WhisperCMS";
+
+/// A site's login settings, the outbox its reset codes go through and the
+/// limits on guessing, for `app_data` on the root scope next to the user
+/// store.
+pub struct Auth {
+    settings: AuthSettings,
+    /// Unset without `[mail]`.
+    resets: Option<Outbox>,
+    /// Reset requests per client address and minute.
+    reset_limits: ClientLimits,
+    /// Failed logins per client address and minute.
+    login_limits: ClientLimits,
+    /// Wrong second-factor codes per account and minute.
+    two_factor_limits: ClientLimits,
+}
+
+impl Auth {
+    pub fn new(settings: AuthSettings) -> Self {
+        Self {
+            settings,
+            resets: None,
+            reset_limits: ClientLimits::per_minute(3),
+            login_limits: ClientLimits::per_minute(10),
+            two_factor_limits: ClientLimits::per_minute(5),
+        }
+    }
+
+    /// Offer password resets, mailing codes through the outbox under
+    /// `root_dir`.
+    pub fn with_resets(mut self, root_dir: &Path) -> Result<Self, EdgeError> {
+        self.resets = Some(Outbox::open(root_dir.join(OUTBOX_DIR))?);
+        Ok(self)
+    }
+}
+
+impl Default for Auth {
+    fn default() -> Self {
+        Self::new(AuthSettings::default())
+    }
+}
+
+/// The site's [`Auth`]; the defaults, without resets, when none is set.
+fn auth(req: &HttpRequest) -> web::Data<Auth> {
+    req.app_data::<web::Data<Auth>>()
+        .cloned()
+        .unwrap_or_else(|| web::Data::new(Auth::default()))
+}
+
+/// The site's user store, for `app_data` on the root scope so every route
+/// can check logins.
+pub fn store(root_dir: &Path) -> web::Data<UserStore> {
+    web::Data::new(UserStore::new(root_dir.join(USERS_DB_DIR)))
+}
+
+/// Login, logout and whoami resources, for the root of the router.
+pub fn services() -> impl HttpServiceFactory {
+    (
+        web::resource("/admin/login")
+            .route(web::get().to(login_page_handler))
+            .route(web::post().to(login_handler)),
+        web::resource("/admin/logout").route(web::post().to(logout_handler)),
+        web::resource("/admin/me").route(web::get().to(me_handler)),
+        web::resource("/admin/sessions")
+            .route(web::get().to(sessions_handler))
+            .route(web::delete().to(end_sessions_handler)),
+        web::resource("/admin/sessions/{id}").route(web::delete().to(end_session_handler)),
+        web::resource(RESET_PAGE)
+            .route(web::get().to(reset_request_page_handler))
+            .route(web::post().to(reset_request_handler)),
+        web::resource(RESET_CONFIRM_PAGE)
+            .route(web::get().to(reset_confirm_page_handler))
+            .route(web::post().to(reset_confirm_handler)),
+        web::resource("/admin/two-factor")
+            .route(web::get().to(two_factor_handler))
+            .route(web::post().to(two_factor_begin_handler))
+            .route(web::delete().to(two_factor_disable_handler)),
+        web::resource("/admin/two-factor/confirm")
+            .route(web::post().to(two_factor_confirm_handler)),
+        web::resource(TWO_FACTOR_PAGE)
+            .route(web::get().to(two_factor_page_handler))
+            .route(web::post().to(two_factor_begin_form_handler)),
+        web::resource(TWO_FACTOR_CONFIRM_PAGE)
+            .route(web::post().to(two_factor_confirm_form_handler)),
+    )
+}
+
+#[derive(Debug, Error)]
+pub enum AuthError {
+    #[error("login required")]
+    Unauthenticated,
+
+    #[error("requires the {0} role")]
+    Forbidden(Role),
+
+    #[error("admins must turn on two-factor login at /admin/two-factor first")]
+    TwoFactorRequired,
+
+    #[error("user store: {0}")]
+    Store(#[from] UserError),
+}
+
+impl ResponseError for AuthError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AuthError::Unauthenticated => StatusCode::UNAUTHORIZED,
+            AuthError::Forbidden(_) | AuthError::TwoFactorRequired => StatusCode::FORBIDDEN,
+            AuthError::Store(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        if let AuthError::Store(e) = self {
+            error!("Checking a login failed: {}", e);
+        }
+        HttpResponse::build(self.status_code()).json(json!({ "error": self.to_string() }))
+    }
+}
+
+/// The least role a [`RequireRole`] admits.
+pub trait MinRole {
+    const ROLE: Role;
+}
+
+macro_rules! min_role {
+    ($($name:ident => $role:expr),* $(,)?) => {$(
+        #[doc = concat!("Marker for `RequireRole<", stringify!($name), ">`.")]
+        #[derive(Debug)]
+        pub struct $name;
+        impl MinRole for $name {
+            const ROLE: Role = $role;
+        }
+    )*};
+}
+
+min_role! {
+    Viewer => Role::Viewer,
+    Author => Role::Author,
+    Editor => Role::Editor,
+    Admin => Role::Admin,
+}
+
+/// Extracts the logged-in user, failing with 401 without a live session
+/// and 403 when their role is below `R`, or when they are an admin without
+/// the two-factor login `require_two_factor` asks for.
+#[derive(Debug)]
+pub struct RequireRole<R: MinRole> {
+    pub user: User,
+    _role: PhantomData<R>,
+}
+
+impl<R: MinRole> FromRequest for RequireRole<R> {
+    type Error = AuthError;
+    type Future = LocalBoxFuture<'static, Result<Self, AuthError>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move {
+            let user = current_user(&req)
+                .await?
+                .ok_or(AuthError::Unauthenticated)?;
+            if !user.role.allows(R::ROLE) {
+                return Err(AuthError::Forbidden(R::ROLE));
+            }
+            if auth(&req).settings.require_two_factor
+                && user.role == Role::Admin
+                && !user.has_two_factor()
+            {
+                return Err(AuthError::TwoFactorRequired);
+            }
+            Ok(Self {
+                user,
+                _role: PhantomData,
+            })
+        })
+    }
+}
+
+/// The user whose session cookie `req` carries, if it is live. They are
+/// also left in the request's extensions.
+pub async fn current_user(req: &HttpRequest) -> Result<Option<User>, AuthError> {
+    let (Some(store), Some(cookie)) = (
+        req.app_data::<web::Data<UserStore>>().cloned(),
+        req.cookie(SESSION_COOKIE),
+    ) else {
+        return Ok(None);
+    };
+    let token = cookie.value().to_owned();
+    let user = blocking(move || store.session_user(&token, Utc::now())).await?;
+    // For the audit trail, which runs after the handler.
+    if let Some(user) = &user {
+        req.extensions_mut().insert(user.clone());
+    }
+    Ok(user)
+}
+
+fn session_cookie(req: &HttpRequest, value: String, max_age: time::Duration) -> Cookie<'static> {
+    Cookie::build(SESSION_COOKIE, value)
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .secure(auth(req).settings.secure_cookie)
+        .max_age(max_age)
+        .finish()
+}
+
+#[derive(Deserialize)]
+struct Login {
+    username: String,
+    password: String,
+    /// From the authenticator app, or a recovery code.
+    #[serde(default)]
+    code: Option<String>,
+}
+
+enum LoginOutcome {
+    Denied,
+    CodeNeeded,
+    /// Too many failures; seconds until the next try.
+    Throttled(u64),
+    Issued(User, String),
+}
+
+#[derive(Serialize)]
+struct Me<'a> {
+    username: &'a str,
+    role: Role,
+}
+
+/// The login page's form; `next` rides along hidden.
+fn login_form() -> FormSpec {
+    FormSpec::new("login", "Log in", "/admin/login")
+        .with_field(
+            FieldSpec::new("username", "Username", FieldKind::Text)
+                .required()
+                .with_autocomplete("username"),
+        )
+        .with_field(
+            FieldSpec::new("password", "Password", FieldKind::Password)
+                .required()
+                .with_autocomplete("current-password"),
+        )
+        .with_field(
+            FieldSpec::new("code", "Two-factor code", FieldKind::Text)
+                .with_help("Only if two-factor login is on for your account.")
+                .with_autocomplete("one-time-code"),
+        )
+        .with_field(FieldSpec::new("next", "", FieldKind::Hidden))
+        .with_submit_label("Log in")
+}
+
+async fn login_page_handler(req: HttpRequest) -> HttpResponse {
+    let mut values = FormValues::new();
+    if let Some(next) = form_pages::values(req.query_string().as_bytes()).remove("next") {
+        values.insert("next".into(), next);
+    }
+    form_pages::respond(
+        &req,
+        StatusCode::OK,
+        &login_form(),
+        &values,
+        &FormErrors::default(),
+    )
+}
+
+#[tracing::instrument(skip_all)]
+async fn login_handler(req: HttpRequest, body: web::Bytes) -> HttpResponse {
+    let Some(store) = req.app_data::<web::Data<UserStore>>().cloned() else {
+        return HttpResponse::NotFound().finish();
+    };
+    if form_pages::is_form(&req) {
+        return login_form_handler(req, store, form_pages::values(&body)).await;
+    }
+    if req.content_type() != "application/json" {
+        return HttpResponse::UnsupportedMediaType()
+            .json(json!({ "error": "send the login as application/json or a form" }));
+    }
+    let login = match serde_json::from_slice::<Login>(&body) {
+        Ok(login) => login,
+        Err(e) => return HttpResponse::BadRequest().json(json!({ "error": e.to_string() })),
+    };
+    let hours = auth(&req).settings.session_hours;
+
+    match log_in(&req, store, login).await {
+        Ok(LoginOutcome::Issued(user, token)) => HttpResponse::Ok()
+            .cookie(session_cookie(
+                &req,
+                token,
+                time::Duration::hours(hours.into()),
+            ))
+            .json(Me {
+                username: &user.username,
+                role: user.role,
+            }),
+        Ok(LoginOutcome::CodeNeeded) => HttpResponse::Unauthorized()
+            .json(json!({ "error": "two-factor code required", "two_factor": true })),
+        Ok(LoginOutcome::Throttled(wait)) => HttpResponse::TooManyRequests()
+            .insert_header((RETRY_AFTER, wait))
+            .json(json!({ "error": "too many failed logins; try again later" })),
+        Ok(LoginOutcome::Denied) => AuthError::Unauthenticated.error_response(),
+        Err(e) => AuthError::Store(e).error_response(),
+    }
+}
+
+async fn login_form_handler(
+    req: HttpRequest,
+    store: web::Data<UserStore>,
+    mut values: FormValues,
+) -> HttpResponse {
+    let spec = login_form();
+    let mut errors = spec.validate_required(&values);
+    if !errors.is_empty() {
+        values.remove("password");
+        return form_pages::respond(
+            &req,
+            StatusCode::UNPROCESSABLE_ENTITY,
+            &spec,
+            &values,
+            &errors,
+        );
+    }
+    let login = Login {
+        username: values["username"].clone(),
+        password: values.remove("password").unwrap_or_default(),
+        code: values.get("code").filter(|c| !c.is_empty()).cloned(),
+    };
+    let hours = auth(&req).settings.session_hours;
+
+    match log_in(&req, store, login).await {
+        Ok(LoginOutcome::Issued(_, token)) => {
+            let next = form_pages::local_path(values.get("next").map(String::as_str));
+            let mut res = form_pages::redirect(&req, next.unwrap_or("/"));
+            if let Err(e) = res.add_cookie(&session_cookie(
+                &req,
+                token,
+                time::Duration::hours(hours.into()),
+            )) {
+                error!("Setting the session cookie failed: {}", e);
+                return HttpResponse::InternalServerError().finish();
+            }
+            res
+        }
+        Ok(LoginOutcome::CodeNeeded) => {
+            errors.add("code", "Enter the code from your authenticator app.");
+            form_pages::respond(&req, StatusCode::UNAUTHORIZED, &spec, &values, &errors)
+        }
+        Ok(LoginOutcome::Throttled(wait)) => {
+            errors.add_form("Too many failed logins; try again in a minute.");
+            let res =
+                form_pages::respond(&req, StatusCode::TOO_MANY_REQUESTS, &spec, &values, &errors);
+            retry_after(res, wait)
+        }
+        Ok(LoginOutcome::Denied) => {
+            errors.add_form("Wrong username, password or code.");
+            form_pages::respond(&req, StatusCode::UNAUTHORIZED, &spec, &values, &errors)
+        }
+        Err(e) => AuthError::Store(e).error_response(),
+    }
+}
+
+/// Check `login` against the store and open a session when it holds up.
+/// Failures count against the client's and the account's limits.
+async fn log_in(
+    req: &HttpRequest,
+    store: web::Data<UserStore>,
+    login: Login,
+) -> Result<LoginOutcome, UserError> {
+    let Login {
+        username,
+        password,
+        code,
+    } = login;
+    let auth = auth(req);
+    let hours = auth.settings.session_hours;
+    audit::annotate(
+        req,
+        AuditNote::new("auth.login", format!("user:{username}")).with_actor(username.clone()),
+    );
+    let client = client_of(req);
+    if let Err(wait) = auth.login_limits.check(&client) {
+        return Ok(LoginOutcome::Throttled(wait));
+    }
+
+    let outcome = blocking(move || {
+        let Some(user) = store.verify(&username, &password)? else {
+            let _ = auth.login_limits.take(&client);
+            return Ok(LoginOutcome::Denied);
+        };
+        if user.has_two_factor() {
+            let Some(code) = code else {
+                return Ok(LoginOutcome::CodeNeeded);
+            };
+            if let Err(wait) = auth.two_factor_limits.check(&user.username) {
+                return Ok(LoginOutcome::Throttled(wait));
+            }
+            if !store.check_second_factor(&user.username, &code, Utc::now())? {
+                let _ = auth.login_limits.take(&client);
+                let _ = auth.two_factor_limits.take(&user.username);
+                return Ok(LoginOutcome::Denied);
+            }
+        }
+        let token = store.issue_session(&user, Duration::hours(hours.into()))?;
+        Ok::<_, UserError>(LoginOutcome::Issued(user, token))
+    })
+    .await?;
+    if let LoginOutcome::Issued(user, _) = &outcome {
+        info!("{} logged in as {}", user.username, user.role);
+    }
+    Ok(outcome)
+}
+
+/// Whom per-client limits count `req` against.
+fn client_of(req: &HttpRequest) -> String {
+    quota::client_addr(req).unwrap_or_else(|| "unknown".to_owned())
+}
+
+/// `res` with how many seconds to wait before trying again.
+fn retry_after(mut res: HttpResponse, wait: u64) -> HttpResponse {
+    res.headers_mut().insert(RETRY_AFTER, wait.into());
+    res
+}
+
+#[tracing::instrument(skip_all)]
+async fn logout_handler(req: HttpRequest) -> HttpResponse {
+    if let (Some(store), Some(cookie)) = (
+        req.app_data::<web::Data<UserStore>>().cloned(),
+        req.cookie(SESSION_COOKIE),
+    ) {
+        let token = cookie.value().to_owned();
+        if let Ok(Err(e)) = web::block(move || store.revoke_session(&token)).await {
+            error!("Ending a session failed: {}", e);
+        }
+    }
+    HttpResponse::NoContent()
+        .cookie(session_cookie(&req, String::new(), time::Duration::ZERO))
+        .finish()
+}
+
+async fn me_handler(auth: RequireRole<Viewer>) -> HttpResponse {
+    HttpResponse::Ok().json(Me {
+        username: &auth.user.username,
+        role: auth.user.role,
+    })
+}
+
+async fn sessions_handler(
+    req: HttpRequest,
+    store: web::Data<UserStore>,
+    auth: RequireRole<Viewer>,
+) -> Result<HttpResponse, AuthError> {
+    let current = req
+        .cookie(SESSION_COOKIE)
+        .map(|c| UserStore::session_id(c.value()));
+    let username = auth.user.username;
+    let sessions = blocking(move || store.sessions(&username, Utc::now())).await?;
+    let items: Vec<_> = sessions
+        .into_iter()
+        .map(|s| {
+            let mut item = json!(s);
+            item["current"] = json!(current.as_deref() == Some(s.id.as_str()));
+            item
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(json!({ "items": items })))
+}
+
+/// Log out everywhere, this browser included.
+#[tracing::instrument(skip_all)]
+async fn end_sessions_handler(
+    req: HttpRequest,
+    store: web::Data<UserStore>,
+    auth: RequireRole<Viewer>,
+) -> Result<HttpResponse, AuthError> {
+    let username = auth.user.username;
+    audit::annotate(
+        &req,
+        AuditNote::new("auth.sessions.end", format!("user:{username}")),
+    );
+    let ended = {
+        let username = username.clone();
+        blocking(move || store.revoke_sessions(&username)).await?
+    };
+    info!("{} ended all {} of their sessions", username, ended);
+    Ok(HttpResponse::NoContent()
+        .cookie(session_cookie(&req, String::new(), time::Duration::ZERO))
+        .finish())
+}
+
+#[tracing::instrument(skip_all)]
+async fn end_session_handler(
+    req: HttpRequest,
+    store: web::Data<UserStore>,
+    path: web::Path<String>,
+    auth: RequireRole<Viewer>,
+) -> Result<HttpResponse, AuthError> {
+    let id = path.into_inner();
+    let username = auth.user.username;
+    audit::annotate(
+        &req,
+        AuditNote::new("auth.session.end", format!("user:{username}")),
+    );
+    if blocking(move || store.revoke_session_id(&username, &id)).await? {
+        Ok(HttpResponse::NoContent().finish())
+    } else {
+        Ok(HttpResponse::NotFound().json(json!({ "error": "no such session" })))
+    }
+}
+
+#[derive(Deserialize)]
+struct ResetRequest {
+    username: String,
+}
+
+/// The email carrying `code` for `user`.
+fn reset_email(user: &User, code: &str, minutes: u32) -> Option<Delivery> {
+    Some(Delivery::Email {
+        to: vec![user.email.clone()?],
+        subject: "Your password reset code".to_owned(),
+        body: format!(
+            "Someone asked to reset the password of {} on this site.\n\n\
+             Your code, good for {} minutes:\n\n    {}\n\n\
+             If that was not you, ignore this email; your password stays the same.\n",
+            user.username, minutes, code
+        ),
+    })
+}
+
+/// The reset pages; the request form goes on to the confirm form.
+const RESET_PAGE: &str = "/admin/password-reset";
+const RESET_CONFIRM_PAGE: &str = "/admin/password-reset/confirm";
+
+fn reset_request_form() -> FormSpec {
+    FormSpec::new("password-reset", "Reset your password", RESET_PAGE)
+        .with_field(
+            FieldSpec::new("username", "Username", FieldKind::Text)
+                .required()
+                .with_autocomplete("username"),
+        )
+        .with_submit_label("Email me a code")
+}
+
+fn reset_confirm_form() -> FormSpec {
+    FormSpec::new(
+        "password-reset-confirm",
+        "Choose a new password",
+        RESET_CONFIRM_PAGE,
+    )
+    .with_field(
+        FieldSpec::new("code", "Reset code", FieldKind::Text)
+            .required()
+            .with_help("From the email, if the account has an address.")
+            .with_autocomplete("one-time-code"),
+    )
+    .with_field(
+        FieldSpec::new("password", "New password", FieldKind::Password)
+            .required()
+            .with_autocomplete("new-password"),
+    )
+    .with_submit_label("Set password")
+}
+
+async fn reset_request_page_handler(req: HttpRequest) -> HttpResponse {
+    if auth(&req).resets.is_none() {
+        return HttpResponse::NotFound().finish();
+    }
+    form_pages::respond(
+        &req,
+        StatusCode::OK,
+        &reset_request_form(),
+        &FormValues::new(),
+        &FormErrors::default(),
+    )
+}
+
+async fn reset_confirm_page_handler(req: HttpRequest) -> HttpResponse {
+    if auth(&req).resets.is_none() {
+        return HttpResponse::NotFound().finish();
+    }
+    form_pages::respond(
+        &req,
+        StatusCode::OK,
+        &reset_confirm_form(),
+        &FormValues::new(),
+        &FormErrors::default(),
+    )
+}
+
+/// Always 202 once resets are on, so the answer does not tell which
+/// usernames exist or have an email address; the form goes on to the
+/// confirm page either way.
+#[tracing::instrument(skip_all)]
+async fn reset_request_handler(
+    req: HttpRequest,
+    store: web::Data<UserStore>,
+    body: web::Bytes,
+) -> Result<HttpResponse, AuthError> {
+    let auth = auth(&req);
+    if auth.resets.is_none() {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    if form_pages::is_form(&req) {
+        return reset_request_form_handler(req, store, auth, form_pages::values(&body)).await;
+    }
+    let username = match serde_json::from_slice::<ResetRequest>(&body) {
+        Ok(body) => body.username,
+        Err(e) => return Ok(HttpResponse::BadRequest().json(json!({ "error": e.to_string() }))),
+    };
+    if let Err(wait) = auth.reset_limits.take(&client_of(&req)) {
+        return Ok(HttpResponse::TooManyRequests()
+            .insert_header((RETRY_AFTER, wait))
+            .json(json!({ "error": "too many reset requests; try again later" })));
+    }
+    send_reset(&req, store, auth, username).await?;
+    Ok(HttpResponse::Accepted().finish())
+}
+
+async fn reset_request_form_handler(
+    req: HttpRequest,
+    store: web::Data<UserStore>,
+    auth: web::Data<Auth>,
+    values: FormValues,
+) -> Result<HttpResponse, AuthError> {
+    let spec = reset_request_form();
+    let mut errors = spec.validate_required(&values);
+    if !errors.is_empty() {
+        return Ok(form_pages::respond(
+            &req,
+            StatusCode::UNPROCESSABLE_ENTITY,
+            &spec,
+            &values,
+            &errors,
+        ));
+    }
+    if let Err(wait) = auth.reset_limits.take(&client_of(&req)) {
+        errors.add_form("Too many reset requests; try again in a minute.");
+        let res = form_pages::respond(&req, StatusCode::TOO_MANY_REQUESTS, &spec, &values, &errors);
+        return Ok(retry_after(res, wait));
+    }
+    send_reset(&req, store, auth, values["username"].clone()).await?;
+    Ok(form_pages::redirect(&req, RESET_CONFIRM_PAGE))
+}
+
+/// Mail `username` a reset code, if the account exists and has an email
+/// address.
+async fn send_reset(
+    req: &HttpRequest,
+    store: web::Data<UserStore>,
+    auth: web::Data<Auth>,
+    username: String,
+) -> Result<(), UserError> {
+    audit::annotate(
+        req,
+        AuditNote::new("auth.reset.request", format!("user:{username}")),
+    );
+    let minutes = auth.settings.reset_minutes;
+    let sent = blocking(move || -> Result<_, UserError> {
+        let Some(outbox) = &auth.resets else {
+            return Ok(None);
+        };
+        let Some((user, code)) = store.issue_reset(&username, Duration::minutes(minutes.into()))?
+        else {
+            return Ok(None);
+        };
+        if let Some(email) = reset_email(&user, &code, minutes) {
+            outbox
+                .enqueue(email)
+                .map_err(|e| UserError::Io(std::io::Error::other(e)))?;
+        }
+        Ok(Some(user.username))
+    })
+    .await?;
+    match sent {
+        Some(username) => info!("Mailed a password reset code to {}", username),
+        None => debug!("Password reset asked for an account without an email address"),
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct ResetConfirm {
+    code: String,
+    password: String,
+}
+
+#[tracing::instrument(skip_all)]
+async fn reset_confirm_handler(
+    req: HttpRequest,
+    store: web::Data<UserStore>,
+    body: web::Bytes,
+) -> Result<HttpResponse, AuthError> {
+    if auth(&req).resets.is_none() {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    if form_pages::is_form(&req) {
+        return reset_confirm_form_handler(req, store, form_pages::values(&body)).await;
+    }
+    let ResetConfirm { code, password } = match serde_json::from_slice(&body) {
+        Ok(body) => body,
+        Err(e) => return Ok(HttpResponse::BadRequest().json(json!({ "error": e.to_string() }))),
+    };
+    let password = match ValidatedPassword::new(password) {
+        Ok(password) => password,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(json!({ "error": e.to_string() })));
+        }
+    };
+    if redeem_reset(&req, store, code, password).await? {
+        Ok(HttpResponse::NoContent().finish())
+    } else {
+        Ok(HttpResponse::BadRequest()
+            .json(json!({ "error": "that reset code is not valid or has expired" })))
+    }
+}
+
+async fn reset_confirm_form_handler(
+    req: HttpRequest,
+    store: web::Data<UserStore>,
+    mut values: FormValues,
+) -> Result<HttpResponse, AuthError> {
+    let spec = reset_confirm_form();
+    let mut errors = spec.validate_required(&values);
+    let password = values.remove("password").unwrap_or_default();
+    if errors.is_empty() {
+        match ValidatedPassword::new(password) {
+            Ok(password) => {
+                if redeem_reset(&req, store, values["code"].clone(), password).await? {
+                    return Ok(form_pages::redirect(&req, "/admin/login"));
+                }
+                errors.add("code", "That reset code is not valid or has expired.");
+            }
+            Err(e) => errors.add("password", e.to_string()),
+        }
+    }
+    Ok(form_pages::respond(
+        &req,
+        StatusCode::UNPROCESSABLE_ENTITY,
+        &spec,
+        &values,
+        &errors,
+    ))
+}
+
+/// Give the account `code` was issued for `password`; false when the code
+/// is not valid.
+async fn redeem_reset(
+    req: &HttpRequest,
+    store: web::Data<UserStore>,
+    code: String,
+    password: ValidatedPassword,
+) -> Result<bool, UserError> {
+    let Some(user) = blocking(move || store.redeem_reset(&code, &password, Utc::now())).await?
+    else {
+        return Ok(false);
+    };
+    audit::annotate(
+        req,
+        AuditNote::new("auth.reset", format!("user:{}", user.username))
+            .with_actor(user.username.clone()),
+    );
+    info!("{} chose a new password with a reset code", user.username);
+    Ok(true)
+}
+
+/// The logged-in user, whatever their role and second factor, for the
+/// routes that set the second factor up.
+async fn logged_in(req: &HttpRequest) -> Result<User, AuthError> {
+    current_user(req).await?.ok_or(AuthError::Unauthenticated)
+}
+
+async fn two_factor_handler(req: HttpRequest) -> Result<HttpResponse, AuthError> {
+    let user = logged_in(&req).await?;
+    let two_factor = user.two_factor.as_ref();
+    Ok(HttpResponse::Ok().json(json!({
+        "enabled": user.has_two_factor(),
+        "pending": two_factor.is_some_and(|t| !t.confirmed),
+        "recovery_codes_left": two_factor.filter(|t| t.confirmed).map_or(0, |t| t.recovery.len()),
+    })))
+}
+
+#[tracing::instrument(skip_all)]
+async fn two_factor_begin_handler(
+    req: HttpRequest,
+    store: web::Data<UserStore>,
+) -> Result<HttpResponse, AuthError> {
+    let username = logged_in(&req).await?.username;
+    let Some(secret) = begin_two_factor(&req, store, username.clone()).await? else {
+        return Ok(HttpResponse::Conflict()
+            .json(json!({ "error": UserError::TwoFactorOn(username).to_string() })));
+    };
+    let uri = totp::provisioning_uri(TOTP_ISSUER, &username, &secret);
+    Ok(HttpResponse::Ok().json(json!({ "secret": secret, "uri": uri })))
+}
+
+/// A new secret for `username`'s app; `None` when two-factor login is
+/// already on.
+async fn begin_two_factor(
+    req: &HttpRequest,
+    store: web::Data<UserStore>,
+    username: String,
+) -> Result<Option<String>, UserError> {
+    audit::annotate(
+        req,
+        AuditNote::new("auth.two_factor.begin", format!("user:{username}")),
+    );
+    match blocking(move || store.begin_two_factor(&username)).await {
+        Ok(secret) => Ok(Some(secret)),
+        Err(UserError::TwoFactorOn(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+#[derive(Deserialize)]
+struct TwoFactorCode {
+    code: String,
+}
+
+#[tracing::instrument(skip_all)]
+async fn two_factor_confirm_handler(
+    req: HttpRequest,
+    store: web::Data<UserStore>,
+    body: web::Json<TwoFactorCode>,
+) -> Result<HttpResponse, AuthError> {
+    let username = logged_in(&req).await?.username;
+    let code = body.into_inner().code;
+    match confirm_two_factor(&req, store, username, code).await? {
+        Some(recovery_codes) => {
+            Ok(HttpResponse::Ok().json(json!({ "recovery_codes": recovery_codes })))
+        }
+        None => Ok(HttpResponse::BadRequest()
+            .json(json!({ "error": "that code does not match; start over if it keeps failing" }))),
+    }
+}
+
+/// Turn on `username`'s pending two-factor login with a first code from
+/// the app: the recovery codes, or `None` when the code does not match.
+async fn confirm_two_factor(
+    req: &HttpRequest,
+    store: web::Data<UserStore>,
+    username: String,
+    code: String,
+) -> Result<Option<Vec<String>>, UserError> {
+    audit::annotate(
+        req,
+        AuditNote::new("auth.two_factor.enable", format!("user:{username}")),
+    );
+    let confirmed = {
+        let username = username.clone();
+        blocking(move || store.confirm_two_factor(&username, &code, Utc::now())).await?
+    };
+    if confirmed.is_some() {
+        info!("{} turned on two-factor login", username);
+    }
+    Ok(confirmed)
+}
+
+/// The two-factor setup pages.
+const TWO_FACTOR_PAGE: &str = "/admin/two-factor/setup";
+const TWO_FACTOR_CONFIRM_PAGE: &str = "/admin/two-factor/setup/confirm";
+
+/// `logged_in` for a page: someone not logged in is sent to log in first.
+async fn page_user(req: &HttpRequest) -> Result<User, HttpResponse> {
+    match logged_in(req).await {
+        Ok(user) => Ok(user),
+        Err(AuthError::Unauthenticated) => Err(form_pages::login_redirect(req)),
+        Err(e) => Err(e.error_response()),
+    }
+}
+
+fn two_factor_begin_form() -> FormSpec {
+    FormSpec::new("two-factor", "Two-factor login", TWO_FACTOR_PAGE)
+        .with_submit_label("Set up two-factor login")
+}
+
+/// Asks for the first code from the app given `secret`.
+fn two_factor_confirm_form(username: &str, secret: &str) -> FormSpec {
+    let uri = totp::provisioning_uri(TOTP_ISSUER, username, secret);
+    FormSpec::new(
+        "two-factor-confirm",
+        "Confirm two-factor login",
+        TWO_FACTOR_CONFIRM_PAGE,
+    )
+    .with_field(
+        FieldSpec::new("code", "Code from the app", FieldKind::Text)
+            .required()
+            .with_help(format!(
+                "Add the key {secret} to your authenticator app, or open {uri} on this device, \
+                 then enter the code it shows."
+            ))
+            .with_autocomplete("one-time-code"),
+    )
+    .with_submit_label("Turn on")
+}
+
+async fn two_factor_page_handler(req: HttpRequest) -> HttpResponse {
+    let user = match page_user(&req).await {
+        Ok(user) => user,
+        Err(res) => return res,
+    };
+    let spec = match user.two_factor.as_ref() {
+        Some(t) if t.confirmed => {
+            let left = format!(
+                "Two-factor login is on, with {} recovery codes left.",
+                t.recovery.len()
+            );
+            return form_pages::notice(&req, "Two-factor login", &[left]);
+        }
+        Some(pending) => two_factor_confirm_form(&user.username, &pending.secret),
+        None => two_factor_begin_form(),
+    };
+    form_pages::respond(
+        &req,
+        StatusCode::OK,
+        &spec,
+        &FormValues::new(),
+        &FormErrors::default(),
+    )
+}
+
+#[tracing::instrument(skip_all)]
+async fn two_factor_begin_form_handler(
+    req: HttpRequest,
+    store: web::Data<UserStore>,
+) -> Result<HttpResponse, AuthError> {
+    let username = match page_user(&req).await {
+        Ok(user) => user.username,
+        Err(res) => return Ok(res),
+    };
+    let Some(secret) = begin_two_factor(&req, store, username.clone()).await? else {
+        return Ok(form_pages::redirect(&req, TWO_FACTOR_PAGE));
+    };
+    Ok(form_pages::respond(
+        &req,
+        StatusCode::OK,
+        &two_factor_confirm_form(&username, &secret),
+        &FormValues::new(),
+        &FormErrors::default(),
+    ))
+}
+
+#[tracing::instrument(skip_all)]
+async fn two_factor_confirm_form_handler(
+    req: HttpRequest,
+    store: web::Data<UserStore>,
+    body: web::Bytes,
+) -> Result<HttpResponse, AuthError> {
+    let user = match page_user(&req).await {
+        Ok(user) => user,
+        Err(res) => return Ok(res),
+    };
+    let Some(pending) = user.two_factor.as_ref().filter(|t| !t.confirmed) else {
+        return Ok(form_pages::redirect(&req, TWO_FACTOR_PAGE));
+    };
+    let spec = two_factor_confirm_form(&user.username, &pending.secret);
+    let values = form_pages::values(&body);
+    let mut errors = spec.validate_required(&values);
+    if errors.is_empty() {
+        let code = values["code"].clone();
+        if let Some(recovery_codes) =
+            confirm_two_factor(&req, store, user.username.clone(), code).await?
+        {
+            let mut lines = vec![String::from(
+                "Keep these recovery codes somewhere safe. Each logs you in once in place \
+                 of a code from the app, and they are not shown again.",
+            )];
+            lines.extend(recovery_codes);
+            return Ok(form_pages::notice(&req, "Two-factor login is on", &lines));
+        }
+        errors.add(
+            "code",
+            "That code does not match; check the clock on the device with the app.",
+        );
+    }
+    Ok(form_pages::respond(
+        &req,
+        StatusCode::UNPROCESSABLE_ENTITY,
+        &spec,
+        &values,
+        &errors,
+    ))
+}
+
+#[tracing::instrument(skip_all)]
+async fn two_factor_disable_handler(
+    req: HttpRequest,
+    store: web::Data<UserStore>,
+    body: web::Json<TwoFactorCode>,
+) -> Result<HttpResponse, AuthError> {
+    let username = logged_in(&req).await?.username;
+    audit::annotate(
+        &req,
+        AuditNote::new("auth.two_factor.disable", format!("user:{username}")),
+    );
+    let auth = auth(&req);
+    if let Err(wait) = auth.two_factor_limits.check(&username) {
+        return Ok(HttpResponse::TooManyRequests()
+            .insert_header((RETRY_AFTER, wait))
+            .json(json!({ "error": "too many wrong codes; try again later" })));
+    }
+    let code = body.into_inner().code;
+    let disabled = {
+        let username = username.clone();
+        blocking(move || {
+            if !store.check_second_factor(&username, &code, Utc::now())? {
+                let _ = auth.two_factor_limits.take(&username);
+                return Ok(false);
+            }
+            store.disable_two_factor(&username).map(|_| true)
+        })
+        .await?
+    };
+    if disabled {
+        info!("{} turned off two-factor login", username);
+        Ok(HttpResponse::NoContent().finish())
+    } else {
+        Ok(HttpResponse::BadRequest().json(json!({ "error": "that code does not match" })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::users::ValidatedPassword;
+    use actix_web::{test as actix_test, App};
+
+    async fn admin_only(auth: RequireRole<Admin>) -> HttpResponse {
+        HttpResponse::Ok().body(auth.user.username)
+    }
+
+    #[actix_web::test]
+    async fn sessions_gate_routes_by_role() {
+        let root = tempfile::tempdir().unwrap();
+        let store = store(root.path());
+        store
+            .create(
+                "ed",
+                Role::Editor,
+                &ValidatedPassword::new("a long enough secret").unwrap(),
+            )
+            .unwrap();
+        let app = actix_test::init_service(
+            App::new().service(
+                web::scope("")
+                    .app_data(store)
+                    .service(services())
+                    .route("/admin/users", web::get().to(admin_only)),
+            ),
+        )
+        .await;
+
+        let req = actix_test::TestRequest::get().uri("/admin/me").to_request();
+        let res = actix_test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let req = actix_test::TestRequest::post()
+            .uri("/admin/login")
+            .set_json(json!({ "username": "ed", "password": "wrong" }))
+            .to_request();
+        let res = actix_test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let req = actix_test::TestRequest::post()
+            .uri("/admin/login")
+            .set_json(json!({ "username": "ed", "password": "a long enough secret" }))
+            .to_request();
+        let res = actix_test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let cookie = res
+            .response()
+            .cookies()
+            .find(|c| c.name() == SESSION_COOKIE)
+            .unwrap()
+            .into_owned();
+        assert!(cookie.http_only().unwrap_or(false));
+
+        let req = actix_test::TestRequest::get()
+            .uri("/admin/me")
+            .cookie(cookie.clone())
+            .to_request();
+        let me: serde_json::Value = actix_test::call_and_read_body_json(&app, req).await;
+        assert_eq!(me, json!({ "username": "ed", "role": "editor" }));
+
+        let req = actix_test::TestRequest::get()
+            .uri("/admin/users")
+            .cookie(cookie.clone())
+            .to_request();
+        let res = actix_test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let req = actix_test::TestRequest::post()
+            .uri("/admin/logout")
+            .cookie(cookie.clone())
+            .to_request();
+        actix_test::call_service(&app, req).await;
+        let req = actix_test::TestRequest::get()
+            .uri("/admin/me")
+            .cookie(cookie)
+            .to_request();
+        let res = actix_test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn sessions_are_listed_ended_and_reset() {
+        let root = tempfile::tempdir().unwrap();
+        let store = store(root.path());
+        store
+            .create(
+                "vi",
+                Role::Viewer,
+                &ValidatedPassword::new("a long enough secret").unwrap(),
+            )
+            .unwrap();
+        store.set_email("vi", Some("vi@example.com")).unwrap();
+        let auth = Auth::default().with_resets(root.path()).unwrap();
+        let app = actix_test::init_service(
+            App::new().service(
+                web::scope("")
+                    .app_data(store.clone())
+                    .app_data(web::Data::new(auth))
+                    .service(services()),
+            ),
+        )
+        .await;
+        let login = || async {
+            let req = actix_test::TestRequest::post()
+                .uri("/admin/login")
+                .set_json(json!({ "username": "vi", "password": "a long enough secret" }))
+                .to_request();
+            let res = actix_test::call_service(&app, req).await;
+            res.response()
+                .cookies()
+                .find(|c| c.name() == SESSION_COOKIE)
+                .unwrap()
+                .into_owned()
+        };
+        let laptop = login().await;
+        let phone = login().await;
+
+        let req = actix_test::TestRequest::get()
+            .uri("/admin/sessions")
+            .cookie(laptop.clone())
+            .to_request();
+        let listed: serde_json::Value = actix_test::call_and_read_body_json(&app, req).await;
+        let items = listed["items"].as_array().unwrap();
+        assert_eq!(items.len(), 2);
+        let phone_id = UserStore::session_id(phone.value());
+        let phone_item = items.iter().find(|i| i["id"] == phone_id.as_str()).unwrap();
+        assert_eq!(phone_item["current"], json!(false));
+
+        let req = actix_test::TestRequest::delete()
+            .uri(&format!("/admin/sessions/{phone_id}"))
+            .cookie(laptop.clone())
+            .to_request();
+        let res = actix_test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        let req = actix_test::TestRequest::get()
+            .uri("/admin/me")
+            .cookie(phone)
+            .to_request();
+        let res = actix_test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let req = actix_test::TestRequest::delete()
+            .uri("/admin/sessions")
+            .cookie(laptop.clone())
+            .to_request();
+        let res = actix_test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        let req = actix_test::TestRequest::get()
+            .uri("/admin/me")
+            .cookie(laptop)
+            .to_request();
+        let res = actix_test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        for username in ["vi", "nobody"] {
+            let req = actix_test::TestRequest::post()
+                .uri("/admin/password-reset")
+                .set_json(json!({ "username": username }))
+                .to_request();
+            let res = actix_test::call_service(&app, req).await;
+            assert_eq!(res.status(), StatusCode::ACCEPTED);
+        }
+        let queued = Outbox::open(root.path().join(OUTBOX_DIR))
+            .unwrap()
+            .list(crate::db::outbox::OutboxState::Pending)
+            .unwrap();
+        assert_eq!(queued.len(), 1);
+        let Delivery::Email { to, body, .. } = &queued[0].delivery else {
+            panic!("expected an email");
+        };
+        assert_eq!(to, &["vi@example.com"]);
+        let code = body
+            .lines()
+            .map(str::trim)
+            .find(|l| l.len() == 64)
+            .unwrap()
+            .to_owned();
+
+        let confirm = |code: String| {
+            actix_test::TestRequest::post()
+                .uri("/admin/password-reset/confirm")
+                .set_json(json!({ "code": code, "password": "the new long secret" }))
+                .to_request()
+        };
+        let res = actix_test::call_service(&app, confirm(code.clone())).await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        let res = actix_test::call_service(&app, confirm(code)).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert!(store.verify("vi", "the new long secret").unwrap().is_some());
+    }
+
+    #[actix_web::test]
+    async fn two_factor_logins_take_a_code() {
+        let root = tempfile::tempdir().unwrap();
+        let store = store(root.path());
+        store
+            .create(
+                "al",
+                Role::Admin,
+                &ValidatedPassword::new("a long enough secret").unwrap(),
+            )
+            .unwrap();
+        let app = actix_test::init_service(
+            App::new().service(
+                web::scope("")
+                    .app_data(store.clone())
+                    .app_data(web::Data::new(Auth::default()))
+                    .service(services()),
+            ),
+        )
+        .await;
+        let login = |code: Option<String>| {
+            actix_test::TestRequest::post()
+                .uri("/admin/login")
+                .set_json(json!({
+                    "username": "al",
+                    "password": "a long enough secret",
+                    "code": code,
+                }))
+                .to_request()
+        };
+        let res = actix_test::call_service(&app, login(None)).await;
+        let cookie = res
+            .response()
+            .cookies()
+            .find(|c| c.name() == SESSION_COOKIE)
+            .unwrap()
+            .into_owned();
+
+        let req = actix_test::TestRequest::post()
+            .uri("/admin/two-factor")
+            .cookie(cookie.clone())
+            .to_request();
+        let begun: serde_json::Value = actix_test::call_and_read_body_json(&app, req).await;
+        let secret = begun["secret"].as_str().unwrap().to_owned();
+        assert!(begun["uri"]
+            .as_str()
+            .unwrap()
+            .starts_with("otpauth://totp/WhisperCMS:al?"));
+        let code = |offset: i64| {
+            let step = totp::step_at(Utc::now().timestamp()) + offset;
+            totp::code_at(&secret, step).unwrap()
+        };
+        let req = actix_test::TestRequest::post()
+            .uri("/admin/two-factor/confirm")
+            .cookie(cookie.clone())
+            .set_json(json!({ "code": code(0) }))
+            .to_request();
+        let confirmed: serde_json::Value = actix_test::call_and_read_body_json(&app, req).await;
+        let recovery = confirmed["recovery_codes"][0].as_str().unwrap().to_owned();
+
+        let res = actix_test::call_service(&app, login(None)).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        let body: serde_json::Value = actix_test::read_body_json(res).await;
+        assert_eq!(body["two_factor"], json!(true));
+        let res = actix_test::call_service(&app, login(Some("000000x".into()))).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        let res = actix_test::call_service(&app, login(Some(code(1)))).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = actix_test::call_service(&app, login(Some(recovery.clone()))).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = actix_test::call_service(&app, login(Some(recovery))).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let req = actix_test::TestRequest::get()
+            .uri("/admin/two-factor")
+            .cookie(cookie)
+            .to_request();
+        let state: serde_json::Value = actix_test::call_and_read_body_json(&app, req).await;
+        assert_eq!(
+            state,
+            json!({ "enabled": true, "pending": false, "recovery_codes_left": 9 })
+        );
+    }
+
+    #[actix_web::test]
+    async fn failed_logins_and_wrong_codes_are_throttled() {
+        let root = tempfile::tempdir().unwrap();
+        let store = store(root.path());
+        for name in ["jo", "mo"] {
+            store
+                .create(
+                    name,
+                    Role::Editor,
+                    &ValidatedPassword::new("a long enough secret").unwrap(),
+                )
+                .unwrap();
+        }
+        let secret = store.begin_two_factor("mo").unwrap();
+        let code = totp::code_at(&secret, totp::step_at(Utc::now().timestamp())).unwrap();
+        store.confirm_two_factor("mo", &code, Utc::now()).unwrap();
+        let app = actix_test::init_service(
+            App::new().service(
+                web::scope("")
+                    .app_data(store)
+                    .app_data(web::Data::new(Auth::default()))
+                    .service(services()),
+            ),
+        )
+        .await;
+        let login = |peer: &str, body: serde_json::Value| {
+            actix_test::TestRequest::post()
+                .uri("/admin/login")
+                .peer_addr(format!("{peer}:40000").parse().unwrap())
+                .set_json(body)
+                .to_request()
+        };
+        let right = json!({ "username": "jo", "password": "a long enough secret" });
+        let wrong = json!({ "username": "jo", "password": "wrong" });
+
+        // Checking a password is slow enough in tests for the bucket to
+        // refill a little, so fail until refused rather than counting.
+        let mut refused = false;
+        for _ in 0..20 {
+            let res = actix_test::call_service(&app, login("192.0.2.1", wrong.clone())).await;
+            if res.status() == StatusCode::TOO_MANY_REQUESTS {
+                refused = true;
+                break;
+            }
+            assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        }
+        assert!(refused);
+        let res = actix_test::call_service(&app, login("192.0.2.1", right.clone())).await;
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(res.headers().contains_key(RETRY_AFTER));
+        let res = actix_test::call_service(&app, login("192.0.2.2", right)).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // Wrong codes count against the account, whichever address sends them.
+        let mut refused = false;
+        for n in 0..20 {
+            let peer = format!("192.0.2.{}", 100 + n);
+            let body = json!({
+                "username": "mo",
+                "password": "a long enough secret",
+                "code": "000000x",
+            });
+            let res = actix_test::call_service(&app, login(&peer, body)).await;
+            if res.status() == StatusCode::TOO_MANY_REQUESTS {
+                refused = true;
+                break;
+            }
+            assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        }
+        assert!(refused);
+        let body = json!({
+            "username": "mo",
+            "password": "a long enough secret",
+            "code": code,
+        });
+        let res = actix_test::call_service(&app, login("192.0.2.200", body)).await;
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(res.headers().contains_key(RETRY_AFTER));
+    }
+
+    #[actix_web::test]
+    async fn passwords_are_reset_and_two_factor_set_up_from_pages() {
+        let root = tempfile::tempdir().unwrap();
+        let store = store(root.path());
+        let user = store
+            .create(
+                "cy",
+                Role::Editor,
+                &ValidatedPassword::new("a long enough secret").unwrap(),
+            )
+            .unwrap();
+        store.set_email("cy", Some("cy@example.com")).unwrap();
+        let auth = Auth::default().with_resets(root.path()).unwrap();
+        let app = actix_test::init_service(
+            App::new().service(
+                web::scope("")
+                    .app_data(store.clone())
+                    .app_data(web::Data::new(auth))
+                    .service(services()),
+            ),
+        )
+        .await;
+        let post = |uri: &str, body: String| {
+            actix_test::TestRequest::post()
+                .uri(uri)
+                .peer_addr("192.0.2.50:40000".parse().unwrap())
+                .insert_header(("content-type", "application/x-www-form-urlencoded"))
+                .set_payload(body)
+        };
+        let page = |res: actix_web::dev::ServiceResponse| async {
+            String::from_utf8(actix_test::read_body(res).await.to_vec()).unwrap()
+        };
+
+        // A cross-site text/plain post is not taken for a login.
+        let req = actix_test::TestRequest::post()
+            .uri("/admin/login")
+            .insert_header(("content-type", "text/plain"))
+            .set_payload(r#"{"username":"cy","password":"a long enough secret"}"#)
+            .to_request();
+        let res = actix_test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        let req = actix_test::TestRequest::get()
+            .uri("/admin/password-reset")
+            .to_request();
+        let form = page(actix_test::call_service(&app, req).await).await;
+        assert!(form.contains(r#"name="username""#), "{form}");
+        let res = actix_test::call_service(
+            &app,
+            post("/admin/password-reset", "username=nobody".into()).to_request(),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::SEE_OTHER);
+        assert_eq!(
+            res.headers().get("location").unwrap(),
+            "/admin/password-reset/confirm"
+        );
+
+        let res = actix_test::call_service(
+            &app,
+            post(
+                "/admin/password-reset/confirm",
+                "code=nope&password=the+new+long+secret".into(),
+            )
+            .to_request(),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let (_, code) = store
+            .issue_reset("cy", Duration::minutes(5))
+            .unwrap()
+            .unwrap();
+        let res = actix_test::call_service(
+            &app,
+            post(
+                "/admin/password-reset/confirm",
+                format!("code={code}&password=the+new+long+secret"),
+            )
+            .to_request(),
+        )
+        .await;
+        assert_eq!(res.headers().get("location").unwrap(), "/admin/login");
+        assert!(store.verify("cy", "the new long secret").unwrap().is_some());
+
+        let req = actix_test::TestRequest::get()
+            .uri("/admin/two-factor/setup")
+            .to_request();
+        let res = actix_test::call_service(&app, req).await;
+        assert_eq!(
+            res.headers().get("location").unwrap(),
+            "/admin/login?next=%2Fadmin%2Ftwo-factor%2Fsetup"
+        );
+
+        let token = store.issue_session(&user, Duration::hours(1)).unwrap();
+        let cookie = Cookie::new(SESSION_COOKIE, token);
+        let req = actix_test::TestRequest::get()
+            .uri("/admin/two-factor/setup")
+            .cookie(cookie.clone())
+            .to_request();
+        let form = page(actix_test::call_service(&app, req).await).await;
+        assert!(form.contains("Set up two-factor login"), "{form}");
+
+        let req = post("/admin/two-factor/setup", String::new())
+            .cookie(cookie.clone())
+            .insert_header(("HX-Request", "true"))
+            .to_request();
+        let fragment = page(actix_test::call_service(&app, req).await).await;
+        assert!(
+            fragment.starts_with(r#"<form id="two-factor-confirm""#),
+            "{fragment}"
+        );
+        let secret = store.get("cy").unwrap().unwrap().two_factor.unwrap().secret;
+        assert!(fragment.contains(&secret));
+
+        let confirm = |code: &str| {
+            post("/admin/two-factor/setup/confirm", format!("code={code}"))
+                .cookie(cookie.clone())
+                .to_request()
+        };
+        let res = actix_test::call_service(&app, confirm("000000")).await;
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let code = totp::code_at(&secret, totp::step_at(Utc::now().timestamp())).unwrap();
+        let res = actix_test::call_service(&app, confirm(&code)).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let shown = page(res).await;
+        assert!(shown.contains("Two-factor login is on"), "{shown}");
+        assert!(store.get("cy").unwrap().unwrap().has_two_factor());
+
+        let req = actix_test::TestRequest::get()
+            .uri("/admin/two-factor/setup")
+            .cookie(cookie)
+            .to_request();
+        let state = page(actix_test::call_service(&app, req).await).await;
+        assert!(state.contains("10 recovery codes left"), "{state}");
+    }
+
+    #[actix_web::test]
+    async fn the_login_page_logs_in_and_goes_on_to_next() {
+        let root = tempfile::tempdir().unwrap();
+        let store = store(root.path());
+        store
+            .create(
+                "ed",
+                Role::Editor,
+                &ValidatedPassword::new("a long enough secret").unwrap(),
+            )
+            .unwrap();
+        let app = actix_test::init_service(
+            App::new().service(
+                web::scope("")
+                    .app_data(store)
+                    .app_data(web::Data::new(Auth::default()))
+                    .service(services()),
+            ),
+        )
+        .await;
+        let post = |body: &'static str| {
+            actix_test::TestRequest::post()
+                .uri("/admin/login")
+                .insert_header(("content-type", "application/x-www-form-urlencoded"))
+                .set_payload(body)
+                .to_request()
+        };
+
+        let req = actix_test::TestRequest::get()
+            .uri("/admin/login?next=%2Fadmin%2Fextensions")
+            .to_request();
+        let res = actix_test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let page = String::from_utf8(actix_test::read_body(res).await.to_vec()).unwrap();
+        assert!(page.contains(r#"name="password""#) && page.contains("/admin/extensions"));
+
+        let res = actix_test::call_service(&app, post("username=ed")).await;
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let res = actix_test::call_service(&app, post("username=ed&password=wrong")).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        let page = String::from_utf8(actix_test::read_body(res).await.to_vec()).unwrap();
+        assert!(!page.contains("wrong"), "the password is not echoed back");
+
+        let res = actix_test::call_service(
+            &app,
+            post("username=ed&password=a+long+enough+secret&next=%2Fadmin%2Fextensions"),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::SEE_OTHER);
+        assert_eq!(res.headers().get("location").unwrap(), "/admin/extensions");
+        assert!(res.response().cookies().any(|c| c.name() == SESSION_COOKIE));
+
+        let res = actix_test::call_service(
+            &app,
+            post("username=ed&password=a+long+enough+secret&next=%2F%2Fevil.example"),
+        )
+        .await;
+        assert_eq!(res.headers().get("location").unwrap(), "/");
+    }
+}
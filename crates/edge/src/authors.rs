@@ -0,0 +1,103 @@
+// crates/edge/src/authors.rs
+
+// Author entities (serve::authors), loaded with the site's content
+// (fs::index::SiteContent):
+//
+//   GET /author/{slug}              archive page of the author's posts
+//   GET /author/{author}/feed.{ext} their feed (mounted by crate::feeds)
+//
+// The page renders the theme's author template (`author.hbs` unless
+// `[authors] template` says otherwise) from the theme mounted at `/`, and a
+// built-in page when the theme has none. Configured by an optional
+// `[authors]` table in settings.toml:
+//
+//   [authors]
+//   file = "authors.toml"
+//   template = "author.hbs"
+
+use crate::assets::AssetManifests;
+use crate::fs::{ext::ThemeBinding, index::ContentMgr};
+use crate::quota;
+use actix_web::{dev::HttpServiceFactory, web, HttpRequest, HttpResponse};
+use domain::setting::AuthorSettings;
+use serve::authors::{collect_posts, page_model, DEFAULT_AUTHOR_TEMPLATE};
+use serve::render::{render_default, template::TemplateRegistry, TemplateEngine};
+use tracing::error;
+
+#[derive(Clone)]
+struct PageState {
+    settings: AuthorSettings,
+    /// `[feeds] summary_chars`, for the summaries of the listed posts.
+    summary_chars: usize,
+    mgr: ContentMgr,
+    /// The theme mounted at `/`, else the first one.
+    theme: Option<ThemeBinding>,
+}
+
+/// The author page resource, for the root of the router.
+pub fn services(
+    settings: AuthorSettings,
+    summary_chars: usize,
+    mgr: ContentMgr,
+    theme: Option<ThemeBinding>,
+) -> impl HttpServiceFactory {
+    web::resource("/author/{slug}")
+        .app_data(web::Data::new(PageState {
+            settings,
+            summary_chars,
+            mgr,
+            theme,
+        }))
+        .route(web::get().to(author_handler))
+}
+
+#[tracing::instrument(skip_all, fields(path = %req.path()))]
+async fn author_handler(state: web::Data<PageState>, req: HttpRequest) -> HttpResponse {
+    let Some(author) = req
+        .match_info()
+        .get("slug")
+        .and_then(|slug| state.mgr.content().authors().get(slug))
+    else {
+        return HttpResponse::NotFound().finish();
+    };
+
+    let posts = match collect_posts(&state.mgr, author, state.summary_chars).await {
+        Ok(posts) => posts,
+        Err(e) => {
+            error!("Author page: reading the front-matter index failed: {}", e);
+            return HttpResponse::ServiceUnavailable().finish();
+        }
+    };
+
+    let model = page_model(author, &posts);
+    let template = &state.settings.template;
+    let themed = state
+        .theme
+        .as_ref()
+        .map(|t| {
+            TemplateRegistry::new(t.template_root.clone())
+                .with_language(t.engine)
+                .with_helpers(AssetManifests::of(&req).helpers(&t.assets_dir(), &t.mount_path))
+        })
+        .filter(|reg| reg.template_modified(template).is_some());
+
+    let _slot = match quota::render_slot(&req).await {
+        Ok(slot) => slot,
+        Err(resp) => return resp,
+    };
+
+    let mut body = Vec::new();
+    let rendered = match themed {
+        Some(reg) => reg.render_to_write(template, &model, &mut body),
+        None => render_default("author", DEFAULT_AUTHOR_TEMPLATE, &model, &mut body),
+    };
+    match rendered {
+        Ok(()) => HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .body(body),
+        Err(e) => {
+            error!("Author page: rendering {} failed: {}", author.slug, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
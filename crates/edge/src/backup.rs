@@ -0,0 +1,446 @@
+// crates/edge/src/backup.rs
+
+// `whispercms backup DIR` and `whispercms restore ARCHIVE DIR`: a site's
+// state in one `.tar.gz`, for disaster recovery and for moving a site.
+//
+// The archive holds `manifest.json` and, under `site/`, what the site cannot
+// get back from anywhere else:
+//
+// - settings.toml, extensions.lock, extensions.snapshot.json and
+//   schema_migrations.json;
+// - the stores: users (with the preview and CSRF keys), comments, forms,
+//   media records, redirects, collections, extension settings, plugin KV,
+//   configuration history, document revisions and the outbox;
+// - the audit and operations logs;
+// - the content and media directories and the certificate directory.
+//
+// The content index is left out: every start builds a new one from the
+// content directory, which is in. The stores replace files with `rename`, so
+// a backup of a running site reads each file whole, though not all of them
+// at one instant.
+//
+// With a passphrase (`--passphrase-env VAR`), the secrets (users_db and the
+// certificate directory) are stored encrypted with AES-256-GCM under a key
+// derived from it with PBKDF2-HMAC-SHA256; the manifest lists which entries
+// are, and restoring them needs the same passphrase. Restoring refuses a
+// directory that is not empty unless told to overwrite.
+
+use crate::cli::OUTBOX_DIR;
+use crate::db::audit::AUDIT_LOG_FILE;
+use crate::db::collections::COLLECTIONS_DB_DIR;
+use crate::db::comments::COMMENTS_DB_DIR;
+use crate::db::ext_settings::EXT_SETTINGS_DIR;
+use crate::db::forms::FORMS_DB_DIR;
+use crate::db::history::{write_atomic, HISTORY_DIR};
+use crate::db::kv::PLUGIN_KV_DIR;
+use crate::db::media::MEDIA_DB_DIR;
+use crate::db::migrate::MIGRATIONS_FILE;
+use crate::db::ops::{CORE_VERSION, OPS_LOG_FILE};
+use crate::db::redirects::REDIRECTS_DB_DIR;
+use crate::db::revisions::REVISIONS_DIR;
+use crate::db::users::USERS_DB_DIR;
+use crate::fs::lock::LOCK_FILE;
+use crate::fs::snapshot::SNAPSHOT_FILE;
+use chrono::{DateTime, Utc};
+use domain::hex;
+use domain::setting::Settings;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use openssl::error::ErrorStack;
+use openssl::hash::MessageDigest;
+use openssl::pkcs5::pbkdf2_hmac;
+use openssl::rand::rand_bytes;
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+use thiserror::Error;
+use tracing::info;
+use walkdir::WalkDir;
+
+/// The archive's table of contents, its first entry.
+pub const MANIFEST: &str = "manifest.json";
+
+/// Folder of the archive the site's files are under.
+const SITE: &str = "site";
+
+const PBKDF2_ROUNDS: usize = 210_000;
+const SALT_LEN: usize = 16;
+const IV_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+#[derive(Debug, Error)]
+pub enum BackupError {
+    #[error("I/O: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("JSON: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("encryption: {0}")]
+    Crypto(#[from] ErrorStack),
+
+    #[error("{0}")]
+    Invalid(String),
+}
+
+/// What a backup holds.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Core version that wrote it.
+    pub version: String,
+    pub created_at: DateTime<Utc>,
+    /// Files under `site/`.
+    pub files: usize,
+    /// Paths, relative to the site, stored encrypted.
+    #[serde(default)]
+    pub encrypted: BTreeSet<String>,
+    /// Hex PBKDF2 salt, with `encrypted`.
+    #[serde(default)]
+    pub salt: Option<String>,
+}
+
+/// The paths backed up, relative to the site directory, and whether each is
+/// a secret.
+fn state_paths(settings: Option<&Settings>) -> Vec<(PathBuf, bool)> {
+    let content = settings
+        .and_then(|s| s.content.as_ref())
+        .map_or_else(|| PathBuf::from("./content/"), |c| c.dir.clone());
+    let media = settings
+        .and_then(|s| s.images.clone())
+        .unwrap_or_default()
+        .media_dir;
+    let mut paths: Vec<(PathBuf, bool)> = [
+        "settings.toml",
+        LOCK_FILE,
+        SNAPSHOT_FILE,
+        MIGRATIONS_FILE,
+        COMMENTS_DB_DIR,
+        FORMS_DB_DIR,
+        MEDIA_DB_DIR,
+        REDIRECTS_DB_DIR,
+        COLLECTIONS_DB_DIR,
+        EXT_SETTINGS_DIR,
+        PLUGIN_KV_DIR,
+        HISTORY_DIR,
+        REVISIONS_DIR,
+        OUTBOX_DIR,
+        AUDIT_LOG_FILE,
+        OPS_LOG_FILE,
+    ]
+    .iter()
+    .map(|p| (PathBuf::from(p), false))
+    .chain([(content, false), (media, false)])
+    .collect();
+    paths.push((PathBuf::from(USERS_DB_DIR), true));
+    if let Some(s) = settings {
+        paths.push((s.cert.dir.clone(), true));
+    }
+    paths
+}
+
+/// `path` as `/`-separated normal components, `None` if it has others.
+fn archive_name(path: &Path) -> Option<String> {
+    let mut parts = Vec::new();
+    for c in path.components() {
+        match c {
+            Component::Normal(p) => parts.push(p.to_str()?.to_owned()),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    (!parts.is_empty()).then(|| parts.join("/"))
+}
+
+/// The files to back up under `site_dir`, by archive name, with whether
+/// each is a secret.
+fn collect(site_dir: &Path, settings: Option<&Settings>) -> Vec<(String, PathBuf, bool)> {
+    let mut files = Vec::new();
+    let mut seen = BTreeSet::new();
+    for (rel, secret) in state_paths(settings) {
+        let root = site_dir.join(&rel);
+        for entry in WalkDir::new(&root).into_iter().filter_map(Result::ok) {
+            if !entry.file_type().is_file() || entry.path().extension().is_some_and(|e| e == "tmp")
+            {
+                continue;
+            }
+            let Some(name) = entry
+                .path()
+                .strip_prefix(site_dir)
+                .ok()
+                .and_then(archive_name)
+            else {
+                continue;
+            };
+            if seen.insert(name.clone()) {
+                files.push((name, entry.path().to_path_buf(), secret));
+            }
+        }
+    }
+    files
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<[u8; 32], ErrorStack> {
+    let mut key = [0u8; 32];
+    pbkdf2_hmac(
+        passphrase.as_bytes(),
+        salt,
+        PBKDF2_ROUNDS,
+        MessageDigest::sha256(),
+        &mut key,
+    )?;
+    Ok(key)
+}
+
+/// `plain` as IV, tag and ciphertext; `name` is authenticated with it so an
+/// entry cannot be swapped for another.
+fn seal(key: &[u8], name: &str, plain: &[u8]) -> Result<Vec<u8>, ErrorStack> {
+    let mut iv = [0u8; IV_LEN];
+    rand_bytes(&mut iv)?;
+    let mut tag = [0u8; TAG_LEN];
+    let sealed = encrypt_aead(
+        Cipher::aes_256_gcm(),
+        key,
+        Some(&iv),
+        name.as_bytes(),
+        plain,
+        &mut tag,
+    )?;
+    Ok([&iv[..], &tag[..], &sealed[..]].concat())
+}
+
+fn open(key: &[u8], name: &str, sealed: &[u8]) -> Result<Vec<u8>, BackupError> {
+    let wrong = || BackupError::Invalid(format!("{name}: wrong passphrase or damaged backup"));
+    if sealed.len() < IV_LEN + TAG_LEN {
+        return Err(wrong());
+    }
+    let (iv, rest) = sealed.split_at(IV_LEN);
+    let (tag, data) = rest.split_at(TAG_LEN);
+    decrypt_aead(
+        Cipher::aes_256_gcm(),
+        key,
+        Some(iv),
+        name.as_bytes(),
+        data,
+        tag,
+    )
+    .map_err(|_| wrong())
+}
+
+fn append(
+    tar: &mut tar::Builder<GzEncoder<fs::File>>,
+    name: &str,
+    data: &[u8],
+    mode: u32,
+) -> io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(mode);
+    header.set_mtime(Utc::now().timestamp().max(0) as u64);
+    header.set_cksum();
+    tar.append_data(&mut header, name, data)
+}
+
+/// Write the state of the site in `site_dir` to the archive `out`, with its
+/// secrets encrypted under `passphrase` if given.
+pub fn backup(
+    site_dir: &Path,
+    settings: Option<&Settings>,
+    out: &Path,
+    passphrase: Option<&str>,
+) -> Result<Manifest, BackupError> {
+    let files = collect(site_dir, settings);
+    let salt = match passphrase {
+        Some(_) => {
+            let mut salt = [0u8; SALT_LEN];
+            rand_bytes(&mut salt)?;
+            Some(salt)
+        }
+        None => None,
+    };
+    let key = match (passphrase, &salt) {
+        (Some(p), Some(salt)) => Some(derive_key(p, salt)?),
+        _ => None,
+    };
+    let manifest = Manifest {
+        version: CORE_VERSION.to_owned(),
+        created_at: Utc::now(),
+        files: files.len(),
+        encrypted: files
+            .iter()
+            .filter(|(_, _, secret)| *secret && key.is_some())
+            .map(|(name, _, _)| name.clone())
+            .collect(),
+        salt: salt.map(hex::encode),
+    };
+
+    let mut tmp = out.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    let written = (|| -> Result<(), BackupError> {
+        let gz = GzEncoder::new(fs::File::create(&tmp)?, Compression::default());
+        let mut tar = tar::Builder::new(gz);
+        append(
+            &mut tar,
+            MANIFEST,
+            &serde_json::to_vec_pretty(&manifest)?,
+            0o644,
+        )?;
+        for (name, path, secret) in &files {
+            let data = fs::read(path)?;
+            let (data, mode) = match (&key, secret) {
+                (Some(key), true) => (seal(key, name, &data)?, 0o600),
+                (None, true) => (data, 0o600),
+                _ => (data, 0o644),
+            };
+            append(&mut tar, &format!("{SITE}/{name}"), &data, mode)?;
+        }
+        tar.into_inner()?.finish()?.sync_all()?;
+        Ok(())
+    })();
+    if let Err(e) = written {
+        let _ = fs::remove_file(&tmp);
+        return Err(e);
+    }
+    fs::rename(&tmp, out)?;
+    info!("Backed up {} files to {}", manifest.files, out.display());
+    Ok(manifest)
+}
+
+/// Recreate the site in `site_dir` from the archive `archive`. A directory
+/// that is not empty is only written to with `overwrite`.
+pub fn restore(
+    archive: &Path,
+    site_dir: &Path,
+    passphrase: Option<&str>,
+    overwrite: bool,
+) -> Result<Manifest, BackupError> {
+    let occupied = fs::read_dir(site_dir).is_ok_and(|mut d| d.next().is_some());
+    if occupied && !overwrite {
+        return Err(BackupError::Invalid(format!(
+            "{} is not empty; restore over it with --force",
+            site_dir.display()
+        )));
+    }
+
+    let mut tar = tar::Archive::new(GzDecoder::new(fs::File::open(archive)?));
+    let mut manifest: Option<Manifest> = None;
+    let mut key = None;
+    let mut restored = 0;
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+
+        if path == Path::new(MANIFEST) {
+            let m: Manifest = serde_json::from_slice(&data)?;
+            if !m.encrypted.is_empty() {
+                let passphrase = passphrase.ok_or_else(|| {
+                    BackupError::Invalid("the backup is encrypted; give its passphrase".into())
+                })?;
+                let salt = m.salt.as_deref().and_then(hex::decode).ok_or_else(|| {
+                    BackupError::Invalid("the backup manifest has no valid salt".into())
+                })?;
+                key = Some(derive_key(passphrase, &salt)?);
+            }
+            fs::create_dir_all(site_dir)?;
+            manifest = Some(m);
+            continue;
+        }
+        let Some(m) = &manifest else {
+            return Err(BackupError::Invalid(format!(
+                "{} is not a whispercms backup",
+                archive.display()
+            )));
+        };
+        let name = path
+            .strip_prefix(SITE)
+            .ok()
+            .and_then(archive_name)
+            .ok_or_else(|| BackupError::Invalid(format!("unexpected entry {}", path.display())))?;
+
+        let secret = m.encrypted.contains(&name);
+        if let (true, Some(key)) = (secret, &key) {
+            data = open(key, &name, &data)?;
+        }
+        let target = site_dir.join(&name);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        write_atomic(&target, &data)?;
+        #[cfg(unix)]
+        if secret || entry.header().mode().is_ok_and(|m| m & 0o077 == 0) {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&target, fs::Permissions::from_mode(0o600))?;
+        }
+        restored += 1;
+    }
+
+    let manifest = manifest.ok_or_else(|| {
+        BackupError::Invalid(format!("{} is not a whispercms backup", archive.display()))
+    })?;
+    if restored != manifest.files {
+        return Err(BackupError::Invalid(format!(
+            "the backup lists {} files but holds {}",
+            manifest.files, restored
+        )));
+    }
+    info!("Restored {} files to {}", restored, site_dir.display());
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secrets_are_encrypted_and_a_restore_brings_everything_back() {
+        let site = tempfile::tempdir().unwrap();
+        let write = |rel: &str, data: &str| {
+            let path = site.path().join(rel);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, data).unwrap();
+        };
+        write("users_db/preview.key", "TOP-SECRET-KEY");
+        write("comments_db/c1.json", "{}");
+        write("content/posts/a.md", "# A");
+        write("ops.jsonl", "{}\n");
+        write("content_index/2025/x.idx", "rebuilt on start");
+        write("comments_db/c2.json.tmp", "half written");
+
+        let out = tempfile::tempdir().unwrap();
+        let archive = out.path().join("site.tar.gz");
+        let manifest = backup(site.path(), None, &archive, Some("hunter2")).unwrap();
+        assert_eq!(manifest.files, 4);
+        assert_eq!(
+            manifest.encrypted,
+            BTreeSet::from(["users_db/preview.key".to_owned()])
+        );
+        let mut raw = Vec::new();
+        GzDecoder::new(fs::File::open(&archive).unwrap())
+            .read_to_end(&mut raw)
+            .unwrap();
+        assert!(!raw.windows(14).any(|w| w == b"TOP-SECRET-KEY"));
+
+        let target = out.path().join("restored");
+        assert!(matches!(
+            restore(&archive, &target, None, false),
+            Err(BackupError::Invalid(_))
+        ));
+        assert!(restore(&archive, &target, Some("wrong"), true).is_err());
+        restore(&archive, &target, Some("hunter2"), true).unwrap();
+        let read = |rel: &str| fs::read_to_string(target.join(rel)).unwrap();
+        assert_eq!(read("users_db/preview.key"), "TOP-SECRET-KEY");
+        assert_eq!(read("content/posts/a.md"), "# A");
+        assert!(!target.join("content_index").exists());
+
+        assert!(matches!(
+            restore(&archive, &target, Some("hunter2"), false),
+            Err(BackupError::Invalid(_))
+        ));
+    }
+}
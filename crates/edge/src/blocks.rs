@@ -0,0 +1,24 @@
+// crates/edge/src/blocks.rs
+
+// Block types (serve::blocks) for `.blocks` documents, loaded with the
+// site's content (fs::index::SiteContent), before the content scan renders
+// any of them. The built-in types are always there; sites add their own and
+// restyle built-in ones with an optional `[blocks]` table in settings.toml:
+//
+//   [blocks]
+//   templates = "./blocks"
+//   [blocks.types.callout]
+//   required = { text = "string" }
+//
+// The admin API validates block bodies against the same registry before it
+// writes them.
+
+use crate::proxy::EdgeError;
+use domain::setting::BlockSettings;
+use serve::blocks::BlockRegistry;
+use std::path::Path;
+
+/// Load the site's block types and templates from under `site_dir`.
+pub fn load(site_dir: &Path, settings: &BlockSettings) -> Result<BlockRegistry, EdgeError> {
+    BlockRegistry::from_settings(site_dir, settings).map_err(|e| EdgeError::Config(e.to_string()))
+}
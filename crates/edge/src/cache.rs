@@ -0,0 +1,447 @@
+// crates/edge/src/cache.rs
+
+// Each site's response cache (serve::cache), enabled by a `[cache]` table
+// in its settings.toml:
+//
+//   [cache]
+//   max_entries = 2048
+//   disk_dir = "./cache/"
+//   disk_max_bytes = 536870912
+//   vary = ["accept", "accept-language", "cookie"]
+//   query_params = ["page"]
+//
+// Requests whose query has a parameter outside `query_params` are rendered
+// every time, so made-up query strings cannot fill the cache.
+//
+// Content index updates, extension reloads and template edits invalidate it;
+// the hooks below are no-ops while caching is off.
+//
+// A theme render runs inside `collecting`; the front-matter lookups and
+// queries it makes report what they read through `consumed`, and the router
+// stores the result as the entry's tags. Index updates then call
+// `invalidate_docs` with the record before and after the change, so only
+// pages that read that document or one of its taxonomy terms are dropped.
+//
+// Concurrent misses for one page share a single render (`coalesce_wait_ms`,
+// default 5000, 0 to turn off). Admins can see how often that happened:
+//
+//   GET /admin/cache   {"entries": 812, "coalescing": {"leaders": 40,
+//                       "followers": 311, "shared": 309, "timeouts": 2}}
+//
+// Deploy scripts and plugins drop exactly what they changed instead of
+// restarting, with an admin login or `Authorization: Bearer <purge_token>`:
+//
+//   POST /admin/api/cache/purge   {"paths": ["/blog/**", "/media/logo.png"],
+//                                  "tags": ["term:tags:rust"], "all": false}
+//                                 → {"responses": 14, "images": 3}
+//
+// A path pattern matches request paths with `*` (within one segment), `**`
+// (across segments) and `?`; it drops the cached responses rendered for
+// those paths and the image derivatives of the `/media/...` originals it
+// names. Tags are the ones above (`doc:<id>`, `slug:<slug>`,
+// `term:<taxonomy>:<term>`, `content:*`). `all` empties both caches.
+
+use crate::audit::{self, AuditNote};
+use crate::auth::{self, Admin, RequireRole};
+use crate::db::users::Role;
+use crate::fs::index::SiteContent;
+use crate::{images, media};
+use actix_web::{
+    dev::HttpServiceFactory, http::header::WWW_AUTHENTICATE, web, HttpRequest, HttpResponse,
+    ResponseError,
+};
+use adapt::mql::{CmpOp, FieldExpr, Filter};
+use adapt::runtime::bootstrap::RuntimeHandles;
+use domain::setting::{CacheSettings, ImageSettings};
+use serde::Deserialize;
+use serde_json::{json, Value as Json};
+use serve::cache::{
+    config_hash, tags_for_doc, term_tag, ResponseCache, ANY_CONTENT, PATH_TAG_PREFIX,
+};
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use tracing::{error, info};
+
+tokio::task_local! {
+    static CONSUMED: RefCell<BTreeSet<String>>;
+}
+
+/// Create the site's cache; `disk_dir` is resolved against `site_dir`.
+pub fn open(site_dir: &Path, settings: &CacheSettings) -> std::io::Result<ResponseCache> {
+    let settings = CacheSettings {
+        disk_dir: settings.disk_dir.as_ref().map(|d| site_dir.join(d)),
+        ..settings.clone()
+    };
+    let cache = ResponseCache::new(&settings)?;
+    info!(
+        "Response cache enabled: {} entries, {} bytes in memory, disk tier {:?}",
+        settings.max_entries, settings.max_bytes, settings.disk_dir
+    );
+    Ok(cache)
+}
+
+/// The `/admin/cache` and purge resources, for the root of the router.
+pub fn services(
+    root_dir: &Path,
+    images: ImageSettings,
+    purge_token: Option<String>,
+) -> impl HttpServiceFactory {
+    (
+        web::resource("/admin/cache").route(web::get().to(stats_handler)),
+        web::resource("/admin/api/cache/purge")
+            .app_data(web::Data::new(PurgeState {
+                root_dir: root_dir.to_path_buf(),
+                images,
+                purge_token,
+            }))
+            .route(web::post().to(purge_handler)),
+    )
+}
+
+struct PurgeState {
+    root_dir: PathBuf,
+    /// Where the image derivatives to drop are.
+    images: ImageSettings,
+    /// `[cache] purge_token`, accepted instead of an admin login.
+    purge_token: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct PurgeRequest {
+    paths: Vec<String>,
+    tags: Vec<String>,
+    all: bool,
+}
+
+/// Whether `path` matches `pattern`: `**` matches anything, `*` anything
+/// but `/`, `?` one character but `/`.
+///
+/// Matched left to right, backing up only to the last `*` and the last
+/// `**`: a `*` cannot cross a `/`, so when it runs into one the only other
+/// way to match is a longer `**`. The work stays within the pattern length
+/// times the path length.
+fn path_matches(pattern: &str, path: &str) -> bool {
+    let (p, s) = (pattern.as_bytes(), path.as_bytes());
+    let (mut pi, mut si) = (0, 0);
+    // Pattern index after the last star, and the path index it took up to.
+    let mut star: Option<(usize, usize)> = None;
+    let mut deep: Option<(usize, usize)> = None;
+    loop {
+        if p[pi..].starts_with(b"**") {
+            pi += 2;
+            deep = Some((pi, si));
+            star = None;
+        } else if p.get(pi) == Some(&b'*') {
+            pi += 1;
+            star = Some((pi, si));
+        } else if si < s.len()
+            && pi < p.len()
+            && (p[pi] == s[si] || (p[pi] == b'?' && s[si] != b'/'))
+        {
+            pi += 1;
+            si += 1;
+        } else if pi == p.len() && si == s.len() {
+            return true;
+        } else if let Some((after, taken)) = star.filter(|&(_, t)| t < s.len() && s[t] != b'/') {
+            // Let the last `*` take one more character and retry.
+            star = Some((after, taken + 1));
+            (pi, si) = (after, taken + 1);
+        } else if let Some((after, taken)) = deep.filter(|&(_, t)| t < s.len()) {
+            deep = Some((after, taken + 1));
+            star = None;
+            (pi, si) = (after, taken + 1);
+        } else {
+            return false;
+        }
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn purge_handler(
+    state: web::Data<PurgeState>,
+    req: HttpRequest,
+    body: web::Json<PurgeRequest>,
+) -> HttpResponse {
+    let by_token = state
+        .purge_token
+        .as_ref()
+        .is_some_and(|token| media::authorized(&req, token));
+    if !by_token {
+        match auth::current_user(&req).await {
+            Ok(Some(user)) if user.role.allows(Role::Admin) => {}
+            Ok(Some(_)) => {
+                return HttpResponse::Forbidden()
+                    .json(json!({ "error": "purging requires the admin role" }))
+            }
+            Ok(None) => {
+                return HttpResponse::Unauthorized()
+                    .insert_header((WWW_AUTHENTICATE, "Bearer"))
+                    .json(json!({ "error": "log in or send the purge token" }))
+            }
+            Err(e) => return e.error_response(),
+        }
+    }
+
+    let PurgeRequest { paths, tags, all } = body.into_inner();
+    if !all && paths.is_empty() && tags.is_empty() {
+        return HttpResponse::BadRequest()
+            .json(json!({ "error": "give paths, tags or \"all\": true" }));
+    }
+
+    let content = SiteContent::of(&req);
+    let responses = content.cache().map_or(0, |cache| {
+        if all {
+            let held = cache.len();
+            cache.invalidate_all();
+            held
+        } else {
+            cache.purge(|tag| {
+                tags.iter().any(|t| t == tag)
+                    || tag
+                        .strip_prefix(PATH_TAG_PREFIX)
+                        .is_some_and(|path| paths.iter().any(|p| path_matches(p, path)))
+            })
+        }
+    });
+
+    let patterns = if all {
+        vec!["/media/**".to_owned()]
+    } else {
+        paths.clone()
+    };
+    let (root_dir, settings) = (state.root_dir.clone(), state.images.clone());
+    let images = match web::block(move || {
+        images::purge(&root_dir, &settings, |path| {
+            patterns.iter().any(|p| path_matches(p, path))
+        })
+    })
+    .await
+    {
+        Ok(Ok(n)) => n,
+        Ok(Err(e)) => {
+            error!("Purging image derivatives failed: {}", e);
+            return HttpResponse::InternalServerError()
+                .json(json!({ "error": "purging image derivatives failed" }));
+        }
+        Err(e) => {
+            error!("Purging image derivatives was cancelled: {}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let report = json!({ "responses": responses, "images": images });
+    audit::annotate(
+        &req,
+        AuditNote::new("cache.purge", "cache").with_change(
+            &Json::Null,
+            &json!({ "paths": paths, "tags": tags, "all": all, "purged": report }),
+        ),
+    );
+    info!(
+        "Purged {} cached responses and {} image derivatives",
+        responses, images
+    );
+    HttpResponse::Ok().json(report)
+}
+
+#[tracing::instrument(skip_all)]
+async fn stats_handler(_auth: RequireRole<Admin>, req: HttpRequest) -> HttpResponse {
+    let content = SiteContent::of(&req);
+    let Some(cache) = content.cache() else {
+        return HttpResponse::NotFound().json(json!({ "error": "caching is off" }));
+    };
+    HttpResponse::Ok().json(json!({
+        "entries": cache.len(),
+        "coalescing": cache.coalesce_stats(),
+    }))
+}
+
+/// Drop every cached response; called when the whole index is cleared and
+/// when theme templates change.
+pub fn invalidate(cache: Option<&ResponseCache>) {
+    if let Some(cache) = cache {
+        cache.invalidate_all();
+    }
+}
+
+/// Drop the cached pages that read any of `docs` (front-matter records, in
+/// the index's projection shape), or that read content too broadly to tell.
+pub fn invalidate_docs(cache: Option<&ResponseCache>, docs: &[Json]) {
+    if let Some(cache) = cache {
+        cache.invalidate_tags(docs.iter().flat_map(tags_for_doc));
+    }
+}
+
+/// Run `fut`, collecting the tags reported through [`consumed`] for
+/// [`consumed_tags`] to read before it returns.
+pub async fn collecting<F: Future>(fut: F) -> F::Output {
+    CONSUMED.scope(RefCell::new(BTreeSet::new()), fut).await
+}
+
+/// Note that the current render read content with these tags. Does nothing
+/// outside [`collecting`].
+pub fn consumed<I: IntoIterator<Item = String>>(tags: I) {
+    let _ = CONSUMED.try_with(|set| set.borrow_mut().extend(tags));
+}
+
+/// Note that the current render read content matching `filter`.
+pub fn consumed_query(filter: &Filter) {
+    consumed(filter_tags(filter).unwrap_or_else(|| vec![ANY_CONTENT.to_owned()]));
+}
+
+/// Tags collected so far in the enclosing [`collecting`] scope.
+pub fn consumed_tags() -> Vec<String> {
+    CONSUMED
+        .try_with(|set| set.borrow().iter().cloned().collect())
+        .unwrap_or_default()
+}
+
+/// Term tags covering every document `filter` can match, or `None` when it
+/// is not narrowed to taxonomy terms (so any change may affect it).
+fn filter_tags(filter: &Filter) -> Option<Vec<String>> {
+    match filter {
+        // Every match satisfies each child, so one narrowed child suffices.
+        Filter::And(children) => children.iter().find_map(filter_tags),
+        // A match may come from any child, so all must be narrowed.
+        Filter::Or(children) if !children.is_empty() => {
+            let mut tags = Vec::new();
+            for child in children {
+                tags.extend(filter_tags(child)?);
+            }
+            Some(tags)
+        }
+        Filter::Or(_) => None,
+        Filter::Field(FieldExpr { path, op }) => {
+            let taxonomy = path.strip_prefix("tax.")?;
+            let terms = match op {
+                CmpOp::Eq(v) => std::slice::from_ref(v),
+                CmpOp::In(vs) | CmpOp::All(vs) => vs.as_slice(),
+                CmpOp::ElemMatch(inner) => return element_terms(taxonomy, inner),
+                _ => return None,
+            };
+            terms
+                .iter()
+                .map(|t| t.as_str().map(|t| term_tag(taxonomy, t)))
+                .collect()
+        }
+    }
+}
+
+/// Term tags for `$elemMatch` on a taxonomy, when it pins the element to
+/// given terms.
+fn element_terms(taxonomy: &str, inner: &Filter) -> Option<Vec<String>> {
+    match inner {
+        Filter::And(children) => children.iter().find_map(|c| element_terms(taxonomy, c)),
+        Filter::Field(FieldExpr { path, op }) if path.is_empty() => {
+            let terms = match op {
+                CmpOp::Eq(v) => std::slice::from_ref(v),
+                CmpOp::In(vs) => vs.as_slice(),
+                _ => return None,
+            };
+            terms
+                .iter()
+                .map(|t| t.as_str().map(|t| term_tag(taxonomy, t)))
+                .collect()
+        }
+        _ => None,
+    }
+}
+
+/// Called after themes or plugins are (re)loaded; a changed configuration
+/// changes every key and drops the old entries.
+pub fn extensions_changed(cache: Option<&ResponseCache>, handles: &RuntimeHandles) {
+    let Some(cache) = cache else {
+        return;
+    };
+
+    let themes = handles
+        .theme_configs
+        .iter()
+        .flat_map(|t| [&t.id, &t.mount_path, &t.source]);
+    let plugins = handles.plugin_configs.iter().flat_map(|p| {
+        [
+            p.id.clone(),
+            p.source.clone(),
+            format!("{:?}", p.permissions),
+        ]
+    });
+
+    let parts: Vec<String> = themes.cloned().chain(plugins).collect();
+    cache.set_config_hash(config_hash(&parts));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use adapt::mql::parser::parse_filter;
+    use serde_json::json;
+
+    fn tags(filter: Json) -> Option<Vec<String>> {
+        filter_tags(&parse_filter(&filter).unwrap())
+    }
+
+    #[test]
+    fn filters_on_taxonomy_terms_yield_term_tags() {
+        assert_eq!(
+            tags(json!({ "tax.tags": "rust", "publish.status": "published" })),
+            Some(vec!["term:tags:rust".to_owned()])
+        );
+        assert_eq!(
+            tags(json!({ "$or": [
+                { "tax.tags": { "$in": ["a", "b"] } },
+                { "tax.series": "intro" },
+            ] })),
+            Some(vec![
+                "term:tags:a".to_owned(),
+                "term:tags:b".to_owned(),
+                "term:series:intro".to_owned(),
+            ])
+        );
+        assert_eq!(
+            tags(json!({ "tax.tags": { "$elemMatch": { "$in": ["a"], "$ne": "b" } } })),
+            Some(vec!["term:tags:a".to_owned()])
+        );
+        assert_eq!(tags(json!({})), None);
+        assert_eq!(tags(json!({ "tax.tags": { "$ne": "rust" } })), None);
+        assert_eq!(tags(json!({ "tax.tags": { "$regex": "ru" } })), None);
+        assert_eq!(
+            tags(json!({ "$or": [{ "tax.tags": "a" }, { "content.section": "blog" }] })),
+            None
+        );
+    }
+
+    #[test]
+    fn path_patterns_match_segments_and_subtrees() {
+        assert!(path_matches("/blog/**", "/blog/2024/a/"));
+        assert!(path_matches("/blog/*/", "/blog/a/"));
+        assert!(!path_matches("/blog/*/", "/blog/2024/a/"));
+        assert!(path_matches("/media/logo.png", "/media/logo.png"));
+        assert!(path_matches("/media/??.jpg", "/media/ab.jpg"));
+        assert!(!path_matches("/media/?.jpg", "/media/ab.jpg"));
+        assert!(path_matches("/**/feed.xml", "/tags/rust/feed.xml"));
+        assert!(!path_matches("/blog", "/blog/"));
+        // A `*` stuck at a `/` hands the retry to the `**` before it.
+        assert!(path_matches("/**/x*y", "/a/xb/xcy"));
+        assert!(!path_matches("/**/x*y", "/a/xb/xc/y"));
+        assert!(path_matches("/a/*b*c/**", "/a/xbcyc/d"));
+        // Many stars against a long miss finish quickly.
+        let stars = "/*".repeat(20) + "/**x";
+        assert!(!path_matches(&stars, &"/aaaa".repeat(40)));
+        assert!(!path_matches(&"**a".repeat(30), &"a".repeat(25)));
+    }
+
+    #[actix_web::test]
+    async fn lookups_inside_a_render_are_collected() {
+        consumed(["ignored".to_owned()]);
+        let tags = collecting(async {
+            consumed(["doc:/a.html".to_owned()]);
+            consumed_query(&parse_filter(&json!({ "content.title": "A" })).unwrap());
+            consumed_tags()
+        })
+        .await;
+        assert_eq!(tags, ["content:*", "doc:/a.html"]);
+    }
+}
@@ -0,0 +1,175 @@
+// crates/edge/src/canonical.rs
+
+// One address per site. When settings.toml names the site's URL
+//
+//   [sitemap]
+//   base_url = "https://example.com"
+//
+// the `canonical` middleware layer answers a request for any other host
+// (`www.example.com`, the server's IP) with a redirect to the same path on
+// that host, and a plain-http request too when the base URL is https. GET
+// and HEAD get a 301; other methods a 308, so a form post is not turned into
+// a GET on the way. Scheme and host are read the way `ConnectionInfo` reads
+// them, so `Forwarded` / `X-Forwarded-*` from a TLS-terminating proxy count.
+//
+// Requests to a loopback host are left alone: that is a developer running
+// the site locally, or a health check on the box itself, neither of which
+// wants to be sent to production. Without a base URL the layer does nothing
+// and themes see the scheme and host each request used instead.
+
+use crate::proxy::EdgeError;
+use actix_web::{
+    body::{BoxBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::{header::LOCATION, Method, StatusCode, Uri},
+    middleware::Next,
+    web, Error, HttpResponse,
+};
+
+/// The scheme and host requests are redirected to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Canonical {
+    scheme: String,
+    /// Lower case, with the port only when it is not the scheme's default.
+    host: String,
+}
+
+impl Canonical {
+    /// Parse a `base_url` such as `https://example.com`. Only the scheme and
+    /// host matter; a path is refused, since redirects keep the request's.
+    pub fn parse(base_url: &str) -> Result<Self, String> {
+        let uri: Uri = base_url
+            .trim_end_matches('/')
+            .parse()
+            .map_err(|e| format!("{base_url:?} is not a URL: {e}"))?;
+        let scheme = match uri.scheme_str() {
+            Some(s @ ("http" | "https")) => s.to_owned(),
+            _ => return Err(format!("{base_url:?} must start with http:// or https://")),
+        };
+        let Some(authority) = uri.authority() else {
+            return Err(format!("{base_url:?} names no host"));
+        };
+        if uri.path() != "/" && !uri.path().is_empty() {
+            return Err(format!(
+                "{base_url:?} has a path; the site must be served from the root of its host"
+            ));
+        }
+        Ok(Self {
+            host: without_default_port(&scheme, authority.as_str()),
+            scheme,
+        })
+    }
+
+    /// Where a request for `scheme://host` + `path_and_query` belongs, if
+    /// not where it is.
+    pub fn redirect_for(&self, scheme: &str, host: &str, path_and_query: &str) -> Option<String> {
+        let host = without_default_port(scheme, host);
+        if is_loopback(&host) {
+            return None;
+        }
+        let wrong_host = host != self.host;
+        let insecure = self.scheme == "https" && scheme != "https";
+        (wrong_host || insecure)
+            .then(|| format!("{}://{}{}", self.scheme, self.host, path_and_query))
+    }
+}
+
+/// Where requests are redirected for `base_url` (from `[sitemap]`); none
+/// without one.
+pub fn from_base_url(base_url: Option<&str>) -> Result<Option<Canonical>, EdgeError> {
+    base_url
+        .map(Canonical::parse)
+        .transpose()
+        .map_err(|e| EdgeError::Config(format!("[sitemap] base_url: {e}")))
+}
+
+/// `actix_web::middleware::from_fn` middleware; see the module comment. The
+/// site's `Canonical` is app data of the scope around the layers.
+pub async fn guard(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let Some(canonical) = req.app_data::<web::Data<Canonical>>().cloned() else {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    };
+    let target = {
+        let info = req.connection_info();
+        let path_and_query = req.uri().path_and_query().map_or("/", |pq| pq.as_str());
+        canonical.redirect_for(info.scheme(), info.host(), path_and_query)
+    };
+    let Some(target) = target else {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    };
+    let status = match *req.method() {
+        Method::GET | Method::HEAD => StatusCode::MOVED_PERMANENTLY,
+        _ => StatusCode::PERMANENT_REDIRECT,
+    };
+    let res = HttpResponse::build(status)
+        .insert_header((LOCATION, target))
+        .finish();
+    Ok(req.into_response(res))
+}
+
+fn without_default_port(scheme: &str, host: &str) -> String {
+    let host = host.to_ascii_lowercase();
+    let default = match scheme {
+        "https" => ":443",
+        _ => ":80",
+    };
+    match host.strip_suffix(default) {
+        Some(bare) => bare.to_owned(),
+        None => host,
+    }
+}
+
+fn is_loopback(host: &str) -> bool {
+    let name = match host.strip_prefix('[') {
+        Some(v6) => v6.split(']').next().unwrap_or_default(),
+        None => host.split(':').next().unwrap_or_default(),
+    };
+    name == "localhost"
+        || name
+            .parse::<std::net::IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn other_hosts_and_plain_http_go_to_the_base_url() {
+        let canonical = Canonical::parse("https://Example.com/").unwrap();
+        let to = |scheme, host, pq| canonical.redirect_for(scheme, host, pq);
+
+        assert_eq!(to("https", "example.com", "/docs/?q=1"), None);
+        assert_eq!(to("https", "example.com:443", "/"), None);
+        assert_eq!(
+            to("http", "example.com", "/docs/?q=1").as_deref(),
+            Some("https://example.com/docs/?q=1")
+        );
+        assert_eq!(
+            to("https", "www.example.com", "/a").as_deref(),
+            Some("https://example.com/a")
+        );
+        assert_eq!(
+            to("https", "example.com:8443", "/").as_deref(),
+            Some("https://example.com/")
+        );
+        for local in ["localhost:8080", "127.0.0.1", "[::1]:8443"] {
+            assert_eq!(to("http", local, "/"), None, "{local}");
+        }
+
+        // An http base URL does not send https visitors back to http.
+        let plain = Canonical::parse("http://example.com:8080").unwrap();
+        assert_eq!(plain.redirect_for("https", "example.com:8080", "/"), None);
+        assert_eq!(
+            plain.redirect_for("http", "example.com", "/").as_deref(),
+            Some("http://example.com:8080/")
+        );
+
+        assert!(Canonical::parse("example.com").is_err());
+        assert!(Canonical::parse("ftp://example.com").is_err());
+        assert!(Canonical::parse("https://example.com/blog").is_err());
+    }
+}
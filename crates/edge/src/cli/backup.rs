@@ -0,0 +1,110 @@
+// crates/edge/src/cli/backup.rs
+
+use super::{dir_must_exist, site_settings, Result};
+use crate::backup;
+use crate::db::ops::{OpsLog, OPS_LOG_FILE};
+use crate::proxy::EdgeError;
+use chrono::Utc;
+use clap::{builder::ValueHint, Parser};
+use std::path::PathBuf;
+
+#[derive(Parser, Debug)]
+pub struct BackupCmd {
+    /// Site directory (or set WHISPERCMS_DIR)
+    #[arg(
+        value_name = "DIR",
+        env = "WHISPERCMS_DIR",
+        required = true,
+        value_hint = ValueHint::DirPath,
+        value_parser = dir_must_exist
+    )]
+    pub dir: PathBuf,
+
+    /// Archive to write; defaults to `whispercms-backup-<time>.tar.gz`
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub out: Option<PathBuf>,
+
+    /// Environment variable holding a passphrase to encrypt the secrets with
+    #[arg(long, value_name = "VAR")]
+    pub passphrase_env: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct RestoreCmd {
+    /// Archive written by `backup`
+    #[arg(value_name = "ARCHIVE", value_hint = ValueHint::FilePath)]
+    pub archive: PathBuf,
+
+    /// Site directory to recreate; created if missing
+    #[arg(value_name = "DIR", value_hint = ValueHint::DirPath)]
+    pub dir: PathBuf,
+
+    /// Environment variable holding the passphrase the backup was made with
+    #[arg(long, value_name = "VAR")]
+    pub passphrase_env: Option<String>,
+
+    /// Restore into a directory that is not empty, replacing its files
+    #[arg(long)]
+    pub force: bool,
+}
+
+/// The value of the environment variable `var`, which must be set.
+fn passphrase(var: Option<&str>) -> Result<Option<String>> {
+    var.map(|var| {
+        std::env::var(var)
+            .ok()
+            .filter(|p| !p.is_empty())
+            .ok_or_else(|| EdgeError::Config(format!("{var} is not set")))
+    })
+    .transpose()
+}
+
+pub(super) fn do_backup(cmd: BackupCmd) -> Result<()> {
+    let ops = OpsLog::new(cmd.dir.join(OPS_LOG_FILE));
+    let began = Utc::now();
+    let passphrase = passphrase(cmd.passphrase_env.as_deref())?;
+    let settings = site_settings(&cmd.dir)?;
+    let out = cmd.out.unwrap_or_else(|| {
+        PathBuf::from(format!(
+            "whispercms-backup-{}.tar.gz",
+            began.format("%Y%m%dT%H%M%SZ")
+        ))
+    });
+    let manifest = ops.finish(
+        "backup",
+        None,
+        began,
+        backup::backup(&cmd.dir, settings.as_ref(), &out, passphrase.as_deref()),
+    )?;
+    println!(
+        "Backed up {} files to {}{}",
+        manifest.files,
+        out.display(),
+        if manifest.encrypted.is_empty() {
+            ""
+        } else {
+            " (secrets encrypted)"
+        }
+    );
+    Ok(())
+}
+
+pub(super) fn do_restore(cmd: RestoreCmd) -> Result<()> {
+    let began = Utc::now();
+    let passphrase = passphrase(cmd.passphrase_env.as_deref())?;
+    let manifest = backup::restore(&cmd.archive, &cmd.dir, passphrase.as_deref(), cmd.force)?;
+    OpsLog::new(cmd.dir.join(OPS_LOG_FILE)).finish(
+        "restore",
+        None,
+        began,
+        Ok::<_, EdgeError>(()),
+    )?;
+    println!(
+        "Restored {} files from a {} backup of {} to {}",
+        manifest.files,
+        manifest.version,
+        manifest.created_at.to_rfc3339(),
+        cmd.dir.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,59 @@
+// crates/edge/src/cli/doctor.rs
+
+use super::{dir_must_exist, Result};
+use crate::doctor;
+use crate::proxy::EdgeError;
+use clap::{builder::ValueHint, Parser};
+use std::path::PathBuf;
+
+#[derive(Parser, Debug)]
+pub struct DoctorCmd {
+    /// Site directory (or set WHISPERCMS_DIR)
+    #[arg(
+        value_name = "DIR",
+        env = "WHISPERCMS_DIR",
+        required = true,
+        value_hint = ValueHint::DirPath,
+        value_parser = dir_must_exist
+    )]
+    pub dir: PathBuf,
+
+    /// Print the report as JSON
+    #[arg(long)]
+    pub json: bool,
+}
+
+pub(super) fn do_doctor(cmd: DoctorCmd) -> Result<()> {
+    let report = doctor::diagnose(&cmd.dir);
+    if cmd.json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&report).map_err(|e| EdgeError::Other(e.to_string()))?
+        );
+    } else {
+        for check in &report.checks {
+            let status = match check.status {
+                doctor::Status::Ok => "ok",
+                doctor::Status::Warn => "warn",
+                doctor::Status::Fail => "FAIL",
+            };
+            println!("{status:<5} {:<12} {}", check.name, check.detail);
+            if let Some(fix) = &check.fix {
+                println!("{:<18} fix: {fix}", "");
+            }
+        }
+    }
+
+    let failed = report
+        .checks
+        .iter()
+        .filter(|c| c.status == doctor::Status::Fail)
+        .count();
+    if failed > 0 {
+        return Err(EdgeError::Other(format!(
+            "{failed} check(s) failed for {}",
+            cmd.dir.display()
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,45 @@
+// crates/edge/src/cli/export.rs
+
+use super::{dir_must_exist, CommandIssued, Result, StartCmd, StartProcess};
+use clap::{builder::ValueHint, Parser};
+use std::path::PathBuf;
+
+#[derive(Parser, Debug)]
+pub struct ExportCmd {
+    /// Site directory (or set WHISPERCMS_DIR)
+    #[arg(
+        value_name = "DIR",
+        env = "WHISPERCMS_DIR",
+        required = true,
+        value_hint = ValueHint::DirPath,
+        value_parser = dir_must_exist
+    )]
+    pub dir: PathBuf,
+
+    /// Directory to write the site to; created if missing
+    #[arg(long, value_name = "DIR", default_value = "./public", value_hint = ValueHint::DirPath)]
+    pub out: PathBuf,
+
+    /// URL the exported site will be served at; defaults to `[sitemap] base_url`
+    #[arg(long, value_name = "URL")]
+    pub base_url: Option<String>,
+}
+
+/// Start the site without listening and write it out (see [`crate::export`]).
+#[tracing::instrument(skip_all)]
+pub(super) async fn do_export(cmd: ExportCmd) -> Result<()> {
+    let start = StartCmd {
+        dir: cmd.dir,
+        sites: Vec::new(),
+        dev: false,
+    };
+    let process = StartProcess::<CommandIssued>::parse_settings_file(start)?
+        .inject_dependencies()
+        .await?
+        .scan_content_directory()
+        .await?
+        .scan_extensions_directory()?
+        .register_routes_and_middleware()
+        .await?;
+    process.export(&cmd.out, cmd.base_url).await
+}
@@ -0,0 +1,74 @@
+// crates/edge/src/cli/import.rs
+
+use super::{dir_must_exist, site_content_dir, Result};
+use crate::db::ops::{OpsLog, OPS_LOG_FILE};
+use crate::import::{apply_plan, wordpress};
+use crate::redirects;
+use chrono::Utc;
+use clap::{builder::ValueHint, Parser, Subcommand};
+use std::path::PathBuf;
+
+#[derive(Subcommand, Debug)]
+pub enum ImportCmd {
+    /// Import posts and pages from a WordPress WXR export
+    Wordpress(WordpressImportCmd),
+}
+
+#[derive(Parser, Debug)]
+pub struct WordpressImportCmd {
+    /// Site directory (or set WHISPERCMS_DIR); files go to its content dir
+    #[arg(
+        value_name = "DIR",
+        env = "WHISPERCMS_DIR",
+        required = true,
+        value_hint = ValueHint::DirPath,
+        value_parser = dir_must_exist
+    )]
+    pub dir: PathBuf,
+
+    /// WXR file from Tools → Export in WordPress
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub file: PathBuf,
+
+    /// Also append the imported records to this front-matter index
+    #[arg(long, value_name = "INDEX_DIR", value_hint = ValueHint::DirPath)]
+    pub index: Option<PathBuf>,
+
+    /// Replace files that already exist
+    #[arg(long)]
+    pub overwrite: bool,
+}
+
+#[tracing::instrument(skip_all)]
+pub(super) async fn do_import(cmd: ImportCmd) -> Result<()> {
+    let ImportCmd::Wordpress(cmd) = cmd;
+    let ops = OpsLog::new(cmd.dir.join(OPS_LOG_FILE));
+    let began = Utc::now();
+
+    let xml = std::fs::read_to_string(&cmd.file)?;
+    let plan = wordpress::convert(&wordpress::parse_wxr(&xml)?);
+
+    let content_dir = site_content_dir(&cmd.dir)?;
+    let applied = apply_plan(&plan, &content_dir, cmd.index.as_deref(), cmd.overwrite).await;
+    let summary = ops.finish("import.wordpress", None, began, applied)?;
+    // Old permalinks keep working.
+    let moves = plan
+        .docs
+        .iter()
+        .filter_map(|doc| Some((doc.legacy_path()?, doc.record.id.clone())));
+    let redirected = redirects::import(&redirects::store(&cmd.dir), moves)?;
+
+    println!(
+        "imported {} into {}  (existing: {}, skipped: {}, indexed: {}, redirects: {})",
+        summary.written.len(),
+        content_dir.display(),
+        summary.existing.len(),
+        plan.skipped.len(),
+        summary.indexed,
+        redirected,
+    );
+    for (what, why) in &plan.skipped {
+        println!("  skipped {what}: {why}");
+    }
+    Ok(())
+}
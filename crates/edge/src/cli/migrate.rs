@@ -0,0 +1,81 @@
+// crates/edge/src/cli/migrate.rs
+
+use super::{dir_must_exist, site_settings, Result};
+use crate::db::migrate::{Migration, Migrator};
+use crate::db::ops::{OpsLog, OPS_LOG_FILE};
+use chrono::Utc;
+use clap::{builder::ValueHint, Parser};
+use std::path::PathBuf;
+
+#[derive(Parser, Debug)]
+pub struct MigrateCmd {
+    /// Site directory (or set WHISPERCMS_DIR)
+    #[arg(
+        value_name = "DIR",
+        env = "WHISPERCMS_DIR",
+        required = true,
+        value_hint = ValueHint::DirPath,
+        value_parser = dir_must_exist
+    )]
+    pub dir: PathBuf,
+
+    /// List every migration and when it was applied (the default)
+    #[arg(long, conflicts_with_all = ["up", "down"])]
+    pub status: bool,
+
+    /// Apply pending migrations
+    #[arg(long, conflicts_with = "down")]
+    pub up: bool,
+
+    /// With --up, stop after this version
+    #[arg(long, value_name = "VERSION", requires = "up")]
+    pub to: Option<u32>,
+
+    /// Revert applied migrations newer than VERSION (0 reverts them all)
+    #[arg(long, value_name = "VERSION")]
+    pub down: Option<u32>,
+
+    /// Show what --up or --down would do without doing it
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+pub(super) fn do_migrate(cmd: MigrateCmd) -> Result<()> {
+    let settings = site_settings(&cmd.dir)?;
+    let migrator = Migrator::new(&cmd.dir, settings.as_ref());
+    let ops = OpsLog::new(cmd.dir.join(OPS_LOG_FILE));
+    let began = Utc::now();
+    let print = |verb: &str, done: &[Migration]| {
+        for m in done {
+            println!("{verb} {:>4} {}", m.version, m.name);
+        }
+        println!("{} migration(s) {}", done.len(), verb.to_lowercase());
+    };
+
+    match (cmd.up, cmd.down) {
+        (true, _) if cmd.dry_run => print("Would apply", &migrator.up(cmd.to, true)?),
+        (true, _) => print(
+            "Applied",
+            &ops.finish("migrate", Some("up"), began, migrator.up(cmd.to, false))?,
+        ),
+        (_, Some(to)) if cmd.dry_run => print("Would revert", &migrator.down(to, true)?),
+        (_, Some(to)) => print(
+            "Reverted",
+            &ops.finish("migrate", Some("down"), began, migrator.down(to, false))?,
+        ),
+        _ if cmd.dry_run => print("Would apply", &migrator.up(None, true)?),
+        _ => {
+            for s in migrator.status()? {
+                println!(
+                    "{:>4} {:<32} {:<10} {}",
+                    s.version,
+                    s.name,
+                    if s.reversible { "reversible" } else { "" },
+                    s.applied_at
+                        .map_or_else(|| "pending".to_owned(), |at| at.to_rfc3339())
+                );
+            }
+        }
+    }
+    Ok(())
+}
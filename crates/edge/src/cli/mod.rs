@@ -0,0 +1,1707 @@
+// crates/edge/src/cli/mod.rs
+
+pub mod backup;
+pub mod doctor;
+pub mod export;
+pub mod import;
+pub mod migrate;
+pub mod outbox;
+pub mod packages;
+pub mod user;
+
+use crate::db::ext_settings::{self, EXT_SETTINGS_DIR};
+use crate::db::history::{ConfigHistory, ConfigVersion, ExtKind, HISTORY_DIR};
+use crate::db::migrate::Migrator;
+use crate::db::ops::{summarize, OpEntry, OpsLog, Outcome, OPS_LOG_FILE};
+use crate::fs::index::{ContentMgr, SiteContent};
+use crate::fs::lock::{self, Lockfile, LOCK_FILE};
+use crate::import::{apply_plan, wordpress};
+use crate::{
+    archetypes, cache,
+    components::ComponentRegistry,
+    fs::{
+        ext::{self, DiscoveredPlugin, DiscoveredTheme, ThemeBinding},
+        filter::{self, DEFAULT_CONTENT_EXTS},
+        incremental, reload,
+        snapshot::{self, SNAPSHOT_FILE},
+    },
+    init::{self, Answers},
+    proxy::{self, EdgeError, EdgeRuntime, SiteEntry, SiteRegistry, WebServerHandle},
+    scaffold, schedule,
+    site::Site,
+    telemetry, tui,
+};
+use adapt::runtime::bootstrap::{bootstrap_all, RuntimeHandles};
+use backup::{do_backup, do_restore, BackupCmd, RestoreCmd};
+use chrono::Utc;
+use clap::{builder::ValueHint, Parser, Subcommand};
+use doctor::{do_doctor, DoctorCmd};
+use domain::{
+    doc::Document,
+    setting::{ContentSettings, ExtensionSettings, Settings, TelemetrySettings, TlsMode},
+};
+use export::{do_export, ExportCmd};
+use futures::future::join_all;
+use import::{do_import, ImportCmd};
+use migrate::{do_migrate, MigrateCmd};
+use outbox::{do_outbox, OutboxCmd};
+use packages::{do_package, PackageCmd};
+use serve::front_matter;
+use serve::indexer::scan_and_process_docs;
+use serve::{indexer::FolderScanConfig, render::http::RequestContext};
+use std::{path::PathBuf, process::ExitCode, sync::Arc};
+use tokio::task::LocalSet;
+use tracing::{debug, error, info, warn};
+use user::{do_user, UserCmd};
+
+pub use outbox::OUTBOX_DIR;
+
+pub type Result<T> = std::result::Result<T, EdgeError>;
+
+/// Operation name for startup steps in the operations history.
+const START: &str = "start";
+
+/// WhisperCMS CLI — Edge Layer
+#[tokio::main(flavor = "multi_thread")]
+#[tracing::instrument(skip_all)]
+pub async fn start() -> ExitCode {
+    let local = LocalSet::new();
+
+    local
+        .run_until(async {
+            // Everything in here can safely call spawn_local,
+            // including bootstrap_all → PluginRuntimeClient::spawn.
+            let cli = Cli::parse();
+            let _telemetry = telemetry::init(
+                site_telemetry(&cli.command).as_ref(),
+                matches!(cli.command, Commands::ServeDev(_)),
+            );
+            info!("logging setup complete");
+
+            let result = match cli.command {
+                Commands::Start(start) => do_start(start).await,
+                Commands::ServeDev(cmd) => do_serve_dev(cmd).await,
+                Commands::Outbox(cmd) => do_outbox(cmd),
+                Commands::Import(cmd) => do_import(cmd).await,
+                Commands::Content(cmd) => do_content(cmd),
+                Commands::Ext(cmd) => do_ext(cmd),
+                Commands::Tui(cmd) => do_tui(cmd),
+                Commands::User(cmd) => do_user(cmd),
+                Commands::Components(cmd) => do_components(cmd),
+                Commands::History(cmd) => do_history(cmd),
+                Commands::Verify(cmd) => do_verify(cmd),
+                Commands::Export(cmd) => do_export(cmd).await,
+                Commands::Backup(cmd) => do_backup(cmd),
+                Commands::Restore(cmd) => do_restore(cmd),
+                Commands::Migrate(cmd) => do_migrate(cmd),
+                Commands::Init(cmd) => do_init(cmd),
+                Commands::Doctor(cmd) => do_doctor(cmd),
+                Commands::New(cmd) => do_new(cmd).await,
+                Commands::Plugin(cmd) => do_package(ExtKind::Plugin, cmd),
+                Commands::Theme(cmd) => do_package(ExtKind::Theme, cmd),
+            };
+
+            result.map_or_else(
+                |e| {
+                    error!("Failed to start WhisperCMS Edge: {}", e);
+                    ExitCode::FAILURE
+                },
+                |_| {
+                    info!("WhisperCMS Edge started successfully");
+                    ExitCode::SUCCESS
+                },
+            )
+        })
+        .await
+}
+
+#[tracing::instrument(skip_all)]
+async fn do_start(start: StartCmd) -> Result<()> {
+    // every site runs its own steps; `--site` directories follow the first,
+    // whose `[edge]` and `[cert]` the edge listens with
+    let StartCmd { dir, sites, dev } = start;
+    let mut prepared = Vec::new();
+    for dir in std::iter::once(dir).chain(sites) {
+        let cmd = StartCmd {
+            dir,
+            sites: Vec::new(),
+            dev,
+        };
+        prepared.push(prepare_site(cmd).await?);
+    }
+
+    // no two sites may answer the same host or take the same loopback port
+    let settings: Vec<&Settings> = prepared.iter().map(|p| &p.state.settings).collect();
+    proxy::host_table(&settings)?;
+
+    let mut served = Vec::new();
+    for process in prepared {
+        served.push(serve_site(process).await?);
+    }
+
+    // start the edge controller (Pingora) in front of every site's web server
+    let entries = served.iter().map(StartProcess::site_entry).collect();
+    let sites = Arc::new(SiteRegistry::new(entries)?);
+    let ops = OpsLog::new(served[0].state.command.dir.join(OPS_LOG_FILE));
+    let then = Utc::now();
+    let edge = ops.finish(
+        START,
+        Some("start_edge"),
+        then,
+        EdgeRuntime::start(sites).await,
+    )?;
+    info!(
+        "Edge started in {} milliseconds for {} site(s)",
+        Utc::now().timestamp_millis() - then.timestamp_millis(),
+        served.len()
+    );
+
+    // run until SIGINT/SIGTERM, stop taking connections, then let each site
+    // drain and leave a snapshot for the next warm start
+    served[0].is_running().await?;
+    edge.shutdown();
+    let stopped = join_all(served.into_iter().map(|process| async move {
+        let ops = OpsLog::new(process.state.command.dir.join(OPS_LOG_FILE));
+        let then = Utc::now();
+        ops.finish(START, Some("shutdown"), then, process.shut_down().await)?;
+        info!(
+            "Shut down in {} milliseconds",
+            Utc::now().timestamp_millis() - then.timestamp_millis()
+        );
+        Ok::<_, EdgeError>(())
+    }))
+    .await;
+    stopped.into_iter().collect()
+}
+
+/// Run the start steps of the site in `start.dir` up to its routes.
+#[tracing::instrument(skip_all, fields(dir = %start.dir.display()))]
+async fn prepare_site(start: StartCmd) -> Result<StartProcess<RouterCreated>> {
+    // each step's duration and outcome also go to the operations history
+    let ops = OpsLog::new(start.dir.join(OPS_LOG_FILE));
+
+    // parse settings file -> does the settings file exist?  If yes, parse it
+    let then = Utc::now();
+    let process = ops.finish(
+        START,
+        Some("parse_settings"),
+        then,
+        StartProcess::<CommandIssued>::parse_settings_file(start),
+    )?;
+    info!(
+        "Settings parsed in {} milliseconds",
+        Utc::now().timestamp_millis() - then.timestamp_millis()
+    );
+
+    // migrate -> bring the site's stores up to this core before any is opened
+    let then = Utc::now();
+    let process = ops.finish(START, Some("migrate"), then, process.migrate())?;
+    info!(
+        "Migrations applied in {} milliseconds",
+        Utc::now().timestamp_millis() - then.timestamp_millis()
+    );
+
+    // inject dependencies -> adapt, serve, and domain have dependencies so inject
+    let then = Utc::now();
+    let process = ops.finish(
+        START,
+        Some("inject_dependencies"),
+        then,
+        process.inject_dependencies().await,
+    )?;
+    info!(
+        "Dependencies injected in {} milliseconds",
+        Utc::now().timestamp_millis() - then.timestamp_millis()
+    );
+
+    // scan for content -> does the content directory exist?  If yes, scan it
+    let then = Utc::now();
+    let process = ops.finish(
+        START,
+        Some("scan_content"),
+        then,
+        process.scan_content_directory().await,
+    )?;
+    info!(
+        "Content scanned in {} milliseconds",
+        Utc::now().timestamp_millis() - then.timestamp_millis()
+    );
+
+    // scan for extensions -> restore them from the shutdown snapshot if nothing
+    // changed since, else does the extensions directory exist?  If yes, scan it
+    let then = Utc::now();
+    let process = ops.finish(
+        START,
+        Some("scan_extensions"),
+        then,
+        process.scan_extensions_directory(),
+    )?;
+    info!(
+        "Extensions scanned in {} milliseconds",
+        Utc::now().timestamp_millis() - then.timestamp_millis()
+    );
+
+    // register routes and middleware in Actix
+    let then = Utc::now();
+    let process = ops.finish(
+        START,
+        Some("register_routes"),
+        then,
+        process.register_routes_and_middleware().await,
+    )?;
+    info!(
+        "Routes registered in {} milliseconds",
+        Utc::now().timestamp_millis() - then.timestamp_millis()
+    );
+
+    Ok(process)
+}
+
+/// Start the web server of a prepared site and its watchers.
+#[tracing::instrument(skip_all, fields(dir = %process.state.command.dir.display()))]
+async fn serve_site(process: StartProcess<RouterCreated>) -> Result<StartProcess<ServerStarted>> {
+    let ops = OpsLog::new(process.state.command.dir.join(OPS_LOG_FILE));
+
+    // start the site's Actix web server on loopback
+    let then = Utc::now();
+    let process = ops.finish(
+        START,
+        Some("start_servers"),
+        then,
+        process.start_servers().await,
+    )?;
+    info!(
+        "Servers started in {} milliseconds",
+        Utc::now().timestamp_millis() - then.timestamp_millis()
+    );
+
+    // watch extensions -> hot reload plugins/themes when their sources change
+    let then = Utc::now();
+    ops.finish(
+        START,
+        Some("watch_extensions"),
+        then,
+        process.watch_extensions_directory(),
+    )?;
+    info!(
+        "Extension watcher started in {} milliseconds",
+        Utc::now().timestamp_millis() - then.timestamp_millis()
+    );
+
+    // watch content -> re-index changed documents; SIGHUP rebuilds from scratch;
+    // publish dates and expiries refresh cached pages as they pass
+    let then = Utc::now();
+    ops.finish(
+        START,
+        Some("watch_content"),
+        then,
+        process.watch_content_directory(),
+    )?;
+    info!(
+        "Content watcher started in {} milliseconds",
+        Utc::now().timestamp_millis() - then.timestamp_millis()
+    );
+
+    Ok(process)
+}
+
+#[derive(Parser, Debug)]
+#[command(name = "whispercms", version, about = "WhisperCMS command-line tool")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Commands,
+}
+
+/// Unified request passed into Tower pipeline (placeholder for future use)
+pub struct CliReq {
+    pub cmd: Commands,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Commands {
+    /// Start WhisperCMS using the specified directory
+    Start(StartCmd),
+    /// Start WhisperCMS for theme and content work: live reload, no
+    /// response cache, error details on pages and query plans in the log
+    ServeDev(ServeDevCmd),
+    /// Inspect pending and dead-lettered outbound deliveries
+    Outbox(OutboxCmd),
+    /// Import content from another CMS
+    #[command(subcommand)]
+    Import(ImportCmd),
+    /// Check content files
+    #[command(subcommand)]
+    Content(ContentCmd),
+    /// Show or roll back plugin and theme configuration versions
+    #[command(subcommand)]
+    Ext(ExtCmd),
+    /// Operate a site from the terminal: status, plugins, maintenance, logs
+    Tui(TuiCmd),
+    /// Manage user accounts and their roles
+    #[command(subcommand)]
+    User(UserCmd),
+    /// Show installed core, plugin and theme versions and licenses
+    #[command(subcommand)]
+    Components(ComponentsCmd),
+    /// Show how long startup steps, rebuilds, reloads and imports took
+    History(HistoryCmd),
+    /// Check installed plugin and theme files against extensions.lock
+    Verify(VerifyCmd),
+    /// Render every published page, feed and asset to a static directory
+    Export(ExportCmd),
+    /// Write the site's settings, stores, logs and content to one archive
+    Backup(BackupCmd),
+    /// Recreate a site directory from a backup archive
+    Restore(RestoreCmd),
+    /// Show, preview, apply or revert migrations of the site's stores
+    Migrate(MigrateCmd),
+    /// Set up a new site without prompts, from flags or an answers file
+    Init(InitCmd),
+    /// Check a stopped site's settings, secrets, stores, extensions and
+    /// ports, and say how to fix what is wrong
+    Doctor(DoctorCmd),
+    /// Create a document from its type's archetype, or a plugin or theme to
+    /// start from
+    #[command(subcommand)]
+    New(NewCmd),
+    /// Install, list, update or remove plugins from packages
+    #[command(subcommand)]
+    Plugin(PackageCmd),
+    /// Install, list, update or remove themes from packages
+    #[command(subcommand)]
+    Theme(PackageCmd),
+}
+
+#[derive(Parser, Debug)]
+pub struct StartCmd {
+    /// Target directory (or set WHISPERCMS_DIR)
+    ///
+    /// Must exist, be a directory, and be readable & writable.
+    #[arg(
+        value_name = "DIR",
+        env = "WHISPERCMS_DIR",
+        required = true,
+        value_hint = ValueHint::DirPath,
+        value_parser = dir_must_exist
+    )]
+    pub dir: PathBuf,
+
+    /// Another site directory to serve from this process, picked by the
+    /// `[edge] hosts` it lists; may be repeated
+    #[arg(
+        long = "site",
+        value_name = "DIR",
+        value_hint = ValueHint::DirPath,
+        value_parser = dir_must_exist
+    )]
+    pub sites: Vec<PathBuf>,
+
+    /// Set by `serve-dev`.
+    #[arg(skip)]
+    pub dev: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct ServeDevCmd {
+    #[command(flatten)]
+    pub start: StartCmd,
+}
+
+/// `start`, with what `serve-dev` turns on.
+async fn do_serve_dev(cmd: ServeDevCmd) -> Result<()> {
+    do_start(StartCmd {
+        dev: true,
+        ..cmd.start
+    })
+    .await
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ContentCmd {
+    /// Validate the front matter of every content file
+    Lint(LintCmd),
+}
+
+#[derive(Parser, Debug)]
+pub struct LintCmd {
+    /// Site directory (or set WHISPERCMS_DIR)
+    #[arg(
+        value_name = "DIR",
+        env = "WHISPERCMS_DIR",
+        required = true,
+        value_hint = ValueHint::DirPath,
+        value_parser = dir_must_exist
+    )]
+    pub dir: PathBuf,
+}
+
+#[tracing::instrument(skip_all)]
+fn do_content(cmd: ContentCmd) -> Result<()> {
+    let ContentCmd::Lint(cmd) = cmd;
+
+    let content = site_content(&cmd.dir)?;
+    let (root, cfg) = content_scan(&cmd.dir, &content)?;
+
+    let mut files = 0;
+    let mut failed = 0;
+    let mut problems = 0;
+    for entry in walkdir::WalkDir::new(&root)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+    {
+        let name = entry.file_name().to_string_lossy();
+        if !cfg.file_re.as_ref().is_none_or(|re| re.is_match(&name)) {
+            continue;
+        }
+
+        let rel = entry.path().strip_prefix(&root).unwrap_or(entry.path());
+        let text = std::fs::read_to_string(entry.path())?;
+        let issues = front_matter::lint(rel, &text);
+
+        files += 1;
+        if !issues.is_empty() {
+            failed += 1;
+            problems += issues.len();
+        }
+        for issue in issues {
+            println!("{issue}");
+        }
+    }
+
+    println!("checked {files} file(s): {failed} with problems, {problems} problem(s)");
+    if problems > 0 {
+        return Err(EdgeError::Other(format!(
+            "front matter problems in {failed} file(s)"
+        )));
+    }
+    Ok(())
+}
+
+/// The `[telemetry]` table of the site being started, if any.
+///
+/// Read ahead of the full settings parse because logging must be up first;
+/// a broken settings file is reported by that parse, not here.
+fn site_telemetry(command: &Commands) -> Option<TelemetrySettings> {
+    #[derive(serde::Deserialize)]
+    struct TelemetryOnly {
+        telemetry: Option<TelemetrySettings>,
+    }
+
+    let start = match command {
+        Commands::Start(start) => start,
+        Commands::ServeDev(cmd) => &cmd.start,
+        _ => return None,
+    };
+    let text = std::fs::read_to_string(start.dir.join("settings.toml")).ok()?;
+    toml::from_str::<TelemetryOnly>(&text).ok()?.telemetry
+}
+
+#[derive(Parser, Debug)]
+pub struct InitCmd {
+    /// Directory of the new site (or set WHISPERCMS_DIR); created if missing
+    #[arg(
+        value_name = "DIR",
+        env = "WHISPERCMS_DIR",
+        required = true,
+        value_hint = ValueHint::DirPath
+    )]
+    pub dir: PathBuf,
+
+    /// TOML file with the answers; the flags below override it
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub answers: Option<PathBuf>,
+
+    /// Feed title
+    #[arg(long, value_name = "NAME")]
+    pub site_name: Option<String>,
+
+    /// Language of documents without one, e.g. en
+    #[arg(long, value_name = "LANG")]
+    pub lang: Option<String>,
+
+    /// Public URL, e.g. https://example.com
+    #[arg(long, value_name = "URL")]
+    pub base_url: Option<String>,
+
+    /// Username of the first admin
+    #[arg(long, value_name = "NAME")]
+    pub admin: Option<String>,
+
+    /// The admin's email address, also the ACME contact
+    #[arg(long, value_name = "EMAIL")]
+    pub admin_email: Option<String>,
+
+    /// off (behind a reverse proxy), manual or acme
+    #[arg(long, value_name = "MODE", value_parser = tls_mode)]
+    pub tls: Option<TlsMode>,
+
+    #[arg(long, value_name = "PORT")]
+    pub http_port: Option<u16>,
+
+    #[arg(long, value_name = "PORT")]
+    pub https_port: Option<u16>,
+
+    /// Read the admin password from the first line of stdin instead of
+    /// WHISPERCMS_PASSWORD
+    #[arg(long)]
+    pub password_stdin: bool,
+
+    /// Replace an existing settings.toml
+    #[arg(long)]
+    pub force: bool,
+}
+
+fn tls_mode(s: &str) -> std::result::Result<TlsMode, String> {
+    match s {
+        "off" => Ok(TlsMode::Off),
+        "manual" => Ok(TlsMode::Manual),
+        "acme" => Ok(TlsMode::Acme),
+        _ => Err(format!("{s:?} is not off, manual or acme")),
+    }
+}
+
+fn do_init(cmd: InitCmd) -> Result<()> {
+    let began = Utc::now();
+    let file = match &cmd.answers {
+        Some(path) => Answers::read(path)?,
+        None => Answers::default(),
+    };
+    let answers = file.or(Answers {
+        site_name: cmd.site_name,
+        lang: cmd.lang,
+        base_url: cmd.base_url,
+        admin: cmd.admin,
+        admin_email: cmd.admin_email,
+        tls: cmd.tls,
+        http_port: cmd.http_port,
+        https_port: cmd.https_port,
+    });
+    let password = if cmd.password_stdin {
+        let mut line = String::new();
+        std::io::stdin().read_line(&mut line)?;
+        Some(line.trim_end_matches(['\r', '\n']).to_owned())
+    } else {
+        std::env::var("WHISPERCMS_PASSWORD").ok()
+    }
+    .filter(|p| !p.is_empty());
+
+    let result = init::install(&cmd.dir, &answers, password, cmd.force);
+    let steps = OpsLog::new(cmd.dir.join(OPS_LOG_FILE)).finish("init", None, began, result)?;
+    for (step, outcome) in steps {
+        let outcome = match outcome {
+            init::Outcome::Done => "done",
+            init::Outcome::Kept => "kept",
+        };
+        println!("{step:<12} {outcome}");
+    }
+    println!("Set up {}", cmd.dir.display());
+    Ok(())
+}
+
+#[derive(Subcommand, Debug)]
+pub enum NewCmd {
+    /// Write a document from archetypes/TYPE.md with its slug, date and
+    /// taxonomy fields filled in
+    Post(NewPostCmd),
+    /// Write plugins/NAME: manifest, plugin.js with its hooks stubbed and
+    /// tests/smoke.js
+    Plugin(NewPluginCmd),
+    /// Write themes/NAME: manifest, theme.js, templates, assets and
+    /// tests/smoke.js
+    Theme(NewThemeCmd),
+}
+
+#[derive(Parser, Debug)]
+pub struct NewPostCmd {
+    /// Site directory (or set WHISPERCMS_DIR)
+    #[arg(
+        value_name = "DIR",
+        env = "WHISPERCMS_DIR",
+        required = true,
+        value_hint = ValueHint::DirPath,
+        value_parser = dir_must_exist
+    )]
+    pub dir: PathBuf,
+
+    /// Title of the document; the slug is made from it
+    #[arg(value_name = "TITLE")]
+    pub title: String,
+
+    /// Content type, naming the archetype
+    #[arg(long = "type", value_name = "TYPE", default_value = "post")]
+    pub kind: String,
+
+    /// Directory below the content directory [default: TYPEs]
+    #[arg(long, value_name = "PATH")]
+    pub section: Option<String>,
+
+    /// Slug instead of the one made from the title
+    #[arg(long, value_name = "SLUG")]
+    pub slug: Option<String>,
+
+    /// Also append the document's record to this front-matter index
+    #[arg(long, value_name = "INDEX_DIR", value_hint = ValueHint::DirPath)]
+    pub index: Option<PathBuf>,
+}
+
+#[derive(Parser, Debug)]
+pub struct NewPluginCmd {
+    /// Site directory (or set WHISPERCMS_DIR)
+    #[arg(
+        value_name = "DIR",
+        env = "WHISPERCMS_DIR",
+        required = true,
+        value_hint = ValueHint::DirPath,
+        value_parser = dir_must_exist
+    )]
+    pub dir: PathBuf,
+
+    /// Plugin id and directory name, e.g. `reading-time`
+    #[arg(value_name = "NAME")]
+    pub name: String,
+}
+
+#[derive(Parser, Debug)]
+pub struct NewThemeCmd {
+    /// Site directory (or set WHISPERCMS_DIR)
+    #[arg(
+        value_name = "DIR",
+        env = "WHISPERCMS_DIR",
+        required = true,
+        value_hint = ValueHint::DirPath,
+        value_parser = dir_must_exist
+    )]
+    pub dir: PathBuf,
+
+    /// Theme id and directory name, e.g. `journal`
+    #[arg(value_name = "NAME")]
+    pub name: String,
+
+    /// URL prefix the theme serves [default: /NAME]
+    #[arg(long, value_name = "PATH")]
+    pub mount: Option<String>,
+}
+
+async fn do_new(cmd: NewCmd) -> Result<()> {
+    let (ext_dir, files) = match cmd {
+        NewCmd::Post(cmd) => return do_new_post(cmd).await,
+        NewCmd::Plugin(cmd) => {
+            let ext_dir = site_extensions_dir(&cmd.dir)?;
+            let files = scaffold::new_plugin(&ext_dir, &cmd.name)?;
+            (ext_dir, files)
+        }
+        NewCmd::Theme(cmd) => {
+            let ext_dir = site_extensions_dir(&cmd.dir)?;
+            let files = scaffold::new_theme(&ext_dir, &cmd.name, cmd.mount.as_deref())?;
+            (ext_dir, files)
+        }
+    };
+    for file in &files {
+        println!("created {}", ext_dir.join(file).display());
+    }
+    println!("run `node tests/smoke.js` in the new directory to try it");
+    Ok(())
+}
+
+async fn do_new_post(cmd: NewPostCmd) -> Result<()> {
+    let doc = archetypes::NewDoc {
+        title: &cmd.title,
+        kind: &cmd.kind,
+        section: cmd.section.as_deref(),
+        slug: cmd.slug.as_deref(),
+    }
+    .build(&cmd.dir.join(archetypes::ARCHETYPES_DIR), Utc::now())?;
+
+    let content_dir = site_content_dir(&cmd.dir)?;
+    let plan = wordpress::ImportPlan {
+        docs: vec![doc],
+        skipped: Vec::new(),
+    };
+    let summary = apply_plan(&plan, &content_dir, cmd.index.as_deref(), false).await?;
+    if let Some(existing) = summary.existing.first() {
+        return Err(EdgeError::Other(format!(
+            "{} already exists",
+            existing.display()
+        )));
+    }
+    for path in &summary.written {
+        println!("created {}", path.display());
+    }
+    if summary.indexed > 0 {
+        println!("indexed {} record(s)", summary.indexed);
+    }
+    Ok(())
+}
+
+#[derive(Parser, Debug)]
+pub struct TuiCmd {
+    /// Site directory (or set WHISPERCMS_DIR)
+    #[arg(
+        value_name = "DIR",
+        env = "WHISPERCMS_DIR",
+        required = true,
+        value_hint = ValueHint::DirPath,
+        value_parser = dir_must_exist
+    )]
+    pub dir: PathBuf,
+
+    /// Server log file to follow in the Logs tab
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub log: Option<PathBuf>,
+
+    /// Length of maintenance started from the TUI, in minutes
+    #[arg(long, value_name = "MINUTES", default_value_t = 60)]
+    pub maintenance_minutes: u32,
+}
+
+fn do_tui(cmd: TuiCmd) -> Result<()> {
+    tui::run(cmd.dir, cmd.log, cmd.maintenance_minutes)?;
+    Ok(())
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ComponentsCmd {
+    /// List components with update availability and license warnings
+    List(ComponentsListCmd),
+}
+
+#[derive(Parser, Debug)]
+pub struct ComponentsListCmd {
+    /// Site directory (or set WHISPERCMS_DIR)
+    #[arg(
+        value_name = "DIR",
+        env = "WHISPERCMS_DIR",
+        required = true,
+        value_hint = ValueHint::DirPath,
+        value_parser = dir_must_exist
+    )]
+    pub dir: PathBuf,
+
+    /// Print JSON, the same items `/admin/components` returns
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[tracing::instrument(skip_all)]
+fn do_components(cmd: ComponentsCmd) -> Result<()> {
+    let ComponentsCmd::List(cmd) = cmd;
+    let settings = site_settings(&cmd.dir)?;
+    let ext_dir = cmd.dir.join(
+        settings
+            .as_ref()
+            .and_then(|s| s.ext.as_ref())
+            .map_or_else(|| PathBuf::from("./extensions/"), |e| e.dir.clone()),
+    );
+    let components = ComponentRegistry::new(
+        &cmd.dir,
+        ext_dir,
+        &settings.and_then(|s| s.components).unwrap_or_default(),
+    )
+    .list()?;
+
+    if cmd.json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&components)
+                .map_err(|e| EdgeError::Other(e.to_string()))?
+        );
+        return Ok(());
+    }
+    for c in &components {
+        let update = match (&c.latest, c.update_available) {
+            (Some(latest), true) => format!("update {latest}"),
+            _ => String::new(),
+        };
+        println!(
+            "{:<7} {:<24} {:<10} {:<20} {:<9} {}",
+            c.kind,
+            c.id,
+            c.version.as_deref().unwrap_or("-"),
+            c.license.as_deref().unwrap_or("-"),
+            if c.enabled { "enabled" } else { "disabled" },
+            update
+        );
+        for warning in &c.warnings {
+            println!("        warning: {warning}");
+        }
+    }
+    Ok(())
+}
+
+#[derive(Parser, Debug)]
+pub struct HistoryCmd {
+    /// Site directory (or set WHISPERCMS_DIR)
+    #[arg(
+        value_name = "DIR",
+        env = "WHISPERCMS_DIR",
+        required = true,
+        value_hint = ValueHint::DirPath,
+        value_parser = dir_must_exist
+    )]
+    pub dir: PathBuf,
+
+    /// Only this operation, e.g. `start` or `index.rebuild`
+    #[arg(long, value_name = "NAME")]
+    pub operation: Option<String>,
+
+    /// Runs, failures and durations per step and version instead of the
+    /// timeline
+    #[arg(long)]
+    pub summary: bool,
+
+    /// Most recent entries shown in the timeline
+    #[arg(long, value_name = "N", default_value_t = 50)]
+    pub limit: usize,
+}
+
+#[tracing::instrument(skip_all)]
+fn do_history(cmd: HistoryCmd) -> Result<()> {
+    let log = OpsLog::new(cmd.dir.join(OPS_LOG_FILE));
+    let entries: Vec<OpEntry> = log
+        .entries()?
+        .into_iter()
+        .filter(|e| cmd.operation.as_ref().is_none_or(|op| *op == e.operation))
+        .collect();
+
+    if cmd.summary {
+        for s in summarize(&entries) {
+            println!(
+                "{:<18} {:<20} {:<10} runs={:<5} failed={:<4} mean={}ms max={}ms  last {}",
+                s.operation,
+                s.step.as_deref().unwrap_or("-"),
+                s.version,
+                s.runs,
+                s.failures,
+                s.mean_ms,
+                s.max_ms,
+                s.last_at.to_rfc3339(),
+            );
+        }
+        return Ok(());
+    }
+
+    let skip = entries.len().saturating_sub(cmd.limit);
+    for e in entries.iter().skip(skip).rev() {
+        println!(
+            "{}  {:<10} {:<18} {:<20} {:>8}ms  {}{}",
+            e.at.to_rfc3339(),
+            e.version,
+            e.operation,
+            e.step.as_deref().unwrap_or("-"),
+            e.ms,
+            match e.outcome {
+                Outcome::Ok => "ok",
+                Outcome::Failed => "FAILED",
+            },
+            e.error
+                .as_ref()
+                .map(|err| format!("  {err}"))
+                .unwrap_or_default(),
+        );
+    }
+    Ok(())
+}
+
+#[derive(Parser, Debug)]
+pub struct VerifyCmd {
+    /// Site directory (or set WHISPERCMS_DIR)
+    #[arg(
+        value_name = "DIR",
+        env = "WHISPERCMS_DIR",
+        required = true,
+        value_hint = ValueHint::DirPath,
+        value_parser = dir_must_exist
+    )]
+    pub dir: PathBuf,
+
+    /// Record the files as they are now, after installing or updating an
+    /// extension
+    #[arg(long)]
+    pub update: bool,
+}
+
+#[tracing::instrument(skip_all)]
+fn do_verify(cmd: VerifyCmd) -> Result<()> {
+    let ext_dir = site_extensions_dir(&cmd.dir)?;
+    let path = cmd.dir.join(LOCK_FILE);
+
+    if cmd.update {
+        let lock = Lockfile::of(&ext_dir)?;
+        lock.save(&path)?;
+        println!("locked {} file(s) in {}", lock.files.len(), path.display());
+        return Ok(());
+    }
+
+    let Some(lock) = Lockfile::load(&path)? else {
+        return Err(EdgeError::Config(format!(
+            "No lockfile at {}; run `whispercms verify --update` to create one",
+            path.display()
+        )));
+    };
+    let drift = lock.verify(&ext_dir)?;
+    for d in &drift {
+        println!("{d}");
+    }
+    println!(
+        "checked {} locked file(s) from {}: {} difference(s)",
+        lock.files.len(),
+        lock.locked_at.to_rfc3339(),
+        drift.len()
+    );
+    if !drift.is_empty() {
+        return Err(EdgeError::Other(format!(
+            "{} extension file(s) differ from the lockfile",
+            drift.len()
+        )));
+    }
+    Ok(())
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ExtCmd {
+    /// List the recorded versions of a plugin's or theme's manifest
+    History(ExtHistoryCmd),
+    /// Write an earlier manifest version back to disk
+    Rollback(ExtRollbackCmd),
+}
+
+#[derive(Parser, Debug)]
+pub struct ExtHistoryCmd {
+    /// Site directory (or set WHISPERCMS_DIR)
+    #[arg(
+        value_name = "DIR",
+        env = "WHISPERCMS_DIR",
+        required = true,
+        value_hint = ValueHint::DirPath,
+        value_parser = dir_must_exist
+    )]
+    pub dir: PathBuf,
+
+    #[command(flatten)]
+    pub target: ExtTarget,
+
+    /// Print this version's manifest and diff instead of the list
+    #[arg(long, value_name = "VERSION")]
+    pub show: Option<u32>,
+}
+
+#[derive(Parser, Debug)]
+pub struct ExtRollbackCmd {
+    /// Site directory (or set WHISPERCMS_DIR)
+    #[arg(
+        value_name = "DIR",
+        env = "WHISPERCMS_DIR",
+        required = true,
+        value_hint = ValueHint::DirPath,
+        value_parser = dir_must_exist
+    )]
+    pub dir: PathBuf,
+
+    #[command(flatten)]
+    pub target: ExtTarget,
+
+    /// Version to restore
+    #[arg(long, value_name = "VERSION")]
+    pub to: u32,
+}
+
+/// Exactly one of `--plugin ID` or `--theme ID`.
+#[derive(clap::Args, Debug)]
+#[group(required = true, multiple = false)]
+pub struct ExtTarget {
+    /// Plugin id
+    #[arg(long, value_name = "ID")]
+    pub plugin: Option<String>,
+
+    /// Theme id
+    #[arg(long, value_name = "ID")]
+    pub theme: Option<String>,
+}
+
+impl ExtTarget {
+    fn kind_and_id(&self) -> (ExtKind, &str) {
+        match (&self.plugin, &self.theme) {
+            (Some(id), _) => (ExtKind::Plugin, id),
+            (None, Some(id)) => (ExtKind::Theme, id),
+            (None, None) => unreachable!("clap requires one of --plugin/--theme"),
+        }
+    }
+}
+
+#[tracing::instrument(skip_all)]
+fn do_ext(cmd: ExtCmd) -> Result<()> {
+    match cmd {
+        ExtCmd::History(cmd) => {
+            let history = ConfigHistory::open(cmd.dir.join(HISTORY_DIR))?;
+            let (kind, id) = cmd.target.kind_and_id();
+
+            if let Some(version) = cmd.show {
+                let v = history.get(kind, id, version)?;
+                println!("{}", describe_config_version(&v));
+                println!("\n{}", v.manifest);
+                print!("{}", v.diff);
+                return Ok(());
+            }
+
+            let versions = history.versions(kind, id)?;
+            println!("{kind} {id}: {} version(s)", versions.len());
+            for v in &versions {
+                println!("{}", describe_config_version(v));
+            }
+            Ok(())
+        }
+        ExtCmd::Rollback(cmd) => {
+            let history = ConfigHistory::open(cmd.dir.join(HISTORY_DIR))?;
+            let (kind, id) = cmd.target.kind_and_id();
+            let ext_dir = site_extensions_dir(&cmd.dir)?;
+
+            let found = match kind {
+                ExtKind::Plugin => ext::discover_plugins(ext_dir.join("plugins/"))?
+                    .into_iter()
+                    .find(|p| p.spec.id == id)
+                    .map(|p| p.dir),
+                ExtKind::Theme => ext::discover_themes(ext_dir.join("themes/"))?
+                    .into_iter()
+                    .find(|t| t.spec.id == id)
+                    .map(|t| t.dir),
+            };
+            let Some(dir) = found else {
+                return Err(EdgeError::Config(format!(
+                    "No {kind} {id:?} under {}",
+                    ext_dir.display()
+                )));
+            };
+
+            let who = format!(
+                "cli:{}",
+                std::env::var("USER").unwrap_or_else(|_| "unknown".into())
+            );
+            let ops = OpsLog::new(cmd.dir.join(OPS_LOG_FILE));
+            let began = Utc::now();
+            let rolled_back =
+                history.rollback(kind, id, cmd.to, &dir.join(kind.manifest_name()), &who);
+            let v = ops.finish("ext.rollback", None, began, rolled_back)?;
+
+            let lock_path = cmd.dir.join(LOCK_FILE);
+            if let Some(mut lock) = Lockfile::load(&lock_path)? {
+                lock.refresh(&ext_dir, &dir.join(kind.manifest_name()))?;
+                lock.save(&lock_path)?;
+            }
+            println!("restored {kind} {id} v{} as v{}", cmd.to, v.version);
+            Ok(())
+        }
+    }
+}
+
+fn describe_config_version(v: &ConfigVersion) -> String {
+    format!(
+        "v{}  {}  by {}{}",
+        v.version,
+        v.at.to_rfc3339(),
+        v.who,
+        v.restored_from
+            .map(|r| format!("  (restored v{r})"))
+            .unwrap_or_default(),
+    )
+}
+
+/// The extensions directory from `<dir>/settings.toml`, or `<dir>/extensions/`.
+fn site_extensions_dir(dir: &std::path::Path) -> Result<PathBuf> {
+    let path = dir.join("settings.toml");
+    let ext = if path.exists() {
+        let text = std::fs::read_to_string(&path)?;
+        let settings: Settings = toml::from_str(&text).map_err(|err| {
+            EdgeError::Config(format!(
+                "Invalid settings.toml at {}: {}",
+                path.display(),
+                err
+            ))
+        })?;
+        settings.ext
+    } else {
+        None
+    };
+
+    Ok(dir.join(ext.map_or_else(|| PathBuf::from("./extensions/"), |e| e.dir)))
+}
+
+/// `<dir>/settings.toml`, if the site has one.
+fn site_settings(dir: &std::path::Path) -> Result<Option<Settings>> {
+    let path = dir.join("settings.toml");
+    if !path.exists() {
+        return Ok(None);
+    }
+    let text = std::fs::read_to_string(&path)?;
+    toml::from_str(&text).map(Some).map_err(|err| {
+        EdgeError::Config(format!(
+            "Invalid settings.toml at {}: {}",
+            path.display(),
+            err
+        ))
+    })
+}
+
+/// The content directory from `<dir>/settings.toml`, or `<dir>/content/`.
+fn site_content_dir(dir: &std::path::Path) -> Result<PathBuf> {
+    Ok(dir.join(site_content(dir)?.dir))
+}
+
+/// The content settings from `<dir>/settings.toml`, defaulting to `./content/`.
+fn site_content(dir: &std::path::Path) -> Result<ContentSettings> {
+    let default = || ContentSettings {
+        dir: PathBuf::from("./content/"),
+        extensions: Vec::new(),
+        index_dir: None,
+    };
+
+    let path = dir.join("settings.toml");
+    if !path.exists() {
+        return Ok(default());
+    }
+
+    let text = std::fs::read_to_string(&path)?;
+    let settings: Settings = toml::from_str(&text).map_err(|err| {
+        EdgeError::Config(format!(
+            "Invalid settings.toml at {}: {}",
+            path.display(),
+            err
+        ))
+    })?;
+
+    Ok(settings.content.unwrap_or_else(default))
+}
+
+fn dir_must_exist(s: &str) -> std::result::Result<PathBuf, String> {
+    let p = PathBuf::from(s);
+    if !p.exists() {
+        return Err(format!("Not found: {}", p.display()));
+    }
+    if !p.is_dir() {
+        return Err(format!("Not a directory: {}", p.display()));
+    }
+    Ok(p)
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Start process state machine
+// ─────────────────────────────────────────────────────────────────────────────
+
+trait ProcessState {}
+
+struct CommandIssued;
+
+struct SettingsLoaded {
+    command: StartCmd,
+    settings: Settings,
+    content_settings: ContentSettings,
+}
+
+struct DependenciesInjected {
+    command: StartCmd,
+    settings: Settings,
+    content_settings: ContentSettings,
+    site: Arc<Site>,
+}
+
+struct ContentLoaded {
+    command: StartCmd,
+    settings: Settings,
+    content_settings: ContentSettings,
+    documents: Vec<Document>,
+    site: Arc<Site>,
+}
+
+struct ExtensionsLoaded {
+    command: StartCmd,
+    settings: Settings,
+    content_settings: ContentSettings,
+    documents: Vec<Document>,
+    extensions: (Vec<DiscoveredPlugin>, Vec<DiscoveredTheme>),
+    site: Arc<Site>,
+}
+
+struct RouterCreated {
+    command: StartCmd,
+    settings: Settings,
+    content_settings: ContentSettings,
+    documents: Vec<Document>,
+    extensions: (Vec<DiscoveredPlugin>, Vec<DiscoveredTheme>),
+    handles: RuntimeHandles,
+    theme_bindings: Vec<ThemeBinding>,
+    site: Arc<Site>,
+}
+
+struct ServerStarted {
+    command: StartCmd,
+    settings: Settings,
+    content_settings: ContentSettings,
+    _documents: Vec<Document>,
+    _extensions: (Vec<DiscoveredPlugin>, Vec<DiscoveredTheme>),
+    handles: RuntimeHandles,
+    theme_bindings: Vec<ThemeBinding>,
+    web: WebServerHandle,
+    site: Arc<Site>,
+}
+
+impl ProcessState for CommandIssued {}
+impl ProcessState for SettingsLoaded {}
+impl ProcessState for DependenciesInjected {}
+impl ProcessState for ContentLoaded {}
+impl ProcessState for ExtensionsLoaded {}
+impl ProcessState for RouterCreated {}
+impl ProcessState for ServerStarted {}
+
+struct StartProcess<S: ProcessState> {
+    state: S,
+}
+
+impl StartProcess<CommandIssued> {
+    /// Load settings from `<dir>/settings.toml`.
+    ///
+    /// `dir` is the directory that contains `settings.toml`.
+    #[tracing::instrument(skip_all)]
+    fn parse_settings_file(command: StartCmd) -> Result<StartProcess<SettingsLoaded>> {
+        let dir = command.dir.clone();
+        // Ensure directory exists
+        if !dir.exists() {
+            return Err(EdgeError::Config(format!(
+                "Settings directory does not exist: {}",
+                dir.display()
+            )));
+        }
+
+        // Construct full path to file
+        let mut path = PathBuf::from(dir);
+        path.push("settings.toml");
+
+        // Ensure file exists
+        if !path.exists() {
+            return Err(EdgeError::Config(format!(
+                "settings.toml not found at {}",
+                path.display()
+            )));
+        }
+
+        // Read the file
+        let text = std::fs::read_to_string(&path).map_err(|err| {
+            EdgeError::Config(format!("Failed reading {}: {}", path.display(), err))
+        })?;
+
+        // Deserialize
+        let settings: Settings = toml::from_str(&text).map_err(|err| {
+            EdgeError::Config(format!(
+                "Invalid settings.toml at {}: {}",
+                path.display(),
+                err
+            ))
+        })?;
+
+        let content_settings = match settings.content.clone() {
+            Some(settings) => settings,
+            None => ContentSettings {
+                dir: PathBuf::from("./content/"),
+                index_dir: None,
+                extensions: vec![],
+            },
+        };
+
+        Ok(StartProcess::<SettingsLoaded>::new(
+            command,
+            settings,
+            content_settings,
+        ))
+    }
+}
+
+impl StartProcess<SettingsLoaded> {
+    fn new(command: StartCmd, settings: Settings, content_settings: ContentSettings) -> Self {
+        Self {
+            state: SettingsLoaded {
+                command,
+                settings,
+                content_settings,
+            },
+        }
+    }
+
+    /// Apply pending migrations (see [`Migrator`]).
+    #[tracing::instrument(skip_all)]
+    fn migrate(self) -> Result<Self> {
+        let applied =
+            Migrator::new(&self.state.command.dir, Some(&self.state.settings)).up(None, false)?;
+        for m in &applied {
+            info!("Migrated the site to {} ({})", m.version, m.name);
+        }
+        Ok(self)
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn inject_dependencies(self) -> Result<StartProcess<DependenciesInjected>> {
+        let dir = self.state.command.dir.clone();
+
+        let index_dir = match self.state.content_settings.index_dir.clone() {
+            Some(d) => dir.join(d),
+            None => dir.join("./content_index/"),
+        };
+
+        let index_dir = index_dir.join(
+            regex::Regex::new(r"[^A-Za-z0-9]")
+                .unwrap()
+                .replace_all(Utc::now().to_rfc3339().as_str(), "_")
+                .to_string(),
+        );
+
+        // inject the dependencies
+        // The indexes, with the block types, authors and permalinks the
+        // content scan reads; `serve-dev` serves every page fresh.
+        let content = SiteContent::open(
+            &dir,
+            &self.state.settings,
+            index_dir.clone(),
+            self.state.command.dev,
+        )
+        .await?;
+        let (content_root, scan_cfg) = content_scan(&dir, &self.state.content_settings)?;
+        let site = Site::open(
+            dir,
+            self.state.settings.clone(),
+            Arc::new(content),
+            content_root,
+            scan_cfg.file_re,
+            extensions_dir(&self.state.command, &self.state.settings),
+            self.state.command.dev,
+        )?;
+
+        Ok(StartProcess {
+            state: DependenciesInjected {
+                command: self.state.command,
+                settings: self.state.settings,
+                content_settings: ContentSettings {
+                    dir: self.state.content_settings.dir,
+                    extensions: self.state.content_settings.extensions,
+                    index_dir: Some(index_dir),
+                },
+                site: Arc::new(site),
+            },
+        })
+    }
+}
+
+impl StartProcess<DependenciesInjected> {
+    #[tracing::instrument(skip_all)]
+    async fn scan_content_directory(self) -> Result<StartProcess<ContentLoaded>> {
+        let (root, cfg) = content_scan(&self.state.command.dir, &self.state.content_settings)?;
+
+        // This now calls the serve-level pipeline
+        let content = self.state.site.content.clone();
+        let (docs, errs) =
+            scan_and_process_docs(&root, cfg, ContentMgr::new(root.clone(), content.clone()))
+                .await?;
+        // After the scan, so only later changes are reported.
+        content.start_webhooks(
+            &self.state.command.dir,
+            self.state.settings.webhooks.as_deref().unwrap_or_default(),
+        )?;
+
+        debug!(
+            "Document and Error Counts: ({}, {})",
+            docs.len(),
+            errs.len()
+        );
+        Ok(self.done(docs))
+    }
+
+    #[tracing::instrument(skip_all)]
+    fn done(self, docs: Vec<Document>) -> StartProcess<ContentLoaded> {
+        StartProcess {
+            state: ContentLoaded {
+                command: self.state.command,
+                settings: self.state.settings,
+                content_settings: self.state.content_settings,
+                documents: docs,
+                site: self.state.site,
+            },
+        }
+    }
+}
+
+/// The content root and scan config; the startup scan and the incremental
+/// watcher must agree on both.
+fn content_scan(
+    dir: &std::path::Path,
+    content_settings: &ContentSettings,
+) -> Result<(PathBuf, FolderScanConfig)> {
+    let cfg = FolderScanConfig {
+        file_re: Some(filter::build_filename_regex(
+            match content_settings.extensions.len() {
+                0 => DEFAULT_CONTENT_EXTS
+                    .iter()
+                    .map(|s| (*s).to_owned())
+                    .collect(),
+                _ => content_settings.extensions.clone(),
+            },
+        )?),
+        ..Default::default()
+    };
+
+    Ok((dir.join(&content_settings.dir), cfg))
+}
+
+/// Resolve the extensions directory (default `./extensions/`) against the start dir.
+fn extensions_dir(command: &StartCmd, settings: &Settings) -> PathBuf {
+    let ext_settings = match &settings.ext {
+        Some(ext) => ext,
+        None => &ExtensionSettings {
+            dir: PathBuf::from("./extensions/"),
+            integrity: Default::default(),
+            log: Default::default(),
+        },
+    };
+
+    command.dir.join(&ext_settings.dir)
+}
+
+impl StartProcess<ContentLoaded> {
+    #[tracing::instrument(skip_all)]
+    fn scan_extensions_directory(self) -> Result<StartProcess<ExtensionsLoaded>> {
+        let ext_dir = extensions_dir(&self.state.command, &self.state.settings);
+        let integrity = self
+            .state
+            .settings
+            .ext
+            .as_ref()
+            .map(|ext| ext.integrity)
+            .unwrap_or_default();
+        lock::check(&self.state.command.dir, &ext_dir, integrity)?;
+
+        let snapshot = self.state.command.dir.join(SNAPSHOT_FILE);
+        let (plugins, themes) = snapshot::discover_or_restore(&snapshot, &ext_dir)?;
+
+        Ok(self.done(plugins, themes))
+    }
+
+    #[tracing::instrument(skip_all)]
+    fn done(
+        self,
+        plugins: Vec<DiscoveredPlugin>,
+        themes: Vec<DiscoveredTheme>,
+    ) -> StartProcess<ExtensionsLoaded> {
+        StartProcess {
+            state: ExtensionsLoaded {
+                command: self.state.command,
+                settings: self.state.settings,
+                content_settings: self.state.content_settings,
+                documents: self.state.documents,
+                extensions: (plugins, themes),
+                site: self.state.site,
+            },
+        }
+    }
+}
+
+impl StartProcess<ExtensionsLoaded> {
+    #[tracing::instrument(skip_all)]
+    async fn register_routes_and_middleware(self) -> Result<StartProcess<RouterCreated>> {
+        let (plugins, themes) = self.state.extensions.clone();
+        let stored = self.state.command.dir.join(EXT_SETTINGS_DIR);
+        let plugin_cfgs = ext_settings::plugin_configs(&stored, &plugins)?;
+        let theme_cfgs = themes
+            .iter()
+            .map(|t| ext_settings::theme_config(&stored, t))
+            .collect::<std::result::Result<_, _>>()?;
+
+        // Build ThemeBinding values from DiscoveredTheme so we have template_root.
+        let theme_bnds = ext_settings::theme_bindings(&stored, &themes);
+        let site = &self.state.site;
+        site.flags.set_plugin_flags(&plugins);
+        site.plugin_routes.set(&plugins);
+        site.strings.set_plugin_catalogs(&plugins);
+        site.strings.set_theme_catalogs(&themes);
+        site.collections.set_themes(&themes);
+        site.redirects.clone().into_inner().start();
+
+        let handles = bootstrap_all(plugin_cfgs, theme_cfgs, site.engines.clone())?;
+
+        info!("Initializing themes...");
+        handles
+            .theme_client
+            .init_all(RequestContext::builder().build())
+            .await?;
+        info!("Initializing plugins...");
+        handles
+            .plugin_client
+            .init_all(RequestContext::builder().build())
+            .await?;
+
+        info!("Plugins and themes initialized successfully");
+        cache::extensions_changed(site.content.cache(), &handles);
+
+        Ok(self.done(handles, theme_bnds))
+    }
+
+    #[tracing::instrument(skip_all)]
+    fn done(
+        self,
+        handles: RuntimeHandles,
+        theme_bindings: Vec<ThemeBinding>,
+    ) -> StartProcess<RouterCreated> {
+        StartProcess {
+            state: RouterCreated {
+                command: self.state.command,
+                settings: self.state.settings,
+                content_settings: self.state.content_settings,
+                documents: self.state.documents,
+                extensions: self.state.extensions,
+                handles,
+                theme_bindings,
+                site: self.state.site,
+            },
+        }
+    }
+}
+
+impl StartProcess<RouterCreated> {
+    /// Render the site into `out` instead of serving it, then stop the
+    /// plugin and theme actors.
+    #[tracing::instrument(skip_all)]
+    async fn export(self, out: &std::path::Path, base_url: Option<String>) -> Result<()> {
+        let base_url = base_url
+            .or_else(|| self.state.settings.sitemap.as_ref()?.base_url.clone())
+            .unwrap_or_else(|| {
+                warn!("No --base-url or [sitemap] base_url; absolute links will use localhost");
+                "http://localhost".to_owned()
+            });
+        let summary = crate::export::export(
+            self.state.site.clone(),
+            self.state.handles.clone(),
+            self.state.theme_bindings.clone(),
+            &base_url,
+            out,
+        )
+        .await;
+        self.state.handles.plugin_client.stop();
+        self.state.handles.theme_client.stop();
+        let summary = summary?;
+
+        println!(
+            "exported {} file(s) and {} redirect(s) to {}",
+            summary.files,
+            summary.redirects,
+            out.display()
+        );
+        for (path, status) in &summary.failed {
+            println!("  skipped {path}: {status}");
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn start_servers(self) -> Result<StartProcess<ServerStarted>> {
+        let handles = self.state.handles.clone();
+        let theme_bindings = self.state.theme_bindings.clone();
+
+        let web = WebServerHandle::start(
+            self.state.site.clone(),
+            handles.clone(),
+            theme_bindings.clone(),
+        )
+        .await?;
+
+        Ok(self.done(web))
+    }
+
+    #[tracing::instrument(skip_all)]
+    fn done(self, web: WebServerHandle) -> StartProcess<ServerStarted> {
+        StartProcess {
+            state: ServerStarted {
+                command: self.state.command,
+                settings: self.state.settings,
+                content_settings: self.state.content_settings,
+                _documents: self.state.documents,
+                _extensions: self.state.extensions,
+                handles: self.state.handles,
+                theme_bindings: self.state.theme_bindings,
+                site: self.state.site,
+                web,
+            },
+        }
+    }
+}
+
+impl StartProcess<ServerStarted> {
+    /// The site, its runtimes and its web server, for the edge to route to.
+    fn site_entry(&self) -> SiteEntry {
+        SiteEntry {
+            site: self.state.site.clone(),
+            handles: self.state.handles.clone(),
+            web: self.state.web.clone(),
+        }
+    }
+
+    /// Watch the extensions directory and hot-reload plugins and themes.
+    #[tracing::instrument(skip_all)]
+    fn watch_extensions_directory(&self) -> Result<()> {
+        reload::watch_extensions(
+            self.state.site.clone(),
+            self.state.handles.clone(),
+            self.state.theme_bindings.clone(),
+            self.state.web.clone(),
+        )
+    }
+
+    /// Watch the content directory and re-index changed documents, and
+    /// refresh cached pages as scheduled documents publish or expire.
+    #[tracing::instrument(skip_all)]
+    fn watch_content_directory(&self) -> Result<()> {
+        let (root, cfg) = content_scan(&self.state.command.dir, &self.state.content_settings)?;
+        let ops = OpsLog::new(self.state.command.dir.join(OPS_LOG_FILE));
+        let site = &self.state.site;
+        let content = site.content.clone();
+        let live = site.live.clone().map(|live| live.into_inner());
+        incremental::watch_content(root, cfg, content.clone(), ops, live)?;
+        schedule::start(content);
+        Ok(())
+    }
+
+    /// Wait for SIGINT or SIGTERM.
+    #[tracing::instrument(skip_all)]
+    async fn is_running(&self) -> Result<()> {
+        #[cfg(unix)]
+        {
+            let mut term =
+                tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+            tokio::select! {
+                r = tokio::signal::ctrl_c() => r?,
+                _ = term.recv() => {}
+            }
+        }
+        #[cfg(not(unix))]
+        tokio::signal::ctrl_c().await?;
+
+        info!("Shutdown requested");
+        Ok(())
+    }
+
+    /// Write the warm-start snapshot, drain the web server, then stop the
+    /// plugin and theme actors once no request can reach them. A snapshot
+    /// that cannot be written only costs the next boot a full discovery.
+    #[tracing::instrument(skip_all)]
+    async fn shut_down(self) -> Result<()> {
+        let ext_dir = extensions_dir(&self.state.command, &self.state.settings);
+        let path = self.state.command.dir.join(SNAPSHOT_FILE);
+        match snapshot::save(&path, &ext_dir) {
+            Ok(()) => info!("Extension snapshot written to {:?}", path),
+            Err(e) => warn!("Writing extension snapshot failed: {}", e),
+        }
+
+        info!(
+            "Draining in-flight requests for up to {}s",
+            self.state.settings.edge.drain_secs
+        );
+        self.state.web.shutdown().await;
+        self.state.handles.plugin_client.stop();
+        self.state.handles.theme_client.stop();
+        Ok(())
+    }
+}
@@ -0,0 +1,69 @@
+// crates/edge/src/cli/outbox.rs
+
+use super::{dir_must_exist, Result};
+use crate::db::outbox::{Delivery, Outbox, OutboxEntry, OutboxState};
+use clap::{builder::ValueHint, Parser};
+use std::path::PathBuf;
+
+#[derive(Parser, Debug)]
+pub struct OutboxCmd {
+    /// Site directory (or set WHISPERCMS_DIR)
+    #[arg(
+        value_name = "DIR",
+        env = "WHISPERCMS_DIR",
+        required = true,
+        value_hint = ValueHint::DirPath,
+        value_parser = dir_must_exist
+    )]
+    pub dir: PathBuf,
+
+    /// List dead-lettered deliveries instead of pending ones
+    #[arg(long)]
+    pub dead: bool,
+
+    /// Move a dead-lettered delivery back to pending
+    #[arg(long, value_name = "ID")]
+    pub retry: Option<String>,
+}
+
+/// Outbox location inside the site directory.
+pub const OUTBOX_DIR: &str = "./outbox/";
+
+#[tracing::instrument(skip_all)]
+pub(super) fn do_outbox(cmd: OutboxCmd) -> Result<()> {
+    let outbox = Outbox::open(cmd.dir.join(OUTBOX_DIR))?;
+
+    if let Some(id) = cmd.retry {
+        let entry = outbox.retry_dead(&id)?;
+        println!("requeued {}", entry.id);
+        return Ok(());
+    }
+
+    let pending = outbox.list(OutboxState::Pending)?;
+    let dead = outbox.list(OutboxState::Dead)?;
+    println!("pending: {}  dead: {}", pending.len(), dead.len());
+
+    for entry in if cmd.dead { &dead } else { &pending } {
+        println!("{}", describe_outbox_entry(entry));
+    }
+    Ok(())
+}
+
+fn describe_outbox_entry(entry: &OutboxEntry) -> String {
+    let target = match &entry.delivery {
+        Delivery::Email { to, subject, .. } => format!("email {} {:?}", to.join(","), subject),
+        Delivery::Webhook { url, .. } => format!("webhook {}", url),
+    };
+    format!(
+        "{}  attempts={}  next={}  {}{}",
+        entry.id,
+        entry.attempts,
+        entry.next_attempt_at.to_rfc3339(),
+        target,
+        entry
+            .last_error
+            .as_ref()
+            .map(|e| format!("  last_error={e:?}"))
+            .unwrap_or_default(),
+    )
+}
@@ -0,0 +1,158 @@
+// crates/edge/src/cli/packages.rs
+
+use super::{dir_must_exist, site_extensions_dir, Result};
+use crate::db::history::ExtKind;
+use crate::db::ops::{OpsLog, OPS_LOG_FILE};
+use crate::packages;
+use chrono::Utc;
+use clap::{builder::ValueHint, Parser, Subcommand};
+use std::path::PathBuf;
+
+#[derive(Subcommand, Debug)]
+pub enum PackageCmd {
+    /// Install a zip or gzipped tar from a path or URL
+    Install(PackageInstallCmd),
+    /// List installed ones, with where their packages came from
+    List(PackageListCmd),
+    /// Replace one with a newer package, by default from where it came
+    Update(PackageUpdateCmd),
+    /// Delete one
+    Remove(PackageRemoveCmd),
+}
+
+/// A detached signature and the key to check it with.
+#[derive(clap::Args, Debug)]
+pub struct PackageSignature {
+    /// Path or URL of the package's signature, raw or base64
+    #[arg(long, value_name = "SIG", requires = "key")]
+    pub signature: Option<String>,
+
+    /// PEM public key the signature must verify with
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath, requires = "signature")]
+    pub key: Option<PathBuf>,
+}
+
+#[derive(Parser, Debug)]
+pub struct PackageInstallCmd {
+    /// Site directory (or set WHISPERCMS_DIR)
+    #[arg(
+        value_name = "DIR",
+        env = "WHISPERCMS_DIR",
+        required = true,
+        value_hint = ValueHint::DirPath,
+        value_parser = dir_must_exist
+    )]
+    pub dir: PathBuf,
+
+    /// Path or http(s) URL of the package
+    #[arg(value_name = "PACKAGE")]
+    pub source: String,
+
+    #[command(flatten)]
+    pub signature: PackageSignature,
+}
+
+#[derive(Parser, Debug)]
+pub struct PackageListCmd {
+    /// Site directory (or set WHISPERCMS_DIR)
+    #[arg(
+        value_name = "DIR",
+        env = "WHISPERCMS_DIR",
+        required = true,
+        value_hint = ValueHint::DirPath,
+        value_parser = dir_must_exist
+    )]
+    pub dir: PathBuf,
+}
+
+#[derive(Parser, Debug)]
+pub struct PackageUpdateCmd {
+    /// Site directory (or set WHISPERCMS_DIR)
+    #[arg(
+        value_name = "DIR",
+        env = "WHISPERCMS_DIR",
+        required = true,
+        value_hint = ValueHint::DirPath,
+        value_parser = dir_must_exist
+    )]
+    pub dir: PathBuf,
+
+    /// Id of the installed plugin or theme
+    #[arg(value_name = "ID")]
+    pub id: String,
+
+    /// Path or http(s) URL of the new package [default: where it came from]
+    #[arg(long, value_name = "PACKAGE")]
+    pub from: Option<String>,
+
+    #[command(flatten)]
+    pub signature: PackageSignature,
+}
+
+#[derive(Parser, Debug)]
+pub struct PackageRemoveCmd {
+    /// Site directory (or set WHISPERCMS_DIR)
+    #[arg(
+        value_name = "DIR",
+        env = "WHISPERCMS_DIR",
+        required = true,
+        value_hint = ValueHint::DirPath,
+        value_parser = dir_must_exist
+    )]
+    pub dir: PathBuf,
+
+    /// Id of the installed plugin or theme
+    #[arg(value_name = "ID")]
+    pub id: String,
+}
+
+#[tracing::instrument(skip_all)]
+pub(super) fn do_package(kind: ExtKind, cmd: PackageCmd) -> Result<()> {
+    let source = |archive: String, signature: PackageSignature| packages::Source {
+        archive,
+        signature: signature.signature,
+        key: signature.key,
+    };
+    let began = Utc::now();
+    match cmd {
+        PackageCmd::Install(cmd) => {
+            let pkgs = packages::Packages::new(&cmd.dir, site_extensions_dir(&cmd.dir)?);
+            let installed = pkgs.install(kind, &source(cmd.source, cmd.signature));
+            let id = OpsLog::new(cmd.dir.join(OPS_LOG_FILE)).finish(
+                "ext.install",
+                None,
+                began,
+                installed,
+            )?;
+            println!("installed {kind} {id}");
+        }
+        PackageCmd::List(cmd) => {
+            let pkgs = packages::Packages::new(&cmd.dir, site_extensions_dir(&cmd.dir)?);
+            for listed in pkgs.list(kind)? {
+                let state = match listed.enabled {
+                    Some(false) => "  (disabled)",
+                    _ => "",
+                };
+                let from = listed
+                    .package
+                    .map(|p| format!("{}  {}", p.source.archive, p.installed_at.to_rfc3339()))
+                    .unwrap_or_else(|| "(not from a package)".into());
+                println!("{}  {}{state}  {from}", listed.id, listed.name);
+            }
+        }
+        PackageCmd::Update(cmd) => {
+            let pkgs = packages::Packages::new(&cmd.dir, site_extensions_dir(&cmd.dir)?);
+            let from = cmd.from.map(|archive| source(archive, cmd.signature));
+            let updated = pkgs.update(kind, &cmd.id, from.as_ref());
+            OpsLog::new(cmd.dir.join(OPS_LOG_FILE)).finish("ext.update", None, began, updated)?;
+            println!("updated {kind} {}", cmd.id);
+        }
+        PackageCmd::Remove(cmd) => {
+            let pkgs = packages::Packages::new(&cmd.dir, site_extensions_dir(&cmd.dir)?);
+            let removed = pkgs.remove(kind, &cmd.id);
+            OpsLog::new(cmd.dir.join(OPS_LOG_FILE)).finish("ext.remove", None, began, removed)?;
+            println!("removed {kind} {}", cmd.id);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,207 @@
+// crates/edge/src/cli/user.rs
+
+use super::{dir_must_exist, Result};
+use crate::db::users::{Role, UserStore, ValidatedPassword, USERS_DB_DIR};
+use clap::{builder::ValueHint, Parser, Subcommand};
+use std::path::PathBuf;
+
+#[derive(Subcommand, Debug)]
+pub enum UserCmd {
+    /// Create an account
+    Add(UserAddCmd),
+    /// List accounts and their roles
+    List(UserListCmd),
+    /// Change an account's role
+    Role(UserRoleCmd),
+    /// Delete an account, ending its sessions
+    Remove(UserRemoveCmd),
+    /// Set or clear where an account's password reset codes are mailed
+    Email(UserEmailCmd),
+    /// End every session of an account
+    Logout(UserLogoutCmd),
+    /// Turn off two-factor login for an account that lost its codes
+    TwoFactorOff(UserTwoFactorOffCmd),
+}
+
+#[derive(Parser, Debug)]
+pub struct UserAddCmd {
+    /// Site directory (or set WHISPERCMS_DIR)
+    #[arg(
+        value_name = "DIR",
+        env = "WHISPERCMS_DIR",
+        required = true,
+        value_hint = ValueHint::DirPath,
+        value_parser = dir_must_exist
+    )]
+    pub dir: PathBuf,
+
+    /// Login name: 1-32 of a-z, 0-9, '-' and '_'
+    #[arg(long, value_name = "NAME")]
+    pub username: String,
+
+    /// admin, editor, author or viewer
+    #[arg(long, value_name = "ROLE", default_value = "author")]
+    pub role: Role,
+
+    /// At least 12 characters; prefer the environment variable, which
+    /// stays out of the process list
+    #[arg(long, env = "WHISPERCMS_PASSWORD", hide_env_values = true)]
+    pub password: String,
+}
+
+#[derive(Parser, Debug)]
+pub struct UserListCmd {
+    /// Site directory (or set WHISPERCMS_DIR)
+    #[arg(
+        value_name = "DIR",
+        env = "WHISPERCMS_DIR",
+        required = true,
+        value_hint = ValueHint::DirPath,
+        value_parser = dir_must_exist
+    )]
+    pub dir: PathBuf,
+}
+
+#[derive(Parser, Debug)]
+pub struct UserRoleCmd {
+    /// Site directory (or set WHISPERCMS_DIR)
+    #[arg(
+        value_name = "DIR",
+        env = "WHISPERCMS_DIR",
+        required = true,
+        value_hint = ValueHint::DirPath,
+        value_parser = dir_must_exist
+    )]
+    pub dir: PathBuf,
+
+    #[arg(long, value_name = "NAME")]
+    pub username: String,
+
+    /// admin, editor, author or viewer
+    #[arg(long, value_name = "ROLE")]
+    pub role: Role,
+}
+
+#[derive(Parser, Debug)]
+pub struct UserRemoveCmd {
+    /// Site directory (or set WHISPERCMS_DIR)
+    #[arg(
+        value_name = "DIR",
+        env = "WHISPERCMS_DIR",
+        required = true,
+        value_hint = ValueHint::DirPath,
+        value_parser = dir_must_exist
+    )]
+    pub dir: PathBuf,
+
+    #[arg(long, value_name = "NAME")]
+    pub username: String,
+}
+
+#[derive(Parser, Debug)]
+pub struct UserEmailCmd {
+    /// Site directory (or set WHISPERCMS_DIR)
+    #[arg(
+        value_name = "DIR",
+        env = "WHISPERCMS_DIR",
+        required = true,
+        value_hint = ValueHint::DirPath,
+        value_parser = dir_must_exist
+    )]
+    pub dir: PathBuf,
+
+    #[arg(long, value_name = "NAME")]
+    pub username: String,
+
+    /// Leave out to clear the address, which turns resets off for them
+    #[arg(long, value_name = "ADDRESS")]
+    pub email: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct UserLogoutCmd {
+    /// Site directory (or set WHISPERCMS_DIR)
+    #[arg(
+        value_name = "DIR",
+        env = "WHISPERCMS_DIR",
+        required = true,
+        value_hint = ValueHint::DirPath,
+        value_parser = dir_must_exist
+    )]
+    pub dir: PathBuf,
+
+    #[arg(long, value_name = "NAME")]
+    pub username: String,
+}
+
+#[derive(Parser, Debug)]
+pub struct UserTwoFactorOffCmd {
+    /// Site directory (or set WHISPERCMS_DIR)
+    #[arg(
+        value_name = "DIR",
+        env = "WHISPERCMS_DIR",
+        required = true,
+        value_hint = ValueHint::DirPath,
+        value_parser = dir_must_exist
+    )]
+    pub dir: PathBuf,
+
+    #[arg(long, value_name = "NAME")]
+    pub username: String,
+}
+
+#[tracing::instrument(skip_all)]
+pub(super) fn do_user(cmd: UserCmd) -> Result<()> {
+    match cmd {
+        UserCmd::Add(cmd) => {
+            let store = UserStore::new(cmd.dir.join(USERS_DB_DIR));
+            let user = store.create(
+                &cmd.username,
+                cmd.role,
+                &ValidatedPassword::new(cmd.password)?,
+            )?;
+            println!("Created {} ({})", user.username, user.role);
+        }
+        UserCmd::List(cmd) => {
+            let store = UserStore::new(cmd.dir.join(USERS_DB_DIR));
+            for user in store.list()? {
+                println!(
+                    "{:<32} {:<8} created {} {}",
+                    user.username,
+                    user.role,
+                    user.created_at.format("%Y-%m-%d"),
+                    user.email.as_deref().unwrap_or("")
+                );
+            }
+        }
+        UserCmd::Role(cmd) => {
+            let store = UserStore::new(cmd.dir.join(USERS_DB_DIR));
+            let user = store.set_role(&cmd.username, cmd.role)?;
+            println!("{} is now {}", user.username, user.role);
+        }
+        UserCmd::Remove(cmd) => {
+            let store = UserStore::new(cmd.dir.join(USERS_DB_DIR));
+            store.remove(&cmd.username)?;
+            println!("Removed {}", cmd.username);
+        }
+        UserCmd::Email(cmd) => {
+            let store = UserStore::new(cmd.dir.join(USERS_DB_DIR));
+            let user = store.set_email(&cmd.username, cmd.email.as_deref())?;
+            match user.email {
+                Some(email) => println!("{} gets reset codes at {}", user.username, email),
+                None => println!("{} has no email address", user.username),
+            }
+        }
+        UserCmd::Logout(cmd) => {
+            let store = UserStore::new(cmd.dir.join(USERS_DB_DIR));
+            let ended = store.revoke_sessions(&cmd.username)?;
+            println!("Ended {} session(s) of {}", ended, cmd.username);
+        }
+        UserCmd::TwoFactorOff(cmd) => {
+            let store = UserStore::new(cmd.dir.join(USERS_DB_DIR));
+            let user = store.disable_two_factor(&cmd.username)?;
+            println!("{} logs in with a password alone", user.username);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,417 @@
+// crates/edge/src/collections.rs
+
+// Saved queries (adapt::mql::collection) for theme requests. Themes ship
+// defaults in theme.toml:
+//
+//   [collections.recent-posts]
+//   filter = { type = "post" }
+//   sort = { "publish.date" = -1 }
+//   limit = 5
+//
+// and editors define or replace them in the admin:
+//
+//   GET    /admin/api/collections          { items: [{ name, origin, definition }] }
+//   PUT    /admin/api/collections/{name}   { filter, sort, limit, skip } → the collection
+//   DELETE /admin/api/collections/{name}   → 204; a theme default of that name returns
+//
+// Admin definitions live in db::collections and win over a theme's; between
+// themes the first to define a name keeps it. Every definition is parsed
+// once into the site's registry (its `Collections`), which each change
+// replaces whole, and the response cache is dropped with it, so the next
+// render of every page that uses a collection sees the edit.
+//
+// Before a theme request renders, the collections run against the
+// front-matter archive, drafts left out and at most `api::content::MAX_LIMIT`
+// documents each (`DEFAULT_LIMIT` when one sets none). They run together as
+// one serve::render::ModelAssembly, alongside the page's own lookup, and
+// share `[query_budget] model_timeout_ms`; a collection still running then
+// is logged and comes back empty, like one that failed. The results go on the
+// `RequestContext` (`ctx.collections` in JS) and behind the `collection`
+// template helper (serve::collections); the page is tagged with what the
+// queries read, like any other query it makes.
+
+use crate::api::content::ContentQuery;
+use crate::audit::{self, AuditNote};
+use crate::auth::{Editor, RequireRole, Viewer};
+use crate::cache;
+use crate::db::collections::{CollectionStore, StoredCollection, COLLECTIONS_DB_DIR};
+use crate::db::store::{blocking, failed};
+use crate::fs::ext::DiscoveredTheme;
+use crate::fs::index::SiteContent;
+use actix_web::{dev::HttpServiceFactory, web, HttpRequest, HttpResponse};
+use adapt::mql::{CollectionRegistry, SavedQuery};
+use chrono::Utc;
+use domain::setting::QueryBudgetSettings;
+use serde_json::{json, Value as Json};
+use serve::render::ModelAssembly;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+/// How long `resolve` waits when `[query_budget]` sets no `model_timeout_ms`.
+pub const DEFAULT_MODEL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The site's admin-defined collections.
+pub fn store(root_dir: &Path) -> CollectionStore {
+    CollectionStore::new(root_dir.join(COLLECTIONS_DB_DIR))
+}
+
+/// One site's collections: the defaults of its themes, the stored
+/// definitions, and the registry parsed from both.
+pub struct Collections {
+    store: CollectionStore,
+    /// Theme defaults: name → (theme id, definition).
+    theme_defaults: RwLock<BTreeMap<String, (String, Json)>>,
+    registry: RwLock<Arc<CollectionRegistry>>,
+}
+
+impl Collections {
+    /// The collections stored under `root_dir`, before any theme's.
+    pub fn open(root_dir: &Path) -> Self {
+        let collections = Self {
+            store: store(root_dir),
+            theme_defaults: Default::default(),
+            registry: Default::default(),
+        };
+        collections.rebuild();
+        collections
+    }
+
+    /// Replace the theme defaults with those of `themes` and rebuild.
+    pub fn set_themes(&self, themes: &[DiscoveredTheme]) {
+        let mut defaults = BTreeMap::new();
+        for theme in themes {
+            for (name, definition) in &theme.collections {
+                defaults
+                    .entry(name.clone())
+                    .or_insert_with(|| (theme.spec.id.clone(), definition.clone()));
+            }
+        }
+        match self.theme_defaults.write() {
+            Ok(mut current) => *current = defaults,
+            Err(e) => *e.into_inner() = defaults,
+        }
+        self.rebuild();
+    }
+
+    fn theme_defaults(&self) -> BTreeMap<String, (String, Json)> {
+        self.theme_defaults
+            .read()
+            .map(|d| d.clone())
+            .unwrap_or_else(|e| e.into_inner().clone())
+    }
+
+    /// Parse the theme defaults and the stored definitions into a new
+    /// registry and use it from the next request on. Bad definitions are
+    /// logged and left out.
+    pub fn rebuild(&self) {
+        let mut sources: BTreeMap<String, Json> = self
+            .theme_defaults()
+            .into_iter()
+            .map(|(name, (_, definition))| (name, definition))
+            .collect();
+        match self.store.list() {
+            Ok(stored) => sources.extend(stored.into_iter().map(|c| (c.name, c.definition))),
+            Err(e) => error!("Reading the saved collections failed: {}", e),
+        }
+
+        let mut registry = CollectionRegistry::new();
+        for (name, definition) in &sources {
+            if let Err(e) = registry.define(name, definition) {
+                warn!("Skipping collection {}: {}", name, e);
+            }
+        }
+        info!("{} collections defined", registry.len());
+        match self.registry.write() {
+            Ok(mut current) => *current = Arc::new(registry),
+            Err(e) => *e.into_inner() = Arc::new(registry),
+        }
+    }
+
+    /// The collections every render uses.
+    pub fn registry(&self) -> Arc<CollectionRegistry> {
+        self.registry
+            .read()
+            .map(|r| r.clone())
+            .unwrap_or_else(|e| e.into_inner().clone())
+    }
+
+    /// Run every collection concurrently against `content`: name → matching
+    /// published documents. A collection that fails or outlasts the model
+    /// timeout in `budget` is logged and comes back empty.
+    pub async fn resolve(
+        &self,
+        content: &SiteContent,
+        budget: Option<&QueryBudgetSettings>,
+    ) -> BTreeMap<String, Json> {
+        let registry = self.registry();
+        let mut assembly = ModelAssembly::new();
+        for query in registry.iter() {
+            assembly = assembly.task(query.name.clone(), async move {
+                let q = ContentQuery::clamped(query.filter.clone(), query.options.clone())
+                    .published_only();
+                content
+                    .query_front_matter(&q.filter, &q.opts)
+                    .await
+                    .map(|(_, docs, _)| Json::Array(docs))
+                    .map_err(|e| e.to_string())
+            });
+        }
+        if assembly.is_empty() {
+            return BTreeMap::new();
+        }
+
+        let timeout = budget
+            .and_then(|b| b.model_timeout_ms)
+            .map_or(DEFAULT_MODEL_TIMEOUT, Duration::from_millis);
+        let mut model = assembly.run(timeout).await;
+        for (name, failure) in model.failures {
+            warn!("Collection {} {}", name, failure);
+            model.values.insert(name, Json::Array(Vec::new()));
+        }
+        model.values
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Admin API
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// The `/admin/api/collections` resources of the site whose `collections`
+/// they manage, for the root of the router.
+pub fn services(collections: web::Data<Collections>) -> impl HttpServiceFactory {
+    (
+        web::resource("/admin/api/collections")
+            .app_data(collections.clone())
+            .route(web::get().to(list_handler)),
+        web::resource("/admin/api/collections/{name}")
+            .app_data(collections)
+            .route(web::put().to(put_handler))
+            .route(web::delete().to(delete_handler)),
+    )
+}
+
+/// Replace the registry after the stored definitions changed, and drop
+/// every cached page of the site serving `req`.
+async fn changed(collections: web::Data<Collections>, req: &HttpRequest) {
+    let _ = web::block(move || collections.rebuild()).await;
+    cache::invalidate(SiteContent::of(req).cache());
+}
+
+#[tracing::instrument(skip_all)]
+async fn list_handler(
+    _auth: RequireRole<Viewer>,
+    collections: web::Data<Collections>,
+) -> HttpResponse {
+    let stored = {
+        let collections = collections.clone();
+        match blocking(move || collections.store.list()).await {
+            Ok(stored) => stored,
+            Err(e) => return failed(e),
+        }
+    };
+    let themes = collections.theme_defaults();
+    let items: Vec<Json> = collections
+        .registry()
+        .iter()
+        .map(|query| {
+            let origin = match stored.iter().find(|c| c.name == query.name) {
+                Some(c) => json!({ "admin": c.updated_by, "at": c.updated_at }),
+                None => json!({ "theme": themes.get(&query.name).map(|(id, _)| id) }),
+            };
+            json!({ "name": query.name, "origin": origin, "definition": query.source })
+        })
+        .collect();
+    HttpResponse::Ok().json(json!({ "items": items }))
+}
+
+#[tracing::instrument(skip_all)]
+async fn put_handler(
+    auth: RequireRole<Editor>,
+    collections: web::Data<Collections>,
+    req: HttpRequest,
+    name: web::Path<String>,
+    definition: web::Json<Json>,
+) -> HttpResponse {
+    let definition = definition.into_inner();
+    let query = match SavedQuery::parse(&name, &definition) {
+        Ok(query) => query,
+        Err(e) => return HttpResponse::BadRequest().json(json!({ "error": e.to_string() })),
+    };
+    let before = collections
+        .registry()
+        .get(&name)
+        .map(|q| q.source.clone())
+        .unwrap_or(Json::Null);
+    let collection = StoredCollection {
+        name: query.name.clone(),
+        definition,
+        updated_at: Utc::now(),
+        updated_by: auth.user.username.clone(),
+    };
+    let saved = {
+        let (collections, collection) = (collections.clone(), collection.clone());
+        blocking(move || collections.store.save(&collection)).await
+    };
+    if let Err(e) = saved {
+        return failed(e);
+    }
+    audit::annotate(
+        &req,
+        AuditNote::new("collection.update", format!("collection:{}", query.name))
+            .with_change(&before, &query.source),
+    );
+    changed(collections, &req).await;
+    info!("{} saved collection {}", auth.user.username, query.name);
+
+    HttpResponse::Ok().json(collection)
+}
+
+#[tracing::instrument(skip_all)]
+async fn delete_handler(
+    auth: RequireRole<Editor>,
+    collections: web::Data<Collections>,
+    req: HttpRequest,
+    name: web::Path<String>,
+) -> HttpResponse {
+    let name = name.into_inner();
+    let before = collections
+        .registry()
+        .get(&name)
+        .map(|q| q.source.clone())
+        .unwrap_or(Json::Null);
+    let deleted = {
+        let (collections, name) = (collections.clone(), name.clone());
+        blocking(move || collections.store.delete(&name)).await
+    };
+    match deleted {
+        Ok(true) => {}
+        Ok(false) => {
+            return HttpResponse::NotFound()
+                .json(json!({ "error": format!("no collection {name} in the admin") }))
+        }
+        Err(e) => return failed(e),
+    }
+    audit::annotate(
+        &req,
+        AuditNote::new("collection.delete", format!("collection:{name}"))
+            .with_change(&before, &Json::Null),
+    );
+    changed(collections, &req).await;
+    info!("{} deleted collection {}", auth.user.username, name);
+
+    HttpResponse::NoContent().finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{self, SESSION_COOKIE};
+    use crate::db::users::{Role, ValidatedPassword};
+    use actix_web::cookie::Cookie;
+    use actix_web::http::StatusCode;
+    use actix_web::{middleware::from_fn, test as actix_test, App};
+    use adapt::runtime::theme::ThemeSpec;
+
+    #[actix_web::test]
+    async fn admin_definitions_replace_theme_defaults_until_deleted() {
+        let site = tempfile::tempdir().unwrap();
+        let theme = DiscoveredTheme {
+            mount_path: "/".into(),
+            dir: site.path().join("themes/plain"),
+            assets_dir: None,
+            spec: ThemeSpec::new("plain", "Plain", "/", ""),
+            engine: None,
+            collections: BTreeMap::from([
+                (
+                    "featured".to_owned(),
+                    json!({ "filter": { "tax.tags": "featured" } }),
+                ),
+                ("broken".to_owned(), json!({ "sort": { "a": 7 } })),
+            ]),
+        };
+        let collections = web::Data::new(Collections::open(site.path()));
+        collections.set_themes(&[theme]);
+        assert!(collections.registry().get("broken").is_none());
+
+        let users = auth::store(site.path());
+        let password = ValidatedPassword::new("a long enough secret").unwrap();
+        let mut cookies = Vec::new();
+        for (name, role) in [("ed", Role::Editor), ("vi", Role::Viewer)] {
+            let user = users.create(name, role, &password).unwrap();
+            let token = users
+                .issue_session(&user, chrono::Duration::hours(1))
+                .unwrap();
+            cookies.push(Cookie::new(SESSION_COOKIE, token));
+        }
+        let (editor, viewer) = (cookies[0].clone(), cookies[1].clone());
+        let app = actix_test::init_service(
+            App::new().service(
+                web::scope("")
+                    .app_data(users)
+                    .app_data(audit::log(site.path()))
+                    .service(services(collections.clone()))
+                    .wrap(from_fn(audit::record)),
+            ),
+        )
+        .await;
+
+        let put = |cookie: &Cookie<'static>, body: Json| {
+            actix_test::TestRequest::put()
+                .uri("/admin/api/collections/featured")
+                .cookie(cookie.clone())
+                .set_json(body)
+                .to_request()
+        };
+        let mine = json!({ "filter": { "type": "post" }, "limit": 3 });
+        let res = actix_test::call_service(&app, put(&viewer, mine.clone())).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        let res =
+            actix_test::call_service(&app, put(&editor, json!({ "limit": "x", "sort": 1 }))).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let res = actix_test::call_service(&app, put(&editor, mine.clone())).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            collections
+                .registry()
+                .get("featured")
+                .unwrap()
+                .options
+                .limit,
+            Some(3)
+        );
+
+        let req = actix_test::TestRequest::get()
+            .uri("/admin/api/collections")
+            .cookie(viewer.clone())
+            .to_request();
+        let list: Json = actix_test::call_and_read_body_json(&app, req).await;
+        assert_eq!(list["items"][0]["name"], "featured");
+        assert_eq!(list["items"][0]["origin"]["admin"], "ed");
+        assert_eq!(list["items"][0]["definition"], mine);
+
+        let delete = || {
+            actix_test::TestRequest::delete()
+                .uri("/admin/api/collections/featured")
+                .cookie(editor.clone())
+                .to_request()
+        };
+        let res = actix_test::call_service(&app, delete()).await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        let res = actix_test::call_service(&app, delete()).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        let featured = collections.registry().get("featured").unwrap();
+        assert_eq!(featured.options.limit, None);
+        assert_eq!(featured.source["filter"]["tax.tags"], "featured");
+
+        let trail =
+            crate::db::audit::AuditLog::new(site.path().join(crate::db::audit::AUDIT_LOG_FILE))
+                .entries()
+                .unwrap();
+        let actions: Vec<_> = trail.iter().map(|e| e.action.as_str()).collect();
+        assert!(actions.contains(&"collection.update"), "{actions:?}");
+        assert!(actions.contains(&"collection.delete"), "{actions:?}");
+    }
+}
@@ -0,0 +1,619 @@
+// crates/edge/src/comments.rs
+
+// First-party reader comments. With a `[comments]` table in the settings,
+// readers submit through a public endpoint, as JSON or a plain HTML form:
+//
+//   [comments]
+//   per_minute = 3       # per client address
+//   max_chars = 5000
+//   notify = ["editor@example.com"]
+//
+//   POST /comments   { post: "/blog/hello", parent?, author, body, website }
+//                    → 202 { id, status: "pending" }; a form post is sent
+//                      back to the post with 303 instead
+//   GET  /comments?post=/blog/hello   → the approved threads of that page
+//
+// `website` is a honeypot: a form hides it from people, so a submission
+// that fills it in is a bot's. It gets the usual answer and is dropped.
+// A client over its rate gets 429 with `Retry-After`.
+//
+// Every comment starts out pending, and with `notify` each one queues an
+// email in the outbox (see crate::mail). Editors moderate in the admin:
+//
+//   GET    /admin/api/comments?status=pending&post=/blog/hello   { items }
+//   PUT    /admin/api/comments/{id}   { status: "approved" | "spam" | "pending" }
+//   DELETE /admin/api/comments/{id}   → 204
+//
+// Only approved comments reach themes, threaded (serve::comments) through
+// the `comments` template helper. They are held in memory, page by page,
+// and replaced whole after each moderation, which also drops the response
+// cache so pages show the change on their next render.
+
+use crate::audit::{self, AuditNote};
+use crate::auth::{Editor, RequireRole, Viewer};
+use crate::cache;
+use crate::cli::OUTBOX_DIR;
+use crate::db::comments::{
+    Comment, CommentStatus, CommentStore, CommentStoreError, COMMENTS_DB_DIR,
+};
+use crate::db::outbox::{Delivery, Outbox};
+use crate::db::store::{blocking, failed};
+use crate::fs::index::SiteContent;
+use crate::proxy::EdgeError;
+use crate::quota::{self, ClientLimits};
+use actix_web::{
+    dev::HttpServiceFactory,
+    http::header::{CONTENT_TYPE, LOCATION, RETRY_AFTER},
+    web, HttpRequest, HttpResponse,
+};
+use domain::setting::CommentSettings;
+use serde::Deserialize;
+use serde_json::{json, Map as JsonMap, Value as Json};
+use serve::comments::{page_key, threads};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use tracing::{debug, error, info};
+use uuid::Uuid;
+
+/// Longest author name, in characters.
+pub const MAX_AUTHOR_CHARS: usize = 100;
+
+/// Longest page path a comment may name.
+const MAX_POST_CHARS: usize = 1024;
+
+/// A site's comments: its store, the approved threads every render uses,
+/// and, with a `[comments]` table, what submissions need.
+pub struct Comments {
+    store: CommentStore,
+    settings: Option<CommentSettings>,
+    limits: Option<ClientLimits>,
+    /// Where new comments are announced, when `notify` names anyone.
+    notify: Option<Outbox>,
+    /// Approved threads by page (serve::comments::page_key).
+    approved: RwLock<Arc<BTreeMap<String, Json>>>,
+}
+
+/// The site's comments.
+pub fn store(root_dir: &Path) -> CommentStore {
+    CommentStore::new(root_dir.join(COMMENTS_DB_DIR))
+}
+
+impl Comments {
+    /// Accept submissions under `settings`, if any, and load the approved
+    /// comments stored under `root_dir`.
+    pub fn open(root_dir: &Path, settings: Option<CommentSettings>) -> Result<Self, EdgeError> {
+        let notify = match &settings {
+            Some(settings) if !settings.notify.is_empty() => {
+                Some(Outbox::open(root_dir.join(OUTBOX_DIR))?)
+            }
+            _ => None,
+        };
+        let comments = Self {
+            store: store(root_dir),
+            limits: settings
+                .as_ref()
+                .map(|s| ClientLimits::per_minute(s.per_minute)),
+            settings,
+            notify,
+            approved: Default::default(),
+        };
+        comments.rebuild();
+        Ok(comments)
+    }
+
+    /// Re-read the approved comments from the store and use them from the
+    /// next render on.
+    pub fn rebuild(&self) {
+        let comments = match self.store.list() {
+            Ok(comments) => comments,
+            Err(e) => {
+                error!("Reading the comments failed: {}", e);
+                return;
+            }
+        };
+        let mut by_page: BTreeMap<String, Vec<Json>> = BTreeMap::new();
+        for comment in comments
+            .iter()
+            .filter(|c| c.status == CommentStatus::Approved)
+        {
+            by_page
+                .entry(page_key(&comment.post))
+                .or_default()
+                .push(public_view(comment));
+        }
+        let approved: BTreeMap<String, Json> = by_page
+            .into_iter()
+            .map(|(page, comments)| (page, threads(comments)))
+            .collect();
+        match self.approved.write() {
+            Ok(mut current) => *current = Arc::new(approved),
+            Err(e) => *e.into_inner() = Arc::new(approved),
+        }
+    }
+
+    /// The approved threads every render uses.
+    pub fn approved(&self) -> Arc<BTreeMap<String, Json>> {
+        self.approved
+            .read()
+            .map(|a| a.clone())
+            .unwrap_or_else(|e| e.into_inner().clone())
+    }
+}
+
+/// The email telling `to` that `comment` awaits moderation.
+fn notification(to: &[String], comment: &Comment) -> Delivery {
+    Delivery::Email {
+        to: to.to_vec(),
+        subject: format!("New comment on {} awaits moderation", comment.post),
+        body: format!(
+            "{} wrote on {}:\n\n{}\n\nApprove or reject it in the admin (comment {}).\n",
+            comment.author, comment.post, comment.body, comment.id
+        ),
+    }
+}
+
+/// What readers see of a comment.
+fn public_view(comment: &Comment) -> Json {
+    json!({
+        "id": comment.id,
+        "parent": comment.parent,
+        "author": comment.author,
+        "body": comment.body,
+        "created_at": comment.created_at,
+    })
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// HTTP
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// The public `/comments` and the `/admin/api/comments` resources, for the
+/// root of the router.
+pub fn services(comments: web::Data<Comments>) -> impl HttpServiceFactory {
+    (
+        web::resource("/comments")
+            .app_data(comments.clone())
+            .route(web::post().to(submit_handler))
+            .route(web::get().to(threads_handler)),
+        web::resource("/admin/api/comments")
+            .app_data(comments.clone())
+            .route(web::get().to(list_handler)),
+        web::resource("/admin/api/comments/{id}")
+            .app_data(comments)
+            .route(web::put().to(moderate_handler))
+            .route(web::delete().to(delete_handler)),
+    )
+}
+
+fn bad_request(message: impl Into<String>) -> HttpResponse {
+    HttpResponse::BadRequest().json(json!({ "error": message.into() }))
+}
+
+/// Reload the approved comments after the store changed, and drop every
+/// cached page.
+async fn changed(comments: web::Data<Comments>, req: &HttpRequest) {
+    let _ = web::block(move || comments.rebuild()).await;
+    cache::invalidate(SiteContent::of(req).cache());
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Submission {
+    post: String,
+    parent: Option<String>,
+    author: String,
+    body: String,
+    /// The honeypot.
+    website: String,
+}
+
+impl Submission {
+    /// From a JSON body, or a form body otherwise.
+    fn parse(req: &HttpRequest, body: &[u8]) -> Result<(Self, bool), String> {
+        let is_json = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with("application/json"));
+        let fields = if is_json {
+            serde_json::from_slice(body).map_err(|e| format!("bad comment JSON: {e}"))?
+        } else {
+            let map: JsonMap<String, Json> = form_urlencoded::parse(body)
+                .filter(|(_, v)| !v.is_empty())
+                .map(|(k, v)| (k.into_owned(), Json::String(v.into_owned())))
+                .collect();
+            Json::Object(map)
+        };
+        let submission = serde_json::from_value(fields).map_err(|e| e.to_string())?;
+        Ok((submission, !is_json))
+    }
+
+    /// Trim the fields and check them against `settings`.
+    fn validate(mut self, settings: &CommentSettings) -> Result<Self, String> {
+        self.author = self.author.trim().to_owned();
+        self.body = self.body.trim().to_owned();
+        self.parent = self.parent.filter(|p| !p.trim().is_empty());
+        if !self.post.starts_with('/')
+            || self.post.chars().count() > MAX_POST_CHARS
+            || self.post.split('/').any(|s| s == "..")
+        {
+            return Err("post must be the path of a page".into());
+        }
+        if self.author.is_empty() || self.author.chars().count() > MAX_AUTHOR_CHARS {
+            return Err(format!("author must be 1 to {MAX_AUTHOR_CHARS} characters"));
+        }
+        if self.body.is_empty() || self.body.chars().count() > settings.max_chars {
+            return Err(format!(
+                "body must be 1 to {} characters",
+                settings.max_chars
+            ));
+        }
+        Ok(self)
+    }
+}
+
+/// What a submission gets back, stored or not.
+fn accepted(id: &str, post: &str, from_form: bool) -> HttpResponse {
+    if from_form {
+        HttpResponse::SeeOther()
+            .insert_header((LOCATION, post.to_owned()))
+            .finish()
+    } else {
+        HttpResponse::Accepted().json(json!({ "id": id, "status": CommentStatus::Pending }))
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn submit_handler(
+    comments: web::Data<Comments>,
+    req: HttpRequest,
+    body: web::Bytes,
+) -> HttpResponse {
+    let (Some(settings), Some(limits)) = (&comments.settings, &comments.limits) else {
+        return HttpResponse::NotFound().finish();
+    };
+    let client = quota::client_addr(&req).unwrap_or_else(|| "unknown".to_owned());
+    if let Err(wait) = limits.take(&client) {
+        return HttpResponse::TooManyRequests()
+            .insert_header((RETRY_AFTER, wait))
+            .json(json!({ "error": "too many comments; try again later" }));
+    }
+
+    let (submission, from_form) = match Submission::parse(&req, &body) {
+        Ok(parsed) => parsed,
+        Err(e) => return bad_request(e),
+    };
+    if !submission.website.is_empty() {
+        debug!("Dropped a comment from {} that filled the honeypot", client);
+        return accepted(&Uuid::new_v4().to_string(), &submission.post, from_form);
+    }
+    let submission = match submission.validate(settings) {
+        Ok(submission) => submission,
+        Err(e) => return bad_request(e),
+    };
+
+    // Replies go under an approved comment on the same page.
+    if let Some(parent) = submission.parent.clone() {
+        let found = {
+            let comments = comments.clone();
+            blocking(move || comments.store.get(&parent)).await
+        };
+        match found {
+            Ok(Some(p))
+                if p.status == CommentStatus::Approved
+                    && page_key(&p.post) == page_key(&submission.post) => {}
+            Ok(_) => return bad_request("parent is not a comment on this post"),
+            Err(e) => return failed(e),
+        }
+    }
+
+    let comment = Comment::pending(
+        page_key(&submission.post),
+        submission.parent,
+        submission.author,
+        submission.body,
+    );
+    let saved = {
+        let (comments, comment) = (comments.clone(), comment.clone());
+        let delivery =
+            (!settings.notify.is_empty()).then(|| notification(&settings.notify, &comment));
+        blocking(move || -> Result<_, CommentStoreError> {
+            let staged = match (&comments.notify, delivery) {
+                (Some(outbox), Some(delivery)) => Some(
+                    outbox
+                        .stage(delivery)
+                        .map_err(|e| CommentStoreError::Io(io::Error::other(e)))?,
+                ),
+                _ => None,
+            };
+            comments.store.save(&comment)?;
+            if let Some(staged) = staged {
+                staged
+                    .commit()
+                    .map_err(|e| CommentStoreError::Io(io::Error::other(e)))?;
+            }
+            Ok(())
+        })
+        .await
+    };
+    if let Err(e) = saved {
+        return failed(e);
+    }
+    info!(
+        "Comment {} on {} awaits moderation",
+        comment.id, comment.post
+    );
+    accepted(&comment.id, &submission.post, from_form)
+}
+
+#[derive(Debug, Deserialize)]
+struct ThreadsQuery {
+    post: String,
+}
+
+#[tracing::instrument(skip_all)]
+async fn threads_handler(
+    comments: web::Data<Comments>,
+    query: web::Query<ThreadsQuery>,
+) -> HttpResponse {
+    let threads = comments
+        .approved()
+        .get(&page_key(&query.post))
+        .cloned()
+        .unwrap_or_else(|| Json::Array(Vec::new()));
+    HttpResponse::Ok().json(json!({ "items": threads }))
+}
+
+#[derive(Debug, Deserialize)]
+struct ListQuery {
+    status: Option<String>,
+    post: Option<String>,
+}
+
+#[tracing::instrument(skip_all)]
+async fn list_handler(
+    _auth: RequireRole<Viewer>,
+    comments: web::Data<Comments>,
+    query: web::Query<ListQuery>,
+) -> HttpResponse {
+    let status = match query.status.as_deref().map(str::parse::<CommentStatus>) {
+        None => None,
+        Some(Ok(status)) => Some(status),
+        Some(Err(e)) => return bad_request(e.to_string()),
+    };
+    let post = query.post.as_deref().map(page_key);
+    let comments = match blocking(move || comments.store.list()).await {
+        Ok(comments) => comments,
+        Err(e) => return failed(e),
+    };
+    let items: Vec<Comment> = comments
+        .into_iter()
+        .filter(|c| status.is_none_or(|s| c.status == s))
+        .filter(|c| post.as_deref().is_none_or(|p| page_key(&c.post) == p))
+        .collect();
+    HttpResponse::Ok().json(json!({ "items": items }))
+}
+
+#[derive(Debug, Deserialize)]
+struct Moderation {
+    status: String,
+}
+
+#[tracing::instrument(skip_all)]
+async fn moderate_handler(
+    auth: RequireRole<Editor>,
+    comments: web::Data<Comments>,
+    req: HttpRequest,
+    id: web::Path<String>,
+    moderation: web::Json<Moderation>,
+) -> HttpResponse {
+    let status = match moderation.status.parse::<CommentStatus>() {
+        Ok(status) => status,
+        Err(e) => return bad_request(e.to_string()),
+    };
+    let id = id.into_inner();
+    let result = {
+        let (comments, id, who) = (comments.clone(), id.clone(), auth.user.username.clone());
+        blocking(move || -> Result<_, CommentStoreError> {
+            let before = comments.store.get(&id)?;
+            let after = comments.store.moderate(&id, status, &who)?;
+            Ok(before.zip(after))
+        })
+        .await
+    };
+    let (before, after) = match result {
+        Ok(Some(change)) => change,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(json!({ "error": format!("no comment {id}") }))
+        }
+        Err(e) => return failed(e),
+    };
+    audit::annotate(
+        &req,
+        AuditNote::new("comment.moderate", format!("comment:{id}"))
+            .with_change(&json!(before), &json!(after)),
+    );
+    changed(comments, &req).await;
+    info!("{} marked comment {} {}", auth.user.username, id, status);
+
+    HttpResponse::Ok().json(after)
+}
+
+#[tracing::instrument(skip_all)]
+async fn delete_handler(
+    auth: RequireRole<Editor>,
+    comments: web::Data<Comments>,
+    req: HttpRequest,
+    id: web::Path<String>,
+) -> HttpResponse {
+    let id = id.into_inner();
+    let result = {
+        let (comments, id) = (comments.clone(), id.clone());
+        blocking(move || -> Result<_, CommentStoreError> {
+            let before = comments.store.get(&id)?;
+            comments.store.delete(&id)?;
+            Ok(before)
+        })
+        .await
+    };
+    let before = match result {
+        Ok(Some(before)) => before,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(json!({ "error": format!("no comment {id}") }))
+        }
+        Err(e) => return failed(e),
+    };
+    audit::annotate(
+        &req,
+        AuditNote::new("comment.delete", format!("comment:{id}"))
+            .with_change(&json!(before), &Json::Null),
+    );
+    changed(comments, &req).await;
+    info!("{} deleted comment {}", auth.user.username, id);
+
+    HttpResponse::NoContent().finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{self, SESSION_COOKIE};
+    use crate::db::outbox::OutboxState;
+    use crate::db::users::{Role, ValidatedPassword};
+    use actix_web::cookie::Cookie;
+    use actix_web::http::StatusCode;
+    use actix_web::{middleware::from_fn, test as actix_test, App};
+
+    #[actix_web::test]
+    async fn submitted_comments_show_once_approved() {
+        let site = tempfile::tempdir().unwrap();
+        let comments = web::Data::new(
+            Comments::open(
+                site.path(),
+                Some(CommentSettings {
+                    per_minute: 3,
+                    max_chars: 20,
+                    notify: vec!["editor@example.com".into()],
+                }),
+            )
+            .unwrap(),
+        );
+
+        let users = auth::store(site.path());
+        let password = ValidatedPassword::new("a long enough secret").unwrap();
+        let mut cookies = Vec::new();
+        for (name, role) in [("ed", Role::Editor), ("vi", Role::Viewer)] {
+            let user = users.create(name, role, &password).unwrap();
+            let token = users
+                .issue_session(&user, chrono::Duration::hours(1))
+                .unwrap();
+            cookies.push(Cookie::new(SESSION_COOKIE, token));
+        }
+        let (editor, viewer) = (cookies[0].clone(), cookies[1].clone());
+        let app = actix_test::init_service(
+            App::new().service(
+                web::scope("")
+                    .app_data(users)
+                    .app_data(audit::log(site.path()))
+                    .service(services(comments.clone()))
+                    .wrap(from_fn(audit::record)),
+            ),
+        )
+        .await;
+
+        let submit = |body: Json| {
+            actix_test::TestRequest::post()
+                .uri("/comments")
+                .peer_addr("10.0.0.1:4000".parse().unwrap())
+                .set_json(body)
+                .to_request()
+        };
+        let res = actix_test::call_service(
+            &app,
+            submit(json!({ "post": "/blog/hello/", "author": "Ada", "body": "x".repeat(21) })),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let res = actix_test::call_service(
+            &app,
+            submit(json!({ "post": "/blog/hello", "author": "Bot", "body": "buy", "website": "spam.example" })),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::ACCEPTED);
+        let pending: Json = actix_test::call_and_read_body_json(
+            &app,
+            submit(json!({ "post": "/blog/hello/", "author": "Ada", "body": "Nice post." })),
+        )
+        .await;
+        assert_eq!(pending["status"], "pending");
+        let res = actix_test::call_service(
+            &app,
+            submit(json!({ "post": "/blog/hello", "author": "Ada", "body": "Again." })),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(res.headers().contains_key(RETRY_AFTER));
+
+        // Only the real submission was stored, and none is shown yet.
+        let queue = |status: &str| {
+            actix_test::TestRequest::get()
+                .uri(&format!("/admin/api/comments?status={status}"))
+                .cookie(viewer.clone())
+                .to_request()
+        };
+        let list: Json = actix_test::call_and_read_body_json(&app, queue("pending")).await;
+        assert_eq!(list["items"].as_array().unwrap().len(), 1);
+        assert_eq!(list["items"][0]["post"], "/blog/hello");
+        assert!(comments.approved().is_empty());
+        let queued = Outbox::open(site.path().join(OUTBOX_DIR))
+            .unwrap()
+            .list(OutboxState::Pending)
+            .unwrap();
+        assert_eq!(queued.len(), 1);
+
+        let id = pending["id"].as_str().unwrap();
+        let moderate = |cookie: &Cookie<'static>, status: &str| {
+            actix_test::TestRequest::put()
+                .uri(&format!("/admin/api/comments/{id}"))
+                .cookie(cookie.clone())
+                .set_json(json!({ "status": status }))
+                .to_request()
+        };
+        let res = actix_test::call_service(&app, moderate(&viewer, "approved")).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        let res = actix_test::call_service(&app, moderate(&editor, "deleted")).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let res = actix_test::call_service(&app, moderate(&editor, "approved")).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(comments.approved()["/blog/hello"][0]["body"], "Nice post.");
+
+        let req = actix_test::TestRequest::get()
+            .uri("/comments?post=/blog/hello/")
+            .to_request();
+        let threads: Json = actix_test::call_and_read_body_json(&app, req).await;
+        assert_eq!(threads["items"][0]["author"], "Ada");
+        assert!(threads["items"][0].get("moderated_by").is_none());
+
+        let delete = || {
+            actix_test::TestRequest::delete()
+                .uri(&format!("/admin/api/comments/{id}"))
+                .cookie(editor.clone())
+                .to_request()
+        };
+        let res = actix_test::call_service(&app, delete()).await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        let res = actix_test::call_service(&app, delete()).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert!(comments.approved().is_empty());
+
+        let trail =
+            crate::db::audit::AuditLog::new(site.path().join(crate::db::audit::AUDIT_LOG_FILE))
+                .entries()
+                .unwrap();
+        let actions: Vec<_> = trail.iter().map(|e| e.action.as_str()).collect();
+        assert!(actions.contains(&"comment.moderate"), "{actions:?}");
+        assert!(actions.contains(&"comment.delete"), "{actions:?}");
+    }
+}
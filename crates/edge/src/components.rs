@@ -0,0 +1,431 @@
+// crates/edge/src/components.rs
+
+// Registry of what a site runs: the core binary, every installed plugin
+// (enabled or not) and every theme, with the version and license their
+// manifests declare. Served to admins at `GET /admin/components` and printed
+// by `whispercms components list`.
+//
+//   # plugin.toml / theme.toml
+//   version = "1.2.0"
+//   license = "MIT"
+//
+//   # settings.toml
+//   [components]
+//   updates_file = "./component-updates.toml"
+//   license = "Apache-2.0"
+//   allowed_licenses = ["Apache-2.0", "MIT", "BSD-3-Clause"]
+//
+//   # component-updates.toml: the latest release of each component
+//   core = "0.2.0"
+//   [plugins]
+//   seo = "1.3.0"
+//   [themes]
+//   demo-theme = "2.0.1"
+//
+// Nothing is fetched; whoever distributes the site's components keeps the
+// updates file current.
+
+use crate::auth::{Admin, RequireRole};
+use crate::fs::ext::plugin_enabled;
+use actix_web::{dev::HttpServiceFactory, web, HttpResponse};
+use domain::setting::ComponentSettings;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use tracing::error;
+
+/// Id the core binary is listed under, and its key in the updates file.
+pub const CORE_ID: &str = "whispercms";
+
+/// The `/admin/components` resource, for the root of the router, listing
+/// the extensions in `ext_dir`.
+pub fn services(
+    settings: &ComponentSettings,
+    root_dir: &Path,
+    ext_dir: PathBuf,
+) -> impl HttpServiceFactory {
+    let registry = ComponentRegistry::new(root_dir, ext_dir, settings);
+    web::resource("/admin/components")
+        .app_data(web::Data::new(registry))
+        .route(web::get().to(list_handler))
+}
+
+#[derive(Debug, Error)]
+pub enum ComponentError {
+    #[error("I/O: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("{0}: {1}")]
+    Toml(PathBuf, toml::de::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ComponentKind {
+    Core,
+    Plugin,
+    Theme,
+}
+
+impl fmt::Display for ComponentKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ComponentKind::Core => "core",
+            ComponentKind::Plugin => "plugin",
+            ComponentKind::Theme => "theme",
+        })
+    }
+}
+
+/// One installed component.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Component {
+    pub kind: ComponentKind,
+    pub id: String,
+    pub name: String,
+    pub version: Option<String>,
+    /// SPDX expression from the manifest.
+    pub license: Option<String>,
+    pub enabled: bool,
+    /// From the updates file.
+    pub latest: Option<String>,
+    pub update_available: bool,
+    pub warnings: Vec<String>,
+}
+
+/// The fields of plugin.toml and theme.toml the registry reads.
+#[derive(Debug, Deserialize)]
+struct Manifest {
+    id: Option<String>,
+    name: Option<String>,
+    version: Option<String>,
+    license: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Updates {
+    core: Option<String>,
+    #[serde(default)]
+    plugins: BTreeMap<String, String>,
+    #[serde(default)]
+    themes: BTreeMap<String, String>,
+}
+
+/// Lists a site's components on demand, so installs made while the server
+/// runs show up.
+#[derive(Debug, Clone)]
+pub struct ComponentRegistry {
+    ext_dir: PathBuf,
+    updates_file: Option<PathBuf>,
+    site_license: Option<String>,
+    allowed_licenses: Vec<String>,
+}
+
+impl ComponentRegistry {
+    pub fn new(site_dir: &Path, ext_dir: PathBuf, settings: &ComponentSettings) -> Self {
+        Self {
+            ext_dir,
+            updates_file: settings.updates_file.as_ref().map(|f| site_dir.join(f)),
+            site_license: settings.license.clone(),
+            allowed_licenses: settings.allowed_licenses.clone(),
+        }
+    }
+
+    /// Core first, then plugins and themes by id.
+    pub fn list(&self) -> Result<Vec<Component>, ComponentError> {
+        let updates = match &self.updates_file {
+            Some(path) => read_toml::<Updates>(path)?.unwrap_or_default(),
+            None => Updates::default(),
+        };
+
+        let mut out = vec![self.component(
+            ComponentKind::Core,
+            Manifest {
+                id: Some(CORE_ID.into()),
+                name: Some("WhisperCMS".into()),
+                version: Some(env!("CARGO_PKG_VERSION").into()),
+                license: option_env!("CARGO_PKG_LICENSE")
+                    .filter(|l| !l.is_empty())
+                    .map(str::to_owned),
+            },
+            CORE_ID,
+            true,
+            updates.core.clone(),
+        )];
+
+        for (kind, sub, file, latest) in [
+            (
+                ComponentKind::Plugin,
+                "plugins",
+                "plugin.toml",
+                &updates.plugins,
+            ),
+            (
+                ComponentKind::Theme,
+                "themes",
+                "theme.toml",
+                &updates.themes,
+            ),
+        ] {
+            let mut found = Vec::new();
+            for (dir, manifest) in self.manifests(sub, file)? {
+                let dir_name = dir
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                let id = manifest.id.clone().unwrap_or(dir_name);
+                let enabled = kind != ComponentKind::Plugin || plugin_enabled(&dir);
+                let latest = latest.get(&id).cloned();
+                found.push(self.component(kind, manifest, &id, enabled, latest));
+            }
+            found.sort_by(|a, b| a.id.cmp(&b.id));
+            out.extend(found);
+        }
+        Ok(out)
+    }
+
+    /// `(dir, manifest)` for each `<ext_dir>/<sub>/*/<file>`.
+    fn manifests(&self, sub: &str, file: &str) -> Result<Vec<(PathBuf, Manifest)>, ComponentError> {
+        let root = self.ext_dir.join(sub);
+        let entries = match fs::read_dir(&root) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut out = Vec::new();
+        for entry in entries {
+            let dir = entry?.path();
+            if let Some(manifest) = read_toml(&dir.join(file))? {
+                out.push((dir, manifest));
+            }
+        }
+        Ok(out)
+    }
+
+    fn component(
+        &self,
+        kind: ComponentKind,
+        manifest: Manifest,
+        id: &str,
+        enabled: bool,
+        latest: Option<String>,
+    ) -> Component {
+        let mut warnings = Vec::new();
+        let update_available = match (&manifest.version, &latest) {
+            (Some(current), Some(latest)) => {
+                match (Version::parse(current), Version::parse(latest)) {
+                    (Ok(current), Ok(latest)) => latest > current,
+                    (Err(_), _) => {
+                        warnings.push(format!("version {current:?} is not semver"));
+                        false
+                    }
+                    (_, Err(_)) => {
+                        warnings.push(format!("latest version {latest:?} is not semver"));
+                        false
+                    }
+                }
+            }
+            (None, _) => {
+                warnings.push("declares no version".into());
+                false
+            }
+            (Some(_), None) => false,
+        };
+        warnings.extend(license_warnings(
+            manifest.license.as_deref(),
+            self.site_license.as_deref(),
+            &self.allowed_licenses,
+        ));
+
+        Component {
+            kind,
+            id: id.to_owned(),
+            name: manifest.name.unwrap_or_else(|| id.to_owned()),
+            version: manifest.version,
+            license: manifest.license,
+            enabled,
+            latest,
+            update_available,
+            warnings,
+        }
+    }
+}
+
+/// `path` parsed as TOML, or `None` when it does not exist.
+fn read_toml<T: serde::de::DeserializeOwned>(path: &Path) -> Result<Option<T>, ComponentError> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    toml::from_str(&text)
+        .map(Some)
+        .map_err(|e| ComponentError::Toml(path.to_owned(), e))
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Licenses
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// The alternatives of an SPDX expression, each the ids that must all be
+/// accepted. Exceptions (`WITH ...`) are dropped; nesting is flattened.
+fn alternatives(expr: &str) -> Vec<Vec<String>> {
+    let flat = expr.replace(['(', ')'], " ");
+    flat.split(" OR ")
+        .map(|alt| {
+            alt.split(" AND ")
+                .filter_map(|id| id.split(" WITH ").next())
+                .map(|id| id.trim().to_owned())
+                .filter(|id| !id.is_empty())
+                .collect()
+        })
+        .filter(|ids: &Vec<String>| !ids.is_empty())
+        .collect()
+}
+
+/// Licenses that ask a combined work to be released under the same terms.
+fn is_copyleft(id: &str) -> bool {
+    id.starts_with("GPL-") || id.starts_with("AGPL-")
+}
+
+/// Why a component under `license` may not suit the site.
+fn license_warnings(
+    license: Option<&str>,
+    site_license: Option<&str>,
+    allowed: &[String],
+) -> Vec<String> {
+    let Some(license) = license.filter(|l| !l.trim().is_empty()) else {
+        return vec!["declares no license".into()];
+    };
+    let alts = alternatives(license);
+    let mut warnings = Vec::new();
+
+    if !allowed.is_empty()
+        && !alts
+            .iter()
+            .any(|ids| ids.iter().all(|id| allowed.contains(id)))
+    {
+        warnings.push(format!("license {license} is not in allowed_licenses"));
+    }
+
+    let site_copyleft = site_license.is_some_and(|site| {
+        alternatives(site)
+            .iter()
+            .flatten()
+            .any(|id| is_copyleft(id))
+    });
+    if let (Some(site), false) = (site_license, site_copyleft) {
+        if alts.iter().all(|ids| ids.iter().any(|id| is_copyleft(id))) {
+            warnings.push(format!(
+                "copyleft license {license} conflicts with the site's {site} license"
+            ));
+        }
+    }
+    warnings
+}
+
+#[tracing::instrument(skip_all)]
+async fn list_handler(
+    _auth: RequireRole<Admin>,
+    registry: web::Data<ComponentRegistry>,
+) -> HttpResponse {
+    let registry = registry.get_ref().clone();
+    match web::block(move || registry.list()).await {
+        Ok(Ok(items)) => HttpResponse::Ok().json(json!({ "items": items })),
+        Ok(Err(e)) => {
+            error!("Listing components failed: {}", e);
+            HttpResponse::InternalServerError().json(json!({ "error": e.to_string() }))
+        }
+        Err(e) => {
+            error!("Listing components was cancelled: {}", e);
+            HttpResponse::InternalServerError().json(json!({ "error": "listing failed" }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(path: &Path, text: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, text).unwrap();
+    }
+
+    #[test]
+    fn lists_core_plugins_and_themes_with_updates() {
+        let site = tempfile::tempdir().unwrap();
+        let ext = site.path().join("extensions");
+        write(
+            &ext.join("plugins/seo/plugin.toml"),
+            "version = \"1.2.0\"\nlicense = \"MIT\"\n",
+        );
+        write(&ext.join("plugins/seo/disabled"), "");
+        write(
+            &ext.join("themes/plain/theme.toml"),
+            "mount = \"/\"\nid = \"plain-theme\"\nversion = \"0.1.0\"\nlicense = \"GPL-3.0-only\"\n",
+        );
+        write(
+            &site.path().join("updates.toml"),
+            "[plugins]\nseo = \"1.3.0\"\n[themes]\nplain-theme = \"0.1.0\"\n",
+        );
+
+        let registry = ComponentRegistry::new(
+            site.path(),
+            ext,
+            &ComponentSettings {
+                updates_file: Some("updates.toml".into()),
+                license: Some("MIT".into()),
+                allowed_licenses: Vec::new(),
+            },
+        );
+        let list = registry.list().unwrap();
+        let ids: Vec<_> = list.iter().map(|c| (c.kind, c.id.as_str())).collect();
+        assert_eq!(
+            ids,
+            vec![
+                (ComponentKind::Core, CORE_ID),
+                (ComponentKind::Plugin, "seo"),
+                (ComponentKind::Theme, "plain-theme"),
+            ]
+        );
+
+        let seo = &list[1];
+        assert!(!seo.enabled);
+        assert!(seo.update_available);
+        assert_eq!(seo.latest.as_deref(), Some("1.3.0"));
+        assert!(seo.warnings.is_empty(), "{:?}", seo.warnings);
+
+        let theme = &list[2];
+        assert!(!theme.update_available);
+        assert_eq!(theme.warnings.len(), 1, "{:?}", theme.warnings);
+    }
+
+    #[test]
+    fn license_expressions_are_checked_per_alternative() {
+        let allowed = vec!["MIT".to_owned(), "Apache-2.0".to_owned()];
+        assert!(license_warnings(Some("MIT OR GPL-3.0-only"), Some("MIT"), &allowed).is_empty());
+        assert!(
+            license_warnings(Some("Apache-2.0 WITH LLVM-exception"), None, &allowed).is_empty()
+        );
+        assert_eq!(
+            license_warnings(Some("(MIT AND BSD-3-Clause)"), None, &allowed).len(),
+            1
+        );
+        assert_eq!(
+            license_warnings(Some("AGPL-3.0-or-later"), Some("Apache-2.0"), &[]).len(),
+            1
+        );
+        assert!(license_warnings(Some("GPL-2.0-only"), Some("GPL-3.0-or-later"), &[]).is_empty());
+        assert_eq!(
+            license_warnings(None, None, &[]),
+            vec!["declares no license"]
+        );
+    }
+}
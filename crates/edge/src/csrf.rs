@@ -0,0 +1,213 @@
+// crates/edge/src/csrf.rs
+
+// CSRF tokens for logged-in requests. The token is an HMAC-SHA256 over the
+// session token, keyed by a random secret kept in the users directory, so
+// it lasts as long as the login and no one without the session cookie can
+// work it out. With `csrf` in the middleware stack, as it is by default,
+// every POST, PUT, PATCH or DELETE that carries a session cookie must send
+// it back:
+//
+//   X-CSRF-Token: 4c1f…            (admin UI and other scripts)
+//   _csrf=4c1f…                    (a urlencoded form field)
+//
+//   GET /admin/csrf   → { token }  for the current session
+//
+// Themes put it into forms they render for logged-in visitors:
+//
+//   <input type="hidden" name="_csrf" value="{{csrf_token}}">
+//
+// `csrf_token` is empty for visitors without a session, and pages rendered
+// for a session are never cached, so no one is served another's token.
+// Requests without a session cookie cannot act as anyone and pass; logging
+// in and resetting a password work before a token can exist, so they pass
+// too.
+
+use crate::auth::{RequireRole, Viewer, SESSION_COOKIE};
+use crate::db::users::{random_key, secret_key, USERS_DB_DIR};
+use actix_web::{
+    body::{BoxBody, MessageBody},
+    dev::{HttpServiceFactory, Payload, ServiceRequest, ServiceResponse},
+    error::ErrorInternalServerError,
+    http::{header::CONTENT_TYPE, Method},
+    middleware::Next,
+    web, Error, HttpRequest, HttpResponse,
+};
+use domain::hex;
+use hmac::{Hmac, Mac};
+use serde_json::json;
+use sha2::Sha256;
+use std::io;
+use std::path::Path;
+use tracing::debug;
+
+/// Header scripts send the token in.
+pub const CSRF_HEADER: &str = "x-csrf-token";
+
+/// Form field HTML forms send the token in.
+pub const CSRF_FIELD: &str = "_csrf";
+
+const KEY_FILE: &str = "csrf.key";
+
+/// Routes used before a session, or its token, can exist.
+const EXEMPT: &[&str] = &[
+    "/admin/login",
+    "/admin/password-reset",
+    "/admin/password-reset/confirm",
+];
+
+/// A site's CSRF key; each site has its own, so a token from one site is
+/// worthless on another served by the same process.
+pub struct CsrfKey(Vec<u8>);
+
+impl CsrfKey {
+    /// Load the key of the site under `root_dir`, creating it on first
+    /// start.
+    pub fn open(root_dir: &Path) -> io::Result<Self> {
+        secret_key(&root_dir.join(USERS_DB_DIR).join(KEY_FILE)).map(Self)
+    }
+
+    /// A key that is never stored, for tests.
+    pub fn random() -> Self {
+        Self(random_key().expect("the CSPRNG is available"))
+    }
+
+    fn mac(&self, session_token: &str) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.0).expect("HMAC takes any key length");
+        mac.update(session_token.as_bytes());
+        mac
+    }
+
+    /// The token for the session `session_token` opened.
+    pub fn token(&self, session_token: &str) -> String {
+        hex::encode(self.mac(session_token).finalize().into_bytes())
+    }
+
+    /// Whether `token` is the one for `session_token`, compared in constant
+    /// time.
+    pub fn verify(&self, session_token: &str, token: &str) -> bool {
+        hex::decode(token).is_some_and(|sent| self.mac(session_token).verify_slice(&sent).is_ok())
+    }
+}
+
+/// The token for the session cookie `req` carries, if any, under the key
+/// of the site serving it.
+pub fn token_for(req: &HttpRequest) -> Option<String> {
+    let key = req.app_data::<web::Data<CsrfKey>>()?;
+    req.cookie(SESSION_COOKIE).map(|c| key.token(c.value()))
+}
+
+/// The `/admin/csrf` resource, for the root of the router.
+pub fn services() -> impl HttpServiceFactory {
+    web::resource("/admin/csrf").route(web::get().to(token_handler))
+}
+
+async fn token_handler(req: HttpRequest, _auth: RequireRole<Viewer>) -> HttpResponse {
+    HttpResponse::Ok().json(json!({ "token": token_for(&req) }))
+}
+
+/// `actix_web::middleware::from_fn` middleware refusing state-changing
+/// requests with a session cookie but without its token.
+pub async fn guard(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let unsafe_method = !matches!(
+        *req.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE
+    );
+    let session = req.cookie(SESSION_COOKIE);
+    let (true, Some(session), false) = (unsafe_method, session, EXEMPT.contains(&req.path()))
+    else {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    };
+    let key = req
+        .app_data::<web::Data<CsrfKey>>()
+        .ok_or_else(|| ErrorInternalServerError("no CSRF key for this site"))?
+        .clone();
+
+    let mut sent = req
+        .headers()
+        .get(CSRF_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_owned);
+    let is_form = req
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|ct| ct.starts_with("application/x-www-form-urlencoded"));
+    if sent.is_none() && is_form {
+        // Read the form for its field, then hand the handler the same bytes.
+        let body = req.extract::<web::Bytes>().await?;
+        sent = form_urlencoded::parse(&body)
+            .find(|(name, _)| name == CSRF_FIELD)
+            .map(|(_, value)| value.into_owned());
+        req.set_payload(Payload::from(body));
+    }
+
+    if sent.is_some_and(|sent| key.verify(session.value(), &sent)) {
+        Ok(next.call(req).await?.map_into_boxed_body())
+    } else {
+        debug!(
+            "Refused {} {} without its CSRF token",
+            req.method(),
+            req.path()
+        );
+        let res = HttpResponse::Forbidden()
+            .json(json!({ "error": "missing or invalid CSRF token; get one at /admin/csrf" }));
+        Ok(req.into_response(res))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{cookie::Cookie, http::StatusCode, middleware::from_fn, test, App};
+
+    #[actix_web::test]
+    async fn state_changes_with_a_session_need_its_token() {
+        let key = web::Data::new(CsrfKey::random());
+        let app = test::init_service(
+            App::new()
+                .app_data(key.clone())
+                .wrap(from_fn(guard))
+                .route(
+                    "/admin/thing",
+                    web::post().to(|body: web::Bytes| async move { HttpResponse::Ok().body(body) }),
+                )
+                .route("/admin/login", web::post().to(HttpResponse::Ok)),
+        )
+        .await;
+        let session = Cookie::new(SESSION_COOKIE, "s3ss10n");
+        let post = || test::TestRequest::post().uri("/admin/thing");
+        let status = |req: test::TestRequest| async {
+            test::call_service(&app, req.to_request()).await.status()
+        };
+
+        assert_eq!(status(post()).await, StatusCode::OK);
+        assert_eq!(
+            status(post().cookie(session.clone())).await,
+            StatusCode::FORBIDDEN
+        );
+        let forged = post()
+            .cookie(session.clone())
+            .insert_header((CSRF_HEADER, key.token("other")));
+        assert_eq!(status(forged).await, StatusCode::FORBIDDEN);
+        let scripted = post()
+            .cookie(session.clone())
+            .insert_header((CSRF_HEADER, key.token("s3ss10n")));
+        assert_eq!(status(scripted).await, StatusCode::OK);
+        let login = test::TestRequest::post()
+            .uri("/admin/login")
+            .cookie(session.clone());
+        assert_eq!(status(login).await, StatusCode::OK);
+
+        let form = format!("title=Hi&{CSRF_FIELD}={}", key.token("s3ss10n"));
+        let req = post()
+            .cookie(session)
+            .insert_header((CONTENT_TYPE, "application/x-www-form-urlencoded"))
+            .set_payload(form.clone())
+            .to_request();
+        let echoed = test::call_and_read_body(&app, req).await;
+        assert_eq!(echoed, form.as_bytes());
+    }
+}
@@ -0,0 +1,253 @@
+// crates/edge/src/db/audit.rs
+
+// Append-only record of mutating admin operations, one JSON object per
+// line:
+//
+//   {"at":"2026-01-02T03:04:05Z","actor":"ed","action":"content.update",
+//    "entity":"content:blog/a.md","status":200,
+//    "diff":{"body":{"before":"Hi\n","after":"Hello\n"}}}
+//
+// Lines are only ever appended, so `tail -f` and `grep` work on a live site;
+// `query` runs the same MQL the Content API accepts over them.
+
+use adapt::mql::{execute_query, Filter, FindOptions, IndexConfig, QueryError};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value as Json};
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use thiserror::Error;
+
+use super::mem::{InMemoryIndexBackend, InMemoryJsonStore};
+
+/// Audit log inside the site directory.
+pub const AUDIT_LOG_FILE: &str = "./audit.jsonl";
+
+const INDEXED_FIELDS: [&str; 2] = ["actor", "action"];
+
+#[derive(Debug, Error)]
+pub enum AuditError {
+    #[error("I/O: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("JSON: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Query: {0}")]
+    Query(#[from] QueryError),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub at: DateTime<Utc>,
+    /// Username, `token` for bearer-token scripts, or `anonymous`.
+    pub actor: String,
+    /// `<entity kind>.<verb>`, or the request method when the handler
+    /// did not name one.
+    pub action: String,
+    /// What was changed, e.g. `content:blog/a.md`, or the request path.
+    pub entity: String,
+    /// Response status of the request that made the change.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    /// Changed fields, as from [`diff`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diff: Option<Json>,
+}
+
+impl AuditEntry {
+    /// An entry stamped with the current time.
+    pub fn new(
+        actor: impl Into<String>,
+        action: impl Into<String>,
+        entity: impl Into<String>,
+    ) -> Self {
+        Self {
+            at: Utc::now(),
+            actor: actor.into(),
+            action: action.into(),
+            entity: entity.into(),
+            status: None,
+            diff: None,
+        }
+    }
+
+    pub fn with_status(mut self, status: u16) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    pub fn with_diff(mut self, diff: Option<Json>) -> Self {
+        self.diff = diff;
+        self
+    }
+}
+
+/// The leaves that differ between `before` and `after`, keyed by dotted
+/// path: `{"content.title": {"before": "A", "after": "B"}}`. A side that
+/// lacks the field shows `null`; `None` when nothing changed.
+pub fn diff(before: &Json, after: &Json) -> Option<Json> {
+    let mut out = Map::new();
+    diff_into(&mut out, String::new(), before, after);
+    (!out.is_empty()).then_some(Json::Object(out))
+}
+
+fn diff_into(out: &mut Map<String, Json>, prefix: String, before: &Json, after: &Json) {
+    match (before, after) {
+        (Json::Object(b), Json::Object(a)) => {
+            let mut keys: Vec<&String> = b.keys().chain(a.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let path = match prefix.as_str() {
+                    "" => key.clone(),
+                    p => format!("{p}.{key}"),
+                };
+                diff_into(
+                    out,
+                    path,
+                    b.get(key).unwrap_or(&Json::Null),
+                    a.get(key).unwrap_or(&Json::Null),
+                );
+            }
+        }
+        (b, a) if b != a => {
+            let key = if prefix.is_empty() { "." } else { &prefix };
+            out.insert(
+                key.to_owned(),
+                serde_json::json!({ "before": b, "after": a }),
+            );
+        }
+        _ => {}
+    }
+}
+
+#[derive(Debug)]
+pub struct AuditLog {
+    path: PathBuf,
+    /// Keeps concurrent writers from interleaving lines.
+    lock: Mutex<()>,
+}
+
+impl AuditLog {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Append `entry`.
+    pub fn record(&self, entry: &AuditEntry) -> Result<(), AuditError> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(&line)?;
+        Ok(())
+    }
+
+    /// Every entry, oldest first. Lines that do not parse are skipped.
+    pub fn entries(&self) -> Result<Vec<AuditEntry>, AuditError> {
+        let file = match fs::File::open(&self.path) {
+            Ok(f) => f,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut entries = Vec::new();
+        for line in BufReader::new(file).lines() {
+            if let Ok(entry) = serde_json::from_str(&line?) {
+                entries.push(entry);
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Entries matching `filter`, newest first unless `opts` sorts, with
+    /// the number of matches before `skip` and `limit` were applied.
+    pub async fn query(
+        &self,
+        filter: &Filter,
+        opts: &FindOptions,
+    ) -> Result<(usize, Vec<Json>), AuditError> {
+        let docs = self
+            .entries()?
+            .iter()
+            .rev()
+            .map(serde_json::to_value)
+            .collect::<Result<Vec<_>, _>>()?;
+        let store = InMemoryJsonStore::new(docs);
+        let config = IndexConfig::new(INDEXED_FIELDS);
+        let index = InMemoryIndexBackend::build(&config, &store).await;
+
+        let unpaged = FindOptions {
+            skip: None,
+            limit: None,
+            ..opts.clone()
+        };
+        let mut matches = execute_query(&config, &store, &index, filter, &unpaged).await?;
+        if opts.sort.is_empty() {
+            // Store ids follow the reversed log.
+            matches.sort_by_key(|r| r.id);
+        }
+        let total = matches.len();
+        let items = matches
+            .into_iter()
+            .skip(opts.skip.unwrap_or(0))
+            .take(opts.limit.unwrap_or(usize::MAX))
+            .map(|r| r.doc)
+            .collect();
+        Ok((total, items))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use adapt::mql::parser::parse_filter;
+    use serde_json::json;
+
+    #[test]
+    fn diff_lists_changed_leaves_by_path() {
+        let before = json!({ "content": { "title": "A", "tags": ["x"] }, "body": "Hi" });
+        let after = json!({ "content": { "title": "B", "tags": ["x"] }, "draft": true });
+        assert_eq!(
+            diff(&before, &after),
+            Some(json!({
+                "body": { "before": "Hi", "after": null },
+                "content.title": { "before": "A", "after": "B" },
+                "draft": { "before": null, "after": true },
+            }))
+        );
+        assert_eq!(diff(&before, &before), None);
+    }
+
+    #[actix_web::test]
+    async fn entries_are_queried_newest_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = AuditLog::new(dir.path().join("logs/audit.jsonl"));
+        assert!(log.entries().unwrap().is_empty());
+
+        log.record(&AuditEntry::new("ed", "content.create", "content:a.md"))
+            .unwrap();
+        log.record(&AuditEntry::new("amy", "content.delete", "content:a.md").with_status(204))
+            .unwrap();
+        log.record(&AuditEntry::new("ed", "content.update", "content:b.md"))
+            .unwrap();
+
+        let filter = parse_filter(&json!({ "actor": "ed" })).unwrap();
+        let (total, items) = log.query(&filter, &FindOptions::default()).await.unwrap();
+        assert_eq!(total, 2);
+        assert_eq!(items[0]["action"], "content.update");
+        assert_eq!(items[1]["action"], "content.create");
+    }
+}
@@ -0,0 +1,132 @@
+// crates/edge/src/db/collections.rs
+
+// Saved queries defined in the admin (see crate::collections), one JSON
+// file each:
+//
+//   <root>/<name>.json   {"name":"featured","definition":{"filter":{..},
+//                         "limit":5},"updated_at":"…","updated_by":"ada"}
+//
+// Names are checked by adapt::mql::SavedQuery before anything is stored, so
+// they are safe as file names.
+
+use crate::db::history::write_atomic;
+use crate::db::store::StoreError;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Saved queries inside the site directory.
+pub const COLLECTIONS_DB_DIR: &str = "./collections_db/";
+
+#[derive(Debug, Error)]
+pub enum CollectionStoreError {
+    #[error("I/O: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("JSON: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+impl StoreError for CollectionStoreError {
+    const STORE: &'static str = "collection";
+}
+
+/// One saved query as the admin last wrote it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredCollection {
+    pub name: String,
+    /// `{ filter, sort, limit, skip }`, as for the Content API.
+    pub definition: Json,
+    pub updated_at: DateTime<Utc>,
+    /// Username of the last editor.
+    pub updated_by: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct CollectionStore {
+    root: PathBuf,
+}
+
+impl CollectionStore {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.root.join(format!("{name}.json"))
+    }
+
+    /// Every stored collection, by name.
+    pub fn list(&self) -> Result<Vec<StoredCollection>, CollectionStoreError> {
+        let mut out = Vec::new();
+        if !self.root.is_dir() {
+            return Ok(out);
+        }
+        for entry in fs::read_dir(&self.root)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            out.push(serde_json::from_slice(&fs::read(&path)?)?);
+        }
+        out.sort_by(|a: &StoredCollection, b| a.name.cmp(&b.name));
+        Ok(out)
+    }
+
+    pub fn save(&self, collection: &StoredCollection) -> Result<(), CollectionStoreError> {
+        fs::create_dir_all(&self.root)?;
+        let bytes = serde_json::to_vec_pretty(collection)?;
+        Ok(write_atomic(&self.path(&collection.name), &bytes)?)
+    }
+
+    /// Remove `name`; whether it was stored.
+    pub fn delete(&self, name: &str) -> Result<bool, CollectionStoreError> {
+        match fs::remove_file(self.path(name)) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn collection(name: &str, limit: u32) -> StoredCollection {
+        StoredCollection {
+            name: name.to_owned(),
+            definition: json!({ "filter": { "type": "post" }, "limit": limit }),
+            updated_at: Utc::now(),
+            updated_by: "ada".to_owned(),
+        }
+    }
+
+    #[test]
+    fn collections_round_trip_by_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = CollectionStore::new(dir.path().join(COLLECTIONS_DB_DIR));
+        assert!(store.list().unwrap().is_empty());
+
+        let recent = collection("recent", 10);
+        let featured = collection("featured", 5);
+        store.save(&recent).unwrap();
+        store.save(&featured).unwrap();
+        fs::write(dir.path().join(COLLECTIONS_DB_DIR).join("notes.txt"), "x").unwrap();
+        assert_eq!(store.list().unwrap(), [featured.clone(), recent.clone()]);
+
+        // Saving under the same name replaces it.
+        let edited = collection("featured", 3);
+        store.save(&edited).unwrap();
+        assert_eq!(store.list().unwrap(), [edited, recent.clone()]);
+
+        assert!(store.delete("featured").unwrap());
+        assert!(!store.delete("featured").unwrap());
+        assert_eq!(store.list().unwrap(), [recent]);
+    }
+}
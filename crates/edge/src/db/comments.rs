@@ -0,0 +1,244 @@
+// crates/edge/src/db/comments.rs
+
+// Reader comments (see crate::comments), one JSON file each:
+//
+//   <root>/<id>.json   {"id":"…","post":"/blog/hello","parent":null,
+//                       "author":"Ada","body":"Nice post.","status":"pending",
+//                       "created_at":"…","moderated_at":null,"moderated_by":null}
+//
+// There is no ops database to hold a comments table yet (see db::kv), so
+// listing reads the whole directory; that is fine for the few thousand
+// comments a small blog collects. Ids are UUIDs minted here, so they are
+// safe as file names; ids from a request are checked before use.
+
+use crate::db::history::write_atomic;
+use crate::db::store::StoreError;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+use thiserror::Error;
+use uuid::Uuid;
+
+/// Comments inside the site directory.
+pub const COMMENTS_DB_DIR: &str = "./comments_db/";
+
+#[derive(Debug, Error)]
+pub enum CommentStoreError {
+    #[error("I/O: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("JSON: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("unknown comment status {0:?}: expected pending, approved or spam")]
+    UnknownStatus(String),
+}
+
+impl StoreError for CommentStoreError {
+    const STORE: &'static str = "comment";
+}
+
+/// Where a comment is in moderation. Only approved comments are shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CommentStatus {
+    Pending,
+    Approved,
+    Spam,
+}
+
+impl CommentStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CommentStatus::Pending => "pending",
+            CommentStatus::Approved => "approved",
+            CommentStatus::Spam => "spam",
+        }
+    }
+}
+
+impl fmt::Display for CommentStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for CommentStatus {
+    type Err = CommentStoreError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "pending" => Ok(CommentStatus::Pending),
+            "approved" => Ok(CommentStatus::Approved),
+            "spam" => Ok(CommentStatus::Spam),
+            _ => Err(CommentStoreError::UnknownStatus(s.to_owned())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Comment {
+    pub id: String,
+    /// Path of the page commented on, e.g. `/blog/hello`.
+    pub post: String,
+    /// The comment this one replies to.
+    pub parent: Option<String>,
+    pub author: String,
+    pub body: String,
+    pub status: CommentStatus,
+    pub created_at: DateTime<Utc>,
+    pub moderated_at: Option<DateTime<Utc>>,
+    /// Username of the editor who last changed the status.
+    pub moderated_by: Option<String>,
+}
+
+impl Comment {
+    /// A new comment on `post`, waiting for moderation.
+    pub fn pending(
+        post: impl Into<String>,
+        parent: Option<String>,
+        author: impl Into<String>,
+        body: impl Into<String>,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            post: post.into(),
+            parent,
+            author: author.into(),
+            body: body.into(),
+            status: CommentStatus::Pending,
+            created_at: Utc::now(),
+            moderated_at: None,
+            moderated_by: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CommentStore {
+    root: PathBuf,
+}
+
+impl CommentStore {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    /// The file for `id`, if `id` could have been minted here.
+    fn path(&self, id: &str) -> Option<PathBuf> {
+        Uuid::parse_str(id)
+            .ok()
+            .map(|_| self.root.join(format!("{id}.json")))
+    }
+
+    /// Every stored comment, oldest first.
+    pub fn list(&self) -> Result<Vec<Comment>, CommentStoreError> {
+        let mut out = Vec::new();
+        if !self.root.is_dir() {
+            return Ok(out);
+        }
+        for entry in fs::read_dir(&self.root)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            out.push(serde_json::from_slice(&fs::read(&path)?)?);
+        }
+        out.sort_by(|a: &Comment, b| (a.created_at, &a.id).cmp(&(b.created_at, &b.id)));
+        Ok(out)
+    }
+
+    pub fn get(&self, id: &str) -> Result<Option<Comment>, CommentStoreError> {
+        let Some(path) = self.path(id) else {
+            return Ok(None);
+        };
+        match fs::read(path) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, comment: &Comment) -> Result<(), CommentStoreError> {
+        let path = self
+            .path(&comment.id)
+            .ok_or_else(|| io::Error::other(format!("bad comment id {:?}", comment.id)))?;
+        fs::create_dir_all(&self.root)?;
+        Ok(write_atomic(&path, &serde_json::to_vec_pretty(comment)?)?)
+    }
+
+    /// Move `id` to `status` on behalf of `who`; the updated comment, or
+    /// `None` when there is no such comment.
+    pub fn moderate(
+        &self,
+        id: &str,
+        status: CommentStatus,
+        who: &str,
+    ) -> Result<Option<Comment>, CommentStoreError> {
+        let Some(mut comment) = self.get(id)? else {
+            return Ok(None);
+        };
+        comment.status = status;
+        comment.moderated_at = Some(Utc::now());
+        comment.moderated_by = Some(who.to_owned());
+        self.save(&comment)?;
+        Ok(Some(comment))
+    }
+
+    /// Remove `id`; whether it was stored. Replies to it stay, and are shown
+    /// as top-level comments.
+    pub fn delete(&self, id: &str) -> Result<bool, CommentStoreError> {
+        let Some(path) = self.path(id) else {
+            return Ok(false);
+        };
+        match fs::remove_file(path) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn comments_are_stored_moderated_and_deleted() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = CommentStore::new(dir.path().join("comments"));
+        assert!(store.list().unwrap().is_empty());
+
+        let first = Comment::pending("/blog/hello", None, "Ada", "Nice post.");
+        let reply = Comment::pending("/blog/hello", Some(first.id.clone()), "Bo", "Agreed.");
+        store.save(&first).unwrap();
+        store.save(&reply).unwrap();
+        assert_eq!(store.list().unwrap(), [first.clone(), reply.clone()]);
+
+        let approved = store
+            .moderate(&first.id, CommentStatus::Approved, "ed")
+            .unwrap()
+            .unwrap();
+        assert_eq!(approved.status, CommentStatus::Approved);
+        assert_eq!(approved.moderated_by.as_deref(), Some("ed"));
+        assert_eq!(store.get(&first.id).unwrap(), Some(approved));
+
+        assert!(store.delete(&reply.id).unwrap());
+        assert!(!store.delete(&reply.id).unwrap());
+        assert!(store
+            .moderate(&reply.id, CommentStatus::Spam, "ed")
+            .unwrap()
+            .is_none());
+        // Ids that are not UUIDs never reach the file system.
+        assert!(store.get("../users_db/users/ada").unwrap().is_none());
+        assert_eq!(
+            "SPAM".parse::<CommentStatus>().unwrap(),
+            CommentStatus::Spam
+        );
+        assert!("deleted".parse::<CommentStatus>().is_err());
+    }
+}
@@ -0,0 +1,260 @@
+// crates/edge/src/db/ext_settings.rs
+
+// The site's settings values for plugins and themes, one JSON object per
+// extension:
+//
+//   <site>/ext_settings/plugins/seo.json    {"title_suffix":" | Blog"}
+//   <site>/ext_settings/themes/plain.json   {"layout":"list","posts_per_page":20}
+//
+// The runtime checks them against the `[settings]` the manifest declares
+// (adapt::runtime::settings) when it loads the extension, at start and on
+// every reload, and hands the result to JS as `ctx.config`. There is no ops
+// database to keep them in yet, so like plugin storage (db::kv) they are
+// files in the site directory. A missing file means nothing is stored.
+//
+// The same directory records which theme serves a mount path when the site
+// has picked one (api::extensions):
+//
+//   <site>/ext_settings/active_themes.json   {"/":"plain","/docs":"docsy"}
+//
+// A theme picked for a mount serves only there; every other theme serves
+// the mount its theme.toml names, unless a picked theme holds it.
+
+use crate::db::history::{write_atomic, ExtKind};
+use crate::fs::ext::{DiscoveredPlugin, DiscoveredTheme, ThemeBinding};
+use adapt::runtime::bootstrap::{PluginConfig, ThemeConfig};
+use adapt::runtime::error::RuntimeError;
+use serde_json::Value as Json;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// Stored extension settings inside the site directory.
+pub const EXT_SETTINGS_DIR: &str = "./ext_settings/";
+
+/// Theme picked per mount path, inside `EXT_SETTINGS_DIR`.
+pub const ACTIVE_THEMES_FILE: &str = "active_themes.json";
+
+/// Where `id`'s values live under `root`, if the id can name a file.
+fn path(root: &Path, kind: ExtKind, id: &str) -> Option<PathBuf> {
+    let safe = !id.is_empty()
+        && !id.starts_with('.')
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"-_.".contains(&b));
+    safe.then(|| root.join(kind.dir_name()).join(format!("{id}.json")))
+}
+
+/// The values stored for `id` under `root` (`<site>/ext_settings`), null
+/// when there are none.
+pub fn stored(root: &Path, kind: ExtKind, id: &str) -> Result<Json, RuntimeError> {
+    let fail = |msg: String| match kind {
+        ExtKind::Plugin => RuntimeError::plugin_bootstrap(msg),
+        ExtKind::Theme => RuntimeError::theme_bootstrap(msg),
+    };
+    let Some(path) = path(root, kind, id) else {
+        return Err(fail(format!(
+            "{kind} id '{id}' cannot name a settings file"
+        )));
+    };
+    let src = match fs::read_to_string(&path) {
+        Ok(src) => src,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Json::Null),
+        Err(e) => {
+            return Err(fail(format!(
+                "reading {kind} '{id}' settings {path:?}: {e}"
+            )))
+        }
+    };
+    serde_json::from_str(&src).map_err(|e| {
+        fail(format!(
+            "{kind} '{id}' settings {path:?} are not valid JSON: {e}"
+        ))
+    })
+}
+
+/// Store `values` for `id` under `root`; they take effect when the
+/// extension is next loaded.
+pub fn save(root: &Path, kind: ExtKind, id: &str, values: &Json) -> io::Result<()> {
+    let Some(path) = path(root, kind, id) else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{kind} id '{id}' cannot name a settings file"),
+        ));
+    };
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    write_atomic(&path, &serde_json::to_vec_pretty(values)?)
+}
+
+/// Theme id picked per mount path under `root`. An unreadable file is
+/// logged and treated as no picks, so themes fall back to their manifests.
+pub fn active_themes(root: &Path) -> BTreeMap<String, String> {
+    let path = root.join(ACTIVE_THEMES_FILE);
+    let picked = match fs::read(&path) {
+        Ok(bytes) => serde_json::from_slice(&bytes).map_err(io::Error::from),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(e),
+    };
+    picked.unwrap_or_else(|e| {
+        warn!("Ignoring active themes in {:?}: {}", path, e);
+        BTreeMap::new()
+    })
+}
+
+/// Have the theme `id` serve `mount` from now on.
+pub fn set_active_theme(root: &Path, mount: &str, id: &str) -> io::Result<()> {
+    let mut picked = active_themes(root);
+    picked.insert(mount.to_owned(), id.to_owned());
+    fs::create_dir_all(root)?;
+    write_atomic(
+        &root.join(ACTIVE_THEMES_FILE),
+        &serde_json::to_vec_pretty(&picked)?,
+    )
+}
+
+/// Which theme serves which mount, given the picks stored under `root`.
+/// Picks naming a theme that is not installed are ignored; of two themes
+/// left on the same mount, the first discovered serves it.
+pub fn theme_bindings(root: &Path, themes: &[DiscoveredTheme]) -> Vec<ThemeBinding> {
+    let picked: BTreeMap<String, &DiscoveredTheme> = active_themes(root)
+        .into_iter()
+        .filter_map(|(mount, id)| {
+            let theme = themes.iter().find(|t| t.spec.id == id)?;
+            Some((mount, theme))
+        })
+        .collect();
+    let picked_ids: BTreeSet<&str> = picked.values().map(|t| t.spec.id.as_str()).collect();
+
+    let mut taken = BTreeSet::new();
+    let mut bindings = Vec::with_capacity(themes.len());
+    for (mount, theme) in &picked {
+        let mut binding = ThemeBinding::from(*theme);
+        binding.mount_path = mount.clone();
+        taken.insert(mount.clone());
+        bindings.push(binding);
+    }
+    for theme in themes {
+        if picked_ids.contains(theme.spec.id.as_str()) || !taken.insert(theme.mount_path.clone()) {
+            continue;
+        }
+        bindings.push(ThemeBinding::from(theme));
+    }
+    bindings
+}
+
+/// Runtime configs for `plugins`, with their stored settings.
+pub fn plugin_configs(
+    root: &Path,
+    plugins: &[DiscoveredPlugin],
+) -> Result<Vec<PluginConfig>, RuntimeError> {
+    plugins
+        .iter()
+        .map(|p| {
+            let mut cfg = PluginConfig::from(&p.spec);
+            cfg.config = stored(root, ExtKind::Plugin, &cfg.id)?;
+            Ok(cfg)
+        })
+        .collect()
+}
+
+/// Runtime config for `theme`, with its stored settings.
+pub fn theme_config(root: &Path, theme: &DiscoveredTheme) -> Result<ThemeConfig, RuntimeError> {
+    let mut cfg = ThemeConfig::from(&theme.spec);
+    cfg.config = stored(root, ExtKind::Theme, &cfg.id)?;
+    Ok(cfg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn stored_settings_are_read_per_kind_and_bad_files_name_the_extension() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("plugins")).unwrap();
+        fs::create_dir_all(dir.path().join("themes")).unwrap();
+        fs::write(
+            dir.path().join("plugins/seo.json"),
+            r#"{"suffix":" | Blog"}"#,
+        )
+        .unwrap();
+        fs::write(dir.path().join("themes/seo.json"), "{ not json").unwrap();
+
+        assert_eq!(
+            stored(dir.path(), ExtKind::Plugin, "seo").unwrap(),
+            json!({ "suffix": " | Blog" })
+        );
+        assert_eq!(
+            stored(dir.path(), ExtKind::Plugin, "gallery").unwrap(),
+            Json::Null
+        );
+        let err = stored(dir.path(), ExtKind::Theme, "seo").unwrap_err();
+        assert!(matches!(err, RuntimeError::ThemeBootstrap(_)));
+        assert!(
+            err.to_string().contains("theme 'seo' settings"),
+            "got {err}"
+        );
+        assert!(stored(dir.path(), ExtKind::Plugin, "../seo").is_err());
+
+        save(
+            dir.path(),
+            ExtKind::Plugin,
+            "gallery",
+            &json!({ "cols": 3 }),
+        )
+        .unwrap();
+        assert_eq!(
+            stored(dir.path(), ExtKind::Plugin, "gallery").unwrap(),
+            json!({ "cols": 3 })
+        );
+    }
+
+    #[test]
+    fn a_picked_theme_takes_its_mount_from_the_others() {
+        use adapt::runtime::theme::ThemeSpec;
+
+        let theme = |id: &str, mount: &str| DiscoveredTheme {
+            mount_path: mount.into(),
+            dir: PathBuf::from("themes").join(id),
+            assets_dir: None,
+            spec: ThemeSpec::new(id, id, mount, ""),
+            engine: None,
+            collections: BTreeMap::new(),
+        };
+        let themes = [
+            theme("plain", "/"),
+            theme("docsy", "/docs"),
+            theme("bold", "/"),
+        ];
+        let served = |root: &Path| -> Vec<(String, String)> {
+            theme_bindings(root, &themes)
+                .into_iter()
+                .map(|b| (b.mount_path, b.theme_id))
+                .collect()
+        };
+        let pair = |m: &str, t: &str| (m.to_owned(), t.to_owned());
+
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(
+            served(dir.path()),
+            [pair("/", "plain"), pair("/docs", "docsy")]
+        );
+
+        set_active_theme(dir.path(), "/", "bold").unwrap();
+        set_active_theme(dir.path(), "/blog", "plain").unwrap();
+        set_active_theme(dir.path(), "/old", "removed").unwrap();
+        assert_eq!(
+            served(dir.path()),
+            [
+                pair("/", "bold"),
+                pair("/blog", "plain"),
+                pair("/docs", "docsy")
+            ]
+        );
+    }
+}
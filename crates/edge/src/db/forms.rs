@@ -0,0 +1,157 @@
+// crates/edge/src/db/forms.rs
+
+// Form submissions (see crate::forms), one JSON file each, a folder per
+// form:
+//
+//   <root>/<form>/<id>.json   {"id":"…","form":"contact","created_at":"…",
+//                              "fields":{"email":"ada@example.com",…}}
+//
+// Like db::comments, this stands in for a table in an ops database that
+// does not exist yet. Form names come from the settings and ids are UUIDs
+// minted here; both are checked before they become paths.
+
+use crate::db::history::write_atomic;
+use crate::db::store::StoreError;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map as JsonMap, Value as Json};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+use uuid::Uuid;
+
+/// Submissions inside the site directory.
+pub const FORMS_DB_DIR: &str = "./forms_db/";
+
+#[derive(Debug, Error)]
+pub enum FormStoreError {
+    #[error("I/O: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("JSON: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("{0:?} is not a form name: use letters, digits, '-' and '_'")]
+    BadName(String),
+}
+
+impl StoreError for FormStoreError {
+    const STORE: &'static str = "form";
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FormSubmission {
+    pub id: String,
+    pub form: String,
+    pub created_at: DateTime<Utc>,
+    /// The checked values (serve::forms::validate), after plugins.
+    pub fields: JsonMap<String, Json>,
+}
+
+impl FormSubmission {
+    pub fn new(form: impl Into<String>, fields: JsonMap<String, Json>) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            form: form.into(),
+            created_at: Utc::now(),
+            fields,
+        }
+    }
+}
+
+/// Whether `name` may name a form (and so a folder).
+pub fn is_form_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+#[derive(Debug, Clone)]
+pub struct FormStore {
+    root: PathBuf,
+}
+
+impl FormStore {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    fn dir(&self, form: &str) -> Result<PathBuf, FormStoreError> {
+        if is_form_name(form) {
+            Ok(self.root.join(form))
+        } else {
+            Err(FormStoreError::BadName(form.to_owned()))
+        }
+    }
+
+    pub fn save(&self, submission: &FormSubmission) -> Result<(), FormStoreError> {
+        let dir = self.dir(&submission.form)?;
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(format!("{}.json", submission.id));
+        Ok(write_atomic(
+            &path,
+            &serde_json::to_vec_pretty(submission)?,
+        )?)
+    }
+
+    /// The submissions of `form`, newest first.
+    pub fn list(&self, form: &str) -> Result<Vec<FormSubmission>, FormStoreError> {
+        let dir = self.dir(form)?;
+        let mut out = Vec::new();
+        if !dir.is_dir() {
+            return Ok(out);
+        }
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            out.push(serde_json::from_slice(&fs::read(&path)?)?);
+        }
+        out.sort_by(|a: &FormSubmission, b| (b.created_at, &b.id).cmp(&(a.created_at, &a.id)));
+        Ok(out)
+    }
+
+    /// Remove submission `id` of `form`; whether it was stored.
+    pub fn delete(&self, form: &str, id: &str) -> Result<bool, FormStoreError> {
+        let dir = self.dir(form)?;
+        if Uuid::parse_str(id).is_err() {
+            return Ok(false);
+        }
+        match fs::remove_file(dir.join(format!("{id}.json"))) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn submissions_are_kept_per_form() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FormStore::new(dir.path().join("forms"));
+        let fields = |v: Json| v.as_object().unwrap().clone();
+
+        let first = FormSubmission::new("contact", fields(json!({ "email": "a@example.com" })));
+        let other = FormSubmission::new("signup", fields(json!({ "name": "Bo" })));
+        store.save(&first).unwrap();
+        store.save(&other).unwrap();
+        assert_eq!(store.list("contact").unwrap(), std::slice::from_ref(&first));
+        assert!(store.list("missing").unwrap().is_empty());
+
+        assert!(store.delete("contact", &first.id).unwrap());
+        assert!(!store.delete("contact", &first.id).unwrap());
+        assert!(!store.delete("contact", "../signup/x").unwrap());
+        assert!(matches!(
+            store.list("../users_db"),
+            Err(FormStoreError::BadName(_))
+        ));
+    }
+}
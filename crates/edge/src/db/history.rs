@@ -0,0 +1,332 @@
+// crates/edge/src/db/history.rs
+
+// Version history for plugin and theme configuration.
+//
+// Configuration is the `plugin.toml` / `theme.toml` manifest on disk, and the
+// extension watcher is what sees it saved. Every save that changes a
+// manifest becomes a version (who, when, full text, diff against the
+// previous version), one JSON file each:
+//
+//   <root>/plugins/<id>/<version>.json
+//   <root>/themes/<id>/<version>.json
+//
+// A rollback writes an old version's manifest back to disk and records that
+// as a new version, so history only ever grows. There is no audit log in
+// this tree to share storage with; like the outbox, this is plain files.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use similar::TextDiff;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// History location inside the site directory.
+pub const HISTORY_DIR: &str = "./history/";
+
+#[derive(Debug, Error)]
+pub enum HistoryError {
+    #[error("I/O: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("JSON: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Not a usable extension id: {0:?}")]
+    InvalidId(String),
+
+    #[error("No version {version} of {kind} {id}")]
+    NotFound {
+        kind: ExtKind,
+        id: String,
+        version: u32,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExtKind {
+    Plugin,
+    Theme,
+}
+
+impl ExtKind {
+    pub(crate) fn dir_name(self) -> &'static str {
+        match self {
+            ExtKind::Plugin => "plugins",
+            ExtKind::Theme => "themes",
+        }
+    }
+
+    /// The manifest file inside an extension's folder.
+    pub fn manifest_name(self) -> &'static str {
+        match self {
+            ExtKind::Plugin => "plugin.toml",
+            ExtKind::Theme => "theme.toml",
+        }
+    }
+}
+
+impl std::fmt::Display for ExtKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExtKind::Plugin => f.write_str("plugin"),
+            ExtKind::Theme => f.write_str("theme"),
+        }
+    }
+}
+
+/// One saved state of an extension's manifest.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfigVersion {
+    /// 1-based, increasing per extension.
+    pub version: u32,
+    pub kind: ExtKind,
+    pub id: String,
+    pub who: String,
+    pub at: DateTime<Utc>,
+    pub manifest: String,
+    /// Unified diff from the previous version (from empty for the first).
+    pub diff: String,
+    /// Set when this version was created by rolling back to an older one.
+    pub restored_from: Option<u32>,
+}
+
+/// File-backed configuration history rooted at a directory.
+#[derive(Debug, Clone)]
+pub struct ConfigHistory {
+    root: PathBuf,
+}
+
+impl ConfigHistory {
+    /// Open (creating if needed) a history store at `root`.
+    pub fn open(root: impl Into<PathBuf>) -> Result<Self, HistoryError> {
+        let root = root.into();
+        for kind in [ExtKind::Plugin, ExtKind::Theme] {
+            fs::create_dir_all(root.join(kind.dir_name()))?;
+        }
+        Ok(Self { root })
+    }
+
+    /// Record a saved manifest. Returns `None` when it matches the latest
+    /// version, so re-saving unchanged files (or restarting) adds nothing.
+    pub fn record(
+        &self,
+        kind: ExtKind,
+        id: &str,
+        manifest: &str,
+        who: &str,
+    ) -> Result<Option<ConfigVersion>, HistoryError> {
+        let latest = self.latest(kind, id)?;
+        if latest.as_ref().is_some_and(|v| v.manifest == manifest) {
+            return Ok(None);
+        }
+        self.append(kind, id, latest.as_ref(), manifest, who, None)
+            .map(Some)
+    }
+
+    /// All versions of an extension's manifest, oldest first.
+    pub fn versions(&self, kind: ExtKind, id: &str) -> Result<Vec<ConfigVersion>, HistoryError> {
+        let dir = self.dir(kind, id)?;
+        if !dir.is_dir() {
+            return Ok(Vec::new());
+        }
+
+        let mut out = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|e| e == "json") {
+                out.push(serde_json::from_slice::<ConfigVersion>(&fs::read(path)?)?);
+            }
+        }
+        out.sort_by_key(|v| v.version);
+        Ok(out)
+    }
+
+    pub fn latest(&self, kind: ExtKind, id: &str) -> Result<Option<ConfigVersion>, HistoryError> {
+        Ok(self.versions(kind, id)?.pop())
+    }
+
+    pub fn get(
+        &self,
+        kind: ExtKind,
+        id: &str,
+        version: u32,
+    ) -> Result<ConfigVersion, HistoryError> {
+        let path = self.dir(kind, id)?.join(version_file(version));
+        match fs::read(path) {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Err(HistoryError::NotFound {
+                kind,
+                id: id.to_string(),
+                version,
+            }),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Write `version`'s manifest to `manifest_path` and record the restore
+    /// as a new version. A running server's watcher then reloads it.
+    pub fn rollback(
+        &self,
+        kind: ExtKind,
+        id: &str,
+        version: u32,
+        manifest_path: &Path,
+        who: &str,
+    ) -> Result<ConfigVersion, HistoryError> {
+        let target = self.get(kind, id, version)?;
+        let latest = self.latest(kind, id)?;
+
+        write_atomic(manifest_path, target.manifest.as_bytes())?;
+        self.append(
+            kind,
+            id,
+            latest.as_ref(),
+            &target.manifest,
+            who,
+            Some(version),
+        )
+    }
+
+    fn append(
+        &self,
+        kind: ExtKind,
+        id: &str,
+        previous: Option<&ConfigVersion>,
+        manifest: &str,
+        who: &str,
+        restored_from: Option<u32>,
+    ) -> Result<ConfigVersion, HistoryError> {
+        let version = previous.map_or(1, |p| p.version + 1);
+        let (old, old_name) = match previous {
+            Some(p) => (p.manifest.as_str(), format!("v{}", p.version)),
+            None => ("", "(none)".to_string()),
+        };
+        let diff = TextDiff::from_lines(old, manifest)
+            .unified_diff()
+            .header(&old_name, &format!("v{version}"))
+            .to_string();
+
+        let entry = ConfigVersion {
+            version,
+            kind,
+            id: id.to_string(),
+            who: who.to_string(),
+            at: Utc::now(),
+            manifest: manifest.to_string(),
+            diff,
+            restored_from,
+        };
+
+        let dir = self.dir(kind, id)?;
+        fs::create_dir_all(&dir)?;
+        write_atomic(
+            &dir.join(version_file(version)),
+            &serde_json::to_vec_pretty(&entry)?,
+        )?;
+        Ok(entry)
+    }
+
+    fn dir(&self, kind: ExtKind, id: &str) -> Result<PathBuf, HistoryError> {
+        // Ids name a directory here; refuse anything that is not one plain
+        // path component.
+        if id.is_empty() || id == "." || id == ".." || id.contains(['/', '\\']) {
+            return Err(HistoryError::InvalidId(id.to_string()));
+        }
+        Ok(self.root.join(kind.dir_name()).join(id))
+    }
+}
+
+fn version_file(version: u32) -> String {
+    format!("{version:06}.json")
+}
+
+/// Write via a temp file + rename, so readers never see a partial file.
+pub(crate) fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    {
+        let mut f = fs::File::create(&tmp)?;
+        f.write_all(data)?;
+        f.sync_all()?;
+    }
+    fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_skips_unchanged_saves_and_diffs_against_the_previous_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = ConfigHistory::open(dir.path()).unwrap();
+
+        let v1 = history
+            .record(ExtKind::Plugin, "seo", "id = \"seo\"\n", "watcher")
+            .unwrap()
+            .unwrap();
+        assert_eq!(v1.version, 1);
+        assert!(v1.diff.contains("+id = \"seo\""));
+
+        assert!(history
+            .record(ExtKind::Plugin, "seo", "id = \"seo\"\n", "watcher")
+            .unwrap()
+            .is_none());
+
+        let v2 = history
+            .record(
+                ExtKind::Plugin,
+                "seo",
+                "id = \"seo\"\npermissions = [\"net\"]\n",
+                "watcher",
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(v2.version, 2);
+        assert!(v2.diff.starts_with("--- v1\n+++ v2\n"));
+        assert!(v2.diff.contains("+permissions = [\"net\"]"));
+
+        // Same id, other kind: separate history.
+        assert!(history.versions(ExtKind::Theme, "seo").unwrap().is_empty());
+        assert!(matches!(
+            history.record(ExtKind::Theme, "../x", "", "watcher"),
+            Err(HistoryError::InvalidId(_))
+        ));
+    }
+
+    #[test]
+    fn rollback_restores_the_manifest_as_a_new_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = ConfigHistory::open(dir.path().join("history")).unwrap();
+        let manifest = dir.path().join("theme.toml");
+
+        history
+            .record(ExtKind::Theme, "docsy", "mount = \"/\"\n", "watcher")
+            .unwrap();
+        history
+            .record(ExtKind::Theme, "docsy", "mount = \"/docs\"\n", "watcher")
+            .unwrap();
+        fs::write(&manifest, "mount = \"/docs\"\n").unwrap();
+
+        let restored = history
+            .rollback(ExtKind::Theme, "docsy", 1, &manifest, "cli:ops")
+            .unwrap();
+        assert_eq!(restored.version, 3);
+        assert_eq!(restored.restored_from, Some(1));
+        assert_eq!(restored.who, "cli:ops");
+        assert_eq!(fs::read_to_string(&manifest).unwrap(), "mount = \"/\"\n");
+
+        let versions = history.versions(ExtKind::Theme, "docsy").unwrap();
+        assert_eq!(
+            versions.iter().map(|v| v.version).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+        assert!(matches!(
+            history.rollback(ExtKind::Theme, "docsy", 9, &manifest, "cli:ops"),
+            Err(HistoryError::NotFound { version: 9, .. })
+        ));
+    }
+}
@@ -1,6 +1,7 @@
 // crates/edge/src/db/json.rs
 
 use adapt::mql::index::{BoolField, I64Field, IndexRecord, StringField};
+use adapt::mql::text::text_index_key;
 use adapt::mql::{IndexBackend, IndexConfig, JsonStore};
 use async_trait::async_trait;
 use chrono::Datelike;
@@ -106,72 +107,7 @@ impl IndexedJsonIndexBackend {
         field: &str,
         value: &Json,
     ) -> Option<Arc<dyn IndexableField + Send + Sync>> {
-        // Map MQL field path -> static key & typed value.
-        macro_rules! string_field {
-            ($key:expr) => {{
-                let s = value
-                    .as_str()
-                    .map(|v| v.to_owned())
-                    .unwrap_or_else(|| value.to_string());
-                Some(Arc::new(StringField::new($key, s)) as Arc<dyn IndexableField + Send + Sync>)
-            }};
-        }
-
-        macro_rules! i64_field {
-            ($key:expr) => {{
-                let n = if let Some(i) = value.as_i64() {
-                    i
-                } else if let Some(u) = value.as_u64() {
-                    u as i64
-                } else {
-                    return None;
-                };
-                Some(Arc::new(I64Field::new($key, n)) as Arc<dyn IndexableField + Send + Sync>)
-            }};
-        }
-
-        macro_rules! bool_field {
-            ($key:expr) => {{
-                let b = value.as_bool()?;
-                Some(Arc::new(BoolField::new($key, b)) as Arc<dyn IndexableField + Send + Sync>)
-            }};
-        }
-
-        match field {
-            // Root
-            "id" => string_field!("id"),
-            "type" => string_field!("type"),
-            "slug" => string_field!("slug"),
-            "parent" => string_field!("parent"),
-
-            // content.*
-            "content.title" => string_field!("content.title"),
-            "content.section" => string_field!("content.section"),
-
-            // publish.*
-            "publish.status" => string_field!("publish.status"),
-            "publish.date" => string_field!("publish.date"),
-            "publish.modified" => string_field!("publish.modified"),
-
-            // nav.*
-            "nav.menu_order" => i64_field!("nav.menu_order"),
-            "nav.menu_visible" => bool_field!("nav.menu_visible"),
-
-            // tax.* — single value at a time; IndexRecord indexes each element separately.
-            "tax.categories" => string_field!("tax.categories"),
-            "tax.tags" => string_field!("tax.tags"),
-            "tax.series" => string_field!("tax.series"),
-
-            // i18n.*
-            "i18n.lang" => string_field!("i18n.lang"),
-            "i18n.canonical_id" => string_field!("i18n.canonical_id"),
-
-            // author.*
-            "author.author" => string_field!("author.author"),
-            "author.co_authors" => string_field!("author.co_authors"),
-
-            _ => None,
-        }
+        make_index_field(field, value)
     }
 
     async fn run_query(&self, q: &Query) -> Option<HashSet<IndexedId>> {
@@ -187,6 +123,134 @@ impl IndexedJsonIndexBackend {
     }
 }
 
+/// Every field path `IndexRecord` projects into the archive index.
+///
+/// Handy for building an `IndexConfig` that lets the planner use all of them.
+pub const INDEXED_FIELDS: &[&str] = &[
+    "id",
+    "type",
+    "slug",
+    "parent",
+    "content.title",
+    "content.section",
+    "content.summary",
+    "publish.status",
+    "publish.date",
+    "publish.modified",
+    "publish.unpublish_at",
+    "nav.menu_order",
+    "nav.menu_visible",
+    "tax.categories",
+    "tax.tags",
+    "tax.series",
+    "i18n.lang",
+    "i18n.canonical_id",
+    "author.author",
+    "author.co_authors",
+];
+
+/// The multi-valued fields among `INDEXED_FIELDS`: `IndexRecord` emits one
+/// entry per element, so `$elemMatch` equality can be looked up directly.
+pub const ELEMENT_FIELDS: &[&str] = &[
+    "tax.categories",
+    "tax.tags",
+    "tax.series",
+    "author.co_authors",
+];
+
+/// Map an MQL field path + JSON value onto the typed `IndexableField` that
+/// `IndexRecord` emits for that path.
+///
+/// Returns `None` for paths that are not part of the index projection, or
+/// when the value cannot be coerced into the field's type.
+pub(crate) fn make_index_field(
+    field: &str,
+    value: &Json,
+) -> Option<Arc<dyn IndexableField + Send + Sync>> {
+    // Map MQL field path -> static key & typed value.
+    macro_rules! string_field {
+        ($key:expr) => {{
+            let s = value
+                .as_str()
+                .map(|v| v.to_owned())
+                .unwrap_or_else(|| value.to_string());
+            Some(Arc::new(StringField::new($key, s)) as Arc<dyn IndexableField + Send + Sync>)
+        }};
+    }
+
+    macro_rules! i64_field {
+        ($key:expr) => {{
+            let n = if let Some(i) = value.as_i64() {
+                i
+            } else if let Some(u) = value.as_u64() {
+                u as i64
+            } else {
+                return None;
+            };
+            Some(Arc::new(I64Field::new($key, n)) as Arc<dyn IndexableField + Send + Sync>)
+        }};
+    }
+
+    macro_rules! bool_field {
+        ($key:expr) => {{
+            let b = value.as_bool()?;
+            Some(Arc::new(BoolField::new($key, b)) as Arc<dyn IndexableField + Send + Sync>)
+        }};
+    }
+
+    match field {
+        // Root
+        "id" => string_field!("id"),
+        "type" => string_field!("type"),
+        "slug" => string_field!("slug"),
+        "parent" => string_field!("parent"),
+
+        // content.*
+        "content.title" => string_field!("content.title"),
+        "content.section" => string_field!("content.section"),
+
+        // publish.*
+        "publish.status" => string_field!("publish.status"),
+        "publish.date" => string_field!("publish.date"),
+        "publish.modified" => string_field!("publish.modified"),
+        "publish.unpublish_at" => string_field!("publish.unpublish_at"),
+
+        // nav.*
+        "nav.menu_order" => i64_field!("nav.menu_order"),
+        "nav.menu_visible" => bool_field!("nav.menu_visible"),
+
+        // tax.* — single value at a time; IndexRecord indexes each element separately.
+        "tax.categories" => string_field!("tax.categories"),
+        "tax.tags" => string_field!("tax.tags"),
+        "tax.series" => string_field!("tax.series"),
+
+        // i18n.*
+        "i18n.lang" => string_field!("i18n.lang"),
+        "i18n.canonical_id" => string_field!("i18n.canonical_id"),
+
+        // author.*
+        "author.author" => string_field!("author.author"),
+        "author.co_authors" => string_field!("author.co_authors"),
+
+        _ => None,
+    }
+}
+
+/// A `$text` lookup as an archive query: each word under the field's word
+/// index key (see `IndexRecord::index`). `None` for fields without one.
+pub(crate) fn make_text_query(field: &str, words: &[String]) -> Option<Query> {
+    let key = text_index_key(field)?;
+    let mut clauses: Vec<Query> = words
+        .iter()
+        .map(|w| Query::Eq(Arc::new(StringField::new(key, w.clone()))))
+        .collect();
+    match clauses.len() {
+        0 => None,
+        1 => Some(clauses.remove(0)),
+        _ => Some(Query::And(clauses)),
+    }
+}
+
 #[async_trait]
 impl IndexBackend for IndexedJsonIndexBackend {
     type Id = IndexedId;
@@ -254,14 +318,21 @@ impl IndexBackend for IndexedJsonIndexBackend {
 
         self.run_query(&q).await
     }
+    async fn lookup_text(&self, field: &str, words: &[String]) -> Option<HashSet<Self::Id>> {
+        if !self.config.is_indexed(field) {
+            return None;
+        }
+        let q = make_text_query(field, words)?;
+        self.run_query(&q).await
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use adapt::mql::index::IndexRecord;
+    use adapt::mql::index::{ContentFields, IndexRecord};
     use adapt::mql::IndexConfig;
-    use chrono::{NaiveDate, Timelike};
+    use chrono::{NaiveDate, TimeZone, Timelike, Utc};
     use indexed_json::Indexable;
     use serde_json::json;
     use smallvec::SmallVec;
@@ -489,6 +560,45 @@ mod tests {
         assert!(ts2.year() >= 1970);
     }
 
+    #[test]
+    fn indexrecord_unpublish_at_expires_only_once_passed() {
+        let doc = json!({ "slug": "sale", "unpublish_at": "2030-06-01T00:00:00Z" });
+        let rec = IndexRecord::from_json_with_id("/sale.html".into(), &doc);
+        assert_eq!(
+            rec.publish.unpublish_at.as_deref(),
+            Some("2030-06-01T00:00:00Z")
+        );
+
+        let before = Utc.with_ymd_and_hms(2030, 5, 31, 23, 59, 59).unwrap();
+        let at = Utc.with_ymd_and_hms(2030, 6, 1, 0, 0, 0).unwrap();
+        assert!(rec.is_live_at(before));
+        assert!(!rec.is_live_at(at));
+
+        // Clearing the field (or an unparseable value) keeps it published.
+        let mut cleared = rec.clone();
+        cleared.publish.unpublish_at = None;
+        assert!(cleared.is_live_at(at));
+        cleared.publish.unpublish_at = Some("someday".into());
+        assert!(cleared.is_live_at(at));
+    }
+
+    #[test]
+    fn indexrecord_is_hidden_until_its_publish_date() {
+        let doc = json!({ "publish": { "date": "2030-06-01T09:00:00+02:00" } });
+        let rec = IndexRecord::from_json_with_id("/launch.html".into(), &doc);
+
+        let before = Utc.with_ymd_and_hms(2030, 6, 1, 6, 59, 59).unwrap();
+        let at = Utc.with_ymd_and_hms(2030, 6, 1, 7, 0, 0).unwrap();
+        assert!(!rec.is_live_at(before));
+        assert!(rec.is_live_at(at));
+        assert_eq!(rec.publish.publish_at(), Some(at));
+
+        // Published and expired at once when the dates cross.
+        let mut both = rec.clone();
+        both.publish.unpublish_at = Some("2030-06-01T07:00:00Z".into());
+        assert!(!both.is_live_at(at));
+    }
+
     #[test]
     fn dyn_partial_cmp_for_scalar_fields() {
         let mut rec = IndexRecord::default();
@@ -707,6 +817,51 @@ mod tests {
         assert_eq!(hits2.len(), 2);
     }
 
+    #[tokio::test]
+    async fn lookup_text_intersects_the_words_of_titles_and_summaries() {
+        let r1 = IndexRecord {
+            id: "1".into(),
+            content: ContentFields {
+                title: Some("Async Rust in practice".into()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let r2 = IndexRecord {
+            id: "2".into(),
+            content: ContentFields {
+                title: Some("Rust for the web".into()),
+                summary: Some("Compiling RUST to WASM".into()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let shared = new_db_with_records(vec![r1, r2]).await;
+        let cfg = IndexConfig::new(["content.title", "content.summary"]);
+        let backend = IndexedJsonIndexBackend::new(shared, cfg);
+        let words = |ws: &[&str]| ws.iter().map(|w| w.to_string()).collect::<Vec<_>>();
+
+        let rust = backend
+            .lookup_text("content.title", &words(&["rust"]))
+            .await
+            .unwrap();
+        assert_eq!(rust.len(), 2);
+        let async_rust = backend
+            .lookup_text("content.title", &words(&["rust", "async"]))
+            .await
+            .unwrap();
+        assert_eq!(async_rust.len(), 1);
+        let wasm = backend
+            .lookup_text("content.summary", &words(&["wasm", "rust"]))
+            .await
+            .unwrap();
+        assert_eq!(wasm.len(), 1);
+
+        // no word index for slugs
+        assert!(backend.lookup_text("slug", &words(&["a"])).await.is_none());
+    }
+
     #[tokio::test]
     async fn lookup_range_builds_correct_queries_and_respects_indexconfig() {
         let mut r1 = IndexRecord::default();
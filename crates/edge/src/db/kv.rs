@@ -0,0 +1,152 @@
+// crates/edge/src/db/kv.rs
+
+// Plugin key-value storage (adapt::js::storage), one JSON object per plugin:
+//
+//   <root>/counter.json   {"hits":42,"cache:/about":{"html":"…"}}
+//
+// The namespace is the calling plugin's id, which the engine supplies; JS
+// only names keys. There is no ops database to hold a namespaced table yet,
+// so a namespace is loaded on first use, kept in memory, and rewritten
+// atomically after every change. Ids that are not safe file names are
+// refused rather than escaped.
+
+use crate::db::history::write_atomic;
+use adapt::js::KvStore;
+use serde_json::Value as Json;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use thiserror::Error;
+
+/// Plugin storage inside the site directory.
+pub const PLUGIN_KV_DIR: &str = "./plugin_kv/";
+
+#[derive(Debug, Error)]
+pub enum KvStoreError {
+    #[error("I/O: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("JSON: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("plugin id '{0}' cannot name a storage namespace")]
+    Namespace(String),
+}
+
+type Namespace = BTreeMap<String, Json>;
+
+#[derive(Debug)]
+pub struct FileKvStore {
+    root: PathBuf,
+    loaded: Mutex<HashMap<String, Namespace>>,
+}
+
+impl FileKvStore {
+    pub fn new(root: PathBuf) -> Self {
+        Self {
+            root,
+            loaded: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn path(&self, namespace: &str) -> Result<PathBuf, KvStoreError> {
+        let safe = !namespace.is_empty()
+            && !namespace.starts_with('.')
+            && namespace
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b"-_.".contains(&b));
+        if !safe {
+            return Err(KvStoreError::Namespace(namespace.to_owned()));
+        }
+        Ok(self.root.join(format!("{namespace}.json")))
+    }
+
+    /// Run `f` on `namespace`, loading it first if needed; write it back
+    /// when `f` reports a change.
+    fn with_namespace<T>(
+        &self,
+        namespace: &str,
+        f: impl FnOnce(&mut Namespace) -> (T, bool),
+    ) -> Result<T, KvStoreError> {
+        let path = self.path(namespace)?;
+        let mut loaded = self.loaded.lock().unwrap_or_else(|e| e.into_inner());
+        if !loaded.contains_key(namespace) {
+            let values = match fs::read(&path) {
+                Ok(bytes) => serde_json::from_slice(&bytes)?,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Namespace::new(),
+                Err(e) => return Err(e.into()),
+            };
+            loaded.insert(namespace.to_owned(), values);
+        }
+        let values = loaded.get_mut(namespace).expect("loaded above");
+        let (out, changed) = f(values);
+        if changed {
+            fs::create_dir_all(&self.root)?;
+            write_atomic(&path, &serde_json::to_vec(values)?)?;
+        }
+        Ok(out)
+    }
+}
+
+impl KvStore for FileKvStore {
+    fn get(&self, namespace: &str, key: &str) -> Result<Option<Json>, String> {
+        self.with_namespace(namespace, |ns| (ns.get(key).cloned(), false))
+            .map_err(|e| e.to_string())
+    }
+
+    fn set(&self, namespace: &str, key: &str, value: Json) -> Result<(), String> {
+        self.with_namespace(namespace, |ns| {
+            let changed = ns.get(key) != Some(&value);
+            ns.insert(key.to_owned(), value);
+            ((), changed)
+        })
+        .map_err(|e| e.to_string())
+    }
+
+    fn delete(&self, namespace: &str, key: &str) -> Result<bool, String> {
+        self.with_namespace(namespace, |ns| {
+            let removed = ns.remove(key).is_some();
+            (removed, removed)
+        })
+        .map_err(|e| e.to_string())
+    }
+
+    fn list(&self, namespace: &str, prefix: &str) -> Result<Vec<String>, String> {
+        self.with_namespace(namespace, |ns| {
+            let keys = ns.keys().filter(|k| k.starts_with(prefix)).cloned();
+            (keys.collect(), false)
+        })
+        .map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn namespaces_persist_across_stores_and_stay_apart() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileKvStore::new(dir.path().to_path_buf());
+        store.set("counter", "hits", json!(2)).unwrap();
+        store
+            .set("counter", "cache:/a", json!({ "html": "a" }))
+            .unwrap();
+        store.set("other", "hits", json!("theirs")).unwrap();
+        assert!(store.delete("counter", "cache:/a").unwrap());
+        assert!(!store.delete("counter", "cache:/a").unwrap());
+
+        let reopened = FileKvStore::new(dir.path().to_path_buf());
+        assert_eq!(reopened.get("counter", "hits").unwrap(), Some(json!(2)));
+        assert_eq!(
+            reopened.get("other", "hits").unwrap(),
+            Some(json!("theirs"))
+        );
+        assert_eq!(reopened.list("counter", "").unwrap(), vec!["hits"]);
+        assert!(reopened.get("../counter", "hits").is_err());
+        assert!(reopened.set(".hidden", "k", json!(1)).is_err());
+    }
+}
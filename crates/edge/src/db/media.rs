@@ -0,0 +1,309 @@
+// crates/edge/src/db/media.rs
+
+// The media library: uploaded files plus one metadata record each.
+//
+// Files are stored by content hash below the media directory, so they are
+// served (and resized) by the `/media` routes like any other original:
+//
+//   <media_dir>/uploads/<id[..2]>/<id>.<ext>   the file
+//   <root>/<id>.json                           its MediaRecord
+//
+// Only images, PDFs, audio and video keep their extension; anything else,
+// HTML and SVG included, is stored as `.bin`, so an upload can never be
+// served as a page on the site's own origin.
+//
+// The id is the file's SHA-256, which makes a second upload of the same
+// bytes a no-op apart from attaching it to one more post. Like the
+// configuration history and the outbox, records are plain JSON files; the
+// library queries them with MQL through the in-memory store and index.
+
+use crate::db::history::write_atomic;
+use crate::db::mem::{InMemoryIndexBackend, InMemoryJsonStore};
+use adapt::mql::{execute_query, Filter, FindOptions, IndexConfig, QueryError};
+use chrono::{DateTime, Utc};
+use domain::hex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+use serve::images::dimensions;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Library records inside the site directory.
+pub const MEDIA_DB_DIR: &str = "./media_db/";
+
+/// Uploads inside the media directory.
+pub const UPLOADS_DIR: &str = "uploads";
+
+/// Extensions an upload keeps: images, PDF, audio and video.
+const MEDIA_EXTENSIONS: &[&str] = &[
+    "avif", "gif", "jpeg", "jpg", "png", "webp", "pdf", "aac", "flac", "m4a", "mp3", "oga", "ogg",
+    "opus", "wav", "m4v", "mov", "mp4", "ogv", "webm",
+];
+
+/// What a file stored as `.bin` is recorded as.
+const OPAQUE_CONTENT_TYPE: &str = "application/octet-stream";
+
+/// Record fields with an equality index. `attached_to` is an array, which
+/// MQL matches with `$all` rather than by equality, so it is not indexed.
+const INDEXED_FIELDS: [&str; 2] = ["id", "content_type"];
+
+#[derive(Debug, Error)]
+pub enum MediaError {
+    #[error("I/O: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("JSON: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Query: {0}")]
+    Query(#[from] QueryError),
+}
+
+/// Metadata of one uploaded file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MediaRecord {
+    /// Hex SHA-256 of the content.
+    pub id: String,
+    /// URL path the file is served at, e.g. `/media/uploads/ab/ab12….jpg`.
+    pub path: String,
+    /// File name as uploaded.
+    pub original_name: String,
+    pub content_type: String,
+    pub size: u64,
+    /// Intrinsic size in pixels, for images.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    pub uploaded_at: DateTime<Utc>,
+    /// Paths of the posts using this file.
+    #[serde(default)]
+    pub attached_to: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MediaLibrary {
+    root: PathBuf,
+    media_dir: PathBuf,
+}
+
+impl MediaLibrary {
+    /// A library with records in `root` and files in `media_dir`; both are
+    /// created on the first upload.
+    pub fn new(root: impl Into<PathBuf>, media_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            media_dir: media_dir.into(),
+        }
+    }
+
+    /// Store `bytes` and return its record, `true` when it is new. A known
+    /// file keeps its record and only gains `attach_to`.
+    pub fn add(
+        &self,
+        bytes: &[u8],
+        original_name: &str,
+        content_type: &str,
+        attach_to: Option<&str>,
+    ) -> Result<(MediaRecord, bool), MediaError> {
+        let id = hex::encode(Sha256::digest(bytes));
+
+        if let Some(mut record) = self.get(&id)? {
+            if let Some(post) = attach_to {
+                if !record.attached_to.iter().any(|p| p == post) {
+                    record.attached_to.push(post.to_owned());
+                    self.save(&record)?;
+                }
+            }
+            return Ok((record, false));
+        }
+
+        let ext = extension_of(original_name);
+        let content_type = if ext == "bin" {
+            OPAQUE_CONTENT_TYPE
+        } else {
+            content_type
+        };
+        let rel = format!("{UPLOADS_DIR}/{}/{id}.{ext}", &id[..2]);
+        let file = self.media_dir.join(&rel);
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        write_atomic(&file, bytes)?;
+        let size = dimensions(&file).ok();
+
+        let record = MediaRecord {
+            path: format!("/media/{rel}"),
+            original_name: original_name.to_owned(),
+            content_type: content_type.to_owned(),
+            size: bytes.len() as u64,
+            width: size.map(|(w, _)| w),
+            height: size.map(|(_, h)| h),
+            uploaded_at: Utc::now(),
+            attached_to: attach_to.map(str::to_owned).into_iter().collect(),
+            id,
+        };
+        self.save(&record)?;
+        Ok((record, true))
+    }
+
+    pub fn get(&self, id: &str) -> Result<Option<MediaRecord>, MediaError> {
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Ok(None);
+        }
+        match fs::read(self.root.join(format!("{id}.json"))) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Every record, in no particular order.
+    pub fn all(&self) -> Result<Vec<MediaRecord>, MediaError> {
+        let entries = match fs::read_dir(&self.root) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut out = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|e| e == "json") {
+                out.push(serde_json::from_slice(&fs::read(path)?)?);
+            }
+        }
+        Ok(out)
+    }
+
+    /// Records matching `filter`, as JSON, with the number of matches before
+    /// `skip` and `limit` were applied.
+    pub async fn query(
+        &self,
+        filter: &Filter,
+        opts: &FindOptions,
+    ) -> Result<(usize, Vec<Json>), MediaError> {
+        let docs = self
+            .all()?
+            .iter()
+            .map(serde_json::to_value)
+            .collect::<Result<Vec<_>, _>>()?;
+        let store = InMemoryJsonStore::new(docs);
+        let config = IndexConfig::new(INDEXED_FIELDS);
+        let index = InMemoryIndexBackend::build(&config, &store).await;
+
+        let unpaged = FindOptions {
+            skip: None,
+            limit: None,
+            ..opts.clone()
+        };
+        let matches = execute_query(&config, &store, &index, filter, &unpaged).await?;
+        let total = matches.len();
+        let items = matches
+            .into_iter()
+            .skip(opts.skip.unwrap_or(0))
+            .take(opts.limit.unwrap_or(usize::MAX))
+            .map(|r| r.doc)
+            .collect();
+        Ok((total, items))
+    }
+
+    fn save(&self, record: &MediaRecord) -> Result<(), MediaError> {
+        fs::create_dir_all(&self.root)?;
+        write_atomic(
+            &self.root.join(format!("{}.json", record.id)),
+            &serde_json::to_vec_pretty(record)?,
+        )?;
+        Ok(())
+    }
+}
+
+/// The lower-case extension of `name` when it is one of
+/// `MEDIA_EXTENSIONS`, else `bin`.
+fn extension_of(name: &str) -> String {
+    Path::new(name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase)
+        .filter(|e| MEDIA_EXTENSIONS.contains(&e.as_str()))
+        .unwrap_or_else(|| "bin".to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use adapt::mql::parser::parse_filter;
+    use serde_json::json;
+
+    fn library() -> (tempfile::TempDir, MediaLibrary) {
+        let dir = tempfile::tempdir().unwrap();
+        let lib = MediaLibrary::new(dir.path().join("media_db"), dir.path().join("media"));
+        assert!(lib.all().unwrap().is_empty());
+        (dir, lib)
+    }
+
+    #[test]
+    fn the_same_bytes_are_stored_once_and_attached_to_every_post() {
+        let (dir, lib) = library();
+
+        let (first, created) = lib
+            .add(b"png bytes", "Cat Photo.PNG", "image/png", Some("/blog/a"))
+            .unwrap();
+        assert!(created);
+        assert!(first.path.starts_with("/media/uploads/"));
+        assert!(first.path.ends_with(".png"));
+        let file = dir
+            .path()
+            .join("media")
+            .join(first.path.trim_start_matches("/media/"));
+        assert_eq!(fs::read(file).unwrap(), b"png bytes");
+
+        let (again, created) = lib
+            .add(b"png bytes", "copy.png", "image/png", Some("/blog/b"))
+            .unwrap();
+        assert!(!created);
+        assert_eq!(again.id, first.id);
+        assert_eq!(again.original_name, "Cat Photo.PNG");
+        assert_eq!(again.attached_to, ["/blog/a", "/blog/b"]);
+        assert_eq!(lib.all().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn records_are_queried_with_mql() {
+        let (_dir, lib) = library();
+        lib.add(b"one", "one.jpg", "image/jpeg", Some("/blog/a"))
+            .unwrap();
+        lib.add(b"two", "two.pdf", "application/pdf", Some("/blog/a"))
+            .unwrap();
+        lib.add(b"three", "three.jpg", "image/jpeg", Some("/blog/b"))
+            .unwrap();
+
+        let filter = parse_filter(&json!({ "attached_to": { "$all": ["/blog/a"] } })).unwrap();
+        let opts = FindOptions {
+            sort: vec![("original_name".into(), 1)],
+            limit: Some(1),
+            ..FindOptions::default()
+        };
+        let (total, items) = lib.query(&filter, &opts).await.unwrap();
+        assert_eq!(total, 2);
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0]["original_name"], "one.jpg");
+
+        let images = parse_filter(&json!({ "content_type": "image/jpeg" })).unwrap();
+        let (total, _) = lib.query(&images, &FindOptions::default()).await.unwrap();
+        assert_eq!(total, 2);
+    }
+
+    #[test]
+    fn extensions_are_sanitised() {
+        assert_eq!(extension_of("a.JPG"), "jpg");
+        assert_eq!(extension_of("noext"), "bin");
+        assert_eq!(extension_of("x.p/h"), "bin");
+        assert_eq!(extension_of("x.toolongextension"), "bin");
+        assert_eq!(extension_of("x.html"), "bin");
+        assert_eq!(extension_of("x.SVG"), "bin");
+        assert_eq!(extension_of("clip.webm"), "webm");
+    }
+}
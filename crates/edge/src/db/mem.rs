@@ -1,3 +1,4 @@
+use adapt::mql::text::tokenize_json;
 use adapt::mql::{IndexBackend, IndexConfig, JsonStore};
 use async_trait::async_trait;
 use serde_json::Value as Json;
@@ -62,6 +63,7 @@ fn value_to_index_key(v: &Json) -> String {
 ///
 /// For each indexed field, we build:
 ///   field -> (value string) -> set of IDs
+///   field -> word -> set of IDs     (string fields, for `$text`)
 ///
 /// NOTE: we index the JSON's string representation for equality/membership.
 /// For a disk-backed integration, you’d replace this implementation with one
@@ -69,6 +71,7 @@ fn value_to_index_key(v: &Json) -> String {
 #[derive(Debug, Clone)]
 pub struct InMemoryIndexBackend {
     pub field_value_to_ids: HashMap<String, HashMap<String, HashSet<usize>>>,
+    pub field_word_to_ids: HashMap<String, HashMap<String, HashSet<usize>>>,
 }
 
 impl InMemoryIndexBackend {
@@ -78,6 +81,8 @@ impl InMemoryIndexBackend {
 
         let mut field_value_to_ids: HashMap<String, HashMap<String, HashSet<usize>>> =
             HashMap::new();
+        let mut field_word_to_ids: HashMap<String, HashMap<String, HashSet<usize>>> =
+            HashMap::new();
 
         for (id, doc) in store.docs.iter().enumerate() {
             for field in config.fields() {
@@ -86,11 +91,19 @@ impl InMemoryIndexBackend {
                     let field_map = field_value_to_ids.entry(field.to_string()).or_default();
                     let ids = field_map.entry(key).or_default();
                     ids.insert(id);
+
+                    for word in tokenize_json(value) {
+                        let word_map = field_word_to_ids.entry(field.to_string()).or_default();
+                        word_map.entry(word).or_default().insert(id);
+                    }
                 }
             }
         }
 
-        Self { field_value_to_ids }
+        Self {
+            field_value_to_ids,
+            field_word_to_ids,
+        }
     }
 }
 
@@ -129,6 +142,19 @@ impl IndexBackend for InMemoryIndexBackend {
         // range queries not supported by in-memory backend (yet)
         None
     }
+
+    async fn lookup_text(&self, field: &str, words: &[String]) -> Option<HashSet<Self::Id>> {
+        let word_map = self.field_word_to_ids.get(field)?;
+        let mut acc: Option<HashSet<Self::Id>> = None;
+        for word in words {
+            let ids = word_map.get(word).cloned().unwrap_or_default();
+            acc = Some(match acc {
+                None => ids,
+                Some(acc) => acc.intersection(&ids).copied().collect(),
+            });
+        }
+        acc
+    }
 }
 
 #[cfg(test)]
@@ -407,4 +433,38 @@ mod tests {
             .await
             .is_none());
     }
+
+    #[tokio::test]
+    async fn lookup_text_matches_every_word() {
+        let docs = vec![
+            json!({ "title": "Async Rust" }),
+            json!({ "title": "Rust and WASM" }),
+            json!({ "title": 7 }),
+        ];
+
+        let store = InMemoryJsonStore::new(docs);
+        let config = IndexConfig::new(["title"]);
+        let backend = InMemoryIndexBackend::build(&config, &store).await;
+        let words = |ws: &[&str]| ws.iter().map(|w| w.to_string()).collect::<Vec<_>>();
+
+        let mut rust: Vec<_> = backend
+            .lookup_text("title", &words(&["rust"]))
+            .await
+            .unwrap()
+            .into_iter()
+            .collect();
+        rust.sort_unstable();
+        assert_eq!(rust, vec![0, 1]);
+
+        let none = backend
+            .lookup_text("title", &words(&["rust", "go"]))
+            .await
+            .unwrap();
+        assert!(none.is_empty());
+
+        assert!(backend
+            .lookup_text("nonexistent_field", &words(&["rust"]))
+            .await
+            .is_none());
+    }
 }
@@ -0,0 +1,395 @@
+// crates/edge/src/db/migrate.rs
+
+// Versioned changes to the files a site keeps, applied in order and recorded
+// in `schema_migrations.json`:
+//
+//   [{"version":1,"name":"image_derivatives_by_source",
+//     "applied_at":"2026-01-02T03:04:05Z"}]
+//
+// The stores are files, so migrations are Rust functions over the site
+// directory rather than SQL; each has an `up` and, when it can be undone, a
+// `down`. `start` applies whatever is pending before anything opens a store,
+// and `whispercms migrate` shows, previews, applies and reverts them.
+//
+// A migration that fails stops the run: the ones before it stay recorded, it
+// and the ones after it stay pending, and the error names it. A site whose
+// record lists a version this core does not know was migrated by a newer
+// core, and is left alone.
+
+use super::history::write_atomic;
+use chrono::{DateTime, Utc};
+use domain::setting::Settings;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use tracing::info;
+
+/// Applied migrations inside the site directory.
+pub const MIGRATIONS_FILE: &str = "./schema_migrations.json";
+
+#[derive(Debug, Error)]
+pub enum MigrateError {
+    #[error("I/O: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("JSON: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("migration {version} ({name}) failed: {source}; earlier migrations were applied")]
+    Failed {
+        version: u32,
+        name: &'static str,
+        source: io::Error,
+    },
+
+    #[error("migration {version} ({name}) cannot be undone")]
+    Irreversible { version: u32, name: &'static str },
+
+    #[error("migration {version} ({name}) was applied by a newer core")]
+    Newer { version: u32, name: String },
+
+    #[error("there is no migration {0}")]
+    UnknownVersion(u32),
+}
+
+/// What a migration works on.
+#[derive(Debug, Clone, Copy)]
+pub struct Site<'a> {
+    pub dir: &'a Path,
+    pub settings: Option<&'a Settings>,
+}
+
+type Step = fn(&Site) -> io::Result<()>;
+
+/// One change to a site's files.
+#[derive(Debug, Clone, Copy)]
+pub struct Migration {
+    pub version: u32,
+    pub name: &'static str,
+    up: Step,
+    down: Option<Step>,
+}
+
+impl Migration {
+    pub const fn new(version: u32, name: &'static str, up: Step, down: Option<Step>) -> Self {
+        Self {
+            version,
+            name,
+            up,
+            down,
+        }
+    }
+
+    pub fn reversible(&self) -> bool {
+        self.down.is_some()
+    }
+}
+
+/// Every migration, oldest first; versions only ever grow.
+pub const MIGRATIONS: &[Migration] = &[Migration::new(
+    1,
+    "image_derivatives_by_source",
+    drop_flat_derivatives,
+    Some(drop_source_derivatives),
+)];
+
+/// Image derivatives moved into one folder per original so an original's
+/// can be purged together; the flat ones would never be read again.
+fn drop_flat_derivatives(site: &Site) -> io::Result<()> {
+    for entry in derivatives(site)? {
+        if entry.file_type()?.is_file() {
+            fs::remove_file(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+/// Older cores only read flat derivatives, and render them again.
+fn drop_source_derivatives(site: &Site) -> io::Result<()> {
+    for entry in derivatives(site)? {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if entry.file_type()?.is_dir()
+            && name.len() == 32
+            && name.bytes().all(|b| b.is_ascii_hexdigit())
+        {
+            fs::remove_dir_all(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+/// What the image derivative folder holds; nothing without `[images]`.
+fn derivatives(site: &Site) -> io::Result<Vec<fs::DirEntry>> {
+    let Some(images) = site.settings.and_then(|s| s.images.as_ref()) else {
+        return Ok(Vec::new());
+    };
+    match fs::read_dir(site.dir.join(&images.cache_dir)) {
+        Ok(entries) => entries.collect(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// One line of `schema_migrations.json`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Applied {
+    pub version: u32,
+    pub name: String,
+    pub applied_at: DateTime<Utc>,
+}
+
+/// A migration and when it was applied, if it was.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Status {
+    pub version: u32,
+    pub name: String,
+    pub reversible: bool,
+    pub applied_at: Option<DateTime<Utc>>,
+}
+
+/// Applies and reverts `migrations` on one site.
+pub struct Migrator<'a> {
+    site: Site<'a>,
+    path: PathBuf,
+    migrations: &'a [Migration],
+}
+
+impl<'a> Migrator<'a> {
+    /// The core's migrations for the site in `dir`.
+    pub fn new(dir: &'a Path, settings: Option<&'a Settings>) -> Self {
+        Self::with(dir, settings, MIGRATIONS)
+    }
+
+    pub fn with(
+        dir: &'a Path,
+        settings: Option<&'a Settings>,
+        migrations: &'a [Migration],
+    ) -> Self {
+        Self {
+            site: Site { dir, settings },
+            path: dir.join(MIGRATIONS_FILE),
+            migrations,
+        }
+    }
+
+    /// What has been applied, oldest first; empty before the first run.
+    pub fn applied(&self) -> Result<Vec<Applied>, MigrateError> {
+        match fs::read(&self.path) {
+            Ok(bytes) => {
+                let mut applied: Vec<Applied> = serde_json::from_slice(&bytes)?;
+                applied.sort_by_key(|a| a.version);
+                Ok(applied)
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Every known migration, and any applied one this core does not know.
+    pub fn status(&self) -> Result<Vec<Status>, MigrateError> {
+        let applied = self.applied()?;
+        let mut status: Vec<Status> = self
+            .migrations
+            .iter()
+            .map(|m| Status {
+                version: m.version,
+                name: m.name.to_owned(),
+                reversible: m.reversible(),
+                applied_at: applied
+                    .iter()
+                    .find(|a| a.version == m.version)
+                    .map(|a| a.applied_at),
+            })
+            .collect();
+        status.extend(
+            applied
+                .iter()
+                .filter(|a| self.known(a.version).is_none())
+                .map(|a| Status {
+                    version: a.version,
+                    name: a.name.clone(),
+                    reversible: false,
+                    applied_at: Some(a.applied_at),
+                }),
+        );
+        status.sort_by_key(|s| s.version);
+        Ok(status)
+    }
+
+    /// Apply pending migrations up to `target` (all without one), oldest
+    /// first; with `dry_run`, only say which.
+    pub fn up(&self, target: Option<u32>, dry_run: bool) -> Result<Vec<Migration>, MigrateError> {
+        if let Some(t) = target {
+            self.known(t).ok_or(MigrateError::UnknownVersion(t))?;
+        }
+        let mut applied = self.applied()?;
+        self.refuse_newer(&applied)?;
+        let pending: Vec<Migration> = self
+            .migrations
+            .iter()
+            .filter(|m| target.is_none_or(|t| m.version <= t))
+            .filter(|m| !applied.iter().any(|a| a.version == m.version))
+            .copied()
+            .collect();
+        if dry_run {
+            return Ok(pending);
+        }
+        for m in &pending {
+            (m.up)(&self.site).map_err(|source| MigrateError::Failed {
+                version: m.version,
+                name: m.name,
+                source,
+            })?;
+            applied.push(Applied {
+                version: m.version,
+                name: m.name.to_owned(),
+                applied_at: Utc::now(),
+            });
+            self.save(&applied)?;
+            info!("Applied migration {} ({})", m.version, m.name);
+        }
+        Ok(pending)
+    }
+
+    /// Revert applied migrations newer than `target`, newest first; with
+    /// `dry_run`, only say which.
+    pub fn down(&self, target: u32, dry_run: bool) -> Result<Vec<Migration>, MigrateError> {
+        if target != 0 {
+            self.known(target)
+                .ok_or(MigrateError::UnknownVersion(target))?;
+        }
+        let mut applied = self.applied()?;
+        self.refuse_newer(&applied)?;
+        let mut undo: Vec<Migration> = applied
+            .iter()
+            .filter(|a| a.version > target)
+            .filter_map(|a| self.known(a.version))
+            .collect();
+        undo.reverse();
+        if let Some(m) = undo.iter().find(|m| !m.reversible()) {
+            return Err(MigrateError::Irreversible {
+                version: m.version,
+                name: m.name,
+            });
+        }
+        if dry_run {
+            return Ok(undo);
+        }
+        for m in &undo {
+            let down = m.down.expect("checked reversible");
+            down(&self.site).map_err(|source| MigrateError::Failed {
+                version: m.version,
+                name: m.name,
+                source,
+            })?;
+            applied.retain(|a| a.version != m.version);
+            self.save(&applied)?;
+            info!("Reverted migration {} ({})", m.version, m.name);
+        }
+        Ok(undo)
+    }
+
+    fn known(&self, version: u32) -> Option<Migration> {
+        self.migrations
+            .iter()
+            .find(|m| m.version == version)
+            .copied()
+    }
+
+    fn refuse_newer(&self, applied: &[Applied]) -> Result<(), MigrateError> {
+        match applied.iter().find(|a| self.known(a.version).is_none()) {
+            Some(a) => Err(MigrateError::Newer {
+                version: a.version,
+                name: a.name.clone(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    fn save(&self, applied: &[Applied]) -> Result<(), MigrateError> {
+        Ok(write_atomic(
+            &self.path,
+            &serde_json::to_vec_pretty(applied)?,
+        )?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn touch(site: &Site) -> io::Result<()> {
+        fs::write(site.dir.join("touched"), "")
+    }
+
+    fn untouch(site: &Site) -> io::Result<()> {
+        fs::remove_file(site.dir.join("touched"))
+    }
+
+    fn fail(_: &Site) -> io::Result<()> {
+        Err(io::Error::other("disk full"))
+    }
+
+    #[test]
+    fn migrations_apply_in_order_record_and_revert() {
+        let dir = tempfile::tempdir().unwrap();
+        let ok = [
+            Migration::new(1, "touch", touch, Some(untouch)),
+            Migration::new(2, "noop", |_| Ok(()), None),
+        ];
+        let migrator = Migrator::with(dir.path(), None, &ok);
+
+        let planned = migrator.up(None, true).unwrap();
+        assert_eq!(
+            planned.iter().map(|m| m.version).collect::<Vec<_>>(),
+            [1, 2]
+        );
+        assert!(migrator.applied().unwrap().is_empty());
+
+        assert_eq!(migrator.up(Some(1), false).unwrap().len(), 1);
+        assert!(dir.path().join("touched").exists());
+        let status = migrator.status().unwrap();
+        assert!(status[0].applied_at.is_some() && status[1].applied_at.is_none());
+
+        assert_eq!(migrator.up(None, false).unwrap().len(), 1);
+        assert!(migrator.up(None, false).unwrap().is_empty());
+        assert!(matches!(
+            migrator.down(0, false),
+            Err(MigrateError::Irreversible { version: 2, .. })
+        ));
+
+        // A failure stops the run and leaves the rest pending.
+        let failing = [
+            ok[0],
+            ok[1],
+            Migration::new(3, "fails", fail, None),
+            Migration::new(4, "after", touch, None),
+        ];
+        let migrator = Migrator::with(dir.path(), None, &failing);
+        assert!(matches!(
+            migrator.up(None, false),
+            Err(MigrateError::Failed { version: 3, .. })
+        ));
+        assert_eq!(migrator.applied().unwrap().len(), 2);
+
+        // An older core leaves a site it does not understand alone.
+        let older = Migrator::with(dir.path(), None, &ok[..1]);
+        assert!(matches!(
+            older.up(None, false),
+            Err(MigrateError::Newer { version: 2, .. })
+        ));
+
+        let first = Migrator::with(dir.path(), None, &ok[..1]);
+        fs::write(
+            dir.path().join(MIGRATIONS_FILE),
+            serde_json::to_vec(&first.applied().unwrap()[..1]).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(first.down(0, false).unwrap().len(), 1);
+        assert!(!dir.path().join("touched").exists());
+        assert!(first.applied().unwrap().is_empty());
+    }
+}
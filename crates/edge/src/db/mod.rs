@@ -1,3 +1,18 @@
+pub mod audit;
+pub mod collections;
+pub mod comments;
+pub mod ext_settings;
+pub mod forms;
+pub mod history;
 pub mod json;
+pub mod kv;
+pub mod media;
 pub mod mem;
+pub mod migrate;
+pub mod ops;
+pub mod outbox;
+pub mod redirects;
+pub mod revisions;
+pub mod store;
 pub mod tantivy;
+pub mod users;
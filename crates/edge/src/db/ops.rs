@@ -0,0 +1,341 @@
+// crates/edge/src/db/ops.rs
+
+// How long operational tasks took, one JSON object per line:
+//
+//   {"at":"2026-01-02T03:04:05Z","version":"0.1.0","operation":"start",
+//    "step":"scan_content","ms":1840,"outcome":"ok"}
+//   {"at":"2026-01-02T04:00:00Z","version":"0.1.0",
+//    "operation":"index.rebuild","ms":2210,"outcome":"failed",
+//    "error":"I/O: permission denied"}
+//
+// Each startup step is one entry under `start`; index rebuilds, extension
+// reloads, imports and rollbacks get one each. Entries carry the core
+// version, so `summarize` can show a step getting slower after an upgrade.
+// Like the audit log, lines are only appended and `query` runs MQL over them.
+
+use adapt::mql::{execute_query, Filter, FindOptions, IndexConfig, QueryError};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use thiserror::Error;
+use tracing::warn;
+
+use super::mem::{InMemoryIndexBackend, InMemoryJsonStore};
+
+/// Operations history inside the site directory.
+pub const OPS_LOG_FILE: &str = "./ops.jsonl";
+
+/// Version recorded with every entry.
+pub const CORE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+const INDEXED_FIELDS: [&str; 4] = ["operation", "step", "version", "outcome"];
+
+#[derive(Debug, Error)]
+pub enum OpsError {
+    #[error("I/O: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("JSON: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Query: {0}")]
+    Query(#[from] QueryError),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Ok,
+    Failed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OpEntry {
+    /// When the operation (or step) began.
+    pub at: DateTime<Utc>,
+    /// Core version that ran it.
+    pub version: String,
+    /// e.g. `start`, `index.rebuild`, `plugins.reload`, `import.wordpress`.
+    pub operation: String,
+    /// Part of a multi-step operation, e.g. `scan_content` during `start`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub step: Option<String>,
+    pub ms: u64,
+    pub outcome: Outcome,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl OpEntry {
+    /// A successful run of `operation` that began at `at` and ends now.
+    pub fn new(operation: impl Into<String>, at: DateTime<Utc>) -> Self {
+        Self {
+            at,
+            version: CORE_VERSION.to_owned(),
+            operation: operation.into(),
+            step: None,
+            ms: (Utc::now() - at).num_milliseconds().max(0) as u64,
+            outcome: Outcome::Ok,
+            error: None,
+        }
+    }
+
+    pub fn with_step(mut self, step: impl Into<String>) -> Self {
+        self.step = Some(step.into());
+        self
+    }
+
+    pub fn failed(mut self, error: impl Display) -> Self {
+        self.outcome = Outcome::Failed;
+        self.error = Some(error.to_string());
+        self
+    }
+}
+
+/// Runs of one operation step on one version.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OpSummary {
+    pub operation: String,
+    pub step: Option<String>,
+    pub version: String,
+    pub runs: usize,
+    pub failures: usize,
+    pub mean_ms: u64,
+    pub max_ms: u64,
+    pub last_at: DateTime<Utc>,
+}
+
+/// Group `entries` by operation, step and version, in that order; versions
+/// appear in the order they were first recorded.
+pub fn summarize(entries: &[OpEntry]) -> Vec<OpSummary> {
+    let mut first_seen: Vec<&str> = Vec::new();
+    for e in entries {
+        if !first_seen.contains(&e.version.as_str()) {
+            first_seen.push(&e.version);
+        }
+    }
+    let seen = |v: &str| first_seen.iter().position(|s| *s == v).unwrap_or(0);
+
+    let mut groups: BTreeMap<(&str, Option<&str>, usize), Vec<&OpEntry>> = BTreeMap::new();
+    for e in entries {
+        groups
+            .entry((&e.operation, e.step.as_deref(), seen(&e.version)))
+            .or_default()
+            .push(e);
+    }
+
+    groups
+        .into_values()
+        .map(|runs| {
+            let total: u64 = runs.iter().map(|e| e.ms).sum();
+            OpSummary {
+                operation: runs[0].operation.clone(),
+                step: runs[0].step.clone(),
+                version: runs[0].version.clone(),
+                runs: runs.len(),
+                failures: runs.iter().filter(|e| e.outcome == Outcome::Failed).count(),
+                mean_ms: total / runs.len() as u64,
+                max_ms: runs.iter().map(|e| e.ms).max().unwrap_or(0),
+                last_at: runs.iter().map(|e| e.at).max().unwrap_or(runs[0].at),
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug)]
+pub struct OpsLog {
+    path: PathBuf,
+    /// Keeps concurrent writers from interleaving lines.
+    lock: Mutex<()>,
+}
+
+impl OpsLog {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Append `entry`.
+    pub fn record(&self, entry: &OpEntry) -> Result<(), OpsError> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(&line)?;
+        Ok(())
+    }
+
+    /// Record how `result` of an operation begun at `at` turned out, and
+    /// pass it on. A history that cannot be written is only logged.
+    pub fn finish<T, E: Display>(
+        &self,
+        operation: &str,
+        step: Option<&str>,
+        at: DateTime<Utc>,
+        result: Result<T, E>,
+    ) -> Result<T, E> {
+        let mut entry = OpEntry::new(operation, at);
+        if let Some(step) = step {
+            entry = entry.with_step(step);
+        }
+        if let Err(e) = &result {
+            entry = entry.failed(e);
+        }
+        if let Err(e) = self.record(&entry) {
+            warn!(
+                "Recording {} in the operations history failed: {}",
+                operation, e
+            );
+        }
+        result
+    }
+
+    /// Every entry, oldest first. Lines that do not parse are skipped.
+    pub fn entries(&self) -> Result<Vec<OpEntry>, OpsError> {
+        let file = match fs::File::open(&self.path) {
+            Ok(f) => f,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut entries = Vec::new();
+        for line in BufReader::new(file).lines() {
+            if let Ok(entry) = serde_json::from_str(&line?) {
+                entries.push(entry);
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Entries matching `filter`, newest first unless `opts` sorts, with
+    /// the number of matches before `skip` and `limit` were applied.
+    pub async fn query(
+        &self,
+        filter: &Filter,
+        opts: &FindOptions,
+    ) -> Result<(usize, Vec<Json>), OpsError> {
+        let docs = self
+            .entries()?
+            .iter()
+            .rev()
+            .map(serde_json::to_value)
+            .collect::<Result<Vec<_>, _>>()?;
+        let store = InMemoryJsonStore::new(docs);
+        let config = IndexConfig::new(INDEXED_FIELDS);
+        let index = InMemoryIndexBackend::build(&config, &store).await;
+
+        let unpaged = FindOptions {
+            skip: None,
+            limit: None,
+            ..opts.clone()
+        };
+        let mut matches = execute_query(&config, &store, &index, filter, &unpaged).await?;
+        if opts.sort.is_empty() {
+            // Store ids follow the reversed log.
+            matches.sort_by_key(|r| r.id);
+        }
+        let total = matches.len();
+        let items = matches
+            .into_iter()
+            .skip(opts.skip.unwrap_or(0))
+            .take(opts.limit.unwrap_or(usize::MAX))
+            .map(|r| r.doc)
+            .collect();
+        Ok((total, items))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use adapt::mql::parser::parse_filter;
+    use chrono::Duration;
+    use serde_json::json;
+
+    fn run(operation: &str, step: Option<&str>, version: &str, ms: u64) -> OpEntry {
+        OpEntry {
+            at: Utc::now(),
+            version: version.to_owned(),
+            operation: operation.to_owned(),
+            step: step.map(str::to_owned),
+            ms,
+            outcome: Outcome::Ok,
+            error: None,
+        }
+    }
+
+    #[actix_web::test]
+    async fn finished_operations_are_recorded_and_queried() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = OpsLog::new(dir.path().join("logs/ops.jsonl"));
+        let began = Utc::now() - Duration::milliseconds(40);
+
+        let ok: Result<u8, String> = log.finish("start", Some("scan_content"), began, Ok(1));
+        assert_eq!(ok, Ok(1));
+        let failed: Result<u8, String> =
+            log.finish("index.rebuild", None, began, Err("boom".into()));
+        assert!(failed.is_err());
+
+        let entries = log.entries().unwrap();
+        assert_eq!(entries[0].step.as_deref(), Some("scan_content"));
+        assert!(entries[0].ms >= 40);
+        assert_eq!(entries[1].outcome, Outcome::Failed);
+        assert_eq!(entries[1].error.as_deref(), Some("boom"));
+
+        let filter = parse_filter(&json!({ "outcome": "failed" })).unwrap();
+        let (total, items) = log.query(&filter, &FindOptions::default()).await.unwrap();
+        assert_eq!(total, 1);
+        assert_eq!(items[0]["operation"], "index.rebuild");
+        assert_eq!(items[0]["version"], CORE_VERSION);
+    }
+
+    #[test]
+    fn summaries_compare_a_step_across_versions() {
+        let mut broken = run("start", Some("scan_content"), "0.2.0", 900);
+        broken.outcome = Outcome::Failed;
+        let entries = [
+            run("start", Some("scan_content"), "0.1.0", 100),
+            run("start", Some("scan_content"), "0.1.0", 300),
+            run("index.rebuild", None, "0.1.0", 50),
+            run("start", Some("scan_content"), "0.2.0", 700),
+            broken,
+        ];
+
+        let summary = summarize(&entries);
+        let rows: Vec<_> = summary
+            .iter()
+            .map(|s| {
+                (
+                    s.operation.as_str(),
+                    s.version.as_str(),
+                    s.runs,
+                    s.failures,
+                    s.mean_ms,
+                    s.max_ms,
+                )
+            })
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                ("index.rebuild", "0.1.0", 1, 0, 50, 50),
+                ("start", "0.1.0", 2, 0, 200, 300),
+                ("start", "0.2.0", 2, 1, 800, 900),
+            ]
+        );
+    }
+}
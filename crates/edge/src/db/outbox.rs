@@ -0,0 +1,555 @@
+// crates/edge/src/db/outbox.rs
+
+// Durable outbox for outbound side effects (email, webhooks).
+//
+// Every delivery is one JSON file, so a queued message survives restarts:
+//
+//   <root>/staged/<id>.json   written, but its triggering change not yet done
+//   <root>/pending/<id>.json  waiting for (another) delivery attempt
+//   <root>/dead/<id>.json     gave up after `max_attempts`
+//
+// Files are written to `staged/` and moved with `rename`, which is atomic on
+// one filesystem, so readers never see a half-written entry. There is no ops
+// database to share a transaction with yet, so "transactional with the
+// triggering change" is approximated by staging: stage first, make the
+// change, then `commit()`. A staged entry that is dropped (the change failed)
+// is discarded; one left behind by a crash is discarded by a dispatcher once
+// it is `STALE_STAGE_AFTER` old, long past any change still running.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+const STAGED: &str = "staged";
+const PENDING: &str = "pending";
+const DEAD: &str = "dead";
+
+/// How old a staged entry must be before a dispatcher takes it for one a
+/// crash left behind.
+pub const STALE_STAGE_AFTER: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Error)]
+pub enum OutboxError {
+    #[error("I/O: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("JSON: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("No outbox entry {0}")]
+    NotFound(String),
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Entries
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// An outbound side effect.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Delivery {
+    Email {
+        to: Vec<String>,
+        subject: String,
+        body: String,
+    },
+    Webhook {
+        url: String,
+        #[serde(default)]
+        headers: BTreeMap<String, String>,
+        body: Json,
+    },
+}
+
+/// A queued delivery plus its retry bookkeeping.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutboxEntry {
+    pub id: String,
+    pub delivery: Delivery,
+    pub attempts: u32,
+    pub created_at: DateTime<Utc>,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+}
+
+/// Where an entry currently lives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutboxState {
+    Pending,
+    Dead,
+}
+
+impl OutboxState {
+    fn dir_name(self) -> &'static str {
+        match self {
+            OutboxState::Pending => PENDING,
+            OutboxState::Dead => DEAD,
+        }
+    }
+}
+
+/// Exponential backoff with a cap, and a dead-letter threshold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 8,
+            base_delay: Duration::from_secs(30),
+            max_delay: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before the next try after `attempts` failures (`base * 2^(n-1)`).
+    pub fn backoff(&self, attempts: u32) -> Duration {
+        let exp = attempts.saturating_sub(1).min(31);
+        self.base_delay
+            .saturating_mul(1u32 << exp)
+            .min(self.max_delay)
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Store
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// File-backed outbox rooted at a directory.
+#[derive(Debug, Clone)]
+pub struct Outbox {
+    root: PathBuf,
+    policy: RetryPolicy,
+}
+
+/// A delivery written to `staged/` whose triggering change is still running.
+///
+/// Call [`commit`](Self::commit) once the change has succeeded; dropping it
+/// instead discards the delivery.
+#[derive(Debug)]
+pub struct StagedDelivery<'a> {
+    outbox: &'a Outbox,
+    entry: Option<OutboxEntry>,
+}
+
+impl Outbox {
+    /// Open (creating if needed) an outbox at `root`.
+    pub fn open(root: impl Into<PathBuf>) -> Result<Self, OutboxError> {
+        let root = root.into();
+        for dir in [STAGED, PENDING, DEAD] {
+            fs::create_dir_all(root.join(dir))?;
+        }
+
+        Ok(Self {
+            root,
+            policy: RetryPolicy::default(),
+        })
+    }
+
+    pub fn with_policy(mut self, policy: RetryPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn policy(&self) -> &RetryPolicy {
+        &self.policy
+    }
+
+    /// Queue a delivery immediately.
+    pub fn enqueue(&self, delivery: Delivery) -> Result<OutboxEntry, OutboxError> {
+        self.stage(delivery)?.commit()
+    }
+
+    /// Write a delivery to `staged/` ahead of the change that triggers it.
+    pub fn stage(&self, delivery: Delivery) -> Result<StagedDelivery<'_>, OutboxError> {
+        let now = Utc::now();
+        let entry = OutboxEntry {
+            id: Uuid::new_v4().simple().to_string(),
+            delivery,
+            attempts: 0,
+            created_at: now,
+            next_attempt_at: now,
+            last_error: None,
+        };
+        self.write(STAGED, &entry)?;
+
+        Ok(StagedDelivery {
+            outbox: self,
+            entry: Some(entry),
+        })
+    }
+
+    /// Remove staged entries (and temp files) last written more than
+    /// `older_than` ago: their change never confirmed them, and no handler
+    /// holds one that long. Returns how many went.
+    pub fn discard_stale_stages(&self, older_than: Duration) -> Result<usize, OutboxError> {
+        let mut discarded = 0;
+        for entry in fs::read_dir(self.root.join(STAGED))? {
+            let entry = entry?;
+            let age = entry.metadata()?.modified()?.elapsed().unwrap_or_default();
+            if age < older_than {
+                continue;
+            }
+            warn!("Discarding unconfirmed outbox entry {:?}", entry.path());
+            match fs::remove_file(entry.path()) {
+                // Another dispatcher on the same outbox got there first.
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+                Ok(()) => discarded += 1,
+            }
+        }
+        Ok(discarded)
+    }
+
+    /// All entries in `state`, oldest first.
+    pub fn list(&self, state: OutboxState) -> Result<Vec<OutboxEntry>, OutboxError> {
+        let mut out = json_files(&self.root.join(state.dir_name()))?
+            .into_iter()
+            .map(|p| read_entry(&p))
+            .collect::<Result<Vec<_>, _>>()?;
+        out.sort_by(|a, b| (a.created_at, &a.id).cmp(&(b.created_at, &b.id)));
+        Ok(out)
+    }
+
+    /// Pending entries whose next attempt is due at `now`, soonest first.
+    pub fn due(&self, now: DateTime<Utc>) -> Result<Vec<OutboxEntry>, OutboxError> {
+        let mut out: Vec<_> = self
+            .list(OutboxState::Pending)?
+            .into_iter()
+            .filter(|e| e.next_attempt_at <= now)
+            .collect();
+        out.sort_by_key(|e| e.next_attempt_at);
+        Ok(out)
+    }
+
+    /// Remove a successfully delivered entry.
+    pub fn mark_delivered(&self, entry: &OutboxEntry) -> Result<(), OutboxError> {
+        match fs::remove_file(self.path(PENDING, &entry.id)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Record a failed attempt: reschedule with backoff, or dead-letter once
+    /// `max_attempts` is reached. Returns where the entry ended up.
+    pub fn mark_failed(
+        &self,
+        entry: &OutboxEntry,
+        error: &str,
+        now: DateTime<Utc>,
+    ) -> Result<OutboxState, OutboxError> {
+        let mut next = entry.clone();
+        next.attempts += 1;
+        next.last_error = Some(error.to_string());
+
+        if next.attempts >= self.policy.max_attempts {
+            self.write(DEAD, &next)?;
+            fs::remove_file(self.path(PENDING, &next.id))?;
+            return Ok(OutboxState::Dead);
+        }
+
+        let delay = chrono::Duration::from_std(self.policy.backoff(next.attempts))
+            .unwrap_or(chrono::Duration::MAX);
+        next.next_attempt_at = now.checked_add_signed(delay).unwrap_or(now);
+        self.write(PENDING, &next)?;
+        Ok(OutboxState::Pending)
+    }
+
+    /// Move a dead-lettered entry back to pending with a fresh attempt budget.
+    pub fn retry_dead(&self, id: &str) -> Result<OutboxEntry, OutboxError> {
+        let dead = self.path(DEAD, id);
+        if !dead.is_file() {
+            return Err(OutboxError::NotFound(id.to_string()));
+        }
+
+        let mut entry = read_entry(&dead)?;
+        entry.attempts = 0;
+        entry.next_attempt_at = Utc::now();
+        self.write(PENDING, &entry)?;
+        fs::remove_file(dead)?;
+        Ok(entry)
+    }
+
+    fn path(&self, dir: &str, id: &str) -> PathBuf {
+        self.root.join(dir).join(format!("{id}.json"))
+    }
+
+    /// Write `entry` into `dir` via a temp file + rename.
+    fn write(&self, dir: &str, entry: &OutboxEntry) -> Result<(), OutboxError> {
+        let tmp = self.root.join(STAGED).join(format!(".{}.tmp", entry.id));
+        {
+            let mut f = fs::File::create(&tmp)?;
+            f.write_all(&serde_json::to_vec_pretty(entry)?)?;
+            f.sync_all()?;
+        }
+        fs::rename(&tmp, self.path(dir, &entry.id))?;
+        Ok(())
+    }
+}
+
+impl StagedDelivery<'_> {
+    /// Make the delivery visible to the dispatcher.
+    pub fn commit(mut self) -> Result<OutboxEntry, OutboxError> {
+        let entry = self.entry.take().expect("staged entry is present");
+        fs::rename(
+            self.outbox.path(STAGED, &entry.id),
+            self.outbox.path(PENDING, &entry.id),
+        )?;
+        Ok(entry)
+    }
+}
+
+impl Drop for StagedDelivery<'_> {
+    fn drop(&mut self) {
+        if let Some(entry) = self.entry.take() {
+            debug!("Discarding staged outbox entry {}", entry.id);
+            let _ = fs::remove_file(self.outbox.path(STAGED, &entry.id));
+        }
+    }
+}
+
+fn json_files(dir: &Path) -> Result<Vec<PathBuf>, OutboxError> {
+    let mut out = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|e| e == "json") {
+            out.push(path);
+        }
+    }
+    Ok(out)
+}
+
+fn read_entry(path: &Path) -> Result<OutboxEntry, OutboxError> {
+    Ok(serde_json::from_slice(&fs::read(path)?)?)
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Dispatcher
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Sends a delivery. An `Err` is recorded on the entry and retried.
+#[async_trait]
+pub trait Transport: Send + Sync {
+    async fn deliver(&self, delivery: &Delivery) -> Result<(), String>;
+
+    /// Whether this transport sends `delivery`; others are left queued for
+    /// the dispatcher of their kind.
+    fn accepts(&self, _delivery: &Delivery) -> bool {
+        true
+    }
+}
+
+/// Outcome of one dispatcher pass.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DispatchReport {
+    pub delivered: usize,
+    pub retrying: usize,
+    pub dead: usize,
+}
+
+/// Attempt every due entry `transport` accepts once.
+pub async fn dispatch_due(
+    outbox: &Outbox,
+    transport: &dyn Transport,
+    now: DateTime<Utc>,
+) -> Result<DispatchReport, OutboxError> {
+    let mut report = DispatchReport::default();
+
+    for entry in outbox.due(now)? {
+        if !transport.accepts(&entry.delivery) {
+            continue;
+        }
+        match transport.deliver(&entry.delivery).await {
+            Ok(()) => {
+                outbox.mark_delivered(&entry)?;
+                report.delivered += 1;
+            }
+            Err(e) => match outbox.mark_failed(&entry, &e, now)? {
+                OutboxState::Pending => report.retrying += 1,
+                OutboxState::Dead => {
+                    warn!("Outbox entry {} dead-lettered: {}", entry.id, e);
+                    report.dead += 1;
+                }
+            },
+        }
+    }
+
+    Ok(report)
+}
+
+/// Run [`dispatch_due`] every `poll` until the returned task is aborted,
+/// first clearing out stale staged entries.
+pub fn spawn_dispatcher(
+    outbox: Arc<Outbox>,
+    transport: Arc<dyn Transport>,
+    poll: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(poll);
+        loop {
+            tick.tick().await;
+            if let Err(e) = outbox.discard_stale_stages(STALE_STAGE_AFTER) {
+                warn!("Clearing stale outbox entries failed: {}", e);
+            }
+            match dispatch_due(&outbox, transport.as_ref(), Utc::now()).await {
+                Ok(r) if r != DispatchReport::default() => info!("Outbox dispatch: {:?}", r),
+                Ok(_) => {}
+                Err(e) => warn!("Outbox dispatch failed: {}", e),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    fn hook() -> Delivery {
+        Delivery::Webhook {
+            url: "https://example.test/hook".into(),
+            headers: BTreeMap::new(),
+            body: json!({ "event": "published" }),
+        }
+    }
+
+    /// Fails the first `failures` calls, then succeeds.
+    struct Flaky {
+        failures: Mutex<u32>,
+    }
+
+    #[async_trait]
+    impl Transport for Flaky {
+        async fn deliver(&self, _: &Delivery) -> Result<(), String> {
+            let mut left = self.failures.lock().unwrap();
+            if *left == 0 {
+                return Ok(());
+            }
+            *left -= 1;
+            Err("connection refused".into())
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_and_caps() {
+        let p = RetryPolicy {
+            max_attempts: 10,
+            base_delay: Duration::from_secs(10),
+            max_delay: Duration::from_secs(60),
+        };
+        assert_eq!(p.backoff(1), Duration::from_secs(10));
+        assert_eq!(p.backoff(2), Duration::from_secs(20));
+        assert_eq!(p.backoff(3), Duration::from_secs(40));
+        assert_eq!(p.backoff(4), Duration::from_secs(60));
+        assert_eq!(p.backoff(40), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn dropped_stage_is_discarded_and_commit_survives_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let outbox = Outbox::open(dir.path()).unwrap();
+
+        // Triggering change failed: nothing is queued.
+        drop(outbox.stage(hook()).unwrap());
+        assert!(outbox.list(OutboxState::Pending).unwrap().is_empty());
+
+        let staged = outbox.stage(hook()).unwrap();
+        let entry = staged.commit().unwrap();
+
+        let reopened = Outbox::open(dir.path()).unwrap();
+        assert_eq!(reopened.list(OutboxState::Pending).unwrap(), vec![entry]);
+    }
+
+    #[test]
+    fn opening_keeps_stages_in_flight_and_only_old_ones_are_discarded() {
+        let dir = tempfile::tempdir().unwrap();
+        let outbox = Outbox::open(dir.path()).unwrap();
+        let in_flight = outbox.stage(hook()).unwrap();
+
+        // Another handler opening the same outbox leaves it alone...
+        let other = Outbox::open(dir.path()).unwrap();
+        assert_eq!(other.discard_stale_stages(STALE_STAGE_AFTER).unwrap(), 0);
+        let entry = in_flight.commit().unwrap();
+        assert_eq!(outbox.list(OutboxState::Pending).unwrap(), vec![entry]);
+
+        // ...while one a crash left behind goes once it is old enough.
+        std::mem::forget(outbox.stage(hook()).unwrap());
+        assert_eq!(outbox.discard_stale_stages(Duration::ZERO).unwrap(), 1);
+        assert_eq!(outbox.discard_stale_stages(Duration::ZERO).unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn failures_back_off_then_deliver() {
+        let dir = tempfile::tempdir().unwrap();
+        let outbox = Outbox::open(dir.path()).unwrap();
+        outbox.enqueue(hook()).unwrap();
+        let transport = Flaky {
+            failures: Mutex::new(1),
+        };
+
+        let now = Utc::now();
+        let r = dispatch_due(&outbox, &transport, now).await.unwrap();
+        assert_eq!(r.retrying, 1);
+
+        let pending = outbox.list(OutboxState::Pending).unwrap();
+        assert_eq!(pending[0].attempts, 1);
+        assert_eq!(pending[0].last_error.as_deref(), Some("connection refused"));
+
+        // Not due again until the backoff has elapsed.
+        assert!(outbox.due(now).unwrap().is_empty());
+
+        let later = pending[0].next_attempt_at;
+        let r = dispatch_due(&outbox, &transport, later).await.unwrap();
+        assert_eq!(r.delivered, 1);
+        assert!(outbox.list(OutboxState::Pending).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn exhausted_entries_are_dead_lettered_and_can_be_retried() {
+        let dir = tempfile::tempdir().unwrap();
+        let outbox = Outbox::open(dir.path()).unwrap().with_policy(RetryPolicy {
+            max_attempts: 2,
+            base_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+        });
+        let entry = outbox.enqueue(hook()).unwrap();
+        let transport = Flaky {
+            failures: Mutex::new(u32::MAX),
+        };
+
+        let now = Utc::now();
+        dispatch_due(&outbox, &transport, now).await.unwrap();
+        let r = dispatch_due(&outbox, &transport, now).await.unwrap();
+        assert_eq!(r.dead, 1);
+
+        let dead = outbox.list(OutboxState::Dead).unwrap();
+        assert_eq!(dead.len(), 1);
+        assert_eq!(dead[0].attempts, 2);
+        assert!(outbox.list(OutboxState::Pending).unwrap().is_empty());
+
+        let revived = outbox.retry_dead(&entry.id).unwrap();
+        assert_eq!(revived.attempts, 0);
+        assert!(outbox.list(OutboxState::Dead).unwrap().is_empty());
+        assert!(matches!(
+            outbox.retry_dead(&entry.id),
+            Err(OutboxError::NotFound(_))
+        ));
+    }
+}
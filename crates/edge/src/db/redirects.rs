@@ -0,0 +1,291 @@
+// crates/edge/src/db/redirects.rs
+
+// Redirect rules (see crate::redirects), one JSON file each:
+//
+//   <root>/<id>.json   {"id":"…","from":"/2020/01/hello-world/","kind":"exact",
+//                       "to":"/posts/hello-world.html","status":301,
+//                       "hits":12,"last_hit":"…","created_at":"…"}
+//
+// An `exact` rule matches one path; a `pattern` rule is a regular expression
+// over the whole path whose groups `to` may use as `$1`, `${name}`:
+//
+//   {"from":"/archives/(\\d+)","kind":"pattern","to":"/posts/$1.html","status":301}
+//
+// A 410 rule has no `to`: the page is gone on purpose. Like comments, rules
+// live in a directory rather than an ops database table (see db::kv); a site
+// has a few thousand at most, and they are read whole into memory anyway.
+
+use crate::db::history::write_atomic;
+use crate::db::store::StoreError;
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+use thiserror::Error;
+use uuid::Uuid;
+
+/// Redirect rules inside the site directory.
+pub const REDIRECTS_DB_DIR: &str = "./redirects_db/";
+
+/// Statuses a rule may answer with.
+pub const REDIRECT_STATUSES: [u16; 3] = [301, 302, 410];
+
+/// Hits by rule id: how many, and when the last was.
+pub type Hits = BTreeMap<String, (u64, DateTime<Utc>)>;
+
+#[derive(Debug, Error)]
+pub enum RedirectStoreError {
+    #[error("I/O: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("JSON: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("invalid redirect: {0}")]
+    Invalid(String),
+}
+
+impl StoreError for RedirectStoreError {
+    const STORE: &'static str = "redirect";
+}
+
+/// How a rule's `from` is matched against a request path.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RedirectKind {
+    #[default]
+    Exact,
+    Pattern,
+}
+
+impl RedirectKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RedirectKind::Exact => "exact",
+            RedirectKind::Pattern => "pattern",
+        }
+    }
+}
+
+impl fmt::Display for RedirectKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for RedirectKind {
+    type Err = RedirectStoreError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "exact" => Ok(RedirectKind::Exact),
+            "pattern" => Ok(RedirectKind::Pattern),
+            _ => Err(RedirectStoreError::Invalid(format!(
+                "unknown kind {s:?}: expected exact or pattern"
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Redirect {
+    pub id: String,
+    /// A path, or a regular expression with `kind: pattern`.
+    pub from: String,
+    #[serde(default)]
+    pub kind: RedirectKind,
+    /// Where to send the request; `None` for 410.
+    pub to: Option<String>,
+    pub status: u16,
+    #[serde(default)]
+    pub hits: u64,
+    pub last_hit: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Redirect {
+    /// A new rule, checked with [`Redirect::validate`].
+    pub fn new(
+        from: impl Into<String>,
+        kind: RedirectKind,
+        to: Option<String>,
+        status: u16,
+    ) -> Result<Self, RedirectStoreError> {
+        let redirect = Self {
+            id: Uuid::new_v4().to_string(),
+            from: from.into(),
+            kind,
+            to,
+            status,
+            hits: 0,
+            last_hit: None,
+            created_at: Utc::now(),
+        };
+        redirect.validate()?;
+        Ok(redirect)
+    }
+
+    /// `from` as the resolver matches it: the pattern anchored to the whole
+    /// path.
+    pub fn regex(&self) -> Result<Regex, RedirectStoreError> {
+        Regex::new(&format!("^(?:{})$", self.from))
+            .map_err(|e| RedirectStoreError::Invalid(format!("bad pattern {:?}: {e}", self.from)))
+    }
+
+    /// Check the status, that `to` is there exactly when it is needed, and
+    /// that `from` is a path or a pattern that compiles.
+    pub fn validate(&self) -> Result<(), RedirectStoreError> {
+        let invalid = |m: String| Err(RedirectStoreError::Invalid(m));
+        if !REDIRECT_STATUSES.contains(&self.status) {
+            return invalid(format!(
+                "status {} is not one of {REDIRECT_STATUSES:?}",
+                self.status
+            ));
+        }
+        match (&self.to, self.status) {
+            (Some(_), 410) => return invalid("a 410 rule has no target".into()),
+            (None, 301 | 302) => return invalid(format!("a {} rule needs a target", self.status)),
+            (Some(to), _) if to.trim().is_empty() => return invalid("empty target".into()),
+            _ => {}
+        }
+        match self.kind {
+            RedirectKind::Exact if !self.from.starts_with('/') => {
+                invalid(format!("{:?} is not a path", self.from))
+            }
+            RedirectKind::Exact => Ok(()),
+            RedirectKind::Pattern => self.regex().map(|_| ()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RedirectStore {
+    root: PathBuf,
+}
+
+impl RedirectStore {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    /// The file for `id`, if `id` could have been minted here.
+    fn path(&self, id: &str) -> Option<PathBuf> {
+        Uuid::parse_str(id)
+            .ok()
+            .map(|_| self.root.join(format!("{id}.json")))
+    }
+
+    /// Every rule, oldest first; the order patterns are tried in.
+    pub fn list(&self) -> Result<Vec<Redirect>, RedirectStoreError> {
+        let mut out = Vec::new();
+        if !self.root.is_dir() {
+            return Ok(out);
+        }
+        for entry in fs::read_dir(&self.root)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            out.push(serde_json::from_slice(&fs::read(&path)?)?);
+        }
+        out.sort_by(|a: &Redirect, b| (a.created_at, &a.id).cmp(&(b.created_at, &b.id)));
+        Ok(out)
+    }
+
+    pub fn get(&self, id: &str) -> Result<Option<Redirect>, RedirectStoreError> {
+        let Some(path) = self.path(id) else {
+            return Ok(None);
+        };
+        match fs::read(path) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, redirect: &Redirect) -> Result<(), RedirectStoreError> {
+        redirect.validate()?;
+        let path = self
+            .path(&redirect.id)
+            .ok_or_else(|| io::Error::other(format!("bad redirect id {:?}", redirect.id)))?;
+        fs::create_dir_all(&self.root)?;
+        Ok(write_atomic(&path, &serde_json::to_vec_pretty(redirect)?)?)
+    }
+
+    /// Remove `id`; whether it was stored.
+    pub fn delete(&self, id: &str) -> Result<bool, RedirectStoreError> {
+        let Some(path) = self.path(id) else {
+            return Ok(false);
+        };
+        match fs::remove_file(path) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Add counted hits, by rule id, to the stored rules. Rules deleted since
+    /// are skipped.
+    pub fn add_hits(&self, hits: &Hits) -> Result<(), RedirectStoreError> {
+        for (id, (count, last)) in hits {
+            if let Some(mut redirect) = self.get(id)? {
+                redirect.hits += count;
+                redirect.last_hit = redirect.last_hit.max(Some(*last));
+                self.save(&redirect)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules_are_checked_stored_and_counted() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = RedirectStore::new(dir.path().join("redirects"));
+        assert!(store.list().unwrap().is_empty());
+
+        let exact = Redirect::new("/old", RedirectKind::Exact, Some("/new".into()), 301).unwrap();
+        let gone = Redirect::new("/drafts/.*", RedirectKind::Pattern, None, 410).unwrap();
+        store.save(&exact).unwrap();
+        store.save(&gone).unwrap();
+        assert_eq!(store.list().unwrap(), [exact.clone(), gone.clone()]);
+        assert!(gone.regex().unwrap().is_match("/drafts/x"));
+        assert!(!gone.regex().unwrap().is_match("/x/drafts/x"));
+
+        let now = Utc::now();
+        let hits = BTreeMap::from([
+            (exact.id.clone(), (3, now)),
+            (Uuid::new_v4().to_string(), (1, now)),
+        ]);
+        store.add_hits(&hits).unwrap();
+        store.add_hits(&hits).unwrap();
+        let counted = store.get(&exact.id).unwrap().unwrap();
+        assert_eq!((counted.hits, counted.last_hit), (6, Some(now)));
+
+        for (from, kind, to, status) in [
+            ("old", RedirectKind::Exact, Some("/new"), 301),
+            ("/old", RedirectKind::Exact, None, 302),
+            ("/old", RedirectKind::Exact, Some("/new"), 410),
+            ("/old", RedirectKind::Exact, Some("/new"), 307),
+            ("/(unclosed", RedirectKind::Pattern, Some("/new"), 301),
+        ] {
+            assert!(Redirect::new(from, kind, to.map(Into::into), status).is_err());
+        }
+        assert!(store.delete(&gone.id).unwrap());
+        assert!(!store.delete(&gone.id).unwrap());
+        assert!(store.get("../users_db/users/ada").unwrap().is_none());
+        assert_eq!(
+            "PATTERN".parse::<RedirectKind>().unwrap(),
+            RedirectKind::Pattern
+        );
+    }
+}
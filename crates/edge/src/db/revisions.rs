@@ -0,0 +1,304 @@
+// crates/edge/src/db/revisions.rs
+
+// Revision history for documents edited through the admin content API.
+//
+// Every create, update, delete and restore of a document becomes a revision
+// (who, when, what, the whole file as saved, diff against the previous
+// revision), one JSON file each, below the document's path:
+//
+//   <root>/<path>/<revision>.json        e.g. revisions/blog/a.md/000003.json
+//
+// A change first records how the document was before it, when that is not
+// its latest revision: a file written by hand or imported, on its first
+// change through the API, or one edited on disk since. A deletion keeps
+// the history and is a revision without text. Restoring writes an earlier
+// revision's file back and records that as a new revision, so history only
+// ever grows, as with manifests (db::history).
+
+use crate::db::history::write_atomic;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use similar::TextDiff;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use thiserror::Error;
+
+/// Revisions location inside the site directory.
+pub const REVISIONS_DIR: &str = "./revisions/";
+
+#[derive(Debug, Error)]
+pub enum RevisionError {
+    #[error("I/O: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("JSON: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Not a usable document path: {0:?}")]
+    InvalidPath(String),
+
+    #[error("No revision {revision} of {path}")]
+    NotFound { path: String, revision: u32 },
+}
+
+/// What a revision recorded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RevisionAction {
+    /// The document as found on disk before a change.
+    Existing,
+    Create,
+    Update,
+    Delete,
+    Restore,
+}
+
+/// One saved state of a document.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Revision {
+    /// 1-based, increasing per document.
+    pub revision: u32,
+    /// Relative to the content directory.
+    pub path: String,
+    pub who: String,
+    pub at: DateTime<Utc>,
+    pub action: RevisionAction,
+    /// The file as saved; `None` once deleted.
+    pub text: Option<String>,
+    /// Unified diff from the previous revision (from empty for the first).
+    pub diff: String,
+    /// Set when this revision was created by restoring an older one.
+    pub restored_from: Option<u32>,
+}
+
+/// File-backed document revisions rooted at a directory.
+#[derive(Debug, Clone)]
+pub struct RevisionStore {
+    root: PathBuf,
+}
+
+impl RevisionStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Record a change of the document at `path` from `before` to `after`
+    /// (`None` where it does not exist). Returns `None` when the two match,
+    /// so re-saving an unchanged document adds nothing.
+    pub fn record(
+        &self,
+        path: &str,
+        action: RevisionAction,
+        before: Option<&str>,
+        after: Option<&str>,
+        who: &str,
+    ) -> Result<Option<Revision>, RevisionError> {
+        let latest = self.settled(path, before)?;
+        if before == after {
+            return Ok(None);
+        }
+        self.append(path, latest.as_ref(), action, after, who, None)
+            .map(Some)
+    }
+
+    /// All revisions of a document, oldest first.
+    pub fn revisions(&self, path: &str) -> Result<Vec<Revision>, RevisionError> {
+        let dir = self.dir(path)?;
+        if !dir.is_dir() {
+            return Ok(Vec::new());
+        }
+
+        let mut out = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let file = entry?.path();
+            if file.extension().is_some_and(|e| e == "json") {
+                out.push(serde_json::from_slice::<Revision>(&fs::read(file)?)?);
+            }
+        }
+        out.sort_by_key(|r| r.revision);
+        Ok(out)
+    }
+
+    pub fn latest(&self, path: &str) -> Result<Option<Revision>, RevisionError> {
+        Ok(self.revisions(path)?.pop())
+    }
+
+    pub fn get(&self, path: &str, revision: u32) -> Result<Revision, RevisionError> {
+        match fs::read(self.dir(path)?.join(revision_file(revision))) {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Err(RevisionError::NotFound {
+                path: path.to_owned(),
+                revision,
+            }),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Record that `target` was written back to `path`, whose file was
+    /// `before`.
+    pub fn restored(
+        &self,
+        path: &str,
+        target: &Revision,
+        before: Option<&str>,
+        who: &str,
+    ) -> Result<Revision, RevisionError> {
+        let latest = self.settled(path, before)?;
+        self.append(
+            path,
+            latest.as_ref(),
+            RevisionAction::Restore,
+            target.text.as_deref(),
+            who,
+            Some(target.revision),
+        )
+    }
+
+    /// The latest revision of `path`, first recording `before` as one if
+    /// the file is not as last recorded.
+    fn settled(&self, path: &str, before: Option<&str>) -> Result<Option<Revision>, RevisionError> {
+        let latest = self.latest(path)?;
+        if latest.as_ref().and_then(|r| r.text.as_deref()) == before {
+            return Ok(latest);
+        }
+        self.append(
+            path,
+            latest.as_ref(),
+            RevisionAction::Existing,
+            before,
+            "unknown",
+            None,
+        )
+        .map(Some)
+    }
+
+    fn append(
+        &self,
+        path: &str,
+        previous: Option<&Revision>,
+        action: RevisionAction,
+        text: Option<&str>,
+        who: &str,
+        restored_from: Option<u32>,
+    ) -> Result<Revision, RevisionError> {
+        let revision = previous.map_or(1, |p| p.revision + 1);
+        let (old, old_name) = match previous {
+            Some(p) => (p.text.as_deref().unwrap_or(""), format!("r{}", p.revision)),
+            None => ("", "(none)".to_string()),
+        };
+        let diff = TextDiff::from_lines(old, text.unwrap_or(""))
+            .unified_diff()
+            .header(&old_name, &format!("r{revision}"))
+            .to_string();
+
+        let entry = Revision {
+            revision,
+            path: path.to_owned(),
+            who: who.to_owned(),
+            at: Utc::now(),
+            action,
+            text: text.map(str::to_owned),
+            diff,
+            restored_from,
+        };
+
+        let dir = self.dir(path)?;
+        fs::create_dir_all(&dir)?;
+        write_atomic(
+            &dir.join(revision_file(revision)),
+            &serde_json::to_vec_pretty(&entry)?,
+        )?;
+        Ok(entry)
+    }
+
+    fn dir(&self, path: &str) -> Result<PathBuf, RevisionError> {
+        // Paths name directories here; refuse anything that could leave root.
+        let rel = Path::new(path);
+        if path.is_empty() || !rel.components().all(|c| matches!(c, Component::Normal(_))) {
+            return Err(RevisionError::InvalidPath(path.to_owned()));
+        }
+        Ok(self.root.join(rel))
+    }
+}
+
+fn revision_file(revision: u32) -> String {
+    format!("{revision:06}.json")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes_keep_the_state_before_them_and_restores_add_revisions() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = RevisionStore::new(dir.path());
+
+        // A hand-written document: its first edit keeps the original.
+        let r2 = store
+            .record(
+                "blog/a.md",
+                RevisionAction::Update,
+                Some("old\n"),
+                Some("new\n"),
+                "amy",
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(r2.revision, 2);
+        assert!(r2.diff.starts_with("--- r1\n+++ r2\n"));
+        assert!(r2.diff.contains("-old\n+new\n"));
+        let r1 = store.get("blog/a.md", 1).unwrap();
+        assert_eq!(
+            (r1.action, r1.text.as_deref()),
+            (RevisionAction::Existing, Some("old\n"))
+        );
+
+        let r3 = store
+            .record(
+                "blog/a.md",
+                RevisionAction::Delete,
+                Some("new\n"),
+                None,
+                "amy",
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!((r3.revision, r3.text), (3, None));
+
+        let r4 = store.restored("blog/a.md", &r1, None, "bob").unwrap();
+        assert_eq!(r4.restored_from, Some(1));
+        assert_eq!(r4.text.as_deref(), Some("old\n"));
+        assert!(store
+            .record(
+                "blog/a.md",
+                RevisionAction::Update,
+                Some("old\n"),
+                Some("old\n"),
+                "bob"
+            )
+            .unwrap()
+            .is_none());
+
+        // Changed on disk since: that state is kept before the restore.
+        let r6 = store
+            .restored("blog/a.md", &r2, Some("by hand\n"), "bob")
+            .unwrap();
+        assert_eq!(r6.revision, 6);
+        assert_eq!(
+            store.get("blog/a.md", 5).unwrap().text.as_deref(),
+            Some("by hand\n")
+        );
+
+        assert!(store.revisions("blog/b.md").unwrap().is_empty());
+        assert!(matches!(
+            store.get("blog/a.md", 9),
+            Err(RevisionError::NotFound { revision: 9, .. })
+        ));
+        assert!(matches!(
+            store.revisions("../a.md"),
+            Err(RevisionError::InvalidPath(_))
+        ));
+    }
+}
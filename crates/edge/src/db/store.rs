@@ -0,0 +1,34 @@
+// crates/edge/src/db/store.rs
+
+// What the admin API handlers share in front of the file-backed stores
+// (forms, collections, comments, redirects, users): running a store call
+// off the async runtime, and answering for a store that failed.
+
+use actix_web::{web, HttpResponse};
+use serde_json::json;
+use std::fmt::Display;
+use std::io;
+use tracing::error;
+
+/// An error of a store the admin API calls into.
+pub trait StoreError: From<io::Error> + Display + Send + 'static {
+    /// What the store holds, as logged and answered ("form", "user").
+    const STORE: &'static str;
+}
+
+/// Run `f` on the blocking thread pool; a pool that could not run it is
+/// the store's I/O error.
+pub async fn blocking<T: Send + 'static, E: StoreError>(
+    f: impl FnOnce() -> Result<T, E> + Send + 'static,
+) -> Result<T, E> {
+    web::block(f)
+        .await
+        .map_err(|e| E::from(io::Error::other(e)))?
+}
+
+/// Log `e` and answer 500 without its details.
+pub fn failed<E: StoreError>(e: E) -> HttpResponse {
+    error!("{} store request failed: {}", E::STORE, e);
+    HttpResponse::InternalServerError()
+        .json(json!({ "error": format!("{} store unavailable", E::STORE) }))
+}
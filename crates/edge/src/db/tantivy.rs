@@ -104,6 +104,7 @@ impl ContentIndex {
 
     /// `add(path, stream)` – read all bytes from `body` and write them to the index under `path`.
     ///
+    /// - Replaces any document already stored under `path`.
     /// - Stores bytes as UTF-8 (lossy if needed) in `content`.
     /// - Indexes `content` for full-text search.
     pub fn add<R: Read>(&self, path: &Path, mut body: R) -> Result<()> {
@@ -128,6 +129,7 @@ impl ContentIndex {
             self.content_field => content_str,
         );
 
+        writer.delete_term(Term::from_field_text(self.path_field, &path_str));
         writer.add_document(doc)?;
         writer.commit()?; // flush + make new segment visible on disk
 
@@ -139,6 +141,36 @@ impl ContentIndex {
         Ok(())
     }
 
+    /// `remove(path)` – drop the document stored under `path`, if any.
+    pub fn remove(&self, path: &Path) -> Result<()> {
+        let path_str = path.to_string_lossy().to_string();
+
+        let mut writer = self
+            .writer
+            .lock()
+            .map_err(|_| ContentIndexError::WriterPoisoned)?;
+
+        writer.delete_term(Term::from_field_text(self.path_field, &path_str));
+        writer.commit()?;
+        self.reader.reload()?;
+
+        Ok(())
+    }
+
+    /// `clear()` – drop every document.
+    pub fn clear(&self) -> Result<()> {
+        let mut writer = self
+            .writer
+            .lock()
+            .map_err(|_| ContentIndexError::WriterPoisoned)?;
+
+        writer.delete_all_documents()?;
+        writer.commit()?;
+        self.reader.reload()?;
+
+        Ok(())
+    }
+
     /// Helper: extract the first `OwnedValue::Str` for a given field name
     /// from a NamedFieldDocument, using a factory to construct the error.
     fn first_string_from_named<F>(
@@ -193,6 +225,7 @@ impl ContentIndex {
     /// `search(terms) -> Vec<SearchHit>` – full-text search over `content`.
     ///
     /// Returns up to `limit` hits with `path` + score.
+    #[tracing::instrument(name = "db.search", skip(self))]
     pub fn search(&self, query_str: &str, limit: usize) -> Result<Vec<SearchHit>> {
         let limit = if limit == 0 {
             DEFAULT_SEARCH_LIMIT
@@ -264,6 +297,30 @@ mod tests {
         assert_eq!(buf, content);
     }
 
+    #[test]
+    fn add_replaces_and_remove_drops_by_path() {
+        let (_tmp, index) = create_temp_index();
+        let path = PathBuf::from("/posts/edited.html");
+
+        index
+            .add(&path, Cursor::new(b"<html>first draft</html>"))
+            .expect("add first");
+        index
+            .add(&path, Cursor::new(b"<html>second draft</html>"))
+            .expect("add second");
+
+        let mut buf = String::new();
+        index.get(&path).unwrap().read_to_string(&mut buf).unwrap();
+        assert_eq!(buf, "<html>second draft</html>");
+        assert!(index.search("first", 10).unwrap().is_empty());
+
+        index.remove(&path).expect("remove");
+        assert!(matches!(
+            index.get(&path),
+            Err(ContentIndexError::NotFound(_))
+        ));
+    }
+
     #[test]
     fn get_missing_path_returns_not_found() {
         let (_tmp, index) = create_temp_index();
@@ -0,0 +1,983 @@
+// crates/edge/src/db/users.rs
+
+// User accounts and their login sessions.
+//
+//   <root>/users/<username>.json             one User, with its argon2 hash
+//   <root>/sessions/<sha256(token)>.json     one Session
+//   <root>/resets/<sha256(token)>.json       one PasswordReset
+//
+// Only a digest of each token, and of each two-factor recovery code, is
+// stored, so reading the directory does not hand out logins. The digest doubles as a session's id when users list
+// and end their sessions. Roles are ordered; a route that needs `Editor`
+// also admits `Admin`.
+
+use crate::db::history::write_atomic;
+use crate::db::store::StoreError;
+use crate::totp;
+use argon2::password_hash::{self, PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use chrono::{DateTime, Duration, Utc};
+use domain::hex;
+use openssl::rand::rand_bytes;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, LazyLock, Mutex, MutexGuard, PoisonError};
+use thiserror::Error;
+use uuid::Uuid;
+
+/// Accounts and sessions inside the site directory.
+pub const USERS_DB_DIR: &str = "./users_db/";
+
+/// Checked against when a username is unknown.
+static DUMMY_HASH: LazyLock<Option<String>> = LazyLock::new(|| {
+    ValidatedPassword::new("not a real password")
+        .and_then(|p| p.hash())
+        .ok()
+});
+
+/// Recovery codes handed out when two-factor login is turned on.
+const RECOVERY_CODES: usize = 10;
+
+const MIN_PASSWORD_CHARS: usize = 12;
+const MAX_PASSWORD_CHARS: usize = 1024;
+
+/// Length in bytes of the keys `secret_key` creates.
+const SECRET_KEY_LEN: usize = 32;
+
+#[derive(Debug, Error)]
+pub enum UserError {
+    #[error("I/O: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("JSON: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("password hashing: {0}")]
+    Hash(String),
+
+    #[error("invalid username {0:?}: use 1-32 of a-z, 0-9, '-' and '_'")]
+    InvalidUsername(String),
+
+    #[error("passwords must be {MIN_PASSWORD_CHARS} to {MAX_PASSWORD_CHARS} characters")]
+    WeakPassword,
+
+    #[error("unknown role {0:?}: expected admin, editor, author or viewer")]
+    UnknownRole(String),
+
+    #[error("user {0} already exists")]
+    Exists(String),
+
+    #[error("no user {0}")]
+    NotFound(String),
+
+    #[error("invalid email address {0:?}")]
+    InvalidEmail(String),
+
+    #[error("two-factor login is already on for {0}")]
+    TwoFactorOn(String),
+}
+
+impl StoreError for UserError {
+    const STORE: &'static str = "user";
+}
+
+impl From<password_hash::Error> for UserError {
+    fn from(e: password_hash::Error) -> Self {
+        UserError::Hash(e.to_string())
+    }
+}
+
+/// What a user may do, from least to most.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Read drafts and the admin API.
+    Viewer,
+    /// Write and upload for their own posts.
+    Author,
+    /// Change anyone's content.
+    Editor,
+    /// Manage users, plugins and settings.
+    Admin,
+}
+
+impl Role {
+    /// Whether this role satisfies a route that needs `required`.
+    pub fn allows(self, required: Role) -> bool {
+        self >= required
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Author => "author",
+            Role::Editor => "editor",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Role {
+    type Err = UserError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "viewer" => Ok(Role::Viewer),
+            "author" => Ok(Role::Author),
+            "editor" => Ok(Role::Editor),
+            "admin" => Ok(Role::Admin),
+            _ => Err(UserError::UnknownRole(s.to_owned())),
+        }
+    }
+}
+
+/// A password that meets the length policy; the only way to set one.
+pub struct ValidatedPassword(String);
+
+impl ValidatedPassword {
+    pub fn new(password: impl Into<String>) -> Result<Self, UserError> {
+        let password = password.into();
+        let chars = password.chars().count();
+        if !(MIN_PASSWORD_CHARS..=MAX_PASSWORD_CHARS).contains(&chars) || password.trim().is_empty()
+        {
+            return Err(UserError::WeakPassword);
+        }
+        Ok(Self(password))
+    }
+
+    fn hash(&self) -> Result<String, UserError> {
+        let salt = SaltString::encode_b64(Uuid::new_v4().as_bytes())?;
+        Ok(Argon2::default()
+            .hash_password(self.0.as_bytes(), &salt)?
+            .to_string())
+    }
+}
+
+impl fmt::Debug for ValidatedPassword {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ValidatedPassword(..)")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct User {
+    pub username: String,
+    pub role: Role,
+    /// PHC string, e.g. `$argon2id$v=19$...`.
+    pub password_hash: String,
+    pub created_at: DateTime<Utc>,
+    /// Where password reset codes are sent; without one, only `whispercms
+    /// user` can set a new password.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub two_factor: Option<TwoFactor>,
+}
+
+impl User {
+    /// Whether logging in takes a code as well as the password.
+    pub fn has_two_factor(&self) -> bool {
+        self.two_factor.as_ref().is_some_and(|t| t.confirmed)
+    }
+}
+
+/// A user's authenticator app (see crate::totp) and recovery codes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TwoFactor {
+    /// Base32 TOTP secret.
+    pub secret: String,
+    /// False until a code from the app proves it holds the secret.
+    pub confirmed: bool,
+    /// Digests of the recovery codes not used yet.
+    #[serde(default)]
+    pub recovery: Vec<String>,
+    /// The last step a code was accepted for; earlier ones are refused.
+    #[serde(default)]
+    pub last_step: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Session {
+    username: String,
+    created_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+}
+
+/// A live session as its user sees it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SessionInfo {
+    /// The digest of the token, not the token.
+    pub id: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PasswordReset {
+    username: String,
+    expires_at: DateTime<Utc>,
+}
+
+/// Clones share one write lock; every change to a user reads and rewrites
+/// its file under it, so two changes never both start from the same state.
+/// Share one store per site so its routes and workers serialize on it.
+#[derive(Debug, Clone)]
+pub struct UserStore {
+    root: PathBuf,
+    writes: Arc<Mutex<()>>,
+}
+
+impl UserStore {
+    /// A store in `root`; its directories are created on the first write.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            writes: Arc::default(),
+        }
+    }
+
+    /// Held from reading a user to saving the change.
+    fn writing(&self) -> MutexGuard<'_, ()> {
+        self.writes.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn create(
+        &self,
+        username: &str,
+        role: Role,
+        password: &ValidatedPassword,
+    ) -> Result<User, UserError> {
+        let username = valid_username(username)?;
+        let password_hash = password.hash()?;
+        let _writing = self.writing();
+        if self.get(&username)?.is_some() {
+            return Err(UserError::Exists(username));
+        }
+        let user = User {
+            username,
+            role,
+            password_hash,
+            created_at: Utc::now(),
+            email: None,
+            two_factor: None,
+        };
+        self.save(&user)?;
+        Ok(user)
+    }
+
+    pub fn get(&self, username: &str) -> Result<Option<User>, UserError> {
+        let Ok(username) = valid_username(username) else {
+            return Ok(None);
+        };
+        read_json(&self.user_path(&username))
+    }
+
+    /// Every user, by username.
+    pub fn list(&self) -> Result<Vec<User>, UserError> {
+        let mut users: Vec<User> = json_files(&self.root.join("users"))?
+            .iter()
+            .map(|p| Ok(serde_json::from_slice(&fs::read(p)?)?))
+            .collect::<Result<_, UserError>>()?;
+        users.sort_by(|a, b| a.username.cmp(&b.username));
+        Ok(users)
+    }
+
+    pub fn set_role(&self, username: &str, role: Role) -> Result<User, UserError> {
+        let _writing = self.writing();
+        let mut user = self
+            .get(username)?
+            .ok_or_else(|| UserError::NotFound(username.to_owned()))?;
+        user.role = role;
+        self.save(&user)?;
+        Ok(user)
+    }
+
+    pub fn set_password(
+        &self,
+        username: &str,
+        password: &ValidatedPassword,
+    ) -> Result<User, UserError> {
+        let password_hash = password.hash()?;
+        let _writing = self.writing();
+        let mut user = self
+            .get(username)?
+            .ok_or_else(|| UserError::NotFound(username.to_owned()))?;
+        user.password_hash = password_hash;
+        self.save(&user)?;
+        Ok(user)
+    }
+
+    /// Set or clear where `username`'s reset codes go.
+    pub fn set_email(&self, username: &str, email: Option<&str>) -> Result<User, UserError> {
+        let _writing = self.writing();
+        let mut user = self
+            .get(username)?
+            .ok_or_else(|| UserError::NotFound(username.to_owned()))?;
+        user.email = email.map(valid_email).transpose()?;
+        self.save(&user)?;
+        Ok(user)
+    }
+
+    /// Start turning on two-factor login for `username`: a new secret for
+    /// their app, unconfirmed until [`UserStore::confirm_two_factor`].
+    pub fn begin_two_factor(&self, username: &str) -> Result<String, UserError> {
+        let _writing = self.writing();
+        let mut user = self.existing(username)?;
+        if user.has_two_factor() {
+            return Err(UserError::TwoFactorOn(user.username));
+        }
+        let secret = totp::generate_secret().map_err(|e| io::Error::other(e.to_string()))?;
+        user.two_factor = Some(TwoFactor {
+            secret: secret.clone(),
+            confirmed: false,
+            recovery: Vec::new(),
+            last_step: None,
+        });
+        self.save(&user)?;
+        Ok(secret)
+    }
+
+    /// Turn two-factor login on once `code` shows `username`'s app has the
+    /// secret; the recovery codes, shown this once. None for a wrong code
+    /// or when nothing was begun.
+    pub fn confirm_two_factor(
+        &self,
+        username: &str,
+        code: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Option<Vec<String>>, UserError> {
+        let _writing = self.writing();
+        let mut user = self.existing(username)?;
+        let Some(two_factor) = user.two_factor.as_mut().filter(|t| !t.confirmed) else {
+            return Ok(None);
+        };
+        let Some(step) = totp::verify(&two_factor.secret, code, now.timestamp(), None) else {
+            return Ok(None);
+        };
+        let codes: Vec<String> = (0..RECOVERY_CODES)
+            .map(|_| {
+                let hex = Uuid::new_v4().simple().to_string();
+                format!("{}-{}-{}", &hex[..4], &hex[4..8], &hex[8..12])
+            })
+            .collect();
+        two_factor.confirmed = true;
+        two_factor.last_step = Some(step);
+        two_factor.recovery = codes.iter().map(|c| digest(c)).collect();
+        self.save(&user)?;
+        Ok(Some(codes))
+    }
+
+    /// Whether `code`, from the app or an unused recovery code, is good for
+    /// `username` now. Either kind works once.
+    pub fn check_second_factor(
+        &self,
+        username: &str,
+        code: &str,
+        now: DateTime<Utc>,
+    ) -> Result<bool, UserError> {
+        // Two requests with the same code must not both see it unused.
+        let _writing = self.writing();
+        let mut user = self.existing(username)?;
+        let Some(two_factor) = user.two_factor.as_mut().filter(|t| t.confirmed) else {
+            return Ok(false);
+        };
+        if let Some(step) = totp::verify(
+            &two_factor.secret,
+            code,
+            now.timestamp(),
+            two_factor.last_step,
+        ) {
+            two_factor.last_step = Some(step);
+        } else {
+            let used = digest(&code.trim().to_ascii_lowercase());
+            let before = two_factor.recovery.len();
+            two_factor.recovery.retain(|c| *c != used);
+            if two_factor.recovery.len() == before {
+                return Ok(false);
+            }
+        }
+        self.save(&user)?;
+        Ok(true)
+    }
+
+    /// Back to logging in with the password alone.
+    pub fn disable_two_factor(&self, username: &str) -> Result<User, UserError> {
+        let _writing = self.writing();
+        let mut user = self.existing(username)?;
+        user.two_factor = None;
+        self.save(&user)?;
+        Ok(user)
+    }
+
+    /// Remove `username`; their sessions stop resolving.
+    pub fn remove(&self, username: &str) -> Result<(), UserError> {
+        let username = valid_username(username)?;
+        match fs::remove_file(self.user_path(&username)) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Err(UserError::NotFound(username)),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// The user whose password is `password`, if any. Slow by design; call
+    /// it off the async runtime.
+    pub fn verify(&self, username: &str, password: &str) -> Result<Option<User>, UserError> {
+        let Some(user) = self.get(username)? else {
+            // Spend the same time as a wrong password, so timing does not
+            // reveal which usernames exist.
+            if let Some(dummy) = DUMMY_HASH.as_deref() {
+                let _ = PasswordHash::new(dummy)
+                    .map(|h| Argon2::default().verify_password(password.as_bytes(), &h));
+            }
+            return Ok(None);
+        };
+        let hash = PasswordHash::new(&user.password_hash)?;
+        Ok(Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok()
+            .then_some(user))
+    }
+
+    /// A new session token for `user`, valid for `ttl`.
+    pub fn issue_session(&self, user: &User, ttl: Duration) -> Result<String, UserError> {
+        let token = new_token();
+        let now = Utc::now();
+        let session = Session {
+            username: user.username.clone(),
+            created_at: now,
+            expires_at: now + ttl,
+        };
+        let path = self.token_path("sessions", &token);
+        fs::create_dir_all(self.root.join("sessions"))?;
+        write_atomic(&path, &serde_json::to_vec_pretty(&session)?)?;
+        Ok(token)
+    }
+
+    /// The user `token` was issued to, while the session lasts and the user
+    /// exists. Expired sessions are deleted on sight.
+    pub fn session_user(&self, token: &str, now: DateTime<Utc>) -> Result<Option<User>, UserError> {
+        let path = self.token_path("sessions", token);
+        let Some(session) = read_json::<Session>(&path)? else {
+            return Ok(None);
+        };
+        if session.expires_at <= now {
+            let _ = fs::remove_file(&path);
+            return Ok(None);
+        }
+        self.get(&session.username)
+    }
+
+    pub fn revoke_session(&self, token: &str) -> Result<(), UserError> {
+        remove_if_present(&self.token_path("sessions", token)).map(|_| ())
+    }
+
+    /// The id [`SessionInfo`] gives the session of `token`.
+    pub fn session_id(token: &str) -> String {
+        digest(token)
+    }
+
+    /// `username`'s live sessions, newest first. Expired ones are deleted
+    /// on sight.
+    pub fn sessions(
+        &self,
+        username: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<SessionInfo>, UserError> {
+        let mut live: Vec<SessionInfo> = self
+            .sessions_of(username)?
+            .into_iter()
+            .filter_map(|(path, session)| {
+                if session.expires_at <= now {
+                    let _ = fs::remove_file(&path);
+                    return None;
+                }
+                let id = path.file_stem()?.to_str()?.to_owned();
+                Some(SessionInfo {
+                    id,
+                    created_at: session.created_at,
+                    expires_at: session.expires_at,
+                })
+            })
+            .collect();
+        live.sort_by(|a, b| (b.created_at, &b.id).cmp(&(a.created_at, &a.id)));
+        Ok(live)
+    }
+
+    /// End `username`'s session `id`; whether it was theirs and live.
+    pub fn revoke_session_id(&self, username: &str, id: &str) -> Result<bool, UserError> {
+        if id.len() != 64 || !id.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Ok(false);
+        }
+        let path = self.root.join("sessions").join(format!("{id}.json"));
+        match read_json::<Session>(&path)? {
+            Some(session) if session.username == valid_username(username)? => {
+                remove_if_present(&path)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// End every session of `username`, wherever they logged in; how many
+    /// there were.
+    pub fn revoke_sessions(&self, username: &str) -> Result<usize, UserError> {
+        let mut ended = 0;
+        for (path, _) in self.sessions_of(username)? {
+            if remove_if_present(&path)? {
+                ended += 1;
+            }
+        }
+        Ok(ended)
+    }
+
+    /// A single-use code that lets `username` choose a new password within
+    /// `ttl`, with the user it was issued for. None when they are unknown
+    /// or have no email address to send it to.
+    pub fn issue_reset(
+        &self,
+        username: &str,
+        ttl: Duration,
+    ) -> Result<Option<(User, String)>, UserError> {
+        let Some(user) = self.get(username)?.filter(|u| u.email.is_some()) else {
+            return Ok(None);
+        };
+        let token = new_token();
+        let reset = PasswordReset {
+            username: user.username.clone(),
+            expires_at: Utc::now() + ttl,
+        };
+        fs::create_dir_all(self.root.join("resets"))?;
+        write_atomic(
+            &self.token_path("resets", &token),
+            &serde_json::to_vec_pretty(&reset)?,
+        )?;
+        Ok(Some((user, token)))
+    }
+
+    /// Set `password` for the user reset code `token` was issued to, and end
+    /// all their sessions. The code is spent even when it has expired; None
+    /// when it was not live.
+    pub fn redeem_reset(
+        &self,
+        token: &str,
+        password: &ValidatedPassword,
+        now: DateTime<Utc>,
+    ) -> Result<Option<User>, UserError> {
+        let path = self.token_path("resets", token);
+        let Some(reset) = read_json::<PasswordReset>(&path)? else {
+            return Ok(None);
+        };
+        // Whoever removes the file gets to use it.
+        if !remove_if_present(&path)? || reset.expires_at <= now {
+            return Ok(None);
+        }
+        let user = match self.set_password(&reset.username, password) {
+            Ok(user) => user,
+            Err(UserError::NotFound(_)) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        self.revoke_sessions(&user.username)?;
+        Ok(Some(user))
+    }
+
+    fn existing(&self, username: &str) -> Result<User, UserError> {
+        self.get(username)?
+            .ok_or_else(|| UserError::NotFound(username.to_owned()))
+    }
+
+    fn save(&self, user: &User) -> Result<(), UserError> {
+        fs::create_dir_all(self.root.join("users"))?;
+        write_atomic(
+            &self.user_path(&user.username),
+            &serde_json::to_vec_pretty(user)?,
+        )?;
+        Ok(())
+    }
+
+    fn user_path(&self, username: &str) -> PathBuf {
+        self.root.join("users").join(format!("{username}.json"))
+    }
+
+    fn token_path(&self, kind: &str, token: &str) -> PathBuf {
+        self.root.join(kind).join(format!("{}.json", digest(token)))
+    }
+
+    fn sessions_of(&self, username: &str) -> Result<Vec<(PathBuf, Session)>, UserError> {
+        let username = valid_username(username)?;
+        let mut out = Vec::new();
+        for path in json_files(&self.root.join("sessions"))? {
+            // Another request may end a session while we look.
+            if let Some(session) = read_json::<Session>(&path)? {
+                if session.username == username {
+                    out.push((path, session));
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// The secret key kept at `path`, such as the CSRF or preview key in the
+/// users directory; a new random one is written there on first use.
+pub fn secret_key(path: &Path) -> io::Result<Vec<u8>> {
+    match fs::read(path) {
+        Ok(key) if !key.is_empty() => return Ok(key),
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let key = random_key()?;
+    write_atomic(path, &key)?;
+    Ok(key)
+}
+
+/// A key of `SECRET_KEY_LEN` bytes from the OpenSSL CSPRNG.
+pub fn random_key() -> io::Result<Vec<u8>> {
+    let mut key = vec![0u8; SECRET_KEY_LEN];
+    rand_bytes(&mut key).map_err(io::Error::other)?;
+    Ok(key)
+}
+
+fn new_token() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+fn digest(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Whether `path` was there to remove.
+fn remove_if_present(path: &Path) -> Result<bool, UserError> {
+    match fs::remove_file(path) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// `email` trimmed, when it looks like an address.
+fn valid_email(email: &str) -> Result<String, UserError> {
+    let email = email.trim();
+    let ok = email.split_once('@').is_some_and(|(local, domain)| {
+        !local.is_empty() && domain.contains('.') && !domain.contains('@')
+    }) && !email.contains(|c: char| c.is_whitespace() || c.is_control());
+    if ok {
+        Ok(email.to_owned())
+    } else {
+        Err(UserError::InvalidEmail(email.to_owned()))
+    }
+}
+
+/// `name` lower-cased, when it is a usable username.
+fn valid_username(name: &str) -> Result<String, UserError> {
+    let lower = name.trim().to_ascii_lowercase();
+    let ok = (1..=32).contains(&lower.len())
+        && lower
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if ok {
+        Ok(lower)
+    } else {
+        Err(UserError::InvalidUsername(name.to_owned()))
+    }
+}
+
+fn read_json<T: for<'de> Deserialize<'de>>(path: &Path) -> Result<Option<T>, UserError> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn json_files(dir: &Path) -> Result<Vec<PathBuf>, UserError> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut out = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_some_and(|e| e == "json") {
+            out.push(path);
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn password() -> ValidatedPassword {
+        ValidatedPassword::new("correct horse battery").unwrap()
+    }
+
+    #[test]
+    fn a_secret_key_is_drawn_once_and_then_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users_db/site.key");
+        let key = secret_key(&path).unwrap();
+        assert_eq!(key.len(), SECRET_KEY_LEN);
+        assert_eq!(secret_key(&path).unwrap(), key);
+        assert_ne!(random_key().unwrap(), key);
+
+        // A key written by an earlier version is read as it is.
+        fs::write(&path, "0123456789abcdef0123456789abcdef").unwrap();
+        assert_eq!(
+            secret_key(&path).unwrap(),
+            b"0123456789abcdef0123456789abcdef"
+        );
+    }
+
+    #[test]
+    fn roles_are_ordered_and_parsed() {
+        assert!(Role::Admin.allows(Role::Editor));
+        assert!(Role::Author.allows(Role::Author));
+        assert!(!Role::Viewer.allows(Role::Author));
+        assert_eq!("Editor".parse::<Role>().unwrap(), Role::Editor);
+        assert!("owner".parse::<Role>().is_err());
+        assert!(ValidatedPassword::new("short").is_err());
+        assert!(matches!(
+            valid_username("../x"),
+            Err(UserError::InvalidUsername(_))
+        ));
+    }
+
+    #[test]
+    fn passwords_verify_and_sessions_expire() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = UserStore::new(dir.path());
+        let alice = store.create("Alice", Role::Editor, &password()).unwrap();
+        assert_eq!(alice.username, "alice");
+        assert!(alice.password_hash.starts_with("$argon2"));
+        assert!(matches!(
+            store.create("alice", Role::Viewer, &password()),
+            Err(UserError::Exists(_))
+        ));
+
+        assert_eq!(
+            store.verify("alice", "correct horse battery").unwrap(),
+            Some(alice.clone())
+        );
+        assert_eq!(store.verify("alice", "wrong").unwrap(), None);
+        assert_eq!(store.verify("bob", "correct horse battery").unwrap(), None);
+
+        let token = store.issue_session(&alice, Duration::hours(1)).unwrap();
+        let now = Utc::now();
+        assert_eq!(store.session_user(&token, now).unwrap(), Some(alice));
+        assert_eq!(
+            store
+                .session_user(&token, now + Duration::hours(2))
+                .unwrap(),
+            None
+        );
+        assert_eq!(store.session_user(&token, now).unwrap(), None);
+    }
+
+    #[test]
+    fn removed_users_lose_their_sessions() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = UserStore::new(dir.path());
+        let bob = store.create("bob", Role::Author, &password()).unwrap();
+        let token = store.issue_session(&bob, Duration::hours(1)).unwrap();
+
+        assert_eq!(
+            store.set_role("bob", Role::Admin).unwrap().role,
+            Role::Admin
+        );
+        assert_eq!(store.list().unwrap().len(), 1);
+        store.remove("bob").unwrap();
+        assert_eq!(store.session_user(&token, Utc::now()).unwrap(), None);
+        assert!(store.list().unwrap().is_empty());
+    }
+
+    #[test]
+    fn sessions_are_listed_and_ended() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = UserStore::new(dir.path());
+        let ann = store.create("ann", Role::Editor, &password()).unwrap();
+        let bob = store.create("bob", Role::Editor, &password()).unwrap();
+        let laptop = store.issue_session(&ann, Duration::hours(1)).unwrap();
+        let phone = store.issue_session(&ann, Duration::hours(1)).unwrap();
+        let other = store.issue_session(&bob, Duration::hours(1)).unwrap();
+
+        let now = Utc::now();
+        let ids: Vec<String> = store
+            .sessions("ann", now)
+            .unwrap()
+            .into_iter()
+            .map(|s| s.id)
+            .collect();
+        assert_eq!(ids.len(), 2);
+        assert!(ids.contains(&UserStore::session_id(&laptop)));
+        assert!(store
+            .sessions("ann", now + Duration::hours(2))
+            .unwrap()
+            .is_empty());
+        assert_eq!(store.sessions("ann", now).unwrap().len(), 0);
+
+        let laptop = store.issue_session(&ann, Duration::hours(1)).unwrap();
+        let phone_id =
+            UserStore::session_id(&store.issue_session(&ann, Duration::hours(1)).unwrap());
+        assert!(!store.revoke_session_id("bob", &phone_id).unwrap());
+        assert!(store.revoke_session_id("ann", &phone_id).unwrap());
+        assert!(!store.revoke_session_id("ann", "../users/ann").unwrap());
+        assert_eq!(store.revoke_sessions("ann").unwrap(), 1);
+        assert_eq!(store.session_user(&laptop, Utc::now()).unwrap(), None);
+        assert_eq!(store.session_user(&phone, Utc::now()).unwrap(), None);
+        assert_eq!(store.session_user(&other, Utc::now()).unwrap(), Some(bob));
+    }
+
+    #[test]
+    fn reset_codes_are_single_use_and_expire() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = UserStore::new(dir.path());
+        let cy = store.create("cy", Role::Author, &password()).unwrap();
+        let session = store.issue_session(&cy, Duration::hours(1)).unwrap();
+        assert!(store
+            .issue_reset("cy", Duration::hours(1))
+            .unwrap()
+            .is_none());
+        assert!(matches!(
+            store.set_email("cy", Some("not an address")),
+            Err(UserError::InvalidEmail(_))
+        ));
+        store.set_email("cy", Some(" cy@example.com ")).unwrap();
+        assert!(store
+            .issue_reset("nobody", Duration::hours(1))
+            .unwrap()
+            .is_none());
+
+        let (user, code) = store
+            .issue_reset("cy", Duration::hours(1))
+            .unwrap()
+            .unwrap();
+        assert_eq!(user.email.as_deref(), Some("cy@example.com"));
+        let fresh = ValidatedPassword::new("a brand new passphrase").unwrap();
+        assert!(store
+            .redeem_reset(&code, &fresh, Utc::now())
+            .unwrap()
+            .is_some());
+        assert!(store
+            .redeem_reset(&code, &fresh, Utc::now())
+            .unwrap()
+            .is_none());
+        assert!(store
+            .verify("cy", "a brand new passphrase")
+            .unwrap()
+            .is_some());
+        assert_eq!(store.session_user(&session, Utc::now()).unwrap(), None);
+
+        let (_, code) = store
+            .issue_reset("cy", Duration::minutes(5))
+            .unwrap()
+            .unwrap();
+        let later = Utc::now() + Duration::minutes(10);
+        assert!(store
+            .redeem_reset(&code, &password(), later)
+            .unwrap()
+            .is_none());
+        assert!(store
+            .redeem_reset(&code, &password(), Utc::now())
+            .unwrap()
+            .is_none());
+        assert!(store
+            .verify("cy", "a brand new passphrase")
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn second_factors_work_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = UserStore::new(dir.path());
+        store.create("di", Role::Admin, &password()).unwrap();
+        let now = Utc::now();
+        assert!(!store.check_second_factor("di", "000000", now).unwrap());
+
+        let secret = store.begin_two_factor("di").unwrap();
+        assert!(!store.get("di").unwrap().unwrap().has_two_factor());
+        let code =
+            |at: DateTime<Utc>| totp::code_at(&secret, totp::step_at(at.timestamp())).unwrap();
+        assert_eq!(
+            store.confirm_two_factor("di", "000000x", now).unwrap(),
+            None
+        );
+        let recovery = store
+            .confirm_two_factor("di", &code(now), now)
+            .unwrap()
+            .unwrap();
+        assert_eq!(recovery.len(), RECOVERY_CODES);
+        assert!(store.get("di").unwrap().unwrap().has_two_factor());
+        assert!(matches!(
+            store.begin_two_factor("di"),
+            Err(UserError::TwoFactorOn(_))
+        ));
+
+        // The confirming code is spent; the next step's is not.
+        assert!(!store.check_second_factor("di", &code(now), now).unwrap());
+        let later = now + Duration::seconds(totp::STEP_SECS);
+        assert!(store
+            .check_second_factor("di", &code(later), later)
+            .unwrap());
+        assert!(store
+            .check_second_factor("di", &recovery[0].to_uppercase(), now)
+            .unwrap());
+        assert!(!store.check_second_factor("di", &recovery[0], now).unwrap());
+
+        assert!(!store.disable_two_factor("di").unwrap().has_two_factor());
+    }
+
+    #[test]
+    fn racing_verifications_spend_a_code_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = UserStore::new(dir.path());
+        store.create("di", Role::Admin, &password()).unwrap();
+        let now = Utc::now();
+        let secret = store.begin_two_factor("di").unwrap();
+        let code_at = |at: DateTime<Utc>| totp::code_at(&secret, totp::step_at(at.timestamp()));
+        let recovery = store
+            .confirm_two_factor("di", &code_at(now).unwrap(), now)
+            .unwrap()
+            .unwrap();
+        let later = now + Duration::seconds(totp::STEP_SECS);
+        let code = code_at(later).unwrap();
+
+        for code in [&code, &recovery[0]] {
+            let barrier = std::sync::Barrier::new(8);
+            let passed = std::thread::scope(|scope| {
+                let racers: Vec<_> = (0..8)
+                    .map(|_| {
+                        let store = store.clone();
+                        let barrier = &barrier;
+                        scope.spawn(move || {
+                            barrier.wait();
+                            store.check_second_factor("di", code, later).unwrap()
+                        })
+                    })
+                    .collect();
+                racers
+                    .into_iter()
+                    .map(|r| r.join().unwrap())
+                    .filter(|&ok| ok)
+                    .count()
+            });
+            assert_eq!(passed, 1, "{code}");
+        }
+    }
+}
@@ -0,0 +1,240 @@
+// crates/edge/src/debugging.rs
+
+// Per-request debugging for admins. A theme request that carries
+//
+//   X-Whisper-Debug: 1
+//
+// and the session cookie of an admin gets its response annotated:
+//
+//   X-Whisper-Cache: hit | shared | miss | uncacheable | bypass | off
+//   X-Whisper-Route: /docs/{tail:.*}
+//   X-Whisper-Theme: docsy
+//   X-Whisper-Template: page.hbs
+//   X-Whisper-Flags: beta,og-images
+//   Server-Timing: plugin;desc="seo before";dur=0.42, theme;dur=3.10, template;dur=1.20
+//
+// Nothing else changes for that request, and nothing at all for anyone
+// else: the header is ignored, without a word to the client, for visitors
+// and non-admin users. Annotated responses are `private, no-store` so no
+// shared cache keeps them.
+
+use crate::auth;
+use crate::db::users::Role;
+use actix_web::{
+    http::header::{HeaderName, HeaderValue, CACHE_CONTROL},
+    HttpRequest, HttpResponse,
+};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tracing::debug;
+
+/// The request header that asks for annotations.
+pub const DEBUG_HEADER: &str = "x-whisper-debug";
+
+/// How the response cache took part in a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheStatus {
+    /// Served from the cache.
+    Hit,
+    /// Served from an identical render already in progress.
+    Shared,
+    /// Rendered and stored.
+    Miss,
+    /// Rendered, but a plugin kept it out of the cache.
+    Uncacheable,
+    /// This request may not use the cache (credentials, preview, method).
+    Bypass,
+    /// No response cache is configured.
+    Off,
+}
+
+impl CacheStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            CacheStatus::Hit => "hit",
+            CacheStatus::Shared => "shared",
+            CacheStatus::Miss => "miss",
+            CacheStatus::Uncacheable => "uncacheable",
+            CacheStatus::Bypass => "bypass",
+            CacheStatus::Off => "off",
+        }
+    }
+}
+
+/// What one debugged request did, collected while it is handled.
+#[derive(Debug, Clone)]
+pub struct DebugTrace {
+    route: String,
+    theme: String,
+    cache: CacheStatus,
+    template: Option<String>,
+    flags: Vec<String>,
+    timings: Vec<(&'static str, String, Duration)>,
+}
+
+impl DebugTrace {
+    fn new(req: &HttpRequest, theme: &str) -> Self {
+        Self {
+            route: req.match_pattern().unwrap_or_else(|| req.path().to_owned()),
+            theme: theme.to_owned(),
+            cache: CacheStatus::Off,
+            template: None,
+            flags: Vec::new(),
+            timings: Vec::new(),
+        }
+    }
+
+    pub fn cache(&mut self, status: CacheStatus) {
+        self.cache = status;
+    }
+
+    pub fn template(&mut self, template: &str) {
+        self.template = Some(template.to_owned());
+    }
+
+    /// The flags that are on.
+    pub fn flags(&mut self, flags: &BTreeMap<String, bool>) {
+        self.flags = flags
+            .iter()
+            .filter(|(_, on)| **on)
+            .map(|(name, _)| name.clone())
+            .collect();
+    }
+
+    /// Record a `Server-Timing` metric, e.g. `("plugin", "seo before", d)`.
+    pub fn timing(&mut self, metric: &'static str, desc: impl Into<String>, took: Duration) {
+        self.timings.push((metric, desc.into(), took));
+    }
+
+    fn server_timing(&self) -> String {
+        self.timings
+            .iter()
+            .map(|(metric, desc, took)| {
+                let ms = took.as_secs_f64() * 1000.0;
+                match desc.is_empty() {
+                    true => format!("{metric};dur={ms:.2}"),
+                    false => format!("{metric};desc=\"{}\";dur={ms:.2}", desc.replace('"', "'")),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Add the annotations to `resp`.
+    pub fn annotate(&self, mut resp: HttpResponse) -> HttpResponse {
+        let headers = resp.headers_mut();
+        let mut set = |name: &'static str, value: &str| {
+            if let Ok(value) = HeaderValue::from_str(value) {
+                headers.insert(HeaderName::from_static(name), value);
+            }
+        };
+        set("x-whisper-cache", self.cache.as_str());
+        set("x-whisper-route", &self.route);
+        set("x-whisper-theme", &self.theme);
+        if let Some(template) = &self.template {
+            set("x-whisper-template", template);
+        }
+        set("x-whisper-flags", &self.flags.join(","));
+        if !self.timings.is_empty() {
+            set("server-timing", &self.server_timing());
+        }
+        headers.insert(CACHE_CONTROL, HeaderValue::from_static("private, no-store"));
+        resp
+    }
+}
+
+/// A trace for `req` if it asks for one and comes from an admin.
+pub async fn trace_for(req: &HttpRequest, theme: &str) -> Option<DebugTrace> {
+    let asked = req
+        .headers()
+        .get(DEBUG_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| matches!(v.trim(), "1" | "true" | "on"));
+    if !asked {
+        return None;
+    }
+    match auth::current_user(req).await {
+        Ok(Some(user)) if user.role.allows(Role::Admin) => Some(DebugTrace::new(req, theme)),
+        _ => {
+            debug!("Ignoring {} on {}: not an admin", DEBUG_HEADER, req.path());
+            None
+        }
+    }
+}
+
+/// `resp`, annotated when there is a trace.
+pub fn annotate(trace: Option<&DebugTrace>, resp: HttpResponse) -> HttpResponse {
+    match trace {
+        Some(trace) => trace.annotate(resp),
+        None => resp,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::SESSION_COOKIE;
+    use crate::db::users::ValidatedPassword;
+    use actix_web::cookie::Cookie;
+    use actix_web::test::TestRequest;
+
+    #[actix_web::test]
+    async fn only_admins_get_annotated_responses() {
+        let site = tempfile::tempdir().unwrap();
+        let store = auth::store(site.path());
+        let password = ValidatedPassword::new("a long enough secret").unwrap();
+        let mut cookies = Vec::new();
+        for (name, role) in [("ada", Role::Admin), ("eve", Role::Editor)] {
+            let user = store.create(name, role, &password).unwrap();
+            let token = store
+                .issue_session(&user, chrono::Duration::hours(1))
+                .unwrap();
+            cookies.push(Cookie::new(SESSION_COOKIE, token));
+        }
+        let request = |cookie: Option<&Cookie<'static>>, header: bool| {
+            let mut req = TestRequest::get()
+                .uri("/docs/a.html")
+                .app_data(store.clone());
+            if let Some(cookie) = cookie {
+                req = req.cookie(cookie.clone());
+            }
+            if header {
+                req = req.insert_header((DEBUG_HEADER, "1"));
+            }
+            req.to_http_request()
+        };
+
+        assert!(trace_for(&request(Some(&cookies[0]), false), "docsy")
+            .await
+            .is_none());
+        assert!(trace_for(&request(None, true), "docsy").await.is_none());
+        assert!(trace_for(&request(Some(&cookies[1]), true), "docsy")
+            .await
+            .is_none());
+
+        let mut trace = trace_for(&request(Some(&cookies[0]), true), "docsy")
+            .await
+            .unwrap();
+        trace.cache(CacheStatus::Miss);
+        trace.template("page.hbs");
+        trace.flags(&BTreeMap::from([
+            ("beta".to_owned(), true),
+            ("old".to_owned(), false),
+        ]));
+        trace.timing("plugin", "seo before", Duration::from_micros(1500));
+        trace.timing("render", "", Duration::from_millis(3));
+
+        let resp = annotate(Some(&trace), HttpResponse::Ok().finish());
+        let header = |name: &str| resp.headers().get(name).unwrap().to_str().unwrap();
+        assert_eq!(header("x-whisper-cache"), "miss");
+        assert_eq!(header("x-whisper-route"), "/docs/a.html");
+        assert_eq!(header("x-whisper-theme"), "docsy");
+        assert_eq!(header("x-whisper-template"), "page.hbs");
+        assert_eq!(header("x-whisper-flags"), "beta");
+        assert_eq!(
+            header("server-timing"),
+            "plugin;desc=\"seo before\";dur=1.50, render;dur=3.00"
+        );
+        assert_eq!(header("cache-control"), "private, no-store");
+    }
+}
@@ -0,0 +1,603 @@
+// crates/edge/src/doctor.rs
+
+// Diagnostics for `whispercms doctor`: checks a site directory without
+// starting it, and says how to fix what it finds.
+//
+//   settings     settings.toml exists and parses
+//   permissions  the directory is writable and key files are private
+//   secrets      variables the settings name are set; a TLS certificate
+//   stores       the user store reads and has an admin
+//   migrations   none pending and none from a newer core
+//   integrity    front matter lints clean; extensions match extensions.lock
+//   extensions   every plugin and theme manifest and script loads
+//   ports        the listeners' ports are free
+//
+// Run it while the site is stopped: a running site holds its ports. With
+// `--json` the report is printed as
+//
+//   {"dir":"./site","healthy":false,"checks":[{"name":"ports","status":"fail",
+//    "detail":"127.0.0.1:8080 is in use","fix":"…"}]}
+//
+// where a site is healthy when no check failed; warnings do not keep it from
+// starting.
+
+use crate::db::migrate::{MigrateError, Migrator};
+use crate::db::users::{Role, UserStore, USERS_DB_DIR};
+use crate::fs::ext::{discover_all_plugins, discover_themes};
+use crate::fs::filter::{build_filename_regex, DEFAULT_CONTENT_EXTS};
+use crate::fs::lock::{Lockfile, LOCK_FILE};
+use crate::init::SETTINGS_FILE;
+use crate::proxy::pick_cert_key_pair;
+use domain::setting::{IntegrityMode, MailTransport, Settings, TlsMode};
+use serde::Serialize;
+use serve::front_matter;
+use std::fs;
+use std::io;
+use std::net::{SocketAddr, TcpListener};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Written and removed to see whether the site directory is writable.
+const PROBE_FILE: &str = ".doctor.tmp";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    /// Worth fixing; the site still starts.
+    Warn,
+    /// The site will not start, or a part of it will not work.
+    Fail,
+}
+
+/// One finding.
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    pub detail: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fix: Option<String>,
+}
+
+impl Check {
+    fn ok(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: Status::Ok,
+            detail: detail.into(),
+            fix: None,
+        }
+    }
+
+    fn warn(name: &'static str, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            status: Status::Warn,
+            fix: Some(fix.into()),
+            ..Self::ok(name, detail)
+        }
+    }
+
+    fn fail(name: &'static str, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            status: Status::Fail,
+            fix: Some(fix.into()),
+            ..Self::ok(name, detail)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub dir: PathBuf,
+    /// No check failed.
+    pub healthy: bool,
+    pub checks: Vec<Check>,
+}
+
+/// Check the site in `dir`. Without readable settings only the permissions
+/// are checked besides.
+pub fn diagnose(dir: &Path) -> Report {
+    let (check, settings) = settings(dir);
+    let mut checks = vec![check];
+    checks.extend(permissions(dir));
+    if let Some(settings) = &settings {
+        checks.extend(secrets(dir, settings));
+        checks.extend(stores(dir));
+        checks.extend(migrations(dir, settings));
+        checks.extend(integrity(dir, settings));
+        checks.extend(extensions(dir, settings));
+        checks.extend(ports(settings));
+    }
+    Report {
+        dir: dir.to_path_buf(),
+        healthy: checks.iter().all(|c| c.status != Status::Fail),
+        checks,
+    }
+}
+
+/// `found`, or one passing check saying `detail` when it is empty.
+fn or_ok(found: Vec<Check>, name: &'static str, detail: impl Into<String>) -> Vec<Check> {
+    if found.is_empty() {
+        vec![Check::ok(name, detail)]
+    } else {
+        found
+    }
+}
+
+fn settings(dir: &Path) -> (Check, Option<Settings>) {
+    const NAME: &str = "settings";
+    let path = dir.join(SETTINGS_FILE);
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let fix = "run `whispercms init` to write one";
+            return (
+                Check::fail(NAME, format!("no {}", path.display()), fix),
+                None,
+            );
+        }
+        Err(e) => {
+            let fix = "make it readable by the user running whispercms";
+            return (
+                Check::fail(NAME, format!("{}: {e}", path.display()), fix),
+                None,
+            );
+        }
+    };
+    match toml::from_str::<Settings>(&text) {
+        Ok(settings) => (
+            Check::ok(NAME, format!("{} parses", path.display())),
+            Some(settings),
+        ),
+        Err(e) => {
+            let detail = format!("{}: {}", path.display(), e.to_string().trim());
+            let fix = "correct the setting named above";
+            (Check::fail(NAME, detail, fix), None)
+        }
+    }
+}
+
+fn permissions(dir: &Path) -> Vec<Check> {
+    const NAME: &str = "permissions";
+    let mut found = Vec::new();
+    let probe = dir.join(PROBE_FILE);
+    match fs::write(&probe, b"").and_then(|()| fs::remove_file(&probe)) {
+        Ok(()) => {}
+        Err(e) => found.push(Check::fail(
+            NAME,
+            format!("{} is not writable: {e}", dir.display()),
+            format!(
+                "give the user running whispercms write access to {}",
+                dir.display()
+            ),
+        )),
+    }
+
+    // The session, CSRF and preview keys, and TLS keys wherever they are.
+    for keys in [Some(dir.join(USERS_DB_DIR)), cert_dir(dir)]
+        .into_iter()
+        .flatten()
+    {
+        for key in key_files(&keys) {
+            if shared(&key) {
+                found.push(Check::warn(
+                    NAME,
+                    format!("{} is readable by other users", key.display()),
+                    format!("run `chmod 600 {}`", key.display()),
+                ));
+            }
+        }
+    }
+    or_ok(found, NAME, format!("{} is writable", dir.display()))
+}
+
+/// The certificate directory, read apart from the other settings so the
+/// keys are checked even when the rest does not parse.
+fn cert_dir(dir: &Path) -> Option<PathBuf> {
+    let text = fs::read_to_string(dir.join(SETTINGS_FILE)).ok()?;
+    let value: toml::Value = toml::from_str(&text).ok()?;
+    Some(dir.join(value.get("cert")?.get("dir")?.as_str()?))
+}
+
+fn key_files(dir: &Path) -> Vec<PathBuf> {
+    fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_file() && p.extension().is_some_and(|x| x == "key"))
+        .collect()
+}
+
+#[cfg(unix)]
+fn shared(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).is_ok_and(|m| m.permissions().mode() & 0o077 != 0)
+}
+
+#[cfg(not(unix))]
+fn shared(_path: &Path) -> bool {
+    false
+}
+
+fn secrets(dir: &Path, settings: &Settings) -> Vec<Check> {
+    const NAME: &str = "secrets";
+    let mut found = Vec::new();
+    let unset = |var: &str| std::env::var_os(var).is_none();
+    let export = |var: &str| format!("set {var} in the environment whispercms runs in");
+
+    if let Some(mail) = settings
+        .mail
+        .as_ref()
+        .filter(|m| m.transport == MailTransport::Smtp)
+    {
+        match mail.smtp.as_ref().map(|s| (&s.username, &s.password_env)) {
+            None => found.push(Check::fail(
+                NAME,
+                "[mail] sends over SMTP without a [mail.smtp] relay",
+                "add [mail.smtp] host = \"…\", or set [mail] transport = \"file\"",
+            )),
+            Some((Some(_), None)) => found.push(Check::fail(
+                NAME,
+                "[mail.smtp] has a username but no password_env",
+                "set password_env to the variable holding the password",
+            )),
+            Some((Some(_), Some(var))) if unset(var) => found.push(Check::fail(
+                NAME,
+                format!("{var}, the SMTP password, is not set"),
+                export(var),
+            )),
+            Some(_) => {}
+        }
+    }
+    for hook in settings.webhooks.iter().flatten() {
+        if let Some(var) = hook.secret_env.as_deref().filter(|v| unset(v)) {
+            found.push(Check::fail(
+                NAME,
+                format!("{var}, the signing key of webhook {}, is not set", hook.url),
+                export(var),
+            ));
+        }
+    }
+
+    let cert_dir = dir.join(&settings.cert.dir);
+    match settings.cert.mode {
+        TlsMode::Manual => match pick_cert_key_pair(&cert_dir) {
+            Ok(Some(_)) => {}
+            Ok(None) => found.push(Check::warn(
+                NAME,
+                format!(
+                    "no certificate and key in {}; the public listeners will not start",
+                    cert_dir.display()
+                ),
+                format!(
+                    "put a .crt or .pem and its .key in {}, or set [cert] mode to \"acme\" or \"off\"",
+                    cert_dir.display()
+                ),
+            )),
+            Err(e) => found.push(Check::fail(
+                NAME,
+                format!("{}: {e}", cert_dir.display()),
+                "make the certificate directory readable by the user running whispercms",
+            )),
+        },
+        TlsMode::Acme
+            if settings
+                .cert
+                .acme
+                .as_ref()
+                .is_none_or(|a| a.domains.is_empty()) =>
+        {
+            found.push(Check::fail(
+                NAME,
+                "[cert] mode = \"acme\" without domains",
+                "add [cert.acme] domains = [\"example.com\"]",
+            ))
+        }
+        TlsMode::Acme | TlsMode::Off => {}
+    }
+    or_ok(found, NAME, "every secret the settings name is present")
+}
+
+fn stores(dir: &Path) -> Vec<Check> {
+    const NAME: &str = "stores";
+    let path = dir.join(USERS_DB_DIR);
+    match UserStore::new(path.clone()).list() {
+        Ok(users) if !users.iter().any(|u| u.role.allows(Role::Admin)) => vec![Check::warn(
+            NAME,
+            format!("{} has no admin account", path.display()),
+            format!(
+                "run `whispercms user add {} --username NAME --role admin`",
+                dir.display()
+            ),
+        )],
+        Ok(users) => vec![Check::ok(
+            NAME,
+            format!("{} reads: {} account(s)", path.display(), users.len()),
+        )],
+        Err(e) => vec![Check::fail(
+            NAME,
+            format!("{}: {e}", path.display()),
+            "fix or remove the file named above, or restore the store with `whispercms restore`",
+        )],
+    }
+}
+
+fn migrations(dir: &Path, settings: &Settings) -> Vec<Check> {
+    const NAME: &str = "migrations";
+    match Migrator::new(dir, Some(settings)).up(None, true) {
+        Ok(pending) if pending.is_empty() => vec![Check::ok(NAME, "none pending")],
+        Ok(pending) => vec![Check::warn(
+            NAME,
+            format!(
+                "{} pending: {}",
+                pending.len(),
+                pending
+                    .iter()
+                    .map(|m| format!("{} {}", m.version, m.name))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            format!(
+                "run `whispercms migrate {} --up`, or start the site, which applies them",
+                dir.display()
+            ),
+        )],
+        Err(e @ MigrateError::Newer { .. }) => vec![Check::fail(
+            NAME,
+            e.to_string(),
+            "run this site with the core that migrated it, or restore a backup from before",
+        )],
+        Err(e) => vec![Check::fail(
+            NAME,
+            e.to_string(),
+            format!("`whispercms migrate {}` shows the record", dir.display()),
+        )],
+    }
+}
+
+fn integrity(dir: &Path, settings: &Settings) -> Vec<Check> {
+    const NAME: &str = "integrity";
+    let mut found = Vec::new();
+
+    let (root, exts) = match &settings.content {
+        Some(c) => (dir.join(&c.dir), c.extensions.clone()),
+        None => (dir.join("./content/"), Vec::new()),
+    };
+    let exts = match exts.len() {
+        0 => DEFAULT_CONTENT_EXTS
+            .iter()
+            .map(|s| (*s).to_owned())
+            .collect(),
+        _ => exts,
+    };
+    match build_filename_regex(exts) {
+        Err(e) => found.push(Check::fail(
+            NAME,
+            format!("[content] extensions: {e}"),
+            "list plain extensions, e.g. [\"md\", \"html\"]",
+        )),
+        Ok(_) if !root.is_dir() => found.push(Check::warn(
+            NAME,
+            format!("no content directory at {}", root.display()),
+            format!("create {}, or set [content] dir", root.display()),
+        )),
+        Ok(re) => {
+            let (mut files, mut problems) = (0, 0);
+            for entry in WalkDir::new(&root)
+                .into_iter()
+                .filter_map(|e| e.ok())
+                .filter(|e| e.file_type().is_file())
+                .filter(|e| re.is_match(&e.file_name().to_string_lossy()))
+            {
+                let rel = entry.path().strip_prefix(&root).unwrap_or(entry.path());
+                match fs::read_to_string(entry.path()) {
+                    Ok(text) if !front_matter::lint(rel, &text).is_empty() => files += 1,
+                    Ok(_) => {}
+                    Err(_) => problems += 1,
+                }
+            }
+            if files + problems > 0 {
+                found.push(Check::warn(
+                    NAME,
+                    format!(
+                        "{files} content file(s) with front matter problems, {problems} unreadable"
+                    ),
+                    format!(
+                        "`whispercms content lint {}` lists the problems",
+                        dir.display()
+                    ),
+                ));
+            }
+        }
+    }
+
+    let ext = settings.ext.as_ref();
+    let mode = ext.map(|e| e.integrity).unwrap_or_default();
+    let ext_dir = dir.join(ext.map_or_else(|| PathBuf::from("./extensions/"), |e| e.dir.clone()));
+    let lock_path = dir.join(LOCK_FILE);
+    // Without a lockfile there is nothing to compare; start writes one.
+    let drift = match mode {
+        IntegrityMode::Off => Ok(Vec::new()),
+        _ => Lockfile::load(&lock_path)
+            .and_then(|lock| lock.map_or_else(|| Ok(Vec::new()), |l| l.verify(&ext_dir))),
+    };
+    match drift {
+        Ok(drift) if drift.is_empty() => {}
+        Ok(drift) => {
+            let detail = format!(
+                "{} extension file(s) differ from {}: {}",
+                drift.len(),
+                lock_path.display(),
+                drift
+                    .iter()
+                    .map(|d| d.path.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            let fix = format!(
+                "check them, then run `whispercms verify {} --update`",
+                dir.display()
+            );
+            found.push(match mode {
+                IntegrityMode::Refuse => Check::fail(NAME, detail, fix),
+                _ => Check::warn(NAME, detail, fix),
+            });
+        }
+        Err(e) => found.push(Check::fail(
+            NAME,
+            format!("{}: {e}", lock_path.display()),
+            format!(
+                "remove it and run `whispercms verify {} --update`",
+                dir.display()
+            ),
+        )),
+    }
+
+    or_ok(
+        found,
+        NAME,
+        "content front matter and extension files check out",
+    )
+}
+
+fn extensions(dir: &Path, settings: &Settings) -> Vec<Check> {
+    const NAME: &str = "extensions";
+    let ext_dir = dir.join(
+        settings
+            .ext
+            .as_ref()
+            .map_or_else(|| PathBuf::from("./extensions/"), |e| e.dir.clone()),
+    );
+    let fix = "correct the file named above, or remove the extension";
+    let plugins = discover_all_plugins(ext_dir.join("plugins/"));
+    let themes = discover_themes(ext_dir.join("themes/"));
+    match (plugins, themes) {
+        (Ok(plugins), Ok(themes)) => vec![Check::ok(
+            NAME,
+            format!("{} plugin(s), {} theme(s)", plugins.len(), themes.len()),
+        )],
+        (plugins, themes) => [plugins.err(), themes.err()]
+            .into_iter()
+            .flatten()
+            .map(|e| Check::fail(NAME, e.to_string(), fix))
+            .collect(),
+    }
+}
+
+fn ports(settings: &Settings) -> Vec<Check> {
+    const NAME: &str = "ports";
+    let edge = &settings.edge;
+    let loopback = &settings.loopback;
+    let mut listeners = vec![
+        (loopback.ip, loopback.port_a, "[loopback] port_a"),
+        (loopback.ip, loopback.port_b, "[loopback] port_b"),
+    ];
+    if settings.cert.mode != TlsMode::Off {
+        listeners.push((edge.ip, edge.http_port, "[edge] http_port"));
+        listeners.push((edge.ip, edge.https_port, "[edge] https_port"));
+    }
+
+    let found = listeners
+        .into_iter()
+        .filter_map(|(ip, port, setting)| {
+            let addr = SocketAddr::new(ip, port);
+            let e = TcpListener::bind(addr).err()?;
+            Some(match e.kind() {
+                io::ErrorKind::AddrInUse => Check::fail(
+                    NAME,
+                    format!("{addr} is in use"),
+                    format!(
+                        "stop what listens there (a running site holds it too), or change {setting}"
+                    ),
+                ),
+                io::ErrorKind::PermissionDenied => Check::fail(
+                    NAME,
+                    format!("listening on {addr} is not permitted"),
+                    format!("grant the binary CAP_NET_BIND_SERVICE, or set {setting} above 1023"),
+                ),
+                _ => Check::fail(
+                    NAME,
+                    format!("{addr}: {e}"),
+                    format!("check that {setting}'s ip is an address of this host"),
+                ),
+            })
+        })
+        .collect();
+    or_ok(found, NAME, "every listener's port is free")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_what_stops_a_site_with_a_fix_for_each() {
+        let dir = tempfile::tempdir().unwrap();
+        let report = diagnose(dir.path());
+        assert!(!report.healthy);
+        assert_eq!(report.checks[0].name, "settings");
+        assert_eq!(report.checks[0].status, Status::Fail);
+        assert_eq!(report.checks.len(), 2, "no settings, no further checks");
+
+        let held = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = held.local_addr().unwrap().port();
+        fs::write(
+            dir.path().join(SETTINGS_FILE),
+            format!(
+                r#"
+[cert]
+dir = "./certs/"
+
+[edge]
+ip = "127.0.0.1"
+http_port = 0
+https_port = 0
+
+[loopback]
+ip = "127.0.0.1"
+port_a = {port}
+port_b = 0
+
+[mail]
+from = "site@example.com"
+
+[mail.smtp]
+host = "localhost"
+username = "site"
+password_env = "WHISPERCMS_DOCTOR_TEST_UNSET"
+"#
+            ),
+        )
+        .unwrap();
+
+        let report = diagnose(dir.path());
+        let find = |name: &str, status: Status| {
+            report
+                .checks
+                .iter()
+                .find(|c| c.name == name && c.status == status)
+                .unwrap_or_else(|| panic!("no {status:?} {name} in {:#?}", report.checks))
+        };
+        assert!(!report.healthy);
+        find("settings", Status::Ok);
+        find("permissions", Status::Ok);
+        assert!(find("secrets", Status::Fail)
+            .detail
+            .contains("WHISPERCMS_DOCTOR_TEST_UNSET"));
+        find("secrets", Status::Warn);
+        find("stores", Status::Warn);
+        find("migrations", Status::Warn);
+        find("integrity", Status::Warn);
+        find("extensions", Status::Ok);
+        let ports = find("ports", Status::Fail);
+        assert_eq!(ports.detail, format!("127.0.0.1:{port} is in use"));
+        assert!(ports.fix.as_deref().unwrap().contains("[loopback] port_a"));
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["healthy"], false);
+        assert!(json["checks"][0].get("fix").is_none());
+        drop(held);
+    }
+}
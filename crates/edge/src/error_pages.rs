@@ -0,0 +1,242 @@
+// crates/edge/src/error_pages.rs
+
+// Error responses as pages of the theme (serve::error_page). A 4xx or 5xx
+// response to a request that accepts `text/html` is rendered again with the
+// theme's `404.hbs` or `error.hbs`, else the built-in page, when its body is
+// empty or plain text; JSON errors from the APIs and pages a handler already
+// rendered (maintenance, say) keep theirs. Status and headers stay as the
+// handler set them. The theme is the one mounted over the path, else the one
+// at `/`, else the first.
+//
+// The model carries the error only in dev mode:
+//
+//   [edge]
+//   dev = true
+//
+// where it is the detail a handler attached with `internal_error`, else the
+// plain-text body. In production the error stays in the log, under the
+// request id the page shows. The `[edge]` table is app data of the scope the
+// layer wraps, beside the theme bindings.
+
+use crate::assets::AssetManifests;
+use crate::fs::ext::ThemeBinding;
+use crate::request_id;
+use actix_web::{
+    body::{self, BodySize, BoxBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{HeaderMap, HeaderValue, ACCEPT, CONTENT_LENGTH, CONTENT_TYPE},
+    middleware::Next,
+    web, Error, HttpRequest, HttpResponse,
+};
+use domain::setting::EdgeSettings;
+use serde_json::Value as Json;
+use serve::error_page::{page_model, template_for, DEFAULT_ERROR_TEMPLATE};
+use serve::render::{render_default, template::TemplateRegistry, TemplateEngine};
+use std::fmt::Display;
+use tracing::error;
+
+/// Largest plain-text body replaced by a page.
+const MAX_PLAIN_BODY: u64 = 64 * 1024;
+
+/// Whether pages for `req` show error details.
+fn dev(req: &HttpRequest) -> bool {
+    req.app_data::<web::Data<EdgeSettings>>()
+        .is_some_and(|edge| edge.dev)
+}
+
+/// The theme bindings, as app data of the scope around the router's
+/// middleware layers.
+#[derive(Clone)]
+pub struct ErrorThemes(pub Vec<ThemeBinding>);
+
+/// What went wrong, for the page in dev mode.
+#[derive(Debug, Clone)]
+struct ErrorDetail(String);
+
+/// A 500 whose body is `summary` and whose page, in dev mode, shows
+/// `detail`.
+pub fn internal_error(summary: &'static str, detail: impl Display) -> HttpResponse {
+    let mut res = HttpResponse::InternalServerError().body(summary);
+    res.extensions_mut()
+        .insert(ErrorDetail(format!("{summary}: {detail}")));
+    res
+}
+
+/// `actix_web::middleware::from_fn` middleware; just inside the request id.
+pub async fn themed(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let wants_html = accepts_html(req.headers());
+    let res = next.call(req).await?.map_into_boxed_body();
+    let status = res.status();
+    if !wants_html || !(status.is_client_error() || status.is_server_error()) || !plain(&res) {
+        return Ok(res);
+    }
+
+    let (req, res) = res.into_parts();
+    let detail = res.extensions().get::<ErrorDetail>().cloned();
+    let (mut res, body) = res.into_parts();
+    let error = match (dev(&req), detail) {
+        (false, _) => None,
+        (true, Some(ErrorDetail(detail))) => Some(detail),
+        (true, None) => body::to_bytes(body)
+            .await
+            .ok()
+            .map(|b| String::from_utf8_lossy(&b).trim().to_owned())
+            .filter(|s| !s.is_empty()),
+    };
+    let model = page_model(
+        status.as_u16(),
+        req.path(),
+        request_id::of(req.headers()),
+        error.as_deref(),
+    );
+    let html = theme_page(&req, template_for(status.as_u16()), &model).unwrap_or_else(|| {
+        let mut body = Vec::new();
+        if let Err(e) = render_default("error", DEFAULT_ERROR_TEMPLATE, &model, &mut body) {
+            error!("Error page: rendering the built-in page failed: {}", e);
+        }
+        body
+    });
+
+    let headers = res.headers_mut();
+    headers.remove(CONTENT_LENGTH);
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("text/html; charset=utf-8"),
+    );
+    Ok(ServiceResponse::new(req, res.set_body(BoxBody::new(html))))
+}
+
+fn accepts_html(headers: &HeaderMap) -> bool {
+    headers
+        .get(ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("text/html"))
+}
+
+/// An empty or small plain-text body.
+fn plain(res: &ServiceResponse<BoxBody>) -> bool {
+    let text = res
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_none_or(|v| v.starts_with("text/plain"));
+    let small = match res.response().body().size() {
+        BodySize::None => true,
+        BodySize::Sized(n) => n <= MAX_PLAIN_BODY,
+        BodySize::Stream => false,
+    };
+    text && small
+}
+
+/// The binding with the longest mount over `path`, else the one at `/`,
+/// else the first.
+fn theme_for<'a>(bindings: &'a [ThemeBinding], path: &str) -> Option<&'a ThemeBinding> {
+    let mounted = |b: &&ThemeBinding| {
+        let mount = b.mount_path.trim_end_matches('/');
+        path.strip_prefix(mount)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    };
+    bindings
+        .iter()
+        .filter(mounted)
+        .max_by_key(|b| b.mount_path.len())
+        .or_else(|| bindings.iter().find(|b| b.mount_path == "/"))
+        .or_else(|| bindings.first())
+}
+
+/// `template` of the theme over `req`'s path, rendered with `model`; `None`
+/// when there is no such theme or template, or it fails to render.
+pub fn theme_page(req: &HttpRequest, template: &str, model: &Json) -> Option<Vec<u8>> {
+    let themes = req.app_data::<web::Data<ErrorThemes>>()?;
+    let theme = theme_for(&themes.0, req.path())?;
+    let registry = TemplateRegistry::new(theme.template_root.clone())
+        .with_language(theme.engine)
+        .with_helpers(AssetManifests::of(req).helpers(&theme.assets_dir(), &theme.mount_path));
+    registry.template_modified(template)?;
+
+    let mut body = Vec::new();
+    match registry.render_to_write(template, model, &mut body) {
+        Ok(()) => Some(body),
+        Err(e) => {
+            error!("Error page: rendering {} failed: {}", template, e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, middleware::from_fn, test, App};
+    use std::fs;
+
+    #[actix_web::test]
+    async fn html_clients_get_the_themes_pages_and_json_errors_are_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let templates = dir.path().join("templates");
+        fs::create_dir_all(&templates).unwrap();
+        fs::write(
+            templates.join("404.hbs"),
+            "<h1>Lost: {{path}}</h1>{{#if error}}{{error}}{{/if}}",
+        )
+        .unwrap();
+        let themes = ErrorThemes(vec![
+            ThemeBinding::new("/", "site", templates),
+            ThemeBinding::new("/docs", "docs", dir.path().join("missing")),
+        ]);
+        // As in the router: the bindings are data of the scope the layer
+        // wraps, so it only sees them on the response's request.
+        let app = test::init_service(
+            App::new().service(
+                web::scope("")
+                    .app_data(web::Data::new(themes))
+                    .wrap(from_fn(themed))
+                    .route(
+                        "/boom",
+                        web::get().to(|| async { internal_error("Theme runtime error", "gone") }),
+                    )
+                    .route(
+                        "/api",
+                        web::get().to(|| async {
+                            HttpResponse::NotFound().json(serde_json::json!({ "error": "no" }))
+                        }),
+                    )
+                    .default_service(web::to(HttpResponse::NotFound)),
+            ),
+        )
+        .await;
+        let html = |uri: &str| {
+            test::TestRequest::get()
+                .uri(uri)
+                .insert_header((ACCEPT, "text/html,*/*;q=0.8"))
+                .to_request()
+        };
+        let body = |res: ServiceResponse| async move {
+            String::from_utf8(test::read_body(res).await.to_vec()).unwrap()
+        };
+
+        let res = test::call_service(&app, html("/nowhere")).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert_eq!(body(res).await, "<h1>Lost: /nowhere</h1>");
+
+        // A mount without the template falls back to the built-in page.
+        let res = test::call_service(&app, html("/docs/gone")).await;
+        assert!(body(res).await.contains("<h1>404 Not Found</h1>"));
+
+        // Outside dev mode the error stays out of the page.
+        let res = test::call_service(&app, html("/boom")).await;
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let page = body(res).await;
+        assert!(page.contains("500 Internal Server Error"));
+        assert!(!page.contains("gone"));
+
+        let res = test::call_service(&app, html("/api")).await;
+        assert_eq!(body(res).await, r#"{"error":"no"}"#);
+        let res =
+            test::call_service(&app, test::TestRequest::get().uri("/boom").to_request()).await;
+        assert_eq!(body(res).await, "Theme runtime error");
+    }
+}
@@ -0,0 +1,341 @@
+// crates/edge/src/export.rs
+
+// `whispercms export DIR --out ./public`: the whole site rendered to files
+// for a static host (Netlify, S3, …).
+//
+// The site starts as it would to serve (settings, content scan, plugins and
+// themes), but instead of listening, the router is called in-process for:
+//
+// - every published document at its permalink, and `/`;
+// - each tag, category and series page with its feed, and each author page
+//   with its feed;
+// - the site feeds, `/sitemap.xml` and `/robots.txt`;
+//
+// and then for every same-site link, `src` and `<loc>` in what those return
+// (later archive pages, sitemap pages, theme assets, images), until nothing
+// new turns up. A 200 is written under `--out` (`/about/` and `/tags/rust`
+// as `…/index.html`, `/feed.xml` as itself); a redirect becomes a line of
+// `_redirects`, which Netlify reads and other hosts can translate. Anything
+// else is reported and skipped.
+//
+// Pages render through the normal plugin and theme pipeline, so what is
+// exported is what a reader of the live site would get at `--base-url`
+// (default `[sitemap] base_url`).
+
+use crate::api::content::ContentQuery;
+use crate::fs::ext::ThemeBinding;
+use crate::fs::index::SiteContent;
+use crate::normalize::NormalizeRequest;
+use crate::proxy::EdgeError;
+use crate::router::build_app_router;
+use crate::site::Site;
+use actix_web::http::header::{CONTENT_TYPE, HOST, LOCATION};
+use actix_web::{test as service, App};
+use adapt::mql::{Filter, FindOptions};
+use adapt::runtime::bootstrap::RuntimeHandles;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+use regex::Regex;
+use serde_json::Value as Json;
+use serve::taxonomy::Taxonomy;
+use std::collections::{BTreeSet, HashSet, VecDeque};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, LazyLock};
+use tracing::{info, warn};
+
+/// Where Netlify looks for redirects.
+pub const REDIRECTS_FILE: &str = "_redirects";
+
+/// Characters encoded in a taxonomy term or author slug put into a path.
+const SEGMENT_ENCODE: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+static LINK: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?:\b(?:href|src)\s*=\s*["']([^"']+)["']|<loc>\s*([^<\s]+)\s*</loc>|url\(\s*["']?([^"')]+)["']?\s*\))"#)
+        .expect("link pattern")
+});
+
+/// What an export wrote and skipped.
+#[derive(Debug, Default)]
+pub struct ExportSummary {
+    pub files: usize,
+    pub redirects: usize,
+    /// Paths that answered neither 200 nor a redirect, with the status.
+    pub failed: Vec<(String, u16)>,
+}
+
+fn segment(s: &str) -> String {
+    utf8_percent_encode(s, SEGMENT_ENCODE).to_string()
+}
+
+/// The paths every export of the site with `content` starts from.
+async fn seeds(content: &SiteContent) -> Result<Vec<String>, EdgeError> {
+    let q = ContentQuery {
+        filter: Filter::And(vec![]),
+        opts: FindOptions::default(),
+    }
+    .published_only();
+    let (_, docs, _) = content.query_front_matter(&q.filter, &q.opts).await?;
+
+    let mut paths: Vec<String> = [
+        "/",
+        "/robots.txt",
+        "/sitemap.xml",
+        "/feed.xml",
+        "/feed.atom",
+        "/feed.json",
+    ]
+    .map(str::to_owned)
+    .to_vec();
+    let rules = content.permalinks();
+    let mut term_pages = BTreeSet::new();
+    for doc in &docs {
+        paths.extend(rules.url_for(doc));
+        for taxonomy in Taxonomy::ALL {
+            let pointer = format!("/tax/{}", taxonomy.segment());
+            for term in doc
+                .pointer(&pointer)
+                .and_then(Json::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(Json::as_str)
+            {
+                term_pages.insert(taxonomy.page_path(&segment(term), 1));
+            }
+        }
+    }
+    for page in term_pages {
+        paths.push(format!("{page}/feed.xml"));
+        paths.push(page);
+    }
+    for author in content.authors().iter() {
+        let slug = segment(&author.slug);
+        paths.push(format!("/author/{slug}"));
+        paths.push(format!("/author/{slug}/feed.xml"));
+    }
+    Ok(paths)
+}
+
+/// The site path `link`, found in the response for `from`, points at; `None`
+/// for other sites, fragments, `mailto:` and the like. Queries and fragments
+/// are dropped.
+fn site_path(link: &str, from: &str, origin: &str) -> Option<String> {
+    let link = link.trim();
+    let link = link.split(['#', '?']).next().unwrap_or_default();
+    let path = if let Some(rest) = link.strip_prefix(origin) {
+        if !(rest.is_empty() || rest.starts_with('/')) {
+            return None;
+        }
+        format!("/{}", rest.trim_start_matches('/'))
+    } else if link.starts_with("//") || link.contains(':') {
+        return None;
+    } else if link.starts_with('/') {
+        link.to_owned()
+    } else if link.is_empty() {
+        return None;
+    } else {
+        let dir = &from[..from.rfind('/').map_or(0, |i| i + 1)];
+        format!("{dir}{link}")
+    };
+
+    // Resolve `.` and `..` the way the server will.
+    let mut segments: Vec<&str> = Vec::new();
+    for seg in path.split('/').skip(1) {
+        match seg {
+            "." => {}
+            ".." => {
+                segments.pop();
+            }
+            s => segments.push(s),
+        }
+    }
+    Some(format!("/{}", segments.join("/")))
+}
+
+/// The file under the output directory for `path`; `None` for a path that
+/// would leave it.
+fn file_for(path: &str) -> Option<PathBuf> {
+    let decoded = percent_decode_str(path).decode_utf8().ok()?;
+    let relative = PathBuf::from(decoded.trim_start_matches('/'));
+    if relative
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_)))
+    {
+        return None;
+    }
+    let has_extension = relative
+        .file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|n| n.contains('.'));
+    Some(if decoded.ends_with('/') || !has_extension {
+        relative.join("index.html")
+    } else {
+        relative
+    })
+}
+
+/// The links in a response of `content_type` worth following.
+fn links(content_type: &str, body: &[u8]) -> Vec<String> {
+    let followed = ["html", "xml", "css", "text/plain"];
+    if !followed.iter().any(|t| content_type.contains(t)) {
+        return Vec::new();
+    }
+    let text = String::from_utf8_lossy(body);
+    LINK.captures_iter(&text)
+        .filter_map(|c| c.get(1).or(c.get(2)).or(c.get(3)))
+        .map(|m| m.as_str().replace("&amp;", "&"))
+        .collect()
+}
+
+/// Render the site into `out`, as served at `base_url`.
+pub async fn export(
+    site: Arc<Site>,
+    handles: RuntimeHandles,
+    bindings: Vec<ThemeBinding>,
+    base_url: &str,
+    out: &Path,
+) -> Result<ExportSummary, EdgeError> {
+    let origin = base_url.trim_end_matches('/');
+    let (scheme, host) = origin
+        .split_once("://")
+        .ok_or_else(|| EdgeError::Config(format!("export: base URL {origin:?} has no scheme")))?;
+    fs::create_dir_all(out)?;
+    let content = site.content.clone();
+    let app = service::init_service(
+        App::new()
+            .wrap(NormalizeRequest)
+            .service(build_app_router(site, handles, bindings)),
+    )
+    .await;
+
+    let mut summary = ExportSummary::default();
+    let mut redirects = String::new();
+    let mut seen = HashSet::new();
+    let mut queue: VecDeque<String> = VecDeque::new();
+    for path in seeds(&content).await? {
+        if seen.insert(path.clone()) {
+            queue.push_back(path);
+        }
+    }
+
+    while let Some(path) = queue.pop_front() {
+        let Some(file) = file_for(&path) else {
+            warn!("Export: {} is outside the output directory, skipped", path);
+            continue;
+        };
+        let req = service::TestRequest::get()
+            .uri(&path)
+            .insert_header((HOST, host))
+            .insert_header(("x-forwarded-proto", scheme))
+            .to_request();
+        let resp = service::call_service(&app, req).await;
+        let status = resp.status();
+        let content_type = resp
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_owned();
+        let location = resp
+            .headers()
+            .get(LOCATION)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_owned);
+
+        let found = if status.is_success() {
+            let body = service::read_body(resp).await;
+            let target = out.join(&file);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&target, &body)?;
+            summary.files += 1;
+            links(&content_type, &body)
+        } else if let (true, Some(to)) = (status.is_redirection(), location) {
+            let code = status.as_u16();
+            redirects.push_str(&format!("{path} {to} {code}\n"));
+            summary.redirects += 1;
+            vec![to]
+        } else {
+            warn!("Export: {} answered {}, skipped", path, status);
+            summary.failed.push((path, status.as_u16()));
+            continue;
+        };
+
+        for link in found {
+            if let Some(next) = site_path(&link, &path, origin) {
+                if seen.insert(next.clone()) {
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+
+    if !redirects.is_empty() {
+        fs::write(out.join(REDIRECTS_FILE), redirects)?;
+    }
+    info!(
+        "Exported {} files and {} redirects to {:?}",
+        summary.files, summary.redirects, out
+    );
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn links_map_to_site_paths_and_files() {
+        let origin = "https://example.com";
+        let page = "/blog/post/";
+        assert_eq!(site_path("/about", page, origin), Some("/about".into()));
+        assert_eq!(
+            site_path("img/a.png?v=2#x", page, origin),
+            Some("/blog/post/img/a.png".into())
+        );
+        assert_eq!(
+            site_path("../../feed.xml", page, origin),
+            Some("/feed.xml".into())
+        );
+        assert_eq!(
+            site_path("https://example.com/sitemap-2.xml", page, origin),
+            Some("/sitemap-2.xml".into())
+        );
+        assert_eq!(site_path("https://example.com.evil/x", page, origin), None);
+        assert_eq!(site_path("https://other.org/", page, origin), None);
+        assert_eq!(site_path("mailto:a@example.com", page, origin), None);
+        assert_eq!(site_path("#top", page, origin), None);
+
+        assert_eq!(file_for("/"), Some(PathBuf::from("index.html")));
+        assert_eq!(file_for("/about/"), Some(PathBuf::from("about/index.html")));
+        assert_eq!(
+            file_for("/tags/c%2B%2B"),
+            Some(PathBuf::from("tags/c++/index.html"))
+        );
+        assert_eq!(file_for("/feed.xml"), Some(PathBuf::from("feed.xml")));
+        assert_eq!(file_for("/a/%2E%2E/%2E%2E/etc"), None);
+
+        let html =
+            br#"<a href="/x">x</a><img src='/i.png'><style>b{background:url("/bg.png")}</style>"#;
+        assert_eq!(
+            links("text/html; charset=utf-8", html),
+            ["/x", "/i.png", "/bg.png"]
+        );
+        assert_eq!(
+            links("application/xml", b"<loc> https://example.com/a </loc>"),
+            ["https://example.com/a"]
+        );
+        assert!(links("image/png", html).is_empty());
+    }
+}
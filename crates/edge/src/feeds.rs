@@ -0,0 +1,209 @@
+// crates/edge/src/feeds.rs
+
+// Routes for serve::feeds, mounted ahead of every theme:
+//
+//   GET /feed.{ext}
+//   GET /tags/{tag}/feed.{ext}
+//   GET /categories/{category}/feed.{ext}
+//   GET /series/{series}/feed.{ext}
+//   GET /sections/{section}/feed.{ext}
+//   GET /author/{author}/feed.{ext}
+//
+// `ext` picks the format (xml → RSS, atom → Atom, json → JSON Feed) and
+// `?lang=` narrows the feed to one language. Configured by an optional
+// `[feeds]` table in settings.toml:
+//
+//   [feeds]
+//   title = "My Site"
+//   items = 30
+//   full_content = true
+
+use crate::fs::index::ContentMgr;
+use crate::sitemap::base_url;
+use actix_web::{dev::HttpServiceFactory, web, HttpRequest, HttpResponse};
+use domain::setting::FeedSettings;
+use serve::feeds::{collect_items, render, FeedFormat, FeedMeta, FeedScope};
+use tracing::error;
+
+/// The feed resources over the documents `mgr` indexes, for the root of
+/// the router.
+pub fn services(settings: FeedSettings, mgr: ContentMgr) -> impl HttpServiceFactory {
+    let settings = web::Data::new(settings);
+    let mgr = web::Data::new(mgr);
+    let resource = |path: &str| {
+        web::resource(path)
+            .app_data(settings.clone())
+            .app_data(mgr.clone())
+            .route(web::get().to(feed_handler))
+    };
+
+    (
+        resource("/feed.{ext}"),
+        resource("/tags/{tag}/feed.{ext}"),
+        resource("/categories/{category}/feed.{ext}"),
+        resource("/series/{series}/feed.{ext}"),
+        resource("/sections/{section}/feed.{ext}"),
+        resource("/author/{author}/feed.{ext}"),
+    )
+}
+
+fn scope_of(req: &HttpRequest) -> FeedScope {
+    let info = req.match_info();
+    if let Some(tag) = info.get("tag") {
+        FeedScope::Tag(tag.to_owned())
+    } else if let Some(category) = info.get("category") {
+        FeedScope::Category(category.to_owned())
+    } else if let Some(series) = info.get("series") {
+        FeedScope::Series(series.to_owned())
+    } else if let Some(section) = info.get("section") {
+        FeedScope::Section(section.to_owned())
+    } else if let Some(author) = info.get("author") {
+        FeedScope::Author(author.to_owned())
+    } else {
+        FeedScope::All
+    }
+}
+
+#[tracing::instrument(skip_all, fields(path = %req.path()))]
+async fn feed_handler(
+    settings: web::Data<FeedSettings>,
+    mgr: web::Data<ContentMgr>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let Some(format) = req
+        .match_info()
+        .get("ext")
+        .and_then(FeedFormat::from_extension)
+    else {
+        return HttpResponse::NotFound().finish();
+    };
+
+    let settings = settings.get_ref();
+    let scope = scope_of(&req);
+    let lang = form_urlencoded::parse(req.query_string().as_bytes())
+        .find(|(k, _)| k == "lang")
+        .map(|(_, v)| v.into_owned())
+        .or_else(|| settings.lang.clone());
+
+    let mut items = match collect_items(mgr.get_ref(), &scope, lang.as_deref(), settings).await {
+        Ok(items) => items,
+        Err(e) => {
+            error!("Feed: reading the front-matter index failed: {}", e);
+            return HttpResponse::ServiceUnavailable().finish();
+        }
+    };
+
+    mgr.content().authors().name_items(&mut items);
+
+    let meta = FeedMeta::new(
+        settings,
+        &scope,
+        &base_url(&req),
+        req.path(),
+        lang.as_deref(),
+    );
+    HttpResponse::Ok()
+        .content_type(format.content_type())
+        .body(render(format, &meta, &items))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::index::SiteContent;
+    use crate::init::Answers;
+    use actix_web::{test, App};
+    use domain::doc::BodyKind;
+    use domain::setting::{Settings, SitemapSettings};
+    use serde_json::{json, Value as Json};
+    use std::sync::Arc;
+
+    #[actix_web::test]
+    async fn feeds_list_published_posts_in_their_scope_at_their_permalinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_path_buf();
+        let settings: Settings = toml::from_str(
+            &(Answers::default().settings_toml().unwrap()
+                + "\n[permalinks]\npost = \"/:year/:slug/\"\n"),
+        )
+        .unwrap();
+        let content = SiteContent::open(&root, &settings, root.join("index"), true)
+            .await
+            .unwrap();
+        let post = |slug: &str, date: &str, status: &str, tags: &[&str]| {
+            json!({ "type": "post", "slug": slug,
+                    "content": { "title": slug.to_uppercase() },
+                    "publish": { "status": status, "date": date },
+                    "tax": { "tags": tags } })
+        };
+        for (served, fm) in [
+            (
+                "posts/old.html",
+                post("old", "2023-01-01T00:00:00Z", "publish", &["rust"]),
+            ),
+            (
+                "posts/new.html",
+                post("new", "2024-06-01T00:00:00Z", "publish", &[]),
+            ),
+            (
+                "posts/wip.html",
+                post("wip", "2024-07-01T00:00:00Z", "draft", &["rust"]),
+            ),
+        ] {
+            content
+                .index_front_matter(root.clone(), &root.join(served), &fm)
+                .await
+                .unwrap();
+            let body = format!("<p>About {}.</p>", fm["slug"].as_str().unwrap());
+            content
+                .index_body(&root, &root.join(served), &body, BodyKind::Html)
+                .await
+                .unwrap();
+        }
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(SitemapSettings {
+                    base_url: Some("https://example.com".into()),
+                    ..SitemapSettings::default()
+                }))
+                .service(services(
+                    FeedSettings {
+                        title: Some("Notes".into()),
+                        ..FeedSettings::default()
+                    },
+                    ContentMgr::new(root, Arc::new(content)),
+                )),
+        )
+        .await;
+        let get = |uri: &str| test::TestRequest::get().uri(uri).to_request();
+        let urls = |feed: &Json| -> Vec<String> {
+            feed["items"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|i| i["url"].as_str().unwrap().to_owned())
+                .collect()
+        };
+
+        let all: Json = test::call_and_read_body_json(&app, get("/feed.json")).await;
+        assert_eq!(all["title"], "Notes");
+        assert_eq!(all["items"][0]["summary"], "About new.");
+        assert_eq!(
+            urls(&all),
+            [
+                "https://example.com/2024/new/",
+                "https://example.com/2023/old/"
+            ]
+        );
+        let tagged: Json = test::call_and_read_body_json(&app, get("/tags/rust/feed.json")).await;
+        assert_eq!(urls(&tagged), ["https://example.com/2023/old/"]);
+
+        let rss = test::call_service(&app, get("/feed.xml")).await;
+        assert!(rss.status().is_success());
+        let rss = String::from_utf8(test::read_body(rss).await.to_vec()).unwrap();
+        assert!(rss.contains("<link>https://example.com/2024/new/</link>"));
+
+        let unknown = test::call_service(&app, get("/feed.txt")).await;
+        assert_eq!(unknown.status(), 404);
+    }
+}
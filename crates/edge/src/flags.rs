@@ -0,0 +1,163 @@
+// crates/edge/src/flags.rs
+
+// Feature flags (serve::flags) for theme requests. Site flags come from
+// `[flags.*]` in settings.toml, plugin flags from `[flags.*]` in each
+// plugin.toml and are replaced whenever plugins are rediscovered.
+//
+// Each theme request is decided once, before the response cache is
+// consulted: the flags that are on become part of the cache key, land on
+// the `RequestContext` for plugins and themes, and back the template
+// `flag` helper. Every decision is logged at debug level with its reason.
+//
+// The visitor a percentage is bucketed by is the session cookie, else the
+// client address. The signed-in user is only looked up when some flag
+// targets roles.
+
+use crate::auth::{self, SESSION_COOKIE};
+use crate::fs::ext::DiscoveredPlugin;
+use crate::quota;
+use actix_web::{web, HttpRequest};
+use domain::setting::FlagSettings;
+use serve::flags::{flag_map, FlagSet, FlagSubject};
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+use tracing::debug;
+
+/// A site's flags and those of its plugins, app data of the scope around
+/// the theme and plugin routes.
+#[derive(Default)]
+pub struct Flags {
+    site: BTreeMap<String, FlagSettings>,
+    current: RwLock<Arc<FlagSet>>,
+}
+
+impl Flags {
+    /// The site's `flags`, before any plugin's.
+    pub fn new(site: BTreeMap<String, FlagSettings>) -> Self {
+        let flags = Self {
+            site,
+            current: Default::default(),
+        };
+        flags.set_plugin_flags(&[]);
+        flags
+    }
+
+    /// Replace the plugin flags with those of `plugins`.
+    pub fn set_plugin_flags(&self, plugins: &[DiscoveredPlugin]) {
+        let mut set = FlagSet::new().with_site(&self.site);
+        for plugin in plugins {
+            set = set.with_plugin(&plugin.spec.id, &plugin.flags);
+        }
+        if let Ok(mut flags) = self.current.write() {
+            *flags = Arc::new(set);
+        }
+    }
+
+    fn current(&self) -> Arc<FlagSet> {
+        self.current
+            .read()
+            .map(|f| f.clone())
+            .unwrap_or_else(|e| e.into_inner().clone())
+    }
+}
+
+/// Decide every flag for `req`: flag name → on.
+pub async fn evaluate(req: &HttpRequest) -> BTreeMap<String, bool> {
+    let Some(flags) = req.app_data::<web::Data<Flags>>().map(|f| f.current()) else {
+        return BTreeMap::new();
+    };
+    if flags.is_empty() {
+        return BTreeMap::new();
+    }
+
+    let role = match flags.uses_roles() {
+        true => match auth::current_user(req).await {
+            Ok(user) => user.map(|u| u.role.to_string()),
+            Err(e) => {
+                debug!("No role for feature flags: {}", e);
+                None
+            }
+        },
+        false => None,
+    };
+    let cookies = req
+        .cookies()
+        .map(|all| {
+            all.iter()
+                .map(|c| (c.name().to_owned(), c.value().to_owned()))
+                .collect()
+        })
+        .unwrap_or_default();
+    let visitor = req
+        .cookie(SESSION_COOKIE)
+        .map(|c| c.value().to_owned())
+        .or_else(|| quota::client_addr(req));
+
+    let decisions = flags.evaluate(&FlagSubject {
+        visitor,
+        cookies,
+        role,
+    });
+    for d in &decisions {
+        debug!(
+            "Flag {} is {} for {}: {} ({:?})",
+            d.flag,
+            if d.on { "on" } else { "off" },
+            req.path(),
+            d.reason,
+            d.source
+        );
+    }
+    flag_map(&decisions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::cookie::Cookie;
+    use actix_web::test::TestRequest;
+
+    #[actix_web::test]
+    async fn requests_are_decided_from_site_and_plugin_flags() {
+        let flags = web::Data::new(Flags::new(BTreeMap::from([(
+            "beta".to_owned(),
+            toml::from_str("cookies = [\"beta\"]").unwrap(),
+        )])));
+        let plugin = DiscoveredPlugin {
+            dir: "plugins/seo".into(),
+            spec: adapt::runtime::plugin::PluginSpec {
+                id: "seo".into(),
+                name: "SEO".into(),
+                source: String::new(),
+                provides: Vec::new(),
+                requires: Vec::new(),
+                permissions: Default::default(),
+                fetch: Default::default(),
+                settings: Default::default(),
+                config: Default::default(),
+                routes: Vec::new(),
+            },
+            flags: BTreeMap::from([
+                ("beta".to_owned(), toml::from_str("").unwrap()),
+                ("og-images".to_owned(), toml::from_str("").unwrap()),
+            ]),
+        };
+        flags.set_plugin_flags(&[plugin]);
+
+        let plain = TestRequest::get()
+            .uri("/")
+            .app_data(flags.clone())
+            .to_http_request();
+        assert_eq!(
+            evaluate(&plain).await,
+            BTreeMap::from([("beta".to_owned(), false), ("og-images".to_owned(), true)])
+        );
+
+        let tester = TestRequest::get()
+            .uri("/")
+            .cookie(Cookie::new("beta", "yes"))
+            .app_data(flags)
+            .to_http_request();
+        assert_eq!(evaluate(&tester).await.get("beta"), Some(&true));
+    }
+}
@@ -0,0 +1,208 @@
+// crates/edge/src/form_pages.rs
+
+// Server-rendered admin forms: a `serve::render::form::FormSpec` answered
+// as a whole page, or as just the `<form>` when HTMX asks (`HX-Request:
+// true`), so a failed submission swaps in the same markup with its errors.
+//
+// Forms post back urlencoded to the URL that rendered them. Pages for a
+// logged-in user carry the session's CSRF token as `_csrf` (crate::csrf),
+// and a successful submission redirects: `303 See Other`, or
+// `HX-Redirect` for HTMX, which follows a redirect inside the swap. When
+// there is something to read rather than another page to go to, such as
+// recovery codes, the form is answered with a `notice` instead.
+
+use crate::auth::{AuthError, MinRole, RequireRole};
+use crate::csrf::{self, CSRF_FIELD};
+use crate::router::to_http_headers;
+use actix_web::{
+    http::{
+        header::{CONTENT_TYPE, LOCATION},
+        Method, StatusCode,
+    },
+    HttpRequest, HttpResponse, ResponseError,
+};
+use html_escape::encode_text as text;
+use serve::render::form::{self, FieldKind, FieldSpec, FormErrors, FormSpec, FormValues};
+
+/// Whether `req` wants only the form fragment.
+pub fn wants_fragment(req: &HttpRequest) -> bool {
+    form::wants_fragment(&to_http_headers(req.headers()))
+}
+
+/// Whether `req` carries a urlencoded form body.
+pub fn is_form(req: &HttpRequest) -> bool {
+    req.headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/x-www-form-urlencoded"))
+}
+
+/// The fields of a urlencoded body; a repeated name keeps its last value.
+pub fn values(body: &[u8]) -> FormValues {
+    form_urlencoded::parse(body).into_owned().collect()
+}
+
+/// `spec` with `values` and `errors`, as a page or a fragment. A logged-in
+/// request gets its CSRF token as a hidden field.
+pub fn respond(
+    req: &HttpRequest,
+    status: StatusCode,
+    spec: &FormSpec,
+    values: &FormValues,
+    errors: &FormErrors,
+) -> HttpResponse {
+    let (spec, values) = with_csrf(req, spec, values);
+    let html = if wants_fragment(req) {
+        spec.render_fragment(&values, errors)
+    } else {
+        spec.render_page(&values, errors)
+    };
+    HttpResponse::build(status)
+        .content_type("text/html; charset=utf-8")
+        .body(html)
+}
+
+/// Just the `<form>` for `spec`, to place in a page of several.
+pub fn fragment(req: &HttpRequest, spec: &FormSpec, values: &FormValues) -> String {
+    let (spec, values) = with_csrf(req, spec, values);
+    spec.render_fragment(&values, &FormErrors::default())
+}
+
+fn with_csrf(req: &HttpRequest, spec: &FormSpec, values: &FormValues) -> (FormSpec, FormValues) {
+    let mut spec = spec.clone();
+    let mut values = values.clone();
+    if let Some(token) = csrf::token_for(req) {
+        spec.fields
+            .push(FieldSpec::new(CSRF_FIELD, "", FieldKind::Hidden));
+        values.insert(CSRF_FIELD.to_string(), token);
+    }
+    (spec, values)
+}
+
+/// Send the browser on to `to` after a successful submission.
+pub fn redirect(req: &HttpRequest, to: &str) -> HttpResponse {
+    if wants_fragment(req) {
+        HttpResponse::Ok()
+            .insert_header(("HX-Redirect", to))
+            .finish()
+    } else {
+        HttpResponse::SeeOther()
+            .insert_header((LOCATION, to))
+            .finish()
+    }
+}
+
+/// `title` and `lines` of text as a page, or as a `<section>` to swap in
+/// for the form that was posted.
+pub fn notice(req: &HttpRequest, title: &str, lines: &[String]) -> HttpResponse {
+    let mut body = String::new();
+    for line in lines {
+        body.push_str("<p>");
+        body.push_str(&text(line));
+        body.push_str("</p>");
+    }
+    let html = if wants_fragment(req) {
+        format!(
+            "<section class=\"notice\" role=\"status\"><h2>{}</h2>{body}</section>",
+            text(title)
+        )
+    } else {
+        format!(
+            concat!(
+                "<!DOCTYPE html><html lang=\"en\"><head><meta charset=\"utf-8\">",
+                "<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">",
+                "<title>{title}</title></head><body><main id=\"main\">",
+                "<h1>{title}</h1>{body}</main></body></html>"
+            ),
+            title = text(title),
+            body = body,
+        )
+    };
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(html)
+}
+
+/// The signed-in user behind `auth`; someone not logged in is sent to the
+/// login page, and back to this page afterwards when it was a GET.
+pub fn signed_in<R: MinRole>(
+    req: &HttpRequest,
+    auth: Result<RequireRole<R>, AuthError>,
+) -> Result<RequireRole<R>, HttpResponse> {
+    match auth {
+        Ok(auth) => Ok(auth),
+        Err(AuthError::Unauthenticated) => Err(login_redirect(req)),
+        Err(e) => Err(e.error_response()),
+    }
+}
+
+/// Send someone who is not logged in to the login page, and back to this
+/// page afterwards when it was a GET.
+pub fn login_redirect(req: &HttpRequest) -> HttpResponse {
+    let login = match req.uri().path_and_query() {
+        Some(here) if req.method() == Method::GET => format!(
+            "/admin/login?{}",
+            form_urlencoded::Serializer::new(String::new())
+                .append_pair("next", here.as_str())
+                .finish()
+        ),
+        _ => "/admin/login".to_string(),
+    };
+    redirect(req, &login)
+}
+
+/// `next` when it is a path on this site, so a crafted link cannot send a
+/// user elsewhere after logging in.
+pub fn local_path(next: Option<&str>) -> Option<&str> {
+    next.filter(|p| p.starts_with('/') && !p.starts_with("//") && !p.contains('\\'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+    use serve::render::form::HX_REQUEST_HEADER;
+
+    #[test]
+    fn only_paths_on_this_site_are_followed() {
+        assert_eq!(
+            local_path(Some("/admin/extensions")),
+            Some("/admin/extensions")
+        );
+        for hostile in [
+            "//evil.example",
+            "https://evil.example",
+            "/\\evil.example",
+            "",
+        ] {
+            assert_eq!(local_path(Some(hostile)), None, "{hostile}");
+        }
+        assert_eq!(local_path(None), None);
+    }
+
+    #[test]
+    fn htmx_gets_the_fragment_and_a_redirect_header() {
+        let spec = FormSpec::new("f", "Title", "/x")
+            .with_field(FieldSpec::new("q", "Q", FieldKind::Text).required());
+        let values = FormValues::new();
+        let errors = spec.validate_required(&values);
+
+        let page = TestRequest::default().to_http_request();
+        let res = respond(
+            &page,
+            StatusCode::UNPROCESSABLE_ENTITY,
+            &spec,
+            &values,
+            &errors,
+        );
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(redirect(&page, "/done").status(), StatusCode::SEE_OTHER);
+
+        let htmx = TestRequest::default()
+            .insert_header((HX_REQUEST_HEADER, "true"))
+            .to_http_request();
+        assert!(wants_fragment(&htmx) && !wants_fragment(&page));
+        let res = redirect(&htmx, "/done");
+        assert_eq!(res.headers().get("HX-Redirect").unwrap(), "/done");
+    }
+}
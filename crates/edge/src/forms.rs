@@ -0,0 +1,560 @@
+// crates/edge/src/forms.rs
+
+// Site forms. Each `[forms.<name>]` table in the settings declares one,
+// posted to `/forms/<name>` as a plain HTML form or JSON:
+//
+//   [forms.contact]
+//   email_to = ["owner@example.com"]
+//   redirect = "/thanks/"
+//   per_minute = 5                 # per client address
+//   [[forms.contact.fields]]
+//   name = "email"
+//   kind = "email"                 # text, textarea, email, url, number,
+//   required = true                # checkbox or select (with `options`)
+//
+//   POST /forms/contact   → 201 { id }; a form post is sent on to `redirect`
+//                           (else back to the page on this site it came
+//                           from, or `/`) with 303
+//                         → 422 { errors: { field: message } }
+//
+// Submissions are checked against the declared fields (serve::forms), then
+// passed through the `form.submission` plugin filter (adapt hooks), whose
+// listeners may change them or turn them away with a `rejected` reason,
+// each with its own plugin's permissions. What is left is stored
+// (db::forms) and, with `email_to`, queued in the outbox as one email
+// (sent once `[mail]` is set, see crate::mail).
+// A filled-in honeypot (serve::forms::HONEYPOT_FIELD) gets the usual answer
+// and is dropped; a client over `per_minute` gets 429 with `Retry-After`.
+//
+// Themes lay forms out with the `form` template helper. Editors read and
+// clear submissions in the admin:
+//
+//   GET    /admin/api/forms                            { items: [{ name, fields, … }] }
+//   GET    /admin/api/forms/{name}/submissions         { items }, newest first
+//   DELETE /admin/api/forms/{name}/submissions/{id}    → 204
+
+use crate::audit::{self, AuditNote};
+use crate::auth::{Editor, RequireRole, Viewer};
+use crate::cli::OUTBOX_DIR;
+use crate::db::forms::{is_form_name, FormStore, FormStoreError, FormSubmission, FORMS_DB_DIR};
+use crate::db::outbox::{Delivery, Outbox, OutboxError};
+use crate::db::store::{blocking, failed};
+use crate::form_pages;
+use crate::proxy::EdgeError;
+use crate::quota::{self, ClientLimits};
+use actix_web::{
+    dev::HttpServiceFactory,
+    http::header::{CONTENT_TYPE, LOCATION, REFERER, RETRY_AFTER},
+    web, HttpRequest, HttpResponse,
+};
+use adapt::js::JsValue;
+use adapt::runtime::hooks::FORM_SUBMISSION;
+use adapt::runtime::plugin_actor::PluginRuntimeClient;
+use domain::setting::FormDefinition;
+use serde_json::{json, Map as JsonMap, Value as Json};
+use serve::forms::{describe, validate, HONEYPOT_FIELD};
+use serve::render::http::RequestContext;
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use tracing::{debug, info, warn};
+use url::{Position, Url};
+use uuid::Uuid;
+
+struct Form {
+    definition: FormDefinition,
+    limits: ClientLimits,
+}
+
+/// A site's forms, the submissions stored for them, and the outbox their
+/// emails go through.
+pub struct Forms {
+    forms: BTreeMap<String, Form>,
+    store: FormStore,
+    /// What the `form` helper shows, by name.
+    described: Arc<BTreeMap<String, Json>>,
+    /// Only opened when some form emails its submissions.
+    outbox: Option<Outbox>,
+}
+
+/// The site's form submissions.
+pub fn store(root_dir: &Path) -> FormStore {
+    FormStore::new(root_dir.join(FORMS_DB_DIR))
+}
+
+impl Forms {
+    /// Accept submissions to `forms`, storing them and emailing through the
+    /// outbox under `root_dir`.
+    pub fn open(
+        root_dir: &Path,
+        forms: BTreeMap<String, FormDefinition>,
+    ) -> Result<Self, EdgeError> {
+        if let Some(name) = forms.keys().find(|name| !is_form_name(name)) {
+            return Err(EdgeError::Config(format!(
+                "form {name:?}: names may only use letters, digits, '-' and '_'"
+            )));
+        }
+        let outbox = if forms.values().any(|f| !f.email_to.is_empty()) {
+            Some(Outbox::open(root_dir.join(OUTBOX_DIR))?)
+        } else {
+            None
+        };
+        let described = forms
+            .iter()
+            .map(|(name, definition)| (name.clone(), describe(name, definition)))
+            .collect();
+        let forms = forms
+            .into_iter()
+            .map(|(name, definition)| {
+                let limits = ClientLimits::per_minute(definition.per_minute);
+                (name, Form { definition, limits })
+            })
+            .collect();
+        Ok(Self {
+            forms,
+            store: store(root_dir),
+            described: Arc::new(described),
+            outbox,
+        })
+    }
+
+    /// The forms as the `form` template helper shows them.
+    pub fn described(&self) -> Arc<BTreeMap<String, Json>> {
+        self.described.clone()
+    }
+}
+
+/// The one email a submission to `name` is sent as.
+fn email(name: &str, definition: &FormDefinition, submission: &FormSubmission) -> Delivery {
+    let mut body = String::new();
+    for (field, value) in &submission.fields {
+        let value = match value {
+            Json::String(s) => s.clone(),
+            other => other.to_string(),
+        };
+        body.push_str(&format!("{field}: {value}\n"));
+    }
+    body.push_str(&format!(
+        "\nSubmitted {} (id {})\n",
+        submission.created_at.to_rfc3339(),
+        submission.id
+    ));
+    Delivery::Email {
+        to: definition.email_to.clone(),
+        subject: definition
+            .subject
+            .clone()
+            .unwrap_or_else(|| format!("New {name} submission")),
+        body,
+    }
+}
+
+/// What the `form.submission` filter made of `fields`: the fields it
+/// returned (the originals when it returned none), or its `rejected` reason.
+fn filtered(fields: JsonMap<String, Json>, out: Json) -> Result<JsonMap<String, Json>, String> {
+    if let Some(reason) = out.get("rejected").filter(|r| !r.is_null()) {
+        return Err(match reason {
+            Json::String(s) => s.clone(),
+            _ => "rejected".to_owned(),
+        });
+    }
+    match out.get("fields") {
+        Some(Json::Object(changed)) => Ok(changed.clone()),
+        _ => Ok(fields),
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// HTTP
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// The public `/forms/{name}` and the `/admin/api/forms` resources, for the
+/// root of the router.
+pub fn services(forms: web::Data<Forms>) -> impl HttpServiceFactory {
+    (
+        web::resource("/forms/{name}")
+            .app_data(forms.clone())
+            .route(web::post().to(submit_handler)),
+        web::resource("/admin/api/forms")
+            .app_data(forms.clone())
+            .route(web::get().to(list_forms_handler)),
+        web::resource("/admin/api/forms/{name}/submissions")
+            .app_data(forms.clone())
+            .route(web::get().to(list_handler)),
+        web::resource("/admin/api/forms/{name}/submissions/{id}")
+            .app_data(forms)
+            .route(web::delete().to(delete_handler)),
+    )
+}
+
+fn no_form(name: &str) -> HttpResponse {
+    HttpResponse::NotFound().json(json!({ "error": format!("no form {name}") }))
+}
+
+/// The submitted fields, from a JSON object or a form body; and whether it
+/// was a form.
+fn parse_fields(req: &HttpRequest, body: &[u8]) -> Result<(JsonMap<String, Json>, bool), String> {
+    let is_json = req
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+    if !is_json {
+        let fields = form_urlencoded::parse(body)
+            .map(|(k, v)| (k.into_owned(), Json::String(v.into_owned())))
+            .collect();
+        return Ok((fields, true));
+    }
+    match serde_json::from_slice(body) {
+        Ok(Json::Object(fields)) => Ok((fields, false)),
+        Ok(_) => Err("expected a JSON object of fields".to_owned()),
+        Err(e) => Err(format!("bad form JSON: {e}")),
+    }
+}
+
+/// What a submission gets back, stored or not.
+fn accepted(
+    req: &HttpRequest,
+    definition: &FormDefinition,
+    id: &str,
+    from_form: bool,
+) -> HttpResponse {
+    if !from_form {
+        return HttpResponse::Created().json(json!({ "id": id }));
+    }
+    let to = definition
+        .redirect
+        .clone()
+        .or_else(|| came_from(req))
+        .unwrap_or_else(|| "/".to_owned());
+    HttpResponse::SeeOther()
+        .insert_header((LOCATION, to))
+        .finish()
+}
+
+/// The page on this site `req`'s `Referer` names, as a path; a `Referer`
+/// anyone can send must not turn the form into an open redirect.
+fn came_from(req: &HttpRequest) -> Option<String> {
+    let referer = req.headers().get(REFERER)?.to_str().ok()?;
+    let path = match Url::parse(referer) {
+        Ok(url)
+            if url[Position::BeforeHost..Position::AfterPort] == *req.connection_info().host() =>
+        {
+            url[Position::BeforePath..Position::AfterQuery].to_owned()
+        }
+        Ok(_) => return None,
+        Err(_) => referer.to_owned(),
+    };
+    form_pages::local_path(Some(&path)).map(str::to_owned)
+}
+
+#[tracing::instrument(skip_all, fields(form = %name))]
+async fn submit_handler(
+    forms: web::Data<Forms>,
+    plugins: Option<web::Data<PluginRuntimeClient>>,
+    req: HttpRequest,
+    name: web::Path<String>,
+    body: web::Bytes,
+) -> HttpResponse {
+    let name = name.into_inner();
+    let Some(form) = forms.forms.get(&name) else {
+        return no_form(&name);
+    };
+    let client = quota::client_addr(&req).unwrap_or_else(|| "unknown".to_owned());
+    if let Err(wait) = form.limits.take(&client) {
+        return HttpResponse::TooManyRequests()
+            .insert_header((RETRY_AFTER, wait))
+            .json(json!({ "error": "too many submissions; try again later" }));
+    }
+
+    let (input, from_form) = match parse_fields(&req, &body) {
+        Ok(parsed) => parsed,
+        Err(e) => return HttpResponse::BadRequest().json(json!({ "error": e })),
+    };
+    let honeypot = input.get(HONEYPOT_FIELD).and_then(Json::as_str);
+    if honeypot.is_some_and(|v| !v.is_empty()) {
+        debug!(
+            "Dropped a {} submission from {} that filled the honeypot",
+            name, client
+        );
+        let id = Uuid::new_v4().to_string();
+        return accepted(&req, &form.definition, &id, from_form);
+    }
+    let mut fields = match validate(&form.definition, &input) {
+        Ok(fields) => fields,
+        Err(errors) => {
+            return HttpResponse::UnprocessableEntity().json(json!({ "errors": errors }))
+        }
+    };
+
+    if let Some(plugins) = plugins {
+        let ctx = RequestContext::builder()
+            .path(req.path())
+            .method("POST")
+            .build();
+        let value = JsValue::from_json(&json!({ "form": name, "fields": fields }));
+        match plugins.filter(FORM_SUBMISSION, value, ctx).await {
+            Ok(out) => match filtered(fields, out.to_json()) {
+                Ok(changed) => fields = changed,
+                Err(reason) => {
+                    info!("A plugin rejected a {} submission: {}", name, reason);
+                    return HttpResponse::UnprocessableEntity().json(json!({ "error": reason }));
+                }
+            },
+            // A broken listener must not lose the submission.
+            Err(e) => warn!(
+                "The {} filter failed; storing the submission as sent: {}",
+                FORM_SUBMISSION, e
+            ),
+        }
+    }
+
+    let submission = FormSubmission::new(name.clone(), fields);
+    let saved = {
+        let submission = submission.clone();
+        let delivery = (!form.definition.email_to.is_empty())
+            .then(|| email(&name, &form.definition, &submission));
+        let forms = forms.clone();
+        blocking(move || -> Result<_, FormStoreError> {
+            let staged = match (&forms.outbox, delivery) {
+                (Some(outbox), Some(delivery)) => Some(
+                    outbox
+                        .stage(delivery)
+                        .map_err(|e| FormStoreError::Io(io::Error::other(e)))?,
+                ),
+                _ => None,
+            };
+            forms.store.save(&submission)?;
+            if let Some(staged) = staged {
+                staged
+                    .commit()
+                    .map_err(|e: OutboxError| FormStoreError::Io(io::Error::other(e)))?;
+            }
+            Ok(())
+        })
+        .await
+    };
+    if let Err(e) = saved {
+        return failed(e);
+    }
+    info!("Stored {} submission {}", name, submission.id);
+    accepted(&req, &form.definition, &submission.id, from_form)
+}
+
+#[tracing::instrument(skip_all)]
+async fn list_forms_handler(_auth: RequireRole<Viewer>, forms: web::Data<Forms>) -> HttpResponse {
+    let items: Vec<Json> = forms
+        .forms
+        .iter()
+        .map(|(name, form)| {
+            let mut item = forms.described.get(name).cloned().unwrap_or(Json::Null);
+            item["email_to"] = json!(form.definition.email_to);
+            item
+        })
+        .collect();
+    HttpResponse::Ok().json(json!({ "items": items }))
+}
+
+#[tracing::instrument(skip_all)]
+async fn list_handler(
+    _auth: RequireRole<Viewer>,
+    forms: web::Data<Forms>,
+    name: web::Path<String>,
+) -> HttpResponse {
+    let name = name.into_inner();
+    if !forms.forms.contains_key(&name) {
+        return no_form(&name);
+    }
+    match blocking(move || forms.store.list(&name)).await {
+        Ok(items) => HttpResponse::Ok().json(json!({ "items": items })),
+        Err(e) => failed(e),
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn delete_handler(
+    auth: RequireRole<Editor>,
+    forms: web::Data<Forms>,
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+) -> HttpResponse {
+    let (name, id) = path.into_inner();
+    if !forms.forms.contains_key(&name) {
+        return no_form(&name);
+    }
+    let deleted = {
+        let (name, id) = (name.clone(), id.clone());
+        blocking(move || forms.store.delete(&name, &id)).await
+    };
+    match deleted {
+        Ok(true) => {}
+        Ok(false) => {
+            return HttpResponse::NotFound()
+                .json(json!({ "error": format!("no {name} submission {id}") }))
+        }
+        Err(e) => return failed(e),
+    }
+    audit::annotate(
+        &req,
+        AuditNote::new("form.submission.delete", format!("form:{name}/{id}")),
+    );
+    info!("{} deleted {} submission {}", auth.user.username, name, id);
+
+    HttpResponse::NoContent().finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{self, SESSION_COOKIE};
+    use crate::db::outbox::OutboxState;
+    use crate::db::users::{Role, ValidatedPassword};
+    use actix_web::cookie::Cookie;
+    use actix_web::http::StatusCode;
+    use actix_web::{middleware::from_fn, test as actix_test, App};
+
+    #[test]
+    fn plugins_change_or_reject_submissions() {
+        let fields = json!({ "email": "a@example.com" })
+            .as_object()
+            .unwrap()
+            .clone();
+        let out = filtered(
+            fields.clone(),
+            json!({ "fields": { "email": "b@example.com" } }),
+        );
+        assert_eq!(out.unwrap()["email"], "b@example.com");
+        assert_eq!(filtered(fields.clone(), Json::Null).unwrap(), fields);
+        assert_eq!(
+            filtered(fields, json!({ "rejected": "looks like spam" })).unwrap_err(),
+            "looks like spam"
+        );
+    }
+
+    #[actix_web::test]
+    async fn submissions_are_checked_stored_and_emailed() {
+        let site = tempfile::tempdir().unwrap();
+        let contact: FormDefinition = toml::from_str(
+            r#"
+            email_to = ["owner@example.com"]
+            per_minute = 3
+            [[fields]]
+            name = "email"
+            kind = "email"
+            required = true
+            [[fields]]
+            name = "message"
+            kind = "textarea"
+            "#,
+        )
+        .unwrap();
+        let forms = Forms::open(
+            site.path(),
+            BTreeMap::from([("contact".to_owned(), contact)]),
+        )
+        .unwrap();
+        assert_eq!(forms.described()["contact"]["action"], "/forms/contact");
+
+        let users = auth::store(site.path());
+        let password = ValidatedPassword::new("a long enough secret").unwrap();
+        let mut cookies = Vec::new();
+        for (name, role) in [("ed", Role::Editor), ("vi", Role::Viewer)] {
+            let user = users.create(name, role, &password).unwrap();
+            let token = users
+                .issue_session(&user, chrono::Duration::hours(1))
+                .unwrap();
+            cookies.push(Cookie::new(SESSION_COOKIE, token));
+        }
+        let (editor, viewer) = (cookies[0].clone(), cookies[1].clone());
+        let app = actix_test::init_service(
+            App::new().service(
+                web::scope("")
+                    .app_data(users)
+                    .app_data(audit::log(site.path()))
+                    .service(services(web::Data::new(forms)))
+                    .wrap(from_fn(audit::record)),
+            ),
+        )
+        .await;
+
+        let post = |body: &str| {
+            actix_test::TestRequest::post()
+                .uri("/forms/contact")
+                .peer_addr("10.0.0.2:4000".parse().unwrap())
+                .insert_header((CONTENT_TYPE, "application/x-www-form-urlencoded"))
+                .insert_header((REFERER, "/contact/"))
+                .set_payload(body.to_owned())
+                .to_request()
+        };
+        let res = actix_test::call_service(&app, post("email=nope")).await;
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let res = actix_test::call_service(&app, post("email=a%40example.com&_website=x")).await;
+        assert_eq!(res.status(), StatusCode::SEE_OTHER);
+        let res =
+            actix_test::call_service(&app, post("email=a%40example.com&message=Hi+there")).await;
+        assert_eq!(res.status(), StatusCode::SEE_OTHER);
+        assert_eq!(res.headers().get(LOCATION).unwrap(), "/contact/");
+        let res = actix_test::call_service(&app, post("email=a%40example.com")).await;
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        for (referer, back) in [
+            ("https://evil.example/phish", "/"),
+            ("http://localhost:8080/contact/?sent", "/contact/?sent"),
+        ] {
+            let req = actix_test::TestRequest::post()
+                .uri("/forms/contact")
+                .peer_addr("10.0.0.3:4000".parse().unwrap())
+                .insert_header((CONTENT_TYPE, "application/x-www-form-urlencoded"))
+                .insert_header((REFERER, referer))
+                .set_payload("email=a%40example.com&_website=x")
+                .to_request();
+            let res = actix_test::call_service(&app, req).await;
+            assert_eq!(res.headers().get(LOCATION).unwrap(), back);
+        }
+        let res = actix_test::call_service(
+            &app,
+            actix_test::TestRequest::post()
+                .uri("/forms/missing")
+                .set_json(json!({}))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        // Only the real submission was stored, and emailed once.
+        let req = actix_test::TestRequest::get()
+            .uri("/admin/api/forms/contact/submissions")
+            .cookie(viewer.clone())
+            .to_request();
+        let list: Json = actix_test::call_and_read_body_json(&app, req).await;
+        let items = list["items"].as_array().unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0]["fields"]["message"], "Hi there");
+        let outbox = Outbox::open(site.path().join(OUTBOX_DIR)).unwrap();
+        let queued = outbox.list(OutboxState::Pending).unwrap();
+        assert_eq!(queued.len(), 1);
+        match &queued[0].delivery {
+            Delivery::Email { to, body, .. } => {
+                assert_eq!(to, &["owner@example.com"]);
+                assert!(body.contains("message: Hi there"), "{body}");
+            }
+            other => panic!("unexpected delivery {other:?}"),
+        }
+
+        let delete = |cookie: &Cookie<'static>| {
+            actix_test::TestRequest::delete()
+                .uri(&format!(
+                    "/admin/api/forms/contact/submissions/{}",
+                    items[0]["id"].as_str().unwrap()
+                ))
+                .cookie(cookie.clone())
+                .to_request()
+        };
+        let res = actix_test::call_service(&app, delete(&viewer)).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        let res = actix_test::call_service(&app, delete(&editor)).await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        let res = actix_test::call_service(&app, delete(&editor)).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -1,9 +1,18 @@
 // crates/edge/src/fs/ext.rs
 
+use adapt::js::FetchPolicy;
 use adapt::runtime::error::RuntimeError;
+use adapt::runtime::permissions::Capability;
 use adapt::runtime::plugin::PluginSpec;
+use adapt::runtime::services::ServiceContract;
+use adapt::runtime::settings::SettingsSchema;
 use adapt::runtime::theme::ThemeSpec;
+use domain::setting::FlagSettings;
 use serde::Deserialize;
+use serde_json::Value as Json;
+use serve::render::TemplateLanguage;
+use serve::resolver::PluginRoute;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
 use std::path::{Path, PathBuf};
 
@@ -12,11 +21,13 @@ use std::path::{Path, PathBuf};
 ///
 /// `template_root` is always `<theme_dir>/templates`
 /// (or `<assets_dir>/templates` if you decide that later).
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ThemeBinding {
     pub mount_path: String,
     pub theme_id: String,
     pub template_root: PathBuf,
+    /// Engine for templates whose extension does not pick one.
+    pub engine: Option<TemplateLanguage>,
 }
 
 impl ThemeBinding {
@@ -25,8 +36,22 @@ impl ThemeBinding {
             mount_path: mount.into(),
             theme_id: theme.into(),
             template_root,
+            engine: None,
         }
     }
+
+    pub fn with_engine(mut self, engine: Option<TemplateLanguage>) -> Self {
+        self.engine = engine;
+        self
+    }
+
+    /// `<theme_dir>/assets`, next to the template root.
+    pub fn assets_dir(&self) -> PathBuf {
+        self.template_root
+            .parent()
+            .unwrap_or(&self.template_root)
+            .join("assets")
+    }
 }
 
 /// A plugin discovered on disk.
@@ -34,6 +59,8 @@ impl ThemeBinding {
 pub struct DiscoveredPlugin {
     pub dir: PathBuf,
     pub spec: PluginSpec,
+    /// `[flags.*]` tables from plugin.toml.
+    pub flags: BTreeMap<String, FlagSettings>,
 }
 
 /// A theme discovered on disk.
@@ -42,12 +69,17 @@ pub struct DiscoveredPlugin {
 /// - `dir` is the theme root directory on disk
 /// - `assets_dir` (if present) is `<dir>/assets`
 /// - `spec` is the runtime ThemeSpec (id, name, mount_path, source)
+/// - `engine` is the template engine from theme.toml, if set
+/// - `collections` are the saved queries the theme defines (see
+///   crate::collections)
 #[derive(Debug, Clone)]
 pub struct DiscoveredTheme {
     pub mount_path: String,
     pub dir: PathBuf,
     pub assets_dir: Option<PathBuf>,
     pub spec: ThemeSpec,
+    pub engine: Option<TemplateLanguage>,
+    pub collections: BTreeMap<String, Json>,
 }
 
 // ─────────────────────────────────────────────────────────────────────────────
@@ -58,6 +90,24 @@ pub struct DiscoveredTheme {
 struct PluginManifest {
     pub id: Option<String>,
     pub name: Option<String>,
+    #[serde(default)]
+    pub provides: Vec<ServiceContract>,
+    #[serde(default)]
+    pub requires: Vec<ServiceContract>,
+    #[serde(default)]
+    pub permissions: BTreeSet<Capability>,
+    /// `[fetch]`: `hosts`, `timeout_ms`, `max_bytes`.
+    #[serde(default)]
+    pub fetch: FetchPolicy,
+    #[serde(default)]
+    pub flags: BTreeMap<String, FlagSettings>,
+    /// `[settings.*]` tables: `type`, `default`, `required`, `enum`,
+    /// `min`, `max`, `description`.
+    #[serde(default)]
+    pub settings: SettingsSchema,
+    /// `[[routes]]` tables: `method`, `path`, `handler`.
+    #[serde(default)]
+    pub routes: Vec<PluginRoute>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -65,15 +115,52 @@ struct ThemeManifest {
     pub mount: String,
     pub id: Option<String>,
     pub name: Option<String>,
+    /// `"handlebars"`, `"tera"` or `"minijinja"`
+    pub engine: Option<TemplateLanguage>,
+    /// `[collections.*]` tables: `filter`, `sort`, `limit`, `skip`.
+    #[serde(default)]
+    pub collections: BTreeMap<String, Json>,
+    /// `[settings.*]` tables, as for plugins.
+    #[serde(default)]
+    pub settings: SettingsSchema,
 }
 
 // ─────────────────────────────────────────────────────────────────────────────
 // Plugin discovery
 // ─────────────────────────────────────────────────────────────────────────────
 
+/// A plugin directory containing this file is skipped by discovery.
+pub const PLUGIN_DISABLED_MARKER: &str = "disabled";
+
+/// Whether the plugin in `dir` is loaded, i.e. has no disabled marker.
+pub fn plugin_enabled(dir: &Path) -> bool {
+    !dir.join(PLUGIN_DISABLED_MARKER).exists()
+}
+
+/// Enable or disable the plugin in `dir`; a running server picks the change
+/// up through hot reload.
+pub fn set_plugin_enabled(dir: &Path, enabled: bool) -> std::io::Result<()> {
+    let marker = dir.join(PLUGIN_DISABLED_MARKER);
+    match (enabled, marker.exists()) {
+        (true, true) => fs::remove_file(marker),
+        (false, false) => fs::write(marker, ""),
+        _ => Ok(()),
+    }
+}
+
 pub fn discover_plugins(root: impl AsRef<Path>) -> Result<Vec<DiscoveredPlugin>, RuntimeError> {
-    let root = root.as_ref();
+    discover_plugins_where(root.as_ref(), plugin_enabled)
+}
+
+/// Every plugin under `root`, disabled ones included; for listing them.
+pub fn discover_all_plugins(root: impl AsRef<Path>) -> Result<Vec<DiscoveredPlugin>, RuntimeError> {
+    discover_plugins_where(root.as_ref(), |_| true)
+}
 
+fn discover_plugins_where(
+    root: &Path,
+    include: impl Fn(&Path) -> bool,
+) -> Result<Vec<DiscoveredPlugin>, RuntimeError> {
     if !root.exists() {
         return Ok(Vec::new());
     }
@@ -95,6 +182,9 @@ pub fn discover_plugins(root: impl AsRef<Path>) -> Result<Vec<DiscoveredPlugin>,
         if !manifest_path.exists() {
             continue; // not a plugin dir
         }
+        if !include(&path) {
+            continue;
+        }
 
         let manifest_src = fs::read_to_string(&manifest_path).map_err(|e| {
             RuntimeError::Other(format!(
@@ -132,9 +222,20 @@ pub fn discover_plugins(root: impl AsRef<Path>) -> Result<Vec<DiscoveredPlugin>,
             id,
             name,
             source: js_src,
+            provides: manifest.provides,
+            requires: manifest.requires,
+            permissions: manifest.permissions,
+            fetch: manifest.fetch,
+            settings: manifest.settings,
+            config: Json::Null,
+            routes: manifest.routes,
         };
 
-        out.push(DiscoveredPlugin { dir: path, spec });
+        out.push(DiscoveredPlugin {
+            dir: path,
+            spec,
+            flags: manifest.flags,
+        });
     }
 
     Ok(out)
@@ -203,18 +304,16 @@ pub fn discover_themes(root: impl AsRef<Path>) -> Result<Vec<DiscoveredTheme>, R
             _ => None,
         };
 
-        let spec = ThemeSpec {
-            id,
-            name,
-            mount_path: manifest.mount.clone(),
-            source: js_src,
-        };
+        let spec = ThemeSpec::new(id, name, manifest.mount.clone(), js_src)
+            .with_settings(manifest.settings, Json::Null);
 
         out.push(DiscoveredTheme {
             mount_path: manifest.mount,
             dir: path,
             assets_dir,
             spec,
+            engine: manifest.engine,
+            collections: manifest.collections,
         });
     }
 
@@ -234,6 +333,7 @@ impl From<&DiscoveredTheme> for ThemeBinding {
             mount_path: t.mount_path.clone(),
             theme_id: t.spec.id.clone(),
             template_root,
+            engine: t.engine,
         }
     }
 }
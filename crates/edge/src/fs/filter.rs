@@ -16,12 +16,14 @@ use std::path::Path;
 use std::sync::LazyLock;
 
 /// De-facto “pure Rust convertible” content extensions we support.
-/// Markdown, AsciiDoc, reStructuredText, Org, plus passthrough HTML/TXT.
+/// Markdown, AsciiDoc, reStructuredText, Org, block documents, plus
+/// passthrough HTML/TXT.
 pub const DEFAULT_CONTENT_EXTS: &[&str] = &[
     "md", "markdown", "mkd", "mkdn", // Markdown family
     "adoc", "asciidoc", // AsciiDoc
     "rst",      // reStructuredText
     "org",      // Org Mode
+    "blocks",   // Block documents
     "html", "htm", "xhtml", // HTML
     "txt", "text", // Plain text
 ];
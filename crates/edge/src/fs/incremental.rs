@@ -0,0 +1,277 @@
+// crates/edge/src/fs/incremental.rs
+
+// Incremental content indexing.
+//
+// Watches the content directory and feeds changed files through
+// serve::indexer::process_changed_docs, so saving one page re-parses that
+// page's front matter and body instead of rescanning the site. Sending the
+// process a SIGHUP (`kill -HUP <pid>`) clears both indexes and runs a full
+// scan: the fallback when the incremental state is suspect, e.g. after the
+// OS dropped watch events. Rebuilds are timed in the operations history.
+// Under `serve-dev` every pass also reloads the open pages (see livereload).
+
+use crate::db::ops::OpsLog;
+use crate::fs::index::{ContentMgr, SiteContent};
+use crate::fs::watch::{watch_folder, FolderWatchConfig};
+use crate::livereload::LiveReload;
+use crate::proxy::EdgeError;
+use chrono::Utc;
+use regex::Regex;
+use serve::indexer::{process_changed_docs, rebuild_docs, ContentManager, FolderScanConfig};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+use walkdir::WalkDir;
+
+/// Debounce window for editor save bursts.
+const DEBOUNCE_MS: u64 = 250;
+
+/// A batch of watcher paths, sorted into what needs doing.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ContentChanges {
+    /// Source files to re-index (or drop, if they no longer exist).
+    pub files: BTreeSet<PathBuf>,
+    /// Vanished paths that were not content files; if they were directories,
+    /// the documents indexed below them must be dropped.
+    pub vanished: BTreeSet<PathBuf>,
+}
+
+/// Sort watcher paths into [`ContentChanges`].
+///
+/// A directory that appears (e.g. moved in) contributes every content file
+/// below it, since the watcher only reports the directory itself.
+pub fn collect_changes(
+    paths: impl IntoIterator<Item = PathBuf>,
+    file_re: Option<&Regex>,
+) -> ContentChanges {
+    let is_content = |path: &Path| match (file_re, path.file_name()) {
+        (None, _) => true,
+        (Some(re), Some(name)) => re.is_match(&name.to_string_lossy()),
+        (Some(_), None) => false,
+    };
+
+    let mut changes = ContentChanges::default();
+    for path in paths {
+        if path.is_dir() {
+            changes.files.extend(
+                WalkDir::new(&path)
+                    .into_iter()
+                    .filter_map(|e| e.ok())
+                    .filter(|e| e.file_type().is_file() && is_content(e.path()))
+                    .map(|e| e.into_path()),
+            );
+        } else if is_content(&path) {
+            changes.files.insert(path);
+        } else if !path.exists() {
+            changes.vanished.insert(path);
+        }
+    }
+
+    changes
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Watcher
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Everything needed to apply a batch or rebuild from scratch.
+struct ContentIndexer {
+    root: PathBuf,
+    scan_cfg: FolderScanConfig,
+    mgr: ContentMgr,
+    ops: OpsLog,
+    live: Option<Arc<LiveReload>>,
+}
+
+/// Start watching the content `root` and keep the indexes in `content` in
+/// step with it, reloading the pages open on `live` after each pass.
+///
+/// `root` and `scan_cfg` must be what the startup scan used, so re-indexed
+/// documents get the same ids. Runs until the process exits; a failed
+/// document is logged and its previous entry stays removed.
+#[tracing::instrument(skip_all)]
+pub fn watch_content(
+    root: PathBuf,
+    scan_cfg: FolderScanConfig,
+    content: Arc<SiteContent>,
+    ops: OpsLog,
+    live: Option<Arc<LiveReload>>,
+) -> Result<(), EdgeError> {
+    if !root.is_dir() {
+        info!(
+            "Content directory {:?} not found; incremental indexing disabled",
+            root
+        );
+        return Ok(());
+    }
+
+    // The startup scan emits canonical paths; watch the canonical root so
+    // event paths (including deleted ones) have the same form.
+    let watch_root = std::fs::canonicalize(&root)?;
+    let (tx, mut rx) = mpsc::channel::<PathBuf>(1024);
+    let cfg = FolderWatchConfig {
+        recursive: true,
+        debounce_ms: DEBOUNCE_MS,
+        canonicalize_paths: false,
+    };
+    let stop = watch_folder(&watch_root, cfg, tx)?;
+
+    #[cfg(unix)]
+    let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?;
+
+    let indexer = ContentIndexer {
+        mgr: ContentMgr::new(root.clone(), content),
+        root,
+        scan_cfg,
+        ops,
+        live,
+    };
+
+    info!("Watching {:?} for content changes", watch_root);
+
+    tokio::spawn(async move {
+        loop {
+            #[cfg(unix)]
+            let first = tokio::select! {
+                first = rx.recv() => first,
+                Some(()) = hangup.recv() => {
+                    indexer.rebuild().await;
+                    continue;
+                }
+            };
+            #[cfg(not(unix))]
+            let first = rx.recv().await;
+
+            let Some(first) = first else { break };
+
+            // Coalesce everything already queued into one pass.
+            let mut paths = vec![first];
+            while let Ok(path) = rx.try_recv() {
+                paths.push(path);
+            }
+
+            indexer.apply(paths).await;
+        }
+
+        stop();
+    });
+
+    Ok(())
+}
+
+impl ContentIndexer {
+    #[tracing::instrument(skip_all)]
+    async fn apply(&self, paths: Vec<PathBuf>) {
+        let changes = collect_changes(paths, self.scan_cfg.file_re.as_ref());
+        debug!("Content changes: {:?}", changes);
+
+        for dir in &changes.vanished {
+            self.remove_below(dir).await;
+        }
+
+        if !changes.files.is_empty() {
+            let outcome =
+                process_changed_docs(changes.files, self.scan_cfg.file_re.as_ref(), &self.mgr)
+                    .await;
+            for (path, err) in &outcome.errors {
+                warn!("Re-indexing {:?} failed: {}", path, err);
+            }
+            info!(
+                "Re-indexed {} document(s), removed {}",
+                outcome.updated.len(),
+                outcome.removed.len()
+            );
+        } else if changes.vanished.is_empty() {
+            return;
+        }
+        if let Some(live) = &self.live {
+            live.changed();
+        }
+    }
+
+    /// Drop every document indexed below a deleted directory.
+    async fn remove_below(&self, dir: &Path) {
+        let ids = match self
+            .mgr
+            .content()
+            .front_matter_ids_under(&self.root, dir)
+            .await
+        {
+            Ok(ids) => ids,
+            Err(e) => return warn!("Listing documents under {:?} failed: {}", dir, e),
+        };
+
+        for id in ids {
+            let served = self.root.join(id.trim_start_matches('/'));
+            if let Err(e) = self.mgr.remove_document(&served).await {
+                warn!("Removing {:?} failed: {}", served, e);
+            }
+        }
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn rebuild(&self) {
+        info!("Rebuilding content indexes from {:?}", self.root);
+        let began = Utc::now();
+        let rebuilt = rebuild_docs(&self.root, self.scan_cfg.clone(), self.mgr.clone()).await;
+        match self.ops.finish("index.rebuild", None, began, rebuilt) {
+            Ok((docs, errs)) => {
+                for (path, err) in &errs {
+                    warn!("Indexing {:?} failed: {}", path, err);
+                }
+                info!("Rebuilt content indexes: {} document(s)", docs.len());
+            }
+            Err(e) => warn!("Content rebuild failed: {}", e),
+        }
+        if let Some(live) = &self.live {
+            live.changed();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::filter::build_filename_regex;
+    use std::fs;
+
+    #[test]
+    fn collect_changes_expands_new_dirs_and_keeps_vanished_paths() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        let re = build_filename_regex(["md"]).unwrap();
+
+        fs::create_dir_all(root.join("moved/deep")).unwrap();
+        fs::write(root.join("moved/a.md"), "a").unwrap();
+        fs::write(root.join("moved/deep/b.md"), "b").unwrap();
+        fs::write(root.join("moved/notes.txt"), "skip").unwrap();
+        fs::write(root.join("edited.md"), "e").unwrap();
+
+        let changes = collect_changes(
+            [
+                root.join("moved"),
+                root.join("edited.md"),
+                root.join("deleted.md"),
+                root.join("old-section"),
+                root.join("style.css"),
+            ],
+            Some(&re),
+        );
+
+        assert_eq!(
+            changes.files,
+            BTreeSet::from([
+                root.join("deleted.md"),
+                root.join("edited.md"),
+                root.join("moved/a.md"),
+                root.join("moved/deep/b.md"),
+            ])
+        );
+        assert_eq!(
+            changes.vanished,
+            BTreeSet::from([root.join("old-section"), root.join("style.css")])
+        );
+    }
+}
@@ -4,54 +4,200 @@
 //
 // - Wires domain::doc's open_* for filesystem-backed Documents.
 // - Wires domain::stream's open_* for CAS-backed ResolvedContent bodies.
-// - Owns a single IndexedJson<IndexRecord> writer worker (Tokio runtime on
-//   a dedicated thread) for front-matter.
-// - Owns a global Tantivy ContentIndex for rendered HTML.
+// - Owns each site's IndexedJson<IndexRecord> archive for front-matter and
+//   its Tantivy ContentIndex for rendered HTML (`SiteContent`).
 // - Exposes start_scan / index_front_matter / index_body with signatures
 //   expected by serve::indexer.
 
+use crate::cache;
+use crate::db::json::{
+    make_index_field, make_text_query, IndexedId, ELEMENT_FIELDS, INDEXED_FIELDS,
+};
 use crate::db::tantivy::{ContentIndex, ContentIndexError};
 use crate::fs::scan::start_folder_scan;
+use crate::preview::{self, PreviewSigner};
 use crate::proxy::EdgeError;
+use crate::related::Rankings;
+use crate::webhooks::Webhooks;
+use crate::{blocks, permalinks};
 
+use actix_web::{web, HttpRequest};
 use adapt::mql::index::IndexRecord;
+use adapt::mql::{
+    aggregate_query, page_query, stream_query, Cursor, Filter, FindOptions, GroupRow, GroupSpec,
+    IndexBackend, IndexConfig, JsonStore, QueryBudget, QueryError, QueryGovernor,
+};
 use anyhow::Error as AnyError;
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use domain::doc::BodyKind;
-use indexed_json::IndexedJson;
+use domain::setting::{QueryBudgetSettings, Settings, WebhookSettings};
+use indexed_json::{IndexEntry, IndexedJson, Query};
 use serde_json::Value as Json;
-use serve::indexer::{ContentManager, DocContextError, FolderScanConfig, ScanStopFn};
+use serve::authors::AuthorRegistry;
+use serve::blocks::BlockRegistry;
+use serve::cache::{doc_tag, slug_tag, ResponseCache};
+use serve::indexer::{
+    ContentManager, DocContextError, FolderScanConfig, ScanStopFn, FRONT_MATTER_STREAM_BUFFER,
+};
+use serve::permalinks::Permalinks;
 use serve::resolver::ResolverError;
-use std::io::Cursor;
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
 use std::string::FromUtf8Error;
-use std::sync::{Arc, LazyLock};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
 use std::{fs, io};
 use thiserror::Error;
 use tokio::sync::{mpsc, RwLock};
 
-static CAS: LazyLock<RwLock<Option<ContentIndex>>> = LazyLock::new(|| RwLock::new(None));
+// ======================================================================
+// THE SITE'S CONTENT
+// ======================================================================
+
+/// One site's content: its front-matter archive and rendered-body index,
+/// what index updates keep in step with them (the response cache, related
+/// rankings, webhooks), and what indexing and resolving read (authors,
+/// block types, permalinks, the preview key).
+///
+/// Each site in the process has its own; the router hands it to routes as
+/// app data (see [`SiteContent::of`]). The default has no index open, so
+/// every lookup fails with `NoIndex`.
+#[derive(Default)]
+pub struct SiteContent {
+    cas: RwLock<Option<ContentIndex>>,
+    index: RwLock<Option<IndexedJson<IndexRecord>>>,
+    /// Archive entries whose document was re-indexed or removed since start.
+    ///
+    /// IndexedJson is append-only, so an edited file is appended again and
+    /// the entries it replaces are recorded here; every reader skips them.
+    /// Lock order is `index` first, then `superseded`.
+    superseded: RwLock<HashSet<IndexedId>>,
+    cache: Option<ResponseCache>,
+    authors: AuthorRegistry,
+    blocks: Arc<BlockRegistry>,
+    permalinks: Arc<Permalinks>,
+    preview: Option<PreviewSigner>,
+    related: Rankings,
+    /// Set after the startup scan, so only later changes are reported.
+    webhooks: OnceLock<Arc<Webhooks>>,
+}
+
+impl SiteContent {
+    /// Load what `settings` configures for the site under `root_dir` and
+    /// open its indexes in `index_dir`. With `dev` the response cache is
+    /// left off, so `serve-dev` serves every page fresh.
+    pub async fn open(
+        root_dir: &Path,
+        settings: &Settings,
+        index_dir: PathBuf,
+        dev: bool,
+    ) -> Result<Self, EdgeError> {
+        let cache = match &settings.cache {
+            Some(cache) if !dev => Some(cache::open(root_dir, cache)?),
+            _ => None,
+        };
+        let author_settings = settings.authors.clone().unwrap_or_default();
+        let authors = AuthorRegistry::load(&root_dir.join(&author_settings.file))
+            .map_err(|e| EdgeError::Config(e.to_string()))?;
+        let blocks = blocks::load(root_dir, &settings.blocks.clone().unwrap_or_default())?;
+        let permalinks = permalinks::load(
+            &settings.permalinks.clone().unwrap_or_default(),
+            settings.i18n.as_ref().map(|i| i.default.as_str()),
+        )?;
+
+        let content = Self {
+            cache,
+            authors,
+            blocks: Arc::new(blocks),
+            permalinks: Arc::new(permalinks),
+            preview: Some(PreviewSigner::open(root_dir)?),
+            ..Default::default()
+        };
+        content.open_index(index_dir).await?;
+        Ok(content)
+    }
+
+    /// Open (or create) both indexes in `index_dir`, replacing any open ones.
+    pub async fn open_index(&self, index_dir: PathBuf) -> Result<(), FrontMatterIndexError> {
+        let cas = ContentIndex::open_or_create(&index_dir, 15_000_000)
+            .expect("Failed to open/create Tantivy index");
+        {
+            let mut c = self.cas.write().await;
+            *c = Some(cas);
+        }
+        let index = IndexedJson::<IndexRecord>::open(&index_dir)
+            .await
+            .map_err(FrontMatterIndexError::IndexedJson)?;
+
+        {
+            let mut i = self.index.write().await;
+            *i = Some(index);
+            self.superseded.write().await.clear();
+        }
+
+        Ok(())
+    }
+
+    /// Report content changes to the endpoints in `settings` from now on.
+    /// Later calls keep the first endpoints.
+    pub fn start_webhooks(
+        &self,
+        root_dir: &Path,
+        settings: &[WebhookSettings],
+    ) -> Result<(), EdgeError> {
+        if self.webhooks.get().is_none() {
+            if let Some(hooks) = Webhooks::open(root_dir, settings)? {
+                let _ = self.webhooks.set(hooks);
+            }
+        }
+        Ok(())
+    }
+
+    /// The site content the router gave the routes serving `req`; an empty
+    /// one outside a site.
+    pub fn of(req: &HttpRequest) -> Arc<Self> {
+        req.app_data::<web::Data<Self>>()
+            .map(|content| content.clone().into_inner())
+            .unwrap_or_default()
+    }
+
+    /// The response cache, when `[cache]` is configured.
+    pub fn cache(&self) -> Option<&ResponseCache> {
+        self.cache.as_ref()
+    }
 
-static INDEX: LazyLock<RwLock<Option<IndexedJson<IndexRecord>>>> =
-    LazyLock::new(|| RwLock::new(None));
+    /// The declared authors.
+    pub fn authors(&self) -> &AuthorRegistry {
+        &self.authors
+    }
 
-pub async fn set_cas_index(index_dir: PathBuf) -> Result<(), FrontMatterIndexError> {
-    let cas = ContentIndex::open_or_create(&index_dir, 15_000_000)
-        .expect("Failed to open/create Tantivy index");
-    {
-        let mut c = CAS.write().await;
-        *c = Some(cas);
+    /// The block types; only the built-in ones unless configured.
+    pub fn blocks(&self) -> Arc<BlockRegistry> {
+        self.blocks.clone()
     }
-    let index = IndexedJson::<IndexRecord>::open(&index_dir)
-        .await
-        .map_err(FrontMatterIndexError::IndexedJson)?;
 
-    {
-        let mut i = INDEX.write().await;
-        *i = Some(index);
+    /// The permalink patterns.
+    pub fn permalinks(&self) -> Arc<Permalinks> {
+        self.permalinks.clone()
     }
 
-    Ok(())
+    /// The preview signer; without it no draft is ever shown.
+    pub fn preview_signer(&self) -> Option<&PreviewSigner> {
+        self.preview.as_ref()
+    }
+
+    /// The related-post rankings worked out so far.
+    pub fn related(&self) -> &Rankings {
+        &self.related
+    }
+
+    /// Whether both content indexes are open. One held by a reader or
+    /// writer counts as open; this never waits for it.
+    pub fn index_ready(&self) -> bool {
+        !matches!(self.cas.try_read().as_deref(), Ok(None))
+            && !matches!(self.index.try_read().as_deref(), Ok(None))
+    }
 }
 
 // ======================================================================
@@ -68,6 +214,9 @@ pub enum FrontMatterIndexError {
 
     #[error("No Index")]
     NoIndex(String),
+
+    #[error("Query: {0}")]
+    Query(#[from] QueryError),
 }
 
 #[derive(Debug, Error)]
@@ -107,98 +256,135 @@ fn canonical_id_from_source(root: &Path, path: &Path) -> String {
 // IndexedJson handlers (reuse a single DB instance)
 // ======================================================================
 
-async fn handle_fm_index(
-    root: PathBuf,
-    served_path: PathBuf,
-    fm: Json,
-) -> Result<(), FrontMatterIndexError> {
-    // IMPORTANT: use canonical *served* ID, not absolute FS path.
-    let id = canonical_id_from_source(&root, &served_path);
-    let mut record = IndexRecord::from_json_with_id(id, &fm);
-
-    // Optionally hydrate slug from FM if not already set.
-    if record.slug.is_none() {
-        if let Some(slug_val) = fm
-            .get("slug")
-            .and_then(|v| v.as_str())
-            .map(|s| s.to_string())
-        {
-            record.slug = Some(slug_val);
-        }
-    }
+impl SiteContent {
+    /// Hide every live entry recorded under `id`.
+    async fn supersede_id(
+        &self,
+        db: &IndexedJson<IndexRecord>,
+        id: &str,
+    ) -> Result<(), FrontMatterIndexError> {
+        let Some(field) = make_index_field("id", &Json::String(id.to_owned())) else {
+            return Ok(());
+        };
+        let entries = db
+            .query(&Query::Eq(field))
+            .map_err(FrontMatterIndexError::IndexedJson)?;
 
-    if let Some(db) = INDEX.write().await.as_mut() {
-        db.append(&record)
+        self.superseded
+            .write()
             .await
-            .map_err(FrontMatterIndexError::IndexedJson)?;
+            .extend(entries.into_iter().map(|e| IndexedId(*e)));
+        Ok(())
+    }
 
-        db.flush().await.map_err(FrontMatterIndexError::IndexedJson)
-    } else {
-        Err(FrontMatterIndexError::NoIndex("No Database".into()))
+    async fn is_superseded(&self, entry: IndexEntry) -> bool {
+        self.superseded.read().await.contains(&IndexedId(entry))
     }
-}
 
-/// Linear scan by `record.id == served_path` (served-path form).
-///
-/// We serialize the IndexRecord back to JSON to use as front_matter.
-/// This is the *projection* shape, not necessarily the original FM.
-async fn handle_get_front_matter_by_path(
-    served_path: &Path,
-) -> Result<Option<Json>, FrontMatterIndexError> {
-    if let Some(db) = INDEX.write().await.as_mut() {
-        let mut current = match db.first() {
-            Some(entry) => entry,
-            None => return Ok(None),
-        };
+    async fn handle_fm_index(
+        &self,
+        root: PathBuf,
+        served_path: PathBuf,
+        fm: Json,
+    ) -> Result<(), FrontMatterIndexError> {
+        // IMPORTANT: use canonical *served* ID, not absolute FS path.
+        let id = canonical_id_from_source(&root, &served_path);
+        let mut record = IndexRecord::from_json_with_id(id, &fm);
 
-        loop {
-            match db.get(current).await {
-                Ok(Some((next, rec))) => {
-                    // NOTE: If IndexRecord.id is a String, you may need to
-                    // compare to served_path.to_string_lossy().
-                    if rec.id == served_path.to_path_buf() {
-                        let json = serde_json::to_value(rec)
-                            .map_err(|e| FrontMatterIndexError::IndexedJson(e.into()))?;
-                        return Ok(Some(json));
-                    }
-                    current = next;
-                }
-                Ok(None) => return Ok(None),
-                Err(e) => return Err(FrontMatterIndexError::IndexedJson(e.into())),
+        // Optionally hydrate slug from FM if not already set.
+        if record.slug.is_none() {
+            if let Some(slug_val) = fm
+                .get("slug")
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string())
+            {
+                record.slug = Some(slug_val);
             }
         }
-    } else {
-        Err(FrontMatterIndexError::NoIndex("No Database".into()))
+
+        if let Some(db) = self.index.write().await.as_mut() {
+            self.supersede_id(db, &record.id).await?;
+            db.append(&record)
+                .await
+                .map_err(FrontMatterIndexError::IndexedJson)?;
+
+            db.flush().await.map_err(FrontMatterIndexError::IndexedJson)
+        } else {
+            Err(FrontMatterIndexError::NoIndex("No Database".into()))
+        }
     }
-}
 
-async fn handle_get_front_matter_by_slug(
-    slug: &str,
-) -> Result<Option<Json>, FrontMatterIndexError> {
-    if let Some(db) = INDEX.write().await.as_mut() {
-        let mut current = match db.first() {
-            None => return Ok(None),
-            Some(entry) => entry,
-        };
+    /// Linear scan by `record.id == served_path` (served-path form).
+    ///
+    /// We serialize the IndexRecord back to JSON to use as front_matter.
+    /// This is the *projection* shape, not necessarily the original FM.
+    async fn handle_get_front_matter_by_path(
+        &self,
+        served_path: &Path,
+    ) -> Result<Option<Json>, FrontMatterIndexError> {
+        if let Some(db) = self.index.write().await.as_mut() {
+            let mut current = match db.first() {
+                Some(entry) => entry,
+                None => return Ok(None),
+            };
 
-        loop {
-            match db.get(current).await {
-                Ok(Some((next, rec))) => {
-                    if let Some(s) = &rec.slug {
-                        if s == &slug {
+            loop {
+                match db.get(current).await {
+                    Ok(Some((next, rec))) => {
+                        // NOTE: If IndexRecord.id is a String, you may need to
+                        // compare to served_path.to_string_lossy().
+                        if rec.id == served_path.to_path_buf() && !self.is_superseded(current).await
+                        {
+                            if !rec.is_live_at(Utc::now()) {
+                                return Ok(None);
+                            }
                             let json = serde_json::to_value(rec)
                                 .map_err(|e| FrontMatterIndexError::IndexedJson(e.into()))?;
                             return Ok(Some(json));
                         }
+                        current = next;
                     }
-                    current = next;
+                    Ok(None) => return Ok(None),
+                    Err(e) => return Err(FrontMatterIndexError::IndexedJson(e)),
                 }
-                Ok(None) => return Ok(None),
-                Err(e) => return Err(FrontMatterIndexError::IndexedJson(e.into())),
             }
+        } else {
+            Err(FrontMatterIndexError::NoIndex("No Database".into()))
+        }
+    }
+
+    async fn handle_get_front_matter_by_slug(
+        &self,
+        slug: &str,
+    ) -> Result<Option<Json>, FrontMatterIndexError> {
+        if let Some(db) = self.index.write().await.as_mut() {
+            let mut current = match db.first() {
+                None => return Ok(None),
+                Some(entry) => entry,
+            };
+
+            loop {
+                match db.get(current).await {
+                    Ok(Some((next, rec))) => {
+                        if let Some(s) = &rec.slug {
+                            if *s == slug
+                                && rec.is_live_at(Utc::now())
+                                && !self.is_superseded(current).await
+                            {
+                                let json = serde_json::to_value(rec)
+                                    .map_err(|e| FrontMatterIndexError::IndexedJson(e.into()))?;
+                                return Ok(Some(json));
+                            }
+                        }
+                        current = next;
+                    }
+                    Ok(None) => return Ok(None),
+                    Err(e) => return Err(FrontMatterIndexError::IndexedJson(e)),
+                }
+            }
+        } else {
+            Err(FrontMatterIndexError::NoIndex("No Database".into()))
         }
-    } else {
-        Err(FrontMatterIndexError::NoIndex("No Database".into()))
     }
 }
 
@@ -220,42 +406,181 @@ pub fn start_scan(
 // 2. FRONT MATTER INDEXER (sync) — matches IndexFrontMatterFn
 // ======================================================================
 
-#[tracing::instrument(skip_all)]
-pub async fn index_front_matter(
-    root: PathBuf,
-    served_path: &Path,
-    fm: &Json,
-) -> Result<(), FrontMatterIndexError> {
-    handle_fm_index(root, served_path.to_path_buf(), fm.clone()).await
-}
+impl SiteContent {
+    #[tracing::instrument(skip_all)]
+    pub async fn index_front_matter(
+        &self,
+        root: PathBuf,
+        served_path: &Path,
+        fm: &Json,
+    ) -> Result<(), FrontMatterIndexError> {
+        self.handle_fm_index(root, served_path.to_path_buf(), fm.clone())
+            .await
+    }
 
-/// Public helper used by the resolver to load front matter by served path.
-///
-/// `served_path` here is already HTTP-style (e.g. `/index.html`).
-/// Returns Ok(None) if the id is not present in the index.
-pub async fn lookup_front_matter_by_path(
-    served_path: &Path,
-) -> Result<Option<Json>, FrontMatterIndexError> {
-    handle_get_front_matter_by_path(served_path).await
-}
+    /// Drop the front matter indexed for `served_path`.
+    pub async fn remove_front_matter(
+        &self,
+        root: &Path,
+        served_path: &Path,
+    ) -> Result<(), FrontMatterIndexError> {
+        let id = canonical_id_from_source(root, served_path);
+        match self.index.write().await.as_ref() {
+            Some(db) => self.supersede_id(db, &id).await,
+            None => Err(FrontMatterIndexError::NoIndex("No Database".into())),
+        }
+    }
 
-/// Public helper used by the resolver to load front matter by **slug**.
-///
-/// This scans the IndexedJson archive for a record whose `slug` field
-/// matches the provided slug. Returns Ok(None) if not found.
-pub async fn lookup_front_matter_by_slug(
-    slug: &str,
-) -> Result<Option<Json>, FrontMatterIndexError> {
-    handle_get_front_matter_by_slug(slug).await
-}
+    /// Drop all indexed front matter.
+    pub async fn clear_front_matter(&self) -> Result<(), FrontMatterIndexError> {
+        if let Some(db) = self.index.write().await.as_mut() {
+            let mut current = db.first();
+            let mut entries = Vec::new();
+            while let Some(entry) = current {
+                match db.get(entry).await {
+                    Ok(Some((next, _))) => {
+                        entries.push(IndexedId(entry));
+                        current = Some(next);
+                    }
+                    Ok(None) => break,
+                    Err(e) => return Err(FrontMatterIndexError::IndexedJson(e)),
+                }
+            }
+            self.superseded.write().await.extend(entries);
+            Ok(())
+        } else {
+            Err(FrontMatterIndexError::NoIndex("No Database".into()))
+        }
+    }
 
-pub async fn lookup_body(key: &str) -> Result<Option<Arc<String>>, ContentBodyIndexError> {
-    if let Some(cas) = CAS.write().await.as_mut() {
-        let cursor = cas.get(Path::new(key))?;
-        let bytes = cursor.into_inner(); // take ownership of the Vec<u8>
-        Ok(Some(Arc::new(String::from_utf8(bytes)?)))
-    } else {
-        Err(ContentBodyIndexError::NoCas("No Database".into()))
+    /// Served ids of the live documents below `dir` (a source directory
+    /// under `root`), e.g. to drop them when the directory is deleted.
+    pub async fn front_matter_ids_under(
+        &self,
+        root: &Path,
+        dir: &Path,
+    ) -> Result<Vec<String>, FrontMatterIndexError> {
+        let prefix = format!(
+            "{}/",
+            canonical_id_from_source(root, dir).trim_end_matches('/')
+        );
+        let mut ids = Vec::new();
+
+        if let Some(db) = self.index.write().await.as_mut() {
+            let mut current = db.first();
+            while let Some(entry) = current {
+                match db.get(entry).await {
+                    Ok(Some((next, rec))) => {
+                        if rec.id.starts_with(&prefix) && !self.is_superseded(entry).await {
+                            ids.push(rec.id);
+                        }
+                        current = Some(next);
+                    }
+                    Ok(None) => break,
+                    Err(e) => return Err(FrontMatterIndexError::IndexedJson(e)),
+                }
+            }
+            Ok(ids)
+        } else {
+            Err(FrontMatterIndexError::NoIndex("No Database".into()))
+        }
+    }
+
+    /// Each indexed document's scheduled visibility changes: its
+    /// `publish.date` and `publish.unpublish_at`, past or future, paired
+    /// with the record in the shape queries return.
+    pub async fn visibility_changes(
+        &self,
+    ) -> Result<Vec<(DateTime<Utc>, Json)>, FrontMatterIndexError> {
+        let mut changes = Vec::new();
+
+        if let Some(db) = self.index.write().await.as_mut() {
+            let mut current = db.first();
+            while let Some(entry) = current {
+                match db.get(entry).await {
+                    Ok(Some((next, rec))) => {
+                        let times = [rec.publish.publish_at(), rec.publish.unpublish_at()];
+                        if times.iter().any(Option::is_some) && !self.is_superseded(entry).await {
+                            let json = serde_json::to_value(&rec)
+                                .map_err(|e| FrontMatterIndexError::IndexedJson(e.into()))?;
+                            changes
+                                .extend(times.into_iter().flatten().map(|at| (at, json.clone())));
+                        }
+                        current = Some(next);
+                    }
+                    Ok(None) => break,
+                    Err(e) => return Err(FrontMatterIndexError::IndexedJson(e)),
+                }
+            }
+            Ok(changes)
+        } else {
+            Err(FrontMatterIndexError::NoIndex("No Database".into()))
+        }
+    }
+
+    /// Public helper used by the resolver to load front matter by served
+    /// path.
+    ///
+    /// `served_path` here is already HTTP-style (e.g. `/index.html`).
+    /// Returns Ok(None) if the id is not present in the index, if its
+    /// `publish.date` has not come yet, or if its `publish.unpublish_at` has
+    /// passed.
+    #[tracing::instrument(name = "db.front_matter", skip_all, fields(path = %served_path.display()))]
+    pub async fn lookup_front_matter_by_path(
+        &self,
+        served_path: &Path,
+    ) -> Result<Option<Json>, FrontMatterIndexError> {
+        let fm = self.handle_get_front_matter_by_path(served_path).await?;
+        // A miss is recorded too: the page changes once the document appears.
+        cache::consumed([doc_tag(&served_path.to_string_lossy())]);
+        Ok(fm)
+    }
+
+    /// Public helper used by the resolver to load front matter by **slug**.
+    ///
+    /// This scans the IndexedJson archive for a record whose `slug` field
+    /// matches the provided slug. Returns Ok(None) if not found, not yet
+    /// published, or expired.
+    #[tracing::instrument(name = "db.front_matter", skip_all, fields(slug = %slug))]
+    pub async fn lookup_front_matter_by_slug(
+        &self,
+        slug: &str,
+    ) -> Result<Option<Json>, FrontMatterIndexError> {
+        let fm = self.handle_get_front_matter_by_slug(slug).await?;
+        cache::consumed([slug_tag(slug)]);
+        Ok(fm)
+    }
+
+    #[tracing::instrument(name = "db.body", skip_all, fields(key = %key))]
+    pub async fn lookup_body(
+        &self,
+        key: &str,
+    ) -> Result<Option<Arc<String>>, ContentBodyIndexError> {
+        if let Some(cas) = self.cas.write().await.as_mut() {
+            let cursor = cas.get(Path::new(key))?;
+            let bytes = cursor.into_inner(); // take ownership of the Vec<u8>
+            Ok(Some(Arc::new(String::from_utf8(bytes)?)))
+        } else {
+            Err(ContentBodyIndexError::NoCas("No Database".into()))
+        }
+    }
+
+    /// Served ids of the rendered bodies matching `query` (Tantivy query
+    /// syntax), best first, at most `limit`.
+    #[tracing::instrument(skip_all)]
+    pub async fn search_bodies(
+        &self,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<String>, ContentBodyIndexError> {
+        match self.cas.read().await.as_ref() {
+            Some(cas) => Ok(cas
+                .search(query, limit)?
+                .into_iter()
+                .map(|hit| hit.path.to_string_lossy().into_owned())
+                .collect()),
+            None => Err(ContentBodyIndexError::NoCas("No Cas".into())),
+        }
     }
 }
 
@@ -263,31 +588,318 @@ pub async fn lookup_body(key: &str) -> Result<Option<Arc<String>>, ContentBodyIn
 // 3. BODY → TANTIVY INDEXER (sync) — matches IndexBodyFn
 // ======================================================================
 
-pub async fn index_body(
-    root: &Path,
-    served_path: &Path,
-    html: &str,
-    _kind: BodyKind,
-) -> Result<(), ContentBodyIndexError> {
-    if let Some(cas) = CAS.write().await.as_mut() {
-        // Canonicalize to served ID so CAS lookups by HTTP path work.
-        let id = canonical_id_from_source(root, served_path);
-        let mut cursor = Cursor::new(html.as_bytes().to_vec());
-        cas.add(Path::new(&id), &mut cursor)?;
-        Ok(())
-    } else {
-        Err(ContentBodyIndexError::NoCas("No Cas".into()))
+impl SiteContent {
+    pub async fn index_body(
+        &self,
+        root: &Path,
+        served_path: &Path,
+        html: &str,
+        _kind: BodyKind,
+    ) -> Result<(), ContentBodyIndexError> {
+        if let Some(cas) = self.cas.write().await.as_mut() {
+            // Canonicalize to served ID so CAS lookups by HTTP path work.
+            let id = canonical_id_from_source(root, served_path);
+            let mut cursor = io::Cursor::new(html.as_bytes().to_vec());
+            cas.add(Path::new(&id), &mut cursor)?;
+            Ok(())
+        } else {
+            Err(ContentBodyIndexError::NoCas("No Cas".into()))
+        }
+    }
+
+    /// Drop the rendered body indexed for `served_path`.
+    pub async fn remove_body(
+        &self,
+        root: &Path,
+        served_path: &Path,
+    ) -> Result<(), ContentBodyIndexError> {
+        if let Some(cas) = self.cas.write().await.as_mut() {
+            let id = canonical_id_from_source(root, served_path);
+            cas.remove(Path::new(&id))?;
+            Ok(())
+        } else {
+            Err(ContentBodyIndexError::NoCas("No Cas".into()))
+        }
+    }
+
+    /// Drop every rendered body.
+    pub async fn clear_bodies(&self) -> Result<(), ContentBodyIndexError> {
+        if let Some(cas) = self.cas.write().await.as_mut() {
+            cas.clear()?;
+            Ok(())
+        } else {
+            Err(ContentBodyIndexError::NoCas("No Cas".into()))
+        }
     }
 }
 
-#[derive(Debug, Clone)]
+// ======================================================================
+// 4. MQL QUERIES — the front-matter archive as JsonStore + IndexBackend
+// ======================================================================
+
+/// Handle onto a site's archive so the MQL planner can run against it
+/// without owning it.
+#[derive(Clone, Copy)]
+struct FrontMatterArchive<'a>(&'a SiteContent);
+
+#[async_trait]
+impl JsonStore for FrontMatterArchive<'_> {
+    type Id = IndexedId;
+
+    async fn all_ids(&self) -> Vec<Self::Id> {
+        let mut ids = Vec::new();
+        let now = Utc::now();
+
+        if let Some(db) = self.0.index.write().await.as_mut() {
+            let mut current = db.first();
+            while let Some(entry) = current {
+                match db.get(entry).await {
+                    Ok(Some((next, rec))) => {
+                        if rec.is_live_at(now) && !self.0.is_superseded(entry).await {
+                            ids.push(IndexedId(entry));
+                        }
+                        current = Some(next);
+                    }
+                    _ => break,
+                }
+            }
+        }
+
+        ids
+    }
+
+    /// Scheduled records (`publish.date` in the future), expired ones
+    /// (`publish.unpublish_at` in the past) and superseded entries read as
+    /// missing, so index-planned queries drop them the same way full scans
+    /// do.
+    async fn get(&self, id: Self::Id) -> Option<Json> {
+        let mut guard = self.0.index.write().await;
+        if self.0.is_superseded(id.0).await {
+            return None;
+        }
+        match guard.as_mut()?.get(id.0).await {
+            Ok(Some((_next, rec))) if rec.is_live_at(Utc::now()) => serde_json::to_value(rec).ok(),
+            _ => None,
+        }
+    }
+}
+
+impl FrontMatterArchive<'_> {
+    async fn run_query(&self, q: &Query) -> Option<HashSet<IndexedId>> {
+        let guard = self.0.index.read().await;
+        let set = guard.as_ref()?.query(q).ok()?;
+        let superseded = self.0.superseded.read().await;
+        Some(
+            set.into_iter()
+                .map(|e| IndexedId(*e))
+                .filter(|id| !superseded.contains(id))
+                .collect(),
+        )
+    }
+}
+
+#[async_trait]
+impl IndexBackend for FrontMatterArchive<'_> {
+    type Id = IndexedId;
+
+    async fn lookup_eq(&self, field: &str, value: &Json) -> Option<HashSet<Self::Id>> {
+        let f = make_index_field(field, value)?;
+        self.run_query(&Query::Eq(f)).await
+    }
+
+    async fn lookup_in(&self, field: &str, values: &[Json]) -> Option<HashSet<Self::Id>> {
+        let mut clauses: Vec<Query> = values
+            .iter()
+            .filter_map(|v| make_index_field(field, v).map(Query::Eq))
+            .collect();
+
+        let q = match clauses.len() {
+            0 => return None,
+            1 => clauses.remove(0),
+            _ => Query::Or(clauses),
+        };
+
+        self.run_query(&q).await
+    }
+
+    async fn lookup_text(&self, field: &str, words: &[String]) -> Option<HashSet<Self::Id>> {
+        self.run_query(&make_text_query(field, words)?).await
+    }
+}
+
+/// Every archive field, with the multi-valued ones marked as indexed per
+/// element.
+fn archive_config() -> IndexConfig {
+    IndexConfig::new(INDEXED_FIELDS.iter().copied())
+        .with_element_fields(ELEMENT_FIELDS.iter().copied())
+}
+
+/// A fresh governor for one request, named `label` in logs, with the
+/// budget in `settings`; unlimited without them. Run the request under
+/// `adapt::mql::governed`.
+pub fn query_governor(
+    label: impl Into<String>,
+    settings: Option<&QueryBudgetSettings>,
+) -> Arc<QueryGovernor> {
+    let budget = settings.map_or_else(QueryBudget::default, |settings| QueryBudget {
+        max_scanned: settings.max_scanned,
+        max_time: settings.max_time_ms.map(Duration::from_millis),
+        slow_query: settings.slow_query_ms.map(Duration::from_millis),
+    });
+    Arc::new(QueryGovernor::new(label, budget))
+}
+
+impl SiteContent {
+    /// Run an MQL query against the front-matter archive.
+    ///
+    /// Returns the total number of matches together with the page selected
+    /// by `opts.after` / `opts.skip` / `opts.limit`, and the cursor the next
+    /// page starts after when there is one, so callers can paginate from one
+    /// pass.
+    #[tracing::instrument(skip_all)]
+    pub async fn query_front_matter(
+        &self,
+        filter: &Filter,
+        opts: &FindOptions,
+    ) -> Result<(usize, Vec<Json>, Option<Cursor>), FrontMatterIndexError> {
+        if self.index.read().await.is_none() {
+            return Err(FrontMatterIndexError::NoIndex("No Database".into()));
+        }
+
+        cache::consumed_query(filter);
+        let config = archive_config();
+        let archive = FrontMatterArchive(self);
+        let page = page_query(&config, &archive, &archive, filter, opts).await?;
+
+        let items = page.results.into_iter().map(|r| r.doc).collect();
+        Ok((page.total, items, page.next))
+    }
+
+    /// Group the front matter matching `filter` once per spec (tag counts,
+    /// posts per month, …) without returning the documents.
+    #[tracing::instrument(skip_all)]
+    pub async fn aggregate_front_matter(
+        &self,
+        filter: &Filter,
+        specs: &[GroupSpec],
+    ) -> Result<Vec<Vec<GroupRow>>, FrontMatterIndexError> {
+        if self.index.read().await.is_none() {
+            return Err(FrontMatterIndexError::NoIndex("No Database".into()));
+        }
+
+        cache::consumed_query(filter);
+        let config = archive_config();
+        let archive = FrontMatterArchive(self);
+        Ok(aggregate_query(&config, &archive, &archive, filter, specs).await?)
+    }
+
+    /// `query_front_matter` for bulk readers: each match is sent to `tx` as
+    /// `(served path, front matter)` as soon as it is read, instead of the
+    /// whole result being collected. Returns the number sent.
+    #[tracing::instrument(skip_all)]
+    pub async fn stream_front_matter(
+        &self,
+        filter: &Filter,
+        opts: &FindOptions,
+        tx: mpsc::Sender<(String, Json)>,
+    ) -> Result<usize, FrontMatterIndexError> {
+        if self.index.read().await.is_none() {
+            return Err(FrontMatterIndexError::NoIndex("No Database".into()));
+        }
+
+        let config = archive_config();
+        let archive = FrontMatterArchive(self);
+        let (results_tx, mut results_rx) = mpsc::channel(FRONT_MATTER_STREAM_BUFFER);
+        let query = async move {
+            stream_query(&config, &archive, &archive, filter, opts, &results_tx).await
+        };
+        let forward = async move {
+            while let Some(result) = results_rx.recv().await {
+                let Some(served) = result.doc.get("id").and_then(Json::as_str) else {
+                    continue;
+                };
+                if tx.send((served.to_owned(), result.doc)).await.is_err() {
+                    // Dropping `results_rx` stops the query too.
+                    break;
+                }
+            }
+        };
+
+        let (sent, ()) = tokio::join!(query, forward);
+        Ok(sent?)
+    }
+}
+
+#[derive(Clone)]
 pub struct ContentMgr {
     root: PathBuf,
+    content: Arc<SiteContent>,
+    /// Preview token from the request; drafts it was signed for resolve.
+    preview: Option<String>,
 }
 
 impl ContentMgr {
-    pub fn new(root: PathBuf) -> Self {
-        Self { root }
+    /// Index and resolve the documents under `root` in `content`.
+    pub fn new(root: PathBuf, content: Arc<SiteContent>) -> Self {
+        Self {
+            root,
+            content,
+            preview: None,
+        }
+    }
+
+    /// Resolve the draft `token` was signed for (see [`preview`]), as well
+    /// as published documents.
+    ///
+    /// [`preview`]: crate::preview
+    pub fn with_preview(mut self, token: impl Into<String>) -> Self {
+        self.preview = Some(token.into());
+        self
+    }
+
+    /// The site content this manager indexes into.
+    pub fn content(&self) -> &Arc<SiteContent> {
+        &self.content
+    }
+
+    /// `fm` unless it is a draft the preview token does not open.
+    fn visible(&self, fm: Option<Json>) -> Option<Json> {
+        let fm = fm?;
+        if !preview::is_draft(&fm) {
+            return Some(fm);
+        }
+        let id = fm.get("id").and_then(Json::as_str)?;
+        let token = self.preview.as_deref()?;
+        self.content
+            .preview_signer()?
+            .verify(id, token, Utc::now())
+            .then_some(fm)
+    }
+
+    /// The live record for the document at `served_path`, for working out
+    /// which cached pages an update affects. Skipped while caching is off.
+    async fn cached_record(&self, served_path: &Path) -> Option<Json> {
+        self.content.cache()?;
+        let id = canonical_id_from_source(&self.root, served_path);
+        let field = make_index_field("id", &Json::String(id))?;
+
+        let mut guard = self.content.index.write().await;
+        let db = guard.as_mut()?;
+        let entries: Vec<IndexEntry> = db
+            .query(&Query::Eq(field))
+            .ok()?
+            .into_iter()
+            .copied()
+            .collect();
+        for entry in entries {
+            if self.content.is_superseded(entry).await {
+                continue;
+            }
+            if let Ok(Some((_, rec))) = db.get(entry).await {
+                return serde_json::to_value(rec).ok();
+            }
+        }
+        None
     }
 }
 #[async_trait]
@@ -310,9 +922,25 @@ impl ContentManager for ContentMgr {
         served_path: &Path,
         fm: &Json,
     ) -> Result<(), DocContextError> {
-        index_front_matter(self.root.clone(), served_path, fm)
+        let content = &self.content;
+        let fm = content.authors.link_front_matter(fm);
+        let before = self.cached_record(served_path).await;
+        let existed = before.is_some();
+        content
+            .index_front_matter(self.root.clone(), served_path, &fm)
             .await
-            .map_err(|e| DocContextError::FrontMatterIndex(e.to_string()))
+            .map_err(|e| DocContextError::FrontMatterIndex(e.to_string()))?;
+        let after = self.cached_record(served_path).await;
+        cache::invalidate_docs(
+            content.cache(),
+            &[before, after].into_iter().flatten().collect::<Vec<_>>(),
+        );
+        let id = canonical_id_from_source(&self.root, served_path);
+        content.related.changed(&id, &fm);
+        if let Some(hooks) = content.webhooks.get() {
+            hooks.changed(&id, existed);
+        }
+        Ok(())
     }
 
     async fn index_body(
@@ -321,26 +949,106 @@ impl ContentManager for ContentMgr {
         html: &str,
         kind: BodyKind,
     ) -> Result<(), DocContextError> {
-        index_body(self.root.as_path(), served_path, html, kind)
+        self.content
+            .index_body(self.root.as_path(), served_path, html, kind)
+            .await
+            .map_err(|e| DocContextError::ContentIndex(e.to_string()))?;
+        let id = canonical_id_from_source(&self.root, served_path);
+        cache::invalidate_docs(self.content.cache(), &[serde_json::json!({ "id": id })]);
+        Ok(())
+    }
+
+    async fn remove_document(&self, served_path: &Path) -> Result<(), DocContextError> {
+        let content = &self.content;
+        let before = self.cached_record(served_path).await;
+        content
+            .remove_front_matter(&self.root, served_path)
+            .await
+            .map_err(|e| DocContextError::FrontMatterIndex(e.to_string()))?;
+        content
+            .remove_body(&self.root, served_path)
             .await
-            .map_err(|e| DocContextError::ContentIndex(e.to_string()))
+            .map_err(|e| DocContextError::ContentIndex(e.to_string()))?;
+        let id = canonical_id_from_source(&self.root, served_path);
+        content.related.removed(&id);
+        if let Some(hooks) = content.webhooks.get() {
+            hooks.removed(&id);
+        }
+        let removed = before.unwrap_or_else(|| serde_json::json!({ "id": id }));
+        cache::invalidate_docs(content.cache(), &[removed]);
+        Ok(())
+    }
+
+    async fn clear_index(&self) -> Result<(), DocContextError> {
+        let content = &self.content;
+        content
+            .clear_front_matter()
+            .await
+            .map_err(|e| DocContextError::FrontMatterIndex(e.to_string()))?;
+        content
+            .clear_bodies()
+            .await
+            .map_err(|e| DocContextError::ContentIndex(e.to_string()))?;
+        content.related.clear();
+        if let Some(hooks) = content.webhooks.get() {
+            hooks.clear();
+        }
+        cache::invalidate(content.cache());
+        Ok(())
+    }
+
+    async fn list_front_matter(&self) -> Result<Vec<(String, Json)>, ResolverError> {
+        let (_, docs, _) = self
+            .content
+            .query_front_matter(&Filter::And(vec![]), &FindOptions::default())
+            .await
+            .map_err(|e| ResolverError::Backend(e.to_string()))?;
+
+        Ok(docs
+            .into_iter()
+            .filter_map(|doc| Some((doc.get("id")?.as_str()?.to_owned(), doc)))
+            .collect())
+    }
+
+    async fn stream_front_matter(
+        &self,
+        tx: mpsc::Sender<(String, Json)>,
+    ) -> Result<(), ResolverError> {
+        self.content
+            .stream_front_matter(&Filter::And(vec![]), &FindOptions::default(), tx)
+            .await
+            .map(|_| ())
+            .map_err(|e| ResolverError::Backend(e.to_string()))
     }
 
     async fn lookup_slug(&self, slug: &str) -> Result<Option<Json>, ResolverError> {
-        lookup_front_matter_by_slug(slug)
+        self.content
+            .lookup_front_matter_by_slug(slug)
             .await
+            .map(|fm| self.visible(fm))
             .map_err(|e| ResolverError::Backend(e.to_string()))
     }
 
     async fn lookup_served(&self, served: &str) -> Result<Option<Json>, ResolverError> {
-        lookup_front_matter_by_path(Path::new(served))
+        self.content
+            .lookup_front_matter_by_path(Path::new(served))
             .await
+            .map(|fm| self.visible(fm))
             .map_err(|e| ResolverError::Backend(e.to_string()))
     }
 
     async fn lookup_body(&self, key: &str) -> Result<Option<Arc<String>>, ResolverError> {
-        lookup_body(key)
+        self.content
+            .lookup_body(key)
             .await
             .map_err(|e| ResolverError::Backend(e.to_string()))
     }
+
+    fn blocks(&self) -> Arc<BlockRegistry> {
+        self.content.blocks()
+    }
+
+    fn permalinks(&self) -> Arc<Permalinks> {
+        self.content.permalinks()
+    }
 }
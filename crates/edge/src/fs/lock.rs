@@ -0,0 +1,316 @@
+// crates/edge/src/fs/lock.rs
+
+// Integrity of installed extensions. `<site>/extensions.lock` records the
+// SHA-256 of every file under `plugins/` and `themes/` in the extensions
+// directory:
+//
+//   { "format": 1, "locked_at": "2026-01-02T03:04:05Z",
+//     "files": { "plugins/seo/plugin.js": "9f2c…",
+//                "themes/plain/templates/index.hbs": "41d0…" } }
+//
+// `whispercms verify` compares the files on disk with it, and so does every
+// start, before discovery; `[ext] integrity` picks what a difference does:
+// "warn" (the default) logs each file and starts anyway, "refuse" stops, and
+// "off" skips the check. The first start without a lockfile writes one, so a
+// site is protected from then on. Installing or updating an extension by
+// hand is confirmed with `whispercms verify --update`; `whispercms plugin`
+// and `theme` install, update and remove (packages), and a manifest rollback
+// through `whispercms ext rollback`, update their own entries.
+//
+// The disabled marker is not recorded: enabling and disabling plugins is an
+// admin action, not a change to what is installed.
+
+use crate::db::history::write_atomic;
+use crate::fs::ext::PLUGIN_DISABLED_MARKER;
+use crate::proxy::EdgeError;
+use chrono::{DateTime, Utc};
+use domain::hex;
+use domain::setting::IntegrityMode;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use thiserror::Error;
+use tracing::{info, warn};
+
+/// The lockfile inside the site directory.
+pub const LOCK_FILE: &str = "./extensions.lock";
+
+const FORMAT: u32 = 1;
+
+#[derive(Debug, Error)]
+pub enum LockError {
+    #[error("I/O: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("JSON: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("unsupported lockfile format {0}")]
+    Format(u32),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lockfile {
+    pub format: u32,
+    pub locked_at: DateTime<Utc>,
+    /// SHA-256 (hex) by `/`-separated path under the extensions directory.
+    pub files: BTreeMap<String, String>,
+}
+
+/// How a file differs from the lockfile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Added,
+    Modified,
+    Removed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Drift {
+    pub path: String,
+    pub change: Change,
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let change = match self.change {
+            Change::Added => "added",
+            Change::Modified => "modified",
+            Change::Removed => "removed",
+        };
+        write!(f, "{change:<8} {}", self.path)
+    }
+}
+
+impl Lockfile {
+    /// The extensions in `ext_dir` as they are now.
+    pub fn of(ext_dir: &Path) -> Result<Self, LockError> {
+        Ok(Self {
+            format: FORMAT,
+            locked_at: Utc::now(),
+            files: hash_files(ext_dir)?,
+        })
+    }
+
+    /// The lockfile at `path`, if there is one.
+    pub fn load(path: &Path) -> Result<Option<Self>, LockError> {
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let lock: Self = serde_json::from_slice(&bytes)?;
+        if lock.format != FORMAT {
+            return Err(LockError::Format(lock.format));
+        }
+        Ok(Some(lock))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), LockError> {
+        Ok(write_atomic(path, &serde_json::to_vec_pretty(self)?)?)
+    }
+
+    /// Files in `ext_dir` that differ from the lockfile, by path.
+    pub fn verify(&self, ext_dir: &Path) -> Result<Vec<Drift>, LockError> {
+        let current = hash_files(ext_dir)?;
+        let mut drift: Vec<Drift> = self
+            .files
+            .iter()
+            .filter_map(|(path, hash)| {
+                let change = match current.get(path) {
+                    None => Change::Removed,
+                    Some(now) if now != hash => Change::Modified,
+                    Some(_) => return None,
+                };
+                Some(Drift {
+                    path: path.clone(),
+                    change,
+                })
+            })
+            .chain(
+                current
+                    .keys()
+                    .filter(|path| !self.files.contains_key(*path))
+                    .map(|path| Drift {
+                        path: path.clone(),
+                        change: Change::Added,
+                    }),
+            )
+            .collect();
+        drift.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(drift)
+    }
+
+    /// Record `file` (inside `ext_dir`) as it is now, or forget it if it is
+    /// gone.
+    pub fn refresh(&mut self, ext_dir: &Path, file: &Path) -> Result<(), LockError> {
+        let Some(key) = file.strip_prefix(ext_dir).ok().map(lock_key) else {
+            return Ok(());
+        };
+        match fs::read(file) {
+            Ok(bytes) => {
+                self.files.insert(key, sha256_hex(&bytes));
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                self.files.remove(&key);
+            }
+            Err(e) => return Err(e.into()),
+        }
+        self.locked_at = Utc::now();
+        Ok(())
+    }
+
+    /// Record every file below `dir` (an extension inside `ext_dir`) as it
+    /// is now, forgetting those that are gone.
+    pub fn refresh_dir(&mut self, ext_dir: &Path, dir: &Path) -> Result<(), LockError> {
+        let Some(prefix) = dir.strip_prefix(ext_dir).ok().map(lock_key) else {
+            return Ok(());
+        };
+        let prefix = format!("{prefix}/");
+        self.files.retain(|path, _| !path.starts_with(&prefix));
+        self.files.extend(
+            hash_files(ext_dir)?
+                .into_iter()
+                .filter(|(path, _)| path.starts_with(&prefix)),
+        );
+        self.locked_at = Utc::now();
+        Ok(())
+    }
+}
+
+fn lock_key(rel: &Path) -> String {
+    rel.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+/// SHA-256 of every file of every plugin and theme in `ext_dir`.
+pub fn hash_files(ext_dir: &Path) -> io::Result<BTreeMap<String, String>> {
+    let mut files = BTreeMap::new();
+    for kind in ["plugins", "themes"] {
+        let root = ext_dir.join(kind);
+        if !root.is_dir() {
+            continue;
+        }
+        for entry in walkdir::WalkDir::new(&root).min_depth(2) {
+            let entry = entry.map_err(io::Error::other)?;
+            if entry.file_type().is_dir() {
+                continue;
+            }
+            if kind == "plugins"
+                && entry.depth() == 2
+                && entry.file_name() == PLUGIN_DISABLED_MARKER
+            {
+                continue;
+            }
+            let rel = entry.path().strip_prefix(ext_dir).unwrap_or(entry.path());
+            files.insert(lock_key(rel), sha256_hex(&fs::read(entry.path())?));
+        }
+    }
+    Ok(files)
+}
+
+/// The startup check: lock a site that has no lockfile yet, otherwise
+/// compare and respond as `mode` says.
+pub fn check(site_dir: &Path, ext_dir: &Path, mode: IntegrityMode) -> Result<(), EdgeError> {
+    if mode == IntegrityMode::Off {
+        return Ok(());
+    }
+    let path = site_dir.join(LOCK_FILE);
+    let Some(lock) = Lockfile::load(&path)? else {
+        let lock = Lockfile::of(ext_dir)?;
+        lock.save(&path)?;
+        info!(
+            "Locked {} extension file(s) in {}",
+            lock.files.len(),
+            path.display()
+        );
+        return Ok(());
+    };
+
+    let drift = lock.verify(ext_dir)?;
+    if drift.is_empty() {
+        return Ok(());
+    }
+    for d in &drift {
+        warn!("Extension file {d} since {}", lock.locked_at.to_rfc3339());
+    }
+    let summary = format!(
+        "{} extension file(s) differ from {}; check them, then run `whispercms verify --update`",
+        drift.len(),
+        path.display()
+    );
+    match mode {
+        IntegrityMode::Refuse => Err(EdgeError::Config(summary)),
+        _ => {
+            warn!("{summary}");
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_reports_added_modified_and_removed_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        let ext = tmp.path().join("extensions");
+        let plugin = ext.join("plugins/seo");
+        let theme = ext.join("themes/plain/templates");
+        fs::create_dir_all(&plugin).unwrap();
+        fs::create_dir_all(&theme).unwrap();
+        fs::write(plugin.join("plugin.toml"), "name = \"SEO\"\n").unwrap();
+        fs::write(plugin.join("plugin.js"), "init = () => {};").unwrap();
+        fs::write(theme.join("index.hbs"), "{{title}}").unwrap();
+
+        let lock_path = tmp.path().join(LOCK_FILE);
+        check(tmp.path(), &ext, IntegrityMode::Refuse).unwrap();
+        let lock = Lockfile::load(&lock_path).unwrap().unwrap();
+        assert_eq!(
+            lock.files.keys().collect::<Vec<_>>(),
+            [
+                "plugins/seo/plugin.js",
+                "plugins/seo/plugin.toml",
+                "themes/plain/templates/index.hbs"
+            ]
+        );
+
+        // Disabling is not tampering.
+        fs::write(plugin.join(PLUGIN_DISABLED_MARKER), "").unwrap();
+        assert!(lock.verify(&ext).unwrap().is_empty());
+
+        fs::write(plugin.join("plugin.js"), "init = () => { steal(); };").unwrap();
+        fs::remove_file(theme.join("index.hbs")).unwrap();
+        fs::write(theme.join("extra.hbs"), "").unwrap();
+        assert_eq!(
+            lock.verify(&ext)
+                .unwrap()
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            [
+                "modified plugins/seo/plugin.js",
+                "added    themes/plain/templates/extra.hbs",
+                "removed  themes/plain/templates/index.hbs",
+            ]
+        );
+        assert!(check(tmp.path(), &ext, IntegrityMode::Warn).is_ok());
+        assert!(check(tmp.path(), &ext, IntegrityMode::Refuse).is_err());
+
+        let mut lock = lock;
+        lock.refresh(&ext, &plugin.join("plugin.js")).unwrap();
+        lock.refresh(&ext, &theme.join("index.hbs")).unwrap();
+        assert_eq!(lock.verify(&ext).unwrap().len(), 1);
+    }
+}
@@ -1,5 +1,9 @@
 pub mod ext;
 pub mod filter;
+pub mod incremental;
 pub mod index;
+pub mod lock;
+pub mod reload;
 pub mod scan;
+pub mod snapshot;
 pub mod watch;
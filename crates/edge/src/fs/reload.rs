@@ -0,0 +1,418 @@
+// crates/edge/src/fs/reload.rs
+
+// Hot reload of plugins and themes.
+//
+// Watches the extensions directory and, when a manifest or entry script
+// changes, re-runs discovery and swaps the affected runtimes inside the
+// actors. Templates and assets are read from disk on every request, so edits
+// to those need no reload, only a response-cache flush. Each manifest change
+// is also recorded in the configuration history (see db::history), and
+// each reload is timed in the operations history (see db::ops). Under
+// `serve-dev` any change also reloads the open pages (see livereload).
+
+use crate::cache;
+use crate::db::ext_settings::{self, EXT_SETTINGS_DIR};
+use crate::db::history::{ConfigHistory, ExtKind, HISTORY_DIR};
+use crate::db::ops::{OpsLog, OPS_LOG_FILE};
+use crate::fs::ext::{self, ThemeBinding};
+use crate::fs::watch::{watch_folder, FolderWatchConfig};
+use crate::normalize::NormalizeRequest;
+use crate::proxy::{EdgeError, WebServerHandle};
+use crate::router::build_app_router;
+use crate::site::Site;
+use actix_web::{web, App, HttpRequest};
+use adapt::runtime::bootstrap::RuntimeHandles;
+use chrono::Utc;
+use serve::render::http::RequestContext;
+use std::collections::BTreeSet;
+use std::ffi::OsStr;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+/// Files (relative to a plugin dir) that discovery reads.
+pub(crate) const PLUGIN_FILES: &[&str] = &["plugin.toml", "plugin.js", ext::PLUGIN_DISABLED_MARKER];
+
+/// Files (relative to a theme dir) that discovery reads.
+pub(crate) const THEME_FILES: &[&str] = &["theme.toml", "theme.js"];
+
+/// Debounce window for editor save bursts.
+const DEBOUNCE_MS: u64 = 250;
+
+/// `who` for manifest saves seen by the watcher; the editor is unknown.
+const WATCHER: &str = "watcher";
+
+/// A site's running watcher's queue, for changes it cannot see itself.
+#[derive(Default)]
+pub struct ExtensionChanges(OnceLock<mpsc::Sender<PathBuf>>);
+
+/// Reload the site serving `req` as if `path`, a file in an extension's
+/// folder, had changed; for admin changes kept outside the extensions
+/// directory (stored settings, the theme picked for a mount). Does nothing
+/// without a running watcher.
+pub fn notify(req: &HttpRequest, path: &Path) {
+    let Some(tx) = req
+        .app_data::<web::Data<ExtensionChanges>>()
+        .and_then(|changes| changes.0.get())
+    else {
+        return;
+    };
+    let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    if let Err(e) = tx.try_send(path) {
+        warn!("Extension reload not queued: {}", e);
+    }
+}
+
+/// What a single filesystem change means for the running extensions.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ExtChange {
+    /// Plugins share one engine, so any plugin change reloads them all.
+    Plugins,
+    /// A single theme directory (by folder name) was added, removed or edited.
+    Theme(String),
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Classification
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Map a changed path under `ext_dir` to the reload it requires.
+///
+/// Only the extension folders themselves and the files discovery reads count;
+/// everything else (templates, assets, editor swap files) returns `None`.
+pub fn classify(ext_dir: &Path, path: &Path) -> Option<ExtChange> {
+    let rel = path.strip_prefix(ext_dir).ok()?;
+    let parts: Vec<&OsStr> = rel
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(s) => Some(s),
+            _ => None,
+        })
+        .collect();
+
+    let (kind, name, rest) = match parts.as_slice() {
+        [kind, name] => (*kind, *name, None),
+        [kind, name, file] => (*kind, *name, Some(*file)),
+        _ => return None,
+    };
+
+    let name = name.to_str()?.to_string();
+    let watched = |files: &[&str]| rest.is_none_or(|f| files.iter().any(|w| OsStr::new(w) == f));
+
+    match kind.to_str()? {
+        "plugins" if watched(PLUGIN_FILES) => Some(ExtChange::Plugins),
+        "themes" if watched(THEME_FILES) => Some(ExtChange::Theme(name)),
+        _ => None,
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Watcher
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Everything needed to rebuild runtimes and routes after a change.
+struct ExtensionReloader {
+    site: Arc<Site>,
+    root: PathBuf,
+    ext_dir: PathBuf,
+    handles: RuntimeHandles,
+    bindings: Vec<ThemeBinding>,
+    web: WebServerHandle,
+    history: Option<ConfigHistory>,
+    ops: OpsLog,
+}
+
+/// Start watching `ext_dir` for plugin and theme changes.
+///
+/// Runs on the current `LocalSet` (the web handle's reload is `!Send`) until
+/// the process exits. A failed reload is logged and the previous runtime keeps
+/// serving.
+#[tracing::instrument(skip_all)]
+pub fn watch_extensions(
+    site: Arc<Site>,
+    handles: RuntimeHandles,
+    bindings: Vec<ThemeBinding>,
+    web: WebServerHandle,
+) -> Result<(), EdgeError> {
+    let (root, ext_dir) = (site.root_dir.clone(), site.ext_dir.clone());
+    if !ext_dir.is_dir() {
+        info!(
+            "Extensions directory {:?} not found; hot reload disabled",
+            ext_dir
+        );
+        return Ok(());
+    }
+
+    // Watch the canonical path so event paths strip cleanly in `classify`.
+    let ext_dir = std::fs::canonicalize(&ext_dir)?;
+    let (tx, mut rx) = mpsc::channel::<PathBuf>(256);
+    let cfg = FolderWatchConfig {
+        recursive: true,
+        debounce_ms: DEBOUNCE_MS,
+        canonicalize_paths: false,
+    };
+    let stop = watch_folder(&ext_dir, cfg, tx.clone())?;
+    let _ = site.ext_changes.0.set(tx);
+
+    let history = match ConfigHistory::open(root.join(HISTORY_DIR)) {
+        Ok(history) => Some(history),
+        Err(e) => {
+            warn!("Configuration history disabled: {}", e);
+            None
+        }
+    };
+
+    let mut reloader = ExtensionReloader {
+        ops: OpsLog::new(root.join(OPS_LOG_FILE)),
+        site,
+        root,
+        ext_dir,
+        handles,
+        bindings,
+        web,
+        history,
+    };
+    reloader.record_startup_manifests();
+
+    info!("Watching {:?} for extension changes", reloader.ext_dir);
+
+    tokio::task::spawn_local(async move {
+        while let Some(first) = rx.recv().await {
+            // Coalesce everything already queued into one reload.
+            let mut changes = BTreeSet::new();
+            let mut next = Some(first);
+            while let Some(path) = next {
+                if let Some(change) = classify(&reloader.ext_dir, &path) {
+                    changes.insert(change);
+                }
+                next = rx.try_recv().ok();
+            }
+
+            if !changes.is_empty() {
+                reloader.apply(changes).await;
+            } else {
+                // Templates and assets need no reload, but pages rendered
+                // from the old ones may be cached, and an edited asset
+                // needs a new fingerprint.
+                reloader.site.assets.invalidate();
+                cache::invalidate(reloader.site.content.cache());
+            }
+            if let Some(live) = &reloader.site.live {
+                live.changed();
+            }
+        }
+
+        stop();
+    });
+
+    Ok(())
+}
+
+impl ExtensionReloader {
+    #[tracing::instrument(skip_all)]
+    async fn apply(&mut self, changes: BTreeSet<ExtChange>) {
+        debug!("Extension changes: {:?}", changes);
+
+        if changes.contains(&ExtChange::Plugins) {
+            let began = Utc::now();
+            let reloaded = self.reload_plugins().await;
+            if let Err(e) = self.ops.finish("plugins.reload", None, began, reloaded) {
+                warn!("Plugin reload failed, keeping previous plugins: {}", e);
+            }
+        }
+
+        let themes: BTreeSet<String> = changes
+            .into_iter()
+            .filter_map(|c| match c {
+                ExtChange::Theme(name) => Some(name),
+                ExtChange::Plugins => None,
+            })
+            .collect();
+
+        if !themes.is_empty() {
+            let began = Utc::now();
+            let reloaded = self.reload_themes(&themes).await;
+            if let Err(e) = self.ops.finish("themes.reload", None, began, reloaded) {
+                warn!("Theme reload failed, keeping previous themes: {}", e);
+            }
+        }
+
+        cache::extensions_changed(self.site.content.cache(), &self.handles);
+    }
+
+    /// Baseline history entries, so edits made while the server was down
+    /// (or before history existed) still get a version to roll back to.
+    fn record_startup_manifests(&self) {
+        match ext::discover_plugins(self.ext_dir.join("plugins/")) {
+            Ok(plugins) => {
+                for p in &plugins {
+                    self.record_manifest(ExtKind::Plugin, &p.spec.id, &p.dir);
+                }
+            }
+            Err(e) => warn!("Recording plugin manifests failed: {}", e),
+        }
+        match ext::discover_themes(self.ext_dir.join("themes/")) {
+            Ok(themes) => {
+                for t in &themes {
+                    self.record_manifest(ExtKind::Theme, &t.spec.id, &t.dir);
+                }
+            }
+            Err(e) => warn!("Recording theme manifests failed: {}", e),
+        }
+    }
+
+    /// Add the manifest in `dir` to the history if it changed.
+    fn record_manifest(&self, kind: ExtKind, id: &str, dir: &Path) {
+        let Some(history) = &self.history else {
+            return;
+        };
+
+        let recorded = std::fs::read_to_string(dir.join(kind.manifest_name()))
+            .map_err(Into::into)
+            .and_then(|manifest| history.record(kind, id, &manifest, WATCHER));
+        match recorded {
+            Ok(Some(v)) => info!("Recorded {} {} configuration v{}", kind, id, v.version),
+            Ok(None) => {}
+            Err(e) => warn!("Recording {} {} configuration failed: {}", kind, id, e),
+        }
+    }
+
+    async fn reload_plugins(&mut self) -> Result<(), EdgeError> {
+        let plugins = ext::discover_plugins(self.ext_dir.join("plugins/"))?;
+        for p in &plugins {
+            self.record_manifest(ExtKind::Plugin, &p.spec.id, &p.dir);
+        }
+        let cfgs = ext_settings::plugin_configs(&self.root.join(EXT_SETTINGS_DIR), &plugins)?;
+        self.site.flags.set_plugin_flags(&plugins);
+        self.site.plugin_routes.set(&plugins);
+        self.site.strings.set_plugin_catalogs(&plugins);
+
+        self.handles
+            .plugin_client
+            .reload(&cfgs, RequestContext::builder().build())
+            .await?;
+        self.handles.plugin_configs = cfgs;
+
+        info!(
+            "Plugins reloaded: {:?}",
+            self.handles.plugin_client.plugin_ids()
+        );
+        Ok(())
+    }
+
+    async fn reload_themes(&mut self, names: &BTreeSet<String>) -> Result<(), EdgeError> {
+        let themes = ext::discover_themes(self.ext_dir.join("themes/"))?;
+        self.site.collections.set_themes(&themes);
+        self.site.strings.set_theme_catalogs(&themes);
+
+        for theme in &themes {
+            let changed = theme
+                .dir
+                .file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| names.contains(n));
+            if !changed {
+                continue;
+            }
+            self.record_manifest(ExtKind::Theme, &theme.spec.id, &theme.dir);
+
+            let cfg = ext_settings::theme_config(&self.root.join(EXT_SETTINGS_DIR), theme)?;
+            self.handles
+                .theme_client
+                .reload_theme(cfg.clone(), RequestContext::builder().build())
+                .await?;
+
+            match self
+                .handles
+                .theme_configs
+                .iter_mut()
+                .find(|c| c.id == cfg.id)
+            {
+                Some(existing) => *existing = cfg,
+                None => self.handles.theme_configs.push(cfg),
+            }
+            info!("Theme reloaded: {}", theme.spec.id);
+        }
+
+        // Mounts only change when a theme is added, removed, or re-mounted;
+        // the router is rebuilt (on the standby port) just for those.
+        let bindings = ext_settings::theme_bindings(&self.root.join(EXT_SETTINGS_DIR), &themes);
+        if bindings != self.bindings {
+            let site = self.site.clone();
+            let handles = self.handles.clone();
+            let next = bindings.clone();
+
+            self.web
+                .hot_reload(move || {
+                    App::new().wrap(NormalizeRequest).service(build_app_router(
+                        site.clone(),
+                        handles.clone(),
+                        next.clone(),
+                    ))
+                })
+                .await?;
+
+            self.bindings = bindings;
+            info!("Theme mounts changed; router reloaded");
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ext() -> PathBuf {
+        PathBuf::from("/site/extensions")
+    }
+
+    #[test]
+    fn plugin_manifest_and_script_reload_plugins() {
+        let e = ext();
+        assert_eq!(
+            classify(&e, &e.join("plugins/seo/plugin.toml")),
+            Some(ExtChange::Plugins)
+        );
+        assert_eq!(
+            classify(&e, &e.join("plugins/seo/plugin.js")),
+            Some(ExtChange::Plugins)
+        );
+        // A plugin folder appearing or disappearing also counts.
+        assert_eq!(
+            classify(&e, &e.join("plugins/seo")),
+            Some(ExtChange::Plugins)
+        );
+    }
+
+    #[test]
+    fn theme_changes_name_the_theme_folder() {
+        let e = ext();
+        assert_eq!(
+            classify(&e, &e.join("themes/docsy/theme.js")),
+            Some(ExtChange::Theme("docsy".into()))
+        );
+        assert_eq!(
+            classify(&e, &e.join("themes/docsy/theme.toml")),
+            Some(ExtChange::Theme("docsy".into()))
+        );
+    }
+
+    #[test]
+    fn templates_assets_and_outside_paths_are_ignored() {
+        let e = ext();
+        assert_eq!(
+            classify(&e, &e.join("themes/docsy/templates/page.hbs")),
+            None
+        );
+        assert_eq!(classify(&e, &e.join("themes/docsy/assets/app.js")), None);
+        assert_eq!(classify(&e, &e.join("plugins/seo/.plugin.js.swp")), None);
+        assert_eq!(classify(&e, &e.join("plugins")), None);
+        assert_eq!(
+            classify(&e, Path::new("/elsewhere/plugins/seo/plugin.js")),
+            None
+        );
+    }
+}
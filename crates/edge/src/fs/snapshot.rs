@@ -0,0 +1,356 @@
+// crates/edge/src/fs/snapshot.rs
+
+// Warm start. On a clean shutdown the discovered plugins and themes (their
+// specs, and the theme fields bindings are built from) are written to
+// `<site>/extensions.snapshot.json` together with a fingerprint of every file
+// discovery reads:
+//
+//   { "format": 2, "core": "0.1.0", "ext_dir": "/site/extensions",
+//     "fingerprint": "9f2c…", "plugins": [..], "themes": [..] }
+//
+// The next boot hashes the same files and, when the fingerprint, the
+// extensions directory and the core version all match, uses the snapshot
+// instead of re-running discovery. Anything else (a missing, unreadable or
+// stale snapshot, an added or disabled plugin, an edited theme.toml) falls
+// back to full discovery, so a snapshot can only ever save work.
+//
+// Hashing still reads the manifests and entry scripts; what is skipped is
+// walking, parsing and validating them into specs.
+
+use crate::db::history::write_atomic;
+use crate::fs::ext::{self, DiscoveredPlugin, DiscoveredTheme};
+use crate::fs::reload::{PLUGIN_FILES, THEME_FILES};
+use crate::proxy::EdgeError;
+use adapt::js::FetchPolicy;
+use adapt::runtime::error::RuntimeError;
+use adapt::runtime::permissions::Capability;
+use adapt::runtime::plugin::PluginSpec;
+use adapt::runtime::services::ServiceContract;
+use adapt::runtime::settings::SettingsSchema;
+use adapt::runtime::theme::ThemeSpec;
+use domain::hex;
+use domain::setting::FlagSettings;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+use serve::render::TemplateLanguage;
+use serve::resolver::PluginRoute;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info};
+
+/// Snapshot location inside the site directory.
+pub const SNAPSHOT_FILE: &str = "./extensions.snapshot.json";
+
+/// Bumped whenever the layout below changes, so old snapshots are ignored.
+const FORMAT: u32 = 5;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Snapshot {
+    format: u32,
+    /// Version of the binary that wrote it.
+    core: String,
+    ext_dir: PathBuf,
+    fingerprint: String,
+    plugins: Vec<PluginEntry>,
+    themes: Vec<ThemeEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PluginEntry {
+    dir: PathBuf,
+    id: String,
+    name: String,
+    source: String,
+    provides: Vec<ServiceContract>,
+    requires: Vec<ServiceContract>,
+    permissions: BTreeSet<Capability>,
+    fetch: FetchPolicy,
+    flags: BTreeMap<String, FlagSettings>,
+    settings: SettingsSchema,
+    #[serde(default)]
+    routes: Vec<PluginRoute>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ThemeEntry {
+    dir: PathBuf,
+    id: String,
+    name: String,
+    mount_path: String,
+    source: String,
+    assets_dir: Option<PathBuf>,
+    engine: Option<TemplateLanguage>,
+    collections: BTreeMap<String, Json>,
+    settings: SettingsSchema,
+}
+
+impl From<&DiscoveredPlugin> for PluginEntry {
+    fn from(p: &DiscoveredPlugin) -> Self {
+        PluginEntry {
+            dir: p.dir.clone(),
+            id: p.spec.id.clone(),
+            name: p.spec.name.clone(),
+            source: p.spec.source.clone(),
+            provides: p.spec.provides.clone(),
+            requires: p.spec.requires.clone(),
+            permissions: p.spec.permissions.clone(),
+            fetch: p.spec.fetch.clone(),
+            flags: p.flags.clone(),
+            settings: p.spec.settings.clone(),
+            routes: p.spec.routes.clone(),
+        }
+    }
+}
+
+impl From<PluginEntry> for DiscoveredPlugin {
+    fn from(e: PluginEntry) -> Self {
+        DiscoveredPlugin {
+            dir: e.dir,
+            spec: PluginSpec {
+                id: e.id,
+                name: e.name,
+                source: e.source,
+                provides: e.provides,
+                requires: e.requires,
+                permissions: e.permissions,
+                fetch: e.fetch,
+                settings: e.settings,
+                config: Json::Null,
+                routes: e.routes,
+            },
+            flags: e.flags,
+        }
+    }
+}
+
+impl From<&DiscoveredTheme> for ThemeEntry {
+    fn from(t: &DiscoveredTheme) -> Self {
+        ThemeEntry {
+            dir: t.dir.clone(),
+            id: t.spec.id.clone(),
+            name: t.spec.name.clone(),
+            mount_path: t.mount_path.clone(),
+            source: t.spec.source.clone(),
+            assets_dir: t.assets_dir.clone(),
+            engine: t.engine,
+            collections: t.collections.clone(),
+            settings: t.spec.settings.clone(),
+        }
+    }
+}
+
+impl From<ThemeEntry> for DiscoveredTheme {
+    fn from(e: ThemeEntry) -> Self {
+        DiscoveredTheme {
+            spec: ThemeSpec::new(&e.id, &e.name, &e.mount_path, &e.source)
+                .with_settings(e.settings, Json::Null),
+            mount_path: e.mount_path,
+            dir: e.dir,
+            assets_dir: e.assets_dir,
+            engine: e.engine,
+            collections: e.collections,
+        }
+    }
+}
+
+/// Plugins and themes under `ext_dir`, from the snapshot at `path` when it
+/// is still current, else from a full discovery.
+pub fn discover_or_restore(
+    path: &Path,
+    ext_dir: &Path,
+) -> Result<(Vec<DiscoveredPlugin>, Vec<DiscoveredTheme>), RuntimeError> {
+    if let Some(found) = restore(path, ext_dir) {
+        info!(
+            "Warm start: {} plugin(s) and {} theme(s) restored from {:?}",
+            found.0.len(),
+            found.1.len(),
+            path
+        );
+        return Ok(found);
+    }
+
+    let plugins = ext::discover_plugins(ext_dir.join("plugins/"))?;
+    let themes = ext::discover_themes(ext_dir.join("themes/"))?;
+    Ok((plugins, themes))
+}
+
+/// The snapshot at `path`, if it exists and matches `ext_dir` as it is now.
+pub fn restore(
+    path: &Path,
+    ext_dir: &Path,
+) -> Option<(Vec<DiscoveredPlugin>, Vec<DiscoveredTheme>)> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+        Err(e) => {
+            debug!("Ignoring unreadable snapshot {:?}: {}", path, e);
+            return None;
+        }
+    };
+    let snapshot: Snapshot = match serde_json::from_slice(&bytes) {
+        Ok(s) => s,
+        Err(e) => {
+            debug!("Ignoring malformed snapshot {:?}: {}", path, e);
+            return None;
+        }
+    };
+
+    if snapshot.format != FORMAT
+        || snapshot.core != env!("CARGO_PKG_VERSION")
+        || snapshot.ext_dir != ext_dir
+    {
+        debug!("Snapshot {:?} is from another build or site", path);
+        return None;
+    }
+    match fingerprint(ext_dir) {
+        Ok(current) if current == snapshot.fingerprint => {}
+        Ok(_) => {
+            debug!("Extensions changed since snapshot {:?}", path);
+            return None;
+        }
+        Err(e) => {
+            debug!("Fingerprinting {:?} failed: {}", ext_dir, e);
+            return None;
+        }
+    }
+
+    Some((
+        snapshot.plugins.into_iter().map(Into::into).collect(),
+        snapshot.themes.into_iter().map(Into::into).collect(),
+    ))
+}
+
+/// Discover the extensions under `ext_dir` and write them to `path`.
+///
+/// The fingerprint is taken before discovery, so an edit that lands in
+/// between makes the snapshot stale rather than wrong.
+pub fn save(path: &Path, ext_dir: &Path) -> Result<(), EdgeError> {
+    let fingerprint = fingerprint(ext_dir)?;
+    let plugins = ext::discover_plugins(ext_dir.join("plugins/"))?;
+    let themes = ext::discover_themes(ext_dir.join("themes/"))?;
+
+    let snapshot = Snapshot {
+        format: FORMAT,
+        core: env!("CARGO_PKG_VERSION").to_owned(),
+        ext_dir: ext_dir.to_path_buf(),
+        fingerprint,
+        plugins: plugins.iter().map(PluginEntry::from).collect(),
+        themes: themes.iter().map(ThemeEntry::from).collect(),
+    };
+    let bytes = serde_json::to_vec(&snapshot).map_err(io::Error::other)?;
+    write_atomic(path, &bytes)?;
+    Ok(())
+}
+
+/// SHA-256 over every extension folder name and the contents of each file
+/// discovery reads in it, plus whether a theme has an `assets` folder.
+pub fn fingerprint(ext_dir: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+
+    for (kind, files) in [("plugins", PLUGIN_FILES), ("themes", THEME_FILES)] {
+        hasher.update(kind.as_bytes());
+
+        let mut dirs: Vec<PathBuf> = match fs::read_dir(ext_dir.join(kind)) {
+            Ok(entries) => entries
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| p.is_dir())
+                .collect(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        dirs.sort();
+
+        for dir in dirs {
+            hasher.update(b"\0dir\0");
+            hasher.update(dir.file_name().unwrap_or_default().as_encoded_bytes());
+            for file in files {
+                match fs::read(dir.join(file)) {
+                    Ok(bytes) => {
+                        hasher.update(b"\0file\0");
+                        hasher.update(file.as_bytes());
+                        hasher.update((bytes.len() as u64).to_le_bytes());
+                        hasher.update(&bytes);
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            if kind == "themes" && dir.join("assets").is_dir() {
+                hasher.update(b"\0assets\0");
+            }
+        }
+    }
+
+    Ok(hex::encode(hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn site() -> (tempfile::TempDir, PathBuf) {
+        let tmp = tempfile::tempdir().unwrap();
+        let ext = tmp.path().join("extensions");
+        let plugin = ext.join("plugins/seo");
+        let theme = ext.join("themes/plain");
+        fs::create_dir_all(&plugin).unwrap();
+        fs::create_dir_all(theme.join("assets")).unwrap();
+        fs::write(
+            plugin.join("plugin.toml"),
+            "name = \"SEO\"\npermissions = [\"db.read\"]\n[flags.beta]\nroles = [\"admin\"]\n",
+        )
+        .unwrap();
+        fs::write(plugin.join("plugin.js"), "init = () => {};").unwrap();
+        fs::write(
+            theme.join("theme.toml"),
+            "mount = \"/\"\nengine = \"tera\"\n",
+        )
+        .unwrap();
+        fs::write(theme.join("theme.js"), "handle = (ctx) => ctx;").unwrap();
+        (tmp, ext)
+    }
+
+    #[test]
+    fn a_current_snapshot_restores_what_discovery_finds() {
+        let (tmp, ext) = site();
+        let path = tmp.path().join(SNAPSHOT_FILE);
+        assert!(restore(&path, &ext).is_none());
+
+        save(&path, &ext).unwrap();
+        let (plugins, themes) = restore(&path, &ext).unwrap();
+
+        assert_eq!(plugins.len(), 1);
+        assert_eq!(plugins[0].spec.name, "SEO");
+        assert!(plugins[0].spec.permissions.contains(&Capability::DbRead));
+        assert_eq!(plugins[0].flags["beta"].roles, ["admin"]);
+        assert_eq!(themes.len(), 1);
+        assert_eq!(themes[0].spec.id, "plain");
+        assert_eq!(themes[0].engine, Some(TemplateLanguage::Tera));
+        assert!(themes[0].assets_dir.is_some());
+    }
+
+    #[test]
+    fn any_change_to_what_discovery_reads_makes_it_stale() {
+        let (tmp, ext) = site();
+        let path = tmp.path().join(SNAPSHOT_FILE);
+
+        save(&path, &ext).unwrap();
+        ext::set_plugin_enabled(&ext.join("plugins/seo"), false).unwrap();
+        assert!(restore(&path, &ext).is_none());
+
+        save(&path, &ext).unwrap();
+        fs::write(ext.join("themes/plain/theme.js"), "handle = () => null;").unwrap();
+        assert!(restore(&path, &ext).is_none());
+
+        save(&path, &ext).unwrap();
+        fs::write(ext.join("themes/plain/assets/site.css"), "body {}").unwrap();
+        assert!(restore(&path, &ext).is_some());
+        assert!(restore(&path, &tmp.path().join("elsewhere")).is_none());
+
+        fs::write(&path, "{ not json").unwrap();
+        assert!(restore(&path, &ext).is_none());
+    }
+}
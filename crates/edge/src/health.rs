@@ -0,0 +1,238 @@
+// crates/edge/src/health.rs
+
+// Probes for process supervisors (Kubernetes, systemd, load balancers):
+//
+//   GET /healthz  liveness: 200 {"status":"ok"} while the web server answers
+//   GET /readyz   readiness: 200 when every check passes, else 503
+//
+//   {"status":"unready",
+//    "checks":{"site":{"ok":true},"stores":{"ok":true},
+//              "index":{"ok":false,"error":"content index is not open"},
+//              "plugins":{"ok":true,"loaded":2},"themes":{"ok":true}}}
+//
+// `site` writes and removes a probe file in the site directory; `stores`
+// lists every store directory that exists; `index` asks whether both content
+// indexes are open; `plugins` and `themes` whether their actors still take
+// commands. The probes sit beside the router's root scope, not inside it, so
+// maintenance, quotas, canonical redirects and the audit log never see them.
+
+use crate::cli::OUTBOX_DIR;
+use crate::db::collections::COLLECTIONS_DB_DIR;
+use crate::db::comments::COMMENTS_DB_DIR;
+use crate::db::ext_settings::EXT_SETTINGS_DIR;
+use crate::db::forms::FORMS_DB_DIR;
+use crate::db::history::HISTORY_DIR;
+use crate::db::kv::PLUGIN_KV_DIR;
+use crate::db::media::MEDIA_DB_DIR;
+use crate::db::redirects::REDIRECTS_DB_DIR;
+use crate::db::revisions::REVISIONS_DIR;
+use crate::db::users::USERS_DB_DIR;
+use crate::fs::index::SiteContent;
+use actix_web::{dev::HttpServiceFactory, http::header, web, HttpResponse};
+use adapt::runtime::{PluginRuntimeClient, ThemeRuntimeClient};
+use serde_json::{json, Map, Value as Json};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Written and removed by the `site` check; `.tmp` keeps it out of backups.
+const PROBE_FILE: &str = ".readyz.tmp";
+
+const STORE_DIRS: [&str; 11] = [
+    USERS_DB_DIR,
+    COMMENTS_DB_DIR,
+    FORMS_DB_DIR,
+    MEDIA_DB_DIR,
+    REDIRECTS_DB_DIR,
+    COLLECTIONS_DB_DIR,
+    EXT_SETTINGS_DIR,
+    PLUGIN_KV_DIR,
+    HISTORY_DIR,
+    REVISIONS_DIR,
+    OUTBOX_DIR,
+];
+
+/// What `/readyz` checks.
+#[derive(Clone)]
+struct Probes {
+    root_dir: PathBuf,
+    content: Arc<SiteContent>,
+    plugins: PluginRuntimeClient,
+    themes: ThemeRuntimeClient,
+}
+
+/// The `/healthz` and `/readyz` resources, mounted ahead of the router's
+/// root scope.
+pub fn services(
+    root_dir: PathBuf,
+    content: Arc<SiteContent>,
+    plugins: PluginRuntimeClient,
+    themes: ThemeRuntimeClient,
+) -> impl HttpServiceFactory {
+    let probes = web::Data::new(Probes {
+        root_dir,
+        content,
+        plugins,
+        themes,
+    });
+    (
+        web::resource("/healthz").route(web::get().to(liveness_handler)),
+        web::resource("/readyz")
+            .app_data(probes)
+            .route(web::get().to(readiness_handler)),
+    )
+}
+
+async fn liveness_handler() -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .json(json!({ "status": "ok" }))
+}
+
+#[tracing::instrument(skip_all)]
+async fn readiness_handler(probes: web::Data<Probes>) -> HttpResponse {
+    let root_dir = probes.root_dir.clone();
+    let disk = web::block(move || {
+        (
+            writable(&root_dir).map_err(|e| e.to_string()),
+            stores_readable(&root_dir),
+        )
+    })
+    .await;
+    let (site, stores) = match disk {
+        Ok(results) => results,
+        Err(e) => (Err(e.to_string()), Err(e.to_string())),
+    };
+
+    let mut checks = Map::new();
+    checks.insert("site".into(), check(site));
+    checks.insert("stores".into(), check(stores));
+    checks.insert(
+        "index".into(),
+        check(
+            probes
+                .content
+                .index_ready()
+                .then_some(())
+                .ok_or("content index is not open"),
+        ),
+    );
+    let mut plugins = check(
+        probes
+            .plugins
+            .is_alive()
+            .then_some(())
+            .ok_or("plugin runtime has stopped"),
+    );
+    plugins["loaded"] = json!(probes.plugins.plugin_ids().len());
+    checks.insert("plugins".into(), plugins);
+    checks.insert(
+        "themes".into(),
+        check(
+            probes
+                .themes
+                .is_alive()
+                .then_some(())
+                .ok_or("theme runtime has stopped"),
+        ),
+    );
+
+    let ready = checks.values().all(|c| c["ok"] == json!(true));
+    let body = json!({
+        "status": if ready { "ready" } else { "unready" },
+        "checks": checks,
+    });
+    if ready {
+        HttpResponse::Ok()
+    } else {
+        HttpResponse::ServiceUnavailable()
+    }
+    .insert_header((header::CACHE_CONTROL, "no-store"))
+    .json(body)
+}
+
+fn check<E: ToString>(result: Result<(), E>) -> Json {
+    match result {
+        Ok(()) => json!({ "ok": true }),
+        Err(e) => json!({ "ok": false, "error": e.to_string() }),
+    }
+}
+
+/// The stores and logs write here.
+fn writable(root_dir: &Path) -> io::Result<()> {
+    let probe = root_dir.join(PROBE_FILE);
+    fs::write(&probe, b"")?;
+    fs::remove_file(&probe)
+}
+
+/// Every store directory the site has can be listed.
+fn stores_readable(root_dir: &Path) -> Result<(), String> {
+    for dir in STORE_DIRS {
+        match fs::read_dir(root_dir.join(dir)) {
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(format!("{dir}: {e}")),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, App};
+    use adapt::js::{BoaEngine, EngineEnv};
+    use adapt::runtime::PluginRuntime;
+    use tokio::task::LocalSet;
+
+    fn readyz() -> test::TestRequest {
+        test::TestRequest::get().uri("/readyz")
+    }
+
+    #[actix_web::test]
+    async fn liveness_answers_and_readiness_reports_each_check() {
+        LocalSet::new()
+            .run_until(async {
+                let dir = tempfile::tempdir().unwrap();
+                let plugins =
+                    PluginRuntimeClient::spawn(PluginRuntime::new(BoaEngine::new()).unwrap());
+                let themes = ThemeRuntimeClient::spawn(Vec::new(), EngineEnv::default());
+                let app = test::init_service(App::new().service(services(
+                    dir.path().to_path_buf(),
+                    Arc::default(),
+                    plugins.clone(),
+                    themes.clone(),
+                )))
+                .await;
+
+                let live =
+                    test::call_service(&app, test::TestRequest::get().uri("/healthz").to_request())
+                        .await;
+                assert!(live.status().is_success());
+
+                let res = test::call_service(&app, readyz().to_request()).await;
+                let status = res.status();
+                let body: Json = test::read_body_json(res).await;
+                let checks = &body["checks"];
+                assert_eq!(checks["site"]["ok"], true);
+                assert_eq!(checks["stores"]["ok"], true);
+                assert_eq!(checks["plugins"], json!({ "ok": true, "loaded": 0 }));
+                assert_eq!(checks["themes"]["ok"], true);
+                // The site's content index was never opened.
+                assert_eq!(status, 503);
+                assert_eq!(checks["index"]["ok"], false);
+                assert!(!dir.path().join(PROBE_FILE).exists());
+
+                themes.stop();
+                tokio::task::yield_now().await;
+                let res = test::call_service(&app, readyz().to_request()).await;
+                let status = res.status();
+                let body: Json = test::read_body_json(res).await;
+                assert_eq!(status, 503);
+                assert_eq!(body["status"], "unready");
+                assert_eq!(body["checks"]["themes"]["ok"], false);
+            })
+            .await;
+    }
+}
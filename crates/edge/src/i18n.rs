@@ -0,0 +1,289 @@
+// crates/edge/src/i18n.rs
+
+// Languages, from an optional `[i18n]` table:
+//
+//   [i18n]
+//   default = "en"
+//   languages = ["de", "fr"]
+//   routing = "prefix"            # or "domain"
+//   domains = { en = "example.com", de = "example.de" }
+//
+// With `prefix` routing a German page lives under `/de/…` (its file under
+// `content/de/`, or a permalink with `:lang`) and the default language has
+// no prefix. With `domain` routing `example.de/about` serves `/de/about`;
+// languages without a domain keep their prefix.
+//
+// Translations share an `i18n.canonical_id` (see serve::i18n). A reader who
+// lands on a page without having picked a language — no prefix or language
+// host, and not following a link from this site — is sent with a 302 to the
+// translation their `Accept-Language` prefers. That relies on
+// `accept-language` being in `[cache] vary`, as it is by default; the Referer
+// is not, so once a page is cached a later reader may see it instead.
+
+use crate::api::content::ContentQuery;
+use crate::fs::index::SiteContent;
+use crate::proxy::EdgeError;
+use crate::sitemap;
+use actix_web::http::header::{ACCEPT_LANGUAGE, LOCATION, REFERER, VARY};
+use actix_web::{http::Method, web, HttpRequest, HttpResponse};
+use adapt::mql::{parser::parse_filter, FindOptions};
+use domain::setting::{I18nSettings, LanguageRouting};
+use serde_json::{json, Value as Json};
+use serve::i18n::Translation;
+use tracing::warn;
+
+/// Whether `settings` hang together: a default language, and domains only
+/// for languages the site has.
+pub fn check(settings: &I18nSettings) -> Result<(), EdgeError> {
+    if settings.default.is_empty() {
+        return Err(EdgeError::Config("[i18n] default is empty".into()));
+    }
+    if let Some(lang) = settings
+        .domains
+        .keys()
+        .find(|l| **l != settings.default && !settings.languages.contains(l))
+    {
+        return Err(EdgeError::Config(format!(
+            "[i18n] domain for {lang:?}, which is not in languages"
+        )));
+    }
+    if settings.routing == LanguageRouting::Domain && settings.domains.is_empty() {
+        return Err(EdgeError::Config(
+            "[i18n] routing = \"domain\" needs domains".into(),
+        ));
+    }
+    Ok(())
+}
+
+/// The site's `[i18n]` table, app data of the scope around the theme
+/// routes; languages are off without it.
+fn settings(req: &HttpRequest) -> Option<web::Data<I18nSettings>> {
+    req.app_data::<web::Data<I18nSettings>>().cloned()
+}
+
+/// The site's default language, if it has languages.
+pub fn default_lang(req: &HttpRequest) -> Option<String> {
+    settings(req).map(|s| s.default.clone())
+}
+
+fn host(req: &HttpRequest) -> String {
+    let info = req.connection_info();
+    let host = info.host();
+    host.rsplit_once(':')
+        .filter(|(_, port)| port.chars().all(|c| c.is_ascii_digit()))
+        .map_or(host, |(name, _)| name)
+        .to_ascii_lowercase()
+}
+
+/// The language whose domain the request came to.
+fn host_lang<'s>(s: &'s I18nSettings, req: &HttpRequest) -> Option<&'s str> {
+    if s.routing != LanguageRouting::Domain {
+        return None;
+    }
+    let host = host(req);
+    s.domains
+        .iter()
+        .find(|(_, h)| h.eq_ignore_ascii_case(&host))
+        .map(|(lang, _)| lang.as_str())
+}
+
+/// The non-default language `path` starts with.
+fn prefix_lang<'s>(s: &'s I18nSettings, path: &str) -> Option<&'s str> {
+    let first = path.trim_start_matches('/').split('/').next()?;
+    s.languages
+        .iter()
+        .find(|l| **l == first && **l != s.default)
+        .map(String::as_str)
+}
+
+/// The host's language, if it has its own domain and is not the default.
+fn routed_lang(req: &HttpRequest) -> Option<(web::Data<I18nSettings>, String)> {
+    let s = settings(req)?;
+    let lang = host_lang(&s, req).filter(|l| *l != s.default)?.to_owned();
+    Some((s, lang))
+}
+
+/// The path to resolve for `path` requested on this host: with `domain`
+/// routing, `example.de/about` is `/de/about`.
+pub fn routed_path(req: &HttpRequest, path: String) -> String {
+    match routed_lang(req) {
+        Some((s, lang)) if prefix_lang(&s, &path).is_none() => format!("/{lang}{path}"),
+        _ => path,
+    }
+}
+
+/// The inverse of [`routed_path`]: what to put in a URL on this host.
+pub fn public_path(req: &HttpRequest, path: String) -> String {
+    let Some((_, lang)) = routed_lang(req) else {
+        return path;
+    };
+    match path
+        .strip_prefix('/')
+        .and_then(|p| p.strip_prefix(lang.as_str()))
+    {
+        Some("") => "/".to_owned(),
+        Some(rest) if rest.starts_with('/') => rest.to_owned(),
+        _ => path,
+    }
+}
+
+/// Whether the reader chose this page's language: the URL names one, or
+/// they came from a page on this site.
+fn picked(s: &I18nSettings, req: &HttpRequest) -> bool {
+    let by_url = match s.routing {
+        LanguageRouting::Prefix => prefix_lang(s, req.path()).is_some(),
+        LanguageRouting::Domain => host_lang(s, req).is_some(),
+    };
+    let from_here = req
+        .headers()
+        .get(REFERER)
+        .and_then(|r| r.to_str().ok())
+        .and_then(|r| r.split_once("://"))
+        .and_then(|(_, rest)| rest.split(['/', '?', '#']).next())
+        .is_some_and(|referer_host| {
+            referer_host.eq_ignore_ascii_case(req.connection_info().host())
+        });
+    by_url || from_here
+}
+
+/// The language versions of the document `meta` describes, itself included,
+/// by language; empty without `[i18n]` or a document.
+pub async fn translations(req: &HttpRequest, meta: &Json) -> Vec<Translation> {
+    let (Some(s), Some(id)) = (settings(req), meta.get("id").and_then(Json::as_str)) else {
+        return Vec::new();
+    };
+    let group = meta
+        .pointer("/i18n/canonical_id")
+        .and_then(Json::as_str)
+        .unwrap_or(id);
+    let filter = match parse_filter(&json!({
+        "$or": [{ "i18n.canonical_id": group }, { "id": group }]
+    })) {
+        Ok(f) => f,
+        Err(e) => {
+            warn!("i18n: translation query for {group}: {e}");
+            return Vec::new();
+        }
+    };
+    let q = ContentQuery {
+        filter,
+        opts: FindOptions::default(),
+    }
+    .published_only();
+    let content = SiteContent::of(req);
+    let docs = match content.query_front_matter(&q.filter, &q.opts).await {
+        Ok((_, docs, _)) => docs,
+        Err(e) => {
+            warn!("i18n: translations of {group}: {e}");
+            return Vec::new();
+        }
+    };
+
+    let rules = content.permalinks();
+    let base_url = sitemap::base_url(req);
+    let scheme = req.connection_info().scheme().to_owned();
+    let this_host = host(req);
+    let mut out: Vec<Translation> = Vec::new();
+    for doc in std::iter::once(meta).chain(&docs) {
+        let lang = doc
+            .pointer("/i18n/lang")
+            .and_then(Json::as_str)
+            .unwrap_or(&s.default);
+        if (lang != s.default && !s.languages.iter().any(|l| l == lang))
+            || out.iter().any(|t| t.lang == lang)
+        {
+            continue;
+        }
+        let Some(path) = rules.url_for(doc) else {
+            continue;
+        };
+        let domain = s
+            .domains
+            .get(lang)
+            .filter(|_| s.routing == LanguageRouting::Domain);
+        let (url, href) = match domain {
+            Some(domain) => {
+                let path = match path.strip_prefix(&format!("/{lang}")) {
+                    Some(rest) if lang != s.default && rest.is_empty() => "/".to_owned(),
+                    Some(rest) if lang != s.default && rest.starts_with('/') => rest.to_owned(),
+                    _ => path,
+                };
+                let href = format!("{scheme}://{domain}{path}");
+                let url = if domain.eq_ignore_ascii_case(&this_host) {
+                    path
+                } else {
+                    href.clone()
+                };
+                (url, href)
+            }
+            None => (path.clone(), format!("{base_url}{path}")),
+        };
+        out.push(Translation {
+            lang: lang.to_owned(),
+            url,
+            href,
+            title: doc
+                .pointer("/content/title")
+                .and_then(Json::as_str)
+                .map(str::to_owned),
+            current: doc.get("id").and_then(Json::as_str) == Some(id),
+        });
+    }
+    out.sort_by(|a, b| a.lang.cmp(&b.lang));
+    out
+}
+
+/// A 302 to the translation the reader's `Accept-Language` prefers, if they
+/// have not picked a language and it is not this page's.
+pub fn negotiate(req: &HttpRequest, translations: &[Translation]) -> Option<HttpResponse> {
+    let s = settings(req)?;
+    if !s.negotiate
+        || translations.len() < 2
+        || !matches!(*req.method(), Method::GET | Method::HEAD)
+        || picked(&s, req)
+    {
+        return None;
+    }
+    let accept = req.headers().get(ACCEPT_LANGUAGE)?.to_str().ok()?;
+    let langs: Vec<&str> = translations.iter().map(|t| t.lang.as_str()).collect();
+    let preferred = serve::i18n::negotiate(accept, &langs)?;
+    let to = translations
+        .iter()
+        .find(|t| t.lang == preferred && !t.current)?;
+    let mut location = to.url.clone();
+    if !req.query_string().is_empty() {
+        location = format!("{location}?{}", req.query_string());
+    }
+    Some(
+        HttpResponse::Found()
+            .insert_header((LOCATION, location))
+            .insert_header((VARY, "Accept-Language"))
+            .finish(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn languages_are_checked_against_their_domains() {
+        let settings = |domains: &[(&str, &str)], routing| I18nSettings {
+            default: "en".into(),
+            languages: vec!["de".into()],
+            routing,
+            domains: domains
+                .iter()
+                .map(|(l, h)| (l.to_string(), h.to_string()))
+                .collect(),
+            negotiate: true,
+        };
+        let s = settings(&[("de", "example.de")], LanguageRouting::Domain);
+        assert_eq!(prefix_lang(&s, "/de/about"), Some("de"));
+        assert_eq!(prefix_lang(&s, "/en/about"), None);
+        assert_eq!(prefix_lang(&s, "/design"), None);
+
+        assert!(check(&settings(&[("fr", "example.fr")], LanguageRouting::Domain)).is_err());
+        assert!(check(&settings(&[], LanguageRouting::Domain)).is_err());
+    }
+}
@@ -0,0 +1,328 @@
+// crates/edge/src/images.rs
+
+// Media originals and their derivatives (serve::images), mounted ahead of
+// every theme:
+//
+//   GET /media/{path}                 the original, revalidated by ETag
+//   GET /media/{path}?w=640&fmt=webp  a derivative, cached for a year
+//
+// Configured by an optional `[images]` table in settings.toml:
+//
+//   [images]
+//   media_dir = "media"
+//   cache_dir = "cache/images"
+//   widths = [320, 640, 1280]
+//   quality = 82
+//   lazy_loading = true
+//   eager_images = 1
+//   srcset = true
+//
+// Anything but a raster image (a PDF, a video, or a file an upload stored
+// as `.bin`) is sent as a download in a sandbox, with `Content-Disposition:
+// attachment` and `Content-Security-Policy: sandbox`, so a file under
+// /media cannot run script on the site's origin; every response carries
+// `X-Content-Type-Options: nosniff`.
+//
+// `w` and `h` must each be one of `widths` (320, 640 and 1280 unless set),
+// so clients cannot fill the cache directory with one derivative per pixel
+// size; an empty list turns resizing off. The same settings drive
+// `annotate_html`, which gives rendered `<img>` tags their dimensions,
+// loading hints and `srcset`.
+
+use crate::quota;
+use actix_web::{
+    dev::HttpServiceFactory,
+    http::header::{
+        CACHE_CONTROL, CONTENT_DISPOSITION, CONTENT_SECURITY_POLICY, CONTENT_TYPE, ETAG,
+        IF_NONE_MATCH, X_CONTENT_TYPE_OPTIONS,
+    },
+    web, HttpRequest, HttpResponse,
+};
+use domain::setting::ImageSettings;
+use serve::assets::{content_type, IMMUTABLE_CACHE_CONTROL, REVALIDATE_CACHE_CONTROL};
+use serve::images::{dimensions, DerivativeError, DerivativeStore, Transform};
+use serve::render::images::{annotate_images, ImagePolicy};
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{debug, error, warn};
+use walkdir::WalkDir;
+
+/// Intrinsic sizes of originals, with the mtime they were read at.
+type SizeCache = HashMap<PathBuf, (SystemTime, Option<(u32, u32)>)>;
+
+/// One site's intrinsic sizes of its originals, read as pages render.
+#[derive(Default)]
+pub struct ImageSizes(Mutex<SizeCache>);
+
+#[derive(Clone)]
+struct MediaState {
+    settings: ImageSettings,
+    media_dir: PathBuf,
+    store: DerivativeStore,
+}
+
+/// The media resource, for the root of the router.
+pub fn services(settings: ImageSettings, root_dir: PathBuf) -> impl HttpServiceFactory {
+    let state = MediaState {
+        media_dir: root_dir.join(&settings.media_dir),
+        store: DerivativeStore::new(root_dir.join(&settings.cache_dir), &settings),
+        settings,
+    };
+    web::resource("/media/{path:.*}")
+        .app_data(web::Data::new(state))
+        .route(web::get().to(media_handler))
+        .route(web::head().to(media_handler))
+}
+
+/// Delete the derivatives of every original whose `/media/...` URL path
+/// `matches` accepts; returns how many files went. Blocks while walking the
+/// media folder.
+pub fn purge(
+    root_dir: &Path,
+    settings: &ImageSettings,
+    matches: impl Fn(&str) -> bool,
+) -> std::io::Result<usize> {
+    let media_dir = root_dir.join(&settings.media_dir);
+    let store = DerivativeStore::new(root_dir.join(&settings.cache_dir), settings);
+    let mut purged = 0;
+    for entry in WalkDir::new(&media_dir).into_iter().filter_map(Result::ok) {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let Ok(rel) = entry.path().strip_prefix(&media_dir) else {
+            continue;
+        };
+        let parts: Vec<_> = rel.iter().map(|p| p.to_string_lossy()).collect();
+        if matches(&format!("/media/{}", parts.join("/"))) {
+            purged += store.purge(entry.path())?;
+        }
+    }
+    Ok(purged)
+}
+
+/// `rel` below `dir`, refusing anything that would leave it.
+fn media_path(dir: &Path, rel: &str) -> Option<PathBuf> {
+    let rel = Path::new(rel);
+    rel.components()
+        .all(|c| matches!(c, Component::Normal(_)))
+        .then(|| dir.join(rel))
+}
+
+/// Width and height of the original a `/media/...` URL serves, or `None`
+/// for other URLs, derivatives and files that are not images.
+fn intrinsic_size(sizes: &ImageSizes, media_dir: &Path, src: &str) -> Option<(u32, u32)> {
+    let rel = src.strip_prefix("/media/")?;
+    if rel.contains(['?', '#']) {
+        return None;
+    }
+    let path = media_path(media_dir, rel)?;
+    let mtime = std::fs::metadata(&path).ok()?.modified().ok()?;
+
+    let mut sizes = sizes.0.lock().ok()?;
+    if let Some((seen, size)) = sizes.get(&path) {
+        if *seen == mtime {
+            return *size;
+        }
+    }
+    let size = dimensions(&path).ok();
+    sizes.insert(path, (mtime, size));
+    size
+}
+
+/// `html` with its images annotated per the `[images]` policy in
+/// `settings`, sizes read through `sizes`; returned unchanged when the pass
+/// fails.
+pub(crate) fn annotate_html(
+    settings: &ImageSettings,
+    sizes: &ImageSizes,
+    media_dir: &Path,
+    html: Vec<u8>,
+) -> Vec<u8> {
+    let policy = ImagePolicy::from_settings(settings);
+    if policy.is_noop() {
+        return html;
+    }
+    let Ok(text) = std::str::from_utf8(&html) else {
+        return html;
+    };
+    match annotate_images(text, &policy, |src| intrinsic_size(sizes, media_dir, src)) {
+        Ok(out) => out.into_bytes(),
+        Err(e) => {
+            warn!("Annotating images failed: {}", e);
+            html
+        }
+    }
+}
+
+/// Whether `content_type` is a picture a browser only ever displays; SVG
+/// is a document that can carry script.
+fn is_raster(content_type: &str) -> bool {
+    content_type.starts_with("image/") && !content_type.starts_with("image/svg")
+}
+
+fn if_none_match(req: &HttpRequest, etag: &str) -> bool {
+    req.headers()
+        .get(IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.split(',').any(|t| t.trim() == etag || t.trim() == "*"))
+}
+
+#[tracing::instrument(skip_all, fields(path = %req.path()))]
+async fn media_handler(state: web::Data<MediaState>, req: HttpRequest) -> HttpResponse {
+    let rel = req.match_info().get("path").unwrap_or_default();
+    let Some(source) = media_path(&state.media_dir, rel).filter(|p| p.is_file()) else {
+        return HttpResponse::NotFound().finish();
+    };
+
+    let transform = match Transform::from_query(req.query_string(), &state.settings) {
+        Ok(t) => t,
+        Err(e) => return HttpResponse::BadRequest().body(e.to_string()),
+    };
+
+    let (path, content_type, etag, cache_control) = match transform {
+        None => {
+            let Ok(meta) = std::fs::metadata(&source) else {
+                return HttpResponse::NotFound().finish();
+            };
+            let mtime = meta
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_nanos())
+                .unwrap_or_default();
+            let etag = format!("\"{:x}-{:x}\"", meta.len(), mtime);
+            let content_type = content_type(&source);
+            (source, content_type, etag, REVALIDATE_CACHE_CONTROL)
+        }
+        Some(t) => {
+            let store = state.store.clone();
+            let derived = web::block(move || store.get_or_create(&source, &t)).await;
+            match derived {
+                Ok(Ok(d)) => (d.path, d.content_type, d.etag, IMMUTABLE_CACHE_CONTROL),
+                Ok(Err(DerivativeError::Image(e))) => {
+                    debug!("Cannot derive from {}: {}", rel, e);
+                    return HttpResponse::UnsupportedMediaType().finish();
+                }
+                Ok(Err(e)) => {
+                    error!("Image derivative for {} failed: {}", rel, e);
+                    return HttpResponse::InternalServerError().finish();
+                }
+                Err(e) => {
+                    error!("Image derivative for {} was cancelled: {}", rel, e);
+                    return HttpResponse::InternalServerError().finish();
+                }
+            }
+        }
+    };
+
+    if if_none_match(&req, &etag) {
+        return HttpResponse::NotModified()
+            .insert_header((ETAG, etag))
+            .insert_header((CACHE_CONTROL, cache_control))
+            .finish();
+    }
+    match tokio::fs::read(&path).await {
+        Ok(body) => {
+            if let Err(over) = quota::take_media(&req, body.len() as u64) {
+                return over;
+            }
+            let mut res = HttpResponse::Ok();
+            res.insert_header((CONTENT_TYPE, content_type))
+                .insert_header((CACHE_CONTROL, cache_control))
+                .insert_header((ETAG, etag))
+                .insert_header((X_CONTENT_TYPE_OPTIONS, "nosniff"));
+            if !is_raster(content_type) {
+                res.insert_header((CONTENT_DISPOSITION, "attachment"))
+                    .insert_header((CONTENT_SECURITY_POLICY, "sandbox"));
+            }
+            res.body(body)
+        }
+        Err(e) => {
+            error!("Reading {:?} failed: {}", path, e);
+            HttpResponse::NotFound().finish()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, test as actix_test, App};
+
+    #[test]
+    fn media_paths_stay_inside_the_media_dir() {
+        let dir = Path::new("/site/media");
+        assert_eq!(
+            media_path(dir, "a/b.png"),
+            Some(PathBuf::from("/site/media/a/b.png"))
+        );
+        assert_eq!(media_path(dir, "../settings.toml"), None);
+        assert_eq!(media_path(dir, "a/../../x"), None);
+        assert_eq!(media_path(dir, "/etc/passwd"), None);
+    }
+
+    #[actix_web::test]
+    async fn originals_revalidate_and_derivatives_are_immutable() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(root.path().join("media")).unwrap();
+        std::fs::write(root.path().join("media/notes.txt"), "hi").unwrap();
+        image::RgbImage::from_pixel(8, 8, image::Rgb([1, 2, 3]))
+            .save(root.path().join("media/dot.png"))
+            .unwrap();
+
+        let app = actix_test::init_service(App::new().service(web::scope("").service(services(
+            ImageSettings::default(),
+            root.path().to_path_buf(),
+        ))))
+        .await;
+
+        let req = actix_test::TestRequest::get()
+            .uri("/media/dot.png?w=320&fmt=jpg")
+            .to_request();
+        let res = actix_test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            *res.headers().get(CACHE_CONTROL).unwrap(),
+            IMMUTABLE_CACHE_CONTROL
+        );
+        assert_eq!(*res.headers().get(CONTENT_TYPE).unwrap(), "image/jpeg");
+
+        let req = actix_test::TestRequest::get()
+            .uri("/media/dot.png")
+            .to_request();
+        let res = actix_test::call_service(&app, req).await;
+        assert_eq!(
+            *res.headers().get(CACHE_CONTROL).unwrap(),
+            REVALIDATE_CACHE_CONTROL
+        );
+        assert_eq!(
+            *res.headers().get(X_CONTENT_TYPE_OPTIONS).unwrap(),
+            "nosniff"
+        );
+        assert!(res.headers().get(CONTENT_DISPOSITION).is_none());
+
+        let html = annotate_html(
+            &ImageSettings::default(),
+            &ImageSizes::default(),
+            &root.path().join("media"),
+            br#"<img src="/media/dot.png"><img src="/media/notes.txt">"#.to_vec(),
+        );
+        assert_eq!(
+            String::from_utf8(html).unwrap(),
+            r#"<img src="/media/dot.png" width="8" height="8" decoding="async"><img src="/media/notes.txt" loading="lazy" decoding="async">"#
+        );
+
+        for (uri, status) in [
+            ("/media/dot.png?w=abc", StatusCode::BAD_REQUEST),
+            ("/media/dot.png?w=4", StatusCode::BAD_REQUEST),
+            ("/media/notes.txt?w=320", StatusCode::UNSUPPORTED_MEDIA_TYPE),
+            ("/media/missing.png?w=320", StatusCode::NOT_FOUND),
+        ] {
+            let req = actix_test::TestRequest::get().uri(uri).to_request();
+            let res = actix_test::call_service(&app, req).await;
+            assert_eq!(res.status(), status, "{uri}");
+        }
+    }
+}
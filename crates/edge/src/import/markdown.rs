@@ -0,0 +1,442 @@
+// crates/edge/src/import/markdown.rs
+
+// Small HTML → Markdown converter for imported post bodies.
+//
+// The markdown pipeline does not pass raw HTML through, so imported bodies
+// are converted rather than embedded. This covers what post editors emit
+// (paragraphs, headings, emphasis, links, images, lists, quotes, code, rules)
+// and keeps the text of anything else. It is a tag scanner, not an HTML
+// parser: malformed markup degrades to text instead of failing the import.
+
+use quick_xml::escape::resolve_html5_entity;
+
+/// Convert an HTML fragment to Markdown.
+pub fn html_to_markdown(html: &str) -> String {
+    let mut md = Converter::default();
+    let mut rest = html;
+
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix("<!--") {
+            // Block-editor delimiters (`<!-- wp:paragraph -->`) and comments.
+            rest = after.find("-->").map_or("", |end| &after[end + 3..]);
+        } else if rest.starts_with('<') {
+            match rest.find('>') {
+                Some(end) => {
+                    md.tag(&rest[1..end]);
+                    rest = &rest[end + 1..];
+                }
+                None => {
+                    md.text(rest);
+                    rest = "";
+                }
+            }
+        } else {
+            let end = rest.find('<').unwrap_or(rest.len());
+            md.text(&rest[..end]);
+            rest = &rest[end..];
+        }
+    }
+
+    md.finish()
+}
+
+/// Decode HTML character references, leaving unknown ones as written.
+pub fn decode_entities(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+
+        let decoded = rest[1..].find(';').filter(|&n| n <= 32).and_then(|n| {
+            let name = &rest[1..n + 1];
+            let ch = match name.strip_prefix('#') {
+                Some(num) => {
+                    let code = match num.strip_prefix(['x', 'X']) {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                        None => num.parse().ok(),
+                    };
+                    code.and_then(char::from_u32).map(String::from)
+                }
+                None => resolve_html5_entity(name).map(str::to_string),
+            };
+            ch.map(|c| (c, n + 2))
+        });
+
+        match decoded {
+            Some((text, len)) => {
+                out.push_str(&text);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+enum FrameKind {
+    Root,
+    Link(String),
+    Quote,
+    Pre,
+}
+
+struct Frame {
+    kind: FrameKind,
+    buf: String,
+}
+
+struct Converter {
+    frames: Vec<Frame>,
+    /// Open lists: `None` for `<ul>`, `Some(next number)` for `<ol>`.
+    lists: Vec<Option<usize>>,
+    /// Depth inside `<script>` / `<style>`, whose text is dropped.
+    skip: usize,
+}
+
+impl Default for Converter {
+    fn default() -> Self {
+        Self {
+            frames: vec![Frame {
+                kind: FrameKind::Root,
+                buf: String::new(),
+            }],
+            lists: Vec::new(),
+            skip: 0,
+        }
+    }
+}
+
+impl Converter {
+    fn buf(&mut self) -> &mut String {
+        &mut self.frames.last_mut().expect("root frame").buf
+    }
+
+    fn in_pre(&self) -> bool {
+        self.frames.iter().any(|f| matches!(f.kind, FrameKind::Pre))
+    }
+
+    /// End the current block with a blank line (no-op at the start).
+    fn block_break(&mut self) {
+        let buf = self.buf();
+        let trimmed = buf.trim_end_matches([' ', '\t']).len();
+        buf.truncate(trimmed);
+        if buf.is_empty() || buf.ends_with("\n\n") {
+            return;
+        }
+        buf.push_str(if buf.ends_with('\n') { "\n" } else { "\n\n" });
+    }
+
+    fn line_break(&mut self) {
+        let buf = self.buf();
+        if !buf.is_empty() && !buf.ends_with('\n') {
+            buf.push('\n');
+        }
+    }
+
+    fn text(&mut self, raw: &str) {
+        if self.skip > 0 {
+            return;
+        }
+        let text = decode_entities(raw);
+
+        if self.in_pre() {
+            self.buf().push_str(&text);
+            return;
+        }
+
+        // Whitespace collapses to one space; a blank line (classic-editor
+        // paragraph) becomes a block break.
+        let mut newlines = 0;
+        let mut pending_ws = false;
+        for c in text.chars() {
+            if c.is_whitespace() && c != '\u{a0}' {
+                pending_ws = true;
+                newlines += usize::from(c == '\n');
+                continue;
+            }
+            if pending_ws {
+                self.whitespace(newlines);
+                pending_ws = false;
+                newlines = 0;
+            }
+            if matches!(c, '\\' | '*' | '_' | '`' | '[' | ']') {
+                self.buf().push('\\');
+            }
+            self.buf().push(c);
+        }
+        if pending_ws {
+            self.whitespace(newlines);
+        }
+    }
+
+    fn whitespace(&mut self, newlines: usize) {
+        if newlines >= 2 {
+            self.block_break();
+            return;
+        }
+        let buf = self.buf();
+        if !buf.is_empty() && !buf.ends_with([' ', '\n']) {
+            buf.push(' ');
+        }
+    }
+
+    fn tag(&mut self, inner: &str) {
+        let inner = inner.trim().trim_end_matches('/').trim_end();
+        let (closing, inner) = match inner.strip_prefix('/') {
+            Some(rest) => (true, rest),
+            None => (false, inner),
+        };
+        let name_end = inner
+            .find(|c: char| c.is_whitespace())
+            .unwrap_or(inner.len());
+        let name = inner[..name_end].to_ascii_lowercase();
+        let attrs = &inner[name_end..];
+
+        if matches!(name.as_str(), "script" | "style") {
+            if closing {
+                self.skip = self.skip.saturating_sub(1);
+            } else {
+                self.skip += 1;
+            }
+            return;
+        }
+        if self.skip > 0 {
+            return;
+        }
+
+        match (name.as_str(), closing) {
+            ("p" | "div" | "figure" | "figcaption" | "table" | "tr", _) => self.block_break(),
+            ("br", false) => {
+                if self.in_pre() {
+                    self.buf().push('\n');
+                } else {
+                    self.buf().push_str("\\\n");
+                }
+            }
+            ("hr", false) => {
+                self.block_break();
+                self.buf().push_str("---");
+                self.block_break();
+            }
+            (h, false) if is_heading(h) => {
+                self.block_break();
+                let level = usize::from(h.as_bytes()[1] - b'0');
+                self.buf().push_str(&"#".repeat(level));
+                self.buf().push(' ');
+            }
+            (h, true) if is_heading(h) => self.block_break(),
+            ("strong" | "b", _) => self.buf().push_str("**"),
+            ("em" | "i", _) => self.buf().push('_'),
+            ("del" | "s" | "strike", _) => self.buf().push_str("~~"),
+            ("code", _) if !self.in_pre() => self.buf().push('`'),
+            ("ul", false) => {
+                self.open_list();
+                self.lists.push(None);
+            }
+            ("ol", false) => {
+                self.open_list();
+                self.lists.push(Some(1));
+            }
+            ("ul" | "ol", true) => {
+                self.lists.pop();
+                if self.lists.is_empty() {
+                    self.block_break();
+                }
+            }
+            ("li", false) => {
+                self.line_break();
+                let depth = self.lists.len().saturating_sub(1);
+                let marker = match self.lists.last_mut() {
+                    Some(Some(n)) => {
+                        *n += 1;
+                        format!("{}. ", *n - 1)
+                    }
+                    _ => "- ".to_string(),
+                };
+                let indent = "   ".repeat(depth);
+                self.buf().push_str(&indent);
+                self.buf().push_str(&marker);
+            }
+            ("li", true) => self.line_break(),
+            ("img", false) => {
+                let alt = attr(attrs, "alt").unwrap_or_default();
+                if let Some(src) = attr(attrs, "src") {
+                    self.buf().push_str(&format!("![{alt}]({src})"));
+                }
+            }
+            ("a", false) => {
+                self.push_frame(FrameKind::Link(attr(attrs, "href").unwrap_or_default()))
+            }
+            ("a", true) => self.pop_frame(|kind, text| match kind {
+                FrameKind::Link(href) if !href.is_empty() => format!("[{}]({href})", text.trim()),
+                _ => text,
+            }),
+            ("blockquote", false) => {
+                self.block_break();
+                self.push_frame(FrameKind::Quote);
+            }
+            ("blockquote", true) => {
+                self.pop_frame(|_, text| {
+                    let quoted: Vec<String> = text
+                        .trim()
+                        .lines()
+                        .map(|l| {
+                            if l.is_empty() {
+                                ">".into()
+                            } else {
+                                format!("> {l}")
+                            }
+                        })
+                        .collect();
+                    quoted.join("\n")
+                });
+                self.block_break();
+            }
+            ("pre", false) => {
+                self.block_break();
+                self.push_frame(FrameKind::Pre);
+            }
+            ("pre", true) => {
+                self.pop_frame(|_, text| format!("```\n{}\n```", text.trim_matches('\n')));
+                self.block_break();
+            }
+            _ => {}
+        }
+    }
+
+    fn open_list(&mut self) {
+        if self.lists.is_empty() {
+            self.block_break();
+        } else {
+            self.line_break();
+        }
+    }
+
+    fn push_frame(&mut self, kind: FrameKind) {
+        self.frames.push(Frame {
+            kind,
+            buf: String::new(),
+        });
+    }
+
+    /// Close the innermost non-root frame, rendering it into its parent.
+    fn pop_frame(&mut self, render: impl FnOnce(FrameKind, String) -> String) {
+        if self.frames.len() < 2 {
+            return;
+        }
+        let frame = self.frames.pop().expect("checked above");
+        let out = render(frame.kind, frame.buf);
+        self.buf().push_str(&out);
+    }
+
+    fn finish(mut self) -> String {
+        // Unclosed links/quotes/pre keep their text.
+        while self.frames.len() > 1 {
+            self.pop_frame(|_, text| text);
+        }
+        let buf = self.buf();
+
+        let mut out = String::with_capacity(buf.len());
+        let mut blank_run = 0;
+        for line in buf.lines() {
+            let line = line.trim_end();
+            if line.is_empty() {
+                blank_run += 1;
+                if blank_run > 1 {
+                    continue;
+                }
+            } else {
+                blank_run = 0;
+            }
+            out.push_str(line);
+            out.push('\n');
+        }
+        out.trim().to_string()
+    }
+}
+
+fn is_heading(name: &str) -> bool {
+    matches!(name, "h1" | "h2" | "h3" | "h4" | "h5" | "h6")
+}
+
+/// Read a (double- or single-quoted) attribute value from a tag's attributes.
+fn attr(attrs: &str, name: &str) -> Option<String> {
+    let lower = attrs.to_ascii_lowercase();
+    let mut from = 0;
+
+    while let Some(pos) = lower[from..].find(name) {
+        let start = from + pos;
+        from = start + name.len();
+
+        let boundary = start == 0 || lower.as_bytes()[start - 1].is_ascii_whitespace();
+        let rest = attrs[from..].trim_start();
+        if !boundary || !rest.starts_with('=') {
+            continue;
+        }
+
+        let value = rest[1..].trim_start();
+        let (quote, body) = match value.chars().next() {
+            Some(q @ ('"' | '\'')) => (Some(q), &value[1..]),
+            _ => (None, value),
+        };
+        let end = match quote {
+            Some(q) => body.find(q),
+            None => body.find(char::is_whitespace),
+        }
+        .unwrap_or(body.len());
+
+        return Some(decode_entities(&body[..end]));
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn block_editor_markup_converts_to_markdown() {
+        let html = r#"<!-- wp:heading --><h2>Hello &amp; welcome</h2><!-- /wp:heading -->
+<!-- wp:paragraph --><p>Some <strong>bold</strong>, <em>italic</em> and a
+<a href="https://example.com/?a=1&amp;b=2">link</a>.</p><!-- /wp:paragraph -->
+<ul><li>one</li><li>two<ol><li>nested</li></ol></li></ul>
+<blockquote><p>Quoted</p></blockquote>
+<pre><code>let x = 1 * 2;</code></pre>
+<p><img src="/img.png" alt="A cat" /></p>"#;
+
+        let md = html_to_markdown(html);
+        assert_eq!(
+            md,
+            "## Hello & welcome\n\n\
+             Some **bold**, _italic_ and a [link](https://example.com/?a=1&b=2).\n\n\
+             - one\n\
+             - two\n   1. nested\n\n\
+             > Quoted\n\n\
+             ```\nlet x = 1 * 2;\n```\n\n\
+             ![A cat](/img.png)"
+        );
+    }
+
+    #[test]
+    fn classic_editor_text_keeps_paragraphs_and_escapes_markdown() {
+        let md =
+            html_to_markdown("First *not emphasis*\n\nSecond line&nbsp;here<script>x()</script>");
+        assert_eq!(md, "First \\*not emphasis\\*\n\nSecond line\u{a0}here");
+    }
+
+    #[test]
+    fn entities_decode_and_unknown_ones_survive() {
+        assert_eq!(
+            decode_entities("a &lt;b&gt; &#8217; &#x2014; &bogus; & c"),
+            "a <b> \u{2019} \u{2014} &bogus; & c"
+        );
+    }
+}
@@ -0,0 +1,176 @@
+// crates/edge/src/import/mod.rs
+
+// Importers that migrate content from other systems into the content
+// directory (markdown + front matter) and, optionally, an existing
+// front-matter index.
+
+pub mod markdown;
+pub mod wordpress;
+
+use adapt::mql::index::IndexRecord;
+use anyhow::Error as AnyError;
+use indexed_json::IndexedJson;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use thiserror::Error;
+use tracing::{info, warn};
+use wordpress::ImportPlan;
+
+#[derive(Debug, Error)]
+pub enum ImportError {
+    #[error("I/O: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("XML: {0}")]
+    Xml(#[from] quick_xml::Error),
+
+    #[error("XML encoding: {0}")]
+    Encoding(#[from] quick_xml::encoding::EncodingError),
+
+    #[error("XML attribute: {0}")]
+    Attr(#[from] quick_xml::events::attributes::AttrError),
+
+    #[error("Front matter: {0}")]
+    FrontMatter(#[from] toml::ser::Error),
+
+    #[error("IndexedJson: {0}")]
+    IndexedJson(#[source] AnyError),
+
+    #[error("Not a WordPress export: {0}")]
+    NotWxr(String),
+
+    #[error("Refusing to write outside the content directory: {0:?}")]
+    OutsideContent(PathBuf),
+}
+
+/// What an import run did.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ImportSummary {
+    pub written: Vec<PathBuf>,
+    /// Files that already existed and were left alone.
+    pub existing: Vec<PathBuf>,
+    /// Records appended to the index (0 without `index_dir`).
+    pub indexed: usize,
+}
+
+/// Write a plan's documents under `content_dir` and, when `index_dir` is
+/// given, append their records to that IndexedJson store.
+///
+/// Existing files are kept unless `overwrite` is set, and are not indexed,
+/// so running an import twice does not duplicate records.
+pub async fn apply_plan(
+    plan: &ImportPlan,
+    content_dir: &Path,
+    index_dir: Option<&Path>,
+    overwrite: bool,
+) -> Result<ImportSummary, ImportError> {
+    // Plans come from foreign exports: check every path before writing any.
+    if let Some(doc) = plan.docs.iter().find(|d| {
+        d.rel_path.as_os_str().is_empty()
+            || !d
+                .rel_path
+                .components()
+                .all(|c| matches!(c, Component::Normal(_)))
+    }) {
+        return Err(ImportError::OutsideContent(doc.rel_path.clone()));
+    }
+
+    let mut summary = ImportSummary::default();
+    let mut records: Vec<&IndexRecord> = Vec::new();
+
+    for doc in &plan.docs {
+        let path = content_dir.join(&doc.rel_path);
+        if path.exists() && !overwrite {
+            warn!("Skipping existing {:?}", path);
+            summary.existing.push(path);
+            continue;
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, doc.to_file_string()?)?;
+        summary.written.push(path);
+        records.push(&doc.record);
+    }
+
+    if let Some(index_dir) = index_dir {
+        let mut db = IndexedJson::<IndexRecord>::open(index_dir)
+            .await
+            .map_err(ImportError::IndexedJson)?;
+        for record in &records {
+            db.append(record).await.map_err(ImportError::IndexedJson)?;
+        }
+        db.flush().await.map_err(ImportError::IndexedJson)?;
+        summary.indexed = records.len();
+    }
+
+    for (what, why) in &plan.skipped {
+        info!("Skipped {}: {}", what, why);
+    }
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wordpress::{convert, WxrExport, WxrItem};
+
+    fn export() -> WxrExport {
+        WxrExport {
+            items: vec![WxrItem {
+                id: 1,
+                title: "Hello".into(),
+                post_name: "hello".into(),
+                post_type: "post".into(),
+                status: "publish".into(),
+                content: "<p>Body</p>".into(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn apply_writes_files_indexes_them_and_is_rerunnable() {
+        let dir = tempfile::tempdir().unwrap();
+        let content = dir.path().join("content");
+        let index = dir.path().join("index");
+        let plan = convert(&export());
+
+        let first = apply_plan(&plan, &content, Some(&index), false)
+            .await
+            .unwrap();
+        assert_eq!(first.written, vec![content.join("posts/hello.md")]);
+        assert_eq!(first.indexed, 1);
+        assert!(fs::read_to_string(content.join("posts/hello.md"))
+            .unwrap()
+            .ends_with("Body\n"));
+
+        let mut db = IndexedJson::<IndexRecord>::open(&index).await.unwrap();
+        let (_, rec) = db.get(db.first().unwrap()).await.unwrap().unwrap();
+        assert_eq!(rec.id, "/posts/hello.html");
+        drop(db); // the store holds an exclusive lock
+
+        let second = apply_plan(&plan, &content, Some(&index), false)
+            .await
+            .unwrap();
+        assert!(second.written.is_empty());
+        assert_eq!(second.existing.len(), 1);
+        assert_eq!(second.indexed, 0);
+    }
+
+    #[tokio::test]
+    async fn apply_refuses_paths_outside_the_content_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let content = dir.path().join("content");
+        let mut plan = convert(&export());
+        plan.docs[0].rel_path = PathBuf::from("../escaped.md");
+
+        let err = apply_plan(&plan, &content, None, false).await.unwrap_err();
+        assert!(matches!(err, ImportError::OutsideContent(_)), "got {err}");
+        assert!(!dir.path().join("escaped.md").exists());
+    }
+}
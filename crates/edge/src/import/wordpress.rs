@@ -0,0 +1,593 @@
+// crates/edge/src/import/wordpress.rs
+
+// WordPress eXtended RSS (WXR) import.
+//
+// `parse_wxr` reads the export into plain structs; `convert` turns posts and
+// pages into markdown files with TOML front matter in the same shape the
+// indexer projects into `IndexRecord` (type, slug, parent, content.title,
+// content.summary, publish.*, nav.menu_order, tax.*, author.author). Attachments, menu items,
+// revisions, trashed and auto-draft entries are skipped and reported.
+
+use super::markdown::html_to_markdown;
+use super::ImportError;
+use adapt::mql::index::IndexRecord;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use serde_json::{json, Map, Value as Json};
+use serve::render::helpers::slugify;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+/// An author from the export's `<wp:author>` list.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WxrAuthor {
+    pub login: String,
+    pub display_name: String,
+}
+
+/// A category or tag on an item: (display name, slug).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WxrTerm {
+    pub name: String,
+    pub slug: String,
+}
+
+/// One `<item>`: a post, page, attachment, menu item, …
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WxrItem {
+    pub id: u64,
+    pub title: String,
+    pub link: String,
+    pub pub_date: String,
+    pub creator: String,
+    pub content: String,
+    pub excerpt: String,
+    pub post_name: String,
+    pub post_type: String,
+    pub status: String,
+    pub parent_id: u64,
+    pub menu_order: i64,
+    pub date_gmt: String,
+    pub modified_gmt: String,
+    pub categories: Vec<WxrTerm>,
+    pub tags: Vec<WxrTerm>,
+}
+
+/// A parsed WXR export.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WxrExport {
+    pub title: String,
+    pub authors: Vec<WxrAuthor>,
+    pub items: Vec<WxrItem>,
+}
+
+/// A converted post or page, ready to be written and indexed.
+#[derive(Debug, Clone)]
+pub struct ImportedDoc {
+    /// Path relative to the content directory, e.g. `posts/hello-world.md`.
+    pub rel_path: PathBuf,
+    pub front_matter: Json,
+    pub body: String,
+    /// Index projection, keyed by the served path (`/posts/hello-world.html`).
+    pub record: IndexRecord,
+}
+
+impl ImportedDoc {
+    /// The file contents: `+++` TOML front matter followed by the body.
+    pub fn to_file_string(&self) -> Result<String, ImportError> {
+        Ok(serve::front_matter::to_toml_file(
+            &self.front_matter,
+            &self.body,
+        )?)
+    }
+
+    /// The path WordPress served this document at, when a redirect to the
+    /// new one is needed: not for plain `/?p=12` links, which only a
+    /// query tells apart, nor when the paths match.
+    pub fn legacy_path(&self) -> Option<String> {
+        let link = self.front_matter["wordpress"]["link"].as_str()?;
+        let after_scheme = link.split_once("://").map_or(link, |(_, rest)| rest);
+        let path = &after_scheme[after_scheme.find('/')?..];
+        let path = path.split(['?', '#']).next().unwrap_or_default();
+        (path != "/" && path != self.record.id).then(|| path.to_owned())
+    }
+}
+
+/// Result of converting an export.
+#[derive(Debug, Clone, Default)]
+pub struct ImportPlan {
+    pub docs: Vec<ImportedDoc>,
+    /// Items not imported: (title or id, reason).
+    pub skipped: Vec<(String, String)>,
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Parsing
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Parse a WXR document.
+pub fn parse_wxr(xml: &str) -> Result<WxrExport, ImportError> {
+    let mut reader = Reader::from_str(xml);
+    let mut export = WxrExport::default();
+    let mut saw_channel = false;
+
+    // Element names from the root down; text accumulates for the innermost.
+    let mut path: Vec<String> = Vec::new();
+    let mut text = String::new();
+    let mut item: Option<WxrItem> = None;
+    let mut author: Option<WxrAuthor> = None;
+    let mut term: Option<(String, String)> = None; // (domain, nicename)
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => {
+                let name = qname(&e);
+                text.clear();
+                match name.as_str() {
+                    "channel" => saw_channel = true,
+                    "item" => item = Some(WxrItem::default()),
+                    "wp:author" if item.is_none() => author = Some(WxrAuthor::default()),
+                    "category" if item.is_some() => {
+                        term = Some((attr(&e, "domain")?, attr(&e, "nicename")?));
+                    }
+                    _ => {}
+                }
+                path.push(name);
+            }
+            Event::End(_) => {
+                let name = path.pop().unwrap_or_default();
+                let value = std::mem::take(&mut text);
+
+                if let Some(it) = item.as_mut() {
+                    match name.as_str() {
+                        "item" => export.items.extend(item.take()),
+                        "title" => it.title = value.trim().to_string(),
+                        "link" => it.link = value.trim().to_string(),
+                        "pubDate" => it.pub_date = value.trim().to_string(),
+                        "dc:creator" => it.creator = value.trim().to_string(),
+                        "content:encoded" => it.content = value,
+                        "excerpt:encoded" => it.excerpt = value,
+                        "wp:post_id" => it.id = value.trim().parse().unwrap_or_default(),
+                        "wp:post_name" => it.post_name = value.trim().to_string(),
+                        "wp:post_type" => it.post_type = value.trim().to_string(),
+                        "wp:status" => it.status = value.trim().to_string(),
+                        "wp:post_parent" => it.parent_id = value.trim().parse().unwrap_or_default(),
+                        "wp:menu_order" => it.menu_order = value.trim().parse().unwrap_or_default(),
+                        "wp:post_date_gmt" => it.date_gmt = value.trim().to_string(),
+                        "wp:post_modified_gmt" => it.modified_gmt = value.trim().to_string(),
+                        "category" => {
+                            if let Some((domain, slug)) = term.take() {
+                                let t = WxrTerm {
+                                    name: value.trim().to_string(),
+                                    slug,
+                                };
+                                match domain.as_str() {
+                                    "category" => it.categories.push(t),
+                                    "post_tag" => it.tags.push(t),
+                                    _ => {}
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                } else if let Some(a) = author.as_mut() {
+                    match name.as_str() {
+                        "wp:author" => export.authors.extend(author.take()),
+                        "wp:author_login" => a.login = value.trim().to_string(),
+                        "wp:author_display_name" => a.display_name = value.trim().to_string(),
+                        _ => {}
+                    }
+                } else if name == "title" && path.last().is_some_and(|p| p == "channel") {
+                    export.title = value.trim().to_string();
+                }
+            }
+            Event::Text(t) => text.push_str(&t.xml_content()?),
+            Event::CData(c) => text.push_str(&c.decode()?),
+            Event::GeneralRef(r) => match r.resolve_char_ref()? {
+                Some(ch) => text.push(ch),
+                None => {
+                    let name = r.decode()?;
+                    match quick_xml::escape::resolve_predefined_entity(&name) {
+                        Some(s) => text.push_str(s),
+                        None => {
+                            text.push('&');
+                            text.push_str(&name);
+                            text.push(';');
+                        }
+                    }
+                }
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    if !saw_channel {
+        return Err(ImportError::NotWxr("no <channel> element".into()));
+    }
+    Ok(export)
+}
+
+fn qname(e: &BytesStart<'_>) -> String {
+    String::from_utf8_lossy(e.name().as_ref()).into_owned()
+}
+
+fn attr(e: &BytesStart<'_>, name: &str) -> Result<String, ImportError> {
+    Ok(match e.try_get_attribute(name)? {
+        Some(a) => a.unescape_value()?.into_owned(),
+        None => String::new(),
+    })
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Conversion
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Map WordPress statuses onto ours; `None` means "do not import".
+fn map_status(status: &str) -> Option<&'static str> {
+    match status {
+        "publish" => Some("published"),
+        "draft" => Some("draft"),
+        "pending" => Some("pending"),
+        "private" => Some("private"),
+        "future" => Some("scheduled"),
+        _ => None,
+    }
+}
+
+/// `2020-01-02 03:04:05` (GMT) → RFC3339; WordPress writes zeros for "unset".
+fn gmt_to_rfc3339(s: &str) -> Option<String> {
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S")
+        .ok()
+        .map(|n| n.and_utc().to_rfc3339())
+}
+
+fn pub_date_to_rfc3339(s: &str) -> Option<String> {
+    DateTime::parse_from_rfc2822(s)
+        .ok()
+        .map(|d| d.with_timezone(&Utc).to_rfc3339())
+}
+
+/// `post_name` as a file name: kept when it is a plain WordPress slug
+/// (percent-encoding included), slugified otherwise, so nothing in it can
+/// step out of the content directory.
+fn file_slug(post_name: &str) -> String {
+    let plain = post_name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '%'));
+    if plain {
+        post_name.to_string()
+    } else {
+        slugify(post_name)
+    }
+}
+
+/// Convert posts and pages into importable documents.
+///
+/// Posts go under `posts/<slug>.md`; pages nest under their parent page's
+/// path (`about/team.md`). Colliding slugs get the WordPress id appended.
+pub fn convert(export: &WxrExport) -> ImportPlan {
+    let mut plan = ImportPlan::default();
+
+    let authors: HashMap<&str, &str> = export
+        .authors
+        .iter()
+        .map(|a| (a.login.as_str(), a.display_name.as_str()))
+        .collect();
+
+    let mut importable = Vec::new();
+    for item in &export.items {
+        let label = if item.title.is_empty() {
+            format!("#{}", item.id)
+        } else {
+            item.title.clone()
+        };
+
+        if !matches!(item.post_type.as_str(), "post" | "page") {
+            plan.skipped
+                .push((label, format!("unsupported post type '{}'", item.post_type)));
+        } else if map_status(&item.status).is_none() {
+            plan.skipped
+                .push((label, format!("status '{}' is not imported", item.status)));
+        } else {
+            importable.push(item);
+        }
+    }
+
+    // Pick a unique slug per item (drafts often have no post_name).
+    let mut slugs: HashMap<u64, String> = HashMap::new();
+    let mut taken: HashSet<(String, u64, String)> = HashSet::new();
+    for item in &importable {
+        let mut slug = if item.post_name.is_empty() {
+            slugify(&item.title)
+        } else {
+            file_slug(&item.post_name)
+        };
+        if slug.is_empty() {
+            slug = format!("{}-{}", item.post_type, item.id);
+        }
+
+        let parent = if item.post_type == "page" {
+            item.parent_id
+        } else {
+            0
+        };
+        if !taken.insert((item.post_type.clone(), parent, slug.clone())) {
+            slug = format!("{slug}-{}", item.id);
+        }
+        slugs.insert(item.id, slug);
+    }
+
+    let pages: HashMap<u64, &WxrItem> = importable
+        .iter()
+        .filter(|i| i.post_type == "page")
+        .map(|i| (i.id, *i))
+        .collect();
+
+    for item in importable {
+        let rel_stem = if item.post_type == "post" {
+            PathBuf::from("posts").join(&slugs[&item.id])
+        } else {
+            page_stem(item, &pages, &slugs)
+        };
+        let rel_path = rel_stem.with_extension("md");
+        let served_id = format!("/{}", rel_stem.with_extension("html").to_string_lossy());
+
+        let mut fm = Map::new();
+        fm.insert("type".into(), json!(item.post_type));
+        fm.insert("slug".into(), json!(slugs[&item.id]));
+
+        if let Some(parent) = pages.get(&item.parent_id) {
+            let parent_stem = page_stem(parent, &pages, &slugs);
+            fm.insert(
+                "parent".into(),
+                json!(format!(
+                    "/{}",
+                    parent_stem.with_extension("html").to_string_lossy()
+                )),
+            );
+        }
+
+        let mut content = Map::new();
+        content.insert("title".into(), json!(item.title));
+        let excerpt = html_to_markdown(&item.excerpt);
+        if !excerpt.is_empty() {
+            content.insert("summary".into(), json!(excerpt));
+        }
+        fm.insert("content".into(), Json::Object(content));
+
+        let mut publish = Map::new();
+        publish.insert("status".into(), json!(map_status(&item.status)));
+        if let Some(date) =
+            gmt_to_rfc3339(&item.date_gmt).or_else(|| pub_date_to_rfc3339(&item.pub_date))
+        {
+            publish.insert("date".into(), json!(date));
+        }
+        if let Some(modified) = gmt_to_rfc3339(&item.modified_gmt) {
+            publish.insert("modified".into(), json!(modified));
+        }
+        fm.insert("publish".into(), Json::Object(publish));
+
+        if item.post_type == "page" {
+            fm.insert("nav".into(), json!({ "menu_order": item.menu_order }));
+        }
+
+        let terms = |ts: &[WxrTerm]| ts.iter().map(|t| t.slug.clone()).collect::<Vec<_>>();
+        fm.insert(
+            "tax".into(),
+            json!({ "categories": terms(&item.categories), "tags": terms(&item.tags) }),
+        );
+
+        if !item.creator.is_empty() {
+            let name = authors
+                .get(item.creator.as_str())
+                .copied()
+                .filter(|n| !n.is_empty())
+                .unwrap_or(&item.creator);
+            fm.insert("author".into(), json!({ "author": name }));
+        }
+
+        fm.insert(
+            "wordpress".into(),
+            json!({ "id": item.id, "link": item.link }),
+        );
+
+        let front_matter = Json::Object(fm);
+        let record = IndexRecord::from_json_with_id(served_id, &front_matter);
+
+        plan.docs.push(ImportedDoc {
+            rel_path,
+            front_matter,
+            body: html_to_markdown(&item.content),
+            record,
+        });
+    }
+
+    plan
+}
+
+/// A page's path stem (no extension), nested under its ancestors.
+fn page_stem(
+    item: &WxrItem,
+    pages: &HashMap<u64, &WxrItem>,
+    slugs: &HashMap<u64, String>,
+) -> PathBuf {
+    let mut parts = vec![slugs[&item.id].clone()];
+    let mut seen = HashSet::from([item.id]);
+    let mut parent = item.parent_id;
+
+    // `seen` guards against parent cycles in hand-edited exports.
+    while let Some(p) = pages.get(&parent).filter(|p| seen.insert(p.id)) {
+        parts.push(slugs[&p.id].clone());
+        parent = p.parent_id;
+    }
+
+    parts.iter().rev().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WXR: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0"
+    xmlns:content="http://purl.org/rss/1.0/modules/content/"
+    xmlns:excerpt="http://wordpress.org/export/1.2/excerpt/"
+    xmlns:dc="http://purl.org/dc/elements/1.1/"
+    xmlns:wp="http://wordpress.org/export/1.2/">
+<channel>
+    <title>My Blog</title>
+    <wp:author>
+        <wp:author_login><![CDATA[jdoe]]></wp:author_login>
+        <wp:author_display_name><![CDATA[Jane Doe]]></wp:author_display_name>
+    </wp:author>
+    <item>
+        <title>Hello &amp; World</title>
+        <link>https://blog.example/2020/01/hello-world/</link>
+        <pubDate>Thu, 02 Jan 2020 03:04:05 +0000</pubDate>
+        <dc:creator><![CDATA[jdoe]]></dc:creator>
+        <content:encoded><![CDATA[<p>Hi <em>there</em></p>]]></content:encoded>
+        <excerpt:encoded><![CDATA[]]></excerpt:encoded>
+        <wp:post_id>10</wp:post_id>
+        <wp:post_date_gmt><![CDATA[2020-01-02 03:04:05]]></wp:post_date_gmt>
+        <wp:post_modified_gmt><![CDATA[2020-02-01 00:00:00]]></wp:post_modified_gmt>
+        <wp:post_name><![CDATA[hello-world]]></wp:post_name>
+        <wp:status><![CDATA[publish]]></wp:status>
+        <wp:post_parent>0</wp:post_parent>
+        <wp:menu_order>0</wp:menu_order>
+        <wp:post_type><![CDATA[post]]></wp:post_type>
+        <category domain="category" nicename="news"><![CDATA[News]]></category>
+        <category domain="post_tag" nicename="rust"><![CDATA[Rust]]></category>
+    </item>
+    <item>
+        <title>About</title>
+        <wp:post_id>2</wp:post_id>
+        <wp:post_name>about</wp:post_name>
+        <wp:status>publish</wp:status>
+        <wp:post_parent>0</wp:post_parent>
+        <wp:menu_order>1</wp:menu_order>
+        <wp:post_type>page</wp:post_type>
+        <content:encoded><![CDATA[About us]]></content:encoded>
+    </item>
+    <item>
+        <title>Our Team</title>
+        <wp:post_id>3</wp:post_id>
+        <wp:post_name></wp:post_name>
+        <wp:status>draft</wp:status>
+        <wp:post_date_gmt>0000-00-00 00:00:00</wp:post_date_gmt>
+        <wp:post_parent>2</wp:post_parent>
+        <wp:menu_order>2</wp:menu_order>
+        <wp:post_type>page</wp:post_type>
+    </item>
+    <item>
+        <title>logo.png</title>
+        <wp:post_id>4</wp:post_id>
+        <wp:status>inherit</wp:status>
+        <wp:post_type>attachment</wp:post_type>
+    </item>
+</channel>
+</rss>"#;
+
+    #[test]
+    fn parses_channel_authors_items_and_terms() {
+        let export = parse_wxr(WXR).unwrap();
+        assert_eq!(export.title, "My Blog");
+        assert_eq!(
+            export.authors,
+            vec![WxrAuthor {
+                login: "jdoe".into(),
+                display_name: "Jane Doe".into()
+            }]
+        );
+        assert_eq!(export.items.len(), 4);
+
+        let post = &export.items[0];
+        assert_eq!(post.title, "Hello & World");
+        assert_eq!(post.content, "<p>Hi <em>there</em></p>");
+        assert_eq!(post.categories[0].slug, "news");
+        assert_eq!(post.tags[0].name, "Rust");
+    }
+
+    #[test]
+    fn converts_posts_and_nested_pages_into_index_records() {
+        let plan = convert(&parse_wxr(WXR).unwrap());
+
+        assert_eq!(plan.skipped.len(), 1);
+        assert!(plan.skipped[0].1.contains("attachment"));
+
+        let paths: Vec<_> = plan.docs.iter().map(|d| d.rel_path.clone()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                PathBuf::from("posts/hello-world.md"),
+                PathBuf::from("about.md"),
+                PathBuf::from("about/our-team.md"),
+            ]
+        );
+
+        let post = &plan.docs[0];
+        assert_eq!(post.body, "Hi _there_");
+        assert_eq!(post.record.id, "/posts/hello-world.html");
+        assert_eq!(post.legacy_path().as_deref(), Some("/2020/01/hello-world/"));
+        assert_eq!(post.record.kind.as_deref(), Some("post"));
+        assert_eq!(post.record.content.title.as_deref(), Some("Hello & World"));
+        assert_eq!(post.record.publish.status.as_deref(), Some("published"));
+        assert_eq!(
+            post.record.publish.date.as_deref(),
+            Some("2020-01-02T03:04:05+00:00")
+        );
+        assert_eq!(post.record.tax.categories, vec!["news"]);
+        assert_eq!(post.record.tax.tags, vec!["rust"]);
+        assert_eq!(post.record.author.author.as_deref(), Some("Jane Doe"));
+
+        let team = &plan.docs[2];
+        assert_eq!(team.record.parent.as_deref(), Some("/about.html"));
+        assert_eq!(team.record.publish.status.as_deref(), Some("draft"));
+        assert_eq!(team.record.publish.date, None);
+        assert_eq!(team.record.nav.menu_order, Some(2));
+    }
+
+    #[test]
+    fn hostile_post_names_stay_inside_the_content_directory() {
+        let mut export = parse_wxr(WXR).unwrap();
+        export.items[0].post_name = "../../../etc/cron.d/evil".into();
+        export.items[1].post_name = "/abs".into();
+
+        let plan = convert(&export);
+        let paths: Vec<_> = plan.docs.iter().map(|d| d.rel_path.clone()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                PathBuf::from("posts/etc-cron-d-evil.md"),
+                PathBuf::from("abs.md"),
+                PathBuf::from("abs/our-team.md"),
+            ]
+        );
+    }
+
+    #[test]
+    fn file_output_round_trips_through_toml_front_matter() {
+        let plan = convert(&parse_wxr(WXR).unwrap());
+        let file = plan.docs[0].to_file_string().unwrap();
+
+        let fm = file
+            .strip_prefix("+++\n")
+            .and_then(|rest| rest.split_once("+++\n"))
+            .map(|(fm, _)| fm)
+            .unwrap();
+        let parsed: Json =
+            serde_json::to_value(toml::from_str::<toml::Value>(fm).unwrap()).unwrap();
+        assert_eq!(parsed["slug"], "hello-world");
+        assert_eq!(parsed["wordpress"]["id"], 10);
+        assert!(file.ends_with("\n\nHi _there_\n"));
+    }
+
+    #[test]
+    fn non_wxr_input_is_rejected() {
+        assert!(matches!(
+            parse_wxr("<html><body/></html>"),
+            Err(ImportError::NotWxr(_))
+        ));
+    }
+}
@@ -0,0 +1,746 @@
+// crates/edge/src/init.rs
+
+// Headless setup of a new site, for `whispercms init`:
+//
+//   WHISPERCMS_PASSWORD=… whispercms init ./site --admin alice \
+//       --site-name "Field Notes" --lang en --base-url https://notes.example
+//
+// or with the answers in a TOML file, which the flags override:
+//
+//   # answers.toml
+//   site_name = "Field Notes"
+//   lang = "en"
+//   base_url = "https://notes.example"
+//   admin = "alice"
+//   tls = "acme"                # off (the default), manual or acme
+//
+// The admin password never comes from the file: it is read from
+// `WHISPERCMS_PASSWORD`, or from the first line of stdin with
+// `--password-stdin`. The steps run in order and each can run again, so a
+// run that failed part-way is finished by running it again:
+//
+//   settings    write settings.toml; an existing one is kept unless --force
+//   directories create the content and certificate directories
+//   admin       create the admin account; an existing one is kept. With
+//               `admin_email` and a `[mail]` table, a welcome mail naming
+//               the sign-in page is queued in the outbox
+//   migrations  apply pending migrations (crate::db::migrate)
+//
+// Each step's outcome is kept in `install_status.json` in the site, and a
+// run after one that stopped part-way resumes at the step that did not
+// finish. The status, step names and states only, is served for an
+// installer to poll:
+//
+//   GET /install/status
+//       { state: "not_started" | "partial" | "complete",
+//         steps: [{ step: "settings", status: "done" }, ..] }
+//
+// with each status one of pending, done, kept or failed. A site set up
+// before the status was kept reports not_started until `init` runs again.
+
+use crate::cli::OUTBOX_DIR;
+use crate::db::migrate::{MigrateError, Migrator};
+use crate::db::outbox::{Delivery, Outbox, OutboxError};
+use crate::db::users::{Role, UserError, UserStore, ValidatedPassword, USERS_DB_DIR};
+use actix_web::{dev::HttpServiceFactory, http::Uri, web, HttpResponse};
+use domain::setting::{Settings, TlsMode};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use tracing::error;
+
+/// Written by the `settings` step.
+pub const SETTINGS_FILE: &str = "settings.toml";
+
+/// Where `install` keeps how far it got.
+pub const INSTALL_STATUS_FILE: &str = "install_status.json";
+
+const CONTENT_DIR: &str = "./content/";
+const CERT_DIR: &str = "./certs/";
+
+#[derive(Debug, Error)]
+pub enum InitError {
+    #[error("I/O: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("answers: {0}")]
+    Answers(String),
+
+    #[error("no password for the new admin {0}; set WHISPERCMS_PASSWORD or use --password-stdin")]
+    NoPassword(String),
+
+    #[error("users: {0}")]
+    Users(#[from] UserError),
+
+    #[error("migrations: {0}")]
+    Migrate(#[from] MigrateError),
+
+    #[error("outbox: {0}")]
+    Outbox(#[from] OutboxError),
+}
+
+/// What a site is set up with; from an answers file, then flags.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Answers {
+    /// Feed title
+    pub site_name: Option<String>,
+    /// Language of documents without one, e.g. `"en"`
+    pub lang: Option<String>,
+    /// Public URL, e.g. `https://example.com`; its host is the ACME domain
+    pub base_url: Option<String>,
+    /// Username of the first admin
+    pub admin: Option<String>,
+    /// Their address for reset codes, and the ACME contact
+    pub admin_email: Option<String>,
+    pub tls: Option<TlsMode>,
+    pub http_port: Option<u16>,
+    pub https_port: Option<u16>,
+}
+
+impl Answers {
+    /// The answers in the TOML file at `path`.
+    pub fn read(path: &Path) -> Result<Self, InitError> {
+        let src = fs::read_to_string(path)?;
+        toml::from_str(&src).map_err(|e| InitError::Answers(format!("{}: {e}", path.display())))
+    }
+
+    /// These answers with every one `over` gives replaced.
+    pub fn or(self, over: Answers) -> Self {
+        Self {
+            site_name: over.site_name.or(self.site_name),
+            lang: over.lang.or(self.lang),
+            base_url: over.base_url.or(self.base_url),
+            admin: over.admin.or(self.admin),
+            admin_email: over.admin_email.or(self.admin_email),
+            tls: over.tls.or(self.tls),
+            http_port: over.http_port.or(self.http_port),
+            https_port: over.https_port.or(self.https_port),
+        }
+    }
+
+    /// `settings.toml` for these answers.
+    pub fn settings_toml(&self) -> Result<String, InitError> {
+        let quote = |s: &str| toml::Value::String(s.to_owned()).to_string();
+        let tls = self.tls.unwrap_or(TlsMode::Off);
+        let mut out = String::from("# Written by `whispercms init`.\n\n[cert]\n");
+        out += &format!("dir = {}\n", quote(CERT_DIR));
+        out += &format!(
+            "mode = \"{}\"\n",
+            match tls {
+                TlsMode::Off => "off",
+                TlsMode::Manual => "manual",
+                TlsMode::Acme => "acme",
+            }
+        );
+        if tls == TlsMode::Acme {
+            let host = self
+                .base_url
+                .as_deref()
+                .and_then(|u| u.parse::<Uri>().ok())
+                .and_then(|u| u.host().map(str::to_owned))
+                .ok_or_else(|| {
+                    InitError::Answers("tls = \"acme\" needs a base_url with a host".into())
+                })?;
+            out += &format!("\n[cert.acme]\ndomains = [{}]\n", quote(&host));
+            if let Some(email) = &self.admin_email {
+                out += &format!("email = {}\n", quote(email));
+            }
+        }
+        out += &format!(
+            "\n[edge]\nip = \"0.0.0.0\"\nhttp_port = {}\nhttps_port = {}\n",
+            self.http_port.unwrap_or(80),
+            self.https_port.unwrap_or(443)
+        );
+        out += "\n[loopback]\nip = \"127.0.0.1\"\nport_a = 8080\nport_b = 8081\n";
+        if let Some(base_url) = &self.base_url {
+            out += &format!("\n[sitemap]\nbase_url = {}\n", quote(base_url));
+        }
+        if let Some(name) = &self.site_name {
+            out += &format!("\n[feeds]\ntitle = {}\n", quote(name));
+        }
+        if let Some(lang) = &self.lang {
+            out += &format!("\n[i18n]\ndefault = {}\n", quote(lang));
+        }
+
+        toml::from_str::<Settings>(&out)
+            .map_err(|e| InitError::Answers(format!("they make invalid settings: {e}")))?;
+        Ok(out)
+    }
+}
+
+/// One step of `install`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Step {
+    Settings,
+    Directories,
+    Admin,
+    Migrations,
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Step::Settings => "settings",
+            Step::Directories => "directories",
+            Step::Admin => "admin",
+            Step::Migrations => "migrations",
+        })
+    }
+}
+
+impl Step {
+    /// Every step, in the order `install` runs them.
+    pub const ALL: [Step; 4] = [
+        Step::Settings,
+        Step::Directories,
+        Step::Admin,
+        Step::Migrations,
+    ];
+}
+
+/// What a step found to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Done,
+    /// Already in place from an earlier run.
+    Kept,
+}
+
+/// Where a step got to in the latest run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StepState {
+    Pending,
+    Done,
+    Kept,
+    Failed,
+}
+
+impl From<Outcome> for StepState {
+    fn from(outcome: Outcome) -> Self {
+        match outcome {
+            Outcome::Done => StepState::Done,
+            Outcome::Kept => StepState::Kept,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StepStatus {
+    pub step: Step,
+    pub status: StepState,
+}
+
+/// How far the site's install got.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InstallState {
+    NotStarted,
+    Partial,
+    Complete,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct InstallStatus {
+    pub state: InstallState,
+    pub steps: Vec<StepStatus>,
+}
+
+impl InstallStatus {
+    fn of(steps: Vec<StepStatus>) -> Self {
+        let finished = |s: &StepStatus| matches!(s.status, StepState::Done | StepState::Kept);
+        let state = if steps.iter().all(finished) {
+            InstallState::Complete
+        } else if steps.iter().all(|s| s.status == StepState::Pending) {
+            InstallState::NotStarted
+        } else {
+            InstallState::Partial
+        };
+        Self { state, steps }
+    }
+}
+
+/// A step as kept in the status file, which another build may have
+/// written with steps this one does not know.
+#[derive(Deserialize)]
+struct SavedStep {
+    step: String,
+    status: StepState,
+}
+
+/// The install status of the site in `dir`, one entry per step of
+/// `Step::ALL` in that order; steps the file lacks are pending and steps
+/// it has that this build does not know are left out.
+pub fn status(dir: &Path) -> Result<InstallStatus, InitError> {
+    let saved: Vec<SavedStep> = match fs::read(dir.join(INSTALL_STATUS_FILE)) {
+        Ok(raw) => serde_json::from_slice(&raw).map_err(io::Error::other)?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let steps = Step::ALL
+        .iter()
+        .map(|&step| StepStatus {
+            step,
+            status: saved
+                .iter()
+                .find(|s| s.step == step.to_string())
+                .map_or(StepState::Pending, |s| s.status),
+        })
+        .collect();
+    Ok(InstallStatus::of(steps))
+}
+
+fn pending() -> Vec<StepStatus> {
+    Step::ALL
+        .iter()
+        .map(|&step| StepStatus {
+            step,
+            status: StepState::Pending,
+        })
+        .collect()
+}
+
+/// The status file of one `install` run.
+struct Progress {
+    path: PathBuf,
+    steps: Vec<StepStatus>,
+    /// Steps before this one finished in the run that stopped part-way.
+    resume_at: usize,
+}
+
+impl Progress {
+    fn start(dir: &Path) -> Result<Self, InitError> {
+        let previous = status(dir)?;
+        // `status` lists `Step::ALL` in order, so this counts the steps
+        // that finished before the first that did not.
+        let resume_at = match previous.state {
+            InstallState::Partial => previous
+                .steps
+                .iter()
+                .take_while(|s| matches!(s.status, StepState::Done | StepState::Kept))
+                .count(),
+            _ => 0,
+        };
+        let mut steps = pending();
+        steps[..resume_at].copy_from_slice(&previous.steps[..resume_at]);
+        let progress = Self {
+            path: dir.join(INSTALL_STATUS_FILE),
+            steps,
+            resume_at,
+        };
+        progress.save()?;
+        Ok(progress)
+    }
+
+    /// Whether `step` finished in the run this one resumes.
+    fn finished(&self, step: Step) -> bool {
+        Step::ALL[..self.resume_at].contains(&step)
+    }
+
+    /// Keep `step`'s result; a failure is kept before it is returned.
+    fn record<T>(
+        &mut self,
+        step: Step,
+        result: Result<T, InitError>,
+        outcome: impl Fn(&T) -> Outcome,
+    ) -> Result<T, InitError> {
+        let status = match &result {
+            Ok(value) => outcome(value).into(),
+            Err(_) => StepState::Failed,
+        };
+        if let Some(s) = self.steps.iter_mut().find(|s| s.step == step) {
+            s.status = status;
+        }
+        match (self.save(), &result) {
+            (Err(e), Ok(_)) => return Err(e),
+            (Err(e), Err(_)) => error!("Keeping the install status failed: {}", e),
+            (Ok(()), _) => {}
+        }
+        result
+    }
+
+    fn save(&self) -> Result<(), InitError> {
+        let json = serde_json::to_vec_pretty(&self.steps).map_err(io::Error::other)?;
+        fs::write(&self.path, json)?;
+        Ok(())
+    }
+}
+
+/// Set up the site in `dir` with `answers`; `password` is only needed when
+/// the admin does not exist yet. After a run that stopped part-way, the
+/// steps it finished are kept as they are.
+pub fn install(
+    dir: &Path,
+    answers: &Answers,
+    password: Option<String>,
+    force: bool,
+) -> Result<Vec<(Step, Outcome)>, InitError> {
+    let admin = answers
+        .admin
+        .as_deref()
+        .ok_or_else(|| InitError::Answers("no admin username".into()))?;
+    let mut done = Vec::new();
+    fs::create_dir_all(dir)?;
+    let mut progress = Progress::start(dir)?;
+    let outcome = |outcome: &Outcome| *outcome;
+
+    let path = dir.join(SETTINGS_FILE);
+    let result = if (progress.finished(Step::Settings) || path.exists()) && !force {
+        Ok(Outcome::Kept)
+    } else {
+        answers
+            .settings_toml()
+            .and_then(|toml| Ok(fs::write(&path, toml)?))
+            .map(|()| Outcome::Done)
+    }
+    .and_then(|outcome| {
+        let settings: Settings = toml::from_str(&fs::read_to_string(&path)?)
+            .map_err(|e| InitError::Answers(format!("{}: {e}", path.display())))?;
+        Ok((outcome, settings))
+    });
+    let (done_settings, settings) = progress.record(Step::Settings, result, |(o, _)| *o)?;
+    done.push((Step::Settings, done_settings));
+
+    let result = if progress.finished(Step::Directories) {
+        Ok(Outcome::Kept)
+    } else {
+        make_directories(dir, &settings)
+    };
+    done.push((
+        Step::Directories,
+        progress.record(Step::Directories, result, outcome)?,
+    ));
+
+    let result = if progress.finished(Step::Admin) {
+        Ok(Outcome::Kept)
+    } else {
+        create_admin(dir, admin, answers, password, &settings)
+    };
+    done.push((Step::Admin, progress.record(Step::Admin, result, outcome)?));
+
+    let result = Migrator::new(dir, Some(&settings))
+        .up(None, false)
+        .map_err(InitError::from)
+        .map(|applied| {
+            if applied.is_empty() {
+                Outcome::Kept
+            } else {
+                Outcome::Done
+            }
+        });
+    done.push((
+        Step::Migrations,
+        progress.record(Step::Migrations, result, outcome)?,
+    ));
+    Ok(done)
+}
+
+fn make_directories(dir: &Path, settings: &Settings) -> Result<Outcome, InitError> {
+    let content_dir = settings
+        .content
+        .as_ref()
+        .map_or_else(|| dir.join(CONTENT_DIR), |c| dir.join(&c.dir));
+    let dirs = [content_dir, dir.join(&settings.cert.dir)];
+    if dirs.iter().all(|d| d.is_dir()) {
+        return Ok(Outcome::Kept);
+    }
+    dirs.iter().try_for_each(fs::create_dir_all)?;
+    Ok(Outcome::Done)
+}
+
+fn create_admin(
+    dir: &Path,
+    admin: &str,
+    answers: &Answers,
+    password: Option<String>,
+    settings: &Settings,
+) -> Result<Outcome, InitError> {
+    let users = UserStore::new(dir.join(USERS_DB_DIR));
+    if users.list()?.iter().any(|u| u.username == admin) {
+        return Ok(Outcome::Kept);
+    }
+    let password = password.ok_or_else(|| InitError::NoPassword(admin.to_owned()))?;
+    users.create(admin, Role::Admin, &ValidatedPassword::new(password)?)?;
+    if let Some(email) = &answers.admin_email {
+        users.set_email(admin, Some(email))?;
+        if settings.mail.is_some() {
+            Outbox::open(dir.join(OUTBOX_DIR))?.enqueue(welcome_email(admin, email, settings))?;
+        }
+    }
+    Ok(Outcome::Done)
+}
+
+fn welcome_email(admin: &str, email: &str, settings: &Settings) -> Delivery {
+    let base_url = settings
+        .sitemap
+        .as_ref()
+        .and_then(|s| s.base_url.as_deref())
+        .unwrap_or("")
+        .trim_end_matches('/');
+    Delivery::Email {
+        to: vec![email.to_owned()],
+        subject: "Welcome to your new site".to_owned(),
+        body: format!(
+            "An admin account has been set up for you on this site.\n\n\
+             Sign in at {}/admin/login as {}, with the password given to init.\n",
+            base_url, admin
+        ),
+    }
+}
+
+/// The `/install/status` resource for the site in `root_dir`.
+pub fn services(root_dir: &Path) -> impl HttpServiceFactory {
+    web::resource("/install/status")
+        .app_data(web::Data::new(SiteDir(root_dir.to_path_buf())))
+        .route(web::get().to(status_handler))
+}
+
+struct SiteDir(PathBuf);
+
+#[tracing::instrument(skip_all)]
+async fn status_handler(site: web::Data<SiteDir>) -> HttpResponse {
+    let status = web::block(move || status(&site.0))
+        .await
+        .map_err(|e| InitError::Io(io::Error::other(e)))
+        .and_then(|status| status);
+    match status {
+        Ok(status) => HttpResponse::Ok().json(status),
+        Err(e) => {
+            error!("Reading the install status failed: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn installs_reproducibly_and_resumes_after_a_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let site = dir.path().join("site");
+        let file = Answers {
+            site_name: Some("Field \"Notes\"".into()),
+            lang: Some("en".into()),
+            base_url: Some("https://notes.example".into()),
+            admin: Some("alice".into()),
+            tls: Some(TlsMode::Acme),
+            ..Answers::default()
+        };
+        let answers = file.or(Answers {
+            admin: Some("root".into()),
+            ..Answers::default()
+        });
+        assert_eq!(answers.admin.as_deref(), Some("root"));
+        assert_eq!(answers.lang.as_deref(), Some("en"));
+
+        // No password: the run stops at the admin step ...
+        assert!(matches!(
+            install(&site, &answers, None, false),
+            Err(InitError::NoPassword(_))
+        ));
+        let stopped = status(&site).unwrap();
+        assert_eq!(stopped.state, InstallState::Partial);
+        assert_eq!(
+            stopped
+                .steps
+                .iter()
+                .map(|s| (s.step, s.status))
+                .collect::<Vec<_>>(),
+            [
+                (Step::Settings, StepState::Done),
+                (Step::Directories, StepState::Done),
+                (Step::Admin, StepState::Failed),
+                (Step::Migrations, StepState::Pending),
+            ]
+        );
+        let settings: Settings =
+            toml::from_str(&fs::read_to_string(site.join(SETTINGS_FILE)).unwrap()).unwrap();
+        assert_eq!(settings.cert.acme.unwrap().domains, ["notes.example"]);
+        assert_eq!(
+            settings.feeds.unwrap().title.as_deref(),
+            Some("Field \"Notes\"")
+        );
+
+        // ... and running it again finishes the rest.
+        let steps = install(&site, &answers, Some("correct horse battery".into()), false).unwrap();
+        assert_eq!(
+            steps,
+            [
+                (Step::Settings, Outcome::Kept),
+                (Step::Directories, Outcome::Kept),
+                (Step::Admin, Outcome::Done),
+                (Step::Migrations, Outcome::Done),
+            ]
+        );
+        assert!(site.join("content").is_dir());
+        assert_eq!(status(&site).unwrap().state, InstallState::Complete);
+        let users = UserStore::new(site.join(USERS_DB_DIR)).list().unwrap();
+        assert_eq!(
+            (users[0].username.as_str(), users[0].role),
+            ("root", Role::Admin)
+        );
+
+        let again = install(&site, &answers, None, false).unwrap();
+        assert!(again.iter().all(|(_, o)| *o == Outcome::Kept));
+
+        assert!(matches!(
+            Answers {
+                tls: Some(TlsMode::Acme),
+                ..Answers::default()
+            }
+            .settings_toml(),
+            Err(InitError::Answers(_))
+        ));
+        assert!(toml::from_str::<Answers>("admin_password = \"x\"").is_err());
+    }
+
+    /// A site stopped at the admin step, with its status file replaced by
+    /// `saved`.
+    fn stopped_site(saved: serde_json::Value) -> (tempfile::TempDir, Answers) {
+        let dir = tempfile::tempdir().unwrap();
+        let answers = Answers {
+            admin: Some("root".into()),
+            ..Answers::default()
+        };
+        install(dir.path(), &answers, None, false).unwrap_err();
+        fs::write(
+            dir.path().join(INSTALL_STATUS_FILE),
+            serde_json::to_vec(&saved).unwrap(),
+        )
+        .unwrap();
+        (dir, answers)
+    }
+
+    #[test]
+    fn a_reordered_status_file_resumes_by_step_name() {
+        let (dir, answers) = stopped_site(serde_json::json!([
+            { "step": "migrations", "status": "pending" },
+            { "step": "admin", "status": "failed" },
+            { "step": "directories", "status": "done" },
+            { "step": "settings", "status": "done" },
+        ]));
+        let steps: Vec<_> = status(dir.path())
+            .unwrap()
+            .steps
+            .iter()
+            .map(|s| s.step)
+            .collect();
+        assert_eq!(steps, Step::ALL);
+
+        // Directories finished, so the resumed run leaves them alone.
+        fs::remove_dir(dir.path().join("content")).unwrap();
+        let done = install(
+            dir.path(),
+            &answers,
+            Some("correct horse battery".into()),
+            false,
+        )
+        .unwrap();
+        assert_eq!(done[1], (Step::Directories, Outcome::Kept));
+        assert_eq!(done[2], (Step::Admin, Outcome::Done));
+        assert!(!dir.path().join("content").exists());
+    }
+
+    #[test]
+    fn steps_this_build_does_not_know_are_dropped() {
+        let (dir, answers) = stopped_site(serde_json::json!([
+            { "step": "settings", "status": "done" },
+            { "step": "directories", "status": "done" },
+            { "step": "databases", "status": "done" },
+            { "step": "admin", "status": "done" },
+            { "step": "mail", "status": "failed" },
+            { "step": "migrations", "status": "pending" },
+        ]));
+        let stopped = status(dir.path()).unwrap();
+        assert_eq!(stopped.state, InstallState::Partial);
+        assert_eq!(stopped.steps.len(), Step::ALL.len());
+
+        // Only migrations are left; the admin step is not run again, so
+        // no password is needed.
+        let done = install(dir.path(), &answers, None, false).unwrap();
+        assert_eq!(done[2], (Step::Admin, Outcome::Kept));
+        assert_eq!(status(dir.path()).unwrap().state, InstallState::Complete);
+    }
+
+    #[test]
+    fn an_admin_with_an_address_is_welcomed_when_mail_is_set_up() {
+        use crate::db::outbox::OutboxState;
+
+        let dir = tempfile::tempdir().unwrap();
+        let answers = Answers {
+            admin: Some("root".into()),
+            admin_email: Some("root@notes.example".into()),
+            base_url: Some("https://notes.example/".into()),
+            ..Answers::default()
+        };
+        let password = || Some("correct horse battery".to_owned());
+        let queued = || {
+            Outbox::open(dir.path().join(OUTBOX_DIR))
+                .unwrap()
+                .list(OutboxState::Pending)
+                .unwrap()
+        };
+
+        // Without [mail] there is nothing to send it with.
+        install(dir.path(), &answers, password(), false).unwrap();
+        assert!(queued().is_empty());
+
+        let settings =
+            answers.settings_toml().unwrap() + "\n[mail]\nfrom = \"site@notes.example\"\n";
+        fs::write(dir.path().join(SETTINGS_FILE), settings).unwrap();
+        fs::remove_dir_all(dir.path().join(USERS_DB_DIR)).unwrap();
+        fs::remove_file(dir.path().join(INSTALL_STATUS_FILE)).unwrap();
+        install(dir.path(), &answers, password(), false).unwrap();
+        let Delivery::Email { to, body, .. } = &queued()[0].delivery else {
+            panic!("not an email");
+        };
+        assert_eq!(to, &["root@notes.example"]);
+        assert!(body.contains("https://notes.example/admin/login as root"));
+
+        // An admin kept from an earlier run is not welcomed again.
+        install(dir.path(), &answers, password(), false).unwrap();
+        assert_eq!(queued().len(), 1);
+    }
+
+    #[actix_web::test]
+    async fn the_status_names_steps_and_states_only() {
+        use actix_web::{test as actix_test, App};
+        use serde_json::{json, Value as Json};
+
+        let site = tempfile::tempdir().unwrap();
+        let app = actix_test::init_service(App::new().service(services(site.path()))).await;
+        let get = || {
+            actix_test::TestRequest::get()
+                .uri("/install/status")
+                .to_request()
+        };
+        let fresh: Json = actix_test::call_and_read_body_json(&app, get()).await;
+        assert_eq!(fresh["state"], "not_started");
+
+        let answers = Answers {
+            admin: Some("root".into()),
+            ..Answers::default()
+        };
+        install(site.path(), &answers, None, false).unwrap_err();
+        let partial: Json = actix_test::call_and_read_body_json(&app, get()).await;
+        assert_eq!(
+            partial,
+            json!({
+                "state": "partial",
+                "steps": [
+                    { "step": "settings", "status": "done" },
+                    { "step": "directories", "status": "done" },
+                    { "step": "admin", "status": "failed" },
+                    { "step": "migrations", "status": "pending" },
+                ],
+            })
+        );
+    }
+}
@@ -0,0 +1,153 @@
+// crates/edge/src/l10n.rs
+
+// The catalogs of the installed themes and plugins (serve::l10n), read from
+// each one's `locales/` directory at startup and again when it is reloaded.
+// A catalog that does not parse is logged and left out; its strings render
+// as their keys until it is fixed.
+//
+// The reader's language is the document's `i18n.lang`, else the site's
+// `[i18n] default`, else English.
+
+use crate::fs::ext::{DiscoveredPlugin, DiscoveredTheme};
+use domain::setting::I18nSettings;
+use serde_json::Value as Json;
+use serve::l10n::{Catalog, Localized};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use tracing::{info, warn};
+
+/// Where a theme or plugin keeps its catalogs.
+pub const LOCALES_DIR: &str = "locales";
+
+/// The language of strings when the site names none.
+pub const FALLBACK_LANG: &str = "en";
+
+type Catalogs = BTreeMap<String, Catalog>;
+
+fn load<'a>(owners: impl Iterator<Item = (&'a str, &'a Path)>, lang: &str) -> Catalogs {
+    let mut catalogs = Catalogs::new();
+    for (id, dir) in owners {
+        match Catalog::load(&dir.join(LOCALES_DIR), lang) {
+            Ok(catalog) if catalog.is_empty() => {}
+            Ok(catalog) => {
+                info!("Loaded string catalogs of {}", id);
+                catalogs.insert(id.to_owned(), catalog);
+            }
+            Err(e) => warn!("String catalogs of {} left out: {}", id, e),
+        }
+    }
+    catalogs
+}
+
+fn replace(slot: &RwLock<Arc<Catalogs>>, catalogs: Catalogs) {
+    match slot.write() {
+        Ok(mut current) => *current = Arc::new(catalogs),
+        Err(e) => *e.into_inner() = Arc::new(catalogs),
+    }
+}
+
+fn current(slot: &RwLock<Arc<Catalogs>>) -> Arc<Catalogs> {
+    slot.read()
+        .map(|c| c.clone())
+        .unwrap_or_else(|e| e.into_inner().clone())
+}
+
+/// The catalogs of one site's themes and plugins.
+#[derive(Default)]
+pub struct Strings {
+    default_lang: Option<String>,
+    themes: RwLock<Arc<Catalogs>>,
+    plugins: RwLock<Arc<Catalogs>>,
+}
+
+impl Strings {
+    /// No catalogs yet, for a site with `i18n` languages.
+    pub fn new(i18n: Option<&I18nSettings>) -> Self {
+        Self {
+            default_lang: i18n.map(|s| s.default.clone()),
+            ..Default::default()
+        }
+    }
+
+    fn default_lang(&self) -> &str {
+        self.default_lang.as_deref().unwrap_or(FALLBACK_LANG)
+    }
+
+    /// Replace the theme catalogs with those of `themes`.
+    pub fn set_theme_catalogs(&self, themes: &[DiscoveredTheme]) {
+        replace(
+            &self.themes,
+            load(
+                themes.iter().map(|t| (t.spec.id.as_str(), t.dir.as_path())),
+                self.default_lang(),
+            ),
+        );
+    }
+
+    /// Replace the plugin catalogs with those of `plugins`.
+    pub fn set_plugin_catalogs(&self, plugins: &[DiscoveredPlugin]) {
+        replace(
+            &self.plugins,
+            load(
+                plugins
+                    .iter()
+                    .map(|p| (p.spec.id.as_str(), p.dir.as_path())),
+                self.default_lang(),
+            ),
+        );
+    }
+
+    /// The language to show strings in for the document `meta` describes.
+    pub fn lang_for<'a>(&'a self, meta: &'a Json) -> &'a str {
+        meta.pointer("/i18n/lang")
+            .and_then(Json::as_str)
+            .unwrap_or(self.default_lang())
+    }
+
+    /// Every theme's and plugin's strings in `lang`, by id.
+    pub fn messages(&self, lang: &str) -> BTreeMap<String, Arc<Localized>> {
+        let (themes, plugins) = (current(&self.themes), current(&self.plugins));
+        themes
+            .iter()
+            .chain(plugins.iter())
+            .map(|(id, catalog)| (id.clone(), catalog.localized(lang)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::fs;
+
+    #[test]
+    fn catalogs_load_per_owner_and_bad_ones_are_left_out() {
+        let dir = tempfile::tempdir().unwrap();
+        let good = dir.path().join("good");
+        let bad = dir.path().join("bad");
+        fs::create_dir_all(good.join("locales/de")).unwrap();
+        fs::create_dir_all(bad.join("locales")).unwrap();
+        fs::write(
+            good.join("locales/en.ftl"),
+            "hi = Hi { $name }\nbye = Bye\n",
+        )
+        .unwrap();
+        fs::write(good.join("locales/de/main.ftl"), "hi = Hallo { $name }\n").unwrap();
+        fs::write(bad.join("locales/en.ftl"), "n = { $n ->\n *[other] x\n}\n").unwrap();
+
+        let catalogs = load(
+            [("good", good.as_path()), ("bad", bad.as_path())].into_iter(),
+            FALLBACK_LANG,
+        );
+        assert_eq!(catalogs.keys().collect::<Vec<_>>(), ["good"]);
+        let de = catalogs["good"].localized("de");
+        assert_eq!(de.t("hi", &json!({ "name": "Ada" })), "Hallo Ada");
+        assert_eq!(de.t("bye", &json!({})), "Bye");
+
+        let strings = Strings::default();
+        assert_eq!(strings.lang_for(&json!({ "i18n": { "lang": "de" } })), "de");
+        assert_eq!(strings.lang_for(&json!({})), FALLBACK_LANG);
+    }
+}
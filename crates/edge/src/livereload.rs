@@ -0,0 +1,226 @@
+// crates/edge/src/livereload.rs
+
+// Browser live reload for `whispercms serve-dev`. Every HTML page gets
+//
+//   <script src="/__livereload.js"></script>
+//
+// before its `</body>`; the script opens a websocket to `/__livereload` and
+// reloads the page on each message, or once the server is back after a
+// restart. The content watcher (fs::incremental) sends one after a re-index,
+// the extension watcher (fs::reload) after any change below the extensions
+// directory, so saving a page, template, asset or manifest refreshes the
+// browsers showing the site. Each site has its own `LiveReload`, so only its
+// own pages reload. Under `whispercms start` no site has one and pages are
+// left as rendered.
+
+use actix_web::{
+    body::{self, BodySize, BoxBody, MessageBody},
+    dev::{HttpServiceFactory, ServiceRequest, ServiceResponse},
+    error::ErrorInternalServerError,
+    http::header::{HeaderValue, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE},
+    middleware::Next,
+    web, Error, HttpRequest, HttpResponse,
+};
+use actix_ws::Message;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::debug;
+
+/// Where pages connect to hear about changes.
+pub const SOCKET_PATH: &str = "/__livereload";
+
+/// The script that connects them.
+pub const SCRIPT_PATH: &str = "/__livereload.js";
+
+/// Added before `</body>`.
+const SCRIPT_TAG: &str = r#"<script src="/__livereload.js"></script>"#;
+
+const SCRIPT: &str = r#"(() => {
+  const url = (location.protocol === "https:" ? "wss://" : "ws://") + location.host + "/__livereload";
+  const connect = (again) => {
+    const ws = new WebSocket(url);
+    ws.onopen = () => { if (again) location.reload(); };
+    ws.onmessage = () => location.reload();
+    ws.onclose = () => setTimeout(() => connect(true), 1000);
+  };
+  connect(false);
+})();
+"#;
+
+/// Largest page the script is added to.
+const MAX_PAGE: u64 = 8 * 1024 * 1024;
+
+/// One site's reload signal; only `serve-dev` sites have one.
+pub struct LiveReload(broadcast::Sender<()>);
+
+impl LiveReload {
+    pub fn new() -> Self {
+        Self(broadcast::channel(16).0)
+    }
+
+    /// Reload every page of the site connected.
+    pub fn changed(&self) {
+        // An error only means no page is open.
+        if self.0.send(()).is_ok() {
+            debug!("Live reload sent");
+        }
+    }
+}
+
+impl Default for LiveReload {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The socket and its script, for the outermost scope of the router of a
+/// site with a `LiveReload` among its app data.
+pub fn services() -> impl HttpServiceFactory {
+    (
+        web::resource(SOCKET_PATH).route(web::get().to(socket_handler)),
+        web::resource(SCRIPT_PATH).route(web::get().to(script_handler)),
+    )
+}
+
+async fn script_handler() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/javascript; charset=utf-8")
+        .insert_header((CACHE_CONTROL, "no-store"))
+        .body(SCRIPT)
+}
+
+async fn socket_handler(req: HttpRequest, body: web::Payload) -> Result<HttpResponse, Error> {
+    let Some(live) = req.app_data::<web::Data<LiveReload>>() else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let mut changes = live.0.subscribe();
+    let (res, mut session, mut messages) = actix_ws::handle(&req, body)?;
+
+    actix_web::rt::spawn(async move {
+        loop {
+            tokio::select! {
+                change = changes.recv() => match change {
+                    Ok(()) | Err(RecvError::Lagged(_)) => {
+                        if session.text("reload").await.is_err() {
+                            return;
+                        }
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                message = messages.recv() => match message {
+                    Some(Ok(Message::Ping(bytes))) => {
+                        if session.pong(&bytes).await.is_err() {
+                            return;
+                        }
+                    }
+                    Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+                    Some(Ok(_)) => {}
+                },
+            }
+        }
+        let _ = session.close(None).await;
+    });
+
+    Ok(res)
+}
+
+/// `actix_web::middleware::from_fn` middleware adding the script to HTML
+/// pages; only wrapped for a site with a `LiveReload`.
+pub async fn inject(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let res = next.call(req).await?.map_into_boxed_body();
+    if !is_page(&res) {
+        return Ok(res);
+    }
+
+    let (req, res) = res.into_parts();
+    let (mut res, body) = res.into_parts();
+    let page = body::to_bytes(body)
+        .await
+        .map_err(|e| ErrorInternalServerError(e.to_string()))?;
+    res.headers_mut().remove(CONTENT_LENGTH);
+    Ok(ServiceResponse::new(
+        req,
+        res.set_body(BoxBody::new(with_script(&page))),
+    ))
+}
+
+/// An uncompressed HTML body small enough to edit.
+fn is_page(res: &ServiceResponse<BoxBody>) -> bool {
+    let headers = res.headers();
+    let html = headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/html"));
+    let identity = headers
+        .get(CONTENT_ENCODING)
+        .is_none_or(|v| v == HeaderValue::from_static("identity"));
+    let small = matches!(res.response().body().size(), BodySize::Sized(n) if n <= MAX_PAGE);
+    html && identity && small
+}
+
+/// `page` with the script before its last `</body>`, else at the end.
+fn with_script(page: &[u8]) -> Vec<u8> {
+    let at = page
+        .windows("</body>".len())
+        .rposition(|w| w.eq_ignore_ascii_case(b"</body>"))
+        .unwrap_or(page.len());
+    let mut out = Vec::with_capacity(page.len() + SCRIPT_TAG.len());
+    out.extend_from_slice(&page[..at]);
+    out.extend_from_slice(SCRIPT_TAG.as_bytes());
+    out.extend_from_slice(&page[at..]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, middleware::from_fn, test, App};
+
+    #[actix_web::test]
+    async fn pages_get_the_script_and_other_responses_are_kept() {
+        assert_eq!(
+            with_script(b"<p>a</p></BODY></html>"),
+            br#"<p>a</p><script src="/__livereload.js"></script></BODY></html>"#
+        );
+        assert!(with_script(b"<p>a</p>").ends_with(SCRIPT_TAG.as_bytes()));
+
+        let app = test::init_service(
+            App::new().service(
+                web::scope("")
+                    .service(services())
+                    .wrap(from_fn(inject))
+                    .route(
+                        "/page",
+                        web::get().to(|| async {
+                            HttpResponse::Ok()
+                                .content_type("text/html; charset=utf-8")
+                                .body("<body><h1>Hi</h1></body>")
+                        }),
+                    )
+                    .route(
+                        "/api",
+                        web::get().to(|| async { HttpResponse::Ok().json([1, 2]) }),
+                    ),
+            ),
+        )
+        .await;
+        let get = |uri: &str| test::TestRequest::get().uri(uri).to_request();
+
+        let page = test::call_and_read_body(&app, get("/page")).await;
+        assert_eq!(
+            page,
+            r#"<body><h1>Hi</h1><script src="/__livereload.js"></script></body>"#
+        );
+        let json = test::call_and_read_body(&app, get("/api")).await;
+        assert_eq!(json, "[1,2]");
+
+        let script = test::call_and_read_body(&app, get(SCRIPT_PATH)).await;
+        assert!(std::str::from_utf8(&script).unwrap().contains(SOCKET_PATH));
+
+        // No `LiveReload`: there is nothing to listen to.
+        let res = test::call_service(&app, get(SOCKET_PATH)).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+}
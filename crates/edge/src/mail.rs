@@ -0,0 +1,570 @@
+// crates/edge/src/mail.rs
+
+// Outbound email. Everything that mails (form submissions, comment
+// notifications) queues a `Delivery::Email` in the outbox (db::outbox);
+// with a `[mail]` table a dispatcher sends what is due through a `Mailer`:
+//
+//   [mail]
+//   from = "Blog <blog@example.com>"
+//   transport = "smtp"              # or "file" while developing
+//   [mail.smtp]
+//   host = "smtp.example.com"
+//   security = "starttls"           # "tls" (465) or "none" (local relay)
+//   username = "blog@example.com"
+//   password_env = "WHISPERCMS_SMTP_PASSWORD"
+//
+// `SmtpMailer` speaks SMTP itself over std sockets and OpenSSL, on the
+// blocking pool; `FileMailer` writes each message to `<site>/mail_out/` as
+// an `.eml` file instead, for reading in a mail client. Failed sends are
+// retried with the outbox's backoff and end up in its dead letters
+// (`whispercms outbox`). Without `[mail]` the outbox keeps everything
+// queued until mail is configured.
+
+use crate::cli::OUTBOX_DIR;
+use crate::db::history::write_atomic;
+use crate::db::outbox::{spawn_dispatcher, Delivery, Outbox, Transport};
+use crate::proxy::EdgeError;
+use async_trait::async_trait;
+use chrono::Utc;
+use domain::setting::{MailSettings, MailTransport, SmtpSecurity, SmtpSettings};
+use openssl::base64::encode_block;
+use openssl::ssl::{SslConnector, SslMethod, SslStream};
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tracing::info;
+use uuid::Uuid;
+
+/// How often the dispatcher looks for due mail.
+pub const DISPATCH_EVERY: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Error)]
+pub enum MailError {
+    #[error("I/O: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("TLS: {0}")]
+    Tls(String),
+
+    /// The relay answered a command with an unexpected reply.
+    #[error("SMTP {command}: {code} {text}")]
+    Smtp {
+        command: String,
+        code: u16,
+        text: String,
+    },
+
+    #[error("mail settings: {0}")]
+    Config(String),
+}
+
+/// One plain-text email.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub to: Vec<String>,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Sends email. Implementations may block; callers run them off the
+/// request path, as the outbox dispatcher does.
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, message: &Message) -> Result<(), MailError>;
+}
+
+/// Build the mailer `settings` describe and start sending the outbox under
+/// `root_dir` through it. Each site calls this once, for its own outbox.
+pub fn init(root_dir: &Path, settings: &MailSettings) -> Result<Arc<dyn Mailer>, EdgeError> {
+    let mailer: Arc<dyn Mailer> = match settings.transport {
+        MailTransport::Smtp => {
+            let smtp = settings.smtp.as_ref().ok_or_else(|| {
+                EdgeError::Config("[mail] transport = \"smtp\" needs a [mail.smtp] table".into())
+            })?;
+            let mailer = SmtpMailer::new(&settings.from, smtp)
+                .map_err(|e| EdgeError::Config(e.to_string()))?;
+            info!("Sending mail through {}:{}", smtp.host, mailer.port);
+            Arc::new(mailer)
+        }
+        MailTransport::File => {
+            let dir = root_dir.join(&settings.dir);
+            info!("Writing mail to {} instead of sending it", dir.display());
+            Arc::new(FileMailer::new(&settings.from, dir))
+        }
+    };
+    let outbox = Outbox::open(root_dir.join(OUTBOX_DIR))?;
+    spawn_dispatcher(
+        Arc::new(outbox),
+        Arc::new(OutboxMailer(mailer.clone())),
+        DISPATCH_EVERY,
+    );
+    Ok(mailer)
+}
+
+/// Hands the outbox's emails to a mailer; webhooks are crate::webhooks'.
+struct OutboxMailer(Arc<dyn Mailer>);
+
+#[async_trait]
+impl Transport for OutboxMailer {
+    async fn deliver(&self, delivery: &Delivery) -> Result<(), String> {
+        match delivery {
+            Delivery::Email { to, subject, body } => self
+                .0
+                .send(&Message {
+                    to: to.clone(),
+                    subject: subject.clone(),
+                    body: body.clone(),
+                })
+                .await
+                .map_err(|e| e.to_string()),
+            Delivery::Webhook { url, .. } => Err(format!("{url} is not an email")),
+        }
+    }
+
+    fn accepts(&self, delivery: &Delivery) -> bool {
+        matches!(delivery, Delivery::Email { .. })
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Message format
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// `message` from `from` as RFC 5322 text with CRLF line endings.
+pub fn format_message(from: &str, message: &Message) -> String {
+    let host = from
+        .rsplit_once('@')
+        .map(|(_, domain)| domain.trim_end_matches('>'))
+        .unwrap_or("localhost");
+    let mut out = String::new();
+    let _ = write!(
+        out,
+        "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nMessage-ID: <{}@{}>\r\n\
+         MIME-Version: 1.0\r\nContent-Type: text/plain; charset=utf-8\r\n\
+         Content-Transfer-Encoding: 8bit\r\n\r\n",
+        header_value(from),
+        header_value(&message.to.join(", ")),
+        encode_subject(&message.subject),
+        Utc::now().to_rfc2822(),
+        Uuid::new_v4().simple(),
+        host,
+    );
+    for line in message.body.lines() {
+        out.push_str(line);
+        out.push_str("\r\n");
+    }
+    out
+}
+
+/// A header value with no line breaks, so it cannot start another header.
+fn header_value(s: &str) -> String {
+    s.replace(['\r', '\n'], " ")
+}
+
+/// Non-ASCII subjects as an RFC 2047 encoded word.
+fn encode_subject(subject: &str) -> String {
+    let subject = header_value(subject);
+    if subject.is_ascii() {
+        subject
+    } else {
+        format!("=?utf-8?B?{}?=", encode_block(subject.as_bytes()))
+    }
+}
+
+/// The bare address of `"Name <addr>"` or `addr`.
+fn address(mailbox: &str) -> &str {
+    match (mailbox.rfind('<'), mailbox.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &mailbox[start + 1..end],
+        _ => mailbox.trim(),
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// File mailer
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Writes each message to `<dir>/<time>-<id>.eml` instead of sending it.
+#[derive(Debug, Clone)]
+pub struct FileMailer {
+    from: String,
+    dir: PathBuf,
+}
+
+impl FileMailer {
+    pub fn new(from: impl Into<String>, dir: PathBuf) -> Self {
+        Self {
+            from: from.into(),
+            dir,
+        }
+    }
+
+    fn write(&self, message: &Message) -> Result<PathBuf, MailError> {
+        fs::create_dir_all(&self.dir)?;
+        let name = format!(
+            "{}-{}.eml",
+            Utc::now().format("%Y%m%dT%H%M%S%.3fZ"),
+            Uuid::new_v4().simple()
+        );
+        let path = self.dir.join(name);
+        write_atomic(&path, format_message(&self.from, message).as_bytes())?;
+        Ok(path)
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, message: &Message) -> Result<(), MailError> {
+        let path = self.write(message)?;
+        info!("Wrote mail to {}", path.display());
+        Ok(())
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// SMTP mailer
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Sends through an SMTP relay, one connection per message.
+#[derive(Debug, Clone)]
+pub struct SmtpMailer {
+    from: String,
+    host: String,
+    port: u16,
+    security: SmtpSecurity,
+    login: Option<(String, String)>,
+    timeout: Duration,
+}
+
+impl SmtpMailer {
+    /// Reads the password from `password_env` now, so a missing variable
+    /// fails at startup rather than on the first message.
+    pub fn new(from: impl Into<String>, settings: &SmtpSettings) -> Result<Self, MailError> {
+        let login = match (&settings.username, &settings.password_env) {
+            (Some(user), Some(var)) => {
+                let password = std::env::var(var)
+                    .map_err(|_| MailError::Config(format!("{var} is not set")))?;
+                Some((user.clone(), password))
+            }
+            (Some(_), None) => {
+                return Err(MailError::Config(
+                    "[mail.smtp] username needs password_env".into(),
+                ))
+            }
+            (None, _) => None,
+        };
+        let port = settings.port.unwrap_or(match settings.security {
+            SmtpSecurity::Tls => 465,
+            SmtpSecurity::Starttls => 587,
+            SmtpSecurity::None => 25,
+        });
+        Ok(Self {
+            from: from.into(),
+            host: settings.host.clone(),
+            port,
+            security: settings.security,
+            login,
+            timeout: Duration::from_secs(settings.timeout_secs.max(1)),
+        })
+    }
+
+    /// The whole SMTP conversation for `message`, blocking.
+    fn send_blocking(&self, message: &Message) -> Result<(), MailError> {
+        let addr = (self.host.as_str(), self.port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| MailError::Config(format!("{} does not resolve", self.host)))?;
+        let tcp = TcpStream::connect_timeout(&addr, self.timeout)?;
+        tcp.set_read_timeout(Some(self.timeout))?;
+        tcp.set_write_timeout(Some(self.timeout))?;
+
+        let mut conn = match self.security {
+            SmtpSecurity::Tls => Conn::new(Stream::Tls(Box::new(self.tls(tcp)?))),
+            _ => Conn::new(Stream::Plain(tcp)),
+        };
+        conn.expect("connect", 220)?;
+        let helo = format!("EHLO {}", hostname());
+        conn.command(&helo, 250)?;
+        if self.security == SmtpSecurity::Starttls {
+            conn.command("STARTTLS", 220)?;
+            let Stream::Plain(tcp) = conn.into_stream() else {
+                unreachable!("STARTTLS runs on the plain connection")
+            };
+            conn = Conn::new(Stream::Tls(Box::new(self.tls(tcp)?)));
+            conn.command(&helo, 250)?;
+        }
+        if let Some((user, password)) = &self.login {
+            let token = encode_block(format!("\0{user}\0{password}").as_bytes());
+            conn.send_line(&format!("AUTH PLAIN {token}"))?;
+            conn.expect("AUTH PLAIN", 235)?;
+        }
+
+        conn.command(&format!("MAIL FROM:<{}>", address(&self.from)), 250)?;
+        for to in &message.to {
+            let rcpt = format!("RCPT TO:<{}>", address(to));
+            let (code, text) = conn.send_and_read(&rcpt)?;
+            if code != 250 && code != 251 {
+                return Err(MailError::Smtp {
+                    command: rcpt,
+                    code,
+                    text,
+                });
+            }
+        }
+        conn.command("DATA", 354)?;
+        let mut data = String::new();
+        for line in format_message(&self.from, message).split("\r\n") {
+            // Dot-stuffing (RFC 5321 §4.5.2).
+            if line.starts_with('.') {
+                data.push('.');
+            }
+            data.push_str(line);
+            data.push_str("\r\n");
+        }
+        data.push_str(".\r\n");
+        conn.write_all(data.as_bytes())?;
+        conn.expect("DATA", 250)?;
+        let _ = conn.send_line("QUIT");
+        Ok(())
+    }
+
+    fn tls(&self, tcp: TcpStream) -> Result<SslStream<TcpStream>, MailError> {
+        let connector = SslConnector::builder(SslMethod::tls_client())
+            .map_err(|e| MailError::Tls(e.to_string()))?
+            .build();
+        connector
+            .connect(&self.host, tcp)
+            .map_err(|e| MailError::Tls(e.to_string()))
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, message: &Message) -> Result<(), MailError> {
+        let (mailer, message) = (self.clone(), message.clone());
+        tokio::task::spawn_blocking(move || mailer.send_blocking(&message))
+            .await
+            .map_err(|e| MailError::Io(io::Error::other(e)))?
+    }
+}
+
+/// This machine's name for `EHLO`.
+fn hostname() -> String {
+    fs::read_to_string("/etc/hostname")
+        .ok()
+        .map(|h| h.trim().to_owned())
+        .filter(|h| !h.is_empty() && !h.contains(char::is_whitespace))
+        .unwrap_or_else(|| "localhost".to_owned())
+}
+
+enum Stream {
+    Plain(TcpStream),
+    Tls(Box<SslStream<TcpStream>>),
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(s) => s.read(buf),
+            Stream::Tls(s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(s) => s.write(buf),
+            Stream::Tls(s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Plain(s) => s.flush(),
+            Stream::Tls(s) => s.flush(),
+        }
+    }
+}
+
+/// One SMTP connection: commands out, (multi-line) replies in.
+struct Conn {
+    inner: BufReader<Stream>,
+}
+
+impl Conn {
+    fn new(stream: Stream) -> Self {
+        Self {
+            inner: BufReader::new(stream),
+        }
+    }
+
+    fn into_stream(self) -> Stream {
+        self.inner.into_inner()
+    }
+
+    fn write_all(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let stream = self.inner.get_mut();
+        stream.write_all(bytes)?;
+        stream.flush()
+    }
+
+    fn send_line(&mut self, line: &str) -> io::Result<()> {
+        self.write_all(format!("{line}\r\n").as_bytes())
+    }
+
+    /// A reply: its code and its lines' text, joined.
+    fn read_reply(&mut self) -> Result<(u16, String), MailError> {
+        let mut text = Vec::new();
+        loop {
+            let mut line = String::new();
+            if self.inner.read_line(&mut line)? == 0 {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+            let line = line.trim_end();
+            let code = line.get(..3).and_then(|c| c.parse::<u16>().ok());
+            let Some(code) = code else {
+                return Err(MailError::Smtp {
+                    command: "reply".into(),
+                    code: 0,
+                    text: line.to_owned(),
+                });
+            };
+            text.push(line.get(4..).unwrap_or("").to_owned());
+            if line.as_bytes().get(3) != Some(&b'-') {
+                return Ok((code, text.join(" ")));
+            }
+        }
+    }
+
+    fn send_and_read(&mut self, command: &str) -> Result<(u16, String), MailError> {
+        self.send_line(command)?;
+        self.read_reply()
+    }
+
+    fn expect(&mut self, command: &str, want: u16) -> Result<(), MailError> {
+        let (code, text) = self.read_reply()?;
+        if code == want {
+            Ok(())
+        } else {
+            Err(MailError::Smtp {
+                command: command.to_owned(),
+                code,
+                text,
+            })
+        }
+    }
+
+    fn command(&mut self, command: &str, want: u16) -> Result<(), MailError> {
+        self.send_line(command)?;
+        self.expect(command.split(' ').next().unwrap_or(command), want)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn message() -> Message {
+        Message {
+            to: vec!["Ada <ada@example.com>".into()],
+            subject: "Grüße\r\nBcc: evil@example.com".into(),
+            body: "Hello\n.hidden line\nBye".into(),
+        }
+    }
+
+    #[test]
+    fn messages_are_formatted_with_safe_headers() {
+        let text = format_message("Blog <blog@example.com>", &message());
+        assert!(text.contains("To: Ada <ada@example.com>\r\n"), "{text}");
+        assert!(text.contains("Subject: =?utf-8?B?"), "{text}");
+        assert!(!text.contains("\r\nBcc:"), "{text}");
+        assert!(text.contains("@example.com>\r\n"), "{text}");
+        assert!(
+            text.ends_with("\r\n\r\nHello\r\n.hidden line\r\nBye\r\n"),
+            "{text}"
+        );
+    }
+
+    #[tokio::test]
+    async fn the_file_mailer_writes_eml_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let mailer = FileMailer::new("blog@example.com", dir.path().join("mail_out"));
+        mailer.send(&message()).await.unwrap();
+        let files: Vec<_> = fs::read_dir(dir.path().join("mail_out"))
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .collect();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].extension().unwrap(), "eml");
+    }
+
+    #[tokio::test]
+    async fn smtp_sends_through_a_relay() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let relay = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut out = stream;
+            let mut transcript = Vec::new();
+            let mut in_data = false;
+            out.write_all(b"220 relay ready\r\n").unwrap();
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+                let line = line.trim_end().to_owned();
+                transcript.push(line.clone());
+                let reply: &[u8] = if in_data {
+                    if line != "." {
+                        continue;
+                    }
+                    in_data = false;
+                    b"250 queued\r\n"
+                } else if line.starts_with("EHLO") {
+                    b"250-relay\r\n250 AUTH PLAIN\r\n"
+                } else if line.starts_with("AUTH PLAIN") {
+                    b"235 ok\r\n"
+                } else if line == "DATA" {
+                    in_data = true;
+                    b"354 go ahead\r\n"
+                } else if line == "QUIT" {
+                    out.write_all(b"221 bye\r\n").unwrap();
+                    break;
+                } else {
+                    b"250 ok\r\n"
+                };
+                out.write_all(reply).unwrap();
+            }
+            transcript
+        });
+
+        std::env::set_var("MAIL_TEST_SMTP_PASSWORD", "secret");
+        let settings = SmtpSettings {
+            host: "127.0.0.1".into(),
+            port: Some(port),
+            security: SmtpSecurity::None,
+            username: Some("blog".into()),
+            password_env: Some("MAIL_TEST_SMTP_PASSWORD".into()),
+            timeout_secs: 5,
+        };
+        let mailer = SmtpMailer::new("Blog <blog@example.com>", &settings).unwrap();
+        mailer.send(&message()).await.unwrap();
+
+        let transcript = relay.join().unwrap();
+        assert!(transcript.contains(&"MAIL FROM:<blog@example.com>".to_owned()));
+        assert!(transcript.contains(&"RCPT TO:<ada@example.com>".to_owned()));
+        assert!(transcript.contains(&format!("AUTH PLAIN {}", encode_block(b"\0blog\0secret"))));
+        // The body line starting with a dot was stuffed.
+        assert!(transcript.contains(&"..hidden line".to_owned()));
+        assert_eq!(transcript.last().unwrap(), "QUIT");
+    }
+}
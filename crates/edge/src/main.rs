@@ -1,23 +1,68 @@
 use std::process::ExitCode;
 
-use tracing::info;
-use tracing_subscriber::{fmt, prelude::*, EnvFilter};
-
+pub mod access_log;
+pub mod acme;
+pub mod api;
+pub mod archetypes;
+pub mod assets;
+pub mod audit;
+pub mod auth;
+pub mod authors;
+pub mod backup;
+pub mod blocks;
+pub mod cache;
+pub mod canonical;
 pub mod cli;
+pub mod collections;
+pub mod comments;
+pub mod components;
+pub mod csrf;
 pub mod db;
+pub mod debugging;
+pub mod doctor;
+pub mod error_pages;
+pub mod export;
+pub mod feeds;
+pub mod flags;
+pub mod form_pages;
+pub mod forms;
 pub mod fs;
+pub mod health;
+pub mod i18n;
+pub mod images;
+pub mod import;
+pub mod init;
+pub mod l10n;
+pub mod livereload;
+pub mod mail;
+pub mod maintenance;
+pub mod media;
+pub mod middleware;
+pub mod normalize;
+pub mod ops;
+pub mod packages;
+pub mod permalinks;
+pub mod plugin_routes;
+pub mod preview;
 pub mod proxy;
+pub mod quota;
+pub mod redirects;
+pub mod related;
+pub mod request_id;
 pub mod router;
+pub mod scaffold;
+pub mod schedule;
+pub mod search;
+pub mod site;
+pub mod sitemap;
+pub mod taxonomies;
+pub mod telemetry;
+pub mod totp;
+pub mod tui;
+pub mod webhooks;
 
 fn main() -> ExitCode {
-    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("warn")); // fallback
-
-    tracing_subscriber::registry()
-        .with(filter)
-        .with(fmt::layer().with_file(true).with_line_number(true))
-        .init();
-
-    info!("logging setup complete");
-    info!("engaging clap to parse commandline");
+    // Logging is set up by `cli::start` once the runtime is up and the site
+    // settings (which may enable trace export) are known.
     cli::start()
 }
@@ -0,0 +1,346 @@
+// crates/edge/src/maintenance.rs
+
+// Middleware for serve::maintenance, wrapped around the whole router. Inside
+// a scheduled window every request gets the 503 page; outside, requests pass
+// through. Either way the response carries `X-Maintenance-Windows`.
+// Configured by `[[maintenance.windows]]` entries in settings.toml:
+//
+//   [[maintenance.windows]]
+//   start = "2026-11-01T02:00:00Z"
+//   end = "2026-11-01T03:30:00Z"
+//   message = "Upgrading the database."
+//
+// A window in `<site>/maintenance.toml` applies as well, so maintenance can
+// be started and ended without a restart, from `whispercms tui` or by admins:
+//
+//   GET    /admin/maintenance   {"active":true,"source":"manual","window":{…}}
+//   PUT    /admin/maintenance   {"minutes":30,"message":"…"} starts it
+//   DELETE /admin/maintenance   ends it → 204
+//
+// `[maintenance] enabled = true` keeps the site in maintenance until the
+// setting is turned off. Admins signed in keep using the site throughout,
+// and signing in, resetting a password and `/admin/maintenance` stay open so
+// an admin can get in and end it; everyone else gets the theme's
+// `maintenance.hbs` if it has one. A site's
+// `Maintenance` is app data of the scope around the middleware layers.
+
+use crate::auth::{self, Admin, RequireRole};
+use crate::db::users::Role;
+use crate::error_pages;
+use actix_web::{
+    body::{BoxBody, MessageBody},
+    dev::{HttpServiceFactory, ServiceRequest, ServiceResponse},
+    http::header::{HeaderName, HeaderValue, RETRY_AFTER},
+    middleware::Next,
+    web, Error, HttpResponse,
+};
+use chrono::{DateTime, Utc};
+use domain::setting::{MaintenanceSettings, MaintenanceWindow};
+use serde::Deserialize;
+use serde_json::json;
+use serve::maintenance::{
+    open_ended, page_html, page_model, read_manual, retry_after_secs, write_manual,
+    MaintenanceSchedule, MANUAL_FILE, PAGE_TEMPLATE, WINDOWS_HEADER,
+};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{error, info};
+
+/// Length of maintenance started with `PUT /admin/maintenance` and no
+/// `minutes`.
+const DEFAULT_MINUTES: i64 = 60;
+
+/// How long a read of the manual maintenance file is trusted.
+const MANUAL_RECHECK: Duration = Duration::from_secs(1);
+
+/// Routes an admin needs to get in and end maintenance.
+const EXEMPT: &[&str] = &[
+    "/admin/login",
+    "/admin/password-reset",
+    "/admin/password-reset/confirm",
+    "/admin/maintenance",
+];
+
+/// A site's maintenance: the settings, its schedule and its manual file.
+pub struct Maintenance {
+    schedule: MaintenanceSchedule,
+    /// `[maintenance] enabled`, with its message.
+    enabled: Option<Option<String>>,
+    manual: Manual,
+    /// Whether the last request saw an active window; only used to log the
+    /// transitions.
+    active: AtomicBool,
+}
+
+struct Manual {
+    path: PathBuf,
+    /// When `window` was read, and what the file held then.
+    checked: Mutex<Option<(Instant, Option<MaintenanceWindow>)>>,
+}
+
+impl Maintenance {
+    /// The windows in `settings`, and `<site_dir>/maintenance.toml`.
+    pub fn new(site_dir: &Path, settings: Option<MaintenanceSettings>) -> Self {
+        let settings = settings.unwrap_or_default();
+        Self {
+            schedule: MaintenanceSchedule::new(settings.windows),
+            enabled: settings.enabled.then_some(settings.message),
+            manual: Manual {
+                path: site_dir.join(MANUAL_FILE),
+                checked: Mutex::new(None),
+            },
+            active: AtomicBool::new(false),
+        }
+    }
+
+    /// Maintenance turned on in the settings, as a window ending soon after
+    /// `now`.
+    fn enabled(&self, now: DateTime<Utc>) -> Option<MaintenanceWindow> {
+        let message = self.enabled.as_ref()?;
+        Some(open_ended(message.clone(), now))
+    }
+
+    /// The manual window, if it is in effect at `now`.
+    fn manual(&self, now: DateTime<Utc>) -> Option<MaintenanceWindow> {
+        let mut checked = self.manual.checked.lock().ok()?;
+        let window = match &*checked {
+            Some((at, window)) if at.elapsed() < MANUAL_RECHECK => window.clone(),
+            _ => {
+                let window = read_manual(&self.manual.path);
+                *checked = Some((Instant::now(), window.clone()));
+                window
+            }
+        };
+        window.filter(|w| w.start <= now && now < w.end)
+    }
+
+    /// Read `maintenance.toml` again on the next request.
+    fn forget_manual(&self) {
+        if let Ok(mut checked) = self.manual.checked.lock() {
+            *checked = None;
+        }
+    }
+
+    /// Where maintenance is coming from at `now`, and its window.
+    fn current(&self, now: DateTime<Utc>) -> Option<(&'static str, MaintenanceWindow)> {
+        self.enabled(now)
+            .map(|w| ("settings", w))
+            .or_else(|| self.schedule.active(now).map(|w| ("schedule", w.clone())))
+            .or_else(|| self.manual(now).map(|w| ("manual", w)))
+    }
+}
+
+/// Whether `req` comes from a signed-in admin, who is let through.
+async fn from_admin(req: &ServiceRequest) -> bool {
+    matches!(
+        auth::current_user(req.request()).await,
+        Ok(Some(user)) if user.role.allows(Role::Admin)
+    )
+}
+
+/// `actix_web::middleware::from_fn` middleware.
+pub async fn guard(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let Some(maintenance) = req.app_data::<web::Data<Maintenance>>().cloned() else {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    };
+    let now = Utc::now();
+    let active = maintenance.current(now).map(|(_, w)| w);
+
+    if maintenance.active.swap(active.is_some(), Ordering::Relaxed) != active.is_some() {
+        match &active {
+            Some(w) => info!("Entering maintenance until {}", w.end),
+            None => info!("Maintenance is over"),
+        }
+    }
+
+    let mut res = match active {
+        Some(window) if !EXEMPT.contains(&req.path()) && !from_admin(&req).await => {
+            let page =
+                error_pages::theme_page(req.request(), PAGE_TEMPLATE, &page_model(&window, now))
+                    .unwrap_or_else(|| page_html(&window).into_bytes());
+            req.into_response(
+                HttpResponse::ServiceUnavailable()
+                    .insert_header((RETRY_AFTER, retry_after_secs(&window, now)))
+                    .content_type("text/html; charset=utf-8")
+                    .body(page),
+            )
+        }
+        _ => next.call(req).await?.map_into_boxed_body(),
+    };
+
+    if let Some(value) = maintenance
+        .schedule
+        .header_value(now)
+        .and_then(|v| HeaderValue::from_str(&v).ok())
+    {
+        res.headers_mut()
+            .insert(HeaderName::from_static(WINDOWS_HEADER), value);
+    }
+    Ok(res)
+}
+
+/// What `PUT /admin/maintenance` takes.
+#[derive(Debug, Default, Deserialize)]
+struct StartRequest {
+    minutes: Option<u32>,
+    message: Option<String>,
+}
+
+/// The `/admin/maintenance` resource for the site `maintenance` belongs to,
+/// for the root of the router.
+pub fn services(maintenance: web::Data<Maintenance>) -> impl HttpServiceFactory {
+    web::resource("/admin/maintenance")
+        .app_data(maintenance)
+        .route(web::get().to(status_handler))
+        .route(web::put().to(start_handler))
+        .route(web::delete().to(end_handler))
+}
+
+#[tracing::instrument(skip_all)]
+async fn status_handler(
+    _auth: RequireRole<Admin>,
+    maintenance: web::Data<Maintenance>,
+) -> HttpResponse {
+    let now = Utc::now();
+    HttpResponse::Ok().json(match maintenance.current(now) {
+        Some((source, window)) => json!({ "active": true, "source": source, "window": window }),
+        None => json!({ "active": false }),
+    })
+}
+
+#[tracing::instrument(skip_all)]
+async fn start_handler(
+    _auth: RequireRole<Admin>,
+    maintenance: web::Data<Maintenance>,
+    body: Option<web::Json<StartRequest>>,
+) -> HttpResponse {
+    let body = body.map(web::Json::into_inner).unwrap_or_default();
+    let minutes = body.minutes.map_or(DEFAULT_MINUTES, i64::from);
+    if minutes == 0 {
+        return HttpResponse::BadRequest().json(json!({ "error": "minutes must be at least 1" }));
+    }
+    let now = Utc::now();
+    let window = MaintenanceWindow {
+        start: now,
+        end: now + chrono::Duration::minutes(minutes),
+        message: body.message,
+    };
+    let written = {
+        let (path, window) = (maintenance.manual.path.clone(), window.clone());
+        web::block(move || write_manual(&path, &window)).await
+    };
+    match written {
+        Ok(Ok(())) => {
+            maintenance.forget_manual();
+            info!("Maintenance started until {}", window.end);
+            HttpResponse::Ok().json(json!({ "active": true, "source": "manual", "window": window }))
+        }
+        Ok(Err(e)) => write_failed(e),
+        Err(e) => write_failed(io::Error::other(e)),
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn end_handler(
+    _auth: RequireRole<Admin>,
+    maintenance: web::Data<Maintenance>,
+) -> HttpResponse {
+    let path = maintenance.manual.path.clone();
+    let removed = web::block(move || match std::fs::remove_file(&path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        other => other,
+    })
+    .await;
+    match removed {
+        Ok(Ok(())) => {
+            maintenance.forget_manual();
+            HttpResponse::NoContent().finish()
+        }
+        Ok(Err(e)) => write_failed(e),
+        Err(e) => write_failed(io::Error::other(e)),
+    }
+}
+
+fn write_failed(e: io::Error) -> HttpResponse {
+    error!("Writing {} failed: {}", MANUAL_FILE, e);
+    HttpResponse::InternalServerError().json(json!({ "error": "maintenance file not written" }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::SESSION_COOKIE;
+    use crate::db::users::ValidatedPassword;
+    use actix_web::{cookie::Cookie, http::StatusCode, middleware::from_fn, test, App};
+
+    #[actix_web::test]
+    async fn windows_refuse_everyone_but_admins_and_the_way_in() {
+        let site = tempfile::tempdir().unwrap();
+        let now = Utc::now();
+        let upcoming = MaintenanceWindow {
+            start: now + chrono::Duration::days(1),
+            end: now + chrono::Duration::days(1) + chrono::Duration::hours(1),
+            message: None,
+        };
+        let maintenance = web::Data::new(Maintenance::new(
+            site.path(),
+            Some(MaintenanceSettings {
+                windows: vec![upcoming],
+                ..Default::default()
+            }),
+        ));
+        let users = auth::store(site.path());
+        let password = ValidatedPassword::new("a long enough secret").unwrap();
+        let admin = users.create("ada", Role::Admin, &password).unwrap();
+        let token = users
+            .issue_session(&admin, chrono::Duration::hours(1))
+            .unwrap();
+        let ada = Cookie::new(SESSION_COOKIE, token);
+        let app = test::init_service(
+            App::new()
+                .app_data(maintenance.clone())
+                .app_data(users)
+                .wrap(from_fn(guard))
+                .route("/", web::get().to(HttpResponse::Ok))
+                .route("/admin/login", web::post().to(HttpResponse::Ok)),
+        )
+        .await;
+        let get = || test::TestRequest::get().uri("/");
+
+        // Only scheduled: everyone passes and hears about the window.
+        let res = test::call_service(&app, get().to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res.headers().contains_key(WINDOWS_HEADER));
+
+        let window = MaintenanceWindow {
+            start: now - chrono::Duration::minutes(1),
+            end: now + chrono::Duration::minutes(30),
+            message: Some("Upgrading.".into()),
+        };
+        write_manual(&site.path().join(MANUAL_FILE), &window).unwrap();
+        maintenance.forget_manual();
+
+        let res = test::call_service(&app, get().to_request()).await;
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(res.headers().contains_key(RETRY_AFTER));
+        assert!(res.headers().contains_key(WINDOWS_HEADER));
+        let res = test::call_service(&app, get().cookie(ada).to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let login = test::TestRequest::post().uri("/admin/login").to_request();
+        assert_eq!(
+            test::call_service(&app, login).await.status(),
+            StatusCode::OK
+        );
+
+        std::fs::remove_file(site.path().join(MANUAL_FILE)).unwrap();
+        maintenance.forget_manual();
+        let res = test::call_service(&app, get().to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+}
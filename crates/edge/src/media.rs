@@ -0,0 +1,367 @@
+// crates/edge/src/media.rs
+
+// Routes for the media library (db::media), mounted ahead of every theme:
+//
+//   POST /admin/media   multipart upload: one or more `file` parts and an
+//                       optional `post` part naming the post they belong to
+//   GET  /api/media     MQL query over the records, same parameters and
+//                       response shape as /api/content
+//
+// Themes and plugins list a post's attachments with
+// `/api/media?filter={"attached_to":{"$all":["/blog/my-post/"]}}`. Uploads need
+// a login with at least the author role, or `Authorization: Bearer <token>`
+// matching `[media] upload_token` for scripts:
+//
+//   [media]
+//   upload_token = "change-me"
+//   max_upload_bytes = 20971520
+
+use crate::api::content::ContentQuery;
+use crate::audit::{self, AuditNote};
+use crate::auth;
+use crate::db::media::{MediaError, MediaLibrary, MEDIA_DB_DIR};
+use crate::db::users::Role;
+use actix_multipart::Multipart;
+use actix_web::{
+    dev::HttpServiceFactory,
+    http::header::{AUTHORIZATION, WWW_AUTHENTICATE},
+    web, HttpRequest, HttpResponse, ResponseError,
+};
+use domain::setting::MediaSettings;
+use futures::StreamExt;
+use serde_json::json;
+use serve::assets::content_type;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use tracing::{error, info};
+
+/// The upload and query resources, for the root of the router; files go to
+/// `media_dir`, where `[images]` serves originals from.
+pub fn services(
+    settings: MediaSettings,
+    root_dir: PathBuf,
+    media_dir: PathBuf,
+) -> impl HttpServiceFactory {
+    let library = web::Data::new(MediaLibrary::new(root_dir.join(MEDIA_DB_DIR), media_dir));
+    (
+        web::resource("/admin/media")
+            .app_data(web::Data::new(settings))
+            .app_data(library.clone())
+            .route(web::post().to(upload_handler)),
+        web::resource("/api/media")
+            .app_data(library)
+            .route(web::get().to(query_handler)),
+    )
+}
+
+/// Whether `req` carries the bearer `token`. Digests are compared so the
+/// time taken does not depend on how much of the token matched.
+pub(crate) fn authorized(req: &HttpRequest, token: &str) -> bool {
+    let Some(given) = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+    else {
+        return false;
+    };
+    Sha256::digest(given.trim().as_bytes()) == Sha256::digest(token.as_bytes())
+}
+
+struct Upload {
+    name: String,
+    content_type: String,
+    bytes: Vec<u8>,
+}
+
+fn error_json(builder: &mut actix_web::HttpResponseBuilder, message: &str) -> HttpResponse {
+    builder.json(json!({ "error": message }))
+}
+
+#[tracing::instrument(skip_all)]
+async fn upload_handler(
+    settings: web::Data<MediaSettings>,
+    library: web::Data<MediaLibrary>,
+    req: HttpRequest,
+    mut payload: Multipart,
+) -> HttpResponse {
+    let by_token = settings
+        .upload_token
+        .as_deref()
+        .is_some_and(|token| authorized(&req, token));
+    if !by_token {
+        match auth::current_user(&req).await {
+            Ok(Some(user)) if user.role.allows(Role::Author) => {}
+            Ok(Some(_)) => {
+                return error_json(
+                    &mut HttpResponse::Forbidden(),
+                    "uploading requires the author role",
+                )
+            }
+            Ok(None) => {
+                return error_json(
+                    HttpResponse::Unauthorized().insert_header((WWW_AUTHENTICATE, "Bearer")),
+                    "log in or send the upload token",
+                )
+            }
+            Err(e) => return e.error_response(),
+        }
+    }
+
+    // Read every part first: `post` may come after the files.
+    let mut uploads = Vec::new();
+    let mut post = None;
+    while let Some(field) = payload.next().await {
+        let mut field = match field {
+            Ok(field) => field,
+            Err(e) => return error_json(&mut HttpResponse::BadRequest(), &e.to_string()),
+        };
+        let part = field.name().unwrap_or_default().to_owned();
+        let file_name = field
+            .content_disposition()
+            .and_then(|cd| cd.get_filename())
+            .map(str::to_owned);
+        let declared_type = field.content_type().map(|m| m.essence_str().to_owned());
+
+        let mut bytes = Vec::new();
+        while let Some(chunk) = field.next().await {
+            match chunk {
+                Ok(chunk) if bytes.len() + chunk.len() <= settings.max_upload_bytes => {
+                    bytes.extend_from_slice(&chunk)
+                }
+                Ok(_) => {
+                    return error_json(
+                        &mut HttpResponse::PayloadTooLarge(),
+                        &format!("files are limited to {} bytes", settings.max_upload_bytes),
+                    )
+                }
+                Err(e) => return error_json(&mut HttpResponse::BadRequest(), &e.to_string()),
+            }
+        }
+
+        match part.as_str() {
+            "post" => post = Some(String::from_utf8_lossy(&bytes).trim().to_owned()),
+            "file" => {
+                let name = file_name.unwrap_or_else(|| "upload".to_owned());
+                let content_type = declared_type
+                    .filter(|t| t != "application/octet-stream")
+                    .unwrap_or_else(|| content_type(Path::new(&name)).to_owned());
+                uploads.push(Upload {
+                    name,
+                    content_type,
+                    bytes,
+                });
+            }
+            _ => {}
+        }
+    }
+    if uploads.is_empty() {
+        return error_json(&mut HttpResponse::BadRequest(), "no `file` part");
+    }
+
+    let lib = library.get_ref().clone();
+    let stored = web::block(move || {
+        uploads
+            .iter()
+            .map(|u| lib.add(&u.bytes, &u.name, &u.content_type, post.as_deref()))
+            .collect::<Result<Vec<_>, MediaError>>()
+    })
+    .await;
+
+    match stored {
+        Ok(Ok(stored)) => {
+            let created = stored.iter().any(|(_, new)| *new);
+            for (record, new) in &stored {
+                info!(
+                    "Media {} {} ({} bytes)",
+                    if *new { "stored" } else { "already stored" },
+                    record.path,
+                    record.size
+                );
+            }
+            let items: Vec<_> = stored.into_iter().map(|(record, _)| record).collect();
+            let ids: Vec<&str> = items.iter().map(|r| r.id.as_str()).collect();
+            audit::annotate(
+                &req,
+                AuditNote::new("media.upload", format!("media:{}", ids.join(","))),
+            );
+            let mut resp = if created {
+                HttpResponse::Created()
+            } else {
+                HttpResponse::Ok()
+            };
+            resp.json(json!({ "items": items }))
+        }
+        Ok(Err(e)) => {
+            error!("Storing an upload failed: {}", e);
+            error_json(
+                &mut HttpResponse::InternalServerError(),
+                "storing the upload failed",
+            )
+        }
+        Err(e) => {
+            error!("Storing an upload was cancelled: {}", e);
+            error_json(
+                &mut HttpResponse::InternalServerError(),
+                "storing the upload failed",
+            )
+        }
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn query_handler(library: web::Data<MediaLibrary>, req: HttpRequest) -> HttpResponse {
+    let q = match ContentQuery::from_query_string(req.query_string()) {
+        Ok(q) => q,
+        Err(e) => return error_json(&mut HttpResponse::BadRequest(), &e.to_string()),
+    };
+    match library.query(&q.filter, &q.opts).await {
+        Ok((total, items)) => HttpResponse::Ok().json(json!({
+            "items": items,
+            "total": total,
+            "limit": q.opts.limit,
+            "skip": q.opts.skip.unwrap_or(0),
+        })),
+        Err(MediaError::Query(e)) => error_json(&mut HttpResponse::BadRequest(), &e.to_string()),
+        Err(e) => {
+            error!("Media query failed: {}", e);
+            error_json(
+                &mut HttpResponse::InternalServerError(),
+                "media query failed",
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, test as actix_test, App};
+    use serde_json::Value as Json;
+
+    const BOUNDARY: &str = "XyZ";
+
+    fn multipart(parts: &[(&str, Option<&str>, &str)]) -> Vec<u8> {
+        let mut body = String::new();
+        for (name, file_name, content) in parts {
+            body.push_str(&format!("--{BOUNDARY}\r\n"));
+            match file_name {
+                Some(f) => body.push_str(&format!(
+                    "Content-Disposition: form-data; name=\"{name}\"; filename=\"{f}\"\r\n\r\n"
+                )),
+                None => body.push_str(&format!(
+                    "Content-Disposition: form-data; name=\"{name}\"\r\n\r\n"
+                )),
+            }
+            body.push_str(content);
+            body.push_str("\r\n");
+        }
+        body.push_str(&format!("--{BOUNDARY}--\r\n"));
+        body.into_bytes()
+    }
+
+    fn upload(body: Vec<u8>, token: Option<&str>) -> actix_test::TestRequest {
+        let req = actix_test::TestRequest::post()
+            .uri("/admin/media")
+            .insert_header((
+                "content-type",
+                format!("multipart/form-data; boundary={BOUNDARY}"),
+            ))
+            .set_payload(body);
+        match token {
+            Some(t) => req.insert_header((AUTHORIZATION, format!("Bearer {t}"))),
+            None => req,
+        }
+    }
+
+    #[test]
+    fn tokens_must_match_exactly() {
+        let req = actix_test::TestRequest::default()
+            .insert_header((AUTHORIZATION, "Bearer s3cret"))
+            .to_http_request();
+        assert!(authorized(&req, "s3cret"));
+        assert!(!authorized(&req, "s3cre"));
+        assert!(!authorized(
+            &actix_test::TestRequest::default().to_http_request(),
+            "s3cret"
+        ));
+    }
+
+    fn with_token(root: &Path) -> impl HttpServiceFactory {
+        let settings = MediaSettings {
+            upload_token: Some("s3cret".into()),
+            ..MediaSettings::default()
+        };
+        services(settings, root.to_path_buf(), root.join("media"))
+    }
+
+    #[actix_web::test]
+    async fn uploads_are_deduplicated_and_listed_per_post() {
+        let root = tempfile::tempdir().unwrap();
+        let app = actix_test::init_service(
+            App::new().service(web::scope("").service(with_token(root.path()))),
+        )
+        .await;
+
+        let body = || {
+            multipart(&[
+                ("file", Some("notes.txt"), "hello"),
+                ("post", None, "/blog/a"),
+            ])
+        };
+        let res = actix_test::call_service(&app, upload(body(), None).to_request()).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let res = actix_test::call_service(&app, upload(body(), Some("s3cret")).to_request()).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let created: Json = actix_test::read_body_json(res).await;
+        assert_eq!(
+            created["items"][0]["content_type"],
+            "application/octet-stream"
+        );
+
+        let res = actix_test::call_service(&app, upload(body(), Some("s3cret")).to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let req = actix_test::TestRequest::get()
+            .uri("/api/media?filter=%7B%22attached_to%22%3A%7B%22%24all%22%3A%5B%22%2Fblog%2Fa%22%5D%7D%7D")
+            .to_request();
+        let listed: Json = actix_test::call_and_read_body_json(&app, req).await;
+        assert_eq!(listed["total"], 1);
+        assert_eq!(listed["items"][0]["id"], created["items"][0]["id"]);
+    }
+
+    #[actix_web::test]
+    async fn uploaded_html_is_stored_opaque_and_served_as_a_sandboxed_download() {
+        let root = tempfile::tempdir().unwrap();
+        let app = actix_test::init_service(
+            App::new().service(web::scope("").service(with_token(root.path())).service(
+                crate::images::services(Default::default(), root.path().to_path_buf()),
+            )),
+        )
+        .await;
+
+        let body = multipart(&[(
+            "file",
+            Some("x.html"),
+            "<script>fetch('/admin/users')</script>",
+        )]);
+        let res = actix_test::call_service(&app, upload(body, Some("s3cret")).to_request()).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let created: Json = actix_test::read_body_json(res).await;
+        let path = created["items"][0]["path"].as_str().unwrap().to_string();
+        assert!(path.ends_with(".bin"), "{path}");
+
+        let req = actix_test::TestRequest::get().uri(&path).to_request();
+        let res = actix_test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let headers = res.headers();
+        assert_eq!(
+            headers.get("content-type").unwrap(),
+            "application/octet-stream"
+        );
+        assert_eq!(headers.get("content-disposition").unwrap(), "attachment");
+        assert_eq!(headers.get("x-content-type-options").unwrap(), "nosniff");
+        assert_eq!(headers.get("content-security-policy").unwrap(), "sandbox");
+    }
+}
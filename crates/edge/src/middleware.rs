@@ -0,0 +1,366 @@
+// crates/edge/src/middleware.rs
+
+// The order of the request middleware, outermost first, from settings.toml:
+//
+//   [middleware]
+//   stack = ["normalize", "access_log", "canonical", "compress", "security_headers", "maintenance", "quota", "csrf", "audit"]
+//
+// Without the table the stack is normalize → canonical → maintenance →
+// quota → csrf → audit, with access_log (see crate::access_log) after
+// normalize when settings.toml has `[access_log]`, so cookie-authenticated
+// writes are checked for a CSRF token (see crate::csrf) out of the box;
+// compression and security headers are only on when listed. A `[middleware]`
+// stack replaces the default whole, so one that leaves out `csrf` turns the
+// checks off. Start refuses a stack that names a layer twice or puts one
+// where it is known to break another (see `RULES`), and prints the order it
+// runs with.
+//
+// `security_headers` adds `X-Content-Type-Options: nosniff`, a
+// `Referrer-Policy`, and a `Content-Security-Policy` with `frame-ancestors`
+// (and `X-Frame-Options` to match) to responses that do not set them:
+//
+//   [security_headers]
+//   referrer_policy = "strict-origin-when-cross-origin"
+//   frame_ancestors = ["'self'"]
+//
+//   [security_headers.csp]
+//   default-src = ["'self'"]
+//   img-src = ["'self'", "data:"]
+//
+// A policy already on the response, such as one plugins built with
+// `appendSource` header patches, is merged into the site's rather than
+// replacing it (see serve::render::csp).
+//
+// `normalize` is the App-level NormalizeRequest, since routing must see the
+// canonical path; that is why it has to come first. The other layers sit in
+// fixed slots around the root scope, and each slot runs whichever layer the
+// site's `Stack` (app data of the scope around the slots) puts in its
+// position, so reordering needs no code change.
+
+use crate::proxy::EdgeError;
+use crate::{access_log, audit, canonical, csrf, maintenance, quota};
+use actix_web::{
+    body::{BoxBody, MessageBody},
+    dev::{fn_service, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{
+        HeaderValue, CONTENT_SECURITY_POLICY, REFERRER_POLICY, X_CONTENT_TYPE_OPTIONS,
+        X_FRAME_OPTIONS,
+    },
+    middleware::{Compress, Next},
+    web, Error, HttpRequest,
+};
+use domain::setting::{MiddlewareLayer, MiddlewareSettings, SecurityHeaderSettings};
+use serve::render::csp::ContentSecurityPolicy;
+use std::rc::Rc;
+use tracing::info;
+
+/// The stack when settings.toml has no `[middleware]`.
+pub const DEFAULT_STACK: &[MiddlewareLayer] = &[
+    MiddlewareLayer::Normalize,
+    MiddlewareLayer::Canonical,
+    MiddlewareLayer::Maintenance,
+    MiddlewareLayer::Quota,
+    MiddlewareLayer::Csrf,
+    MiddlewareLayer::Audit,
+];
+
+/// `(outer, inner, why)`: when both are in the stack, `outer` must come
+/// before `inner`.
+const RULES: &[(MiddlewareLayer, MiddlewareLayer, &str)] = &[
+    (
+        MiddlewareLayer::Canonical,
+        MiddlewareLayer::Quota,
+        "redirects to the site's address would use up tenants' request quotas",
+    ),
+    (
+        MiddlewareLayer::Maintenance,
+        MiddlewareLayer::Quota,
+        "the maintenance page would use up tenants' request and render quotas",
+    ),
+    (
+        MiddlewareLayer::Maintenance,
+        MiddlewareLayer::Audit,
+        "requests refused for maintenance would be audited as admin changes",
+    ),
+    (
+        MiddlewareLayer::Quota,
+        MiddlewareLayer::Audit,
+        "requests refused for quota would be audited as admin changes",
+    ),
+    (
+        MiddlewareLayer::Csrf,
+        MiddlewareLayer::Audit,
+        "requests refused for a missing CSRF token would be audited as admin changes",
+    ),
+    (
+        MiddlewareLayer::Compress,
+        MiddlewareLayer::Quota,
+        "compressing inside the quota would hold render slots while encoding",
+    ),
+];
+
+fn name(layer: MiddlewareLayer) -> &'static str {
+    match layer {
+        MiddlewareLayer::Normalize => "normalize",
+        MiddlewareLayer::Canonical => "canonical",
+        MiddlewareLayer::Maintenance => "maintenance",
+        MiddlewareLayer::Quota => "quota",
+        MiddlewareLayer::Audit => "audit",
+        MiddlewareLayer::Compress => "compress",
+        MiddlewareLayer::SecurityHeaders => "security_headers",
+        MiddlewareLayer::Csrf => "csrf",
+        MiddlewareLayer::AccessLog => "access_log",
+    }
+}
+
+/// Why `stack` cannot run, if it cannot.
+pub fn validate(stack: &[MiddlewareLayer]) -> Result<(), String> {
+    if stack.first() != Some(&MiddlewareLayer::Normalize) {
+        return Err(
+            "`normalize` must come first: routing, every other layer and the response cache \
+             key read the canonical path"
+                .into(),
+        );
+    }
+    for (i, layer) in stack.iter().enumerate() {
+        if stack[..i].contains(layer) {
+            return Err(format!("`{}` is listed twice", name(*layer)));
+        }
+    }
+    let position = |layer| stack.iter().position(|l| *l == layer);
+    for (outer, inner, why) in RULES {
+        if let (Some(o), Some(i)) = (position(*outer), position(*inner)) {
+            if i < o {
+                return Err(format!(
+                    "`{}` must come before `{}`: {why}",
+                    name(*outer),
+                    name(*inner)
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The stack as it reads in logs, e.g. `normalize → maintenance → quota`.
+pub fn describe(stack: &[MiddlewareLayer]) -> String {
+    stack
+        .iter()
+        .map(|l| name(*l))
+        .collect::<Vec<_>>()
+        .join(" → ")
+}
+
+/// A site's layers, in order, and what its `security_headers` layer sends.
+#[derive(Debug, Clone)]
+pub struct Stack {
+    layers: Vec<MiddlewareLayer>,
+    security_headers: SecurityHeaderSettings,
+}
+
+impl Default for Stack {
+    fn default() -> Self {
+        Self {
+            layers: DEFAULT_STACK.to_vec(),
+            security_headers: SecurityHeaderSettings::default(),
+        }
+    }
+}
+
+impl Stack {
+    /// Validate the stack in `settings` (or the default, with `access_log`
+    /// when asked for).
+    pub fn new(
+        settings: Option<&MiddlewareSettings>,
+        security_headers: Option<SecurityHeaderSettings>,
+        access_log: bool,
+    ) -> Result<Self, EdgeError> {
+        let layers = settings.map_or_else(
+            || {
+                let mut stack = DEFAULT_STACK.to_vec();
+                if access_log {
+                    stack.insert(1, MiddlewareLayer::AccessLog);
+                }
+                stack
+            },
+            |s| s.stack.clone(),
+        );
+        validate(&layers).map_err(|e| EdgeError::Config(format!("[middleware] stack: {e}")))?;
+        info!("middleware: {}", describe(&layers));
+        Ok(Self {
+            layers,
+            security_headers: security_headers.unwrap_or_default(),
+        })
+    }
+
+    /// The layer in slot `i`, counting from the outermost.
+    fn layer_at(&self, i: usize) -> Option<MiddlewareLayer> {
+        self.layers
+            .iter()
+            .copied()
+            .filter(|l| *l != MiddlewareLayer::Normalize)
+            .nth(i)
+    }
+}
+
+/// The stack of the site serving `req`; the default without one.
+fn stack(req: &HttpRequest) -> web::Data<Stack> {
+    req.app_data::<web::Data<Stack>>()
+        .cloned()
+        .unwrap_or_else(|| web::Data::new(Stack::default()))
+}
+
+/// The base `Content-Security-Policy` of the site serving `req`, when its
+/// `security_headers` layer runs; what plugins' `appendSource` patches add
+/// to.
+pub fn site_policy(req: &HttpRequest) -> Option<ContentSecurityPolicy> {
+    let stack = stack(req);
+    stack
+        .layers
+        .contains(&MiddlewareLayer::SecurityHeaders)
+        .then(|| base_policy(&stack.security_headers))
+}
+
+fn base_policy(settings: &SecurityHeaderSettings) -> ContentSecurityPolicy {
+    let mut policy = ContentSecurityPolicy::new();
+    for (directive, sources) in &settings.csp {
+        policy.add_sources(directive, sources);
+    }
+    policy.add_sources("frame-ancestors", &settings.frame_ancestors);
+    policy
+}
+
+/// `actix_web::middleware::from_fn` middleware for slot `I`; wrap slot 0
+/// last so it is the outermost.
+pub async fn slot<const I: usize>(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    match stack(req.request()).layer_at(I) {
+        Some(MiddlewareLayer::Canonical) => canonical::guard(req, next).await,
+        Some(MiddlewareLayer::Maintenance) => maintenance::guard(req, next).await,
+        Some(MiddlewareLayer::Quota) => quota::guard(req, next).await,
+        Some(MiddlewareLayer::Audit) => audit::record(req, next).await,
+        Some(MiddlewareLayer::Compress) => compress(req, next).await,
+        Some(MiddlewareLayer::SecurityHeaders) => security_headers(req, next).await,
+        Some(MiddlewareLayer::Csrf) => csrf::guard(req, next).await,
+        Some(MiddlewareLayer::AccessLog) => access_log::record(req, next).await,
+        Some(MiddlewareLayer::Normalize) | None => Ok(next.call(req).await?.map_into_boxed_body()),
+    }
+}
+
+/// actix's `Compress` around the rest of the stack.
+async fn compress<B: MessageBody + 'static>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let next = Rc::new(next);
+    let rest = fn_service(move |req: ServiceRequest| {
+        let next = next.clone();
+        async move { next.call(req).await }
+    });
+    let service = Compress::default()
+        .new_transform(rest)
+        .await
+        .map_err(|()| actix_web::error::ErrorInternalServerError("compression unavailable"))?;
+    Ok(service.call(req).await?.map_into_boxed_body())
+}
+
+/// Add the security headers the response does not set, and merge its
+/// `Content-Security-Policy` into the site's.
+async fn security_headers(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let stack = stack(req.request());
+    let settings = &stack.security_headers;
+    let mut res = next.call(req).await?.map_into_boxed_body();
+    let headers = res.headers_mut();
+    let mut defaults = vec![(X_CONTENT_TYPE_OPTIONS, "nosniff".to_owned())];
+    defaults.push((REFERRER_POLICY, settings.referrer_policy.clone()));
+    match settings.frame_ancestors.as_slice() {
+        [only] if only == "'self'" => defaults.push((X_FRAME_OPTIONS, "SAMEORIGIN".to_owned())),
+        [only] if only == "'none'" => defaults.push((X_FRAME_OPTIONS, "DENY".to_owned())),
+        _ => {}
+    }
+    for (name, value) in defaults {
+        if !headers.contains_key(&name) {
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                headers.insert(name, value);
+            }
+        }
+    }
+
+    let mut policy = base_policy(settings);
+    for existing in headers.get_all(CONTENT_SECURITY_POLICY) {
+        if let Ok(existing) = existing.to_str() {
+            policy.merge(&ContentSecurityPolicy::parse(existing));
+        }
+    }
+    if let Ok(value) = HeaderValue::from_str(&policy.to_string()) {
+        headers.insert(CONTENT_SECURITY_POLICY, value);
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use MiddlewareLayer::*;
+
+    #[test]
+    fn validation_rejects_known_bad_orderings() {
+        assert!(validate(DEFAULT_STACK).is_ok());
+        assert!(validate(&[
+            Normalize,
+            AccessLog,
+            Canonical,
+            Compress,
+            SecurityHeaders,
+            Maintenance,
+            Quota,
+            Csrf,
+            Audit
+        ])
+        .is_ok());
+
+        let err = |stack: &[MiddlewareLayer]| validate(stack).unwrap_err();
+        assert!(err(&[Maintenance, Normalize]).contains("`normalize` must come first"));
+        assert!(err(&[Normalize, Audit, Audit]).contains("listed twice"));
+        assert!(err(&[Normalize, Quota, Maintenance]).contains("quotas"));
+        assert!(err(&[Normalize, Audit, Quota]).contains("`quota` must come before `audit`"));
+        assert!(err(&[Normalize, Quota, Compress]).contains("render slots"));
+        assert!(err(&[Normalize, Quota, Canonical]).contains("`canonical` must come before"));
+        assert!(err(&[Normalize, Audit, Csrf]).contains("CSRF token"));
+        assert_eq!(
+            describe(DEFAULT_STACK),
+            "normalize → canonical → maintenance → quota → csrf → audit"
+        );
+    }
+
+    #[actix_web::test]
+    async fn security_headers_merge_into_the_response_policy() {
+        use actix_web::{middleware::from_fn, test, web, App, HttpResponse};
+
+        let app = test::init_service(App::new().wrap(from_fn(security_headers)).route(
+            "/widget",
+            web::get().to(|| async {
+                HttpResponse::Ok()
+                    .insert_header((REFERRER_POLICY, "no-referrer"))
+                    .insert_header((CONTENT_SECURITY_POLICY, "script-src https://cdn.example"))
+                    .finish()
+            }),
+        ))
+        .await;
+        let res =
+            test::call_service(&app, test::TestRequest::get().uri("/widget").to_request()).await;
+        let header = |name| res.headers().get(name).unwrap().to_str().unwrap();
+        assert_eq!(header(X_CONTENT_TYPE_OPTIONS), "nosniff");
+        assert_eq!(header(REFERRER_POLICY), "no-referrer");
+        assert_eq!(header(X_FRAME_OPTIONS), "SAMEORIGIN");
+        assert_eq!(
+            header(CONTENT_SECURITY_POLICY),
+            "frame-ancestors 'self'; script-src https://cdn.example"
+        );
+    }
+}
@@ -0,0 +1,352 @@
+// crates/edge/src/normalize.rs
+
+// Request normalization, applied before routing.
+//
+// Every request path is percent-decoded exactly once, checked, and reduced to
+// one canonical form: duplicate slashes collapsed, `.`/`..` segments removed.
+// The URI is rewritten to that form before the router sees it, and the
+// decoded result is attached to the request as a [`NormalizedRequest`] so
+// handlers never decode again. Two spellings of the same resource therefore
+// route, resolve and cache identically, and `..` or `%2e%2e` cannot climb out
+// of a theme mount.
+
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::{uri::PathAndQuery, StatusCode, Uri};
+use actix_web::{HttpMessage, HttpResponse};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use thiserror::Error;
+use tracing::debug;
+
+/// Longest raw (still encoded) path accepted.
+pub const MAX_PATH_BYTES: usize = 2048;
+
+/// Characters re-encoded when the canonical path is put back into the URI.
+const PATH_ENCODE: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'[')
+    .add(b'\\')
+    .add(b']')
+    .add(b'^')
+    .add(b'`')
+    .add(b'{')
+    .add(b'|')
+    .add(b'}');
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum NormalizeError {
+    #[error("Path is {0} bytes; the limit is {MAX_PATH_BYTES}")]
+    TooLong(usize),
+
+    #[error("Malformed percent-escape in {0}")]
+    BadEscape(&'static str),
+
+    #[error("Invalid UTF-8 in {0}")]
+    InvalidUtf8(&'static str),
+
+    #[error("Forbidden character {0:?} in path")]
+    Forbidden(char),
+}
+
+impl NormalizeError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            NormalizeError::TooLong(_) => StatusCode::URI_TOO_LONG,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+/// A request's path and query in canonical form.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NormalizedRequest {
+    /// Decoded, dot-segment-free path; always starts with `/`.
+    pub path: String,
+    /// Decoded query parameters, sorted by name then value.
+    pub query: Vec<(String, String)>,
+}
+
+impl NormalizedRequest {
+    /// Normalize a raw (encoded) path and query string.
+    pub fn parse(raw_path: &str, raw_query: &str) -> Result<Self, NormalizeError> {
+        Ok(Self {
+            path: normalize_path(raw_path)?,
+            query: normalize_query(raw_query)?,
+        })
+    }
+
+    /// The path re-encoded for use in a URI.
+    pub fn encoded_path(&self) -> String {
+        utf8_percent_encode(&self.path, PATH_ENCODE).to_string()
+    }
+
+    /// Key identifying this request's resource: the encoded path plus the
+    /// sorted query, so parameter order does not split cache entries.
+    pub fn cache_key(&self) -> String {
+        let mut key = self.encoded_path();
+        if !self.query.is_empty() {
+            let query = form_urlencoded::Serializer::new(String::new())
+                .extend_pairs(&self.query)
+                .finish();
+            key.push('?');
+            key.push_str(&query);
+        }
+        key
+    }
+
+    /// Query parameters as a map; for repeated names the last value wins.
+    pub fn query_map(&self) -> std::collections::HashMap<String, String> {
+        self.query.iter().cloned().collect()
+    }
+}
+
+/// Decode `raw` once and reduce it to its canonical form.
+pub fn normalize_path(raw: &str) -> Result<String, NormalizeError> {
+    if raw.len() > MAX_PATH_BYTES {
+        return Err(NormalizeError::TooLong(raw.len()));
+    }
+    check_escapes(raw, "path")?;
+
+    let decoded = percent_decode_str(raw)
+        .decode_utf8()
+        .map_err(|_| NormalizeError::InvalidUtf8("path"))?;
+
+    // NUL and friends confuse filesystem lookups; a backslash is a separator
+    // on some platforms and would slip past dot-segment removal.
+    if let Some(c) = decoded.chars().find(|c| c.is_control() || *c == '\\') {
+        return Err(NormalizeError::Forbidden(c));
+    }
+
+    Ok(remove_dot_segments(&decoded))
+}
+
+/// Decode and sort a raw query string.
+pub fn normalize_query(raw: &str) -> Result<Vec<(String, String)>, NormalizeError> {
+    check_escapes(raw, "query")?;
+    percent_decode_str(raw)
+        .decode_utf8()
+        .map_err(|_| NormalizeError::InvalidUtf8("query"))?;
+
+    let mut pairs: Vec<(String, String)> = form_urlencoded::parse(raw.as_bytes())
+        .into_owned()
+        .filter(|(k, _)| !k.is_empty())
+        .collect();
+    pairs.sort();
+    Ok(pairs)
+}
+
+/// Every `%` must start a two-digit hex escape.
+fn check_escapes(raw: &str, part: &'static str) -> Result<(), NormalizeError> {
+    let bytes = raw.as_bytes();
+    for (i, b) in bytes.iter().enumerate() {
+        if *b == b'%' {
+            let hex = bytes.get(i + 1..i + 3).unwrap_or_default();
+            if hex.len() != 2 || !hex.iter().all(u8::is_ascii_hexdigit) {
+                return Err(NormalizeError::BadEscape(part));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Collapse empty segments and resolve `.`/`..` (RFC 3986 §5.2.4); `..` at
+/// the root stays at the root. A trailing slash is kept.
+fn remove_dot_segments(path: &str) -> String {
+    let mut segments: Vec<&str> = Vec::new();
+    for seg in path.split('/') {
+        match seg {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            s => segments.push(s),
+        }
+    }
+
+    let trailing = path.ends_with('/') || path.ends_with("/.") || path.ends_with("/..");
+    let mut out = format!("/{}", segments.join("/"));
+    if trailing && !segments.is_empty() {
+        out.push('/');
+    }
+    out
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Middleware
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Actix middleware that normalizes each request (see the module docs).
+///
+/// Wrap the `App`, not a scope, so it runs before routing:
+/// `App::new().wrap(NormalizeRequest).service(...)`. Requests that fail
+/// normalization get a 400 (or 414 for overlong paths) without reaching a
+/// handler.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NormalizeRequest;
+
+impl<S, B> Transform<S, ServiceRequest> for NormalizeRequest
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = actix_web::Error;
+    type Transform = NormalizeRequestMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(NormalizeRequestMiddleware { service }))
+    }
+}
+
+pub struct NormalizeRequestMiddleware<S> {
+    service: S,
+}
+
+type LocalBoxFuture<T> = Pin<Box<dyn Future<Output = T>>>;
+
+impl<S, B> Service<ServiceRequest> for NormalizeRequestMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let normalized = match NormalizedRequest::parse(req.path(), req.query_string()) {
+            Ok(n) => n,
+            Err(e) => {
+                debug!("Rejecting {}: {}", req.uri(), e);
+                let res = HttpResponse::build(e.status()).body(e.to_string());
+                return Box::pin(ready(Ok(req.into_response(res))));
+            }
+        };
+
+        let encoded = normalized.encoded_path();
+        if encoded != req.path() {
+            let path_and_query = match req.query_string() {
+                "" => encoded,
+                q => format!("{encoded}?{q}"),
+            };
+            let mut parts = req.head().uri.clone().into_parts();
+            // Built from an encoded path and the original query, so both parse.
+            parts.path_and_query = PathAndQuery::try_from(path_and_query).ok();
+            if let Ok(uri) = Uri::from_parts(parts) {
+                req.match_info_mut().get_mut().update(&uri);
+                req.head_mut().uri = uri;
+            }
+        }
+
+        req.extensions_mut().insert(normalized);
+        let fut = self.service.call(req);
+        Box::pin(async move { fut.await.map(ServiceResponse::map_into_boxed_body) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test as actix_test, web, App, HttpRequest};
+
+    #[test]
+    fn paths_collapse_to_one_canonical_form() {
+        for (raw, want) in [
+            ("/", "/"),
+            ("//docs///guide/", "/docs/guide/"),
+            ("/docs/./a/../b.html", "/docs/b.html"),
+            ("/../../etc/passwd", "/etc/passwd"),
+            ("/docs/%2e%2e/%2E%2E/secret", "/secret"),
+            ("/a%20b/caf%C3%A9.html", "/a b/café.html"),
+            ("/a/..", "/"),
+            ("/a/b/.", "/a/b/"),
+        ] {
+            assert_eq!(normalize_path(raw).unwrap(), want, "{raw}");
+        }
+    }
+
+    #[test]
+    fn decodes_once_and_rejects_bad_input() {
+        // %252e is a literal "%2e" after one decode, not a dot segment.
+        assert_eq!(normalize_path("/a/%252e%252e/b").unwrap(), "/a/%2e%2e/b");
+
+        assert_eq!(
+            normalize_path("/a%zz"),
+            Err(NormalizeError::BadEscape("path"))
+        );
+        assert_eq!(
+            normalize_path("/a%"),
+            Err(NormalizeError::BadEscape("path"))
+        );
+        assert_eq!(
+            normalize_path("/%C3%28"),
+            Err(NormalizeError::InvalidUtf8("path"))
+        );
+        assert_eq!(
+            normalize_path("/a%00.html"),
+            Err(NormalizeError::Forbidden('\0'))
+        );
+        assert_eq!(
+            normalize_path("/a%5C..%5Cb"),
+            Err(NormalizeError::Forbidden('\\'))
+        );
+
+        let long = format!("/{}", "a".repeat(MAX_PATH_BYTES));
+        let err = normalize_path(&long).unwrap_err();
+        assert_eq!(err.status(), StatusCode::URI_TOO_LONG);
+    }
+
+    #[test]
+    fn cache_key_ignores_query_order() {
+        let a = NormalizedRequest::parse("/search//", "q=rust&page=2&lang=en").unwrap();
+        let b = NormalizedRequest::parse("/search/", "lang=en&page=2&q=rust").unwrap();
+        assert_eq!(a.cache_key(), b.cache_key());
+        assert_eq!(a.cache_key(), "/search/?lang=en&page=2&q=rust");
+
+        let spaced = NormalizedRequest::parse("/a%20b", "").unwrap();
+        assert_eq!(spaced.cache_key(), "/a%20b");
+    }
+
+    #[actix_web::test]
+    async fn middleware_rewrites_before_routing_and_rejects_bad_paths() {
+        async fn echo(req: HttpRequest) -> String {
+            let n = req
+                .extensions()
+                .get::<NormalizedRequest>()
+                .cloned()
+                .unwrap();
+            format!("{} {}", req.path(), n.path)
+        }
+
+        let app = actix_test::init_service(
+            App::new()
+                .wrap(NormalizeRequest)
+                .route("/docs/{page}", web::get().to(echo)),
+        )
+        .await;
+
+        let req = actix_test::TestRequest::get()
+            .uri("//docs/x/..//caf%C3%A9%20menu")
+            .to_request();
+        let body = actix_test::call_and_read_body(&app, req).await;
+        assert_eq!(body, "/docs/caf%C3%A9%20menu /docs/café menu");
+
+        let req = actix_test::TestRequest::get().uri("/docs/%FF").to_request();
+        let res = actix_test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,169 @@
+// crates/edge/src/ops.rs
+
+// Timeline of operational tasks (db::ops): startup steps, index rebuilds,
+// extension reloads, imports and rollbacks, each with its duration, outcome
+// and the core version that ran it. Admins read it at:
+//
+//   GET /admin/history?filter={"operation":"start"}&limit=50
+//                                same shape as /api/content, newest first
+//   GET /admin/history/summary   runs, failures, mean and max duration per
+//                                operation step and version
+//
+// `whispercms history` prints the same from the command line.
+
+use crate::api::content::ContentQuery;
+use crate::auth::{Admin, RequireRole};
+use crate::db::ops::{summarize, OpsError, OpsLog, OPS_LOG_FILE};
+use actix_web::{dev::HttpServiceFactory, web, HttpRequest, HttpResponse};
+use serde_json::json;
+use std::path::Path;
+use tracing::error;
+
+/// The site's operations history, for `app_data` on the root scope.
+pub fn log(root_dir: &Path) -> web::Data<OpsLog> {
+    web::Data::new(OpsLog::new(root_dir.join(OPS_LOG_FILE)))
+}
+
+/// The `/admin/history` resources, for the root of the router.
+pub fn services() -> impl HttpServiceFactory {
+    (
+        web::resource("/admin/history").route(web::get().to(timeline_handler)),
+        web::resource("/admin/history/summary").route(web::get().to(summary_handler)),
+    )
+}
+
+#[tracing::instrument(skip_all)]
+async fn timeline_handler(
+    _auth: RequireRole<Admin>,
+    log: Option<web::Data<OpsLog>>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let Some(log) = log else {
+        return HttpResponse::NotFound().finish();
+    };
+    let q = match ContentQuery::from_query_string(req.query_string()) {
+        Ok(q) => q,
+        Err(e) => return HttpResponse::BadRequest().json(json!({ "error": e.to_string() })),
+    };
+    match log.query(&q.filter, &q.opts).await {
+        Ok((total, items)) => HttpResponse::Ok().json(json!({
+            "items": items,
+            "total": total,
+            "limit": q.opts.limit,
+            "skip": q.opts.skip.unwrap_or(0),
+        })),
+        Err(OpsError::Query(e)) => {
+            HttpResponse::BadRequest().json(json!({ "error": e.to_string() }))
+        }
+        Err(e) => {
+            error!("Operations history query failed: {}", e);
+            HttpResponse::InternalServerError().json(json!({ "error": "history query failed" }))
+        }
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn summary_handler(
+    _auth: RequireRole<Admin>,
+    log: Option<web::Data<OpsLog>>,
+) -> HttpResponse {
+    let Some(log) = log else {
+        return HttpResponse::NotFound().finish();
+    };
+    let entries = web::block(move || log.entries()).await;
+    match entries {
+        Ok(Ok(entries)) => HttpResponse::Ok().json(json!({ "items": summarize(&entries) })),
+        Ok(Err(e)) => {
+            error!("Reading the operations history failed: {}", e);
+            HttpResponse::InternalServerError().json(json!({ "error": "history unavailable" }))
+        }
+        Err(e) => {
+            error!("Reading the operations history was cancelled: {}", e);
+            HttpResponse::InternalServerError().json(json!({ "error": "history unavailable" }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{self, SESSION_COOKIE};
+    use crate::db::ops::OpEntry;
+    use crate::db::users::{Role, ValidatedPassword};
+    use actix_web::{cookie::Cookie, http::StatusCode, test, App};
+    use chrono::{Duration, Utc};
+    use serde_json::Value as Json;
+
+    #[actix_web::test]
+    async fn admins_read_the_history_and_its_summary() {
+        let site = tempfile::tempdir().unwrap();
+        let history = log(site.path());
+        let began = Utc::now() - Duration::minutes(5);
+        history.record(&OpEntry::new("start", began)).unwrap();
+        history
+            .finish("migrate", Some("up"), began, Err::<(), _>("disk full"))
+            .unwrap_err();
+        history
+            .finish("migrate", Some("up"), began, Ok::<_, String>(()))
+            .unwrap();
+
+        let users = auth::store(site.path());
+        let password = ValidatedPassword::new("a long enough secret").unwrap();
+        let admin = users.create("ada", Role::Admin, &password).unwrap();
+        let editor = users.create("ed", Role::Editor, &password).unwrap();
+        let cookie = |user| {
+            Cookie::new(
+                SESSION_COOKIE,
+                users.issue_session(user, Duration::hours(1)).unwrap(),
+            )
+        };
+        let (ada, ed) = (cookie(&admin), cookie(&editor));
+        let app = test::init_service(
+            App::new()
+                .app_data(history)
+                .app_data(users.clone())
+                .service(services()),
+        )
+        .await;
+        let get = |uri: &str| test::TestRequest::get().uri(uri);
+
+        let anonymous = test::call_service(&app, get("/admin/history").to_request()).await;
+        assert_eq!(anonymous.status(), StatusCode::UNAUTHORIZED);
+        let editors = test::call_service(&app, get("/admin/history").cookie(ed).to_request()).await;
+        assert_eq!(editors.status(), StatusCode::FORBIDDEN);
+
+        let migrations: Json = test::call_and_read_body_json(
+            &app,
+            get("/admin/history?filter=%7B%22operation%22%3A%22migrate%22%7D")
+                .cookie(ada.clone())
+                .to_request(),
+        )
+        .await;
+        assert_eq!(migrations["total"], 2);
+
+        let bad = test::call_service(
+            &app,
+            get("/admin/history?filter=nope")
+                .cookie(ada.clone())
+                .to_request(),
+        )
+        .await;
+        assert_eq!(bad.status(), StatusCode::BAD_REQUEST);
+
+        let summary: Json = test::call_and_read_body_json(
+            &app,
+            get("/admin/history/summary").cookie(ada).to_request(),
+        )
+        .await;
+        let migrate = summary["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|s| s["operation"] == "migrate")
+            .unwrap();
+        assert_eq!(
+            (migrate["runs"].as_u64(), migrate["failures"].as_u64()),
+            (Some(2), Some(1))
+        );
+    }
+}
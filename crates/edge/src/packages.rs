@@ -0,0 +1,737 @@
+// crates/edge/src/packages.rs
+
+// Plugins and themes installed from packages:
+//
+//   whispercms plugin install ./site ./reading-time-1.2.0.zip
+//   whispercms theme install ./site https://example.com/journal.tar.gz \
+//       --signature https://example.com/journal.tar.gz.sig --key publisher.pem
+//   whispercms plugin list ./site
+//   whispercms plugin update ./site reading-time     # again from its source
+//   whispercms plugin remove ./site reading-time
+//
+// A package is a zip or gzipped tar of one extension, with its manifest
+// (plugin.toml or theme.toml) at the top or in the archive's only directory,
+// as in source archives from a forge. It is unpacked into a staging
+// directory, must pass discovery there (manifest, entry script) and is then
+// moved to `plugins/<id>` or `themes/<id>` in the extensions directory.
+// `install` never replaces an extension; `update` does, and keeps a
+// disabled plugin disabled. With `--signature` the archive must verify
+// against `--key`, a PEM public key (Ed25519, else RSA or EC over SHA-256):
+//
+//   openssl pkeyutl -sign -rawin -inkey publisher-key.pem \
+//       -in journal.tar.gz -out journal.tar.gz.sig
+//
+// Where each package came from is kept in `<site>/extensions.packages.json`
+// for `list` and `update`, and the site's lockfile (fs::lock), if it has one,
+// is brought up to date. A running site loads the change through hot reload
+// (fs::reload), which runs discovery again; a stopped one on its next start.
+
+use crate::db::history::{write_atomic, ExtKind};
+use crate::fs::ext::{self, PLUGIN_DISABLED_MARKER};
+use crate::fs::lock::{LockError, Lockfile, LOCK_FILE};
+use adapt::js::fetch::{FetchPolicy, FetchRequest};
+use adapt::js::HttpFetcher;
+use chrono::{DateTime, Utc};
+use domain::hex;
+use flate2::read::GzDecoder;
+use openssl::base64::decode_block;
+use openssl::hash::MessageDigest;
+use openssl::pkey::{Id, PKey};
+use openssl::sign::Verifier;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Cursor, Read};
+use std::path::{Component, Path, PathBuf};
+use thiserror::Error;
+use url::Url;
+use uuid::Uuid;
+
+/// Where packages came from, inside the site directory.
+pub const PACKAGES_FILE: &str = "./extensions.packages.json";
+
+/// Largest archive or signature downloaded.
+const MAX_DOWNLOAD: usize = 64 * 1024 * 1024;
+
+/// Most bytes a package may unpack to.
+const MAX_UNPACKED: u64 = 256 * 1024 * 1024;
+
+const DOWNLOAD_TIMEOUT_MS: u64 = 60_000;
+
+const MAX_REDIRECTS: usize = 5;
+
+#[derive(Debug, Error)]
+pub enum PackageError {
+    #[error("I/O: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("JSON: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("zip: {0}")]
+    Zip(#[from] zip::result::ZipError),
+
+    #[error("lockfile: {0}")]
+    Lock(#[from] LockError),
+
+    #[error("download: {0}")]
+    Download(String),
+
+    #[error("signature: {0}")]
+    Signature(String),
+
+    #[error("invalid package: {0}")]
+    Invalid(String),
+
+    #[error("{0} {1:?} is already installed; use update to replace it")]
+    Exists(ExtKind, String),
+
+    #[error("no {0} {1:?} is installed")]
+    NotInstalled(ExtKind, String),
+}
+
+/// Where a package is read from: a path or an `http(s)` URL each.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Source {
+    pub archive: String,
+    /// Detached signature of the archive.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    /// PEM public key the signature must verify with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<PathBuf>,
+}
+
+impl Source {
+    /// This source with local paths made absolute, so `update` finds them
+    /// from any directory.
+    fn resolved(&self) -> Result<Self, PackageError> {
+        let local = |s: &String| -> Result<String, PackageError> {
+            if is_url(s) {
+                return Ok(s.clone());
+            }
+            Ok(fs::canonicalize(s)?.to_string_lossy().into_owned())
+        };
+        if self.signature.is_some() != self.key.is_some() {
+            return Err(PackageError::Signature(
+                "give both the signature and the key".into(),
+            ));
+        }
+        Ok(Self {
+            archive: local(&self.archive)?,
+            signature: self.signature.as_ref().map(local).transpose()?,
+            key: self.key.as_deref().map(fs::canonicalize).transpose()?,
+        })
+    }
+}
+
+/// A package as recorded at install.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Installed {
+    #[serde(flatten)]
+    pub source: Source,
+    /// SHA-256 (hex) of the archive.
+    pub sha256: String,
+    pub installed_at: DateTime<Utc>,
+}
+
+/// `extensions.packages.json`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Registry {
+    #[serde(default)]
+    plugins: BTreeMap<String, Installed>,
+    #[serde(default)]
+    themes: BTreeMap<String, Installed>,
+}
+
+impl Registry {
+    fn of(&mut self, kind: ExtKind) -> &mut BTreeMap<String, Installed> {
+        match kind {
+            ExtKind::Plugin => &mut self.plugins,
+            ExtKind::Theme => &mut self.themes,
+        }
+    }
+}
+
+/// An installed plugin or theme, for `list`.
+#[derive(Debug, Clone)]
+pub struct Listed {
+    pub id: String,
+    pub name: String,
+    pub dir: PathBuf,
+    /// Whether a plugin is loaded; `None` for themes.
+    pub enabled: Option<bool>,
+    /// `None` for extensions not installed from a package.
+    pub package: Option<Installed>,
+}
+
+/// Packages of the site in `site_dir`, whose extensions are in `ext_dir`.
+#[derive(Debug, Clone)]
+pub struct Packages {
+    site_dir: PathBuf,
+    ext_dir: PathBuf,
+}
+
+impl Packages {
+    pub fn new(site_dir: impl Into<PathBuf>, ext_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            site_dir: site_dir.into(),
+            ext_dir: ext_dir.into(),
+        }
+    }
+
+    /// Install the package at `source`; its id.
+    pub fn install(&self, kind: ExtKind, source: &Source) -> Result<String, PackageError> {
+        let source = source.resolved()?;
+        let (staging, staged, id, sha256) = self.stage(kind, &source)?;
+        if self.find(kind, &id)?.is_some() || self.kind_dir(kind).join(&id).exists() {
+            return Err(PackageError::Exists(kind, id));
+        }
+
+        let dir = self.kind_dir(kind).join(&id);
+        fs::create_dir_all(self.kind_dir(kind))?;
+        fs::rename(&staged, &dir)?;
+        drop(staging);
+        self.installed(kind, &id, &dir, Some((source, sha256)))?;
+        Ok(id)
+    }
+
+    /// Replace the extension `id` with the package at `source`, else where
+    /// it was installed from.
+    pub fn update(
+        &self,
+        kind: ExtKind,
+        id: &str,
+        source: Option<&Source>,
+    ) -> Result<(), PackageError> {
+        let dir = self
+            .find(kind, id)?
+            .ok_or_else(|| PackageError::NotInstalled(kind, id.to_owned()))?;
+        let source = match source {
+            Some(source) => source.resolved()?,
+            None => self
+                .registry()?
+                .of(kind)
+                .remove(id)
+                .map(|p| p.source)
+                .ok_or_else(|| {
+                    PackageError::Invalid(format!(
+                        "{kind} {id:?} was not installed from a package; give one to update from"
+                    ))
+                })?,
+        };
+        let (staging, staged, new_id, sha256) = self.stage(kind, &source)?;
+        if new_id != id {
+            return Err(PackageError::Invalid(format!(
+                "the package holds {kind} {new_id:?}, not {id:?}"
+            )));
+        }
+        if kind == ExtKind::Plugin && !ext::plugin_enabled(&dir) {
+            fs::write(staged.join(PLUGIN_DISABLED_MARKER), "")?;
+        }
+
+        fs::rename(&dir, staging.0.join("previous"))?;
+        fs::rename(&staged, &dir)?;
+        drop(staging);
+        self.installed(kind, id, &dir, Some((source, sha256)))
+    }
+
+    /// Delete the extension `id`.
+    pub fn remove(&self, kind: ExtKind, id: &str) -> Result<(), PackageError> {
+        let dir = self
+            .find(kind, id)?
+            .ok_or_else(|| PackageError::NotInstalled(kind, id.to_owned()))?;
+        fs::remove_dir_all(&dir)?;
+        self.installed(kind, id, &dir, None)
+    }
+
+    /// Every installed extension of `kind`, packaged or not.
+    pub fn list(&self, kind: ExtKind) -> Result<Vec<Listed>, PackageError> {
+        let mut registry = self.registry()?;
+        let packages = registry.of(kind);
+        let root = self.kind_dir(kind);
+        let found: Vec<(String, String, PathBuf, Option<bool>)> = match kind {
+            ExtKind::Plugin => ext::discover_all_plugins(&root)
+                .map_err(|e| PackageError::Invalid(e.to_string()))?
+                .into_iter()
+                .map(|p| {
+                    let enabled = ext::plugin_enabled(&p.dir);
+                    (p.spec.id, p.spec.name, p.dir, Some(enabled))
+                })
+                .collect(),
+            ExtKind::Theme => ext::discover_themes(&root)
+                .map_err(|e| PackageError::Invalid(e.to_string()))?
+                .into_iter()
+                .map(|t| (t.spec.id, t.spec.name, t.dir, None))
+                .collect(),
+        };
+        let mut listed: Vec<Listed> = found
+            .into_iter()
+            .map(|(id, name, dir, enabled)| Listed {
+                package: packages.get(&id).cloned(),
+                id,
+                name,
+                dir,
+                enabled,
+            })
+            .collect();
+        listed.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(listed)
+    }
+
+    fn kind_dir(&self, kind: ExtKind) -> PathBuf {
+        self.ext_dir.join(kind.dir_name())
+    }
+
+    /// The directory of the installed extension `id`.
+    fn find(&self, kind: ExtKind, id: &str) -> Result<Option<PathBuf>, PackageError> {
+        Ok(self
+            .list(kind)?
+            .into_iter()
+            .find(|l| l.id == id)
+            .map(|l| l.dir))
+    }
+
+    fn registry(&self) -> Result<Registry, PackageError> {
+        match fs::read(self.site_dir.join(PACKAGES_FILE)) {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Registry::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Record that `id` in `dir` now came from `package`, or is gone, in the
+    /// registry and the lockfile.
+    fn installed(
+        &self,
+        kind: ExtKind,
+        id: &str,
+        dir: &Path,
+        package: Option<(Source, String)>,
+    ) -> Result<(), PackageError> {
+        let mut registry = self.registry()?;
+        match package {
+            Some((source, sha256)) => {
+                registry.of(kind).insert(
+                    id.to_owned(),
+                    Installed {
+                        source,
+                        sha256,
+                        installed_at: Utc::now(),
+                    },
+                );
+            }
+            None => {
+                registry.of(kind).remove(id);
+            }
+        }
+        write_atomic(
+            &self.site_dir.join(PACKAGES_FILE),
+            &serde_json::to_vec_pretty(&registry)?,
+        )?;
+
+        let lock_path = self.site_dir.join(LOCK_FILE);
+        if let Some(mut lock) = Lockfile::load(&lock_path)? {
+            lock.refresh_dir(&self.ext_dir, dir)?;
+            lock.save(&lock_path)?;
+        }
+        Ok(())
+    }
+
+    /// Read, check and unpack `source` into a staging directory; the
+    /// directory, the extension in it, its id and the archive's SHA-256.
+    fn stage(
+        &self,
+        kind: ExtKind,
+        source: &Source,
+    ) -> Result<(Staging, PathBuf, String, String), PackageError> {
+        let archive = read(&source.archive)?;
+        if let (Some(signature), Some(key)) = (&source.signature, &source.key) {
+            verify(&archive, &read(signature)?, &fs::read(key)?)?;
+        }
+        let sha256 = hex::encode(Sha256::digest(&archive));
+
+        let staging = Staging::new(&self.ext_dir)?;
+        let unpacked = staging.0.join("unpacked");
+        unpack(&archive, &unpacked)?;
+
+        // The manifest at the top, or in the only directory.
+        let manifest = kind.manifest_name();
+        let (root, name) = if unpacked.join(manifest).is_file() {
+            (unpacked.clone(), archive_stem(&source.archive))
+        } else {
+            let entries: Vec<_> = fs::read_dir(&unpacked)?.collect::<Result<_, _>>()?;
+            match entries.as_slice() {
+                [only] if only.path().join(manifest).is_file() => {
+                    (only.path(), only.file_name().to_string_lossy().into_owned())
+                }
+                _ => {
+                    return Err(PackageError::Invalid(format!(
+                        "no {manifest} at the top of {}",
+                        source.archive
+                    )))
+                }
+            }
+        };
+
+        // Discovery reads the extensions below a directory.
+        let check = staging.0.join("check");
+        fs::create_dir_all(&check)?;
+        fs::rename(&root, check.join(&name))?;
+        let invalid = |e: adapt::runtime::RuntimeError| PackageError::Invalid(e.to_string());
+        let found: Vec<(String, PathBuf)> = match kind {
+            ExtKind::Plugin => ext::discover_all_plugins(&check)
+                .map_err(invalid)?
+                .into_iter()
+                .map(|p| (p.spec.id, p.dir))
+                .collect(),
+            ExtKind::Theme => ext::discover_themes(&check)
+                .map_err(invalid)?
+                .into_iter()
+                .map(|t| (t.spec.id, t.dir))
+                .collect(),
+        };
+        let [(id, dir)] = <[_; 1]>::try_from(found)
+            .map_err(|_| PackageError::Invalid(format!("{} holds no {kind}", source.archive)))?;
+        if !is_id(&id) {
+            return Err(PackageError::Invalid(format!("unusable id {id:?}")));
+        }
+        Ok((staging, dir, id, sha256))
+    }
+}
+
+/// A directory removed, with whatever is left in it, when dropped.
+struct Staging(PathBuf);
+
+impl Staging {
+    fn new(ext_dir: &Path) -> io::Result<Self> {
+        let dir = ext_dir.join(format!(".staging-{}", Uuid::new_v4().simple()));
+        fs::create_dir_all(&dir)?;
+        Ok(Self(dir))
+    }
+}
+
+impl Drop for Staging {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn is_url(s: &str) -> bool {
+    s.starts_with("https://") || s.starts_with("http://")
+}
+
+/// An id usable as a directory name.
+fn is_id(id: &str) -> bool {
+    !id.is_empty()
+        && !id.starts_with('.')
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// `journal` for `…/journal.tar.gz` or `journal.zip`.
+fn archive_stem(archive: &str) -> String {
+    let name = archive
+        .trim_end_matches('/')
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default();
+    let name = name.split(['?', '#']).next().unwrap_or_default();
+    [".tar.gz", ".tgz", ".zip"]
+        .iter()
+        .find_map(|ext| name.strip_suffix(ext))
+        .unwrap_or(name)
+        .to_owned()
+}
+
+/// The bytes at a path or URL.
+fn read(source: &str) -> Result<Vec<u8>, PackageError> {
+    if !is_url(source) {
+        return Ok(fs::read(source)?);
+    }
+
+    let failed =
+        |url: &str, e: &dyn std::fmt::Display| PackageError::Download(format!("{url}: {e}"));
+    let mut url = Url::parse(source).map_err(|e| failed(source, &e))?;
+    for _ in 0..=MAX_REDIRECTS {
+        let policy = FetchPolicy {
+            hosts: url.host_str().map(str::to_owned).into_iter().collect(),
+            timeout_ms: DOWNLOAD_TIMEOUT_MS,
+            max_bytes: MAX_DOWNLOAD,
+        };
+        let request = FetchRequest {
+            url: url.to_string(),
+            ..FetchRequest::default()
+        };
+        let (response, body) = HttpFetcher
+            .fetch_bytes(&request, &policy)
+            .map_err(|e| failed(url.as_str(), &e))?;
+        match response.status {
+            200 => return Ok(body),
+            301 | 302 | 303 | 307 | 308 => {
+                let location = response
+                    .headers
+                    .get("location")
+                    .ok_or_else(|| failed(url.as_str(), &"redirect without a location"))?;
+                url = url.join(location).map_err(|e| failed(url.as_str(), &e))?;
+            }
+            status => return Err(failed(url.as_str(), &format!("HTTP {status}"))),
+        }
+    }
+    Err(failed(source, &"too many redirects"))
+}
+
+/// Check `signature` (raw or base64) of `archive` against the PEM `key`.
+fn verify(archive: &[u8], signature: &[u8], key: &[u8]) -> Result<(), PackageError> {
+    let failed = |e: openssl::error::ErrorStack| PackageError::Signature(e.to_string());
+    let key = PKey::public_key_from_pem(key).map_err(failed)?;
+    let text: String = String::from_utf8_lossy(signature)
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace())
+        .collect();
+    let base64 = !text.is_empty()
+        && text
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '/' | '='));
+    let signature = match base64.then(|| decode_block(&text).ok()).flatten() {
+        Some(decoded) => decoded,
+        None => signature.to_vec(),
+    };
+
+    let mut verifier = if key.id() == Id::ED25519 {
+        Verifier::new_without_digest(&key)
+    } else {
+        Verifier::new(MessageDigest::sha256(), &key)
+    }
+    .map_err(failed)?;
+    if verifier
+        .verify_oneshot(&signature, archive)
+        .map_err(failed)?
+    {
+        Ok(())
+    } else {
+        Err(PackageError::Signature(
+            "does not match the archive and key".into(),
+        ))
+    }
+}
+
+/// Unpack a zip or gzipped tar into `into`. Links, absolute paths and
+/// paths leaving `into` are refused.
+fn unpack(archive: &[u8], into: &Path) -> Result<(), PackageError> {
+    fs::create_dir_all(into)?;
+    let mut total = 0u64;
+    let mut put = |path: &Path, data: &mut dyn Read| -> Result<(), PackageError> {
+        let rel = inside(path)
+            .ok_or_else(|| PackageError::Invalid(format!("unsafe path {}", path.display())))?;
+        let target = into.join(rel);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = fs::File::create(&target)?;
+        total += io::copy(&mut data.take(MAX_UNPACKED - total + 1), &mut file)?;
+        if total > MAX_UNPACKED {
+            return Err(PackageError::Invalid(format!(
+                "unpacks to more than {MAX_UNPACKED} bytes"
+            )));
+        }
+        Ok(())
+    };
+
+    if archive.starts_with(b"PK\x03\x04") {
+        let mut zip = zip::ZipArchive::new(Cursor::new(archive))?;
+        for i in 0..zip.len() {
+            let mut entry = zip.by_index(i)?;
+            if entry.is_dir() {
+                continue;
+            }
+            if entry.is_symlink() {
+                return Err(PackageError::Invalid(format!("link {}", entry.name())));
+            }
+            let path = PathBuf::from(entry.name());
+            put(&path, &mut entry)?;
+        }
+    } else if archive.starts_with(&[0x1f, 0x8b]) {
+        let mut tar = tar::Archive::new(GzDecoder::new(archive));
+        for entry in tar.entries()? {
+            let mut entry = entry?;
+            let path = entry.path()?.into_owned();
+            match entry.header().entry_type() {
+                tar::EntryType::Directory | tar::EntryType::XGlobalHeader => {}
+                tar::EntryType::Regular | tar::EntryType::Continuous => put(&path, &mut entry)?,
+                _ => {
+                    return Err(PackageError::Invalid(format!(
+                        "{} is not a file or directory",
+                        path.display()
+                    )))
+                }
+            }
+        }
+    } else {
+        return Err(PackageError::Invalid(
+            "not a zip or gzipped tar archive".into(),
+        ));
+    }
+    Ok(())
+}
+
+/// `path` if it stays below the directory it is joined to.
+fn inside(path: &Path) -> Option<PathBuf> {
+    let mut rel = PathBuf::new();
+    for c in path.components() {
+        match c {
+            Component::Normal(p) => rel.push(p),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    (!rel.as_os_str().is_empty()).then_some(rel)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scaffold;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use openssl::pkey::PKey;
+    use openssl::sign::Signer;
+    use std::io::Write;
+
+    /// `dir` as a zip whose entries sit below `top/`.
+    fn zip_of(dir: &Path, top: &str) -> Vec<u8> {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for entry in walkdir::WalkDir::new(dir).min_depth(1) {
+            let entry = entry.unwrap();
+            if entry.file_type().is_file() {
+                let rel = entry.path().strip_prefix(dir).unwrap();
+                zip.start_file(
+                    format!("{top}/{}", rel.to_string_lossy()),
+                    zip::write::SimpleFileOptions::default(),
+                )
+                .unwrap();
+                zip.write_all(&fs::read(entry.path()).unwrap()).unwrap();
+            }
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn packages_install_update_and_remove_with_their_checks() {
+        let dir = tempfile::tempdir().unwrap();
+        let (site, work) = (dir.path().join("site"), dir.path().join("work"));
+        let ext_dir = site.join("extensions");
+        fs::create_dir_all(&ext_dir).unwrap();
+        Lockfile::of(&ext_dir)
+            .unwrap()
+            .save(&site.join(LOCK_FILE))
+            .unwrap();
+        scaffold::new_plugin(&work, "reading-time").unwrap();
+        let plugin_dir = work.join("plugins/reading-time");
+        let archive = work.join("reading-time.zip");
+        fs::write(&archive, zip_of(&plugin_dir, "reading-time-main")).unwrap();
+
+        // Signed with Ed25519; a wrong key is refused.
+        let key = PKey::generate_ed25519().unwrap();
+        let mut signer = Signer::new_without_digest(&key).unwrap();
+        let signature = signer
+            .sign_oneshot_to_vec(&fs::read(&archive).unwrap())
+            .unwrap();
+        fs::write(work.join("sig"), openssl::base64::encode_block(&signature)).unwrap();
+        fs::write(work.join("key.pem"), key.public_key_to_pem().unwrap()).unwrap();
+        let other = PKey::generate_ed25519().unwrap();
+        fs::write(work.join("other.pem"), other.public_key_to_pem().unwrap()).unwrap();
+        let source = |key: &str| Source {
+            archive: archive.to_string_lossy().into_owned(),
+            signature: Some(work.join("sig").to_string_lossy().into_owned()),
+            key: Some(work.join(key)),
+        };
+
+        let packages = Packages::new(&site, &ext_dir);
+        assert!(matches!(
+            packages.install(ExtKind::Plugin, &source("other.pem")),
+            Err(PackageError::Signature(_))
+        ));
+        assert_eq!(
+            packages
+                .install(ExtKind::Plugin, &source("key.pem"))
+                .unwrap(),
+            "reading-time"
+        );
+        let installed = ext_dir.join("plugins/reading-time");
+        assert!(installed.join("plugin.js").is_file());
+        assert!(matches!(
+            packages.install(ExtKind::Plugin, &source("key.pem")),
+            Err(PackageError::Exists(..))
+        ));
+        let lock = Lockfile::load(&site.join(LOCK_FILE)).unwrap().unwrap();
+        assert!(lock.verify(&ext_dir).unwrap().is_empty());
+
+        // Update from the recorded source; a disabled plugin stays so.
+        ext::set_plugin_enabled(&installed, false).unwrap();
+        fs::write(plugin_dir.join("plugin.js"), "function init(ctx) {}\n").unwrap();
+        fs::write(&archive, zip_of(&plugin_dir, "reading-time-main")).unwrap();
+        assert!(matches!(
+            packages.update(ExtKind::Plugin, "reading-time", None),
+            Err(PackageError::Signature(_))
+        ));
+        let unsigned = Source {
+            archive: archive.to_string_lossy().into_owned(),
+            signature: None,
+            key: None,
+        };
+        packages
+            .update(ExtKind::Plugin, "reading-time", Some(&unsigned))
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(installed.join("plugin.js")).unwrap(),
+            "function init(ctx) {}\n"
+        );
+        let listed = packages.list(ExtKind::Plugin).unwrap();
+        assert_eq!(listed[0].enabled, Some(false));
+        assert_eq!(
+            listed[0].package.as_ref().unwrap().source,
+            unsigned.resolved().unwrap()
+        );
+
+        // A gzipped tar escaping its directory is refused.
+        let mut tar = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::fast()));
+        let mut header = tar::Header::new_gnu();
+        header.set_size(1);
+        header.set_mode(0o644);
+        // `append_data` refuses `..`, so write the name directly.
+        header.as_gnu_mut().unwrap().name[..9].copy_from_slice(b"../evil.x");
+        header.set_cksum();
+        tar.append(&header, &b"x"[..]).unwrap();
+        fs::write(
+            work.join("evil.tar.gz"),
+            tar.into_inner().unwrap().finish().unwrap(),
+        )
+        .unwrap();
+        assert!(matches!(
+            packages.install(
+                ExtKind::Theme,
+                &Source {
+                    archive: work.join("evil.tar.gz").to_string_lossy().into_owned(),
+                    signature: None,
+                    key: None,
+                }
+            ),
+            Err(PackageError::Invalid(_))
+        ));
+        assert!(!ext_dir.join("evil.x").exists() && !site.join("evil.x").exists());
+
+        packages.remove(ExtKind::Plugin, "reading-time").unwrap();
+        assert!(!installed.exists());
+        assert!(packages.list(ExtKind::Plugin).unwrap().is_empty());
+        let lock = Lockfile::load(&site.join(LOCK_FILE)).unwrap().unwrap();
+        assert!(lock.verify(&ext_dir).unwrap().is_empty());
+        assert_eq!(
+            fs::read_dir(&ext_dir).unwrap().count(),
+            1,
+            "only plugins/ is left, no staging"
+        );
+    }
+}
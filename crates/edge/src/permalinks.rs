@@ -0,0 +1,86 @@
+// crates/edge/src/permalinks.rs
+
+// The site's permalink patterns (serve::permalinks), loaded with its content
+// (fs::index::SiteContent) from an optional `[permalinks]` table:
+//
+//   [permalinks]
+//   post = "/:year/:month/:slug/"
+//
+// The resolver finds documents at their permalinks, the sitemap and feeds
+// list them there, and the router sends requests for a document's served
+// path on to its permalink with a 301, so each page has one address.
+
+use crate::proxy::EdgeError;
+use serve::permalinks::Permalinks;
+use std::collections::BTreeMap;
+
+/// The patterns in `settings` (pattern by content type); `:lang` is left
+/// out for `default_lang`.
+pub fn load(
+    settings: &BTreeMap<String, String>,
+    default_lang: Option<&str>,
+) -> Result<Permalinks, EdgeError> {
+    let mut permalinks = Permalinks::from_settings(settings)
+        .map_err(|e| EdgeError::Config(format!("[permalinks] {e}")))?;
+    if let Some(lang) = default_lang {
+        permalinks = permalinks.with_default_lang(lang);
+    }
+    Ok(permalinks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn documents_link_at_the_site_patterns() {
+        let settings = BTreeMap::from([
+            ("post".to_owned(), "/:lang/:year/:slug/".to_owned()),
+            ("page".to_owned(), "/:section/:slug".to_owned()),
+        ]);
+        let permalinks = load(&settings, Some("en")).unwrap();
+        let post = |lang: &str| {
+            json!({ "id": "/posts/hello.html", "type": "post", "slug": "hello",
+                    "publish": { "date": "2024-03-09T10:00:00Z" },
+                    "i18n": { "lang": lang } })
+        };
+
+        assert_eq!(
+            permalinks.url_for(&post("en")).as_deref(),
+            Some("/2024/hello/")
+        );
+        assert_eq!(
+            permalinks.url_for(&post("de")).as_deref(),
+            Some("/de/2024/hello/")
+        );
+        assert_eq!(
+            permalinks
+                .url_for(&json!({ "id": "/about/team.html", "type": "page" }))
+                .as_deref(),
+            Some("/about/team")
+        );
+        // Without a pattern, or a date, a document links to its served path.
+        for doc in [
+            json!({ "id": "/notes/x.html", "type": "note" }),
+            json!({ "id": "/posts/undated.html", "type": "post", "slug": "undated" }),
+        ] {
+            assert_eq!(
+                permalinks.url_for(&doc),
+                doc["id"].as_str().map(str::to_owned)
+            );
+        }
+
+        // The default language only drops `:lang` when one is given.
+        let every_lang = load(&settings, None).unwrap();
+        assert_eq!(
+            every_lang.url_for(&post("en")).as_deref(),
+            Some("/en/2024/hello/")
+        );
+
+        let bad = BTreeMap::from([("post".to_owned(), "/:year/:title".to_owned())]);
+        assert!(
+            matches!(load(&bad, None), Err(EdgeError::Config(e)) if e.starts_with("[permalinks]"))
+        );
+    }
+}
@@ -0,0 +1,204 @@
+// crates/edge/src/plugin_routes.rs
+
+// Endpoints plugins answer themselves. A plugin.toml may declare
+//
+//   [[routes]]
+//   method = "POST"
+//   path = "/api/comments/{post}"
+//   handler = "postComment"
+//
+// and a POST to /api/comments/hello then skips content resolution, the
+// `before` hooks and the theme: the plugin's top-level `postComment`
+// function is called with the request context (`post` in
+// `ctx.request.routeParams`, the parsed body in `ctx.request.body`) and the
+// plugin's permissions, and what it leaves on `ctx.response` is sent back. A
+// JSON or HTML-string body is served as is; a template body needs a theme to
+// render it, so it is answered with a 500.
+//
+// Each site's routes (serve::resolver::PluginRoutes, in its `Site`) are
+// registered at start-up and again whenever its plugins are rediscovered. Two plugins claiming a route go
+// to the one whose id sorts first; each route left out is logged. Built-in
+// endpoints (logins, the APIs, media) win over plugin routes, and plugin
+// routes win over theme pages. Their answers are never cached.
+
+use crate::flags;
+use crate::fs::ext::DiscoveredPlugin;
+use crate::normalize::NormalizedRequest;
+use crate::router::{parse_query_params, to_http_headers, to_http_method};
+use crate::sitemap;
+use actix_web::{
+    http::{header::CONTENT_TYPE, StatusCode},
+    web, HttpMessage, HttpRequest, HttpResponse,
+};
+use adapt::runtime::plugin_actor::PluginRuntimeClient;
+use domain::content::ResolvedContent;
+use serve::render::http::{ResponseBodySpec, ResponseSpec};
+use serve::resolver::{build_request_context, PluginRoutes};
+use std::sync::{Arc, RwLock};
+use tracing::{error, info, warn};
+
+/// The plugin routes of one site, replaced whole when its plugins change.
+#[derive(Default)]
+pub struct PluginRouteTable(RwLock<Arc<PluginRoutes>>);
+
+impl PluginRouteTable {
+    /// Replace the routes with those `plugins` declare.
+    pub fn set(&self, plugins: &[DiscoveredPlugin]) {
+        let (routes, skipped) = PluginRoutes::build(
+            plugins
+                .iter()
+                .map(|p| (p.spec.id.as_str(), p.spec.routes.as_slice())),
+        );
+        for route in &skipped {
+            warn!("Skipping {}", route);
+        }
+        info!("{} plugin routes registered", routes.len());
+        match self.0.write() {
+            Ok(mut current) => *current = Arc::new(routes),
+            Err(e) => *e.into_inner() = Arc::new(routes),
+        }
+    }
+
+    fn current(&self) -> Arc<PluginRoutes> {
+        self.0
+            .read()
+            .map(|r| r.clone())
+            .unwrap_or_else(|e| e.into_inner().clone())
+    }
+}
+
+/// Answer `req` with the plugin route of its site it matches, if it matches one.
+pub async fn dispatch(
+    plugin_client: &PluginRuntimeClient,
+    req: &HttpRequest,
+    body: &web::Bytes,
+) -> Option<HttpResponse> {
+    let routes = req.app_data::<web::Data<PluginRouteTable>>()?.current();
+    if routes.is_empty() {
+        return None;
+    }
+    let (path, query_params) = match req.extensions().get::<NormalizedRequest>() {
+        Some(n) => (n.path.clone(), n.query_map()),
+        None => (
+            req.uri().path().to_string(),
+            parse_query_params(req.uri().query().unwrap_or_default()),
+        ),
+    };
+    let method = to_http_method(req.method());
+    let hit = routes.matches(&method, &path)?;
+    let (plugin_id, handler) = (hit.plugin_id.to_owned(), hit.handler.to_owned());
+
+    let mut ctx = build_request_context(
+        path,
+        method,
+        to_http_headers(req.headers()),
+        query_params,
+        ResolvedContent::empty(),
+    );
+    ctx.route_params = hit.params;
+    ctx.flags = flags::evaluate(req).await;
+    ctx.base_url = Some(sitemap::base_url(req));
+    if !body.is_empty() {
+        ctx.req_body = Some(body.clone());
+    }
+
+    let resp = match plugin_client.route(&plugin_id, &handler, ctx).await {
+        Ok(ctx) => respond(&plugin_id, ctx.response_spec),
+        Err(e) => {
+            error!("Plugin {} route {} failed: {}", plugin_id, handler, e);
+            HttpResponse::InternalServerError().body("Plugin route error")
+        }
+    };
+    Some(resp)
+}
+
+/// Fallback for paths no built-in endpoint or theme mount takes: a plugin
+/// route, else 404.
+pub async fn fallback(
+    plugin_client: web::Data<PluginRuntimeClient>,
+    req: HttpRequest,
+    body: web::Bytes,
+) -> HttpResponse {
+    dispatch(&plugin_client, &req, &body)
+        .await
+        .unwrap_or_else(|| HttpResponse::NotFound().finish())
+}
+
+fn respond(plugin_id: &str, spec: ResponseSpec) -> HttpResponse {
+    let status = StatusCode::from_u16(spec.status.as_u16()).unwrap_or(StatusCode::OK);
+    let (content_type, body) = match spec.body {
+        ResponseBodySpec::JsonValue(value) => match serde_json::to_vec(&value) {
+            Ok(bytes) => ("application/json", bytes),
+            Err(e) => {
+                error!(
+                    "Plugin {} route returned unserializable JSON: {}",
+                    plugin_id, e
+                );
+                return HttpResponse::InternalServerError().body("Plugin route error");
+            }
+        },
+        ResponseBodySpec::HtmlString(html) => ("text/html; charset=utf-8", html.into_bytes()),
+        ResponseBodySpec::HtmlTemplate { template, .. } => {
+            error!(
+                "Plugin {} route asked for template {}; only themes render templates",
+                plugin_id, template
+            );
+            return HttpResponse::InternalServerError().body("Plugin route error");
+        }
+        ResponseBodySpec::None | ResponseBodySpec::Unset if status == StatusCode::OK => {
+            return HttpResponse::NoContent().finish()
+        }
+        ResponseBodySpec::None | ResponseBodySpec::Unset => ("", Vec::new()),
+    };
+
+    let mut resp = HttpResponse::build(status);
+    for (name, value) in &spec.headers {
+        resp.append_header((name.as_str(), value.as_bytes()));
+    }
+    if !content_type.is_empty() && !spec.headers.contains_key(CONTENT_TYPE.as_str()) {
+        resp.insert_header((CONTENT_TYPE, content_type));
+    }
+    resp.body(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::to_bytes;
+    use serde_json::json;
+
+    #[actix_web::test]
+    async fn route_responses_keep_the_plugins_status_headers_and_body() {
+        let mut spec = ResponseSpec {
+            status: http::StatusCode::CREATED,
+            body: ResponseBodySpec::JsonValue(json!({ "ok": true })),
+            ..Default::default()
+        };
+        spec.set_header("Location", "/api/comments/7").unwrap();
+        let resp = respond("comments", spec);
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        assert_eq!(resp.headers().get("location").unwrap(), "/api/comments/7");
+        assert_eq!(
+            resp.headers().get("content-type").unwrap(),
+            "application/json"
+        );
+        let body = to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!(&body[..], br#"{"ok":true}"#);
+
+        let nothing = respond("comments", ResponseSpec::default());
+        assert_eq!(nothing.status(), StatusCode::NO_CONTENT);
+
+        let template = ResponseSpec {
+            body: ResponseBodySpec::HtmlTemplate {
+                template: "page.hbs".into(),
+                model: json!({}),
+                islands: Vec::new(),
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            respond("comments", template).status(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+}
@@ -0,0 +1,180 @@
+// crates/edge/src/preview.rs
+
+// Draft previews. Documents with `publish.status = "draft"` are hidden from
+// theme routes and the public Content API; a signed link shows one anyway:
+//
+//   POST /admin/preview   {"id": "/blog/a.html", "ttl_hours": 24}
+//                         → {"url": "/blog/a.html?preview=1767225600.9f2c…",
+//                            "expires_at": "2026-01-01T00:00:00Z"}
+//
+// The token is the expiry (Unix seconds) and an HMAC-SHA256 over the served
+// id and that expiry, keyed by a random secret kept in the users directory.
+// Anyone holding the link can read the draft until it expires; nothing else
+// about the site changes for them. Preview responses are never cached.
+
+use crate::audit::{self, AuditNote};
+use crate::auth::{Author, RequireRole};
+use crate::db::users::{secret_key, USERS_DB_DIR};
+use crate::fs::index::SiteContent;
+use actix_web::{dev::HttpServiceFactory, web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Duration, Utc};
+use domain::hex;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use serde_json::{json, Value as Json};
+use sha2::Sha256;
+use std::io;
+use std::path::{Path, PathBuf};
+use tracing::error;
+
+/// Query parameter carrying the token.
+pub const PREVIEW_PARAM: &str = "preview";
+
+/// Link lifetime when the request does not give one, and the longest allowed.
+pub const DEFAULT_TTL_HOURS: i64 = 24;
+pub const MAX_TTL_HOURS: i64 = 30 * 24;
+
+const KEY_FILE: &str = "preview.key";
+
+/// The `/admin/preview` resource, for the root of the router.
+pub fn services() -> impl HttpServiceFactory {
+    web::resource("/admin/preview").route(web::post().to(create_handler))
+}
+
+/// Whether `fm` (a front-matter record) is a draft.
+pub fn is_draft(fm: &Json) -> bool {
+    fm.pointer("/publish/status").and_then(Json::as_str) == Some("draft")
+}
+
+pub struct PreviewSigner {
+    key: Vec<u8>,
+}
+
+impl PreviewSigner {
+    pub fn new(key: impl Into<Vec<u8>>) -> Self {
+        Self { key: key.into() }
+    }
+
+    /// The preview key of the site under `root_dir`, created on first start.
+    pub fn open(root_dir: &Path) -> io::Result<Self> {
+        Self::load_or_create(&root_dir.join(USERS_DB_DIR).join(KEY_FILE))
+    }
+
+    /// Read the key at `path`, or write a new random one there.
+    pub fn load_or_create(path: &Path) -> io::Result<Self> {
+        secret_key(path).map(Self::new)
+    }
+
+    fn mac(&self, id: &str, expires: i64) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC takes any key length");
+        mac.update(id.as_bytes());
+        mac.update(b"\n");
+        mac.update(expires.to_string().as_bytes());
+        mac
+    }
+
+    /// A token for the document served at `id`, good until `expires`.
+    pub fn sign(&self, id: &str, expires: DateTime<Utc>) -> String {
+        let expires = expires.timestamp();
+        let sig = hex::encode(self.mac(id, expires).finalize().into_bytes());
+        format!("{expires}.{sig}")
+    }
+
+    /// Whether `token` was signed for `id` and has not expired at `now`.
+    pub fn verify(&self, id: &str, token: &str, now: DateTime<Utc>) -> bool {
+        let Some((expires, sig)) = token.split_once('.') else {
+            return false;
+        };
+        let Ok(expires) = expires.parse::<i64>() else {
+            return false;
+        };
+        let Some(sig) = hex::decode(sig) else {
+            return false;
+        };
+        expires > now.timestamp() && self.mac(id, expires).verify_slice(&sig).is_ok()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct PreviewRequest {
+    id: String,
+    #[serde(default)]
+    ttl_hours: Option<i64>,
+}
+
+#[tracing::instrument(skip_all)]
+async fn create_handler(
+    _auth: RequireRole<Author>,
+    req: HttpRequest,
+    body: web::Json<PreviewRequest>,
+) -> HttpResponse {
+    let content = SiteContent::of(&req);
+    let Some(signer) = content.preview_signer() else {
+        return HttpResponse::ServiceUnavailable().json(json!({ "error": "previews are off" }));
+    };
+    let id = format!("/{}", body.id.trim_start_matches('/'));
+    audit::annotate(
+        &req,
+        AuditNote::new("preview.create", format!("content:{id}")),
+    );
+
+    let ttl = body.ttl_hours.unwrap_or(DEFAULT_TTL_HOURS);
+    if !(1..=MAX_TTL_HOURS).contains(&ttl) {
+        return HttpResponse::BadRequest().json(json!({
+            "error": format!("ttl_hours must be between 1 and {MAX_TTL_HOURS}")
+        }));
+    }
+
+    match content
+        .lookup_front_matter_by_path(&PathBuf::from(&id))
+        .await
+    {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().json(json!({ "error": "not found" })),
+        Err(e) => {
+            error!("Looking up {} for a preview failed: {}", id, e);
+            return HttpResponse::ServiceUnavailable().json(json!({ "error": e.to_string() }));
+        }
+    }
+
+    let expires = Utc::now() + Duration::hours(ttl);
+    let token = signer.sign(&id, expires);
+    HttpResponse::Ok().json(json!({
+        "url": format!("{id}?{PREVIEW_PARAM}={token}"),
+        "expires_at": expires.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_are_bound_to_the_document_and_expire() {
+        let signer = PreviewSigner::new("k");
+        let now = Utc::now();
+        let token = signer.sign("/blog/a.html", now + Duration::hours(1));
+
+        assert!(signer.verify("/blog/a.html", &token, now));
+        assert!(!signer.verify("/blog/b.html", &token, now));
+        assert!(!signer.verify("/blog/a.html", &token, now + Duration::hours(2)));
+        assert!(!PreviewSigner::new("other").verify("/blog/a.html", &token, now));
+
+        let (expires, sig) = token.split_once('.').unwrap();
+        let later = format!("{}.{sig}", expires.parse::<i64>().unwrap() + 3600);
+        assert!(!signer.verify("/blog/a.html", &later, now));
+        assert!(!signer.verify("/blog/a.html", "garbage", now));
+    }
+
+    #[test]
+    fn the_key_is_created_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users_db/preview.key");
+        let first = PreviewSigner::load_or_create(&path).unwrap();
+        let again = PreviewSigner::load_or_create(&path).unwrap();
+
+        let expires = Utc::now() + Duration::hours(1);
+        let token = first.sign("/a.html", expires);
+        assert!(again.verify("/a.html", &token, Utc::now()));
+    }
+}
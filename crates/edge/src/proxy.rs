@@ -7,16 +7,19 @@ use adapt::runtime::RuntimeError;
 use http::{Response, StatusCode};
 use parking_lot::RwLock;
 use pingora::apps::http_app::{HttpServer as PingoraHttpServer, ServeHttp};
+use pingora::listeners::tls::TlsSettings;
 use pingora::prelude::*;
 use pingora::protocols::http::server::Session as HttpSession;
 use pingora::protocols::raw_connect::ConnectProxyError;
 use pingora::proxy::{http_proxy_service, ProxyHttp, Session as ProxySession};
-use pingora::server::Server;
+use pingora::server::configuration::ServerConf;
+use pingora::server::{RunArgs, Server, ShutdownSignal, ShutdownSignalWatch};
 use pingora::services::listening::Service as ListeningService;
 use pingora::upstreams::peer::HttpPeer;
 use serve::indexer::DocContextError;
 
 use std::{
+    collections::HashMap,
     fs,
     net::{IpAddr, SocketAddr},
     path::{Path, PathBuf},
@@ -24,13 +27,31 @@ use std::{
     time::Duration,
 };
 use thiserror::Error;
-
-use domain::setting::Settings;
-
+use tokio::sync::watch;
+
+use domain::setting::{EdgeSettings, Settings, TlsMode};
+
+use crate::acme::{self, CertStore, CHALLENGE_PREFIX};
+use crate::archetypes::ArchetypeError;
+use crate::backup::BackupError;
+use crate::components::ComponentError;
+use crate::db::history::HistoryError;
+use crate::db::migrate::MigrateError;
+use crate::db::ops::OpsError;
+use crate::db::outbox::OutboxError;
+use crate::db::redirects::RedirectStoreError;
 use crate::db::tantivy::ContentIndexError;
+use crate::db::users::UserError;
 use crate::fs::ext::ThemeBinding;
 use crate::fs::index::FrontMatterIndexError;
+use crate::fs::lock::LockError;
+use crate::import::ImportError;
+use crate::init::InitError;
+use crate::normalize::NormalizeRequest;
+use crate::packages::PackageError;
 use crate::router::build_app_router;
+use crate::scaffold::ScaffoldError;
+use crate::site::Site;
 
 /// Shared state: which loopback port is currently "active" for the WebServer.
 ///
@@ -89,11 +110,80 @@ pub enum EdgeError {
     #[error("DocContextError error: {0}")]
     DocContextError(#[from] DocContextError),
 
+    #[error("Watch error: {0}")]
+    Watch(#[from] notify::Error),
+
+    #[error("Outbox error: {0}")]
+    Outbox(#[from] OutboxError),
+
+    #[error("History error: {0}")]
+    History(#[from] HistoryError),
+
+    #[error("Import error: {0}")]
+    Import(#[from] ImportError),
+
+    #[error("User error: {0}")]
+    Users(#[from] UserError),
+
+    #[error("Components error: {0}")]
+    Components(#[from] ComponentError),
+
+    #[error("Operations history error: {0}")]
+    Ops(#[from] OpsError),
+
+    #[error("Lockfile error: {0}")]
+    Lock(#[from] LockError),
+
+    #[error("Redirects error: {0}")]
+    Redirects(#[from] RedirectStoreError),
+
+    #[error("Backup error: {0}")]
+    Backup(#[from] BackupError),
+
+    #[error("Migration error: {0}")]
+    Migrate(#[from] MigrateError),
+
+    #[error("Init error: {0}")]
+    Init(#[from] InitError),
+
+    #[error("Scaffold error: {0}")]
+    Scaffold(#[from] ScaffoldError),
+
+    #[error("Archetype error: {0}")]
+    Archetype(#[from] ArchetypeError),
+
+    #[error("Package error: {0}")]
+    Package(#[from] PackageError),
+
     #[error("Other: {0}")]
     Other(String),
 }
 
+/// Connection timeouts for the Actix WebServer, from `[edge]`.
+#[derive(Debug, Clone, Copy)]
+pub struct ServerTimeouts {
+    /// Idle time before a kept-alive connection is closed.
+    pub keep_alive: Duration,
+    /// Time a client has to send request headers.
+    pub request: Duration,
+    /// Time in-flight requests get to finish on shutdown or hot reload.
+    pub drain: Duration,
+}
+
+impl From<&EdgeSettings> for ServerTimeouts {
+    fn from(edge: &EdgeSettings) -> Self {
+        Self {
+            keep_alive: Duration::from_secs(edge.keep_alive_secs),
+            request: Duration::from_secs(edge.request_timeout_secs),
+            drain: Duration::from_secs(edge.drain_secs),
+        }
+    }
+}
+
 /// Handle for controlling the Actix WebServer (hot reload, shutdown).
+///
+/// Clones share the same backend state and server handle.
+#[derive(Clone)]
 pub struct WebServerHandle {
     current_backend: Arc<BackendState>,
     /// Handle for the *current* Actix HttpServer.
@@ -102,6 +192,7 @@ pub struct WebServerHandle {
     loopback_ip: IpAddr,
     port_a: u16,
     port_b: u16,
+    timeouts: ServerTimeouts,
 }
 
 impl WebServerHandle {
@@ -111,6 +202,7 @@ impl WebServerHandle {
         loopback_ip: IpAddr,
         port_a: u16,
         port_b: u16,
+        timeouts: ServerTimeouts,
     ) -> Self {
         Self {
             current_backend,
@@ -118,9 +210,58 @@ impl WebServerHandle {
             loopback_ip,
             port_a,
             port_b,
+            timeouts,
         }
     }
 
+    /// Start the Actix WebServer of `site` on its loopback port A.
+    #[tracing::instrument(skip_all)]
+    pub async fn start(
+        site: Arc<Site>,
+        handles: RuntimeHandles,
+        bindings: Vec<ThemeBinding>,
+    ) -> Result<Self, EdgeError> {
+        let loopback = site.settings.loopback.clone();
+        let timeouts = ServerTimeouts::from(&site.settings.edge);
+        let initial_addr = SocketAddr::from((loopback.ip, loopback.port_a));
+
+        let server = HttpServer::new(move || {
+            App::new().wrap(NormalizeRequest).service(build_app_router(
+                site.clone(),
+                handles.clone(),
+                bindings.clone(),
+            ))
+        })
+        .keep_alive(timeouts.keep_alive)
+        .client_request_timeout(timeouts.request)
+        .shutdown_timeout(timeouts.drain.as_secs())
+        .bind(initial_addr)?
+        .run();
+
+        tracing::info!("Actix WebServer started on {}", initial_addr);
+        let server_handle = server.handle();
+
+        tokio::spawn(async move {
+            if let Err(err) = server.await {
+                tracing::error!("Actix WebServer error: {err}");
+            }
+        });
+
+        Ok(Self::new(
+            Arc::new(BackendState::new(initial_addr)),
+            server_handle,
+            loopback.ip,
+            loopback.port_a,
+            loopback.port_b,
+            timeouts,
+        ))
+    }
+
+    /// Where the WebServer currently listens.
+    pub fn backend(&self) -> SocketAddr {
+        self.current_backend.get()
+    }
+
     /// Compute the "other" port (A/B flip).
     fn next_port(&self) -> u16 {
         let current = self.current_backend.get().port();
@@ -154,7 +295,12 @@ impl WebServerHandle {
 
         tracing::info!("Starting new Actix WebServer on {new_addr}");
 
-        let server = HttpServer::new(make_app).bind(new_addr)?.run();
+        let server = HttpServer::new(make_app)
+            .keep_alive(self.timeouts.keep_alive)
+            .client_request_timeout(self.timeouts.request)
+            .shutdown_timeout(self.timeouts.drain.as_secs())
+            .bind(new_addr)?
+            .run();
 
         let new_handle = server.handle();
 
@@ -179,7 +325,8 @@ impl WebServerHandle {
         Ok(())
     }
 
-    /// Gracefully stop the current WebServer.
+    /// Gracefully stop the current WebServer: stop accepting, then wait up
+    /// to the drain timeout for in-flight requests.
     #[tracing::instrument(skip_all)]
     pub async fn shutdown(&self) {
         if let Some(handle) = self.server_handle.write().take() {
@@ -188,14 +335,117 @@ impl WebServerHandle {
     }
 }
 
-/// Proxy implementation: HTTPS EdgeController → Actix WebServer.
+/// One site the edge serves: its state, its plugin and theme runtimes, and
+/// the WebServer rendering it.
+#[derive(Clone)]
+pub struct SiteEntry {
+    pub site: Arc<Site>,
+    pub handles: RuntimeHandles,
+    pub web: WebServerHandle,
+}
+
+/// The sites one process serves, picked per request by `Host`.
+///
+/// Each site lists the names it answers in `[edge] hosts`; a request for a
+/// name no site lists, or without one, goes to the first site.
+pub struct SiteRegistry {
+    sites: Vec<SiteEntry>,
+    /// Normalized host → index into `sites`.
+    hosts: HashMap<String, usize>,
+}
+
+impl SiteRegistry {
+    /// The registry of `sites`, the first being the default.
+    pub fn new(sites: Vec<SiteEntry>) -> Result<Self, EdgeError> {
+        if sites.is_empty() {
+            return Err(EdgeError::Config("no site to serve".to_string()));
+        }
+        let settings: Vec<&Settings> = sites.iter().map(|s| &s.site.settings).collect();
+        let hosts = host_table(&settings)?;
+        Ok(Self { sites, hosts })
+    }
+
+    /// The first site, whose `[edge]` and `[cert]` the edge listens with.
+    pub fn primary(&self) -> &SiteEntry {
+        &self.sites[0]
+    }
+
+    pub fn sites(&self) -> &[SiteEntry] {
+        &self.sites
+    }
+
+    /// The site answering `host`, else the first.
+    pub fn for_host(&self, host: Option<&str>) -> &SiteEntry {
+        let index = host
+            .and_then(|h| self.hosts.get(&normalize_host(h)))
+            .copied()
+            .unwrap_or(0);
+        &self.sites[index]
+    }
+}
+
+/// `host` as `[edge] hosts` are compared: lowercase, without a port or a
+/// trailing dot.
+pub fn normalize_host(host: &str) -> String {
+    let host = host.trim();
+    let host = match host.strip_prefix('[') {
+        // An IPv6 literal keeps its brackets and loses only the port.
+        Some(rest) => rest
+            .split_once(']')
+            .map_or(host, |(addr, _)| &host[..addr.len() + 2]),
+        None => host.split(':').next().unwrap_or(host),
+    };
+    host.trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// Normalized host → index of the site in `sites` naming it. Two sites may
+/// not name the same host, nor share a loopback port.
+pub fn host_table(sites: &[&Settings]) -> Result<HashMap<String, usize>, EdgeError> {
+    let mut hosts = HashMap::new();
+    let mut ports = HashMap::new();
+    for (index, settings) in sites.iter().enumerate() {
+        for host in &settings.edge.hosts {
+            let host = normalize_host(host);
+            if let Some(other) = hosts.insert(host.clone(), index) {
+                if other != index {
+                    return Err(EdgeError::Config(format!(
+                        "[edge] hosts: sites {} and {} both answer {host}",
+                        other + 1,
+                        index + 1
+                    )));
+                }
+            }
+        }
+        let loopback = &settings.loopback;
+        for port in [loopback.port_a, loopback.port_b] {
+            if let Some(other) = ports.insert((loopback.ip, port), index) {
+                if other != index || loopback.port_a == loopback.port_b {
+                    return Err(EdgeError::Config(format!(
+                        "[loopback]: sites {} and {} both use {}:{port}",
+                        other + 1,
+                        index + 1,
+                        loopback.ip
+                    )));
+                }
+            }
+        }
+    }
+    Ok(hosts)
+}
+
+/// Proxy implementation: HTTPS EdgeController → the Actix WebServer of the
+/// site the request's `Host` names.
 pub struct EdgeProxy {
-    backend: Arc<BackendState>,
+    sites: Arc<SiteRegistry>,
+    keep_alive_secs: u64,
 }
 
 impl EdgeProxy {
-    pub fn new(backend: Arc<BackendState>) -> Self {
-        Self { backend }
+    pub fn new(sites: Arc<SiteRegistry>, keep_alive_secs: u64) -> Self {
+        Self {
+            sites,
+            keep_alive_secs,
+        }
     }
 }
 
@@ -207,12 +457,28 @@ impl ProxyHttp for EdgeProxy {
         ()
     }
 
+    async fn early_request_filter(
+        &self,
+        session: &mut ProxySession,
+        _ctx: &mut Self::CTX,
+    ) -> pingora::Result<()> {
+        session.set_keepalive(Some(self.keep_alive_secs));
+        Ok(())
+    }
+
     async fn upstream_peer(
         &self,
-        _session: &mut ProxySession,
+        session: &mut ProxySession,
         _ctx: &mut Self::CTX,
     ) -> pingora::Result<Box<HttpPeer>> {
-        let addr = self.backend.get();
+        // HTTP/2 carries the host in the URI's authority, HTTP/1.1 in `Host`.
+        let req = session.req_header();
+        let host = req.uri.host().or_else(|| {
+            req.headers
+                .get(http::header::HOST)
+                .and_then(|v| v.to_str().ok())
+        });
+        let addr = self.sites.for_host(host).web.backend();
         // No TLS between Pingora and Actix (plain HTTP over loopback)
         let is_tls = false;
         let sni = addr.ip().to_string();
@@ -242,6 +508,20 @@ impl ServeHttp for RedirectApp {
     async fn response(&self, http_session: &mut HttpSession) -> Response<Vec<u8>> {
         let req = http_session.req_header();
 
+        // ACME HTTP-01 challenges must be answered over plain http.
+        if let Some(answer) = req
+            .uri
+            .path()
+            .strip_prefix(CHALLENGE_PREFIX)
+            .and_then(acme::challenge)
+        {
+            return Response::builder()
+                .status(StatusCode::OK)
+                .header("Content-Type", "application/octet-stream")
+                .body(answer.into_bytes())
+                .unwrap_or_default();
+        }
+
         // Try to reuse Host header; fallback to "localhost"
         let host = req
             .headers
@@ -276,192 +556,202 @@ impl ServeHttp for RedirectApp {
     }
 }
 
-/// Aggregate runtime: owns Pingora EdgeController and Actix WebServer.
-pub struct EdgeRuntime {
-    /// Join handle for the Pingora server thread.
-    pingora_thread: std::thread::JoinHandle<()>,
+/// Tells Pingora to shut down gracefully once `EdgeRuntime::shutdown` runs,
+/// instead of Pingora watching process signals itself: the CLI owns SIGINT
+/// and SIGTERM so the snapshot and Actix drain happen in order.
+struct EdgeStop(watch::Receiver<bool>);
 
-    /// Handle to control the WebServer (hot reload / shutdown).
-    web_handle: WebServerHandle,
+#[async_trait::async_trait]
+impl ShutdownSignalWatch for EdgeStop {
+    async fn recv(&self) -> ShutdownSignal {
+        let mut stop = self.0.clone();
+        // A dropped sender means the runtime is gone; stop as well.
+        let _ = stop.wait_for(|stop| *stop).await;
+        ShutdownSignal::GracefulTerminate
+    }
+}
+
+/// The Pingora EdgeController in front of every site's Actix WebServer.
+pub struct EdgeRuntime {
+    /// Starts Pingora's graceful shutdown.
+    stop_edge: watch::Sender<bool>,
 }
 
 impl EdgeRuntime {
-    /// Initialize everything and return a handle.
+    /// Start the edge for `sites`, with the first site's `[edge]` and
+    /// `[cert]`; its certificate must cover every site's hosts.
     ///
-    /// * Validates cert_dir contains at least one file.
-    /// * If **no TLS certificates are found**, we:
+    /// * With `[cert] mode = "manual"`, validates cert_dir contains at least one file.
+    /// * With `mode = "acme"`, loads the issued certificate (if any) and starts renewal.
+    /// * With `mode = "off"`, or if **no TLS certificates are found** in manual mode, we:
     ///   - **Do not bind any EdgeController listeners**
-    ///   - **Still start the WebServer** (on loopback) so you can configure/fix certs.
+    ///   - **Still serve on the WebServers** (on loopback) so you can configure/fix certs.
     #[tracing::instrument(skip_all)]
-    pub async fn start(
-        root: PathBuf,
-        settings: Settings,
-        handles: RuntimeHandles,
-        bindings: Vec<ThemeBinding>,
-    ) -> Result<Self, EdgeError> {
-        // Derive runtime values from Settings
+    pub async fn start(sites: Arc<SiteRegistry>) -> Result<Self, EdgeError> {
+        // Derive runtime values from the first site's Settings
+        let primary = &sites.primary().site;
+        let (root, settings) = (primary.root_dir.clone(), primary.settings.clone());
         let cert_dir = root.join(settings.cert.dir);
-        let edge_ip = settings.edge.ip;
-        let edge_http = SocketAddr::from((edge_ip, settings.edge.http_port));
-        let edge_https = SocketAddr::from((edge_ip, settings.edge.https_port));
-
-        let loopback_ip = settings.loopback.ip;
-        let web_port_a = settings.loopback.port_a;
-        let web_port_b = settings.loopback.port_b;
-        let external_https_port = settings.edge.https_port;
-
-        // 1) Cert directory check (require at least one file)
-        let has_cert = cert_dir_has_files(&cert_dir)?;
-        if !has_cert {
+        let edge = settings.edge.clone();
+
+        // 1) Certificate source: files in cert_dir, ACME, or none at all
+        let has_cert = match settings.cert.mode {
+            TlsMode::Manual => cert_dir_has_files(&cert_dir)?,
+            TlsMode::Acme | TlsMode::Off => false,
+        };
+        if settings.cert.mode == TlsMode::Manual && !has_cert {
             tracing::warn!(
                 "No TLS certificates found in {:?}; EdgeController will NOT bind listeners, \
-                 but WebServer will still start.",
+                 but the WebServers still run.",
                 cert_dir
             );
         }
-
-        // 2) Start initial Actix WebServer (port A)
-        let initial_addr = SocketAddr::from((loopback_ip, web_port_a));
-        let backend_state = Arc::new(BackendState::new(initial_addr));
-
-        let handles_for_server = handles.clone();
-        let bindings_for_server = bindings.clone();
-
-        tracing::info!("Actix WebServer started on {}", initial_addr);
-
-        let server = HttpServer::new(move || {
-            App::new().service(build_app_router(
-                root.clone(),
-                handles_for_server.clone(),
-                bindings_for_server.clone(),
-            ))
-        })
-        .bind(initial_addr)?
-        .run();
-
-        let server_handle = server.handle();
-
-        tokio::spawn(async move {
-            if let Err(err) = server.await {
-                tracing::error!("Actix WebServer error: {err}");
+        let acme_store = match (settings.cert.mode, settings.cert.acme) {
+            (TlsMode::Acme, Some(acme_settings)) if !acme_settings.domains.is_empty() => {
+                let store = CertStore::load(&cert_dir).map_err(|e| {
+                    EdgeError::Config(format!("ACME certificate in {cert_dir:?}: {e}"))
+                })?;
+                acme::spawn_renewal(acme_settings, cert_dir.clone(), store.clone());
+                Some(store)
             }
-        });
-
-        let web_handle = WebServerHandle::new(
-            backend_state.clone(),
-            server_handle,
-            loopback_ip,
-            web_port_a,
-            web_port_b,
-        );
-
-        // Copy cert_dir for the Pingora thread
-        let cert_dir_for_pingora = cert_dir.clone();
+            (TlsMode::Acme, _) => {
+                return Err(EdgeError::Config(
+                    "[cert] mode = \"acme\" needs [cert.acme] domains".to_string(),
+                ))
+            }
+            _ => None,
+        };
 
-        // 3) Start Pingora EdgeController on a dedicated thread
-        let pingora_thread = std::thread::spawn(move || {
+        // 2) Start Pingora EdgeController on a dedicated thread
+        let (stop_edge, stop_rx) = watch::channel(false);
+        std::thread::spawn(move || {
             if let Err(err) = run_pingora_edge(
-                backend_state,
-                cert_dir_for_pingora,
+                sites,
+                cert_dir,
                 has_cert,
-                edge_http,
-                edge_https,
-                external_https_port,
+                acme_store,
+                edge,
+                EdgeStop(stop_rx),
             ) {
                 eprintln!("Pingora EdgeController failed: {err}");
             }
         });
 
-        Ok(Self {
-            pingora_thread,
-            web_handle,
-        })
+        Ok(Self { stop_edge })
     }
 
-    /// Access the WebServer handle to hot-reload routes/config.
-    pub fn web_handle(&self) -> &WebServerHandle {
-        &self.web_handle
-    }
-
-    /// Stop Actix and wait for Pingora thread to exit.
-    pub async fn shutdown(self) {
-        self.web_handle.shutdown().await;
-        // For now, we don’t send any shutdown signal to Pingora; you’d usually
-        // send a process signal (SIGTERM) and let Pingora’s graceful shutdown kick in.
-        let _ = self.pingora_thread.join();
+    /// Stop taking new connections.
+    ///
+    /// Pingora closes its listeners and keeps proxying what it already
+    /// accepted for `[edge] drain_secs`, while each site's WebServer
+    /// finishes those requests (`WebServerHandle::shutdown`). The Pingora
+    /// thread is not joined, since it sits out the whole drain period even
+    /// when nothing is in flight; it ends with the process.
+    pub fn shutdown(self) {
+        let _ = self.stop_edge.send(true);
     }
 }
 
 /// Implementation detail: start Pingora EdgeController.
 #[tracing::instrument(skip_all)]
 fn run_pingora_edge(
-    backend_state: Arc<BackendState>,
+    sites: Arc<SiteRegistry>,
     cert_dir_for_pingora: PathBuf,
     has_cert: bool,
-    edge_http: SocketAddr,
-    edge_https: SocketAddr,
-    external_https_port: u16,
+    acme_store: Option<CertStore>,
+    edge: EdgeSettings,
+    stop: EdgeStop,
 ) -> Result<(), EdgeError> {
-    // Use Pingora's default options (no explicit config file / CLI opts).
+    let edge_http = SocketAddr::from((edge.ip, edge.http_port));
+    let edge_https = SocketAddr::from((edge.ip, edge.https_port));
+    let external_https_port = edge.https_port;
+
+    // Pingora's defaults (no config file / CLI opts), except that graceful
+    // shutdown keeps proxying accepted requests for the drain period.
+    let conf = ServerConf {
+        grace_period_seconds: Some(edge.drain_secs),
+        graceful_shutdown_timeout_seconds: Some(1),
+        ..ServerConf::default()
+    };
     let mut server =
-        Server::new(None::<pingora::server::configuration::Opt>).map_err(EdgeError::Pingora)?;
+        Server::new_with_opt_and_conf(None::<pingora::server::configuration::Opt>, conf);
     server.bootstrap();
 
     let mut services: Vec<Box<dyn pingora::services::Service>> = Vec::new();
 
-    if has_cert {
+    let tls = if let Some(store) = acme_store {
+        tracing::info!("Using ACME certificates for Pingora");
+        Some(TlsSettings::with_callbacks(Box::new(store))?)
+    } else if has_cert {
         // Discover a cert/key pair in the cert directory.
-        let pair = pick_cert_key_pair(&cert_dir_for_pingora)?;
-        if let Some((cert_path, key_path)) = pair {
-            tracing::info!(
-                "Using TLS cert: {} and key: {} for Pingora",
-                cert_path.display(),
-                key_path.display()
-            );
-
-            let cert_str = cert_path
-                .to_str()
-                .ok_or_else(|| EdgeError::Config("Non-UTF8 cert path".to_string()))?;
-            let key_str = key_path
-                .to_str()
-                .ok_or_else(|| EdgeError::Config("Non-UTF8 key path".to_string()))?;
-
-            // HTTPS proxy service (EdgeController → Actix) with TLS termination
-            let proxy = EdgeProxy::new(backend_state);
-            let mut proxy_service = http_proxy_service(&server.configuration, proxy);
-
-            // Bind a TLS listener on edge_https.
-            proxy_service
-                .add_tls(edge_https.to_string().as_str(), cert_str, key_str)
-                .map_err(|e| {
+        match pick_cert_key_pair(&cert_dir_for_pingora)? {
+            Some((cert_path, key_path)) => {
+                tracing::info!(
+                    "Using TLS cert: {} and key: {} for Pingora",
+                    cert_path.display(),
+                    key_path.display()
+                );
+
+                let cert_str = cert_path
+                    .to_str()
+                    .ok_or_else(|| EdgeError::Config("Non-UTF8 cert path".to_string()))?;
+                let key_str = key_path
+                    .to_str()
+                    .ok_or_else(|| EdgeError::Config("Non-UTF8 key path".to_string()))?;
+                Some(TlsSettings::intermediate(cert_str, key_str).map_err(|e| {
                     EdgeError::Config(format!("Failed to add TLS listener on {}: {e}", edge_https))
-                })?;
-
-            services.push(Box::new(proxy_service));
-
-            // HTTP → HTTPS redirect service
-            let redirect_app = RedirectApp::new(external_https_port);
-            let http_server = PingoraHttpServer::new_app(redirect_app);
-            let mut redirect_service =
-                ListeningService::new("http_redirect".to_string(), http_server);
-
-            redirect_service.add_tcp(edge_http.to_string().as_str());
-            services.push(Box::new(redirect_service));
-        } else {
-            tracing::warn!(
-                "TLS cert directory {:?} has files, but no usable (pem/crt, key) pair was found; \
-                 EdgeController will not bind listeners.",
-                cert_dir_for_pingora
-            );
+                })?)
+            }
+            None => {
+                tracing::warn!(
+                    "TLS cert directory {:?} has files, but no usable (pem/crt, key) pair was found; \
+                     EdgeController will not bind listeners.",
+                    cert_dir_for_pingora
+                );
+                None
+            }
         }
     } else {
         // No certs: don’t bind any EdgeController listeners
         tracing::warn!("Pingora EdgeController not binding any listeners (no TLS certs)");
+        None
+    };
+
+    if let Some(mut tls) = tls {
+        if edge.http2 {
+            tls.enable_h2();
+        }
+
+        // HTTPS proxy service (EdgeController → Actix) with TLS termination
+        let proxy = EdgeProxy::new(sites, edge.keep_alive_secs);
+        let mut proxy_service = http_proxy_service(&server.configuration, proxy);
+
+        // Bind a TLS listener on edge_https.
+        proxy_service.add_tls_with_settings(edge_https.to_string().as_str(), None, tls);
+
+        services.push(Box::new(proxy_service));
+
+        // HTTP → HTTPS redirect service (which also answers ACME challenges)
+        let redirect_app = RedirectApp::new(external_https_port);
+        let http_server = PingoraHttpServer::new_app(redirect_app);
+        let mut redirect_service = ListeningService::new("http_redirect".to_string(), http_server);
+
+        redirect_service.add_tcp(edge_http.to_string().as_str());
+        services.push(Box::new(redirect_service));
     }
 
     if !services.is_empty() {
         server.add_services(services);
-        server.run_forever(); // blocks in this thread
+        // Blocks in this thread until `EdgeRuntime::shutdown`.
+        #[cfg(unix)]
+        server.run(RunArgs {
+            shutdown_signal: Box::new(stop),
+        });
+        #[cfg(not(unix))]
+        {
+            let _ = stop;
+            server.run(RunArgs::default());
+        }
     } else {
         // If no services, just keep the thread alive (or return Ok(()) if you prefer).
         loop {
@@ -492,7 +782,7 @@ fn cert_dir_has_files(dir: &Path) -> Result<bool, EdgeError> {
 /// Heuristic:
 ///   * first file with extension in { "pem", "crt" } -> cert
 ///   * first file with extension == "key"            -> key
-fn pick_cert_key_pair(dir: &Path) -> Result<Option<(PathBuf, PathBuf)>, EdgeError> {
+pub(crate) fn pick_cert_key_pair(dir: &Path) -> Result<Option<(PathBuf, PathBuf)>, EdgeError> {
     if !dir.exists() {
         return Ok(None);
     }
@@ -538,3 +828,51 @@ fn pick_cert_key_pair(dir: &Path) -> Result<Option<(PathBuf, PathBuf)>, EdgeErro
         _ => None,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(hosts: &str, port_a: u16) -> Settings {
+        toml::from_str(&format!(
+            r#"
+            [cert]
+            dir = "certs"
+            mode = "off"
+            [edge]
+            ip = "0.0.0.0"
+            http_port = 80
+            https_port = 443
+            hosts = [{hosts}]
+            [loopback]
+            ip = "127.0.0.1"
+            port_a = {port_a}
+            port_b = {}
+            "#,
+            port_a + 1
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn hosts_compare_without_case_port_or_trailing_dot() {
+        assert_eq!(normalize_host("Blog.Example.COM"), "blog.example.com");
+        assert_eq!(normalize_host("blog.example.com:8443"), "blog.example.com");
+        assert_eq!(normalize_host("blog.example.com."), "blog.example.com");
+        assert_eq!(normalize_host("[::1]:8443"), "[::1]");
+    }
+
+    #[test]
+    fn each_host_names_one_site_on_its_own_ports() {
+        let blog = settings(r#""blog.example.com", "www.example.com""#, 8080);
+        let docs = settings(r#""Docs.Example.com.""#, 8090);
+        let table = host_table(&[&blog, &docs]).unwrap();
+        assert_eq!(table["www.example.com"], 0);
+        assert_eq!(table["docs.example.com"], 1);
+
+        let twin = settings(r#""docs.example.com:443""#, 8100);
+        assert!(host_table(&[&blog, &docs, &twin]).is_err());
+        let crowded = settings(r#""shop.example.com""#, 8081);
+        assert!(host_table(&[&blog, &crowded]).is_err());
+    }
+}
@@ -0,0 +1,581 @@
+// crates/edge/src/quota.rs
+
+// Per-tenant resource quotas, wrapped around the whole router next to
+// maintenance. Each tenant (request host name) gets its own token bucket
+// for the request rate, its own queue of render slots, its own share of the
+// response cache and its own budget of media bytes, so a site that floods
+// the server only waits on, and is refused by, its own limits:
+//
+//   [quotas]
+//   requests_per_sec = 50
+//   concurrent_renders = 8
+//   cache_bytes = 16777216
+//   media_bytes_per_sec = 10485760
+//
+//   [quotas.tenants."docs.example.com"]
+//   requests_per_sec = 200
+//
+// Over the rate a request gets 429 with `Retry-After`. Only requests that
+// render a page take a render slot (see `render_slot`); static files, media
+// and cache hits never wait for one, and a request that waits longer than
+// `RENDER_QUEUE_TIMEOUT` gets 503. A tenant's cached pages beyond
+// `cache_bytes` push out its own oldest ones (serve::cache::CacheShare), and
+// `/media` responses over its byte rate get 429 too.
+//
+// Hosts named under `[quotas.tenants]` always have their own limits; other
+// hosts do too until `MAX_TRACKED_TENANTS` have been seen, after which new
+// ones share the `OVERFLOW_TENANT`.
+//
+// Per-client limits (`ClientLimits`, for comments, forms and logins) key on
+// the address the connection comes from. Any client can send
+// `X-Forwarded-For`, so that header is only believed when settings.toml
+// says a proxy in front sets it:
+//
+//   [edge]
+//   trust_forwarded = true
+//
+// A site's `Quotas` and its `[edge]` table are app data of the scope around
+// the middleware layers.
+
+use actix_web::{
+    body::{BoxBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::RETRY_AFTER,
+    middleware::Next,
+    web, Error, HttpMessage, HttpRequest, HttpResponse,
+};
+use domain::setting::{EdgeSettings, QuotaSettings, TenantQuota};
+use serve::cache::CacheShare;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::debug;
+
+/// Longest a request waits for one of its tenant's render slots.
+pub const RENDER_QUEUE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Unconfigured tenants tracked individually; hosts seen after that share
+/// one entry, so made-up `Host` headers cannot grow the table without bound.
+const MAX_TRACKED_TENANTS: usize = 10_000;
+const OVERFLOW_TENANT: &str = "*";
+
+/// A site's `[quotas]` and the buckets and render slots of its tenants.
+pub struct Quotas {
+    settings: QuotaSettings,
+    tenants: Mutex<HashMap<String, Arc<Tenant>>>,
+}
+
+impl Quotas {
+    pub fn new(settings: QuotaSettings) -> Self {
+        Self {
+            settings,
+            tenants: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn tenant(&self, host: &str) -> Arc<Tenant> {
+        let mut tenants = self.tenants.lock().unwrap_or_else(|e| e.into_inner());
+        let key = if self.settings.tenants.contains_key(host)
+            || tenants.contains_key(host)
+            || tenants.len() < MAX_TRACKED_TENANTS
+        {
+            host
+        } else {
+            OVERFLOW_TENANT
+        };
+        tenants
+            .entry(key.to_owned())
+            .or_insert_with(|| Arc::new(Tenant::new(key, &self.settings.for_tenant(key))))
+            .clone()
+    }
+}
+
+/// One tenant's limits and the state of each; `guard` leaves it in the
+/// request's extensions for the routes that render or send media.
+struct Tenant {
+    host: String,
+    bucket: Option<Mutex<TokenBucket>>,
+    renders: Option<Arc<Semaphore>>,
+    cache: Option<CacheShare>,
+    media: Option<Mutex<TokenBucket>>,
+}
+
+impl Tenant {
+    fn new(host: &str, quota: &TenantQuota) -> Self {
+        Self {
+            host: host.to_owned(),
+            bucket: quota
+                .requests_per_sec
+                .map(|rate| Mutex::new(TokenBucket::new(rate, quota.burst.unwrap_or(rate)))),
+            renders: quota
+                .concurrent_renders
+                .map(|n| Arc::new(Semaphore::new(n.max(1)))),
+            cache: quota.cache_bytes.map(|max_bytes| CacheShare {
+                tenant: host.to_owned(),
+                max_bytes,
+            }),
+            media: quota
+                .media_bytes_per_sec
+                .map(|rate| Mutex::new(TokenBucket::bytes_per_sec(rate))),
+        }
+    }
+}
+
+/// The tenant `guard` found for a request.
+#[derive(Clone)]
+struct TenantOf(Arc<Tenant>);
+
+fn tenant_for(req: &HttpRequest) -> Option<Arc<Tenant>> {
+    req.extensions().get::<TenantOf>().map(|t| t.0.clone())
+}
+
+/// One of the tenant's render slots, held while the page renders; waits
+/// its turn behind the tenant's other renders. `Ok(None)` when the tenant
+/// has no limit; `Err` is the 503 to send when no slot frees up in
+/// `RENDER_QUEUE_TIMEOUT`.
+pub async fn render_slot(req: &HttpRequest) -> Result<Option<OwnedSemaphorePermit>, HttpResponse> {
+    let Some(tenant) = tenant_for(req) else {
+        return Ok(None);
+    };
+    let Some(slots) = &tenant.renders else {
+        return Ok(None);
+    };
+    match tokio::time::timeout(RENDER_QUEUE_TIMEOUT, slots.clone().acquire_owned()).await {
+        Ok(Ok(permit)) => Ok(Some(permit)),
+        _ => {
+            debug!("Tenant {} timed out waiting for a render slot", tenant.host);
+            Err(HttpResponse::ServiceUnavailable()
+                .insert_header((RETRY_AFTER, 1))
+                .finish())
+        }
+    }
+}
+
+/// Charge `bytes` of media to the request's tenant, or the 429 to send
+/// when it is over its `media_bytes_per_sec`.
+pub fn take_media(req: &HttpRequest, bytes: u64) -> Result<(), HttpResponse> {
+    let Some(tenant) = tenant_for(req) else {
+        return Ok(());
+    };
+    let Some(bucket) = &tenant.media else {
+        return Ok(());
+    };
+    let taken = bucket
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .try_take_n(Instant::now(), bytes as f64);
+    taken.map_err(|wait| {
+        debug!("Tenant {} is over its media bandwidth", tenant.host);
+        HttpResponse::TooManyRequests()
+            .insert_header((RETRY_AFTER, wait.as_secs().max(1)))
+            .finish()
+    })
+}
+
+/// The request's tenant's share of the response cache, if it has one.
+pub fn cache_share(req: &HttpRequest) -> Option<CacheShare> {
+    tenant_for(req)?.cache.clone()
+}
+
+/// The address `req` comes from: the connection's peer, or what the proxy
+/// in front forwarded when it is trusted.
+pub fn client_addr(req: &HttpRequest) -> Option<String> {
+    let trusted = req
+        .app_data::<web::Data<EdgeSettings>>()
+        .is_some_and(|edge| edge.trust_forwarded);
+    if trusted {
+        return req
+            .connection_info()
+            .realip_remote_addr()
+            .map(str::to_owned);
+    }
+    req.peer_addr().map(|addr| addr.ip().to_string())
+}
+
+/// Per-client limits on one endpoint, such as a public form: each client
+/// address gets its own bucket of `per_minute` requests. Past
+/// `MAX_TRACKED_CLIENTS` addresses the least recently seen one is dropped
+/// for each new one, so made-up addresses cannot grow the table without
+/// bound nor wipe the buckets of clients in the middle of a burst.
+#[derive(Debug)]
+pub(crate) struct ClientLimits {
+    per_minute: u32,
+    clients: Mutex<Clients>,
+}
+
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// Client buckets, least recently used first.
+#[derive(Debug, Default)]
+struct Clients {
+    /// Address → bucket and last-use tick.
+    buckets: HashMap<String, (TokenBucket, u64)>,
+    /// Last-use tick → address; the first entry is the eviction candidate.
+    order: BTreeMap<u64, String>,
+    tick: u64,
+}
+
+impl ClientLimits {
+    pub(crate) fn per_minute(per_minute: u32) -> Self {
+        Self {
+            per_minute,
+            clients: Mutex::new(Clients::default()),
+        }
+    }
+
+    /// Whether `client` has a request left, without taking it; or how many
+    /// seconds until it does.
+    pub(crate) fn check(&self, client: &str) -> Result<(), u64> {
+        let mut clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        match clients.buckets.get_mut(client) {
+            Some((bucket, _)) => bucket
+                .available(Instant::now())
+                .map_err(|wait| wait.as_secs().max(1)),
+            None => Ok(()),
+        }
+    }
+
+    /// Take one of `client`'s requests, or say how many seconds until it
+    /// may try again.
+    pub(crate) fn take(&self, client: &str) -> Result<(), u64> {
+        let mut clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        let Clients {
+            buckets,
+            order,
+            tick,
+        } = &mut *clients;
+        *tick += 1;
+        if !buckets.contains_key(client) && buckets.len() >= MAX_TRACKED_CLIENTS {
+            if let Some((_, oldest)) = order.pop_first() {
+                buckets.remove(&oldest);
+            }
+        }
+        let (bucket, used) = buckets
+            .entry(client.to_owned())
+            .or_insert_with(|| (TokenBucket::per_minute(self.per_minute), 0));
+        order.remove(used);
+        *used = *tick;
+        order.insert(*tick, client.to_owned());
+        bucket
+            .try_take(Instant::now())
+            .map_err(|wait| wait.as_secs().max(1))
+    }
+}
+
+/// Classic token bucket: `capacity` tokens, refilled at `rate` per second.
+#[derive(Debug)]
+struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: u32, burst: u32) -> Self {
+        Self::with_rate(f64::from(rate.max(1)), f64::from(burst.max(1)))
+    }
+
+    /// `per_minute` tokens a minute, all of which may be taken at once.
+    fn per_minute(per_minute: u32) -> Self {
+        let per_minute = f64::from(per_minute.max(1));
+        Self::with_rate(per_minute / 60.0, per_minute)
+    }
+
+    /// `rate` tokens (bytes) a second, a second's worth at once.
+    fn bytes_per_sec(rate: u64) -> Self {
+        let rate = rate.max(1) as f64;
+        Self::with_rate(rate, rate)
+    }
+
+    fn with_rate(rate: f64, capacity: f64) -> Self {
+        Self {
+            rate,
+            capacity,
+            tokens: capacity,
+            last: Instant::now(),
+        }
+    }
+
+    /// Take a token, or say how long until one is available.
+    fn try_take(&mut self, now: Instant) -> Result<(), Duration> {
+        self.try_take_n(now, 1.0)
+    }
+
+    /// Take `n` tokens, or say how long until they are available. More than
+    /// the bucket holds go once it is full, leaving it in debt that later
+    /// takers wait out.
+    fn try_take_n(&mut self, now: Instant, n: f64) -> Result<(), Duration> {
+        self.available_n(now, n.min(self.capacity))?;
+        self.tokens -= n;
+        Ok(())
+    }
+
+    /// Whether a token is there to take, or how long until one is.
+    fn available(&mut self, now: Instant) -> Result<(), Duration> {
+        self.available_n(now, 1.0)
+    }
+
+    fn available_n(&mut self, now: Instant, n: f64) -> Result<(), Duration> {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.last = now;
+
+        if self.tokens >= n {
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((n - self.tokens) / self.rate))
+        }
+    }
+}
+
+/// The tenant a request belongs to: its host name without the port.
+fn tenant_of(req: &ServiceRequest) -> String {
+    let info = req.connection_info();
+    let host = info.host();
+    let name = match host.rsplit_once(':') {
+        // Keep IPv6 literals such as `[::1]` whole.
+        Some((name, port)) if !name.is_empty() && port.chars().all(|c| c.is_ascii_digit()) => name,
+        _ => host,
+    };
+    name.to_ascii_lowercase()
+}
+
+/// `actix_web::middleware::from_fn` middleware.
+pub async fn guard(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let Some(quotas) = req.app_data::<web::Data<Quotas>>().cloned() else {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    };
+    let host = tenant_of(&req);
+    let tenant = quotas.tenant(&host);
+
+    if let Some(bucket) = &tenant.bucket {
+        let taken = bucket
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .try_take(Instant::now());
+        if let Err(wait) = taken {
+            debug!("Tenant {} is over its request rate", host);
+            return Ok(req.into_response(
+                HttpResponse::TooManyRequests()
+                    .insert_header((RETRY_AFTER, wait.as_secs().max(1)))
+                    .finish(),
+            ));
+        }
+    }
+
+    req.extensions_mut().insert(TenantOf(tenant));
+    Ok(next.call(req).await?.map_into_boxed_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn buckets_allow_a_burst_then_the_sustained_rate() {
+        let mut bucket = TokenBucket::new(2, 3);
+        let start = bucket.last;
+
+        for _ in 0..3 {
+            assert!(bucket.try_take(start).is_ok());
+        }
+        let wait = bucket.try_take(start).unwrap_err();
+        assert_eq!(wait, Duration::from_millis(500));
+
+        assert!(bucket.try_take(start + Duration::from_millis(500)).is_ok());
+        assert!(bucket.try_take(start + Duration::from_millis(600)).is_err());
+    }
+
+    #[test]
+    fn checking_a_client_takes_nothing() {
+        let limits = ClientLimits::per_minute(2);
+        assert!(limits.check("a").is_ok());
+        assert!(limits.take("a").is_ok());
+        assert!(limits.check("a").is_ok());
+        assert!(limits.take("a").is_ok());
+        assert!(limits.check("a").unwrap_err() >= 1);
+        assert!(limits.take("a").is_err());
+        assert!(limits.check("b").is_ok());
+    }
+
+    #[test]
+    fn a_full_client_table_drops_only_the_least_recent_client() {
+        let limits = ClientLimits::per_minute(1);
+        assert!(limits.take("busy").is_ok());
+        for i in 1..MAX_TRACKED_CLIENTS {
+            assert!(limits.take(&format!("client-{i}")).is_ok());
+        }
+        // Seen again, `busy` is now the most recent and keeps its spent
+        // bucket while a newcomer pushes out `client-1`.
+        assert!(limits.take("busy").is_err());
+        assert!(limits.take("newcomer").is_ok());
+
+        let clients = limits.clients.lock().unwrap();
+        assert_eq!(clients.buckets.len(), MAX_TRACKED_CLIENTS);
+        assert!(!clients.buckets.contains_key("client-1"));
+        assert!(clients.buckets.contains_key("client-2"));
+        drop(clients);
+        assert!(limits.take("busy").is_err());
+    }
+
+    #[test]
+    fn tenants_get_their_own_limits_and_state() {
+        let settings: QuotaSettings = toml::from_str(
+            r#"
+            requests_per_sec = 1
+            concurrent_renders = 2
+            cache_bytes = 4096
+
+            [tenants."big.example"]
+            requests_per_sec = 100
+            media_bytes_per_sec = 1000
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            settings.for_tenant("big.example"),
+            TenantQuota {
+                requests_per_sec: Some(100),
+                burst: None,
+                concurrent_renders: Some(2),
+                cache_bytes: Some(4096),
+                media_bytes_per_sec: Some(1000),
+            }
+        );
+
+        let quotas = Quotas::new(settings);
+        let now = Instant::now();
+        let take = |host: &str| {
+            let tenant = quotas.tenant(host);
+            let taken = tenant
+                .bucket
+                .as_ref()
+                .unwrap()
+                .lock()
+                .unwrap()
+                .try_take(now);
+            taken
+        };
+
+        assert!(take("a.example").is_ok());
+        assert!(take("a.example").is_err());
+        // A noisy neighbour does not use up anyone else's budget.
+        assert!(take("b.example").is_ok());
+        assert!(take("big.example").is_ok());
+        assert!(take("big.example").is_ok());
+        assert_eq!(
+            quotas.tenant("big.example").cache,
+            Some(CacheShare {
+                tenant: "big.example".into(),
+                max_bytes: 4096,
+            })
+        );
+    }
+
+    #[test]
+    fn configured_tenants_never_fall_into_the_overflow() {
+        let settings: QuotaSettings = toml::from_str(
+            r#"
+            requests_per_sec = 1
+
+            [tenants."late.example"]
+            requests_per_sec = 5
+            "#,
+        )
+        .unwrap();
+        let quotas = Quotas::new(settings);
+        for i in 0..MAX_TRACKED_TENANTS {
+            quotas.tenant(&format!("{i}.example"));
+        }
+
+        assert_eq!(quotas.tenant("made-up.example").host, OVERFLOW_TENANT);
+        assert!(Arc::ptr_eq(
+            &quotas.tenant("made-up.example"),
+            &quotas.tenant("other.example")
+        ));
+        assert_eq!(quotas.tenant("late.example").host, "late.example");
+    }
+
+    #[test]
+    fn media_bytes_may_overdraw_a_full_bucket_then_wait() {
+        let mut bucket = TokenBucket::bytes_per_sec(1000);
+        let start = bucket.last;
+
+        assert!(bucket.try_take_n(start, 400.0).is_ok());
+        assert_eq!(
+            bucket.try_take_n(start, 800.0).unwrap_err(),
+            Duration::from_millis(200)
+        );
+        // A file larger than a second's worth goes once the bucket is full,
+        // and the debt holds the next one back.
+        let later = start + Duration::from_millis(400);
+        assert!(bucket.try_take_n(later, 3000.0).is_ok());
+        assert_eq!(
+            bucket.try_take_n(later, 1.0).unwrap_err(),
+            Duration::from_millis(2001)
+        );
+    }
+
+    #[actix_web::test]
+    async fn only_rendering_takes_a_render_slot() {
+        let settings: QuotaSettings = toml::from_str("concurrent_renders = 1").unwrap();
+        let quotas = Quotas::new(settings);
+        let req = TestRequest::default().to_http_request();
+        req.extensions_mut()
+            .insert(TenantOf(quotas.tenant("a.example")));
+
+        let slot = render_slot(&req).await.unwrap();
+        assert!(slot.is_some());
+        // Media and cache shares do not wait on the render that holds it.
+        assert!(take_media(&req, 1 << 30).is_ok());
+        assert_eq!(cache_share(&req), None);
+        let renders = quotas.tenant("a.example").renders.clone().unwrap();
+        assert_eq!(renders.available_permits(), 0);
+        drop(slot);
+        assert_eq!(renders.available_permits(), 1);
+    }
+
+    #[test]
+    fn tenants_are_host_names_without_ports() {
+        let req = TestRequest::default()
+            .insert_header(("host", "Docs.Example.com:8443"))
+            .to_srv_request();
+        assert_eq!(tenant_of(&req), "docs.example.com");
+
+        let req = TestRequest::default()
+            .insert_header(("host", "[::1]"))
+            .to_srv_request();
+        assert_eq!(tenant_of(&req), "[::1]");
+    }
+
+    #[test]
+    fn clients_are_their_connection_unless_a_proxy_is_trusted() {
+        let req = || {
+            TestRequest::default()
+                .peer_addr("203.0.113.7:51000".parse().unwrap())
+                .insert_header(("x-forwarded-for", "198.51.100.1"))
+        };
+        assert_eq!(
+            client_addr(&req().to_http_request()).as_deref(),
+            Some("203.0.113.7")
+        );
+
+        let edge: EdgeSettings = toml::from_str(
+            r#"
+            ip = "127.0.0.1"
+            http_port = 80
+            https_port = 443
+            trust_forwarded = true
+            "#,
+        )
+        .unwrap();
+        let trusted = req().app_data(web::Data::new(edge)).to_http_request();
+        assert_eq!(client_addr(&trusted).as_deref(), Some("198.51.100.1"));
+    }
+}
@@ -0,0 +1,577 @@
+// crates/edge/src/redirects.rs
+
+// Redirects for moved and removed pages, checked before plugin routes and
+// content lookup (db::redirects has the rule format). Editors manage them in
+// the admin:
+//
+//   GET    /admin/api/redirects        { items }   with hit counts
+//   POST   /admin/api/redirects        { from, kind?, to?, status? } → 201
+//   PUT    /admin/api/redirects/{id}   { from, kind?, to?, status? }
+//   DELETE /admin/api/redirects/{id}   → 204
+//
+// `kind` defaults to exact and `status` to 301. Exact rules are tried first
+// and ignore a trailing slash, so `/2020/01/hello/` also catches
+// `/2020/01/hello`; then patterns, oldest first. A redirect keeps the
+// request's query string unless its target has one. Only GET and HEAD are
+// redirected; a form posted to an old address should fail loudly.
+//
+// Each site's rules are held in memory (its `Redirects`) and replaced whole
+// after each change, which also drops the response cache. Hits are counted
+// in memory too and added to the stored rules every `FLUSH_EVERY`, and
+// before the admin lists them, so a busy legacy URL costs no disk write per
+// request.
+
+use crate::audit::{self, AuditNote};
+use crate::auth::{Editor, RequireRole, Viewer};
+use crate::cache;
+use crate::db::redirects::{
+    Hits, Redirect, RedirectKind, RedirectStore, RedirectStoreError, REDIRECTS_DB_DIR,
+};
+use crate::db::store::blocking;
+use crate::fs::index::SiteContent;
+use actix_web::{
+    dev::HttpServiceFactory,
+    http::{header::LOCATION, Method, StatusCode},
+    web, HttpRequest, HttpResponse,
+};
+use chrono::Utc;
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::{json, Value as Json};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+/// How often counted hits are written to the rules.
+pub const FLUSH_EVERY: Duration = Duration::from_secs(60);
+
+/// A rule as the resolver uses it.
+#[derive(Debug, Clone)]
+struct Rule {
+    id: String,
+    to: Option<String>,
+    status: u16,
+}
+
+#[derive(Debug, Default)]
+struct Resolver {
+    /// By normalized path.
+    exact: HashMap<String, Rule>,
+    patterns: Vec<(Regex, Rule)>,
+}
+
+/// One site's redirects: its stored rules, the resolver built from them
+/// and the hits not yet written, by rule id.
+pub struct Redirects {
+    store: RedirectStore,
+    resolver: RwLock<Arc<Resolver>>,
+    hits: Mutex<Hits>,
+}
+
+/// The site's redirect rules.
+pub fn store(root_dir: &Path) -> RedirectStore {
+    RedirectStore::new(root_dir.join(REDIRECTS_DB_DIR))
+}
+
+impl Redirects {
+    /// The rules stored under `root_dir`, loaded.
+    pub fn open(root_dir: &Path) -> Self {
+        let redirects = Self {
+            store: store(root_dir),
+            resolver: Default::default(),
+            hits: Default::default(),
+        };
+        redirects.rebuild();
+        redirects
+    }
+
+    /// The stored rules.
+    pub fn store(&self) -> &RedirectStore {
+        &self.store
+    }
+
+    /// Write counted hits to the rules every `FLUSH_EVERY`.
+    pub fn start(self: &Arc<Self>) {
+        let redirects = self.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(FLUSH_EVERY).await;
+                let redirects = redirects.clone();
+                if let Ok(Err(e)) = web::block(move || redirects.flush()).await {
+                    warn!("Saving redirect hits failed: {}", e);
+                }
+            }
+        });
+    }
+
+    /// Re-read the stored rules and use them from the next request on.
+    /// Patterns that no longer compile are skipped.
+    pub fn rebuild(&self) {
+        let redirects = match self.store.list() {
+            Ok(redirects) => redirects,
+            Err(e) => {
+                error!("Reading the redirects failed: {}", e);
+                return;
+            }
+        };
+        let mut resolver = Resolver::default();
+        for redirect in redirects {
+            let rule = Rule {
+                id: redirect.id.clone(),
+                to: redirect.to.clone(),
+                status: redirect.status,
+            };
+            match redirect.kind {
+                RedirectKind::Exact => {
+                    resolver
+                        .exact
+                        .entry(normalize(&redirect.from).to_owned())
+                        .or_insert(rule);
+                }
+                RedirectKind::Pattern => match redirect.regex() {
+                    Ok(regex) => resolver.patterns.push((regex, rule)),
+                    Err(e) => warn!("Skipping redirect {}: {}", redirect.id, e),
+                },
+            }
+        }
+        match self.resolver.write() {
+            Ok(mut current) => *current = Arc::new(resolver),
+            Err(e) => *e.into_inner() = Arc::new(resolver),
+        }
+    }
+
+    /// Add the hits counted since the last flush to the stored rules.
+    pub fn flush(&self) -> Result<(), RedirectStoreError> {
+        let hits = std::mem::take(&mut *self.hits.lock().unwrap_or_else(|e| e.into_inner()));
+        if hits.is_empty() {
+            return Ok(());
+        }
+        self.store.add_hits(&hits)
+    }
+
+    /// The status and target for `path`, and the id of the rule that matched.
+    fn resolve(&self, path: &str) -> Option<(String, u16, Option<String>)> {
+        let resolver = self
+            .resolver
+            .read()
+            .map(|r| r.clone())
+            .unwrap_or_else(|e| e.into_inner().clone());
+        if let Some(rule) = resolver.exact.get(normalize(path)) {
+            return Some((rule.id.clone(), rule.status, rule.to.clone()));
+        }
+        resolver.patterns.iter().find_map(|(regex, rule)| {
+            let captures = regex.captures(path)?;
+            let to = rule.to.as_ref().map(|to| {
+                let mut expanded = String::new();
+                captures.expand(to, &mut expanded);
+                expanded
+            });
+            Some((rule.id.clone(), rule.status, to))
+        })
+    }
+
+    /// The redirect (or 410) for `req`, if a rule matches it; counts the hit.
+    pub fn respond(&self, req: &HttpRequest) -> Option<HttpResponse> {
+        if !matches!(*req.method(), Method::GET | Method::HEAD) {
+            return None;
+        }
+        let (id, status, to) = self.resolve(req.path())?;
+        {
+            let mut hits = self.hits.lock().unwrap_or_else(|e| e.into_inner());
+            let entry = hits.entry(id).or_insert((0, Utc::now()));
+            *entry = (entry.0 + 1, Utc::now());
+        }
+        let status = StatusCode::from_u16(status).unwrap_or(StatusCode::MOVED_PERMANENTLY);
+        let Some(mut location) = to else {
+            return Some(HttpResponse::build(status).finish());
+        };
+        if !location.contains('?') && !req.query_string().is_empty() {
+            location = format!("{location}?{}", req.query_string());
+        }
+        Some(
+            HttpResponse::build(status)
+                .insert_header((LOCATION, location))
+                .finish(),
+        )
+    }
+}
+
+/// `path` without a trailing slash, except for the root.
+fn normalize(path: &str) -> &str {
+    match path.trim_end_matches('/') {
+        "" => "/",
+        trimmed => trimmed,
+    }
+}
+
+/// Add a 301 from each `(old, new)` path whose old path is not redirected
+/// yet; how many were added. Used by imports, before the server runs.
+pub fn import(
+    store: &RedirectStore,
+    moves: impl IntoIterator<Item = (String, String)>,
+) -> Result<usize, RedirectStoreError> {
+    let mut added = 0;
+    for (from, to) in moves {
+        let redirect = Redirect::new(from, RedirectKind::Exact, Some(to), 301)?;
+        if clash(store, &redirect).is_ok() {
+            store.save(&redirect)?;
+            added += 1;
+        }
+    }
+    Ok(added)
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// HTTP
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// The `/admin/api/redirects` resources of the site whose `redirects` they
+/// manage, for the root of the router.
+pub fn services(redirects: web::Data<Redirects>) -> impl HttpServiceFactory {
+    (
+        web::resource("/admin/api/redirects")
+            .app_data(redirects.clone())
+            .route(web::get().to(list_handler))
+            .route(web::post().to(create_handler)),
+        web::resource("/admin/api/redirects/{id}")
+            .app_data(redirects)
+            .route(web::put().to(update_handler))
+            .route(web::delete().to(delete_handler)),
+    )
+}
+
+fn failed(e: RedirectStoreError) -> HttpResponse {
+    if let RedirectStoreError::Invalid(message) = e {
+        return HttpResponse::BadRequest().json(json!({ "error": message }));
+    }
+    crate::db::store::failed(e)
+}
+
+fn not_found(id: &str) -> HttpResponse {
+    HttpResponse::NotFound().json(json!({ "error": format!("no redirect {id}") }))
+}
+
+/// Use the stored rules from now on, and drop every cached page.
+async fn changed(redirects: web::Data<Redirects>, req: &HttpRequest) {
+    let _ = web::block(move || redirects.rebuild()).await;
+    cache::invalidate(SiteContent::of(req).cache());
+}
+
+#[derive(Debug, Deserialize)]
+struct RuleBody {
+    from: String,
+    #[serde(default)]
+    kind: Option<String>,
+    #[serde(default)]
+    to: Option<String>,
+    #[serde(default)]
+    status: Option<u16>,
+}
+
+impl RuleBody {
+    fn kind(&self) -> Result<RedirectKind, RedirectStoreError> {
+        self.kind
+            .as_deref()
+            .map_or(Ok(RedirectKind::Exact), str::parse)
+    }
+}
+
+/// An exact rule other than `id` for the same path as `redirect`.
+fn clash(store: &RedirectStore, redirect: &Redirect) -> Result<(), RedirectStoreError> {
+    if redirect.kind != RedirectKind::Exact {
+        return Ok(());
+    }
+    let taken = store.list()?.into_iter().any(|r| {
+        r.id != redirect.id
+            && r.kind == RedirectKind::Exact
+            && normalize(&r.from) == normalize(&redirect.from)
+    });
+    if taken {
+        return Err(RedirectStoreError::Invalid(format!(
+            "{:?} already redirects",
+            redirect.from
+        )));
+    }
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn list_handler(_auth: RequireRole<Viewer>, redirects: web::Data<Redirects>) -> HttpResponse {
+    match blocking(move || redirects.flush().and_then(|()| redirects.store().list())).await {
+        Ok(items) => HttpResponse::Ok().json(json!({ "items": items })),
+        Err(e) => failed(e),
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn create_handler(
+    auth: RequireRole<Editor>,
+    redirects: web::Data<Redirects>,
+    req: HttpRequest,
+    body: web::Json<RuleBody>,
+) -> HttpResponse {
+    let body = body.into_inner();
+    let created = body
+        .kind()
+        .and_then(|kind| Redirect::new(body.from, kind, body.to, body.status.unwrap_or(301)));
+    let redirect = match created {
+        Ok(redirect) => redirect,
+        Err(e) => return failed(e),
+    };
+    let saved = {
+        let (redirects, redirect) = (redirects.clone(), redirect.clone());
+        blocking(move || {
+            let store = redirects.store();
+            clash(store, &redirect)?;
+            store.save(&redirect)
+        })
+        .await
+    };
+    if let Err(e) = saved {
+        return failed(e);
+    }
+    audit::annotate(
+        &req,
+        AuditNote::new("redirect.create", format!("redirect:{}", redirect.id))
+            .with_change(&Json::Null, &json!(redirect)),
+    );
+    changed(redirects, &req).await;
+    info!(
+        "{} redirected {} ({})",
+        auth.user.username, redirect.from, redirect.status
+    );
+
+    HttpResponse::Created().json(redirect)
+}
+
+#[tracing::instrument(skip_all)]
+async fn update_handler(
+    auth: RequireRole<Editor>,
+    redirects: web::Data<Redirects>,
+    req: HttpRequest,
+    id: web::Path<String>,
+    body: web::Json<RuleBody>,
+) -> HttpResponse {
+    let id = id.into_inner();
+    let body = body.into_inner();
+    let kind = match body.kind() {
+        Ok(kind) => kind,
+        Err(e) => return failed(e),
+    };
+    let result = {
+        let (redirects, id) = (redirects.clone(), id.clone());
+        blocking(move || {
+            // Counted hits first, so the rewrite keeps them.
+            redirects.flush()?;
+            let store = redirects.store();
+            let Some(before) = store.get(&id)? else {
+                return Ok(None);
+            };
+            let after = Redirect {
+                from: body.from,
+                kind,
+                to: body.to,
+                status: body.status.unwrap_or(before.status),
+                ..before.clone()
+            };
+            after.validate()?;
+            clash(store, &after)?;
+            store.save(&after)?;
+            Ok(Some((before, after)))
+        })
+        .await
+    };
+    let (before, after) = match result {
+        Ok(Some(change)) => change,
+        Ok(None) => return not_found(&id),
+        Err(e) => return failed(e),
+    };
+    audit::annotate(
+        &req,
+        AuditNote::new("redirect.update", format!("redirect:{id}"))
+            .with_change(&json!(before), &json!(after)),
+    );
+    changed(redirects, &req).await;
+    info!("{} changed redirect {}", auth.user.username, id);
+
+    HttpResponse::Ok().json(after)
+}
+
+#[tracing::instrument(skip_all)]
+async fn delete_handler(
+    auth: RequireRole<Editor>,
+    redirects: web::Data<Redirects>,
+    req: HttpRequest,
+    id: web::Path<String>,
+) -> HttpResponse {
+    let id = id.into_inner();
+    let result = {
+        let (redirects, id) = (redirects.clone(), id.clone());
+        blocking(move || {
+            let before = redirects.store().get(&id)?;
+            redirects.store().delete(&id)?;
+            Ok(before)
+        })
+        .await
+    };
+    let before = match result {
+        Ok(Some(before)) => before,
+        Ok(None) => return not_found(&id),
+        Err(e) => return failed(e),
+    };
+    audit::annotate(
+        &req,
+        AuditNote::new("redirect.delete", format!("redirect:{id}"))
+            .with_change(&json!(before), &Json::Null),
+    );
+    changed(redirects, &req).await;
+    info!("{} deleted redirect {}", auth.user.username, id);
+
+    HttpResponse::NoContent().finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{self, SESSION_COOKIE};
+    use crate::db::users::{Role, ValidatedPassword};
+    use actix_web::cookie::Cookie;
+    use actix_web::{middleware::from_fn, test as actix_test, App};
+
+    #[actix_web::test]
+    async fn rules_redirect_count_hits_and_are_managed_by_editors() {
+        let site = tempfile::tempdir().unwrap();
+        let redirects = web::Data::new(Redirects::open(site.path()));
+
+        let users = auth::store(site.path());
+        let password = ValidatedPassword::new("a long enough secret").unwrap();
+        let mut cookies = Vec::new();
+        for (name, role) in [("ed", Role::Editor), ("vi", Role::Viewer)] {
+            let user = users.create(name, role, &password).unwrap();
+            let token = users
+                .issue_session(&user, chrono::Duration::hours(1))
+                .unwrap();
+            cookies.push(Cookie::new(SESSION_COOKIE, token));
+        }
+        let (editor, viewer) = (cookies[0].clone(), cookies[1].clone());
+        let app = actix_test::init_service(
+            App::new().service(
+                web::scope("")
+                    .app_data(users)
+                    .app_data(audit::log(site.path()))
+                    .service(services(redirects.clone()))
+                    .default_service(web::to(move |req: HttpRequest| {
+                        let redirects = redirects.clone();
+                        async move {
+                            redirects
+                                .respond(&req)
+                                .unwrap_or_else(|| HttpResponse::Ok().body("page"))
+                        }
+                    }))
+                    .wrap(from_fn(audit::record)),
+            ),
+        )
+        .await;
+
+        let create = |cookie: &Cookie<'static>, body: Json| {
+            actix_test::TestRequest::post()
+                .uri("/admin/api/redirects")
+                .cookie(cookie.clone())
+                .set_json(body)
+                .to_request()
+        };
+        let exact = json!({ "from": "/2020/01/hello/", "to": "/posts/hello.html" });
+        let res = actix_test::call_service(&app, create(&viewer, exact.clone())).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        let res = actix_test::call_service(
+            &app,
+            create(&editor, json!({ "from": "/a", "to": "/b", "status": 307 })),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let created: Json =
+            actix_test::call_and_read_body_json(&app, create(&editor, exact.clone())).await;
+        assert_eq!(created["status"], 301);
+        let res = actix_test::call_service(
+            &app,
+            create(&editor, json!({ "from": "/2020/01/hello", "to": "/x" })),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        for body in [
+            json!({ "from": "/archives/(\\d+)", "kind": "pattern", "to": "/posts/$1.html" }),
+            json!({ "from": "/drafts/.*", "kind": "pattern", "status": 410 }),
+        ] {
+            let res = actix_test::call_service(&app, create(&editor, body)).await;
+            assert_eq!(res.status(), StatusCode::CREATED);
+        }
+
+        let get = |uri: &str| actix_test::TestRequest::get().uri(uri).to_request();
+        let res = actix_test::call_service(&app, get("/2020/01/hello?utm=x")).await;
+        assert_eq!(res.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(
+            res.headers().get(LOCATION).unwrap(),
+            "/posts/hello.html?utm=x"
+        );
+        let res = actix_test::call_service(&app, get("/archives/42")).await;
+        assert_eq!(res.headers().get(LOCATION).unwrap(), "/posts/42.html");
+        let res = actix_test::call_service(&app, get("/drafts/secret")).await;
+        assert_eq!(res.status(), StatusCode::GONE);
+        let res = actix_test::call_service(&app, get("/archives/x")).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let post = actix_test::TestRequest::post()
+            .uri("/2020/01/hello/")
+            .to_request();
+        assert_eq!(
+            actix_test::call_service(&app, post).await.status(),
+            StatusCode::OK
+        );
+
+        let list = || {
+            actix_test::TestRequest::get()
+                .uri("/admin/api/redirects")
+                .cookie(viewer.clone())
+                .to_request()
+        };
+        let listed: Json = actix_test::call_and_read_body_json(&app, list()).await;
+        assert_eq!(listed["items"].as_array().unwrap().len(), 3);
+        assert_eq!(listed["items"][0]["hits"], 1);
+        assert!(listed["items"][0]["last_hit"].is_string());
+
+        let id = created["id"].as_str().unwrap();
+        let req = actix_test::TestRequest::put()
+            .uri(&format!("/admin/api/redirects/{id}"))
+            .cookie(editor.clone())
+            .set_json(json!({ "from": "/2020/01/hello/", "to": "/hello.html", "status": 302 }))
+            .to_request();
+        let updated: Json = actix_test::call_and_read_body_json(&app, req).await;
+        assert_eq!(updated["hits"], 1);
+        let res = actix_test::call_service(&app, get("/2020/01/hello/")).await;
+        assert_eq!(res.status(), StatusCode::FOUND);
+        assert_eq!(res.headers().get(LOCATION).unwrap(), "/hello.html");
+
+        let delete = || {
+            actix_test::TestRequest::delete()
+                .uri(&format!("/admin/api/redirects/{id}"))
+                .cookie(editor.clone())
+                .to_request()
+        };
+        let res = actix_test::call_service(&app, delete()).await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        let res = actix_test::call_service(&app, delete()).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        let res = actix_test::call_service(&app, get("/2020/01/hello/")).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let trail =
+            crate::db::audit::AuditLog::new(site.path().join(crate::db::audit::AUDIT_LOG_FILE))
+                .entries()
+                .unwrap();
+        let actions: Vec<_> = trail.iter().map(|e| e.action.as_str()).collect();
+        for action in ["redirect.create", "redirect.update", "redirect.delete"] {
+            assert!(actions.contains(&action), "{actions:?}");
+        }
+    }
+}
@@ -0,0 +1,227 @@
+// crates/edge/src/related.rs
+
+// Related posts (serve::related) for theme requests. A document's ranking
+// is worked out the first time it renders, from one query for the published
+// documents sharing a tag or category with it, and kept with the
+// front-matter index until the content changes:
+//
+// - a re-indexed document drops its own ranking, every ranking it is in,
+//   and every ranking of a document sharing a term with it now;
+// - a removed one drops its own and every ranking it is in;
+// - clearing the index drops them all.
+//
+// Everything else keeps its ranking, so an edit costs one recomputation per
+// affected page rather than one per page. The ranking goes into the theme's
+// template model as `related` unless the theme set that itself. Configured
+// by an optional `[related]` table in settings.toml:
+//
+//   [related]
+//   limit = 5
+//   tag_weight = 1.0
+//   category_weight = 2.0
+//   half_life_days = 365
+
+use crate::api::content::ContentQuery;
+use crate::fs::index::SiteContent;
+use adapt::mql::{parser::parse_filter, FindOptions};
+use chrono::Utc;
+use domain::setting::RelatedSettings;
+use serde_json::{json, Value as Json};
+use serve::related::{entry, rank, Terms};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use tracing::warn;
+
+struct Ranking {
+    terms: Terms,
+    related: Arc<Vec<Json>>,
+}
+
+impl Ranking {
+    fn includes(&self, id: &str) -> bool {
+        self.related.iter().any(|r| r["id"] == id)
+    }
+}
+
+/// One site's rankings, kept with its front-matter index.
+#[derive(Default)]
+pub struct Rankings {
+    /// Rankings by document id.
+    rankings: RwLock<HashMap<String, Ranking>>,
+    /// Bumped by every change, so a ranking computed across one is not kept.
+    generation: AtomicU64,
+}
+
+impl Rankings {
+    fn update(&self, f: impl FnOnce(&mut HashMap<String, Ranking>)) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        match self.rankings.write() {
+            Ok(mut rankings) => f(&mut rankings),
+            Err(e) => f(&mut e.into_inner()),
+        }
+    }
+
+    /// The document `id` was indexed as `doc`.
+    pub fn changed(&self, id: &str, doc: &Json) {
+        let terms = Terms::of(doc);
+        self.update(|rankings| drop_affected(rankings, id, Some(&terms)));
+    }
+
+    /// The document `id` was dropped from the index.
+    pub fn removed(&self, id: &str) {
+        self.update(|rankings| drop_affected(rankings, id, None));
+    }
+
+    /// The index was cleared.
+    pub fn clear(&self) {
+        self.update(HashMap::clear);
+    }
+}
+
+/// Drop the rankings a change to `id` may alter: its own, those it is in,
+/// and those of documents sharing a term with its new `terms`.
+fn drop_affected(rankings: &mut HashMap<String, Ranking>, id: &str, terms: Option<&Terms>) {
+    rankings.retain(|key, r| {
+        key != id && !r.includes(id) && !terms.is_some_and(|t| r.terms.overlap(t))
+    });
+}
+
+/// The published documents sharing a tag or category with `terms`.
+fn candidates_query(terms: &Terms) -> Result<ContentQuery, String> {
+    let shared: Vec<Json> = terms
+        .tags
+        .iter()
+        .map(|t| json!({ "tax.tags": { "$all": [t] } }))
+        .chain(
+            terms
+                .categories
+                .iter()
+                .map(|c| json!({ "tax.categories": { "$all": [c] } })),
+        )
+        .collect();
+    let filter = parse_filter(&json!({ "$or": shared })).map_err(|e| e.to_string())?;
+    Ok(ContentQuery {
+        filter,
+        opts: FindOptions::default(),
+    }
+    .published_only())
+}
+
+/// The posts in `content` related to the document `meta` describes, best
+/// first by `settings`; empty for a document without tags or categories.
+pub async fn for_document(
+    content: &SiteContent,
+    meta: &Json,
+    settings: &RelatedSettings,
+) -> Arc<Vec<Json>> {
+    let Some(id) = meta.get("id").and_then(Json::as_str) else {
+        return Arc::default();
+    };
+    let terms = Terms::of(meta);
+    if terms.is_empty() {
+        return Arc::default();
+    }
+    let rankings = content.related();
+    let cached = rankings
+        .rankings
+        .read()
+        .ok()
+        .and_then(|r| r.get(id).map(|r| r.related.clone()));
+    if let Some(related) = cached {
+        return related;
+    }
+
+    let generation = rankings.generation.load(Ordering::SeqCst);
+    let docs = match candidates_query(&terms) {
+        Ok(q) => match content.query_front_matter(&q.filter, &q.opts).await {
+            Ok((_, docs, _)) => docs,
+            Err(e) => {
+                warn!("Related posts of {}: {}", id, e);
+                return Arc::default();
+            }
+        },
+        Err(e) => {
+            warn!("Related posts of {}: building the query failed: {}", id, e);
+            return Arc::default();
+        }
+    };
+    let rules = content.permalinks();
+    let related: Arc<Vec<Json>> = Arc::new(
+        rank(meta, &docs, settings, Utc::now())
+            .into_iter()
+            .filter_map(|(score, doc)| Some(entry(&rules.url_for(doc)?, doc, score)))
+            .collect(),
+    );
+
+    if let Ok(mut held) = rankings.rankings.write() {
+        if rankings.generation.load(Ordering::SeqCst) == generation {
+            held.insert(
+                id.to_owned(),
+                Ranking {
+                    terms,
+                    related: related.clone(),
+                },
+            );
+        }
+    }
+    related
+}
+
+/// `model` with `related` added, unless the theme set it or the model is
+/// not an object.
+pub fn into_model(mut model: Json, related: &[Json]) -> Json {
+    if let Some(fields) = model.as_object_mut() {
+        fields
+            .entry("related")
+            .or_insert_with(|| Json::Array(related.to_vec()));
+    }
+    model
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes_drop_only_the_rankings_they_touch() {
+        let ranking = |terms: Json, related: &[&str]| Ranking {
+            terms: Terms::of(&json!({ "tax": terms })),
+            related: Arc::new(related.iter().map(|id| json!({ "id": id })).collect()),
+        };
+        let mut rankings = HashMap::from([
+            (
+                "/a".to_owned(),
+                ranking(json!({ "tags": ["rust"] }), &["/b"]),
+            ),
+            (
+                "/b".to_owned(),
+                ranking(json!({ "tags": ["rust"] }), &["/a"]),
+            ),
+            ("/c".to_owned(), ranking(json!({ "tags": ["go"] }), &["/d"])),
+            (
+                "/d".to_owned(),
+                ranking(json!({ "categories": ["ops"] }), &["/c"]),
+            ),
+        ]);
+        let ops = Terms::of(&json!({ "tax": { "categories": ["ops"] } }));
+        drop_affected(&mut rankings, "/e", Some(&ops));
+        let mut left: Vec<&String> = rankings.keys().collect();
+        left.sort();
+        assert_eq!(left, ["/a", "/b", "/c"]);
+
+        drop_affected(&mut rankings, "/a", None);
+        assert_eq!(rankings.keys().collect::<Vec<_>>(), ["/c"]);
+
+        let q = candidates_query(&Terms::of(&json!({ "tax": { "tags": ["go"] } }))).unwrap();
+        assert!(adapt::mql::eval_filter(
+            &q.filter,
+            &json!({ "publish": { "status": "published" }, "tax": { "tags": ["go", "ops"] } })
+        ));
+
+        let model = into_model(json!({ "title": "A" }), &[json!({ "id": "/c" })]);
+        assert_eq!(model["related"][0]["id"], "/c");
+        let own = into_model(json!({ "related": [] }), &[json!({ "id": "/c" })]);
+        assert_eq!(own["related"], json!([]));
+    }
+}
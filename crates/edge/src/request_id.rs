@@ -0,0 +1,162 @@
+// crates/edge/src/request_id.rs
+
+// Every request gets an id (serve::request_id), outside all the middleware
+// layers so each of them, the access log, plugins and themes see the same
+// one:
+//
+// - it replaces the request's `X-Request-Id`, which is where the access log
+//   and `ctx.request.requestId` read it from;
+// - it is the `request_id` of the `http.server` span every span of the
+//   request runs under, which also continues the caller's `traceparent`;
+// - it goes back on the response as `X-Request-Id`, and into the body of a
+//   JSON error response as `request_id`, so a user can quote it.
+//
+// An id the request came with is kept only when settings.toml trusts the
+// proxy in front to set it:
+//
+//   [edge]
+//   trust_request_id = true
+//
+// and only when it is short and plain; otherwise a client could choose ids
+// that collide with others' in the logs. The `[edge]` table is app data of
+// the scope around this layer.
+
+use crate::telemetry;
+use actix_web::{
+    body::{self, BodySize, BoxBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{HeaderName, HeaderValue, CONTENT_TYPE},
+    middleware::Next,
+    web, Error,
+};
+use domain::setting::EdgeSettings;
+use serde_json::Value as Json;
+use serve::request_id::{acceptable, generate, HEADER};
+use tracing::{info_span, Instrument};
+
+/// Largest JSON error body the id is added to.
+const MAX_ERROR_BODY: u64 = 64 * 1024;
+
+/// The id of `req`, once `assign` has run.
+pub fn of(headers: &actix_web::http::header::HeaderMap) -> Option<&str> {
+    headers.get(HEADER).and_then(|v| v.to_str().ok())
+}
+
+/// `actix_web::middleware::from_fn` middleware; the outermost layer.
+pub async fn assign(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let trusted = req
+        .app_data::<web::Data<EdgeSettings>>()
+        .is_some_and(|edge| edge.trust_request_id);
+    let id = of(req.headers())
+        .filter(|id| trusted && acceptable(id))
+        .map_or_else(generate, str::to_owned);
+    let value = HeaderValue::from_str(&id).expect("request ids are header-safe");
+    req.headers_mut()
+        .insert(HeaderName::from_static(HEADER), value.clone());
+
+    let span = info_span!(
+        "http.server",
+        request_id = %id,
+        http.method = %req.method(),
+        http.target = %req.path(),
+    );
+    telemetry::adopt_remote_parent(&span, req.headers());
+
+    let mut res = next.call(req).instrument(span).await?.map_into_boxed_body();
+    res.headers_mut()
+        .insert(HeaderName::from_static(HEADER), value);
+    if res.status().is_client_error() || res.status().is_server_error() {
+        res = with_id_in_body(res, &id).await;
+    }
+    Ok(res)
+}
+
+/// `res` with `"request_id"` added to its body, when that is a small JSON
+/// object without one.
+async fn with_id_in_body(res: ServiceResponse<BoxBody>, id: &str) -> ServiceResponse<BoxBody> {
+    let json = res
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+    let small = matches!(res.response().body().size(), BodySize::Sized(n) if n <= MAX_ERROR_BODY);
+    if !json || !small {
+        return res;
+    }
+
+    let (req, res) = res.into_parts();
+    let (res, body) = res.into_parts();
+    let Ok(bytes) = body::to_bytes(body).await else {
+        return ServiceResponse::new(req, res.set_body(BoxBody::new(())));
+    };
+    let bytes = match serde_json::from_slice::<Json>(&bytes) {
+        Ok(Json::Object(mut object)) if !object.contains_key("request_id") => {
+            object.insert("request_id".into(), Json::String(id.to_owned()));
+            serde_json::to_vec(&object).map_or(bytes, Into::into)
+        }
+        _ => bytes,
+    };
+    ServiceResponse::new(req, res.set_body(BoxBody::new(bytes)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{middleware::from_fn, test, web, App, HttpResponse};
+    use serde_json::json;
+
+    #[actix_web::test]
+    async fn ids_are_assigned_echoed_and_added_to_json_errors() {
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(assign))
+                .route(
+                    "/echo",
+                    web::get().to(|req: actix_web::HttpRequest| async move {
+                        HttpResponse::Ok().body(of(req.headers()).unwrap_or_default().to_owned())
+                    }),
+                )
+                .route(
+                    "/fail",
+                    web::get().to(|| async {
+                        HttpResponse::BadRequest().json(json!({ "error": "bad slug" }))
+                    }),
+                ),
+        )
+        .await;
+
+        // Untrusted: the client's id is replaced.
+        let res = test::call_service(
+            &app,
+            test::TestRequest::get()
+                .uri("/echo")
+                .insert_header((HEADER, "mine"))
+                .to_request(),
+        )
+        .await;
+        let header = res
+            .headers()
+            .get(HEADER)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_owned();
+        assert_eq!(header.len(), 26);
+        assert_eq!(test::read_body(res).await, header.as_bytes());
+
+        let res =
+            test::call_service(&app, test::TestRequest::get().uri("/fail").to_request()).await;
+        let header = res
+            .headers()
+            .get(HEADER)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_owned();
+        let body: Json = test::read_body_json(res).await;
+        assert_eq!(body, json!({ "error": "bad slug", "request_id": header }));
+    }
+}
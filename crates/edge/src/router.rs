@@ -1,80 +1,239 @@
 // crates/edge/src/router.rs
 
-use crate::fs::{ext::ThemeBinding, index::ContentMgr};
+use crate::api;
+use crate::assets;
+use crate::audit;
+use crate::auth;
+use crate::authors;
+use crate::cache;
+use crate::collections;
+use crate::comments;
+use crate::components;
+use crate::csrf;
+use crate::debugging::{self, CacheStatus};
+use crate::error_pages::{self, ErrorThemes};
+use crate::feeds;
+use crate::flags;
+use crate::forms;
+use crate::fs::{
+    ext::ThemeBinding,
+    index::{self, ContentMgr},
+};
+use crate::health;
+use crate::i18n;
+use crate::images;
+use crate::init;
+use crate::livereload;
+use crate::maintenance;
+use crate::media;
+use crate::middleware;
+use crate::normalize::NormalizedRequest;
+use crate::ops;
+use crate::plugin_routes;
+use crate::preview;
+use crate::quota;
+use crate::redirects;
+use crate::related;
+use crate::request_id;
+use crate::search;
+use crate::site::Site;
+use crate::sitemap;
+use crate::taxonomies;
 use actix_web::{
-    dev::HttpServiceFactory, http::Method as ActixMethod, web, HttpMessage, HttpRequest,
-    HttpResponse,
+    dev::HttpServiceFactory,
+    http::{
+        header::{HeaderName, HeaderValue, AUTHORIZATION, CACHE_CONTROL, LOCATION},
+        Method as ActixMethod, StatusCode,
+    },
+    middleware::{from_fn, Condition},
+    web, HttpMessage, HttpRequest, HttpResponse,
 };
+use adapt::mql::governed;
 use adapt::runtime::bootstrap::RuntimeHandles;
+use adapt::runtime::ctx_to_js_for_plugins;
+use adapt::runtime::hooks::CONTENT_BEFORE_RENDER;
 use adapt::runtime::plugin_actor::PluginRuntimeClient;
 use adapt::runtime::theme_actor::ThemeRuntimeClient;
+use chrono::DateTime;
 use domain::content::ResolvedContent;
+use domain::setting::QueryBudgetSettings;
+use futures::future::join;
+use serde_json::Value as Json;
 use serve::{
+    cache::{path_tag, CacheKey, CachedResponse, Flight, ResponseCache, ANY_CONTENT},
+    collections::CollectionHelper,
+    comments::CommentsHelper,
+    flags::FlagHelper,
+    forms::{CsrfHelper, FormHelper},
+    i18n::{HreflangHelper, TranslationsHelper},
+    l10n::TranslateHelper,
+    permalinks::{same_path, PermalinkHelper},
     render::{
         http::{RequestContext, ResponseBodySpec},
-        pipeline::{render_html_string_to, render_html_template_to, render_json_to},
-        template::TemplateRegistry,
+        islands::embed_islands,
+        pipeline::{
+            compute_etag, http_date, is_not_modified, render_html_string_to,
+            render_html_template_to, render_json_to, EtagStrength,
+        },
+        recommendation::{HeaderPatch, HeaderPatchKind},
+        template::{TemplateLanguage, TemplateRegistry},
     },
     resolver::{build_request_context, resolve},
 };
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::PathBuf;
-use tracing::{debug, error};
+use std::sync::Arc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tracing::{debug, error, info_span, Instrument};
 
 /// Per-theme state carried on the scope.
 #[derive(Clone)]
 struct ThemeAppState {
     theme_client: ThemeRuntimeClient,
     plugin_client: PluginRuntimeClient,
-    plugin_ids: Vec<String>,
     /// Theme identifier (as known to the JS runtime).
     theme_id: String,
     /// Filesystem root for this theme's templates directory.
     ///
     /// Typically `<theme-dir>/templates`.
     template_root: PathBuf,
+    /// Engine for templates whose extension does not pick one.
+    engine: Option<TemplateLanguage>,
+    /// `<theme-dir>/assets`, served under `<mount>/assets`.
+    assets_dir: PathBuf,
+    mount_path: String,
+    /// Media originals, for the dimensions of rendered `<img>` tags.
+    media_dir: PathBuf,
     content_mgr: ContentMgr,
+    site: Arc<Site>,
 }
 
 // ─────────────────────────────────────────────────────────────────────────────
 // Router construction
 // ─────────────────────────────────────────────────────────────────────────────
 
-/// Build the main Actix scope given:
+/// Build the site's Actix services given:
+/// - the site, whose settings and state the routes share
 /// - runtime handles (theme + plugin actors)
 /// - a list of theme bindings (mount path → theme id + template root)
 ///
-/// This returns an `impl HttpServiceFactory` you can mount directly on
-/// `HttpServer::new(move || build_app_router(handles.clone(), bindings.clone()))`.
+/// This returns two services as one `impl HttpServiceFactory` (a tuple):
+/// the health probes, outside every middleware layer, and the site's root
+/// scope. Mount both with one call:
+/// `App::new().service(build_app_router(site.clone(), handles.clone(), bindings.clone()))`.
 #[tracing::instrument(skip_all)]
 pub fn build_app_router(
-    root_dir: PathBuf,
+    site: Arc<Site>,
     handles: RuntimeHandles,
     bindings: Vec<ThemeBinding>,
 ) -> impl HttpServiceFactory {
     let theme_client = handles.theme_client.clone();
     let plugin_client = handles.plugin_client.clone();
-    let plugin_ids: Vec<String> = handles
-        .plugin_configs
-        .iter()
-        .map(|cfg| cfg.id.clone())
-        .collect();
+    let root_dir = site.root_dir.clone();
+    let settings = &site.settings;
 
-    // Root "container" scope; we add one nested scope per ThemeBinding.
-    let mut root = web::scope("");
+    // Author, taxonomy and search pages use the templates of the theme at "/", else
+    // the first.
+    let error_themes = bindings.clone();
+    let author_theme = bindings
+        .iter()
+        .find(|b| b.mount_path == "/")
+        .or_else(|| bindings.first())
+        .cloned();
+
+    // Liveness and readiness probes sit beside the root scope, outside
+    // every middleware layer below.
+    let probes = health::services(
+        root_dir.clone(),
+        site.content.clone(),
+        plugin_client.clone(),
+        theme_client.clone(),
+    );
+
+    // Root "container" scope; logins, the Content, components and media APIs, comments, forms, sitemap,
+    // feeds, author, taxonomy and search pages and media files go first so a theme bound at "/"
+    // cannot shadow them, then one nested scope per ThemeBinding.
+    let feed_settings = settings.feeds.clone().unwrap_or_default();
+    let media_dir = root_dir.join(&site.images.media_dir);
+    let content_mgr = ContentMgr::new(root_dir.clone(), site.content.clone());
+    let mut root = web::scope("")
+        .app_data(web::Data::new(plugin_client.clone()))
+        .app_data(web::PayloadConfig::new(settings.edge.max_body_bytes))
+        .app_data(audit::log(&root_dir))
+        .app_data(ops::log(&root_dir))
+        .service(auth::services())
+        .service(csrf::services())
+        .service(audit::services())
+        .service(ops::services())
+        .service(init::services(&root_dir))
+        .service(maintenance::services(site.maintenance.clone()))
+        .service(cache::services(
+            &root_dir,
+            site.images.clone(),
+            settings.cache.as_ref().and_then(|c| c.purge_token.clone()),
+        ))
+        .service(preview::services())
+        .service(api::content::scope())
+        .service(api::admin::scope(
+            &root_dir,
+            site.content_root.clone(),
+            site.content_re.clone(),
+            site.content.clone(),
+        ))
+        .service(api::extensions::scope(&root_dir, site.ext_dir.clone()))
+        .service(api::extensions::pages(&root_dir, site.ext_dir.clone()))
+        .service(api::users::pages())
+        .service(collections::services(site.collections.clone()))
+        .service(comments::services(site.comments.clone()))
+        .service(redirects::services(site.redirects.clone()))
+        .service(forms::services(site.forms.clone()))
+        .service(components::services(
+            &settings.components.clone().unwrap_or_default(),
+            &root_dir,
+            site.ext_dir.clone(),
+        ))
+        .service(sitemap::services(content_mgr.clone()))
+        .service(authors::services(
+            settings.authors.clone().unwrap_or_default(),
+            feed_settings.summary_chars,
+            content_mgr.clone(),
+            author_theme.clone(),
+        ))
+        .service(feeds::services(feed_settings, content_mgr.clone()))
+        .service(taxonomies::services(
+            settings.taxonomies.clone().unwrap_or_default(),
+            author_theme.clone(),
+        ))
+        .service(search::services(
+            settings.search.clone().unwrap_or_default(),
+            author_theme,
+        ))
+        .service(media::services(
+            settings.media.clone().unwrap_or_default(),
+            root_dir.clone(),
+            media_dir.clone(),
+        ))
+        .service(images::services(site.images.clone(), root_dir.clone()))
+        .service(assets::islands_loader());
 
     for binding in bindings {
         let mount_path = binding.mount_path.clone();
         let theme_id = binding.theme_id.clone();
         let template_root = binding.template_root.clone();
+        let engine = binding.engine;
+        let assets_dir = binding.assets_dir();
 
         let state = ThemeAppState {
             theme_client: theme_client.clone(),
             plugin_client: plugin_client.clone(),
-            plugin_ids: plugin_ids.clone(),
             theme_id,
             template_root,
-            content_mgr: ContentMgr::new(root_dir.clone()),
+            engine,
+            assets_dir: assets_dir.clone(),
+            mount_path: mount_path.clone(),
+            media_dir: media_dir.clone(),
+            content_mgr: content_mgr.clone(),
+            site: site.clone(),
         };
 
         // Normalize root theme mount: treat "/" as "" so that both "/"
@@ -85,6 +244,8 @@ pub fn build_app_router(
             .app_data(web::Data::new(state))
             // "/" under this mount (for the docsy demo: "/")
             .route("/", web::to(theme_route_handler))
+            // fingerprinted theme assets, e.g. "/assets/css/app.1a2b3c4d5e.css"
+            .service(assets::service(assets_dir))
             // everything else under this mount, e.g. "/index.html",
             // "/docs/search.html", etc.
             .route("/{tail:.*}", web::to(theme_route_handler));
@@ -92,7 +253,76 @@ pub fn build_app_router(
         root = root.service(scope);
     }
 
-    root
+    // Plugin routes outside every theme mount.
+    root = root.default_service(web::to(plugin_routes::fallback));
+
+    // One slot per configurable layer, slot 0 outermost; `[middleware]`
+    // decides which layer each runs (by default the canonical-host
+    // redirect, maintenance, then quotas, then the audit log).
+    let root = root
+        .wrap(from_fn(middleware::slot::<7>))
+        .wrap(from_fn(middleware::slot::<6>))
+        .wrap(from_fn(middleware::slot::<5>))
+        .wrap(from_fn(middleware::slot::<4>))
+        .wrap(from_fn(middleware::slot::<3>))
+        .wrap(from_fn(middleware::slot::<2>))
+        .wrap(from_fn(middleware::slot::<1>))
+        .wrap(from_fn(middleware::slot::<0>));
+
+    // Error pages wrap the layers so their plain errors get the theme's page
+    // too, and the request id wraps everything so every layer logs the same
+    // one. Under `serve-dev` the live-reload socket sits beside the layers
+    // and every page, error pages included, gets its script.
+    let dev = site.live.is_some();
+    let root = web::scope("")
+        .configure(|cfg| {
+            if dev {
+                cfg.service(livereload::services());
+            }
+        })
+        .service(root)
+        .wrap(from_fn(error_pages::themed))
+        .wrap(Condition::new(dev, from_fn(livereload::inject)))
+        .wrap(from_fn(request_id::assign));
+
+    // Around all of it, the data the layers read before any route matches,
+    // which a scope's own middleware cannot see: the site's settings and
+    // state, the user store, shared so any layer or route can check a login,
+    // its CSRF key, plugin routes and asset manifests, and the themes for
+    // error and maintenance pages.
+    let mut outer = web::scope("")
+        .app_data(site.edge.clone())
+        .app_data(site.sitemap.clone())
+        .app_data(site.stack.clone())
+        .app_data(site.maintenance.clone())
+        .app_data(site.auth.clone())
+        .app_data(site.flags.clone())
+        .app_data(web::Data::from(site.content.clone()))
+        .app_data(site.users.clone())
+        .app_data(site.csrf.clone())
+        .app_data(site.plugin_routes.clone())
+        .app_data(site.assets.clone())
+        .app_data(site.ext_changes.clone())
+        .app_data(web::Data::new(ErrorThemes(error_themes)));
+    if let Some(canonical) = &site.canonical {
+        outer = outer.app_data(canonical.clone());
+    }
+    if let Some(quotas) = &site.quotas {
+        outer = outer.app_data(quotas.clone());
+    }
+    if let Some(access_log) = &site.access_log {
+        outer = outer.app_data(access_log.clone());
+    }
+    if let Some(budget) = &site.query_budget {
+        outer = outer.app_data(budget.clone());
+    }
+    if let Some(i18n) = &site.i18n {
+        outer = outer.app_data(i18n.clone());
+    }
+    if let Some(live) = &site.live {
+        outer = outer.app_data(live.clone());
+    }
+    (probes, outer.service(root))
 }
 
 // ─────────────────────────────────────────────────────────────────────────────
@@ -100,7 +330,7 @@ pub fn build_app_router(
 // ─────────────────────────────────────────────────────────────────────────────
 
 /// Very small query parser into `HashMap<String, String>`.
-fn parse_query_params(raw_query: &str) -> HashMap<String, String> {
+pub(crate) fn parse_query_params(raw_query: &str) -> HashMap<String, String> {
     let mut out = HashMap::new();
 
     if raw_query.is_empty() {
@@ -124,12 +354,14 @@ fn parse_query_params(raw_query: &str) -> HashMap<String, String> {
 }
 
 /// Convert Actix method → `http` 1.x Method for the resolver.
-fn to_http_method(m: &ActixMethod) -> http::Method {
+pub(crate) fn to_http_method(m: &ActixMethod) -> http::Method {
     http::Method::from_bytes(m.as_str().as_bytes()).unwrap_or(http::Method::GET)
 }
 
 /// Convert Actix header map → `http` 1.x HeaderMap for the resolver.
-fn to_http_headers(actix_headers: &actix_web::http::header::HeaderMap) -> http::HeaderMap {
+pub(crate) fn to_http_headers(
+    actix_headers: &actix_web::http::header::HeaderMap,
+) -> http::HeaderMap {
     let mut out = http::HeaderMap::new();
 
     for (name, value) in actix_headers.iter() {
@@ -145,38 +377,298 @@ fn to_http_headers(actix_headers: &actix_web::http::header::HeaderMap) -> http::
     out
 }
 
+/// `publish.modified` (else `publish.date`) of the resolved content.
+fn content_modified(meta: &Json) -> Option<SystemTime> {
+    let publish = meta.get("publish")?;
+    let date = publish
+        .get("modified")
+        .or_else(|| publish.get("date"))?
+        .as_str()?;
+    DateTime::parse_from_rfc3339(date)
+        .ok()
+        .map(SystemTime::from)
+}
+
+/// A rendered body with its validators: `ETag`, and `Last-Modified` when
+/// the content declares a date.
+///
+/// Last-Modified is the later of the content date and the template's mtime;
+/// without a content date it is omitted, since the body could change without
+/// the template doing so.
+fn rendered_response(
+    content_type: &str,
+    body: Vec<u8>,
+    template_modified: Option<SystemTime>,
+    content_modified: Option<SystemTime>,
+) -> CachedResponse {
+    let version = template_modified
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos().to_string());
+    let etag = compute_etag(&body, version.as_deref(), EtagStrength::Strong);
+    let last_modified = content_modified.map(|c| template_modified.map_or(c, |t| t.max(c)));
+
+    CachedResponse {
+        status: StatusCode::OK.as_u16(),
+        content_type: content_type.to_owned(),
+        etag,
+        last_modified,
+        tags: Vec::new(),
+        headers: Vec::new(),
+        body: body.into(),
+    }
+}
+
+/// The headers plugins' `patches` leave on a page, to store with it.
+/// `appendSource` patches add to the site's policy; without one (no
+/// `security_headers` layer) they would make up the whole policy, so they
+/// are dropped.
+fn patched_headers(req: &HttpRequest, patches: &[HeaderPatch]) -> Vec<(String, String)> {
+    let mut headers = http::HeaderMap::new();
+    let site = patches
+        .iter()
+        .any(|p| p.kind == HeaderPatchKind::AppendSource)
+        .then(|| middleware::site_policy(req))
+        .flatten();
+    if let Some(value) = site.and_then(|p| http::HeaderValue::from_str(&p.to_string()).ok()) {
+        headers.insert(http::header::CONTENT_SECURITY_POLICY, value);
+    }
+    for patch in patches {
+        if patch.kind == HeaderPatchKind::AppendSource && headers.is_empty() {
+            debug!(
+                "Dropped {}'s sources for {}: the site sets no policy",
+                patch.source_plugin, patch.name
+            );
+            continue;
+        }
+        patch.apply(&mut headers);
+    }
+    headers
+        .iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_owned())))
+        .collect()
+}
+
+/// Send a rendered (or cached) response, or an empty 304 when the request
+/// already has it.
+fn respond_rendered(req: &HttpRequest, rendered: &CachedResponse) -> HttpResponse {
+    let method = to_http_method(req.method());
+    let headers = to_http_headers(req.headers());
+    let not_modified = is_not_modified(
+        &method,
+        &headers,
+        Some(&rendered.etag),
+        rendered.last_modified,
+    );
+    let mut resp = if not_modified {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::build(StatusCode::from_u16(rendered.status).unwrap_or(StatusCode::OK))
+    };
+
+    for (name, value) in &rendered.headers {
+        resp.append_header((name.as_str(), value.as_str()));
+    }
+    resp.insert_header(("ETag", rendered.etag.as_str()));
+    if let Some(t) = rendered.last_modified {
+        resp.insert_header(("Last-Modified", http_date(t)));
+    }
+
+    if not_modified {
+        resp.finish()
+    } else {
+        resp.insert_header(("Content-Type", rendered.content_type.as_str()))
+            .body(rendered.body.clone())
+    }
+}
+
+/// The response-cache key for this request, if it may be served from or
+/// stored in the cache: caching is on, it is a GET or HEAD that went through
+/// [`NormalizeRequest`](crate::normalize::NormalizeRequest), and it carries
+/// no credentials, and its query has only parameters in `[cache]
+/// query_params`. Pages for a session hold its CSRF token.
+fn cache_key_for(
+    cache: Option<&ResponseCache>,
+    req: &HttpRequest,
+    flags: &BTreeMap<String, bool>,
+) -> Option<CacheKey> {
+    let cache = cache?;
+    if !matches!(*req.method(), ActixMethod::GET | ActixMethod::HEAD)
+        || req.headers().contains_key(AUTHORIZATION)
+        || req.cookie(auth::SESSION_COOKIE).is_some()
+    {
+        return None;
+    }
+
+    let normalized = req.extensions().get::<NormalizedRequest>().cloned()?;
+    // Any other parameter would make a new entry per value sent.
+    if !normalized
+        .query
+        .iter()
+        .all(|(name, _)| cache.caches_query(name))
+    {
+        return None;
+    }
+    // Requests that see different flags render differently.
+    let mut key = normalized.cache_key();
+    let on: Vec<&str> = flags
+        .iter()
+        .filter(|(_, on)| **on)
+        .map(|(name, _)| name.as_str())
+        .collect();
+    if !on.is_empty() {
+        key.push_str("\0flags=");
+        key.push_str(&on.join(","));
+    }
+    // Themes build absolute links from the base URL, which follows the
+    // request's host when settings do not fix one.
+    key.push_str("\0base=");
+    key.push_str(&sitemap::base_url(req));
+    Some(cache.key(&key, &to_http_headers(req.headers())))
+}
+
+/// The `preview` query parameter, if the request carries one.
+fn preview_token(req: &HttpRequest) -> Option<String> {
+    form_urlencoded::parse(req.query_string().as_bytes())
+        .find(|(k, _)| k == preview::PREVIEW_PARAM)
+        .map(|(_, v)| v.into_owned())
+}
+
 // ─────────────────────────────────────────────────────────────────────────────
 // Handler
 // ─────────────────────────────────────────────────────────────────────────────
 
 /// Actix handler for all requests under a given theme mount.
 ///
-/// State carries `theme_client`, `plugin_client`, plugin IDs, and the template root.
-#[tracing::instrument(skip_all)]
-async fn theme_route_handler(state: web::Data<ThemeAppState>, req: HttpRequest) -> HttpResponse {
+/// Runs the request in an `http.request` span, under the `http.server`
+/// span of `request_id::assign`.
+async fn theme_route_handler(
+    state: web::Data<ThemeAppState>,
+    req: HttpRequest,
+    body: web::Bytes,
+) -> HttpResponse {
+    let span = info_span!(
+        "http.request",
+        http.method = %req.method(),
+        http.target = %req.uri().path(),
+    );
+
+    let budget = req.app_data::<web::Data<QueryBudgetSettings>>().cloned();
+    let governor = index::query_governor(req.path(), budget.as_ref().map(|b| b.get_ref()));
+    governed(
+        governor,
+        cache::collecting(handle_theme_request(state, req, body)),
+    )
+    .instrument(span)
+    .await
+}
+
+/// State carries `theme_client`, `plugin_client`, and the template root. Plugin
+/// IDs are read from the client per request so hot-reloaded plugins apply.
+async fn handle_theme_request(
+    state: web::Data<ThemeAppState>,
+    req: HttpRequest,
+    body: web::Bytes,
+) -> HttpResponse {
     let ThemeAppState {
         theme_client,
         plugin_client,
-        plugin_ids,
         theme_id,
         template_root,
+        engine,
+        assets_dir,
+        mount_path,
+        media_dir,
         content_mgr,
+        site,
     } = state.get_ref().clone();
 
     let path_for_log = req.uri().path().to_string();
     debug!("theme_route_handler hit for path: {}", path_for_log);
 
+    // Moved and removed pages answer before anything is looked up.
+    if let Some(resp) = site.redirects.respond(&req) {
+        return resp;
+    }
+
+    // A plugin route takes the path from the theme's pages.
+    if let Some(resp) = plugin_routes::dispatch(&plugin_client, &req, &body).await {
+        return resp;
+    }
+
+    // Preview links are per-reader and may show drafts, so they bypass the
+    // cache both ways.
+    let preview_token = preview_token(&req);
+    let csrf_token = csrf::token_for(&req);
+    let content_mgr = match &preview_token {
+        Some(token) => content_mgr.with_preview(token.clone()),
+        None => content_mgr,
+    };
+    let request_flags = flags::evaluate(&req).await;
+    let content = content_mgr.content().clone();
+    let response_cache = content.cache();
+    let cache_key =
+        cache_key_for(response_cache, &req, &request_flags).filter(|_| preview_token.is_none());
+    let mut trace = debugging::trace_for(&req, &theme_id).await;
+    if let Some(trace) = &mut trace {
+        trace.flags(&request_flags);
+        trace.cache(match (response_cache, &cache_key) {
+            (None, _) => CacheStatus::Off,
+            (Some(_), None) => CacheStatus::Bypass,
+            (Some(_), Some(_)) => CacheStatus::Miss,
+        });
+    }
+    // On a miss, wait for an identical render already in progress rather
+    // than starting another; `flight` shares ours with later misses.
+    let mut flight = None;
+    if let (Some(cache), Some(key)) = (response_cache, &cache_key) {
+        if let Some(hit) = cache.get(key) {
+            debug!("Response cache hit for {}", path_for_log);
+            if let Some(trace) = &mut trace {
+                trace.cache(CacheStatus::Hit);
+            }
+            return debugging::annotate(trace.as_ref(), respond_rendered(&req, &hit));
+        }
+        match cache.join_render(key) {
+            Flight::Leader(leader) => flight = Some(leader),
+            Flight::Follower(follower) => {
+                if let Some(shared) = follower.wait().await {
+                    debug!("Served {} from a concurrent render", path_for_log);
+                    if let Some(trace) = &mut trace {
+                        trace.cache(CacheStatus::Shared);
+                    }
+                    return debugging::annotate(trace.as_ref(), respond_rendered(&req, &shared));
+                }
+            }
+        }
+    }
+
+    // Only an actual render waits for one of the tenant's render slots.
+    let _slot = match quota::render_slot(&req).await {
+        Ok(slot) => slot,
+        Err(resp) => return resp,
+    };
+
     // Prefer a RequestContext injected by some earlier layer (if any),
-    // otherwise build it directly here from the resolver.
-    let base_ctx: RequestContext = if let Some(existing) = req.extensions().get::<RequestContext>()
-    {
-        existing.clone()
-    } else {
-        let path = req.uri().path().to_string();
+    // otherwise build it directly here from the resolver. The page lookup
+    // and the collections are independent, so they run together.
+    let injected = req.extensions().get::<RequestContext>().cloned();
+    let base_ctx = async {
+        if let Some(existing) = injected {
+            return existing;
+        }
+        // NormalizeRequest has already decoded the path; without it (e.g.
+        // a bare router in tests) fall back to the raw URI.
+        let normalized = req.extensions().get::<NormalizedRequest>().cloned();
+        let (path, query_params) = match normalized {
+            Some(n) => (n.path.clone(), n.query_map()),
+            None => (
+                req.uri().path().to_string(),
+                parse_query_params(req.uri().query().unwrap_or_default()),
+            ),
+        };
+        let path = i18n::routed_path(&req, path);
         let actix_method = req.method().clone();
         let headers_actix = req.headers().clone();
-        let raw_query = req.uri().query().unwrap_or_default().to_string();
-        let query_params = parse_query_params(&raw_query);
 
         let method = to_http_method(&actix_method);
         let headers = to_http_headers(&headers_actix);
@@ -188,28 +680,97 @@ async fn theme_route_handler(state: web::Data<ThemeAppState>, req: HttpRequest)
 
         build_request_context(path, method, headers, query_params, resolved)
     };
+    let started = Instant::now();
+    let budget = req.app_data::<web::Data<QueryBudgetSettings>>().cloned();
+    let (base_ctx, request_collections) = join(
+        base_ctx,
+        site.collections
+            .resolve(&content, budget.as_ref().map(|b| b.get_ref())),
+    )
+    .await;
+    if let (Some(trace), false) = (&mut trace, request_collections.is_empty()) {
+        trace.timing("collections", "", started.elapsed());
+    }
 
     debug!("theme_id: {}", theme_id);
 
+    // A document with a permalink has one address; its served path and bare
+    // slug send readers there.
+    let requested = i18n::routed_path(&req, req.path().to_owned());
+    if let Some(mut to) = content
+        .permalinks()
+        .for_document(&base_ctx.content_meta)
+        .filter(|p| !same_path(p, &requested))
+    {
+        to = i18n::public_path(&req, to);
+        if !req.query_string().is_empty() {
+            to = format!("{to}?{}", req.query_string());
+        }
+        return HttpResponse::MovedPermanently()
+            .insert_header((LOCATION, to))
+            .finish();
+    }
+
+    // Readers who have not picked a language get the translation they
+    // prefer.
+    let translations = i18n::translations(&req, &base_ctx.content_meta).await;
+    if let Some(negotiated) = i18n::negotiate(&req, &translations) {
+        return negotiated;
+    }
+    let translations = Arc::new(translations);
+
     // ─────────────────────────────────────────────────────────────────────
     // Run plugin BEFORE hooks (in configured order).
     // ─────────────────────────────────────────────────────────────────────
     let mut ctx = base_ctx;
-    for plugin_id in &plugin_ids {
+    ctx.flags = request_flags.clone();
+    ctx.collections = request_collections.clone();
+    ctx.base_url = Some(sitemap::base_url(&req));
+    ctx.messages = site
+        .strings
+        .messages(site.strings.lang_for(&ctx.content_meta));
+    let theme_strings = ctx.messages.get(&theme_id).cloned().unwrap_or_default();
+    if ctx.req_body.is_none() && !body.is_empty() {
+        ctx.req_body = Some(body);
+    }
+    let modified = content_modified(&ctx.content_meta);
+    for plugin_id in &plugin_client.plugin_ids() {
         debug!("Running before_plugin for plugin_id={}", plugin_id);
+        let started = Instant::now();
         match plugin_client.before_plugin(plugin_id.clone(), ctx).await {
             Ok(new_ctx) => {
                 ctx = new_ctx;
+                if let Some(trace) = &mut trace {
+                    trace.timing("plugin", format!("{plugin_id} before"), started.elapsed());
+                }
             }
             Err(e) => {
                 error!(
                     "before_plugin failed for plugin_id={} on theme {}: {}",
                     plugin_id, theme_id, e
                 );
-                return HttpResponse::InternalServerError().body("Plugin before error");
+                return error_pages::internal_error("Plugin before error", e);
             }
         }
     }
+    let started = Instant::now();
+    let payload = ctx_to_js_for_plugins(&ctx, "");
+    ctx = match plugin_client
+        .emit(CONTENT_BEFORE_RENDER, payload, ctx)
+        .await
+    {
+        Ok(new_ctx) => new_ctx,
+        Err(e) => {
+            error!(
+                "{} listeners failed on theme {}: {}",
+                CONTENT_BEFORE_RENDER, theme_id, e
+            );
+            return error_pages::internal_error("Plugin hook error", e);
+        }
+    };
+    if let Some(trace) = &mut trace {
+        trace.timing("hooks", CONTENT_BEFORE_RENDER, started.elapsed());
+    }
 
     // NOTE: we currently do NOT run after_plugin hooks, because the theme
     // runtime API returns a ResponseBodySpec, not an updated RequestContext.
@@ -217,19 +778,79 @@ async fn theme_route_handler(state: web::Data<ThemeAppState>, req: HttpRequest)
     // on `&mut RequestContext` and return it, or move the plugin calls inside
     // the theme actor so they share the same context instance.
 
+    for mark in &ctx.recommendations.uncacheable {
+        debug!(
+            "Plugin {} marked {} uncacheable: {}",
+            mark.source_plugin,
+            path_for_log,
+            mark.reason.as_deref().unwrap_or("no reason given")
+        );
+    }
+    let cacheable = ctx.recommendations.is_cacheable();
+    if let (Some(trace), false, Some(_)) = (&mut trace, cacheable, &cache_key) {
+        trace.cache(CacheStatus::Uncacheable);
+    }
+
     // Ask the theme actor to render a ResponseBodySpec from the (possibly
     // plugin-mutated) RequestContext.
+    let related_posts = related::for_document(
+        &content,
+        &ctx.content_meta,
+        &site.settings.related.clone().unwrap_or_default(),
+    )
+    .await;
+    let page_path = ctx.req_path.as_str().unwrap_or("/").to_owned();
+    let header_patches = ctx.recommendations.header_patches.clone();
+    let started = Instant::now();
     let result = theme_client.render(&theme_id, ctx).await;
     debug!("The ResponseBodySpec: {:?}", result);
+    if let Some(trace) = &mut trace {
+        trace.timing("theme", "", started.elapsed());
+    }
 
     // NOTE: body patches (from plugins/themes) are not yet wired here.
     let body_patches: &[serve::render::recommendation::BodyPatch] = &[];
 
-    match result {
+    let mut rendered = match result {
         // HtmlTemplate – detect engine + render from /templates
-        Ok(ResponseBodySpec::HtmlTemplate { template, model }) => {
-            let registry = TemplateRegistry::new(template_root);
+        Ok(ResponseBodySpec::HtmlTemplate {
+            template,
+            model,
+            islands,
+        }) => {
+            if let Some(trace) = &mut trace {
+                trace.template(&template);
+            }
+            let started = Instant::now();
+            let registry = TemplateRegistry::new(template_root)
+                .with_language(engine)
+                .with_helpers(
+                    site.assets
+                        .helpers(&assets_dir, &mount_path)
+                        .with_helper("flag", FlagHelper::new(request_flags))
+                        .with_helper("collection", CollectionHelper::new(request_collections))
+                        .with_helper(
+                            "comments",
+                            CommentsHelper::new(&page_path, site.comments.approved()),
+                        )
+                        .with_helper("form", FormHelper::new(site.forms.described()))
+                        .with_helper("csrf_token", CsrfHelper::new(csrf_token.clone()))
+                        .with_helper("permalink", PermalinkHelper::new(content.permalinks()))
+                        .with_helper(
+                            "hreflang",
+                            HreflangHelper::new(
+                                &translations,
+                                &i18n::default_lang(&req).unwrap_or_default(),
+                            ),
+                        )
+                        .with_helper(
+                            "translations",
+                            TranslationsHelper::new(translations.clone()),
+                        )
+                        .with_helper("t", TranslateHelper::new(theme_strings)),
+                );
 
+            let model = related::into_model(model, &related_posts);
             let mut buf = Vec::new();
             if let Err(e) =
                 render_html_template_to(&registry, &template, &model, body_patches, &mut buf)
@@ -238,12 +859,25 @@ async fn theme_route_handler(state: web::Data<ThemeAppState>, req: HttpRequest)
                     "HtmlTemplate render failed for theme {} and template {}: {}",
                     theme_id, template, e
                 );
-                HttpResponse::InternalServerError().body("Template rendering error")
+                return error_pages::internal_error("Template rendering error", e);
+            }
+            let buf = if islands.is_empty() {
+                buf
             } else {
-                HttpResponse::Ok()
-                    .insert_header(("Content-Type", "text/html; charset=utf-8"))
-                    .body(buf)
+                match embed_islands(&String::from_utf8_lossy(&buf), &islands) {
+                    Ok(html) => html.into_bytes(),
+                    Err(e) => {
+                        error!("Embedding islands failed for theme {}: {}", theme_id, e);
+                        return error_pages::internal_error("Template rendering error", e);
+                    }
+                }
+            };
+            let buf = images::annotate_html(&site.images, &site.image_sizes, &media_dir, buf);
+            let template_modified = registry.template_modified(&template);
+            if let Some(trace) = &mut trace {
+                trace.timing("template", "", started.elapsed());
             }
+            rendered_response("text/html; charset=utf-8", buf, template_modified, modified)
         }
 
         // HtmlString – routed through same render pipeline (body patches empty for now).
@@ -251,12 +885,10 @@ async fn theme_route_handler(state: web::Data<ThemeAppState>, req: HttpRequest)
             let mut buf = Vec::new();
             if let Err(e) = render_html_string_to(&html, body_patches, &mut buf) {
                 error!("HtmlString render failed for theme {}: {}", theme_id, e);
-                HttpResponse::InternalServerError().body("HTML rendering error")
-            } else {
-                HttpResponse::Ok()
-                    .insert_header(("Content-Type", "text/html; charset=utf-8"))
-                    .body(buf)
+                return error_pages::internal_error("HTML rendering error", e);
             }
+            let buf = images::annotate_html(&site.images, &site.image_sizes, &media_dir, buf);
+            rendered_response("text/html; charset=utf-8", buf, None, modified)
         }
 
         // JsonValue – regex / JSON body patches (empty for now).
@@ -264,19 +896,49 @@ async fn theme_route_handler(state: web::Data<ThemeAppState>, req: HttpRequest)
             let mut buf = Vec::new();
             if let Err(e) = render_json_to(&val, body_patches, &mut buf) {
                 error!("JSON render failed for theme {}: {}", theme_id, e);
-                HttpResponse::InternalServerError().body("JSON rendering error")
-            } else {
-                HttpResponse::Ok()
-                    .insert_header(("Content-Type", "application/json"))
-                    .body(buf)
+                return error_pages::internal_error("JSON rendering error", e);
             }
+            rendered_response("application/json", buf, None, modified)
         }
 
-        Ok(ResponseBodySpec::None | ResponseBodySpec::Unset) => HttpResponse::NoContent().finish(),
+        Ok(ResponseBodySpec::None | ResponseBodySpec::Unset) => {
+            return HttpResponse::NoContent().finish()
+        }
 
         Err(e) => {
             error!("Theme runtime error: {}", e);
-            HttpResponse::InternalServerError().body("Theme runtime error")
+            return error_pages::internal_error("Theme runtime error", e);
         }
+    };
+
+    rendered.headers = patched_headers(&req, &header_patches);
+
+    if let (Some(cache), Some(key), true) = (response_cache, &cache_key, cacheable) {
+        let mut tags = cache::consumed_tags();
+        if tags.is_empty() {
+            tags.push(ANY_CONTENT.to_owned());
+        }
+        tags.push(path_tag(req.path()));
+        cache.put_shared(
+            key,
+            CachedResponse {
+                tags,
+                ..rendered.clone()
+            },
+            quota::cache_share(&req).as_ref(),
+        );
+    }
+    if let (Some(flight), true) = (flight, cacheable) {
+        flight.finish(&rendered);
+    }
+    let mut resp = respond_rendered(&req, &rendered);
+    if preview_token.is_some() {
+        let headers = resp.headers_mut();
+        headers.insert(CACHE_CONTROL, HeaderValue::from_static("private, no-store"));
+        headers.insert(
+            HeaderName::from_static("x-robots-tag"),
+            HeaderValue::from_static("noindex"),
+        );
     }
+    debugging::annotate(trace.as_ref(), resp)
 }
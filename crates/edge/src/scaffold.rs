@@ -0,0 +1,415 @@
+// crates/edge/src/scaffold.rs
+
+// Starting points for extensions, for `whispercms new`:
+//
+//   whispercms new plugin ./site reading-time
+//   whispercms new theme ./site journal --mount /journal
+//
+// write, below the site's extensions directory,
+//
+//   plugins/reading-time/          themes/journal/
+//     plugin.toml                    theme.toml
+//     plugin.js                      theme.js
+//     tests/smoke.js                 templates/page.hbs
+//                                    templates/404.hbs
+//                                    assets/css/site.css
+//                                    tests/smoke.js
+//
+// The manifest declares one example setting, the entrypoint stubs `init`
+// and the hooks (`before`/`after` for plugins, `render` for themes) and
+// uses it, and `node tests/smoke.js` runs the entrypoint against a stubbed
+// host. A theme is mounted at `/<name>` unless told otherwise, so a running
+// site, which hot-reloads new extensions, does not swap its pages for the
+// sample one. Nothing is overwritten: an existing directory is an error.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ScaffoldError {
+    #[error("I/O: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("invalid name {0:?}; use lowercase letters, digits, '-' and '_', starting with a letter or digit")]
+    Name(String),
+
+    #[error("{0} already exists")]
+    Exists(PathBuf),
+}
+
+const PLUGIN_TOML: &str = r#"# Written by `whispercms new plugin`.
+
+id = "__ID__"
+name = "__NAME__"
+
+# What the plugin may do beyond its hooks: any of "network", "db.read",
+# "db.write" and "fs.read". Requests to hosts also need a [fetch] table.
+# permissions = ["network"]
+
+# Settings are checked against these tables when the plugin loads and reach
+# the hooks, defaults filled in, as ctx.config.
+[settings.greeting]
+type = "string"
+default = "hello"
+description = "Value of the x-__ID__ header added to every response"
+"#;
+
+const PLUGIN_JS: &str = r#"// __NAME__
+//
+// init(ctx) runs once when the plugin loads and registers the hooks.
+// before(ctx) runs ahead of the theme on every request and after(ctx) once
+// it has rendered; each returns recommendations for the host to merge, or
+// nothing to change nothing.
+
+function init(ctx) {
+  registerPlugin({
+    before(ctx) {
+      return {
+        recommendations: {
+          headerPatches: [
+            {
+              kind: "set",
+              name: "x-__ID__",
+              value: String(ctx.config.greeting),
+              sourcePlugin: "__ID__",
+            },
+          ],
+        },
+      };
+    },
+
+    after(ctx) {
+      return {};
+    },
+  });
+}
+"#;
+
+const PLUGIN_SMOKE_JS: &str = r#"// Smoke test for __NAME__: `node tests/smoke.js` in the plugin directory.
+// Runs plugin.js against a stubbed host and one request.
+
+const assert = require("assert");
+const fs = require("fs");
+const path = require("path");
+
+let hooks;
+const source = fs.readFileSync(path.join(__dirname, "..", "plugin.js"), "utf8");
+const init = new Function("registerPlugin", "log", `${source}\nreturn init;`)(
+  (h) => { hooks = h; },
+  console,
+);
+
+// As plugin.toml's defaults.
+const ctx = {
+  request: { path: "/", method: "GET", headers: {}, queryParams: {} },
+  content: { meta: { title: "Hello" }, body: "<p>Hi</p>" },
+  config: { greeting: "hello" },
+  flags: {},
+};
+
+init(ctx);
+assert.strictEqual(typeof hooks.before, "function", "init registers before");
+const out = hooks.before(ctx) || {};
+const patches = (out.recommendations || {}).headerPatches || [];
+assert.ok(patches.some((p) => p.name === "x-__ID__"), "before sets x-__ID__");
+if (hooks.after) hooks.after(ctx);
+console.log("ok __ID__");
+"#;
+
+const THEME_TOML: &str = r#"# Written by `whispercms new theme`.
+
+id = "__ID__"
+name = "__NAME__"
+# URL prefix the theme serves; "/" makes it the site's main theme.
+mount = "__MOUNT__"
+engine = "handlebars"
+
+# Settings reach render(ctx), defaults filled in, as ctx.config.
+[settings.site_title]
+type = "string"
+default = "__NAME__"
+description = "Shown in the page header and as the title of untitled pages"
+"#;
+
+const THEME_JS: &str = r#"// __NAME__
+//
+// init(ctx) runs once when the theme loads and registers render(ctx), which
+// answers each request below the mount: here, templates/page.hbs with the
+// page's front matter (ctx.content.meta) and body (ctx.content.body).
+
+function init(ctx) {
+  registerTheme({
+    render(ctx) {
+      const meta = ctx.content.meta || {};
+      return {
+        response: {
+          status: 200,
+          headers: { "content-type": "text/html; charset=utf-8" },
+          body: {
+            kind: "htmlTemplate",
+            template: "page.hbs",
+            model: {
+              siteTitle: ctx.config.site_title,
+              title: meta.title || ctx.config.site_title,
+              meta,
+              body: ctx.content.body || "",
+            },
+          },
+        },
+      };
+    },
+  });
+}
+"#;
+
+const THEME_PAGE_HBS: &str = r#"<!doctype html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <meta name="viewport" content="width=device-width, initial-scale=1">
+  <title>{{title}} · {{siteTitle}}</title>
+  <link rel="stylesheet" href="{{asset_url "css/site.css"}}">
+</head>
+<body>
+  <header><a href="__MOUNT__">{{siteTitle}}</a></header>
+  <main>
+    <h1>{{title}}</h1>
+    {{{body}}}
+  </main>
+</body>
+</html>
+"#;
+
+const THEME_404_HBS: &str = r#"<!doctype html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <title>Not found</title>
+  <link rel="stylesheet" href="{{asset_url "css/site.css"}}">
+</head>
+<body>
+  <main>
+    <h1>Not found</h1>
+    <p>Nothing lives at <code>{{path}}</code>.</p>
+  </main>
+</body>
+</html>
+"#;
+
+const THEME_CSS: &str = r#"body {
+  max-width: 42rem;
+  margin: 2rem auto;
+  padding: 0 1rem;
+  font: 1.05rem/1.6 system-ui, sans-serif;
+}
+
+header a {
+  color: inherit;
+  font-weight: 600;
+  text-decoration: none;
+}
+"#;
+
+const THEME_SMOKE_JS: &str = r#"// Smoke test for __NAME__: `node tests/smoke.js` in the theme directory.
+// Runs theme.js against a stubbed host and one page.
+
+const assert = require("assert");
+const fs = require("fs");
+const path = require("path");
+
+let hooks;
+const source = fs.readFileSync(path.join(__dirname, "..", "theme.js"), "utf8");
+const init = new Function("registerTheme", "log", `${source}\nreturn init;`)(
+  (h) => { hooks = h; },
+  console,
+);
+
+// As theme.toml's defaults.
+const ctx = {
+  request: { path: "__MOUNT__", method: "GET", headers: {}, queryParams: {} },
+  content: { meta: { title: "Hello" }, body: "<p>Hi</p>" },
+  config: { site_title: "__NAME__" },
+  flags: {},
+};
+
+init(ctx);
+assert.strictEqual(typeof hooks.render, "function", "init registers render");
+const { response } = hooks.render(ctx);
+assert.strictEqual(response.body.kind, "htmlTemplate");
+const template = path.join(__dirname, "..", "templates", response.body.template);
+assert.ok(fs.existsSync(template), `${response.body.template} exists`);
+assert.strictEqual(response.body.model.title, "Hello");
+console.log("ok __ID__");
+"#;
+
+/// Write a plugin called `name` to `<ext_dir>/plugins/<name>`; the files
+/// written, relative to `ext_dir`.
+pub fn new_plugin(ext_dir: &Path, name: &str) -> Result<Vec<PathBuf>, ScaffoldError> {
+    let vars = [("__ID__", name), ("__NAME__", &title(name))];
+    write_all(
+        ext_dir,
+        &Path::new("plugins").join(checked(name)?),
+        &[
+            ("plugin.toml", PLUGIN_TOML),
+            ("plugin.js", PLUGIN_JS),
+            ("tests/smoke.js", PLUGIN_SMOKE_JS),
+        ],
+        &vars,
+    )
+}
+
+/// Write a theme called `name`, mounted at `mount` (else `/<name>`), to
+/// `<ext_dir>/themes/<name>`; the files written, relative to `ext_dir`.
+pub fn new_theme(
+    ext_dir: &Path,
+    name: &str,
+    mount: Option<&str>,
+) -> Result<Vec<PathBuf>, ScaffoldError> {
+    let mount = match mount {
+        Some(m) => format!("/{}", m.trim_matches('/')),
+        None => format!("/{name}"),
+    };
+    let vars = [
+        ("__ID__", name),
+        ("__NAME__", &title(name)),
+        ("__MOUNT__", &mount),
+    ];
+    write_all(
+        ext_dir,
+        &Path::new("themes").join(checked(name)?),
+        &[
+            ("theme.toml", THEME_TOML),
+            ("theme.js", THEME_JS),
+            ("templates/page.hbs", THEME_PAGE_HBS),
+            ("templates/404.hbs", THEME_404_HBS),
+            ("assets/css/site.css", THEME_CSS),
+            ("tests/smoke.js", THEME_SMOKE_JS),
+        ],
+        &vars,
+    )
+}
+
+/// `name` if it is a usable id, directory and header name.
+fn checked(name: &str) -> Result<&str, ScaffoldError> {
+    let mut chars = name.chars();
+    let first = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_lowercase() || c.is_ascii_digit());
+    let rest = chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+    if first && rest {
+        Ok(name)
+    } else {
+        Err(ScaffoldError::Name(name.to_owned()))
+    }
+}
+
+/// `reading-time` as `Reading Time`.
+fn title(name: &str) -> String {
+    name.split(['-', '_'])
+        .filter(|w| !w.is_empty())
+        .map(|w| {
+            let mut chars = w.chars();
+            chars
+                .next()
+                .map(|c| c.to_ascii_uppercase().to_string() + chars.as_str())
+                .unwrap_or_default()
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn write_all(
+    ext_dir: &Path,
+    dir: &Path,
+    files: &[(&str, &str)],
+    vars: &[(&str, &str)],
+) -> Result<Vec<PathBuf>, ScaffoldError> {
+    let root = ext_dir.join(dir);
+    if root.exists() {
+        return Err(ScaffoldError::Exists(root));
+    }
+
+    let mut written = Vec::with_capacity(files.len());
+    for (file, template) in files {
+        let path = root.join(file);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let text = vars
+            .iter()
+            .fold((*template).to_owned(), |text, (var, value)| {
+                text.replace(var, value)
+            });
+        fs::write(&path, text)?;
+        written.push(dir.join(file));
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::ext::{discover_all_plugins, discover_themes};
+    use adapt::js::BoaEngine;
+    use adapt::runtime::{PluginRuntime, ThemeRuntime};
+    use serde_json::json;
+    use serve::render::http::{RequestContext, ResponseBodySpec};
+
+    #[test]
+    fn generated_extensions_load_and_run_their_hooks() {
+        let dir = tempfile::tempdir().unwrap();
+        let ext = dir.path();
+
+        let files = new_plugin(ext, "reading-time").unwrap();
+        assert!(files.contains(&PathBuf::from("plugins/reading-time/tests/smoke.js")));
+        new_theme(ext, "journal", None).unwrap();
+        assert!(matches!(
+            new_plugin(ext, "reading-time"),
+            Err(ScaffoldError::Exists(_))
+        ));
+        assert!(matches!(
+            new_theme(ext, "../up", None),
+            Err(ScaffoldError::Name(_))
+        ));
+
+        let plugin = discover_all_plugins(ext.join("plugins")).unwrap().remove(0);
+        assert_eq!(
+            (plugin.spec.id.as_str(), plugin.spec.name.as_str()),
+            ("reading-time", "Reading Time")
+        );
+        let mut plugins = PluginRuntime::new(BoaEngine::new()).unwrap();
+        plugins.load_plugins(&[plugin.spec]).unwrap();
+        let ctx = RequestContext::builder()
+            .path("/")
+            .method("GET")
+            .content_meta(json!({ "title": "Hello" }))
+            .build();
+        plugins.init_all(&ctx).unwrap();
+        let mut req = ctx.clone();
+        plugins.before_all(&mut req).unwrap();
+        let patch = &req.recommendations.header_patches[0];
+        assert_eq!(
+            (patch.name.as_str(), patch.value.as_deref()),
+            ("x-reading-time", Some("hello"))
+        );
+
+        let theme = discover_themes(ext.join("themes")).unwrap().remove(0);
+        assert_eq!(theme.mount_path, "/journal");
+        assert!(theme.dir.join("assets/css/site.css").is_file());
+        let mut runtime = ThemeRuntime::new(BoaEngine::new(), theme.spec).unwrap();
+        runtime.init(&ctx).unwrap();
+        let mut req = ctx.clone();
+        runtime.handle(&mut req).unwrap();
+        let ResponseBodySpec::HtmlTemplate {
+            template, model, ..
+        } = &req.response_spec.body
+        else {
+            panic!("not a template: {:?}", req.response_spec.body);
+        };
+        assert_eq!(template, "page.hbs");
+        assert_eq!(model["title"], "Hello");
+        assert_eq!(model["siteTitle"], "Journal");
+    }
+}
@@ -0,0 +1,105 @@
+// crates/edge/src/schedule.rs
+
+// Scheduled publishing. A document whose `publish.date` is in the future is
+// hidden from lookups, queries, listings and feeds until that moment, and one
+// whose `publish.unpublish_at` has passed is hidden after it (see
+// IndexRecord::is_live_at). Both are checked whenever content is read, so
+// nothing needs re-indexing; only cached pages can go stale. This task wakes
+// at each of those moments and drops the pages that used the documents,
+// along with listings, so they appear and disappear without a restart.
+//
+// The index is re-read at least every `MAX_SLEEP`, so a date added by an edit
+// is noticed even when it falls before the one being waited for.
+
+use crate::cache;
+use crate::fs::index::SiteContent;
+use chrono::{DateTime, Utc};
+use serde_json::Value as Json;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// Longest the task sleeps before re-reading the index.
+pub const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+/// Start the task for the site's `content`. Without a response cache there
+/// is nothing to refresh.
+pub fn start(content: Arc<SiteContent>) {
+    if content.cache().is_none() {
+        debug!("Response cache off; publish scheduling not needed");
+        return;
+    }
+    tokio::spawn(run(content));
+}
+
+async fn run(content: Arc<SiteContent>) {
+    let mut checked = Utc::now();
+    loop {
+        let changes = content.visibility_changes().await.unwrap_or_else(|e| {
+            warn!("Reading publish schedules failed: {}", e);
+            Vec::new()
+        });
+        let now = Utc::now();
+
+        let due = due_between(&changes, checked, now);
+        if !due.is_empty() {
+            info!("{} scheduled document(s) published or expired", due.len());
+            cache::invalidate_docs(content.cache(), &due);
+        }
+        checked = now;
+
+        let sleep = next_change(&changes, now)
+            .and_then(|at| (at - now).to_std().ok())
+            .map_or(MAX_SLEEP, |d| d.min(MAX_SLEEP));
+        tokio::time::sleep(sleep).await;
+    }
+}
+
+/// Records whose visibility changed after `after`, up to and including
+/// `until`.
+fn due_between(
+    changes: &[(DateTime<Utc>, Json)],
+    after: DateTime<Utc>,
+    until: DateTime<Utc>,
+) -> Vec<Json> {
+    changes
+        .iter()
+        .filter(|(at, _)| *at > after && *at <= until)
+        .map(|(_, doc)| doc.clone())
+        .collect()
+}
+
+/// The first change after `now`.
+fn next_change(changes: &[(DateTime<Utc>, Json)], now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    changes
+        .iter()
+        .map(|(at, _)| *at)
+        .filter(|at| *at > now)
+        .min()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use serde_json::json;
+
+    #[test]
+    fn changes_are_due_once_and_the_next_is_waited_for() {
+        let t = |h| Utc.with_ymd_and_hms(2030, 6, 1, h, 0, 0).unwrap();
+        let changes = vec![
+            (t(9), json!({ "id": "/launch.html" })),
+            (t(12), json!({ "id": "/sale.html" })),
+            (t(18), json!({ "id": "/launch.html" })),
+        ];
+
+        assert!(due_between(&changes, t(8), t(8)).is_empty());
+        assert_eq!(next_change(&changes, t(8)), Some(t(9)));
+
+        assert_eq!(due_between(&changes, t(8), t(12)).len(), 2);
+        assert!(due_between(&changes, t(12), t(13)).is_empty());
+        assert_eq!(next_change(&changes, t(12)), Some(t(18)));
+
+        assert_eq!(next_change(&changes, t(18)), None);
+    }
+}
@@ -0,0 +1,227 @@
+// crates/edge/src/search.rs
+
+// The reader-facing search page (serve::search):
+//
+//   GET /search?q=…[&page=n]           the theme's search template
+//   GET /search?q=…&format=json        the same model as JSON; so is
+//                                      `Accept: application/json`
+//
+// Candidates are the published documents whose title or summary has a query
+// word, plus the best `BODY_CANDIDATES` hits of the body index; their bodies
+// are read back from it as plain text and the lot ranked together. Pages
+// render the theme's search template like author pages do (crate::authors).
+// Configured by an optional `[search]` table in settings.toml:
+//
+//   [search]
+//   per_page = 10
+//   snippet_chars = 160
+//   template = "search.hbs"
+
+use crate::api::content::ContentQuery;
+use crate::assets::AssetManifests;
+use crate::fs::ext::ThemeBinding;
+use crate::fs::index::SiteContent;
+use crate::quota;
+use actix_web::http::header::ACCEPT;
+use actix_web::{dev::HttpServiceFactory, web, HttpRequest, HttpResponse};
+use adapt::mql::{Filter, FindOptions};
+use domain::setting::SearchSettings;
+use serde_json::Value as Json;
+use serve::render::{render_default, template::TemplateRegistry, TemplateEngine};
+use serve::search::{
+    html_text, page_model, query_terms, rank, tokenize, SearchDoc, DEFAULT_SEARCH_TEMPLATE,
+};
+use serve::taxonomy::Pagination;
+use std::collections::HashSet;
+use tracing::{error, warn};
+
+/// Most body-index hits taken as candidates.
+const BODY_CANDIDATES: usize = 500;
+
+#[derive(Clone)]
+struct PageState {
+    settings: SearchSettings,
+    /// The theme mounted at `/`, else the first one.
+    theme: Option<ThemeBinding>,
+}
+
+/// The search resource, for the root of the router.
+pub fn services(settings: SearchSettings, theme: Option<ThemeBinding>) -> impl HttpServiceFactory {
+    web::resource("/search")
+        .app_data(web::Data::new(PageState { settings, theme }))
+        .route(web::get().to(search_handler))
+}
+
+/// Whether the request asked for JSON rather than a page.
+fn wants_json(req: &HttpRequest, format: Option<&str>) -> bool {
+    match format {
+        Some(format) => format == "json",
+        None => req
+            .headers()
+            .get(ACCEPT)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with("application/json")),
+    }
+}
+
+fn str_at(doc: &Json, pointer: &str) -> String {
+    doc.pointer(pointer)
+        .and_then(Json::as_str)
+        .unwrap_or_default()
+        .to_owned()
+}
+
+/// The documents that may match `terms`, with their bodies.
+async fn candidates(content: &SiteContent, terms: &[String]) -> Result<Vec<SearchDoc>, String> {
+    let q = ContentQuery {
+        filter: Filter::And(vec![]),
+        opts: FindOptions::default(),
+    }
+    .published_only();
+    let (_, docs, _) = content
+        .query_front_matter(&q.filter, &q.opts)
+        .await
+        .map_err(|e| e.to_string())?;
+    let in_body: HashSet<String> = match content
+        .search_bodies(&terms.join(" "), BODY_CANDIDATES)
+        .await
+    {
+        Ok(ids) => ids.into_iter().collect(),
+        Err(e) => {
+            warn!("Search: the body index failed, titles only: {}", e);
+            HashSet::new()
+        }
+    };
+
+    let rules = content.permalinks();
+    let mut out = Vec::new();
+    for doc in docs {
+        let id = str_at(&doc, "/id");
+        let (title, summary) = (
+            str_at(&doc, "/content/title"),
+            str_at(&doc, "/content/summary"),
+        );
+        let named = tokenize(&format!("{title} {summary}"))
+            .iter()
+            .any(|w| terms.contains(w));
+        if !named && !in_body.contains(&id) {
+            continue;
+        }
+        let Some(path) = rules.url_for(&doc) else {
+            continue;
+        };
+        let body = match content.lookup_body(&id).await {
+            Ok(Some(html)) => html_text(&html),
+            _ => String::new(),
+        };
+        out.push(SearchDoc {
+            title: if title.is_empty() {
+                path.clone()
+            } else {
+                title
+            },
+            path,
+            summary,
+            body,
+            date: doc
+                .pointer("/publish/date")
+                .and_then(Json::as_str)
+                .map(str::to_owned),
+        });
+    }
+    Ok(out)
+}
+
+#[tracing::instrument(skip_all, fields(path = %req.path()))]
+async fn search_handler(state: web::Data<PageState>, req: HttpRequest) -> HttpResponse {
+    let (mut query, mut number, mut format) = (String::new(), 1, None);
+    for (k, v) in form_urlencoded::parse(req.query_string().as_bytes()) {
+        match &*k {
+            "q" => query = v.trim().to_owned(),
+            "page" => match v.parse::<usize>() {
+                Ok(n) if n > 0 => number = n,
+                _ => return HttpResponse::NotFound().finish(),
+            },
+            "format" => format = Some(v.into_owned()),
+            _ => {}
+        }
+    }
+    let json = wants_json(&req, format.as_deref());
+
+    let s = &state.settings;
+    let terms = query_terms(&query);
+    let docs = if terms.is_empty() {
+        Vec::new()
+    } else {
+        match candidates(&SiteContent::of(&req), &terms).await {
+            Ok(docs) => docs,
+            Err(e) => {
+                error!("Search: reading the front-matter index failed: {}", e);
+                return HttpResponse::ServiceUnavailable().finish();
+            }
+        }
+    };
+    let ranked = rank(&terms, &docs);
+    let page = Pagination::new(number, s.per_page, ranked.len());
+    if !page.exists() {
+        return HttpResponse::NotFound().finish();
+    }
+    let slice = ranked
+        .get(page.skip()..(page.skip() + page.per_page).min(ranked.len()))
+        .unwrap_or_default();
+    let model = page_model(&query, &terms, &docs, slice, page, s.snippet_chars);
+    if json {
+        return HttpResponse::Ok().json(model);
+    }
+
+    let themed = state
+        .theme
+        .as_ref()
+        .map(|t| {
+            TemplateRegistry::new(t.template_root.clone())
+                .with_language(t.engine)
+                .with_helpers(AssetManifests::of(&req).helpers(&t.assets_dir(), &t.mount_path))
+        })
+        .filter(|reg| reg.template_modified(&s.template).is_some());
+    let _slot = match quota::render_slot(&req).await {
+        Ok(slot) => slot,
+        Err(resp) => return resp,
+    };
+
+    let mut body = Vec::new();
+    let rendered = match themed {
+        Some(reg) => reg.render_to_write(&s.template, &model, &mut body),
+        None => render_default("search", DEFAULT_SEARCH_TEMPLATE, &model, &mut body),
+    };
+    match rendered {
+        Ok(()) => HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .body(body),
+        Err(e) => {
+            error!(
+                "Search page: rendering results for {:?} failed: {}",
+                query, e
+            );
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn json_is_asked_for_by_format_or_accept() {
+        let plain = TestRequest::get().uri("/search?q=x").to_http_request();
+        let accepting = TestRequest::get()
+            .uri("/search?q=x")
+            .insert_header((ACCEPT, "application/json"))
+            .to_http_request();
+        assert!(!wants_json(&plain, None));
+        assert!(wants_json(&plain, Some("json")));
+        assert!(wants_json(&accepting, None));
+        assert!(!wants_json(&accepting, Some("html")));
+    }
+}
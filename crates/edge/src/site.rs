@@ -0,0 +1,175 @@
+// crates/edge/src/site.rs
+
+// One site: its directory, its settings, its content (fs::index::SiteContent)
+// and the state built from them that outlives any one request (rate limits,
+// the approved comments, the flags, routes, collections and string catalogs
+// of its extensions, its redirects, CSRF key, mailer, asset manifests and
+// plugin storage, maintenance). The router hands the pieces to the routes and
+// middleware as app data, so nothing a site configures is process-wide and
+// two sites in one process keep apart (see `SiteRegistry` in proxy).
+//
+// Built once at startup, after its content indexes are opened and before
+// the content scan; every worker's router shares it.
+
+use crate::access_log::AccessLog;
+use crate::assets::AssetManifests;
+use crate::auth::{self, Auth};
+use crate::canonical::{self, Canonical};
+use crate::collections::Collections;
+use crate::comments::Comments;
+use crate::csrf::CsrfKey;
+use crate::db::kv::{FileKvStore, PLUGIN_KV_DIR};
+use crate::db::users::UserStore;
+use crate::flags::Flags;
+use crate::forms::Forms;
+use crate::fs::index::SiteContent;
+use crate::fs::reload::ExtensionChanges;
+use crate::i18n;
+use crate::images::ImageSizes;
+use crate::l10n::Strings;
+use crate::livereload::LiveReload;
+use crate::mail::{self, Mailer};
+use crate::maintenance::Maintenance;
+use crate::middleware::Stack;
+use crate::plugin_routes::PluginRouteTable;
+use crate::proxy::EdgeError;
+use crate::quota::Quotas;
+use crate::redirects::Redirects;
+use actix_web::web;
+use adapt::js::{EngineEnv, LogLevels};
+use domain::setting::{
+    EdgeSettings, I18nSettings, ImageSettings, QueryBudgetSettings, Settings, SitemapSettings,
+};
+use regex::Regex;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+pub struct Site {
+    pub root_dir: PathBuf,
+    /// `settings.toml`, with `edge.dev` on under `serve-dev`.
+    pub settings: Settings,
+    /// Where documents are scanned from, and which files count.
+    pub content_root: PathBuf,
+    pub content_re: Option<Regex>,
+    pub ext_dir: PathBuf,
+    pub content: Arc<SiteContent>,
+    pub edge: web::Data<EdgeSettings>,
+    pub sitemap: web::Data<SitemapSettings>,
+    pub canonical: Option<web::Data<Canonical>>,
+    pub images: ImageSettings,
+    pub query_budget: Option<web::Data<QueryBudgetSettings>>,
+    pub i18n: Option<web::Data<I18nSettings>>,
+    pub stack: web::Data<Stack>,
+    pub access_log: Option<web::Data<AccessLog>>,
+    pub maintenance: web::Data<Maintenance>,
+    pub quotas: Option<web::Data<Quotas>>,
+    pub auth: web::Data<Auth>,
+    /// One store for every worker, so changes to a user take turns.
+    pub users: web::Data<UserStore>,
+    pub flags: web::Data<Flags>,
+    pub strings: Arc<Strings>,
+    pub forms: web::Data<Forms>,
+    pub comments: web::Data<Comments>,
+    pub csrf: web::Data<CsrfKey>,
+    pub redirects: web::Data<Redirects>,
+    pub plugin_routes: web::Data<PluginRouteTable>,
+    pub collections: web::Data<Collections>,
+    pub assets: web::Data<AssetManifests>,
+    pub image_sizes: ImageSizes,
+    /// Sending the outbox, with a `[mail]` table.
+    pub mailer: Option<Arc<dyn Mailer>>,
+    /// Under `serve-dev`.
+    pub live: Option<web::Data<LiveReload>>,
+    pub ext_changes: web::Data<ExtensionChanges>,
+    /// Where the plugins and themes keep values, and how much they log.
+    pub engines: EngineEnv,
+}
+
+impl Site {
+    /// Check `settings` and build the site under `root_dir` from them, with
+    /// the `content` opened for it.
+    pub fn open(
+        root_dir: PathBuf,
+        mut settings: Settings,
+        content: Arc<SiteContent>,
+        content_root: PathBuf,
+        content_re: Option<Regex>,
+        ext_dir: PathBuf,
+        dev: bool,
+    ) -> Result<Self, EdgeError> {
+        settings.edge.dev |= dev;
+        let canonical = canonical::from_base_url(
+            settings
+                .sitemap
+                .as_ref()
+                .and_then(|s| s.base_url.as_deref()),
+        )?;
+        if let Some(i18n) = &settings.i18n {
+            i18n::check(i18n)?;
+        }
+        let stack = Stack::new(
+            settings.middleware.as_ref(),
+            settings.security_headers.clone(),
+            settings.access_log.is_some(),
+        )?;
+        let mut auth = Auth::new(settings.auth.clone().unwrap_or_default());
+        if settings.mail.is_some() {
+            auth = auth.with_resets(&root_dir)?;
+        }
+        let forms = Forms::open(&root_dir, settings.forms.clone().unwrap_or_default())?;
+        let comments = Comments::open(&root_dir, settings.comments.clone())?;
+        let mailer = match &settings.mail {
+            Some(mail) => Some(mail::init(&root_dir, mail)?),
+            None => None,
+        };
+        let log_levels = match &settings.ext {
+            Some(ext) => LogLevels::parse(&ext.log)
+                .map_err(|e| EdgeError::Config(format!("[ext.log] {e}")))?,
+            None => LogLevels::default(),
+        };
+
+        Ok(Self {
+            edge: web::Data::new(settings.edge.clone()),
+            sitemap: web::Data::new(settings.sitemap.clone().unwrap_or_default()),
+            canonical: canonical.map(web::Data::new),
+            images: settings.images.clone().unwrap_or_default(),
+            query_budget: settings.query_budget.clone().map(web::Data::new),
+            i18n: settings.i18n.clone().map(web::Data::new),
+            stack: web::Data::new(stack),
+            access_log: settings
+                .access_log
+                .as_ref()
+                .map(|s| web::Data::new(AccessLog::new(&root_dir, s))),
+            maintenance: web::Data::new(Maintenance::new(&root_dir, settings.maintenance.clone())),
+            quotas: settings
+                .quotas
+                .clone()
+                .map(|q| web::Data::new(Quotas::new(q))),
+            auth: web::Data::new(auth),
+            users: auth::store(&root_dir),
+            flags: web::Data::new(Flags::new(settings.flags.clone().unwrap_or_default())),
+            strings: Arc::new(Strings::new(settings.i18n.as_ref())),
+            forms: web::Data::new(forms),
+            comments: web::Data::new(comments),
+            csrf: web::Data::new(CsrfKey::open(&root_dir)?),
+            redirects: web::Data::new(Redirects::open(&root_dir)),
+            plugin_routes: Default::default(),
+            collections: web::Data::new(Collections::open(&root_dir)),
+            assets: Default::default(),
+            image_sizes: ImageSizes::default(),
+            mailer,
+            live: dev.then(|| web::Data::new(LiveReload::new())),
+            ext_changes: Default::default(),
+            engines: EngineEnv {
+                storage: Arc::new(FileKvStore::new(root_dir.join(PLUGIN_KV_DIR))),
+                log_levels: Arc::new(log_levels),
+            },
+            root_dir,
+            settings,
+            content,
+            content_root,
+            content_re,
+            ext_dir,
+        })
+    }
+}
@@ -0,0 +1,174 @@
+// crates/edge/src/sitemap.rs
+
+// Routes for serve::sitemap, mounted ahead of every theme:
+//
+//   GET /sitemap.xml      urlset, or a sitemap index past 50k URLs
+//   GET /sitemap-{n}.xml  page n of a split sitemap
+//   GET /robots.txt
+//
+// Configured by an optional `[sitemap]` table in settings.toml:
+//
+//   [sitemap]
+//   base_url = "https://example.com"
+//
+//   [sitemap.robots]
+//   disallow = ["/drafts/"]
+//
+// The table is app data of the scope around the router's middleware layers,
+// since feeds, plugin routes and theme pages build absolute URLs from it too.
+
+use crate::fs::index::ContentMgr;
+use actix_web::{dev::HttpServiceFactory, web, HttpRequest, HttpResponse};
+use domain::setting::SitemapSettings;
+use serve::sitemap::{collect_entries, robots_txt, sitemap_page, sitemap_xml, SitemapEntry};
+use tracing::error;
+
+/// The `[sitemap]` table of the site serving `req`; the defaults without one.
+fn settings(req: &HttpRequest) -> web::Data<SitemapSettings> {
+    req.app_data::<web::Data<SitemapSettings>>()
+        .cloned()
+        .unwrap_or_else(|| web::Data::new(SitemapSettings::default()))
+}
+
+/// The sitemap and robots.txt resources over the documents `mgr` indexes,
+/// for the root of the router.
+pub fn services(mgr: ContentMgr) -> impl HttpServiceFactory {
+    let mgr = web::Data::new(mgr);
+    (
+        web::resource("/sitemap.xml")
+            .app_data(mgr.clone())
+            .route(web::get().to(sitemap_handler)),
+        web::resource("/sitemap-{page}.xml")
+            .app_data(mgr)
+            .route(web::get().to(sitemap_page_handler)),
+        web::resource("/robots.txt").route(web::get().to(robots_handler)),
+    )
+}
+
+/// The site's absolute URL: `base_url` from settings, else the scheme and
+/// host this request used.
+pub(crate) fn base_url(req: &HttpRequest) -> String {
+    match &settings(req).base_url {
+        Some(url) => url.trim_end_matches('/').to_owned(),
+        None => {
+            let info = req.connection_info();
+            format!("{}://{}", info.scheme(), info.host())
+        }
+    }
+}
+
+async fn entries(mgr: &ContentMgr) -> Result<Vec<SitemapEntry>, HttpResponse> {
+    collect_entries(mgr).await.map_err(|e| {
+        error!("Sitemap: reading the front-matter index failed: {}", e);
+        HttpResponse::ServiceUnavailable().finish()
+    })
+}
+
+fn xml(body: String) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("application/xml; charset=utf-8")
+        .body(body)
+}
+
+#[tracing::instrument(skip_all)]
+async fn sitemap_handler(mgr: web::Data<ContentMgr>, req: HttpRequest) -> HttpResponse {
+    match entries(&mgr).await {
+        Ok(entries) => xml(sitemap_xml(&entries, &base_url(&req))),
+        Err(resp) => resp,
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn sitemap_page_handler(mgr: web::Data<ContentMgr>, req: HttpRequest) -> HttpResponse {
+    let Some(page) = req.match_info().get("page").and_then(|p| p.parse().ok()) else {
+        return HttpResponse::NotFound().finish();
+    };
+
+    match entries(&mgr).await {
+        Ok(entries) => match sitemap_page(&entries, &base_url(&req), page) {
+            Some(body) => xml(body),
+            None => HttpResponse::NotFound().finish(),
+        },
+        Err(resp) => resp,
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn robots_handler(req: HttpRequest) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; charset=utf-8")
+        .body(robots_txt(&settings(&req).robots, &base_url(&req)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::index::SiteContent;
+    use crate::init::Answers;
+    use actix_web::{test, App};
+    use domain::setting::Settings;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    #[actix_web::test]
+    async fn the_sitemap_lists_listed_documents_at_their_permalinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_path_buf();
+        let settings: Settings = toml::from_str(
+            &(Answers::default().settings_toml().unwrap()
+                + "\n[permalinks]\npost = \"/:year/:slug/\"\n"),
+        )
+        .unwrap();
+        let content = SiteContent::open(&root, &settings, root.join("index"), true)
+            .await
+            .unwrap();
+        for (served, fm) in [
+            (
+                "posts/hello.html",
+                json!({ "type": "post", "slug": "hello",
+                        "publish": { "status": "publish", "date": "2024-03-09T10:00:00Z" } }),
+            ),
+            ("docs/index.html", json!({ "type": "page" })),
+            (
+                "drafts/wip.html",
+                json!({ "publish": { "status": "draft" } }),
+            ),
+            ("feed.json", json!({ "type": "data" })),
+        ] {
+            content
+                .index_front_matter(root.clone(), &root.join(served), &fm)
+                .await
+                .unwrap();
+        }
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(SitemapSettings {
+                    base_url: Some("https://example.com/".into()),
+                    ..SitemapSettings::default()
+                }))
+                .service(services(ContentMgr::new(root, Arc::new(content)))),
+        )
+        .await;
+        let get = |uri: &str| test::TestRequest::get().uri(uri).to_request();
+
+        let resp = test::call_service(&app, get("/sitemap.xml")).await;
+        assert_eq!(
+            resp.headers().get("content-type").unwrap(),
+            "application/xml; charset=utf-8"
+        );
+        let xml = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+        assert!(xml.contains("<loc>https://example.com/2024/hello/</loc>"));
+        assert!(xml.contains("<lastmod>2024-03-09T10:00:00Z</lastmod>"));
+        assert!(xml.contains("<loc>https://example.com/docs/</loc>"));
+        assert!(!xml.contains("wip") && !xml.contains("feed.json"));
+
+        // Everything fits in one file, so there are no pages.
+        let page = test::call_service(&app, get("/sitemap-1.xml")).await;
+        assert_eq!(page.status(), 404);
+
+        let robots = test::call_and_read_body(&app, get("/robots.txt")).await;
+        assert!(std::str::from_utf8(&robots)
+            .unwrap()
+            .ends_with("Sitemap: https://example.com/sitemap.xml\n"));
+    }
+}
@@ -0,0 +1,184 @@
+// crates/edge/src/taxonomies.rs
+
+// Archive pages for tags, categories and series (serve::taxonomy):
+//
+//   GET /tags/{tag}                      newest published posts first
+//   GET /tags/{tag}/page/{n}             later pages; `/page/1` redirects
+//   GET /tags/{tag}/feed.{ext}           its feed (mounted by crate::feeds)
+//
+// and the same under `/categories/` and `/series/`. A page is one query
+// against the front-matter index; a term nothing is tagged with, or a page
+// past the last, is a 404. Pages render the theme's taxonomy template like
+// author pages do (crate::authors). Configured by an optional `[taxonomies]`
+// table in settings.toml:
+//
+//   [taxonomies]
+//   per_page = 10
+//   template = "taxonomy.hbs"
+
+use crate::api::content::ContentQuery;
+use crate::assets::AssetManifests;
+use crate::fs::{ext::ThemeBinding, index::SiteContent};
+use crate::quota;
+use actix_web::http::header::LOCATION;
+use actix_web::{dev::HttpServiceFactory, web, HttpRequest, HttpResponse};
+use adapt::mql::{parser::parse_filter, FindOptions};
+use domain::setting::TaxonomySettings;
+use serde_json::{json, Value as Json};
+use serve::render::{render_default, template::TemplateRegistry, TemplateEngine};
+use serve::taxonomy::{page_model, Pagination, Taxonomy, DEFAULT_TAXONOMY_TEMPLATE};
+use tracing::error;
+
+#[derive(Clone)]
+struct PageState {
+    settings: TaxonomySettings,
+    /// The theme mounted at `/`, else the first one.
+    theme: Option<ThemeBinding>,
+}
+
+/// The archive page resources, for the root of the router.
+pub fn services(
+    settings: TaxonomySettings,
+    theme: Option<ThemeBinding>,
+) -> impl HttpServiceFactory {
+    web::resource([
+        "/{taxonomy:tags|categories|series}/{term}",
+        "/{taxonomy:tags|categories|series}/{term}/page/{page}",
+    ])
+    .app_data(web::Data::new(PageState { settings, theme }))
+    .route(web::get().to(taxonomy_handler))
+}
+
+/// The index query for page `number` of `term`, `per_page` to a page.
+fn page_query(
+    taxonomy: Taxonomy,
+    term: &str,
+    number: usize,
+    per_page: usize,
+) -> Result<ContentQuery, String> {
+    let filter = parse_filter(&json!({ taxonomy.field(): { "$all": [term] } }))
+        .map_err(|e| e.to_string())?;
+    let page = Pagination::new(number, per_page, 0);
+    Ok(ContentQuery {
+        filter,
+        opts: FindOptions {
+            sort: vec![("publish.date".into(), -1), ("id".into(), 1)],
+            limit: Some(page.per_page),
+            skip: Some(page.skip()),
+            ..FindOptions::default()
+        },
+    }
+    .published_only())
+}
+
+#[tracing::instrument(skip_all, fields(path = %req.path()))]
+async fn taxonomy_handler(state: web::Data<PageState>, req: HttpRequest) -> HttpResponse {
+    let info = req.match_info();
+    let (Some(taxonomy), Some(term)) = (
+        info.get("taxonomy").and_then(Taxonomy::from_segment),
+        info.get("term"),
+    ) else {
+        return HttpResponse::NotFound().finish();
+    };
+    let number = match info.get("page").map(str::parse::<usize>) {
+        None => 1,
+        Some(Ok(1)) => {
+            return HttpResponse::MovedPermanently()
+                .insert_header((LOCATION, taxonomy.page_path(term, 1)))
+                .finish()
+        }
+        Some(Ok(n)) if n > 1 => n,
+        Some(_) => return HttpResponse::NotFound().finish(),
+    };
+
+    let per_page = state.settings.per_page;
+    let q = match page_query(taxonomy, term, number, per_page) {
+        Ok(q) => q,
+        Err(e) => {
+            error!(
+                "Taxonomy page: building the query for {} failed: {}",
+                term, e
+            );
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    let content = SiteContent::of(&req);
+    let (total, docs) = match content.query_front_matter(&q.filter, &q.opts).await {
+        Ok((total, docs, _)) => (total, docs),
+        Err(e) => {
+            error!(
+                "Taxonomy page: reading the front-matter index failed: {}",
+                e
+            );
+            return HttpResponse::ServiceUnavailable().finish();
+        }
+    };
+    let page = Pagination::new(number, per_page, total);
+    if total == 0 || !page.exists() {
+        return HttpResponse::NotFound().finish();
+    }
+
+    let rules = content.permalinks();
+    let posts: Vec<(String, Json)> = docs
+        .into_iter()
+        .filter_map(|doc| Some((rules.url_for(&doc)?, doc)))
+        .collect();
+    let model = page_model(taxonomy, term, &posts, page);
+    let template = &state.settings.template;
+    let themed = state
+        .theme
+        .as_ref()
+        .map(|t| {
+            TemplateRegistry::new(t.template_root.clone())
+                .with_language(t.engine)
+                .with_helpers(AssetManifests::of(&req).helpers(&t.assets_dir(), &t.mount_path))
+        })
+        .filter(|reg| reg.template_modified(template).is_some());
+
+    let _slot = match quota::render_slot(&req).await {
+        Ok(slot) => slot,
+        Err(resp) => return resp,
+    };
+
+    let mut body = Vec::new();
+    let rendered = match themed {
+        Some(reg) => reg.render_to_write(template, &model, &mut body),
+        None => render_default("taxonomy", DEFAULT_TAXONOMY_TEMPLATE, &model, &mut body),
+    };
+    match rendered {
+        Ok(()) => HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .body(body),
+        Err(e) => {
+            error!(
+                "Taxonomy page: rendering {} {} failed: {}",
+                taxonomy.segment(),
+                term,
+                e
+            );
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use adapt::mql::eval_filter;
+
+    #[test]
+    fn pages_query_one_slice_of_published_posts() {
+        let q = page_query(Taxonomy::Series, "engines", 3, 10).unwrap();
+        assert_eq!(q.opts.skip, Some(20));
+        assert_eq!(q.opts.limit, Some(10));
+        assert_eq!(q.opts.sort[0], ("publish.date".to_owned(), -1));
+
+        let doc = |status: &str, series: &[&str]| json!({ "publish": { "status": status }, "tax": { "series": series } });
+        assert!(eval_filter(
+            &q.filter,
+            &doc("published", &["engines", "looms"])
+        ));
+        assert!(!eval_filter(&q.filter, &doc("draft", &["engines"])));
+        assert!(!eval_filter(&q.filter, &doc("published", &["looms"])));
+    }
+}
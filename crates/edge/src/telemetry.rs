@@ -0,0 +1,163 @@
+// crates/edge/src/telemetry.rs
+
+// Tracing setup: console logs plus optional OTLP trace export.
+//
+// Logs keep the RUST_LOG filter (default `warn`), except that plugin and
+// theme `log` calls (target `extension`) are let through at every level:
+// `[ext.log]` already picked which to emit, per extension. A RUST_LOG that
+// names `extension` itself takes precedence. Under `serve-dev` the MQL query
+// planner logs at debug too, how it found each query's candidates. When
+// settings.toml has a
+// `[telemetry]` table, spans from WhisperCMS's own crates are also exported
+// over OTLP/gRPC, so a request's resolver lookups, plugin hooks and render
+// stages arrive as one trace in Jaeger or Tempo. Plugin `fetch` requests
+// carry the trace on as a W3C `traceparent`:
+//
+//   [telemetry]
+//   endpoint = "http://localhost:4317"
+//   sample_rate = 0.25
+//   service_name = "blog"
+
+use adapt::js::log::LOG_TARGET;
+use domain::setting::TelemetrySettings;
+use opentelemetry::propagation::Extractor;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{Sampler, SdkTracerProvider};
+use opentelemetry_sdk::Resource;
+use std::collections::HashMap;
+use tracing::{warn, Level, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::{fmt, prelude::*, EnvFilter};
+
+/// Target of the query planner's plan choices.
+const PLANNER_TARGET: &str = "adapt::mql";
+
+/// Crates whose spans are exported. Dependencies (actix, tantivy, h2, …)
+/// would flood the collector and add little.
+const TRACED_TARGETS: &[&str] = &["whispercms", "edge", "serve", "adapt", "domain"];
+
+/// Keeps the exporter alive; dropping it flushes buffered spans.
+pub struct TelemetryGuard {
+    provider: Option<SdkTracerProvider>,
+}
+
+impl Drop for TelemetryGuard {
+    fn drop(&mut self) {
+        if let Some(provider) = self.provider.take() {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("Flushing traces failed: {e}");
+            }
+        }
+    }
+}
+
+fn build_provider(settings: &TelemetrySettings) -> Result<SdkTracerProvider, String> {
+    let exporter = SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(&settings.endpoint)
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    let rate = settings.sample_rate.clamp(0.0, 1.0);
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+            rate,
+        ))))
+        .with_resource(
+            Resource::builder()
+                .with_service_name(settings.service_name.clone())
+                .build(),
+        )
+        .build())
+}
+
+/// Install the global subscriber. Must run inside the Tokio runtime, which
+/// the gRPC exporter uses for its connection.
+///
+/// An exporter that cannot be built is reported and skipped; logging still
+/// comes up. `dev` adds the planner's debug logs.
+pub fn init(settings: Option<&TelemetrySettings>, dev: bool) -> TelemetryGuard {
+    let mut filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("warn")); // fallback
+    let named =
+        |target: &str| std::env::var(EnvFilter::DEFAULT_ENV).is_ok_and(|v| v.contains(target));
+    if !named(LOG_TARGET) {
+        let all = format!("{LOG_TARGET}=trace")
+            .parse()
+            .expect("valid directive");
+        filter = filter.add_directive(all);
+    }
+    if dev && !named(PLANNER_TARGET) {
+        let plans = format!("{PLANNER_TARGET}=debug")
+            .parse()
+            .expect("valid directive");
+        filter = filter.add_directive(plans);
+    }
+    let logs = fmt::layer()
+        .with_file(true)
+        .with_line_number(true)
+        .with_filter(filter);
+
+    let provider = settings.map(|s| (s, build_provider(s)));
+    let otel = match &provider {
+        Some((_, Ok(provider))) => {
+            opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+            adapt::js::fetch::set_trace_headers(outbound_headers);
+            let targets =
+                Targets::new().with_targets(TRACED_TARGETS.iter().map(|t| (*t, Level::INFO)));
+            Some(
+                tracing_opentelemetry::layer()
+                    .with_tracer(provider.tracer("whispercms"))
+                    .with_filter(targets),
+            )
+        }
+        _ => None,
+    };
+
+    tracing_subscriber::registry().with(logs).with(otel).init();
+
+    match provider {
+        Some((s, Ok(provider))) => {
+            tracing::info!("Exporting traces to {}", s.endpoint);
+            TelemetryGuard {
+                provider: Some(provider),
+            }
+        }
+        Some((s, Err(e))) => {
+            warn!("Trace export to {} disabled: {}", s.endpoint, e);
+            TelemetryGuard { provider: None }
+        }
+        None => TelemetryGuard { provider: None },
+    }
+}
+
+struct HeaderExtractor<'a>(&'a actix_web::http::header::HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|v| v.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|k| k.as_str()).collect()
+    }
+}
+
+/// Continue the caller's trace when the request carries a W3C
+/// `traceparent`, so an upstream service and this request share one trace.
+pub fn adopt_remote_parent(span: &Span, headers: &actix_web::http::header::HeaderMap) {
+    let cx =
+        opentelemetry::global::get_text_map_propagator(|p| p.extract(&HeaderExtractor(headers)));
+    let _ = span.set_parent(cx);
+}
+
+/// The current span's trace context as headers, for requests plugins make.
+fn outbound_headers() -> Vec<(String, String)> {
+    let mut headers = HashMap::new();
+    let cx = Span::current().context();
+    opentelemetry::global::get_text_map_propagator(|p| p.inject_context(&cx, &mut headers));
+    headers.into_iter().collect()
+}
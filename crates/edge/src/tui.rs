@@ -0,0 +1,524 @@
+// crates/edge/src/tui.rs
+
+// `whispercms tui <DIR>`: a terminal dashboard for operating a site on a
+// headless server, e.g. over SSH, without exposing a web UI.
+//
+//   Status       listeners, directories, extensions and maintenance at a glance
+//   Plugins      enable or disable plugins (Space)
+//   Maintenance  start or end manual maintenance (m)
+//   Logs         the tail of the server's log file, given with `--log`
+//
+// Every action is a file change in the site directory: the plugin
+// `disabled` marker and `maintenance.toml`. A running server picks both up
+// without a restart, and they work the same when it is stopped.
+
+use crate::fs::ext;
+use chrono::{DateTime, Duration, Utc};
+use domain::setting::{MaintenanceWindow, Settings};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Tabs, Wrap};
+use ratatui::Frame;
+use serve::maintenance::{read_manual, write_manual, MaintenanceSchedule, MANUAL_FILE};
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// How often the screen is refreshed from disk without a key press.
+const REFRESH: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Bytes read from the end of the log file.
+const LOG_TAIL_BYTES: u64 = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tab {
+    Status,
+    Plugins,
+    Maintenance,
+    Logs,
+}
+
+impl Tab {
+    const ALL: [Tab; 4] = [Tab::Status, Tab::Plugins, Tab::Maintenance, Tab::Logs];
+
+    fn title(self) -> &'static str {
+        match self {
+            Tab::Status => "Status",
+            Tab::Plugins => "Plugins",
+            Tab::Maintenance => "Maintenance",
+            Tab::Logs => "Logs",
+        }
+    }
+
+    fn index(self) -> usize {
+        Self::ALL.iter().position(|t| *t == self).unwrap_or(0)
+    }
+
+    fn step(self, by: isize) -> Self {
+        let n = Self::ALL.len() as isize;
+        Self::ALL[(self.index() as isize + by).rem_euclid(n) as usize]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PluginRow {
+    /// Directory name below `plugins/`.
+    pub name: String,
+    pub dir: PathBuf,
+    pub enabled: bool,
+}
+
+/// Everything shown on screen, re-read from the site directory on refresh.
+pub struct Dashboard {
+    dir: PathBuf,
+    settings: Result<Settings, String>,
+    ext_dir: PathBuf,
+    log: Option<PathBuf>,
+    maintenance_minutes: i64,
+
+    pub tab: Tab,
+    pub plugins: Vec<PluginRow>,
+    themes: Vec<(String, String)>,
+    manual: Option<MaintenanceWindow>,
+    log_lines: Vec<String>,
+    selected: usize,
+    /// Outcome of the last action, shown in the footer.
+    message: Option<String>,
+}
+
+impl Dashboard {
+    pub fn open(dir: PathBuf, log: Option<PathBuf>, maintenance_minutes: u32) -> Self {
+        let settings = load_settings(&dir);
+        let ext_dir = dir.join(
+            settings
+                .as_ref()
+                .ok()
+                .and_then(|s| s.ext.as_ref())
+                .map_or_else(|| PathBuf::from("./extensions/"), |e| e.dir.clone()),
+        );
+
+        let mut dash = Self {
+            dir,
+            settings,
+            ext_dir,
+            log,
+            maintenance_minutes: i64::from(maintenance_minutes.max(1)),
+            tab: Tab::Status,
+            plugins: Vec::new(),
+            themes: Vec::new(),
+            manual: None,
+            log_lines: Vec::new(),
+            selected: 0,
+            message: None,
+        };
+        dash.refresh();
+        dash
+    }
+
+    /// Re-read extensions, manual maintenance and the log tail.
+    pub fn refresh(&mut self) {
+        self.plugins = list_plugins(&self.ext_dir.join("plugins/"));
+        self.selected = self.selected.min(self.plugins.len().saturating_sub(1));
+        self.themes = ext::discover_themes(self.ext_dir.join("themes/"))
+            .map(|themes| {
+                themes
+                    .into_iter()
+                    .map(|t| (t.spec.id, t.mount_path))
+                    .collect()
+            })
+            .unwrap_or_default();
+        self.manual = read_manual(&self.dir.join(MANUAL_FILE));
+        self.log_lines = match &self.log {
+            Some(path) => tail_lines(path, LOG_TAIL_BYTES)
+                .unwrap_or_else(|e| vec![format!("Cannot read {}: {e}", path.display())]),
+            None => Vec::new(),
+        };
+    }
+
+    /// Apply a key press; `true` means quit.
+    pub fn handle_key(&mut self, key: KeyEvent) -> bool {
+        let result = match (self.tab, key.code) {
+            (_, KeyCode::Char('q') | KeyCode::Esc) => return true,
+            (_, KeyCode::Tab | KeyCode::Right) => {
+                self.tab = self.tab.step(1);
+                Ok(())
+            }
+            (_, KeyCode::BackTab | KeyCode::Left) => {
+                self.tab = self.tab.step(-1);
+                Ok(())
+            }
+            (Tab::Plugins, KeyCode::Up) => {
+                self.selected = self.selected.saturating_sub(1);
+                Ok(())
+            }
+            (Tab::Plugins, KeyCode::Down) => {
+                self.selected = (self.selected + 1).min(self.plugins.len().saturating_sub(1));
+                Ok(())
+            }
+            (Tab::Plugins, KeyCode::Char(' ')) => self.toggle_plugin(),
+            (Tab::Maintenance, KeyCode::Char('m')) => self.toggle_maintenance(Utc::now()),
+            (_, KeyCode::Char('r')) => Ok(()),
+            _ => return false,
+        };
+        if let Err(e) = result {
+            self.message = Some(format!("Failed: {e}"));
+        }
+        self.refresh();
+        false
+    }
+
+    /// Flip the selected plugin between enabled and disabled.
+    pub fn toggle_plugin(&mut self) -> io::Result<()> {
+        let Some(row) = self.plugins.get(self.selected) else {
+            return Ok(());
+        };
+        ext::set_plugin_enabled(&row.dir, !row.enabled)?;
+        self.message = Some(format!(
+            "{} {}",
+            if row.enabled { "Disabled" } else { "Enabled" },
+            row.name
+        ));
+        Ok(())
+    }
+
+    /// End manual maintenance if it is in effect at `now`, else start a
+    /// window of the configured length.
+    pub fn toggle_maintenance(&mut self, now: DateTime<Utc>) -> io::Result<()> {
+        let path = self.dir.join(MANUAL_FILE);
+        if self.manual_active(now).is_some() {
+            fs::remove_file(&path)?;
+            self.message = Some("Maintenance ended".to_owned());
+        } else {
+            let window = MaintenanceWindow {
+                start: now,
+                end: now + Duration::minutes(self.maintenance_minutes),
+                message: None,
+            };
+            write_manual(&path, &window)?;
+            self.message = Some(format!("Maintenance until {}", window.end));
+        }
+        self.manual = read_manual(&path);
+        Ok(())
+    }
+
+    fn manual_active(&self, now: DateTime<Utc>) -> Option<&MaintenanceWindow> {
+        self.manual
+            .as_ref()
+            .filter(|w| w.start <= now && now < w.end)
+    }
+
+    fn schedule(&self) -> MaintenanceSchedule {
+        let windows = self
+            .settings
+            .as_ref()
+            .ok()
+            .and_then(|s| s.maintenance.as_ref())
+            .map(|m| m.windows.clone())
+            .unwrap_or_default();
+        MaintenanceSchedule::new(windows)
+    }
+
+    pub fn draw(&self, frame: &mut Frame) {
+        let [tabs, body, footer] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        frame.render_widget(
+            Tabs::new(Tab::ALL.iter().map(|t| t.title()))
+                .select(self.tab.index())
+                .highlight_style(Style::new().add_modifier(Modifier::REVERSED))
+                .block(Block::bordered().title(format!(" WhisperCMS · {} ", self.dir.display()))),
+            tabs,
+        );
+
+        let block = Block::bordered().title(format!(" {} ", self.tab.title()));
+        match self.tab {
+            Tab::Plugins => {
+                let items: Vec<ListItem> = self
+                    .plugins
+                    .iter()
+                    .map(|p| {
+                        ListItem::new(format!(
+                            "[{}] {}",
+                            if p.enabled { "x" } else { " " },
+                            p.name
+                        ))
+                    })
+                    .collect();
+                let mut state = ListState::default().with_selected(Some(self.selected));
+                frame.render_stateful_widget(
+                    List::new(items)
+                        .block(block)
+                        .highlight_style(Style::new().add_modifier(Modifier::REVERSED)),
+                    body,
+                    &mut state,
+                );
+            }
+            Tab::Logs => {
+                let height = usize::from(body.height.saturating_sub(2));
+                let lines: Vec<Line> = match &self.log {
+                    None => vec![Line::from(
+                        "Start the TUI with --log <FILE> to follow a log.",
+                    )],
+                    Some(_) => self.log_lines[self.log_lines.len().saturating_sub(height)..]
+                        .iter()
+                        .map(|l| Line::from(l.as_str()))
+                        .collect(),
+                };
+                frame.render_widget(Paragraph::new(lines).block(block), body);
+            }
+            Tab::Status | Tab::Maintenance => {
+                let lines = if self.tab == Tab::Status {
+                    self.status_lines()
+                } else {
+                    self.maintenance_lines(Utc::now())
+                };
+                frame.render_widget(
+                    Paragraph::new(lines)
+                        .block(block)
+                        .wrap(Wrap { trim: false }),
+                    body,
+                );
+            }
+        }
+
+        let help = match self.tab {
+            Tab::Plugins => "↑/↓ select · Space enable/disable",
+            Tab::Maintenance => "m start/end maintenance",
+            _ => "r refresh",
+        };
+        let footer_text = match &self.message {
+            Some(m) => format!(" {m} · {help} · Tab switch · q quit"),
+            None => format!(" {help} · Tab switch · q quit"),
+        };
+        frame.render_widget(Paragraph::new(footer_text), footer);
+    }
+
+    fn status_lines(&self) -> Vec<Line<'_>> {
+        let mut lines = Vec::new();
+        match &self.settings {
+            Ok(s) => {
+                lines.push(Line::from(format!(
+                    "Edge:       {} (http {}, https {})",
+                    s.edge.ip, s.edge.http_port, s.edge.https_port
+                )));
+                lines.push(Line::from(format!(
+                    "Loopback:   {} (ports {}/{})",
+                    s.loopback.ip, s.loopback.port_a, s.loopback.port_b
+                )));
+                let content = s
+                    .content
+                    .as_ref()
+                    .map_or_else(|| PathBuf::from("./content/"), |c| c.dir.clone());
+                lines.push(Line::from(format!("Content:    {}", content.display())));
+            }
+            Err(e) => lines.push(Line::from(format!("settings.toml: {e}"))),
+        }
+        lines.push(Line::from(format!(
+            "Extensions: {}",
+            self.ext_dir.display()
+        )));
+
+        let enabled = self.plugins.iter().filter(|p| p.enabled).count();
+        lines.push(Line::from(format!(
+            "Plugins:    {enabled} enabled of {}",
+            self.plugins.len()
+        )));
+        lines.push(Line::from(format!("Themes:     {}", self.themes.len())));
+        for (id, mount) in &self.themes {
+            lines.push(Line::from(format!("  {mount:<12} {id}")));
+        }
+
+        let now = Utc::now();
+        let state = match (self.manual_active(now), self.schedule().active(now)) {
+            (Some(w), _) => format!("manual, until {}", w.end),
+            (None, Some(w)) => format!("scheduled, until {}", w.end),
+            (None, None) => "off".to_owned(),
+        };
+        lines.push(Line::from(format!("Maintenance: {state}")));
+        lines
+    }
+
+    fn maintenance_lines(&self, now: DateTime<Utc>) -> Vec<Line<'_>> {
+        let mut lines = vec![match self.manual_active(now) {
+            Some(w) => Line::from(format!("Manual maintenance is ON until {}.", w.end)),
+            None => Line::from(format!(
+                "Manual maintenance is off; m starts {} minutes of it.",
+                self.maintenance_minutes
+            )),
+        }];
+        lines.push(Line::from(""));
+        lines.push(Line::from("Scheduled windows (settings.toml):"));
+        let schedule = self.schedule();
+        let upcoming: Vec<_> = schedule.upcoming(now).collect();
+        if upcoming.is_empty() {
+            lines.push(Line::from("  none"));
+        }
+        for w in upcoming {
+            lines.push(Line::from(format!(
+                "  {} – {}  {}",
+                w.start,
+                w.end,
+                w.message.as_deref().unwrap_or_default()
+            )));
+        }
+        lines
+    }
+}
+
+fn load_settings(dir: &Path) -> Result<Settings, String> {
+    let path = dir.join("settings.toml");
+    let text = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+    toml::from_str(&text).map_err(|e| e.to_string())
+}
+
+/// Every plugin directory below `root`, enabled or not, by name.
+fn list_plugins(root: &Path) -> Vec<PluginRow> {
+    let Ok(entries) = fs::read_dir(root) else {
+        return Vec::new();
+    };
+    let mut rows: Vec<PluginRow> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.join("plugin.toml").is_file())
+        .map(|dir| PluginRow {
+            name: dir
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            enabled: ext::plugin_enabled(&dir),
+            dir,
+        })
+        .collect();
+    rows.sort_by(|a, b| a.name.cmp(&b.name));
+    rows
+}
+
+/// The complete lines in the last `max_bytes` of `path`.
+fn tail_lines(path: &Path, max_bytes: u64) -> io::Result<Vec<String>> {
+    let mut file = fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    let start = len.saturating_sub(max_bytes);
+    file.seek(SeekFrom::Start(start))?;
+
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf)?;
+    let text = String::from_utf8_lossy(&buf);
+    let mut lines: Vec<String> = text.lines().map(str::to_owned).collect();
+    // The first line is likely cut off mid-way.
+    if start > 0 && !lines.is_empty() {
+        lines.remove(0);
+    }
+    Ok(lines)
+}
+
+/// Run the dashboard until the operator quits.
+pub fn run(dir: PathBuf, log: Option<PathBuf>, maintenance_minutes: u32) -> io::Result<()> {
+    let mut dash = Dashboard::open(dir, log, maintenance_minutes);
+    let mut terminal = ratatui::init();
+
+    let result = (|| loop {
+        terminal.draw(|frame| dash.draw(frame))?;
+        if !event::poll(REFRESH)? {
+            dash.refresh();
+            continue;
+        }
+        if let Event::Key(key) = event::read()? {
+            if key.kind == KeyEventKind::Press && dash.handle_key(key) {
+                return Ok(());
+            }
+        }
+    })();
+
+    ratatui::restore();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::backend::TestBackend;
+    use ratatui::crossterm::event::KeyModifiers;
+    use ratatui::Terminal;
+
+    fn site() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["seo", "analytics"] {
+            let plugin = dir.path().join("extensions/plugins").join(name);
+            fs::create_dir_all(&plugin).unwrap();
+            fs::write(plugin.join("plugin.toml"), "").unwrap();
+            fs::write(plugin.join("plugin.js"), "").unwrap();
+        }
+        dir
+    }
+
+    fn press(dash: &mut Dashboard, code: KeyCode) -> bool {
+        dash.handle_key(KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    #[test]
+    fn plugins_are_toggled_with_the_disabled_marker() {
+        let dir = site();
+        let mut dash = Dashboard::open(dir.path().to_path_buf(), None, 30);
+        let names: Vec<_> = dash.plugins.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["analytics", "seo"]);
+
+        press(&mut dash, KeyCode::Tab);
+        assert_eq!(dash.tab, Tab::Plugins);
+        press(&mut dash, KeyCode::Down);
+        press(&mut dash, KeyCode::Char(' '));
+
+        let seo = dir.path().join("extensions/plugins/seo");
+        assert!(seo.join(ext::PLUGIN_DISABLED_MARKER).exists());
+        assert!(!dash.plugins[1].enabled);
+        let loaded = ext::discover_plugins(dir.path().join("extensions/plugins")).unwrap();
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded[0].spec.id, "analytics");
+
+        press(&mut dash, KeyCode::Char(' '));
+        assert!(dash.plugins[1].enabled);
+        assert!(press(&mut dash, KeyCode::Char('q')));
+    }
+
+    #[test]
+    fn maintenance_is_started_and_ended_through_the_manual_file() {
+        let dir = site();
+        let mut dash = Dashboard::open(dir.path().to_path_buf(), None, 30);
+        let now = Utc::now();
+
+        dash.toggle_maintenance(now).unwrap();
+        let window = read_manual(&dir.path().join(MANUAL_FILE)).unwrap();
+        assert_eq!(window.end - window.start, Duration::minutes(30));
+        assert!(dash.manual_active(now).is_some());
+
+        dash.toggle_maintenance(now).unwrap();
+        assert!(!dir.path().join(MANUAL_FILE).exists());
+        assert!(dash.manual_active(now).is_none());
+    }
+
+    #[test]
+    fn logs_show_the_last_complete_lines() {
+        let dir = site();
+        let log = dir.path().join("edge.log");
+        fs::write(&log, "first line\nsecond\nthird\n").unwrap();
+        assert_eq!(tail_lines(&log, 14).unwrap(), ["second", "third"]);
+
+        let mut dash = Dashboard::open(dir.path().to_path_buf(), Some(log), 30);
+        dash.tab = Tab::Logs;
+        let mut terminal = Terminal::new(TestBackend::new(60, 10)).unwrap();
+        terminal.draw(|f| dash.draw(f)).unwrap();
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|c| c.symbol())
+            .collect();
+        assert!(screen.contains("first line") && screen.contains("third"));
+    }
+}
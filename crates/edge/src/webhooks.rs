@@ -0,0 +1,384 @@
+// crates/edge/src/webhooks.rs
+
+// Content-change webhooks, for build systems and caches outside the site.
+// With `[[webhooks]]` entries in settings.toml, every change the indexer
+// makes after the startup scan is reported to each endpoint:
+//
+//   [[webhooks]]
+//   url = "https://ci.example.com/hooks/rebuild"
+//   secret_env = "WHISPERCMS_WEBHOOK_SECRET"
+//   headers = { "x-api-key" = "…" }
+//
+// Changes are collected for `BATCH_WINDOW` after the first one, so saving a
+// dozen files makes one notice rather than a dozen:
+//
+//   POST <url>
+//   content-type: application/json
+//   x-whispercms-event: content.changed
+//   x-whispercms-signature: sha256=<hex HMAC-SHA256 of the body>
+//
+//   { "id": "…", "event": "content.changed", "at": "<RFC 3339>",
+//     "created": [ids], "updated": [ids], "deleted": [ids],
+//     "reindexed": false }
+//
+// Ids are front-matter ids. A document created and deleted in one batch is
+// left out; `reindexed` says the index was rebuilt, after which every
+// document is reported as created again. The signature header is only sent
+// with `secret_env`. Each notice is queued in the outbox (db::outbox) once
+// per endpoint and sent with its backoff, so it survives restarts; one that
+// keeps failing ends up in the dead letters (`whispercms outbox`). A retried
+// notice keeps its `id`, which receivers can use to skip repeats.
+
+use crate::cli::OUTBOX_DIR;
+use crate::db::outbox::{spawn_dispatcher, Delivery, Outbox, Transport};
+use crate::proxy::EdgeError;
+use adapt::js::fetch::{FetchPolicy, FetchRequest, Fetcher};
+use adapt::js::HttpFetcher;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use domain::hex;
+use domain::setting::WebhookSettings;
+use hmac::{Hmac, Mac};
+use http::Uri;
+use serde_json::{json, Value as Json};
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// How long changes are collected before a notice goes out.
+pub const BATCH_WINDOW: Duration = Duration::from_secs(2);
+
+/// How often the dispatcher looks for due notices.
+pub const DISPATCH_EVERY: Duration = Duration::from_secs(10);
+
+/// The `event` of a content-change notice.
+pub const CONTENT_CHANGED: &str = "content.changed";
+
+pub const EVENT_HEADER: &str = "x-whispercms-event";
+pub const SIGNATURE_HEADER: &str = "x-whispercms-signature";
+
+/// How long one delivery may take.
+const TIMEOUT_MS: u64 = 10_000;
+
+/// One `[[webhooks]]` entry, its secret read.
+struct Hook {
+    url: String,
+    secret: Option<Vec<u8>>,
+    headers: BTreeMap<String, String>,
+}
+
+impl Hook {
+    /// The endpoint's settings, failing on a bad URL or an unset secret.
+    fn new(settings: &WebhookSettings) -> Result<Self, EdgeError> {
+        let url = &settings.url;
+        let valid = url.parse::<Uri>().ok().is_some_and(|u| {
+            matches!(u.scheme_str(), Some("http" | "https")) && u.host().is_some()
+        });
+        if !valid {
+            return Err(EdgeError::Config(format!(
+                "[[webhooks]] url {url:?} is not an http(s) URL"
+            )));
+        }
+        let secret = match &settings.secret_env {
+            Some(var) => Some(
+                std::env::var(var)
+                    .map_err(|_| {
+                        EdgeError::Config(format!("[[webhooks]] {url}: {var} is not set"))
+                    })?
+                    .into_bytes(),
+            ),
+            None => None,
+        };
+        Ok(Self {
+            url: url.clone(),
+            secret,
+            headers: settings.headers.clone(),
+        })
+    }
+
+    /// `body` as a delivery to this endpoint, signed if it has a secret.
+    fn delivery(&self, body: &Json) -> Delivery {
+        let mut headers = self.headers.clone();
+        headers.insert(EVENT_HEADER.to_owned(), CONTENT_CHANGED.to_owned());
+        if let Some(secret) = &self.secret {
+            headers.insert(
+                SIGNATURE_HEADER.to_owned(),
+                signature(secret, body.to_string().as_bytes()),
+            );
+        }
+        Delivery::Webhook {
+            url: self.url.clone(),
+            headers,
+            body: body.clone(),
+        }
+    }
+}
+
+/// One site's endpoints, and the changes not yet reported to them.
+pub struct Webhooks {
+    outbox: Outbox,
+    hooks: Vec<Hook>,
+    batch: Mutex<Batch>,
+}
+
+/// `sha256=` and the hex HMAC-SHA256 of `payload` under `secret`, as sent
+/// in `x-whispercms-signature`.
+pub fn signature(secret: &[u8], payload: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC takes any key length");
+    mac.update(payload);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+impl Webhooks {
+    /// The endpoints in `settings`, none without any, and start sending the
+    /// notices queued in the outbox under `root_dir`.
+    pub fn open(
+        root_dir: &Path,
+        settings: &[WebhookSettings],
+    ) -> Result<Option<Arc<Self>>, EdgeError> {
+        if settings.is_empty() {
+            return Ok(None);
+        }
+        let hooks = settings.iter().map(Hook::new).collect::<Result<_, _>>()?;
+        let outbox = Outbox::open(root_dir.join(OUTBOX_DIR))?;
+        info!("Reporting content changes to {} webhook(s)", settings.len());
+        spawn_dispatcher(
+            Arc::new(outbox.clone()),
+            Arc::new(WebhookTransport),
+            DISPATCH_EVERY,
+        );
+        Ok(Some(Arc::new(Self {
+            outbox,
+            hooks,
+            batch: Mutex::default(),
+        })))
+    }
+
+    /// The document `id` was indexed; `existed` if it was in the index
+    /// before.
+    pub fn changed(self: &Arc<Self>, id: &str, existed: bool) {
+        let change = if existed {
+            Change::Updated
+        } else {
+            Change::Created
+        };
+        self.record(|batch| batch.record(id, change));
+    }
+
+    /// The document `id` was dropped from the index.
+    pub fn removed(self: &Arc<Self>, id: &str) {
+        self.record(|batch| batch.record(id, Change::Deleted));
+    }
+
+    /// The index was cleared for a rebuild.
+    pub fn clear(self: &Arc<Self>) {
+        self.record(|batch| batch.reindexed = true);
+    }
+
+    fn record(self: &Arc<Self>, f: impl FnOnce(&mut Batch)) {
+        let started = {
+            let mut batch = self.batch.lock().unwrap_or_else(|e| e.into_inner());
+            let was_empty = batch.is_empty();
+            f(&mut batch);
+            was_empty && !batch.is_empty()
+        };
+        if !started {
+            return;
+        }
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                let hooks = self.clone();
+                runtime.spawn(async move {
+                    tokio::time::sleep(BATCH_WINDOW).await;
+                    hooks.flush();
+                });
+            }
+            Err(_) => self.flush(),
+        }
+    }
+
+    /// Queue a notice of the batch for every endpoint and start a new one.
+    fn flush(&self) {
+        let batch = std::mem::take(&mut *self.batch.lock().unwrap_or_else(|e| e.into_inner()));
+        if batch.is_empty() {
+            return;
+        }
+        let body = batch.payload(&Uuid::new_v4().simple().to_string(), Utc::now());
+        for hook in &self.hooks {
+            if let Err(e) = self.outbox.enqueue(hook.delivery(&body)) {
+                warn!("Webhook {}: queueing a notice failed: {}", hook.url, e);
+            }
+        }
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Batching
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Change {
+    Created,
+    Updated,
+    Deleted,
+}
+
+/// The changes since the last notice.
+#[derive(Debug, Default)]
+struct Batch {
+    changes: BTreeMap<String, Change>,
+    reindexed: bool,
+}
+
+impl Batch {
+    fn is_empty(&self) -> bool {
+        self.changes.is_empty() && !self.reindexed
+    }
+
+    /// Fold `change` to `id` into what the batch already says about it.
+    fn record(&mut self, id: &str, change: Change) {
+        use Change::*;
+        let merged = match (self.changes.get(id), change) {
+            (Some(Created), Updated) => Some(Created),
+            (Some(Created), Deleted) => None,
+            (Some(Deleted), Created | Updated) => Some(Updated),
+            (_, change) => Some(change),
+        };
+        match merged {
+            Some(change) => {
+                self.changes.insert(id.to_owned(), change);
+            }
+            None => {
+                self.changes.remove(id);
+            }
+        }
+    }
+
+    fn payload(&self, id: &str, at: DateTime<Utc>) -> Json {
+        let ids = |kind: Change| -> Vec<&str> {
+            self.changes
+                .iter()
+                .filter(|(_, change)| **change == kind)
+                .map(|(id, _)| id.as_str())
+                .collect()
+        };
+        json!({
+            "id": id,
+            "event": CONTENT_CHANGED,
+            "at": at.to_rfc3339(),
+            "created": ids(Change::Created),
+            "updated": ids(Change::Updated),
+            "deleted": ids(Change::Deleted),
+            "reindexed": self.reindexed,
+        })
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Delivery
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// POSTs the outbox's webhooks as JSON; anything but a 2xx is retried.
+struct WebhookTransport;
+
+#[async_trait]
+impl Transport for WebhookTransport {
+    async fn deliver(&self, delivery: &Delivery) -> Result<(), String> {
+        let Delivery::Webhook { url, headers, body } = delivery else {
+            return Err("not a webhook".into());
+        };
+        let host = url
+            .parse::<Uri>()
+            .ok()
+            .and_then(|u| u.host().map(str::to_owned))
+            .ok_or_else(|| format!("{url} has no host"))?;
+        let mut headers = headers.clone();
+        headers.insert("content-type".to_owned(), "application/json".to_owned());
+        let request = FetchRequest {
+            url: url.clone(),
+            method: "POST".to_owned(),
+            headers,
+            body: Some(body.to_string()),
+        };
+        let policy = FetchPolicy {
+            hosts: vec![host],
+            timeout_ms: TIMEOUT_MS,
+            max_bytes: 64 * 1024,
+        };
+        let response = tokio::task::spawn_blocking(move || HttpFetcher.fetch(&request, &policy))
+            .await
+            .map_err(|e| e.to_string())?
+            .map_err(|e| e.to_string())?;
+        if (200..300).contains(&response.status) {
+            Ok(())
+        } else {
+            Err(format!("{url} answered {}", response.status))
+        }
+    }
+
+    fn accepts(&self, delivery: &Delivery) -> bool {
+        matches!(delivery, Delivery::Webhook { .. })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn batches_fold_changes_into_one_signed_notice() {
+        let mut batch = Batch::default();
+        batch.record("/a.md", Change::Created);
+        batch.record("/a.md", Change::Updated);
+        batch.record("/b.md", Change::Updated);
+        batch.record("/c.md", Change::Created);
+        batch.record("/c.md", Change::Deleted);
+        batch.record("/d.md", Change::Deleted);
+        batch.record("/e.md", Change::Deleted);
+        batch.record("/e.md", Change::Created);
+        let at = DateTime::parse_from_rfc3339("2025-01-01T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let body = batch.payload("n1", at);
+        assert_eq!(body["created"], json!(["/a.md"]));
+        assert_eq!(body["updated"], json!(["/b.md", "/e.md"]));
+        assert_eq!(body["deleted"], json!(["/d.md"]));
+        assert_eq!(body["reindexed"], false);
+
+        let hook = Hook::new(&WebhookSettings {
+            url: "https://ci.example.com/hook".into(),
+            secret_env: None,
+            headers: BTreeMap::from([("x-api-key".into(), "k".into())]),
+        })
+        .unwrap();
+        let signed = Hook {
+            secret: Some(b"s3cret".to_vec()),
+            ..hook
+        };
+        let Delivery::Webhook {
+            url,
+            headers,
+            body: sent,
+        } = signed.delivery(&body)
+        else {
+            panic!("not a webhook");
+        };
+        assert_eq!(url, "https://ci.example.com/hook");
+        assert_eq!(headers["x-api-key"], "k");
+        assert_eq!(headers[EVENT_HEADER], CONTENT_CHANGED);
+        assert_eq!(
+            headers[SIGNATURE_HEADER],
+            signature(b"s3cret", sent.to_string().as_bytes())
+        );
+        assert!(Hook::new(&WebhookSettings {
+            url: "ftp://example.com/".into(),
+            secret_env: None,
+            headers: BTreeMap::new(),
+        })
+        .is_err());
+    }
+}
@@ -0,0 +1,301 @@
+// crates/serve/src/assets.rs
+
+// Fingerprinted theme assets.
+//
+// Every file under a theme's `assets/` directory is addressable two ways:
+//
+//   /assets/css/app.css              logical name; revalidated on every use
+//   /assets/css/app.3f2a9c1b7e.css   fingerprinted; cached for a year
+//
+// The fingerprint is a prefix of the file's SHA-256, so any edit produces a
+// new URL and far-future caching is safe. Templates never spell the hash:
+// `{{asset_url "css/app.css"}}` writes the current fingerprinted URL.
+
+use domain::hex;
+use handlebars::{Context, Handlebars, Helper, HelperDef, HelperResult, Output, RenderContext};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// `Cache-Control` for fingerprinted URLs.
+pub const IMMUTABLE_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+/// `Cache-Control` for logical names, whose content may change.
+pub const REVALIDATE_CACHE_CONTROL: &str = "public, no-cache";
+
+/// Hex digits of the content hash kept in file names.
+const FINGERPRINT_LEN: usize = 10;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssetEntry {
+    /// Path below the assets directory with `/` separators, e.g. `css/app.css`.
+    pub logical: String,
+    /// `logical` with the fingerprint before the extension.
+    pub hashed: String,
+    /// File on disk.
+    pub path: PathBuf,
+    /// Full content hash, usable as a strong ETag.
+    pub digest: String,
+}
+
+/// The fingerprints of one assets directory at the time it was read.
+#[derive(Debug, Clone, Default)]
+pub struct AssetManifest {
+    entries: Vec<AssetEntry>,
+    by_logical: HashMap<String, usize>,
+    by_hashed: HashMap<String, usize>,
+}
+
+/// How a requested asset path was matched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssetMatch<'a> {
+    Fingerprinted(&'a AssetEntry),
+    Logical(&'a AssetEntry),
+}
+
+impl AssetManifest {
+    /// Hash every file below `dir`; a missing directory has no assets.
+    pub fn build(dir: &Path) -> io::Result<Self> {
+        let mut manifest = Self::default();
+        let mut pending = vec![dir.to_path_buf()];
+
+        while let Some(current) = pending.pop() {
+            let entries = match fs::read_dir(&current) {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+            for entry in entries {
+                let path = entry?.path();
+                if path.is_dir() {
+                    pending.push(path);
+                    continue;
+                }
+                let Ok(rel) = path.strip_prefix(dir) else {
+                    continue;
+                };
+                let logical = rel
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+
+                let digest = hex::encode(Sha256::digest(fs::read(&path)?));
+                manifest.insert(AssetEntry {
+                    hashed: fingerprinted_name(&logical, &digest[..FINGERPRINT_LEN]),
+                    logical,
+                    path,
+                    digest,
+                });
+            }
+        }
+        Ok(manifest)
+    }
+
+    fn insert(&mut self, entry: AssetEntry) {
+        let i = self.entries.len();
+        self.by_logical.insert(entry.logical.clone(), i);
+        self.by_hashed.insert(entry.hashed.clone(), i);
+        self.entries.push(entry);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The asset a request path below the assets prefix refers to.
+    pub fn lookup(&self, requested: &str) -> Option<AssetMatch<'_>> {
+        let requested = requested.trim_start_matches('/');
+        if let Some(&i) = self.by_hashed.get(requested) {
+            return Some(AssetMatch::Fingerprinted(&self.entries[i]));
+        }
+        self.by_logical
+            .get(requested)
+            .map(|&i| AssetMatch::Logical(&self.entries[i]))
+    }
+
+    /// URL path for `logical` below `prefix`: fingerprinted when the asset
+    /// exists, else the logical name as is.
+    pub fn url_for(&self, prefix: &str, logical: &str) -> String {
+        let logical = logical.trim_start_matches('/');
+        let name = self
+            .by_logical
+            .get(logical)
+            .map_or(logical, |&i| self.entries[i].hashed.as_str());
+        format!("{}/{}", prefix.trim_end_matches('/'), name)
+    }
+}
+
+/// `css/app.css` → `css/app.<fingerprint>.css`.
+fn fingerprinted_name(logical: &str, fingerprint: &str) -> String {
+    let (dir, file) = match logical.rsplit_once('/') {
+        Some((dir, file)) => (Some(dir), file),
+        None => (None, logical),
+    };
+    let file = match file.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => format!("{stem}.{fingerprint}.{ext}"),
+        _ => format!("{file}.{fingerprint}"),
+    };
+    match dir {
+        Some(dir) => format!("{dir}/{file}"),
+        None => file,
+    }
+}
+
+/// `Content-Type` by file extension.
+pub fn content_type(path: &Path) -> &'static str {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    match ext.as_str() {
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" | "map" => "application/json",
+        "html" | "htm" => "text/html; charset=utf-8",
+        "txt" => "text/plain; charset=utf-8",
+        "xml" => "application/xml",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "wasm" => "application/wasm",
+        "pdf" => "application/pdf",
+        "mp3" => "audio/mpeg",
+        "m4a" => "audio/mp4",
+        "aac" => "audio/aac",
+        "ogg" | "oga" | "opus" => "audio/ogg",
+        "wav" => "audio/wav",
+        "flac" => "audio/flac",
+        "mp4" | "m4v" => "video/mp4",
+        "webm" => "video/webm",
+        "ogv" => "video/ogg",
+        "mov" => "video/quicktime",
+        _ => "application/octet-stream",
+    }
+}
+
+/// `{{asset_url "css/app.css"}}` for one theme's manifest and URL prefix.
+#[derive(Clone)]
+pub struct AssetUrl {
+    manifest: Arc<AssetManifest>,
+    prefix: String,
+}
+
+impl AssetUrl {
+    pub fn new(manifest: Arc<AssetManifest>, prefix: impl Into<String>) -> Self {
+        Self {
+            manifest,
+            prefix: prefix.into(),
+        }
+    }
+}
+
+impl HelperDef for AssetUrl {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        let logical = h
+            .param(0)
+            .and_then(|p| p.value().as_str())
+            .unwrap_or_default();
+        out.write(&self.manifest.url_for(&self.prefix, logical))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::HelperSet;
+
+    fn site() -> (tempfile::TempDir, AssetManifest) {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("css")).unwrap();
+        fs::write(dir.path().join("css/app.css"), "body { color: red }").unwrap();
+        fs::write(dir.path().join("LICENSE"), "MIT").unwrap();
+        let manifest = AssetManifest::build(dir.path()).unwrap();
+        (dir, manifest)
+    }
+
+    #[test]
+    fn assets_are_found_by_logical_and_fingerprinted_name() {
+        let (_dir, manifest) = site();
+        assert_eq!(manifest.len(), 2);
+
+        let url = manifest.url_for("/assets/", "css/app.css");
+        let hashed = url.strip_prefix("/assets/").unwrap();
+        assert!(hashed.starts_with("css/app.") && hashed.ends_with(".css"));
+        assert_eq!(hashed.len(), "css/app..css".len() + FINGERPRINT_LEN);
+
+        let Some(AssetMatch::Fingerprinted(entry)) = manifest.lookup(hashed) else {
+            panic!("fingerprinted name not found");
+        };
+        assert_eq!(entry.logical, "css/app.css");
+        assert!(matches!(
+            manifest.lookup("/css/app.css"),
+            Some(AssetMatch::Logical(_))
+        ));
+        assert!(manifest.lookup("css/app.0000000000.css").is_none());
+
+        assert!(manifest
+            .url_for("/docs/assets", "LICENSE")
+            .starts_with("/docs/assets/LICENSE."));
+        assert_eq!(
+            manifest.url_for("/assets", "missing.js"),
+            "/assets/missing.js"
+        );
+    }
+
+    #[test]
+    fn editing_an_asset_changes_its_url() {
+        let (dir, before) = site();
+        fs::write(dir.path().join("css/app.css"), "body { color: blue }").unwrap();
+        let after = AssetManifest::build(dir.path()).unwrap();
+
+        assert_ne!(
+            before.url_for("/assets", "css/app.css"),
+            after.url_for("/assets", "css/app.css")
+        );
+        assert_eq!(
+            before.url_for("/assets", "LICENSE"),
+            after.url_for("/assets", "LICENSE")
+        );
+    }
+
+    #[test]
+    fn templates_get_urls_from_the_asset_url_helper() {
+        let (_dir, manifest) = site();
+        let manifest = Arc::new(manifest);
+        let expected = manifest.url_for("/assets", "css/app.css");
+        let helpers = HelperSet::new().with_helper("asset_url", AssetUrl::new(manifest, "/assets"));
+
+        let mut hbs = Handlebars::new();
+        crate::render::helpers::register_theme(&mut hbs, Path::new("/nonexistent"), &helpers)
+            .unwrap();
+        hbs.register_template_string("t", r#"<link href="{{asset_url "css/app.css"}}">"#)
+            .unwrap();
+        let html = hbs.render("t", &()).unwrap();
+        assert_eq!(html, format!(r#"<link href="{expected}">"#));
+    }
+}
@@ -0,0 +1,328 @@
+// crates/serve/src/authors.rs
+
+// Author entities and `/author/{slug}` archive pages.
+//
+// Authors are declared once in a TOML data file (`authors.toml` by default):
+//
+//   [[author]]
+//   slug = "ada"
+//   name = "Ada Lovelace"
+//   bio = "Wrote the first program."
+//   avatar = "/img/ada.png"
+//
+//   [author.links]
+//   github = "https://github.com/ada"
+//
+// Front matter may name an author by slug or by display name. Before a
+// document is indexed, `link_front_matter` rewrites `author.author` and
+// `author.co_authors` to slugs, so archive pages and author feeds only have
+// to compare slugs. Names that match no author are kept as written.
+
+use crate::feeds::{collect_items, FeedItem, FeedScope};
+use crate::indexer::ContentManager;
+use crate::resolver::ResolverError;
+use domain::author::Author;
+use domain::setting::FeedSettings;
+use serde::Deserialize;
+use serde_json::{json, Value as Json};
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use thiserror::Error;
+
+/// Fallback author page: the author and their posts, as [`page_model`]
+/// lays them out.
+pub const DEFAULT_AUTHOR_TEMPLATE: &str = r#"<!doctype html>
+<html>
+<head><meta charset="utf-8"><title>{{author.name}}</title></head>
+<body>
+<header>
+  {{#if author.avatar}}<img src="{{author.avatar}}" alt="{{author.name}}" width="96" height="96">{{/if}}
+  <h1>{{author.name}}</h1>
+  {{#if author.bio}}<p>{{author.bio}}</p>{{/if}}
+  {{#if author.links}}<ul>{{#each author.links}}<li><a href="{{this}}" rel="me">{{@key}}</a></li>{{/each}}</ul>{{/if}}
+  <p><a href="{{feed}}">Feed</a></p>
+</header>
+<main>
+  <ul>
+  {{#each posts}}
+    <li><a href="{{path}}">{{title}}</a>{{#if date}} <time datetime="{{date}}">{{date}}</time>{{/if}}{{#if summary}}<p>{{summary}}</p>{{/if}}</li>
+  {{/each}}
+  </ul>
+</main>
+</body>
+</html>
+"#;
+
+#[derive(Debug, Error)]
+pub enum AuthorError {
+    #[error("reading authors file: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("parsing authors file: {0}")]
+    Parse(#[from] toml::de::Error),
+
+    #[error("author slug {0:?} must be non-empty and use only letters, digits, '-' or '_'")]
+    InvalidSlug(String),
+
+    #[error("author slug {0:?} is declared more than once")]
+    Duplicate(String),
+}
+
+#[derive(Deserialize)]
+struct AuthorsFile {
+    #[serde(default, rename = "author")]
+    authors: Vec<Author>,
+}
+
+/// Every declared author, looked up by slug or display name.
+#[derive(Debug, Clone, Default)]
+pub struct AuthorRegistry {
+    authors: Vec<Author>,
+    /// Lower-cased slugs and names → index into `authors`.
+    keys: HashMap<String, usize>,
+}
+
+impl AuthorRegistry {
+    /// Load `path`; a missing file is an empty registry.
+    pub fn load(path: &Path) -> Result<Self, AuthorError> {
+        match std::fs::read_to_string(path) {
+            Ok(src) => Self::from_toml_str(&src),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn from_toml_str(src: &str) -> Result<Self, AuthorError> {
+        let file: AuthorsFile = toml::from_str(src)?;
+        Self::from_authors(file.authors)
+    }
+
+    pub fn from_authors(authors: Vec<Author>) -> Result<Self, AuthorError> {
+        let mut keys = HashMap::new();
+        for (i, author) in authors.iter().enumerate() {
+            let valid = !author.slug.is_empty()
+                && author
+                    .slug
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            if !valid {
+                return Err(AuthorError::InvalidSlug(author.slug.clone()));
+            }
+            if keys.insert(author.slug.to_lowercase(), i).is_some() {
+                return Err(AuthorError::Duplicate(author.slug.clone()));
+            }
+        }
+        // Names only fill gaps, so a slug always means its own author.
+        for (i, author) in authors.iter().enumerate() {
+            keys.entry(author.name.trim().to_lowercase()).or_insert(i);
+        }
+
+        Ok(Self { authors, keys })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.authors.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Author> {
+        self.authors.iter()
+    }
+
+    /// The author with this slug.
+    pub fn get(&self, slug: &str) -> Option<&Author> {
+        self.resolve(slug).filter(|a| a.slug == slug)
+    }
+
+    /// The author a front-matter value refers to, by slug or display name,
+    /// ignoring case.
+    pub fn resolve(&self, name_or_slug: &str) -> Option<&Author> {
+        self.keys
+            .get(&name_or_slug.trim().to_lowercase())
+            .map(|&i| &self.authors[i])
+    }
+
+    /// `fm` with `author.author` and `author.co_authors` rewritten to slugs
+    /// where they name a known author.
+    pub fn link_front_matter(&self, fm: &Json) -> Json {
+        let mut fm = fm.clone();
+        if self.is_empty() {
+            return fm;
+        }
+
+        let Some(section) = fm.get_mut("author").and_then(Json::as_object_mut) else {
+            return fm;
+        };
+        for field in ["author", "co_authors"] {
+            match section.get_mut(field) {
+                Some(Json::String(name)) => self.link(name),
+                Some(Json::Array(names)) => names.iter_mut().for_each(|v| {
+                    if let Json::String(name) = v {
+                        self.link(name);
+                    }
+                }),
+                _ => {}
+            }
+        }
+        fm
+    }
+
+    /// Replace feed items' author slugs with display names.
+    pub fn name_items(&self, items: &mut [FeedItem]) {
+        for item in items {
+            if let Some(author) = item.author.as_deref().and_then(|a| self.resolve(a)) {
+                item.author = Some(author.name.clone());
+            }
+        }
+    }
+
+    fn link(&self, name: &mut String) {
+        if let Some(author) = self.resolve(name) {
+            name.clone_from(&author.slug);
+        }
+    }
+}
+
+/// Every published document by `author` (as author or co-author), newest
+/// first, with text summaries.
+pub async fn collect_posts(
+    mgr: &impl ContentManager,
+    author: &Author,
+    summary_chars: usize,
+) -> Result<Vec<FeedItem>, ResolverError> {
+    let settings = FeedSettings {
+        items: usize::MAX,
+        full_content: false,
+        summary_chars,
+        ..FeedSettings::default()
+    };
+    collect_items(
+        mgr,
+        &FeedScope::Author(author.slug.clone()),
+        None,
+        &settings,
+    )
+    .await
+}
+
+/// Template model for an author page:
+/// `{ author, feed, posts: [{ path, title, date, summary, tags }] }`.
+pub fn page_model(author: &Author, posts: &[FeedItem]) -> Json {
+    let posts: Vec<Json> = posts
+        .iter()
+        .map(|p| {
+            json!({
+                "path": p.path,
+                "title": p.title,
+                "date": p.published.map(|d| d.format("%Y-%m-%d").to_string()),
+                "summary": p.summary,
+                "tags": p.tags,
+            })
+        })
+        .collect();
+
+    json!({
+        "author": author,
+        "feed": format!("/author/{}/feed.xml", author.slug),
+        "posts": posts,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::render_default;
+    use chrono::{TimeZone, Utc};
+
+    const AUTHORS: &str = r#"
+        [[author]]
+        slug = "ada"
+        name = "Ada Lovelace"
+        bio = "Wrote the first program."
+
+        [author.links]
+        github = "https://github.com/ada"
+
+        [[author]]
+        slug = "grace"
+        name = "Grace Hopper"
+    "#;
+
+    #[test]
+    fn front_matter_names_are_linked_to_slugs() {
+        let reg = AuthorRegistry::from_toml_str(AUTHORS).unwrap();
+        assert_eq!(reg.get("ada").unwrap().name, "Ada Lovelace");
+        assert!(reg.get("Ada Lovelace").is_none());
+        assert_eq!(reg.resolve("ada lovelace").unwrap().slug, "ada");
+
+        let fm = json!({
+            "author": { "author": "Ada Lovelace", "co_authors": ["grace", "Alan Turing"] },
+            "content": { "title": "Notes" },
+        });
+        let linked = reg.link_front_matter(&fm);
+        assert_eq!(linked["author"]["author"], "ada");
+        assert_eq!(
+            linked["author"]["co_authors"],
+            json!(["grace", "Alan Turing"])
+        );
+        assert_eq!(linked["content"], fm["content"]);
+
+        assert_eq!(
+            AuthorRegistry::default().link_front_matter(&fm),
+            fm,
+            "without authors front matter is untouched"
+        );
+    }
+
+    #[test]
+    fn bad_author_files_are_rejected() {
+        let dup =
+            "[[author]]\nslug = \"a\"\nname = \"A\"\n[[author]]\nslug = \"a\"\nname = \"B\"\n";
+        assert!(matches!(
+            AuthorRegistry::from_toml_str(dup),
+            Err(AuthorError::Duplicate(s)) if s == "a"
+        ));
+
+        let bad = "[[author]]\nslug = \"a b\"\nname = \"A\"\n";
+        assert!(matches!(
+            AuthorRegistry::from_toml_str(bad),
+            Err(AuthorError::InvalidSlug(_))
+        ));
+
+        let missing = AuthorRegistry::load(Path::new("/nonexistent/authors.toml")).unwrap();
+        assert!(missing.is_empty());
+    }
+
+    #[test]
+    fn default_page_lists_posts() {
+        let reg = AuthorRegistry::from_toml_str(AUTHORS).unwrap();
+        let ada = reg.get("ada").unwrap();
+        let mut posts = vec![FeedItem {
+            path: "/blog/engine.html".into(),
+            title: "The Engine".into(),
+            published: Utc.with_ymd_and_hms(1843, 9, 1, 0, 0, 0).single(),
+            updated: None,
+            author: Some("ada".into()),
+            tags: Vec::new(),
+            content_html: None,
+            summary: Some("Notes on the engine".into()),
+        }];
+        reg.name_items(&mut posts);
+        assert_eq!(posts[0].author.as_deref(), Some("Ada Lovelace"));
+
+        let mut out = Vec::new();
+        render_default(
+            "author",
+            DEFAULT_AUTHOR_TEMPLATE,
+            &page_model(ada, &posts),
+            &mut out,
+        )
+        .unwrap();
+        let html = String::from_utf8(out).unwrap();
+        assert!(html.contains("<h1>Ada Lovelace</h1>"));
+        assert!(html.contains(r#"<a href="https://github.com/ada" rel="me">github</a>"#));
+        assert!(html.contains(r#"<a href="/blog/engine.html">The Engine</a>"#));
+        assert!(html.contains("1843-09-01"));
+        assert!(html.contains(r#"href="/author/ada/feed.xml""#));
+    }
+}
@@ -0,0 +1,558 @@
+// crates/serve/src/blocks.rs
+
+// Block documents: a body made of typed blocks instead of Markdown. A
+// `.blocks` file has the usual front matter followed by a JSON array:
+//
+//   +++
+//   title = "Launch notes"
+//   +++
+//   [
+//     { "type": "paragraph", "text": "We shipped **blocks**." },
+//     { "type": "image", "src": "/media/launch.png", "alt": "The team", "caption": "Day one" },
+//     { "type": "embed", "url": "https://www.youtube.com/watch?v=…", "title": "Demo" },
+//     { "type": "code", "language": "toml", "code": "[blocks]\n" }
+//   ]
+//
+// Every block is checked against the schema of its type before the document
+// is indexed or saved: required fields present, every field of its declared
+// type, no fields the type does not know. `paragraph.text` is Markdown, so
+// block and Markdown writing mix.
+//
+// Sites add types and restyle built-in ones from settings.toml:
+//
+//   [blocks]
+//   templates = "./blocks"          # <type>.hbs, model = the block's fields
+//   [blocks.types.callout]
+//   required = { text = "string" }
+//   optional = { tone = "string" }
+//
+// A template replaces the built-in HTML of its type; a site type must have
+// one. Built-in output marks each block with `data-block="<type>"`, so
+// plugins can rewrite them (e.g. turn embed links into players) with body
+// patches. The rendered HTML is what gets indexed, like any other body.
+
+use crate::indexer::default_markdown_options;
+use domain::setting::{BlockSettings, BlockTypeSettings};
+use handlebars::Handlebars;
+use html_escape::{encode_double_quoted_attribute as attr, encode_text as text};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value as Json};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Types every site has.
+pub const BUILTIN_TYPES: [&str; 4] = ["paragraph", "image", "embed", "code"];
+
+#[derive(Debug, Error)]
+pub enum BlockError {
+    #[error("blocks must be a JSON array of objects with a \"type\": {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("block {index} ({kind}): {reason}")]
+    Invalid {
+        index: usize,
+        kind: String,
+        reason: String,
+    },
+
+    #[error("block type {kind}: {reason}")]
+    Type { kind: String, reason: String },
+
+    #[error("I/O: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// What a block field may hold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldType {
+    String,
+    Number,
+    Boolean,
+    Array,
+    Object,
+    Any,
+}
+
+impl FieldType {
+    fn accepts(self, value: &Json) -> bool {
+        match self {
+            FieldType::String => value.is_string(),
+            FieldType::Number => value.is_number(),
+            FieldType::Boolean => value.is_boolean(),
+            FieldType::Array => value.is_array(),
+            FieldType::Object => value.is_object(),
+            FieldType::Any => true,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            FieldType::String => "string",
+            FieldType::Number => "number",
+            FieldType::Boolean => "boolean",
+            FieldType::Array => "array",
+            FieldType::Object => "object",
+            FieldType::Any => "any",
+        }
+    }
+}
+
+impl FromStr for FieldType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "string" => Ok(FieldType::String),
+            "number" => Ok(FieldType::Number),
+            "boolean" => Ok(FieldType::Boolean),
+            "array" => Ok(FieldType::Array),
+            "object" => Ok(FieldType::Object),
+            "any" => Ok(FieldType::Any),
+            other => Err(format!("unknown field type {other:?}")),
+        }
+    }
+}
+
+/// The fields a block type has.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BlockSchema {
+    required: BTreeMap<String, FieldType>,
+    optional: BTreeMap<String, FieldType>,
+}
+
+impl BlockSchema {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn required(mut self, name: impl Into<String>, ty: FieldType) -> Self {
+        self.required.insert(name.into(), ty);
+        self
+    }
+
+    pub fn optional(mut self, name: impl Into<String>, ty: FieldType) -> Self {
+        self.optional.insert(name.into(), ty);
+        self
+    }
+
+    /// A schema from `[blocks.types.<kind>]`.
+    pub fn from_settings(kind: &str, settings: &BlockTypeSettings) -> Result<Self, BlockError> {
+        let parse = |fields: &BTreeMap<String, String>| {
+            fields
+                .iter()
+                .map(|(name, ty)| Ok((name.clone(), ty.parse::<FieldType>()?)))
+                .collect::<Result<BTreeMap<_, _>, String>>()
+                .map_err(|reason| BlockError::Type {
+                    kind: kind.to_owned(),
+                    reason,
+                })
+        };
+        Ok(Self {
+            required: parse(&settings.required)?,
+            optional: parse(&settings.optional)?,
+        })
+    }
+
+    fn check(&self, block: &Block) -> Result<(), String> {
+        for (name, ty) in &self.required {
+            match block.fields.get(name) {
+                None | Some(Json::Null) => return Err(format!("missing field `{name}`")),
+                Some(value) if !ty.accepts(value) => {
+                    return Err(format!("`{name}` must be a {}", ty.as_str()))
+                }
+                Some(_) => {}
+            }
+        }
+        for (name, value) in &block.fields {
+            if self.required.contains_key(name) || value.is_null() {
+                continue;
+            }
+            match self.optional.get(name) {
+                None => return Err(format!("unknown field `{name}`")),
+                Some(ty) if !ty.accepts(value) => {
+                    return Err(format!("`{name}` must be a {}", ty.as_str()))
+                }
+                Some(_) => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// One block of a block document.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Block {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(flatten)]
+    pub fields: Map<String, Json>,
+}
+
+impl Block {
+    fn str(&self, name: &str) -> Option<&str> {
+        self.fields.get(name).and_then(Json::as_str)
+    }
+}
+
+/// Known block types, their schemas and templates.
+#[derive(Debug)]
+pub struct BlockRegistry {
+    schemas: BTreeMap<String, BlockSchema>,
+    templates: Handlebars<'static>,
+}
+
+impl Default for BlockRegistry {
+    fn default() -> Self {
+        use FieldType::*;
+
+        let schemas = BTreeMap::from([
+            (
+                "paragraph".to_owned(),
+                BlockSchema::new().required("text", String),
+            ),
+            (
+                "image".to_owned(),
+                BlockSchema::new()
+                    .required("src", String)
+                    .optional("alt", String)
+                    .optional("caption", String)
+                    .optional("width", Number)
+                    .optional("height", Number),
+            ),
+            (
+                "embed".to_owned(),
+                BlockSchema::new()
+                    .required("url", String)
+                    .optional("title", String),
+            ),
+            (
+                "code".to_owned(),
+                BlockSchema::new()
+                    .required("code", String)
+                    .optional("language", String),
+            ),
+        ]);
+        Self {
+            schemas,
+            templates: Handlebars::new(),
+        }
+    }
+}
+
+impl BlockRegistry {
+    /// The built-in types only.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The built-in types plus the site's, with templates from
+    /// `<site_dir>/<settings.templates>`.
+    pub fn from_settings(site_dir: &Path, settings: &BlockSettings) -> Result<Self, BlockError> {
+        let mut registry = Self::new();
+        for (kind, ty) in &settings.types {
+            registry.register(kind, BlockSchema::from_settings(kind, ty)?)?;
+        }
+        registry.load_templates(&site_dir.join(&settings.templates))?;
+
+        for kind in settings.types.keys() {
+            if !registry.templates.has_template(kind) {
+                return Err(BlockError::Type {
+                    kind: kind.clone(),
+                    reason: format!("no {kind}.hbs in {:?}", settings.templates),
+                });
+            }
+        }
+        Ok(registry)
+    }
+
+    /// Add a type. Built-in types cannot be redefined, only restyled.
+    pub fn register(&mut self, kind: &str, schema: BlockSchema) -> Result<(), BlockError> {
+        let valid = !kind.is_empty()
+            && kind
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+        if !valid || BUILTIN_TYPES.contains(&kind) {
+            return Err(BlockError::Type {
+                kind: kind.to_owned(),
+                reason: "not a free block type name".into(),
+            });
+        }
+        self.schemas.insert(kind.to_owned(), schema);
+        Ok(())
+    }
+
+    /// Render blocks of `kind` with the Handlebars `source`.
+    pub fn register_template(&mut self, kind: &str, source: &str) -> Result<(), BlockError> {
+        let fail = |reason: String| BlockError::Type {
+            kind: kind.to_owned(),
+            reason,
+        };
+        if !self.schemas.contains_key(kind) {
+            return Err(fail("template for an unknown block type".into()));
+        }
+        self.templates
+            .register_template_string(kind, source)
+            .map_err(|e| fail(e.to_string()))
+    }
+
+    /// Register every `<type>.hbs` in `dir`; a missing folder has none.
+    pub fn load_templates(&mut self, dir: &Path) -> Result<(), BlockError> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("hbs") {
+                continue;
+            }
+            let Some(kind) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            self.register_template(kind, &fs::read_to_string(&path)?)?;
+        }
+        Ok(())
+    }
+
+    /// Every type name, built-in ones included.
+    pub fn kinds(&self) -> impl Iterator<Item = &str> {
+        self.schemas.keys().map(String::as_str)
+    }
+
+    /// Parse and validate the body of a block document.
+    pub fn parse(&self, src: &str) -> Result<Vec<Block>, BlockError> {
+        let blocks: Vec<Block> = serde_json::from_str(src)?;
+        self.validate(&blocks)?;
+        Ok(blocks)
+    }
+
+    /// Check every block against the schema of its type.
+    pub fn validate(&self, blocks: &[Block]) -> Result<(), BlockError> {
+        for (index, block) in blocks.iter().enumerate() {
+            let invalid = |reason: String| BlockError::Invalid {
+                index,
+                kind: block.kind.clone(),
+                reason,
+            };
+            let schema = self
+                .schemas
+                .get(&block.kind)
+                .ok_or_else(|| invalid("unknown block type".into()))?;
+            schema.check(block).map_err(invalid)?;
+            check_urls(block).map_err(invalid)?;
+        }
+        Ok(())
+    }
+
+    /// HTML for validated `blocks`, one after the other.
+    pub fn render(&self, blocks: &[Block]) -> Result<String, BlockError> {
+        let mut html = String::new();
+        for (index, block) in blocks.iter().enumerate() {
+            if self.templates.has_template(&block.kind) {
+                let rendered = self
+                    .templates
+                    .render(&block.kind, &block.fields)
+                    .map_err(|e| BlockError::Invalid {
+                        index,
+                        kind: block.kind.clone(),
+                        reason: e.to_string(),
+                    })?;
+                html.push_str(&rendered);
+            } else {
+                render_builtin(block, &mut html);
+            }
+            html.push('\n');
+        }
+        Ok(html)
+    }
+}
+
+/// `blocks` as saved in a `.blocks` file.
+pub fn to_source(blocks: &[Block]) -> String {
+    let mut src = serde_json::to_string_pretty(blocks).unwrap_or_else(|_| "[]".into());
+    src.push('\n');
+    src
+}
+
+/// Image sources may be site paths or http(s) URLs; embeds must be http(s).
+fn check_urls(block: &Block) -> Result<(), String> {
+    let (field, relative_ok) = match block.kind.as_str() {
+        "image" => ("src", true),
+        "embed" => ("url", false),
+        _ => return Ok(()),
+    };
+    let url = block.str(field).unwrap_or_default();
+    let lower = url.to_ascii_lowercase();
+    let absolute = lower.starts_with("https://") || lower.starts_with("http://");
+    let relative = relative_ok && !lower.contains(':') && !lower.starts_with("//");
+    match absolute || relative {
+        true => Ok(()),
+        false => Err(format!("`{field}` must be an http(s) URL")),
+    }
+}
+
+fn render_builtin(block: &Block, out: &mut String) {
+    let get = |name: &str| block.str(name).unwrap_or_default();
+    match block.kind.as_str() {
+        "paragraph" => {
+            let md = comrak::markdown_to_html(get("text"), &default_markdown_options());
+            out.push_str("<div data-block=\"paragraph\">");
+            out.push_str(md.trim_end());
+            out.push_str("</div>");
+        }
+        "image" => {
+            out.push_str("<figure data-block=\"image\"><img src=\"");
+            out.push_str(&attr(get("src")));
+            out.push_str("\" alt=\"");
+            out.push_str(&attr(get("alt")));
+            out.push('"');
+            for dim in ["width", "height"] {
+                if let Some(n) = block.fields.get(dim).and_then(Json::as_u64) {
+                    out.push_str(&format!(" {dim}=\"{n}\""));
+                }
+            }
+            out.push('>');
+            if let Some(caption) = block.str("caption") {
+                out.push_str("<figcaption>");
+                out.push_str(&text(caption));
+                out.push_str("</figcaption>");
+            }
+            out.push_str("</figure>");
+        }
+        "embed" => {
+            let url = get("url");
+            out.push_str("<figure data-block=\"embed\"><a href=\"");
+            out.push_str(&attr(url));
+            out.push_str("\" rel=\"noopener\">");
+            out.push_str(&text(block.str("title").unwrap_or(url)));
+            out.push_str("</a></figure>");
+        }
+        "code" => {
+            out.push_str("<pre data-block=\"code\"><code");
+            if let Some(lang) = block.str("language") {
+                out.push_str(" class=\"language-");
+                out.push_str(&attr(lang));
+                out.push('"');
+            }
+            out.push('>');
+            out.push_str(&text(get("code")));
+            out.push_str("</code></pre>");
+        }
+        // Site types always have a template.
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn blocks(value: Json) -> String {
+        value.to_string()
+    }
+
+    #[test]
+    fn built_in_blocks_validate_and_render() {
+        let registry = BlockRegistry::new();
+        let parsed = registry
+            .parse(&blocks(json!([
+                { "type": "paragraph", "text": "Hello *there* <script>" },
+                { "type": "image", "src": "/media/a.png", "alt": "A \"b\"", "width": 640, "caption": "x < y" },
+                { "type": "embed", "url": "https://example.com/v/1" },
+                { "type": "code", "language": "rust", "code": "if a < b {}" },
+            ])))
+            .unwrap();
+
+        let html = registry.render(&parsed).unwrap();
+        assert!(html.contains("<div data-block=\"paragraph\"><p>Hello <em>there</em>"));
+        assert!(!html.contains("<script>"));
+        assert!(html.contains(
+            "<img src=\"/media/a.png\" alt=\"A &quot;b&quot;\" width=\"640\"><figcaption>x &lt; y</figcaption>"
+        ));
+        assert!(html.contains(
+            "<a href=\"https://example.com/v/1\" rel=\"noopener\">https://example.com/v/1</a>"
+        ));
+        assert!(html.contains("<code class=\"language-rust\">if a &lt; b {}</code>"));
+
+        assert_eq!(registry.parse(&to_source(&parsed)).unwrap(), parsed);
+    }
+
+    #[test]
+    fn blocks_that_break_their_schema_are_rejected() {
+        let registry = BlockRegistry::new();
+        let cases = [
+            (json!([{ "type": "image" }]), "missing field `src`"),
+            (
+                json!([{ "type": "code", "code": 5 }]),
+                "`code` must be a string",
+            ),
+            (
+                json!([{ "type": "paragraph", "text": "", "color": "red" }]),
+                "unknown field `color`",
+            ),
+            (
+                json!([{ "type": "embed", "url": "javascript:alert(1)" }]),
+                "http(s) URL",
+            ),
+            (
+                json!([{ "type": "image", "src": "data:image/png;base64,AA" }]),
+                "http(s) URL",
+            ),
+            (
+                json!([{ "type": "paragraph", "text": "" }, { "type": "quote" }]),
+                "block 1 (quote): unknown block type",
+            ),
+        ];
+        for (doc, expected) in cases {
+            let err = registry.parse(&blocks(doc)).unwrap_err().to_string();
+            assert!(err.contains(expected), "{err}");
+        }
+        assert!(matches!(registry.parse("{}"), Err(BlockError::Json(_))));
+    }
+
+    #[test]
+    fn site_types_and_templates_come_from_settings() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("blocks");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("callout.hbs"),
+            "<aside class=\"{{tone}}\">{{text}}</aside>",
+        )
+        .unwrap();
+        fs::write(dir.join("code.hbs"), "<pre class=\"hl\">{{code}}</pre>").unwrap();
+
+        let settings: BlockSettings = toml::from_str(
+            "[types.callout]\nrequired = { text = \"string\" }\noptional = { tone = \"string\" }\n",
+        )
+        .unwrap();
+        let registry = BlockRegistry::from_settings(tmp.path(), &settings).unwrap();
+        assert!(registry.kinds().any(|k| k == "callout"));
+
+        let parsed = registry
+            .parse(&blocks(json!([
+                { "type": "callout", "text": "<b>Note</b>", "tone": "warn" },
+                { "type": "code", "code": "a < b" },
+            ])))
+            .unwrap();
+        assert_eq!(
+            registry.render(&parsed).unwrap(),
+            "<aside class=\"warn\">&lt;b&gt;Note&lt;/b&gt;</aside>\n<pre class=\"hl\">a &lt; b</pre>\n"
+        );
+
+        fs::remove_file(dir.join("callout.hbs")).unwrap();
+        assert!(BlockRegistry::from_settings(tmp.path(), &settings).is_err());
+
+        let bad: BlockSettings = toml::from_str("[types.image]\n").unwrap();
+        assert!(BlockRegistry::from_settings(tmp.path(), &bad).is_err());
+        let bad: BlockSettings =
+            toml::from_str("[types.x]\nrequired = { a = \"text\" }\n").unwrap();
+        assert!(BlockRegistry::from_settings(tmp.path(), &bad).is_err());
+    }
+}
@@ -0,0 +1,1208 @@
+// crates/serve/src/cache.rs
+
+// Rendered-response cache.
+//
+// Finished responses (status, content type, validators, body) are keyed on
+// the normalized path and query, the request headers named in `vary`, and a
+// hash of the active theme/plugin configuration. The memory tier is an LRU
+// bounded by entry count and body bytes. The optional disk tier is written
+// through, so an entry evicted from memory can be reloaded without
+// re-rendering; it is an LRU too, bounded by file bytes, and is emptied on
+// startup since content may have changed while the server was down. Only the
+// query parameters in `query_params` may select an entry; the host renders
+// requests carrying others without storing them.
+//
+// Each entry carries tags naming the content its render read: `doc:<id>` and
+// `slug:<slug>` for lookups (including ones that found nothing), and
+// `term:<taxonomy>:<term>` for queries narrowed to taxonomy terms. When a
+// document changes, `invalidate_tags` drops the entries tagged with its old
+// or new id, slug and terms. Entries whose render read something broader (a
+// listing of everything, a query on other fields) or recorded nothing carry
+// `ANY_CONTENT` and go on every change. The host also tags each entry with
+// its `path:<path>`, so `purge` can drop entries by path as well as by tag
+// without touching the `ANY_CONTENT` ones. A generation counter keeps a render
+// that started before an invalidation from storing its result after it. An
+// entry's tags are dropped from the index once neither tier holds it.
+//
+// Hosts serving several tenants can store an entry under a `CacheShare`:
+// the tenant's entries then hold at most its `max_bytes` of body between
+// them, and past that its oldest go first, so one tenant filling the cache
+// evicts its own pages rather than everyone else's.
+//
+// Concurrent misses for one key are coalesced: the first becomes the leader
+// and renders, later ones wait up to `coalesce_wait_ms` for the response it
+// shares through `RenderFlight::finish`. A leader that fails or renders
+// something uncacheable shares nothing, and its followers render for
+// themselves, as do followers that time out. `coalesce_stats` counts each
+// outcome.
+
+use bytes::Bytes;
+use domain::hex;
+use domain::setting::CacheSettings;
+use http::{HeaderMap, HeaderName};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio::sync::watch;
+use tracing::{debug, warn};
+
+/// A response as it is stored and replayed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CachedResponse {
+    pub status: u16,
+    pub content_type: String,
+    pub etag: String,
+    pub last_modified: Option<SystemTime>,
+    /// Content the render read (see [`doc_tag`], [`term_tag`]); empty means
+    /// unknown, which is treated as [`ANY_CONTENT`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Headers plugins patched in, replayed with every hit.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub headers: Vec<(String, String)>,
+    /// Stored after the JSON header in the disk tier, not inside it.
+    #[serde(skip)]
+    pub body: Bytes,
+}
+
+/// Where a response is stored, plus the cache generation it was computed in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheKey {
+    digest: String,
+    generation: u64,
+}
+
+impl CacheKey {
+    pub fn as_str(&self) -> &str {
+        &self.digest
+    }
+}
+
+/// Hash the active theme/plugin configuration (ids, sources, settings), so
+/// changing any of it yields new keys.
+pub fn config_hash<I, P>(parts: I) -> String
+where
+    I: IntoIterator<Item = P>,
+    P: AsRef<[u8]>,
+{
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part.as_ref());
+        hasher.update([0]);
+    }
+    hex::encode(&hasher.finalize()[..16])
+}
+
+/// Tag for entries that may depend on any document; every invalidation
+/// drops them.
+pub const ANY_CONTENT: &str = "content:*";
+
+/// Tag for a document by served id (e.g. `/blog/a.html`).
+pub fn doc_tag(id: &str) -> String {
+    format!("doc:{id}")
+}
+
+/// Tag for a document by slug.
+pub fn slug_tag(slug: &str) -> String {
+    format!("slug:{slug}")
+}
+
+/// Tag for a taxonomy term, e.g. `term_tag("tags", "rust")`.
+pub fn term_tag(taxonomy: &str, term: &str) -> String {
+    format!("term:{taxonomy}:{term}")
+}
+
+/// Tag for the request path an entry was rendered for, e.g. `/blog/a/`.
+pub fn path_tag(path: &str) -> String {
+    format!("{PATH_TAG_PREFIX}{path}")
+}
+
+pub const PATH_TAG_PREFIX: &str = "path:";
+
+/// The tags a change to this front-matter record affects: its id, its slug,
+/// and every term under `tax`.
+pub fn tags_for_doc(fm: &Json) -> Vec<String> {
+    let mut tags = Vec::new();
+    if let Some(id) = fm.get("id").and_then(Json::as_str) {
+        tags.push(doc_tag(id));
+    }
+    if let Some(slug) = fm.get("slug").and_then(Json::as_str) {
+        tags.push(slug_tag(slug));
+    }
+    if let Some(tax) = fm.get("tax").and_then(Json::as_object) {
+        for (taxonomy, terms) in tax {
+            let terms = terms.as_array().into_iter().flatten();
+            tags.extend(
+                terms
+                    .filter_map(Json::as_str)
+                    .map(|t| term_tag(taxonomy, t)),
+            );
+        }
+    }
+    tags
+}
+
+/// How concurrent misses have been handled since startup.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct CoalesceStats {
+    /// Misses that rendered while others could wait on them
+    pub leaders: u64,
+    /// Misses that waited on another request's render
+    pub followers: u64,
+    /// Followers answered with the leader's response
+    pub shared: u64,
+    /// Followers that stopped waiting and rendered themselves
+    pub timeouts: u64,
+}
+
+#[derive(Default)]
+struct CoalesceCounters {
+    leaders: AtomicU64,
+    followers: AtomicU64,
+    shared: AtomicU64,
+    timeouts: AtomicU64,
+}
+
+/// One tenant's part of the cache; see [`ResponseCache::put_shared`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheShare {
+    pub tenant: String,
+    /// Body bytes the tenant's entries may hold between them.
+    pub max_bytes: usize,
+}
+
+/// A leader's channel: `None` until it finishes.
+type FlightRx = watch::Receiver<Option<CachedResponse>>;
+
+/// Lock `mutex`, carrying on past a panic in another holder: every update
+/// below leaves the maps consistent between statements.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+pub struct ResponseCache {
+    mem: Mutex<Lru>,
+    /// The entries written to `disk`. Locked after `mem`.
+    disk_index: Mutex<DiskIndex>,
+    /// Which entries carry which tags. Locked after `disk_index`.
+    tags: Mutex<TagIndex>,
+    /// The entries stored under a `CacheShare`. Locked after `tags`.
+    shares: Mutex<Shares>,
+    disk: Option<PathBuf>,
+    vary: Vec<HeaderName>,
+    /// Query parameter names that may be part of a key.
+    query_params: HashSet<String>,
+    config_hash: RwLock<String>,
+    generation: AtomicU64,
+    /// Digest → generation and channel of the render in progress.
+    flights: Mutex<HashMap<String, (u64, FlightRx)>>,
+    coalesce_wait: Duration,
+    coalesce: CoalesceCounters,
+}
+
+/// What a miss should do; see [`ResponseCache::join_render`].
+pub enum Flight<'a> {
+    /// Render, then [`finish`](RenderFlight::finish) to share the response.
+    Leader(RenderFlight<'a>),
+    /// Wait for the leader's response.
+    Follower(FlightWait<'a>),
+}
+
+/// Held by the request rendering a key; dropping it without finishing lets
+/// the followers go and render for themselves.
+pub struct RenderFlight<'a> {
+    cache: &'a ResponseCache,
+    key: CacheKey,
+    /// `None` when coalescing is off.
+    tx: Option<watch::Sender<Option<CachedResponse>>>,
+}
+
+impl RenderFlight<'_> {
+    /// Hand `resp` to every request waiting on this render.
+    pub fn finish(self, resp: &CachedResponse) {
+        if let Some(tx) = &self.tx {
+            tx.send_replace(Some(resp.clone()));
+        }
+    }
+}
+
+impl Drop for RenderFlight<'_> {
+    fn drop(&mut self) {
+        if self.tx.is_none() {
+            return;
+        }
+        let mut flights = lock(&self.cache.flights);
+        // A miss in a later generation may have replaced this flight.
+        if flights
+            .get(&self.key.digest)
+            .is_some_and(|(generation, _)| *generation == self.key.generation)
+        {
+            flights.remove(&self.key.digest);
+        }
+    }
+}
+
+pub struct FlightWait<'a> {
+    cache: &'a ResponseCache,
+    rx: FlightRx,
+}
+
+impl FlightWait<'_> {
+    /// The leader's response, or `None` when it shared none or did not
+    /// finish within `coalesce_wait_ms`; the caller then renders itself.
+    pub async fn wait(mut self) -> Option<CachedResponse> {
+        let counters = &self.cache.coalesce;
+        match tokio::time::timeout(self.cache.coalesce_wait, self.rx.wait_for(Option::is_some))
+            .await
+        {
+            Ok(Ok(resp)) => {
+                counters.shared.fetch_add(1, Ordering::Relaxed);
+                resp.clone()
+            }
+            // The leader went away without a response.
+            Ok(Err(_)) => None,
+            Err(_) => {
+                counters.timeouts.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+}
+
+impl ResponseCache {
+    /// Build a cache from settings. `settings.disk_dir` must already be
+    /// resolved against the site directory; it is created (or emptied).
+    pub fn new(settings: &CacheSettings) -> io::Result<Self> {
+        if let Some(dir) = &settings.disk_dir {
+            clear_dir(dir)?;
+        }
+
+        let vary = settings
+            .vary
+            .iter()
+            .filter_map(|name| match HeaderName::try_from(name.as_str()) {
+                Ok(h) => Some(h),
+                Err(_) => {
+                    warn!("Ignoring invalid cache vary header {:?}", name);
+                    None
+                }
+            })
+            .collect();
+
+        Ok(Self {
+            mem: Mutex::new(Lru::new(settings.max_entries, settings.max_bytes)),
+            disk_index: Mutex::new(DiskIndex::new(settings.disk_max_bytes)),
+            tags: Mutex::new(TagIndex::default()),
+            shares: Mutex::new(Shares::default()),
+            disk: settings.disk_dir.clone(),
+            vary,
+            query_params: settings.query_params.iter().cloned().collect(),
+            config_hash: RwLock::new(String::new()),
+            generation: AtomicU64::new(0),
+            flights: Mutex::new(HashMap::new()),
+            coalesce_wait: Duration::from_millis(settings.coalesce_wait_ms),
+            coalesce: CoalesceCounters::default(),
+        })
+    }
+
+    /// Whether a request with the query parameter `name` may be cached;
+    /// the host stores none whose query has a parameter outside the list.
+    pub fn caches_query(&self, name: &str) -> bool {
+        self.query_params.contains(name)
+    }
+
+    /// The key for a request. `path_and_query` should already be normalized
+    /// (decoded once, query sorted) so equivalent URLs share an entry.
+    pub fn key(&self, path_and_query: &str, headers: &HeaderMap) -> CacheKey {
+        let mut hasher = Sha256::new();
+        hasher.update(
+            self.config_hash
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .as_bytes(),
+        );
+        hasher.update([0]);
+        hasher.update(path_and_query.as_bytes());
+        for name in &self.vary {
+            hasher.update([0]);
+            hasher.update(name.as_str());
+            hasher.update([0]);
+            for value in headers.get_all(name) {
+                hasher.update(value.as_bytes());
+                hasher.update([b',']);
+            }
+        }
+
+        CacheKey {
+            digest: hex::encode(&hasher.finalize()[..16]),
+            generation: self.generation.load(Ordering::SeqCst),
+        }
+    }
+
+    pub fn get(&self, key: &CacheKey) -> Option<CachedResponse> {
+        if let Some(hit) = lock(&self.mem).get(&key.digest) {
+            return Some(hit);
+        }
+
+        let disk = self.disk.as_ref()?;
+        let entry = match read_entry(&disk.join(&key.digest)) {
+            Ok(entry) => entry,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+            Err(e) => {
+                warn!("Reading cached response {} failed: {}", key.digest, e);
+                return None;
+            }
+        };
+
+        let mut mem = lock(&self.mem);
+        if self.generation.load(Ordering::SeqCst) != key.generation {
+            return None;
+        }
+        lock(&self.disk_index).touch(&key.digest);
+        self.index_tags(&key.digest, &entry);
+        let evicted = mem.insert(key.digest.clone(), entry.clone());
+        self.forget_unheld(&mem, evicted);
+        Some(entry)
+    }
+
+    /// Store a response, unless the cache was invalidated since `key` was
+    /// made (the response may have been rendered from old content).
+    pub fn put(&self, key: &CacheKey, resp: CachedResponse) {
+        self.put_shared(key, resp, None);
+    }
+
+    /// [`put`](Self::put), counting the entry against `share`: when the
+    /// tenant's entries come to more than its `max_bytes`, its oldest are
+    /// dropped from both tiers.
+    pub fn put_shared(&self, key: &CacheKey, resp: CachedResponse, share: Option<&CacheShare>) {
+        let mut mem = lock(&self.mem);
+        if self.generation.load(Ordering::SeqCst) != key.generation {
+            debug!("Not caching {}: invalidated while rendering", key.digest);
+            return;
+        }
+
+        let mut evicted = Vec::new();
+        if let Some(disk) = &self.disk {
+            match write_entry(&disk.join(&key.digest), &resp) {
+                Ok(size) => {
+                    let dropped = lock(&self.disk_index).insert(key.digest.clone(), size);
+                    for digest in &dropped {
+                        remove_file(disk, digest);
+                    }
+                    evicted = dropped;
+                }
+                Err(e) => warn!("Writing cached response {} failed: {}", key.digest, e),
+            }
+        }
+        self.index_tags(&key.digest, &resp);
+        let over_share = match share {
+            Some(share) => lock(&self.shares).insert(&key.digest, share, resp.body.len()),
+            None => {
+                lock(&self.shares).forget(&key.digest);
+                HashSet::new()
+            }
+        };
+        evicted.extend(mem.insert(key.digest.clone(), resp));
+        self.forget_unheld(&mem, evicted);
+        if !over_share.is_empty() {
+            let removed = self.remove_entries(&mut mem, &over_share);
+            debug!("Dropped {} cached responses over a tenant's share", removed);
+        }
+    }
+
+    /// Call after a miss on `key`: the first miss leads and renders, later
+    /// ones follow it until it finishes. With coalescing off every miss
+    /// leads.
+    pub fn join_render(&self, key: &CacheKey) -> Flight<'_> {
+        if self.coalesce_wait.is_zero() {
+            return Flight::Leader(RenderFlight {
+                cache: self,
+                key: key.clone(),
+                tx: None,
+            });
+        }
+
+        let mut flights = lock(&self.flights);
+        match flights.get(&key.digest) {
+            Some((generation, rx)) if *generation == key.generation => {
+                self.coalesce.followers.fetch_add(1, Ordering::Relaxed);
+                Flight::Follower(FlightWait {
+                    cache: self,
+                    rx: rx.clone(),
+                })
+            }
+            // None in flight, or one rendering content invalidated since.
+            _ => {
+                let (tx, rx) = watch::channel(None);
+                flights.insert(key.digest.clone(), (key.generation, rx));
+                self.coalesce.leaders.fetch_add(1, Ordering::Relaxed);
+                Flight::Leader(RenderFlight {
+                    cache: self,
+                    key: key.clone(),
+                    tx: Some(tx),
+                })
+            }
+        }
+    }
+
+    pub fn coalesce_stats(&self) -> CoalesceStats {
+        let counters = &self.coalesce;
+        CoalesceStats {
+            leaders: counters.leaders.load(Ordering::Relaxed),
+            followers: counters.followers.load(Ordering::Relaxed),
+            shared: counters.shared.load(Ordering::Relaxed),
+            timeouts: counters.timeouts.load(Ordering::Relaxed),
+        }
+    }
+
+    fn index_tags(&self, digest: &str, resp: &CachedResponse) {
+        let any = [ANY_CONTENT.to_owned()];
+        let tags = if resp.tags.is_empty() {
+            &any[..]
+        } else {
+            &resp.tags[..]
+        };
+        lock(&self.tags).insert(digest, tags);
+    }
+
+    /// Unindex the tags of those `digests` neither tier holds any more.
+    fn forget_unheld(&self, mem: &Lru, digests: Vec<String>) {
+        if digests.is_empty() {
+            return;
+        }
+        let disk = lock(&self.disk_index);
+        let mut tags = lock(&self.tags);
+        let mut shares = lock(&self.shares);
+        for digest in digests {
+            if !mem.entries.contains_key(&digest) && !disk.entries.contains_key(&digest) {
+                tags.forget(&digest);
+                shares.forget(&digest);
+            }
+        }
+    }
+
+    /// Drop the entries tagged with any of `tags`, and those tagged
+    /// [`ANY_CONTENT`], in memory and on disk.
+    pub fn invalidate_tags<I, T>(&self, tags: I)
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<str>,
+    {
+        let mut mem = lock(&self.mem);
+        self.generation.fetch_add(1, Ordering::SeqCst);
+
+        let mut index = lock(&self.tags);
+        let mut digests = index.take(ANY_CONTENT);
+        for tag in tags {
+            digests.extend(index.take(tag.as_ref()));
+        }
+        drop(index);
+
+        self.remove_entries(&mut mem, &digests);
+        debug!("Invalidated {} cached responses by tag", digests.len());
+    }
+
+    /// Drop the entries carrying a tag `matches` accepts, leaving the
+    /// [`ANY_CONTENT`] ones unless it accepts that too; returns how many
+    /// went.
+    pub fn purge(&self, matches: impl Fn(&str) -> bool) -> usize {
+        let mut mem = lock(&self.mem);
+        self.generation.fetch_add(1, Ordering::SeqCst);
+
+        let mut index = lock(&self.tags);
+        let tags: Vec<String> = index
+            .digests
+            .keys()
+            .filter(|t| matches(t))
+            .cloned()
+            .collect();
+        let mut digests = HashSet::new();
+        for tag in tags {
+            digests.extend(index.take(&tag));
+        }
+        drop(index);
+
+        let removed = self.remove_entries(&mut mem, &digests);
+        debug!("Purged {} cached responses", removed);
+        removed
+    }
+
+    /// Remove `digests` from both tiers; returns how many were held.
+    fn remove_entries(&self, mem: &mut Lru, digests: &HashSet<String>) -> usize {
+        let mut disk_index = lock(&self.disk_index);
+        let mut tags = lock(&self.tags);
+        let mut shares = lock(&self.shares);
+        let mut removed = 0;
+        for digest in digests {
+            let mut held = mem.entries.contains_key(digest);
+            mem.remove(digest);
+            disk_index.remove(digest);
+            tags.forget(digest);
+            shares.forget(digest);
+            if let Some(disk) = &self.disk {
+                held |= remove_file(disk, digest);
+            }
+            removed += usize::from(held);
+        }
+        removed
+    }
+
+    /// Drop every entry, in memory and on disk.
+    pub fn invalidate_all(&self) {
+        let mut mem = lock(&self.mem);
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        mem.clear();
+        lock(&self.disk_index).clear();
+        lock(&self.tags).clear();
+        *lock(&self.shares) = Shares::default();
+
+        if let Some(disk) = &self.disk {
+            if let Err(e) = clear_dir(disk) {
+                warn!("Clearing response cache {:?} failed: {}", disk, e);
+            }
+        }
+    }
+
+    /// Record the active theme/plugin configuration (see [`config_hash`]);
+    /// a different hash invalidates everything cached under the old one.
+    pub fn set_config_hash(&self, hash: String) {
+        let mut current = self
+            .config_hash
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        if *current != hash {
+            *current = hash;
+            drop(current);
+            self.invalidate_all();
+        }
+    }
+
+    /// Entries currently held in memory.
+    pub fn len(&self) -> usize {
+        lock(&self.mem).entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Memory tier
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Least-recently-used map bounded by entry count and total body bytes.
+struct Lru {
+    entries: HashMap<String, (CachedResponse, u64)>,
+    /// Last-use tick → key; the first entry is the eviction candidate.
+    order: BTreeMap<u64, String>,
+    tick: u64,
+    bytes: usize,
+    max_entries: usize,
+    max_bytes: usize,
+}
+
+impl Lru {
+    fn new(max_entries: usize, max_bytes: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+            bytes: 0,
+            max_entries,
+            max_bytes,
+        }
+    }
+
+    fn get(&mut self, key: &str) -> Option<CachedResponse> {
+        self.tick += 1;
+        let (resp, used) = self.entries.get_mut(key)?;
+        self.order.remove(used);
+        *used = self.tick;
+        self.order.insert(self.tick, key.to_owned());
+        Some(resp.clone())
+    }
+
+    /// Store `resp` under `key`; returns the keys evicted to make room.
+    fn insert(&mut self, key: String, resp: CachedResponse) -> Vec<String> {
+        self.remove(&key);
+        if self.max_entries == 0 || resp.body.len() > self.max_bytes {
+            return vec![key];
+        }
+
+        self.tick += 1;
+        self.bytes += resp.body.len();
+        self.order.insert(self.tick, key.clone());
+        self.entries.insert(key, (resp, self.tick));
+
+        let mut evicted = Vec::new();
+        while self.entries.len() > self.max_entries || self.bytes > self.max_bytes {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            if let Some((resp, _)) = self.entries.remove(&oldest) {
+                self.bytes -= resp.body.len();
+            }
+            evicted.push(oldest);
+        }
+        evicted
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some((resp, used)) = self.entries.remove(key) {
+            self.order.remove(&used);
+            self.bytes -= resp.body.len();
+        }
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+        self.bytes = 0;
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tag index
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[derive(Default)]
+struct TagIndex {
+    /// Tag → digests of the entries carrying it.
+    digests: HashMap<String, HashSet<String>>,
+    /// Digest → its tags, so an entry can be unindexed when it goes.
+    tags: HashMap<String, Vec<String>>,
+}
+
+impl TagIndex {
+    fn insert(&mut self, digest: &str, tags: &[String]) {
+        self.forget(digest);
+        for tag in tags {
+            self.digests
+                .entry(tag.clone())
+                .or_default()
+                .insert(digest.to_owned());
+        }
+        self.tags.insert(digest.to_owned(), tags.to_vec());
+    }
+
+    /// The digests tagged `tag`, no longer indexed under it.
+    fn take(&mut self, tag: &str) -> HashSet<String> {
+        self.digests.remove(tag).unwrap_or_default()
+    }
+
+    /// Drop `digest` from every tag it carries.
+    fn forget(&mut self, digest: &str) {
+        for tag in self.tags.remove(digest).into_iter().flatten() {
+            if let Some(digests) = self.digests.get_mut(&tag) {
+                digests.remove(digest);
+                if digests.is_empty() {
+                    self.digests.remove(&tag);
+                }
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.digests.clear();
+        self.tags.clear();
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tenant shares
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// The entries stored under each tenant's share, oldest first.
+#[derive(Default)]
+struct Shares {
+    /// Digest → tenant, store tick and body size.
+    entries: HashMap<String, (String, u64, usize)>,
+    /// Tenant → body bytes held, and its entries by store tick.
+    tenants: HashMap<String, (usize, BTreeMap<u64, String>)>,
+    tick: u64,
+}
+
+impl Shares {
+    /// Count `digest` against `share`; returns the digests that must go to
+    /// keep the tenant within it, `digest` itself when it alone is over.
+    fn insert(&mut self, digest: &str, share: &CacheShare, size: usize) -> HashSet<String> {
+        self.forget(digest);
+        if size > share.max_bytes {
+            return HashSet::from([digest.to_owned()]);
+        }
+        self.tick += 1;
+        self.entries
+            .insert(digest.to_owned(), (share.tenant.clone(), self.tick, size));
+        let (bytes, order) = self.tenants.entry(share.tenant.clone()).or_default();
+        *bytes += size;
+        order.insert(self.tick, digest.to_owned());
+
+        let mut over = HashSet::new();
+        while *bytes > share.max_bytes {
+            let Some((_, oldest)) = order.pop_first() else {
+                break;
+            };
+            if let Some((_, _, size)) = self.entries.remove(&oldest) {
+                *bytes -= size;
+            }
+            over.insert(oldest);
+        }
+        if order.is_empty() {
+            self.tenants.remove(&share.tenant);
+        }
+        over
+    }
+
+    fn forget(&mut self, digest: &str) {
+        let Some((tenant, stored, size)) = self.entries.remove(digest) else {
+            return;
+        };
+        if let Some((bytes, order)) = self.tenants.get_mut(&tenant) {
+            *bytes -= size;
+            order.remove(&stored);
+            if order.is_empty() {
+                self.tenants.remove(&tenant);
+            }
+        }
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Disk tier
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// The files in the disk tier, least recently used first, bounded by their
+/// total size.
+struct DiskIndex {
+    /// Digest → file size and last-use tick.
+    entries: HashMap<String, (u64, u64)>,
+    /// Last-use tick → digest; the first entry is the eviction candidate.
+    order: BTreeMap<u64, String>,
+    tick: u64,
+    bytes: u64,
+    max_bytes: u64,
+}
+
+impl DiskIndex {
+    fn new(max_bytes: u64) -> Self {
+        Self {
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+            bytes: 0,
+            max_bytes,
+        }
+    }
+
+    /// Record a file of `size` bytes written for `digest`; returns the
+    /// digests whose files must go to stay within the bound, `digest`
+    /// itself when it alone is over.
+    fn insert(&mut self, digest: String, size: u64) -> Vec<String> {
+        self.remove(&digest);
+        if size > self.max_bytes {
+            return vec![digest];
+        }
+
+        self.tick += 1;
+        self.bytes += size;
+        self.order.insert(self.tick, digest.clone());
+        self.entries.insert(digest, (size, self.tick));
+
+        let mut evicted = Vec::new();
+        while self.bytes > self.max_bytes {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            if let Some((size, _)) = self.entries.remove(&oldest) {
+                self.bytes -= size;
+            }
+            evicted.push(oldest);
+        }
+        evicted
+    }
+
+    fn touch(&mut self, digest: &str) {
+        self.tick += 1;
+        if let Some((_, used)) = self.entries.get_mut(digest) {
+            self.order.remove(used);
+            *used = self.tick;
+            self.order.insert(self.tick, digest.to_owned());
+        }
+    }
+
+    fn remove(&mut self, digest: &str) {
+        if let Some((size, used)) = self.entries.remove(digest) {
+            self.order.remove(&used);
+            self.bytes -= size;
+        }
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+        self.bytes = 0;
+    }
+}
+
+/// One file per entry: the JSON header, a newline, then the raw body.
+/// Returns the file's size.
+fn write_entry(path: &Path, resp: &CachedResponse) -> io::Result<u64> {
+    let mut data = serde_json::to_vec(resp)?;
+    data.push(b'\n');
+    data.extend_from_slice(&resp.body);
+    let size = data.len() as u64;
+
+    // Write then rename, so a concurrent reader never sees half an entry.
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, data)?;
+    fs::rename(tmp, path)?;
+    Ok(size)
+}
+
+/// Delete the file of `digest` under `disk`; whether there was one.
+fn remove_file(disk: &Path, digest: &str) -> bool {
+    match fs::remove_file(disk.join(digest)) {
+        Ok(()) => true,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => false,
+        Err(e) => {
+            warn!("Removing cached response {} failed: {}", digest, e);
+            false
+        }
+    }
+}
+
+fn read_entry(path: &Path) -> io::Result<CachedResponse> {
+    let data = fs::read(path)?;
+    let split = data
+        .iter()
+        .position(|b| *b == b'\n')
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing header"))?;
+
+    let mut resp: CachedResponse = serde_json::from_slice(&data[..split])?;
+    resp.body = Bytes::copy_from_slice(&data[split + 1..]);
+    Ok(resp)
+}
+
+fn clear_dir(dir: &Path) -> io::Result<()> {
+    match fs::remove_dir_all(dir) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    fs::create_dir_all(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::header::{ACCEPT_LANGUAGE, AUTHORIZATION};
+    use serde_json::json;
+
+    fn settings(max_entries: usize, max_bytes: usize, disk_dir: Option<PathBuf>) -> CacheSettings {
+        CacheSettings {
+            max_entries,
+            max_bytes,
+            disk_dir,
+            vary: vec!["accept-language".into()],
+            coalesce_wait_ms: 50,
+            purge_token: None,
+            disk_max_bytes: 1024 * 1024,
+            query_params: vec!["page".into()],
+        }
+    }
+
+    fn resp(body: &str) -> CachedResponse {
+        CachedResponse {
+            status: 200,
+            content_type: "text/html; charset=utf-8".into(),
+            etag: "\"e\"".into(),
+            last_modified: None,
+            tags: Vec::new(),
+            headers: Vec::new(),
+            body: Bytes::from(body.to_owned()),
+        }
+    }
+
+    fn tagged(body: &str, tags: &[&str]) -> CachedResponse {
+        CachedResponse {
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            ..resp(body)
+        }
+    }
+
+    #[test]
+    fn lru_evicts_least_recently_used_by_count_and_bytes() {
+        let mut lru = Lru::new(2, 10);
+        lru.insert("a".into(), resp("aaa"));
+        lru.insert("b".into(), resp("bbb"));
+        assert!(lru.get("a").is_some()); // b is now the oldest
+        lru.insert("c".into(), resp("ccc"));
+        assert!(lru.get("b").is_none());
+        assert!(lru.get("a").is_some() && lru.get("c").is_some());
+
+        // 3 + 8 bytes exceeds the budget, so the older entry goes too.
+        lru.insert("d".into(), resp("dddddddd"));
+        assert_eq!(lru.entries.len(), 1);
+        assert_eq!(lru.bytes, 8);
+
+        // Bodies larger than the whole budget are never stored.
+        lru.insert("e".into(), resp("eeeeeeeeeeee"));
+        assert!(lru.get("e").is_none());
+    }
+
+    #[test]
+    fn keys_follow_vary_headers_and_config() {
+        let cache = ResponseCache::new(&settings(8, 1024, None)).unwrap();
+        let mut en = HeaderMap::new();
+        en.insert(ACCEPT_LANGUAGE, "en".parse().unwrap());
+        en.insert(AUTHORIZATION, "ignored".parse().unwrap());
+        let mut fr = HeaderMap::new();
+        fr.insert(ACCEPT_LANGUAGE, "fr".parse().unwrap());
+
+        let key = cache.key("/a.html", &en);
+        assert_ne!(key, cache.key("/a.html", &fr));
+        assert_ne!(key, cache.key("/b.html", &en));
+        en.remove(AUTHORIZATION);
+        assert_eq!(key, cache.key("/a.html", &en));
+
+        cache.put(&key, resp("hello"));
+        assert_eq!(cache.get(&key).unwrap().body, "hello");
+
+        cache.set_config_hash(config_hash(["theme-a", "source v2"]));
+        assert!(cache.is_empty());
+        assert_ne!(cache.key("/a.html", &en).as_str(), key.as_str());
+    }
+
+    #[test]
+    fn renders_that_straddle_an_invalidation_are_not_stored() {
+        let cache = ResponseCache::new(&settings(8, 1024, None)).unwrap();
+        let key = cache.key("/a.html", &HeaderMap::new());
+
+        cache.invalidate_all(); // content changed mid-render
+        cache.put(&key, resp("stale"));
+        assert!(cache.get(&key).is_none());
+
+        let key = cache.key("/a.html", &HeaderMap::new());
+        cache.put(&key, resp("fresh"));
+        assert_eq!(cache.get(&key).unwrap().body, "fresh");
+    }
+
+    #[test]
+    fn disk_tier_refills_memory_and_is_cleared_with_it() {
+        let dir = tempfile::tempdir().unwrap();
+        let disk = dir.path().join("cache");
+        let cache = ResponseCache::new(&settings(1, 1024, Some(disk.clone()))).unwrap();
+        let headers = HeaderMap::new();
+
+        let a = cache.key("/a.html", &headers);
+        let b = cache.key("/b.html", &headers);
+        cache.put(&a, resp("line one\nline two"));
+        cache.put(&b, resp("b")); // evicts a from memory
+
+        let hit = cache.get(&a).unwrap();
+        assert_eq!(hit.body, "line one\nline two");
+        assert_eq!(hit.etag, "\"e\"");
+
+        cache.invalidate_all();
+        assert_eq!(fs::read_dir(&disk).unwrap().count(), 0);
+        assert!(cache.get(&cache.key("/a.html", &headers)).is_none());
+    }
+
+    #[test]
+    fn evicted_entries_leave_the_disk_and_the_tag_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let disk = dir.path().join("cache");
+        let cache = ResponseCache::new(&CacheSettings {
+            disk_max_bytes: 600,
+            ..settings(1, 1024, Some(disk.clone()))
+        })
+        .unwrap();
+        let headers = HeaderMap::new();
+        for i in 0..10 {
+            let key = cache.key(&format!("/{i}.html"), &headers);
+            cache.put(
+                &key,
+                tagged(&"x".repeat(100), &[&doc_tag(&format!("/{i}.html"))]),
+            );
+        }
+
+        let sizes: Vec<u64> = fs::read_dir(&disk)
+            .unwrap()
+            .map(|e| e.unwrap().metadata().unwrap().len())
+            .collect();
+        assert!(sizes.len() < 10 && sizes.iter().sum::<u64>() <= 600);
+        // Memory holds the newest entry, which is on disk too.
+        assert_eq!(lock(&cache.tags).tags.len(), sizes.len());
+        assert!(cache.get(&cache.key("/9.html", &headers)).is_some());
+        assert!(cache.get(&cache.key("/0.html", &headers)).is_none());
+
+        // Without a disk tier, the memory bound alone decides.
+        let cache = ResponseCache::new(&settings(2, 1024, None)).unwrap();
+        for i in 0..10 {
+            let key = cache.key(&format!("/{i}.html"), &headers);
+            cache.put(&key, tagged("x", &[&doc_tag(&format!("/{i}.html"))]));
+        }
+        assert_eq!(lock(&cache.tags).tags.len(), 2);
+        assert_eq!(lock(&cache.tags).digests.len(), 2);
+    }
+
+    #[test]
+    fn a_tenant_over_its_share_evicts_its_own_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let disk = dir.path().join("cache");
+        let cache = ResponseCache::new(&settings(8, 1024, Some(disk.clone()))).unwrap();
+        let headers = HeaderMap::new();
+        let share = |tenant: &str| CacheShare {
+            tenant: tenant.into(),
+            max_bytes: 250,
+        };
+
+        let quiet = cache.key("quiet/a.html", &headers);
+        cache.put_shared(&quiet, resp(&"q".repeat(200)), Some(&share("quiet")));
+        for i in 0..5 {
+            let key = cache.key(&format!("noisy/{i}.html"), &headers);
+            cache.put_shared(&key, resp(&"n".repeat(100)), Some(&share("noisy")));
+        }
+
+        // The noisy tenant keeps its two newest pages; the quiet one keeps
+        // its own.
+        assert!(cache.get(&quiet).is_some());
+        let held: Vec<bool> = (0..5)
+            .map(|i| {
+                let key = cache.key(&format!("noisy/{i}.html"), &headers);
+                cache.get(&key).is_some()
+            })
+            .collect();
+        assert_eq!(held, [false, false, false, true, true]);
+        assert_eq!(fs::read_dir(&disk).unwrap().count(), 3);
+        assert_eq!(lock(&cache.tags).tags.len(), 3);
+
+        // A page bigger than the whole share is not kept, and costs the
+        // tenant nothing it had.
+        let big = cache.key("quiet/big.html", &headers);
+        cache.put_shared(&big, resp(&"b".repeat(300)), Some(&share("quiet")));
+        assert!(cache.get(&big).is_none());
+        assert!(cache.get(&quiet).is_some());
+    }
+
+    #[test]
+    fn invalidating_tags_drops_only_the_pages_that_used_them() {
+        let dir = tempfile::tempdir().unwrap();
+        let disk = dir.path().join("cache");
+        let cache = ResponseCache::new(&settings(8, 1024, Some(disk.clone()))).unwrap();
+        let headers = HeaderMap::new();
+
+        let post = cache.key("/a.html", &headers);
+        let listing = cache.key("/tags/rust/", &headers);
+        let other = cache.key("/b.html", &headers);
+        let untagged = cache.key("/nav.html", &headers);
+        cache.put(&post, tagged("a", &["doc:/a.html"]));
+        cache.put(&listing, tagged("list", &["term:tags:rust"]));
+        cache.put(&other, tagged("b", &["doc:/b.html"]));
+        cache.put(&untagged, resp("nav"));
+
+        let edited = json!({
+            "id": "/a.html",
+            "slug": "a",
+            "tax": { "categories": [], "tags": ["rust"], "series": [] },
+        });
+        let tags = tags_for_doc(&edited);
+        assert_eq!(tags, ["doc:/a.html", "slug:a", "term:tags:rust"]);
+        cache.invalidate_tags(&tags);
+
+        let headers = HeaderMap::new();
+        for path in ["/a.html", "/tags/rust/", "/nav.html"] {
+            assert!(cache.get(&cache.key(path, &headers)).is_none(), "{path}");
+        }
+        let kept = cache.get(&cache.key("/b.html", &headers)).unwrap();
+        assert_eq!(kept.tags, ["doc:/b.html"]);
+        assert_eq!(fs::read_dir(&disk).unwrap().count(), 1);
+
+        // A purge by path leaves the pages that read everything alone.
+        cache.put(&cache.key("/nav.html", &headers), resp("nav"));
+        let page = cache.key("/c/", &headers);
+        cache.put(&page, tagged("c", &["doc:/c.html", &path_tag("/c/")]));
+        assert_eq!(cache.purge(|t| t == path_tag("/c/")), 1);
+        assert!(cache.get(&page).is_none());
+        assert!(cache.get(&cache.key("/nav.html", &headers)).is_some());
+    }
+
+    #[tokio::test]
+    async fn concurrent_misses_wait_for_one_render() {
+        let cache = ResponseCache::new(&settings(8, 1024, None)).unwrap();
+        let key = cache.key("/a.html", &HeaderMap::new());
+
+        let Flight::Leader(leader) = cache.join_render(&key) else {
+            panic!("the first miss renders");
+        };
+        let Flight::Follower(first) = cache.join_render(&key) else {
+            panic!("a second miss waits");
+        };
+        let Flight::Follower(second) = cache.join_render(&key) else {
+            panic!("a third miss waits");
+        };
+        let (first, ()) = tokio::join!(first.wait(), async { leader.finish(&resp("hello")) });
+        assert_eq!(first.unwrap().body, "hello");
+        assert_eq!(second.wait().await.unwrap().body, "hello");
+
+        // The flight is over once the leader is gone.
+        assert!(matches!(cache.join_render(&key), Flight::Leader(_)));
+        assert_eq!(
+            cache.coalesce_stats(),
+            CoalesceStats {
+                leaders: 2,
+                followers: 2,
+                shared: 2,
+                timeouts: 0,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn followers_render_themselves_when_the_leader_shares_nothing() {
+        let cache = ResponseCache::new(&settings(8, 1024, None)).unwrap();
+        let key = cache.key("/a.html", &HeaderMap::new());
+
+        let leader = cache.join_render(&key);
+        let Flight::Follower(follower) = cache.join_render(&key) else {
+            panic!("a second miss waits");
+        };
+        drop(leader); // e.g. the render failed
+        assert!(follower.wait().await.is_none());
+
+        // Held past `coalesce_wait_ms`.
+        let _leader = cache.join_render(&key);
+        let Flight::Follower(follower) = cache.join_render(&key) else {
+            panic!("a second miss waits");
+        };
+        assert!(follower.wait().await.is_none());
+        assert_eq!(cache.coalesce_stats().timeouts, 1);
+
+        // Content changed since the leader started, so its render is stale.
+        cache.invalidate_all();
+        let key = cache.key("/a.html", &HeaderMap::new());
+        assert!(matches!(cache.join_render(&key), Flight::Leader(_)));
+    }
+
+    #[test]
+    fn coalescing_can_be_turned_off() {
+        let cache = ResponseCache::new(&CacheSettings {
+            coalesce_wait_ms: 0,
+            ..settings(8, 1024, None)
+        })
+        .unwrap();
+        let key = cache.key("/a.html", &HeaderMap::new());
+
+        let _first = cache.join_render(&key);
+        assert!(matches!(cache.join_render(&key), Flight::Leader(_)));
+        assert_eq!(cache.coalesce_stats(), CoalesceStats::default());
+    }
+}
@@ -0,0 +1,71 @@
+// crates/serve/src/collections.rs
+
+// Saved queries (adapt::mql::collection) as themes see them. The host runs
+// every collection before a theme request renders and puts the matching
+// documents on the `RequestContext` (`ctx.collections` in JS), keyed by
+// name. Templates read the same results through the `collection` helper:
+//
+//   <ul>
+//   {{#each (collection "recent-posts")}}
+//     <li><a href="{{id}}">{{title}}</a></li>
+//   {{/each}}
+//   </ul>
+//
+// An unknown name is an empty list, so a theme can ship templates for
+// collections a site has not defined.
+
+use handlebars::{
+    Context, Handlebars, Helper, HelperDef, RenderContext, RenderError as HbsError, ScopedJson,
+};
+use serde_json::Value as Json;
+use std::collections::BTreeMap;
+
+/// `{{#each (collection "name")}}`: the documents of a saved query.
+#[derive(Clone, Debug, Default)]
+pub struct CollectionHelper {
+    collections: BTreeMap<String, Json>,
+}
+
+impl CollectionHelper {
+    pub fn new(collections: BTreeMap<String, Json>) -> Self {
+        Self { collections }
+    }
+}
+
+impl HelperDef for CollectionHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, HbsError> {
+        let docs = h
+            .param(0)
+            .and_then(|p| p.value().as_str())
+            .and_then(|name| self.collections.get(name))
+            .cloned()
+            .unwrap_or_else(|| Json::Array(Vec::new()));
+        Ok(ScopedJson::Derived(docs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn templates_read_collections_through_the_helper() {
+        let mut hbs = Handlebars::new();
+        hbs.register_helper(
+            "collection",
+            Box::new(CollectionHelper::new(BTreeMap::from([(
+                "featured".to_owned(),
+                json!([{ "title": "A" }, { "title": "B" }]),
+            )]))),
+        );
+        let tpl = r#"{{#each (collection "featured")}}{{title}};{{/each}}{{#each (collection "x")}}x{{/each}}"#;
+        assert_eq!(hbs.render_template(tpl, &json!({})).unwrap(), "A;B;");
+    }
+}
@@ -0,0 +1,168 @@
+// crates/serve/src/comments.rs
+
+// Approved reader comments as themes see them. The host keeps each page's
+// comments threaded (see `threads`) and hands them to the `comments`
+// helper, which reads the current page's by default or another page's by
+// path:
+//
+//   {{#each (comments)}}
+//     <article id="comment-{{id}}">
+//       <b>{{author}}</b> <time>{{created_at}}</time> {{body}}
+//       {{#each replies}}…{{/each}}
+//     </article>
+//   {{/each}}
+//   {{#with (comments "/blog/other")}}{{len this}}{{/with}}
+//
+// Comments are plain text; `{{body}}` escapes them like any other value.
+
+use handlebars::{
+    Context, Handlebars, Helper, HelperDef, RenderContext, RenderError as HbsError, ScopedJson,
+};
+use serde_json::{Map as JsonMap, Value as Json};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+/// The key comments on `path` are kept under: `/blog/hello/` and
+/// `/blog/hello` are the same page.
+pub fn page_key(path: &str) -> String {
+    match path.trim_end_matches('/') {
+        "" => "/".to_owned(),
+        trimmed => trimmed.to_owned(),
+    }
+}
+
+/// Nest `comments` (objects with an `id` and an optional `parent`, oldest
+/// first) into threads: the top-level comments, each with its `replies`,
+/// recursively. A reply whose parent is not among them (deleted, or not
+/// approved) is shown at the top level.
+pub fn threads(comments: Vec<Json>) -> Json {
+    let id_of = |c: &Json| c.get("id").and_then(Json::as_str).map(str::to_owned);
+    let ids: Vec<Option<String>> = comments.iter().map(id_of).collect();
+
+    let mut children: HashMap<String, Vec<usize>> = HashMap::new();
+    let mut roots = Vec::new();
+    for (i, comment) in comments.iter().enumerate() {
+        let parent = comment.get("parent").and_then(Json::as_str).filter(|p| {
+            ids.iter().any(|id| id.as_deref() == Some(*p)) && ids[i].as_deref() != Some(*p)
+        });
+        match parent {
+            Some(parent) => children.entry(parent.to_owned()).or_default().push(i),
+            None => roots.push(i),
+        }
+    }
+
+    fn build(
+        i: usize,
+        comments: &[Json],
+        ids: &[Option<String>],
+        children: &HashMap<String, Vec<usize>>,
+        depth: usize,
+    ) -> Json {
+        let mut obj = comments[i]
+            .as_object()
+            .cloned()
+            .unwrap_or_else(JsonMap::new);
+        let replies = match (&ids[i], depth) {
+            // A parent chain longer than the thread itself is a cycle.
+            (Some(id), d) if d < comments.len() => children
+                .get(id)
+                .map(|kids| {
+                    kids.iter()
+                        .map(|k| build(*k, comments, ids, children, depth + 1))
+                        .collect()
+                })
+                .unwrap_or_default(),
+            _ => Vec::new(),
+        };
+        obj.insert("replies".to_owned(), Json::Array(replies));
+        Json::Object(obj)
+    }
+
+    Json::Array(
+        roots
+            .into_iter()
+            .map(|i| build(i, &comments, &ids, &children, 0))
+            .collect(),
+    )
+}
+
+/// `{{#each (comments)}}`: the threads on this page, or `(comments "/path")`
+/// on another.
+#[derive(Clone, Debug, Default)]
+pub struct CommentsHelper {
+    page: String,
+    threads: Arc<BTreeMap<String, Json>>,
+}
+
+impl CommentsHelper {
+    /// `threads` by [`page_key`]; `page` is the path being rendered.
+    pub fn new(page: &str, threads: Arc<BTreeMap<String, Json>>) -> Self {
+        Self {
+            page: page_key(page),
+            threads,
+        }
+    }
+}
+
+impl HelperDef for CommentsHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, HbsError> {
+        let page = match h.param(0).and_then(|p| p.value().as_str()) {
+            Some(path) => page_key(path),
+            None => self.page.clone(),
+        };
+        let threads = self
+            .threads
+            .get(&page)
+            .cloned()
+            .unwrap_or_else(|| Json::Array(Vec::new()));
+        Ok(ScopedJson::Derived(threads))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn replies_nest_under_their_parents() {
+        let threads = threads(vec![
+            json!({ "id": "a", "parent": null, "body": "first" }),
+            json!({ "id": "b", "parent": "a", "body": "reply" }),
+            json!({ "id": "c", "parent": "gone", "body": "orphan" }),
+            json!({ "id": "d", "parent": "b", "body": "nested" }),
+        ]);
+        assert_eq!(threads[0]["body"], "first");
+        assert_eq!(threads[0]["replies"][0]["body"], "reply");
+        assert_eq!(threads[0]["replies"][0]["replies"][0]["body"], "nested");
+        assert_eq!(threads[1]["body"], "orphan");
+        assert_eq!(threads.as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn templates_read_the_pages_comments_through_the_helper() {
+        let all = Arc::new(BTreeMap::from([
+            (
+                "/blog/hello".to_owned(),
+                threads(vec![json!({ "id": "a", "author": "Ada" })]),
+            ),
+            (
+                "/blog/other".to_owned(),
+                threads(vec![json!({ "id": "b", "author": "Bo" })]),
+            ),
+        ]));
+        let mut hbs = Handlebars::new();
+        hbs.register_helper(
+            "comments",
+            Box::new(CommentsHelper::new("/blog/hello/", all)),
+        );
+        let tpl = r#"{{#each (comments)}}{{author}};{{/each}}{{#each (comments "/blog/other/")}}{{author}};{{/each}}{{#each (comments "/none")}}x{{/each}}"#;
+        assert_eq!(hbs.render_template(tpl, &json!({})).unwrap(), "Ada;Bo;");
+    }
+}
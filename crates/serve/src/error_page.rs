@@ -0,0 +1,91 @@
+// crates/serve/src/error_page.rs
+
+// Pages for error statuses. A theme may provide
+//
+//   404.hbs     for 404 Not Found
+//   error.hbs   for every other 4xx and 5xx
+//
+// rendered with `page_model`:
+//
+//   {"status":404,"title":"Not Found","path":"/missing/",
+//    "request_id":"01J…","error":"…"}
+//
+// where `error` is only present when the site runs with `[edge] dev = true`.
+// The host decides which responses get a page; this module names the
+// templates, builds the model and renders the built-in page for themes
+// without one.
+
+use http::StatusCode;
+use serde_json::{json, Value as Json};
+
+/// Served for any status the theme has no [`template_for`]; its model comes
+/// from [`page_model`].
+pub const DEFAULT_ERROR_TEMPLATE: &str = r#"<!doctype html>
+<html>
+<head><meta charset="utf-8"><title>{{status}} {{title}}</title></head>
+<body>
+<main>
+  <h1>{{status}} {{title}}</h1>
+  {{#if (eq status 404)}}<p>There is nothing at <code>{{path}}</code>.</p>{{else}}<p>Something went wrong.</p>{{/if}}
+  {{#if error}}<pre>{{error}}</pre>{{/if}}
+  {{#if request_id}}<p><small>Request {{request_id}}</small></p>{{/if}}
+</main>
+</body>
+</html>
+"#;
+
+/// The theme template for `status`.
+pub fn template_for(status: u16) -> &'static str {
+    if status == 404 {
+        "404.hbs"
+    } else {
+        "error.hbs"
+    }
+}
+
+/// The model of an error page; `error` only in dev mode.
+pub fn page_model(status: u16, path: &str, request_id: Option<&str>, error: Option<&str>) -> Json {
+    let mut model = json!({
+        "status": status,
+        "title": StatusCode::from_u16(status)
+            .ok()
+            .and_then(|s| s.canonical_reason())
+            .unwrap_or("Error"),
+        "path": path,
+        "request_id": request_id,
+    });
+    if let Some(error) = error {
+        model["error"] = json!(error);
+    }
+    model
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::render_default;
+
+    #[test]
+    fn pages_name_the_status_and_show_errors_only_when_given() {
+        assert_eq!(template_for(404), "404.hbs");
+        assert_eq!(template_for(502), "error.hbs");
+
+        let model = page_model(404, "/<x>/", Some("01J"), None);
+        assert_eq!(model["title"], "Not Found");
+        assert!(model.get("error").is_none());
+        let mut out = Vec::new();
+        render_default("error", DEFAULT_ERROR_TEMPLATE, &model, &mut out).unwrap();
+        let html = String::from_utf8(out).unwrap();
+        assert!(html.contains("<h1>404 Not Found</h1>"));
+        assert!(html.contains("<code>/&lt;x&gt;/</code>"));
+        assert!(html.contains("Request 01J"));
+        assert!(!html.contains("<pre>"));
+
+        let model = page_model(500, "/", None, Some("template page.hbs: unknown helper"));
+        let mut out = Vec::new();
+        render_default("error", DEFAULT_ERROR_TEMPLATE, &model, &mut out).unwrap();
+        let html = String::from_utf8(out).unwrap();
+        assert!(html.contains("Something went wrong."));
+        assert!(html.contains("<pre>template page.hbs: unknown helper</pre>"));
+    }
+}
@@ -0,0 +1,540 @@
+// crates/serve/src/feeds.rs
+
+// RSS 2.0, Atom and JSON Feed from the front-matter index.
+//
+// A feed lists the newest published documents (`publish.status = "publish"`)
+// of the whole site or of one tag, category, section or author, optionally
+// narrowed to one `i18n.lang`. Author feeds match `author.author` and
+// `author.co_authors`, which indexing links to author slugs (see
+// `crate::authors`). Items carry either the full rendered body or a plain
+// text summary cut from it. The format follows the requested extension:
+//
+//   feed.xml  → RSS 2.0
+//   feed.atom → Atom
+//   feed.json → JSON Feed 1.1
+
+use crate::indexer::{for_each_front_matter, ContentManager};
+use crate::resolver::ResolverError;
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
+use domain::setting::FeedSettings;
+use serde_json::{json, Map as JsonMap, Value as Json};
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeedFormat {
+    Rss,
+    Atom,
+    Json,
+}
+
+impl FeedFormat {
+    /// The format served for `feed.<ext>`.
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext {
+            "xml" | "rss" => Some(FeedFormat::Rss),
+            "atom" => Some(FeedFormat::Atom),
+            "json" => Some(FeedFormat::Json),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            FeedFormat::Rss => "application/rss+xml; charset=utf-8",
+            FeedFormat::Atom => "application/atom+xml; charset=utf-8",
+            FeedFormat::Json => "application/feed+json; charset=utf-8",
+        }
+    }
+}
+
+/// Which documents a feed covers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FeedScope {
+    All,
+    Tag(String),
+    Category(String),
+    Series(String),
+    Section(String),
+    /// An author slug.
+    Author(String),
+}
+
+impl FeedScope {
+    fn matches(&self, fm: &Json) -> bool {
+        match self {
+            FeedScope::All => true,
+            FeedScope::Tag(tag) => strings(fm, &["tax", "tags"]).contains(tag),
+            FeedScope::Category(cat) => strings(fm, &["tax", "categories"]).contains(cat),
+            FeedScope::Series(series) => strings(fm, &["tax", "series"]).contains(series),
+            FeedScope::Section(section) => {
+                string(fm, &["content", "section"]).as_deref() == Some(section.as_str())
+            }
+            FeedScope::Author(slug) => {
+                string(fm, &["author", "author"]).as_deref() == Some(slug.as_str())
+                    || strings(fm, &["author", "co_authors"]).contains(slug)
+            }
+        }
+    }
+
+    /// Appended to the site title, e.g. `Site – tag: rust`.
+    fn label(&self) -> Option<String> {
+        match self {
+            FeedScope::All => None,
+            FeedScope::Tag(t) => Some(format!("tag: {t}")),
+            FeedScope::Category(c) => Some(format!("category: {c}")),
+            FeedScope::Series(s) => Some(format!("series: {s}")),
+            FeedScope::Section(s) => Some(format!("section: {s}")),
+            FeedScope::Author(a) => Some(format!("author: {a}")),
+        }
+    }
+}
+
+/// One feed entry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeedItem {
+    /// Permalink, else served path, e.g. `/blog/hello.html`.
+    pub path: String,
+    pub title: String,
+    pub published: Option<DateTime<Utc>>,
+    pub updated: Option<DateTime<Utc>>,
+    pub author: Option<String>,
+    /// Tags followed by categories.
+    pub tags: Vec<String>,
+    /// Full HTML body when `full_content` is set.
+    pub content_html: Option<String>,
+    /// Plain-text summary otherwise.
+    pub summary: Option<String>,
+}
+
+/// Channel-level details.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeedMeta {
+    pub title: String,
+    /// Absolute site URL without a trailing slash.
+    pub base_url: String,
+    /// Path the feed itself is served at.
+    pub feed_path: String,
+    pub lang: Option<String>,
+}
+
+impl FeedMeta {
+    pub fn new(
+        settings: &FeedSettings,
+        scope: &FeedScope,
+        base_url: &str,
+        feed_path: &str,
+        lang: Option<&str>,
+    ) -> Self {
+        let base_url = base_url.trim_end_matches('/').to_owned();
+        let site = settings.title.clone().unwrap_or_else(|| base_url.clone());
+        let title = match scope.label() {
+            Some(label) => format!("{site} – {label}"),
+            None => site,
+        };
+
+        Self {
+            title,
+            base_url,
+            feed_path: feed_path.to_owned(),
+            lang: lang.map(str::to_owned),
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+}
+
+/// The newest published documents in `scope` (and `lang`, when given),
+/// newest first, at most `settings.items` of them.
+#[tracing::instrument(skip_all)]
+pub async fn collect_items(
+    mgr: &impl ContentManager,
+    scope: &FeedScope,
+    lang: Option<&str>,
+    settings: &FeedSettings,
+) -> Result<Vec<FeedItem>, ResolverError> {
+    // Only the newest `settings.items` are kept, trimmed as documents
+    // stream in, so big sites are never held in memory at once.
+    let mut docs: Vec<(String, Json)> = Vec::new();
+    let trim_at = settings.items.saturating_mul(2).max(64);
+    for_each_front_matter(mgr, |served, fm| {
+        let listed = served.ends_with(".html")
+            && string(&fm, &["publish", "status"]).as_deref() == Some("publish")
+            && scope.matches(&fm)
+            && lang.is_none_or(|l| string(&fm, &["i18n", "lang"]).as_deref() == Some(l));
+        if listed {
+            docs.push((served, fm));
+            if docs.len() >= trim_at {
+                keep_newest(&mut docs, settings.items);
+            }
+        }
+    })
+    .await?;
+    keep_newest(&mut docs, settings.items);
+
+    let permalinks = mgr.permalinks();
+    let mut items = Vec::with_capacity(docs.len());
+    for (served, fm) in docs {
+        let body = mgr.lookup_body(&served).await?;
+        let (content_html, summary) = match (&body, settings.full_content) {
+            (Some(html), true) => (Some(html.to_string()), None),
+            (Some(html), false) => (None, Some(summarize(html, settings.summary_chars))),
+            (None, _) => (None, None),
+        };
+
+        let mut tags = strings(&fm, &["tax", "tags"]);
+        tags.extend(strings(&fm, &["tax", "categories"]));
+
+        items.push(FeedItem {
+            title: string(&fm, &["content", "title"]).unwrap_or_else(|| served.clone()),
+            published: date(&fm, "date"),
+            updated: date(&fm, "modified"),
+            author: string(&fm, &["author", "author"]),
+            tags,
+            content_html,
+            summary,
+            path: permalinks.for_document(&fm).unwrap_or(served),
+        });
+    }
+
+    Ok(items)
+}
+
+/// Render `items` as `format`.
+pub fn render(format: FeedFormat, meta: &FeedMeta, items: &[FeedItem]) -> String {
+    match format {
+        FeedFormat::Rss => rss(meta, items),
+        FeedFormat::Atom => atom(meta, items),
+        FeedFormat::Json => json_feed(meta, items),
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Formats
+// ─────────────────────────────────────────────────────────────────────────────
+
+fn rss(meta: &FeedMeta, items: &[FeedItem]) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str("<rss version=\"2.0\" xmlns:atom=\"http://www.w3.org/2005/Atom\">\n<channel>\n");
+    let _ = writeln!(out, "  <title>{}</title>", escape(&meta.title));
+    let _ = writeln!(out, "  <link>{}/</link>", escape(&meta.base_url));
+    let _ = writeln!(out, "  <description>{}</description>", escape(&meta.title));
+    let _ = writeln!(
+        out,
+        "  <atom:link href=\"{}\" rel=\"self\" type=\"application/rss+xml\"/>",
+        escape(&meta.url(&meta.feed_path))
+    );
+    if let Some(lang) = &meta.lang {
+        let _ = writeln!(out, "  <language>{}</language>", escape(lang));
+    }
+    if let Some(latest) = latest(items) {
+        let _ = writeln!(
+            out,
+            "  <lastBuildDate>{}</lastBuildDate>",
+            latest.to_rfc2822()
+        );
+    }
+
+    for item in items {
+        let url = escape(&meta.url(&item.path));
+        out.push_str("  <item>\n");
+        let _ = writeln!(out, "    <title>{}</title>", escape(&item.title));
+        let _ = writeln!(out, "    <link>{url}</link>");
+        let _ = writeln!(out, "    <guid isPermaLink=\"true\">{url}</guid>");
+        if let Some(published) = item.published {
+            let _ = writeln!(out, "    <pubDate>{}</pubDate>", published.to_rfc2822());
+        }
+        if let Some(author) = &item.author {
+            // RSS expects an e-mail address; front matter usually has a name.
+            let _ = writeln!(out, "    <author>{}</author>", escape(author));
+        }
+        for tag in &item.tags {
+            let _ = writeln!(out, "    <category>{}</category>", escape(tag));
+        }
+        if let Some(text) = item.content_html.as_ref().or(item.summary.as_ref()) {
+            let _ = writeln!(out, "    <description>{}</description>", escape(text));
+        }
+        out.push_str("  </item>\n");
+    }
+
+    out.push_str("</channel>\n</rss>\n");
+    out
+}
+
+fn atom(meta: &FeedMeta, items: &[FeedItem]) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    match &meta.lang {
+        Some(lang) => {
+            let _ = writeln!(
+                out,
+                "<feed xmlns=\"http://www.w3.org/2005/Atom\" xml:lang=\"{}\">",
+                escape(lang)
+            );
+        }
+        None => out.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n"),
+    }
+
+    let self_url = escape(&meta.url(&meta.feed_path));
+    let _ = writeln!(out, "  <title>{}</title>", escape(&meta.title));
+    let _ = writeln!(out, "  <id>{self_url}</id>");
+    let _ = writeln!(out, "  <link rel=\"self\" href=\"{self_url}\"/>");
+    let _ = writeln!(out, "  <link href=\"{}/\"/>", escape(&meta.base_url));
+    // <updated> is required; an empty feed falls back to the epoch.
+    let _ = writeln!(
+        out,
+        "  <updated>{}</updated>",
+        rfc3339(latest(items).unwrap_or_default())
+    );
+
+    for item in items {
+        let url = escape(&meta.url(&item.path));
+        out.push_str("  <entry>\n");
+        let _ = writeln!(out, "    <title>{}</title>", escape(&item.title));
+        let _ = writeln!(out, "    <id>{url}</id>");
+        let _ = writeln!(out, "    <link href=\"{url}\"/>");
+        let updated = item.updated.or(item.published).unwrap_or_default();
+        let _ = writeln!(out, "    <updated>{}</updated>", rfc3339(updated));
+        if let Some(published) = item.published {
+            let _ = writeln!(out, "    <published>{}</published>", rfc3339(published));
+        }
+        if let Some(author) = &item.author {
+            let _ = writeln!(out, "    <author><name>{}</name></author>", escape(author));
+        }
+        for tag in &item.tags {
+            let _ = writeln!(out, "    <category term=\"{}\"/>", escape(tag));
+        }
+        if let Some(html) = &item.content_html {
+            let _ = writeln!(out, "    <content type=\"html\">{}</content>", escape(html));
+        } else if let Some(summary) = &item.summary {
+            let _ = writeln!(out, "    <summary>{}</summary>", escape(summary));
+        }
+        out.push_str("  </entry>\n");
+    }
+
+    out.push_str("</feed>\n");
+    out
+}
+
+fn json_feed(meta: &FeedMeta, items: &[FeedItem]) -> String {
+    let items: Vec<Json> = items
+        .iter()
+        .map(|item| {
+            let url = meta.url(&item.path);
+            let mut obj = JsonMap::new();
+            obj.insert("id".into(), json!(url));
+            obj.insert("url".into(), json!(url));
+            obj.insert("title".into(), json!(item.title));
+            if let Some(html) = &item.content_html {
+                obj.insert("content_html".into(), json!(html));
+            }
+            if let Some(summary) = &item.summary {
+                obj.insert("summary".into(), json!(summary));
+                obj.insert("content_text".into(), json!(summary));
+            }
+            if let Some(published) = item.published {
+                obj.insert("date_published".into(), json!(rfc3339(published)));
+            }
+            if let Some(updated) = item.updated {
+                obj.insert("date_modified".into(), json!(rfc3339(updated)));
+            }
+            if let Some(author) = &item.author {
+                obj.insert("authors".into(), json!([{ "name": author }]));
+            }
+            if !item.tags.is_empty() {
+                obj.insert("tags".into(), json!(item.tags));
+            }
+            Json::Object(obj)
+        })
+        .collect();
+
+    let mut feed = json!({
+        "version": "https://jsonfeed.org/version/1.1",
+        "title": meta.title,
+        "home_page_url": format!("{}/", meta.base_url),
+        "feed_url": meta.url(&meta.feed_path),
+        "items": items,
+    });
+    if let Some(lang) = &meta.lang {
+        feed["language"] = json!(lang);
+    }
+
+    serde_json::to_string_pretty(&feed).unwrap_or_default()
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Helpers
+// ─────────────────────────────────────────────────────────────────────────────
+
+fn get<'a>(fm: &'a Json, path: &[&str]) -> Option<&'a Json> {
+    path.iter().try_fold(fm, |v, key| v.get(key))
+}
+
+fn string(fm: &Json, path: &[&str]) -> Option<String> {
+    get(fm, path).and_then(Json::as_str).map(str::to_owned)
+}
+
+/// A string or array-of-strings field as a list.
+fn strings(fm: &Json, path: &[&str]) -> Vec<String> {
+    match get(fm, path) {
+        Some(Json::Array(items)) => items
+            .iter()
+            .filter_map(Json::as_str)
+            .map(str::to_owned)
+            .collect(),
+        Some(Json::String(s)) => vec![s.clone()],
+        _ => Vec::new(),
+    }
+}
+
+/// `publish.<field>` as RFC 3339 or a bare `YYYY-MM-DD` (midnight UTC).
+fn date(fm: &Json, field: &str) -> Option<DateTime<Utc>> {
+    let raw = string(fm, &["publish", field])?;
+    let raw = raw.trim();
+    DateTime::parse_from_rfc3339(raw)
+        .map(|d| d.with_timezone(&Utc))
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(raw, "%Y-%m-%d")
+                .ok()
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+                .map(|d| d.and_utc())
+        })
+}
+
+/// Sort newest first and drop all but `n`.
+fn keep_newest(docs: &mut Vec<(String, Json)>, n: usize) {
+    docs.sort_by_cached_key(|(served, fm)| (std::cmp::Reverse(date(fm, "date")), served.clone()));
+    docs.truncate(n);
+}
+
+fn latest(items: &[FeedItem]) -> Option<DateTime<Utc>> {
+    items.iter().filter_map(|i| i.updated.or(i.published)).max()
+}
+
+fn rfc3339(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Plain text of `html`, whitespace collapsed, cut at a word boundary to at
+/// most `max_chars` characters (plus an ellipsis).
+fn summarize(html: &str, max_chars: usize) -> String {
+    let mut text = String::with_capacity(html.len().min(max_chars * 2));
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => {
+                in_tag = true;
+                text.push(' ');
+            }
+            '>' => in_tag = false,
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    let text = html_escape::decode_html_entities(&text);
+    let words: Vec<&str> = text.split_whitespace().collect();
+
+    let mut out = String::new();
+    for word in words {
+        let needed = word.chars().count() + usize::from(!out.is_empty());
+        if out.chars().count() + needed > max_chars {
+            out.push('…');
+            return out;
+        }
+        if !out.is_empty() {
+            out.push(' ');
+        }
+        out.push_str(word);
+    }
+    out
+}
+
+fn escape(s: &str) -> String {
+    html_escape::encode_quoted_attribute(s).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn item(path: &str, title: &str, published: &str) -> FeedItem {
+        FeedItem {
+            path: path.into(),
+            title: title.into(),
+            published: DateTime::parse_from_rfc3339(published)
+                .ok()
+                .map(|d| d.with_timezone(&Utc)),
+            updated: None,
+            author: Some("Ada".into()),
+            tags: vec!["rust".into()],
+            content_html: None,
+            summary: Some("Hello & welcome".into()),
+        }
+    }
+
+    fn meta() -> FeedMeta {
+        FeedMeta::new(
+            &FeedSettings::default(),
+            &FeedScope::Tag("rust".into()),
+            "https://example.com/",
+            "/tags/rust/feed.xml",
+            Some("en"),
+        )
+    }
+
+    #[test]
+    fn scopes_match_tags_categories_and_sections() {
+        let fm = json!({
+            "content": { "section": "blog" },
+            "tax": { "tags": ["rust", "web"], "categories": "news" },
+        });
+        assert!(FeedScope::All.matches(&fm));
+        assert!(FeedScope::Tag("web".into()).matches(&fm));
+        assert!(!FeedScope::Tag("go".into()).matches(&fm));
+        assert!(FeedScope::Category("news".into()).matches(&fm));
+        assert!(FeedScope::Section("blog".into()).matches(&fm));
+        assert!(!FeedScope::Section("docs".into()).matches(&fm));
+
+        let fm = json!({ "author": { "author": "ada", "co_authors": ["grace"] } });
+        assert!(FeedScope::Author("ada".into()).matches(&fm));
+        assert!(FeedScope::Author("grace".into()).matches(&fm));
+        assert!(!FeedScope::Author("alan".into()).matches(&fm));
+    }
+
+    #[test]
+    fn each_extension_renders_its_format() {
+        let items = vec![item("/a.html", "A <1>", "2024-02-01T00:00:00Z")];
+        let m = meta();
+        assert_eq!(m.title, "https://example.com – tag: rust");
+
+        let rss = render(FeedFormat::from_extension("xml").unwrap(), &m, &items);
+        assert!(rss.contains("<rss version=\"2.0\""));
+        assert!(rss.contains("<title>A &lt;1&gt;</title>"));
+        assert!(rss.contains("<pubDate>Thu, 1 Feb 2024 00:00:00 +0000</pubDate>"));
+        assert!(rss.contains("<language>en</language>"));
+        assert!(rss.contains("<description>Hello &amp; welcome</description>"));
+
+        let atom = render(FeedFormat::from_extension("atom").unwrap(), &m, &items);
+        assert!(atom.contains("xml:lang=\"en\""));
+        assert!(atom.contains("<id>https://example.com/a.html</id>"));
+        assert!(atom.contains("<updated>2024-02-01T00:00:00Z</updated>"));
+        assert!(atom.contains("<category term=\"rust\"/>"));
+
+        let feed: Json = serde_json::from_str(&render(FeedFormat::Json, &m, &items)).unwrap();
+        assert_eq!(feed["version"], "https://jsonfeed.org/version/1.1");
+        assert_eq!(feed["feed_url"], "https://example.com/tags/rust/feed.xml");
+        assert_eq!(feed["items"][0]["url"], "https://example.com/a.html");
+        assert_eq!(feed["items"][0]["summary"], "Hello & welcome");
+
+        assert_eq!(FeedFormat::from_extension("html"), None);
+    }
+
+    #[test]
+    fn summaries_are_plain_text_cut_at_words() {
+        let html = "<h1>Title</h1>\n<p>One &amp; two <em>three</em> four</p>";
+        assert_eq!(summarize(html, 100), "Title One & two three four");
+        assert_eq!(summarize(html, 15), "Title One & two…");
+    }
+}
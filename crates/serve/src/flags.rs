@@ -0,0 +1,285 @@
+// crates/serve/src/flags.rs
+
+// Feature flags, decided once per request. The site and its plugins define
+// them in TOML:
+//
+//   # settings.toml (or plugin.toml)
+//   [flags.new-nav]
+//   percentage = 25           # a quarter of visitors
+//   cookies = ["beta"]        # and anyone with a `beta` cookie
+//   roles = ["admin"]         # and signed-in admins
+//
+//   [flags.dark-mode]         # on for everyone
+//
+//   [flags.comments]
+//   enabled = false           # off for everyone
+//
+// A site flag replaces a plugin flag of the same name; between plugins the
+// first to define a name keeps it. `FlagSet::evaluate` turns the request's
+// `FlagSubject` into one `FlagDecision` per flag, with the reason, so the
+// host can log why a flag was on or off. The answers go on the
+// `RequestContext` (`ctx.flags` in JS) and into templates:
+//
+//   {{#if (flag "new-nav")}} … {{/if}}
+//
+// Percentage buckets hash the flag name with the visitor, so a visitor sees
+// the same answer on every request, and different flags split visitors
+// independently. Without a visitor a percentage never matches.
+
+use domain::setting::FlagSettings;
+use handlebars::{
+    Context, Handlebars, Helper, HelperDef, RenderContext, RenderError as HbsError, ScopedJson,
+};
+use serde::Serialize;
+use serde_json::Value as Json;
+use std::collections::{BTreeMap, HashMap};
+
+/// Who a request is from, as far as targeting cares.
+#[derive(Debug, Clone, Default)]
+pub struct FlagSubject {
+    /// Stable per visitor (a session, else the client address).
+    pub visitor: Option<String>,
+    pub cookies: HashMap<String, String>,
+    /// Role of the signed-in user.
+    pub role: Option<String>,
+}
+
+/// Where a flag was defined.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FlagSource {
+    Site,
+    Plugin(String),
+}
+
+/// One flag's answer for one request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FlagDecision {
+    pub flag: String,
+    pub on: bool,
+    pub reason: String,
+    pub source: FlagSource,
+}
+
+/// Every defined flag.
+#[derive(Debug, Clone, Default)]
+pub struct FlagSet {
+    flags: BTreeMap<String, (FlagSettings, FlagSource)>,
+}
+
+impl FlagSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Site flags, replacing any of the same name.
+    pub fn with_site(mut self, flags: &BTreeMap<String, FlagSettings>) -> Self {
+        for (name, def) in flags {
+            self.flags
+                .insert(name.clone(), (def.clone(), FlagSource::Site));
+        }
+        self
+    }
+
+    /// A plugin's flags, skipping names already defined.
+    pub fn with_plugin(mut self, plugin: &str, flags: &BTreeMap<String, FlagSettings>) -> Self {
+        for (name, def) in flags {
+            self.flags
+                .entry(name.clone())
+                .or_insert_with(|| (def.clone(), FlagSource::Plugin(plugin.to_owned())));
+        }
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.flags.is_empty()
+    }
+
+    /// Whether any flag targets roles, i.e. evaluating needs the user.
+    pub fn uses_roles(&self) -> bool {
+        self.flags.values().any(|(def, _)| !def.roles.is_empty())
+    }
+
+    /// Decide every flag for `subject`, in name order.
+    pub fn evaluate(&self, subject: &FlagSubject) -> Vec<FlagDecision> {
+        self.flags
+            .iter()
+            .map(|(name, (def, source))| {
+                let (on, reason) = decide(name, def, subject);
+                FlagDecision {
+                    flag: name.clone(),
+                    on,
+                    reason,
+                    source: source.clone(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Flag name → on, as stored on the `RequestContext`.
+pub fn flag_map(decisions: &[FlagDecision]) -> BTreeMap<String, bool> {
+    decisions.iter().map(|d| (d.flag.clone(), d.on)).collect()
+}
+
+fn decide(name: &str, def: &FlagSettings, subject: &FlagSubject) -> (bool, String) {
+    if !def.enabled {
+        return (false, "disabled".into());
+    }
+    if def.percentage.is_none() && def.cookies.is_empty() && def.roles.is_empty() {
+        return (true, "on for everyone".into());
+    }
+
+    if let Some(role) = &subject.role {
+        if def.roles.iter().any(|r| r.eq_ignore_ascii_case(role)) {
+            return (true, format!("role {role}"));
+        }
+    }
+    for rule in &def.cookies {
+        let matched = match rule.split_once('=') {
+            Some((cookie, value)) => subject.cookies.get(cookie).is_some_and(|v| v == value),
+            None => subject.cookies.contains_key(rule),
+        };
+        if matched {
+            return (true, format!("cookie {rule}"));
+        }
+    }
+    if let (Some(pct), Some(visitor)) = (def.percentage, &subject.visitor) {
+        let bucket = bucket(name, visitor);
+        if bucket < u32::from(pct.min(100)) {
+            return (true, format!("bucket {bucket} < {pct}%"));
+        }
+        return (false, format!("bucket {bucket} >= {pct}%"));
+    }
+    (false, "no rule matched".into())
+}
+
+/// 0-99, stable for a flag and visitor (FNV-1a).
+fn bucket(flag: &str, visitor: &str) -> u32 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in flag.bytes().chain([0]).chain(visitor.bytes()) {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    (hash % 100) as u32
+}
+
+/// `{{#if (flag "name")}}`: whether the flag is on for this request; an
+/// unknown flag is off.
+#[derive(Clone, Debug, Default)]
+pub struct FlagHelper {
+    flags: BTreeMap<String, bool>,
+}
+
+impl FlagHelper {
+    pub fn new(flags: BTreeMap<String, bool>) -> Self {
+        Self { flags }
+    }
+}
+
+impl HelperDef for FlagHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, HbsError> {
+        let on = h
+            .param(0)
+            .and_then(|p| p.value().as_str())
+            .and_then(|name| self.flags.get(name))
+            .copied()
+            .unwrap_or(false);
+        Ok(ScopedJson::Derived(Json::Bool(on)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn def(toml: &str) -> FlagSettings {
+        toml::from_str(toml).unwrap()
+    }
+
+    fn subject(visitor: &str) -> FlagSubject {
+        FlagSubject {
+            visitor: Some(visitor.to_owned()),
+            ..FlagSubject::default()
+        }
+    }
+
+    #[test]
+    fn site_flags_win_and_targeting_is_any_of() {
+        let site = BTreeMap::from([
+            (
+                "beta".to_owned(),
+                def("cookies = [\"beta=1\"]\nroles = [\"admin\"]"),
+            ),
+            ("comments".to_owned(), def("enabled = false")),
+        ]);
+        let plugin = BTreeMap::from([
+            ("comments".to_owned(), def("")),
+            ("search".to_owned(), def("")),
+        ]);
+        let flags = FlagSet::new().with_plugin("seo", &plugin).with_site(&site);
+        assert!(flags.uses_roles());
+
+        let anon = flags.evaluate(&FlagSubject::default());
+        assert_eq!(
+            flag_map(&anon),
+            BTreeMap::from([
+                ("beta".to_owned(), false),
+                ("comments".to_owned(), false),
+                ("search".to_owned(), true),
+            ])
+        );
+        assert_eq!(anon[1].reason, "disabled");
+        assert_eq!(anon[2].source, FlagSource::Plugin("seo".into()));
+
+        let mut tester = FlagSubject::default();
+        tester.cookies.insert("beta".into(), "1".into());
+        assert!(flags.evaluate(&tester)[0].on);
+        tester.cookies.insert("beta".into(), "0".into());
+        assert!(!flags.evaluate(&tester)[0].on);
+
+        let admin = FlagSubject {
+            role: Some("admin".into()),
+            ..FlagSubject::default()
+        };
+        assert_eq!(flags.evaluate(&admin)[0].reason, "role admin");
+    }
+
+    #[test]
+    fn percentages_are_sticky_per_visitor() {
+        let flags = FlagSet::new().with_site(&BTreeMap::from([(
+            "new-nav".to_owned(),
+            def("percentage = 30"),
+        )]));
+
+        let on = (0..1000)
+            .filter(|i| flags.evaluate(&subject(&format!("visitor-{i}")))[0].on)
+            .count();
+        assert!((200..400).contains(&on), "{on}");
+
+        let first = flags.evaluate(&subject("visitor-7"));
+        assert_eq!(flags.evaluate(&subject("visitor-7")), first);
+        assert!(!flags.evaluate(&FlagSubject::default())[0].on);
+    }
+
+    #[test]
+    fn templates_read_flags_through_the_helper() {
+        let mut hbs = Handlebars::new();
+        hbs.register_helper(
+            "flag",
+            Box::new(FlagHelper::new(BTreeMap::from([(
+                "new-nav".to_owned(),
+                true,
+            )]))),
+        );
+        let tpl = r#"{{#if (flag "new-nav")}}new{{else}}old{{/if}} {{#if (flag "x")}}x{{/if}}"#;
+        assert_eq!(hbs.render_template(tpl, &json!({})).unwrap(), "new ");
+    }
+}
@@ -0,0 +1,290 @@
+// crates/serve/src/forms.rs
+
+// Site forms (`[forms.<name>]` in the settings) as themes see them, and the
+// checks a submission must pass. The `form` helper gives a template what it
+// needs to lay a form out:
+//
+//   {{#with (form "contact")}}
+//   <form method="post" action="{{action}}">
+//     {{#each fields}}
+//       <label>{{label}} <input name="{{name}}" type="{{kind}}"
+//         {{#if required}}required{{/if}}></label>
+//     {{/each}}
+//     <input name="{{honeypot}}" tabindex="-1" autocomplete="off" hidden>
+//     <input type="hidden" name="_csrf" value="{{csrf_token}}">
+//     <button>Send</button>
+//   </form>
+//   {{/with}}
+//
+// The honeypot input must stay hidden: people leave it empty, bots fill it
+// in. An unknown form name is null, so `{{#with}}` renders nothing.
+// `csrf_token` is the logged-in visitor's CSRF token (edge::csrf), and
+// empty for everyone else.
+
+use domain::setting::{FormDefinition, FormFieldKind};
+use handlebars::{
+    Context, Handlebars, Helper, HelperDef, RenderContext, RenderError as HbsError, ScopedJson,
+};
+use serde_json::{json, Map as JsonMap, Value as Json};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// The input every form hides from people; a submission that fills it in
+/// is dropped.
+pub const HONEYPOT_FIELD: &str = "_website";
+
+/// Where form `name` is posted.
+pub fn action(name: &str) -> String {
+    format!("/forms/{name}")
+}
+
+/// What the `form` helper gives templates for form `name`.
+pub fn describe(name: &str, definition: &FormDefinition) -> Json {
+    let fields: Vec<Json> = definition
+        .fields
+        .iter()
+        .map(|field| {
+            let mut described = json!(field);
+            described["label"] = json!(field.label.as_deref().unwrap_or(&field.name));
+            described
+        })
+        .collect();
+    json!({
+        "name": name,
+        "action": action(name),
+        "honeypot": HONEYPOT_FIELD,
+        "fields": fields,
+    })
+}
+
+/// Check `input` against `definition`: the declared fields, trimmed and
+/// typed (numbers as numbers, checkboxes as booleans), or a message for
+/// each field that failed. Fields the form does not declare are dropped.
+pub fn validate(
+    definition: &FormDefinition,
+    input: &JsonMap<String, Json>,
+) -> Result<JsonMap<String, Json>, BTreeMap<String, String>> {
+    let mut values = JsonMap::new();
+    let mut errors = BTreeMap::new();
+    for field in &definition.fields {
+        let raw = match input.get(&field.name) {
+            Some(Json::String(s)) => s.trim().to_owned(),
+            Some(Json::Null) | None => String::new(),
+            Some(other) => other.to_string(),
+        };
+        if field.kind == FormFieldKind::Checkbox {
+            let checked = !matches!(raw.as_str(), "" | "false" | "off" | "0");
+            if field.required && !checked {
+                errors.insert(field.name.clone(), "must be checked".to_owned());
+            }
+            values.insert(field.name.clone(), Json::Bool(checked));
+            continue;
+        }
+        if raw.is_empty() {
+            if field.required {
+                errors.insert(field.name.clone(), "is required".to_owned());
+            }
+            continue;
+        }
+        if let Some(max) = field.max_chars {
+            if raw.chars().count() > max {
+                errors.insert(
+                    field.name.clone(),
+                    format!("is longer than {max} characters"),
+                );
+                continue;
+            }
+        }
+        let value = match field.kind {
+            FormFieldKind::Email if !looks_like_email(&raw) => Err("is not an email address"),
+            FormFieldKind::Url if !looks_like_url(&raw) => Err("is not an http(s) URL"),
+            FormFieldKind::Select if !field.options.contains(&raw) => {
+                Err("is not one of the options")
+            }
+            FormFieldKind::Number => match raw.parse::<f64>() {
+                Ok(n) if n.is_finite() => Ok(json!(n)),
+                _ => Err("is not a number"),
+            },
+            _ => Ok(Json::String(raw)),
+        };
+        match value {
+            Ok(value) => {
+                values.insert(field.name.clone(), value);
+            }
+            Err(message) => {
+                errors.insert(field.name.clone(), message.to_owned());
+            }
+        }
+    }
+    if errors.is_empty() {
+        Ok(values)
+    } else {
+        Err(errors)
+    }
+}
+
+fn looks_like_email(s: &str) -> bool {
+    match s.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && domain.contains('.')
+                && !domain.starts_with('.')
+                && !domain.ends_with('.')
+                && !s.contains(char::is_whitespace)
+                && !domain.contains('@')
+        }
+        None => false,
+    }
+}
+
+fn looks_like_url(s: &str) -> bool {
+    (s.starts_with("https://") || s.starts_with("http://"))
+        && !s.contains(char::is_whitespace)
+        && s.split("://").nth(1).is_some_and(|rest| !rest.is_empty())
+}
+
+/// `{{#with (form "contact")}}`: a form's action, honeypot and fields.
+#[derive(Clone, Debug, Default)]
+pub struct FormHelper {
+    forms: Arc<BTreeMap<String, Json>>,
+}
+
+impl FormHelper {
+    /// `forms` by name, as made by [`describe`].
+    pub fn new(forms: Arc<BTreeMap<String, Json>>) -> Self {
+        Self { forms }
+    }
+}
+
+impl HelperDef for FormHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, HbsError> {
+        let form = h
+            .param(0)
+            .and_then(|p| p.value().as_str())
+            .and_then(|name| self.forms.get(name))
+            .cloned()
+            .unwrap_or(Json::Null);
+        Ok(ScopedJson::Derived(form))
+    }
+}
+
+/// `{{csrf_token}}`: the token for the request's session, or "".
+#[derive(Clone, Debug, Default)]
+pub struct CsrfHelper {
+    token: Option<String>,
+}
+
+impl CsrfHelper {
+    pub fn new(token: Option<String>) -> Self {
+        Self { token }
+    }
+}
+
+impl HelperDef for CsrfHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        _: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, HbsError> {
+        Ok(ScopedJson::Derived(Json::String(
+            self.token.clone().unwrap_or_default(),
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contact() -> FormDefinition {
+        toml::from_str(
+            r#"
+            [[fields]]
+            name = "email"
+            kind = "email"
+            required = true
+
+            [[fields]]
+            name = "topic"
+            kind = "select"
+            options = ["sales", "support"]
+
+            [[fields]]
+            name = "message"
+            label = "Your message"
+            kind = "textarea"
+            required = true
+            max_chars = 10
+
+            [[fields]]
+            name = "copies"
+            kind = "number"
+
+            [[fields]]
+            name = "consent"
+            kind = "checkbox"
+            required = true
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn submissions_are_checked_against_the_definition() {
+        let input = json!({
+            "email": " ada@example.com ",
+            "topic": "sales",
+            "message": "Hello",
+            "copies": "2",
+            "consent": "on",
+            "extra": "dropped",
+        });
+        let values = validate(&contact(), input.as_object().unwrap()).unwrap();
+        assert_eq!(
+            Json::Object(values),
+            json!({
+                "email": "ada@example.com",
+                "topic": "sales",
+                "message": "Hello",
+                "copies": 2.0,
+                "consent": true,
+            })
+        );
+
+        let input = json!({
+            "email": "ada@",
+            "topic": "other",
+            "message": "far too long",
+            "copies": "many",
+        });
+        let errors = validate(&contact(), input.as_object().unwrap()).unwrap_err();
+        assert_eq!(
+            errors.keys().collect::<Vec<_>>(),
+            ["consent", "copies", "email", "message", "topic"]
+        );
+    }
+
+    #[test]
+    fn templates_read_forms_through_the_helper() {
+        let forms = Arc::new(BTreeMap::from([(
+            "contact".to_owned(),
+            describe("contact", &contact()),
+        )]));
+        let mut hbs = Handlebars::new();
+        hbs.register_helper("form", Box::new(FormHelper::new(forms)));
+        hbs.register_helper("csrf_token", Box::new(CsrfHelper::new(Some("t0k".into()))));
+        let tpl = r#"{{#with (form "contact")}}{{action}} {{honeypot}}{{#each fields}} {{name}}:{{label}}{{/each}}{{/with}}{{#with (form "x")}}x{{/with}} {{csrf_token}}"#;
+        assert_eq!(
+            hbs.render_template(tpl, &json!({})).unwrap(),
+            "/forms/contact _website email:email topic:topic message:Your message copies:copies consent:consent t0k"
+        );
+    }
+}
@@ -0,0 +1,436 @@
+// crates/serve/src/front_matter.rs
+
+//! Front-matter detection, parsing and field checks.
+//!
+//! The format is picked from the opening delimiter:
+//!
+//! | Opens with | Format | Closes with      |
+//! |------------|--------|------------------|
+//! | `---`      | YAML   | `---` or `...`   |
+//! | `+++`      | TOML   | `+++`            |
+//! | `{`        | JSON   | the matching `}` |
+//!
+//! All three parse into the same JSON shape, so the index projection does not
+//! care which one a file used. Errors carry the file, the 1-based line in that
+//! file and, for field checks, the dotted field path.
+
+use domain::doc::FmKind;
+use serde_json::{Map, Value as Json};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Front matter split off a source file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrontMatter {
+    pub kind: FmKind,
+    /// Always a JSON object.
+    pub data: Json,
+    pub body: String,
+}
+
+/// A front-matter problem, located as precisely as the parser allows.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub struct FrontMatterError {
+    pub file: PathBuf,
+    /// 1-based line in `file`.
+    pub line: Option<usize>,
+    /// Dotted path of the offending field, e.g. `nav.menu_order`.
+    pub field: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for FrontMatterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.file.display())?;
+        if let Some(line) = self.line {
+            write!(f, ":{line}")?;
+        }
+        if let Some(field) = &self.field {
+            write!(f, ": field '{field}'")?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl FrontMatterError {
+    fn new(file: &Path, line: Option<usize>, message: impl Into<String>) -> Self {
+        Self {
+            file: file.to_owned(),
+            line,
+            field: None,
+            message: message.into(),
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Parsing
+// ---------------------------------------------------------------------------
+
+/// The fenced block of a YAML or TOML header.
+struct Fenced<'a> {
+    src: &'a str,
+    /// Line of `src`'s first line in the file.
+    first_line: usize,
+    body: &'a str,
+}
+
+/// Split `text` at the closing `fences` line. `None` if it never closes.
+fn fenced<'a>(text: &'a str, fences: &[&str]) -> Option<Fenced<'a>> {
+    let after_open = text.split_once('\n').map_or("", |(_, rest)| rest);
+
+    let mut offset = 0;
+    for line in after_open.split_inclusive('\n') {
+        if fences.contains(&line.trim_end()) {
+            let body = &after_open[offset + line.len()..];
+            return Some(Fenced {
+                src: &after_open[..offset],
+                first_line: 2,
+                body: body.trim_start_matches(['\r', '\n']),
+            });
+        }
+        offset += line.len();
+    }
+    None
+}
+
+fn line_of(src: &str, byte: usize) -> usize {
+    src[..byte.min(src.len())].matches('\n').count() + 1
+}
+
+/// Detect and parse the front matter at the top of `text`.
+///
+/// Returns `Ok(None)` when the file has none. `file` only labels errors.
+pub fn parse(file: &Path, text: &str) -> Result<Option<FrontMatter>, FrontMatterError> {
+    let text = text.trim_start_matches('\u{feff}');
+    let opening = text.lines().next().unwrap_or("").trim_end();
+
+    let (kind, data, body) = if opening == "---" {
+        let block = fenced(text, &["---", "..."]).ok_or_else(|| {
+            FrontMatterError::new(file, Some(1), "unterminated YAML front matter")
+        })?;
+        let data = serde_yml::from_str::<Json>(block.src).map_err(|e| {
+            let line = e.location().map(|l| block.first_line + l.line() - 1);
+            let msg = e.to_string();
+            let msg = msg.split(" at line ").next().unwrap_or(&msg);
+            FrontMatterError::new(file, line, format!("invalid YAML: {msg}"))
+        })?;
+        (FmKind::Yaml, data, block.body)
+    } else if opening == "+++" {
+        let block = fenced(text, &["+++"]).ok_or_else(|| {
+            FrontMatterError::new(file, Some(1), "unterminated TOML front matter")
+        })?;
+        let data = toml::from_str::<toml::Value>(block.src).map_err(|e| {
+            let line = e
+                .span()
+                .map(|span| block.first_line + line_of(block.src, span.start) - 1);
+            FrontMatterError::new(file, line, format!("invalid TOML: {}", e.message()))
+        })?;
+        (FmKind::Toml, toml_to_json(data), block.body)
+    } else if text.starts_with('{') {
+        let mut stream = serde_json::Deserializer::from_str(text).into_iter::<Json>();
+        let data = match stream.next() {
+            Some(Ok(data)) => data,
+            Some(Err(e)) => {
+                return Err(FrontMatterError::new(
+                    file,
+                    Some(e.line()),
+                    format!("invalid JSON: {e}"),
+                ))
+            }
+            None => return Ok(None),
+        };
+        let body = &text[stream.byte_offset()..];
+        (FmKind::Json, data, body.trim_start_matches(['\r', '\n']))
+    } else {
+        return Ok(None);
+    };
+
+    let data = match data {
+        Json::Null => Json::Object(Map::new()),
+        Json::Object(_) => data,
+        _ => {
+            return Err(FrontMatterError::new(
+                file,
+                Some(1),
+                "front matter must be a table of fields",
+            ))
+        }
+    };
+
+    Ok(Some(FrontMatter {
+        kind,
+        data,
+        body: body.to_owned(),
+    }))
+}
+
+/// TOML values as JSON; datetimes become their RFC 3339 strings, the same
+/// shape a quoted date has in YAML or JSON.
+fn toml_to_json(value: toml::Value) -> Json {
+    match value {
+        toml::Value::String(s) => Json::String(s),
+        toml::Value::Integer(i) => Json::from(i),
+        toml::Value::Float(f) => Json::from(f),
+        toml::Value::Boolean(b) => Json::Bool(b),
+        toml::Value::Datetime(dt) => Json::String(dt.to_string()),
+        toml::Value::Array(items) => Json::Array(items.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(table) => Json::Object(
+            table
+                .into_iter()
+                .map(|(k, v)| (k, toml_to_json(v)))
+                .collect(),
+        ),
+    }
+}
+
+/// `body` under `+++` TOML front matter holding `data`, the layout
+/// importers and the admin API write. Nulls, which TOML lacks, are dropped.
+pub fn to_toml_file(data: &Json, body: &str) -> Result<String, toml::ser::Error> {
+    let data = json_to_toml(data).unwrap_or_else(|| toml::Value::Table(Default::default()));
+    let fm = toml::to_string(&data)?;
+    Ok(format!("+++\n{fm}+++\n\n{}\n", body.trim_end_matches('\n')))
+}
+
+/// `None` for a null, which is left out of the table or array holding it.
+fn json_to_toml(v: &Json) -> Option<toml::Value> {
+    Some(match v {
+        Json::Null => return None,
+        Json::Bool(b) => toml::Value::Boolean(*b),
+        Json::Number(n) => match n.as_i64() {
+            Some(i) => toml::Value::Integer(i),
+            None => toml::Value::Float(n.as_f64().unwrap_or_default()),
+        },
+        Json::String(s) => toml::Value::String(s.clone()),
+        Json::Array(a) => toml::Value::Array(a.iter().filter_map(json_to_toml).collect()),
+        Json::Object(o) => toml::Value::Table(
+            o.iter()
+                .filter_map(|(k, v)| Some((k.clone(), json_to_toml(v)?)))
+                .collect(),
+        ),
+    })
+}
+
+// ---------------------------------------------------------------------------
+// Field checks
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy)]
+enum Expect {
+    Str,
+    Int,
+    Bool,
+    /// A string or a list of strings.
+    StrList,
+}
+
+/// Fields the index projection reads, and the types it understands. A value
+/// of another type is silently dropped from the index, so it is worth a lint.
+const INDEXED_FIELDS: &[(&str, Expect)] = &[
+    ("type", Expect::Str),
+    ("kind", Expect::Str),
+    ("slug", Expect::Str),
+    ("parent", Expect::Str),
+    ("unpublish_at", Expect::Str),
+    ("content.title", Expect::Str),
+    ("content.section", Expect::Str),
+    ("content.summary", Expect::Str),
+    ("publish.status", Expect::Str),
+    ("publish.date", Expect::Str),
+    ("publish.modified", Expect::Str),
+    ("publish.unpublish_at", Expect::Str),
+    ("nav.menu_order", Expect::Int),
+    ("nav.menu_visible", Expect::Bool),
+    ("tax.categories", Expect::StrList),
+    ("tax.tags", Expect::StrList),
+    ("tax.series", Expect::StrList),
+    ("i18n.lang", Expect::Str),
+    ("i18n.canonical_id", Expect::Str),
+    ("author.author", Expect::Str),
+    ("author.co_authors", Expect::StrList),
+];
+
+fn lookup<'a>(data: &'a Json, path: &str) -> Option<&'a Json> {
+    path.split('.').try_fold(data, |v, key| v.get(key))
+}
+
+fn mismatch(value: &Json, expect: Expect) -> Option<&'static str> {
+    let ok = match expect {
+        Expect::Str => value.is_string(),
+        Expect::Int => value.is_i64() || value.is_u64(),
+        Expect::Bool => value.is_boolean(),
+        Expect::StrList => {
+            value.is_string()
+                || value
+                    .as_array()
+                    .is_some_and(|a| a.iter().all(Json::is_string))
+        }
+    };
+    if ok {
+        return None;
+    }
+    Some(match expect {
+        Expect::Str => "expected a string",
+        Expect::Int => "expected an integer",
+        Expect::Bool => "expected true or false",
+        Expect::StrList => "expected a string or a list of strings",
+    })
+}
+
+/// Best-effort line of `path` in the raw header: each segment is looked for
+/// as a YAML/JSON key or a TOML key/table below the previous one's line.
+fn locate_field(text: &str, path: &str) -> Option<usize> {
+    let lines: Vec<&str> = text.lines().collect();
+    let is_key = |line: &str, key: &str| {
+        let l = line.trim();
+        if l.starts_with('[') {
+            // TOML table header
+            let name = l.trim_matches(['[', ']']);
+            return name == key || name.ends_with(&format!(".{key}"));
+        }
+        l.trim_start_matches('"')
+            .strip_prefix(key)
+            .is_some_and(|rest| {
+                let rest = rest.trim_start_matches('"').trim_start();
+                rest.starts_with(':') || rest.starts_with('=')
+            })
+    };
+
+    if let Some(i) = lines.iter().position(|l| is_key(l, path)) {
+        return Some(i + 1); // TOML dotted key, e.g. `nav.menu_order = 1`
+    }
+
+    let mut from = 0;
+    for key in path.split('.') {
+        from += lines[from..].iter().position(|l| is_key(l, key))?;
+        from += 1;
+    }
+    Some(from)
+}
+
+/// Type-check the fields the index reads. `text` is the file the front
+/// matter came from, used to find each field's line.
+pub fn check_fields(file: &Path, text: &str, fm: &FrontMatter) -> Vec<FrontMatterError> {
+    let text = text.trim_start_matches('\u{feff}');
+    INDEXED_FIELDS
+        .iter()
+        .filter_map(|(path, expect)| {
+            let message = mismatch(lookup(&fm.data, path)?, *expect)?;
+            Some(FrontMatterError {
+                file: file.to_owned(),
+                line: locate_field(text, path),
+                field: Some((*path).to_owned()),
+                message: message.to_owned(),
+            })
+        })
+        .collect()
+}
+
+/// Every problem with `text`'s front matter: the parse error, or the field
+/// checks when it parsed. Empty for files without front matter.
+pub fn lint(file: &Path, text: &str) -> Vec<FrontMatterError> {
+    match parse(file, text) {
+        Ok(Some(fm)) => check_fields(file, text, &fm),
+        Ok(None) => Vec::new(),
+        Err(e) => vec![e],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn file() -> &'static Path {
+        Path::new("posts/a.md")
+    }
+
+    #[test]
+    fn all_three_formats_parse_to_the_same_data() {
+        let yaml = "---\ncontent:\n  title: Hi\ntax:\n  tags: [a, b]\n---\n\nBody\n";
+        let toml = "+++\n[content]\ntitle = \"Hi\"\n[tax]\ntags = [\"a\", \"b\"]\n+++\nBody\n";
+        let json =
+            "{\"content\": {\"title\": \"Hi\"}, \"tax\": {\"tags\": [\"a\", \"b\"]}}\nBody\n";
+
+        let expected = json!({ "content": { "title": "Hi" }, "tax": { "tags": ["a", "b"] } });
+        for (src, kind) in [
+            (yaml, FmKind::Yaml),
+            (toml, FmKind::Toml),
+            (json, FmKind::Json),
+        ] {
+            let fm = parse(file(), src).unwrap().unwrap();
+            assert_eq!(fm.kind, kind);
+            assert_eq!(fm.data, expected);
+            assert_eq!(fm.body, "Body\n");
+        }
+
+        assert_eq!(parse(file(), "# Just markdown\n").unwrap(), None);
+    }
+
+    #[test]
+    fn syntax_errors_point_at_the_file_line() {
+        let err = parse(file(), "+++\ntitle = \"ok\"\ndate = = 1\n+++\n").unwrap_err();
+        assert_eq!(err.line, Some(3));
+        assert!(
+            err.to_string().starts_with("posts/a.md:3: invalid TOML"),
+            "{err}"
+        );
+
+        let err = parse(file(), "---\ntitle: ok\ntags: [a\n---\n").unwrap_err();
+        assert!(err.message.starts_with("invalid YAML"), "{err}");
+        assert!(err.line.is_some_and(|l| l >= 3), "{err:?}");
+
+        let err = parse(file(), "{\n  \"title\": \"ok\",\n  \"tags\": ]\n}\n").unwrap_err();
+        assert_eq!(err.line, Some(3));
+
+        let err = parse(file(), "---\ntitle: never closed\n").unwrap_err();
+        assert_eq!(err.line, Some(1));
+    }
+
+    #[test]
+    fn field_checks_name_the_field_and_its_line() {
+        let src = "+++\nslug = \"a\"\n\n[nav]\nmenu_order = \"first\"\n\n[tax]\ntags = [\"ok\", 3]\n+++\n";
+        let fm = parse(file(), src).unwrap().unwrap();
+
+        let errs = check_fields(file(), src, &fm);
+        let found: Vec<_> = errs
+            .iter()
+            .map(|e| (e.field.as_deref().unwrap(), e.line))
+            .collect();
+        assert_eq!(
+            found,
+            vec![("nav.menu_order", Some(5)), ("tax.tags", Some(8))]
+        );
+        assert_eq!(
+            errs[0].to_string(),
+            "posts/a.md:5: field 'nav.menu_order': expected an integer"
+        );
+    }
+
+    #[test]
+    fn toml_dates_become_strings() {
+        let fm = parse(file(), "+++\n[publish]\ndate = 2024-05-01T10:00:00Z\n+++\n")
+            .unwrap()
+            .unwrap();
+        assert_eq!(fm.data["publish"]["date"], json!("2024-05-01T10:00:00Z"));
+        assert!(check_fields(file(), "", &fm).is_empty());
+    }
+
+    #[test]
+    fn nulls_are_left_out_of_written_toml() {
+        let data = json!({
+            "slug": null,
+            "tax": { "tags": ["a", null, "b"], "series": null },
+            "nav": [{ "menu": "main", "order": null }],
+        });
+        let text = to_toml_file(&data, "Body").unwrap();
+
+        let fm = parse(file(), &text).unwrap().unwrap();
+        assert_eq!(
+            fm.data,
+            json!({ "tax": { "tags": ["a", "b"] }, "nav": [{ "menu": "main" }] })
+        );
+        assert_eq!(fm.body.trim(), "Body");
+    }
+}
@@ -0,0 +1,196 @@
+// crates/serve/src/i18n.rs
+
+// Translations as themes see them. Documents that translate one another
+// share an `i18n.canonical_id`: the served path of the original, which may
+// leave it out. The host looks up the current document's translations and
+// hands them to two helpers:
+//
+//   <head>{{{hreflang}}}</head>
+//     → <link rel="alternate" hreflang="de" href="https://example.com/de/about">
+//       … one per language, and `x-default` for the site's default
+//
+//   {{#each (translations)}}
+//     <a href="{{url}}" hreflang="{{lang}}" {{#if current}}aria-current="page"{{/if}}>{{lang}}</a>
+//   {{/each}}
+//
+// `hreflang` needs the triple braces; it is markup.
+
+use handlebars::{
+    Context, Handlebars, Helper, HelperDef, RenderContext, RenderError as HbsError, ScopedJson,
+};
+use serde::Serialize;
+use serde_json::Value as Json;
+use std::fmt::Write;
+use std::sync::Arc;
+
+/// One language version of a document.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Translation {
+    pub lang: String,
+    /// Where to link to it from this site: a path, or a full URL on another
+    /// host.
+    pub url: String,
+    /// Its full URL, for `hreflang`.
+    pub href: String,
+    pub title: Option<String>,
+    /// The document being rendered.
+    pub current: bool,
+}
+
+/// The language in `available` that `accept_language` (an `Accept-Language`
+/// header) prefers. `de-AT` takes `de` when there is no `de-AT`, and `de`
+/// takes `de-CH`; `*` and `q=0` prefer nothing.
+pub fn negotiate<'a>(accept_language: &str, available: &[&'a str]) -> Option<&'a str> {
+    let mut ranges: Vec<(&str, f32)> = accept_language
+        .split(',')
+        .filter_map(|part| {
+            let mut params = part.split(';');
+            let tag = params.next()?.trim();
+            let q = params
+                .filter_map(|p| p.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            (!tag.is_empty() && tag != "*" && q > 0.0).then_some((tag, q))
+        })
+        .collect();
+    // Stable, so equal weights keep the reader's order.
+    ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    let primary = |tag: &str| tag.split('-').next().unwrap_or(tag).to_ascii_lowercase();
+    ranges.iter().find_map(|(tag, _)| {
+        available
+            .iter()
+            .find(|lang| lang.eq_ignore_ascii_case(tag))
+            .or_else(|| available.iter().find(|lang| primary(lang) == primary(tag)))
+            .copied()
+    })
+}
+
+/// `<link rel="alternate">` tags for `translations`, plus `x-default`
+/// pointing at the one in `default_lang`. Nothing for a lone document.
+pub fn hreflang_links(translations: &[Translation], default_lang: &str) -> String {
+    let mut out = String::new();
+    if translations.len() < 2 {
+        return out;
+    }
+    let mut link = |lang: &str, href: &str| {
+        let _ = writeln!(
+            out,
+            r#"<link rel="alternate" hreflang="{}" href="{}">"#,
+            escape(lang),
+            escape(href)
+        );
+    };
+    for t in translations {
+        link(&t.lang, &t.href);
+    }
+    if let Some(default) = translations.iter().find(|t| t.lang == default_lang) {
+        link("x-default", &default.href);
+    }
+    out
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// `{{translations}}`: the current document's language versions, itself
+/// included, in language order.
+#[derive(Debug, Clone, Default)]
+pub struct TranslationsHelper {
+    translations: Arc<Vec<Translation>>,
+}
+
+impl TranslationsHelper {
+    pub fn new(translations: Arc<Vec<Translation>>) -> Self {
+        Self { translations }
+    }
+}
+
+impl HelperDef for TranslationsHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        _: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, HbsError> {
+        let list = serde_json::to_value(self.translations.as_slice()).unwrap_or(Json::Null);
+        Ok(ScopedJson::Derived(list))
+    }
+}
+
+/// `{{{hreflang}}}`: [`hreflang_links`] for the current document.
+#[derive(Debug, Clone, Default)]
+pub struct HreflangHelper {
+    links: String,
+}
+
+impl HreflangHelper {
+    pub fn new(translations: &[Translation], default_lang: &str) -> Self {
+        Self {
+            links: hreflang_links(translations, default_lang),
+        }
+    }
+}
+
+impl HelperDef for HreflangHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        _: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, HbsError> {
+        Ok(ScopedJson::Derived(Json::String(self.links.clone())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn readers_get_the_closest_language_they_accept() {
+        let available = ["en", "de-CH", "fr"];
+        assert_eq!(
+            negotiate("fr;q=0.5, de-AT, en;q=0.9", &available),
+            Some("de-CH")
+        );
+        assert_eq!(negotiate("EN-gb", &available), Some("en"));
+        assert_eq!(negotiate("es, fr;q=0", &available), None);
+        assert_eq!(negotiate("*", &available), None);
+        assert_eq!(negotiate("", &available), None);
+    }
+
+    #[test]
+    fn templates_list_translations_and_alternates() {
+        let translation = |lang: &str, current| Translation {
+            lang: lang.into(),
+            url: format!("/{lang}/about"),
+            href: format!("https://example.com/{lang}/about"),
+            title: None,
+            current,
+        };
+        let all = vec![translation("de", false), translation("en", true)];
+        let mut hbs = Handlebars::new();
+        hbs.register_helper(
+            "translations",
+            Box::new(TranslationsHelper::new(Arc::new(all.clone()))),
+        );
+        hbs.register_helper("hreflang", Box::new(HreflangHelper::new(&all, "en")));
+        let tpl =
+            r#"{{#each (translations)}}{{lang}}{{#if current}}*{{/if}} {{/each}}{{{hreflang}}}"#;
+        assert_eq!(
+            hbs.render_template(tpl, &json!({})).unwrap(),
+            "de en* <link rel=\"alternate\" hreflang=\"de\" href=\"https://example.com/de/about\">\n\
+             <link rel=\"alternate\" hreflang=\"en\" href=\"https://example.com/en/about\">\n\
+             <link rel=\"alternate\" hreflang=\"x-default\" href=\"https://example.com/en/about\">\n"
+        );
+        assert!(hreflang_links(&all[..1], "en").is_empty());
+    }
+}
@@ -0,0 +1,440 @@
+// crates/serve/src/images.rs
+
+// On-demand image derivatives.
+//
+// A media URL with transform parameters names a resized or transcoded copy
+// of the original:
+//
+//   /media/photos/cat.jpg?w=800            800px wide, still JPEG
+//   /media/photos/cat.jpg?w=800&fmt=webp   the same as WebP
+//   /media/photos/cat.jpg?h=300&fmt=avif   300px high AVIF
+//
+// Width and height fit the image inside the box, keeping its aspect ratio,
+// and never upscale. Both must be one of the configured `widths`, so
+// clients cannot fill the cache directory with a derivative per pixel size;
+// with no widths configured, only `fmt` is honoured. Each derivative is generated once and kept in the
+// cache directory under a hash of the original's path, size and mtime plus
+// the transform, so replacing an original orphans its old derivatives
+// instead of serving them. Each original's derivatives share a folder named
+// by a hash of its path, which `purge` empties.
+
+use domain::hex;
+use domain::setting::ImageSettings;
+use image::codecs::avif::AvifEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{self, Cursor};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use thiserror::Error;
+
+/// AVIF encoder speed, 1 (slowest, smallest) to 10.
+const AVIF_SPEED: u8 = 8;
+
+#[derive(Debug, Error)]
+pub enum DerivativeError {
+    #[error("image I/O: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("processing image: {0}")]
+    Image(#[from] image::ImageError),
+
+    #[error("invalid image parameter {name}={value:?}")]
+    InvalidParam { name: &'static str, value: String },
+
+    #[error("size {0} is not one of the configured widths")]
+    SizeNotAllowed(u32),
+
+    #[error("{0}px exceeds the largest allowed image dimension")]
+    TooLarge(u32),
+}
+
+/// Formats derivatives can be written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OutputFormat {
+    Jpeg,
+    Png,
+    Webp,
+    Avif,
+}
+
+impl OutputFormat {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "jpg" | "jpeg" => Some(Self::Jpeg),
+            "png" => Some(Self::Png),
+            "webp" => Some(Self::Webp),
+            "avif" => Some(Self::Avif),
+            _ => None,
+        }
+    }
+
+    /// The format an original is kept in when no `fmt` is given; PNG for
+    /// sources that cannot be written back as they are (e.g. GIF).
+    fn of_source(path: &Path) -> Self {
+        path.extension()
+            .and_then(|e| e.to_str())
+            .and_then(Self::parse)
+            .unwrap_or(Self::Png)
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Jpeg => "jpg",
+            Self::Png => "png",
+            Self::Webp => "webp",
+            Self::Avif => "avif",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Jpeg => "image/jpeg",
+            Self::Png => "image/png",
+            Self::Webp => "image/webp",
+            Self::Avif => "image/avif",
+        }
+    }
+}
+
+/// The transform a request asks for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Transform {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub format: Option<OutputFormat>,
+}
+
+impl Transform {
+    /// Read `w`, `h` and `fmt` from a raw query string; `None` when it asks
+    /// for the original. Other parameters are ignored.
+    pub fn from_query(
+        query: &str,
+        settings: &ImageSettings,
+    ) -> Result<Option<Self>, DerivativeError> {
+        let mut t = Self::default();
+        for pair in query.split('&').filter(|p| !p.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            match key {
+                "w" => t.width = Some(dimension("w", value, settings)?),
+                "h" => t.height = Some(dimension("h", value, settings)?),
+                "fmt" => {
+                    t.format = Some(OutputFormat::parse(value).ok_or_else(|| {
+                        DerivativeError::InvalidParam {
+                            name: "fmt",
+                            value: value.to_owned(),
+                        }
+                    })?)
+                }
+                _ => {}
+            }
+        }
+
+        for size in [t.width, t.height].into_iter().flatten() {
+            if !settings.widths.contains(&size) {
+                return Err(DerivativeError::SizeNotAllowed(size));
+            }
+        }
+        Ok((t != Self::default()).then_some(t))
+    }
+
+    /// Width and height of the result for a `width`×`height` original.
+    fn target_size(&self, width: u32, height: u32) -> (u32, u32) {
+        let scale_w = self.width.map_or(1.0, |w| f64::from(w) / f64::from(width));
+        let scale_h = self
+            .height
+            .map_or(1.0, |h| f64::from(h) / f64::from(height));
+        let scale = scale_w.min(scale_h).min(1.0);
+        let fit = |n: u32| ((f64::from(n) * scale).round() as u32).max(1);
+        (fit(width), fit(height))
+    }
+}
+
+fn dimension(
+    name: &'static str,
+    value: &str,
+    settings: &ImageSettings,
+) -> Result<u32, DerivativeError> {
+    let n: u32 =
+        value
+            .parse()
+            .ok()
+            .filter(|&n| n > 0)
+            .ok_or_else(|| DerivativeError::InvalidParam {
+                name,
+                value: value.to_owned(),
+            })?;
+    if n > settings.max_dimension {
+        return Err(DerivativeError::TooLarge(n));
+    }
+    Ok(n)
+}
+
+/// A derivative on disk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Derivative {
+    pub path: PathBuf,
+    pub content_type: &'static str,
+    /// Quoted strong ETag.
+    pub etag: String,
+}
+
+/// Generates derivatives into, and serves them from, one cache directory.
+#[derive(Debug, Clone)]
+pub struct DerivativeStore {
+    dir: PathBuf,
+    quality: u8,
+}
+
+impl DerivativeStore {
+    pub fn new(dir: PathBuf, settings: &ImageSettings) -> Self {
+        Self {
+            dir,
+            quality: settings.quality.clamp(1, 100),
+        }
+    }
+
+    /// The derivative of `source`, generating it on first request.
+    ///
+    /// Blocks while decoding and encoding; call it off the async runtime.
+    pub fn get_or_create(
+        &self,
+        source: &Path,
+        t: &Transform,
+    ) -> Result<Derivative, DerivativeError> {
+        let format = t.format.unwrap_or_else(|| OutputFormat::of_source(source));
+        let key = self.key(source, t, format)?;
+        let dir = self.source_dir(source);
+        let path = dir.join(format!("{key}.{}", format.extension()));
+        let derivative = Derivative {
+            path,
+            content_type: format.content_type(),
+            etag: format!("\"{key}\""),
+        };
+        if derivative.path.is_file() {
+            return Ok(derivative);
+        }
+
+        let bytes = self.render(source, t, format)?;
+        fs::create_dir_all(&dir)?;
+        // Concurrent requests for the same derivative each write their own
+        // temporary file; whichever rename lands last wins with equal bytes.
+        let tmp = dir.join(format!(
+            ".{key}.{}.{:?}.tmp",
+            std::process::id(),
+            std::thread::current().id()
+        ));
+        fs::write(&tmp, &bytes)?;
+        fs::rename(&tmp, &derivative.path)?;
+        Ok(derivative)
+    }
+
+    /// Delete every derivative of `source`, current or orphaned; returns
+    /// how many files went.
+    pub fn purge(&self, source: &Path) -> io::Result<usize> {
+        let dir = self.source_dir(source);
+        let count = match fs::read_dir(&dir) {
+            Ok(entries) => entries.count(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e),
+        };
+        fs::remove_dir_all(&dir)?;
+        Ok(count)
+    }
+
+    /// The folder holding the derivatives of `source`.
+    fn source_dir(&self, source: &Path) -> PathBuf {
+        self.dir.join(hex::encode(
+            &Sha256::digest(source.to_string_lossy().as_bytes())[..16],
+        ))
+    }
+
+    fn key(&self, source: &Path, t: &Transform, format: OutputFormat) -> io::Result<String> {
+        let meta = fs::metadata(source)?;
+        let mtime = meta
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+
+        let mut hasher = Sha256::new();
+        hasher.update(source.to_string_lossy().as_bytes());
+        hasher.update(format!(
+            "\0{}\0{mtime}\0{:?}\0{:?}\0{}\0{}",
+            meta.len(),
+            t.width,
+            t.height,
+            format.extension(),
+            self.quality
+        ));
+        Ok(hex::encode(&hasher.finalize()[..16]))
+    }
+
+    fn render(
+        &self,
+        source: &Path,
+        t: &Transform,
+        format: OutputFormat,
+    ) -> Result<Vec<u8>, DerivativeError> {
+        let img = image::ImageReader::open(source)?
+            .with_guessed_format()?
+            .decode()?;
+        let (w, h) = t.target_size(img.width(), img.height());
+        let img = if (w, h) == (img.width(), img.height()) {
+            img
+        } else {
+            img.resize_exact(w, h, FilterType::Lanczos3)
+        };
+        encode(&img, format, self.quality)
+    }
+}
+
+/// Width and height of the image at `path`, read from its header alone.
+pub fn dimensions(path: &Path) -> Result<(u32, u32), DerivativeError> {
+    Ok(image::ImageReader::open(path)?
+        .with_guessed_format()?
+        .into_dimensions()?)
+}
+
+fn encode(
+    img: &DynamicImage,
+    format: OutputFormat,
+    quality: u8,
+) -> Result<Vec<u8>, DerivativeError> {
+    let mut out = Vec::new();
+    match format {
+        // JPEG has no alpha channel.
+        OutputFormat::Jpeg => DynamicImage::ImageRgb8(img.to_rgb8())
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut out, quality))?,
+        OutputFormat::Png => img.write_to(&mut Cursor::new(&mut out), ImageFormat::Png)?,
+        OutputFormat::Webp => DynamicImage::ImageRgba8(img.to_rgba8())
+            .write_to(&mut Cursor::new(&mut out), ImageFormat::WebP)?,
+        OutputFormat::Avif => DynamicImage::ImageRgba8(img.to_rgba8()).write_with_encoder(
+            AvifEncoder::new_with_speed_quality(&mut out, AVIF_SPEED, quality),
+        )?,
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, Rgb};
+
+    fn original(dir: &Path) -> PathBuf {
+        let path = dir.join("wide.png");
+        ImageBuffer::from_fn(40, 20, |x, _| Rgb([(x * 6) as u8, 0, 0]))
+            .save(&path)
+            .unwrap();
+        path
+    }
+
+    #[test]
+    fn queries_are_parsed_and_checked_against_settings() {
+        let settings = ImageSettings::default();
+        assert_eq!(Transform::from_query("", &settings).unwrap(), None);
+        assert_eq!(Transform::from_query("utm=x", &settings).unwrap(), None);
+        assert_eq!(
+            Transform::from_query("w=640&fmt=WEBP", &settings).unwrap(),
+            Some(Transform {
+                width: Some(640),
+                height: None,
+                format: Some(OutputFormat::Webp),
+            })
+        );
+        assert!(matches!(
+            Transform::from_query("w=0", &settings),
+            Err(DerivativeError::InvalidParam { name: "w", .. })
+        ));
+        assert!(matches!(
+            Transform::from_query("fmt=bmp", &settings),
+            Err(DerivativeError::InvalidParam { name: "fmt", .. })
+        ));
+        assert!(matches!(
+            Transform::from_query("h=100000", &settings),
+            Err(DerivativeError::TooLarge(100000))
+        ));
+
+        // Only the listed sizes, for heights as for widths.
+        assert!(Transform::from_query("w=1280&h=320", &settings).is_ok());
+        assert!(matches!(
+            Transform::from_query("w=500", &settings),
+            Err(DerivativeError::SizeNotAllowed(500))
+        ));
+        assert!(matches!(
+            Transform::from_query("w=320&h=333", &settings),
+            Err(DerivativeError::SizeNotAllowed(333))
+        ));
+
+        // No widths: no resizing, but formats still convert.
+        let none = ImageSettings {
+            widths: Vec::new(),
+            ..ImageSettings::default()
+        };
+        assert!(matches!(
+            Transform::from_query("h=320", &none),
+            Err(DerivativeError::SizeNotAllowed(320))
+        ));
+        assert!(Transform::from_query("fmt=webp", &none).unwrap().is_some());
+    }
+
+    #[test]
+    fn sizes_fit_the_box_without_upscaling() {
+        let t = |w, h| Transform {
+            width: w,
+            height: h,
+            format: None,
+        };
+        assert_eq!(t(Some(20), None).target_size(40, 20), (20, 10));
+        assert_eq!(t(Some(20), Some(5)).target_size(40, 20), (10, 5));
+        assert_eq!(t(None, Some(10)).target_size(40, 20), (20, 10));
+        assert_eq!(t(Some(400), None).target_size(40, 20), (40, 20));
+    }
+
+    #[test]
+    fn derivatives_are_generated_once_and_replaced_with_the_original() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = original(dir.path());
+        let store = DerivativeStore::new(dir.path().join("cache"), &ImageSettings::default());
+        let t = Transform {
+            width: Some(10),
+            height: None,
+            format: Some(OutputFormat::Webp),
+        };
+
+        let first = store.get_or_create(&source, &t).unwrap();
+        assert_eq!(first.content_type, "image/webp");
+        let img = image::open(&first.path).unwrap();
+        assert_eq!((img.width(), img.height()), (10, 5));
+
+        let written = fs::metadata(&first.path).unwrap().modified().unwrap();
+        let again = store.get_or_create(&source, &t).unwrap();
+        assert_eq!(again, first);
+        assert_eq!(
+            fs::metadata(&again.path).unwrap().modified().unwrap(),
+            written
+        );
+
+        let as_is = store
+            .get_or_create(&source, &Transform { format: None, ..t })
+            .unwrap();
+        assert_eq!(as_is.content_type, "image/png");
+
+        // A replaced original has a different size, so a new key.
+        ImageBuffer::from_pixel(30, 30, Rgb([0u8, 0, 255]))
+            .save(&source)
+            .unwrap();
+        let replaced = store.get_or_create(&source, &t).unwrap();
+        assert_ne!(replaced.path, first.path);
+        let img = image::open(&replaced.path).unwrap();
+        assert_eq!((img.width(), img.height()), (10, 10));
+
+        // Purging takes the orphans along with the current derivatives.
+        assert_eq!(store.purge(&source).unwrap(), 3);
+        assert!(!replaced.path.exists() && !first.path.exists());
+        assert_eq!(store.purge(&source).unwrap(), 0);
+    }
+}
@@ -10,20 +10,27 @@
 //!   2. `index_front_matter` — persist indexed_json FM using served path
 //!   3. `index_body` — persist HTML (or passthrough) into CAS/Tantivy using served path
 //!
+//! `scan_and_process_docs` indexes a whole tree; `process_changed_docs` re-indexes
+//! only the paths a watcher reported, dropping documents whose source is gone.
+//!
 //! After indexing, the runtime resolvers (in edge + serve/resolver.rs) will search these
 //! stores using additional functions injected separately.
 
 use async_trait::async_trait;
-use domain::doc::{BodyKind, Document, FmKind};
+use domain::doc::{BodyKind, Document};
 use regex::Regex;
 use serde_json::Value as Json;
 use thiserror::Error;
+use tracing::warn;
 
 use std::io;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::sync::mpsc;
 
+use crate::blocks::{BlockError, BlockRegistry};
+use crate::front_matter::{self, FrontMatterError};
+use crate::permalinks::Permalinks;
 use crate::resolver::ResolverError;
 
 // ---------------------------------------------------------------------------
@@ -81,7 +88,7 @@ pub enum DocContextError {
     Io(#[from] io::Error),
 
     #[error("front matter parse error: {0}")]
-    FrontMatter(String),
+    FrontMatter(#[from] FrontMatterError),
 
     #[error("JSON error: {0}")]
     Json(#[from] serde_json::Error),
@@ -103,6 +110,9 @@ pub enum DocContextError {
 
     #[error("Edge scan error: {0}")]
     Scan(String),
+
+    #[error("block document error: {0}")]
+    Blocks(#[from] BlockError),
 }
 
 // ---------------------------------------------------------------------------
@@ -134,16 +144,59 @@ pub trait ContentManager {
         kind: BodyKind,
     ) -> Result<(), DocContextError>;
 
+    /// Drop everything indexed for `served_path` (front matter and body).
+    async fn remove_document(&self, served_path: &Path) -> Result<(), DocContextError>;
+
+    /// Drop every indexed document; the first step of a full rebuild.
+    async fn clear_index(&self) -> Result<(), DocContextError>;
+
+    /// Every live document as `(served path, front matter)`.
+    async fn list_front_matter(&self) -> Result<Vec<(String, Json)>, ResolverError>;
+
+    /// `list_front_matter`, one document at a time. Should stop early, without
+    /// error, once `tx` is closed.
+    async fn stream_front_matter(
+        &self,
+        tx: mpsc::Sender<(String, Json)>,
+    ) -> Result<(), ResolverError>;
+
     async fn lookup_slug(&self, slug: &str) -> Result<Option<Json>, ResolverError>;
     async fn lookup_served(&self, served: &str) -> Result<Option<Json>, ResolverError>;
     async fn lookup_body(&self, body: &str) -> Result<Option<Arc<String>>, ResolverError>;
+
+    /// Block types for `.blocks` documents; the built-in ones by default.
+    fn blocks(&self) -> Arc<BlockRegistry> {
+        Arc::new(BlockRegistry::new())
+    }
+
+    /// URL patterns by content type; none by default.
+    fn permalinks(&self) -> Arc<Permalinks> {
+        Arc::new(Permalinks::default())
+    }
+}
+
+/// Documents buffered between `stream_front_matter` and its consumer.
+pub const FRONT_MATTER_STREAM_BUFFER: usize = 64;
+
+/// Call `f` with every live document without collecting them first.
+pub async fn for_each_front_matter(
+    mgr: &impl ContentManager,
+    mut f: impl FnMut(String, Json),
+) -> Result<(), ResolverError> {
+    let (tx, mut rx) = mpsc::channel(FRONT_MATTER_STREAM_BUFFER);
+    let (streamed, ()) = tokio::join!(mgr.stream_front_matter(tx), async {
+        while let Some((served, fm)) = rx.recv().await {
+            f(served, fm);
+        }
+    });
+    streamed
 }
 
 // ---------------------------------------------------------------------------
 // Markdown + Body conversion helpers
 // ---------------------------------------------------------------------------
 
-fn default_markdown_options() -> comrak::Options<'static> {
+pub(crate) fn default_markdown_options() -> comrak::Options<'static> {
     let mut opt = comrak::Options::default();
     opt.extension.strikethrough = true;
     opt.extension.table = true;
@@ -162,17 +215,19 @@ fn infer_body_kind(ext: Option<&str>) -> BodyKind {
         Some(ref e) if e == "html" || e == "htm" || e == "xhtml" => BodyKind::Html,
         Some(ref e) if e == "rst" => BodyKind::ReStructuredText,
         Some(ref e) if e == "org" => BodyKind::OrgMode,
+        Some(ref e) if e == "blocks" => BodyKind::Blocks,
         _ => BodyKind::Plain,
     }
 }
 
-fn served_path_for_source(path: &Path) -> PathBuf {
+pub fn served_path_for_source(path: &Path) -> PathBuf {
     let kind = infer_body_kind(path.extension().and_then(|s| s.to_str()));
     match kind {
         BodyKind::Markdown
         | BodyKind::AsciiDoc
         | BodyKind::ReStructuredText
-        | BodyKind::OrgMode => {
+        | BodyKind::OrgMode
+        | BodyKind::Blocks => {
             let mut p = path.to_owned();
             p.set_extension("html");
             p
@@ -203,74 +258,19 @@ pub async fn upsert_front_matter_db(
     ctx: DocContext,
     scan_indexer: &impl ContentManager,
 ) -> Result<DocContext, DocContextError> {
-    use gray_matter::engine::YAML;
-    use gray_matter::Matter;
-
     let mut ctx = read_document_utf8(ctx, scan_indexer).await?;
-    let full = ctx
-        .document
-        .cache
-        .as_ref()
-        .map(String::as_str)
-        .unwrap_or("");
-
-    let mut fm_json = None;
-    let mut fm_kind = None;
-    let mut body = None;
-
-    // YAML FM
-    if fm_json.is_none() {
-        let matter: Matter<YAML> = Matter::new();
-        if let Ok(parsed) = matter.parse::<Json>(full) {
-            if let Some(data) = parsed.data {
-                fm_json = Some(data);
-                fm_kind = Some(FmKind::Yaml);
-                body = Some(parsed.content);
-            }
-        }
-    }
-
-    // TOML FM
-    if fm_json.is_none() {
-        let trimmed = full.trim_start_matches('\u{feff}');
-        if trimmed.starts_with("+++") {
-            let after = trimmed.trim_start_matches('+').trim_start_matches("\n");
-            if let Some(end_idx) = after.find("\n+++") {
-                let fm_src = &after[..end_idx];
-                match toml::from_str::<toml::Value>(fm_src) {
-                    Ok(toml_val) => {
-                        fm_json = Some(
-                            serde_json::to_value(toml_val)
-                                .map_err(|e| DocContextError::FrontMatter(e.to_string()))?,
-                        );
-                        fm_kind = Some(FmKind::Toml);
-                        body = Some(after[end_idx + 4..].trim_start().to_owned());
-                    }
-                    Err(e) => return Err(DocContextError::FrontMatter(e.to_string())),
-                }
-            }
-        }
-    }
+    let full = ctx.document.cache.as_deref().unwrap_or("");
+    let path = ctx.document.path.clone();
 
-    // JSON FM
-    if fm_json.is_none() {
-        let trimmed = full.trim();
-        if trimmed.starts_with('{') {
-            match serde_json::from_str::<Json>(trimmed) {
-                Ok(v) => {
-                    fm_json = Some(v);
-                    fm_kind = Some(FmKind::Json);
-                    body = Some(String::new());
-                }
-                Err(e) => return Err(DocContextError::FrontMatter(e.to_string())),
+    let (fm_json, fm_kind, body) = match front_matter::parse(&path, full)? {
+        Some(fm) => {
+            for issue in front_matter::check_fields(&path, full, &fm) {
+                warn!("{}", issue);
             }
+            (Some(fm.data), Some(fm.kind), Some(fm.body))
         }
-    }
-
-    // If body not extracted, set full file as body
-    if body.is_none() {
-        body = Some(full.to_owned());
-    }
+        None => (None, None, Some(full.to_owned())),
+    };
 
     if let Some(data) = fm_json {
         let served = served_path_for_source(&ctx.document.path);
@@ -355,6 +355,10 @@ pub async fn upsert_body_db(
         }
         BodyKind::ReStructuredText => render_rst(body_text)?,
         BodyKind::OrgMode => render_org(body_text)?,
+        BodyKind::Blocks => {
+            let blocks = scan_indexer.blocks();
+            blocks.render(&blocks.parse(body_text)?)?
+        }
     };
 
     let served = served_path_for_source(&ctx.document.path);
@@ -372,6 +376,16 @@ pub async fn upsert_body_db(
 // High-Level Pipeline
 // ---------------------------------------------------------------------------
 
+async fn process_doc(
+    document: Document,
+    scan_indexer: &impl ContentManager,
+) -> Result<Document, DocContextError> {
+    let ctx = DocContext { document };
+    let ctx = upsert_front_matter_db(ctx, scan_indexer).await?;
+    let ctx = upsert_body_db(ctx, scan_indexer).await?;
+    Ok(ctx.document)
+}
+
 pub async fn scan_and_process_docs(
     root: &Path,
     scan_cfg: FolderScanConfig,
@@ -383,22 +397,238 @@ pub async fn scan_and_process_docs(
     let mut errors = Vec::new();
 
     while let Some(path) = rx.recv().await {
-        let document = Document::new(path.clone());
-        let ctx = DocContext { document };
+        match process_doc(Document::new(path.clone()), &scan_indexer).await {
+            Ok(done) => docs.push(done),
+            Err(err) => errors.push((path, err)),
+        }
+    }
+
+    stop();
+    Ok((docs, errors))
+}
+
+/// Clear the indexes and run a full scan; the fallback when incremental
+/// updates can no longer be trusted.
+pub async fn rebuild_docs(
+    root: &Path,
+    scan_cfg: FolderScanConfig,
+    scan_indexer: impl ContentManager,
+) -> Result<(Vec<Document>, Vec<(PathBuf, DocContextError)>), DocContextError> {
+    scan_indexer.clear_index().await?;
+    scan_and_process_docs(root, scan_cfg, scan_indexer).await
+}
+
+// ---------------------------------------------------------------------------
+// Incremental Pipeline
+// ---------------------------------------------------------------------------
+
+/// What one incremental pass did.
+#[derive(Debug, Default)]
+pub struct ChangeOutcome {
+    /// Source files that were re-parsed and re-indexed.
+    pub updated: Vec<Document>,
+    /// Source files that no longer exist and were dropped from the indexes.
+    pub removed: Vec<PathBuf>,
+    pub errors: Vec<(PathBuf, DocContextError)>,
+}
+
+/// Re-index only `paths`, e.g. the files a watcher reported as changed.
+///
+/// Paths whose file name does not match `file_re` are ignored. A file that
+/// can still be read is dropped from the indexes and processed again, so
+/// removed front matter does not linger; a file that is gone is only dropped.
+pub async fn process_changed_docs(
+    paths: impl IntoIterator<Item = PathBuf>,
+    file_re: Option<&Regex>,
+    scan_indexer: &impl ContentManager,
+) -> ChangeOutcome {
+    let mut outcome = ChangeOutcome::default();
+
+    for path in paths {
+        if let Some(re) = file_re {
+            let name = path.file_name().map(|n| n.to_string_lossy());
+            if !name.is_some_and(|n| re.is_match(&n)) {
+                continue;
+            }
+        }
+
+        let served = served_path_for_source(&path);
+        let text = match scan_indexer.scan_file(&path).await {
+            Ok(text) => text,
+            Err(DocContextError::Io(e)) if e.kind() == io::ErrorKind::NotFound => {
+                match scan_indexer.remove_document(&served).await {
+                    Ok(()) => outcome.removed.push(path),
+                    Err(err) => outcome.errors.push((path, err)),
+                }
+                continue;
+            }
+            Err(err) => {
+                outcome.errors.push((path, err));
+                continue;
+            }
+        };
 
         let processed = async {
-            let ctx = upsert_front_matter_db(ctx, &scan_indexer).await?;
-            let ctx = upsert_body_db(ctx, &scan_indexer).await?;
-            Ok::<_, DocContextError>(ctx)
+            scan_indexer.remove_document(&served).await?;
+            process_doc(Document::new(path.clone()).with_cache(text), scan_indexer).await
         }
         .await;
 
         match processed {
-            Ok(done) => docs.push(done.document),
-            Err(err) => errors.push((path, err)),
+            Ok(done) => outcome.updated.push(done),
+            Err(err) => outcome.errors.push((path, err)),
         }
     }
 
-    stop();
-    Ok((docs, errors))
+    outcome
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    /// Files and indexes held in memory.
+    #[derive(Default)]
+    struct MemMgr {
+        files: Mutex<HashMap<PathBuf, String>>,
+        fm: Mutex<HashMap<PathBuf, Json>>,
+        bodies: Mutex<HashMap<PathBuf, String>>,
+    }
+
+    impl MemMgr {
+        fn write(&self, path: &str, text: &str) {
+            self.files
+                .lock()
+                .unwrap()
+                .insert(PathBuf::from(path), text.to_owned());
+        }
+    }
+
+    #[async_trait]
+    impl ContentManager for MemMgr {
+        async fn scan_file(&self, path: &Path) -> Result<String, DocContextError> {
+            self.files
+                .lock()
+                .unwrap()
+                .get(path)
+                .cloned()
+                .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound).into())
+        }
+
+        async fn scan_folder(
+            &self,
+            _root: &Path,
+            _cfg: &FolderScanConfig,
+        ) -> Result<(mpsc::Receiver<PathBuf>, ScanStopFn), DocContextError> {
+            let (tx, rx) = mpsc::channel(16);
+            for path in self.files.lock().unwrap().keys() {
+                tx.try_send(path.clone()).unwrap();
+            }
+            Ok((rx, Box::new(|| ())))
+        }
+
+        async fn index_front_matter(
+            &self,
+            served_path: &Path,
+            fm: &Json,
+        ) -> Result<(), DocContextError> {
+            self.fm
+                .lock()
+                .unwrap()
+                .insert(served_path.to_owned(), fm.clone());
+            Ok(())
+        }
+
+        async fn index_body(
+            &self,
+            served_path: &Path,
+            html: &str,
+            _kind: BodyKind,
+        ) -> Result<(), DocContextError> {
+            self.bodies
+                .lock()
+                .unwrap()
+                .insert(served_path.to_owned(), html.to_owned());
+            Ok(())
+        }
+
+        async fn remove_document(&self, served_path: &Path) -> Result<(), DocContextError> {
+            self.fm.lock().unwrap().remove(served_path);
+            self.bodies.lock().unwrap().remove(served_path);
+            Ok(())
+        }
+
+        async fn clear_index(&self) -> Result<(), DocContextError> {
+            self.fm.lock().unwrap().clear();
+            self.bodies.lock().unwrap().clear();
+            Ok(())
+        }
+
+        async fn list_front_matter(&self) -> Result<Vec<(String, Json)>, ResolverError> {
+            Ok(self
+                .fm
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(path, fm)| (path.display().to_string(), fm.clone()))
+                .collect())
+        }
+
+        async fn stream_front_matter(
+            &self,
+            tx: mpsc::Sender<(String, Json)>,
+        ) -> Result<(), ResolverError> {
+            for doc in self.list_front_matter().await? {
+                if tx.send(doc).await.is_err() {
+                    break;
+                }
+            }
+            Ok(())
+        }
+
+        async fn lookup_slug(&self, _slug: &str) -> Result<Option<Json>, ResolverError> {
+            Ok(None)
+        }
+
+        async fn lookup_served(&self, _served: &str) -> Result<Option<Json>, ResolverError> {
+            Ok(None)
+        }
+
+        async fn lookup_body(&self, _body: &str) -> Result<Option<Arc<String>>, ResolverError> {
+            Ok(None)
+        }
+    }
+
+    #[tokio::test]
+    async fn changed_docs_are_reparsed_and_vanished_ones_removed() {
+        let mgr = MemMgr::default();
+        mgr.write("/c/a.md", "+++\ntitle = \"A\"\n+++\nfirst");
+        mgr.write("/c/b.md", "+++\ntitle = \"B\"\n+++\nbee");
+        let first =
+            process_changed_docs(["/c/a.md", "/c/b.md"].map(PathBuf::from), None, &mgr).await;
+        assert_eq!(first.updated.len(), 2);
+        assert_eq!(mgr.fm.lock().unwrap().len(), 2);
+
+        // a.md loses its front matter, b.md is deleted, notes.txt is ignored.
+        mgr.write("/c/a.md", "second");
+        mgr.files.lock().unwrap().remove(Path::new("/c/b.md"));
+        let re = Regex::new(r"\.md$").unwrap();
+
+        let outcome = process_changed_docs(
+            ["/c/a.md", "/c/b.md", "/c/notes.txt"].map(PathBuf::from),
+            Some(&re),
+            &mgr,
+        )
+        .await;
+
+        assert_eq!(outcome.updated.len(), 1);
+        assert_eq!(outcome.removed, vec![PathBuf::from("/c/b.md")]);
+        assert!(outcome.errors.is_empty());
+        assert!(mgr.fm.lock().unwrap().is_empty());
+        let bodies = mgr.bodies.lock().unwrap();
+        assert_eq!(bodies.len(), 1);
+        assert!(bodies[Path::new("/c/a.html")].contains("second"));
+    }
 }
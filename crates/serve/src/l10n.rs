@@ -0,0 +1,555 @@
+// crates/serve/src/l10n.rs
+
+// Theme and plugin strings in the reader's language. A theme or plugin
+// ships catalogs under `locales/`, Fluent or gettext:
+//
+//   locales/de.ftl          welcome = Willkommen, { $name }!
+//   locales/fr.po           msgid "welcome"
+//                           msgstr "Bienvenue, {name} !"
+//   locales/de/*.ftl        one language split over several files
+//
+// Only the parts of each format themes need are read. Fluent: messages,
+// terms (`-brand`), attributes (`login.title`, looked up as such),
+// multi-line values, and placeables naming a variable (`{ $name }`), a term,
+// another message, or a string literal. Selectors and functions are
+// refused when the catalog loads. gettext: `msgid` / `msgstr` with continued
+// lines and the usual escapes, the msgid being the key; `msgctxt` is
+// ignored, plural forms take `msgstr[0]`, untranslated entries are skipped,
+// and `{name}` is a variable.
+//
+// Templates call `{{t "welcome" name=user.name}}` and JS `ctx.t("welcome",
+// { name })`. A key the reader's language lacks comes from the default
+// language; one missing there as well renders as the key.
+
+use handlebars::{
+    Context, Handlebars, Helper, HelperDef, RenderContext, RenderError as HbsError, ScopedJson,
+};
+use serde::Serialize;
+use serde_json::{Map as JsonMap, Value as Json};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use thiserror::Error;
+
+/// Key → Fluent pattern.
+pub type Messages = BTreeMap<String, String>;
+
+/// How deep terms and messages may refer to one another.
+const MAX_DEPTH: usize = 8;
+
+#[derive(Debug, Error)]
+pub enum CatalogError {
+    #[error("I/O: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("{}:{line}: {message}", file.display())]
+    Parse {
+        file: PathBuf,
+        line: usize,
+        message: String,
+    },
+}
+
+fn parse_error(file: &Path, line: usize, message: impl Into<String>) -> CatalogError {
+    CatalogError::Parse {
+        file: file.to_path_buf(),
+        line,
+        message: message.into(),
+    }
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// The placeables in `pattern`, by byte range of their insides.
+fn placeables(pattern: &str) -> impl Iterator<Item = Result<(usize, usize), usize>> + '_ {
+    let mut from = 0;
+    std::iter::from_fn(move || {
+        let open = from + pattern[from..].find('{')?;
+        // A literal may hold a brace: `{ "}" }`.
+        let mut in_literal = false;
+        let mut escaped = false;
+        for (i, c) in pattern[open + 1..].char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if in_literal => escaped = true,
+                '"' => in_literal = !in_literal,
+                '}' if !in_literal => {
+                    let close = open + 1 + i;
+                    from = close + 1;
+                    return Some(Ok((open + 1, close)));
+                }
+                _ => {}
+            }
+        }
+        from = pattern.len();
+        Some(Err(open))
+    })
+}
+
+/// Check that `pattern` only uses the placeables [`Localized::t`] knows.
+fn check_pattern(pattern: &str) -> Result<(), String> {
+    for placeable in placeables(pattern) {
+        let (start, end) = placeable.map_err(|_| "unclosed `{`".to_owned())?;
+        let inner = pattern[start..end].trim();
+        let ok = match inner.as_bytes().first() {
+            Some(b'$') => is_identifier(&inner[1..]),
+            Some(b'-') => is_identifier(&inner[1..]),
+            Some(b'"') => inner.len() >= 2 && inner.ends_with('"'),
+            _ => is_identifier(inner),
+        };
+        if !ok {
+            let what = if inner.contains("->") {
+                "selectors are not supported".to_owned()
+            } else if inner.contains('(') {
+                "functions are not supported".to_owned()
+            } else {
+                format!("bad placeable `{{{inner}}}`")
+            };
+            return Err(what);
+        }
+    }
+    Ok(())
+}
+
+/// Messages and terms in the Fluent file `file`.
+pub fn parse_ftl(file: &Path, src: &str) -> Result<Messages, CatalogError> {
+    // (key, line it starts on, lines of its value)
+    let mut entries: Vec<(String, usize, Vec<String>)> = Vec::new();
+    let mut message: Option<String> = None;
+    for (n, line) in src.lines().enumerate() {
+        let n = n + 1;
+        if line.trim().is_empty() {
+            if let Some((_, _, value)) = entries.last_mut() {
+                value.push(String::new());
+            }
+            continue;
+        }
+        if line.starts_with('#') {
+            message = None;
+            continue;
+        }
+        if line.starts_with([' ', '\t']) {
+            let text = line.trim();
+            if let Some(attribute) = text.strip_prefix('.') {
+                let (name, value) = attribute
+                    .split_once('=')
+                    .ok_or_else(|| parse_error(file, n, "expected `.attribute = value`"))?;
+                let Some(of) = &message else {
+                    return Err(parse_error(file, n, "attribute outside a message"));
+                };
+                if !is_identifier(name.trim()) {
+                    return Err(parse_error(file, n, format!("bad attribute {name:?}")));
+                }
+                entries.push((
+                    format!("{of}.{}", name.trim()),
+                    n,
+                    vec![value.trim().to_owned()],
+                ));
+                continue;
+            }
+            if text.starts_with(['[', '*']) {
+                return Err(parse_error(file, n, "selectors are not supported"));
+            }
+            match entries.last_mut() {
+                Some((_, _, value)) if message.is_some() => value.push(text.to_owned()),
+                _ => return Err(parse_error(file, n, "indented line outside a message")),
+            }
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| parse_error(file, n, "expected `key = value`"))?;
+        let key = key.trim();
+        if !is_identifier(key.strip_prefix('-').unwrap_or(key)) {
+            return Err(parse_error(file, n, format!("bad message id {key:?}")));
+        }
+        message = Some(key.to_owned());
+        entries.push((key.to_owned(), n, vec![value.trim().to_owned()]));
+    }
+
+    let mut messages = Messages::new();
+    for (key, line, value) in entries {
+        let lines: Vec<&str> = value.iter().map(String::as_str).collect();
+        let pattern = lines.join("\n").trim_matches('\n').trim_start().to_owned();
+        if pattern.is_empty() {
+            continue;
+        }
+        check_pattern(&pattern).map_err(|m| parse_error(file, line, m))?;
+        messages.insert(key, pattern);
+    }
+    Ok(messages)
+}
+
+/// The quoted string `s` starts with, unescaped.
+fn po_string(s: &str) -> Option<String> {
+    let inner = s.trim().strip_prefix('"')?.strip_suffix('"')?;
+    let mut out = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next()? {
+            'n' => out.push('\n'),
+            't' => out.push('\t'),
+            'r' => out.push('\r'),
+            other => out.push(other),
+        }
+    }
+    Some(out)
+}
+
+/// A gettext message as a Fluent pattern: `{name}` becomes `{ $name }`
+/// and any other brace a literal.
+fn po_pattern(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find(['{', '}']) {
+        out.push_str(&rest[..i]);
+        let brace = &rest[i..i + 1];
+        let variable = (brace == "{")
+            .then(|| rest[i + 1..].split_once('}'))
+            .flatten()
+            .filter(|(name, _)| is_identifier(name));
+        match variable {
+            Some((name, after)) => {
+                out.push_str(&format!("{{ ${name} }}"));
+                rest = after;
+            }
+            None => {
+                out.push_str(&format!("{{ \"{brace}\" }}"));
+                rest = &rest[i + 1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Translated entries in the gettext file `file`.
+pub fn parse_po(file: &Path, src: &str) -> Result<Messages, CatalogError> {
+    #[derive(PartialEq)]
+    enum Field {
+        None,
+        Context,
+        Id,
+        Plural,
+        Str,
+        OtherStr,
+    }
+    let mut messages = Messages::new();
+    let (mut id, mut text) = (String::new(), String::new());
+    let mut field = Field::None;
+    let mut flush = |id: &mut String, text: &mut String| {
+        if !id.is_empty() && !text.is_empty() {
+            messages.insert(std::mem::take(id), po_pattern(text));
+        }
+        id.clear();
+        text.clear();
+    };
+
+    for (n, line) in src.lines().enumerate() {
+        let n = n + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let bad = || parse_error(file, n, format!("expected a quoted string: {line}"));
+        let (keyword, rest) = match line.find([' ', '\t']) {
+            Some(i) if !line.starts_with('"') => (&line[..i], &line[i..]),
+            _ => ("", line),
+        };
+        let value = po_string(rest).ok_or_else(bad)?;
+        field = match keyword {
+            "msgctxt" => {
+                if field != Field::Context {
+                    flush(&mut id, &mut text);
+                }
+                Field::Context
+            }
+            "msgid" => {
+                if field != Field::Context {
+                    flush(&mut id, &mut text);
+                }
+                id = value;
+                Field::Id
+            }
+            "msgid_plural" => Field::Plural,
+            "msgstr" | "msgstr[0]" => {
+                text = value;
+                Field::Str
+            }
+            k if k.starts_with("msgstr[") => Field::OtherStr,
+            "" => {
+                match field {
+                    Field::Id => id.push_str(&value),
+                    Field::Str => text.push_str(&value),
+                    Field::None => return Err(bad()),
+                    _ => {}
+                }
+                continue;
+            }
+            other => return Err(parse_error(file, n, format!("unknown keyword {other}"))),
+        };
+    }
+    flush(&mut id, &mut text);
+    Ok(messages)
+}
+
+/// Messages in `file`, by its extension; `None` for other files.
+fn parse_file(file: &Path) -> Result<Option<Messages>, CatalogError> {
+    let parse = match file.extension().and_then(|e| e.to_str()) {
+        Some("ftl") => parse_ftl,
+        Some("po") => parse_po,
+        _ => return Ok(None),
+    };
+    let src = fs::read_to_string(file)?;
+    parse(file, &src).map(Some)
+}
+
+/// One reader language's strings, with the default language's filling the
+/// gaps.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Localized {
+    pub lang: String,
+    pub messages: Messages,
+}
+
+impl Localized {
+    /// `key` with `args` (an object) in its variables; the key itself when
+    /// there is no such message.
+    pub fn t(&self, key: &str, args: &Json) -> String {
+        self.format(key, args, 0).unwrap_or_else(|| key.to_owned())
+    }
+
+    fn format(&self, key: &str, args: &Json, depth: usize) -> Option<String> {
+        let pattern = self.messages.get(key)?;
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        let mut out = String::with_capacity(pattern.len());
+        let mut last = 0;
+        for (start, end) in placeables(pattern).filter_map(Result::ok) {
+            out.push_str(&pattern[last..start - 1]);
+            last = end + 1;
+            let inner = pattern[start..end].trim();
+            if let Some(name) = inner.strip_prefix('$') {
+                match args.get(name) {
+                    Some(Json::String(s)) => out.push_str(s),
+                    Some(Json::Null) | None => out.push_str(&format!("{{${name}}}")),
+                    Some(other) => out.push_str(&other.to_string()),
+                }
+            } else if let Some(literal) = inner.strip_prefix('"') {
+                let literal = literal.strip_suffix('"').unwrap_or(literal);
+                out.push_str(&literal.replace("\\\"", "\"").replace("\\\\", "\\"));
+            } else {
+                // A term or another message; they do not see our variables.
+                let referred = self.format(inner, &Json::Null, depth + 1);
+                out.push_str(&referred.unwrap_or_else(|| format!("{{{inner}}}")));
+            }
+        }
+        out.push_str(&pattern[last..]);
+        Some(out)
+    }
+}
+
+/// A theme's or plugin's catalogs, by language.
+#[derive(Debug, Clone, Default)]
+pub struct Catalog {
+    default_lang: String,
+    by_lang: BTreeMap<String, Arc<Localized>>,
+}
+
+impl Catalog {
+    /// Read `dir` (a `locales/` directory; none is fine) with `default_lang`
+    /// as the fallback.
+    pub fn load(dir: &Path, default_lang: &str) -> Result<Self, CatalogError> {
+        let mut raw: BTreeMap<String, Messages> = BTreeMap::new();
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut paths: Vec<PathBuf> = entries
+            .map(|e| e.map(|e| e.path()))
+            .collect::<Result<_, _>>()?;
+        paths.sort();
+        for path in paths {
+            let Some(stem) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            if path.is_dir() {
+                let lang = path.file_name().and_then(|s| s.to_str()).unwrap_or(stem);
+                let mut files: Vec<PathBuf> = fs::read_dir(&path)?
+                    .map(|e| e.map(|e| e.path()))
+                    .collect::<Result<_, _>>()?;
+                files.sort();
+                for file in files {
+                    if let Some(messages) = parse_file(&file)? {
+                        raw.entry(lang.to_owned()).or_default().extend(messages);
+                    }
+                }
+            } else if let Some(messages) = parse_file(&path)? {
+                raw.entry(stem.to_owned()).or_default().extend(messages);
+            }
+        }
+        Ok(Self::from_messages(raw, default_lang))
+    }
+
+    /// Catalogs already parsed, by language.
+    pub fn from_messages(raw: BTreeMap<String, Messages>, default_lang: &str) -> Self {
+        let fallback = raw.get(default_lang).cloned().unwrap_or_default();
+        let by_lang = raw
+            .into_iter()
+            .map(|(lang, messages)| {
+                let mut merged = fallback.clone();
+                merged.extend(messages);
+                let localized = Localized {
+                    lang: lang.clone(),
+                    messages: merged,
+                };
+                (lang, Arc::new(localized))
+            })
+            .collect();
+        Self {
+            default_lang: default_lang.to_owned(),
+            by_lang,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_lang.is_empty()
+    }
+
+    /// The strings for `lang`: its own, else its primary language's
+    /// (`de` for `de-AT`), else the default language's.
+    pub fn localized(&self, lang: &str) -> Arc<Localized> {
+        let primary = lang.split('-').next().unwrap_or(lang);
+        [lang, primary, self.default_lang.as_str()]
+            .into_iter()
+            .find_map(|l| self.by_lang.get(l))
+            .cloned()
+            .unwrap_or_else(|| {
+                Arc::new(Localized {
+                    lang: lang.to_owned(),
+                    messages: Messages::new(),
+                })
+            })
+    }
+}
+
+/// `{{t "key" name=value}}`, or `{{t "key" args}}` with an object, in the
+/// theme's strings for the request.
+#[derive(Debug, Clone, Default)]
+pub struct TranslateHelper {
+    localized: Arc<Localized>,
+}
+
+impl TranslateHelper {
+    pub fn new(localized: Arc<Localized>) -> Self {
+        Self { localized }
+    }
+}
+
+impl HelperDef for TranslateHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, HbsError> {
+        let key = h.param(0).and_then(|p| p.value().as_str()).unwrap_or("");
+        let mut args = match h.param(1).map(|p| p.value()) {
+            Some(Json::Object(map)) => map.clone(),
+            _ => JsonMap::new(),
+        };
+        for (name, value) in h.hash() {
+            args.insert((*name).to_owned(), value.value().clone());
+        }
+        let text = self.localized.t(key, &Json::Object(args));
+        Ok(ScopedJson::Derived(Json::String(text)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn fluent_and_gettext_catalogs_share_one_lookup() {
+        let ftl = Path::new("de.ftl");
+        let de = parse_ftl(
+            ftl,
+            "# Begrüßung\n-brand = Whisper\nwelcome = Willkommen bei { -brand }, { $name }!\n\
+             login = Anmelden\n    .title = Bei { -brand } anmelden\nfooter =\n    Zeile eins\n\n    Zeile { \"{\" }zwei\n",
+        )
+        .unwrap();
+        let en = parse_po(
+            Path::new("en.po"),
+            "msgid \"\"\nmsgstr \"Language: en\\n\"\n\n#: theme.hbs:3\nmsgid \"welcome\"\n\
+             msgstr \"Welcome, \"\n\"{name} {not a var}\"\n\nmsgid \"login\"\nmsgstr \"\"\n\n\
+             msgid \"items\"\nmsgid_plural \"items\"\nmsgstr[0] \"{n} item\"\nmsgstr[1] \"{n} items\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            en.len(),
+            2,
+            "untranslated entries and the header are skipped"
+        );
+
+        let catalog = Catalog::from_messages(
+            BTreeMap::from([("de".to_owned(), de), ("en".to_owned(), en)]),
+            "en",
+        );
+        let args = json!({ "name": "Ada", "n": 3 });
+        let de = catalog.localized("de-AT");
+        assert_eq!(de.t("welcome", &args), "Willkommen bei Whisper, Ada!");
+        assert_eq!(de.t("login.title", &args), "Bei Whisper anmelden");
+        assert_eq!(de.t("footer", &args), "Zeile eins\n\nZeile {zwei");
+        assert_eq!(de.t("items", &args), "3 item", "falls back to the default");
+        assert_eq!(de.t("missing", &args), "missing");
+        let en = catalog.localized("fr");
+        assert_eq!(en.t("welcome", &args), "Welcome, Ada {not a var}");
+        assert_eq!(en.t("welcome", &json!({})), "Welcome, {$name} {not a var}");
+
+        for bad in [
+            "n = { $count ->\n    *[other] many\n}\n",
+            "n = { NUMBER($count) }\n",
+            "n = { $open\n",
+            "  indented = outside\n",
+        ] {
+            assert!(parse_ftl(ftl, bad).is_err(), "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn templates_translate_with_hash_or_object_arguments() {
+        let catalog = Catalog::from_messages(
+            BTreeMap::from([(
+                "en".to_owned(),
+                Messages::from([("hi".to_owned(), "Hi, { $name } <3".to_owned())]),
+            )]),
+            "en",
+        );
+        let mut hbs = Handlebars::new();
+        hbs.register_helper("t", Box::new(TranslateHelper::new(catalog.localized("en"))));
+        let data = json!({ "user": { "name": "Ada" } });
+        assert_eq!(
+            hbs.render_template(
+                r#"{{t "hi" name=user.name}}|{{t "hi" user}}|{{t "nope"}}"#,
+                &data
+            )
+            .unwrap(),
+            "Hi, Ada &lt;3|Hi, Ada &lt;3|nope"
+        );
+    }
+}
@@ -1,3 +1,24 @@
+pub mod assets;
+pub mod authors;
+pub mod blocks;
+pub mod cache;
+pub mod collections;
+pub mod comments;
+pub mod error_page;
+pub mod feeds;
+pub mod flags;
+pub mod forms;
+pub mod front_matter;
+pub mod i18n;
+pub mod images;
 pub mod indexer;
+pub mod l10n;
+pub mod maintenance;
+pub mod permalinks;
+pub mod related;
 pub mod render;
+pub mod request_id;
 pub mod resolver;
+pub mod search;
+pub mod sitemap;
+pub mod taxonomy;
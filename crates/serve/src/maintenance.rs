@@ -0,0 +1,255 @@
+// crates/serve/src/maintenance.rs
+
+// Scheduled maintenance windows.
+//
+// Windows are declared ahead of time in settings.toml:
+//
+//   [[maintenance.windows]]
+//   start = "2026-11-01T02:00:00Z"
+//   end = "2026-11-01T03:30:00Z"
+//   message = "Upgrading the database."
+//
+// The site is in maintenance while `start <= now < end`, so entering and
+// leaving needs no restart or operator action. During a window every request
+// gets a 503 page with the window's message and a `Retry-After` for its end.
+// Every response, maintenance or not, lists the current and upcoming windows
+// in `X-Maintenance-Windows` so monitoring can suppress alerts for them.
+//
+// Operators can also start maintenance on the spot by writing one window to
+// `maintenance.toml` in the site directory (same fields as above) and end it
+// by deleting the file; the server checks for it while running.
+//
+// Or, with no end in sight, turn it on in settings.toml until it is turned
+// off again:
+//
+//   [maintenance]
+//   enabled = true
+//   message = "Moving to a new host."
+//
+// which is answered as a window that always ends `OPEN_ENDED_RETRY` from now.
+// Themes may style the page with a `maintenance.hbs` rendered with
+// `page_model`.
+
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use domain::setting::MaintenanceWindow;
+use serde_json::{json, Value as Json};
+use std::io;
+use std::path::Path;
+use tracing::warn;
+
+/// Response header listing current and upcoming windows as comma-separated
+/// RFC 3339 intervals, `start/end`.
+pub const WINDOWS_HEADER: &str = "x-maintenance-windows";
+
+/// Most windows listed in `WINDOWS_HEADER`.
+pub const MAX_ADVERTISED_WINDOWS: usize = 5;
+
+/// Manual maintenance window, relative to the site directory.
+pub const MANUAL_FILE: &str = "maintenance.toml";
+
+/// Theme template for the 503 page.
+pub const PAGE_TEMPLATE: &str = "maintenance.hbs";
+
+/// `Retry-After` of maintenance without an end.
+pub const OPEN_ENDED_RETRY: Duration = Duration::minutes(5);
+
+const DEFAULT_MESSAGE: &str = "The site is down for scheduled maintenance.";
+
+/// Every declared window, ordered by start.
+#[derive(Debug, Clone, Default)]
+pub struct MaintenanceSchedule {
+    windows: Vec<MaintenanceWindow>,
+}
+
+impl MaintenanceSchedule {
+    /// Windows that end before they start are dropped with a warning.
+    pub fn new(windows: Vec<MaintenanceWindow>) -> Self {
+        let mut windows: Vec<MaintenanceWindow> = windows
+            .into_iter()
+            .filter(|w| {
+                let valid = w.start < w.end;
+                if !valid {
+                    warn!(
+                        "Ignoring maintenance window {} – {}: it ends before it starts",
+                        w.start, w.end
+                    );
+                }
+                valid
+            })
+            .collect();
+        windows.sort_by_key(|w| (w.start, w.end));
+        Self { windows }
+    }
+
+    /// The window in effect at `now`.
+    pub fn active(&self, now: DateTime<Utc>) -> Option<&MaintenanceWindow> {
+        self.windows.iter().find(|w| w.start <= now && now < w.end)
+    }
+
+    /// Windows that have not ended by `now`, the active one first.
+    pub fn upcoming(&self, now: DateTime<Utc>) -> impl Iterator<Item = &MaintenanceWindow> {
+        self.windows.iter().filter(move |w| now < w.end)
+    }
+
+    /// The `WINDOWS_HEADER` value at `now`; `None` when nothing is scheduled.
+    pub fn header_value(&self, now: DateTime<Utc>) -> Option<String> {
+        let intervals: Vec<String> = self
+            .upcoming(now)
+            .take(MAX_ADVERTISED_WINDOWS)
+            .map(|w| format!("{}/{}", rfc3339(w.start), rfc3339(w.end)))
+            .collect();
+        (!intervals.is_empty()).then(|| intervals.join(", "))
+    }
+}
+
+/// Whole seconds from `now` until `window` ends, at least 1.
+pub fn retry_after_secs(window: &MaintenanceWindow, now: DateTime<Utc>) -> u64 {
+    let secs = (window.end - now).num_seconds();
+    u64::try_from(secs).unwrap_or(0).max(1)
+}
+
+/// Maintenance without an end, as a window; see `OPEN_ENDED_RETRY`.
+pub fn open_ended(message: Option<String>, now: DateTime<Utc>) -> MaintenanceWindow {
+    MaintenanceWindow {
+        start: now,
+        end: now + OPEN_ENDED_RETRY,
+        message,
+    }
+}
+
+/// The model of a theme's `PAGE_TEMPLATE`.
+pub fn page_model(window: &MaintenanceWindow, now: DateTime<Utc>) -> Json {
+    json!({
+        "message": window.message.as_deref().unwrap_or(DEFAULT_MESSAGE),
+        "end": rfc3339(window.end),
+        "retry_after": retry_after_secs(window, now),
+    })
+}
+
+/// The 503 page shown during `window`.
+pub fn page_html(window: &MaintenanceWindow) -> String {
+    let message = window.message.as_deref().unwrap_or(DEFAULT_MESSAGE);
+    let end = rfc3339(window.end);
+    format!(
+        "<!doctype html>\n<html>\n<head><meta charset=\"utf-8\"><title>Down for maintenance</title></head>\n\
+         <body>\n<h1>Down for maintenance</h1>\n<p>{}</p>\n\
+         <p>Expected back by <time datetime=\"{end}\">{end}</time>.</p>\n</body>\n</html>\n",
+        html_escape::encode_text(message)
+    )
+}
+
+/// The window in `path` (see `MANUAL_FILE`); `None` when there is no file or
+/// it cannot be read.
+pub fn read_manual(path: &Path) -> Option<MaintenanceWindow> {
+    let src = match std::fs::read_to_string(path) {
+        Ok(src) => src,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+        Err(e) => {
+            warn!("Reading {:?} failed: {}", path, e);
+            return None;
+        }
+    };
+    toml::from_str(&src)
+        .inspect_err(|e| warn!("Ignoring {:?}: {}", path, e))
+        .ok()
+}
+
+/// Start manual maintenance by writing `window` to `path`.
+pub fn write_manual(path: &Path, window: &MaintenanceWindow) -> io::Result<()> {
+    let src = toml::to_string(window).map_err(io::Error::other)?;
+    std::fs::write(path, src)
+}
+
+fn rfc3339(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use domain::setting::MaintenanceSettings;
+
+    fn at(h: u32, m: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 11, 1, h, m, 0).unwrap()
+    }
+
+    fn window(
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        message: Option<&str>,
+    ) -> MaintenanceWindow {
+        MaintenanceWindow {
+            start,
+            end,
+            message: message.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn windows_are_active_from_start_until_end() {
+        let schedule = MaintenanceSchedule::new(vec![
+            window(at(4, 0), at(5, 0), None),
+            window(at(2, 0), at(3, 30), Some("db")),
+            window(at(6, 0), at(5, 0), None),
+        ]);
+
+        assert!(schedule.active(at(1, 59)).is_none());
+        assert_eq!(
+            schedule.active(at(2, 0)).unwrap().message.as_deref(),
+            Some("db")
+        );
+        assert!(schedule.active(at(3, 30)).is_none());
+        assert!(schedule.active(at(4, 30)).is_some());
+
+        assert_eq!(
+            schedule.header_value(at(3, 0)).as_deref(),
+            Some("2026-11-01T02:00:00Z/2026-11-01T03:30:00Z, 2026-11-01T04:00:00Z/2026-11-01T05:00:00Z")
+        );
+        assert_eq!(schedule.header_value(at(5, 0)), None);
+    }
+
+    #[test]
+    fn the_page_shows_the_message_and_retry_after_counts_to_the_end() {
+        let w = window(at(2, 0), at(3, 0), Some("Moving <servers>"));
+        let page = page_html(&w);
+        assert!(page.contains("<p>Moving &lt;servers&gt;</p>"));
+        assert!(page.contains("2026-11-01T03:00:00Z"));
+        assert_eq!(retry_after_secs(&w, at(2, 59)), 60);
+        assert_eq!(retry_after_secs(&w, at(3, 0)), 1);
+
+        assert!(page_html(&window(at(2, 0), at(3, 0), None)).contains(DEFAULT_MESSAGE));
+
+        let model = page_model(&open_ended(None, at(2, 0)), at(2, 0));
+        assert_eq!(model["message"], DEFAULT_MESSAGE);
+        assert_eq!(model["end"], "2026-11-01T02:05:00Z");
+        assert_eq!(model["retry_after"], 300);
+    }
+
+    #[test]
+    fn manual_windows_round_trip_through_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(MANUAL_FILE);
+        assert_eq!(read_manual(&path), None);
+
+        let w = window(at(2, 0), at(3, 0), Some("Restoring a backup"));
+        write_manual(&path, &w).unwrap();
+        assert_eq!(read_manual(&path), Some(w));
+
+        std::fs::write(&path, "start = 1").unwrap();
+        assert_eq!(read_manual(&path), None);
+    }
+
+    #[test]
+    fn settings_take_quoted_rfc3339_times() {
+        let settings: MaintenanceSettings = toml::from_str(
+            r#"
+            [[windows]]
+            start = "2026-11-01T02:00:00Z"
+            end = "2026-11-01T05:00:00+02:00"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(settings.windows[0], window(at(2, 0), at(3, 0), None));
+    }
+}
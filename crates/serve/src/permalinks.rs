@@ -0,0 +1,364 @@
+// crates/serve/src/permalinks.rs
+
+// URLs built from front matter instead of the file layout. A `[permalinks]`
+// table gives a pattern per content `type`:
+//
+//   [permalinks]
+//   post = "/:year/:month/:slug/"
+//   page = "/:section/:slug"
+//
+//   posts/hello.md  (type = "post", slug = "hello", publish.date = 2024-03-09…)
+//     → /2024/03/hello/
+//
+// Tokens take whole segments: `:year`, `:month` and `:day` come from
+// `publish.date`; `:slug` from `slug`, else the file name; `:section` from
+// `content.section`, else the first folder of the served path; `:type` from
+// `type`; `:lang` from `i18n.lang`, empty for the site's default language.
+// An empty `:section` or `:lang` drops its segment, so `/about.html` with
+// the page pattern above is `/about`. A document missing any other token, such
+// as an undated post, keeps its served path.
+//
+// Every pattern must contain `:slug`. The resolver reads the slug out of a
+// request path, looks the document up by it, and takes the document only
+// if its permalink is that path, so slugs should be unique among documents
+// with permalinks. Themes link with the `permalink` helper, which falls
+// back to the served path:
+//
+//   <a href="{{permalink this}}">{{content.title}}</a>
+
+use handlebars::{
+    Context, Handlebars, Helper, HelperDef, RenderContext, RenderError as HbsError, ScopedJson,
+};
+use serde_json::Value as Json;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("permalink pattern for {kind:?}: {reason}")]
+pub struct PermalinkError {
+    pub kind: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Token(Token),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    Year,
+    Month,
+    Day,
+    Slug,
+    Section,
+    Type,
+    Lang,
+}
+
+impl Token {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "year" => Token::Year,
+            "month" => Token::Month,
+            "day" => Token::Day,
+            "slug" => Token::Slug,
+            "section" => Token::Section,
+            "type" => Token::Type,
+            "lang" => Token::Lang,
+            _ => return None,
+        })
+    }
+
+    /// The value for the document `fm` describes; `Some("")` drops the
+    /// segment, `None` means no permalink.
+    fn value(self, fm: &Json, default_lang: Option<&str>) -> Option<String> {
+        let str_at = |path: &[&str]| {
+            path.iter()
+                .try_fold(fm, |v, key| v.get(key))
+                .and_then(Json::as_str)
+                .filter(|s| !s.is_empty())
+        };
+        let served = str_at(&["id"]).unwrap_or_default();
+        let date = || {
+            let date = str_at(&["publish", "date"])?.get(..10)?;
+            let valid = date.as_bytes()[4] == b'-'
+                && date.as_bytes()[7] == b'-'
+                && date.bytes().filter(u8::is_ascii_digit).count() == 8;
+            valid.then_some(date)
+        };
+        match self {
+            Token::Year => date().map(|d| d[0..4].to_owned()),
+            Token::Month => date().map(|d| d[5..7].to_owned()),
+            Token::Day => date().map(|d| d[8..10].to_owned()),
+            Token::Slug => str_at(&["slug"]).map(str::to_owned).or_else(|| {
+                let name = served.rsplit('/').next()?;
+                let stem = name.split('.').next().filter(|s| !s.is_empty())?;
+                Some(stem.to_owned())
+            }),
+            Token::Section => Some(
+                str_at(&["content", "section"])
+                    .map(str::to_owned)
+                    .unwrap_or_else(|| {
+                        let mut parts = served.trim_start_matches('/').split('/');
+                        match (parts.next(), parts.next()) {
+                            (Some(folder), Some(_)) => folder.to_owned(),
+                            _ => String::new(),
+                        }
+                    }),
+            ),
+            Token::Type => str_at(&["type"]).map(str::to_owned),
+            Token::Lang => Some(
+                str_at(&["i18n", "lang"])
+                    .filter(|lang| Some(*lang) != default_lang)
+                    .unwrap_or_default()
+                    .to_owned(),
+            ),
+        }
+    }
+}
+
+/// One content type's pattern.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Pattern {
+    segments: Vec<Segment>,
+    trailing_slash: bool,
+}
+
+impl Pattern {
+    fn parse(kind: &str, pattern: &str) -> Result<Self, PermalinkError> {
+        let error = |reason: String| PermalinkError {
+            kind: kind.to_owned(),
+            reason,
+        };
+        if !pattern.starts_with('/') {
+            return Err(error(format!("{pattern:?} must start with '/'")));
+        }
+        let mut segments = Vec::new();
+        for part in pattern.split('/').filter(|p| !p.is_empty()) {
+            let segment = match part.strip_prefix(':') {
+                Some(name) => Segment::Token(
+                    Token::parse(name).ok_or_else(|| error(format!("unknown token ':{name}'")))?,
+                ),
+                None if part.contains(':') => {
+                    return Err(error(format!("'{part}' mixes text and a token")))
+                }
+                None => Segment::Literal(part.to_owned()),
+            };
+            segments.push(segment);
+        }
+        if !segments.contains(&Segment::Token(Token::Slug)) {
+            return Err(error(format!("{pattern:?} has no ':slug'")));
+        }
+        Ok(Self {
+            segments,
+            trailing_slash: pattern.len() > 1 && pattern.ends_with('/'),
+        })
+    }
+
+    /// The permalink of the document `fm` describes, if it has every token.
+    fn generate(&self, fm: &Json, default_lang: Option<&str>) -> Option<String> {
+        let mut out = String::new();
+        for segment in &self.segments {
+            let value = match segment {
+                Segment::Literal(text) => text.clone(),
+                Segment::Token(token) => token.value(fm, default_lang)?,
+            };
+            if !value.is_empty() {
+                out.push('/');
+                out.push_str(&value);
+            }
+        }
+        if out.is_empty() || self.trailing_slash {
+            out.push('/');
+        }
+        Some(out)
+    }
+
+    /// The `:slug` segment of `path`, counted from the end: only literals
+    /// follow it, so a dropped `:section` does not move it.
+    fn slug_in<'p>(&self, path: &'p str) -> Option<&'p str> {
+        let after = self
+            .segments
+            .iter()
+            .rev()
+            .position(|s| *s == Segment::Token(Token::Slug))?;
+        path.split('/').filter(|p| !p.is_empty()).rev().nth(after)
+    }
+}
+
+/// Every content type's pattern. Empty (the default) leaves URLs to the
+/// file layout.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Permalinks {
+    by_type: BTreeMap<String, Pattern>,
+    default_lang: Option<String>,
+}
+
+impl Permalinks {
+    /// From the `[permalinks]` table: pattern by content type.
+    pub fn from_settings(settings: &BTreeMap<String, String>) -> Result<Self, PermalinkError> {
+        let by_type = settings
+            .iter()
+            .map(|(kind, pattern)| Ok((kind.clone(), Pattern::parse(kind, pattern)?)))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            by_type,
+            default_lang: None,
+        })
+    }
+
+    /// Documents in `lang` get no `:lang` segment.
+    pub fn with_default_lang(mut self, lang: impl Into<String>) -> Self {
+        self.default_lang = Some(lang.into());
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_type.is_empty()
+    }
+
+    /// The permalink of the document `fm` (an index record) describes, when
+    /// its type has a pattern and it has every token.
+    pub fn for_document(&self, fm: &Json) -> Option<String> {
+        let kind = fm.get("type").and_then(Json::as_str)?;
+        self.by_type
+            .get(kind)?
+            .generate(fm, self.default_lang.as_deref())
+    }
+
+    /// Where to link to the document `fm` describes: its permalink, else
+    /// its served path `id`.
+    pub fn url_for(&self, fm: &Json) -> Option<String> {
+        self.for_document(fm)
+            .or_else(|| fm.get("id").and_then(Json::as_str).map(str::to_owned))
+    }
+
+    /// Slugs `path` could be the permalink of, one per distinct candidate.
+    pub fn candidate_slugs<'p>(&self, path: &'p str) -> Vec<&'p str> {
+        let mut slugs: Vec<&str> = Vec::new();
+        for pattern in self.by_type.values() {
+            if let Some(slug) = pattern.slug_in(path) {
+                if !slugs.contains(&slug) {
+                    slugs.push(slug);
+                }
+            }
+        }
+        slugs
+    }
+}
+
+/// Whether `a` and `b` are the same permalink; a trailing slash does not
+/// count.
+pub fn same_path(a: &str, b: &str) -> bool {
+    let trim = |p: &str| p.trim_end_matches('/').to_owned();
+    trim(a) == trim(b)
+}
+
+/// `{{permalink doc}}`: where to link to the document `doc` (an index
+/// record, such as a collection item or `content_meta`).
+#[derive(Debug, Clone, Default)]
+pub struct PermalinkHelper {
+    permalinks: Arc<Permalinks>,
+}
+
+impl PermalinkHelper {
+    pub fn new(permalinks: Arc<Permalinks>) -> Self {
+        Self { permalinks }
+    }
+}
+
+impl HelperDef for PermalinkHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, HbsError> {
+        let url = match h.param(0).map(|p| p.value()) {
+            Some(Json::String(path)) => Some(path.clone()),
+            Some(doc) => self.permalinks.url_for(doc),
+            None => None,
+        };
+        Ok(ScopedJson::Derived(url.map_or(Json::Null, Json::String)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn permalinks() -> Permalinks {
+        Permalinks::from_settings(&BTreeMap::from([
+            ("post".to_owned(), "/:year/:month/:slug/".to_owned()),
+            ("page".to_owned(), "/:section/:slug".to_owned()),
+            ("doc".to_owned(), "/:lang/docs/:slug".to_owned()),
+        ]))
+        .unwrap()
+        .with_default_lang("en")
+    }
+
+    #[test]
+    fn permalinks_come_from_front_matter_and_lead_back_to_it() {
+        let permalinks = permalinks();
+        let post = json!({
+            "id": "/posts/hello.html", "type": "post", "slug": "hello",
+            "publish": { "date": "2024-03-09T10:00:00Z" }
+        });
+        let page = json!({ "id": "/about.html", "type": "page" });
+        let team = json!({ "id": "/about/team.html", "type": "page" });
+        let undated = json!({ "id": "/posts/draft.html", "type": "post" });
+        let note = json!({ "id": "/notes/x.html", "type": "note" });
+
+        assert_eq!(
+            permalinks.for_document(&post).as_deref(),
+            Some("/2024/03/hello/")
+        );
+        assert_eq!(permalinks.for_document(&page).as_deref(), Some("/about"));
+        assert_eq!(
+            permalinks.for_document(&team).as_deref(),
+            Some("/about/team")
+        );
+        assert_eq!(permalinks.for_document(&undated), None);
+        for (lang, url) in [("en", "/docs/intro"), ("de", "/de/docs/intro")] {
+            let doc = json!({ "id": "/d.html", "type": "doc", "slug": "intro", "i18n": { "lang": lang } });
+            assert_eq!(permalinks.for_document(&doc).as_deref(), Some(url));
+        }
+        assert_eq!(permalinks.url_for(&note).as_deref(), Some("/notes/x.html"));
+
+        assert_eq!(permalinks.candidate_slugs("/2024/03/hello/"), ["hello"]);
+        assert_eq!(permalinks.candidate_slugs("/about"), ["about"]);
+        assert!(same_path("/2024/03/hello", "/2024/03/hello/"));
+        assert!(Permalinks::default().candidate_slugs("/x").is_empty());
+
+        for bad in ["2024/:slug", "/:year/:title", "/p-:slug", "/:year"] {
+            let settings = BTreeMap::from([("post".to_owned(), bad.to_owned())]);
+            assert!(Permalinks::from_settings(&settings).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn templates_link_through_the_helper() {
+        let mut hbs = Handlebars::new();
+        hbs.register_helper(
+            "permalink",
+            Box::new(PermalinkHelper::new(Arc::new(permalinks()))),
+        );
+        let data = json!({
+            "items": [
+                { "id": "/posts/a.html", "type": "post", "slug": "a",
+                  "publish": { "date": "2023-12-01" } },
+                { "id": "/feed.json", "type": "data" }
+            ]
+        });
+        let tpl = r#"{{#each items}}{{permalink this}};{{/each}}{{permalink "/x"}}"#;
+        assert_eq!(
+            hbs.render_template(tpl, &data).unwrap(),
+            "/2023/12/a/;/feed.json;/x"
+        );
+    }
+}
@@ -0,0 +1,202 @@
+// crates/serve/src/related.rs
+
+// Related posts: the other dated documents sharing the most tags and
+// categories with one, newer ones ahead of older ones. Each shared tag and
+// category adds its weight from `[related]`, and the sum halves every
+// `half_life_days` of the candidate's age:
+//
+//   score = (shared tags × tag_weight + shared categories × category_weight)
+//           × 0.5 ^ (age in days / half_life_days)
+//
+// Every candidate's score decays at the same rate, so the order does not
+// change as time passes and a ranking stays good until the content does.
+// Translations of the document and documents in other languages are not
+// related to it. The host finds the candidates and keeps the rankings;
+// templates see them as `related`:
+//
+//   {{#each related}}<a href="{{path}}">{{title}}</a>{{/each}}
+
+use chrono::{DateTime, NaiveDate, Utc};
+use domain::setting::RelatedSettings;
+use serde_json::{json, Value as Json};
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+
+/// The tags and categories of a document.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Terms {
+    pub tags: BTreeSet<String>,
+    pub categories: BTreeSet<String>,
+}
+
+impl Terms {
+    pub fn of(doc: &Json) -> Self {
+        let set = |pointer: &str| match doc.pointer(pointer) {
+            Some(Json::Array(items)) => items
+                .iter()
+                .filter_map(Json::as_str)
+                .map(str::to_owned)
+                .collect(),
+            Some(Json::String(s)) => BTreeSet::from([s.clone()]),
+            _ => BTreeSet::new(),
+        };
+        Self {
+            tags: set("/tax/tags"),
+            categories: set("/tax/categories"),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty() && self.categories.is_empty()
+    }
+
+    /// Whether a document with `other` could be related to one with these.
+    pub fn overlap(&self, other: &Terms) -> bool {
+        !self.tags.is_disjoint(&other.tags) || !self.categories.is_disjoint(&other.categories)
+    }
+}
+
+fn str_at<'a>(doc: &'a Json, pointer: &str) -> Option<&'a str> {
+    doc.pointer(pointer).and_then(Json::as_str)
+}
+
+/// `publish.date` as RFC 3339 or a bare `YYYY-MM-DD` (midnight UTC).
+fn published(doc: &Json) -> Option<DateTime<Utc>> {
+    let raw = str_at(doc, "/publish/date")?.trim();
+    DateTime::parse_from_rfc3339(raw)
+        .map(|d| d.with_timezone(&Utc))
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(raw, "%Y-%m-%d")
+                .ok()
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+                .map(|d| d.and_utc())
+        })
+}
+
+/// The id shared by a document's translations.
+fn group(doc: &Json) -> Option<&str> {
+    str_at(doc, "/i18n/canonical_id").or_else(|| str_at(doc, "/id"))
+}
+
+/// How related `candidate` is to a document with `terms`, as of `now`;
+/// 0 when it shares nothing or has no date.
+pub fn score(
+    terms: &Terms,
+    candidate: &Json,
+    settings: &RelatedSettings,
+    now: DateTime<Utc>,
+) -> f64 {
+    let Some(date) = published(candidate) else {
+        return 0.0;
+    };
+    let theirs = Terms::of(candidate);
+    let shared = terms.tags.intersection(&theirs.tags).count() as f64 * settings.tag_weight
+        + terms.categories.intersection(&theirs.categories).count() as f64
+            * settings.category_weight;
+    if shared <= 0.0 || settings.half_life_days <= 0.0 {
+        return shared.max(0.0);
+    }
+    let age_days = (now - date).num_seconds().max(0) as f64 / 86_400.0;
+    shared * 0.5_f64.powf(age_days / settings.half_life_days)
+}
+
+/// The `settings.limit` candidates most related to `doc`, best first; ties
+/// go to the newer.
+pub fn rank<'a>(
+    doc: &Json,
+    candidates: &'a [Json],
+    settings: &RelatedSettings,
+    now: DateTime<Utc>,
+) -> Vec<(f64, &'a Json)> {
+    let terms = Terms::of(doc);
+    let lang = str_at(doc, "/i18n/lang");
+    let mut ranked: Vec<(f64, &Json)> = candidates
+        .iter()
+        .filter(|c| group(c) != group(doc) && str_at(c, "/i18n/lang") == lang)
+        .map(|c| (score(&terms, c, settings, now), c))
+        .filter(|(score, _)| *score > 0.0)
+        .collect();
+    ranked.sort_by(|(a, x), (b, y)| {
+        b.partial_cmp(a)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| published(y).cmp(&published(x)))
+            .then_with(|| str_at(x, "/id").cmp(&str_at(y, "/id")))
+    });
+    ranked.truncate(settings.limit);
+    ranked
+}
+
+/// What templates see of one related post, `path` being its permalink or
+/// served path: `{ id, path, title, date, summary, score }`.
+pub fn entry(path: &str, doc: &Json, score: f64) -> Json {
+    json!({
+        "id": str_at(doc, "/id"),
+        "path": path,
+        "title": str_at(doc, "/content/title").unwrap_or(path),
+        "date": str_at(doc, "/publish/date"),
+        "summary": str_at(doc, "/content/summary"),
+        "score": score,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn post(id: &str, date: &str, tags: &[&str], categories: &[&str]) -> Json {
+        json!({
+            "id": id,
+            "publish": { "date": date },
+            "tax": { "tags": tags, "categories": categories },
+        })
+    }
+
+    #[test]
+    fn shared_terms_rank_and_age_decays_the_score() {
+        let now = DateTime::parse_from_rfc3339("2025-01-01T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let settings = RelatedSettings {
+            limit: 3,
+            ..RelatedSettings::default()
+        };
+        let doc = post("/a.html", "2024-12-01", &["rust", "wasm"], &["dev"]);
+        let candidates = vec![
+            doc.clone(),
+            post("/tags.html", "2024-12-31", &["rust", "wasm"], &[]),
+            post("/old.html", "2024-01-01", &["rust", "wasm"], &[]),
+            post("/cat.html", "2025-01-01", &[], &["dev"]),
+            post("/none.html", "2025-01-01", &["go"], &["ops"]),
+            json!({ "id": "/undated.html", "tax": { "tags": ["rust"] } }),
+            json!({
+                "id": "/de/a.html",
+                "publish": { "date": "2025-01-01" },
+                "i18n": { "canonical_id": "/a.html", "lang": "de" },
+                "tax": { "tags": ["rust"] },
+            }),
+        ];
+        let ranked = rank(&doc, &candidates, &settings, now);
+        let ids: Vec<&str> = ranked
+            .iter()
+            .map(|(_, d)| d["id"].as_str().unwrap())
+            .collect();
+        assert_eq!(ids, ["/cat.html", "/tags.html", "/old.html"]);
+        assert!((ranked[0].0 - 2.0).abs() < 1e-9);
+        assert!(ranked[2].0 < 1.1 && ranked[2].0 > 0.9);
+
+        let undecayed = RelatedSettings {
+            half_life_days: 0.0,
+            ..settings
+        };
+        assert_eq!(
+            score(&Terms::of(&doc), &candidates[2], &undecayed, now),
+            2.0
+        );
+        assert!(Terms::of(&doc).overlap(&Terms::of(&candidates[3])));
+        assert_eq!(
+            entry("/2024/cat/", &candidates[3], 2.0)["title"],
+            "/2024/cat/"
+        );
+    }
+}
@@ -0,0 +1,141 @@
+// crates/serve/src/render/csp.rs
+
+// A `Content-Security-Policy` that several parties can add to without
+// clobbering each other. The site sets a base policy (`[security_headers]`
+// in edge), and a plugin that needs a CDN for its scripts adds that source
+// with an `appendSource` header patch instead of setting the header:
+//
+//   base     default-src 'self'; script-src 'self'
+//   plugin   script-src https://cdn.example.com; img-src data:
+//   sent     default-src 'self'; script-src 'self' https://cdn.example.com; img-src data:
+//
+// Merging only ever widens a directive, so the most permissive party wins;
+// the site decides which plugins run at all.
+
+use std::fmt;
+
+/// Directives in the order they were first named, each with its sources.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ContentSecurityPolicy {
+    directives: Vec<(String, Vec<String>)>,
+}
+
+impl ContentSecurityPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read a header value; empty and repeated directives are folded in.
+    pub fn parse(value: &str) -> Self {
+        let mut csp = Self::new();
+        for directive in value.split(';') {
+            let mut tokens = directive.split_ascii_whitespace();
+            if let Some(name) = tokens.next() {
+                csp.add_sources(name, tokens);
+            }
+        }
+        csp
+    }
+
+    /// Builder form of [`ContentSecurityPolicy::add_sources`].
+    pub fn with<I, S>(mut self, directive: &str, sources: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.add_sources(directive, sources);
+        self
+    }
+
+    /// Allow `sources` for `directive`, adding the directive if it is new.
+    /// A real source replaces `'none'`, which would contradict it.
+    pub fn add_sources<I, S>(&mut self, directive: &str, sources: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let name = directive.to_ascii_lowercase();
+        let index = match self.directives.iter().position(|(n, _)| *n == name) {
+            Some(i) => i,
+            None => {
+                self.directives.push((name, Vec::new()));
+                self.directives.len() - 1
+            }
+        };
+        let existing = &mut self.directives[index].1;
+        for source in sources {
+            let source = source.as_ref();
+            if source == "'none'" {
+                if existing.is_empty() {
+                    existing.push(source.to_owned());
+                }
+                continue;
+            }
+            existing.retain(|s| s != "'none'");
+            if !existing.iter().any(|s| s == source) {
+                existing.push(source.to_owned());
+            }
+        }
+    }
+
+    /// Add every source of `other`, directive by directive.
+    pub fn merge(&mut self, other: &ContentSecurityPolicy) {
+        for (name, sources) in &other.directives {
+            self.add_sources(name, sources);
+        }
+    }
+
+    /// The sources of `directive`, if it is set.
+    pub fn sources(&self, directive: &str) -> Option<&[String]> {
+        let name = directive.to_ascii_lowercase();
+        self.directives
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, s)| s.as_slice())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.directives.is_empty()
+    }
+}
+
+impl fmt::Display for ContentSecurityPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (name, sources)) in self.directives.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            f.write_str(name)?;
+            for source in sources {
+                write!(f, " {source}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sources_are_added_once_and_replace_none() {
+        let mut csp = ContentSecurityPolicy::new()
+            .with("frame-ancestors", ["'self'"])
+            .with("object-src", ["'none'"]);
+        csp.merge(&ContentSecurityPolicy::parse(
+            " Frame-Ancestors 'self' https://embed.example.com ;; object-src https://a.example",
+        ));
+        csp.add_sources("upgrade-insecure-requests", [""; 0]);
+        assert_eq!(
+            csp.to_string(),
+            "frame-ancestors 'self' https://embed.example.com; object-src https://a.example; \
+             upgrade-insecure-requests"
+        );
+        assert_eq!(
+            csp.sources("FRAME-ANCESTORS").unwrap(),
+            ["'self'", "https://embed.example.com"]
+        );
+        assert!(ContentSecurityPolicy::parse(" ; ").is_empty());
+    }
+}
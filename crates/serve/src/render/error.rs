@@ -32,3 +32,9 @@ impl From<handlebars::TemplateError> for RenderError {
         RenderError::Template(e.to_string())
     }
 }
+
+impl From<tera::Error> for RenderError {
+    fn from(e: tera::Error) -> Self {
+        RenderError::Template(e.to_string())
+    }
+}
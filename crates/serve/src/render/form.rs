@@ -0,0 +1,508 @@
+// crates/serve/src/render/form.rs
+
+// Shared form builder for server-rendered admin / installer forms.
+//
+// A `FormSpec` renders either as a complete HTML page or as the bare form
+// fragment (for HTMX partial swaps). Both paths produce identical markup for
+// the form itself so validation errors look the same whichever way the form
+// was requested:
+//
+// - every control has a `<label for>`;
+// - help text and errors are wired through `aria-describedby`;
+// - invalid controls carry `aria-invalid="true"`;
+// - an error summary with `role="alert"` links to each invalid field.
+
+use html_escape::{encode_double_quoted_attribute as attr, encode_text as text};
+use http::HeaderMap;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+
+/// Header HTMX sets on every request it issues.
+pub const HX_REQUEST_HEADER: &str = "hx-request";
+
+/// Returns true when the request came from HTMX and only the fragment
+/// should be sent back.
+pub fn wants_fragment(headers: &HeaderMap) -> bool {
+    headers
+        .get(HX_REQUEST_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Form definition
+// ─────────────────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum FieldKind {
+    Text,
+    Email,
+    Password,
+    Url,
+    Number,
+    TextArea { rows: u16 },
+    Select { options: Vec<(String, String)> },
+    Checkbox,
+    Hidden,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldSpec {
+    pub name: String,
+    pub label: String,
+    pub kind: FieldKind,
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default)]
+    pub help: Option<String>,
+    #[serde(default)]
+    pub autocomplete: Option<String>,
+}
+
+impl FieldSpec {
+    pub fn new(name: impl Into<String>, label: impl Into<String>, kind: FieldKind) -> Self {
+        Self {
+            name: name.into(),
+            label: label.into(),
+            kind,
+            required: false,
+            help: None,
+            autocomplete: None,
+        }
+    }
+
+    pub fn required(mut self) -> Self {
+        self.required = true;
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    pub fn with_autocomplete(mut self, token: impl Into<String>) -> Self {
+        self.autocomplete = Some(token.into());
+        self
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FormSpec {
+    /// DOM id of the `<form>`; also the prefix for every control id.
+    pub id: String,
+    pub title: String,
+    pub action: String,
+    #[serde(default = "default_method")]
+    pub method: String,
+    pub fields: Vec<FieldSpec>,
+    #[serde(default = "default_submit_label")]
+    pub submit_label: String,
+}
+
+fn default_method() -> String {
+    "post".into()
+}
+
+fn default_submit_label() -> String {
+    "Save".into()
+}
+
+impl FormSpec {
+    pub fn new(id: impl Into<String>, title: impl Into<String>, action: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            title: title.into(),
+            action: action.into(),
+            method: default_method(),
+            fields: Vec::new(),
+            submit_label: default_submit_label(),
+        }
+    }
+
+    pub fn with_field(mut self, field: FieldSpec) -> Self {
+        self.fields.push(field);
+        self
+    }
+
+    pub fn with_submit_label(mut self, label: impl Into<String>) -> Self {
+        self.submit_label = label.into();
+        self
+    }
+
+    fn control_id(&self, field: &FieldSpec) -> String {
+        format!("{}-{}", self.id, field.name)
+    }
+
+    /// Check `required` constraints against submitted values.
+    ///
+    /// Callers layer their own domain checks on top by adding to the
+    /// returned `FormErrors`.
+    pub fn validate_required(&self, values: &FormValues) -> FormErrors {
+        let mut errors = FormErrors::default();
+        for field in self.fields.iter().filter(|f| f.required) {
+            let present = values
+                .get(&field.name)
+                .map(|v| !v.trim().is_empty())
+                .unwrap_or(false);
+            if !present {
+                errors.add(&field.name, format!("{} is required.", field.label));
+            }
+        }
+        errors
+    }
+
+    /// Render only the `<form>` element (HTMX partial response).
+    pub fn render_fragment(&self, values: &FormValues, errors: &FormErrors) -> String {
+        let mut out = String::new();
+        let _ = write!(
+            out,
+            r#"<form id="{id}" action="{action}" method="{method}" hx-{method}="{action}" hx-target="this" hx-swap="outerHTML" novalidate"#,
+            id = attr(&self.id),
+            action = attr(&self.action),
+            method = attr(&self.method.to_ascii_lowercase()),
+        );
+        if !errors.is_empty() {
+            let _ = write!(out, r#" aria-describedby="{}-errors""#, attr(&self.id));
+        }
+        out.push('>');
+
+        self.render_error_summary(errors, &mut out);
+
+        for field in &self.fields {
+            self.render_field(field, values.get(&field.name), errors, &mut out);
+        }
+
+        let _ = write!(
+            out,
+            r#"<div class="form-actions"><button type="submit">{}</button></div></form>"#,
+            text(&self.submit_label)
+        );
+        out
+    }
+
+    /// Render a complete, standalone HTML document wrapping the fragment.
+    pub fn render_page(&self, values: &FormValues, errors: &FormErrors) -> String {
+        let title = if errors.is_empty() {
+            self.title.clone()
+        } else {
+            format!("Error: {}", self.title)
+        };
+        format!(
+            concat!(
+                "<!DOCTYPE html><html lang=\"en\"><head><meta charset=\"utf-8\">",
+                "<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">",
+                "<title>{title}</title></head><body><main id=\"main\">",
+                "<h1>{heading}</h1>{form}</main></body></html>"
+            ),
+            title = text(&title),
+            heading = text(&self.title),
+            form = self.render_fragment(values, errors),
+        )
+    }
+
+    /// Render the page or the fragment depending on who is asking.
+    pub fn render_for(
+        &self,
+        headers: &HeaderMap,
+        values: &FormValues,
+        errors: &FormErrors,
+    ) -> String {
+        if wants_fragment(headers) {
+            self.render_fragment(values, errors)
+        } else {
+            self.render_page(values, errors)
+        }
+    }
+
+    fn render_error_summary(&self, errors: &FormErrors, out: &mut String) {
+        if errors.is_empty() {
+            return;
+        }
+
+        let _ = write!(
+            out,
+            r#"<div id="{}-errors" class="form-errors" role="alert" tabindex="-1"><p>Please correct the following:</p><ul>"#,
+            attr(&self.id)
+        );
+        for msg in &errors.form {
+            let _ = write!(out, "<li>{}</li>", text(msg));
+        }
+        for field in &self.fields {
+            for msg in errors.for_field(&field.name) {
+                let _ = write!(
+                    out,
+                    r##"<li><a href="#{}">{}</a></li>"##,
+                    attr(&self.control_id(field)),
+                    text(msg)
+                );
+            }
+        }
+        out.push_str("</ul></div>");
+    }
+
+    fn render_field(
+        &self,
+        field: &FieldSpec,
+        value: Option<&String>,
+        errors: &FormErrors,
+        out: &mut String,
+    ) {
+        let id = self.control_id(field);
+        let value = value.map(String::as_str).unwrap_or("");
+
+        if field.kind == FieldKind::Hidden {
+            let _ = write!(
+                out,
+                r#"<input type="hidden" id="{}" name="{}" value="{}">"#,
+                attr(&id),
+                attr(&field.name),
+                attr(value)
+            );
+            return;
+        }
+
+        let field_errors = errors.for_field(&field.name);
+        let help_id = format!("{id}-help");
+        let error_id = format!("{id}-error");
+
+        let mut described_by = Vec::new();
+        if field.help.is_some() {
+            described_by.push(help_id.as_str());
+        }
+        if !field_errors.is_empty() {
+            described_by.push(error_id.as_str());
+        }
+
+        let mut common = format!(r#" id="{}" name="{}""#, attr(&id), attr(&field.name));
+        if field.required {
+            common.push_str(r#" required aria-required="true""#);
+        }
+        if !field_errors.is_empty() {
+            common.push_str(r#" aria-invalid="true""#);
+        }
+        if !described_by.is_empty() {
+            let _ = write!(common, r#" aria-describedby="{}""#, described_by.join(" "));
+        }
+        if let Some(token) = &field.autocomplete {
+            let _ = write!(common, r#" autocomplete="{}""#, attr(token));
+        }
+
+        let _ = write!(out, r#"<div class="form-field">"#);
+
+        let label = format!(
+            r#"<label for="{}">{}{}</label>"#,
+            attr(&id),
+            text(&field.label),
+            if field.required {
+                r#" <span aria-hidden="true">*</span>"#
+            } else {
+                ""
+            }
+        );
+
+        match &field.kind {
+            FieldKind::Checkbox => {
+                let checked = matches!(value, "on" | "true" | "1");
+                let _ = write!(
+                    out,
+                    r#"<input type="checkbox"{common}{}> {label}"#,
+                    if checked { " checked" } else { "" }
+                );
+            }
+            FieldKind::TextArea { rows } => {
+                let _ = write!(
+                    out,
+                    r#"{label}<textarea{common} rows="{rows}">{}</textarea>"#,
+                    text(value)
+                );
+            }
+            FieldKind::Select { options } => {
+                let _ = write!(out, "{label}<select{common}>");
+                for (opt_value, opt_label) in options {
+                    let _ = write!(
+                        out,
+                        r#"<option value="{}"{}>{}</option>"#,
+                        attr(opt_value),
+                        if opt_value == value { " selected" } else { "" },
+                        text(opt_label)
+                    );
+                }
+                out.push_str("</select>");
+            }
+            kind => {
+                let input_type = match kind {
+                    FieldKind::Email => "email",
+                    FieldKind::Password => "password",
+                    FieldKind::Url => "url",
+                    FieldKind::Number => "number",
+                    _ => "text",
+                };
+                // Never echo passwords back into the page.
+                let shown = if input_type == "password" { "" } else { value };
+                let _ = write!(
+                    out,
+                    r#"{label}<input type="{input_type}"{common} value="{}">"#,
+                    attr(shown)
+                );
+            }
+        }
+
+        if let Some(help) = &field.help {
+            let _ = write!(
+                out,
+                r#"<p id="{}" class="form-help">{}</p>"#,
+                attr(&help_id),
+                text(help)
+            );
+        }
+        if !field_errors.is_empty() {
+            let _ = write!(out, r#"<p id="{}" class="form-error">"#, attr(&error_id));
+            out.push_str(
+                &field_errors
+                    .iter()
+                    .map(|m| text(m).into_owned())
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            );
+            out.push_str("</p>");
+        }
+
+        out.push_str("</div>");
+    }
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Submitted values + validation errors
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Submitted (or pre-filled) values keyed by field name.
+pub type FormValues = BTreeMap<String, String>;
+
+/// Validation errors, both per-field and form-wide.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FormErrors {
+    pub fields: BTreeMap<String, Vec<String>>,
+    pub form: Vec<String>,
+}
+
+impl FormErrors {
+    pub fn add(&mut self, field: &str, msg: impl Into<String>) {
+        self.fields
+            .entry(field.to_string())
+            .or_default()
+            .push(msg.into());
+    }
+
+    pub fn add_form(&mut self, msg: impl Into<String>) {
+        self.form.push(msg.into());
+    }
+
+    pub fn for_field(&self, field: &str) -> &[String] {
+        self.fields.get(field).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.form.is_empty() && self.fields.values().all(Vec::is_empty)
+    }
+
+    pub fn merge(&mut self, other: FormErrors) {
+        for (field, msgs) in other.fields {
+            self.fields.entry(field).or_default().extend(msgs);
+        }
+        self.form.extend(other.form);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::HeaderValue;
+
+    fn login_form() -> FormSpec {
+        FormSpec::new("login", "Sign in", "/admin/login")
+            .with_field(
+                FieldSpec::new("email", "Email", FieldKind::Email)
+                    .required()
+                    .with_autocomplete("username"),
+            )
+            .with_field(
+                FieldSpec::new("password", "Password", FieldKind::Password)
+                    .required()
+                    .with_help("At least 12 characters."),
+            )
+            .with_submit_label("Sign in")
+    }
+
+    #[test]
+    fn fragment_labels_every_control() {
+        let html = login_form().render_fragment(&FormValues::new(), &FormErrors::default());
+        assert!(html.starts_with(r#"<form id="login""#));
+        assert!(html.contains(r#"<label for="login-email">"#));
+        assert!(html.contains(r#"id="login-email" name="email" required aria-required="true""#));
+        assert!(html.contains(r#"aria-describedby="login-password-help""#));
+        assert!(!html.contains("role=\"alert\""));
+        assert!(!html.contains("<html"));
+    }
+
+    #[test]
+    fn required_validation_marks_fields_invalid_and_summarises() {
+        let form = login_form();
+        let mut values = FormValues::new();
+        values.insert("email".into(), "a@b.c".into());
+
+        let errors = form.validate_required(&values);
+        assert_eq!(errors.for_field("password"), ["Password is required."]);
+        assert!(errors.for_field("email").is_empty());
+
+        let html = form.render_fragment(&values, &errors);
+        assert!(html.contains(r#"role="alert""#));
+        assert!(html.contains(r##"<a href="#login-password">Password is required.</a>"##));
+        assert!(html.contains(
+            r#"aria-invalid="true" aria-describedby="login-password-help login-password-error""#
+        ));
+        assert!(html.contains(r#"value="a@b.c""#));
+    }
+
+    #[test]
+    fn passwords_are_never_echoed() {
+        let mut values = FormValues::new();
+        values.insert("password".into(), "hunter2".into());
+        let html = login_form().render_fragment(&values, &FormErrors::default());
+        assert!(!html.contains("hunter2"));
+    }
+
+    #[test]
+    fn values_and_labels_are_escaped() {
+        let form = FormSpec::new("f", "T", "/x").with_field(FieldSpec::new(
+            "q",
+            "<b>Q</b>",
+            FieldKind::Text,
+        ));
+        let mut values = FormValues::new();
+        values.insert("q".into(), r#""><script>"#.into());
+
+        let html = form.render_fragment(&values, &FormErrors::default());
+        assert!(!html.contains("<script>"));
+        assert!(!html.contains("<b>Q</b>"));
+    }
+
+    #[test]
+    fn render_for_switches_on_hx_request() {
+        let form = login_form();
+        let values = FormValues::new();
+        let errors = FormErrors::default();
+
+        let page = form.render_for(&HeaderMap::new(), &values, &errors);
+        assert!(page.starts_with("<!DOCTYPE html>"));
+        assert!(page.contains(&form.render_fragment(&values, &errors)));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(HX_REQUEST_HEADER, HeaderValue::from_static("true"));
+        let fragment = form.render_for(&headers, &values, &errors);
+        assert!(fragment.starts_with("<form"));
+    }
+}
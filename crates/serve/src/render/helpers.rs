@@ -0,0 +1,355 @@
+// crates/serve/src/render/helpers.rs
+
+// Theme building blocks for Handlebars templates.
+//
+// Rendering a `.hbs` template from a theme's template root registers:
+//
+// - every `partials/**/*.hbs` below the root as a partial named by its path
+//   without the extension, so `partials/cards/post.hbs` is `{{> cards/post}}`;
+// - the built-in helpers `format_date`, `slugify` and `t`;
+// - any helpers added through a `HelperSet`, which win over built-ins of the
+//   same name.
+//
+// `t` looks keys up in `i18n/<lang>.toml` below the root, e.g.
+// `{{t "nav.home"}}` reads `[nav] home = "…"`. The language comes from a
+// `lang=` hash argument, else `lang` on the model root, else `en`; a missing
+// key renders as the key itself.
+
+use super::error::RenderError;
+use chrono::{DateTime, NaiveDate};
+use handlebars::{
+    Context, Handlebars, Helper, HelperDef, HelperResult, Output, RenderContext,
+    RenderError as HbsError, RenderErrorReason, ScopedJson,
+};
+use serde_json::Value as Json;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+/// A helper shared between renders.
+pub type SharedHelper = Arc<dyn HelperDef + Send + Sync>;
+
+/// Custom helpers added by Rust code on top of the built-ins.
+#[derive(Clone, Default)]
+pub struct HelperSet {
+    helpers: Vec<(String, SharedHelper)>,
+}
+
+impl HelperSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_helper(
+        mut self,
+        name: impl Into<String>,
+        helper: impl HelperDef + Send + Sync + 'static,
+    ) -> Self {
+        self.register(name, helper);
+        self
+    }
+
+    /// Add `helper` as `name`, replacing an earlier helper of that name.
+    pub fn register(
+        &mut self,
+        name: impl Into<String>,
+        helper: impl HelperDef + Send + Sync + 'static,
+    ) {
+        let name = name.into();
+        self.helpers.retain(|(n, _)| *n != name);
+        self.helpers.push((name, Arc::new(helper)));
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.helpers.iter().map(|(n, _)| n.as_str())
+    }
+
+    pub(crate) fn register_into(&self, hbs: &mut Handlebars<'_>) {
+        for (name, helper) in &self.helpers {
+            hbs.register_helper(name, Box::new(Shared(helper.clone())));
+        }
+    }
+}
+
+impl std::fmt::Debug for HelperSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.names()).finish()
+    }
+}
+
+/// Registers a `SharedHelper` with a registry that wants a `Box`.
+struct Shared(SharedHelper);
+
+impl HelperDef for Shared {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        r: &'reg Handlebars<'reg>,
+        ctx: &'rc Context,
+        rc: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, HbsError> {
+        self.0.call_inner(h, r, ctx, rc)
+    }
+
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        r: &'reg Handlebars<'reg>,
+        ctx: &'rc Context,
+        rc: &mut RenderContext<'reg, 'rc>,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        self.0.call(h, r, ctx, rc, out)
+    }
+}
+
+/// Register the partials, built-in helpers and `custom` helpers of the
+/// theme whose templates live in `template_root`.
+pub(crate) fn register_theme(
+    hbs: &mut Handlebars<'_>,
+    template_root: &Path,
+    custom: &HelperSet,
+) -> Result<(), RenderError> {
+    register_partials(hbs, &template_root.join("partials"))?;
+    hbs.register_helper("format_date", Box::new(FormatDate));
+    hbs.register_helper("slugify", Box::new(Slugify));
+    hbs.register_helper(
+        "t",
+        Box::new(Translate {
+            i18n_dir: template_root.join("i18n"),
+        }),
+    );
+    custom.register_into(hbs);
+    Ok(())
+}
+
+/// Register every `*.hbs` below `dir`; a missing directory has none.
+pub(crate) fn register_partials(hbs: &mut Handlebars<'_>, dir: &Path) -> Result<(), RenderError> {
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        let entries = match fs::read_dir(&current) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(RenderError::Io(e)),
+        };
+
+        for entry in entries {
+            let path = entry.map_err(RenderError::Io)?.path();
+            if path.is_dir() {
+                pending.push(path);
+                continue;
+            }
+            if path.extension().and_then(|e| e.to_str()) != Some("hbs") {
+                continue;
+            }
+
+            let Ok(rel) = path
+                .with_extension("")
+                .strip_prefix(dir)
+                .map(Path::to_path_buf)
+            else {
+                continue;
+            };
+            let name = rel
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            let src = fs::read_to_string(&path).map_err(RenderError::Io)?;
+            hbs.register_partial(&name, src)
+                .map_err(RenderError::from)?;
+        }
+    }
+    Ok(())
+}
+
+fn param_str<'a>(h: &'a Helper<'_>, idx: usize) -> Option<&'a str> {
+    h.param(idx).and_then(|p| p.value().as_str())
+}
+
+/// `{{format_date date "%B %e, %Y"}}`: an RFC 3339 or `YYYY-MM-DD` date in
+/// a strftime format (default `%Y-%m-%d`). Unparseable input is written
+/// unchanged.
+#[derive(Clone, Copy)]
+pub struct FormatDate;
+
+impl HelperDef for FormatDate {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        let Some(raw) = param_str(h, 0) else {
+            return Ok(());
+        };
+        let fmt = param_str(h, 1).unwrap_or("%Y-%m-%d");
+
+        let formatted = match DateTime::parse_from_rfc3339(raw.trim()) {
+            Ok(at) => at.format(fmt).to_string(),
+            Err(_) => match NaiveDate::parse_from_str(raw.trim(), "%Y-%m-%d") {
+                Ok(day) => day.format(fmt).to_string(),
+                Err(_) => raw.to_owned(),
+            },
+        };
+        out.write(&formatted)?;
+        Ok(())
+    }
+}
+
+/// `{{slugify title}}`: lower-case ASCII letters and digits joined by `-`.
+#[derive(Clone, Copy)]
+pub struct Slugify;
+
+impl HelperDef for Slugify {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        out.write(&slugify(param_str(h, 0).unwrap_or_default()))?;
+        Ok(())
+    }
+}
+
+pub fn slugify(s: &str) -> String {
+    let mut slug = String::with_capacity(s.len());
+    for c in s.chars().flat_map(char::to_lowercase) {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.truncate(slug.trim_end_matches('-').len());
+    slug
+}
+
+/// `{{t "key"}}` / `{{t "key" lang="de"}}`; see the module comment.
+struct Translate {
+    i18n_dir: std::path::PathBuf,
+}
+
+impl HelperDef for Translate {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        ctx: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        let key =
+            param_str(h, 0).ok_or_else(|| RenderErrorReason::ParamNotFoundForIndex("t", 0))?;
+        let lang = h
+            .hash_get("lang")
+            .and_then(|v| v.value().as_str())
+            .or_else(|| ctx.data().get("lang").and_then(Json::as_str))
+            .unwrap_or("en");
+
+        let text = self.lookup(lang, key);
+        out.write(text.as_deref().unwrap_or(key))?;
+        Ok(())
+    }
+}
+
+impl Translate {
+    fn lookup(&self, lang: &str, key: &str) -> Option<String> {
+        // The language is template data; keep it to one file name.
+        if lang.is_empty()
+            || !lang
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return None;
+        }
+        let src = fs::read_to_string(self.i18n_dir.join(format!("{lang}.toml"))).ok()?;
+        let table: toml::Table = toml::from_str(&src).ok()?;
+
+        let mut parts = key.split('.');
+        let mut value = table.get(parts.next()?)?;
+        for part in parts {
+            value = value.as_table()?.get(part)?;
+        }
+        value.as_str().map(str::to_owned)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use handlebars::handlebars_helper;
+    use serde_json::json;
+
+    fn render(root: &Path, custom: &HelperSet, template: &str, model: &Json) -> String {
+        let mut hbs = Handlebars::new();
+        register_theme(&mut hbs, root, custom).unwrap();
+        hbs.register_template_string("page", template).unwrap();
+        hbs.render("page", model).unwrap()
+    }
+
+    #[test]
+    fn partials_are_registered_by_relative_path() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("partials/cards")).unwrap();
+        fs::write(dir.path().join("partials/header.hbs"), "<h1>{{title}}</h1>").unwrap();
+        fs::write(
+            dir.path().join("partials/cards/post.hbs"),
+            "<article>{{this}}</article>",
+        )
+        .unwrap();
+        fs::write(dir.path().join("partials/notes.txt"), "ignored").unwrap();
+
+        let html = render(
+            dir.path(),
+            &HelperSet::new(),
+            "{{> header}}{{#each posts}}{{> cards/post}}{{/each}}",
+            &json!({ "title": "Hi", "posts": ["a", "b"] }),
+        );
+        assert_eq!(html, "<h1>Hi</h1><article>a</article><article>b</article>");
+    }
+
+    #[test]
+    fn built_in_helpers_format_slugify_and_translate() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("i18n")).unwrap();
+        fs::write(
+            dir.path().join("i18n/de.toml"),
+            "[nav]\nhome = \"Startseite\"\n",
+        )
+        .unwrap();
+
+        let html = render(
+            dir.path(),
+            &HelperSet::new(),
+            r#"{{format_date date "%d.%m.%Y"}}|{{format_date "soon"}}|{{slugify title}}|{{t "nav.home"}}|{{t "nav.home" lang="en"}}"#,
+            &json!({ "date": "2024-03-05T10:00:00Z", "title": "Hello, World & Co!", "lang": "de" }),
+        );
+        assert_eq!(html, "05.03.2024|soon|hello-world-co|Startseite|nav.home");
+    }
+
+    #[test]
+    fn custom_helpers_override_built_ins() {
+        handlebars_helper!(shout: |s: str| s.to_uppercase());
+        handlebars_helper!(fixed_slug: |_s: str| "slug");
+
+        let custom = HelperSet::new()
+            .with_helper("shout", shout)
+            .with_helper("slugify", fixed_slug);
+        let dir = tempfile::tempdir().unwrap();
+
+        let html = render(
+            dir.path(),
+            &custom,
+            "{{shout name}} {{slugify name}}",
+            &json!({ "name": "ada" }),
+        );
+        assert_eq!(html, "ADA slug");
+    }
+}
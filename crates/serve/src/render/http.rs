@@ -1,19 +1,21 @@
 // crates/serve/src/render/http.rs
 
+use crate::l10n::Localized;
 use crate::render::error::RenderError;
+use crate::render::islands::{embed_islands, Island};
 use crate::render::pipeline::{render_html_string_to, render_html_template_to, render_json_to};
 use crate::render::recommendation::BodyPatch;
 use crate::render::recommendation::Recommendations;
 use crate::render::template::TemplateRegistry;
+use crate::request_id;
 use bytes::Bytes;
 use http::{HeaderMap, HeaderValue, StatusCode};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value as Json};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 use thiserror::Error;
 use tracing::debug;
-use uuid::Uuid;
 
 #[derive(Debug, Error)]
 pub enum ContextError {
@@ -139,8 +141,14 @@ pub enum ResponseBodySpec {
     /// Theme explicitly requested "no body" (used by ctx_bridge).
     None,
 
-    /// Render a template with a JSON model (HTML responses).
-    HtmlTemplate { template: String, model: Json },
+    /// Render a template with a JSON model (HTML responses), then embed
+    /// the props of any client-hydrated `islands`.
+    HtmlTemplate {
+        template: String,
+        model: Json,
+        #[serde(default)]
+        islands: Vec<Island>,
+    },
 
     /// Raw HTML string (not recommended for large bodies).
     HtmlString(String),
@@ -187,7 +195,11 @@ impl ResponseSpec {
     }
 
     pub fn set_html_template(&mut self, template: String, model: Json) {
-        self.body = ResponseBodySpec::HtmlTemplate { template, model };
+        self.body = ResponseBodySpec::HtmlTemplate {
+            template,
+            model,
+            islands: Vec::new(),
+        };
     }
 
     pub fn set_html_string<S: Into<String>>(&mut self, html: S) {
@@ -244,7 +256,11 @@ pub fn render_body_with_templates(
             })
         }
 
-        ResponseBodySpec::HtmlTemplate { template, model } => {
+        ResponseBodySpec::HtmlTemplate {
+            template,
+            model,
+            islands,
+        } => {
             let registry = registry.ok_or_else(|| {
                 RenderError::Template(format!(
                     "HtmlTemplate requested for `{template}`, but no TemplateRegistry provided"
@@ -254,6 +270,11 @@ pub fn render_body_with_templates(
             let mut buf = Vec::new();
             // Note: `model` is already a `serde_json::Value`, which implements Serialize.
             render_html_template_to(registry, template, model, body_patches, &mut buf)?;
+            if !islands.is_empty() {
+                let html = String::from_utf8(buf)
+                    .map_err(|e| RenderError::Template(format!("non-UTF-8 output: {e}")))?;
+                buf = embed_islands(&html, islands)?.into_bytes();
+            }
 
             Ok(RenderedBody {
                 bytes: buf,
@@ -292,17 +313,42 @@ pub fn status_for_render_error(_err: &RenderError) -> StatusCode {
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct RequestContext {
-    pub req_id: Json, // UUID as JSON string
+    pub req_id: Json, // ULID as JSON string
     pub req_path: Json,
     pub req_method: Json,
     pub req_version: Json,
     pub req_headers: Json,
     pub req_params: Json,
 
+    /// The site's absolute URL for this request, without a trailing slash:
+    /// the configured `base_url`, else the scheme and host the request
+    /// used. For absolute links (`ctx.request.baseUrl` in JS).
+    #[serde(default)]
+    pub base_url: Option<String>,
+
+    /// The `{name}` segments of the plugin route the request matched, if it
+    /// matched one (`ctx.request.routeParams` in JS).
+    #[serde(default)]
+    pub route_params: BTreeMap<String, String>,
+
     pub content_meta: Json, // includes frontmatter etc.
     pub theme_config: Json,
     pub plugin_configs: HashMap<String, Json>,
 
+    /// Feature flags decided for this request (see `crate::flags`).
+    #[serde(default)]
+    pub flags: BTreeMap<String, bool>,
+
+    /// Saved queries resolved for this request: name → matching documents
+    /// (see `crate::collections`).
+    #[serde(default)]
+    pub collections: BTreeMap<String, Json>,
+
+    /// Catalog strings in the reader's language, by theme or plugin id
+    /// (see `crate::l10n`); each sees its own as `ctx.t` in JS.
+    #[serde(skip)]
+    pub messages: BTreeMap<String, Arc<Localized>>,
+
     #[serde(skip)]
     pub req_body: Option<Bytes>, // opaque HTTP request stream
 
@@ -316,7 +362,7 @@ pub struct RequestContext {
 impl RequestContext {
     /// Convenience constructor that wires through to the builder.
     ///
-    /// Note: `req_id` is auto-generated as a UUID JSON string.
+    /// Note: `req_id` is auto-generated as a ULID JSON string.
     #[allow(clippy::too_many_arguments)]
     pub fn new(
         req_path: Json,
@@ -367,14 +413,20 @@ impl RequestContext {
 
 #[derive(Default, Debug, Clone)]
 pub struct RequestContextBuilder {
+    pub req_id: Option<String>,
     pub req_path: Json,
     pub req_method: Json,
     pub req_version: Json,
     pub req_headers: Json,
     pub req_params: Json,
+    pub base_url: Option<String>,
+    pub route_params: BTreeMap<String, String>,
     pub content_meta: Json,
     pub theme_config: Json,
     pub plugin_configs: HashMap<String, Json>,
+    pub flags: BTreeMap<String, bool>,
+    pub collections: BTreeMap<String, Json>,
+    pub messages: BTreeMap<String, Arc<Localized>>,
     pub req_body: Option<Bytes>,
     pub content_body: Option<Arc<String>>,
 }
@@ -384,6 +436,12 @@ impl RequestContextBuilder {
         Self::default()
     }
 
+    /// The request's id; a new one when not given.
+    pub fn req_id(mut self, id: impl Into<String>) -> Self {
+        self.req_id = Some(id.into());
+        self
+    }
+
     pub fn path(mut self, v: impl Into<Json>) -> Self {
         self.req_path = v.into();
         self
@@ -409,6 +467,16 @@ impl RequestContextBuilder {
         self
     }
 
+    pub fn base_url(mut self, v: impl Into<String>) -> Self {
+        self.base_url = Some(v.into());
+        self
+    }
+
+    pub fn route_params(mut self, params: BTreeMap<String, String>) -> Self {
+        self.route_params = params;
+        self
+    }
+
     pub fn content_meta(mut self, v: impl Into<Json>) -> Self {
         self.content_meta = v.into();
         self
@@ -429,6 +497,21 @@ impl RequestContextBuilder {
         self
     }
 
+    pub fn flags(mut self, flags: BTreeMap<String, bool>) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    pub fn collections(mut self, collections: BTreeMap<String, Json>) -> Self {
+        self.collections = collections;
+        self
+    }
+
+    pub fn messages(mut self, messages: BTreeMap<String, Arc<Localized>>) -> Self {
+        self.messages = messages;
+        self
+    }
+
     pub fn req_body(mut self, s: Bytes) -> Self {
         self.req_body = Some(s);
         self
@@ -451,7 +534,7 @@ impl RequestContextBuilder {
 
     pub fn build(self) -> RequestContext {
         RequestContext {
-            req_id: Json::String(Uuid::new_v4().to_string()),
+            req_id: Json::String(self.req_id.unwrap_or_else(request_id::generate)),
             req_path: self.req_path,
             req_method: self.req_method,
             req_version: match self.req_version.is_null() {
@@ -460,9 +543,14 @@ impl RequestContextBuilder {
             },
             req_headers: self.req_headers,
             req_params: self.req_params,
+            base_url: self.base_url,
+            route_params: self.route_params,
             content_meta: self.content_meta,
             theme_config: self.theme_config,
             plugin_configs: self.plugin_configs,
+            flags: self.flags,
+            collections: self.collections,
+            messages: self.messages,
             req_body: self.req_body,
             content_body: self.content_body,
             recommendations: Recommendations::default(),
@@ -0,0 +1,173 @@
+// crates/serve/src/render/images.rs
+
+// Final pass over rendered HTML that makes `<img>` tags cheaper to lay out
+// and load:
+//
+//   <img src="/media/cat.jpg">
+//     → <img src="/media/cat.jpg" width="1600" height="1200"
+//            loading="lazy" decoding="async"
+//            srcset="/media/cat.jpg?w=640 640w, /media/cat.jpg 1600w">
+//
+// The caller's lookup decides which sources are local and how large they
+// are; other images only get the loading attributes. Attributes a template
+// already set are never overwritten.
+
+use super::error::RenderError;
+use domain::setting::ImageSettings;
+use lol_html::{element, html_content::Element, rewrite_str, Settings};
+use std::cell::Cell;
+
+/// Which attributes the pass adds.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImagePolicy {
+    /// Add `loading="lazy"` and `decoding="async"`.
+    pub lazy_loading: bool,
+    /// Leading images that keep the browser's default eager loading.
+    pub eager_images: usize,
+    /// Derivative widths offered in `srcset`; none when empty.
+    pub srcset_widths: Vec<u32>,
+}
+
+impl ImagePolicy {
+    pub fn from_settings(settings: &ImageSettings) -> Self {
+        Self {
+            lazy_loading: settings.lazy_loading,
+            eager_images: settings.eager_images,
+            srcset_widths: if settings.srcset {
+                settings.widths.clone()
+            } else {
+                Vec::new()
+            },
+        }
+    }
+
+    /// Whether the pass would leave every page untouched.
+    pub fn is_noop(&self) -> bool {
+        !self.lazy_loading && self.srcset_widths.is_empty()
+    }
+}
+
+/// `html` with dimension, loading and `srcset` attributes added to its
+/// images. `lookup` maps a `src` to the intrinsic width and height of a
+/// local image, or `None`.
+pub fn annotate_images<F>(
+    html: &str,
+    policy: &ImagePolicy,
+    lookup: F,
+) -> Result<String, RenderError>
+where
+    F: Fn(&str) -> Option<(u32, u32)>,
+{
+    let seen = Cell::new(0usize);
+    let settings = Settings {
+        element_content_handlers: vec![element!("img[src]", |el: &mut Element| {
+            let index = seen.get();
+            seen.set(index + 1);
+            let src = el.get_attribute("src").unwrap_or_default();
+            let size = lookup(&src);
+
+            if let Some((w, h)) = size {
+                if !el.has_attribute("width") && !el.has_attribute("height") {
+                    el.set_attribute("width", &w.to_string())?;
+                    el.set_attribute("height", &h.to_string())?;
+                }
+            }
+
+            if policy.lazy_loading {
+                if index >= policy.eager_images && !el.has_attribute("loading") {
+                    el.set_attribute("loading", "lazy")?;
+                }
+                if !el.has_attribute("decoding") {
+                    el.set_attribute("decoding", "async")?;
+                }
+            }
+
+            // Derivatives of a derivative would be resized twice.
+            if let (Some((w, _)), false) = (size, src.contains('?')) {
+                if !el.has_attribute("srcset") {
+                    if let Some(srcset) = srcset(&src, w, &policy.srcset_widths) {
+                        el.set_attribute("srcset", &srcset)?;
+                    }
+                }
+            }
+            Ok(())
+        })],
+        ..Settings::default()
+    };
+    rewrite_str(html, settings).map_err(|e| RenderError::LolHtml(e.to_string()))
+}
+
+/// Candidates for each width below the original's, then the original.
+fn srcset(src: &str, intrinsic_width: u32, widths: &[u32]) -> Option<String> {
+    let mut smaller: Vec<u32> = widths
+        .iter()
+        .copied()
+        .filter(|w| *w < intrinsic_width)
+        .collect();
+    if smaller.is_empty() {
+        return None;
+    }
+    smaller.sort_unstable();
+    smaller.dedup();
+    let mut candidates: Vec<String> = smaller
+        .into_iter()
+        .map(|w| format!("{src}?w={w} {w}w"))
+        .collect();
+    candidates.push(format!("{src} {intrinsic_width}w"));
+    Some(candidates.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local(src: &str) -> Option<(u32, u32)> {
+        src.starts_with("/media/").then_some((1600, 1200))
+    }
+
+    #[test]
+    fn local_images_get_dimensions_and_all_but_the_first_are_lazy() {
+        let policy = ImagePolicy {
+            lazy_loading: true,
+            eager_images: 1,
+            srcset_widths: Vec::new(),
+        };
+        let html = r#"<img src="/media/a.jpg"><img src="https://cdn.example/b.png">"#;
+        let out = annotate_images(html, &policy, local).unwrap();
+        assert_eq!(
+            out,
+            r#"<img src="/media/a.jpg" width="1600" height="1200" decoding="async"><img src="https://cdn.example/b.png" loading="lazy" decoding="async">"#
+        );
+    }
+
+    #[test]
+    fn template_attributes_win() {
+        let policy = ImagePolicy {
+            lazy_loading: true,
+            eager_images: 0,
+            srcset_widths: vec![640],
+        };
+        let html = r#"<img src="/media/a.jpg" width="10" loading="eager" srcset="x 1x">"#;
+        let out = annotate_images(html, &policy, local).unwrap();
+        assert_eq!(
+            out,
+            r#"<img src="/media/a.jpg" width="10" loading="eager" srcset="x 1x" decoding="async">"#
+        );
+    }
+
+    #[test]
+    fn srcset_lists_smaller_widths_then_the_original() {
+        assert_eq!(
+            srcset("/media/a.jpg", 1600, &[1280, 320, 2000, 320]).as_deref(),
+            Some("/media/a.jpg?w=320 320w, /media/a.jpg?w=1280 1280w, /media/a.jpg 1600w")
+        );
+        assert_eq!(srcset("/media/a.jpg", 300, &[320]), None);
+
+        let policy = ImagePolicy {
+            srcset_widths: vec![640],
+            ..ImagePolicy::default()
+        };
+        let out = annotate_images(r#"<img src="/media/a.jpg?w=800">"#, &policy, local).unwrap();
+        assert!(!out.contains("srcset"), "{out}");
+    }
+}
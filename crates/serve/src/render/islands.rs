@@ -0,0 +1,294 @@
+// crates/serve/src/render/islands.rs
+
+// Interactive islands: parts of a server-rendered page that a script brings
+// to life in the browser. A theme marks where each one goes and returns its
+// props alongside the body:
+//
+//   // theme JS
+//   ctx.response.body = {
+//     kind: "htmlTemplate",
+//     template: "post.hbs",
+//     model,
+//     islands: [
+//       { component: "Comments", props: { postId: 42 } },
+//       { component: "Rating", id: "rating", props: { stars: 4 } },
+//     ],
+//   };
+//
+//   <!-- post.hbs -->
+//   <div data-island="comments-0">…server-rendered fallback…</div>
+//   <div data-island="rating"></div>
+//
+// An island without an `id` gets the component name in kebab case and its
+// position among islands of that component, so ids stay the same from one
+// render to the next. `embed_islands` then adds, before `</body>`:
+//
+//   <script type="application/json" id="wc-island-comments-0"
+//           data-island-component="Comments">{"postId":42}</script>
+//   …
+//   <script src="/_whisper/islands.js" defer></script>
+//
+// and the loader (`LOADER_JS`) hands each marked element and its props to
+// whatever the theme's own script registers for the component:
+//
+//   WhisperIslands.register("Comments", (el, props) => { … });
+//
+// Scripts that run before the loader queue their components instead:
+//
+//   (window.WhisperIslands ??= { queue: [] }).queue.push(["Comments", fn]);
+//
+// Registration may happen before or after the page loads; an island whose
+// component never registers keeps its server-rendered markup.
+
+use super::error::RenderError;
+use lol_html::{element, html_content::ContentType, rewrite_str, Settings};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+use std::cell::Cell;
+use std::collections::HashMap;
+
+/// Attribute a template puts on the element an island hydrates.
+pub const ISLAND_ATTR: &str = "data-island";
+
+/// Prefix of the id on each island's props script.
+pub const PROPS_ID_PREFIX: &str = "wc-island-";
+
+/// Where the host serves `LOADER_JS`.
+pub const LOADER_PATH: &str = "/_whisper/islands.js";
+
+/// Client runtime that hydrates islands.
+pub const LOADER_JS: &str = r#"(function (global) {
+    var registry = {};
+    var hydrated = new WeakSet();
+
+    function hydrate(name) {
+        var selector = 'script[type="application/json"][data-island-component]';
+        document.querySelectorAll(selector).forEach(function (script) {
+            var component = script.getAttribute("data-island-component");
+            if ((name && component !== name) || !registry[component]) return;
+            var id = script.id.slice("wc-island-".length);
+            var el = document.querySelector('[data-island="' + CSS.escape(id) + '"]');
+            if (!el || hydrated.has(el)) return;
+            hydrated.add(el);
+            try {
+                registry[component](el, JSON.parse(script.textContent || "null"));
+                el.setAttribute("data-island-hydrated", "");
+            } catch (e) {
+                console.error("Hydrating island " + id + " failed", e);
+            }
+        });
+    }
+
+    var queued = (global.WhisperIslands && global.WhisperIslands.queue) || [];
+    global.WhisperIslands = {
+        register: function (name, fn) {
+            registry[name] = fn;
+            if (document.readyState !== "loading") hydrate(name);
+        },
+    };
+    queued.forEach(function (args) {
+        global.WhisperIslands.register(args[0], args[1]);
+    });
+    if (document.readyState === "loading") {
+        document.addEventListener("DOMContentLoaded", function () { hydrate(); });
+    } else {
+        hydrate();
+    }
+})(window);
+"#;
+
+/// One client-hydrated component, as the theme returned it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Island {
+    /// Name the client script registers, e.g. `Comments`.
+    pub component: String,
+    /// Value of the `data-island` attribute that marks the element; see
+    /// `assign_ids` when the theme leaves it out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// Anything JSON can hold.
+    #[serde(default)]
+    pub props: Json,
+}
+
+impl Island {
+    pub fn new(component: impl Into<String>, props: Json) -> Self {
+        Self {
+            component: component.into(),
+            id: None,
+            props,
+        }
+    }
+
+    pub fn with_id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+}
+
+/// Islands from the `islands` array of a theme's response body. Entries
+/// without a `component` are skipped.
+pub fn islands_from_json(value: Option<&Json>) -> Vec<Island> {
+    value
+        .and_then(Json::as_array)
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|v| serde_json::from_value::<Island>(v.clone()).ok())
+                .filter(|i| !i.component.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Fill in missing ids with `<component-in-kebab-case>-<n>`, where `n`
+/// counts earlier islands of the same component.
+pub fn assign_ids(islands: &mut [Island]) {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for island in islands.iter_mut() {
+        let n = seen.entry(island.component.clone()).or_default();
+        if island.id.is_none() {
+            island.id = Some(format!("{}-{}", kebab_case(&island.component), n));
+        }
+        *n += 1;
+    }
+}
+
+/// `html` with a props script per island and the loader added before
+/// `</body>`, or at the end when there is no body element. Unchanged
+/// when there are no islands.
+pub fn embed_islands(html: &str, islands: &[Island]) -> Result<String, RenderError> {
+    if islands.is_empty() {
+        return Ok(html.to_owned());
+    }
+    let mut islands = islands.to_vec();
+    assign_ids(&mut islands);
+
+    let mut scripts = String::new();
+    for island in &islands {
+        let props = serde_json::to_string(&island.props)?;
+        scripts.push_str(&format!(
+            r#"<script type="application/json" id="{}{}" data-island-component="{}">{}</script>"#,
+            PROPS_ID_PREFIX,
+            escape_attr(island.id.as_deref().unwrap_or_default()),
+            escape_attr(&island.component),
+            escape_script(&props),
+        ));
+    }
+    scripts.push_str(&format!(r#"<script src="{LOADER_PATH}" defer></script>"#));
+
+    let placed = Cell::new(false);
+    let settings = Settings {
+        element_content_handlers: vec![element!("body", |el| {
+            if !placed.replace(true) {
+                el.append(&scripts, ContentType::Html);
+            }
+            Ok(())
+        })],
+        ..Settings::default()
+    };
+    let mut out = rewrite_str(html, settings).map_err(|e| RenderError::LolHtml(e.to_string()))?;
+    if !placed.get() {
+        out.push_str(&scripts);
+    }
+    Ok(out)
+}
+
+/// `Comments` → `comments`, `StarRating` → `star-rating`.
+fn kebab_case(name: &str) -> String {
+    let mut out = String::with_capacity(name.len() + 4);
+    for (i, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() {
+            if i > 0 && !out.ends_with('-') {
+                out.push('-');
+            }
+            out.push(c.to_ascii_lowercase());
+        } else if c.is_ascii_alphanumeric() {
+            out.push(c);
+        } else if !out.ends_with('-') {
+            out.push('-');
+        }
+    }
+    out
+}
+
+/// JSON that cannot close its `<script>` early.
+fn escape_script(json: &str) -> String {
+    json.replace('<', "\\u003c")
+        .replace('>', "\\u003e")
+        .replace('&', "\\u0026")
+}
+
+fn escape_attr(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn ids_are_stable_per_component() {
+        let mut islands = vec![
+            Island::new("StarRating", json!({})),
+            Island::new("Comments", json!({})),
+            Island::new("StarRating", json!({})).with_id("main-rating"),
+            Island::new("StarRating", json!({})),
+        ];
+        assign_ids(&mut islands);
+        let ids: Vec<_> = islands.iter().map(|i| i.id.as_deref().unwrap()).collect();
+        assert_eq!(
+            ids,
+            [
+                "star-rating-0",
+                "comments-0",
+                "main-rating",
+                "star-rating-2"
+            ]
+        );
+    }
+
+    #[test]
+    fn props_and_loader_go_before_the_end_of_body() {
+        let html = r#"<html><body><div data-island="comments-0"></div></body></html>"#;
+        let islands = [Island::new("Comments", json!({ "postId": 42 }))];
+        let out = embed_islands(html, &islands).unwrap();
+        assert_eq!(
+            out,
+            concat!(
+                r#"<html><body><div data-island="comments-0"></div>"#,
+                r#"<script type="application/json" id="wc-island-comments-0" data-island-component="Comments">{"postId":42}</script>"#,
+                r#"<script src="/_whisper/islands.js" defer></script>"#,
+                "</body></html>"
+            )
+        );
+
+        assert_eq!(embed_islands(html, &[]).unwrap(), html);
+    }
+
+    #[test]
+    fn props_cannot_break_out_of_their_script() {
+        let islands = [Island::new("Note", json!({ "text": "</script><b>" }))];
+        let out = embed_islands("<p>fragment</p>", &islands).unwrap();
+        assert!(out.starts_with("<p>fragment</p><script"), "{out}");
+        assert!(!out.contains("</script><b>"), "{out}");
+        assert!(out.contains(r#"\u003c/script\u003e\u003cb\u003e"#), "{out}");
+    }
+
+    #[test]
+    fn theme_json_without_a_component_is_skipped() {
+        let value = json!([
+            { "component": "Comments", "props": { "postId": 1 } },
+            { "props": { "orphan": true } },
+            { "component": "Rating", "id": "rating" },
+        ]);
+        let islands = islands_from_json(Some(&value));
+        assert_eq!(islands.len(), 2);
+        assert_eq!(islands[1].id.as_deref(), Some("rating"));
+        assert_eq!(islands[1].props, Json::Null);
+        assert!(islands_from_json(None).is_empty());
+    }
+}
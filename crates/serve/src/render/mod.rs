@@ -1,6 +1,12 @@
 pub mod body;
+pub mod csp;
 pub mod error;
+pub mod form;
+pub mod helpers;
 pub mod http;
+pub mod images;
+pub mod islands;
+pub mod model;
 pub mod pipeline;
 pub mod recommendation;
 pub mod rewriter;
@@ -8,6 +14,11 @@ pub mod template;
 
 pub use body::BodyRegexWriter;
 pub use error::RenderError;
-pub use pipeline::{render_html_template_to, render_json_to};
+pub use helpers::HelperSet;
+pub use islands::{embed_islands, Island};
+pub use model::{AssembledModel, ModelAssembly, TaskFailure};
+pub use pipeline::{
+    compute_etag, is_not_modified, render_html_template_to, render_json_to, EtagStrength,
+};
 pub use rewriter::HtmlDomRewriter;
-pub use template::{HbsEngine, TemplateEngine};
+pub use template::{render_default, HbsEngine, TemplateEngine, TemplateLanguage, TeraEngine};
@@ -0,0 +1,153 @@
+// crates/serve/src/render/model.rs
+
+// Template model assembly. A page's model is usually several independent
+// queries (the page itself, a menu, sidebar widgets, related posts); rather
+// than awaiting them one after another, the host declares each as a named
+// task and runs them together:
+//
+//   let model = ModelAssembly::new()
+//       .task("menu", async { menu_query().await })
+//       .task("related", async { related_posts(&page).await })
+//       .run(Duration::from_secs(2))
+//       .await;
+//   // model.values: { "menu": [...], "related": [...] }
+//
+// The tasks are polled together on the calling task, not spawned, so
+// task-locals (the query governor, consumed cache tags) still see them.
+// They share one deadline: whatever has not finished by then is dropped
+// and reported as timed out, and the render goes on with the rest. A task
+// that fails or times out has no value; the caller picks the fallback.
+
+use futures::future::{join_all, LocalBoxFuture};
+use futures::FutureExt;
+use serde_json::Value as Json;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+/// Why a task contributed nothing to the model.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TaskFailure {
+    Failed(String),
+    TimedOut,
+}
+
+impl fmt::Display for TaskFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TaskFailure::Failed(e) => write!(f, "failed: {e}"),
+            TaskFailure::TimedOut => f.write_str("timed out"),
+        }
+    }
+}
+
+/// The named tasks a model is built from.
+#[derive(Default)]
+pub struct ModelAssembly<'a> {
+    tasks: Vec<(String, LocalBoxFuture<'a, Result<Json, String>>)>,
+}
+
+/// What `ModelAssembly::run` produced.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AssembledModel {
+    /// Value by task name, for the tasks that finished.
+    pub values: BTreeMap<String, Json>,
+    pub failures: BTreeMap<String, TaskFailure>,
+    pub elapsed: Duration,
+}
+
+impl<'a> ModelAssembly<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a task whose result goes into the model as `name`. A later task
+    /// of the same name replaces the earlier one.
+    pub fn task<F>(mut self, name: impl Into<String>, task: F) -> Self
+    where
+        F: Future<Output = Result<Json, String>> + 'a,
+    {
+        let name = name.into();
+        self.tasks.retain(|(n, _)| *n != name);
+        self.tasks.push((name, task.boxed_local()));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    /// Run every task concurrently until all finish or `timeout` passes.
+    #[tracing::instrument(skip_all, fields(tasks = self.tasks.len()))]
+    pub async fn run(self, timeout: Duration) -> AssembledModel {
+        let started = Instant::now();
+        let deadline = tokio::time::Instant::now() + timeout;
+        let results = join_all(self.tasks.into_iter().map(|(name, task)| async move {
+            let result = match tokio::time::timeout_at(deadline, task).await {
+                Ok(Ok(value)) => Ok(value),
+                Ok(Err(e)) => Err(TaskFailure::Failed(e)),
+                Err(_) => Err(TaskFailure::TimedOut),
+            };
+            (name, result)
+        }))
+        .await;
+
+        let mut model = AssembledModel::default();
+        for (name, result) in results {
+            match result {
+                Ok(value) => {
+                    model.values.insert(name, value);
+                }
+                Err(failure) => {
+                    model.failures.insert(name, failure);
+                }
+            }
+        }
+        model.elapsed = started.elapsed();
+        model
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn tasks_run_together_and_slow_ones_time_out() {
+        let sleep = |ms| tokio::time::sleep(Duration::from_millis(ms));
+        let model = ModelAssembly::new()
+            .task("menu", async {
+                sleep(150).await;
+                Ok(json!(["home", "about"]))
+            })
+            .task("related", async {
+                sleep(150).await;
+                Ok(json!([]))
+            })
+            .task("broken", async { Err("no index".to_owned()) })
+            .task("slow", async {
+                sleep(10_000).await;
+                Ok(json!("never"))
+            })
+            .run(Duration::from_millis(400))
+            .await;
+
+        assert_eq!(model.values.len(), 2);
+        assert_eq!(model.values["menu"], json!(["home", "about"]));
+        assert_eq!(
+            model.failures,
+            BTreeMap::from([
+                ("broken".to_owned(), TaskFailure::Failed("no index".into())),
+                ("slow".to_owned(), TaskFailure::TimedOut),
+            ])
+        );
+        // The slow task was dropped at the deadline, not awaited.
+        assert!(model.elapsed < Duration::from_secs(2));
+    }
+}
@@ -4,17 +4,25 @@ use super::error::RenderError;
 use super::recommendation::{BodyPatch, BodyPatchKind};
 use super::rewriter::build_lol_settings_from_body_patches;
 use super::template::TemplateEngine; // <-- needed for render_to_write()
+use domain::hex;
+use http::{header, HeaderMap, Method};
 use lol_html::rewrite_str;
 use regex::Regex;
 use serde::Serialize;
 use serde_json::Value as Json;
+use sha2::{Digest, Sha256};
 use std::io::Write;
+use std::time::SystemTime;
+use tracing::info_span;
 
 /// Render an HTML template + model into the given writer,
 /// applying body-level regex and HtmlDom patches in the
 /// correct order:
 ///
 /// TemplateEngine → Regex → HtmlDom → out
+///
+/// Each stage gets its own span, so a trace shows where render time went.
+#[tracing::instrument(skip_all, fields(template = template_name))]
 pub fn render_html_template_to<T, M, W>(
     engine: &T,
     template_name: &str,
@@ -66,21 +74,25 @@ where
     // Templates are expected to be valid UTF-8; using `from_utf8_lossy`
     // avoids introducing a new error variant while remaining robust.
     let mut buf = Vec::new();
-    engine.render_to_write(template_name, model, &mut buf)?;
+    info_span!("render.template")
+        .in_scope(|| engine.render_to_write(template_name, model, &mut buf))?;
     let mut html_text = String::from_utf8_lossy(&buf).into_owned();
 
     // 2) Apply regex patches over the full HTML text (in order).
-    for (re, replacement) in &regex_specs {
-        html_text = re
-            .replace_all(&html_text, replacement.as_str())
-            .into_owned();
-    }
+    info_span!("render.regex_patches", count = regex_specs.len()).in_scope(|| {
+        for (re, replacement) in &regex_specs {
+            html_text = re
+                .replace_all(&html_text, replacement.as_str())
+                .into_owned();
+        }
+    });
 
     // 3) Apply HtmlDom patches using lol_html if any exist.
     if !html_dom_patches.is_empty() {
         let settings = build_lol_settings_from_body_patches(&html_dom_patches);
-        let rewritten =
-            rewrite_str(&html_text, settings).map_err(|e| RenderError::LolHtml(e.to_string()))?;
+        let rewritten = info_span!("render.dom_patches", count = html_dom_patches.len())
+            .in_scope(|| rewrite_str(&html_text, settings))
+            .map_err(|e| RenderError::LolHtml(e.to_string()))?;
         out.write_all(rewritten.as_bytes())
             .map_err(RenderError::Io)?;
     } else {
@@ -96,6 +108,7 @@ where
 ///
 /// 1) Regex patches on the HTML text
 /// 2) HtmlDom patches via lol_html
+#[tracing::instrument(skip_all)]
 pub fn render_html_string_to<W: Write>(
     html: &str,
     body_patches: &[BodyPatch],
@@ -161,6 +174,7 @@ pub fn render_html_string_to<W: Write>(
 /// 2) JSON Patch body patches
 ///
 /// Then serializing the final value to UTF-8.
+#[tracing::instrument(skip_all)]
 pub fn render_json_to<W: Write>(
     value: &Json,
     body_patches: &[BodyPatch],
@@ -223,3 +237,165 @@ pub fn render_json_to<W: Write>(
     serde_json::to_writer(&mut out, &patched_value)?;
     Ok(())
 }
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Conditional requests
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Whether an ETag promises byte-identical bodies (strong) or only
+/// equivalent ones (weak).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EtagStrength {
+    Strong,
+    Weak,
+}
+
+/// ETag over a rendered body and the version of the template that produced
+/// it, e.g. `"3f7a…"` or `W/"3f7a…"`.
+pub fn compute_etag(body: &[u8], template_version: Option<&str>, strength: EtagStrength) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(body);
+    if let Some(version) = template_version {
+        hasher.update([0]);
+        hasher.update(version.as_bytes());
+    }
+    let digest = hasher.finalize();
+    let hex = hex::encode(&digest[..16]);
+
+    match strength {
+        EtagStrength::Strong => format!("\"{hex}\""),
+        EtagStrength::Weak => format!("W/\"{hex}\""),
+    }
+}
+
+/// Whether a GET/HEAD carrying these request headers can be answered with
+/// `304 Not Modified`.
+///
+/// `If-None-Match` wins when present (weak comparison, `*` matches any
+/// current representation); otherwise `If-Modified-Since` is compared with
+/// `last_modified` to the second.
+pub fn is_not_modified(
+    method: &Method,
+    headers: &HeaderMap,
+    etag: Option<&str>,
+    last_modified: Option<SystemTime>,
+) -> bool {
+    if method != Method::GET && method != Method::HEAD {
+        return false;
+    }
+
+    if let Some(inm) = headers.get(header::IF_NONE_MATCH) {
+        let (Some(etag), Ok(inm)) = (etag, inm.to_str()) else {
+            return false;
+        };
+        let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_owned();
+        let current = opaque(etag);
+        return inm
+            .split(',')
+            .any(|tag| tag.trim() == "*" || opaque(tag) == current);
+    }
+
+    let (Some(last_modified), Some(ims)) = (last_modified, headers.get(header::IF_MODIFIED_SINCE))
+    else {
+        return false;
+    };
+    let Some(since) = ims
+        .to_str()
+        .ok()
+        .and_then(|s| httpdate::parse_http_date(s).ok())
+    else {
+        return false;
+    };
+
+    // HTTP dates have whole seconds.
+    let secs = |t: SystemTime| {
+        t.duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+    };
+    secs(last_modified) <= secs(since)
+}
+
+/// `Last-Modified` header value for `t`.
+pub fn http_date(t: SystemTime) -> String {
+    httpdate::fmt_http_date(t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::HeaderValue;
+    use std::time::Duration;
+
+    fn headers(name: header::HeaderName, value: &str) -> HeaderMap {
+        let mut h = HeaderMap::new();
+        h.insert(name, HeaderValue::from_str(value).unwrap());
+        h
+    }
+
+    #[test]
+    fn etag_depends_on_body_and_template_version() {
+        let a = compute_etag(b"<p>hi</p>", Some("v1"), EtagStrength::Strong);
+        assert!(a.starts_with('"') && a.ends_with('"'));
+        assert_eq!(
+            a,
+            compute_etag(b"<p>hi</p>", Some("v1"), EtagStrength::Strong)
+        );
+        assert_ne!(
+            a,
+            compute_etag(b"<p>hi</p>", Some("v2"), EtagStrength::Strong)
+        );
+        assert_ne!(
+            a,
+            compute_etag(b"<p>yo</p>", Some("v1"), EtagStrength::Strong)
+        );
+
+        let weak = compute_etag(b"<p>hi</p>", Some("v1"), EtagStrength::Weak);
+        assert_eq!(weak, format!("W/{a}"));
+    }
+
+    #[test]
+    fn if_none_match_uses_weak_comparison_and_wins() {
+        let etag = compute_etag(b"body", None, EtagStrength::Strong);
+        let get = Method::GET;
+
+        let h = headers(header::IF_NONE_MATCH, &format!("\"other\", W/{etag}"));
+        assert!(is_not_modified(&get, &h, Some(&etag), None));
+        assert!(!is_not_modified(&Method::POST, &h, Some(&etag), None));
+
+        let h = headers(header::IF_NONE_MATCH, "\"other\"");
+        assert!(!is_not_modified(&get, &h, Some(&etag), None));
+        assert!(is_not_modified(
+            &get,
+            &headers(header::IF_NONE_MATCH, "*"),
+            Some(&etag),
+            None
+        ));
+
+        // A mismatching If-None-Match is not rescued by If-Modified-Since.
+        let mut h = headers(header::IF_NONE_MATCH, "\"other\"");
+        h.insert(
+            header::IF_MODIFIED_SINCE,
+            HeaderValue::from_str(&http_date(SystemTime::now())).unwrap(),
+        );
+        assert!(!is_not_modified(
+            &get,
+            &h,
+            Some(&etag),
+            Some(SystemTime::UNIX_EPOCH)
+        ));
+    }
+
+    #[test]
+    fn if_modified_since_compares_whole_seconds() {
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_millis(1_700_000_000_500);
+        let get = Method::GET;
+
+        let h = headers(header::IF_MODIFIED_SINCE, &http_date(modified));
+        assert!(is_not_modified(&get, &h, None, Some(modified)));
+
+        let later = modified + Duration::from_secs(60);
+        assert!(!is_not_modified(&get, &h, None, Some(later)));
+        assert!(!is_not_modified(&get, &h, None, None));
+    }
+}
@@ -1,5 +1,6 @@
 // crates/serve/src/render/recommendation.rs
 
+use crate::render::csp::ContentSecurityPolicy;
 use http::header::HeaderName;
 use http::HeaderMap;
 use json_patch::{patch as apply_json_patch_doc, Patch, PatchError};
@@ -12,11 +13,14 @@ use serde_json::Value as Json;
 /// * header patches
 /// * model JSON patches
 /// * body patches (regex, HTML DOM, JSON patch)
+/// * that the response must not be cached
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Recommendations {
     pub header_patches: Vec<HeaderPatch>,
     pub model_patches: Vec<ModelPatch>,
     pub body_patches: Vec<BodyPatch>,
+    #[serde(default)]
+    pub uncacheable: Vec<Uncacheable>,
 }
 
 impl Recommendations {
@@ -24,6 +28,12 @@ impl Recommendations {
         self.header_patches.is_empty()
             && self.model_patches.is_empty()
             && self.body_patches.is_empty()
+            && self.uncacheable.is_empty()
+    }
+
+    /// False once any plugin has marked the response uncacheable.
+    pub fn is_cacheable(&self) -> bool {
+        self.uncacheable.is_empty()
     }
 
     /// Apply all header patches in-order to the given map.
@@ -42,12 +52,16 @@ impl Recommendations {
     }
 }
 
-/// Patch type for headers: set, append, or remove.
+/// Patch type for headers: set, append, append-source, or remove.
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 pub enum HeaderPatchKind {
     Set,
     Append,
     Remove,
+    /// Merge a policy-shaped value (`Content-Security-Policy`) into the
+    /// header directive by directive, so patches widen it together instead
+    /// of replacing each other's sources.
+    AppendSource,
 }
 
 /// A single header patch.
@@ -78,6 +92,15 @@ impl HeaderPatch {
         }
     }
 
+    pub fn append_source(name: String, value: String, source_plugin: String) -> Self {
+        Self {
+            kind: HeaderPatchKind::AppendSource,
+            name,
+            value: Some(value),
+            source_plugin,
+        }
+    }
+
     pub fn remove(name: String, source_plugin: String) -> Self {
         Self {
             kind: HeaderPatchKind::Remove,
@@ -118,6 +141,20 @@ impl HeaderPatch {
             Remove => {
                 headers.remove(header_name);
             }
+            AppendSource => {
+                if let Some(ref v) = self.value {
+                    let mut policy = ContentSecurityPolicy::new();
+                    for existing in headers.get_all(&header_name) {
+                        if let Ok(existing) = existing.to_str() {
+                            policy.merge(&ContentSecurityPolicy::parse(existing));
+                        }
+                    }
+                    policy.merge(&ContentSecurityPolicy::parse(v));
+                    if let Ok(hv) = policy.to_string().parse() {
+                        headers.insert(header_name, hv);
+                    }
+                }
+            }
         }
     }
 
@@ -128,6 +165,14 @@ impl HeaderPatch {
     }
 }
 
+/// A plugin's request that this response not be stored in the response
+/// cache, e.g. because it depends on the visitor or the time of day.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Uncacheable {
+    pub reason: Option<String>,
+    pub source_plugin: String,
+}
+
 /// A JSON Patch to be applied to the *model* (template data), not the body.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModelPatch {
@@ -389,6 +434,32 @@ mod tests {
         assert_eq!(vals, vec!["one".to_string(), "two".to_string()]);
     }
 
+    #[test]
+    fn header_patch_append_source_merges_policies() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "content-security-policy",
+            HeaderValue::from_static("default-src 'self'; script-src 'self'"),
+        );
+        for (value, plugin) in [
+            ("script-src https://cdn.one.example", "p1"),
+            ("script-src https://cdn.two.example; img-src data:", "p2"),
+        ] {
+            HeaderPatch::append_source(
+                "content-security-policy".to_string(),
+                value.to_string(),
+                plugin.to_string(),
+            )
+            .apply(&mut headers);
+        }
+
+        assert_eq!(
+            headers.get("content-security-policy").unwrap(),
+            "default-src 'self'; script-src 'self' https://cdn.one.example \
+             https://cdn.two.example; img-src data:"
+        );
+    }
+
     #[test]
     fn header_patch_remove_removes_header() {
         let mut headers = HeaderMap::new();
@@ -1,21 +1,23 @@
 // crates/serve/src/render/template.rs
 
 use super::error::RenderError;
+use super::helpers::{register_partials, register_theme, HelperSet};
 use handlebars::{
     handlebars_helper, Context, Handlebars, Helper, HelperDef, HelperResult, Output, RenderContext,
 };
 use handlebars_misc_helpers as misc;
 use minijinja::{Environment as MiniJinjaEnv, Error as MiniJinjaError};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::fs;
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 use tera::{Context as TeraContext, Error as TeraError, Tera};
 
 /// Trait for template engines that can render to an arbitrary `Write`.
 ///
-/// This is intentionally minimal and is implemented by both `HbsEngine`
-/// (historical) and the multi-engine `TemplateRegistry`.
+/// This is intentionally minimal and is implemented by `HbsEngine`
+/// (historical), `TeraEngine` and the multi-engine `TemplateRegistry`.
 pub trait TemplateEngine: Send + Sync {
     fn render_to_write<M, W>(
         &self,
@@ -47,12 +49,31 @@ impl HbsEngine {
         }
     }
 
+    /// An engine with the partials, built-in helpers and `helpers` of the
+    /// theme whose templates live in `template_root` (see `helpers`).
+    pub fn for_theme(template_root: &Path, helpers: &HelperSet) -> Result<Self, RenderError> {
+        let mut engine = Self::new();
+        register_theme(&mut engine.handlebars, template_root, helpers)?;
+        Ok(engine)
+    }
+
     /// Register a template by name.
     pub fn register_template_str(&mut self, name: &str, template: &str) -> Result<(), RenderError> {
         self.handlebars
             .register_template_string(name, template)
             .map_err(RenderError::from)
     }
+
+    /// Register every `*.hbs` below `dir` as a partial named by its relative
+    /// path without the extension.
+    pub fn register_partials_dir(&mut self, dir: &Path) -> Result<(), RenderError> {
+        register_partials(&mut self.handlebars, dir)
+    }
+
+    /// Register a custom helper, replacing any helper of the same name.
+    pub fn register_helper(&mut self, name: &str, helper: impl HelperDef + Send + Sync + 'static) {
+        self.handlebars.register_helper(name, Box::new(helper));
+    }
 }
 
 impl TemplateEngine for HbsEngine {
@@ -72,10 +93,107 @@ impl TemplateEngine for HbsEngine {
     }
 }
 
+/// Render `model` with `template`, registered as `name` on a bare
+/// Handlebars engine: the built-in pages (error, author, taxonomy, search)
+/// served when the theme has no template of its own for them.
+pub fn render_default<M, W>(
+    name: &str,
+    template: &str,
+    model: &M,
+    out: &mut W,
+) -> Result<(), RenderError>
+where
+    M: Serialize,
+    W: Write,
+{
+    let mut hbs = HbsEngine::new();
+    hbs.register_template_str(name, template)?;
+    hbs.render_to_write(name, model, out)
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Tera engine
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// Tera counterpart of `HbsEngine`, for callers that register templates
+/// up front instead of rendering from a theme directory.
+pub struct TeraEngine {
+    tera: Tera,
+}
+
+impl TeraEngine {
+    pub fn new() -> Self {
+        Self {
+            tera: Tera::default(),
+        }
+    }
+
+    /// Register a template by name.
+    pub fn register_template_str(&mut self, name: &str, template: &str) -> Result<(), RenderError> {
+        self.tera
+            .add_raw_template(name, template)
+            .map_err(RenderError::from)
+    }
+
+    /// Register the template at `path` as `name`.
+    pub fn register_template_file(&mut self, name: &str, path: &Path) -> Result<(), RenderError> {
+        self.tera
+            .add_template_file(path, Some(name))
+            .map_err(RenderError::from)
+    }
+}
+
+impl Default for TeraEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TemplateEngine for TeraEngine {
+    fn render_to_write<M, W>(
+        &self,
+        template_name: &str,
+        model: &M,
+        out: &mut W,
+    ) -> Result<(), RenderError>
+    where
+        M: Serialize,
+        W: Write,
+    {
+        let ctx = TeraContext::from_serialize(model)?;
+        self.tera
+            .render_to(template_name, &ctx, out)
+            .map_err(RenderError::from)
+    }
+}
+
 // ─────────────────────────────────────────────────────────────────────────────
 // Multi-engine registry (Handlebars / MiniJinja / Tera)
 // ─────────────────────────────────────────────────────────────────────────────
 
+/// A theme's template language, from `engine = "…"` in `theme.toml`. It
+/// renders templates whose extension does not pick an engine, such as
+/// `page.html`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TemplateLanguage {
+    #[serde(alias = "hbs")]
+    Handlebars,
+    #[serde(alias = "jinja")]
+    MiniJinja,
+    Tera,
+}
+
+impl TemplateLanguage {
+    fn kind(self) -> EngineKind {
+        match self {
+            TemplateLanguage::Handlebars => EngineKind::Handlebars,
+            TemplateLanguage::MiniJinja => EngineKind::MiniJinja,
+            TemplateLanguage::Tera => EngineKind::Tera,
+        }
+    }
+}
+
 /// Internal enum to decide which engine to use.
 /// This does **not** escape this module (no dyn on the hot path).
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -129,8 +247,13 @@ impl HelperDef for DumpRoot {
 /// There is intentionally **no caching**: each call reads the template
 /// file from disk and constructs the engine environment just for that call.
 /// This keeps the implementation simple and matches your “no caching” answer.
+///
+/// Handlebars templates also get the theme's partials and helpers; see
+/// `super::helpers`.
 pub struct TemplateRegistry {
     template_root: PathBuf,
+    helpers: HelperSet,
+    language: Option<TemplateLanguage>,
 }
 
 impl TemplateRegistry {
@@ -139,7 +262,32 @@ impl TemplateRegistry {
     /// The directory is not required to exist at construction time – errors
     /// are reported only when attempting to render a specific template.
     pub fn new(template_root: PathBuf) -> Self {
-        Self { template_root }
+        Self {
+            template_root,
+            helpers: HelperSet::default(),
+            language: None,
+        }
+    }
+
+    /// Render templates with no engine-specific extension in `language`.
+    /// Without one, such templates are an error.
+    pub fn with_language(mut self, language: Option<TemplateLanguage>) -> Self {
+        self.language = language;
+        self
+    }
+
+    /// Add custom Handlebars helpers on top of the built-in ones.
+    pub fn with_helpers(mut self, helpers: HelperSet) -> Self {
+        self.helpers = helpers;
+        self
+    }
+
+    /// When `template_name` was last written; doubles as its version for
+    /// cache validators. `None` if the file cannot be read.
+    pub fn template_modified(&self, template_name: &str) -> Option<SystemTime> {
+        fs::metadata(self.resolve_path(template_name))
+            .and_then(|m| m.modified())
+            .ok()
     }
 
     /// Helper for creating an `io::Error` from a display-able value.
@@ -168,12 +316,14 @@ impl TemplateRegistry {
             .and_then(|s| s.to_str())
             .unwrap_or("");
 
-        let kind = EngineKind::from_extension(ext).ok_or_else(|| {
-            RenderError::Io(Self::io_other(format!(
-                "unsupported template extension {:?} for {:?}",
-                ext, template_name
-            )))
-        })?;
+        let kind = EngineKind::from_extension(ext)
+            .or(self.language.map(TemplateLanguage::kind))
+            .ok_or_else(|| {
+                RenderError::Io(Self::io_other(format!(
+                    "unsupported template extension {:?} for {:?}",
+                    ext, template_name
+                )))
+            })?;
 
         Ok((kind, src))
     }
@@ -199,6 +349,7 @@ impl TemplateRegistry {
         misc::register(&mut hbs);
         hbs.register_helper("dump", Box::new(dump_json));
         hbs.register_helper("dump_root", Box::new(DumpRoot));
+        register_theme(&mut hbs, &self.template_root, &self.helpers)?;
         hbs.render_to_write(template_name, model, out)
             .map_err(RenderError::from)
     }
@@ -277,3 +428,50 @@ impl TemplateEngine for TemplateRegistry {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn tera_engine_renders_registered_templates() {
+        let mut engine = TeraEngine::new();
+        engine
+            .register_template_str("hello", "Hello {{ name | upper }}!")
+            .unwrap();
+
+        let mut out = Vec::new();
+        engine
+            .render_to_write("hello", &json!({ "name": "ada" }), &mut out)
+            .unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "Hello ADA!");
+        assert!(engine
+            .render_to_write("missing", &json!({}), &mut Vec::new())
+            .is_err());
+    }
+
+    #[test]
+    fn the_theme_language_renders_templates_without_an_engine_extension() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("page.html"), "{% if ok %}yes{% endif %}").unwrap();
+        fs::write(dir.path().join("other.hbs"), "{{#if ok}}hbs{{/if}}").unwrap();
+        let model = json!({ "ok": true });
+
+        let plain = TemplateRegistry::new(dir.path().to_path_buf());
+        assert!(plain
+            .render_to_write("page.html", &model, &mut Vec::new())
+            .is_err());
+
+        let language: TemplateLanguage = serde_json::from_value(json!("tera")).unwrap();
+        let tera = TemplateRegistry::new(dir.path().to_path_buf()).with_language(Some(language));
+        let mut out = Vec::new();
+        tera.render_to_write("page.html", &model, &mut out).unwrap();
+        assert_eq!(out, b"yes");
+
+        // An engine extension still wins over the theme language.
+        let mut out = Vec::new();
+        tera.render_to_write("other.hbs", &model, &mut out).unwrap();
+        assert_eq!(out, b"hbs");
+    }
+}
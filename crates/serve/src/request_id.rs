@@ -0,0 +1,70 @@
+// crates/serve/src/request_id.rs
+
+// Request ids: ULIDs, 26 Crockford base32 characters of which the first ten
+// are the millisecond timestamp, so ids sort by arrival in logs. An id that
+// came with the request is only kept when it looks like one a proxy would
+// send (see `acceptable`). The request context takes its id from the
+// request's `X-Request-Id`, once the edge has settled it.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+/// Carries the id in and out.
+pub const HEADER: &str = "x-request-id";
+
+const CROCKFORD: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// A new ULID.
+pub fn generate() -> String {
+    let ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default();
+    ulid(ms, Uuid::new_v4().as_bytes())
+}
+
+/// The ULID of `ms` and the first ten bytes of `random`.
+fn ulid(ms: u64, random: &[u8; 16]) -> String {
+    let mut value = u128::from(ms & 0xFFFF_FFFF_FFFF) << 80;
+    for (i, b) in random.iter().take(10).enumerate() {
+        value |= u128::from(*b) << (72 - 8 * i);
+    }
+    (0..26)
+        .rev()
+        .map(|i| CROCKFORD[((value >> (5 * i)) & 0x1F) as usize] as char)
+        .collect()
+}
+
+/// Whether an incoming id may be kept: 1–128 of `[A-Za-z0-9._-]`, so it
+/// cannot break a log line or a header.
+pub fn acceptable(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= 128
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'.' | b'_' | b'-'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ulids_encode_time_first_and_incoming_ids_are_checked() {
+        assert_eq!(ulid(0, &[0; 16]), "00000000000000000000000000");
+        assert_eq!(
+            ulid(1_469_922_850_259, &[0xFF; 16]),
+            "01ARZ3NDEKZZZZZZZZZZZZZZZZ"
+        );
+        let (a, b) = (generate(), generate());
+        assert_eq!(a.len(), 26);
+        assert_ne!(a, b);
+        assert!(acceptable(&a));
+
+        assert!(acceptable("4f1c-88.a_b"));
+        assert!(!acceptable(""));
+        assert!(!acceptable("a b"));
+        assert!(!acceptable("a\"b"));
+        assert!(!acceptable(&"a".repeat(129)));
+    }
+}
@@ -13,14 +13,24 @@
 //!
 //! All the data retrieval — FM lookup, content lookup, slug lookup, CAS stream creation —
 //! is performed via injected closures.
+//!
+//! Plugins may also claim paths outright ([`PluginRoutes`]); requests to those skip
+//! content resolution entirely.
 
 use domain::content::{ContentKind, ResolvedContent};
 use http::{HeaderMap, Method};
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Map as JsonMap, Value as Json};
-use std::{collections::HashMap, string::FromUtf8Error};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    string::FromUtf8Error,
+};
 use thiserror::Error;
 
-use crate::{indexer::ContentManager, render::http::RequestContext};
+use crate::{
+    indexer::ContentManager, permalinks::same_path, render::http::RequestContext, request_id,
+};
 
 // -----------------------------------------------------------------------------
 // Error Type
@@ -71,6 +81,31 @@ pub async fn resolve(
 ) -> Result<ResolvedContent, ResolverError> {
     let path = normalize(path);
 
+    // -------------------------------------------
+    // Step 0: Is the path a document's permalink?
+    // -------------------------------------------
+    let permalinks = resolver.permalinks();
+    for slug in permalinks.candidate_slugs(&path) {
+        let Some(fm) = resolver.lookup_slug(slug).await? else {
+            continue;
+        };
+        if !permalinks
+            .for_document(&fm)
+            .is_some_and(|p| same_path(&p, &path))
+        {
+            continue;
+        }
+        if let Some(served) = fm.get("id").and_then(|v| v.as_str()) {
+            if let Some(body) = resolver.lookup_body(served).await? {
+                return Ok(ResolvedContent {
+                    content_kind: infer_kind_from_ext(served),
+                    front_matter: fm,
+                    body: Some(body),
+                });
+            }
+        }
+    }
+
     // -------------------------------------------
     // Step 1: Does the path match a slug exactly?
     // -------------------------------------------
@@ -150,6 +185,7 @@ pub async fn resolve(
 ///
 /// - Normalizes header names to `Accept-Language` style.
 /// - Converts query params map into a JSON object.
+/// - Takes the request id from `X-Request-Id`, else makes one.
 /// - Uses `resolved.front_matter` as `content_meta`.
 #[tracing::instrument(skip_all)]
 pub fn build_request_context(
@@ -172,7 +208,16 @@ pub fn build_request_context(
         qp_obj.insert(k.clone(), Json::String(v.clone()));
     }
 
-    RequestContext::builder()
+    let builder = match headers
+        .get(request_id::HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|id| request_id::acceptable(id))
+    {
+        Some(id) => RequestContext::builder().req_id(id),
+        None => RequestContext::builder(),
+    };
+
+    builder
         .path(Json::String(path))
         .method(Json::String(method.to_string()))
         .headers(Json::Object(hdr_obj))
@@ -203,3 +248,317 @@ pub fn canonicalize_header_name(raw: &str) -> String {
 
     out
 }
+
+// -----------------------------------------------------------------------------
+// Plugin routes
+// -----------------------------------------------------------------------------
+
+/// An endpoint a plugin declares in plugin.toml:
+///
+/// ```toml
+/// [[routes]]
+/// method = "POST"
+/// path = "/api/comments/{post}"
+/// handler = "postComment"
+/// ```
+///
+/// A matching request skips content resolution and goes straight to the
+/// plugin's top-level `handler` function, with `post` among the params.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PluginRoute {
+    pub method: String,
+    pub path: String,
+    pub handler: String,
+}
+
+/// A declared route left out of [`PluginRoutes`], and why.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedRoute {
+    pub plugin_id: String,
+    pub route: PluginRoute,
+    pub reason: String,
+}
+
+impl fmt::Display for SkippedRoute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "plugin '{}' route {} {}: {}",
+            self.plugin_id, self.route.method, self.route.path, self.reason
+        )
+    }
+}
+
+/// The plugin function a request goes to, with the `{name}` segments of the
+/// route's path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteMatch<'a> {
+    pub plugin_id: &'a str,
+    pub handler: &'a str,
+    pub params: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Param(String),
+}
+
+#[derive(Debug, Clone)]
+struct RouteEntry {
+    plugin_id: String,
+    method: Method,
+    segments: Vec<Segment>,
+    handler: String,
+}
+
+/// Every plugin's routes, ready to match requests against.
+#[derive(Debug, Clone, Default)]
+pub struct PluginRoutes {
+    /// Most specific first: at each segment a literal beats a parameter.
+    entries: Vec<RouteEntry>,
+}
+
+impl PluginRoutes {
+    /// Register the routes of `plugins` (plugin id, declared routes).
+    ///
+    /// Conflicts settle the same way whatever order the plugins were
+    /// discovered in: plugins are taken by id, and the first to claim a
+    /// method and path shape keeps it (`/a/{id}` and `/a/{slug}` are one
+    /// shape). Later claims, and routes that do not parse, are skipped.
+    pub fn build<'a>(
+        plugins: impl IntoIterator<Item = (&'a str, &'a [PluginRoute])>,
+    ) -> (Self, Vec<SkippedRoute>) {
+        let mut plugins: Vec<_> = plugins.into_iter().collect();
+        plugins.sort_by_key(|(id, _)| *id);
+
+        let mut entries: Vec<RouteEntry> = Vec::new();
+        let mut skipped = Vec::new();
+        for (plugin_id, routes) in plugins {
+            for route in routes {
+                let skip = |reason: String| SkippedRoute {
+                    plugin_id: plugin_id.to_owned(),
+                    route: route.clone(),
+                    reason,
+                };
+                let entry = match parse_route(plugin_id, route) {
+                    Ok(entry) => entry,
+                    Err(reason) => {
+                        skipped.push(skip(reason));
+                        continue;
+                    }
+                };
+                if let Some(owner) = entries
+                    .iter()
+                    .find(|e| e.method == entry.method && same_shape(&e.segments, &entry.segments))
+                {
+                    skipped.push(skip(format!(
+                        "already routed to plugin '{}'",
+                        owner.plugin_id
+                    )));
+                    continue;
+                }
+                entries.push(entry);
+            }
+        }
+
+        entries.sort_by_key(|e| {
+            e.segments
+                .iter()
+                .map(|s| matches!(s, Segment::Param(_)))
+                .collect::<Vec<_>>()
+        });
+        (Self { entries }, skipped)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// The route `method` and `path` go to, if any. HEAD requests also
+    /// match GET routes.
+    pub fn matches(&self, method: &Method, path: &str) -> Option<RouteMatch<'_>> {
+        let parts = split_path(path);
+        self.entries
+            .iter()
+            .filter(|e| e.method == *method || (*method == Method::HEAD && e.method == Method::GET))
+            .find_map(|e| {
+                if e.segments.len() != parts.len() {
+                    return None;
+                }
+                let mut params = BTreeMap::new();
+                for (segment, part) in e.segments.iter().zip(&parts) {
+                    match segment {
+                        Segment::Literal(lit) if lit == part => {}
+                        Segment::Literal(_) => return None,
+                        Segment::Param(name) => {
+                            params.insert(name.clone(), (*part).to_owned());
+                        }
+                    }
+                }
+                Some(RouteMatch {
+                    plugin_id: &e.plugin_id,
+                    handler: &e.handler,
+                    params,
+                })
+            })
+    }
+}
+
+/// `/a/b/` and `/a/b` are the same path; `/` has no segments.
+fn split_path(path: &str) -> Vec<&str> {
+    path.trim_matches('/')
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+fn parse_route(plugin_id: &str, route: &PluginRoute) -> Result<RouteEntry, String> {
+    let method = Method::from_bytes(route.method.to_ascii_uppercase().as_bytes())
+        .map_err(|_| format!("'{}' is not an HTTP method", route.method))?;
+    if !route.path.starts_with('/') {
+        return Err("the path must start with '/'".into());
+    }
+    let mut segments = Vec::new();
+    for part in split_path(&route.path) {
+        let segment = match part.strip_prefix('{').and_then(|p| p.strip_suffix('}')) {
+            Some(name) if is_identifier(name) => Segment::Param(name.to_owned()),
+            Some(name) => return Err(format!("'{{{name}}}' is not a parameter name")),
+            None if part.contains(['{', '}']) => {
+                return Err(format!("'{part}' mixes text and a parameter"))
+            }
+            None => Segment::Literal(part.to_owned()),
+        };
+        if let Segment::Param(name) = &segment {
+            if segments.contains(&segment) {
+                return Err(format!("parameter '{name}' appears twice"));
+            }
+        }
+        segments.push(segment);
+    }
+    if !is_identifier(&route.handler) {
+        return Err(format!("'{}' is not a function name", route.handler));
+    }
+    Ok(RouteEntry {
+        plugin_id: plugin_id.to_owned(),
+        method,
+        segments,
+        handler: route.handler.clone(),
+    })
+}
+
+fn same_shape(a: &[Segment], b: &[Segment]) -> bool {
+    a.len() == b.len()
+        && a.iter().zip(b).all(|pair| match pair {
+            (Segment::Literal(x), Segment::Literal(y)) => x == y,
+            (Segment::Param(_), Segment::Param(_)) => true,
+            _ => false,
+        })
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(method: &str, path: &str, handler: &str) -> PluginRoute {
+        PluginRoute {
+            method: method.into(),
+            path: path.into(),
+            handler: handler.into(),
+        }
+    }
+
+    #[test]
+    fn plugin_routes_match_by_method_and_path() {
+        let comments = [
+            route("post", "/api/comments/{post}", "postComment"),
+            route("GET", "/api/comments/{post}", "listComments"),
+            route("GET", "/api/comments/recent/", "recentComments"),
+        ];
+        let (routes, skipped) = PluginRoutes::build([("comments", &comments[..])]);
+        assert!(skipped.is_empty(), "{skipped:?}");
+
+        let hit = routes
+            .matches(&Method::POST, "/api/comments/hello")
+            .unwrap();
+        assert_eq!((hit.plugin_id, hit.handler), ("comments", "postComment"));
+        assert_eq!(hit.params["post"], "hello");
+
+        // A literal segment beats a parameter, whatever the declared order.
+        let hit = routes
+            .matches(&Method::GET, "/api/comments/recent")
+            .unwrap();
+        assert_eq!(hit.handler, "recentComments");
+        assert!(hit.params.is_empty());
+
+        assert_eq!(
+            routes
+                .matches(&Method::HEAD, "/api/comments/x")
+                .unwrap()
+                .handler,
+            "listComments"
+        );
+        assert!(routes.matches(&Method::DELETE, "/api/comments/x").is_none());
+        assert!(routes.matches(&Method::GET, "/api/comments").is_none());
+        assert!(routes.matches(&Method::GET, "/api/comments/x/y").is_none());
+    }
+
+    #[test]
+    fn plugin_route_conflicts_go_to_the_first_plugin_by_id() {
+        let zebra = [route("POST", "/api/comments", "zebra")];
+        let alpha = [
+            route("POST", "/api/comments/", "alpha"),
+            route("GET", "/api/{thing}", "get"),
+        ];
+        let mallard = [route("GET", "/api/{other}", "get")];
+        let (routes, skipped) = PluginRoutes::build([
+            ("zebra", &zebra[..]),
+            ("mallard", &mallard[..]),
+            ("alpha", &alpha[..]),
+        ]);
+
+        let hit = routes.matches(&Method::POST, "/api/comments").unwrap();
+        assert_eq!(hit.plugin_id, "alpha");
+        assert_eq!(
+            routes.matches(&Method::GET, "/api/x").unwrap().plugin_id,
+            "alpha"
+        );
+        assert_eq!(
+            skipped
+                .iter()
+                .map(|s| s.plugin_id.as_str())
+                .collect::<Vec<_>>(),
+            ["mallard", "zebra"]
+        );
+        assert!(skipped[1]
+            .to_string()
+            .contains("already routed to plugin 'alpha'"));
+    }
+
+    #[test]
+    fn malformed_plugin_routes_are_skipped() {
+        let bad = [
+            route("FETCH ME", "/a", "f"),
+            route("GET", "a", "f"),
+            route("GET", "/a/x{id}", "f"),
+            route("GET", "/a/{1d}", "f"),
+            route("GET", "/a/{id}/{id}", "f"),
+            route("GET", "/a", "not a name"),
+        ];
+        let (routes, skipped) = PluginRoutes::build([("bad", &bad[..])]);
+        assert!(routes.is_empty());
+        assert_eq!(skipped.len(), bad.len());
+    }
+}
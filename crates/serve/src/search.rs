@@ -0,0 +1,360 @@
+// crates/serve/src/search.rs
+
+// Site search for readers, beyond raw MQL:
+//
+//   GET /search?q=rust+wasm&page=2          themed results page
+//   GET /search?q=rust+wasm&format=json     the same results as JSON
+//
+// A query is split into lowercase words. The host gathers the published
+// documents that could match (a title or summary word, or a hit in the body
+// index) with their plain-text bodies, and this module ranks them with BM25
+// per field, weighted so a word in the title counts more than one in the
+// summary, and that more than one in the body:
+//
+//   score = Σ field weight × Σ idf(word) × tf × (k1 + 1)
+//                                       / (tf + k1 × (1 − b + b × len / avg len))
+//
+// Document frequencies and average lengths come from the gathered documents,
+// not the whole site. Each result carries a snippet of its body around the
+// first match, with the query's words in `<mark>`; templates print it with
+// triple braces:
+//
+//   {{#each results}}<a href="{{path}}">{{{title_html}}}</a><p>{{{snippet}}}</p>{{/each}}
+
+use crate::taxonomy::Pagination;
+use serde_json::{json, Value as Json};
+use std::collections::{BTreeSet, HashMap};
+
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+
+const TITLE_WEIGHT: f64 = 3.0;
+const SUMMARY_WEIGHT: f64 = 2.0;
+const BODY_WEIGHT: f64 = 1.0;
+
+/// Results page for themes without a `search` template, over the model
+/// [`page_model`] builds.
+pub const DEFAULT_SEARCH_TEMPLATE: &str = r#"<!doctype html>
+<html>
+<head><meta charset="utf-8"><title>Search{{#if query}}: {{query}}{{/if}}</title></head>
+<body>
+<header>
+  <h1>Search</h1>
+  <form action="/search" method="get" role="search">
+    <input type="search" name="q" value="{{query}}" aria-label="Search">
+    <button type="submit">Search</button>
+  </form>
+  {{#if query}}<p>{{total}} {{#if (eq total 1)}}result{{else}}results{{/if}} for “{{query}}”</p>{{/if}}
+</header>
+<main>
+  <ol>
+  {{#each results}}
+    <li><a href="{{path}}">{{{title_html}}}</a>{{#if date}} <time datetime="{{date}}">{{date}}</time>{{/if}}{{#if snippet}}<p>{{{snippet}}}</p>{{/if}}</li>
+  {{/each}}
+  </ol>
+</main>
+{{#if (or page.prev page.next)}}
+<nav>
+  {{#if page.prev}}<a href="{{page.prev}}" rel="prev">Previous</a>{{/if}}
+  <span>Page {{page.number}} of {{page.count}}</span>
+  {{#if page.next}}<a href="{{page.next}}" rel="next">Next</a>{{/if}}
+</nav>
+{{/if}}
+</body>
+</html>
+"#;
+
+/// The lowercase words of `text`, in order.
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// The distinct words of a query, in the order first given.
+pub fn query_terms(q: &str) -> Vec<String> {
+    let mut seen = BTreeSet::new();
+    tokenize(q)
+        .into_iter()
+        .filter(|w| seen.insert(w.clone()))
+        .collect()
+}
+
+/// Plain text of `html`, tags dropped, entities decoded and whitespace
+/// collapsed.
+pub fn html_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => {
+                in_tag = true;
+                text.push(' ');
+            }
+            '>' => in_tag = false,
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    let text = html_escape::decode_html_entities(&text);
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// One document that may match, as the host gathered it.
+#[derive(Debug, Clone, Default)]
+pub struct SearchDoc {
+    /// Its permalink or served path.
+    pub path: String,
+    pub title: String,
+    pub summary: String,
+    /// Plain text; see [`html_text`].
+    pub body: String,
+    pub date: Option<String>,
+}
+
+struct Field {
+    weight: f64,
+    /// Words of each document, counted.
+    counts: Vec<HashMap<String, usize>>,
+    lengths: Vec<usize>,
+    avg_len: f64,
+}
+
+impl Field {
+    fn new(weight: f64, texts: impl Iterator<Item = String>) -> Self {
+        let (counts, lengths): (Vec<_>, Vec<_>) = texts
+            .map(|text| {
+                let words = tokenize(&text);
+                let len = words.len();
+                let mut counts = HashMap::new();
+                for w in words {
+                    *counts.entry(w).or_insert(0) += 1;
+                }
+                (counts, len)
+            })
+            .unzip();
+        let avg_len = match lengths.len() {
+            0 => 0.0,
+            n => lengths.iter().sum::<usize>() as f64 / n as f64,
+        };
+        Self {
+            weight,
+            counts,
+            lengths,
+            avg_len,
+        }
+    }
+
+    fn score(&self, doc: usize, terms: &[String]) -> f64 {
+        let n = self.counts.len() as f64;
+        let norm = match self.avg_len {
+            avg if avg > 0.0 => self.lengths[doc] as f64 / avg,
+            _ => 1.0,
+        };
+        terms
+            .iter()
+            .map(|term| {
+                let tf = self.counts[doc].get(term).copied().unwrap_or(0) as f64;
+                if tf == 0.0 {
+                    return 0.0;
+                }
+                let df = self.counts.iter().filter(|c| c.contains_key(term)).count() as f64;
+                let idf = ((n - df + 0.5) / (df + 0.5) + 1.0).ln();
+                idf * tf * (K1 + 1.0) / (tf + K1 * (1.0 - B + B * norm))
+            })
+            .sum::<f64>()
+            * self.weight
+    }
+}
+
+/// The documents matching `terms`, best first, as `(score, index into
+/// docs)`.
+pub fn rank(terms: &[String], docs: &[SearchDoc]) -> Vec<(f64, usize)> {
+    let fields = [
+        Field::new(TITLE_WEIGHT, docs.iter().map(|d| d.title.clone())),
+        Field::new(SUMMARY_WEIGHT, docs.iter().map(|d| d.summary.clone())),
+        Field::new(BODY_WEIGHT, docs.iter().map(|d| d.body.clone())),
+    ];
+    let mut ranked: Vec<(f64, usize)> = (0..docs.len())
+        .map(|i| (fields.iter().map(|f| f.score(i, terms)).sum(), i))
+        .filter(|(score, _)| *score > 0.0)
+        .collect();
+    ranked.sort_by(|(a, i), (b, j)| {
+        b.total_cmp(a)
+            .then_with(|| docs[*i].path.cmp(&docs[*j].path))
+    });
+    ranked
+}
+
+/// `text` HTML-escaped, with whole words in `terms` wrapped in `<mark>`.
+pub fn highlight(text: &str, terms: &[String]) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut word = String::new();
+    let flush = |word: &mut String, out: &mut String| {
+        if word.is_empty() {
+            return;
+        }
+        let escaped = html_escape::encode_text(word.as_str()).into_owned();
+        if terms.contains(&word.to_lowercase()) {
+            out.push_str("<mark>");
+            out.push_str(&escaped);
+            out.push_str("</mark>");
+        } else {
+            out.push_str(&escaped);
+        }
+        word.clear();
+    };
+    for c in text.chars() {
+        if c.is_alphanumeric() {
+            word.push(c);
+        } else {
+            flush(&mut word, &mut out);
+            out.push_str(&html_escape::encode_text(c.encode_utf8(&mut [0; 4])));
+        }
+    }
+    flush(&mut word, &mut out);
+    out
+}
+
+/// About `max_chars` of `text` around the first word in `terms`, cut at word
+/// boundaries, highlighted. The start of the text when nothing matches.
+pub fn snippet(text: &str, terms: &[String], max_chars: usize) -> String {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let hit = words
+        .iter()
+        .position(|w| tokenize(w).iter().any(|t| terms.contains(t)))
+        .unwrap_or(0);
+
+    // The words from `start` that fit, at least one.
+    let fill = |start: usize| {
+        let (mut end, mut len) = (start, 0);
+        while end < words.len() && (end == start || len + words[end].chars().count() < max_chars) {
+            len += words[end].chars().count() + 1;
+            end += 1;
+        }
+        end
+    };
+    // A few words of lead-in, unless that leaves the match out.
+    let mut start = hit.saturating_sub(3);
+    let mut end = fill(start);
+    if end <= hit {
+        start = hit;
+        end = fill(start);
+    }
+
+    let mut out = highlight(&words[start..end].join(" "), terms);
+    if start > 0 {
+        out.insert_str(0, "… ");
+    }
+    if end < words.len() {
+        out.push_str(" …");
+    }
+    out
+}
+
+/// The results page `number` of `query`; the first has no `page`.
+pub fn page_path(query: &str, number: usize) -> String {
+    let q: String = form_urlencoded::byte_serialize(query.as_bytes()).collect();
+    match number {
+        0 | 1 => format!("/search?q={q}"),
+        n => format!("/search?q={q}&page={n}"),
+    }
+}
+
+/// Template model (and JSON response) for one page of results:
+/// `{ query, total, results: [{ path, title, title_html, date, summary,
+/// snippet, score }], page: { number, count, prev, next } }`, `ranked`
+/// being this page's slice of `rank`.
+pub fn page_model(
+    query: &str,
+    terms: &[String],
+    docs: &[SearchDoc],
+    ranked: &[(f64, usize)],
+    page: Pagination,
+    snippet_chars: usize,
+) -> Json {
+    let results: Vec<Json> = ranked
+        .iter()
+        .map(|(score, i)| {
+            let doc = &docs[*i];
+            json!({
+                "path": doc.path,
+                "title": doc.title,
+                "title_html": highlight(&doc.title, terms),
+                "date": doc.date.as_deref().and_then(|d| d.get(..10)),
+                "summary": doc.summary,
+                "snippet": snippet(&doc.body, terms, snippet_chars),
+                "score": score,
+            })
+        })
+        .collect();
+    let link = |n: usize| page_path(query, n);
+    json!({
+        "query": query,
+        "total": page.total,
+        "results": results,
+        "page": {
+            "number": page.number,
+            "count": page.count(),
+            "prev": (page.number > 1).then(|| link(page.number - 1)),
+            "next": (page.number < page.count()).then(|| link(page.number + 1)),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::render_default;
+
+    fn doc(path: &str, title: &str, summary: &str, body: &str) -> SearchDoc {
+        SearchDoc {
+            path: path.into(),
+            title: title.into(),
+            summary: summary.into(),
+            body: body.into(),
+            date: Some("2024-03-05T10:00:00Z".into()),
+        }
+    }
+
+    #[test]
+    fn titles_outrank_bodies_and_snippets_mark_the_words() {
+        let docs = vec![
+            doc("/body", "Notes", "", "We tried rust once and liked it."),
+            doc("/title", "Rust & WASM", "Compiling to the web", "Short."),
+            doc("/none", "Go", "", "Nothing to see."),
+        ];
+        let terms = query_terms("Rust rust");
+        assert_eq!(terms, ["rust"]);
+        let ranked = rank(&terms, &docs);
+        let order: Vec<&str> = ranked.iter().map(|(_, i)| docs[*i].path.as_str()).collect();
+        assert_eq!(order, ["/title", "/body"]);
+
+        assert_eq!(
+            highlight("Rust & <rust>", &terms),
+            "<mark>Rust</mark> &amp; &lt;<mark>rust</mark>&gt;"
+        );
+        let long = format!("{} rust at last {}", "word ".repeat(40), "tail ".repeat(40));
+        let cut = snippet(&long, &terms, 40);
+        assert!(cut.starts_with("… word"));
+        assert!(cut.contains("<mark>rust</mark> at last"));
+        assert!(cut.ends_with(" …"));
+        assert_eq!(html_text("<p>A&amp;B</p>\n<p>c</p>"), "A&B c");
+
+        let model = page_model(
+            "rust & wasm",
+            &terms,
+            &docs,
+            &ranked[..1],
+            Pagination::new(1, 1, 2),
+            160,
+        );
+        assert_eq!(model["page"]["next"], "/search?q=rust+%26+wasm&page=2");
+        let mut out = Vec::new();
+        render_default("search", DEFAULT_SEARCH_TEMPLATE, &model, &mut out).unwrap();
+        let html = String::from_utf8(out).unwrap();
+        assert!(html.contains(r#"<a href="/title"><mark>Rust</mark> &amp; WASM</a>"#));
+        assert!(html.contains("2 results for"));
+    }
+}
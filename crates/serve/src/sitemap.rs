@@ -0,0 +1,279 @@
+// crates/serve/src/sitemap.rs
+
+// `/sitemap.xml` and `/robots.txt` from the front-matter index.
+//
+// Every live HTML document becomes a `<url>` at its permalink (see
+// crate::permalinks), with `<lastmod>` taken from `publish.modified` (else
+// `publish.date`). The sitemap protocol caps a file
+// at 50,000 URLs, so larger sites get a sitemap index at `/sitemap.xml`
+// pointing at numbered pages, `/sitemap-1.xml`, `/sitemap-2.xml`, …
+
+use crate::indexer::{for_each_front_matter, ContentManager};
+use crate::resolver::ResolverError;
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
+use domain::setting::RobotsSettings;
+use serde_json::Value as Json;
+use std::fmt::Write;
+
+/// Most URLs one sitemap file may list.
+pub const MAX_URLS_PER_SITEMAP: usize = 50_000;
+
+const XML_HEADER: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n";
+const SITEMAP_NS: &str = "http://www.sitemaps.org/schemas/sitemap/0.9";
+
+/// One `<url>` of the sitemap.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SitemapEntry {
+    /// Path below the site root, e.g. `/docs/` or `/blog/hello.html`.
+    pub path: String,
+    /// W3C datetime, when the document declares one.
+    pub lastmod: Option<String>,
+}
+
+impl SitemapEntry {
+    /// The entry for a served document, or `None` when it should not be
+    /// listed: not HTML, or `publish.status = "draft"`.
+    pub fn from_front_matter(served: &str, fm: &Json) -> Option<Self> {
+        if !served.ends_with(".html") {
+            return None;
+        }
+
+        let publish = fm.get("publish");
+        let field = |name: &str| publish.and_then(|p| p.get(name)).and_then(Json::as_str);
+        if field("status") == Some("draft") {
+            return None;
+        }
+
+        // `/docs/index.html` is served at `/docs/` too; list the shorter URL.
+        let path = match served.strip_suffix("index.html") {
+            Some(dir) if dir.ends_with('/') => dir.to_owned(),
+            _ => served.to_owned(),
+        };
+
+        Some(Self {
+            path,
+            lastmod: field("modified")
+                .or_else(|| field("date"))
+                .and_then(w3c_date),
+        })
+    }
+}
+
+/// Sitemap entries for every listed document, sorted by path.
+#[tracing::instrument(skip_all)]
+pub async fn collect_entries(
+    mgr: &impl ContentManager,
+) -> Result<Vec<SitemapEntry>, ResolverError> {
+    let permalinks = mgr.permalinks();
+    let mut entries = Vec::new();
+    for_each_front_matter(mgr, |served, fm| {
+        entries.extend(
+            SitemapEntry::from_front_matter(&served, &fm).map(|entry| SitemapEntry {
+                path: permalinks.for_document(&fm).unwrap_or(entry.path),
+                ..entry
+            }),
+        );
+    })
+    .await?;
+
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    entries.dedup_by(|a, b| a.path == b.path);
+    Ok(entries)
+}
+
+/// Number of `/sitemap-N.xml` pages; 0 when everything fits in one file.
+pub fn page_count(entries: &[SitemapEntry]) -> usize {
+    if entries.len() <= MAX_URLS_PER_SITEMAP {
+        0
+    } else {
+        entries.len().div_ceil(MAX_URLS_PER_SITEMAP)
+    }
+}
+
+/// The document served at `/sitemap.xml`: a `<urlset>`, or a
+/// `<sitemapindex>` of the numbered pages once there are too many URLs.
+pub fn sitemap_xml(entries: &[SitemapEntry], base_url: &str) -> String {
+    let base = base_url.trim_end_matches('/');
+    let pages = page_count(entries);
+    if pages == 0 {
+        return url_set(entries, base);
+    }
+
+    let mut out = format!("{XML_HEADER}<sitemapindex xmlns=\"{SITEMAP_NS}\">\n");
+    for (i, chunk) in entries.chunks(MAX_URLS_PER_SITEMAP).enumerate() {
+        out.push_str("  <sitemap>\n");
+        let _ = writeln!(out, "    <loc>{base}/sitemap-{}.xml</loc>", i + 1);
+        if let Some(lastmod) = chunk.iter().filter_map(|e| e.lastmod.as_deref()).max() {
+            let _ = writeln!(out, "    <lastmod>{}</lastmod>", escape(lastmod));
+        }
+        out.push_str("  </sitemap>\n");
+    }
+    out.push_str("</sitemapindex>\n");
+    out
+}
+
+/// Page `page` (1-based) of a split sitemap, or `None` if there is no such
+/// page.
+pub fn sitemap_page(entries: &[SitemapEntry], base_url: &str, page: usize) -> Option<String> {
+    if page == 0 || page > page_count(entries) {
+        return None;
+    }
+    let chunk = entries.chunks(MAX_URLS_PER_SITEMAP).nth(page - 1)?;
+    Some(url_set(chunk, base_url.trim_end_matches('/')))
+}
+
+/// `/robots.txt`: the configured rules followed by the sitemap location.
+pub fn robots_txt(settings: &RobotsSettings, base_url: &str) -> String {
+    let mut out = format!("User-agent: {}\n", settings.user_agent);
+    for path in &settings.allow {
+        let _ = writeln!(out, "Allow: {path}");
+    }
+    for path in &settings.disallow {
+        let _ = writeln!(out, "Disallow: {path}");
+    }
+    if settings.allow.is_empty() && settings.disallow.is_empty() {
+        // An empty Disallow is the spelled-out "everything is allowed".
+        out.push_str("Disallow:\n");
+    }
+    for line in &settings.extra {
+        let _ = writeln!(out, "{line}");
+    }
+
+    let _ = write!(
+        out,
+        "\nSitemap: {}/sitemap.xml\n",
+        base_url.trim_end_matches('/')
+    );
+    out
+}
+
+fn url_set(entries: &[SitemapEntry], base: &str) -> String {
+    let mut out = format!("{XML_HEADER}<urlset xmlns=\"{SITEMAP_NS}\">\n");
+    for entry in entries {
+        out.push_str("  <url>\n");
+        let _ = writeln!(
+            out,
+            "    <loc>{}</loc>",
+            escape(&format!("{base}{}", entry.path))
+        );
+        if let Some(lastmod) = &entry.lastmod {
+            let _ = writeln!(out, "    <lastmod>{}</lastmod>", escape(lastmod));
+        }
+        out.push_str("  </url>\n");
+    }
+    out.push_str("</urlset>\n");
+    out
+}
+
+/// RFC 3339 timestamps become UTC seconds; a bare `YYYY-MM-DD` is kept.
+/// Anything else is dropped, since one bad `<lastmod>` can get the whole
+/// file rejected.
+fn w3c_date(raw: &str) -> Option<String> {
+    let raw = raw.trim();
+    if let Ok(at) = DateTime::parse_from_rfc3339(raw) {
+        return Some(
+            at.with_timezone(&Utc)
+                .to_rfc3339_opts(SecondsFormat::Secs, true),
+        );
+    }
+    NaiveDate::parse_from_str(raw, "%Y-%m-%d")
+        .ok()
+        .map(|d| d.to_string())
+}
+
+fn escape(s: &str) -> String {
+    html_escape::encode_quoted_attribute(s).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn entry(path: &str, lastmod: Option<&str>) -> SitemapEntry {
+        SitemapEntry {
+            path: path.to_owned(),
+            lastmod: lastmod.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn entries_come_from_html_documents_with_publish_dates() {
+        let fm =
+            json!({ "publish": { "date": "2024-01-01", "modified": "2024-03-05T10:00:00+02:00" } });
+        assert_eq!(
+            SitemapEntry::from_front_matter("/blog/hello.html", &fm),
+            Some(entry("/blog/hello.html", Some("2024-03-05T08:00:00Z")))
+        );
+        assert_eq!(
+            SitemapEntry::from_front_matter(
+                "/docs/index.html",
+                &json!({ "publish": { "date": "2024-01-01" } })
+            ),
+            Some(entry("/docs/", Some("2024-01-01")))
+        );
+        assert_eq!(
+            SitemapEntry::from_front_matter(
+                "/a.html",
+                &json!({ "publish": { "modified": "last tuesday" } })
+            ),
+            Some(entry("/a.html", None))
+        );
+
+        let draft = json!({ "publish": { "status": "draft" } });
+        assert_eq!(SitemapEntry::from_front_matter("/wip.html", &draft), None);
+        assert_eq!(
+            SitemapEntry::from_front_matter("/feed.json", &json!({})),
+            None
+        );
+    }
+
+    #[test]
+    fn small_sites_get_one_urlset() {
+        let entries = vec![entry("/", None), entry("/a&b.html", Some("2024-01-01"))];
+        let xml = sitemap_xml(&entries, "https://example.com/");
+
+        assert!(xml.starts_with(XML_HEADER));
+        assert!(xml.contains("<urlset"));
+        assert!(xml.contains("<loc>https://example.com/</loc>"));
+        assert!(xml.contains("<loc>https://example.com/a&amp;b.html</loc>"));
+        assert!(xml.contains("<lastmod>2024-01-01</lastmod>"));
+        assert_eq!(sitemap_page(&entries, "https://example.com", 1), None);
+    }
+
+    #[test]
+    fn large_sites_get_a_sitemap_index() {
+        let entries: Vec<SitemapEntry> = (0..MAX_URLS_PER_SITEMAP + 1)
+            .map(|i| entry(&format!("/p{i:06}.html"), None))
+            .collect();
+
+        let index = sitemap_xml(&entries, "https://example.com");
+        assert!(index.contains("<sitemapindex"));
+        assert!(index.contains("<loc>https://example.com/sitemap-1.xml</loc>"));
+        assert!(index.contains("<loc>https://example.com/sitemap-2.xml</loc>"));
+        assert!(!index.contains("sitemap-3.xml"));
+
+        let last = sitemap_page(&entries, "https://example.com", 2).unwrap();
+        assert_eq!(last.matches("<url>").count(), 1);
+        assert!(sitemap_page(&entries, "https://example.com", 3).is_none());
+        assert!(sitemap_page(&entries, "https://example.com", 0).is_none());
+    }
+
+    #[test]
+    fn robots_lists_rules_and_the_sitemap() {
+        let open = robots_txt(&RobotsSettings::default(), "https://example.com/");
+        assert_eq!(
+            open,
+            "User-agent: *\nDisallow:\n\nSitemap: https://example.com/sitemap.xml\n"
+        );
+
+        let settings = RobotsSettings {
+            disallow: vec!["/drafts/".into()],
+            extra: vec!["Crawl-delay: 5".into()],
+            ..RobotsSettings::default()
+        };
+        let robots = robots_txt(&settings, "https://example.com");
+        assert!(robots.contains("Disallow: /drafts/\nCrawl-delay: 5\n"));
+        assert!(!robots.contains("Disallow:\n"));
+    }
+}
@@ -0,0 +1,208 @@
+// crates/serve/src/taxonomy.rs
+
+// Archive pages for the taxonomies front matter may carry (`tax.tags`,
+// `tax.categories`, `tax.series`):
+//
+//   /tags/{tag}                 newest first, `per_page` to a page
+//   /tags/{tag}/page/{n}        later pages
+//   /tags/{tag}/feed.{ext}      its feed (see crate::feeds)
+//
+// and likewise under `/categories/` and `/series/`. The host queries the
+// index for one page of documents; this module names the taxonomies,
+// builds the template model, and renders the built-in page for themes
+// without a taxonomy template.
+
+use serde_json::{json, Value as Json};
+
+/// A term's listing for themes without one, over [`page_model`].
+pub const DEFAULT_TAXONOMY_TEMPLATE: &str = r#"<!doctype html>
+<html>
+<head><meta charset="utf-8"><title>{{label}}: {{term}}</title>
+<link rel="alternate" type="application/rss+xml" href="{{feed}}"></head>
+<body>
+<header>
+  <h1>{{label}}: {{term}}</h1>
+  <p>{{total}} {{#if (eq total 1)}}post{{else}}posts{{/if}} · <a href="{{feed}}">Feed</a></p>
+</header>
+<main>
+  <ul>
+  {{#each posts}}
+    <li><a href="{{path}}">{{title}}</a>{{#if date}} <time datetime="{{date}}">{{date}}</time>{{/if}}{{#if summary}}<p>{{summary}}</p>{{/if}}</li>
+  {{/each}}
+  </ul>
+</main>
+{{#if (or page.prev page.next)}}
+<nav>
+  {{#if page.prev}}<a href="{{page.prev}}" rel="prev">Newer</a>{{/if}}
+  <span>Page {{page.number}} of {{page.count}}</span>
+  {{#if page.next}}<a href="{{page.next}}" rel="next">Older</a>{{/if}}
+</nav>
+{{/if}}
+</body>
+</html>
+"#;
+
+/// A taxonomy with archive pages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Taxonomy {
+    Tags,
+    Categories,
+    Series,
+}
+
+impl Taxonomy {
+    pub const ALL: [Taxonomy; 3] = [Taxonomy::Tags, Taxonomy::Categories, Taxonomy::Series];
+
+    /// The first path segment of its pages, also its name under `tax`.
+    pub fn segment(self) -> &'static str {
+        match self {
+            Taxonomy::Tags => "tags",
+            Taxonomy::Categories => "categories",
+            Taxonomy::Series => "series",
+        }
+    }
+
+    pub fn from_segment(segment: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|t| t.segment() == segment)
+    }
+
+    /// The indexed field holding a document's terms.
+    pub fn field(self) -> String {
+        format!("tax.{}", self.segment())
+    }
+
+    /// How pages title one of its terms.
+    pub fn label(self) -> &'static str {
+        match self {
+            Taxonomy::Tags => "Tag",
+            Taxonomy::Categories => "Category",
+            Taxonomy::Series => "Series",
+        }
+    }
+
+    /// The archive page `number` of `term`; the first has no `/page/1`.
+    pub fn page_path(self, term: &str, number: usize) -> String {
+        match number {
+            0 | 1 => format!("/{}/{term}", self.segment()),
+            n => format!("/{}/{term}/page/{n}", self.segment()),
+        }
+    }
+}
+
+/// Where one page sits among a term's pages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pagination {
+    /// From 1.
+    pub number: usize,
+    pub per_page: usize,
+    /// Documents under the term, on every page.
+    pub total: usize,
+}
+
+impl Pagination {
+    pub fn new(number: usize, per_page: usize, total: usize) -> Self {
+        Self {
+            number: number.max(1),
+            per_page: per_page.max(1),
+            total,
+        }
+    }
+
+    /// Documents before this page.
+    pub fn skip(&self) -> usize {
+        (self.number - 1).saturating_mul(self.per_page)
+    }
+
+    /// How many pages there are; one even when the term has no documents.
+    pub fn count(&self) -> usize {
+        self.total.div_ceil(self.per_page).max(1)
+    }
+
+    pub fn exists(&self) -> bool {
+        self.number <= self.count()
+    }
+}
+
+/// Template model for a taxonomy page:
+/// `{ taxonomy, label, term, feed, total, posts: [{ path, title, date,
+/// summary, tags }], page: { number, count, prev, next } }`, where `posts`
+/// are index records with `path` their permalink or served path.
+pub fn page_model(
+    taxonomy: Taxonomy,
+    term: &str,
+    posts: &[(String, Json)],
+    page: Pagination,
+) -> Json {
+    let str_at = |doc: &Json, pointer: &str| {
+        doc.pointer(pointer)
+            .and_then(Json::as_str)
+            .map(str::to_owned)
+    };
+    let posts: Vec<Json> = posts
+        .iter()
+        .map(|(path, doc)| {
+            json!({
+                "path": path,
+                "title": str_at(doc, "/content/title").unwrap_or_else(|| path.clone()),
+                "date": str_at(doc, "/publish/date").and_then(|d| d.get(..10).map(str::to_owned)),
+                "summary": str_at(doc, "/content/summary"),
+                "tags": doc.pointer("/tax/tags").cloned().unwrap_or_else(|| json!([])),
+            })
+        })
+        .collect();
+    let link = |n: usize| taxonomy.page_path(term, n);
+    json!({
+        "taxonomy": taxonomy.segment(),
+        "label": taxonomy.label(),
+        "term": term,
+        "feed": format!("/{}/{term}/feed.xml", taxonomy.segment()),
+        "total": page.total,
+        "posts": posts,
+        "page": {
+            "number": page.number,
+            "count": page.count(),
+            "prev": (page.number > 1).then(|| link(page.number - 1)),
+            "next": (page.number < page.count()).then(|| link(page.number + 1)),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::render_default;
+
+    #[test]
+    fn pages_link_to_their_neighbours() {
+        assert_eq!(Taxonomy::from_segment("series"), Some(Taxonomy::Series));
+        assert_eq!(Taxonomy::from_segment("authors"), None);
+        assert_eq!(Taxonomy::Categories.field(), "tax.categories");
+
+        let page = Pagination::new(2, 2, 5);
+        assert_eq!((page.skip(), page.count(), page.exists()), (2, 3, true));
+        assert!(!Pagination::new(4, 2, 5).exists());
+        assert!(Pagination::new(1, 10, 0).exists());
+
+        let posts = vec![(
+            "/2024/03/engine/".to_owned(),
+            json!({
+                "id": "/posts/engine.html",
+                "content": { "title": "The Engine", "summary": "Notes" },
+                "publish": { "date": "2024-03-05T10:00:00Z" },
+                "tax": { "tags": ["rust"] },
+            }),
+        )];
+        let model = page_model(Taxonomy::Tags, "rust", &posts, page);
+        assert_eq!(model["page"]["prev"], "/tags/rust");
+        assert_eq!(model["page"]["next"], "/tags/rust/page/3");
+
+        let mut out = Vec::new();
+        render_default("taxonomy", DEFAULT_TAXONOMY_TEMPLATE, &model, &mut out).unwrap();
+        let html = String::from_utf8(out).unwrap();
+        assert!(html.contains("<h1>Tag: rust</h1>"));
+        assert!(html.contains(r#"<a href="/2024/03/engine/">The Engine</a>"#));
+        assert!(html.contains(r#"<time datetime="2024-03-05">"#));
+        assert!(html.contains(r#"href="/tags/rust/feed.xml""#));
+        assert!(html.contains(r#"<a href="/tags/rust/page/3" rel="next">"#));
+    }
+}